@@ -35,6 +35,7 @@ type nodeServiceServer struct {
 	configManager    *ConfigManager
 	securityManager  *SecurityManager // Mandate 3: Security & encryption
 	mlCoordinator    *MLCoordinator   // Mandate 3: ML coordination
+	manifests        *ManifestStore   // Manifests synced with the Rust node
 }
 
 // NewNodeServiceServer creates a new NodeService server
@@ -70,6 +71,7 @@ func NewNodeServiceServerWithConfig(store *NodeStore, network NetworkAdapter, sh
 		configManager:   configMgr,
 		securityManager: NewSecurityManager(), // Mandate 3
 		mlCoordinator:   NewMLCoordinator(),   // Mandate 3
+		manifests:       NewManifestStore(),
 	}
 }
 
@@ -696,6 +698,20 @@ func (s *nodeServiceServer) CesReconstruct(ctx context.Context, call NodeService
 	return nil
 }
 
+// uploadResults is satisfied by the results of both upload and uploadWithProgress
+type uploadResults interface {
+	NewResponse() (UploadResponse, error)
+	Segment() *capnp.Segment
+}
+
+// downloadResults is satisfied by the results of both download and downloadWithProgress
+type downloadResults interface {
+	NewResponse() (DownloadResponse, error)
+}
+
+// shardProgress is called after each shard is sent or fetched
+type shardProgress func(done, total int, bytes uint64)
+
 // Upload implements the upload method - high-level CES + distribute
 func (s *nodeServiceServer) Upload(ctx context.Context, call NodeService_upload) error {
 	results, err := call.AllocResults()
@@ -709,6 +725,44 @@ func (s *nodeServiceServer) Upload(ctx context.Context, call NodeService_upload)
 		return err
 	}
 
+	return s.upload(ctx, request, results, nil)
+}
+
+// UploadWithProgress implements the uploadWithProgress method - upload, reporting each shard sent to the listener
+func (s *nodeServiceServer) UploadWithProgress(ctx context.Context, call NodeService_uploadWithProgress) error {
+	results, err := call.AllocResults()
+	if err != nil {
+		return err
+	}
+
+	args := call.Args()
+	request, err := args.Request()
+	if err != nil {
+		return err
+	}
+
+	listener := args.Listener()
+	transferID := fmt.Sprintf("upload-%d", time.Now().UnixNano())
+	started := time.Now()
+	var bytesDone uint64
+	progress := func(done, total int, bytes uint64) {
+		bytesDone += bytes
+		notifyProgress(ctx, listener, transferID, true, started, done, total, bytesDone)
+	}
+
+	if err := s.upload(ctx, request, results, progress); err != nil {
+		return err
+	}
+
+	response, err := results.Response()
+	if err != nil {
+		return err
+	}
+	notifyFinished(ctx, listener, transferID, true, response.Success())
+	return nil
+}
+
+func (s *nodeServiceServer) upload(ctx context.Context, request UploadRequest, results uploadResults, progress shardProgress) error {
 	// Get input data
 	data, err := request.Data()
 	if err != nil {
@@ -825,6 +879,9 @@ func (s *nodeServiceServer) Upload(ctx context.Context, call NodeService_upload)
 		}
 
 		shardLocations[i] = [2]uint32{uint32(i), peerID}
+		if progress != nil {
+			progress(i+1, len(shards), uint64(len(shard.Data)))
+		}
 	}
 
 	// Build manifest - fileHash already computed above
@@ -879,6 +936,44 @@ func (s *nodeServiceServer) Download(ctx context.Context, call NodeService_downl
 		return err
 	}
 
+	return s.download(ctx, request, results, nil)
+}
+
+// DownloadWithProgress implements the downloadWithProgress method - download, reporting each shard fetched to the listener
+func (s *nodeServiceServer) DownloadWithProgress(ctx context.Context, call NodeService_downloadWithProgress) error {
+	results, err := call.AllocResults()
+	if err != nil {
+		return err
+	}
+
+	args := call.Args()
+	request, err := args.Request()
+	if err != nil {
+		return err
+	}
+
+	listener := args.Listener()
+	transferID := fmt.Sprintf("download-%d", time.Now().UnixNano())
+	started := time.Now()
+	var bytesDone uint64
+	progress := func(done, total int, bytes uint64) {
+		bytesDone += bytes
+		notifyProgress(ctx, listener, transferID, false, started, done, total, bytesDone)
+	}
+
+	if err := s.download(ctx, request, results, progress); err != nil {
+		return err
+	}
+
+	response, err := results.Response()
+	if err != nil {
+		return err
+	}
+	notifyFinished(ctx, listener, transferID, false, response.Success())
+	return nil
+}
+
+func (s *nodeServiceServer) download(ctx context.Context, request DownloadRequest, results downloadResults, progress shardProgress) error {
 	// Get shard locations
 	shardLocationsList, err := request.ShardLocations()
 	if err != nil {
@@ -910,6 +1005,9 @@ func (s *nodeServiceServer) Download(ctx context.Context, call NodeService_downl
 		present[i] = true
 		shards[i] = ShardData{Data: shardData}
 		log.Printf("Successfully fetched shard %d (%d bytes)", shardIndex, len(shardData))
+		if progress != nil {
+			progress(i+1, shardCount, uint64(len(shardData)))
+		}
 	}
 
 	// Check if we have enough shards to reconstruct
@@ -1018,6 +1116,60 @@ func (s *nodeServiceServer) Download(ctx context.Context, call NodeService_downl
 	return nil
 }
 
+// notifyProgress pushes a per-shard TransferProgress snapshot to the caller's listener.
+// Listener failures are logged and never fail the transfer itself.
+func notifyProgress(ctx context.Context, listener ProgressListener, transferID string, isUpload bool, started time.Time, done, total int, bytesDone uint64) {
+	sendProgress(ctx, listener, func(p TransferProgress) error {
+		if err := p.SetTransferId(transferID); err != nil {
+			return err
+		}
+		p.SetIsUpload(isUpload)
+		p.SetShardsDone(uint32(done))
+		p.SetShardsTotal(uint32(total))
+		p.SetBytesDone(bytesDone)
+		if elapsed := time.Since(started).Seconds(); elapsed > 0 {
+			p.SetBytesPerSec(float64(bytesDone) / elapsed)
+		}
+		if total > 0 {
+			p.SetPercent(float32(done) * 100 / float32(total))
+		}
+		return nil
+	})
+}
+
+// notifyFinished pushes the final TransferProgress snapshot for a transfer
+func notifyFinished(ctx context.Context, listener ProgressListener, transferID string, isUpload bool, success bool) {
+	sendProgress(ctx, listener, func(p TransferProgress) error {
+		if err := p.SetTransferId(transferID); err != nil {
+			return err
+		}
+		p.SetIsUpload(isUpload)
+		p.SetFinished(true)
+		p.SetSuccess(success)
+		if success {
+			p.SetPercent(100)
+		}
+		return nil
+	})
+}
+
+func sendProgress(ctx context.Context, listener ProgressListener, fill func(TransferProgress) error) {
+	if !listener.IsValid() {
+		return
+	}
+	future, release := listener.OnProgress(ctx, func(params ProgressListener_onProgress_Params) error {
+		progress, err := params.NewProgress()
+		if err != nil {
+			return err
+		}
+		return fill(progress)
+	})
+	defer release()
+	if _, err := future.Struct(); err != nil {
+		log.Printf("Warning: progress listener failed: %v", err)
+	}
+}
+
 // ============================================================================
 // Streaming Services (Go handles all networking per Golden Rule)
 // ============================================================================
@@ -1383,8 +1535,8 @@ func (s *nodeServiceServer) GetComputeJobStatus(ctx context.Context, call NodeSe
 
 	// Map status to string
 	statusStr := jobStatus.Status.String()
-	log.Printf("📊 [COMPUTE] Job %s status: %s (%.1f%% complete, %d/%d chunks)",
-		jobID, statusStr, jobStatus.Progress*100, jobStatus.CompletedChunks, jobStatus.TotalChunks)
+	log.Printf("📊 [COMPUTE] Job %s status: %s (%.1f%% complete, %d/%d chunks, %d stragglers)",
+		jobID, statusStr, jobStatus.Progress*100, jobStatus.CompletedChunks, jobStatus.TotalChunks, jobStatus.Stragglers)
 
 	status, err := results.NewStatus()
 	if err != nil {
@@ -2327,3 +2479,172 @@ func (s *nodeServiceServer) StopMLTraining(ctx context.Context, call NodeService
 	results.SetSuccess(true)
 	return nil
 }
+
+// ============================================================================
+// Rust-node services
+//
+// These methods are served by the Rust node's RPC server, which owns the
+// cache, transfers and metrics. The Go node answers them so that it still
+// satisfies NodeService, but points callers at the Rust node.
+// ============================================================================
+
+func errServedByRustNode(method string) error {
+	return fmt.Errorf("%s is served by the Rust node's RPC server, not the Go node", method)
+}
+
+// PutMany implements the putMany method
+func (s *nodeServiceServer) PutMany(ctx context.Context, call NodeService_putMany) error {
+	return errServedByRustNode("putMany")
+}
+
+// GetMany implements the getMany method
+func (s *nodeServiceServer) GetMany(ctx context.Context, call NodeService_getMany) error {
+	return errServedByRustNode("getMany")
+}
+
+// GetMetricsHistory implements the getMetricsHistory method
+func (s *nodeServiceServer) GetMetricsHistory(ctx context.Context, call NodeService_getMetricsHistory) error {
+	return errServedByRustNode("getMetricsHistory")
+}
+
+// GetActiveTransfers implements the getActiveTransfers method
+func (s *nodeServiceServer) GetActiveTransfers(ctx context.Context, call NodeService_getActiveTransfers) error {
+	return errServedByRustNode("getActiveTransfers")
+}
+
+// SetLimit implements the setLimit method
+func (s *nodeServiceServer) SetLimit(ctx context.Context, call NodeService_setLimit) error {
+	return errServedByRustNode("setLimit")
+}
+
+// GetLimits implements the getLimits method
+func (s *nodeServiceServer) GetLimits(ctx context.Context, call NodeService_getLimits) error {
+	return errServedByRustNode("getLimits")
+}
+
+// GetNodeStats implements the getNodeStats method
+func (s *nodeServiceServer) GetNodeStats(ctx context.Context, call NodeService_getNodeStats) error {
+	return errServedByRustNode("getNodeStats")
+}
+
+// PutStream implements the putStream method
+func (s *nodeServiceServer) PutStream(ctx context.Context, call NodeService_putStream) error {
+	return errServedByRustNode("putStream")
+}
+
+// GetStream implements the getStream method
+func (s *nodeServiceServer) GetStream(ctx context.Context, call NodeService_getStream) error {
+	return errServedByRustNode("getStream")
+}
+
+// ListFiles implements the listFiles method
+func (s *nodeServiceServer) ListFiles(ctx context.Context, call NodeService_listFiles) error {
+	return errServedByRustNode("listFiles")
+}
+
+// SearchFiles implements the searchFiles method
+func (s *nodeServiceServer) SearchFiles(ctx context.Context, call NodeService_searchFiles) error {
+	return errServedByRustNode("searchFiles")
+}
+
+// GetFileInfo implements the getFileInfo method
+func (s *nodeServiceServer) GetFileInfo(ctx context.Context, call NodeService_getFileInfo) error {
+	return errServedByRustNode("getFileInfo")
+}
+
+// ============================================================================
+// Manifest Sync
+// ============================================================================
+
+// GetManifestDigests implements the getManifestDigests method
+func (s *nodeServiceServer) GetManifestDigests(ctx context.Context, call NodeService_getManifestDigests) error {
+	results, err := call.AllocResults()
+	if err != nil {
+		return err
+	}
+
+	digests := s.manifests.Digests()
+	digestsList, err := results.NewDigests(int32(len(digests)))
+	if err != nil {
+		return err
+	}
+	for i, digest := range digests {
+		digestMsg := digestsList.At(i)
+		if err := digestMsg.SetFileHash(digest.FileHash); err != nil {
+			return err
+		}
+		digestMsg.SetTimestamp(digest.Timestamp)
+		if err := digestMsg.SetDigest(digest.Digest); err != nil {
+			return err
+		}
+	}
+
+	return nil
+}
+
+// SyncManifests implements the syncManifests method
+func (s *nodeServiceServer) SyncManifests(ctx context.Context, call NodeService_syncManifests) error {
+	args := call.Args()
+	results, err := call.AllocResults()
+	if err != nil {
+		return err
+	}
+
+	pushList, err := args.Push()
+	if err != nil {
+		return err
+	}
+	accepted := uint32(0)
+	for i := 0; i < pushList.Len(); i++ {
+		recordMsg := pushList.At(i)
+		manifest, _ := recordMsg.Manifest()
+		signer, _ := recordMsg.Signer()
+		signature, _ := recordMsg.Signature()
+		stored, err := s.manifests.Put(ManifestRecord{
+			Manifest:  manifest,
+			Signer:    signer,
+			Signature: signature,
+		})
+		if err != nil {
+			log.Printf("Refused pushed manifest: %v", err)
+			continue
+		}
+		if stored {
+			accepted++
+		}
+	}
+	results.SetAccepted(accepted)
+
+	wantList, err := args.Want()
+	if err != nil {
+		return err
+	}
+	want := make([]string, 0, wantList.Len())
+	for i := 0; i < wantList.Len(); i++ {
+		hash, err := wantList.At(i)
+		if err != nil {
+			return err
+		}
+		want = append(want, hash)
+	}
+
+	records := s.manifests.Records(want)
+	recordsList, err := results.NewRecords(int32(len(records)))
+	if err != nil {
+		return err
+	}
+	for i, record := range records {
+		recordMsg := recordsList.At(i)
+		if err := recordMsg.SetManifest(record.Manifest); err != nil {
+			return err
+		}
+		if err := recordMsg.SetSigner(record.Signer); err != nil {
+			return err
+		}
+		if err := recordMsg.SetSignature(record.Signature); err != nil {
+			return err
+		}
+	}
+
+	return nil
+}
@@ -26,6 +26,12 @@ typedef struct {
     size_t count;
 } FFIShards;
 
+// Shard in a shared ring: len bytes at ring base + offset (matches Rust)
+typedef struct {
+    size_t offset;
+    size_t len;
+} FFIShmShard;
+
 // Forward declarations for Rust FFI functions
 void* ces_new(int compression_level);
 void* ces_new_with_key(int compression_level, const uint8_t* key);
@@ -34,6 +40,14 @@ FFIShards ces_process(void* pipeline, const uint8_t* data, size_t data_len);
 FFIResult ces_reconstruct(void* pipeline, const FFIShard* shards, size_t shard_count, const int* shard_present);
 void ces_free_result(FFIResult result);
 void ces_free_shards(FFIShards shards);
+int ces_derive_key(const uint8_t* passphrase, size_t passphrase_len, const uint8_t* salt, size_t salt_len, uint32_t memory_kib, uint32_t iterations, uint32_t parallelism, uint8_t* out_key);
+uint32_t ces_last_error_code(void);
+void* ces_shm_ring_new(size_t capacity);
+const uint8_t* ces_shm_ring_base(const void* ring);
+int ces_shm_ring_fd(const void* ring);
+void ces_shm_ring_free(void* ring);
+int ces_process_shm(const void* pipeline, const void* ring, const uint8_t* data, size_t data_len, FFIShmShard* out, size_t out_capacity);
+int ces_shm_release(const void* ring, FFIShmShard shard);
 */
 import "C"
 import (
@@ -73,6 +87,37 @@ func NewCESPipelineWithKey(compressionLevel int, key [32]byte) *CESPipeline {
 	return &CESPipeline{handle: handle}
 }
 
+// DeriveKeyFromPassphrase derives a 32-byte CES key with Argon2id
+// Use the salt and cost parameters from the file manifest's "kdf" entry to
+// get the same key the Rust node used
+func DeriveKeyFromPassphrase(passphrase string, salt []byte, memoryKiB, iterations, parallelism uint32) ([32]byte, error) {
+	var key [32]byte
+	if len(passphrase) == 0 {
+		return key, fmt.Errorf("passphrase is empty")
+	}
+	if len(salt) == 0 {
+		return key, fmt.Errorf("salt is empty")
+	}
+
+	pass := C.CBytes([]byte(passphrase))
+	defer C.free(pass)
+
+	rc := C.ces_derive_key(
+		(*C.uint8_t)(pass),
+		C.size_t(len(passphrase)),
+		(*C.uint8_t)(unsafe.Pointer(&salt[0])),
+		C.size_t(len(salt)),
+		C.uint32_t(memoryKiB),
+		C.uint32_t(iterations),
+		C.uint32_t(parallelism),
+		(*C.uint8_t)(unsafe.Pointer(&key[0])),
+	)
+	if rc != 0 {
+		return key, fmt.Errorf("key derivation failed")
+	}
+	return key, nil
+}
+
 // Close frees the CES pipeline resources
 func (c *CESPipeline) Close() {
 	if c.handle != nil {
@@ -209,8 +254,119 @@ func (c *CESPipeline) Reconstruct(shards []ShardData, present []bool) ([]byte, e
 	return reconstructed, nil
 }
 
+// Error code reported by ces_process_shm when the ring has no room
+const ffiShmFull = 9006
+
+// SharedShardRing is a Rust-owned shared-memory ring that shards are written
+// into, so Process results can be read in place instead of copied into Go
+type SharedShardRing struct {
+	handle unsafe.Pointer
+	base   unsafe.Pointer
+}
+
+// SharedShard is a shard living in a SharedShardRing
+// Data aliases ring memory: it is valid only until Release is called
+type SharedShard struct {
+	Data   []byte
+	desc   C.FFIShmShard
+	shared bool
+}
+
+// NewSharedShardRing maps a ring of capacity bytes (0 = default size)
+// Returns nil on platforms without shared rings; use Process there
+func NewSharedShardRing(capacity int) *SharedShardRing {
+	handle := C.ces_shm_ring_new(C.size_t(capacity))
+	if handle == nil {
+		return nil
+	}
+	return &SharedShardRing{
+		handle: handle,
+		base:   unsafe.Pointer(C.ces_shm_ring_base(handle)),
+	}
+}
+
+// Fd returns the memfd backing the ring, for mapping it in another process
+func (r *SharedShardRing) Fd() int {
+	return int(C.ces_shm_ring_fd(r.handle))
+}
+
+// Close frees the ring; shards still held become invalid
+func (r *SharedShardRing) Close() {
+	if r.handle != nil {
+		C.ces_shm_ring_free(r.handle)
+		r.handle = nil
+	}
+}
+
+// Release hands the shard's ring space back once it has been sent
+func (r *SharedShardRing) Release(shard SharedShard) {
+	if r.handle != nil && shard.shared {
+		C.ces_shm_release(r.handle, shard.desc)
+	}
+}
+
+// ProcessShared runs the CES pipeline, writing shards into ring
+// Falls back to copied shards (with nothing to release) when ring is nil or full
+func (c *CESPipeline) ProcessShared(ring *SharedShardRing, data []byte) ([]SharedShard, error) {
+	if c.handle == nil {
+		return nil, fmt.Errorf("pipeline is closed")
+	}
+	if len(data) == 0 {
+		return nil, fmt.Errorf("data is empty")
+	}
+	if ring == nil || ring.handle == nil {
+		return c.processCopied(data)
+	}
+
+	const maxShardCount = 1000
+	descs := make([]C.FFIShmShard, maxShardCount)
+	count := C.ces_process_shm(
+		c.handle,
+		ring.handle,
+		(*C.uint8_t)(unsafe.Pointer(&data[0])),
+		C.size_t(len(data)),
+		(*C.FFIShmShard)(unsafe.Pointer(&descs[0])),
+		C.size_t(maxShardCount),
+	)
+	if count < 0 {
+		if C.ces_last_error_code() == ffiShmFull {
+			return c.processCopied(data)
+		}
+		return nil, fmt.Errorf("CES processing failed")
+	}
+
+	shards := make([]SharedShard, int(count))
+	for i := range shards {
+		desc := descs[i]
+		shards[i] = SharedShard{
+			Data:   unsafe.Slice((*byte)(unsafe.Add(ring.base, uintptr(desc.offset))), int(desc.len)),
+			desc:   desc,
+			shared: true,
+		}
+	}
+	return shards, nil
+}
+
+// processCopied wraps Process results as SharedShards that need no release
+func (c *CESPipeline) processCopied(data []byte) ([]SharedShard, error) {
+	copied, err := c.Process(data)
+	if err != nil {
+		return nil, err
+	}
+	shards := make([]SharedShard, len(copied))
+	for i, shard := range copied {
+		shards[i] = SharedShard{Data: shard.Data}
+	}
+	return shards, nil
+}
+
 // Example usage:
 // pipeline := NewCESPipeline(3)
 // defer pipeline.Close()
 // shards, err := pipeline.Process(data)
 // reconstructed, err := pipeline.Reconstruct(shards, present)
+//
+// Zero-copy hand-off:
+// ring := NewSharedShardRing(0) // nil where unsupported
+// shards, err := pipeline.ProcessShared(ring, data)
+// ... send shard.Data, then ring.Release(shard)
@@ -29,6 +29,9 @@ const (
 	MsgTypeTaskRequest  uint8 = 1
 	MsgTypeTaskResponse uint8 = 2
 	MsgTypeCapacity     uint8 = 3
+	// MsgTypeHeartbeat is sent by a worker, ahead of the response, while a
+	// task is still running
+	MsgTypeHeartbeat uint8 = 4
 )
 
 // ComputeProtocol handles distributed compute over libp2p
@@ -144,9 +147,26 @@ func (cp *ComputeProtocol) handleTaskRequest(s network.Stream, from peer.ID) {
 	log.Printf("🔧 [COMPUTE] Received task %s (chunk %d, %d bytes) from %s",
 		req.TaskID, req.ChunkIndex, len(req.InputData), from.String()[:12])
 
-	// Execute the compute task
+	// Execute the compute task, reporting progress until it finishes
 	startTime := time.Now()
-	response := cp.executeTask(&req)
+	done := make(chan *TaskResponse, 1)
+	go func() { done <- cp.executeTask(&req) }()
+
+	heartbeat := time.NewTicker(compute.HeartbeatInterval)
+	defer heartbeat.Stop()
+
+	var response *TaskResponse
+	for response == nil {
+		select {
+		case response = <-done:
+		case <-heartbeat.C:
+			if _, err := s.Write([]byte{MsgTypeHeartbeat}); err != nil {
+				// The requester gave up on us; let the task finish and drop it
+				log.Printf("⚠️ [COMPUTE] Requester of task %s went away: %v", req.TaskID, err)
+				return
+			}
+		}
+	}
 	response.ExecutionTimeMs = uint64(time.Since(startTime).Milliseconds())
 
 	log.Printf("✅ [COMPUTE] Task %s completed in %dms (result: %d bytes)",
@@ -224,6 +244,18 @@ func (cp *ComputeProtocol) SendTask(ctx context.Context, workerPeer peer.ID, tas
 	}
 	defer s.Close()
 
+	// Abort the stream when the caller stops waiting, e.g. when another
+	// worker already returned this chunk
+	stop := make(chan struct{})
+	defer close(stop)
+	go func() {
+		select {
+		case <-ctx.Done():
+			s.Reset()
+		case <-stop:
+		}
+	}()
+
 	// Marshal task request
 	reqData, err := json.Marshal(task)
 	if err != nil {
@@ -240,10 +272,18 @@ func (cp *ComputeProtocol) SendTask(ctx context.Context, workerPeer peer.ID, tas
 		return nil, fmt.Errorf("failed to send task: %w", err)
 	}
 
-	// Read response type
+	// Read response type, passing heartbeats on to the manager
 	respType := make([]byte, 1)
-	if _, err := io.ReadFull(s, respType); err != nil {
-		return nil, fmt.Errorf("failed to read response type: %w", err)
+	for {
+		if _, err := io.ReadFull(s, respType); err != nil {
+			return nil, fmt.Errorf("failed to read response type: %w", err)
+		}
+		if respType[0] != MsgTypeHeartbeat {
+			break
+		}
+		if cp.manager != nil {
+			cp.manager.Heartbeat(task.ParentJobID, task.ChunkIndex, workerPeer.String())
+		}
 	}
 
 	if respType[0] != MsgTypeTaskResponse {
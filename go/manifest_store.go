@@ -0,0 +1,164 @@
+package main
+
+import (
+	"crypto/ed25519"
+	"crypto/sha256"
+	"encoding/hex"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"sort"
+	"sync"
+)
+
+// Manifest records synced with the Rust node (getManifestDigests and
+// syncManifests). These constants and the conflict rule mirror
+// rust/src/manifest_sync.rs, so that both sides converge.
+const (
+	// Domain separation for manifest record signatures
+	manifestSigningContext = "pangea-manifest-record-v1"
+	// Largest manifest JSON accepted
+	maxManifestBytes = 4 * 1024 * 1024
+	// Manifest bytes returned in one sync; the rest follow in later rounds
+	maxManifestBatchBytes = 16 * 1024 * 1024
+)
+
+// ManifestDigest is the version of one manifest
+type ManifestDigest struct {
+	FileHash  string
+	Timestamp int64  // the manifest's own timestamp
+	Digest    string // SHA-256 of the manifest JSON, hex
+}
+
+// Supersedes reports whether this version wins a conflict with other: the
+// newer timestamp wins, then the larger digest
+func (d ManifestDigest) Supersedes(other ManifestDigest) bool {
+	if d.Timestamp != other.Timestamp {
+		return d.Timestamp > other.Timestamp
+	}
+	return d.Digest > other.Digest
+}
+
+// ManifestRecord is a manifest as JSON, signed by the node that wrote it
+type ManifestRecord struct {
+	Manifest  string // stored byte for byte; digests are taken over it
+	Signer    string // Ed25519 public key, hex; empty when unsigned
+	Signature string // hex
+}
+
+// Verify checks the record's signature; unsigned records pass
+func (r ManifestRecord) Verify() error {
+	if r.Signer == "" {
+		return nil
+	}
+	public, err := hex.DecodeString(r.Signer)
+	if err != nil || len(public) != ed25519.PublicKeySize {
+		return errors.New("malformed signer key")
+	}
+	signature, err := hex.DecodeString(r.Signature)
+	if err != nil || len(signature) != ed25519.SignatureSize {
+		return errors.New("malformed signature")
+	}
+	signed := append([]byte(manifestSigningContext), r.Manifest...)
+	if !ed25519.Verify(public, signed, signature) {
+		return errors.New("bad manifest record signature")
+	}
+	return nil
+}
+
+// Open reads the file hash and timestamp from the manifest and takes its digest
+func (r ManifestRecord) Open() (ManifestDigest, error) {
+	if len(r.Manifest) > maxManifestBytes {
+		return ManifestDigest{}, fmt.Errorf("manifest record is %d bytes", len(r.Manifest))
+	}
+	var manifest struct {
+		FileHash  string `json:"file_hash"`
+		Timestamp int64  `json:"timestamp"`
+	}
+	if err := json.Unmarshal([]byte(r.Manifest), &manifest); err != nil {
+		return ManifestDigest{}, fmt.Errorf("invalid manifest: %w", err)
+	}
+	if manifest.FileHash == "" {
+		return ManifestDigest{}, errors.New("manifest has no file_hash")
+	}
+	sum := sha256.Sum256([]byte(r.Manifest))
+	return ManifestDigest{
+		FileHash:  manifest.FileHash,
+		Timestamp: manifest.Timestamp,
+		Digest:    hex.EncodeToString(sum[:]),
+	}, nil
+}
+
+type storedManifest struct {
+	record ManifestRecord
+	digest ManifestDigest
+}
+
+// ManifestStore keeps the latest version of each manifest in memory.
+// Signatures are checked but signers are not; the Rust node decides which
+// signers it trusts when it pulls records back.
+type ManifestStore struct {
+	manifests map[string]storedManifest
+	mu        sync.RWMutex
+}
+
+// NewManifestStore creates an empty manifest store
+func NewManifestStore() *ManifestStore {
+	return &ManifestStore{
+		manifests: make(map[string]storedManifest),
+	}
+}
+
+// Digests returns the digest of every manifest held, by file hash
+func (ms *ManifestStore) Digests() []ManifestDigest {
+	ms.mu.RLock()
+	defer ms.mu.RUnlock()
+	digests := make([]ManifestDigest, 0, len(ms.manifests))
+	for _, stored := range ms.manifests {
+		digests = append(digests, stored.digest)
+	}
+	sort.Slice(digests, func(i, j int) bool {
+		return digests[i].FileHash < digests[j].FileHash
+	})
+	return digests
+}
+
+// Put stores a record if it verifies and wins over the version held,
+// reporting whether it was stored
+func (ms *ManifestStore) Put(record ManifestRecord) (bool, error) {
+	if err := record.Verify(); err != nil {
+		return false, err
+	}
+	digest, err := record.Open()
+	if err != nil {
+		return false, err
+	}
+	ms.mu.Lock()
+	defer ms.mu.Unlock()
+	if held, ok := ms.manifests[digest.FileHash]; ok && !digest.Supersedes(held.digest) {
+		return false, nil
+	}
+	ms.manifests[digest.FileHash] = storedManifest{record: record, digest: digest}
+	return true, nil
+}
+
+// Records returns the held records of the named manifests, up to one batch
+func (ms *ManifestStore) Records(fileHashes []string) []ManifestRecord {
+	ms.mu.RLock()
+	defer ms.mu.RUnlock()
+	var records []ManifestRecord
+	bytes := 0
+	for _, hash := range fileHashes {
+		stored, ok := ms.manifests[hash]
+		if !ok {
+			continue
+		}
+		size := len(stored.record.Manifest)
+		if bytes+size > maxManifestBatchBytes && len(records) > 0 {
+			break
+		}
+		bytes += size
+		records = append(records, stored.record)
+	}
+	return records
+}
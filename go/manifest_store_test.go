@@ -0,0 +1,52 @@
+package main
+
+import (
+	"crypto/ed25519"
+	"encoding/hex"
+	"fmt"
+	"testing"
+)
+
+func signedRecord(t *testing.T, key ed25519.PrivateKey, fileHash, fileName string, timestamp int64) ManifestRecord {
+	t.Helper()
+	manifest := fmt.Sprintf(`{"file_hash":%q,"file_name":%q,"timestamp":%d}`, fileHash, fileName, timestamp)
+	signature := ed25519.Sign(key, append([]byte(manifestSigningContext), manifest...))
+	return ManifestRecord{
+		Manifest:  manifest,
+		Signer:    hex.EncodeToString(key.Public().(ed25519.PublicKey)),
+		Signature: hex.EncodeToString(signature),
+	}
+}
+
+func TestManifestStoreKeepsNewestVersion(t *testing.T) {
+	_, key, err := ed25519.GenerateKey(nil)
+	if err != nil {
+		t.Fatalf("GenerateKey failed: %v", err)
+	}
+	store := NewManifestStore()
+
+	if stored, err := store.Put(signedRecord(t, key, "a", "new.txt", 20)); err != nil || !stored {
+		t.Fatalf("Put newer = %v, %v; want stored", stored, err)
+	}
+	if stored, err := store.Put(signedRecord(t, key, "a", "old.txt", 10)); err != nil || stored {
+		t.Fatalf("Put older = %v, %v; want kept out", stored, err)
+	}
+	if stored, err := store.Put(ManifestRecord{Manifest: `{"file_hash":"b","timestamp":1}`}); err != nil || !stored {
+		t.Fatalf("Put unsigned = %v, %v; want stored", stored, err)
+	}
+
+	forged := signedRecord(t, key, "c", "c.txt", 5)
+	forged.Manifest = `{"file_hash":"c","file_name":"evil.txt","timestamp":5}`
+	if _, err := store.Put(forged); err == nil {
+		t.Fatalf("Put accepted a record with a bad signature")
+	}
+
+	digests := store.Digests()
+	if len(digests) != 2 || digests[0].FileHash != "a" || digests[0].Timestamp != 20 {
+		t.Fatalf("Digests = %+v", digests)
+	}
+	records := store.Records([]string{"a", "missing"})
+	if len(records) != 1 || records[0].Manifest != signedRecord(t, key, "a", "new.txt", 20).Manifest {
+		t.Fatalf("Records = %+v", records)
+	}
+}
@@ -2,6 +2,8 @@ package compute
 
 import (
 	"context"
+	"encoding/binary"
+	"math"
 	"sync/atomic"
 	"testing"
 	"time"
@@ -493,3 +495,200 @@ func TestDelegatorHasWorkersCheck(t *testing.T) {
 		t.Errorf("Expected DelegateTask not to be called when no workers, but called %d times", delegateCount)
 	}
 }
+
+// stragglingDelegator runs tasks on "slow" and "fast" workers; the slow one
+// takes slowFor, sending heartbeats if heartbeats is set
+type stragglingDelegator struct {
+	manager    *Manager
+	slowFor    time.Duration
+	heartbeats bool
+	fastCalled atomic.Int32
+}
+
+func (d *stragglingDelegator) DelegateTask(ctx context.Context, workerID string, task *ComputeTask) (*TaskResult, error) {
+	if workerID == "fast" {
+		d.fastCalled.Add(1)
+	} else {
+		done := time.After(d.slowFor)
+		ticker := time.NewTicker(10 * time.Millisecond)
+		defer ticker.Stop()
+	wait:
+		for {
+			select {
+			case <-ctx.Done():
+				return nil, ctx.Err()
+			case <-done:
+				break wait
+			case <-ticker.C:
+				if d.heartbeats {
+					d.manager.Heartbeat(task.ParentJobID, task.ChunkIndex, workerID)
+				}
+			}
+		}
+	}
+	return &TaskResult{
+		TaskID:     task.TaskID,
+		Status:     TaskCompleted,
+		ResultData: []byte(workerID),
+		WorkerID:   workerID,
+	}, nil
+}
+
+func (d *stragglingDelegator) GetAvailableWorkers() []string {
+	return []string{"slow", "fast"}
+}
+
+func (d *stragglingDelegator) HasWorkers() bool {
+	return true
+}
+
+func submitStragglerJob(t *testing.T, d *stragglingDelegator, jobID string) (string, *JobStatus) {
+	t.Helper()
+	config := DefaultConfig()
+	config.ChunkDeadline = 50 * time.Millisecond
+	manager := NewManager(config)
+	t.Cleanup(manager.Close)
+	d.manager = manager
+	manager.SetDelegator(d)
+
+	// One chunk, which goes to "slow" first
+	_, err := manager.SubmitJob(&JobManifest{
+		JobID:        jobID,
+		InputData:    []byte("data"),
+		MinChunkSize: 1,
+		MaxChunkSize: 100,
+		TimeoutSecs:  10,
+	})
+	if err != nil {
+		t.Fatalf("SubmitJob failed: %v", err)
+	}
+
+	result, workerID, err := manager.GetJobResultWithWorker(jobID, 5*time.Second)
+	if err != nil {
+		t.Fatalf("GetJobResultWithWorker failed: %v", err)
+	}
+	if string(result) != workerID {
+		t.Errorf("Expected the result of %s, got %q", workerID, result)
+	}
+	status, err := manager.GetJobStatus(jobID)
+	if err != nil {
+		t.Fatalf("GetJobStatus failed: %v", err)
+	}
+	return workerID, status
+}
+
+func TestStragglerIsRedispatched(t *testing.T) {
+	d := &stragglingDelegator{slowFor: time.Minute}
+	workerID, status := submitStragglerJob(t, d, "test-straggler-job")
+
+	// The fast worker's result wins while the slow one is still running
+	if workerID != "fast" {
+		t.Errorf("Expected the re-dispatched chunk to win, got result from %s", workerID)
+	}
+	if status.Stragglers != 1 || status.Redispatched != 1 {
+		t.Errorf("Expected 1 straggler and 1 re-dispatch, got %d and %d",
+			status.Stragglers, status.Redispatched)
+	}
+}
+
+func TestHeartbeatsExtendChunkDeadline(t *testing.T) {
+	d := &stragglingDelegator{slowFor: 200 * time.Millisecond, heartbeats: true}
+	workerID, status := submitStragglerJob(t, d, "test-heartbeat-job")
+
+	if workerID != "slow" {
+		t.Errorf("Expected the heartbeating worker to finish the chunk, got %s", workerID)
+	}
+	if status.Stragglers != 0 || d.fastCalled.Load() != 0 {
+		t.Errorf("Expected no re-dispatch, got %d stragglers", status.Stragglers)
+	}
+}
+
+func TestSpotCheckSampleIsDeterministic(t *testing.T) {
+	sample := spotCheckSample("job-a", 1000, 0.1)
+	if len(sample) < 50 || len(sample) > 150 {
+		t.Errorf("Expected about 100 of 1000 chunks sampled, got %d", len(sample))
+	}
+	again := spotCheckSample("job-a", 1000, 0.1)
+	for index := range sample {
+		if !again[index] {
+			t.Fatalf("Sample of the same job differs at chunk %d", index)
+		}
+	}
+	if len(spotCheckSample("job-a", 1000, 0)) != 0 {
+		t.Error("Expected no chunks sampled with spot checks disabled")
+	}
+	if len(spotCheckSample("job-a", 10, 1)) != 10 {
+		t.Error("Expected every chunk sampled at rate 1")
+	}
+}
+
+// forgingDelegator returns made-up results from its only worker
+type forgingDelegator struct{}
+
+func (d *forgingDelegator) DelegateTask(ctx context.Context, workerID string, task *ComputeTask) (*TaskResult, error) {
+	return &TaskResult{
+		TaskID:     task.TaskID,
+		Status:     TaskCompleted,
+		ResultData: []byte("forged"),
+		WorkerID:   workerID,
+	}, nil
+}
+
+func (d *forgingDelegator) GetAvailableWorkers() []string {
+	return []string{"forger"}
+}
+
+func (d *forgingDelegator) HasWorkers() bool {
+	return true
+}
+
+// matrixInput encodes a 1x1 by 1x1 matrix multiplication
+func matrixInput(a, b float64) []byte {
+	data := make([]byte, 0, 32)
+	for _, v := range []float64{a, b} {
+		data = binary.BigEndian.AppendUint32(data, 1)
+		data = binary.BigEndian.AppendUint32(data, 1)
+		data = binary.BigEndian.AppendUint64(data, math.Float64bits(v))
+	}
+	return data
+}
+
+func TestFailedSpotCheckEscalatesWorker(t *testing.T) {
+	config := DefaultConfig()
+	config.SpotCheckRate = 1
+	manager := NewManager(config)
+	defer manager.Close()
+	manager.SetDelegator(&forgingDelegator{})
+
+	input := matrixInput(2, 3)
+	_, err := manager.SubmitJob(&JobManifest{
+		JobID:        "test-spot-check-job",
+		InputData:    input,
+		MinChunkSize: 1,
+		MaxChunkSize: 100,
+		TimeoutSecs:  10,
+	})
+	if err != nil {
+		t.Fatalf("SubmitJob failed: %v", err)
+	}
+
+	result, workerID, err := manager.GetJobResultWithWorker("test-spot-check-job", 5*time.Second)
+	if err != nil {
+		t.Fatalf("GetJobResultWithWorker failed: %v", err)
+	}
+	expected, _ := executeMatrixBlockMultiply(input)
+	if string(result) != string(expected) || workerID != "local" {
+		t.Errorf("Expected the forged result replaced by the local one, got %q from %s", result, workerID)
+	}
+
+	status, err := manager.GetJobStatus("test-spot-check-job")
+	if err != nil {
+		t.Fatalf("GetJobStatus failed: %v", err)
+	}
+	if status.SpotChecks != 1 || status.SpotCheckFailures != 1 {
+		t.Errorf("Expected 1 failed spot check, got %d of %d", status.SpotCheckFailures, status.SpotChecks)
+	}
+	if !manager.IsEscalated("forger") {
+		t.Error("Expected the forging worker to be escalated")
+	}
+}
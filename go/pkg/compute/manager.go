@@ -44,8 +44,17 @@ type ComputeConfig struct {
 	MaxChunkSize int64
 	// VerificationMode determines how results are verified
 	VerificationMode VerificationMode
+	// ChunkDeadline is how long a delegated chunk may go without a heartbeat
+	// from its worker before it counts as a straggler and is re-dispatched
+	ChunkDeadline time.Duration
+	// SpotCheckRate is the fraction of remotely executed chunks re-executed
+	// locally to check the worker's result (0 disables spot checks)
+	SpotCheckRate float64
 }
 
+// HeartbeatInterval is how often workers report that a chunk is still running
+const HeartbeatInterval = 10 * time.Second
+
 // DefaultConfig returns a default compute configuration
 func DefaultConfig() ComputeConfig {
 	return ComputeConfig{
@@ -56,6 +65,8 @@ func DefaultConfig() ComputeConfig {
 		MinChunkSize:        1024,        // 1 KB - smaller chunks for testing
 		MaxChunkSize:        1024 * 1024, // 1 MB
 		VerificationMode:    VerificationHash,
+		ChunkDeadline:       3 * HeartbeatInterval,
+		SpotCheckRate:       0.05,
 	}
 }
 
@@ -204,6 +215,15 @@ type JobStatus struct {
 	TotalChunks uint32 `json:"totalChunks"`
 	// EstimatedTimeRemaining is the estimated time remaining in seconds
 	EstimatedTimeRemaining uint32 `json:"estimatedTimeRemaining"`
+	// Stragglers is the number of dispatches that missed their deadline
+	Stragglers uint32 `json:"stragglers"`
+	// Redispatched is the number of chunks sent to another worker after a
+	// failure or a missed deadline
+	Redispatched uint32 `json:"redispatched"`
+	// SpotChecks is the number of remote results re-executed for checking
+	SpotChecks uint32 `json:"spotChecks"`
+	// SpotCheckFailures is the number of spot checks a worker's result failed
+	SpotCheckFailures uint32 `json:"spotCheckFailures"`
 	// Error is the error message if failed
 	Error string `json:"error,omitempty"`
 }
@@ -239,6 +259,8 @@ type Manager struct {
 	workers   map[string]*workerState
 	capacity  ComputeCapacity
 	delegator TaskDelegator
+	// escalated holds the workers that failed a spot check
+	escalated map[string]bool
 	mu        sync.RWMutex
 	ctx       context.Context
 	cancel    context.CancelFunc
@@ -252,6 +274,20 @@ type jobState struct {
 	status     TaskStatus
 	startTime  time.Time
 	lastUpdate time.Time
+	// dispatches holds the in-flight remote attempts of each chunk by worker
+	dispatches   map[uint32]map[string]*chunkDispatch
+	stragglers   uint32
+	redispatched uint32
+	// spotCheckSample holds the chunks to re-execute for spot-checking
+	spotCheckSample   map[uint32]bool
+	spotChecks        uint32
+	spotCheckFailures uint32
+}
+
+// chunkDispatch is one in-flight attempt at a chunk on a remote worker
+type chunkDispatch struct {
+	deadline  time.Time
+	straggler bool
 }
 
 // workerState tracks the internal state of a worker
@@ -270,13 +306,18 @@ type workerState struct {
 func NewManager(config ComputeConfig) *Manager {
 	ctx, cancel := context.WithCancel(context.Background())
 
+	if config.ChunkDeadline <= 0 {
+		config.ChunkDeadline = DefaultConfig().ChunkDeadline
+	}
+
 	return &Manager{
-		config:   config,
-		jobs:     make(map[string]*jobState),
-		workers:  make(map[string]*workerState),
-		capacity: probeCapacity(),
-		ctx:      ctx,
-		cancel:   cancel,
+		config:    config,
+		jobs:      make(map[string]*jobState),
+		workers:   make(map[string]*workerState),
+		escalated: make(map[string]bool),
+		capacity:  probeCapacity(),
+		ctx:       ctx,
+		cancel:    cancel,
 	}
 }
 
@@ -336,6 +377,7 @@ func (m *Manager) SubmitJob(manifest *JobManifest) (string, error) {
 		manifest:   manifest,
 		chunks:     []ChunkInfo{},
 		results:    make(map[uint32]*TaskResult),
+		dispatches: make(map[uint32]map[string]*chunkDispatch),
 		status:     TaskPending,
 		startTime:  time.Now(),
 		lastUpdate: time.Now(),
@@ -380,6 +422,10 @@ func (m *Manager) GetJobStatus(jobID string) (*JobStatus, error) {
 		CompletedChunks:        completed,
 		TotalChunks:            total,
 		EstimatedTimeRemaining: m.estimateTimeRemaining(state, completed, total),
+		Stragglers:             state.stragglers,
+		Redispatched:           state.redispatched,
+		SpotChecks:             state.spotChecks,
+		SpotCheckFailures:      state.spotCheckFailures,
 	}, nil
 }
 
@@ -472,6 +518,28 @@ func (m *Manager) RegisterWorker(workerID string, capacity ComputeCapacity) {
 	}
 }
 
+// Heartbeat records that workerID is still running a chunk, extending the
+// chunk's deadline. It returns false if no such attempt is in flight.
+func (m *Manager) Heartbeat(jobID string, chunkIndex uint32, workerID string) bool {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+
+	if worker, ok := m.workers[workerID]; ok {
+		worker.lastSeen = time.Now()
+	}
+
+	state, exists := m.jobs[jobID]
+	if !exists {
+		return false
+	}
+	dispatch, ok := state.dispatches[chunkIndex][workerID]
+	if !ok {
+		return false
+	}
+	dispatch.deadline = time.Now().Add(m.config.ChunkDeadline)
+	return true
+}
+
 // processJob processes a job (internal)
 func (m *Manager) processJob(jobID string) {
 	m.mu.Lock()
@@ -566,6 +634,7 @@ func (m *Manager) delegateJob(jobID string, manifest *JobManifest) {
 			Status: TaskPending,
 		}
 	}
+	state.spotCheckSample = spotCheckSample(jobID, len(chunks), m.config.SpotCheckRate)
 	delegator := m.delegator
 	m.mu.Unlock()
 
@@ -694,106 +763,203 @@ func (m *Manager) executeChunk(jobID string, chunkIndex uint32, manifest *JobMan
 	m.mu.Unlock()
 }
 
+// remoteAttempt is the outcome of one dispatch of a chunk
+type remoteAttempt struct {
+	workerID string
+	result   *TaskResult
+	err      error
+}
+
 // executeChunkRemote executes a chunk on a remote worker
-// Handles TOCTOU by checking worker availability and retrying with fresh workers if needed
+//
+// Every dispatch has a deadline, extended by heartbeats from its worker. A
+// dispatch that fails is retried on a fresh worker; one that misses its
+// deadline is a straggler, and the chunk is also sent to another worker
+// while it keeps running. The first completed result wins and the attempts
+// still running are cancelled. If every attempt fails, the chunk runs locally.
 func (m *Manager) executeChunkRemote(jobID string, chunkIndex uint32, manifest *JobManifest, data []byte, workerID string, delegator TaskDelegator) {
 	start := time.Now()
-	maxRetries := 3
-	currentWorkerID := workerID
-
-	for attempt := 0; attempt < maxRetries; attempt++ {
-		// Check if worker is still available (mitigate TOCTOU)
-		if delegator.HasWorkers() {
-			workers := delegator.GetAvailableWorkers()
-			workerAvailable := false
-			for _, w := range workers {
-				if w == currentWorkerID {
-					workerAvailable = true
-					break
-				}
-			}
-			if !workerAvailable && len(workers) > 0 {
-				// Worker disconnected, select a new one
-				log.Printf("🔄 [COMPUTE] Worker %s disconnected, selecting new worker for chunk %d",
-					truncateID(currentWorkerID, 12), chunkIndex)
-				currentWorkerID = workers[int(chunkIndex)%len(workers)]
-			}
+	maxDispatches := 1 + m.config.RetryCount
+
+	timeout := time.Duration(manifest.TimeoutSecs) * time.Second
+	if timeout == 0 {
+		timeout = m.config.DefaultTimeout
+	}
+	ctx, cancel := context.WithTimeout(m.ctx, timeout)
+	defer cancel()
+
+	task := &ComputeTask{
+		TaskID:          fmt.Sprintf("%s:%d", jobID, chunkIndex),
+		ParentJobID:     jobID,
+		ChunkIndex:      chunkIndex,
+		WASMModule:      manifest.WASMModule,
+		InputData:       data,
+		FunctionName:    "matrix_block_multiply",
+		DelegationDepth: 0,
+		TimeoutMs:       uint64(timeout.Milliseconds()),
+	}
+
+	// Buffered so that attempts finishing after the winner never block
+	attempts := make(chan remoteAttempt, maxDispatches)
+	dispatched := 0
+	dispatch := func(wID string) {
+		dispatched++
+		m.mu.Lock()
+		state := m.jobs[jobID]
+		if state.dispatches[chunkIndex] == nil {
+			state.dispatches[chunkIndex] = make(map[string]*chunkDispatch)
+		}
+		state.dispatches[chunkIndex][wID] = &chunkDispatch{deadline: time.Now().Add(m.config.ChunkDeadline)}
+		if dispatched > 1 {
+			state.redispatched++
 		}
+		m.mu.Unlock()
 
-		shortID := truncateID(currentWorkerID, 12)
 		log.Printf("📤 [COMPUTE] Delegating chunk %d to worker %s (%d bytes, attempt %d)",
-			chunkIndex, shortID, len(data), attempt+1)
+			chunkIndex, truncateID(wID, 12), len(data), dispatched)
+		go func() {
+			result, err := delegator.DelegateTask(ctx, wID, task)
+			attempts <- remoteAttempt{workerID: wID, result: result, err: err}
+		}()
+	}
 
-		// Create compute task for remote execution
-		task := &ComputeTask{
-			TaskID:          fmt.Sprintf("%s:%d", jobID, chunkIndex),
-			ParentJobID:     jobID,
-			ChunkIndex:      chunkIndex,
-			WASMModule:      manifest.WASMModule,
-			InputData:       data,
-			FunctionName:    "matrix_block_multiply",
-			DelegationDepth: 0,
-			TimeoutMs:       uint64(manifest.TimeoutSecs) * 1000,
-		}
+	// Check if the worker is still available (mitigate TOCTOU)
+	if wID := m.pickWorker(jobID, chunkIndex, workerID, delegator); wID != "" {
+		dispatch(wID)
+	}
 
-		// Execute on remote worker via delegator
-		ctx, cancel := context.WithTimeout(m.ctx, time.Duration(manifest.TimeoutSecs)*time.Second)
-		remoteResult, err := delegator.DelegateTask(ctx, currentWorkerID, task)
-		cancel()
-
-		if err != nil {
-			log.Printf("❌ [COMPUTE] Remote chunk %d failed on %s: %v (attempt %d)",
-				chunkIndex, shortID, err, attempt+1)
-
-			// Refresh worker list for next attempt
-			if delegator.HasWorkers() {
-				workers := delegator.GetAvailableWorkers()
-				if len(workers) > 0 {
-					// Pick a different worker for retry
-					currentWorkerID = workers[(int(chunkIndex)+attempt+1)%len(workers)]
-					continue
-				}
+	checkEvery := m.config.ChunkDeadline / 4
+	if checkEvery < 10*time.Millisecond {
+		checkEvery = 10 * time.Millisecond
+	}
+	ticker := time.NewTicker(checkEvery)
+	defer ticker.Stop()
+
+	for m.inFlight(jobID, chunkIndex) > 0 {
+		select {
+		case attempt := <-attempts:
+			m.mu.Lock()
+			delete(m.jobs[jobID].dispatches[chunkIndex], attempt.workerID)
+			m.mu.Unlock()
+
+			shortID := truncateID(attempt.workerID, 12)
+			if attempt.err == nil && attempt.result.Status == TaskCompleted {
+				log.Printf("✅ [COMPUTE] Chunk %d completed by worker %s in %dms: %d bytes",
+					chunkIndex, shortID, attempt.result.ExecutionTimeMs, len(attempt.result.ResultData))
+
+				result := attempt.result
+				result.WorkerID = attempt.workerID
+				result.ExecutionTimeMs = uint64(time.Since(start).Milliseconds())
+				result = m.spotCheck(jobID, chunkIndex, data, result)
+
+				m.mu.Lock()
+				state := m.jobs[jobID]
+				state.results[chunkIndex] = result
+				state.chunks[chunkIndex].Status = TaskCompleted
+				state.chunks[chunkIndex].AssignedWorker = result.WorkerID
+				delete(state.dispatches, chunkIndex)
+				state.lastUpdate = time.Now()
+				m.mu.Unlock()
+				return
 			}
 
-			// No more workers available, fall back to local execution
-			log.Printf("🔄 [COMPUTE] No workers available, falling back to local execution for chunk %d", chunkIndex)
-			m.executeChunk(jobID, chunkIndex, manifest, data)
-			return
-		}
+			if attempt.err != nil {
+				log.Printf("❌ [COMPUTE] Remote chunk %d failed on %s: %v", chunkIndex, shortID, attempt.err)
+			} else {
+				log.Printf("❌ [COMPUTE] Remote chunk %d returned failure on %s: %s",
+					chunkIndex, shortID, attempt.result.Error)
+			}
 
-		if remoteResult.Status == TaskCompleted {
-			log.Printf("✅ [COMPUTE] Chunk %d completed by worker %s in %dms: %d bytes",
-				chunkIndex, shortID, remoteResult.ExecutionTimeMs, len(remoteResult.ResultData))
+			// Try a fresh worker unless another attempt is still running
+			if dispatched < maxDispatches && m.inFlight(jobID, chunkIndex) == 0 {
+				if wID := m.pickWorker(jobID, chunkIndex, "", delegator); wID != "" {
+					dispatch(wID)
+				}
+			}
 
-			result := remoteResult
-			result.WorkerID = currentWorkerID
-			result.ExecutionTimeMs = uint64(time.Since(start).Milliseconds())
+		case <-ticker.C:
+			stragglers := m.markStragglers(jobID, chunkIndex)
+			for _, straggler := range stragglers {
+				log.Printf("🐢 [COMPUTE] Chunk %d on worker %s missed its deadline", chunkIndex, truncateID(straggler, 12))
+				if dispatched >= maxDispatches {
+					continue
+				}
+				if wID := m.pickWorker(jobID, chunkIndex, "", delegator); wID != "" {
+					dispatch(wID)
+				}
+			}
 
+		case <-ctx.Done():
+			log.Printf("⏱️ [COMPUTE] Chunk %d timed out on remote workers", chunkIndex)
 			m.mu.Lock()
-			state := m.jobs[jobID]
-			state.results[chunkIndex] = result
-			state.chunks[chunkIndex].Status = TaskCompleted
-			state.lastUpdate = time.Now()
+			delete(m.jobs[jobID].dispatches, chunkIndex)
 			m.mu.Unlock()
-			return
 		}
+	}
+
+	// All remote attempts failed, fall back to local execution
+	log.Printf("🔄 [COMPUTE] All remote attempts failed, falling back to local execution for chunk %d", chunkIndex)
+	m.executeChunk(jobID, chunkIndex, manifest, data)
+}
 
-		log.Printf("❌ [COMPUTE] Remote chunk %d returned failure: %s (attempt %d)",
-			chunkIndex, remoteResult.Error, attempt+1)
+// pickWorker selects a worker for a chunk that is not already running it,
+// preferring the given worker while it is still available. It returns an
+// empty string if there is none.
+func (m *Manager) pickWorker(jobID string, chunkIndex uint32, preferred string, delegator TaskDelegator) string {
+	if !delegator.HasWorkers() {
+		return ""
+	}
+	workers := delegator.GetAvailableWorkers()
 
-		// Try to get a different worker for retry
-		if delegator.HasWorkers() {
-			workers := delegator.GetAvailableWorkers()
-			if len(workers) > 0 {
-				currentWorkerID = workers[(int(chunkIndex)+attempt+1)%len(workers)]
-				continue
+	m.mu.RLock()
+	defer m.mu.RUnlock()
+	running := m.jobs[jobID].dispatches[chunkIndex]
+
+	if preferred != "" {
+		if _, busy := running[preferred]; !busy {
+			for _, w := range workers {
+				if w == preferred {
+					return preferred
+				}
 			}
+			log.Printf("🔄 [COMPUTE] Worker %s disconnected, selecting new worker for chunk %d",
+				truncateID(preferred, 12), chunkIndex)
 		}
 	}
 
-	// All retries exhausted, fall back to local execution
-	log.Printf("🔄 [COMPUTE] All remote attempts failed, falling back to local execution for chunk %d", chunkIndex)
-	m.executeChunk(jobID, chunkIndex, manifest, data)
+	for i := range workers {
+		w := workers[(int(chunkIndex)+i)%len(workers)]
+		if _, busy := running[w]; !busy {
+			return w
+		}
+	}
+	return ""
+}
+
+// inFlight returns the number of running remote attempts at a chunk
+func (m *Manager) inFlight(jobID string, chunkIndex uint32) int {
+	m.mu.RLock()
+	defer m.mu.RUnlock()
+	return len(m.jobs[jobID].dispatches[chunkIndex])
+}
+
+// markStragglers flags the attempts at a chunk that just missed their
+// deadline and returns their workers
+func (m *Manager) markStragglers(jobID string, chunkIndex uint32) []string {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+
+	state := m.jobs[jobID]
+	now := time.Now()
+	var stragglers []string
+	for wID, dispatch := range state.dispatches[chunkIndex] {
+		if !dispatch.straggler && now.After(dispatch.deadline) {
+			dispatch.straggler = true
+			state.stragglers++
+			stragglers = append(stragglers, wID)
+		}
+	}
+	return stragglers
 }
 
 // ExecuteMatrixBlockMultiply executes matrix block multiplication (exported for compute protocol)
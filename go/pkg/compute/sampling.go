@@ -0,0 +1,87 @@
+package compute
+
+import (
+	"crypto/sha256"
+	"encoding/binary"
+	"log"
+	"math/rand"
+	"time"
+)
+
+// spotCheckSample picks the chunks of a job to re-execute for spot-checking
+//
+// Each chunk is drawn with probability rate from a generator seeded with the
+// job ID, so the same job always samples the same chunks and a worker cannot
+// tell from the job which of its results will be checked.
+func spotCheckSample(jobID string, total int, rate float64) map[uint32]bool {
+	sample := make(map[uint32]bool)
+	if rate <= 0 {
+		return sample
+	}
+
+	seed := sha256.Sum256([]byte(jobID))
+	rng := rand.New(rand.NewSource(int64(binary.BigEndian.Uint64(seed[:8]))))
+	for i := 0; i < total; i++ {
+		if rng.Float64() < rate {
+			sample[uint32(i)] = true
+		}
+	}
+	return sample
+}
+
+// IsEscalated returns true if workerID failed a spot check, so that every
+// result it returns is re-executed
+func (m *Manager) IsEscalated(workerID string) bool {
+	m.mu.RLock()
+	defer m.mu.RUnlock()
+	return m.escalated[workerID]
+}
+
+// spotCheck re-executes a chunk returned by a remote worker if the chunk was
+// sampled or the worker is escalated, and returns the result to keep. A
+// worker whose result does not match fails the check: it is escalated to
+// full redundancy and the local result is kept instead.
+func (m *Manager) spotCheck(jobID string, chunkIndex uint32, data []byte, result *TaskResult) *TaskResult {
+	m.mu.RLock()
+	state := m.jobs[jobID]
+	check := state.spotCheckSample[chunkIndex] || m.escalated[result.WorkerID]
+	m.mu.RUnlock()
+	if !check {
+		return result
+	}
+
+	start := time.Now()
+	expected, err := executeMatrixBlockMultiply(data)
+	if err != nil {
+		// The input itself is bad, so there is nothing to hold the worker to
+		log.Printf("⚠️ [COMPUTE] Spot check of chunk %d could not run: %v", chunkIndex, err)
+		return result
+	}
+	passed := hashData(expected) == hashData(result.ResultData)
+
+	m.mu.Lock()
+	defer m.mu.Unlock()
+	state.spotChecks++
+	if passed {
+		log.Printf("🔍 [COMPUTE] Chunk %d from worker %s passed its spot check",
+			chunkIndex, truncateID(result.WorkerID, 12))
+		return result
+	}
+
+	log.Printf("🚨 [COMPUTE] Chunk %d from worker %s failed its spot check, verifying all its results from now on",
+		chunkIndex, truncateID(result.WorkerID, 12))
+	state.spotCheckFailures++
+	m.escalated[result.WorkerID] = true
+	if worker, ok := m.workers[result.WorkerID]; ok {
+		worker.trustScore = 0
+	}
+
+	return &TaskResult{
+		TaskID:          result.TaskID,
+		Status:          TaskCompleted,
+		ResultData:      expected,
+		ResultHash:      hashData(expected),
+		ExecutionTimeMs: result.ExecutionTimeMs + uint64(time.Since(start).Milliseconds()),
+		WorkerID:        "local",
+	}
+}
@@ -88,9 +88,13 @@ def list_nodes(host, port, schema):
     nodes = client.get_all_nodes()
     click.echo(f"\nFound {len(nodes)} nodes:\n")
     for node in nodes:
+        location = ""
+        if node.get("asn") or node.get("country"):
+            asn = f"AS{node['asn']}" if node.get("asn") else "AS?"
+            location = f", location={node.get('country') or '??'}/{asn}"
         click.echo(
             f"  Node {node['id']}: latency={node['latencyMs']:.2f}ms, "
-            f"threat={node['threatScore']:.3f}, status={node['status']}"
+            f"threat={node['threatScore']:.3f}, status={node['status']}{location}"
         )
 
     client.disconnect()
@@ -9,7 +9,7 @@ import threading
 import time
 import sys
 import asyncio
-from typing import Optional, List, Dict, Tuple, Any
+from typing import Optional, List, Dict, Tuple, Any, Callable
 from pathlib import Path
 from concurrent.futures import ThreadPoolExecutor, Future
 
@@ -190,6 +190,9 @@ class GoNodeClient:
                         "status": node.status,
                         "latencyMs": node.latencyMs,
                         "threatScore": node.threatScore,
+                        "country": node.country or None,
+                        "asn": node.asn or None,
+                        "asOrg": node.asOrg or None,
                     }
                 )
             return nodes
@@ -587,6 +590,446 @@ class GoNodeClient:
             logger.error(f"Error in download: {e}")
             return None
 
+    @staticmethod
+    def _progress_to_dict(progress) -> Dict:
+        """Convert a TransferProgress struct into a plain dict."""
+        return {
+            "transferId": progress.transferId,
+            "isUpload": progress.isUpload,
+            "shardsDone": progress.shardsDone,
+            "shardsTotal": progress.shardsTotal,
+            "bytesDone": progress.bytesDone,
+            "bytesTotal": progress.bytesTotal or None,
+            "bytesPerSec": progress.bytesPerSec,
+            "etaSecs": progress.etaSecs if progress.etaSecs >= 0 else None,
+            "percent": progress.percent,
+            "warning": progress.warning or None,
+            "finished": progress.finished,
+            "success": progress.success,
+        }
+
+    def _make_progress_listener(self, on_progress: Callable[[Dict], None]) -> Any:
+        """Build a ProgressListener capability that forwards updates to on_progress."""
+        schema = self.schema
+        to_dict = self._progress_to_dict
+
+        class _ProgressListener(schema.ProgressListener.Server):
+            async def onProgress(self, progress, **kwargs):
+                update = to_dict(progress)
+                if update["warning"]:
+                    logger.warning(
+                        f"Transfer {update['transferId']}: {update['warning']}"
+                    )
+                try:
+                    on_progress(update)
+                except Exception as e:
+                    # A failing callback must not abort the transfer
+                    logger.error(f"Progress callback raised: {e}")
+
+        return _ProgressListener()
+
+    def upload_with_progress(
+        self,
+        data: bytes,
+        target_peers: List[int],
+        on_progress: Callable[[Dict], None],
+        timeout: float = 300.0,
+    ) -> Optional[Dict]:
+        """
+        Upload like upload(), receiving progress updates while it runs.
+
+        Args:
+            data: Raw data to upload
+            target_peers: List of peer IDs to distribute shards to
+            on_progress: Called with a dict per update (percent, bytesPerSec,
+                etaSecs, warning, finished, ...) on the RPC event loop thread
+            timeout: Seconds to wait for the upload to complete
+
+        Returns:
+            File manifest dictionary with shard locations, or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        if not target_peers or len(target_peers) == 0:
+            raise ValueError("target_peers cannot be empty or None")
+
+        async def _async_upload_with_progress():
+            request = self.schema.UploadRequest.new_message()
+            request.data = data
+            peers_list = request.init("targetPeers", len(target_peers))
+            for i, peer_id in enumerate(target_peers):
+                peers_list[i] = peer_id
+
+            listener = self._make_progress_listener(on_progress)
+            result = await self.service.uploadWithProgress(request, listener)
+
+            if not result.response.success:
+                logger.error(f"Upload failed: {result.response.errorMsg}")
+                return None
+
+            manifest = result.response.manifest
+            return {
+                "fileHash": manifest.fileHash,
+                "fileName": manifest.fileName,
+                "fileSize": manifest.fileSize,
+                "shardCount": manifest.shardCount,
+                "parityCount": manifest.parityCount,
+                "shardLocations": [
+                    {"shardIndex": loc.shardIndex, "peerId": loc.peerId}
+                    for loc in manifest.shardLocations
+                ],
+                "timestamp": manifest.timestamp,
+                "ttl": manifest.ttl,
+            }
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(
+                _async_upload_with_progress(), self._loop
+            )
+            return future.result(timeout=timeout)
+        except Exception as e:
+            logger.error(f"Error in upload with progress: {e}")
+            return None
+
+    def download_with_progress(
+        self,
+        shard_locations: List[Dict],
+        on_progress: Callable[[Dict], None],
+        file_hash: str = "",
+        timeout: float = 300.0,
+    ) -> Optional[Tuple[bytes, int]]:
+        """
+        Download like download(), receiving progress updates while it runs.
+
+        Args:
+            shard_locations: List of dicts with 'shardIndex' and 'peerId' keys
+            on_progress: Called with a dict per update on the RPC event loop thread
+            file_hash: Optional file hash for cache lookup
+            timeout: Seconds to wait for the download to complete
+
+        Returns:
+            Tuple of (data: bytes, bytes_downloaded: int), or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_download_with_progress():
+            request = self.schema.DownloadRequest.new_message()
+            request.fileHash = file_hash
+            locs_list = request.init("shardLocations", len(shard_locations))
+            for i, loc in enumerate(shard_locations):
+                if "shardIndex" not in loc or "peerId" not in loc:
+                    raise ValueError(
+                        f"shard_locations[{i}] missing required keys 'shardIndex' or 'peerId'"
+                    )
+                locs_list[i].shardIndex = loc["shardIndex"]
+                locs_list[i].peerId = loc["peerId"]
+
+            listener = self._make_progress_listener(on_progress)
+            result = await self.service.downloadWithProgress(request, listener)
+
+            if not result.response.success:
+                logger.error(f"Download failed: {result.response.errorMsg}")
+                return None
+
+            return bytes(result.response.data), result.response.bytesDownloaded
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(
+                _async_download_with_progress(), self._loop
+            )
+            return future.result(timeout=timeout)
+        except Exception as e:
+            logger.error(f"Error in download with progress: {e}")
+            return None
+
+    def put_many(
+        self,
+        items: List[bytes],
+        target_peers: List[int],
+        max_concurrency: int = 4,
+        timeout: float = 300.0,
+    ) -> List[Optional[Dict]]:
+        """
+        Upload several payloads in one round trip.
+
+        Args:
+            items: Raw data for each upload
+            target_peers: Peer IDs to distribute shards to (shared by all items)
+            max_concurrency: Uploads the node runs at once
+            timeout: Seconds to wait for the whole batch
+
+        Returns:
+            One manifest dictionary per item, in order; None for failed items
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        if not target_peers or len(target_peers) == 0:
+            raise ValueError("target_peers cannot be empty or None")
+
+        async def _async_put_many():
+            request = self.service.putMany_request()
+            requests = request.init("requests", len(items))
+            for i, data in enumerate(items):
+                requests[i].data = data
+                peers_list = requests[i].init("targetPeers", len(target_peers))
+                for j, peer_id in enumerate(target_peers):
+                    peers_list[j] = peer_id
+            request.maxConcurrency = max_concurrency
+
+            result = await request.send()
+
+            manifests: List[Optional[Dict]] = []
+            for i, response in enumerate(result.responses):
+                if not response.success:
+                    logger.error(f"Upload {i} failed: {response.errorMsg}")
+                    manifests.append(None)
+                    continue
+                manifest = response.manifest
+                manifests.append(
+                    {
+                        "fileHash": manifest.fileHash,
+                        "fileName": manifest.fileName,
+                        "fileSize": manifest.fileSize,
+                        "shardCount": manifest.shardCount,
+                        "parityCount": manifest.parityCount,
+                        "shardLocations": [
+                            {"shardIndex": loc.shardIndex, "peerId": loc.peerId}
+                            for loc in manifest.shardLocations
+                        ],
+                        "timestamp": manifest.timestamp,
+                        "ttl": manifest.ttl,
+                    }
+                )
+            return manifests
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_put_many(), self._loop)
+            return future.result(timeout=timeout)
+        except Exception as e:
+            logger.error(f"Error in batch upload: {e}")
+            return [None] * len(items)
+
+    def get_many(
+        self,
+        items: List[Dict],
+        max_concurrency: int = 4,
+        timeout: float = 300.0,
+    ) -> List[Optional[Tuple[bytes, int]]]:
+        """
+        Download several files in one round trip.
+
+        Args:
+            items: Dicts with 'shardLocations' (as for download()) and an
+                optional 'fileHash'
+            max_concurrency: Downloads the node runs at once
+            timeout: Seconds to wait for the whole batch
+
+        Returns:
+            One (data, bytes_downloaded) tuple per item, in order; None for
+            failed items
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_get_many():
+            request = self.service.getMany_request()
+            requests = request.init("requests", len(items))
+            for i, item in enumerate(items):
+                shard_locations = item.get("shardLocations", [])
+                requests[i].fileHash = item.get("fileHash", "")
+                locs_list = requests[i].init("shardLocations", len(shard_locations))
+                for j, loc in enumerate(shard_locations):
+                    if "shardIndex" not in loc or "peerId" not in loc:
+                        raise ValueError(
+                            f"items[{i}] shard location {j} missing 'shardIndex' or 'peerId'"
+                        )
+                    locs_list[j].shardIndex = loc["shardIndex"]
+                    locs_list[j].peerId = loc["peerId"]
+            request.maxConcurrency = max_concurrency
+
+            result = await request.send()
+
+            downloads: List[Optional[Tuple[bytes, int]]] = []
+            for i, response in enumerate(result.responses):
+                if not response.success:
+                    logger.error(f"Download {i} failed: {response.errorMsg}")
+                    downloads.append(None)
+                    continue
+                downloads.append((bytes(response.data), response.bytesDownloaded))
+            return downloads
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_get_many(), self._loop)
+            return future.result(timeout=timeout)
+        except Exception as e:
+            logger.error(f"Error in batch download: {e}")
+            return [None] * len(items)
+
+    def get_metrics_history(
+        self,
+        from_unix: int,
+        to_unix: int,
+        operation: str = "",
+    ) -> Optional[List[Dict]]:
+        """
+        Get per-minute metrics aggregates persisted by the node.
+
+        Args:
+            from_unix: Start of the range (Unix seconds)
+            to_unix: End of the range (Unix seconds), inclusive
+            operation: Only return this operation; all operations if empty
+
+        Returns:
+            List of point dicts ordered by minute, or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_get_history():
+            result = await self.service.getMetricsHistory(operation, from_unix, to_unix)
+            if not result.success:
+                logger.error(f"Metrics history query failed: {result.errorMsg}")
+                return None
+            return [
+                {
+                    "minute": point.minute,
+                    "operation": point.operation,
+                    "samples": point.samples,
+                    "avgLatencyMs": point.avgLatencyMs,
+                    "p50LatencyMs": point.p50LatencyMs,
+                    "p95LatencyMs": point.p95LatencyMs,
+                    "p99LatencyMs": point.p99LatencyMs,
+                    "bytes": point.bytes,
+                    "throughputMbps": point.throughputMbps,
+                    "cacheHitRate": point.cacheHitRate if point.cacheHitRate >= 0 else None,
+                }
+                for point in result.points
+            ]
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_get_history(), self._loop)
+            return future.result(timeout=10.0)
+        except Exception as e:
+            logger.error(f"Error getting metrics history: {e}")
+            return None
+
+    def get_active_transfers(self) -> Optional[List[Dict]]:
+        """
+        Get rate, ETA and counts of every upload and download in flight.
+
+        Returns:
+            List of progress dicts (same keys as on_progress updates), or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_get_transfers():
+            result = await self.service.getActiveTransfers()
+            return [self._progress_to_dict(progress) for progress in result.transfers]
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_get_transfers(), self._loop)
+            return future.result(timeout=10.0)
+        except Exception as e:
+            logger.error(f"Error getting active transfers: {e}")
+            return None
+
+    def set_limit(self, name: str, limit: int) -> bool:
+        """
+        Change a concurrency limit on the node without restarting it.
+
+        Args:
+            name: "uploads", "downloads", "heal" or "dht_queries"
+            limit: Operations allowed at once (at least 1)
+
+        Returns:
+            True if the limit was changed
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_set_limit():
+            result = await self.service.setLimit(name, limit)
+            if not result.success:
+                logger.error(f"Setting limit {name} failed: {result.errorMsg}")
+            return result.success
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_set_limit(), self._loop)
+            return future.result(timeout=10.0)
+        except Exception as e:
+            logger.error(f"Error setting limit {name}: {e}")
+            return False
+
+    def get_limits(self) -> Optional[List[Dict]]:
+        """
+        Get the limit, current and peak usage of every concurrency limit.
+
+        Returns:
+            List of dicts with name, limit, inUse, peak and waiting, or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_get_limits():
+            result = await self.service.getLimits()
+            return [
+                {
+                    "name": limit.name,
+                    "limit": limit.limit,
+                    "inUse": limit.inUse,
+                    "peak": limit.peak,
+                    "waiting": limit.waiting,
+                }
+                for limit in result.limits
+            ]
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_get_limits(), self._loop)
+            return future.result(timeout=10.0)
+        except Exception as e:
+            logger.error(f"Error getting limits: {e}")
+            return None
+
+    def get_node_stats(self) -> Optional[Dict]:
+        """
+        Get the health of the node's dependencies.
+
+        Returns:
+            Dict with a "goClient" dict (connected, circuit, poolSize,
+            healthyConnections, requests, failures, timeouts, reconnects,
+            circuitTrips), or None on error
+        """
+        if not self._connected:
+            raise RuntimeError("Not connected to Go node")
+
+        async def _async_get_stats():
+            result = await self.service.getNodeStats()
+            go_client = result.stats.goClient
+            return {
+                "goClient": {
+                    "connected": go_client.connected,
+                    "circuit": go_client.circuit,
+                    "poolSize": go_client.poolSize,
+                    "healthyConnections": go_client.healthyConnections,
+                    "requests": go_client.requests,
+                    "failures": go_client.failures,
+                    "timeouts": go_client.timeouts,
+                    "reconnects": go_client.reconnects,
+                    "circuitTrips": go_client.circuitTrips,
+                }
+            }
+
+        try:
+            future = asyncio.run_coroutine_threadsafe(_async_get_stats(), self._loop)
+            return future.result(timeout=10.0)
+        except Exception as e:
+            logger.error(f"Error getting node stats: {e}")
+            return None
+
     # ========================================================================
     # Streaming Methods (Go handles all networking per Golden Rule)
     # ========================================================================
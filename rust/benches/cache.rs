@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::Cache;
+use tokio::runtime::Runtime;
+
+const SHARD_SIZE: usize = 256 * 1024;
+
+fn bench_put_get(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Cache::new(dir.path(), 1024, 1024 * 1024 * 1024).unwrap();
+    let shard = vec![7u8; SHARD_SIZE];
+
+    let mut group = c.benchmark_group("cache");
+    group.throughput(Throughput::Bytes(SHARD_SIZE as u64));
+    group.bench_function("put_shard", |b| {
+        let mut index = 0;
+        b.to_async(&rt).iter(|| {
+            index = (index + 1) % 512;
+            let (cache, shard) = (&cache, shard.clone());
+            async move { cache.put_shard("bench", index, shard).await.unwrap() }
+        })
+    });
+
+    rt.block_on(async {
+        for index in 0..512 {
+            cache.put_shard("warm", index, shard.clone()).await.unwrap();
+        }
+    });
+    group.bench_function("get_shard_hit", |b| {
+        let mut index = 0;
+        b.to_async(&rt).iter(|| {
+            index = (index + 1) % 512;
+            let cache = &cache;
+            async move { black_box(cache.get_shard("warm", index).await.unwrap()) }
+        })
+    });
+    group.bench_function("get_shard_miss", |b| {
+        let cache = &cache;
+        b.to_async(&rt)
+            .iter(|| async move { black_box(cache.get_shard("absent", 0).await) })
+    });
+    group.finish();
+}
+
+fn bench_eviction(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("cache_eviction");
+    for capacity in [64, 1024] {
+        let dir = tempfile::tempdir().unwrap();
+        // Full at `capacity` shards, so every put past that evicts
+        let cache = Cache::new(dir.path(), capacity, capacity * 4096).unwrap();
+        let mut index = 0;
+        group.bench_function(BenchmarkId::from_parameter(capacity), |b| {
+            b.to_async(&rt).iter(|| {
+                index += 1;
+                let cache = &cache;
+                async move {
+                    cache
+                        .put_shard("churn", index, vec![0; 4096])
+                        .await
+                        .unwrap()
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_put_get, bench_eviction);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::types::CompressionAlgorithm;
+use pangea_ces::{CesConfig, CesPipeline};
+
+const SIZES: [usize; 3] = [64 * 1024, 1024 * 1024, 16 * 1024 * 1024];
+
+/// Half repetitive text, half noise, so compression does real work
+fn sample(size: usize) -> Vec<u8> {
+    let text = b"pangea shards travel far ".repeat(size / 50 + 1);
+    let mut data: Vec<u8> = text.into_iter().take(size / 2).collect();
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    while data.len() < size {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.push(state as u8);
+    }
+    data
+}
+
+fn pipeline(algorithm: CompressionAlgorithm) -> CesPipeline {
+    CesPipeline::new(
+        CesConfig::default()
+            .with_compression(algorithm, 3)
+            .with_shards(8, 4),
+    )
+    .with_key([1; 32])
+}
+
+fn bench_process(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ces_process");
+    group.sample_size(10);
+    for algorithm in CompressionAlgorithm::ALL {
+        let ces = pipeline(algorithm);
+        for size in SIZES {
+            let data = sample(size);
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", algorithm), size),
+                &data,
+                |b, data| b.iter(|| ces.process_at_level(black_box(data), 3).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_reconstruct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ces_reconstruct");
+    group.sample_size(10);
+    let ces = pipeline(CompressionAlgorithm::Zstd);
+    for size in SIZES {
+        let shards = ces.process_at_level(&sample(size), 3).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        let complete: Vec<Option<Vec<u8>>> = shards.iter().cloned().map(Some).collect();
+        group.bench_with_input(
+            BenchmarkId::new("complete", size),
+            &complete,
+            |b, shards| b.iter(|| ces.reconstruct(black_box(shards.clone())).unwrap()),
+        );
+
+        // Worst case: every parity shard is needed to rebuild lost data
+        let mut degraded = complete.clone();
+        for slot in degraded.iter_mut().take(ces.parity_count()) {
+            *slot = None;
+        }
+        group.bench_with_input(
+            BenchmarkId::new("degraded", size),
+            &degraded,
+            |b, shards| b.iter(|| ces.reconstruct(black_box(shards.clone())).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_process, bench_reconstruct);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::{ComputeConfig, ComputeEngine, ComputeTask, VerificationMode};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+const TASKS: usize = 16;
+const INPUT_SIZE: usize = 4 * 1024 * 1024;
+
+/// A batch of tasks submitted at once; throughput should grow with the
+/// number of workers up to the core count
+fn bench_concurrent_tasks(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let input = vec![7u8; INPUT_SIZE];
+
+    let mut group = c.benchmark_group("compute_workers");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((TASKS * INPUT_SIZE) as u64));
+    for workers in [1, 2, 4, 8] {
+        let engine = Arc::new(
+            ComputeEngine::new(ComputeConfig {
+                worker_threads: workers,
+                simulation_mode: true,
+                verification_mode: VerificationMode::Merkle,
+                ..ComputeConfig::default()
+            })
+            .unwrap(),
+        );
+        group.bench_function(BenchmarkId::from_parameter(workers), |b| {
+            b.to_async(&rt).iter(|| {
+                let tasks: Vec<_> = (0..TASKS)
+                    .map(|i| {
+                        let engine = engine.clone();
+                        let task = ComputeTask::new(
+                            "bench".into(),
+                            i as u32,
+                            b"module".to_vec(),
+                            input.clone(),
+                        );
+                        tokio::spawn(async move { engine.process_task(task).await.unwrap() })
+                    })
+                    .collect();
+                async move {
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_tasks);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::dcdn::types::{FecGroupId, Packet};
+use pangea_ces::{FecAlgorithm, FecEngine, FecEngineConfig, FecGroup};
+
+const PACKET_SIZE: usize = 1200;
+
+fn config(block_size: usize, parity_count: usize) -> FecEngineConfig {
+    FecEngineConfig {
+        block_size,
+        parity_count,
+        algorithm: FecAlgorithm::ReedSolomon,
+    }
+}
+
+fn packets(count: usize) -> Vec<Packet> {
+    (0..count)
+        .map(|index| Packet {
+            group_id: FecGroupId(1),
+            index,
+            data: Bytes::from(vec![index as u8; PACKET_SIZE]),
+        })
+        .collect()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fec_encode");
+    for (data, parity) in [(8, 2), (16, 4), (32, 8)] {
+        let config = config(data, parity);
+        let engine = FecEngine::new(config.clone());
+        let packets = packets(data);
+        group.throughput(Throughput::Bytes((data * PACKET_SIZE) as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}+{}", data, parity)),
+            &packets,
+            |b, packets| b.iter(|| engine.encode(black_box(packets), &config).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fec_decode");
+    for (data, parity) in [(8, 2), (16, 4), (32, 8)] {
+        let config = config(data, parity);
+        let engine = FecEngine::new(config.clone());
+        let packets = packets(data);
+        let parity_packets = engine.encode(&packets, &config).unwrap();
+
+        // Lose as many data packets as there is parity
+        let mut lossy = FecGroup::new(FecGroupId(1), data, parity);
+        for (index, packet) in packets.into_iter().enumerate().skip(parity) {
+            lossy.data_packets[index] = Some(packet);
+            lossy.received_count += 1;
+        }
+        lossy.parity_packets = parity_packets;
+        lossy.received_count += parity;
+
+        group.throughput(Throughput::Bytes((data * PACKET_SIZE) as u64));
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{}+{}", data, parity)),
+            |b| b.iter(|| engine.decode(black_box(&lossy)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode);
+criterion_main!(benches);
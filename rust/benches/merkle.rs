@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::MerkleTree;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn bench_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merkle_build");
+    for leaves in [16, 256, 4096] {
+        let chunks: Vec<Vec<u8>> = (0..leaves).map(|i| vec![i as u8; CHUNK_SIZE]).collect();
+        group.throughput(Throughput::Bytes((leaves * CHUNK_SIZE) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(leaves), &chunks, |b, chunks| {
+            b.iter(|| MerkleTree::build(black_box(chunks)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_proofs(c: &mut Criterion) {
+    let chunks: Vec<Vec<u8>> = (0..4096).map(|i| vec![i as u8; 1024]).collect();
+    let tree = MerkleTree::build(&chunks);
+    let proof = tree.get_proof(1234).unwrap();
+
+    c.bench_function("merkle_proof/get", |b| {
+        b.iter(|| tree.get_proof(black_box(1234)).unwrap())
+    });
+    c.bench_function("merkle_proof/verify", |b| {
+        b.iter(|| MerkleTree::verify_proof(tree.root(), &chunks[1234], 1234, black_box(&proof)))
+    });
+}
+
+criterion_group!(benches, bench_build, bench_proofs);
+criterion_main!(benches);
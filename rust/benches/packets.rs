@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::compute::ComputeExecutor;
+use pangea_ces::{StreamPacket, StreamType};
+
+fn bench_stream_packets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stream_packet");
+    for payload in [160, 1200, 64 * 1024] {
+        let packet = StreamPacket {
+            sequence: 42,
+            timestamp: 1_700_000_000,
+            stream_type: StreamType::Audio,
+            payload: vec![3; payload],
+            fec_data: Some(vec![5; payload / 4]),
+        };
+        let bytes = packet.to_bytes().unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("to_bytes", payload), &packet, |b, p| {
+            b.iter(|| black_box(p).to_bytes().unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("from_bytes", payload),
+            &bytes,
+            |b, bytes| b.iter(|| StreamPacket::from_bytes(black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_compute_chunks(c: &mut Criterion) {
+    let executor = ComputeExecutor::default();
+    let mut group = c.benchmark_group("compute_chunks");
+    for count in [4, 64, 1024] {
+        let chunks: Vec<Vec<u8>> = (0..count).map(|i| vec![i as u8; 4096]).collect();
+        let bytes = executor.serialize_chunks(&chunks).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("serialize", count),
+            &chunks,
+            |b, chunks| b.iter(|| executor.serialize_chunks(black_box(chunks)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("deserialize", count),
+            &bytes,
+            |b, bytes| b.iter(|| executor.deserialize_chunks(black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stream_packets, bench_compute_chunks);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pangea_ces::ffi::{
+    ces_free, ces_free_shards, ces_new_with_key, ces_process, ces_process_shm, ces_shm_release,
+    ces_shm_ring_base, ces_shm_ring_free, ces_shm_ring_new, FFIShmShard,
+};
+
+/// Shard hand-off to Go: copying every shard out (what `C.GoBytes` does)
+/// versus reading it in place from the shared ring
+fn bench_shard_handoff(c: &mut Criterion) {
+    let key = [0x42u8; 32];
+    let pipeline = ces_new_with_key(1, key.as_ptr());
+    let ring = ces_shm_ring_new(0);
+    if ring.is_null() {
+        eprintln!("shared shard rings unsupported here; skipping shm benchmarks");
+        ces_free(pipeline);
+        return;
+    }
+    let base = ces_shm_ring_base(ring);
+
+    let mut group = c.benchmark_group("shard_handoff");
+    for size in [64 * 1024, 1024 * 1024, 8 * 1024 * 1024] {
+        let data: Vec<u8> = (0..size).map(|i| (i * 31 % 251) as u8).collect();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &data, |b, data| {
+            b.iter(|| {
+                let shards = ces_process(pipeline, data.as_ptr(), data.len());
+                let parts = unsafe { std::slice::from_raw_parts(shards.shards, shards.count) };
+                let copied: Vec<Vec<u8>> = parts
+                    .iter()
+                    .map(|s| unsafe { std::slice::from_raw_parts(s.data, s.len) }.to_vec())
+                    .collect();
+                ces_free_shards(shards);
+                black_box(copied)
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("shm", size), &data, |b, data| {
+            let mut descriptors = vec![FFIShmShard { offset: 0, len: 0 }; 64];
+            b.iter(|| {
+                let count = ces_process_shm(
+                    pipeline,
+                    ring,
+                    data.as_ptr(),
+                    data.len(),
+                    descriptors.as_mut_ptr(),
+                    descriptors.len(),
+                );
+                assert!(count > 0);
+                for descriptor in &descriptors[..count as usize] {
+                    let shard = unsafe {
+                        std::slice::from_raw_parts(base.add(descriptor.offset), descriptor.len)
+                    };
+                    black_box(shard);
+                    ces_shm_release(ring, *descriptor);
+                }
+            })
+        });
+    }
+    group.finish();
+
+    ces_shm_ring_free(ring);
+    ces_free(pipeline);
+}
+
+criterion_group!(benches, bench_shard_handoff);
+criterion_main!(benches);
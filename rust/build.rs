@@ -19,4 +19,10 @@ fn main() {
 
     println!("cargo:rerun-if-changed=schema.capnp");
     println!("cargo:rerun-if-changed=src/schema_capnp.rs");
+
+    // gRPC bindings (needs `protoc` on the PATH)
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/pangea.proto")
+        .unwrap_or_else(|err| panic!("Failed to compile gRPC protos: {}", err));
+    println!("cargo:rerun-if-changed=proto/pangea.proto");
 }
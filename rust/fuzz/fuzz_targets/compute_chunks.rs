@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pangea_ces::compute::ComputeExecutor;
+
+fuzz_target!(|data: &[u8]| {
+    let executor = ComputeExecutor::default();
+    if let Ok(chunks) = executor.deserialize_chunks(data) {
+        let bytes = executor.serialize_chunks(&chunks).unwrap();
+        assert_eq!(executor.deserialize_chunks(&bytes).unwrap(), chunks);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pangea_ces::cache::FileManifest;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(manifest) = FileManifest::from_json(json) {
+        // Everything downstream indexes by these
+        assert!(manifest
+            .shard_locations
+            .iter()
+            .all(|(index, _)| *index < manifest.shard_count));
+        assert_eq!(manifest.shard_refs().len(), manifest.shard_count);
+    }
+});
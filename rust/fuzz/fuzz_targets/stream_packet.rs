@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pangea_ces::streaming::StreamPacket;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that decodes must survive a round trip unchanged
+    if let Ok(packet) = StreamPacket::from_bytes(data) {
+        let bytes = packet.to_bytes().expect("decoded packet re-encodes");
+        let again = StreamPacket::from_bytes(&bytes).expect("re-encoded packet decodes");
+        assert_eq!(packet.sequence, again.sequence);
+        assert_eq!(packet.payload, again.payload);
+        assert_eq!(packet.fec_data, again.fec_data);
+    }
+});
@@ -0,0 +1,615 @@
+/// Transport-independent node API
+///
+/// [`NodeApi`] is the set of operations remote clients use: upload,
+/// download, list, search, info and compute. The Cap'n Proto service in
+/// `rpc.rs` implements it, and other front ends (the optional gRPC server)
+/// call the same implementation, so gateway rules, namespace tokens and
+/// audit records apply identically whichever protocol a client speaks.
+///
+/// The implementation is not `Send` (uploads and downloads go through the
+/// Go client), so it runs on the RPC `LocalSet`. Servers that need `Send`
+/// handlers reach it through an [`ApiBridge`], which queues each call to an
+/// [`ApiWorker`] running on that `LocalSet`.
+use async_trait::async_trait;
+use futures::future::LocalBoxFuture;
+use rand::RngCore;
+use std::ffi::OsStr;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+use crate::audit::AuditLog;
+use crate::automated::{AutomatedDownloader, AutomatedUploader, FileInfo, UploadResult};
+use crate::bloom::ShardAvailabilityIndex;
+use crate::cache::{Cache, FileManifest};
+use crate::ces::CesPipeline;
+use crate::compute::{ComputeError, ComputeTask, TaskResult};
+use crate::envelope::NodeKeypair;
+use crate::error::{DownloadError, ErrorCode, ErrorReport, NamespaceError, UploadError};
+use crate::gateway::GatewayRejection;
+use crate::go_client::GoClient;
+use crate::identity::PeerDirectory;
+use crate::kdf::KdfParams;
+use crate::limits::ConcurrencyLimits;
+use crate::network::QuicNode;
+use crate::placement::PlacementPolicy;
+use crate::progress::ProgressHub;
+use crate::query_cache::QueryCache;
+use crate::scheduler::{TransferPriority, TransferScheduler};
+use crate::store::{self, NodeStore};
+use crate::transport::ShardTransport;
+use x25519_dalek::PublicKey;
+
+/// Largest file accepted by, or returned from, an API call
+pub const MAX_API_FILE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Why an API call failed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ApiError {
+    /// The operation is not enabled on this node
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error(transparent)]
+    Gateway(#[from] GatewayRejection),
+
+    /// A typed node error, with its stable code
+    #[error("{}", .0.message)]
+    Failed(ErrorReport),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl From<UploadError> for ApiError {
+    fn from(e: UploadError) -> Self {
+        ApiError::Failed(e.report())
+    }
+}
+
+impl From<DownloadError> for ApiError {
+    fn from(e: DownloadError) -> Self {
+        ApiError::Failed(e.report())
+    }
+}
+
+impl From<NamespaceError> for ApiError {
+    fn from(e: NamespaceError) -> Self {
+        ApiError::Failed(e.report())
+    }
+}
+
+impl From<ComputeError> for ApiError {
+    fn from(e: ComputeError) -> Self {
+        match e {
+            ComputeError::Rejected(e) => ApiError::Failed(e.report()),
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(format!("{:#}", e))
+    }
+}
+
+/// A file to upload, sent inline
+#[derive(Debug, Clone)]
+pub struct UploadCall {
+    /// Name recorded in the manifest (no directories)
+    pub file_name: String,
+    pub data: Vec<u8>,
+    pub namespace: String,
+    pub priority: TransferPriority,
+}
+
+/// A file to download
+#[derive(Debug, Clone)]
+pub struct DownloadCall {
+    pub file_hash: String,
+    pub namespace: String,
+    pub priority: TransferPriority,
+}
+
+/// How one upload departs from the node's defaults
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+    /// Derive the file key from this passphrase instead of picking one
+    pub passphrase: Option<String>,
+    /// Also wrap the file key for these public keys
+    pub recipients: Vec<PublicKey>,
+    /// Only place shards on peers in this storage class
+    pub storage_class: Option<String>,
+    /// Upload even if the file is already complete in the network
+    pub force: bool,
+}
+
+/// A downloaded file, returned inline
+#[derive(Debug, Clone)]
+pub struct DownloadReply {
+    pub file_hash: String,
+    pub file_name: String,
+    pub data: Vec<u8>,
+    pub shards_fetched: usize,
+    /// Peers holding the file's shards
+    pub peers: Vec<u32>,
+}
+
+/// Operations served to remote clients
+///
+/// `token` is the caller's namespace token, if any.
+#[async_trait(?Send)]
+pub trait NodeApi {
+    async fn upload(&self, token: Option<&str>, call: UploadCall)
+        -> Result<UploadResult, ApiError>;
+
+    async fn download(
+        &self,
+        token: Option<&str>,
+        call: DownloadCall,
+    ) -> Result<DownloadReply, ApiError>;
+
+    async fn list(&self, token: Option<&str>, namespace: &str) -> Result<Vec<FileInfo>, ApiError>;
+
+    async fn search(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+        pattern: &str,
+    ) -> Result<Vec<FileInfo>, ApiError>;
+
+    async fn info(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+        file_hash: &str,
+    ) -> Result<Option<FileInfo>, ApiError>;
+
+    /// Run one task in the compute sandbox
+    async fn compute(&self, task: ComputeTask) -> Result<TaskResult, ApiError>;
+}
+
+/// Builds uploaders and downloaders for API callers
+///
+/// Each call gets its own, scoped to the caller's namespace and attributed
+/// to the caller in the audit log. Inline file contents are staged under
+/// `staging_dir` while they are processed; a node that must not write to
+/// disk refuses them instead (`without_staging`).
+pub struct Transfers {
+    ces: Arc<CesPipeline>,
+    go_client: Arc<GoClient>,
+    cache: Arc<Cache>,
+    store: Arc<NodeStore>,
+    staging_dir: Option<PathBuf>,
+    network: Option<Arc<QuicNode>>,
+    transport: Option<Arc<dyn ShardTransport>>,
+    keypair: Option<Arc<NodeKeypair>>,
+    scheduler: Option<Arc<TransferScheduler>>,
+    audit: Option<Arc<AuditLog>>,
+    directory: Option<Arc<PeerDirectory>>,
+    queries: Option<Arc<QueryCache<FileManifest>>>,
+    limits: Option<Arc<ConcurrencyLimits>>,
+    availability: Option<Arc<ShardAvailabilityIndex>>,
+    progress: Option<ProgressHub>,
+    placement: PlacementPolicy,
+    local_peer: Option<u32>,
+}
+
+impl Transfers {
+    pub fn new(
+        ces: Arc<CesPipeline>,
+        go_client: Arc<GoClient>,
+        cache: Arc<Cache>,
+        store: Arc<NodeStore>,
+        staging_dir: impl AsRef<Path>,
+    ) -> Self {
+        Self {
+            ces,
+            go_client,
+            cache,
+            store,
+            staging_dir: Some(staging_dir.as_ref().to_path_buf()),
+            network: None,
+            transport: None,
+            keypair: None,
+            scheduler: None,
+            audit: None,
+            directory: None,
+            queries: None,
+            limits: None,
+            availability: None,
+            progress: None,
+            placement: PlacementPolicy::default(),
+            local_peer: None,
+        }
+    }
+
+    /// Move shards over native QUIC when the Go node is unavailable
+    pub fn with_native_transport(mut self, network: Arc<QuicNode>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Move shards through `transport` instead of the Go node (a simulated
+    /// network, say)
+    pub fn with_transport(mut self, transport: Arc<dyn ShardTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Wrap uploaded file keys for, and unwrap downloads with, this keypair
+    pub fn with_keypair(mut self, keypair: Arc<NodeKeypair>) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Share bandwidth with other transfers through a scheduler
+    pub fn with_scheduler(mut self, scheduler: Arc<TransferScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Record and check shard holders' identities in manifests
+    pub fn with_peer_directory(mut self, directory: Arc<PeerDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Share DHT lookup results across every call's transfers
+    pub fn with_query_cache(mut self, queries: Arc<QueryCache<FileManifest>>) -> Self {
+        self.queries = Some(queries);
+        self
+    }
+
+    /// Queue every call's transfers behind the node's concurrency limits
+    pub fn with_concurrency_limits(mut self, limits: Arc<ConcurrencyLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Pick probable shard holders for downloads from advertised filters
+    pub fn with_availability_index(mut self, index: Arc<ShardAvailabilityIndex>) -> Self {
+        self.availability = Some(index);
+        self
+    }
+
+    /// Publish every call's per-shard progress to this hub
+    pub fn with_progress(mut self, hub: ProgressHub) -> Self {
+        self.progress = Some(hub);
+        self
+    }
+
+    /// Lay out every upload's shards by this policy
+    pub fn with_placement(mut self, policy: PlacementPolicy) -> Self {
+        self.placement = policy;
+        self
+    }
+
+    /// This node's peer ID, kept out of uploads unless the placement policy
+    /// allows it
+    pub fn with_local_peer(mut self, peer_id: u32) -> Self {
+        self.local_peer = Some(peer_id);
+        self
+    }
+
+    /// Refuse inline file contents rather than stage them on disk
+    pub fn without_staging(mut self) -> Self {
+        self.staging_dir = None;
+        self
+    }
+
+    pub fn uploader(&self, namespace: &str, requester: &str) -> AutomatedUploader {
+        self.build_uploader(self.ces.clone(), namespace, requester, &[])
+    }
+
+    /// Uploader for one call with `options` applied
+    ///
+    /// A passphrase key is derived off the runtime, as Argon2id takes a
+    /// while.
+    pub async fn uploader_with(
+        &self,
+        namespace: &str,
+        requester: &str,
+        options: &UploadOptions,
+    ) -> Result<AutomatedUploader, ApiError> {
+        let ces = match &options.passphrase {
+            Some(passphrase) => {
+                let ces = self.ces.rekeyed([0u8; 32]);
+                let passphrase = passphrase.clone();
+                let ces = tokio::task::spawn_blocking(move || {
+                    ces.with_passphrase(passphrase.as_bytes(), KdfParams::generate())
+                })
+                .await
+                .map_err(|e| ApiError::Internal(format!("Key derivation failed: {}", e)))??;
+                Arc::new(ces)
+            }
+            None => self.ces.clone(),
+        };
+        let mut uploader = self
+            .build_uploader(ces, namespace, requester, &options.recipients)
+            .with_force(options.force);
+        if let Some(class) = &options.storage_class {
+            store::validate_storage_class(class)
+                .map_err(|e| ApiError::InvalidArgument(e.to_string()))?;
+            uploader = uploader.with_storage_class(class.as_str());
+        }
+        Ok(uploader)
+    }
+
+    /// Uploader whose file keys are wrapped for this node and `recipients`
+    fn build_uploader(
+        &self,
+        ces: Arc<CesPipeline>,
+        namespace: &str,
+        requester: &str,
+        recipients: &[PublicKey],
+    ) -> AutomatedUploader {
+        let mut uploader = AutomatedUploader::new(
+            ces,
+            self.go_client.clone(),
+            self.cache.clone(),
+            self.store.clone(),
+            None,
+        )
+        .with_namespace(namespace)
+        .with_placement(self.placement.clone());
+        if let Some(peer_id) = self.local_peer {
+            uploader = uploader.with_local_peer(peer_id);
+        }
+        if let Some(network) = &self.network {
+            uploader = uploader.with_native_transport(network.clone());
+        }
+        if let Some(transport) = &self.transport {
+            uploader = uploader.with_transport(transport.clone());
+        }
+        let mut keys: Vec<PublicKey> = self.keypair.iter().map(|k| k.public_key()).collect();
+        keys.extend(recipients.iter().copied());
+        if !keys.is_empty() {
+            uploader = uploader.with_recipients(keys);
+        }
+        if let Some(scheduler) = &self.scheduler {
+            uploader = uploader.with_scheduler(scheduler.clone());
+        }
+        if let Some(audit) = &self.audit {
+            uploader = uploader.with_audit_log(audit.clone(), requester);
+        }
+        if let Some(directory) = &self.directory {
+            uploader = uploader.with_peer_directory(directory.clone());
+        }
+        if let Some(queries) = &self.queries {
+            uploader = uploader.with_query_cache(queries.clone());
+        }
+        if let Some(limits) = &self.limits {
+            uploader = uploader.with_concurrency_limits(limits.clone());
+        }
+        if let Some(hub) = &self.progress {
+            uploader = uploader.with_progress(hub.clone());
+        }
+        uploader
+    }
+
+    pub fn downloader(&self, namespace: &str, requester: &str) -> AutomatedDownloader {
+        let mut downloader = AutomatedDownloader::new(
+            self.ces.clone(),
+            self.go_client.clone(),
+            self.cache.clone(),
+            self.store.clone(),
+            None,
+        )
+        .with_namespace(namespace);
+        if let Some(network) = &self.network {
+            downloader = downloader.with_native_transport(network.clone());
+        }
+        if let Some(transport) = &self.transport {
+            downloader = downloader.with_transport(transport.clone());
+        }
+        if let Some(keypair) = &self.keypair {
+            downloader = downloader.with_keypair(keypair.clone());
+        }
+        if let Some(scheduler) = &self.scheduler {
+            downloader = downloader.with_scheduler(scheduler.clone());
+        }
+        if let Some(audit) = &self.audit {
+            downloader = downloader.with_audit_log(audit.clone(), requester);
+        }
+        if let Some(directory) = &self.directory {
+            downloader = downloader.with_peer_directory(directory.clone());
+        }
+        if let Some(queries) = &self.queries {
+            downloader = downloader.with_query_cache(queries.clone());
+        }
+        if let Some(limits) = &self.limits {
+            downloader = downloader.with_concurrency_limits(limits.clone());
+        }
+        if let Some(index) = &self.availability {
+            downloader = downloader.with_availability_index(index.clone());
+        }
+        if let Some(hub) = &self.progress {
+            downloader = downloader.with_progress(hub.clone());
+        }
+        downloader
+    }
+
+    /// A fresh, empty directory for one call's files
+    pub async fn stage(&self) -> Result<StagingDir, ApiError> {
+        let staging_dir = self.staging_dir.as_ref().ok_or_else(|| {
+            ApiError::Unavailable(
+                "This node keeps nothing on disk and cannot take file contents over the API".into(),
+            )
+        })?;
+        let mut id = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut id);
+        let path = staging_dir.join(hex::encode(id));
+        tokio::fs::create_dir_all(&path)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to create {:?}: {}", path, e)))?;
+        Ok(StagingDir { path })
+    }
+}
+
+/// Per-call staging directory, removed on drop
+pub struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path for `file_name` inside the directory
+    ///
+    /// Fails unless the name is a single path component.
+    pub fn file(&self, file_name: &str) -> Result<PathBuf, ApiError> {
+        if file_name.is_empty() || Path::new(file_name).file_name() != Some(OsStr::new(file_name)) {
+            return Err(ApiError::InvalidArgument(format!(
+                "Invalid file name {:?}",
+                file_name
+            )));
+        }
+        Ok(self.path.join(file_name))
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            warn!("Failed to remove staging dir {:?}: {}", self.path, e);
+        }
+    }
+}
+
+type Job<S> = Box<dyn FnOnce(Arc<S>) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// `Send` handle that runs calls on a target living on a `LocalSet`
+pub struct ApiBridge<S> {
+    jobs: mpsc::UnboundedSender<Job<S>>,
+}
+
+impl<S> Clone for ApiBridge<S> {
+    fn clone(&self) -> Self {
+        Self {
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+impl<S: 'static> ApiBridge<S> {
+    /// A bridge and the worker that serves it
+    pub fn new() -> (Self, ApiWorker<S>) {
+        let (jobs, queue) = mpsc::unbounded_channel();
+        (Self { jobs }, ApiWorker { queue })
+    }
+
+    /// Run `call` against the worker's target and wait for its result
+    pub async fn call<T, F, Fut>(&self, call: F) -> Result<T, ApiError>
+    where
+        F: FnOnce(Arc<S>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, ApiError>> + 'static,
+        T: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job<S> = Box::new(move |target| {
+            Box::pin(async move {
+                let _ = reply.send(call(target).await);
+            })
+        });
+        let stopped = || ApiError::Unavailable("Node is shutting down".to_string());
+        self.jobs.send(job).map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+/// Runs calls queued through an [`ApiBridge`]
+pub struct ApiWorker<S> {
+    queue: mpsc::UnboundedReceiver<Job<S>>,
+}
+
+impl<S: 'static> ApiWorker<S> {
+    /// Serve calls until every bridge is dropped; must run on a `LocalSet`
+    pub async fn run(mut self, target: Arc<S>) {
+        while let Some(job) = self.queue.recv().await {
+            tokio::task::spawn_local(job(target.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CesConfig;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_bridge_runs_calls_on_local_target() {
+        let local = tokio::task::LocalSet::new();
+        let (bridge, worker) = ApiBridge::<Cell<u32>>::new();
+        local.spawn_local(worker.run(Arc::new(Cell::new(0))));
+
+        local
+            .run_until(async move {
+                // Callers may live on other threads
+                let caller = tokio::spawn(async move {
+                    for _ in 0..3 {
+                        bridge
+                            .call(|counter| async move {
+                                counter.set(counter.get() + 1);
+                                Ok(())
+                            })
+                            .await
+                            .unwrap();
+                    }
+                    bridge
+                        .call(|counter| async move { Ok(counter.get()) })
+                        .await
+                });
+                assert_eq!(caller.await.unwrap(), Ok(3));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_staging_dir_rejects_paths_and_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 10, 1024 * 1024).unwrap());
+        let transfers = Transfers::new(
+            Arc::new(CesPipeline::new(CesConfig::default())),
+            Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap())),
+            cache,
+            Arc::new(NodeStore::new()),
+            dir.path().join("staging"),
+        );
+
+        let staging = transfers.stage().await.unwrap();
+        let staged = staging.path().to_path_buf();
+        assert!(staged.is_dir());
+        assert_eq!(staging.file("photo.jpg").unwrap(), staged.join("photo.jpg"));
+        for bad in ["", "../photo.jpg", "a/b", ".."] {
+            assert!(staging.file(bad).is_err(), "accepted {:?}", bad);
+        }
+
+        drop(staging);
+        assert!(!staged.exists());
+
+        let diskless = transfers.without_staging();
+        assert!(matches!(
+            diskless.stage().await,
+            Err(ApiError::Unavailable(_))
+        ));
+        assert!(!dir.path().join("staging").read_dir().unwrap().any(|_| true));
+    }
+}
@@ -0,0 +1,386 @@
+/// Append-only audit log of uploads and downloads
+///
+/// Each completed or failed transfer is written as one JSON line recording
+/// what moved, between which peers, how many bytes, how long it took and who
+/// asked for it. The log rotates by size (`audit.jsonl`, `audit.jsonl.1`, ...)
+/// and is configured in the `[audit]` section of the node config file.
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+/// Default file name of the audit log inside the cache directory
+pub const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// Audit configuration (`[audit]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Record transfers
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Log file (defaults to `audit.jsonl` in the cache directory)
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Rotate once the active file reaches this size
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Rotated files kept besides the active one
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_file_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            path: None,
+            max_file_bytes: default_max_file_bytes(),
+            max_files: default_max_files(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    audit: AuditConfig,
+}
+
+impl AuditConfig {
+    /// Load the `[audit]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[audit]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.audit.validate()?;
+        Ok(file.audit)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_file_bytes == 0 {
+            anyhow::bail!("audit max_file_bytes must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Log file to use when the config does not name one
+    pub fn path_or(&self, cache_dir: impl AsRef<Path>) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| cache_dir.as_ref().join(AUDIT_LOG_FILE))
+    }
+}
+
+/// Kind of transfer recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOperation {
+    Upload,
+    Download,
+}
+
+/// One audited transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix seconds when the transfer finished
+    pub timestamp: i64,
+    pub operation: AuditOperation,
+    pub file_hash: Option<String>,
+    /// Peers that received or served shards
+    #[serde(default)]
+    pub peers: Vec<u32>,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Who asked for the transfer (`cli:<user>`, `embedded`, `rpc:<addr>`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requester: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(operation: AuditOperation, duration: Duration) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().timestamp(),
+            operation,
+            file_hash: None,
+            peers: Vec::new(),
+            bytes: 0,
+            duration_ms: duration.as_millis() as u64,
+            success: true,
+            error: None,
+            requester: None,
+        }
+    }
+}
+
+/// Which records `AuditLog::read` returns
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub operation: Option<AuditOperation>,
+    pub file_hash: Option<String>,
+    pub peer: Option<u32>,
+    pub requester: Option<String>,
+    /// Unix seconds, inclusive
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub failed_only: bool,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.operation.is_none_or(|op| op == record.operation)
+            && self
+                .file_hash
+                .as_ref()
+                .is_none_or(|hash| record.file_hash.as_deref() == Some(hash.as_str()))
+            && self.peer.is_none_or(|peer| record.peers.contains(&peer))
+            && self
+                .requester
+                .as_ref()
+                .is_none_or(|who| record.requester.as_deref() == Some(who.as_str()))
+            && self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+            && (!self.failed_only || !record.success)
+    }
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+}
+
+/// Size-rotated JSONL audit log
+pub struct AuditLog {
+    path: PathBuf,
+    config: AuditConfig,
+    active: Mutex<Option<ActiveFile>>,
+}
+
+impl AuditLog {
+    /// Log to `path` with the rotation limits from `config`
+    pub fn open(path: impl AsRef<Path>, config: AuditConfig) -> Result<Self> {
+        config.validate()?;
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create audit dir {}", dir.display()))?;
+        }
+        Ok(Self {
+            path,
+            config,
+            active: Mutex::new(None),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a record, rotating first if the active file is full
+    pub fn record(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut active = self.active.lock();
+        if let Some(current) = active.as_ref() {
+            if current.size > 0 && current.size + line.len() as u64 > self.config.max_file_bytes {
+                *active = None;
+                self.rotate()?;
+            }
+        }
+        if active.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+            let size = file.metadata()?.len();
+            *active = Some(ActiveFile { file, size });
+            // An existing file may already be over the limit
+            if size > 0 && size + line.len() as u64 > self.config.max_file_bytes {
+                *active = None;
+                drop(active);
+                self.rotate()?;
+                return self.record(record);
+            }
+        }
+
+        let current = active.as_mut().expect("audit file opened above");
+        current.file.write_all(&line)?;
+        current.file.flush()?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Append a record, logging instead of failing the transfer on error
+    pub fn record_or_warn(&self, record: &AuditRecord) {
+        if let Err(e) = self.record(record) {
+            warn!("Failed to write audit record: {:#}", e);
+        }
+    }
+
+    /// Shift `audit.jsonl.N` to `.N+1`, dropping the oldest
+    fn rotate(&self) -> Result<()> {
+        if self.config.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+        let oldest = rotated_path(&self.path, self.config.max_files);
+        if oldest.exists() {
+            std::fs::remove_file(&oldest)?;
+        }
+        for index in (1..self.config.max_files).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))
+            .with_context(|| format!("Failed to rotate audit log {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Records from the log at `path` and its rotations, oldest first
+    ///
+    /// Lines that fail to parse (e.g. a torn final write) are skipped.
+    pub fn read(path: impl AsRef<Path>, filter: &AuditFilter) -> Result<Vec<AuditRecord>> {
+        let path = path.as_ref();
+        let mut files: Vec<PathBuf> = (1..)
+            .map(|index| rotated_path(path, index))
+            .take_while(|rotated| rotated.exists())
+            .collect();
+        files.reverse();
+        files.push(path.to_path_buf());
+
+        let mut records = Vec::new();
+        for file in files {
+            let file = match File::open(&file) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to open audit log {}", file.display()))
+                }
+            };
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if let Ok(record) = serde_json::from_str::<AuditRecord>(&line) {
+                    if filter.matches(&record) {
+                        records.push(record);
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(operation: AuditOperation, hash: &str, peers: Vec<u32>) -> AuditRecord {
+        AuditRecord {
+            file_hash: Some(hash.to_string()),
+            peers,
+            bytes: 1024,
+            requester: Some("cli:test".to_string()),
+            ..AuditRecord::new(operation, Duration::from_millis(5))
+        }
+    }
+
+    #[test]
+    fn test_config_section() {
+        let config = AuditConfig::from_toml("[audit]\nenabled = false\nmax_files = 2\n").unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.max_files, 2);
+        assert!(AuditConfig::from_toml("").unwrap().enabled);
+    }
+
+    #[test]
+    fn test_record_rotate_and_filter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(AUDIT_LOG_FILE);
+        let line_len = serde_json::to_vec(&record(AuditOperation::Upload, "h0", vec![1]))
+            .unwrap()
+            .len() as u64
+            + 1;
+        let log = AuditLog::open(
+            &path,
+            AuditConfig {
+                // Two records per file, two rotated files kept
+                max_file_bytes: line_len * 2,
+                max_files: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        for i in 0..7 {
+            let operation = if i % 2 == 0 {
+                AuditOperation::Upload
+            } else {
+                AuditOperation::Download
+            };
+            log.record(&record(operation, &format!("h{}", i), vec![i]))
+                .unwrap();
+        }
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+
+        // h0 and h1 rotated out; the rest come back oldest first
+        let all = AuditLog::read(&path, &AuditFilter::default()).unwrap();
+        let hashes: Vec<_> = all.iter().filter_map(|r| r.file_hash.clone()).collect();
+        assert_eq!(hashes, vec!["h2", "h3", "h4", "h5", "h6"]);
+
+        let downloads = AuditLog::read(
+            &path,
+            &AuditFilter {
+                operation: Some(AuditOperation::Download),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(downloads.len(), 2);
+
+        let by_peer = AuditLog::read(
+            &path,
+            &AuditFilter {
+                peer: Some(4),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(by_peer[0].file_hash.as_deref(), Some("h4"));
+    }
+}
@@ -1,17 +1,60 @@
 /// Auto-Healing module for maintaining shard redundancy
 /// Monitors local shard count and requests replacement data when needed
-use anyhow::Result;
-use std::collections::HashMap;
+use anyhow::{Context, Result};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
-use tokio::time::{interval, sleep};
+use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
+use crate::blob::BlobId;
 use crate::cache::{Cache, FileManifest};
 use crate::ces::CesPipeline;
+use crate::envelope::NodeKeypair;
 use crate::go_client::GoClient;
+use crate::limits::{ConcurrencyLimits, LimitName};
+use crate::retry::{retry, RetryPolicy};
+use crate::rotation::ShardKeys;
+use crate::scheduler::{TransferPriority, TransferScheduler};
 use crate::store::NodeStore;
+use crate::transport::ShardTransport;
+use crate::types::NodeStatus;
+
+/// Backoff between heal attempts for a file: 1 minute, doubling to 32 minutes
+fn heal_backoff() -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_secs(60),
+        max_backoff: Duration::from_secs(60 * 32),
+        jitter: 0.0,
+        ..Default::default()
+    }
+}
+
+/// How strongly churn shortens the check interval: at 10% churn the
+/// interval is half of `check_interval_secs`
+const CHURN_GAIN: f64 = 10.0;
+
+/// Next check interval in seconds given the current one, the churn seen by
+/// the last check (disconnect rate plus shard-loss rate) and whether any
+/// file fell below `min_shard_copies`
+///
+/// Below minimum redundancy the interval drops to the minimum; under churn
+/// it shrinks from the base interval in proportion; on a quiet check it
+/// doubles back toward the maximum.
+fn adapt_interval(config: &AutoHealConfig, current: u64, churn: f64, below_min: bool) -> u64 {
+    let min = config.min_check_interval_secs.max(1);
+    let max = config.max_check_interval_secs.max(min);
+    let next = if below_min {
+        min
+    } else if churn > 0.0 {
+        (config.check_interval_secs as f64 / (1.0 + CHURN_GAIN * churn)) as u64
+    } else {
+        current.saturating_mul(2)
+    };
+    next.clamp(min, max)
+}
 
 /// Configuration for auto-healing
 #[derive(Debug, Clone)]
@@ -20,8 +63,19 @@ pub struct AutoHealConfig {
     pub min_shard_copies: usize,
     /// Target number of shard copies
     pub target_shard_copies: usize,
-    /// Check interval in seconds
+    /// Check interval in seconds under moderate churn; the effective
+    /// interval adapts between the bounds below
     pub check_interval_secs: u64,
+    /// Shortest interval, used while a file is below `min_shard_copies`
+    pub min_check_interval_secs: u64,
+    /// Longest interval, reached after quiet checks
+    pub max_check_interval_secs: u64,
+    /// Bytes of files repaired per check, most fragile first; the rest
+    /// wait for the next check (0 = unlimited)
+    pub heal_byte_budget: u64,
+    /// Count copies in this node's cache and on a file's uploader toward
+    /// its redundancy; otherwise the uploader's shards are moved to peers
+    pub count_local_copies: bool,
     /// Enable/disable auto-healing
     pub enabled: bool,
 }
@@ -32,6 +86,10 @@ impl Default for AutoHealConfig {
             min_shard_copies: 3,
             target_shard_copies: 5,
             check_interval_secs: 300, // 5 minutes
+            min_check_interval_secs: 30,
+            max_check_interval_secs: 1800,
+            heal_byte_budget: 1024 * 1024 * 1024, // 1 GiB
+            count_local_copies: false,
             enabled: true,
         }
     }
@@ -48,18 +106,61 @@ struct HealingStatus {
     heal_failures: u32,
 }
 
+/// A file short of `target_shard_copies`, waiting for repair
+struct HealCandidate {
+    manifest: FileManifest,
+    available: usize,
+    pinned: bool,
+    popularity: f64,
+}
+
+impl HealCandidate {
+    /// Repair order: furthest below `min_shard_copies` first, then pinned
+    /// files, then the most popular, then furthest below target
+    fn priority(&self, other: &Self, config: &AutoHealConfig) -> Ordering {
+        let below_min = |c: &Self| config.min_shard_copies.saturating_sub(c.available);
+        let below_target = |c: &Self| config.target_shard_copies.saturating_sub(c.available);
+        below_min(other)
+            .cmp(&below_min(self))
+            .then(other.pinned.cmp(&self.pinned))
+            .then(other.popularity.total_cmp(&self.popularity))
+            .then(below_target(other).cmp(&below_target(self)))
+            .then_with(|| self.manifest.file_hash.cmp(&other.manifest.file_hash))
+    }
+}
+
 /// Auto-healing service
 pub struct AutoHealer {
     config: AutoHealConfig,
     cache: Arc<Cache>,
+    #[allow(dead_code)]
     ces: Arc<CesPipeline>,
     #[allow(dead_code)]
     go_client: Arc<GoClient>,
     store: Arc<NodeStore>,
 
+    /// Fetches surviving shards and re-places rebuilt ones; without it
+    /// healing only repairs the local cache
+    transport: Option<Arc<dyn ShardTransport>>,
+    retry: RetryPolicy,
+
+    /// Bounds repairs in flight, if shared with the rest of the node
+    limits: Option<Arc<ConcurrencyLimits>>,
+
+    /// Charges repair traffic to the heal class, so it yields bandwidth to
+    /// interactive transfers sharing the scheduler
+    scheduler: Option<Arc<TransferScheduler>>,
+
+    /// Unwraps the keys of rotated files, so their shards can be opened
+    /// and re-encrypted under the newest key
+    keypair: Option<Arc<NodeKeypair>>,
+
     /// Track files being healed
     healing_status: Arc<RwLock<HashMap<String, HealingStatus>>>,
 
+    /// Peers active at the previous check, to measure disconnects
+    last_active: Arc<RwLock<Option<HashSet<u32>>>>,
+
     /// Statistics
     stats: Arc<RwLock<HealStats>>,
 }
@@ -72,6 +173,110 @@ pub struct HealStats {
     pub heals_succeeded: u64,
     pub heals_failed: u64,
     pub shards_recovered: u64,
+    /// Shard copies re-encrypted under a rotated file key
+    pub shards_reencrypted: u64,
+    /// Seconds between checks, adapted to churn
+    pub effective_interval_secs: u64,
+    /// Share of the peers active at the previous check that are gone
+    pub disconnect_rate: f64,
+    /// Share of shard locations without a reachable copy at the last check
+    pub shard_loss_rate: f64,
+    /// Files below target at the last check but still in heal backoff
+    pub files_skipped: usize,
+    /// Files below target left for the next check by the byte budget,
+    /// most fragile first
+    pub backlog: Vec<String>,
+}
+
+/// State of one copy of a shard found by [`AutoHealer::verify`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardStatus {
+    /// Present and matching the manifest's hash
+    Valid,
+    /// Present but failing the manifest's hash
+    Corrupt,
+    /// The holder answered without the shard (or the cache has none)
+    Missing,
+    /// The holder could not be reached
+    Unreachable,
+}
+
+impl std::fmt::Display for ShardStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShardStatus::Valid => write!(f, "valid"),
+            ShardStatus::Corrupt => write!(f, "corrupt"),
+            ShardStatus::Missing => write!(f, "missing"),
+            ShardStatus::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+/// Checked copies of one manifest shard location
+#[derive(Debug, Clone)]
+pub struct ShardReport {
+    pub shard_index: usize,
+    pub peer_id: u32,
+    /// Copy in the local cache
+    pub local: ShardStatus,
+    /// Copy on the peer the manifest names
+    pub remote: ShardStatus,
+}
+
+impl ShardReport {
+    pub fn is_valid(&self) -> bool {
+        self.local == ShardStatus::Valid || self.remote == ShardStatus::Valid
+    }
+}
+
+/// Result of a deep integrity check of one file
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    pub file_hash: String,
+    pub file_name: String,
+    pub namespace: String,
+    pub shard_count: usize,
+    /// Shards needed to reconstruct the file
+    pub data_shards: usize,
+    pub shards: Vec<ShardReport>,
+    /// Whether the file was rebuilt from exactly `data_shards` valid shards
+    /// and every other shard came out matching its recorded hash
+    pub reconstructable: bool,
+}
+
+impl VerifyReport {
+    /// Distinct shards with at least one valid copy
+    pub fn valid_shards(&self) -> usize {
+        let valid: HashSet<usize> = self
+            .shards
+            .iter()
+            .filter(|shard| shard.is_valid())
+            .map(|shard| shard.shard_index)
+            .collect();
+        valid.len()
+    }
+
+    /// Shards that must be rebuilt to restore full redundancy
+    pub fn missing_redundancy(&self) -> usize {
+        self.shard_count.saturating_sub(self.valid_shards())
+    }
+
+    /// Further shard losses the file survives
+    pub fn loss_tolerance(&self) -> usize {
+        self.valid_shards().saturating_sub(self.data_shards)
+    }
+
+    /// Locations whose holder no longer serves a valid copy
+    pub fn degraded_locations(&self) -> usize {
+        self.shards
+            .iter()
+            .filter(|shard| shard.remote != ShardStatus::Valid)
+            .count()
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.reconstructable && self.degraded_locations() == 0
+    }
 }
 
 impl AutoHealer {
@@ -84,16 +289,56 @@ impl AutoHealer {
         store: Arc<NodeStore>,
     ) -> Self {
         Self {
-            config,
             cache,
             ces,
             go_client,
             store,
+            transport: None,
+            retry: RetryPolicy::default(),
+            limits: None,
+            scheduler: None,
+            keypair: None,
             healing_status: Arc::new(RwLock::new(HashMap::new())),
-            stats: Arc::new(RwLock::new(HealStats::default())),
+            last_active: Arc::new(RwLock::new(None)),
+            stats: Arc::new(RwLock::new(HealStats {
+                effective_interval_secs: config.check_interval_secs,
+                ..Default::default()
+            })),
+            config,
         }
     }
 
+    /// Fetch shards from and re-place shards on peers over `transport`
+    pub fn with_transport(mut self, transport: Arc<dyn ShardTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Retry policy for individual shard transfers
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Hold each repair to the node's `heal` concurrency limit
+    pub fn with_concurrency_limits(mut self, limits: Arc<ConcurrencyLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Share bandwidth with the node's other transfers, as heal traffic
+    pub fn with_scheduler(mut self, scheduler: Arc<TransferScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Open and re-encrypt shards of rotated files with keys wrapped for
+    /// `keypair`
+    pub fn with_keypair(mut self, keypair: Arc<NodeKeypair>) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
     /// Start the auto-healing background task
     pub async fn start(self: Arc<Self>) {
         if !self.config.enabled {
@@ -102,25 +347,46 @@ impl AutoHealer {
         }
 
         info!(
-            "🔧 Auto-healing started: min_copies={}, target={}, interval={}s",
+            "🔧 Auto-healing started: min_copies={}, target={}, interval={}s (adapts within {}-{}s)",
             self.config.min_shard_copies,
             self.config.target_shard_copies,
-            self.config.check_interval_secs
+            self.config.check_interval_secs,
+            self.config.min_check_interval_secs,
+            self.config.max_check_interval_secs
         );
 
-        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_secs));
-
+        // Peers lost between checks bring the next one forward
+        let mut events = self.store.subscribe_events().await.skip_replay();
         loop {
-            check_interval.tick().await;
-
             if let Err(e) = self.run_healing_cycle().await {
                 error!("Healing cycle failed: {}", e);
             }
+
+            let interval = self.effective_interval().await;
+            tokio::select! {
+                _ = sleep(interval) => {}
+                Some(peer_id) = events.next_peer_loss() => {
+                    info!("Peer {} lost, checking shard copies early", peer_id);
+                    // Let a burst of departures settle into one check
+                    let settle = Duration::from_secs(self.config.min_check_interval_secs);
+                    sleep(settle.min(interval)).await;
+                }
+            }
         }
     }
 
-    /// Run a single healing cycle
-    async fn run_healing_cycle(&self) -> Result<()> {
+    /// Time until the next check, as adapted by the last one
+    pub async fn effective_interval(&self) -> Duration {
+        Duration::from_secs(self.stats.read().await.effective_interval_secs)
+    }
+
+    /// Run a single healing cycle over every cached manifest
+    ///
+    /// Files short of target are repaired most fragile first until
+    /// `heal_byte_budget` is spent. The peers lost since the previous cycle
+    /// and the shards found without a reachable copy set the interval until
+    /// the next one.
+    pub async fn run_healing_cycle(&self) -> Result<()> {
         debug!("Running healing cycle...");
 
         // Get all manifests from cache
@@ -133,40 +399,218 @@ impl AutoHealer {
 
         debug!("Checking {} files for healing needs", manifests.len());
 
+        let disconnect_rate = self.observe_disconnects().await;
+        let mut locations = 0;
+        let mut lost = 0;
+        let mut below_min = false;
+        let mut queue = Vec::new();
         for manifest in manifests {
-            if let Err(e) = self.check_and_heal_file(&manifest).await {
-                warn!("Failed to heal file {}: {}", manifest.file_hash, e);
+            let available = match self.count_available_shards(&manifest).await {
+                Ok(available) => available,
+                Err(e) => {
+                    warn!("Failed to check file {}: {}", manifest.file_hash, e);
+                    continue;
+                }
+            };
+            locations += manifest.shard_locations.len();
+            lost += manifest.shard_locations.len().saturating_sub(available);
+            below_min |= available < self.config.min_shard_copies;
+
+            debug!(
+                "File {}: {} available shards (min: {}, target: {})",
+                manifest.file_hash,
+                available,
+                self.config.min_shard_copies,
+                self.config.target_shard_copies
+            );
+            // Files mid key rotation are healed anyway, which moves their
+            // copies to the newest key
+            if available >= self.config.target_shard_copies && !manifest.rotation_pending() {
+                // File is healthy
+                self.healing_status
+                    .write()
+                    .await
+                    .remove(&manifest.file_hash);
+                continue;
+            }
+            queue.push(HealCandidate {
+                pinned: self.cache.is_pinned(&manifest.file_hash).await,
+                popularity: self
+                    .cache
+                    .popularity()
+                    .map_or(0.0, |popularity| popularity.score(&manifest.file_hash)),
+                manifest,
+                available,
+            });
+        }
+        queue.sort_by(|a, b| a.priority(b, &self.config));
+
+        // A file larger than the whole budget still goes when it is first
+        let budget = match self.config.heal_byte_budget {
+            0 => u64::MAX,
+            budget => budget,
+        };
+        let mut spent = 0u64;
+        let mut skipped = 0;
+        let mut backlog = Vec::new();
+        for candidate in queue {
+            let cost = candidate.manifest.file_size as u64;
+            if spent > 0 && spent.saturating_add(cost) > budget {
+                backlog.push(candidate.manifest.file_hash);
+                continue;
+            }
+            if self
+                .heal_if_due(&candidate.manifest, candidate.available)
+                .await
+            {
+                spent = spent.saturating_add(cost);
+            } else {
+                skipped += 1;
             }
 
             // Small delay between files to avoid overwhelming the network
             sleep(Duration::from_millis(100)).await;
         }
+        if !backlog.is_empty() {
+            info!(
+                "🔧 Heal budget of {} bytes spent, {} files wait for the next check",
+                budget,
+                backlog.len()
+            );
+        }
+
+        let shard_loss_rate = if locations == 0 {
+            0.0
+        } else {
+            lost as f64 / locations as f64
+        };
+        let mut stats = self.stats.write().await;
+        let next = adapt_interval(
+            &self.config,
+            stats.effective_interval_secs,
+            disconnect_rate + shard_loss_rate,
+            below_min,
+        );
+        if next != stats.effective_interval_secs {
+            debug!(
+                "Heal interval {}s -> {}s (disconnects {:.1}%, shard loss {:.1}%)",
+                stats.effective_interval_secs,
+                next,
+                disconnect_rate * 100.0,
+                shard_loss_rate * 100.0
+            );
+        }
+        stats.effective_interval_secs = next;
+        stats.disconnect_rate = disconnect_rate;
+        stats.shard_loss_rate = shard_loss_rate;
+        stats.files_skipped = skipped;
+        stats.backlog = backlog;
 
         debug!("Healing cycle complete");
         Ok(())
     }
 
-    /// Check and heal a specific file
-    async fn check_and_heal_file(&self, manifest: &FileManifest) -> Result<()> {
-        let file_hash = &manifest.file_hash;
+    /// Share of the peers active at the previous check that no longer are
+    async fn observe_disconnects(&self) -> f64 {
+        let active: HashSet<u32> = self
+            .store
+            .get_nodes_by_status(NodeStatus::Active)
+            .await
+            .into_iter()
+            .map(|node| node.id)
+            .collect();
+        let previous = self.last_active.write().await.replace(active.clone());
+        match previous {
+            Some(previous) if !previous.is_empty() => {
+                previous.difference(&active).count() as f64 / previous.len() as f64
+            }
+            _ => 0.0,
+        }
+    }
 
-        // Count available shards
-        let available_count = self.count_available_shards(manifest).await?;
+    /// Check every shard of a file against its recorded hash
+    ///
+    /// Both the cached copy and the one on the peer the manifest names are
+    /// fetched, regardless of what the node store believes about that peer.
+    /// The file is then rebuilt from the minimum set of valid shards to
+    /// prove it is still reconstructable; copies re-encrypted under a
+    /// rotated key only count toward that with the node keypair.
+    pub async fn verify(&self, manifest: &FileManifest) -> VerifyReport {
+        let (data_shards, parity) = shard_split(manifest);
+        let mut shards = Vec::with_capacity(manifest.shard_locations.len());
+        let mut valid: Vec<Option<Vec<u8>>> = vec![None; manifest.shard_count];
 
-        debug!(
-            "File {}: {} available shards (min: {}, target: {})",
-            file_hash,
-            available_count,
-            self.config.min_shard_copies,
-            self.config.target_shard_copies
-        );
+        for (shard_idx, peer_id) in &manifest.shard_locations {
+            let cached = self.cache.get_shard(&manifest.file_hash, *shard_idx).await;
+            let local = match &cached {
+                Some(data) if manifest.shard_matches(*shard_idx, data) => ShardStatus::Valid,
+                Some(_) => ShardStatus::Corrupt,
+                None => ShardStatus::Missing,
+            };
+            let (remote, fetched) = self.probe_remote(manifest, *shard_idx, *peer_id).await;
+            if remote == ShardStatus::Corrupt {
+                warn!(
+                    "🚨 Peer {} holds a corrupt copy of shard {} of {}",
+                    peer_id, shard_idx, manifest.file_hash
+                );
+            }
+
+            let data = match (local, remote) {
+                (ShardStatus::Valid, _) => cached,
+                (_, ShardStatus::Valid) => fetched,
+                _ => None,
+            };
+            if let Some(slot) = valid.get_mut(*shard_idx) {
+                if slot.is_none() {
+                    *slot = data;
+                }
+            }
+            shards.push(ShardReport {
+                shard_index: *shard_idx,
+                peer_id: *peer_id,
+                local,
+                remote,
+            });
+        }
 
-        // Check if healing is needed
-        if available_count >= self.config.target_shard_copies {
-            // File is healthy
-            self.healing_status.write().await.remove(file_hash);
-            return Ok(());
+        // Copies sealed under a rotated key count once opened
+        match self.shard_keys(manifest) {
+            Ok(keys) => keys.unwrap_or_default().open_all(manifest, &mut valid),
+            Err(e) => debug!("Cannot open shards of {}: {:#}", manifest.file_hash, e),
         }
+        let reconstructable = rebuilds_from_minimum(manifest, data_shards, parity, valid);
+        VerifyReport {
+            file_hash: manifest.file_hash.clone(),
+            file_name: manifest.file_name.clone(),
+            namespace: manifest.namespace.clone(),
+            shard_count: manifest.shard_count,
+            data_shards,
+            shards,
+            reconstructable,
+        }
+    }
+
+    /// Heal a file now, whatever its shard count and backoff state
+    ///
+    /// Returns the number of shards rebuilt or moved to new peers.
+    pub async fn heal(&self, manifest: &FileManifest) -> Result<usize> {
+        self.stats.write().await.heals_attempted += 1;
+        let result = self.perform_healing(manifest).await;
+        let mut stats = self.stats.write().await;
+        match &result {
+            Ok(recovered) => {
+                stats.heals_succeeded += 1;
+                stats.shards_recovered += *recovered as u64;
+            }
+            Err(_) => stats.heals_failed += 1,
+        }
+        result
+    }
+
+    /// Heal a file short of target unless it is in backoff, returning
+    /// whether a heal was attempted
+    async fn heal_if_due(&self, manifest: &FileManifest, available_count: usize) -> bool {
+        let file_hash = &manifest.file_hash;
 
         if available_count < self.config.min_shard_copies {
             warn!(
@@ -182,7 +626,10 @@ impl AutoHealer {
             .or_insert_with(|| HealingStatus {
                 file_hash: file_hash.clone(),
                 current_copies: available_count,
-                needed_copies: self.config.target_shard_copies - available_count,
+                needed_copies: self
+                    .config
+                    .target_shard_copies
+                    .saturating_sub(available_count),
                 last_heal_attempt: None,
                 heal_failures: 0,
             });
@@ -194,10 +641,9 @@ impl AutoHealer {
                 .unwrap_or(Duration::from_secs(0));
 
             // Exponential backoff: wait longer after each failure
-            let backoff_secs = 60 * (1 << status.heal_failures.min(5));
-            if elapsed < Duration::from_secs(backoff_secs) {
+            if elapsed < heal_backoff().base_delay(status.heal_failures) {
                 debug!("Skipping heal attempt for {} (backoff)", file_hash);
-                return Ok(());
+                return false;
             }
         }
 
@@ -241,7 +687,7 @@ impl AutoHealer {
             }
         }
 
-        Ok(())
+        true
     }
 
     /// Count available shards for a file
@@ -249,50 +695,73 @@ impl AutoHealer {
         let mut available = 0;
 
         for (shard_idx, peer_id) in &manifest.shard_locations {
-            // Check if shard is in cache
-            if self.cache.has_shard(&manifest.file_hash, *shard_idx).await {
-                available += 1;
+            if self.config.count_local_copies {
+                // Check if shard is in cache
+                if self.cache.has_shard(&manifest.file_hash, *shard_idx).await {
+                    available += 1;
+                    continue;
+                }
+            } else if manifest.is_uploader(*peer_id) {
+                // The uploader's own copy is not redundancy
                 continue;
             }
 
             // Check if peer is online
-            if let Some(node) = self.store.get_node(*peer_id).await {
-                if node.status == crate::types::NodeStatus::Active {
-                    available += 1;
-                }
+            if self.is_active(*peer_id).await {
+                available += 1;
             }
         }
 
         Ok(available)
     }
 
-    /// Perform the actual healing by requesting and re-encoding shards
+    /// Perform the actual healing by collecting surviving shards, rebuilding
+    /// the missing ones and placing them on healthy peers
     async fn perform_healing(&self, manifest: &FileManifest) -> Result<usize> {
-        // 1. Collect available shards
+        let _permit = match &self.limits {
+            Some(limits) => Some(limits.acquire(LimitName::Heal).await),
+            None => None,
+        };
+
+        let keys = self.shard_keys(manifest)?;
+
+        // 1. Collect available shards, noting which locations still hold
+        // theirs and which hold them under an older key
         let mut shards = vec![None; manifest.shard_count];
-        let mut collected = 0;
+        let mut placed = vec![false; manifest.shard_locations.len()];
+        let mut stale = vec![false; manifest.shard_locations.len()];
 
-        for (shard_idx, _peer_id) in &manifest.shard_locations {
-            // Try to get from cache first
-            if let Some(data) = self.cache.get_shard(&manifest.file_hash, *shard_idx).await {
-                shards[*shard_idx] = Some(data);
-                collected += 1;
+        for (position, (shard_idx, peer_id)) in manifest.shard_locations.iter().enumerate() {
+            let from_peer = self.fetch_verified(manifest, *shard_idx, *peer_id).await;
+            placed[position] = from_peer.is_some()
+                && (self.config.count_local_copies || !manifest.is_uploader(*peer_id));
+            if let (Some(rotation), Some(data)) = (&manifest.rotation, &from_peer) {
+                stale[position] =
+                    manifest.shard_version(*shard_idx, data) != Some(rotation.current);
+            }
+            if shards[*shard_idx].is_some() {
                 continue;
             }
 
-            // TODO: Request from peer via go_client
-            // This would involve RPC call to peer to fetch shard
-            // For now, we'll just check cache
+            let data = match from_peer {
+                Some(data) => Some(data),
+                None => self
+                    .cache
+                    .get_shard(&manifest.file_hash, *shard_idx)
+                    .await
+                    .filter(|data| manifest.shard_matches(*shard_idx, data)),
+            };
+            if data.is_some() {
+                shards[*shard_idx] = data;
+            }
+        }
+        if let Some(keys) = &keys {
+            keys.open_all(manifest, &mut shards);
         }
+        let collected = shards.iter().filter(|shard| shard.is_some()).count();
 
         // 2. Check if we have enough shards to reconstruct
-        // For Reed-Solomon, we need k (data shards) = total - parity
-        let required = if manifest.parity_count > 0 {
-            manifest.shard_count - manifest.parity_count
-        } else {
-            // Fallback for old manifests without parity_count: assume 2/3 threshold
-            manifest.shard_count - (manifest.shard_count / 3)
-        };
+        let (required, parity) = shard_split(manifest);
         if collected < required {
             return Err(anyhow::anyhow!(
                 "Not enough shards to reconstruct: {} < {}",
@@ -301,25 +770,255 @@ impl AutoHealer {
             ));
         }
 
-        // 3. Reconstruct the original data
-        let original_data = self.ces.reconstruct(shards)?;
-        debug!("Reconstructed {} bytes", original_data.len());
-
-        // 4. Re-encode to create new shards
-        let new_shards = self.ces.process(&original_data)?;
-        debug!("Created {} new shards", new_shards.len());
+        // 3. Rebuild the missing shards byte for byte
+        let shards = repair_all(manifest, required, parity, shards)?;
+        let rebuilt = manifest.shard_count - collected;
+        debug!("Rebuilt {} shards", rebuilt);
 
-        // 5. Store new shards in cache
-        for (idx, shard) in new_shards.iter().enumerate() {
+        // 4. Seal the shards of a rotated file under its newest key, and
+        // store them in cache
+        let mut updated = manifest.clone();
+        let shards = match &keys {
+            Some(keys) => shards
+                .iter()
+                .enumerate()
+                .map(|(idx, shard)| keys.seal(&mut updated, idx, shard))
+                .collect::<Result<Vec<_>>>()?,
+            None => shards,
+        };
+        for (idx, shard) in shards.iter().enumerate() {
             self.cache
                 .put_shard(&manifest.file_hash, idx, shard.clone())
                 .await?;
         }
 
-        // 6. TODO: Distribute new shards to peers
-        // This would involve selecting healthy peers and uploading shards
+        // 5. Move shards whose holder is dead, lost them or uploaded the file
+        // to healthy peers, and re-encrypt copies under an older key in place
+        let Some(transport) = &self.transport else {
+            if updated.rotation != manifest.rotation {
+                self.cache.put_manifest(updated).await?;
+            }
+            return Ok(rebuilt);
+        };
+        // Peers already named by the manifest never take a second shard, and
+        // the uploader takes no more of its own file
+        let holders: HashSet<u32> = manifest
+            .shard_locations
+            .iter()
+            .map(|(_, peer)| *peer)
+            .chain(manifest.uploader)
+            .collect();
+        let mut candidates = self
+            .replacement_candidates(&holders, manifest.storage_class.as_deref())
+            .await
+            .into_iter();
+        let mut replaced = 0;
+        let mut stranded = 0;
+        let mut reencrypted = 0;
+        let locations = manifest.shard_locations.len();
+
+        for (position, (shard_idx, peer_id)) in manifest.shard_locations.iter().enumerate() {
+            let shard = &shards[*shard_idx];
+            if placed[position] {
+                if !stale[position] {
+                    continue;
+                }
+                self.throttle(shard.len()).await;
+                let sent = retry(&self.retry, "heal_reencrypt_shard", || {
+                    transport.send_shard(*peer_id, &manifest.file_hash, *shard_idx, shard.clone())
+                })
+                .await;
+                match sent {
+                    Ok(true) => {
+                        if let Some(rotation) = updated.rotation.as_mut() {
+                            rotation.mark_current(position, locations);
+                        }
+                        reencrypted += 1;
+                    }
+                    Ok(false) => {
+                        warn!("Peer {} rejected re-encrypted shard {}", peer_id, shard_idx)
+                    }
+                    Err(e) => warn!(
+                        "Failed to re-encrypt shard {} on {}: {}",
+                        shard_idx, peer_id, e
+                    ),
+                }
+                continue;
+            }
+            let mut target = None;
+            for candidate in candidates.by_ref() {
+                self.throttle(shard.len()).await;
+                let sent = retry(&self.retry, "heal_send_shard", || {
+                    transport.send_shard(candidate, &manifest.file_hash, *shard_idx, shard.clone())
+                })
+                .await;
+                match sent {
+                    Ok(true) => {
+                        target = Some(candidate);
+                        break;
+                    }
+                    Ok(false) => warn!("Peer {} rejected shard {}", candidate, shard_idx),
+                    Err(e) => warn!(
+                        "Failed to place shard {} on {}: {}",
+                        shard_idx, candidate, e
+                    ),
+                }
+            }
+            let Some(target) = target else {
+                stranded += 1;
+                continue;
+            };
+            info!(
+                "🔧 Moved shard {} of {} from peer {} to peer {}",
+                shard_idx, manifest.file_hash, peer_id, target
+            );
+            updated.shard_locations[position] = (*shard_idx, target);
+            if let Some(rotation) = updated.rotation.as_mut() {
+                rotation.mark_current(position, locations);
+            }
+            replaced += 1;
+        }
+
+        if let Some(rotation) = updated.rotation.as_mut() {
+            if reencrypted > 0 {
+                info!(
+                    "🔑 Re-encrypted {} shard(s) of {} in place",
+                    reencrypted, manifest.file_hash
+                );
+                self.stats.write().await.shards_reencrypted += reencrypted as u64;
+            }
+            if rotation.retire(locations) {
+                info!(
+                    "🔑 Every copy of {} is at key version {}; older keys retired",
+                    manifest.file_hash, rotation.current
+                );
+            }
+        }
+
+        // Keep whatever was re-placed even if some shards found no new home
+        if replaced > 0 || updated.rotation != manifest.rotation {
+            self.cache.put_manifest(updated).await?;
+        }
+        if stranded > 0 {
+            anyhow::bail!(
+                "No healthy peer left for {} shard(s) of {}",
+                stranded,
+                manifest.file_hash
+            );
+        }
+        Ok(replaced)
+    }
+
+    /// Keys to open and re-encrypt the shards of a rotated file; `None` for
+    /// files never rotated
+    fn shard_keys(&self, manifest: &FileManifest) -> Result<Option<ShardKeys>> {
+        if manifest.rotation.is_none() {
+            return Ok(None);
+        }
+        let keypair = self.keypair.as_ref().with_context(|| {
+            format!(
+                "{} was rotated; healing it needs the node keypair",
+                manifest.file_hash
+            )
+        })?;
+        ShardKeys::unwrap(keypair, manifest).map(Some)
+    }
+
+    /// Fetch a shard from its recorded holder, discarding corrupt copies
+    async fn fetch_verified(
+        &self,
+        manifest: &FileManifest,
+        shard_idx: usize,
+        peer_id: u32,
+    ) -> Option<Vec<u8>> {
+        if !self.is_active(peer_id).await {
+            return None;
+        }
+        match self.probe_remote(manifest, shard_idx, peer_id).await {
+            (ShardStatus::Valid, data) => data,
+            (ShardStatus::Corrupt, _) => {
+                warn!(
+                    "🚨 Peer {} served a corrupt copy of shard {} of {}",
+                    peer_id, shard_idx, manifest.file_hash
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Fetch a shard from `peer_id` and check it against the manifest
+    ///
+    /// The data is returned only for valid copies.
+    async fn probe_remote(
+        &self,
+        manifest: &FileManifest,
+        shard_idx: usize,
+        peer_id: u32,
+    ) -> (ShardStatus, Option<Vec<u8>>) {
+        let Some(transport) = &self.transport else {
+            return (ShardStatus::Unreachable, None);
+        };
+        let fetched = retry(&self.retry, "heal_fetch_shard", || {
+            transport.fetch_shard(peer_id, &manifest.file_hash, shard_idx)
+        })
+        .await;
+        if let Ok(data) = &fetched {
+            self.throttle(data.len()).await;
+        }
+        match fetched {
+            Ok(data) if data.is_empty() => (ShardStatus::Missing, None),
+            Ok(data) if manifest.shard_matches(shard_idx, &data) => {
+                (ShardStatus::Valid, Some(data))
+            }
+            Ok(_) => (ShardStatus::Corrupt, None),
+            Err(e) => {
+                debug!(
+                    "Peer {} could not serve shard {}: {}",
+                    peer_id, shard_idx, e
+                );
+                (ShardStatus::Unreachable, None)
+            }
+        }
+    }
+
+    /// Charge repair bytes against the scheduler's heal class
+    async fn throttle(&self, bytes: usize) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler
+                .acquire(TransferPriority::Heal, bytes as u64)
+                .await;
+        }
+    }
+
+    /// Active peers not already holding a shard of the file, best first
+    ///
+    /// Files uploaded to a storage class only move to peers in that class.
+    async fn replacement_candidates(
+        &self,
+        holders: &HashSet<u32>,
+        storage_class: Option<&str>,
+    ) -> Vec<u32> {
+        let mut candidates: Vec<u32> = self
+            .store
+            .get_nodes_by_status(NodeStatus::Active)
+            .await
+            .into_iter()
+            .map(|node| node.id)
+            .filter(|id| !holders.contains(id))
+            .collect();
+        candidates.sort_unstable();
+        if let Some(class) = storage_class {
+            candidates = self.store.filter_by_class(&candidates, class).await;
+        }
+        self.store.rank_by_latency(&candidates).await
+    }
 
-        Ok(new_shards.len() - collected)
+    async fn is_active(&self, peer_id: u32) -> bool {
+        self.store
+            .get_node(peer_id)
+            .await
+            .is_some_and(|node| node.status == NodeStatus::Active)
     }
 
     /// Get current statistics
@@ -334,9 +1033,84 @@ impl AutoHealer {
     }
 }
 
+/// Data and parity shard counts of a file's outer code
+///
+/// For Reed-Solomon we need k (data shards) = total - parity; local parity
+/// shards of two-tier layouts are not part of either.
+fn shard_split(manifest: &FileManifest) -> (usize, usize) {
+    let parity = if manifest.parity_count > 0 {
+        manifest.parity_count
+    } else {
+        // Fallback for old manifests without parity_count: assume 2/3 threshold
+        manifest.shard_count / 3
+    };
+    (manifest.outer_shard_count().saturating_sub(parity), parity)
+}
+
+/// Rebuild every shard of a file, through its local parity groups first
+/// when it has them
+fn repair_all(
+    manifest: &FileManifest,
+    data_shards: usize,
+    parity: usize,
+    shards: Vec<Option<Vec<u8>>>,
+) -> Result<Vec<Vec<u8>>> {
+    match &manifest.local_parity {
+        Some(local) => local.repair(data_shards, parity, shards),
+        None => CesPipeline::repair_shards(data_shards, parity, shards),
+    }
+}
+
+/// Whether the file rebuilds from its first `data_shards` valid shards alone
+///
+/// Every shard left out is regenerated and must match its recorded hash, so
+/// a pass proves the remaining copies are consistent with each other.
+fn rebuilds_from_minimum(
+    manifest: &FileManifest,
+    data_shards: usize,
+    parity: usize,
+    mut valid: Vec<Option<Vec<u8>>>,
+) -> bool {
+    let mut kept = 0;
+    for slot in valid.iter_mut() {
+        if slot.is_some() {
+            if kept == data_shards {
+                *slot = None;
+            } else {
+                kept += 1;
+            }
+        }
+    }
+    if data_shards == 0 || kept < data_shards {
+        return false;
+    }
+    match repair_all(manifest, data_shards, parity, valid) {
+        Ok(rebuilt) => {
+            rebuilt.len() == manifest.shard_count
+                && rebuilt
+                    .iter()
+                    .enumerate()
+                    .all(|(idx, shard)| is_original(manifest, idx, shard))
+        }
+        Err(e) => {
+            debug!("Failed to rebuild {}: {}", manifest.file_hash, e);
+            false
+        }
+    }
+}
+
+/// Whether `data` is the shard as uploaded, before any key rotation
+fn is_original(manifest: &FileManifest, shard_idx: usize, data: &[u8]) -> bool {
+    match manifest.shard_hashes.get(shard_idx) {
+        Some(expected) => BlobId::of(data).to_hex() == *expected,
+        None => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sha2::{Digest, Sha256};
 
     #[test]
     fn test_config_defaults() {
@@ -345,4 +1119,284 @@ mod tests {
         assert_eq!(config.target_shard_copies, 5);
         assert!(config.enabled);
     }
+
+    /// A 100-byte file with one shard on each of `peers`
+    fn placed_manifest(file_hash: &str, peers: &[u32]) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.txt", file_hash),
+            file_size: 100,
+            shard_count: peers.len(),
+            parity_count: 2,
+            shard_locations: peers.iter().copied().enumerate().collect(),
+            namespace: crate::namespace::DEFAULT_NAMESPACE.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_uploader_copies_do_not_count() {
+        use crate::types::Node;
+
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(Node::new(id)).await;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+        let mut manifest = placed_manifest("mine", &[1, 2, 3, 4]);
+        manifest.uploader = Some(1);
+        cache.put_shard("mine", 2, vec![2; 16]).await.unwrap();
+
+        let healer = |count_local_copies| {
+            AutoHealer::new(
+                AutoHealConfig {
+                    count_local_copies,
+                    ..Default::default()
+                },
+                cache.clone(),
+                Arc::new(CesPipeline::new(Default::default())),
+                Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap())),
+                store.clone(),
+            )
+        };
+        assert_eq!(
+            healer(false)
+                .count_available_shards(&manifest)
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            healer(true)
+                .count_available_shards(&manifest)
+                .await
+                .unwrap(),
+            4
+        );
+
+        // Peer 2 leaves: only the cached copy keeps its shard counted
+        store.remove_node(2).await;
+        assert_eq!(
+            healer(false)
+                .count_available_shards(&manifest)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            healer(true)
+                .count_available_shards(&manifest)
+                .await
+                .unwrap(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_adapt_interval() {
+        let config = AutoHealConfig::default();
+        // Quiet checks back off to the maximum
+        assert_eq!(adapt_interval(&config, 300, 0.0, false), 600);
+        assert_eq!(adapt_interval(&config, 1200, 0.0, false), 1800);
+        // Churn shortens the base interval
+        assert_eq!(adapt_interval(&config, 1800, 0.1, false), 150);
+        assert_eq!(adapt_interval(&config, 1800, 1.0, false), 30);
+        // Lost redundancy tightens to the minimum right away
+        assert_eq!(adapt_interval(&config, 1800, 0.0, true), 30);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_cycle_adapts_interval_to_churn() {
+        use crate::types::Node;
+
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(Node::new(id)).await;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+        cache
+            .put_manifest(placed_manifest("churn", &[1, 2, 3, 4]))
+            .await
+            .unwrap();
+
+        let healer = AutoHealer::new(
+            AutoHealConfig::default(),
+            cache,
+            Arc::new(CesPipeline::new(Default::default())),
+            Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap())),
+            store.clone(),
+        )
+        .with_retry_policy(RetryPolicy::none());
+        assert_eq!(healer.effective_interval().await, Duration::from_secs(300));
+
+        // Every shard reachable, no peers lost
+        healer.run_healing_cycle().await.unwrap();
+        let stats = healer.get_stats().await;
+        assert_eq!(stats.effective_interval_secs, 600);
+        assert_eq!(stats.disconnect_rate, 0.0);
+        assert_eq!(stats.shard_loss_rate, 0.0);
+
+        // Half the peers leave and the file drops below three copies
+        store.remove_node(1).await;
+        store.remove_node(2).await;
+        healer.run_healing_cycle().await.unwrap();
+        let stats = healer.get_stats().await;
+        assert_eq!(stats.effective_interval_secs, 30);
+        assert_eq!(stats.disconnect_rate, 0.5);
+        assert_eq!(stats.shard_loss_rate, 0.5);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_verify_reports_shards_and_heal_restores_them() {
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+        use crate::types::{CesConfig, Node};
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=8));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=8 {
+            store.upsert_node(Node::new(id)).await;
+        }
+        let ces =
+            Arc::new(CesPipeline::new(CesConfig::default().with_shards(4, 2)).with_key([7; 32]));
+        let shards = ces.process(&b"verify me ".repeat(500)).unwrap();
+        let mut shard_locations = Vec::new();
+        for (index, shard) in shards.iter().enumerate() {
+            let peer = index as u32 + 1;
+            network
+                .send_shard(peer, "verifyme", index, shard.clone())
+                .await
+                .unwrap();
+            shard_locations.push((index, peer));
+        }
+        let manifest = FileManifest {
+            file_hash: "verifyme".to_string(),
+            file_name: "verifyme.txt".to_string(),
+            file_size: 5000,
+            shard_count: shards.len(),
+            parity_count: 2,
+            shard_locations,
+            shard_hashes: shards
+                .iter()
+                .map(|shard| format!("{:x}", Sha256::digest(shard)))
+                .collect(),
+            namespace: crate::namespace::DEFAULT_NAMESPACE.to_string(),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+        cache.put_manifest(manifest.clone()).await.unwrap();
+
+        // Peer 2 holds garbage, peer 3 is gone but its shard is cached here
+        network
+            .send_shard(2, "verifyme", 1, vec![0; 16])
+            .await
+            .unwrap();
+        network.set_peer_down(3, true);
+        cache
+            .put_shard("verifyme", 2, shards[2].clone())
+            .await
+            .unwrap();
+
+        let go_client = Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap()));
+        let scheduler = Arc::new(TransferScheduler::unlimited());
+        let healer = AutoHealer::new(
+            AutoHealConfig::default(),
+            cache.clone(),
+            ces,
+            go_client,
+            store,
+        )
+        .with_transport(network.clone())
+        .with_retry_policy(RetryPolicy::none())
+        .with_scheduler(scheduler.clone());
+
+        let report = healer.verify(&manifest).await;
+        assert_eq!(report.shards[1].remote, ShardStatus::Corrupt);
+        assert_eq!(report.shards[1].local, ShardStatus::Missing);
+        assert_eq!(report.shards[2].remote, ShardStatus::Unreachable);
+        assert_eq!(report.shards[2].local, ShardStatus::Valid);
+        assert_eq!(report.valid_shards(), 5);
+        assert_eq!(report.missing_redundancy(), 1);
+        assert_eq!(report.loss_tolerance(), 1);
+        assert_eq!(report.degraded_locations(), 2);
+        assert!(report.reconstructable);
+        assert!(!report.is_healthy());
+
+        assert_eq!(healer.heal(&manifest).await.unwrap(), 2);
+        let healed = cache.get_manifest("verifyme").await.unwrap();
+        assert!(healer.verify(&healed).await.is_healthy());
+
+        // Repair traffic is charged to the heal class only
+        assert!(scheduler.class_stats(TransferPriority::Heal).grants > 0);
+        assert_eq!(
+            scheduler.class_stats(TransferPriority::Interactive).grants,
+            0
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_cycle_heals_most_fragile_first_within_budget() {
+        use crate::popularity::{PopularityConfig, PopularityTracker};
+        use crate::types::Node;
+
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(Node::new(id)).await;
+        }
+        let popularity = Arc::new(PopularityTracker::new(PopularityConfig::default()));
+        popularity.record("popular");
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(
+            Cache::new(dir.path(), 100, 64 * 1024 * 1024)
+                .unwrap()
+                .with_popularity(popularity),
+        );
+        // Peer 9 is unknown, so "fragile" has two of its three locations
+        for manifest in [
+            placed_manifest("healthy", &[1, 2, 3, 4, 1]),
+            placed_manifest("plain", &[1, 2, 3, 4]),
+            placed_manifest("popular", &[1, 2, 3, 4]),
+            placed_manifest("pinned", &[1, 2, 3, 4]),
+            placed_manifest("fragile", &[1, 2, 9]),
+        ] {
+            cache.put_manifest(manifest).await.unwrap();
+        }
+        cache.pin("pinned").await;
+
+        let healer = AutoHealer::new(
+            AutoHealConfig {
+                heal_byte_budget: 250,
+                ..Default::default()
+            },
+            cache,
+            Arc::new(CesPipeline::new(Default::default())),
+            Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap())),
+            store,
+        )
+        .with_retry_policy(RetryPolicy::none());
+
+        // Two 100-byte files fit the budget; the rest wait in priority order
+        healer.run_healing_cycle().await.unwrap();
+        let stats = healer.get_stats().await;
+        assert_eq!(stats.heals_attempted, 2);
+        assert_eq!(stats.files_skipped, 0);
+        assert_eq!(stats.backlog, vec!["popular", "plain"]);
+        let healing = healer.get_healing_status().await;
+        let mut attempted: Vec<&str> = healing.iter().map(|s| s.file_hash.as_str()).collect();
+        attempted.sort_unstable();
+        assert_eq!(attempted, vec!["fragile", "pinned"]);
+
+        // Failed files back off, so the backlog goes next
+        healer.run_healing_cycle().await.unwrap();
+        let stats = healer.get_stats().await;
+        assert_eq!(stats.heals_attempted, 4);
+        assert_eq!(stats.files_skipped, 2);
+        assert!(stats.backlog.is_empty());
+    }
 }
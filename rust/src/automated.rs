@@ -7,19 +7,43 @@
 /// - Manifest management
 /// - Cache integration
 /// - Error recovery
-use anyhow::{bail, Context, Result};
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 use tracing::{debug, info, warn};
+use x25519_dalek::PublicKey;
 
-use crate::cache::Cache;
+use crate::audit::{AuditLog, AuditOperation, AuditRecord};
+use crate::bloom::ShardAvailabilityIndex;
+use crate::bundle::{Bundle, ImportReport};
+use crate::cache::{Cache, FileManifest};
 use crate::ces::CesPipeline;
+use crate::chunking::ChunkingPolicy;
+use crate::compression::CompressionController;
 use crate::dht::DhtNode;
 use crate::download::DownloadProtocol;
+use crate::envelope::NodeKeypair;
+use crate::error::{DownloadError, UploadError};
 use crate::go_client::GoClient;
-use crate::lookup::LookupService;
+use crate::identity::PeerDirectory;
+use crate::keywords::KeywordIndex;
+use crate::limits::{ConcurrencyLimits, LimitName};
+use crate::lookup::{LookupService, SearchSource};
+use crate::metrics::MetricsTracker;
+use crate::namespace::DEFAULT_NAMESPACE;
+use crate::network::QuicNode;
+use crate::placement::PlacementPolicy;
+use crate::progress::ProgressHub;
+use crate::query_cache::QueryCache;
+use crate::rotation::ShardKeys;
+use crate::scheduler::{TransferPriority, TransferScheduler};
 use crate::store::NodeStore;
+use crate::transport::{ShardTransfer, ShardTransport};
 use crate::upload::UploadProtocol;
+use crate::upload_session::{UploadSession, UploadSessions};
 
 /// Reserved node ID for the local node (not included in peer discovery)
 const LOCAL_NODE_ID: u32 = 0;
@@ -27,13 +51,21 @@ const LOCAL_NODE_ID: u32 = 0;
 /// Constant for bytes to MB conversion
 const BYTES_PER_MB: f64 = 1_048_576.0;
 
+/// Files processed at once by batch uploads and downloads
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
 /// High-level automated uploader
 /// Just provide a file path and it handles everything
 pub struct AutomatedUploader {
-    upload: Arc<UploadProtocol>,
+    upload: UploadProtocol,
+    cache: Arc<Cache>,
     lookup: Arc<LookupService>,
     store: Arc<NodeStore>,
     dht: Option<Arc<tokio::sync::RwLock<DhtNode>>>,
+    audit: Option<Arc<AuditLog>>,
+    requester: Option<String>,
+    force: bool,
+    limits: Option<Arc<ConcurrencyLimits>>,
 }
 
 impl AutomatedUploader {
@@ -46,39 +78,285 @@ impl AutomatedUploader {
         store: Arc<NodeStore>,
         dht: Option<Arc<tokio::sync::RwLock<DhtNode>>>,
     ) -> Self {
-        let upload = Arc::new(UploadProtocol::with_cache(ces, go_client, cache.clone()));
-        let lookup = Arc::new(LookupService::new(cache, dht.clone(), store.clone()));
+        let upload = UploadProtocol::with_cache(ces, go_client, cache.clone())
+            .with_node_store(store.clone());
+        let lookup = Arc::new(LookupService::new(
+            cache.clone(),
+            dht.clone(),
+            store.clone(),
+        ));
 
         Self {
             upload,
+            cache,
             lookup,
             store,
             dht,
+            audit: None,
+            requester: None,
+            force: false,
+            limits: None,
+        }
+    }
+
+    /// Share bandwidth with other transfers through a scheduler
+    pub fn with_scheduler(self, scheduler: Arc<TransferScheduler>) -> Self {
+        Self {
+            upload: self.upload.with_scheduler(scheduler),
+            ..self
+        }
+    }
+
+    /// Send shards over native QUIC when the Go node is unavailable
+    pub fn with_native_transport(self, network: Arc<QuicNode>) -> Self {
+        Self {
+            upload: self.upload.with_native_transport(network),
+            ..self
+        }
+    }
+
+    /// Record in manifests the identity each shard holder is bound to
+    pub fn with_peer_directory(self, directory: Arc<PeerDirectory>) -> Self {
+        Self {
+            upload: self.upload.with_peer_directory(directory),
+            ..self
+        }
+    }
+
+    /// Send shards through `transport` instead of the Go node
+    pub fn with_transport(self, transport: Arc<dyn ShardTransport>) -> Self {
+        Self {
+            upload: self.upload.with_transport(transport),
+            ..self
+        }
+    }
+
+    /// Adapt compression levels to live CPU load and transfer backlog
+    pub fn with_compression_controller(self, controller: Arc<CompressionController>) -> Self {
+        Self {
+            upload: self.upload.with_compression_controller(controller),
+            ..self
+        }
+    }
+
+    /// Pick each file's shard layout from its size, the peers and their throughput
+    pub fn with_chunking(self, policy: ChunkingPolicy) -> Self {
+        Self {
+            upload: self.upload.with_chunking(policy),
+            ..self
+        }
+    }
+
+    /// Add local parity per failure domain (two-tier erasure coding) and
+    /// decide whether this node takes shards of its own uploads
+    pub fn with_placement(self, policy: PlacementPolicy) -> Self {
+        Self {
+            upload: self.upload.with_placement(policy),
+            ..self
+        }
+    }
+
+    /// This node's peer ID, kept out of its own uploads unless the placement
+    /// policy allows it
+    pub fn with_local_peer(self, peer_id: u32) -> Self {
+        Self {
+            upload: self.upload.with_local_peer(peer_id),
+            ..self
+        }
+    }
+
+    /// Wrap each file key for these X25519 public keys
+    pub fn with_recipients(self, recipients: Vec<PublicKey>) -> Self {
+        Self {
+            upload: self.upload.with_recipients(recipients),
+            ..self
+        }
+    }
+
+    /// Publish per-shard upload progress to a hub
+    pub fn with_progress(self, hub: ProgressHub) -> Self {
+        Self {
+            upload: self.upload.with_progress(hub),
+            ..self
+        }
+    }
+
+    /// Record every upload in an audit log, attributed to `requester`
+    pub fn with_audit_log(self, audit: Arc<AuditLog>, requester: impl Into<String>) -> Self {
+        Self {
+            audit: Some(audit),
+            requester: Some(requester.into()),
+            ..self
+        }
+    }
+
+    /// Store uploaded files in this namespace, subject to its quota
+    pub fn with_namespace(self, namespace: impl Into<String>) -> Self {
+        Self {
+            upload: self.upload.with_namespace(namespace),
+            ..self
+        }
+    }
+
+    /// Only place shards on peers tagged with this storage class
+    pub fn with_storage_class(self, class: impl Into<String>) -> Self {
+        Self {
+            upload: self.upload.with_storage_class(class),
+            ..self
+        }
+    }
+
+    /// Upload files even when a healthy copy is already in the network
+    pub fn with_force(self, force: bool) -> Self {
+        Self { force, ..self }
+    }
+
+    /// Publish the filename keywords of registered files to the DHT
+    pub fn with_keyword_index(self, index: Arc<KeywordIndex>) -> Self {
+        Self {
+            lookup: Arc::new((*self.lookup).clone().with_keyword_index(index)),
+            ..self
+        }
+    }
+
+    /// Share DHT lookup results (and misses) with other lookups
+    pub fn with_query_cache(self, queries: Arc<QueryCache<FileManifest>>) -> Self {
+        Self {
+            lookup: Arc::new((*self.lookup).clone().with_query_cache(queries)),
+            ..self
+        }
+    }
+
+    /// Checkpoint each upload so [`Self::resume`] can finish it after a crash
+    pub fn with_sessions(self, sessions: Arc<UploadSessions>) -> Self {
+        Self {
+            upload: self.upload.with_sessions(sessions),
+            ..self
+        }
+    }
+
+    /// Queue uploads (and their DHT queries) behind the node's limits
+    pub fn with_concurrency_limits(self, limits: Arc<ConcurrencyLimits>) -> Self {
+        Self {
+            lookup: Arc::new(
+                (*self.lookup)
+                    .clone()
+                    .with_concurrency_limits(limits.clone()),
+            ),
+            limits: Some(limits),
+            ..self
         }
     }
 
     /// Upload a file with full automation
     ///
     /// This function:
-    /// 1. Validates the file path
+    /// 1. Validates the file path, returning the existing manifest instead
+    ///    if the file is already healthy in the network
     /// 2. Discovers available peers via DHT
     /// 3. Processes file through CES pipeline
     /// 4. Distributes shards to peers
     /// 5. Creates and caches manifest
     /// 6. Registers file in DHT
     /// 7. Returns file hash and manifest
-    pub async fn upload(&self, file_path: impl AsRef<Path>) -> Result<UploadResult> {
-        let file_path = file_path.as_ref();
+    pub async fn upload(&self, file_path: impl AsRef<Path>) -> Result<UploadResult, UploadError> {
+        self.upload_with_priority(file_path, TransferPriority::Interactive)
+            .await
+    }
 
-        // 1. Validate file
+    /// Upload a file with an explicit transfer priority
+    pub async fn upload_with_priority(
+        &self,
+        file_path: impl AsRef<Path>,
+        priority: TransferPriority,
+    ) -> Result<UploadResult, UploadError> {
+        let file_path = file_path.as_ref();
         info!("🚀 Starting automated upload: {:?}", file_path);
+        Self::validate_file(file_path).await?;
+        if let Some(existing) = self.reuse_existing(file_path).await? {
+            return Ok(existing);
+        }
+
+        // 2. Discover available peers
+        let target_peers = self.require_target_peers().await?;
+        self.upload_to_peers(file_path, None, target_peers, priority)
+            .await
+    }
+
+    /// Finish an upload session interrupted by a crash or failed peer
+    ///
+    /// Only shards no peer accepted yet are sent, to peers of the session's
+    /// storage class; the file itself is not read again.
+    pub async fn resume(
+        &self,
+        session_id: &str,
+        priority: TransferPriority,
+    ) -> Result<UploadResult, UploadError> {
+        info!("🚀 Resuming upload session {}", session_id);
+        let session = self
+            .upload
+            .load_session(session_id)
+            .await?
+            .ok_or_else(|| UploadError::SessionNotFound(session_id.to_string()))?;
+        let target_peers = self
+            .require_peers_in(session.manifest.storage_class.as_deref())
+            .await?;
+        let file_path = session.file_path.clone();
+        self.upload_to_peers(&file_path, Some(session), target_peers, priority)
+            .await
+    }
 
+    /// Upload several files, discovering peers once for the whole batch
+    ///
+    /// At most `concurrency` files are in flight at a time. Results are in
+    /// the order of `file_paths`; a failed file does not stop the others.
+    /// Only a failed peer discovery fails the batch as a whole.
+    pub async fn upload_many(
+        &self,
+        file_paths: Vec<PathBuf>,
+        priority: TransferPriority,
+        concurrency: usize,
+    ) -> Result<Vec<Result<UploadResult, UploadError>>, UploadError> {
+        info!(
+            "🚀 Starting batch upload of {} file(s) (concurrency {})",
+            file_paths.len(),
+            concurrency
+        );
+        let target_peers = self.require_target_peers().await?;
+
+        let results: Vec<_> = stream::iter(file_paths)
+            .map(|file_path| {
+                let target_peers = target_peers.clone();
+                async move {
+                    Self::validate_file(&file_path).await?;
+                    if let Some(existing) = self.reuse_existing(&file_path).await? {
+                        return Ok(existing);
+                    }
+                    self.upload_to_peers(&file_path, None, target_peers, priority)
+                        .await
+                }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        info!(
+            "✅ Batch upload finished: {} succeeded, {} failed",
+            results.len() - failed,
+            failed
+        );
+        Ok(results)
+    }
+
+    /// Check that a path exists and is a regular file
+    async fn validate_file(file_path: &Path) -> Result<(), UploadError> {
         if !file_path.exists() {
-            bail!("File not found: {:?}", file_path);
+            return Err(UploadError::FileNotFound(file_path.to_path_buf()));
         }
 
         if !file_path.is_file() {
-            bail!("Path is not a file: {:?}", file_path);
+            return Err(UploadError::NotAFile(file_path.to_path_buf()));
         }
 
         let metadata = tokio::fs::metadata(file_path).await?;
@@ -88,13 +366,96 @@ impl AutomatedUploader {
             file_size,
             file_size as f64 / BYTES_PER_MB
         );
+        Ok(())
+    }
 
-        // 2. Discover available peers
+    /// Skip the transfer if the file is already complete in the network
+    ///
+    /// Looks the file's hash up in the cache and DHT. A complete manifest the
+    /// upload could have produced itself has its TTL renewed and is returned
+    /// as is; anything else (missing, degraded, another namespace, different
+    /// keys) is left for a fresh upload. Disabled by [`Self::with_force`].
+    async fn reuse_existing(&self, file_path: &Path) -> Result<Option<UploadResult>, UploadError> {
+        if self.force {
+            return Ok(None);
+        }
+        let started = Instant::now();
+        let data = tokio::fs::read(file_path).await?;
+        let file_hash = format!("{:x}", Sha256::digest(&data));
+
+        let Some(found) = self.lookup.lookup_file(&file_hash).await? else {
+            return Ok(None);
+        };
+        if !found.is_complete {
+            info!(
+                "♻️  {} is in the network but degraded ({}/{} shards); uploading again",
+                file_hash, found.available_shards, found.manifest.shard_count
+            );
+            return Ok(None);
+        }
+        if !self.upload.can_reuse(&found.manifest) {
+            debug!(
+                "Existing manifest for {} differs in namespace, class or keys",
+                file_hash
+            );
+            return Ok(None);
+        }
+
+        // Renew the lease on the existing copy rather than sending it again
+        let mut manifest = found.manifest;
+        if manifest.ttl > 0 {
+            match self.cache.refresh_ttl(&file_hash, manifest.ttl).await {
+                Ok(_) => manifest.timestamp = chrono::Utc::now().timestamp(),
+                Err(e) => warn!("Failed to renew TTL of {}: {}", file_hash, e),
+            }
+        }
+        if self.dht.is_some() {
+            self.lookup.register_file(&manifest).await?;
+        }
+        info!(
+            "✅ {} is already in the network; skipped the transfer",
+            file_hash
+        );
+
+        if let Some(audit) = &self.audit {
+            let mut record = AuditRecord::new(AuditOperation::Upload, started.elapsed());
+            record.requester = self.requester.clone();
+            record.file_hash = Some(file_hash.clone());
+            record.bytes = manifest.file_size as u64;
+            record.peers = manifest.shard_locations.iter().map(|(_, p)| *p).collect();
+            record.peers.sort_unstable();
+            record.peers.dedup();
+            audit.record_or_warn(&record);
+        }
+
+        Ok(Some(UploadResult {
+            file_hash,
+            manifest_json: serde_json::to_string_pretty(&manifest)?,
+            shard_count: manifest.shard_count,
+            total_peers: manifest.shard_locations.len(),
+            transfers: Vec::new(),
+            deduplicated: true,
+        }))
+    }
+
+    /// Discover peers in the uploader's storage class, failing if there are none
+    async fn require_target_peers(&self) -> Result<Vec<u32>, UploadError> {
+        self.require_peers_in(self.upload.storage_class()).await
+    }
+
+    /// Discover peers (in `class`, if given), failing if there are none
+    async fn require_peers_in(&self, class: Option<&str>) -> Result<Vec<u32>, UploadError> {
         info!("🔍 Discovering available peers...");
-        let target_peers = self.discover_target_peers().await?;
+        let mut target_peers = self.discover_target_peers().await;
 
         if target_peers.is_empty() {
-            bail!("No available peers found. Start at least one other node.");
+            return Err(UploadError::NoPeers);
+        }
+        if let Some(class) = class {
+            target_peers = self.store.filter_by_class(&target_peers, class).await;
+            if target_peers.is_empty() {
+                return Err(UploadError::NoClassPeers(class.to_string()));
+            }
         }
 
         info!(
@@ -102,17 +463,80 @@ impl AutomatedUploader {
             target_peers.len(),
             target_peers
         );
+        Ok(target_peers)
+    }
+
+    /// Upload a validated file (or finish its interrupted `session`) to
+    /// already discovered peers, auditing the outcome
+    async fn upload_to_peers(
+        &self,
+        file_path: &Path,
+        session: Option<UploadSession>,
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
+    ) -> Result<UploadResult, UploadError> {
+        let _permit = match &self.limits {
+            Some(limits) => Some(limits.acquire(LimitName::Uploads).await),
+            None => None,
+        };
+        let started = Instant::now();
+        let result = self
+            .distribute(file_path, session, target_peers.clone(), priority)
+            .await;
 
+        if let Some(audit) = &self.audit {
+            let mut record = AuditRecord::new(AuditOperation::Upload, started.elapsed());
+            record.requester = self.requester.clone();
+            match &result {
+                Ok((upload, manifest)) => {
+                    record.file_hash = Some(upload.file_hash.clone());
+                    record.bytes = manifest.file_size as u64;
+                    record.peers = manifest.shard_locations.iter().map(|(_, p)| *p).collect();
+                    record.peers.sort_unstable();
+                    record.peers.dedup();
+                }
+                Err(e) => {
+                    record.success = false;
+                    record.error = Some(e.to_string());
+                    record.peers = target_peers;
+                    record.bytes = tokio::fs::metadata(file_path)
+                        .await
+                        .map(|m| m.len())
+                        .unwrap_or(0);
+                }
+            }
+            audit.record_or_warn(&record);
+        }
+        result.map(|(upload, _)| upload)
+    }
+
+    /// Process a file, or pick up its session, and send its shards to `target_peers`
+    async fn distribute(
+        &self,
+        file_path: &Path,
+        session: Option<UploadSession>,
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
+    ) -> Result<(UploadResult, FileManifest), UploadError> {
         // 3. Upload file
         info!("📤 Uploading file and distributing shards...");
-        let manifest_json = self
-            .upload
-            .upload_file(file_path, target_peers)
-            .await
-            .context("Upload failed")?;
+        let report = match session {
+            Some(session) => {
+                self.upload
+                    .resume_session(session, target_peers, priority)
+                    .await
+            }
+            None => {
+                self.upload
+                    .upload_file_with_report(file_path, target_peers, priority)
+                    .await
+            }
+        }
+        .map_err(UploadError::Transfer)?;
+        let manifest_json = report.manifest_json;
 
         // Parse manifest to get file hash
-        let manifest: crate::cache::FileManifest = serde_json::from_str(&manifest_json)?;
+        let manifest: FileManifest = serde_json::from_str(&manifest_json)?;
         let file_hash = manifest.file_hash.clone();
 
         // 4. Register in DHT
@@ -126,16 +550,19 @@ impl AutomatedUploader {
         info!("📦 Shards created: {}", manifest.shard_count);
         info!("📍 Shard locations: {:?}", manifest.shard_locations);
 
-        Ok(UploadResult {
+        let result = UploadResult {
             file_hash,
             manifest_json,
             shard_count: manifest.shard_count,
             total_peers: manifest.shard_locations.len(),
-        })
+            transfers: report.transfers,
+            deduplicated: false,
+        };
+        Ok((result, manifest))
     }
 
     /// Discover target peers for upload
-    async fn discover_target_peers(&self) -> Result<Vec<u32>> {
+    async fn discover_target_peers(&self) -> Vec<u32> {
         let mut peers = Vec::new();
 
         // Get all active nodes from store
@@ -147,6 +574,12 @@ impl AutomatedUploader {
             }
         }
 
+        // Prefer peers with the lowest recent latency (skipping peers banned
+        // for serving corrupt shards), then spread shards across failure
+        // domains so one network outage loses fewer of them
+        let peers = self.store.rank_sources(&peers).await;
+        let peers = self.store.spread_across_domains(&peers).await;
+
         // DHT peer discovery: Currently we rely on the NodeStore for peer tracking.
         // The DHT is used for file registration and lookup (see lookup.rs) but not
         // for discovering arbitrary peers. Peers are discovered through the node store
@@ -155,7 +588,7 @@ impl AutomatedUploader {
             debug!("DHT available for file operations");
         }
 
-        Ok(peers)
+        peers
     }
 }
 
@@ -166,13 +599,23 @@ pub struct UploadResult {
     pub manifest_json: String,
     pub shard_count: usize,
     pub total_peers: usize,
+    /// Transport used for each shard
+    pub transfers: Vec<ShardTransfer>,
+    /// The file was already complete in the network, so nothing was sent
+    pub deduplicated: bool,
 }
 
 /// High-level automated downloader
 /// Just provide a file hash and it handles everything
 pub struct AutomatedDownloader {
-    download: Arc<DownloadProtocol>,
+    download: DownloadProtocol,
+    cache: Arc<Cache>,
     lookup: Arc<LookupService>,
+    keypair: Option<Arc<NodeKeypair>>,
+    audit: Option<Arc<AuditLog>>,
+    requester: Option<String>,
+    namespace: String,
+    limits: Option<Arc<ConcurrencyLimits>>,
 }
 
 impl AutomatedDownloader {
@@ -185,10 +628,131 @@ impl AutomatedDownloader {
         store: Arc<NodeStore>,
         dht: Option<Arc<tokio::sync::RwLock<DhtNode>>>,
     ) -> Self {
-        let download = Arc::new(DownloadProtocol::with_cache(ces, go_client, cache.clone()));
-        let lookup = Arc::new(LookupService::new(cache, dht, store));
+        let download = DownloadProtocol::with_cache(ces, go_client, cache.clone())
+            .with_node_store(store.clone());
+        let lookup = Arc::new(LookupService::new(cache.clone(), dht, store));
+
+        Self {
+            download,
+            cache,
+            lookup,
+            keypair: None,
+            audit: None,
+            requester: None,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            limits: None,
+        }
+    }
 
-        Self { download, lookup }
+    /// Share bandwidth with other transfers through a scheduler
+    pub fn with_scheduler(self, scheduler: Arc<TransferScheduler>) -> Self {
+        Self {
+            download: self.download.with_scheduler(scheduler),
+            ..self
+        }
+    }
+
+    /// Fetch shards over native QUIC when the Go node is unavailable
+    pub fn with_native_transport(self, network: Arc<QuicNode>) -> Self {
+        Self {
+            download: self.download.with_native_transport(network),
+            ..self
+        }
+    }
+
+    /// Hold shard holders to the identities manifests record for them
+    pub fn with_peer_directory(self, directory: Arc<PeerDirectory>) -> Self {
+        Self {
+            download: self.download.with_peer_directory(directory),
+            ..self
+        }
+    }
+
+    /// Fetch shards through `transport` instead of the Go node
+    pub fn with_transport(self, transport: Arc<dyn ShardTransport>) -> Self {
+        Self {
+            download: self.download.with_transport(transport),
+            ..self
+        }
+    }
+
+    /// Unwrap file keys that uploaders wrapped for this node
+    pub fn with_keypair(self, keypair: Arc<NodeKeypair>) -> Self {
+        Self {
+            keypair: Some(keypair),
+            ..self
+        }
+    }
+
+    /// Record fetch latencies and hedge slow shard fetches
+    pub fn with_metrics(self, metrics: Arc<MetricsTracker>) -> Self {
+        Self {
+            download: self.download.with_metrics(metrics),
+            ..self
+        }
+    }
+
+    /// Publish per-shard download progress to a hub
+    pub fn with_progress(self, hub: ProgressHub) -> Self {
+        Self {
+            download: self.download.with_progress(hub),
+            ..self
+        }
+    }
+
+    /// Record every download in an audit log, attributed to `requester`
+    pub fn with_audit_log(self, audit: Arc<AuditLog>, requester: impl Into<String>) -> Self {
+        Self {
+            audit: Some(audit),
+            requester: Some(requester.into()),
+            ..self
+        }
+    }
+
+    /// Only see and fetch files in this namespace
+    pub fn with_namespace(self, namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            ..self
+        }
+    }
+
+    /// Also search keywords other nodes published to the DHT
+    pub fn with_keyword_index(self, index: Arc<KeywordIndex>) -> Self {
+        Self {
+            lookup: Arc::new((*self.lookup).clone().with_keyword_index(index)),
+            ..self
+        }
+    }
+
+    /// Share DHT lookup results (and misses) with other lookups
+    pub fn with_query_cache(self, queries: Arc<QueryCache<FileManifest>>) -> Self {
+        Self {
+            lookup: Arc::new((*self.lookup).clone().with_query_cache(queries)),
+            ..self
+        }
+    }
+
+    /// Pick probable shard holders from peers' advertised shard filters
+    pub fn with_availability_index(self, index: Arc<ShardAvailabilityIndex>) -> Self {
+        Self {
+            download: self.download.with_availability_index(index.clone()),
+            lookup: Arc::new((*self.lookup).clone().with_availability_index(index)),
+            ..self
+        }
+    }
+
+    /// Queue downloads (and their DHT queries) behind the node's limits
+    pub fn with_concurrency_limits(self, limits: Arc<ConcurrencyLimits>) -> Self {
+        Self {
+            lookup: Arc::new(
+                (*self.lookup)
+                    .clone()
+                    .with_concurrency_limits(limits.clone()),
+            ),
+            limits: Some(limits),
+            ..self
+        }
     }
 
     /// Download a file with full automation
@@ -204,9 +768,51 @@ impl AutomatedDownloader {
         &self,
         file_hash: &str,
         output_path: impl AsRef<Path>,
-    ) -> Result<DownloadResult> {
-        let output_path = output_path.as_ref();
+    ) -> Result<DownloadResult, DownloadError> {
+        self.download_with_priority(file_hash, output_path, TransferPriority::Interactive)
+            .await
+    }
 
+    /// Download a file with an explicit transfer priority
+    pub async fn download_with_priority(
+        &self,
+        file_hash: &str,
+        output_path: impl AsRef<Path>,
+        priority: TransferPriority,
+    ) -> Result<DownloadResult, DownloadError> {
+        let _permit = match &self.limits {
+            Some(limits) => Some(limits.acquire(LimitName::Downloads).await),
+            None => None,
+        };
+        let started = Instant::now();
+        let result = self.fetch(file_hash, output_path.as_ref(), priority).await;
+
+        if let Some(audit) = &self.audit {
+            let mut record = AuditRecord::new(AuditOperation::Download, started.elapsed());
+            record.requester = self.requester.clone();
+            record.file_hash = Some(file_hash.to_string());
+            match &result {
+                Ok(download) => {
+                    record.bytes = download.bytes_written as u64;
+                    record.peers = download.peers.clone();
+                }
+                Err(e) => {
+                    record.success = false;
+                    record.error = Some(e.to_string());
+                }
+            }
+            audit.record_or_warn(&record);
+        }
+        result
+    }
+
+    /// Look up, fetch and reconstruct a file
+    async fn fetch(
+        &self,
+        file_hash: &str,
+        output_path: &Path,
+        priority: TransferPriority,
+    ) -> Result<DownloadResult, DownloadError> {
         info!("🚀 Starting automated download");
         info!("🔑 File hash: {}", file_hash);
         info!("💾 Output path: {:?}", output_path);
@@ -217,7 +823,8 @@ impl AutomatedDownloader {
             .lookup
             .lookup_file(file_hash)
             .await?
-            .context("File not found in cache or DHT")?;
+            .filter(|result| self.in_namespace(&result.manifest))
+            .ok_or_else(|| DownloadError::FileNotFound(file_hash.to_string()))?;
 
         if !lookup_result.is_complete {
             warn!(
@@ -235,21 +842,36 @@ impl AutomatedDownloader {
 
         // 2. Prepare shard locations
         let shard_locations = lookup_result.manifest.shard_locations.clone();
+        let mut peers: Vec<u32> = shard_locations.iter().map(|(_, peer)| *peer).collect();
+        peers.sort_unstable();
+        peers.dedup();
         info!(
             "📍 Fetching shards from {} location(s)...",
             shard_locations.len()
         );
 
-        // 3. Download and reconstruct
+        // 3. Unwrap the file key if it was shared with this node, and the
+        // keys of any rotation
+        let file_key = self.unwrap_file_key(&lookup_result.manifest)?;
+        let shard_keys = self.unwrap_shard_keys(&lookup_result.manifest)?;
+
+        // 4. Download and reconstruct
         info!("📥 Downloading shards and reconstructing file...");
         let bytes_written = self
             .download
-            .download_file_with_hash(output_path, shard_locations, Some(file_hash))
+            .download_manifest(
+                output_path,
+                &lookup_result.manifest,
+                priority,
+                file_key,
+                shard_keys.as_ref(),
+            )
             .await
-            .context("Download failed")?;
+            .map_err(DownloadError::Transfer)?;
 
         info!("✅ Download complete!");
         info!("💾 Bytes written: {}", bytes_written);
+        self.cache.record_access(file_hash);
 
         Ok(DownloadResult {
             file_hash: file_hash.to_string(),
@@ -257,16 +879,185 @@ impl AutomatedDownloader {
             bytes_written,
             shards_fetched: lookup_result.available_shards,
             output_path: output_path.to_path_buf(),
+            peers,
         })
     }
 
+    /// Download several files, at most `concurrency` at a time
+    ///
+    /// Each item is a file hash and its output path. Results are in input
+    /// order; a failed file does not stop the others.
+    pub async fn download_many(
+        &self,
+        items: Vec<(String, PathBuf)>,
+        priority: TransferPriority,
+        concurrency: usize,
+    ) -> Vec<Result<DownloadResult, DownloadError>> {
+        info!(
+            "🚀 Starting batch download of {} file(s) (concurrency {})",
+            items.len(),
+            concurrency
+        );
+        let results: Vec<_> = stream::iter(items)
+            .map(|(file_hash, output_path)| async move {
+                self.download_with_priority(&file_hash, &output_path, priority)
+                    .await
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        info!(
+            "✅ Batch download finished: {} succeeded, {} failed",
+            results.len() - failed,
+            failed
+        );
+        results
+    }
+
+    /// Write a file's manifest and shards to a bundle for offline transfer
+    ///
+    /// Shards stay encrypted, so no key is needed to export. Fails unless
+    /// enough shards could be fetched to reconstruct the file.
+    pub async fn export_bundle(
+        &self,
+        file_hash: &str,
+        bundle_path: impl AsRef<Path>,
+        priority: TransferPriority,
+    ) -> Result<ExportResult, DownloadError> {
+        info!("📦 Exporting {} to {:?}", file_hash, bundle_path.as_ref());
+        let manifest = self
+            .lookup
+            .lookup_file(file_hash)
+            .await?
+            .map(|result| result.manifest)
+            .filter(|manifest| self.in_namespace(manifest))
+            .ok_or_else(|| DownloadError::FileNotFound(file_hash.to_string()))?;
+
+        let shards = self
+            .download
+            .fetch_manifest_shards(&manifest, priority)
+            .await
+            .map_err(DownloadError::Transfer)?;
+        let bundle = Bundle::new(manifest, shards);
+        bundle.verify().map_err(DownloadError::Transfer)?;
+        bundle.save(bundle_path.as_ref()).await?;
+
+        let bytes = tokio::fs::metadata(bundle_path.as_ref()).await?.len();
+        info!(
+            "✅ Exported {} shard(s), {} bytes",
+            bundle.shards.len(),
+            bytes
+        );
+        Ok(ExportResult {
+            file_hash: file_hash.to_string(),
+            file_name: bundle.manifest.file_name,
+            shards_exported: bundle.shards.len(),
+            bytes,
+            bundle_path: bundle_path.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Verify a bundle and store its manifest and shards in the local cache
+    ///
+    /// With `register`, the manifest is also published to the DHT so peers
+    /// can find the file again.
+    pub async fn import_bundle(
+        &self,
+        bundle_path: impl AsRef<Path>,
+        register: bool,
+    ) -> Result<ImportReport, DownloadError> {
+        info!("📦 Importing bundle {:?}", bundle_path.as_ref());
+        let bundle = Bundle::load(bundle_path).await?;
+        let manifest = bundle.manifest.clone();
+        let report = bundle.import_into(&self.cache).await?;
+        if register {
+            info!("📡 Registering file in DHT...");
+            self.lookup.register_file(&manifest).await?;
+        }
+        info!(
+            "✅ Imported {} ({} shard(s))",
+            report.file_hash, report.shards_imported
+        );
+        Ok(report)
+    }
+
+    /// Files in other namespaces are treated as absent
+    /// Keep the hottest files' shards cached ahead of demand
+    ///
+    /// Takes up to `limit` hot files from the cache's popularity tracker,
+    /// whatever their namespace, and fetches what each is missing to be
+    /// rebuilt locally. Returns the number of shards fetched.
+    pub async fn prefetch_hot(&self, limit: usize) -> usize {
+        let Some(popularity) = self.cache.popularity() else {
+            return 0;
+        };
+        let mut fetched = 0;
+        for file_hash in popularity.prefetch_candidates(limit) {
+            let Some(manifest) = self.cache.get_manifest(&file_hash).await else {
+                continue;
+            };
+            match self.download.prefetch_manifest(&manifest).await {
+                Ok(count) => fetched += count,
+                Err(e) => debug!("Prefetch of {} failed: {:#}", file_hash, e),
+            }
+        }
+        if fetched > 0 {
+            info!("🔥 Prefetched {} shard(s) of hot files", fetched);
+        }
+        fetched
+    }
+
+    fn in_namespace(&self, manifest: &FileManifest) -> bool {
+        manifest.namespace == self.namespace
+    }
+
+    /// File key wrapped for this node's public key, if any
+    fn unwrap_file_key(&self, manifest: &FileManifest) -> Result<Option<[u8; 32]>, DownloadError> {
+        if manifest.recipients.is_empty() {
+            return Ok(None);
+        }
+        let Some(keypair) = &self.keypair else {
+            warn!("⚠️  File key is wrapped for recipients but no node keypair is configured");
+            return Ok(None);
+        };
+        let key = keypair
+            .unwrap_key(&manifest.recipients)
+            .map_err(DownloadError::KeyUnwrap)?;
+        match key {
+            Some(_) => info!("🔑 Unwrapped file key with node keypair"),
+            None => warn!("⚠️  File key was not wrapped for this node"),
+        }
+        Ok(key)
+    }
+
+    /// Keys of the file's rotated key versions, if it was rotated
+    ///
+    /// Without them only shard copies not yet re-encrypted can be read.
+    fn unwrap_shard_keys(
+        &self,
+        manifest: &FileManifest,
+    ) -> Result<Option<ShardKeys>, DownloadError> {
+        if manifest.rotation.is_none() {
+            return Ok(None);
+        }
+        let Some(keypair) = &self.keypair else {
+            warn!("⚠️  File key was rotated but no node keypair is configured");
+            return Ok(None);
+        };
+        ShardKeys::unwrap(keypair, manifest)
+            .map(Some)
+            .map_err(DownloadError::KeyUnwrap)
+    }
+
     /// List all available files
-    pub async fn list_files(&self) -> Result<Vec<FileInfo>> {
+    pub async fn list_files(&self) -> Result<Vec<FileInfo>, DownloadError> {
         info!("📋 Listing all available files...");
         let manifests = self.lookup.list_cached_files().await?;
 
         let mut files = Vec::new();
-        for manifest in manifests {
+        for manifest in manifests.into_iter().filter(|m| self.in_namespace(m)) {
             // Check availability
             let is_available = self.lookup.verify_file(&manifest.file_hash).await?;
 
@@ -277,6 +1068,7 @@ impl AutomatedDownloader {
                 shard_count: manifest.shard_count,
                 is_available,
                 timestamp: manifest.timestamp,
+                source: SearchSource::LocalCache,
             });
         }
 
@@ -284,22 +1076,52 @@ impl AutomatedDownloader {
         Ok(files)
     }
 
+    /// List local files, then files recently published to the network
+    ///
+    /// Network files already cached locally are listed once, as local.
+    pub async fn list_files_with_network(&self) -> Result<Vec<FileInfo>, DownloadError> {
+        let mut files = self.list_files().await?;
+        let local: HashSet<String> = files.iter().map(|f| f.file_hash.clone()).collect();
+        let recent = self.lookup.recent_network_files().await?;
+        let mut announced = 0;
+        for hit in recent
+            .into_iter()
+            .filter(|h| h.namespace == self.namespace && !local.contains(&h.file_hash))
+        {
+            let is_available = self.lookup.verify_file(&hit.file_hash).await?;
+            files.push(FileInfo {
+                file_hash: hit.file_hash,
+                file_name: hit.file_name,
+                file_size: hit.file_size,
+                shard_count: hit.shard_count,
+                is_available,
+                timestamp: hit.timestamp,
+                source: hit.source,
+            });
+            announced += 1;
+        }
+
+        info!("🌐 Found {} more file(s) in the network", announced);
+        Ok(files)
+    }
+
     /// Search files by name
-    pub async fn search(&self, pattern: &str) -> Result<Vec<FileInfo>> {
+    pub async fn search(&self, pattern: &str) -> Result<Vec<FileInfo>, DownloadError> {
         info!("🔍 Searching files matching: '{}'", pattern);
-        let manifests = self.lookup.search_files(pattern).await?;
+        let hits = self.lookup.search_files(pattern).await?;
 
         let mut files = Vec::new();
-        for manifest in manifests {
-            let is_available = self.lookup.verify_file(&manifest.file_hash).await?;
+        for hit in hits.into_iter().filter(|h| h.namespace == self.namespace) {
+            let is_available = self.lookup.verify_file(&hit.file_hash).await?;
 
             files.push(FileInfo {
-                file_hash: manifest.file_hash,
-                file_name: manifest.file_name,
-                file_size: manifest.file_size,
-                shard_count: manifest.shard_count,
+                file_hash: hit.file_hash,
+                file_name: hit.file_name,
+                file_size: hit.file_size,
+                shard_count: hit.shard_count,
                 is_available,
-                timestamp: manifest.timestamp,
+                timestamp: hit.timestamp,
+                source: hit.source,
             });
         }
 
@@ -308,8 +1130,12 @@ impl AutomatedDownloader {
     }
 
     /// Get file info without downloading
-    pub async fn get_info(&self, file_hash: &str) -> Result<Option<FileInfo>> {
-        let lookup_result = self.lookup.lookup_file(file_hash).await?;
+    pub async fn get_info(&self, file_hash: &str) -> Result<Option<FileInfo>, DownloadError> {
+        let lookup_result = self
+            .lookup
+            .lookup_file(file_hash)
+            .await?
+            .filter(|result| self.in_namespace(&result.manifest));
 
         if let Some(result) = lookup_result {
             Ok(Some(FileInfo {
@@ -319,6 +1145,7 @@ impl AutomatedDownloader {
                 shard_count: result.manifest.shard_count,
                 is_available: result.is_complete,
                 timestamp: result.manifest.timestamp,
+                source: SearchSource::LocalCache,
             }))
         } else {
             Ok(None)
@@ -326,6 +1153,17 @@ impl AutomatedDownloader {
     }
 }
 
+/// Result of exporting a bundle
+#[derive(Debug, Clone)]
+pub struct ExportResult {
+    pub file_hash: String,
+    pub file_name: String,
+    pub shards_exported: usize,
+    /// Size of the bundle file
+    pub bytes: u64,
+    pub bundle_path: PathBuf,
+}
+
 /// Result of an automated download
 #[derive(Debug, Clone)]
 pub struct DownloadResult {
@@ -334,6 +1172,8 @@ pub struct DownloadResult {
     pub bytes_written: usize,
     pub shards_fetched: usize,
     pub output_path: PathBuf,
+    /// Peers holding the file's shards
+    pub peers: Vec<u32>,
 }
 
 /// File information for listing
@@ -345,6 +1185,8 @@ pub struct FileInfo {
     pub shard_count: usize,
     pub is_available: bool,
     pub timestamp: i64,
+    /// Local cache, or the DHT keyword index for files found by search
+    pub source: SearchSource,
 }
 
 #[cfg(test)]
@@ -354,6 +1196,7 @@ mod tests {
     use crate::types::CesConfig;
 
     #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
     async fn test_automated_uploader_creation() {
         let caps = HardwareCaps::probe();
         let config = CesConfig::adaptive(&caps, 8 * 1024 * 1024, 1.0);
@@ -372,10 +1215,11 @@ mod tests {
             store.clone(),
             None,
         );
-        assert!(true); // Uploader created successfully
+        // Uploader created successfully - just checking it doesn't panic
     }
 
     #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
     async fn test_automated_downloader_creation() {
         let caps = HardwareCaps::probe();
         let config = CesConfig::adaptive(&caps, 8 * 1024 * 1024, 1.0);
@@ -388,6 +1232,385 @@ mod tests {
         let store = Arc::new(NodeStore::new());
 
         let _downloader = AutomatedDownloader::new(ces, go_client, cache, store, None);
-        assert!(true); // Downloader created successfully
+        // Downloader created successfully - just checking it doesn't panic
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_batch_operations_report_per_item_results() {
+        let caps = HardwareCaps::probe();
+        let config = CesConfig::adaptive(&caps, 8 * 1024 * 1024, 1.0);
+        let ces = Arc::new(CesPipeline::new(config));
+        let go_addr: std::net::SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let go_client = Arc::new(GoClient::new(go_addr));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 1000, 1024 * 1024).unwrap());
+        let store = Arc::new(NodeStore::new());
+
+        let uploader = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            None,
+        );
+        let missing = vec![dir.path().join("a.txt"), dir.path().join("b.txt")];
+
+        // No peers: the shared discovery fails the whole batch
+        let result = uploader
+            .upload_many(missing.clone(), TransferPriority::Background, 2)
+            .await;
+        assert!(matches!(result, Err(UploadError::NoPeers)));
+
+        store.upsert_node(crate::types::Node::new(7)).await;
+        let results = uploader
+            .upload_many(missing.clone(), TransferPriority::Background, 2)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        for (result, path) in results.iter().zip(&missing) {
+            assert!(matches!(result, Err(UploadError::FileNotFound(p)) if p == path));
+        }
+
+        let downloader = AutomatedDownloader::new(ces, go_client, cache, store, None);
+        let items = vec![
+            ("hash-1".to_string(), dir.path().join("out-1")),
+            ("hash-2".to_string(), dir.path().join("out-2")),
+        ];
+        let results = downloader
+            .download_many(items, TransferPriority::Background, 2)
+            .await;
+        assert!(matches!(&results[0], Err(DownloadError::FileNotFound(h)) if h == "hash-1"));
+        assert!(matches!(&results[1], Err(DownloadError::FileNotFound(h)) if h == "hash-2"));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_storage_class_limits_target_peers() {
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 1000, 1024 * 1024).unwrap());
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(crate::types::Node::new(id)).await;
+        }
+
+        let uploader = AutomatedUploader::new(ces, go_client, cache, store.clone(), None)
+            .with_storage_class("archival");
+        assert!(matches!(
+            uploader.require_target_peers().await,
+            Err(UploadError::NoClassPeers(class)) if class == "archival"
+        ));
+
+        store
+            .set_peer_tags(3, ["archival".to_string()])
+            .await
+            .unwrap();
+        store.set_peer_tags(4, ["edge".to_string()]).await.unwrap();
+        assert_eq!(uploader.require_target_peers().await.unwrap(), vec![3]);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_upload_skips_files_already_in_network() {
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=4));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(crate::types::Node::new(id)).await;
+        }
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 1000, 1024 * 1024).unwrap());
+        let file = dir.path().join("dup.txt");
+        tokio::fs::write(&file, b"upload me once ".repeat(200))
+            .await
+            .unwrap();
+
+        let uploader = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            None,
+        )
+        .with_transport(network.clone());
+        let first = uploader.upload(&file).await.unwrap();
+        assert!(!first.deduplicated);
+        let sent = network.stats().sent;
+        assert!(sent > 0);
+
+        let second = uploader.upload(&file).await.unwrap();
+        assert!(second.deduplicated);
+        assert_eq!(second.file_hash, first.file_hash);
+        assert!(second.transfers.is_empty());
+        assert_eq!(network.stats().sent, sent);
+
+        // Another namespace does not share the existing copy
+        let other = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            None,
+        )
+        .with_transport(network.clone())
+        .with_namespace("photos");
+        assert!(!other.upload(&file).await.unwrap().deduplicated);
+
+        let forced = AutomatedUploader::new(ces, go_client, cache, store, None)
+            .with_transport(network.clone())
+            .with_force(true);
+        assert!(!forced.upload(&file).await.unwrap().deduplicated);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_interrupted_upload_resumes_without_resending() {
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=4));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(crate::types::Node::new(id)).await;
+        }
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 1000, 1024 * 1024).unwrap());
+        let sessions = Arc::new(UploadSessions::in_cache_dir(dir.path()));
+        let file = dir.path().join("big.bin");
+        tokio::fs::write(&file, b"checkpoint me ".repeat(500))
+            .await
+            .unwrap();
+
+        // A peer going away part way through leaves a session behind
+        network.set_peer_down(4, true);
+        let uploader = AutomatedUploader::new(ces, go_client, cache.clone(), store, None)
+            .with_transport(network.clone())
+            .with_sessions(sessions.clone());
+        assert!(uploader.upload(&file).await.is_err());
+        let pending = sessions.list().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let session = &pending[0];
+        let left = session.pending().len();
+        assert!(left > 0 && left < session.manifest.shard_count);
+
+        // Resuming sends only what is missing and retires the session
+        network.set_peer_down(4, false);
+        let sent = network.stats().sent;
+        let result = uploader
+            .resume(&session.id, TransferPriority::Interactive)
+            .await
+            .unwrap();
+        assert_eq!(network.stats().sent - sent, left as u64);
+        assert_eq!(result.file_hash, session.manifest.file_hash);
+        let manifest = cache.get_manifest(&result.file_hash).await.unwrap();
+        assert_eq!(manifest.shard_locations.len(), manifest.shard_count);
+        assert!(sessions.list().await.unwrap().is_empty());
+
+        assert!(matches!(
+            uploader
+                .resume(&session.id, TransferPriority::Interactive)
+                .await,
+            Err(UploadError::SessionNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_prefetch_warms_cache_for_hot_files() {
+        use crate::popularity::{PopularityConfig, PopularityTracker};
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=4));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(crate::types::Node::new(id)).await;
+        }
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let popularity = Arc::new(PopularityTracker::new(PopularityConfig::default()));
+        let cache = Arc::new(
+            Cache::new(dir.path().join("cache"), 1000, 1024 * 1024)
+                .unwrap()
+                .with_popularity(popularity.clone()),
+        );
+        let file = dir.path().join("hot.txt");
+        tokio::fs::write(&file, b"everyone wants this ".repeat(300))
+            .await
+            .unwrap();
+        let uploader = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            None,
+        )
+        .with_transport(network.clone());
+        let hash = uploader.upload(&file).await.unwrap().file_hash;
+        cache.clear_shards().await.unwrap();
+
+        let downloader = AutomatedDownloader::new(ces, go_client, cache.clone(), store, None)
+            .with_transport(network.clone());
+        // Not hot yet: nothing to prefetch
+        assert_eq!(downloader.prefetch_hot(8).await, 0);
+
+        for _ in 0..10 {
+            cache.record_access(&hash);
+        }
+        let manifest = cache.get_manifest(&hash).await.unwrap();
+        let needed = manifest.data_shard_count();
+        assert_eq!(downloader.prefetch_hot(8).await, needed);
+        assert!(cache.has_shard(&hash, 0).await);
+        // Everything needed is cached now
+        assert_eq!(downloader.prefetch_hot(8).await, 0);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_tiered_upload_keeps_groups_in_their_domain() {
+        use crate::placement::PlacementPolicy;
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+        use crate::types::{GeoInfo, Node};
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=6));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=6 {
+            let mut node = Node::new(id);
+            node.geo = Some(GeoInfo {
+                asn: Some(if id <= 3 { 100 } else { 200 }),
+                ..GeoInfo::default()
+            });
+            store.upsert_node(node).await;
+        }
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 1000, 1024 * 1024).unwrap());
+        let file = dir.path().join("regional.txt");
+        let contents = b"kept in two regions ".repeat(300);
+        tokio::fs::write(&file, &contents).await.unwrap();
+        let uploader = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            None,
+        )
+        .with_transport(network.clone())
+        .with_placement(PlacementPolicy {
+            tiers: true,
+            ..PlacementPolicy::default()
+        });
+        let hash = uploader.upload(&file).await.unwrap().file_hash;
+
+        let manifest = cache.get_manifest(&hash).await.unwrap();
+        let local = manifest.local_parity.clone().unwrap();
+        assert_eq!(
+            manifest.shard_count,
+            manifest.outer_shard_count() + local.shard_count()
+        );
+        for group in &local.groups {
+            let domain = if group.domain == "AS100" {
+                1..=3
+            } else {
+                4..=6
+            };
+            for index in group.members.iter().chain(&group.parity) {
+                let (_, peer) = manifest
+                    .shard_locations
+                    .iter()
+                    .find(|(shard, _)| shard == index)
+                    .unwrap();
+                assert!(domain.contains(peer), "shard {} on peer {}", index, peer);
+            }
+        }
+
+        // Intact files only need their outer shards
+        cache.clear_shards().await.unwrap();
+        let downloader = AutomatedDownloader::new(ces, go_client, cache.clone(), store, None)
+            .with_transport(network.clone());
+        let fetched = network.stats().fetched;
+        let out = dir.path().join("out-1");
+        downloader.download(&hash, &out).await.unwrap();
+        assert_eq!(
+            network.stats().fetched - fetched,
+            manifest.outer_shard_count() as u64
+        );
+        assert_eq!(tokio::fs::read(&out).await.unwrap(), contents);
+
+        // Losing a peer's shards is repaired from either tier
+        cache.clear_shards().await.unwrap();
+        network.wipe_peer(1);
+        let out = dir.path().join("out-2");
+        downloader.download(&hash, &out).await.unwrap();
+        assert_eq!(tokio::fs::read(&out).await.unwrap(), contents);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_upload_leaves_out_the_local_node() {
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+        use crate::types::Node;
+
+        let network = Arc::new(SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=4));
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=4 {
+            store.upsert_node(Node::new(id)).await;
+        }
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let go_client = Arc::new(GoClient::new("127.0.0.1:8080".parse().unwrap()));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path().join("cache"), 1000, 1024 * 1024).unwrap());
+        let file = dir.path().join("mine.txt");
+        tokio::fs::write(&file, b"not kept at home ".repeat(300))
+            .await
+            .unwrap();
+        let uploader = AutomatedUploader::new(ces, go_client, cache.clone(), store, None)
+            .with_transport(network)
+            .with_local_peer(1);
+        let hash = uploader.upload(&file).await.unwrap().file_hash;
+
+        let manifest = cache.get_manifest(&hash).await.unwrap();
+        assert_eq!(manifest.uploader, Some(1));
+        assert!(manifest
+            .shard_locations
+            .iter()
+            .all(|&(_, peer)| !manifest.is_uploader(peer)));
     }
 }
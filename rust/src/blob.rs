@@ -0,0 +1,475 @@
+/// Content-addressed blob storage shared by the cache, DCDN and compute
+///
+/// Cached shards, DCDN chunks spilled out of the ring buffer and compiled
+/// WASM modules are all byte strings addressed by their SHA-256. A
+/// `BlobStore` keeps each distinct blob once, however many owners hold it:
+/// every `put` takes a reference and every `release` drops one, and the
+/// bytes go with the last reference.
+///
+/// `TieredBlobStore` keeps recently used blobs in memory and moves the least
+/// recently used ones to a disk directory when the memory budget runs out.
+/// Both budgets are shared by every owner of the store, so one node-wide
+/// limit replaces the separate limits of each subsystem. The disk tier is
+/// scratch space: references are not persisted, so files left from an
+/// earlier run are removed when the store opens.
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+/// SHA-256 of a blob's contents
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BlobId(pub [u8; 32]);
+
+impl BlobId {
+    pub fn of(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    /// Lowercase hex, the form shard content hashes take in manifests
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl fmt::Display for BlobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for BlobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlobId({})", &self.to_hex()[..16])
+    }
+}
+
+/// A blob did not fit in what is left of the store's budgets
+///
+/// Owners that can drop blobs of their own (the cache) free some and retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("blob store is full: no room for {needed} more bytes")]
+pub struct BlobStoreFull {
+    pub needed: u64,
+}
+
+/// Usage of a blob store
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobStats {
+    /// Distinct blobs stored
+    pub blobs: usize,
+    /// References held on them
+    pub references: u64,
+    pub memory_bytes: u64,
+    pub disk_bytes: u64,
+    /// Puts of content that was already stored
+    pub deduplicated: u64,
+    /// Blobs moved from memory to disk
+    pub spilled: u64,
+}
+
+/// Hash-addressed, reference-counted byte storage
+pub trait BlobStore: Send + Sync {
+    /// Store `data`, taking one reference to it
+    ///
+    /// Storing content that is already present only takes a reference. Fails
+    /// with [`BlobStoreFull`] when the blob does not fit.
+    fn put(&self, data: &[u8]) -> Result<BlobId>;
+
+    /// Contents of a stored blob
+    fn get(&self, id: &BlobId) -> Option<Bytes>;
+
+    /// Take another reference to a stored blob; false if it is not stored
+    fn retain(&self, id: &BlobId) -> bool;
+
+    /// Drop one reference, deleting the blob with its last one
+    ///
+    /// Returns true if the blob was deleted.
+    fn release(&self, id: &BlobId) -> bool;
+
+    fn contains(&self, id: &BlobId) -> bool;
+
+    fn stats(&self) -> BlobStats;
+}
+
+/// Blob store settings (`[blobs]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlobStoreConfig {
+    /// Bytes of blobs kept in memory
+    pub memory_bytes: u64,
+    /// Bytes of blobs kept on disk once memory is full (0 = no disk tier)
+    pub disk_bytes: u64,
+    /// Disk tier directory (defaults to `blobs` in the cache directory)
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BlobStoreConfig {
+    fn default() -> Self {
+        Self {
+            memory_bytes: 256 * 1024 * 1024,
+            disk_bytes: 0,
+            dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    blobs: BlobStoreConfig,
+}
+
+impl BlobStoreConfig {
+    /// Load the `[blobs]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[blobs]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.blobs.validate()?;
+        Ok(file.blobs)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.memory_bytes == 0 && self.disk_bytes == 0 {
+            bail!("blobs: memory_bytes and disk_bytes cannot both be 0");
+        }
+        Ok(())
+    }
+
+    /// Open the store this config describes
+    pub fn open(&self, cache_dir: impl AsRef<Path>) -> Result<TieredBlobStore> {
+        let store = TieredBlobStore::new(self.memory_bytes);
+        if self.disk_bytes == 0 {
+            return Ok(store);
+        }
+        let dir = self
+            .dir
+            .clone()
+            .unwrap_or_else(|| cache_dir.as_ref().join("blobs"));
+        store.with_disk(dir, self.disk_bytes)
+    }
+
+    /// Open the store this config describes without its disk tier
+    pub fn open_in_memory(&self) -> Result<TieredBlobStore> {
+        if self.memory_bytes == 0 {
+            bail!("blobs: memory_bytes cannot be 0 without a disk tier");
+        }
+        if self.disk_bytes > 0 {
+            warn!("Blobs are kept in memory only; disk_bytes is ignored");
+        }
+        Ok(TieredBlobStore::new(self.memory_bytes))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tier {
+    Memory,
+    Disk,
+}
+
+struct Entry {
+    refs: u64,
+    size: u64,
+    tier: Tier,
+}
+
+struct DiskTier {
+    dir: PathBuf,
+    budget: u64,
+}
+
+struct Inner {
+    entries: HashMap<BlobId, Entry>,
+    /// Blobs in memory, least recently used last
+    memory: LruCache<BlobId, Bytes>,
+    memory_bytes: u64,
+    disk_bytes: u64,
+    deduplicated: u64,
+    spilled: u64,
+}
+
+/// Blob store with a memory tier over an optional disk tier
+pub struct TieredBlobStore {
+    memory_budget: u64,
+    disk: Option<DiskTier>,
+    inner: Mutex<Inner>,
+}
+
+impl TieredBlobStore {
+    /// A store holding up to `memory_budget` bytes in memory
+    pub fn new(memory_budget: u64) -> Self {
+        Self {
+            memory_budget,
+            disk: None,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                memory: LruCache::unbounded(),
+                memory_bytes: 0,
+                disk_bytes: 0,
+                deduplicated: 0,
+                spilled: 0,
+            }),
+        }
+    }
+
+    /// A store with no limit, for owners that enforce their own
+    pub fn unbounded() -> Self {
+        Self::new(u64::MAX)
+    }
+
+    /// Move blobs that do not fit in memory to `dir`, up to `budget` bytes
+    pub fn with_disk(mut self, dir: impl Into<PathBuf>, budget: u64) -> Result<Self> {
+        let dir = dir.into();
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .with_context(|| format!("Failed to clear blob directory {:?}", dir))?;
+        }
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create blob directory {:?}", dir))?;
+        info!(
+            "Blobs beyond memory spill to {:?} (budget: {} bytes)",
+            dir, budget
+        );
+        self.disk = Some(DiskTier { dir, budget });
+        Ok(self)
+    }
+
+    fn path(dir: &Path, id: &BlobId) -> PathBuf {
+        let hex = id.to_hex();
+        dir.join(&hex[..2]).join(hex)
+    }
+
+    /// Whether `size` more bytes fit in the disk tier
+    fn disk_has_room(&self, inner: &Inner, size: u64) -> bool {
+        self.disk
+            .as_ref()
+            .is_some_and(|disk| inner.disk_bytes + size <= disk.budget)
+    }
+
+    fn write_disk(&self, inner: &mut Inner, id: &BlobId, data: &[u8]) -> Result<()> {
+        let Some(disk) = &self.disk else {
+            bail!("no disk tier for blob {}", id);
+        };
+        let path = Self::path(&disk.dir, id);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, data).with_context(|| format!("Failed to write blob {}", id))?;
+        inner.disk_bytes += data.len() as u64;
+        Ok(())
+    }
+
+    /// Place a new blob, moving least recently used blobs to disk to make room
+    fn admit(&self, inner: &mut Inner, id: BlobId, data: &[u8]) -> Result<Tier> {
+        let size = data.len() as u64;
+        if size > self.memory_budget {
+            if !self.disk_has_room(inner, size) {
+                return Err(BlobStoreFull { needed: size }.into());
+            }
+            self.write_disk(inner, &id, data)?;
+            return Ok(Tier::Disk);
+        }
+
+        let excess = (inner.memory_bytes + size).saturating_sub(self.memory_budget);
+        let mut victims = Vec::new();
+        let mut freed = 0;
+        for (victim, bytes) in inner.memory.iter().rev() {
+            if freed >= excess {
+                break;
+            }
+            freed += bytes.len() as u64;
+            victims.push((*victim, bytes.clone()));
+        }
+        if freed > 0 && !self.disk_has_room(inner, freed) {
+            return Err(BlobStoreFull { needed: size }.into());
+        }
+        for (victim, bytes) in victims {
+            self.write_disk(inner, &victim, &bytes)?;
+            inner.memory.pop(&victim);
+            inner.memory_bytes -= bytes.len() as u64;
+            inner.spilled += 1;
+            if let Some(entry) = inner.entries.get_mut(&victim) {
+                entry.tier = Tier::Disk;
+            }
+            debug!("Spilled blob {} to disk", victim);
+        }
+        inner.memory.put(id, Bytes::copy_from_slice(data));
+        inner.memory_bytes += size;
+        Ok(Tier::Memory)
+    }
+}
+
+impl BlobStore for TieredBlobStore {
+    fn put(&self, data: &[u8]) -> Result<BlobId> {
+        let id = BlobId::of(data);
+        let mut inner = self.inner.lock();
+        if let Some(entry) = inner.entries.get_mut(&id) {
+            entry.refs += 1;
+            inner.deduplicated += 1;
+            inner.memory.promote(&id);
+            return Ok(id);
+        }
+        let tier = self.admit(&mut inner, id, data)?;
+        inner.entries.insert(
+            id,
+            Entry {
+                refs: 1,
+                size: data.len() as u64,
+                tier,
+            },
+        );
+        Ok(id)
+    }
+
+    fn get(&self, id: &BlobId) -> Option<Bytes> {
+        let mut inner = self.inner.lock();
+        let tier = inner.entries.get(id)?.tier;
+        match tier {
+            Tier::Memory => inner.memory.get(id).cloned(),
+            Tier::Disk => {
+                let path = Self::path(&self.disk.as_ref()?.dir, id);
+                match std::fs::read(&path) {
+                    Ok(data) => Some(Bytes::from(data)),
+                    Err(e) => {
+                        warn!("Failed to read blob {}: {}", id, e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn retain(&self, id: &BlobId) -> bool {
+        match self.inner.lock().entries.get_mut(id) {
+            Some(entry) => {
+                entry.refs += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn release(&self, id: &BlobId) -> bool {
+        let mut inner = self.inner.lock();
+        let Some(entry) = inner.entries.get_mut(id) else {
+            return false;
+        };
+        entry.refs -= 1;
+        if entry.refs > 0 {
+            return false;
+        }
+        let Some(entry) = inner.entries.remove(id) else {
+            return false;
+        };
+        match entry.tier {
+            Tier::Memory => {
+                inner.memory.pop(id);
+                inner.memory_bytes -= entry.size;
+            }
+            Tier::Disk => {
+                inner.disk_bytes -= entry.size;
+                if let Some(disk) = &self.disk {
+                    if let Err(e) = std::fs::remove_file(Self::path(&disk.dir, id)) {
+                        warn!("Failed to delete blob {}: {}", id, e);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn contains(&self, id: &BlobId) -> bool {
+        self.inner.lock().entries.contains_key(id)
+    }
+
+    fn stats(&self) -> BlobStats {
+        let inner = self.inner.lock();
+        BlobStats {
+            blobs: inner.entries.len(),
+            references: inner.entries.values().map(|entry| entry.refs).sum(),
+            memory_bytes: inner.memory_bytes,
+            disk_bytes: inner.disk_bytes,
+            deduplicated: inner.deduplicated,
+            spilled: inner.spilled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blobs_are_shared_until_last_release() {
+        let store = TieredBlobStore::unbounded();
+        let a = store.put(b"shard").unwrap();
+        let b = store.put(b"shard").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.to_hex(), format!("{:x}", Sha256::digest(b"shard")));
+
+        let stats = store.stats();
+        assert_eq!(
+            (stats.blobs, stats.references, stats.deduplicated),
+            (1, 2, 1)
+        );
+        assert_eq!(stats.memory_bytes, 5);
+
+        assert!(!store.release(&a));
+        assert_eq!(store.get(&a).unwrap().as_ref(), b"shard");
+        assert!(store.release(&a));
+        assert!(store.get(&a).is_none());
+        assert!(!store.release(&a));
+        assert_eq!(
+            store.stats(),
+            BlobStats {
+                deduplicated: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_blobs_spill_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TieredBlobStore::new(8)
+            .with_disk(dir.path().join("blobs"), 8)
+            .unwrap();
+        let old = store.put(b"aaaa").unwrap();
+        let warm = store.put(b"bbbb").unwrap();
+        store.get(&old);
+        // `warm` is now least recently used and goes to disk
+        let new = store.put(b"cccc").unwrap();
+
+        let stats = store.stats();
+        assert_eq!(
+            (stats.memory_bytes, stats.disk_bytes, stats.spilled),
+            (8, 4, 1)
+        );
+        assert_eq!(store.get(&warm).unwrap().as_ref(), b"bbbb");
+        assert_eq!(store.get(&new).unwrap().as_ref(), b"cccc");
+
+        // Both tiers are full of referenced blobs
+        let err = store.put(b"dddddddd").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BlobStoreFull>(),
+            Some(&BlobStoreFull { needed: 8 })
+        );
+
+        assert!(store.release(&warm));
+        assert_eq!(store.stats().disk_bytes, 0);
+        store.put(b"dddd").unwrap();
+    }
+}
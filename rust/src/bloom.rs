@@ -0,0 +1,459 @@
+/// Bloom-filter based shard availability advertisement
+///
+/// Peers periodically publish a compact Bloom filter of the shard keys they
+/// hold. Lookups and the download planner consult these filters to pick
+/// probable holders before issuing direct queries.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::clock::PeerClocks;
+
+/// Default false positive rate for advertised filters
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Default lifetime of an advertisement before it is considered stale
+pub const DEFAULT_ADVERTISEMENT_TTL_SECS: u64 = 600;
+
+/// How often a node should republish its filter
+pub const REPUBLISH_INTERVAL_SECS: u64 = 300;
+
+/// DHT key prefix for shard filter records
+pub const DHT_KEY_PREFIX: &str = "pangea/shard-filter/";
+
+const MIN_BITS: usize = 64;
+const MAX_HASHES: u32 = 16;
+
+/// Build the canonical shard key (matches the cache key format)
+pub fn shard_key(file_hash: &str, shard_index: usize) -> String {
+    format!("{}:{}", file_hash, shard_index)
+}
+
+/// Compact probabilistic set of shard keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardBloomFilter {
+    bits: Vec<u64>,
+    num_bits: usize,
+    num_hashes: u32,
+    item_count: usize,
+}
+
+impl ShardBloomFilter {
+    /// Create a filter sized for `expected_items` at the given false positive rate
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let p = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let num_bits = ((-n * p.ln()) / (ln2 * ln2)).ceil() as usize;
+        let num_bits = num_bits.max(MIN_BITS);
+        let num_hashes = ((num_bits as f64 / n) * ln2).round() as u32;
+
+        Self::with_params(num_bits, num_hashes)
+    }
+
+    /// Create a filter with explicit size and hash count
+    pub fn with_params(num_bits: usize, num_hashes: u32) -> Self {
+        let num_bits = num_bits.max(MIN_BITS);
+        Self {
+            bits: vec![0u64; num_bits.div_ceil(64)],
+            num_bits,
+            num_hashes: num_hashes.clamp(1, MAX_HASHES),
+            item_count: 0,
+        }
+    }
+
+    /// Filter with the given fields as they are, for decoding tests
+    #[cfg(test)]
+    pub(crate) fn from_raw_parts(bits: Vec<u64>, num_bits: usize, num_hashes: u32) -> Self {
+        Self {
+            bits,
+            num_bits,
+            num_hashes,
+            item_count: 0,
+        }
+    }
+
+    /// Check that a decoded filter is one `with_params` could have built
+    ///
+    /// Filters come from remote peers; a bit array shorter than `num_bits`
+    /// would index out of bounds, and too many hashes stall every lookup.
+    pub fn validate(&self) -> Result<()> {
+        if self.num_bits < MIN_BITS {
+            bail!(
+                "Shard filter has {} bits; minimum is {}",
+                self.num_bits,
+                MIN_BITS
+            );
+        }
+        if self.bits.len() != self.num_bits.div_ceil(64) {
+            bail!(
+                "Shard filter of {} bits carries {} words",
+                self.num_bits,
+                self.bits.len()
+            );
+        }
+        if !(1..=MAX_HASHES).contains(&self.num_hashes) {
+            bail!(
+                "Shard filter uses {} hashes; allowed are 1-{}",
+                self.num_hashes,
+                MAX_HASHES
+            );
+        }
+        Ok(())
+    }
+
+    /// Build a filter from an iterator of raw shard keys
+    pub fn from_keys<I, S>(keys: I, false_positive_rate: f64) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let keys: Vec<S> = keys.into_iter().collect();
+        let mut filter = Self::new(keys.len(), false_positive_rate);
+        for key in &keys {
+            filter.insert_key(key.as_ref());
+        }
+        filter
+    }
+
+    /// Insert a shard
+    pub fn insert(&mut self, file_hash: &str, shard_index: usize) {
+        self.insert_key(&shard_key(file_hash, shard_index));
+    }
+
+    /// Insert a raw shard key
+    pub fn insert_key(&mut self, key: &str) {
+        for bit in self.bit_positions(key) {
+            self.bits[bit / 64] |= 1u64 << (bit % 64);
+        }
+        self.item_count += 1;
+    }
+
+    /// Check whether a shard may be present (false means definitely absent)
+    pub fn contains(&self, file_hash: &str, shard_index: usize) -> bool {
+        self.contains_key(&shard_key(file_hash, shard_index))
+    }
+
+    /// Check whether a raw shard key may be present
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[bit / 64] & (1u64 << (bit % 64)) != 0)
+    }
+
+    /// Number of items inserted
+    pub fn len(&self) -> usize {
+        self.item_count
+    }
+
+    /// Whether no items have been inserted
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+    }
+
+    /// Size of the bit array
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    /// Number of hash functions
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Estimated false positive rate for the current fill level
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        let k = self.num_hashes as f64;
+        let n = self.item_count as f64;
+        let m = self.num_bits as f64;
+        (1.0 - (-k * n / m).exp()).powf(k)
+    }
+
+    /// Double hashing (Kirsch-Mitzenmacher) over a SHA256 digest
+    fn bit_positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha256::digest(key.as_bytes());
+        let mut h1_bytes = [0u8; 8];
+        let mut h2_bytes = [0u8; 8];
+        h1_bytes.copy_from_slice(&digest[0..8]);
+        h2_bytes.copy_from_slice(&digest[8..16]);
+        let h1 = u64::from_le_bytes(h1_bytes);
+        let h2 = u64::from_le_bytes(h2_bytes) | 1;
+        let m = self.num_bits as u64;
+
+        (0..self.num_hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+}
+
+/// Shard filter published by a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardAdvertisement {
+    pub peer_id: u32,
+    pub filter: ShardBloomFilter,
+    /// Unix timestamp of publication
+    pub published_at: i64,
+    /// Lifetime in seconds
+    pub ttl_secs: u64,
+}
+
+impl ShardAdvertisement {
+    /// Create an advertisement stamped with the current time
+    pub fn new(peer_id: u32, filter: ShardBloomFilter) -> Self {
+        Self {
+            peer_id,
+            filter,
+            published_at: chrono::Utc::now().timestamp(),
+            ttl_secs: DEFAULT_ADVERTISEMENT_TTL_SECS,
+        }
+    }
+
+    /// Whether the advertisement is past its lifetime
+    pub fn is_expired(&self, now: i64) -> bool {
+        now.saturating_sub(self.published_at) > self.ttl_secs as i64
+    }
+
+    /// DHT key under which a peer's filter is stored
+    pub fn dht_key(peer_id: u32) -> Vec<u8> {
+        format!("{}{}", DHT_KEY_PREFIX, peer_id).into_bytes()
+    }
+
+    /// Serialize for DHT publication
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).context("Failed to serialize shard advertisement")
+    }
+
+    /// Deserialize from a DHT record, rejecting malformed filters
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let advert: Self =
+            bincode::deserialize(bytes).context("Failed to deserialize shard advertisement")?;
+        advert
+            .filter
+            .validate()
+            .with_context(|| format!("Invalid shard filter from peer {}", advert.peer_id))?;
+        Ok(advert)
+    }
+}
+
+/// Index of the latest shard filter advertised by each peer
+pub struct ShardAvailabilityIndex {
+    adverts: RwLock<HashMap<u32, ShardAdvertisement>>,
+    clocks: Option<Arc<PeerClocks>>,
+}
+
+impl ShardAvailabilityIndex {
+    pub fn new() -> Self {
+        Self {
+            adverts: RwLock::new(HashMap::new()),
+            clocks: None,
+        }
+    }
+
+    /// Judge advertisements' age by their publisher's clock, as measured
+    pub fn with_peer_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    fn is_expired(&self, advert: &ShardAdvertisement, now: i64) -> bool {
+        match &self.clocks {
+            Some(clocks) => {
+                clocks.is_expired(advert.peer_id, advert.published_at, advert.ttl_secs, now)
+            }
+            None => advert.is_expired(now),
+        }
+    }
+
+    /// Record an advertisement, keeping only the newest per peer
+    pub async fn update(&self, advert: ShardAdvertisement) -> bool {
+        let mut adverts = self.adverts.write().await;
+        match adverts.get(&advert.peer_id) {
+            Some(existing) if existing.published_at > advert.published_at => false,
+            _ => {
+                debug!(
+                    "Shard filter from peer {}: {} keys",
+                    advert.peer_id,
+                    advert.filter.len()
+                );
+                adverts.insert(advert.peer_id, advert);
+                true
+            }
+        }
+    }
+
+    /// Drop a peer's advertisement
+    pub async fn remove_peer(&self, peer_id: u32) -> bool {
+        self.adverts.write().await.remove(&peer_id).is_some()
+    }
+
+    /// Whether a peer may hold a shard
+    ///
+    /// Returns `None` when there is no fresh filter for the peer.
+    pub async fn might_hold(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Option<bool> {
+        let now = chrono::Utc::now().timestamp();
+        let adverts = self.adverts.read().await;
+        adverts
+            .get(&peer_id)
+            .filter(|a| !self.is_expired(a, now))
+            .map(|a| a.filter.contains(file_hash, shard_index))
+    }
+
+    /// Peers whose fresh filters claim to hold a shard
+    pub async fn probable_holders(&self, file_hash: &str, shard_index: usize) -> Vec<u32> {
+        let now = chrono::Utc::now().timestamp();
+        let key = shard_key(file_hash, shard_index);
+        let adverts = self.adverts.read().await;
+
+        let mut holders: Vec<u32> = adverts
+            .values()
+            .filter(|a| !self.is_expired(a, now) && a.filter.contains_key(&key))
+            .map(|a| a.peer_id)
+            .collect();
+        holders.sort_unstable();
+        holders
+    }
+
+    /// Remove stale advertisements, returning how many were dropped
+    pub async fn prune_expired(&self) -> usize {
+        let now = chrono::Utc::now().timestamp();
+        let mut adverts = self.adverts.write().await;
+        let before = adverts.len();
+        adverts.retain(|_, a| !self.is_expired(a, now));
+        before - adverts.len()
+    }
+
+    /// Number of peers with an advertisement
+    pub async fn peer_count(&self) -> usize {
+        self.adverts.read().await.len()
+    }
+}
+
+impl Default for ShardAvailabilityIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut filter = ShardBloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&format!("hash_{}", i), i % 8);
+        }
+        for i in 0..1000 {
+            assert!(filter.contains(&format!("hash_{}", i), i % 8));
+        }
+        assert_eq!(filter.len(), 1000);
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = ShardBloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert("present", i);
+        }
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains("absent", *i))
+            .count();
+        // Allow generous slack over the 1% target
+        assert!(
+            false_positives < 300,
+            "too many false positives: {}",
+            false_positives
+        );
+    }
+
+    #[test]
+    fn test_advertisement_roundtrip() {
+        let filter = ShardBloomFilter::from_keys(["abc:0", "abc:1"], DEFAULT_FALSE_POSITIVE_RATE);
+        let advert = ShardAdvertisement::new(7, filter);
+
+        let bytes = advert.to_bytes().unwrap();
+        let decoded = ShardAdvertisement::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.peer_id, 7);
+        assert!(decoded.filter.contains("abc", 1));
+        assert_eq!(decoded.filter, advert.filter);
+    }
+
+    #[test]
+    fn test_malformed_filters_rejected() {
+        let malformed = [
+            ShardBloomFilter::from_raw_parts(vec![0; 2], 4096, 3),
+            ShardBloomFilter::from_raw_parts(Vec::new(), 0, 3),
+            ShardBloomFilter::from_raw_parts(vec![0; 1], 64, 0),
+            ShardBloomFilter::from_raw_parts(vec![0; 1], 64, u32::MAX),
+        ];
+        for filter in malformed {
+            let bytes = ShardAdvertisement::new(7, filter.clone())
+                .to_bytes()
+                .unwrap();
+            assert!(
+                ShardAdvertisement::from_bytes(&bytes).is_err(),
+                "accepted {:?}",
+                filter
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_probable_holders() {
+        let index = ShardAvailabilityIndex::new();
+
+        let mut f1 = ShardBloomFilter::new(10, 0.001);
+        f1.insert("file", 0);
+        let mut f2 = ShardBloomFilter::new(10, 0.001);
+        f2.insert("file", 1);
+
+        index.update(ShardAdvertisement::new(1, f1)).await;
+        index.update(ShardAdvertisement::new(2, f2)).await;
+
+        assert_eq!(index.probable_holders("file", 0).await, vec![1]);
+        assert_eq!(index.might_hold(2, "file", 0).await, Some(false));
+        assert_eq!(index.might_hold(3, "file", 0).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_index_ignores_stale() {
+        let index = ShardAvailabilityIndex::new();
+
+        let mut expired = ShardAdvertisement::new(1, ShardBloomFilter::from_keys(["f:0"], 0.01));
+        expired.published_at -= (DEFAULT_ADVERTISEMENT_TTL_SECS as i64) + 10;
+        index.update(expired).await;
+
+        assert!(index.probable_holders("f", 0).await.is_empty());
+        assert_eq!(index.prune_expired().await, 1);
+        assert_eq!(index.peer_count().await, 0);
+    }
+    #[tokio::test]
+    async fn test_index_corrects_publisher_clock_skew() {
+        use crate::clock::ClockSample;
+
+        // Peer 1's clock runs 15 minutes behind ours
+        let clocks = Arc::new(PeerClocks::default());
+        clocks.record(
+            1,
+            ClockSample {
+                offset_ms: -900_000,
+                delay_ms: 10,
+            },
+        );
+        let index = ShardAvailabilityIndex::new().with_peer_clocks(clocks);
+        let mut fresh = ShardAdvertisement::new(1, ShardBloomFilter::from_keys(["f:0"], 0.01));
+        fresh.published_at -= 900;
+        index.update(fresh).await;
+
+        assert_eq!(index.probable_holders("f", 0).await, vec![1]);
+        assert_eq!(index.prune_expired().await, 0);
+    }
+}
@@ -0,0 +1,476 @@
+/// Peer-assisted DHT bootstrap
+///
+/// Bootstrap multiaddrs given with `--bootstrap` go stale as nodes come and
+/// go. Well-known peers can instead publish a bootstrap list: the
+/// multiaddrs of nodes they know to be up, stamped with the time and signed
+/// by their node identity (`pangea-rust-node bootstrap-list`). The daemon
+/// fetches the lists from the `[bootstrap]` sources at start-up and keeps
+/// those that are
+///
+/// - signed by one of `trusted_signers`, over the peers and the timestamp
+/// - fresh: issued no more than `max_age_secs` ago, and not in the future
+///
+/// Their peers are merged into the `--bootstrap` set. The merged peers are
+/// saved as last-known-good, so a node whose sources are all unreachable
+/// still starts from the peers it last saw.
+///
+/// Entries naming peers through DNS (`/dnsaddr`, `/dns*`) are resolved by
+/// [`DnsBootstrap`](crate::dnsaddr::DnsBootstrap), every `dns_refresh_secs`
+/// and after `dns_redial_failures` failed dials.
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use futures::future::join_all;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::durability::{write_atomic, Durability};
+use crate::identity::{parse_peer_id, peer_id_of, NodeIdentity};
+
+/// File in the cache directory holding the last-known-good peers
+pub const BOOTSTRAP_STATE_FILE: &str = "bootstrap_peers.json";
+
+/// Domain separation for bootstrap list signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-bootstrap-list-v1";
+
+/// Largest bootstrap list accepted from a source
+const MAX_LIST_BYTES: usize = 1024 * 1024;
+
+/// Clock skew allowed for lists stamped in the future
+const MAX_FUTURE_SKEW_SECS: u64 = 300;
+
+/// Bootstrap discovery settings (`[bootstrap]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BootstrapConfig {
+    /// URLs serving signed bootstrap lists
+    pub sources: Vec<String>,
+    /// Peer IDs whose lists are accepted
+    pub trusted_signers: Vec<String>,
+    /// Oldest list accepted
+    pub max_age_secs: u64,
+    /// Most discovered peers added to the configured ones
+    pub max_peers: usize,
+    /// Time allowed for each source to answer
+    pub timeout_secs: u64,
+    /// How often DNS bootstrap entries are resolved again
+    pub dns_refresh_secs: u64,
+    /// Failed dials to bootstrap peers that trigger an early re-resolve
+    pub dns_redial_failures: u32,
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            trusted_signers: Vec::new(),
+            max_age_secs: 7 * 24 * 3600,
+            max_peers: 64,
+            timeout_secs: 10,
+            dns_refresh_secs: 3600,
+            dns_redial_failures: 3,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    bootstrap: BootstrapConfig,
+}
+
+impl BootstrapConfig {
+    /// Load the `[bootstrap]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[bootstrap]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.bootstrap.validate()?;
+        Ok(file.bootstrap)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for source in &self.sources {
+            if !source.starts_with("https://") && !source.starts_with("http://") {
+                bail!("bootstrap: source {:?} is not an http(s) URL", source);
+            }
+        }
+        if !self.sources.is_empty() && self.trusted_signers.is_empty() {
+            bail!("bootstrap: sources need at least one trusted signer");
+        }
+        for signer in &self.trusted_signers {
+            parse_peer_id(signer).context("bootstrap: invalid trusted signer")?;
+        }
+        if self.max_age_secs == 0 {
+            bail!("bootstrap: max_age_secs must be at least 1");
+        }
+        if self.max_peers == 0 {
+            bail!("bootstrap: max_peers must be at least 1");
+        }
+        if self.timeout_secs == 0 {
+            bail!("bootstrap: timeout_secs must be at least 1");
+        }
+        if self.dns_refresh_secs == 0 {
+            bail!("bootstrap: dns_refresh_secs must be at least 1");
+        }
+        if self.dns_redial_failures == 0 {
+            bail!("bootstrap: dns_redial_failures must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// A signed, timestamped list of bootstrap peers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapList {
+    /// Multiaddrs of the peers
+    pub peers: Vec<String>,
+    /// Unix time the list was signed
+    pub issued_at: u64,
+    /// Signer's Ed25519 public key, hex
+    pub signer: String,
+    /// Signature over peers and timestamp, hex
+    pub signature: String,
+}
+
+impl BootstrapList {
+    /// Sign `peers` as of `issued_at` with a node's identity
+    pub fn sign(identity: &NodeIdentity, peers: &[Multiaddr], issued_at: u64) -> Result<Self> {
+        let peers: Vec<String> = peers.iter().map(|addr| addr.to_string()).collect();
+        let signature = identity
+            .libp2p_keypair()
+            .sign(&signed_bytes(&peers, issued_at))
+            .context("Failed to sign bootstrap list")?;
+        Ok(Self {
+            peers,
+            issued_at,
+            signer: hex::encode(identity.verifying_key().as_bytes()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Check signature and freshness; returns the signer and the peers
+    pub fn verify(
+        &self,
+        trusted: &[PeerId],
+        max_age_secs: u64,
+        now: u64,
+    ) -> Result<(PeerId, Vec<Multiaddr>)> {
+        let mut public = [0u8; 32];
+        hex::decode_to_slice(&self.signer, &mut public).context("Malformed signer key")?;
+        let signer = peer_id_of(&public)?;
+        if !trusted.contains(&signer) {
+            bail!("Bootstrap list signed by untrusted {}", signer);
+        }
+        let signature = hex::decode(&self.signature).context("Malformed signature")?;
+        let signature =
+            Signature::from_slice(&signature).map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        VerifyingKey::from_bytes(&public)
+            .map_err(|e| anyhow!("Invalid signer key: {}", e))?
+            .verify_strict(&signed_bytes(&self.peers, self.issued_at), &signature)
+            .map_err(|_| anyhow!("Bad bootstrap list signature"))?;
+
+        if self.issued_at > now + MAX_FUTURE_SKEW_SECS {
+            bail!("Bootstrap list from {} is dated in the future", signer);
+        }
+        let age = now.saturating_sub(self.issued_at);
+        if age > max_age_secs {
+            bail!(
+                "Bootstrap list from {} is {}s old; limit is {}s",
+                signer,
+                age,
+                max_age_secs
+            );
+        }
+
+        let peers = self
+            .peers
+            .iter()
+            .map(|peer| {
+                peer.parse()
+                    .with_context(|| format!("Invalid bootstrap multiaddr {:?}", peer))
+            })
+            .collect::<Result<_>>()?;
+        Ok((signer, peers))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Malformed bootstrap list")
+    }
+}
+
+fn signed_bytes(peers: &[String], issued_at: u64) -> Vec<u8> {
+    let mut bytes = SIGNING_CONTEXT.to_vec();
+    bytes.extend_from_slice(&issued_at.to_be_bytes());
+    bytes.extend_from_slice(&(peers.len() as u32).to_be_bytes());
+    for peer in peers {
+        bytes.extend_from_slice(&(peer.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(peer.as_bytes());
+    }
+    bytes
+}
+
+/// Peers saved after the last successful discovery
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LastKnownGood {
+    saved_at: u64,
+    peers: Vec<String>,
+}
+
+/// Fetches bootstrap lists and merges them into the configured peers
+pub struct BootstrapDiscovery {
+    config: BootstrapConfig,
+    trusted: Vec<PeerId>,
+    state_file: Option<PathBuf>,
+}
+
+impl BootstrapDiscovery {
+    pub fn new(config: BootstrapConfig) -> Result<Self> {
+        config.validate()?;
+        let trusted = config
+            .trusted_signers
+            .iter()
+            .map(|signer| parse_peer_id(signer))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            config,
+            trusted,
+            state_file: None,
+        })
+    }
+
+    /// Save discovered peers to `path` and fall back to them when no
+    /// source answers
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(path.into());
+        self
+    }
+
+    pub fn config(&self) -> &BootstrapConfig {
+        &self.config
+    }
+
+    /// The configured peers plus those from every valid list
+    ///
+    /// Never fails: sources that cannot be reached or whose lists do not
+    /// verify are logged and skipped.
+    pub async fn discover(&self, configured: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        let now = unix_now();
+        let fetches = self.config.sources.iter().map(|url| async move {
+            let result = match self.fetch(url).await {
+                Ok(list) => list.verify(&self.trusted, self.config.max_age_secs, now),
+                Err(e) => Err(e),
+            };
+            (url, result)
+        });
+
+        let mut discovered = Vec::new();
+        let mut answered = false;
+        for (url, result) in join_all(fetches).await {
+            match result {
+                Ok((signer, peers)) => {
+                    info!(
+                        "Bootstrap list from {} (signed by {}): {} peers",
+                        url,
+                        signer,
+                        peers.len()
+                    );
+                    answered = true;
+                    discovered.extend(peers);
+                }
+                Err(e) => warn!("Ignoring bootstrap list from {}: {:#}", url, e),
+            }
+        }
+
+        let discovered = if answered {
+            let discovered = merge(Vec::new(), discovered, self.config.max_peers);
+            if let Err(e) = self.save(&discovered, now).await {
+                warn!("Failed to save bootstrap peers: {:#}", e);
+            }
+            discovered
+        } else {
+            let saved = self.load();
+            if !self.config.sources.is_empty() {
+                warn!(
+                    "No bootstrap source answered; using {} last-known-good peers",
+                    saved.len()
+                );
+            }
+            saved
+        };
+        merge(configured, discovered, self.config.max_peers)
+    }
+
+    /// Download the list served at `url`
+    pub async fn fetch(&self, url: &str) -> Result<BootstrapList> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .build()?;
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch bootstrap list from {}", url))?
+            .error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|len| len as usize > MAX_LIST_BYTES)
+        {
+            bail!("Bootstrap list at {} is too large", url);
+        }
+        let text = response.text().await?;
+        if text.len() > MAX_LIST_BYTES {
+            bail!("Bootstrap list at {} is too large", url);
+        }
+        BootstrapList::from_json(&text)
+    }
+
+    async fn save(&self, peers: &[Multiaddr], now: u64) -> Result<()> {
+        let Some(path) = &self.state_file else {
+            return Ok(());
+        };
+        let state = LastKnownGood {
+            saved_at: now,
+            peers: peers.iter().map(|addr| addr.to_string()).collect(),
+        };
+        write_atomic(path, &serde_json::to_vec(&state)?, Durability::FsyncFile).await
+    }
+
+    /// Last-known-good peers; none if they were never saved or are unreadable
+    fn load(&self) -> Vec<Multiaddr> {
+        let Some(path) = &self.state_file else {
+            return Vec::new();
+        };
+        let state: LastKnownGood = match std::fs::read_to_string(path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!("Ignoring unreadable bootstrap state {:?}: {}", path, e);
+                    return Vec::new();
+                }
+            },
+            Err(_) => return Vec::new(),
+        };
+        state
+            .peers
+            .iter()
+            .filter_map(|peer| peer.parse().ok())
+            .collect()
+    }
+}
+
+/// `configured` followed by up to `max` new peers of `discovered`
+fn merge(configured: Vec<Multiaddr>, discovered: Vec<Multiaddr>, max: usize) -> Vec<Multiaddr> {
+    let mut seen: HashSet<Multiaddr> = configured.iter().cloned().collect();
+    let mut peers = configured;
+    peers.extend(
+        discovered
+            .into_iter()
+            .filter(|addr| seen.insert(addr.clone()))
+            .take(max),
+    );
+    peers
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(ports: &[u16]) -> Vec<Multiaddr> {
+        ports
+            .iter()
+            .map(|port| format!("/ip4/10.0.0.1/tcp/{}", port).parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_list_signature_and_freshness() {
+        let identity = NodeIdentity::generate();
+        let trusted = [identity.peer_id()];
+        let list = BootstrapList::sign(&identity, &addrs(&[4001, 4002]), 1_000_000).unwrap();
+
+        let list = BootstrapList::from_json(&list.to_json().unwrap()).unwrap();
+        let (signer, peers) = list.verify(&trusted, 3600, 1_000_100).unwrap();
+        assert_eq!(signer, identity.peer_id());
+        assert_eq!(peers, addrs(&[4001, 4002]));
+
+        // Stale, from the future, or from someone else
+        assert!(list.verify(&trusted, 3600, 1_003_601).is_err());
+        assert!(list.verify(&trusted, 3600, 990_000).is_err());
+        assert!(list
+            .verify(&[NodeIdentity::generate().peer_id()], 3600, 1_000_100)
+            .is_err());
+
+        // Peers and timestamp are both covered by the signature
+        let mut tampered = list.clone();
+        tampered.peers.push("/ip4/10.0.0.9/tcp/4001".to_string());
+        assert!(tampered.verify(&trusted, 3600, 1_000_100).is_err());
+        let mut tampered = list;
+        tampered.issued_at += 60;
+        assert!(tampered.verify(&trusted, 3600, 1_000_100).is_err());
+    }
+
+    #[test]
+    fn test_config_needs_trusted_signers() {
+        let signer = NodeIdentity::generate().peer_id();
+        let config = BootstrapConfig::from_toml(&format!(
+            "[bootstrap]\nsources = [\"https://example.org/bootstrap.json\"]\ntrusted_signers = [\"{}\"]\n",
+            signer
+        ))
+        .unwrap();
+        assert_eq!(config.max_peers, 64);
+        assert!(BootstrapConfig::from_toml(
+            "[bootstrap]\nsources = [\"https://example.org/bootstrap.json\"]\n"
+        )
+        .is_err());
+        assert!(BootstrapConfig::from_toml(&format!(
+            "[bootstrap]\nsources = [\"ftp://example.org/list\"]\ntrusted_signers = [\"{}\"]\n",
+            signer
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_merge_keeps_configured_and_caps_discovered() {
+        let merged = merge(addrs(&[1, 2]), addrs(&[2, 3, 4, 5]), 2);
+        assert_eq!(merged, addrs(&[1, 2, 3, 4]));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_last_known_good() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = dir.path().join(BOOTSTRAP_STATE_FILE);
+        let discovery = BootstrapDiscovery::new(BootstrapConfig {
+            // Nothing listens here, so discovery falls back to saved peers
+            sources: vec!["http://127.0.0.1:9/bootstrap.json".to_string()],
+            trusted_signers: vec![NodeIdentity::generate().peer_id().to_string()],
+            timeout_secs: 2,
+            ..BootstrapConfig::default()
+        })
+        .unwrap()
+        .with_state_file(&state);
+
+        assert_eq!(discovery.discover(addrs(&[1])).await, addrs(&[1]));
+        discovery.save(&addrs(&[7, 8]), unix_now()).await.unwrap();
+        assert_eq!(discovery.discover(addrs(&[1])).await, addrs(&[1, 7, 8]));
+    }
+}
@@ -0,0 +1,593 @@
+/// Local HTTP file browser
+///
+/// A headless node's files can be fetched with a browser instead of the
+/// CLI. The daemon serves a few read-only endpoints on a loopback address
+/// (`[browser]` section of the node config file):
+///
+/// - `GET /` - HTML list of the files in the browser's namespace
+/// - `GET /api/files` - the same list as JSON
+/// - `GET /files/<hash>` - the file itself, as an attachment under its
+///   original name, with `Range` support so downloads can resume
+///
+/// Only complete files are served. The first request for one reconstructs
+/// it into the browser's directory; later requests, and concurrent ones,
+/// read that copy. Nothing can be uploaded or changed through it.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+use crate::automated::AutomatedDownloader;
+use crate::cache::is_safe_file_hash;
+use crate::namespace::DEFAULT_NAMESPACE;
+use crate::singleflight::SingleFlight;
+
+/// Default directory name of reconstructed files inside the cache directory
+pub const BROWSER_DIR: &str = "browser";
+
+/// Longest request head (request line and headers) accepted
+const MAX_REQUEST_HEAD_BYTES: usize = 8192;
+
+/// File browser settings (`[browser]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    pub enabled: bool,
+    /// Loopback address to listen on
+    pub listen: String,
+    /// Namespace whose files are listed
+    pub namespace: String,
+    /// Where reconstructed files are kept (default: `browser` in the cache
+    /// directory)
+    pub download_dir: Option<PathBuf>,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:8088".to_string(),
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            download_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    browser: BrowserConfig,
+}
+
+impl BrowserConfig {
+    /// Load the `[browser]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[browser]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.browser.validate()?;
+        Ok(file.browser)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let addr = self.listen_addr()?;
+        if !addr.ip().is_loopback() {
+            bail!("browser: listen must be a loopback address, not {}", addr);
+        }
+        if self.namespace.is_empty() {
+            bail!("browser: namespace must not be empty");
+        }
+        Ok(())
+    }
+
+    pub fn listen_addr(&self) -> Result<SocketAddr> {
+        self.listen
+            .parse()
+            .with_context(|| format!("browser: invalid listen address {:?}", self.listen))
+    }
+}
+
+/// A file as listed by `/api/files`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrowserFile {
+    pub file_hash: String,
+    pub file_name: String,
+    pub file_size: usize,
+    /// Whether enough shards exist to download it
+    pub complete: bool,
+    pub timestamp: i64,
+}
+
+/// Serves the file list and complete files over HTTP
+pub struct FileBrowser {
+    downloader: AutomatedDownloader,
+    dir: PathBuf,
+    reconstructions: SingleFlight<String, Result<PathBuf, String>>,
+}
+
+impl FileBrowser {
+    /// Browse `downloader`'s namespace, keeping reconstructed files in `dir`
+    pub fn new(downloader: AutomatedDownloader, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            downloader,
+            dir: dir.into(),
+            reconstructions: SingleFlight::new(),
+        }
+    }
+
+    /// Accept connections on `addr` until the task is aborted
+    ///
+    /// Must run on a `LocalSet`; every connection is answered on it.
+    pub async fn serve(self: Rc<Self>, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind file browser to {}", addr))?;
+        info!("📂 File browser on http://{}", addr);
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("Failed to accept file browser connection: {}", e);
+                    continue;
+                }
+            };
+            let browser = self.clone();
+            tokio::task::spawn_local(async move {
+                if let Err(e) = browser.handle(stream).await {
+                    debug!("File browser connection from {} failed: {:#}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Answer one request, then close the connection
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let request = match read_request(&mut stream).await {
+            Ok(request) => request,
+            Err(e) => {
+                let body = format!("{:#}\n", e);
+                return Response::text(400, &body).send(&mut stream, false).await;
+            }
+        };
+        let head_only = request.method == "HEAD";
+        if request.method != "GET" && !head_only {
+            return Response::text(405, "Only GET and HEAD are supported\n")
+                .header("Allow", "GET, HEAD")
+                .send(&mut stream, false)
+                .await;
+        }
+
+        let path = request.path.split('?').next().unwrap_or_default();
+        match path {
+            "/" => {
+                let files = self.files().await?;
+                Response::new(
+                    200,
+                    "text/html; charset=utf-8",
+                    render_index(&files).into_bytes(),
+                )
+                .send(&mut stream, head_only)
+                .await
+            }
+            "/api/files" => {
+                let files = self.files().await?;
+                Response::new(200, "application/json", serde_json::to_vec(&files)?)
+                    .send(&mut stream, head_only)
+                    .await
+            }
+            _ => match path.strip_prefix("/files/") {
+                Some(hash) if is_safe_file_hash(hash) => {
+                    self.send_file(&mut stream, hash, request.range.as_deref(), head_only)
+                        .await
+                }
+                _ => {
+                    Response::text(404, "Not found\n")
+                        .send(&mut stream, head_only)
+                        .await
+                }
+            },
+        }
+    }
+
+    /// Files of the namespace, newest first
+    pub async fn files(&self) -> Result<Vec<BrowserFile>> {
+        let mut files: Vec<BrowserFile> = self
+            .downloader
+            .list_files()
+            .await?
+            .into_iter()
+            .map(|file| BrowserFile {
+                file_hash: file.file_hash,
+                file_name: file.file_name,
+                file_size: file.file_size,
+                complete: file.is_available,
+                timestamp: file.timestamp,
+            })
+            .collect();
+        files.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(files)
+    }
+
+    async fn send_file(
+        &self,
+        stream: &mut TcpStream,
+        file_hash: &str,
+        range: Option<&str>,
+        head_only: bool,
+    ) -> Result<()> {
+        let Some(info) = self.downloader.get_info(file_hash).await? else {
+            return Response::text(404, "No such file\n")
+                .send(stream, head_only)
+                .await;
+        };
+        if !info.is_available {
+            return Response::text(409, "File is not complete on this node\n")
+                .send(stream, head_only)
+                .await;
+        }
+        let path = match self.reconstruct(file_hash, info.file_size as u64).await {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("File browser could not reconstruct {}: {}", file_hash, e);
+                return Response::text(502, "File could not be reconstructed\n")
+                    .send(stream, head_only)
+                    .await;
+            }
+        };
+
+        let mut file = tokio::fs::File::open(&path).await?;
+        let len = file.metadata().await?.len();
+        let (status, start, end) = match range.and_then(|range| parse_range(range, len)) {
+            None => (200, 0, len),
+            Some(Ok((start, end))) => (206, start, end + 1),
+            Some(Err(())) => {
+                return Response::text(416, "Range not satisfiable\n")
+                    .header("Content-Range", format!("bytes */{}", len))
+                    .send(stream, head_only)
+                    .await;
+            }
+        };
+
+        let mut response = Response::new(status, "application/octet-stream", Vec::new())
+            .header("Accept-Ranges", "bytes")
+            .header("Content-Disposition", content_disposition(&info.file_name));
+        if status == 206 {
+            response = response.header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end - 1, len),
+            );
+        }
+        stream
+            .write_all(response.head(end - start).as_bytes())
+            .await?;
+        if !head_only {
+            file.seek(std::io::SeekFrom::Start(start)).await?;
+            tokio::io::copy(&mut file.take(end - start), stream).await?;
+        }
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Path of the reconstructed file, reconstructing it on first use
+    async fn reconstruct(&self, file_hash: &str, size: u64) -> Result<PathBuf, String> {
+        let path = self.dir.join(file_hash);
+        if tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|meta| meta.len() == size)
+        {
+            return Ok(path);
+        }
+
+        self.reconstructions
+            .run(file_hash.to_string(), || async {
+                let partial = self.dir.join(format!("{}.part", file_hash));
+                tokio::fs::create_dir_all(&self.dir)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                self.downloader
+                    .download(file_hash, &partial)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                tokio::fs::rename(&partial, &path)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                info!("📂 Reconstructed {} for the file browser", file_hash);
+                Ok(path.clone())
+            })
+            .await
+    }
+}
+
+/// The parts of a request the browser looks at
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    range: Option<String>,
+}
+
+/// Read a request head; bodies are never expected
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD_BYTES {
+            bail!("Request head too large");
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            bail!("Connection closed before the request was complete");
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    parse_request(&head)
+}
+
+fn parse_request(head: &[u8]) -> Result<Request> {
+    let head = std::str::from_utf8(head).context("Request is not UTF-8")?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(path), Some(version)) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        bail!("Malformed request line");
+    };
+    if !version.starts_with("HTTP/1.") || !path.starts_with('/') {
+        bail!("Malformed request line");
+    }
+
+    let range = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("range"))
+        .map(|(_, value)| value.trim().to_string());
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        range,
+    })
+}
+
+/// Byte range `start..=end` asked for by a `Range` header
+///
+/// `None` for anything but a single byte range, which is answered with the
+/// whole file as RFC 9110 allows; `Some(Err(()))` for a range that lies
+/// outside the file.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = match end {
+            "" => len.saturating_sub(1),
+            end => end.parse::<u64>().ok()?.min(len.saturating_sub(1)),
+        };
+        if start >= len || end < start {
+            return Some(Err(()));
+        }
+        (start, end)
+    };
+    Some(Ok(range))
+}
+
+/// `Content-Disposition` saving the download under its original name
+fn content_disposition(file_name: &str) -> String {
+    let name = Path::new(file_name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download");
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+fn render_index(files: &[BrowserFile]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Pangea files</title></head>\n\
+         <body><h1>Files</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Hash</th></tr>\n",
+    );
+    for file in files {
+        let name = escape_html(&file.file_name);
+        let name = if file.complete {
+            format!("<a href=\"/files/{}\">{}</a>", file.file_hash, name)
+        } else {
+            format!("{} (incomplete)", name)
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+            name, file.file_size, file.file_hash
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A response with its body in memory
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", content_type.to_string())],
+            body,
+        }
+    }
+
+    fn text(status: u16, body: &str) -> Self {
+        Self::new(
+            status,
+            "text/plain; charset=utf-8",
+            body.as_bytes().to_vec(),
+        )
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Status line and headers for a body of `content_length` bytes
+    fn head(&self, content_length: u64) -> String {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            content_length
+        ));
+        head
+    }
+
+    async fn send(self, stream: &mut TcpStream, head_only: bool) -> Result<()> {
+        stream
+            .write_all(self.head(self.body.len() as u64).as_bytes())
+            .await?;
+        if !head_only {
+            stream.write_all(&self.body).await?;
+        }
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        416 => "Range Not Satisfiable",
+        502 => "Bad Gateway",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            b"GET /files/abc123?x=1 HTTP/1.1\r\nHost: localhost\r\nrange: bytes=0-99\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/files/abc123?x=1");
+        assert_eq!(request.range.as_deref(), Some("bytes=0-99"));
+
+        assert!(parse_request(b"GET\r\n\r\n").is_err());
+        assert!(parse_request(b"GET files HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(Ok((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Some(Ok((500, 999))));
+        assert_eq!(parse_range("bytes=-5000", 1000), Some(Ok((0, 999))));
+
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(Err(())));
+        assert_eq!(parse_range("bytes=20-10", 1000), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(Err(())));
+
+        // Multiple or foreign ranges are served whole
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+    }
+
+    #[test]
+    fn test_content_disposition_and_escaping() {
+        assert_eq!(
+            content_disposition("../notes \"v2\".txt"),
+            "attachment; filename=\"notes _v2_.txt\"; filename*=UTF-8''notes%20%22v2%22.txt"
+        );
+        assert_eq!(
+            content_disposition("résumé.pdf"),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+        assert_eq!(
+            escape_html("<a href='x'>&</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_config_requires_loopback() {
+        let config = BrowserConfig::from_toml("[browser]\nenabled = true\n").unwrap();
+        assert_eq!(config.listen_addr().unwrap().port(), 8088);
+        assert!(BrowserConfig::from_toml("[browser]\nlisten = \"0.0.0.0:8088\"\n").is_err());
+        assert!(BrowserConfig::from_toml("[browser]\nlisten = \"[::1]:8088\"\n").is_ok());
+    }
+}
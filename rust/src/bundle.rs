@@ -0,0 +1,312 @@
+/// Offline transfer bundles
+///
+/// A bundle is a single file holding a manifest and the still-encrypted
+/// shards it describes, so a file can move between nodes on a USB stick or
+/// over an air gap. Importing a bundle verifies every shard against the
+/// manifest, stores the shards and manifest in the local cache, and can
+/// optionally publish the manifest to the DHT.
+///
+/// Layout (integers big-endian):
+///
+/// ```text
+/// "PNGABNDL" | version u8 | manifest_len u32 | manifest JSON
+/// shard_count u32 | { index u32 | len u32 | shard bytes } * shard_count
+/// SHA-256 of everything above
+/// ```
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::info;
+
+use crate::cache::{Cache, FileManifest, MAX_MANIFEST_BYTES};
+
+/// Leading bytes of every bundle
+pub const BUNDLE_MAGIC: &[u8; 8] = b"PNGABNDL";
+
+/// Bundle format version written by this node
+pub const BUNDLE_VERSION: u8 = 1;
+
+/// Largest shard accepted from a bundle
+pub const MAX_BUNDLE_SHARD_BYTES: usize = 512 * 1024 * 1024;
+
+const CHECKSUM_LEN: usize = 32;
+
+/// A manifest plus the shards needed to reconstruct its file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    pub manifest: FileManifest,
+    /// (shard_index, encrypted shard bytes)
+    pub shards: Vec<(usize, Vec<u8>)>,
+}
+
+/// Outcome of importing a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub file_hash: String,
+    pub file_name: String,
+    pub namespace: String,
+    pub shards_imported: usize,
+}
+
+impl Bundle {
+    /// Bundle the shards fetched for `manifest`, skipping missing slots
+    pub fn new(manifest: FileManifest, shards: Vec<Option<Vec<u8>>>) -> Self {
+        let shards = shards
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, shard)| shard.map(|data| (index, data)))
+            .collect();
+        Self { manifest, shards }
+    }
+
+    /// Check the manifest, and every shard against its recorded hash
+    ///
+    /// Fails unless enough shards are present to reconstruct the file.
+    pub fn verify(&self) -> Result<()> {
+        self.manifest.validate()?;
+        let manifest = &self.manifest;
+        let mut seen = HashSet::new();
+        for (index, data) in &self.shards {
+            if *index >= manifest.shard_count {
+                anyhow::bail!(
+                    "Bundle shard {} out of range for {} shards",
+                    index,
+                    manifest.shard_count
+                );
+            }
+            if !seen.insert(*index) {
+                anyhow::bail!("Bundle holds shard {} twice", index);
+            }
+            // Shards re-encrypted under a rotated key match its hashes
+            if !manifest.shard_matches(*index, data) {
+                anyhow::bail!("Bundle shard {} does not match its manifest hash", index);
+            }
+        }
+        let needed = manifest.data_shard_count();
+        if seen.len() < needed {
+            anyhow::bail!(
+                "Bundle holds {} of {} shards; {} are needed",
+                seen.len(),
+                manifest.shard_count,
+                needed
+            );
+        }
+        Ok(())
+    }
+
+    /// Serialize the bundle
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let manifest = serde_json::to_vec(&self.manifest)?;
+        let shard_bytes: usize = self.shards.iter().map(|(_, data)| data.len() + 8).sum();
+        let mut buffer =
+            Vec::with_capacity(BUNDLE_MAGIC.len() + 9 + manifest.len() + shard_bytes + 32);
+
+        buffer.extend_from_slice(BUNDLE_MAGIC);
+        buffer.push(BUNDLE_VERSION);
+        buffer.extend_from_slice(&u32::try_from(manifest.len())?.to_be_bytes());
+        buffer.extend_from_slice(&manifest);
+        buffer.extend_from_slice(&u32::try_from(self.shards.len())?.to_be_bytes());
+        for (index, data) in &self.shards {
+            buffer.extend_from_slice(&u32::try_from(*index)?.to_be_bytes());
+            buffer.extend_from_slice(&u32::try_from(data.len())?.to_be_bytes());
+            buffer.extend_from_slice(data);
+        }
+        let checksum = Sha256::digest(&buffer);
+        buffer.extend_from_slice(&checksum);
+        Ok(buffer)
+    }
+
+    /// Parse and verify a bundle
+    ///
+    /// Bundles come from outside the node, so every length is checked
+    /// against the buffer and its limit before use.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let header_len = BUNDLE_MAGIC.len() + 1;
+        if data.len() < header_len + CHECKSUM_LEN {
+            anyhow::bail!("Bundle too small: {} bytes", data.len());
+        }
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+        if &data[..BUNDLE_MAGIC.len()] != BUNDLE_MAGIC {
+            anyhow::bail!("Not a Pangea bundle");
+        }
+        let version = data[BUNDLE_MAGIC.len()];
+        if version != BUNDLE_VERSION {
+            anyhow::bail!("Unsupported bundle version {}", version);
+        }
+        if Sha256::digest(body).as_slice() != checksum {
+            anyhow::bail!("Bundle checksum mismatch");
+        }
+
+        let (manifest, mut rest) = read_field(&body[header_len..], "manifest", MAX_MANIFEST_BYTES)?;
+        let manifest = FileManifest::from_json(
+            std::str::from_utf8(manifest).context("Bundle manifest is not UTF-8")?,
+        )?;
+
+        let shard_count = read_u32(&mut rest, "shard count")? as usize;
+        if shard_count > manifest.shard_count {
+            anyhow::bail!(
+                "Bundle claims {} shards for a {}-shard file",
+                shard_count,
+                manifest.shard_count
+            );
+        }
+        let mut shards = Vec::with_capacity(shard_count);
+        for _ in 0..shard_count {
+            let index = read_u32(&mut rest, "shard index")? as usize;
+            let (shard, remaining) = read_field(rest, "shard", MAX_BUNDLE_SHARD_BYTES)?;
+            shards.push((index, shard.to_vec()));
+            rest = remaining;
+        }
+        if !rest.is_empty() {
+            anyhow::bail!("{} trailing bytes after bundle", rest.len());
+        }
+
+        let bundle = Self { manifest, shards };
+        bundle.verify()?;
+        Ok(bundle)
+    }
+
+    /// Write the bundle to `path`
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        tokio::fs::write(path, self.to_bytes()?)
+            .await
+            .with_context(|| format!("Failed to write bundle {:?}", path))
+    }
+
+    /// Read and verify the bundle at `path`
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read bundle {:?}", path))?;
+        Self::from_bytes(&data)
+    }
+
+    /// Store the manifest and shards in `cache`
+    ///
+    /// Shards are persisted so the file stays readable offline after they
+    /// leave the in-memory cache. The namespace quota is checked before any
+    /// shard is written.
+    pub async fn import_into(self, cache: &Cache) -> Result<ImportReport> {
+        self.verify()?;
+        let manifest = self.manifest;
+        cache.put_manifest(manifest.clone()).await?;
+        let shards_imported = self.shards.len();
+        for (index, data) in self.shards {
+            cache
+                .persist_shard(&manifest.file_hash, index, data)
+                .await
+                .with_context(|| format!("Failed to store shard {}", index))?;
+        }
+        info!(
+            "Imported {} ({} of {} shards)",
+            manifest.file_hash, shards_imported, manifest.shard_count
+        );
+        Ok(ImportReport {
+            file_hash: manifest.file_hash,
+            file_name: manifest.file_name,
+            namespace: manifest.namespace,
+            shards_imported,
+        })
+    }
+}
+
+fn read_u32(data: &mut &[u8], name: &str) -> Result<u32> {
+    let bytes = data
+        .get(..4)
+        .with_context(|| format!("Truncated bundle {}", name))?;
+    let value = u32::from_be_bytes(bytes.try_into()?);
+    *data = &data[4..];
+    Ok(value)
+}
+
+/// Split a `[u32 BE length][bytes]` field off the front of `data`
+fn read_field<'a>(data: &'a [u8], name: &str, limit: usize) -> Result<(&'a [u8], &'a [u8])> {
+    let mut rest = data;
+    let len = read_u32(&mut rest, name)? as usize;
+    if len > limit {
+        anyhow::bail!("Bundle {} length {} exceeds limit of {}", name, len, limit);
+    }
+    if rest.len() < len {
+        anyhow::bail!(
+            "Incomplete bundle {}: need {} bytes, have {}",
+            name,
+            len,
+            rest.len()
+        );
+    }
+    Ok(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn sample() -> Bundle {
+        let shards: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![4, 5], vec![6]];
+        let manifest = FileManifest {
+            file_hash: "bundled".to_string(),
+            file_name: "bundled.bin".to_string(),
+            file_size: 6,
+            shard_count: 3,
+            parity_count: 1,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3)],
+            shard_hashes: shards
+                .iter()
+                .map(|s| format!("{:x}", Sha256::digest(s)))
+                .collect(),
+            ..Default::default()
+        };
+        Bundle::new(manifest, shards.into_iter().map(Some).collect())
+    }
+
+    #[test]
+    fn test_bundle_roundtrip_and_tamper_detection() {
+        let bundle = sample();
+        let bytes = bundle.to_bytes().unwrap();
+        assert_eq!(Bundle::from_bytes(&bytes).unwrap(), bundle);
+
+        let mut flipped = bytes.clone();
+        let last_shard_byte = bytes.len() - CHECKSUM_LEN - 1;
+        flipped[last_shard_byte] ^= 0xff;
+        assert!(Bundle::from_bytes(&flipped).is_err());
+        assert!(Bundle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // A shard that no longer matches its manifest hash is refused even
+        // with a consistent checksum
+        let mut corrupt = bundle.clone();
+        corrupt.shards[0].1 = vec![9, 9, 9];
+        assert!(Bundle::from_bytes(&corrupt.to_bytes().unwrap()).is_err());
+
+        // Parity covers one missing shard, but not two
+        let mut partial = bundle;
+        partial.shards.truncate(2);
+        assert!(partial.verify().is_ok());
+        partial.shards.truncate(1);
+        assert!(partial.verify().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_populates_cache() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("file.pangea");
+        sample().save(&path).await.unwrap();
+
+        let cache = Cache::new(temp_dir.path().join("cache"), 100, 1024 * 1024).unwrap();
+        let report = Bundle::load(&path)
+            .await
+            .unwrap()
+            .import_into(&cache)
+            .await
+            .unwrap();
+        assert_eq!(report.shards_imported, 3);
+        assert!(cache.get_manifest("bundled").await.is_some());
+
+        // Shards survive losing the in-memory cache
+        cache.clear_shards().await.unwrap();
+        assert_eq!(cache.get_shard("bundled", 1).await, Some(vec![4, 5]));
+    }
+}
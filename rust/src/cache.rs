@@ -1,13 +1,44 @@
 use anyhow::{Context, Result};
 use lru::LruCache;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use thiserror::Error;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+use crate::blob::{BlobId, BlobStore, BlobStoreFull, TieredBlobStore};
+use crate::bloom::ShardBloomFilter;
+use crate::denylist::DenyList;
+use crate::durability::{self, write_atomic, Durability};
+use crate::envelope::WrappedKey;
+use crate::error::NamespaceError;
+use crate::identity::parse_peer_id;
+use crate::kdf::KdfParams;
+use crate::manifest_log::ManifestLog;
+use crate::namespace::{
+    default_namespace, is_default_namespace, validate_namespace, NamespaceQuota, NamespaceStats,
+};
+use crate::negotiation::{MANIFEST_V1, MANIFEST_V2, MANIFEST_V3};
+use crate::placement::LocalParity;
+use crate::popularity::PopularityTracker;
+use crate::refcount::RefIndex;
+use crate::rotation::{KeyRotation, ORIGINAL_VERSION};
+use crate::singleflight::SingleFlight;
+use crate::store::validate_storage_class;
+
+/// Largest manifest document accepted from disk or a peer
+pub const MAX_MANIFEST_BYTES: usize = 4 * 1024 * 1024;
+
+/// Most shards a manifest may describe (Reed-Solomon over GF(2^8))
+pub const MAX_MANIFEST_SHARDS: usize = 256;
+
+/// Pinned file hashes, kept in the cache directory
+pub const PINS_FILE: &str = "pins.json";
+
 /// File manifest - stores metadata about uploaded files
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileManifest {
@@ -17,9 +48,9 @@ pub struct FileManifest {
     pub file_name: String,
     /// Total file size in bytes
     pub file_size: usize,
-    /// Number of shards (total = k + m)
+    /// Number of shards (total = k + m, plus any local parity)
     pub shard_count: usize,
-    /// Number of parity shards (m)
+    /// Number of parity shards of the outer code (m)
     #[serde(default)]
     pub parity_count: usize,
     /// Shard locations: (shard_index, peer_id)
@@ -28,16 +59,233 @@ pub struct FileManifest {
     pub timestamp: i64,
     /// TTL in seconds (0 = permanent)
     pub ttl: u64,
+    /// Passphrase key derivation parameters, if the key came from a passphrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfParams>,
+    /// File key wrapped for each recipient's X25519 public key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<WrappedKey>,
+    /// SHA-256 of each shard, by index (empty for older manifests)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shard_hashes: Vec<String>,
+    /// Compression level the file was processed at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_level: Option<i32>,
+    /// Tenant namespace the file (and its shards) belong to
+    #[serde(
+        default = "default_namespace",
+        skip_serializing_if = "is_default_namespace"
+    )]
+    pub namespace: String,
+    /// Storage class the shards were placed on; heal keeps them there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+    /// Local parity groups of a two-tier layout; their shards follow the
+    /// outer shards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_parity: Option<LocalParity>,
+    /// Identity (base58 peer ID) each node in `shard_locations` proved
+    /// when it took its shards, by node ID; empty for older manifests
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shard_identities: BTreeMap<u32, String>,
+    /// Node that uploaded the file; locations naming it are the uploader's
+    /// own copies, which heal does not count toward redundancy by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<u32>,
+    /// Key rotation in progress or done; shards re-encrypted under a
+    /// rotated key no longer match `shard_hashes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<KeyRotation>,
+}
+
+/// An empty manifest in the default namespace, for building others with
+/// `..Default::default()`
+impl Default for FileManifest {
+    fn default() -> Self {
+        Self {
+            file_hash: String::new(),
+            file_name: String::new(),
+            file_size: 0,
+            shard_count: 0,
+            parity_count: 0,
+            shard_locations: Vec::new(),
+            timestamp: 0,
+            ttl: 0,
+            kdf: None,
+            recipients: Vec::new(),
+            shard_hashes: Vec::new(),
+            compression_level: None,
+            namespace: default_namespace(),
+            storage_class: None,
+            local_parity: None,
+            shard_identities: BTreeMap::new(),
+            uploader: None,
+            rotation: None,
+        }
+    }
+}
+
+impl FileManifest {
+    /// Parse and validate a manifest from untrusted JSON
+    pub fn from_json(json: &str) -> Result<Self> {
+        if json.len() > MAX_MANIFEST_BYTES {
+            anyhow::bail!(
+                "Manifest is {} bytes; limit is {}",
+                json.len(),
+                MAX_MANIFEST_BYTES
+            );
+        }
+        let manifest: FileManifest = serde_json::from_str(json).context("Invalid manifest")?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Check the invariants the download and cache paths index by
+    pub fn validate(&self) -> Result<()> {
+        if !is_safe_file_hash(&self.file_hash) {
+            // The hash names the manifest file on disk
+            anyhow::bail!("Invalid manifest file hash {:?}", self.file_hash);
+        }
+        validate_namespace(&self.namespace)?;
+        if let Some(class) = &self.storage_class {
+            validate_storage_class(class)?;
+        }
+        if self.shard_count == 0 || self.shard_count > MAX_MANIFEST_SHARDS {
+            anyhow::bail!(
+                "Manifest shard count {} outside 1..={}",
+                self.shard_count,
+                MAX_MANIFEST_SHARDS
+            );
+        }
+        if let Some(local) = &self.local_parity {
+            local.validate(self.outer_shard_count(), self.shard_count)?;
+        }
+        if self.parity_count >= self.outer_shard_count() {
+            anyhow::bail!(
+                "Manifest has {} parity shards out of {}",
+                self.parity_count,
+                self.outer_shard_count()
+            );
+        }
+        if let Some((index, _)) = self
+            .shard_locations
+            .iter()
+            .find(|(index, _)| *index >= self.shard_count)
+        {
+            anyhow::bail!(
+                "Manifest shard location {} out of range for {} shards",
+                index,
+                self.shard_count
+            );
+        }
+        if !self.shard_hashes.is_empty() && self.shard_hashes.len() != self.shard_count {
+            anyhow::bail!(
+                "Manifest lists {} shard hashes for {} shards",
+                self.shard_hashes.len(),
+                self.shard_count
+            );
+        }
+        for identity in self.shard_identities.values() {
+            parse_peer_id(identity)?;
+        }
+        if let Some(rotation) = &self.rotation {
+            if self.shard_hashes.is_empty() {
+                anyhow::bail!("Manifest without shard hashes has a key rotation");
+            }
+            rotation.validate(self.shard_count)?;
+        }
+        Ok(())
+    }
+
+    /// Oldest manifest format that can describe this manifest, so it is
+    /// only sent to peers that read it
+    pub fn format_version(&self) -> u16 {
+        if self.rotation.is_some() {
+            MANIFEST_V3
+        } else if self.local_parity.is_some() {
+            MANIFEST_V2
+        } else {
+            MANIFEST_V1
+        }
+    }
+
+    /// Whether `peer_id` uploaded the file, so its copies are its own
+    /// rather than network redundancy
+    pub fn is_uploader(&self, peer_id: u32) -> bool {
+        self.uploader == Some(peer_id)
+    }
+
+    /// Shards of the file-wide Reed-Solomon code; local parity shards,
+    /// if any, are numbered after them
+    pub fn outer_shard_count(&self) -> usize {
+        let local = self
+            .local_parity
+            .as_ref()
+            .map_or(0, LocalParity::shard_count);
+        self.shard_count.saturating_sub(local)
+    }
+
+    /// Shards needed to rebuild the file
+    pub fn data_shard_count(&self) -> usize {
+        self.outer_shard_count().saturating_sub(self.parity_count)
+    }
+
+    /// Key version of a copy of shard `index`, if a valid one: the
+    /// original, or a rotated version still accepted
+    pub fn shard_version(&self, index: usize, data: &[u8]) -> Option<u32> {
+        let original = self.shard_hashes.get(index)?;
+        let sha256 = BlobId::of(data).to_hex();
+        match &self.rotation {
+            Some(rotation) => rotation.version_of(index, original, &sha256),
+            None => (*original == sha256).then_some(ORIGINAL_VERSION),
+        }
+    }
+
+    /// Whether `data` is a valid copy of shard `index`; shards of
+    /// manifests without hashes cannot be checked and always match
+    pub fn shard_matches(&self, index: usize, data: &[u8]) -> bool {
+        index >= self.shard_hashes.len() || self.shard_version(index, data).is_some()
+    }
+
+    /// Whether a key rotation still accepts copies under an older key,
+    /// so heal passes should move them to the newest
+    pub fn rotation_pending(&self) -> bool {
+        self.rotation
+            .as_ref()
+            .is_some_and(|rotation| rotation.oldest < rotation.current)
+    }
+
+    /// Reference key for a shard: its content hash, or `file_hash:index`
+    /// for manifests written before shard hashes were recorded
+    pub fn shard_ref(&self, shard_index: usize) -> String {
+        match self.shard_hashes.get(shard_index) {
+            Some(hash) => hash.clone(),
+            None => format!("{}:{}", self.file_hash, shard_index),
+        }
+    }
+
+    /// Reference keys for every shard in the manifest
+    pub fn shard_refs(&self) -> Vec<String> {
+        (0..self.shard_count).map(|i| self.shard_ref(i)).collect()
+    }
 }
 
-/// Cached shard entry
+/// Cached shard entry; the bytes live in the blob store
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 struct CachedShard {
-    data: Vec<u8>,
+    blob: BlobId,
+    size: usize,
     timestamp: i64,
+    /// Fetched speculatively and not read since
+    prefetched: bool,
 }
 
+/// Inbound shards are refused while the cache volume is critically full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("cache volume is critically low on space; not accepting shards")]
+pub struct InboundPaused;
+
 /// Cache statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheStats {
@@ -48,6 +296,23 @@ pub struct CacheStats {
     pub total_shards_cached: usize,
     pub total_manifests_cached: usize,
     pub cache_size_bytes: usize,
+    /// Shards set aside after failing their hash check
+    #[serde(default)]
+    pub shards_quarantined: u64,
+    /// Shard fetches that waited on an identical fetch already in flight
+    #[serde(default)]
+    pub coalesced_fetches: u64,
+}
+
+/// What [`Cache::recover`] found after an unclean shutdown
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    /// Interrupted writes deleted
+    pub partial_writes_removed: usize,
+    /// Manifests that no longer parse
+    pub manifests_quarantined: usize,
+    /// Persisted shards that no longer match their manifest's hash
+    pub shards_quarantined: usize,
 }
 
 /// Caching layer for shards and manifests
@@ -55,17 +320,47 @@ pub struct Cache {
     /// LRU cache for shards (key: file_hash:shard_index)
     shard_cache: Arc<RwLock<LruCache<String, CachedShard>>>,
 
+    /// Shard contents, shared by shards with the same bytes
+    blobs: Arc<dyn BlobStore>,
+
     /// Manifest cache (key: file_hash)
     manifest_cache: Arc<RwLock<HashMap<String, FileManifest>>>,
 
+    /// Which manifests reference each shard
+    refs: Arc<RwLock<RefIndex>>,
+
     /// Cache statistics
     stats: Arc<RwLock<CacheStats>>,
 
-    /// Persistent storage directory
-    cache_dir: PathBuf,
+    /// Persistent storage directory; `None` for an in-memory cache
+    cache_dir: Option<PathBuf>,
 
     /// Maximum cache size in bytes
     max_cache_size: usize,
+
+    /// Hashes this node refuses to store
+    deny_list: Option<Arc<DenyList>>,
+
+    /// Files whose shards survive disk-pressure eviction
+    pinned: Arc<RwLock<HashSet<String>>>,
+
+    /// Cleared by the disk watchdog when free space is critical
+    accepting: AtomicBool,
+
+    /// Storage limits per namespace; unlisted namespaces are unlimited
+    quotas: Arc<RwLock<HashMap<String, NamespaceQuota>>>,
+
+    /// Access counts deciding which files' shards are evicted first
+    popularity: Option<Arc<PopularityTracker>>,
+
+    /// How hard manifest, shard and pin writes are pushed to disk
+    durability: Durability,
+
+    /// Where manifests are persisted; `None` for an in-memory cache
+    manifest_log: Option<ManifestLog>,
+
+    /// Remote fetches of missing shards, one per shard at a time
+    fetches: SingleFlight<String, Option<Vec<u8>>>,
 }
 
 impl Cache {
@@ -80,11 +375,35 @@ impl Cache {
         // Create cache directory if it doesn't exist
         std::fs::create_dir_all(&cache_dir).context("Failed to create cache directory")?;
 
+        Self::build(Some(cache_dir), max_entries, max_size_bytes)
+    }
+
+    /// Create a cache that never touches the disk
+    ///
+    /// For nodes that cannot write to disk (CI, sandboxes): manifests and
+    /// pins live in memory only and are gone on restart, "persisted" shards
+    /// are only cached and so can be evicted, shards failing their hash check are dropped rather than quarantined,
+    /// and [`Cache::recover`] and [`Cache::load_persisted_manifests`] find
+    /// nothing.
+    pub fn in_memory(max_entries: usize, max_size_bytes: usize) -> Result<Self> {
+        Self::build(None, max_entries, max_size_bytes)
+    }
+
+    fn build(
+        cache_dir: Option<PathBuf>,
+        max_entries: usize,
+        max_size_bytes: usize,
+    ) -> Result<Self> {
         let capacity = NonZeroUsize::new(max_entries).context("Cache capacity must be > 0")?;
+        let manifest_log = cache_dir
+            .as_ref()
+            .map(|dir| ManifestLog::new(dir.join("manifests"), Durability::default()));
 
         Ok(Self {
             shard_cache: Arc::new(RwLock::new(LruCache::new(capacity))),
+            blobs: Arc::new(TieredBlobStore::unbounded()),
             manifest_cache: Arc::new(RwLock::new(HashMap::new())),
+            refs: Arc::new(RwLock::new(RefIndex::new())),
             stats: Arc::new(RwLock::new(CacheStats {
                 shard_hits: 0,
                 shard_misses: 0,
@@ -93,28 +412,152 @@ impl Cache {
                 total_shards_cached: 0,
                 total_manifests_cached: 0,
                 cache_size_bytes: 0,
+                shards_quarantined: 0,
+                coalesced_fetches: 0,
             })),
             cache_dir,
             max_cache_size: max_size_bytes,
+            deny_list: None,
+            pinned: Arc::new(RwLock::new(HashSet::new())),
+            accepting: AtomicBool::new(true),
+            quotas: Arc::new(RwLock::new(HashMap::new())),
+            popularity: None,
+            durability: Durability::default(),
+            manifest_log,
+            fetches: SingleFlight::new(),
         })
     }
 
+    /// Keep shard contents in `blobs`, shared with other subsystems
+    ///
+    /// When the store is full the cache evicts shards of its own to make room.
+    pub fn with_blob_store(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.blobs = blobs;
+        self
+    }
+
+    pub fn blob_store(&self) -> &Arc<dyn BlobStore> {
+        &self.blobs
+    }
+
+    /// Push persisted writes to disk as `durability` asks
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self.manifest_log = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| ManifestLog::new(dir.join("manifests"), durability));
+        self
+    }
+
+    /// Whether manifests, pins and persisted shards are written to disk
+    pub fn is_persistent(&self) -> bool {
+        self.cache_dir.is_some()
+    }
+
+    /// Refuse shards whose file or content hash is deny-listed
+    pub fn with_deny_list(mut self, deny_list: Arc<DenyList>) -> Self {
+        self.deny_list = Some(deny_list);
+        self
+    }
+
+    pub fn deny_list(&self) -> Option<&Arc<DenyList>> {
+        self.deny_list.as_ref()
+    }
+
+    /// Evict shards of cold files before those of warm and hot files
+    pub fn with_popularity(mut self, popularity: Arc<PopularityTracker>) -> Self {
+        self.popularity = Some(popularity);
+        self
+    }
+
+    pub fn popularity(&self) -> Option<&Arc<PopularityTracker>> {
+        self.popularity.as_ref()
+    }
+
+    /// Count a download of a file towards its popularity
+    pub fn record_access(&self, file_hash: &str) {
+        if let Some(popularity) = &self.popularity {
+            popularity.record(file_hash);
+        }
+    }
+
+    /// Enforce storage limits per namespace
+    pub fn with_quotas(self, quotas: HashMap<String, NamespaceQuota>) -> Self {
+        Self {
+            quotas: Arc::new(RwLock::new(quotas)),
+            ..self
+        }
+    }
+
+    /// Set or replace the quota of a namespace
+    pub async fn set_quota(&self, namespace: &str, quota: NamespaceQuota) -> Result<()> {
+        validate_namespace(namespace)?;
+        self.quotas
+            .write()
+            .await
+            .insert(namespace.to_string(), quota);
+        Ok(())
+    }
+
+    pub async fn quota(&self, namespace: &str) -> NamespaceQuota {
+        self.quotas
+            .read()
+            .await
+            .get(namespace)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Fail if another file of `file_size` bytes would exceed the namespace quota
+    ///
+    /// Uploads call this before sending any shard; `put_manifest` checks
+    /// again when the file is recorded.
+    pub async fn check_quota(&self, namespace: &str, file_size: u64) -> Result<(), NamespaceError> {
+        validate_namespace(namespace)?;
+        let quota = self.quota(namespace).await;
+        if quota.is_unlimited() {
+            return Ok(());
+        }
+        let manifests = self.manifest_cache.read().await;
+        let (files, bytes) = usage(manifests.values(), namespace);
+        quota.check(namespace, files, bytes, file_size)
+    }
+
     /// Get a shard from cache
+    ///
+    /// Shards evicted from memory are still served from disk if they were
+    /// persisted with [`Cache::persist_shard`].
     pub async fn get_shard(&self, file_hash: &str, shard_index: usize) -> Option<Vec<u8>> {
         let key = format!("{}:{}", file_hash, shard_index);
-        let mut cache = self.shard_cache.write().await;
+        {
+            let mut cache = self.shard_cache.write().await;
+            if let Some(cached) = cache.get_mut(&key) {
+                cached.prefetched = false;
+                if let Some(data) = self.blobs.get(&cached.blob) {
+                    let mut stats = self.stats.write().await;
+                    stats.shard_hits += 1;
+                    debug!("Cache hit: {}", key);
+                    return Some(data.to_vec());
+                }
+            }
+        }
 
-        if let Some(cached) = cache.get(&key) {
-            let mut stats = self.stats.write().await;
-            stats.shard_hits += 1;
-            debug!("Cache hit: {}", key);
-            Some(cached.data.clone())
-        } else {
-            let mut stats = self.stats.write().await;
-            stats.shard_misses += 1;
-            debug!("Cache miss: {}", key);
-            None
+        if let Some(path) = self.persisted_shard_path(file_hash, shard_index) {
+            if let Ok(data) = tokio::fs::read(&path).await {
+                self.stats.write().await.shard_hits += 1;
+                debug!("Cache hit on disk: {}", key);
+                if let Err(e) = self.put_shard(file_hash, shard_index, data.clone()).await {
+                    debug!("Not keeping {} in memory: {:#}", key, e);
+                }
+                return Some(data);
+            }
         }
+
+        let mut stats = self.stats.write().await;
+        stats.shard_misses += 1;
+        debug!("Cache miss: {}", key);
+        None
     }
 
     /// Put a shard into cache
@@ -124,6 +567,97 @@ impl Cache {
         shard_index: usize,
         data: Vec<u8>,
     ) -> Result<()> {
+        self.insert_shard(file_hash, shard_index, data, false).await
+    }
+
+    /// Put a speculatively fetched shard into cache
+    ///
+    /// Prefetched shards are the first to go under disk pressure, until they
+    /// are read.
+    pub async fn put_prefetched_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        self.insert_shard(file_hash, shard_index, data, true).await
+    }
+
+    /// Put a shard into cache and keep a copy on disk
+    ///
+    /// Persisted shards survive restarts and memory eviction, so files
+    /// brought in offline (see [`crate::bundle`]) stay retrievable without
+    /// the network. The copy is removed with the file's manifest.
+    pub async fn persist_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        if !self.is_accepting() {
+            return Err(InboundPaused.into());
+        }
+        if let Some(deny_list) = &self.deny_list {
+            deny_list.check_shard(file_hash, &data)?;
+        }
+        if self.is_persistent() {
+            let path = self
+                .persisted_shard_path(file_hash, shard_index)
+                .with_context(|| format!("Invalid file hash {:?}", file_hash))?;
+            write_atomic(&path, &data, self.durability)
+                .await
+                .context("Failed to persist shard")?;
+            debug!("Persisted shard to: {:?}", path);
+        }
+        self.put_shard(file_hash, shard_index, data).await
+    }
+
+    /// Read a persisted shard from disk, bypassing the memory cache
+    ///
+    /// Lets the scrubber catch copies that rotted on disk while a good copy
+    /// is still held in memory.
+    pub async fn read_persisted_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Option<Vec<u8>> {
+        let path = self.persisted_shard_path(file_hash, shard_index)?;
+        tokio::fs::read(&path).await.ok()
+    }
+
+    /// Whether a shard has a copy on disk
+    pub fn has_persisted_shard(&self, file_hash: &str, shard_index: usize) -> bool {
+        self.persisted_shard_path(file_hash, shard_index)
+            .is_some_and(|path| path.exists())
+    }
+
+    /// Where a persisted shard is kept
+    ///
+    /// `None` for hashes that are not safe as a file name, and in memory.
+    fn persisted_shard_path(&self, file_hash: &str, shard_index: usize) -> Option<PathBuf> {
+        let cache_dir = self.cache_dir.as_ref()?;
+        is_safe_file_hash(file_hash).then(|| {
+            cache_dir
+                .join("shards")
+                .join(file_hash)
+                .join(format!("{}.bin", shard_index))
+        })
+    }
+
+    async fn insert_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        data: Vec<u8>,
+        prefetched: bool,
+    ) -> Result<()> {
+        if !self.is_accepting() {
+            return Err(InboundPaused.into());
+        }
+        if let Some(deny_list) = &self.deny_list {
+            deny_list.check_shard(file_hash, &data)?;
+        }
+
         let key = format!("{}:{}", file_hash, shard_index);
         let data_size = data.len();
 
@@ -138,22 +672,92 @@ impl Cache {
             self.evict_to_fit(data_size).await?;
         }
 
+        let blob = match self.blobs.put(&data) {
+            Ok(blob) => blob,
+            Err(e) if e.is::<BlobStoreFull>() => {
+                // Other owners of the store filled it; give up some of ours
+                self.evict_to_fit(data_size).await?;
+                self.blobs.put(&data)?
+            }
+            Err(e) => return Err(e),
+        };
         let cached = CachedShard {
-            data,
+            blob,
+            size: data_size,
             timestamp: chrono::Utc::now().timestamp(),
+            prefetched,
         };
 
         let mut cache = self.shard_cache.write().await;
-        cache.put(key.clone(), cached);
+        // Either the shard this one replaces or the entry evicted to fit it
+        let displaced = cache.push(key.clone(), cached).map(|(_, old)| old);
 
         let mut stats = self.stats.write().await;
         stats.total_shards_cached = cache.len();
         stats.cache_size_bytes += data_size;
+        if let Some(old) = displaced {
+            self.blobs.release(&old.blob);
+            stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(old.size);
+        }
 
         debug!("Cached shard: {} ({} bytes)", key, data_size);
         Ok(())
     }
 
+    /// Set aside a shard that failed its hash check
+    ///
+    /// Any cached copy is dropped so it is never served again, and the bytes
+    /// are kept under `quarantine/` as `<file_hash>.<index>.<peer>.<unix>.bin`
+    /// for later inspection. Returns where; `None` in memory, where the
+    /// bytes are only dropped.
+    pub async fn quarantine_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        peer_id: u32,
+        data: &[u8],
+    ) -> Result<Option<PathBuf>> {
+        let key = format!("{}:{}", file_hash, shard_index);
+        if let Some(dropped) = self.shard_cache.write().await.pop(&key) {
+            self.blobs.release(&dropped.blob);
+            let mut stats = self.stats.write().await;
+            stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(dropped.size);
+        }
+        if let Some(persisted) = self.persisted_shard_path(file_hash, shard_index) {
+            if persisted.exists() {
+                tokio::fs::remove_file(&persisted).await?;
+            }
+        }
+        self.stats.write().await.shards_quarantined += 1;
+
+        let Some(dir) = self.quarantine_dir() else {
+            warn!("☣️  Dropped shard {} from peer {}", key, peer_id);
+            return Ok(None);
+        };
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!(
+            "{}.{}.{}.{}.bin",
+            file_hash,
+            shard_index,
+            peer_id,
+            chrono::Utc::now().timestamp()
+        ));
+        tokio::fs::write(&path, data)
+            .await
+            .context("Failed to write quarantined shard")?;
+
+        warn!(
+            "☣️  Quarantined shard {} from peer {} at {:?}",
+            key, peer_id, path
+        );
+        Ok(Some(path))
+    }
+
+    /// Directory holding quarantined shards; `None` in memory
+    pub fn quarantine_dir(&self) -> Option<PathBuf> {
+        self.cache_dir.as_ref().map(|dir| dir.join("quarantine"))
+    }
+
     /// Get a manifest from cache
     pub async fn get_manifest(&self, file_hash: &str) -> Option<FileManifest> {
         let cache = self.manifest_cache.read().await;
@@ -172,15 +776,39 @@ impl Cache {
     }
 
     /// Put a manifest into cache
+    ///
+    /// A new file is refused with [`NamespaceError::QuotaExceeded`] if it
+    /// would take its namespace over quota; replacing a manifest is not
+    /// counted as a new file.
     pub async fn put_manifest(&self, manifest: FileManifest) -> Result<()> {
+        validate_namespace(&manifest.namespace)?;
+        let quota = self.quota(&manifest.namespace).await;
         let mut cache = self.manifest_cache.write().await;
-        cache.insert(manifest.file_hash.clone(), manifest.clone());
+        if !quota.is_unlimited() {
+            let (files, bytes) = usage(
+                cache
+                    .values()
+                    .filter(|existing| existing.file_hash != manifest.file_hash),
+                &manifest.namespace,
+            );
+            quota.check(&manifest.namespace, files, bytes, manifest.file_size as u64)?;
+        }
+        let previous = cache.insert(manifest.file_hash.clone(), manifest.clone());
+        let released = self
+            .refs
+            .write()
+            .await
+            .add(&manifest.file_hash, manifest.shard_refs());
+        if let Some(previous) = previous {
+            self.drop_released_shards(&previous, &released).await;
+        }
 
         let mut stats = self.stats.write().await;
         stats.total_manifests_cached = cache.len();
 
         // Also persist to disk
         self.persist_manifest(&manifest).await?;
+        self.compact_manifest_log(&cache).await;
 
         info!(
             "Cached manifest: {} ({} shards)",
@@ -195,14 +823,101 @@ impl Cache {
         cache.values().cloned().collect()
     }
 
+    /// List cached manifests in one namespace
+    pub async fn list_manifests_in(&self, namespace: &str) -> Vec<FileManifest> {
+        let cache = self.manifest_cache.read().await;
+        cache
+            .values()
+            .filter(|manifest| manifest.namespace == namespace)
+            .cloned()
+            .collect()
+    }
+
+    /// Usage and quota of one namespace
+    pub async fn namespace_stats(&self, namespace: &str) -> NamespaceStats {
+        self.all_namespace_stats()
+            .await
+            .into_iter()
+            .find(|stats| stats.namespace == namespace)
+            .unwrap_or_else(|| NamespaceStats {
+                namespace: namespace.to_string(),
+                ..Default::default()
+            })
+    }
+
+    /// Usage and quota of every namespace with files or a quota, by name
+    ///
+    /// Cached shards count towards the namespace of their file's manifest.
+    pub async fn all_namespace_stats(&self) -> Vec<NamespaceStats> {
+        let quotas = self.quotas.read().await;
+        let manifests = self.manifest_cache.read().await;
+        let shards = self.shard_cache.read().await;
+
+        let mut by_name: HashMap<&str, NamespaceStats> = HashMap::new();
+        let entry = |namespace: &str| -> NamespaceStats {
+            NamespaceStats {
+                namespace: namespace.to_string(),
+                quota: quotas.get(namespace).copied().unwrap_or_default(),
+                ..Default::default()
+            }
+        };
+        for namespace in quotas.keys() {
+            by_name.insert(namespace.as_str(), entry(namespace));
+        }
+        for manifest in manifests.values() {
+            let stats = by_name
+                .entry(manifest.namespace.as_str())
+                .or_insert_with(|| entry(&manifest.namespace));
+            stats.files += 1;
+            stats.bytes += manifest.file_size as u64;
+        }
+        for (key, shard) in shards.iter() {
+            let file_hash = key.rsplit_once(':').map_or(key.as_str(), |(hash, _)| hash);
+            if let Some(manifest) = manifests.get(file_hash) {
+                if let Some(stats) = by_name.get_mut(manifest.namespace.as_str()) {
+                    stats.shards_cached += 1;
+                    stats.shard_bytes_cached += shard.size as u64;
+                }
+            }
+        }
+
+        let mut stats: Vec<NamespaceStats> = by_name.into_values().collect();
+        stats.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        stats
+    }
+
     /// Get cache statistics
     pub async fn get_stats(&self) -> CacheStats {
-        self.stats.read().await.clone()
+        let mut stats = self.stats.read().await.clone();
+        stats.coalesced_fetches = self.fetches.coalesced();
+        stats
+    }
+
+    /// Fetch a missing shard with `fetch`, once however many callers want it
+    ///
+    /// Callers arriving while a fetch of the same shard is in flight wait
+    /// for its result instead of starting their own.
+    pub async fn fetch_shard_once<F, Fut>(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        fetch: F,
+    ) -> Option<Vec<u8>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Option<Vec<u8>>>,
+    {
+        self.fetches
+            .run(format!("{}:{}", file_hash, shard_index), fetch)
+            .await
     }
 
     /// Clear all cached shards
     pub async fn clear_shards(&self) -> Result<()> {
         let mut cache = self.shard_cache.write().await;
+        for (_, shard) in cache.iter() {
+            self.blobs.release(&shard.blob);
+        }
         cache.clear();
 
         let mut stats = self.stats.write().await;
@@ -222,10 +937,13 @@ impl Cache {
         stats.total_manifests_cached = 0;
 
         // Also clear persisted manifests
-        let manifest_dir = self.cache_dir.join("manifests");
-        if manifest_dir.exists() {
-            std::fs::remove_dir_all(&manifest_dir)?;
-            std::fs::create_dir_all(&manifest_dir)?;
+        if let (Some(cache_dir), Some(manifest_log)) = (&self.cache_dir, &self.manifest_log) {
+            let manifest_dir = cache_dir.join("manifests");
+            if manifest_dir.exists() {
+                std::fs::remove_dir_all(&manifest_dir)?;
+                std::fs::create_dir_all(&manifest_dir)?;
+            }
+            manifest_log.reset();
         }
 
         info!("Cleared all manifests");
@@ -240,14 +958,18 @@ impl Cache {
         let mut freed_space = 0;
         let target_space = required_space + (self.max_cache_size / 10); // Free 10% extra
 
-        while freed_space < target_space && !cache.is_empty() {
-            if let Some((_, evicted)) = cache.pop_lru() {
-                let evicted_size = evicted.data.len();
+        // `iter()` runs from most to least recently used
+        let victims = self.coldest_first(cache.iter().rev().map(|(key, _)| key.clone()).collect());
+        for key in victims {
+            if freed_space >= target_space {
+                break;
+            }
+            if let Some(evicted) = cache.pop(&key) {
+                self.blobs.release(&evicted.blob);
+                let evicted_size = evicted.size;
                 freed_space += evicted_size;
                 stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(evicted_size);
                 debug!("Evicted shard ({} bytes freed)", evicted_size);
-            } else {
-                break;
             }
         }
 
@@ -256,71 +978,309 @@ impl Cache {
         Ok(())
     }
 
-    /// Persist a manifest to disk
-    async fn persist_manifest(&self, manifest: &FileManifest) -> Result<()> {
-        let manifest_dir = self.cache_dir.join("manifests");
-        tokio::fs::create_dir_all(&manifest_dir).await?;
+    /// Keep a file's shards through disk-pressure eviction
+    pub async fn pin(&self, file_hash: &str) {
+        let mut pinned = self.pinned.write().await;
+        if pinned.insert(file_hash.to_string()) {
+            self.persist_pins(&pinned).await;
+        }
+    }
+
+    pub async fn unpin(&self, file_hash: &str) -> bool {
+        let mut pinned = self.pinned.write().await;
+        let removed = pinned.remove(file_hash);
+        if removed {
+            self.persist_pins(&pinned).await;
+        }
+        removed
+    }
 
-        let manifest_path = manifest_dir.join(format!("{}.json", manifest.file_hash));
-        let json = serde_json::to_string_pretty(manifest)?;
+    /// Pinned file hashes, sorted
+    pub async fn pinned_files(&self) -> Vec<String> {
+        let mut pinned: Vec<String> = self.pinned.read().await.iter().cloned().collect();
+        pinned.sort_unstable();
+        pinned
+    }
+
+    /// Save the pin set so it survives restarts (best effort)
+    async fn persist_pins(&self, pinned: &HashSet<String>) {
+        let Some(cache_dir) = &self.cache_dir else {
+            return;
+        };
+        let mut sorted: Vec<&String> = pinned.iter().collect();
+        sorted.sort_unstable();
+        let written = match serde_json::to_vec_pretty(&sorted) {
+            Ok(json) => write_atomic(&cache_dir.join(PINS_FILE), &json, self.durability).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            warn!("Failed to persist pins: {:#}", e);
+        }
+    }
+
+    pub async fn is_pinned(&self, file_hash: &str) -> bool {
+        self.pinned.read().await.contains(file_hash)
+    }
+
+    /// Whether new shards are accepted
+    pub fn is_accepting(&self) -> bool {
+        self.accepting.load(Ordering::Relaxed)
+    }
+
+    /// Stop or resume accepting new shards
+    pub fn set_accepting(&self, accepting: bool) {
+        if self.accepting.swap(accepting, Ordering::Relaxed) != accepting {
+            if accepting {
+                info!("Resumed accepting shards");
+            } else {
+                warn!("⚠️  Paused accepting shards");
+            }
+        }
+    }
+
+    /// Drop shards that were prefetched and never read, returning bytes freed
+    pub async fn evict_prefetched(&self) -> usize {
+        self.evict_where(usize::MAX, |_, shard, _| shard.prefetched)
+            .await
+    }
+
+    /// Drop unpinned shards, coldest and least recently used first, until
+    /// `target` bytes are freed
+    pub async fn evict_unpinned_lru(&self, target: usize) -> usize {
+        self.evict_where(target, |file_hash, _, pinned| !pinned.contains(file_hash))
+            .await
+    }
+
+    /// Drop shards of at least `min_size` bytes, pinned or not
+    pub async fn evict_oversized(&self, min_size: usize) -> usize {
+        self.evict_where(usize::MAX, |_, shard, _| shard.size >= min_size)
+            .await
+    }
+
+    /// Evict matching shards, coldest files first and in LRU order within
+    /// each temperature, until `target` bytes are freed
+    async fn evict_where(
+        &self,
+        target: usize,
+        matches: impl Fn(&str, &CachedShard, &HashSet<String>) -> bool,
+    ) -> usize {
+        let pinned = self.pinned.read().await;
+        let mut cache = self.shard_cache.write().await;
+
+        // `iter()` runs from most to least recently used
+        let victims: Vec<String> = cache
+            .iter()
+            .rev()
+            .filter(|(key, shard)| {
+                let file_hash = key.rsplit_once(':').map_or(key.as_str(), |(hash, _)| hash);
+                matches(file_hash, shard, &pinned)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut freed = 0;
+        for key in self.coldest_first(victims) {
+            if freed >= target {
+                break;
+            }
+            if let Some(shard) = cache.pop(&key) {
+                self.blobs.release(&shard.blob);
+                freed += shard.size;
+            }
+        }
+
+        let mut stats = self.stats.write().await;
+        stats.total_shards_cached = cache.len();
+        stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(freed);
+        freed
+    }
+
+    /// Reorder eviction candidates, given least recently used first, so
+    /// shards of colder files go first (a stable sort keeps LRU order within
+    /// each temperature)
+    fn coldest_first(&self, mut keys: Vec<String>) -> Vec<String> {
+        if let Some(popularity) = &self.popularity {
+            keys.sort_by_cached_key(|key| {
+                let file_hash = key.rsplit_once(':').map_or(key.as_str(), |(hash, _)| hash);
+                popularity.temperature(file_hash)
+            });
+        }
+        keys
+    }
 
-        tokio::fs::write(&manifest_path, json)
+    /// Persist a manifest to disk
+    async fn persist_manifest(&self, manifest: &FileManifest) -> Result<()> {
+        let Some(manifest_log) = &self.manifest_log else {
+            return Ok(());
+        };
+        manifest_log
+            .put(manifest)
             .await
             .context("Failed to persist manifest")?;
 
-        debug!("Persisted manifest to: {:?}", manifest_path);
+        debug!("Persisted manifest {}", manifest.file_hash);
         Ok(())
     }
 
-    /// Load persisted manifests from disk
-    pub async fn load_persisted_manifests(&self) -> Result<usize> {
-        let manifest_dir = self.cache_dir.join("manifests");
-
-        if !manifest_dir.exists() {
-            return Ok(0);
+    /// Rewrite the manifest log once superseded entries outnumber `manifests`,
+    /// the live ones; a failure only costs disk space, so it is logged
+    async fn compact_manifest_log(&self, manifests: &HashMap<String, FileManifest>) {
+        let Some(manifest_log) = &self.manifest_log else {
+            return;
+        };
+        if !manifest_log.needs_compaction(manifests.len()) {
+            return;
+        }
+        if let Err(e) = manifest_log.compact(manifests.values()).await {
+            warn!("Failed to compact manifest log: {:#}", e);
         }
+    }
+
+    /// Find writes a crash left torn; call before loading manifests
+    ///
+    /// Interrupted writes are deleted, as is an unfinished last entry of the
+    /// manifest log. Manifest files that no longer parse and persisted
+    /// shards that no longer match their manifest's shard hash (torn before
+    /// writes were atomic, or by a disk losing unsynced data) are moved
+    /// under `quarantine/` so they are never served. An in-memory cache has
+    /// nothing to recover.
+    pub async fn recover(&self) -> Result<RecoveryReport> {
+        let Some(cache_dir) = &self.cache_dir else {
+            return Ok(RecoveryReport::default());
+        };
+        let mut report = RecoveryReport {
+            partial_writes_removed: durability::remove_partial_writes(cache_dir).await?,
+            ..Default::default()
+        };
 
-        let mut count = 0;
-        let mut entries = tokio::fs::read_dir(&manifest_dir).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                match tokio::fs::read_to_string(&path).await {
-                    Ok(json) => match serde_json::from_str::<FileManifest>(&json) {
-                        Ok(manifest) => {
-                            let mut cache = self.manifest_cache.write().await;
-                            cache.insert(manifest.file_hash.clone(), manifest);
-                            count += 1;
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse manifest {:?}: {}", path, e);
-                        }
-                    },
+        let mut manifests = Vec::new();
+        if let Ok(mut entries) = tokio::fs::read_dir(cache_dir.join("manifests")).await {
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                    continue;
+                }
+                let parsed = match tokio::fs::read_to_string(&path).await {
+                    Ok(json) => FileManifest::from_json(&json),
+                    Err(e) => Err(e.into()),
+                };
+                match parsed {
+                    Ok(manifest) => manifests.push(manifest),
                     Err(e) => {
-                        warn!("Failed to read manifest {:?}: {}", path, e);
+                        warn!("☣️  Quarantining torn manifest {:?}: {:#}", path, e);
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        self.quarantine_file(&path, &name).await?;
+                        report.manifests_quarantined += 1;
                     }
                 }
             }
         }
+        // A log of its own, so reading it here does not count as loading the
+        // manifests into memory (which compaction waits for)
+        let log = ManifestLog::new(cache_dir.join("manifests"), self.durability);
+        manifests.extend(log.replay().await?.manifests.into_values());
+
+        for manifest in &manifests {
+            for index in 0..manifest.shard_hashes.len() {
+                let Some(path) = self.persisted_shard_path(&manifest.file_hash, index) else {
+                    continue;
+                };
+                let Ok(data) = tokio::fs::read(&path).await else {
+                    continue;
+                };
+                // Copies re-encrypted under a rotated key are intact too
+                if !manifest.shard_matches(index, &data) {
+                    warn!(
+                        "☣️  Quarantining torn shard {}:{}",
+                        manifest.file_hash, index
+                    );
+                    let name = format!("{}.{}.bin", manifest.file_hash, index);
+                    self.quarantine_file(&path, &name).await?;
+                    report.shards_quarantined += 1;
+                }
+            }
+        }
+        self.stats.write().await.shards_quarantined += report.shards_quarantined as u64;
+
+        if report != RecoveryReport::default() {
+            info!(
+                "Recovered cache: removed {} interrupted write(s), quarantined {} manifest(s) and {} shard(s)",
+                report.partial_writes_removed,
+                report.manifests_quarantined,
+                report.shards_quarantined
+            );
+        }
+        Ok(report)
+    }
+
+    /// Move a torn file to `quarantine/<name>.torn.<unix>`
+    async fn quarantine_file(&self, path: &Path, name: &str) -> Result<PathBuf> {
+        let dir = self
+            .quarantine_dir()
+            .context("An in-memory cache has no quarantine")?;
+        tokio::fs::create_dir_all(&dir).await?;
+        let target = dir.join(format!("{}.torn.{}", name, chrono::Utc::now().timestamp()));
+        tokio::fs::rename(path, &target)
+            .await
+            .with_context(|| format!("Failed to quarantine {:?}", path))?;
+        Ok(target)
+    }
+
+    /// Load persisted manifests from disk
+    ///
+    /// Manifest files under `manifests/` (an older cache, a restored
+    /// snapshot) are first moved into the manifest log. An in-memory cache
+    /// has none.
+    pub async fn load_persisted_manifests(&self) -> Result<usize> {
+        let (Some(cache_dir), Some(manifest_log)) = (&self.cache_dir, &self.manifest_log) else {
+            return Ok(0);
+        };
+        manifest_log.import_legacy().await?;
+        let replay = manifest_log.replay().await?;
+        let count = replay.manifests.len();
+        {
+            let mut cache = self.manifest_cache.write().await;
+            let mut refs = self.refs.write().await;
+            for (file_hash, manifest) in replay.manifests {
+                refs.add(&file_hash, manifest.shard_refs());
+                cache.insert(file_hash, manifest);
+            }
+            self.compact_manifest_log(&cache).await;
+        }
 
         info!("Loaded {} persisted manifests", count);
+
+        let pins_path = cache_dir.join(PINS_FILE);
+        if pins_path.exists() {
+            let json = tokio::fs::read(&pins_path).await?;
+            match serde_json::from_slice::<Vec<String>>(&json) {
+                Ok(pins) => self.pinned.write().await.extend(pins),
+                Err(e) => warn!("Failed to parse pins {:?}: {}", pins_path, e),
+            }
+        }
         Ok(count)
     }
 
     /// Remove a manifest from cache
+    ///
+    /// Its cached shards are dropped too, except those another manifest
+    /// still references.
     pub async fn remove_manifest(&self, file_hash: &str) -> Result<bool> {
         let mut cache = self.manifest_cache.write().await;
-        let removed = cache.remove(file_hash).is_some();
+        let removed = cache.remove(file_hash);
+
+        if let Some(manifest) = &removed {
+            let released = self.refs.write().await.remove(file_hash);
+            self.drop_released_shards(manifest, &released).await;
 
-        if removed {
             // Also remove from disk
-            let manifest_path = self
-                .cache_dir
-                .join("manifests")
-                .join(format!("{}.json", file_hash));
-            if manifest_path.exists() {
-                tokio::fs::remove_file(&manifest_path).await?;
+            if let (Some(cache_dir), Some(manifest_log)) = (&self.cache_dir, &self.manifest_log) {
+                manifest_log.remove(file_hash).await?;
+                self.compact_manifest_log(&cache).await;
+                let shard_dir = cache_dir.join("shards").join(file_hash);
+                if shard_dir.exists() {
+                    tokio::fs::remove_dir_all(&shard_dir).await?;
+                }
             }
 
             let mut stats = self.stats.write().await;
@@ -329,14 +1289,99 @@ impl Cache {
             info!("Removed manifest: {}", file_hash);
         }
 
-        Ok(removed)
+        Ok(removed.is_some())
     }
 
-    /// Check if a shard exists in cache
+    /// Number of manifests referencing a shard (by content hash or `file_hash:index`)
+    pub async fn shard_ref_count(&self, shard_ref: &str) -> usize {
+        self.refs.read().await.count(shard_ref)
+    }
+
+    /// Drop cached shards that are no longer referenced by any manifest
+    ///
+    /// Shards whose file still has a manifest are kept, as are shards whose
+    /// content is still referenced elsewhere. Returns the number removed.
+    pub async fn collect_garbage(&self) -> usize {
+        let manifests = self.manifest_cache.read().await;
+        let refs = self.refs.read().await;
+        let mut cache = self.shard_cache.write().await;
+
+        let orphans: Vec<String> = cache
+            .iter()
+            .filter(|(key, shard)| {
+                let file_hash = key.rsplit_once(':').map_or(key.as_str(), |(hash, _)| hash);
+                !manifests.contains_key(file_hash)
+                    && !refs.is_referenced(key)
+                    && !refs.is_referenced(&shard.blob.to_hex())
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut freed = 0;
+        for key in &orphans {
+            if let Some(shard) = cache.pop(key) {
+                self.blobs.release(&shard.blob);
+                freed += shard.size;
+            }
+        }
+
+        let mut stats = self.stats.write().await;
+        stats.total_shards_cached = cache.len();
+        stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(freed);
+        if !orphans.is_empty() {
+            info!(
+                "Collected {} orphaned shard(s) ({} bytes)",
+                orphans.len(),
+                freed
+            );
+        }
+        orphans.len()
+    }
+
+    /// Remove a manifest's cached shards whose references were just released
+    async fn drop_released_shards(&self, manifest: &FileManifest, released: &[String]) {
+        if released.is_empty() {
+            return;
+        }
+        let mut cache = self.shard_cache.write().await;
+        let mut freed = 0;
+        for index in 0..manifest.shard_count {
+            if released.contains(&manifest.shard_ref(index)) {
+                let key = format!("{}:{}", manifest.file_hash, index);
+                if let Some(shard) = cache.pop(&key) {
+                    self.blobs.release(&shard.blob);
+                    freed += shard.size;
+                }
+            }
+        }
+        let mut stats = self.stats.write().await;
+        stats.total_shards_cached = cache.len();
+        stats.cache_size_bytes = stats.cache_size_bytes.saturating_sub(freed);
+        debug!(
+            "Released {} shard reference(s) of {} ({} bytes freed)",
+            released.len(),
+            manifest.file_hash,
+            freed
+        );
+    }
+
+    /// Check if a shard exists in cache, in memory or persisted
     pub async fn has_shard(&self, file_hash: &str, shard_index: usize) -> bool {
         let key = format!("{}:{}", file_hash, shard_index);
         let cache = self.shard_cache.read().await;
         cache.contains(&key)
+            || self
+                .persisted_shard_path(file_hash, shard_index)
+                .is_some_and(|path| path.exists())
+    }
+
+    /// Build a Bloom filter of all locally cached shard keys
+    pub async fn shard_filter(&self, false_positive_rate: f64) -> ShardBloomFilter {
+        let cache = self.shard_cache.read().await;
+        ShardBloomFilter::from_keys(
+            cache.iter().map(|(key, _)| key.as_str()),
+            false_positive_rate,
+        )
     }
 
     /// Get all manifests (for auto-healing)
@@ -360,6 +1405,8 @@ impl Cache {
         };
         if let Some(manifest) = manifest_to_persist {
             self.persist_manifest(&manifest).await?;
+            self.compact_manifest_log(&*self.manifest_cache.read().await)
+                .await;
             info!("Refreshed TTL for {}: {} seconds", file_hash, new_ttl);
             Ok(true)
         } else {
@@ -368,9 +1415,29 @@ impl Cache {
     }
 }
 
+/// Whether a file hash is safe to use as a file name under the cache dir
+pub(crate) fn is_safe_file_hash(file_hash: &str) -> bool {
+    !file_hash.is_empty()
+        && file_hash.len() <= 128
+        && file_hash
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Files and original bytes in `namespace`
+fn usage<'a>(manifests: impl Iterator<Item = &'a FileManifest>, namespace: &str) -> (usize, u64) {
+    manifests
+        .filter(|manifest| manifest.namespace == namespace)
+        .fold((0, 0), |(files, bytes), manifest| {
+            (files + 1, bytes + manifest.file_size as u64)
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::namespace::DEFAULT_NAMESPACE;
+    use sha2::{Digest, Sha256};
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -389,6 +1456,108 @@ mod tests {
         assert_eq!(stats.total_shards_cached, 1);
     }
 
+    #[tokio::test]
+    async fn test_deny_list_refuses_shards() {
+        let temp_dir = tempdir().unwrap();
+        let deny_list = Arc::new(DenyList::new());
+        deny_list.add("bad", None).unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024)
+            .unwrap()
+            .with_deny_list(deny_list);
+
+        let err = cache.put_shard("bad", 0, vec![1, 2, 3]).await.unwrap_err();
+        assert!(err.downcast_ref::<crate::denylist::Denied>().is_some());
+        assert_eq!(cache.get_shard("bad", 0).await, None);
+
+        cache.put_shard("good", 0, vec![1, 2, 3]).await.unwrap();
+        assert!(cache.get_shard("good", 0).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pressure_eviction_stages() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+
+        cache
+            .put_prefetched_shard("pre", 0, vec![0; 10])
+            .await
+            .unwrap();
+        cache
+            .put_prefetched_shard("pre", 1, vec![0; 10])
+            .await
+            .unwrap();
+        // Reading a prefetched shard makes it an ordinary one
+        cache.get_shard("pre", 1).await.unwrap();
+        cache.put_shard("old", 0, vec![0; 20]).await.unwrap();
+        cache.put_shard("new", 0, vec![0; 20]).await.unwrap();
+        cache.put_shard("keep", 0, vec![0; 100]).await.unwrap();
+        cache.pin("keep").await;
+
+        assert_eq!(cache.evict_prefetched().await, 10);
+        assert!(!cache.has_shard("pre", 0).await);
+
+        // Least recently used unpinned shards go first
+        assert_eq!(cache.evict_unpinned_lru(25).await, 30);
+        assert!(!cache.has_shard("pre", 1).await);
+        assert!(!cache.has_shard("old", 0).await);
+        assert!(cache.has_shard("new", 0).await);
+
+        assert_eq!(cache.evict_unpinned_lru(usize::MAX).await, 20);
+        assert!(cache.has_shard("keep", 0).await);
+
+        assert_eq!(cache.evict_oversized(64).await, 100);
+        assert_eq!(cache.get_stats().await.cache_size_bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cold_files_are_evicted_first() {
+        use crate::popularity::PopularityConfig;
+
+        let temp_dir = tempdir().unwrap();
+        let popularity = Arc::new(PopularityTracker::new(PopularityConfig::default()));
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024)
+            .unwrap()
+            .with_popularity(popularity.clone());
+        for _ in 0..10 {
+            cache.record_access("hot");
+        }
+        cache.put_shard("hot", 0, vec![0; 20]).await.unwrap();
+        cache.put_shard("cold", 0, vec![0; 20]).await.unwrap();
+        cache.put_shard("cold", 1, vec![0; 20]).await.unwrap();
+
+        // The hot shard is the least recently used, yet outlives cold ones
+        assert_eq!(cache.evict_unpinned_lru(30).await, 40);
+        assert!(cache.has_shard("hot", 0).await);
+        assert!(!cache.has_shard("cold", 1).await);
+    }
+
+    #[tokio::test]
+    async fn test_pins_survive_reload() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 1024 * 1024).unwrap();
+        cache.pin("b").await;
+        cache.pin("a").await;
+        cache.pin("c").await;
+        assert!(cache.unpin("c").await);
+
+        let reopened = Cache::new(temp_dir.path(), 100, 1024 * 1024).unwrap();
+        reopened.load_persisted_manifests().await.unwrap();
+        assert_eq!(reopened.pinned_files().await, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_paused_cache_refuses_shards() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+
+        cache.set_accepting(false);
+        let err = cache.put_shard("a", 0, vec![1]).await.unwrap_err();
+        assert!(err.downcast_ref::<InboundPaused>().is_some());
+
+        cache.set_accepting(true);
+        cache.put_shard("a", 0, vec![1]).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_manifest_cache() {
         let temp_dir = tempdir().unwrap();
@@ -403,6 +1572,7 @@ mod tests {
             shard_locations: vec![(0, 1), (1, 2), (2, 3), (3, 4), (4, 5)],
             timestamp: chrono::Utc::now().timestamp(),
             ttl: 3600,
+            ..Default::default()
         };
 
         cache.put_manifest(manifest.clone()).await.unwrap();
@@ -416,6 +1586,24 @@ mod tests {
         assert_eq!(stats.total_manifests_cached, 1);
     }
 
+    #[test]
+    fn test_manifest_from_json_validates() {
+        let json = r#"{"file_hash":"abc123","file_name":"f","file_size":10,
+            "shard_count":6,"parity_count":2,"shard_locations":[[0,1],[5,2]],
+            "timestamp":0,"ttl":0}"#;
+        let manifest = FileManifest::from_json(json).unwrap();
+        assert_eq!(manifest.shard_count, 6);
+
+        let bad_index = json.replace("[5,2]", "[6,2]");
+        assert!(FileManifest::from_json(&bad_index).is_err());
+        let traversal = json.replace("abc123", "../../etc");
+        assert!(FileManifest::from_json(&traversal).is_err());
+        let huge = json.replace("\"shard_count\":6", "\"shard_count\":100000");
+        assert!(FileManifest::from_json(&huge).is_err());
+        let all_parity = json.replace("\"parity_count\":2", "\"parity_count\":6");
+        assert!(FileManifest::from_json(&all_parity).is_err());
+    }
+
     #[tokio::test]
     async fn test_cache_eviction() {
         let temp_dir = tempdir().unwrap();
@@ -428,4 +1616,279 @@ mod tests {
         let stats = cache.get_stats().await;
         assert!(stats.total_shards_cached <= 2);
     }
+
+    #[tokio::test]
+    async fn test_identical_shards_share_a_blob() {
+        let temp_dir = tempdir().unwrap();
+        let blobs = Arc::new(TieredBlobStore::unbounded());
+        let cache = Cache::new(temp_dir.path(), 10, 1024)
+            .unwrap()
+            .with_blob_store(blobs.clone());
+
+        cache.put_shard("a", 0, vec![7; 100]).await.unwrap();
+        cache.put_shard("b", 3, vec![7; 100]).await.unwrap();
+        let stats = blobs.stats();
+        assert_eq!(
+            (stats.blobs, stats.references, stats.memory_bytes),
+            (1, 2, 100)
+        );
+
+        assert_eq!(cache.evict_unpinned_lru(1).await, 100);
+        assert_eq!(cache.get_shard("b", 3).await, Some(vec![7; 100]));
+        cache.clear_shards().await.unwrap();
+        assert_eq!(blobs.stats().blobs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_shard_filter() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+
+        cache.put_shard("hash1", 0, vec![1, 2, 3]).await.unwrap();
+        cache.put_shard("hash1", 2, vec![4, 5, 6]).await.unwrap();
+
+        let filter = cache.shard_filter(0.001).await;
+        assert_eq!(filter.len(), 2);
+        assert!(filter.contains("hash1", 0));
+        assert!(filter.contains("hash1", 2));
+    }
+
+    #[tokio::test]
+    async fn test_remove_manifest_keeps_shared_shards() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+
+        let manifest = |file_hash: &str, shard_hashes: &[&str]| FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.bin", file_hash),
+            file_size: 6,
+            shard_count: shard_hashes.len(),
+            shard_hashes: shard_hashes.iter().map(|h| h.to_string()).collect(),
+            ..Default::default()
+        };
+        let shared = format!("{:x}", Sha256::digest([4, 5, 6]));
+        cache
+            .put_manifest(manifest("a", &["s1", &shared]))
+            .await
+            .unwrap();
+        cache.put_manifest(manifest("b", &[&shared])).await.unwrap();
+        cache.put_shard("a", 0, vec![1, 2, 3]).await.unwrap();
+        cache.put_shard("a", 1, vec![4, 5, 6]).await.unwrap();
+        assert_eq!(cache.shard_ref_count(&shared).await, 2);
+
+        assert!(cache.remove_manifest("a").await.unwrap());
+        assert!(!cache.has_shard("a", 0).await);
+        assert!(cache.has_shard("a", 1).await);
+        assert_eq!(cache.shard_ref_count(&shared).await, 1);
+
+        // Only shards with neither a manifest nor referenced content are garbage
+        cache.put_shard("gone", 0, vec![9]).await.unwrap();
+        assert_eq!(cache.collect_garbage().await, 1);
+        assert!(!cache.has_shard("gone", 0).await);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_quotas_and_stats() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024)
+            .unwrap()
+            .with_quotas(HashMap::from([(
+                "photos".to_string(),
+                NamespaceQuota {
+                    max_files: 2,
+                    max_bytes: 1000,
+                },
+            )]));
+
+        let manifest = |file_hash: &str, namespace: &str, file_size: usize| FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.bin", file_hash),
+            file_size,
+            shard_count: 1,
+            namespace: namespace.to_string(),
+            ..Default::default()
+        };
+        cache
+            .put_manifest(manifest("p1", "photos", 600))
+            .await
+            .unwrap();
+        cache.put_shard("p1", 0, vec![0; 40]).await.unwrap();
+        cache
+            .put_manifest(manifest("d1", DEFAULT_NAMESPACE, 5000))
+            .await
+            .unwrap();
+
+        // Over the byte limit, then over the file limit
+        let err = cache
+            .put_manifest(manifest("p2", "photos", 500))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NamespaceError>(),
+            Some(NamespaceError::QuotaExceeded { .. })
+        ));
+        assert!(cache.check_quota("photos", 500).await.is_err());
+        cache
+            .put_manifest(manifest("p2", "photos", 100))
+            .await
+            .unwrap();
+        assert!(cache.check_quota("photos", 1).await.is_err());
+        // Replacing a file does not count as a new one
+        cache
+            .put_manifest(manifest("p2", "photos", 200))
+            .await
+            .unwrap();
+
+        assert_eq!(cache.list_manifests_in("photos").await.len(), 2);
+        let photos = cache.namespace_stats("photos").await;
+        assert_eq!((photos.files, photos.bytes), (2, 800));
+        assert_eq!((photos.shards_cached, photos.shard_bytes_cached), (1, 40));
+        assert_eq!(photos.quota.max_files, 2);
+        let names: Vec<String> = cache
+            .all_namespace_stats()
+            .await
+            .into_iter()
+            .map(|stats| stats.namespace)
+            .collect();
+        assert_eq!(names, vec![DEFAULT_NAMESPACE, "photos"]);
+
+        // Older manifests without a namespace land in the default one
+        let json = r#"{"file_hash":"old","file_name":"f","file_size":1,
+            "shard_count":1,"shard_locations":[],"timestamp":0,"ttl":0}"#;
+        let old = FileManifest::from_json(json).unwrap();
+        assert_eq!(old.namespace, DEFAULT_NAMESPACE);
+        assert!(!serde_json::to_string(&old).unwrap().contains("namespace"));
+        let bad = json.replace("\"ttl\":0", "\"ttl\":0,\"namespace\":\"a/b\"");
+        assert!(FileManifest::from_json(&bad).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_persisted_shards_survive_eviction() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+
+        cache.persist_shard("file", 0, vec![1, 2, 3]).await.unwrap();
+        cache.clear_shards().await.unwrap();
+        assert!(cache.has_shard("file", 0).await);
+        assert_eq!(cache.get_shard("file", 0).await, Some(vec![1, 2, 3]));
+
+        // A fresh cache over the same directory still finds it
+        let reopened = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+        assert_eq!(reopened.get_shard("file", 0).await, Some(vec![1, 2, 3]));
+
+        reopened
+            .quarantine_shard("file", 0, 7, &[1, 2, 3])
+            .await
+            .unwrap();
+        assert!(!reopened.has_shard("file", 0).await);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_cache_never_touches_disk() {
+        let cache = Cache::in_memory(100, 10 * 1024 * 1024).unwrap();
+        assert!(!cache.is_persistent());
+        assert_eq!(cache.recover().await.unwrap(), RecoveryReport::default());
+        assert_eq!(cache.load_persisted_manifests().await.unwrap(), 0);
+
+        let manifest = FileManifest {
+            file_hash: "file".to_string(),
+            file_name: "file.bin".to_string(),
+            file_size: 3,
+            shard_count: 1,
+            timestamp: chrono::Utc::now().timestamp(),
+            shard_hashes: vec![format!("{:x}", Sha256::digest([1, 2, 3]))],
+            ..Default::default()
+        };
+        cache.put_manifest(manifest).await.unwrap();
+        cache.persist_shard("file", 0, vec![1, 2, 3]).await.unwrap();
+        cache.pin("file").await;
+        assert!(!cache.has_persisted_shard("file", 0));
+        assert_eq!(cache.get_shard("file", 0).await, Some(vec![1, 2, 3]));
+        assert!(cache.get_manifest("file").await.is_some());
+
+        assert!(cache.quarantine_dir().is_none());
+        let quarantined = cache.quarantine_shard("file", 0, 7, &[1, 2, 3]).await;
+        assert!(quarantined.unwrap().is_none());
+        assert!(!cache.has_shard("file", 0).await);
+        assert_eq!(cache.get_stats().await.shards_quarantined, 1);
+
+        assert!(cache.remove_manifest("file").await.unwrap());
+        cache.clear_manifests().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_fetches_of_a_shard_are_coalesced() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            Some(vec![1, 2, 3])
+        };
+
+        let (a, b, c) = tokio::join!(
+            cache.fetch_shard_once("file", 0, fetch),
+            cache.fetch_shard_once("file", 0, fetch),
+            cache.fetch_shard_once("file", 1, fetch),
+        );
+        assert_eq!(a, Some(vec![1, 2, 3]));
+        assert_eq!((&b, &c), (&a, &a));
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.get_stats().await.coalesced_fetches, 1);
+    }
+
+    #[tokio::test]
+    async fn test_recover_quarantines_torn_writes() {
+        let temp_dir = tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+        let shards = [vec![1u8, 2, 3], vec![4u8, 5, 6]];
+        let manifest = FileManifest {
+            file_hash: "file".to_string(),
+            file_name: "file.bin".to_string(),
+            file_size: 6,
+            shard_count: 2,
+            timestamp: chrono::Utc::now().timestamp(),
+            shard_hashes: shards
+                .iter()
+                .map(|data| format!("{:x}", Sha256::digest(data)))
+                .collect(),
+            ..Default::default()
+        };
+        cache.put_manifest(manifest).await.unwrap();
+        for (index, data) in shards.iter().enumerate() {
+            cache
+                .persist_shard("file", index, data.clone())
+                .await
+                .unwrap();
+        }
+        assert_eq!(cache.recover().await.unwrap(), RecoveryReport::default());
+
+        // Shard 1 torn by a crash, a manifest cut short, a write interrupted
+        let shard = cache.persisted_shard_path("file", 1).unwrap();
+        std::fs::write(&shard, [4u8]).unwrap();
+        let manifests = temp_dir.path().join("manifests");
+        std::fs::write(manifests.join("other.json"), b"{\"file_hash\": \"oth").unwrap();
+        std::fs::write(manifests.join("next.json.partial"), b"{").unwrap();
+
+        let reopened = Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap();
+        let report = reopened.recover().await.unwrap();
+        assert_eq!(
+            report,
+            RecoveryReport {
+                partial_writes_removed: 1,
+                manifests_quarantined: 1,
+                shards_quarantined: 1,
+            }
+        );
+        assert_eq!(reopened.load_persisted_manifests().await.unwrap(), 1);
+        assert_eq!(reopened.get_shard("file", 0).await, Some(vec![1, 2, 3]));
+        assert!(!reopened.has_shard("file", 1).await);
+        assert_eq!(
+            std::fs::read_dir(reopened.quarantine_dir().unwrap())
+                .unwrap()
+                .count(),
+            2
+        );
+    }
 }
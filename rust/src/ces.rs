@@ -1,18 +1,38 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chacha20poly1305::{
     aead::{Aead, KeyInit},
     XChaCha20Poly1305, XNonce,
 };
+use parking_lot::Mutex;
 use rand::RngCore;
 use rayon::prelude::*;
 use reed_solomon_erasure::ReedSolomon;
 use sha2::{Digest, Sha256};
 use std::io::{Read, Write};
-use tracing::{debug, info};
-
-use crate::file_detector::FileDetector;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+
+use crate::compression::{CompressionDecision, CompressionStats};
+use crate::envelope::{self, WrappedKey};
+use crate::file_detector::{CompressionPolicy, FileDetector, MIN_ENTROPY_SAMPLE_BYTES};
+use crate::kdf::KdfParams;
 use crate::types::{CesConfig, CompressionAlgorithm};
 
+/// Bytes of the little-endian ciphertext length leading the sharded data
+pub const LENGTH_PREFIX_BYTES: usize = 4;
+
+/// XChaCha20 nonce prepended plus Poly1305 tag appended by encryption
+pub const ENCRYPTION_OVERHEAD_BYTES: usize = 24 + 16;
+
+/// Magic leading the encrypted payload, followed by one byte of
+/// [`CompressionDecision`]; payloads from before decisions were recorded
+/// have no header and are always compressed
+const COMPRESSION_HEADER_MAGIC: &[u8; 3] = b"PCH";
+
+/// Bytes of the compression header inside the encrypted payload
+pub const COMPRESSION_HEADER_BYTES: usize = 4;
+
 // Brotli compression constants
 const BROTLI_BUFFER_SIZE: usize = 4096;
 const BROTLI_LG_WINDOW_SIZE: u32 = 22;
@@ -21,6 +41,10 @@ const BROTLI_LG_WINDOW_SIZE: u32 = 22;
 pub struct CesPipeline {
     config: CesConfig,
     encryption_key: [u8; 32],
+    key_derivation: Option<KdfParams>,
+    compression_policy: CompressionPolicy,
+    /// Shared with pipelines derived from this one
+    stats: Arc<Mutex<CompressionStats>>,
 }
 
 impl CesPipeline {
@@ -33,45 +57,137 @@ impl CesPipeline {
         Self {
             config,
             encryption_key,
+            key_derivation: None,
+            compression_policy: CompressionPolicy::default(),
+            stats: Arc::default(),
         }
     }
 
     /// Set the encryption key explicitly
     pub fn with_key(mut self, key: [u8; 32]) -> Self {
         self.encryption_key = key;
+        self.key_derivation = None;
+        self
+    }
+
+    /// Derive the encryption key from a passphrase
+    ///
+    /// The parameters are kept so uploads can record them in the manifest.
+    pub fn with_passphrase(mut self, passphrase: &[u8], params: KdfParams) -> Result<Self> {
+        self.encryption_key = params.derive_key(passphrase)?;
+        self.key_derivation = Some(params);
+        Ok(self)
+    }
+
+    /// Override per-type compression levels
+    pub fn with_compression_policy(mut self, policy: CompressionPolicy) -> Self {
+        self.compression_policy = policy;
         self
     }
 
+    /// Parameters the key was derived with, if it came from a passphrase
+    pub fn key_derivation(&self) -> Option<&KdfParams> {
+        self.key_derivation.as_ref()
+    }
+
+    /// Same configuration with a different encryption key
+    pub fn rekeyed(&self, key: [u8; 32]) -> Self {
+        Self {
+            config: self.config.clone(),
+            encryption_key: key,
+            key_derivation: None,
+            compression_policy: self.compression_policy.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Same key and settings with a different shard layout
+    pub fn resharded(&self, data_shards: usize, parity_shards: usize) -> Self {
+        Self {
+            config: CesConfig {
+                shard_count: data_shards,
+                parity_count: parity_shards,
+                ..self.config.clone()
+            },
+            encryption_key: self.encryption_key,
+            key_derivation: self.key_derivation.clone(),
+            compression_policy: self.compression_policy.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Compression decisions of this pipeline and those derived from it
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.stats.lock().clone()
+    }
+
+    /// Wrap the encryption key for each recipient's X25519 public key
+    pub fn wrap_key_for(&self, recipients: &[x25519_dalek::PublicKey]) -> Result<Vec<WrappedKey>> {
+        recipients
+            .iter()
+            .map(|recipient| envelope::wrap_key(&self.encryption_key, recipient))
+            .collect()
+    }
+
     /// Get the parity count from config
     pub fn parity_count(&self) -> usize {
         self.config.parity_count
     }
 
+    /// Get the data shard count from config
+    pub fn data_shard_count(&self) -> usize {
+        self.config.shard_count
+    }
+
     /// Process data through the CES pipeline
     pub fn process(&self, data: &[u8]) -> Result<Vec<Vec<u8>>> {
-        // Step 0: Detect file type (from content)
-        let file_type = FileDetector::detect_from_content(data);
-        debug!("Detected file type: {}", file_type.name());
-
-        // Step 1: Compress (skip if already compressed)
-        let compressed = if file_type.skip_compression() {
-            info!("Skipping compression for {} type", file_type.name());
-            data.to_vec()
+        self.process_at_level(data, self.recommended_level(data))
+    }
+
+    /// Compression level suggested by the content's file type (0 = skip)
+    pub fn recommended_level(&self, data: &[u8]) -> i32 {
+        self.recommended_level_for(None, data)
+    }
+
+    /// Compression level for a file, using its name as an extra type hint
+    pub fn recommended_level_for(&self, path: Option<&Path>, data: &[u8]) -> i32 {
+        let detection = FileDetector::detect_with_confidence(path, data);
+        debug!(
+            "Detected file type: {} ({}, {:?} confidence)",
+            detection.file_type.name(),
+            detection.format,
+            detection.confidence
+        );
+        let level = self.compression_policy.level_for(&detection);
+        if level == 0 {
+            info!("Skipping compression for {} content", detection.format);
         } else {
-            let level = file_type.recommended_compression_level();
-            debug!("Using compression level {} for {}", level, file_type.name());
-            self.compress_with_level(data, level)?
-        };
+            debug!("Using compression level {} for {}", level, detection.format);
+        }
+        level
+    }
 
-        if compressed.len() < data.len() {
+    /// Process data through the CES pipeline at an explicit compression level
+    #[instrument(skip_all, fields(bytes = data.len(), level = level))]
+    pub fn process_at_level(&self, data: &[u8], level: i32) -> Result<Vec<Vec<u8>>> {
+        // Step 1: Compress (unless skipped), after a header recording whether
+        let decision = self.compression_decision(data, level);
+        let mut header = COMPRESSION_HEADER_MAGIC.to_vec();
+        header.push(decision as u8);
+        let level = if decision.is_compressed() { level } else { 0 };
+        let compressed = self.compress_onto(header, data, level)?;
+        let body_len = compressed.len() - COMPRESSION_HEADER_BYTES;
+        self.stats.lock().record(decision, data.len(), body_len);
+
+        if body_len < data.len() {
             info!(
                 "Compressed {} bytes to {} bytes ({:.1}% reduction)",
                 data.len(),
-                compressed.len(),
-                (1.0 - compressed.len() as f64 / data.len() as f64) * 100.0
+                body_len,
+                (1.0 - body_len as f64 / data.len() as f64) * 100.0
             );
         } else {
-            info!("Data not compressed ({} bytes)", data.len());
+            info!("Data not compressed ({} bytes, {:?})", data.len(), decision);
         }
 
         // Step 2: Encrypt
@@ -80,7 +196,8 @@ impl CesPipeline {
 
         // Prepend encrypted length (4 bytes) to help with reconstruction
         let enc_len = encrypted.len() as u32;
-        let mut data_to_shard = enc_len.to_le_bytes().to_vec();
+        let mut data_to_shard = Vec::with_capacity(LENGTH_PREFIX_BYTES + encrypted.len());
+        data_to_shard.extend_from_slice(&enc_len.to_le_bytes());
         data_to_shard.extend_from_slice(&encrypted);
 
         // Step 3: Shard with Reed-Solomon
@@ -94,13 +211,14 @@ impl CesPipeline {
     }
 
     /// Reconstruct data from shards (reverse CES pipeline)
+    #[instrument(skip_all, fields(shards = shards.len()))]
     pub fn reconstruct(&self, shards: Vec<Option<Vec<u8>>>) -> Result<Vec<u8>> {
         // Step 1: Reconstruct from Reed-Solomon shards
         let reconstructed = self.reconstruct_shards(shards)?;
         info!("Reconstructed {} bytes from shards", reconstructed.len());
 
         // Extract encrypted length
-        if reconstructed.len() < 4 {
+        if reconstructed.len() < LENGTH_PREFIX_BYTES {
             anyhow::bail!("Reconstructed data too small");
         }
         let enc_len = u32::from_le_bytes([
@@ -111,10 +229,10 @@ impl CesPipeline {
         ]) as usize;
 
         // Extract encrypted data (trim RS padding)
-        if reconstructed.len() < 4 + enc_len {
+        if reconstructed.len() - LENGTH_PREFIX_BYTES < enc_len {
             anyhow::bail!("Reconstructed data smaller than expected encrypted length");
         }
-        let encrypted_data = &reconstructed[4..4 + enc_len];
+        let encrypted_data = &reconstructed[LENGTH_PREFIX_BYTES..LENGTH_PREFIX_BYTES + enc_len];
 
         // Step 2: Decrypt
         let decrypted = self.decrypt(encrypted_data)?;
@@ -127,46 +245,85 @@ impl CesPipeline {
         Ok(decompressed)
     }
 
+    /// Whether to compress `data` at `level`
+    ///
+    /// Data that looks random is not, whatever its detected type: a sample
+    /// above the policy's entropy limit would barely shrink.
+    fn compression_decision(&self, data: &[u8], level: i32) -> CompressionDecision {
+        if level <= 0 {
+            return CompressionDecision::SkippedByType;
+        }
+        if data.len() >= MIN_ENTROPY_SAMPLE_BYTES {
+            let entropy = FileDetector::sample_entropy(data);
+            if entropy > self.compression_policy.max_entropy_bits {
+                info!(
+                    "Skipping compression: sampled entropy {:.2} bits/byte",
+                    entropy
+                );
+                return CompressionDecision::SkippedByEntropy;
+            }
+        }
+        CompressionDecision::Compressed
+    }
+
     /// Compress data using zstd
     #[allow(dead_code)]
     fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
-        self.compress_with_level(data, self.config.compression_level)
+        self.compress_onto(Vec::new(), data, self.config.compression_level)
     }
 
-    /// Compress data with specific level
-    fn compress_with_level(&self, data: &[u8], level: i32) -> Result<Vec<u8>> {
+    /// Append `data` compressed at `level` to `out`
+    fn compress_onto(&self, mut out: Vec<u8>, data: &[u8], level: i32) -> Result<Vec<u8>> {
         if level == 0 {
             // No compression
-            return Ok(data.to_vec());
+            out.extend_from_slice(data);
+            return Ok(out);
         }
 
         match self.config.compression_algorithm {
             CompressionAlgorithm::Zstd => {
-                let mut compressed = Vec::new();
-                let mut encoder = zstd::Encoder::new(&mut compressed, level)?;
+                let mut encoder = zstd::Encoder::new(&mut out, level)?;
                 encoder.write_all(data)?;
                 encoder.finish()?;
-                Ok(compressed)
+                Ok(out)
             }
             CompressionAlgorithm::Brotli => {
                 // Brotli quality range: 0-11, map from our 1-22 range
                 let quality = level.clamp(0, 11) as u32;
-                let mut compressed = Vec::new();
                 let mut compressor = brotli::CompressorReader::new(
                     data,
                     BROTLI_BUFFER_SIZE,
                     quality,
                     BROTLI_LG_WINDOW_SIZE,
                 );
-                compressor.read_to_end(&mut compressed)?;
-                Ok(compressed)
+                compressor.read_to_end(&mut out)?;
+                Ok(out)
+            }
+            CompressionAlgorithm::None => {
+                out.extend_from_slice(data);
+                Ok(out)
             }
-            CompressionAlgorithm::None => Ok(data.to_vec()),
         }
     }
 
-    /// Decompress data using configured algorithm
+    /// Undo compression, if the payload's header says it was applied
     fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match data.strip_prefix(COMPRESSION_HEADER_MAGIC.as_slice()) {
+            Some([byte, body @ ..]) => {
+                let decision = CompressionDecision::from_byte(*byte)
+                    .with_context(|| format!("Unknown compression decision {}", byte))?;
+                if decision.is_compressed() {
+                    self.decode(body)
+                } else {
+                    Ok(body.to_vec())
+                }
+            }
+            _ => self.decode(data),
+        }
+    }
+
+    /// Decompress data using configured algorithm
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
         match self.config.compression_algorithm {
             CompressionAlgorithm::Zstd => {
                 let mut decompressed = Vec::new();
@@ -290,6 +447,35 @@ impl CesPipeline {
         Ok(result)
     }
 
+    /// Rebuild missing shards with Reed-Solomon alone
+    ///
+    /// Unlike `reconstruct` this needs no key and returns shards identical to
+    /// the lost ones, so hashes recorded in the manifest stay valid.
+    pub fn repair_shards(
+        data_shards: usize,
+        parity_shards: usize,
+        mut shards: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Vec<u8>>> {
+        let rs =
+            ReedSolomon::<reed_solomon_erasure::galois_8::Field>::new(data_shards, parity_shards)?;
+        rs.reconstruct(&mut shards)?;
+        Ok(shards.into_iter().flatten().collect())
+    }
+
+    /// Reed-Solomon parity for equally sized shards
+    ///
+    /// Used for the local parity of two-tier layouts (see `placement`); the
+    /// result repairs with `repair_shards(data.len(), parity_shards, ..)`.
+    pub fn encode_parity(data: &[Vec<u8>], parity_shards: usize) -> Result<Vec<Vec<u8>>> {
+        let shard_size = data.first().map_or(0, Vec::len);
+        let mut shards = data.to_vec();
+        shards.resize(data.len() + parity_shards, vec![0u8; shard_size]);
+        let rs =
+            ReedSolomon::<reed_solomon_erasure::galois_8::Field>::new(data.len(), parity_shards)?;
+        rs.encode(&mut shards)?;
+        Ok(shards.split_off(data.len()))
+    }
+
     /// Calculate SHA256 hash of data
     pub fn hash(data: &[u8]) -> Vec<u8> {
         let mut hasher = Sha256::new();
@@ -384,4 +570,85 @@ mod tests {
         let reconstructed = pipeline.reconstruct(recovery_shards).unwrap();
         assert_eq!(data.to_vec(), reconstructed);
     }
+
+    /// Decision recorded in the header of processed shards
+    fn recorded_decision(pipeline: &CesPipeline, shards: &[Vec<u8>]) -> CompressionDecision {
+        let data = pipeline
+            .reconstruct_shards(shards.iter().cloned().map(Some).collect())
+            .unwrap();
+        let len = u32::from_le_bytes(data[..LENGTH_PREFIX_BYTES].try_into().unwrap()) as usize;
+        let payload = pipeline
+            .decrypt(&data[LENGTH_PREFIX_BYTES..LENGTH_PREFIX_BYTES + len])
+            .unwrap();
+        assert_eq!(&payload[..3], COMPRESSION_HEADER_MAGIC);
+        CompressionDecision::from_byte(payload[3]).unwrap()
+    }
+
+    #[test]
+    fn test_high_entropy_data_skips_compression() {
+        let pipeline = CesPipeline::new(CesConfig::default()).with_key([1u8; 32]);
+        let mut random = vec![0u8; 256 * 1024];
+        rand::thread_rng().fill_bytes(&mut random);
+        // No archive or video signature by chance
+        random[0] = 0;
+        let text = b"compressible text ".repeat(1000);
+
+        // Unknown content would get the default level; the sample vetoes it
+        let shards = pipeline.process(&random).unwrap();
+        assert_eq!(
+            recorded_decision(&pipeline, &shards),
+            CompressionDecision::SkippedByEntropy
+        );
+        let restored = pipeline
+            .reconstruct(shards.into_iter().map(Some).collect())
+            .unwrap();
+        assert_eq!(restored, random);
+
+        let shards = pipeline.process(&text).unwrap();
+        assert_eq!(
+            recorded_decision(&pipeline, &shards),
+            CompressionDecision::Compressed
+        );
+        let shards = pipeline.process_at_level(&text, 0).unwrap();
+        assert_eq!(
+            recorded_decision(&pipeline, &shards),
+            CompressionDecision::SkippedByType
+        );
+        let restored = pipeline
+            .reconstruct(shards.into_iter().map(Some).collect())
+            .unwrap();
+        assert_eq!(restored, text);
+
+        // Derived pipelines count into the same stats
+        pipeline.resharded(2, 1).process(&random).unwrap();
+        let stats = pipeline.compression_stats();
+        assert_eq!(stats.compressed, 1);
+        assert_eq!(stats.skipped_by_type, 1);
+        assert_eq!(stats.skipped_by_entropy, 2);
+        assert_eq!(stats.bytes_in, text.len() as u64);
+        assert!(stats.bytes_out < stats.bytes_in);
+    }
+
+    #[test]
+    fn test_passphrase_pipelines_interoperate() {
+        let params = crate::kdf::KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            ..crate::kdf::KdfParams::generate()
+        };
+        let sender = CesPipeline::new(CesConfig::default())
+            .with_passphrase(b"hunter2", params.clone())
+            .unwrap();
+        let receiver = CesPipeline::new(CesConfig::default())
+            .with_passphrase(b"hunter2", params.clone())
+            .unwrap();
+        assert_eq!(sender.key_derivation(), Some(&params));
+
+        let data = b"passphrase protected".repeat(20);
+        let shards = sender.process(&data).unwrap();
+        let reconstructed = receiver
+            .reconstruct(shards.into_iter().map(Some).collect())
+            .unwrap();
+        assert_eq!(data.to_vec(), reconstructed);
+    }
 }
@@ -0,0 +1,242 @@
+/// Fault injection for exercising auto-heal and retry paths
+///
+/// `ChaosTransport` wraps any [`ShardTransport`] and, on demand, treats peers
+/// as dead, drops a fraction of shard requests and flips bytes in shards
+/// from chosen peers. Together with `NodeStore::chaos_kill_peer` it lets
+/// tests take peers out of a running swarm and check that the
+/// [`AutoHealer`](crate::auto_heal::AutoHealer) restores redundancy.
+///
+/// Only compiled with the `chaos` feature; never enable it in production.
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::error::NetworkError;
+use crate::transport::{ShardTransport, TransportKind};
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+/// Faults currently injected
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    /// Peers that refuse every request
+    pub dead_peers: HashSet<u32>,
+    /// Fraction of requests (0.0..=1.0) dropped as timeouts
+    pub drop_rate: f64,
+    /// Peers whose shards have one byte flipped in transit
+    pub corrupt_peers: HashSet<u32>,
+    /// Fraction of all other shards corrupted in transit
+    pub corrupt_rate: f64,
+}
+
+/// Faults injected so far
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaosStats {
+    pub refused: u64,
+    pub dropped: u64,
+    pub corrupted: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    refused: AtomicU64,
+    dropped: AtomicU64,
+    corrupted: AtomicU64,
+}
+
+/// Transport wrapper that injects peer death, request loss and corruption
+pub struct ChaosTransport {
+    inner: Arc<dyn ShardTransport>,
+    config: Mutex<ChaosConfig>,
+    rng: Mutex<StdRng>,
+    counters: Counters,
+}
+
+impl ChaosTransport {
+    /// Pass-through wrapper; faults are added with the setters below
+    pub fn new(inner: Arc<dyn ShardTransport>) -> Self {
+        Self {
+            inner,
+            config: Mutex::new(ChaosConfig::default()),
+            rng: Mutex::new(StdRng::from_entropy()),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Make the injected faults reproducible
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock() = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn with_config(self, config: ChaosConfig) -> Self {
+        *self.config.lock() = config;
+        self
+    }
+
+    pub fn config(&self) -> ChaosConfig {
+        self.config.lock().clone()
+    }
+
+    pub fn kill_peer(&self, peer_id: u32) {
+        self.config.lock().dead_peers.insert(peer_id);
+    }
+
+    pub fn revive_peer(&self, peer_id: u32) {
+        self.config.lock().dead_peers.remove(&peer_id);
+    }
+
+    pub fn set_drop_rate(&self, rate: f64) {
+        self.config.lock().drop_rate = rate.clamp(0.0, 1.0);
+    }
+
+    pub fn corrupt_peer(&self, peer_id: u32) {
+        self.config.lock().corrupt_peers.insert(peer_id);
+    }
+
+    pub fn set_corrupt_rate(&self, rate: f64) {
+        self.config.lock().corrupt_rate = rate.clamp(0.0, 1.0);
+    }
+
+    /// Remove every injected fault
+    pub fn calm(&self) {
+        *self.config.lock() = ChaosConfig::default();
+    }
+
+    pub fn stats(&self) -> ChaosStats {
+        ChaosStats {
+            refused: self.counters.refused.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            corrupted: self.counters.corrupted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Refuse or drop the request before it reaches the inner transport
+    fn intercept(&self, peer_id: u32, what: &str) -> Result<()> {
+        let config = self.config.lock();
+        if config.dead_peers.contains(&peer_id) {
+            self.counters.refused.fetch_add(1, Ordering::Relaxed);
+            return Err(NetworkError::PeerUnreachable(peer_id));
+        }
+        if config.drop_rate > 0.0 && self.rng.lock().gen_bool(config.drop_rate) {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            debug!("Chaos: dropped {} for peer {}", what, peer_id);
+            return Err(NetworkError::Timeout(format!(
+                "chaos: dropped {} for peer {}",
+                what, peer_id
+            )));
+        }
+        Ok(())
+    }
+
+    fn maybe_corrupt(&self, peer_id: u32, shard: &mut [u8]) {
+        if shard.is_empty() {
+            return;
+        }
+        let config = self.config.lock();
+        let mut rng = self.rng.lock();
+        let corrupt = config.corrupt_peers.contains(&peer_id)
+            || (config.corrupt_rate > 0.0 && rng.gen_bool(config.corrupt_rate));
+        if corrupt {
+            let at = rng.gen_range(0..shard.len());
+            shard[at] ^= rng.gen_range(1..=u8::MAX);
+            self.counters.corrupted.fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Chaos: corrupted byte {} of shard from peer {}",
+                at, peer_id
+            );
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ShardTransport for ChaosTransport {
+    fn kind(&self) -> TransportKind {
+        self.inner.kind()
+    }
+
+    async fn is_available(&self, peer_id: u32) -> bool {
+        !self.config.lock().dead_peers.contains(&peer_id) && self.inner.is_available(peer_id).await
+    }
+
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        mut shard: Vec<u8>,
+    ) -> Result<bool> {
+        self.intercept(peer_id, "send")?;
+        self.maybe_corrupt(peer_id, &mut shard);
+        self.inner
+            .send_shard(peer_id, file_hash, shard_index, shard)
+            .await
+    }
+
+    async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Vec<u8>> {
+        self.intercept(peer_id, "fetch")?;
+        let mut shard = self
+            .inner
+            .fetch_shard(peer_id, file_hash, shard_index)
+            .await?;
+        self.maybe_corrupt(peer_id, &mut shard);
+        Ok(shard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SimulatedNetwork;
+
+    #[tokio::test]
+    async fn test_faults_are_injected_and_cleared() {
+        let network = Arc::new(SimulatedNetwork::new(Default::default()).with_peers([1, 2]));
+        let chaos = ChaosTransport::new(network).with_seed(7);
+        chaos.send_shard(1, "file", 0, vec![1, 2, 3]).await.unwrap();
+
+        chaos.kill_peer(1);
+        assert!(matches!(
+            chaos.fetch_shard(1, "file", 0).await,
+            Err(NetworkError::PeerUnreachable(1))
+        ));
+
+        chaos.revive_peer(1);
+        chaos.corrupt_peer(1);
+        assert_ne!(
+            chaos.fetch_shard(1, "file", 0).await.unwrap(),
+            vec![1, 2, 3]
+        );
+
+        chaos.set_drop_rate(1.0);
+        assert!(matches!(
+            chaos.fetch_shard(2, "file", 0).await,
+            Err(NetworkError::Timeout(_))
+        ));
+
+        chaos.calm();
+        assert_eq!(
+            chaos.fetch_shard(1, "file", 0).await.unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            chaos.stats(),
+            ChaosStats {
+                refused: 1,
+                dropped: 1,
+                corrupted: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,206 @@
+/// Per-file shard layout selection
+///
+/// `CesConfig` fixes the number of data and parity shards for every file, so
+/// a 10 KB note is cut into slivers a few KB long while a multi-GB video is
+/// split into the same handful of enormous shards. The `ChunkingPolicy`
+/// picks a layout per upload from the file size, the number of peers that
+/// will hold it and their measured throughput:
+///
+/// - shards aim to take `target_shard_secs` to send to a typical peer
+/// - every peer gets a shard when the file is big enough
+/// - shards never drop below `min_shard_bytes` or (while the shard count
+///   allows) grow past `max_shard_bytes`
+///
+/// The chosen layout is recorded in the manifest, so downloads reconstruct
+/// with the same shard counts regardless of the node's own `CesConfig`.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::cache::MAX_MANIFEST_SHARDS;
+
+/// Data and parity shard counts chosen for one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardLayout {
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    /// Approximate size of each shard (before compression and encryption)
+    pub shard_bytes: u64,
+}
+
+impl ShardLayout {
+    pub fn total_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+}
+
+/// Limits for per-file layouts (`[chunking]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkingPolicy {
+    /// Pick layouts per file; when off the pipeline's static config is used
+    pub enabled: bool,
+    /// Smallest shard worth a separate transfer
+    pub min_shard_bytes: u64,
+    /// Largest shard, unless the file needs more than `max_data_shards`
+    pub max_shard_bytes: u64,
+    /// Shard size used before any peer throughput has been measured
+    pub default_shard_bytes: u64,
+    /// Seconds one shard should take to send at measured peer throughput
+    pub target_shard_secs: f64,
+    /// Most data shards per file
+    pub max_data_shards: usize,
+    /// Parity shards per data shard
+    pub parity_ratio: f64,
+    /// Fewest parity shards per file
+    pub min_parity_shards: usize,
+}
+
+impl Default for ChunkingPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_shard_bytes: 64 * 1024,
+            max_shard_bytes: 64 * 1024 * 1024,
+            default_shard_bytes: 4 * 1024 * 1024,
+            target_shard_secs: 4.0,
+            max_data_shards: 128,
+            parity_ratio: 0.5,
+            min_parity_shards: 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    chunking: ChunkingPolicy,
+}
+
+impl ChunkingPolicy {
+    /// Load the `[chunking]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[chunking]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.chunking.validate()?;
+        Ok(file.chunking)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.min_shard_bytes == 0 || self.min_shard_bytes > self.max_shard_bytes {
+            anyhow::bail!(
+                "chunking: need 0 < min_shard_bytes ({}) <= max_shard_bytes ({})",
+                self.min_shard_bytes,
+                self.max_shard_bytes
+            );
+        }
+        let secs_ok = self.target_shard_secs.is_finite() && self.target_shard_secs > 0.0;
+        let ratio_ok = self.parity_ratio.is_finite() && self.parity_ratio >= 0.0;
+        if !secs_ok || !ratio_ok {
+            anyhow::bail!(
+                "chunking: target_shard_secs must be positive and parity_ratio non-negative"
+            );
+        }
+        if self.min_parity_shards == 0 {
+            anyhow::bail!("chunking: min_parity_shards must be at least 1");
+        }
+        if self.max_data_shards == 0
+            || self.max_data_shards + self.min_parity_shards > MAX_MANIFEST_SHARDS
+        {
+            anyhow::bail!(
+                "chunking: max_data_shards must be 1..={}",
+                MAX_MANIFEST_SHARDS - self.min_parity_shards
+            );
+        }
+        Ok(())
+    }
+
+    /// Layout for a `file_size`-byte file spread over `peers` peers
+    ///
+    /// `peer_throughput` is the typical bytes/second those peers accept
+    /// shards at, if it has been measured.
+    pub fn plan(&self, file_size: u64, peers: usize, peer_throughput: Option<f64>) -> ShardLayout {
+        let target = peer_throughput
+            .filter(|bps| bps.is_finite() && *bps > 0.0)
+            .map_or(self.default_shard_bytes, |bps| {
+                (bps * self.target_shard_secs) as u64
+            })
+            .clamp(self.min_shard_bytes, self.max_shard_bytes);
+
+        let mut data_shards = file_size.div_ceil(target) as usize;
+        // Give every peer a shard...
+        let for_peers = (peers as f64 / (1.0 + self.parity_ratio)).ceil() as usize;
+        data_shards = data_shards.max(for_peers);
+        // ...unless that would make shards too small to be worth sending
+        let by_min_size = (file_size / self.min_shard_bytes).max(1) as usize;
+        data_shards = data_shards.min(by_min_size);
+        // Oversized shards are worse than extra transfers
+        data_shards = data_shards.max(file_size.div_ceil(self.max_shard_bytes) as usize);
+        data_shards = data_shards.clamp(1, self.max_data_shards);
+
+        let parity_shards = ((data_shards as f64 * self.parity_ratio).ceil() as usize)
+            .max(self.min_parity_shards)
+            .min(MAX_MANIFEST_SHARDS - data_shards);
+
+        ShardLayout {
+            data_shards,
+            parity_shards,
+            shard_bytes: file_size.div_ceil(data_shards as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_small_files_keep_shards_above_minimum() {
+        let policy = ChunkingPolicy::default();
+        let layout = policy.plan(10 * 1024, 20, None);
+        assert_eq!(layout.data_shards, 1);
+        assert_eq!(layout.parity_shards, policy.min_parity_shards);
+
+        // 20 peers would like 14 data shards, but 256 KiB only makes 4
+        let layout = policy.plan(256 * 1024, 20, None);
+        assert_eq!(layout.data_shards, 4);
+        assert!(layout.shard_bytes >= policy.min_shard_bytes);
+    }
+
+    #[test]
+    fn test_peer_count_and_throughput_shape_layout() {
+        let policy = ChunkingPolicy::default();
+
+        // Enough data to give each of 12 peers a shard
+        let layout = policy.plan(12 * MIB, 12, None);
+        assert_eq!(layout.data_shards, 8);
+        assert_eq!(layout.total_shards(), 12);
+
+        // Slow peers get smaller shards than fast ones
+        let slow = policy.plan(256 * MIB, 3, Some(256.0 * 1024.0));
+        let fast = policy.plan(256 * MIB, 3, Some(100.0 * MIB as f64));
+        assert!(slow.data_shards > fast.data_shards);
+        assert_eq!(fast.shard_bytes, 64 * MIB);
+    }
+
+    #[test]
+    fn test_huge_files_are_capped() {
+        let policy = ChunkingPolicy::default();
+        let layout = policy.plan(100 * 1024 * MIB, 4, Some(1e9));
+        assert_eq!(layout.data_shards, policy.max_data_shards);
+        assert!(layout.total_shards() <= MAX_MANIFEST_SHARDS);
+
+        assert!(ChunkingPolicy::from_toml("[chunking]\nmin_shard_bytes = 0\n").is_err());
+        let policy = ChunkingPolicy::from_toml("[chunking]\nenabled = false\n").unwrap();
+        assert!(!policy.enabled);
+        assert_eq!(policy.max_data_shards, 128);
+    }
+}
@@ -0,0 +1,337 @@
+/// Clock skew between this node and its peers
+///
+/// Leases, TTLs and signature freshness compare timestamps one node stamped
+/// against another node's clock. When a node connects to a peer it asks for
+/// the peer's wall clock with a `PCLK` request. The round trip is timed on
+/// the monotonic clock, and the peer reports how long it held the request
+/// the same way, so a wall clock stepping mid-exchange cannot distort the
+/// estimate. Per peer, the offset of the quickest of the last few exchanges
+/// is kept: as in NTP, a short round trip leaves little room for
+/// asymmetric delays.
+///
+/// Remote timestamps are mapped onto the local clock with that offset, and
+/// expiry checks allow `tolerance_secs` more for error in the estimate (or
+/// for peers never measured), so nothing expires early because a peer's
+/// clock runs behind ours.
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::network::{RequestHandler, RequestTag};
+
+/// Leading bytes of a clock request
+pub const CLOCK_TAG: RequestTag = *b"PCLK";
+
+/// Largest clock response
+pub const MAX_CLOCK_RESPONSE_BYTES: usize = 64;
+
+/// Skew settings (`[clock]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    /// Seconds a remote timestamp may be off, after correcting for the
+    /// measured offset, before anything it stamped counts as expired
+    pub tolerance_secs: u64,
+    /// Measured offsets are capped at this, so a peer lying about its
+    /// clock cannot stretch expiry checks arbitrarily
+    pub max_offset_secs: u64,
+    /// Exchanges remembered per peer
+    pub samples: usize,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            tolerance_secs: 30,
+            max_offset_secs: 3600,
+            samples: 8,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    clock: ClockConfig,
+}
+
+impl ClockConfig {
+    /// Load the `[clock]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[clock]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.clock.validate()?;
+        Ok(file.clock)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.samples == 0 {
+            anyhow::bail!("clock: samples must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// A peer's answer to a clock request
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ClockReading {
+    /// Peer wall clock when the request arrived, unix milliseconds
+    wall_ms: i64,
+    /// Microseconds the peer held the request before answering
+    held_us: u64,
+}
+
+/// One clock exchange with a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSample {
+    /// Peer clock minus local clock, in milliseconds
+    pub offset_ms: i64,
+    /// Round trip less the time the peer held the request
+    pub delay_ms: u64,
+}
+
+impl ClockSample {
+    /// Estimate from a request sent at local `sent_at_ms` and answered after
+    /// `round_trip` (both sides timed on their monotonic clocks)
+    pub fn from_exchange(
+        sent_at_ms: i64,
+        round_trip: Duration,
+        remote_ms: i64,
+        held: Duration,
+    ) -> Self {
+        let delay = round_trip.saturating_sub(held);
+        // The peer read its clock halfway through the network delay
+        let arrived_at_ms = sent_at_ms + (delay.as_millis() / 2) as i64;
+        Self {
+            offset_ms: remote_ms - arrived_at_ms,
+            delay_ms: delay.as_millis() as u64,
+        }
+    }
+}
+
+/// Per-peer clock offsets
+pub struct PeerClocks {
+    config: ClockConfig,
+    peers: Mutex<HashMap<u32, VecDeque<ClockSample>>>,
+}
+
+impl Default for PeerClocks {
+    fn default() -> Self {
+        Self::new(ClockConfig::default())
+    }
+}
+
+impl PeerClocks {
+    pub fn new(config: ClockConfig) -> Self {
+        Self {
+            config,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &ClockConfig {
+        &self.config
+    }
+
+    /// Remember an exchange with `peer_id`
+    pub fn record(&self, peer_id: u32, sample: ClockSample) {
+        {
+            let mut peers = self.peers.lock();
+            let samples = peers.entry(peer_id).or_default();
+            if samples.len() >= self.config.samples {
+                samples.pop_front();
+            }
+            samples.push_back(sample);
+        }
+        let tolerance_ms = self.config.tolerance_secs.saturating_mul(1000);
+        if sample.offset_ms.unsigned_abs() > tolerance_ms {
+            warn!(
+                "Clock of peer {} is {} ms off ours (tolerance {} s)",
+                peer_id, sample.offset_ms, self.config.tolerance_secs
+            );
+        } else {
+            debug!(
+                "Clock of peer {}: offset {} ms, delay {} ms",
+                peer_id, sample.offset_ms, sample.delay_ms
+            );
+        }
+    }
+
+    /// Time an exchange answered with `response` and remember it
+    pub fn record_exchange(
+        &self,
+        peer_id: u32,
+        sent_at_ms: i64,
+        round_trip: Duration,
+        response: &[u8],
+    ) -> Result<ClockSample> {
+        let reading: ClockReading =
+            bincode::deserialize(response).context("Malformed clock response")?;
+        let sample = ClockSample::from_exchange(
+            sent_at_ms,
+            round_trip,
+            reading.wall_ms,
+            Duration::from_micros(reading.held_us),
+        );
+        self.record(peer_id, sample);
+        Ok(sample)
+    }
+
+    /// Estimated offset of `peer_id`'s clock from ours in milliseconds,
+    /// capped at `max_offset_secs`; `None` if never measured
+    pub fn offset_ms(&self, peer_id: u32) -> Option<i64> {
+        let max_ms = self.config.max_offset_secs.saturating_mul(1000) as i64;
+        self.peers
+            .lock()
+            .get(&peer_id)?
+            .iter()
+            .min_by_key(|sample| sample.delay_ms)
+            .map(|sample| sample.offset_ms.clamp(-max_ms, max_ms))
+    }
+
+    /// Whole seconds `peer_id`'s clock is ahead of ours (0 if unknown)
+    pub fn offset_secs(&self, peer_id: u32) -> i64 {
+        self.offset_ms(peer_id).map_or(0, |ms| ms / 1000)
+    }
+
+    /// A unix timestamp stamped by `peer_id`, on the local clock
+    pub fn to_local(&self, peer_id: u32, remote_secs: i64) -> i64 {
+        remote_secs.saturating_sub(self.offset_secs(peer_id))
+    }
+
+    /// What `peer_id`'s clock reads when ours reads `now`
+    pub fn peer_now(&self, peer_id: u32, now: u64) -> u64 {
+        now.saturating_add_signed(self.offset_secs(peer_id))
+    }
+
+    /// Extra time allowed on every expiry check
+    pub fn tolerance(&self) -> Duration {
+        Duration::from_secs(self.config.tolerance_secs)
+    }
+
+    /// Whether something `peer_id` stamped at `stamped_at` with a lifetime
+    /// of `ttl_secs` has expired at local time `now`
+    pub fn is_expired(&self, peer_id: u32, stamped_at: i64, ttl_secs: u64, now: i64) -> bool {
+        let lifetime = ttl_secs.saturating_add(self.config.tolerance_secs);
+        now.saturating_sub(self.to_local(peer_id, stamped_at)) > lifetime as i64
+    }
+
+    /// Measured peers and their current offsets in milliseconds
+    pub fn offsets(&self) -> Vec<(u32, i64)> {
+        let mut peers: Vec<u32> = self.peers.lock().keys().copied().collect();
+        peers.sort_unstable();
+        peers
+            .into_iter()
+            .filter_map(|peer_id| Some((peer_id, self.offset_ms(peer_id)?)))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RequestHandler for PeerClocks {
+    async fn handle(&self, _remote: SocketAddr, _request: Vec<u8>) -> Vec<u8> {
+        let arrived = Instant::now();
+        let wall_ms = chrono::Utc::now().timestamp_millis();
+        let reading = ClockReading {
+            wall_ms,
+            held_us: arrived.elapsed().as_micros() as u64,
+        };
+        bincode::serialize(&reading).unwrap_or_default()
+    }
+
+    fn is_replay_safe(&self, _request: &[u8]) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_comes_from_quickest_exchange() {
+        let clocks = PeerClocks::default();
+        assert_eq!(clocks.offset_ms(7), None);
+
+        // Peer 90 s ahead; the slow exchange's asymmetric delay skews it
+        let quick = ClockSample::from_exchange(
+            1_000_000,
+            Duration::from_millis(40),
+            1_090_020,
+            Duration::ZERO,
+        );
+        assert_eq!(
+            quick,
+            ClockSample {
+                offset_ms: 90_000,
+                delay_ms: 40
+            }
+        );
+        let slow = ClockSample::from_exchange(
+            2_000_000,
+            Duration::from_millis(900),
+            2_090_050,
+            Duration::from_millis(100),
+        );
+        assert_eq!(slow.delay_ms, 800);
+        clocks.record(7, slow);
+        clocks.record(7, quick);
+        assert_eq!(clocks.offset_ms(7), Some(90_000));
+        assert_eq!(clocks.offsets(), vec![(7, 90_000)]);
+
+        // Lying peers are capped
+        clocks.record(
+            8,
+            ClockSample {
+                offset_ms: -1_000_000_000,
+                delay_ms: 1,
+            },
+        );
+        assert_eq!(clocks.offset_secs(8), -3600);
+    }
+
+    #[test]
+    fn test_expiry_accounts_for_skew_and_tolerance() {
+        let clocks = PeerClocks::new(ClockConfig {
+            tolerance_secs: 10,
+            ..ClockConfig::default()
+        });
+        // Peer 2's clock is 5 minutes behind: its fresh stamps look old
+        clocks.record(
+            2,
+            ClockSample {
+                offset_ms: -300_000,
+                delay_ms: 20,
+            },
+        );
+        let now = 1_700_000_000;
+        let stamped = now - 300 - 50;
+        assert!(!clocks.is_expired(2, stamped, 60, now));
+        assert!(clocks.is_expired(2, stamped, 30, now));
+        assert_eq!(clocks.peer_now(2, now as u64), now as u64 - 300);
+
+        // Unmeasured peers get the tolerance only
+        assert!(!clocks.is_expired(3, now - 65, 60, now));
+        assert!(clocks.is_expired(3, now - 71, 60, now));
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = ClockConfig::from_toml("[clock]\ntolerance_secs = 5\n").unwrap();
+        assert_eq!(config.tolerance_secs, 5);
+        assert_eq!(config.samples, 8);
+        assert!(ClockConfig::from_toml("[clock]\nsamples = 0\n").is_err());
+    }
+}
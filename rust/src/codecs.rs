@@ -8,8 +8,15 @@
 /// - High audio quality
 /// - Adaptable bitrate
 /// - Loss tolerance for lossy networks
+///
+/// A `Transcoder` turns one incoming stream into a ladder of renditions
+/// (decode → resample/remix → re-encode), so the DCDN HLS gateway can offer
+/// several bitrates of the same stream (`StreamManifest::hls_master_playlist`).
+use crate::dcdn::CodecInfo;
 use anyhow::Result;
 use opus::{Application, Channels, Decoder as OpusDecoder, Encoder as OpusEncoder};
+use serde::Serialize;
+use std::time::Instant;
 use tracing::{debug, info};
 
 /// Audio codec configuration for real-time communication
@@ -125,6 +132,17 @@ impl AudioEncoder {
         self.config.bitrate = bitrate;
         Ok(())
     }
+
+    /// Turn in-band FEC on or off, tuned for the expected packet loss
+    ///
+    /// With FEC each packet also carries a low-bitrate copy of the previous
+    /// frame, so a single lost packet can be rebuilt from the next one.
+    pub fn set_fec(&mut self, enabled: bool, expected_loss_percent: u8) -> Result<()> {
+        self.encoder.set_inband_fec(enabled)?;
+        self.encoder
+            .set_packet_loss_perc(i32::from(expected_loss_percent.min(100)))?;
+        Ok(())
+    }
 }
 
 /// Opus audio decoder for low-latency streaming
@@ -154,8 +172,9 @@ impl AudioDecoder {
         let frame_size = self.config.frame_size();
         let mut output = vec![0i16; frame_size * self.config.channels as usize];
 
+        // Opus reports samples per channel; the output is interleaved
         let len = self.decoder.decode(opus_packet, &mut output, false)?;
-        output.truncate(len);
+        output.truncate(len * self.config.channels as usize);
 
         debug!("Decoded {} bytes to {} PCM samples", opus_packet.len(), len);
         Ok(output)
@@ -170,7 +189,7 @@ impl AudioDecoder {
 
         // Decode without a packet (FEC/PLC mode)
         let len = self.decoder.decode(&[], &mut output, true)?;
-        output.truncate(len);
+        output.truncate(len * self.config.channels as usize);
 
         debug!("Generated {} PLC samples for lost packet", len);
         Ok(output)
@@ -200,6 +219,8 @@ impl AudioDecoder {
 /// 2. Create VideoEncoder and VideoDecoder similar to AudioEncoder
 /// 3. Configure for low-latency mode (tune for realtime)
 /// 4. Integrate with streaming pipeline
+/// 5. Feed video renditions through `Transcoder` alongside audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct VideoConfig {
     pub width: u32,
     pub height: u32,
@@ -207,6 +228,312 @@ pub struct VideoConfig {
     pub bitrate: u32,
 }
 
+/// Resolutions and bitrates (kbps) of the standard video ladder
+const VIDEO_LADDER: [(u32, u32); 4] = [(1080, 5000), (720, 3000), (480, 1200), (360, 700)];
+
+impl VideoConfig {
+    /// Rungs of the video ladder a source of this size can fill
+    ///
+    /// Never upscales: the source itself is the top rung, followed by each
+    /// standard height below it with the aspect ratio kept. Used to plan
+    /// playlists now; rescaling waits on the video codec.
+    pub fn ladder(&self) -> Vec<VideoConfig> {
+        let mut rungs = vec![*self];
+        for (height, kbps) in VIDEO_LADDER {
+            if height >= self.height {
+                continue;
+            }
+            // Encoders want even dimensions
+            let width = (self.width as u64 * height as u64 / self.height as u64) as u32 & !1;
+            rungs.push(VideoConfig {
+                width,
+                height,
+                framerate: self.framerate.min(30),
+                bitrate: (kbps * 1000).min(self.bitrate),
+            });
+        }
+        rungs
+    }
+}
+
+/// Linear-interpolation resampler for interleaved 16-bit PCM
+///
+/// Keeps the tail of each call's input so consecutive frames join without
+/// clicks. Not band-limited, which is acceptable for the ladder's rungs since
+/// Opus low-passes each rung to its bitrate anyway.
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+    /// Input frames not yet passed
+    pending: Vec<i16>,
+    /// Position of the next output sample, in frames into `pending`
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        Self {
+            from_rate,
+            to_rate,
+            channels: channels.max(1),
+            pending: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    /// Resample `input`, returning the output it completes
+    pub fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        if self.from_rate == self.to_rate {
+            return input.to_vec();
+        }
+        let channels = self.channels;
+        self.pending.extend_from_slice(input);
+        let frames = self.pending.len() / channels;
+        let step = self.from_rate as f64 / self.to_rate as f64;
+
+        let mut output = Vec::with_capacity((input.len() as f64 / step) as usize + channels);
+        while self.position + 1.0 < frames as f64 {
+            let index = self.position as usize;
+            let fraction = self.position - index as f64;
+            for channel in 0..channels {
+                let a = self.pending[index * channels + channel] as f64;
+                let b = self.pending[(index + 1) * channels + channel] as f64;
+                output.push((a + (b - a) * fraction).round() as i16);
+            }
+            self.position += step;
+        }
+
+        let consumed = (self.position as usize).min(frames);
+        self.pending.drain(..consumed * channels);
+        self.position -= consumed as f64;
+        output
+    }
+}
+
+/// Convert interleaved PCM between mono and stereo
+fn remix(pcm: &[i16], from: Channels, to: Channels) -> Vec<i16> {
+    match (from, to) {
+        (Channels::Mono, Channels::Stereo) => pcm.iter().flat_map(|s| [*s, *s]).collect(),
+        (Channels::Stereo, Channels::Mono) => pcm
+            .chunks_exact(2)
+            .map(|pair| ((pair[0] as i32 + pair[1] as i32) / 2) as i16)
+            .collect(),
+        _ => pcm.to_vec(),
+    }
+}
+
+/// One output of a transcoder: a named encoding of the source
+#[derive(Debug, Clone)]
+pub struct AudioRendition {
+    pub name: String,
+    pub config: AudioConfig,
+}
+
+impl AudioRendition {
+    pub fn new(name: impl Into<String>, config: AudioConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+        }
+    }
+
+    /// Standard audio ladder: stereo music quality down to mono speech
+    pub fn ladder() -> Vec<AudioRendition> {
+        vec![
+            AudioRendition::new("high", AudioConfig::high_quality()),
+            AudioRendition::new("standard", AudioConfig::default()),
+            AudioRendition::new(
+                "low",
+                AudioConfig {
+                    sample_rate: 24000,
+                    channels: Channels::Mono,
+                    bitrate: 24000,
+                    frame_duration_ms: 20.0,
+                },
+            ),
+        ]
+    }
+
+    /// How the rendition is described in stream manifests and playlists
+    pub fn codec_info(&self) -> CodecInfo {
+        CodecInfo {
+            codecs: "opus".to_string(),
+            container: "fmp4".to_string(),
+            bitrate_kbps: (self.config.bitrate / 1000) as u32,
+            width: 0,
+            height: 0,
+        }
+    }
+}
+
+/// Output of one rendition for one encoded frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscodedPacket {
+    /// Index of the rendition, in the order given to the transcoder
+    pub rendition: usize,
+    pub packet: Vec<u8>,
+}
+
+/// Counters of one rendition
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RenditionMetrics {
+    pub name: String,
+    /// Configured bitrate, bits per second
+    pub target_bitrate: i32,
+    pub frames: u64,
+    pub bytes: u64,
+    /// Media time encoded, in milliseconds
+    pub media_ms: f64,
+    /// Time spent resampling and encoding, in microseconds
+    pub encode_micros: u64,
+}
+
+impl RenditionMetrics {
+    /// Bitrate actually produced, bits per second
+    pub fn bitrate(&self) -> f64 {
+        if self.media_ms <= 0.0 {
+            return 0.0;
+        }
+        self.bytes as f64 * 8.0 * 1000.0 / self.media_ms
+    }
+
+    /// Encoding speed relative to real time (above 1 keeps up with a live stream)
+    pub fn realtime_factor(&self) -> f64 {
+        if self.encode_micros == 0 {
+            return 0.0;
+        }
+        self.media_ms * 1000.0 / self.encode_micros as f64
+    }
+}
+
+/// Counters of a transcoder
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TranscodeStats {
+    pub packets_in: u64,
+    /// Lost input packets replaced by concealment
+    pub packets_concealed: u64,
+    pub renditions: Vec<RenditionMetrics>,
+}
+
+/// Encoder of one rendition, with the PCM waiting for a full frame
+struct RenditionEncoder {
+    config: AudioConfig,
+    encoder: AudioEncoder,
+    resampler: Resampler,
+    buffered: Vec<i16>,
+    metrics: RenditionMetrics,
+}
+
+/// Decode → resample/remix → re-encode pipeline producing an ABR ladder
+///
+/// Each input packet is decoded once and fed to every rendition, which
+/// buffers PCM until it has a full frame of its own duration, so renditions
+/// may differ in rate, channels and frame size.
+pub struct Transcoder {
+    source: AudioConfig,
+    decoder: AudioDecoder,
+    outputs: Vec<RenditionEncoder>,
+    packets_in: u64,
+    packets_concealed: u64,
+}
+
+impl Transcoder {
+    /// Transcode a stream encoded as `source` into `renditions`
+    pub fn new(source: AudioConfig, renditions: Vec<AudioRendition>) -> Result<Self> {
+        if renditions.is_empty() {
+            anyhow::bail!("Transcoder needs at least one rendition");
+        }
+        let decoder = AudioDecoder::new(source.clone())?;
+        let outputs = renditions
+            .into_iter()
+            .map(|rendition| {
+                Ok(RenditionEncoder {
+                    encoder: AudioEncoder::new(rendition.config.clone())?,
+                    resampler: Resampler::new(
+                        source.sample_rate,
+                        rendition.config.sample_rate,
+                        rendition.config.channels as usize,
+                    ),
+                    buffered: Vec::new(),
+                    metrics: RenditionMetrics {
+                        name: rendition.name,
+                        target_bitrate: rendition.config.bitrate,
+                        ..RenditionMetrics::default()
+                    },
+                    config: rendition.config,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!("Created transcoder with {} rendition(s)", outputs.len());
+        Ok(Self {
+            source,
+            decoder,
+            outputs,
+            packets_in: 0,
+            packets_concealed: 0,
+        })
+    }
+
+    /// Transcode one source packet into whatever frames it completes
+    pub fn transcode(&mut self, packet: &[u8]) -> Result<Vec<TranscodedPacket>> {
+        self.packets_in += 1;
+        let pcm = self.decoder.decode(packet)?;
+        self.encode(&pcm)
+    }
+
+    /// Stand in for a lost source packet with concealment audio
+    pub fn conceal(&mut self) -> Result<Vec<TranscodedPacket>> {
+        self.packets_concealed += 1;
+        let pcm = self.decoder.decode_plc()?;
+        self.encode(&pcm)
+    }
+
+    pub fn stats(&self) -> TranscodeStats {
+        TranscodeStats {
+            packets_in: self.packets_in,
+            packets_concealed: self.packets_concealed,
+            renditions: self.outputs.iter().map(|o| o.metrics.clone()).collect(),
+        }
+    }
+
+    /// Codec of each rendition, in order, for manifests and playlists
+    pub fn codec_infos(&self) -> Vec<CodecInfo> {
+        self.outputs
+            .iter()
+            .map(|output| {
+                AudioRendition::new(output.metrics.name.clone(), output.config.clone()).codec_info()
+            })
+            .collect()
+    }
+
+    fn encode(&mut self, pcm: &[i16]) -> Result<Vec<TranscodedPacket>> {
+        let mut packets = Vec::new();
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            let started = Instant::now();
+            let remixed = remix(pcm, self.source.channels, output.config.channels);
+            let resampled = output.resampler.process(&remixed);
+            output.buffered.extend_from_slice(&resampled);
+
+            let frame_len = output.config.frame_size() * output.config.channels as usize;
+            while output.buffered.len() >= frame_len {
+                let frame: Vec<i16> = output.buffered.drain(..frame_len).collect();
+                let packet = output.encoder.encode(&frame)?;
+                output.metrics.frames += 1;
+                output.metrics.bytes += packet.len() as u64;
+                output.metrics.media_ms += output.config.frame_duration_ms as f64;
+                packets.push(TranscodedPacket {
+                    rendition: index,
+                    packet,
+                });
+            }
+            output.metrics.encode_micros += started.elapsed().as_micros() as u64;
+        }
+        Ok(packets)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -259,6 +586,93 @@ mod tests {
         Ok(())
     }
 
+    /// 20 ms of a 440 Hz tone, interleaved for `config`'s channels
+    fn tone(config: &AudioConfig, offset: usize) -> Vec<i16> {
+        let channels = config.channels as usize;
+        (0..config.frame_size())
+            .flat_map(|i| {
+                let t = (offset + i) as f32 / config.sample_rate as f32;
+                let sample = (8000.0 * (2.0 * std::f32::consts::PI * 440.0 * t).sin()) as i16;
+                vec![sample; channels]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resampler_keeps_rate_ratio_across_calls() {
+        let mut resampler = Resampler::new(48000, 24000, 2);
+        let mut produced = 0;
+        for _ in 0..10 {
+            produced += resampler.process(&vec![100i16; 960 * 2]).len();
+        }
+        // Half the frames, less at most one held back for interpolation
+        assert!((4799 * 2..=4800 * 2).contains(&produced), "{}", produced);
+
+        let mut upsampler = Resampler::new(24000, 48000, 1);
+        let output = upsampler.process(&[0, 100, 200]);
+        assert_eq!(output, vec![0, 50, 100, 150]);
+
+        assert_eq!(
+            remix(&[10, 20, 30, 50], Channels::Stereo, Channels::Mono),
+            vec![15, 40]
+        );
+        assert_eq!(remix(&[7], Channels::Mono, Channels::Stereo), vec![7, 7]);
+    }
+
+    #[test]
+    fn test_transcoder_produces_every_rendition() -> Result<()> {
+        let source = AudioConfig::default();
+        let mut encoder = AudioEncoder::new(source.clone())?;
+        let mut transcoder = Transcoder::new(source.clone(), AudioRendition::ladder())?;
+
+        let mut counts = [0usize; 3];
+        for frame in 0..25 {
+            let packet = encoder.encode(&tone(&source, frame * source.frame_size()))?;
+            let outputs = if frame == 10 {
+                transcoder.conceal()?
+            } else {
+                transcoder.transcode(&packet)?
+            };
+            for output in outputs {
+                assert!(!output.packet.is_empty());
+                counts[output.rendition] += 1;
+            }
+        }
+
+        // The high and standard rungs share the source's frame timing; the
+        // resampled low rung may hold its last frame back
+        assert_eq!(counts[0], 25);
+        assert_eq!(counts[1], 25);
+        assert!(counts[2] >= 24);
+
+        let stats = transcoder.stats();
+        assert_eq!(stats.packets_in, 24);
+        assert_eq!(stats.packets_concealed, 1);
+        assert_eq!(stats.renditions[2].name, "low");
+        assert_eq!(stats.renditions[0].frames, 25);
+        assert!(stats.renditions[2].bitrate() > 0.0);
+        assert_eq!(stats.renditions[2].media_ms, counts[2] as f64 * 20.0);
+        assert_eq!(transcoder.codec_infos()[1].bitrate_kbps, 64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_video_ladder_never_upscales() {
+        let source = VideoConfig {
+            width: 1280,
+            height: 720,
+            framerate: 60,
+            bitrate: 4_000_000,
+        };
+        let ladder = source.ladder();
+        assert_eq!(ladder[0], source);
+        let heights: Vec<u32> = ladder.iter().map(|rung| rung.height).collect();
+        assert_eq!(heights, vec![720, 480, 360]);
+        assert_eq!(ladder[1].width, 852);
+        assert_eq!(ladder[1].framerate, 30);
+        assert_eq!(ladder[1].bitrate, 1_200_000);
+    }
+
     #[test]
     fn test_packet_loss_concealment() -> Result<()> {
         let config = AudioConfig::low_latency();
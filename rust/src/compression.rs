@@ -0,0 +1,214 @@
+/// Adaptive compression level control
+///
+/// `CesConfig::adaptive` picks a level from static hardware caps. The
+/// `CompressionController` adjusts that choice per file from live signals:
+/// CPU load (as reported in `ComputeCapacity::current_load`) and the number
+/// of transfers waiting in the scheduler. Under pressure levels step down
+/// quickly; when the node is idle they creep back up.
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sysinfo::{CpuExt, System, SystemExt};
+use tracing::debug;
+
+use crate::compute::ComputeCapacity;
+
+/// Highest zstd level the controller will pick
+pub const MAX_COMPRESSION_LEVEL: i32 = 19;
+
+/// Why a payload was or was not compressed, recorded in its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CompressionDecision {
+    Compressed = 1,
+    /// The file type's level (or the caller's) was 0
+    SkippedByType = 2,
+    /// A sample of the data looked too random to shrink
+    SkippedByEntropy = 3,
+}
+
+impl CompressionDecision {
+    pub fn is_compressed(self) -> bool {
+        self == CompressionDecision::Compressed
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(CompressionDecision::Compressed),
+            2 => Some(CompressionDecision::SkippedByType),
+            3 => Some(CompressionDecision::SkippedByEntropy),
+            _ => None,
+        }
+    }
+}
+
+/// Compression decisions since start
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionStats {
+    pub compressed: u64,
+    pub skipped_by_type: u64,
+    pub skipped_by_entropy: u64,
+    /// Bytes before and after compression, over compressed payloads only
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl CompressionStats {
+    pub(crate) fn record(
+        &mut self,
+        decision: CompressionDecision,
+        bytes_in: usize,
+        bytes_out: usize,
+    ) {
+        match decision {
+            CompressionDecision::Compressed => {
+                self.compressed += 1;
+                self.bytes_in += bytes_in as u64;
+                self.bytes_out += bytes_out as u64;
+            }
+            CompressionDecision::SkippedByType => self.skipped_by_type += 1,
+            CompressionDecision::SkippedByEntropy => self.skipped_by_entropy += 1,
+        }
+    }
+}
+
+/// Controller thresholds
+#[derive(Debug, Clone)]
+pub struct CompressionControlConfig {
+    /// CPU load (0.0 - 1.0) above which levels are lowered
+    pub high_load: f32,
+    /// CPU load below which levels may rise
+    pub low_load: f32,
+    /// Queued transfers above which levels are lowered
+    pub max_backlog: usize,
+    /// Levels removed per observation under pressure
+    pub step_down: i32,
+    /// Levels added per observation when idle
+    pub step_up: i32,
+    /// Largest reduction from a file's base level
+    pub max_reduction: i32,
+    /// Largest increase over a file's base level
+    pub max_boost: i32,
+}
+
+impl Default for CompressionControlConfig {
+    fn default() -> Self {
+        Self {
+            high_load: 0.85,
+            low_load: 0.4,
+            max_backlog: 4,
+            step_down: 2,
+            step_up: 1,
+            max_reduction: 8,
+            max_boost: 3,
+        }
+    }
+}
+
+/// Picks per-file compression levels from CPU load and transfer backlog
+pub struct CompressionController {
+    config: CompressionControlConfig,
+    /// Offset applied to each file's base level
+    adjustment: Mutex<i32>,
+    system: Mutex<System>,
+}
+
+impl CompressionController {
+    pub fn new(config: CompressionControlConfig) -> Self {
+        let mut system = System::new();
+        // CPU usage is measured between refreshes; prime the first sample
+        system.refresh_cpu();
+        Self {
+            config,
+            adjustment: Mutex::new(0),
+            system: Mutex::new(system),
+        }
+    }
+
+    /// Current CPU load (0.0 - 1.0) since the previous sample
+    pub fn sample_load(&self) -> f32 {
+        let mut system = self.system.lock();
+        system.refresh_cpu();
+        (system.global_cpu_info().cpu_usage() / 100.0).clamp(0.0, 1.0)
+    }
+
+    /// Feed one observation and return the updated level adjustment
+    pub fn observe(&self, cpu_load: f32, backlog: usize) -> i32 {
+        let config = &self.config;
+        let mut adjustment = self.adjustment.lock();
+        if cpu_load > config.high_load || backlog > config.max_backlog {
+            *adjustment = (*adjustment - config.step_down).max(-config.max_reduction);
+        } else if cpu_load < config.low_load && backlog == 0 {
+            *adjustment = (*adjustment + config.step_up).min(config.max_boost);
+        }
+        debug!(
+            "Compression control: load {:.2}, backlog {} -> adjustment {}",
+            cpu_load, backlog, *adjustment
+        );
+        *adjustment
+    }
+
+    /// Observe the load reported by the compute subsystem
+    pub fn observe_capacity(&self, capacity: &ComputeCapacity, backlog: usize) -> i32 {
+        self.observe(capacity.current_load, backlog)
+    }
+
+    pub fn adjustment(&self) -> i32 {
+        *self.adjustment.lock()
+    }
+
+    /// Level to use for a file whose content suggests `base_level`
+    ///
+    /// A base level of 0 (content that should not be compressed) is kept.
+    pub fn level_for(&self, base_level: i32) -> i32 {
+        if base_level <= 0 {
+            return 0;
+        }
+        (base_level + self.adjustment()).clamp(1, MAX_COMPRESSION_LEVEL)
+    }
+}
+
+impl Default for CompressionController {
+    fn default() -> Self {
+        Self::new(CompressionControlConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_lowers_and_idle_raises_levels() {
+        let controller = CompressionController::default();
+        assert_eq!(controller.level_for(9), 9);
+
+        controller.observe(0.95, 0);
+        controller.observe(0.5, 10);
+        assert_eq!(controller.adjustment(), -4);
+        assert_eq!(controller.level_for(9), 5);
+        assert_eq!(controller.level_for(3), 1);
+
+        for _ in 0..10 {
+            controller.observe(0.1, 0);
+        }
+        assert_eq!(controller.adjustment(), 3);
+        assert_eq!(controller.level_for(9), 12);
+    }
+
+    #[test]
+    fn test_uncompressible_content_stays_uncompressed() {
+        let controller = CompressionController::default();
+        for _ in 0..5 {
+            controller.observe(0.0, 0);
+        }
+        assert_eq!(controller.level_for(0), 0);
+    }
+
+    #[test]
+    fn test_moderate_load_holds_level() {
+        let controller = CompressionController::default();
+        controller.observe(0.6, 1);
+        assert_eq!(controller.adjustment(), 0);
+        assert!((0.0..=1.0).contains(&controller.sample_load()));
+    }
+}
@@ -0,0 +1,269 @@
+//! Admission control for compute tasks
+//!
+//! Every task reserves the memory it may use (its sandbox limit plus its
+//! input) and one CPU slot before it runs, and gives both back when it
+//! finishes. A task that does not fit right now waits in a bounded queue
+//! until running tasks release enough, the host load drops, or its wait
+//! times out; a task larger than the whole budget is rejected outright.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::debug;
+
+use crate::error::AdmissionError;
+
+/// Admission counters since start, and the reservations held now
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdmissionStats {
+    pub running: usize,
+    pub queued: usize,
+    pub reserved_memory_mb: u64,
+    pub admitted: u64,
+    /// Tasks that had to wait before being admitted or rejected
+    pub queued_total: u64,
+    pub rejected_memory: u64,
+    pub rejected_queue_full: u64,
+    pub rejected_timeout: u64,
+}
+
+#[derive(Debug, Default)]
+struct Reserved {
+    running: usize,
+    queued: usize,
+    memory_mb: u64,
+}
+
+/// Memory and CPU-slot bookkeeping for the tasks of one engine
+pub struct Admission {
+    memory_budget_mb: u64,
+    slots: usize,
+    max_load: f32,
+    max_queued: usize,
+    queue_timeout: Duration,
+    reserved: Mutex<Reserved>,
+    /// Host load as `f32` bits
+    load: AtomicU32,
+    released: Notify,
+    admitted: AtomicU64,
+    queued_total: AtomicU64,
+    rejected_memory: AtomicU64,
+    rejected_queue_full: AtomicU64,
+    rejected_timeout: AtomicU64,
+}
+
+impl Admission {
+    pub fn new(
+        memory_budget_mb: u64,
+        slots: usize,
+        max_load: f32,
+        max_queued: usize,
+        queue_timeout: Duration,
+    ) -> Self {
+        Self {
+            memory_budget_mb,
+            slots: slots.max(1),
+            max_load,
+            max_queued,
+            queue_timeout,
+            reserved: Mutex::new(Reserved::default()),
+            load: AtomicU32::new(0f32.to_bits()),
+            released: Notify::new(),
+            admitted: AtomicU64::new(0),
+            queued_total: AtomicU64::new(0),
+            rejected_memory: AtomicU64::new(0),
+            rejected_queue_full: AtomicU64::new(0),
+            rejected_timeout: AtomicU64::new(0),
+        }
+    }
+
+    /// Record the host load; queued tasks are re-checked when it drops
+    pub fn set_load(&self, load: f32) {
+        let previous = f32::from_bits(self.load.swap(load.to_bits(), Ordering::Relaxed));
+        if load < previous {
+            self.released.notify_waiters();
+        }
+    }
+
+    /// Reserve `memory_mb` and a CPU slot, waiting in the queue if needed
+    pub async fn admit(&self, memory_mb: u64) -> Result<Reservation<'_>, AdmissionError> {
+        if memory_mb > self.memory_budget_mb {
+            self.rejected_memory.fetch_add(1, Ordering::Relaxed);
+            return Err(AdmissionError::InsufficientMemory {
+                required_mb: memory_mb,
+                capacity_mb: self.memory_budget_mb,
+            });
+        }
+        if let Some(reservation) = self.try_reserve(memory_mb) {
+            return Ok(reservation);
+        }
+
+        {
+            let mut reserved = self.reserved.lock();
+            if reserved.queued >= self.max_queued {
+                drop(reserved);
+                self.rejected_queue_full.fetch_add(1, Ordering::Relaxed);
+                return Err(AdmissionError::QueueFull(self.max_queued));
+            }
+            reserved.queued += 1;
+        }
+        self.queued_total.fetch_add(1, Ordering::Relaxed);
+        debug!("Compute task queued for {} MB", memory_mb);
+        let _queued = QueueSlot(self);
+
+        let deadline = tokio::time::Instant::now() + self.queue_timeout;
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            if let Some(reservation) = self.try_reserve(memory_mb) {
+                return Ok(reservation);
+            }
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                self.rejected_timeout.fetch_add(1, Ordering::Relaxed);
+                return Err(AdmissionError::QueueTimeout(
+                    self.queue_timeout.as_millis() as u64
+                ));
+            }
+        }
+    }
+
+    pub fn stats(&self) -> AdmissionStats {
+        let reserved = self.reserved.lock();
+        AdmissionStats {
+            running: reserved.running,
+            queued: reserved.queued,
+            reserved_memory_mb: reserved.memory_mb,
+            admitted: self.admitted.load(Ordering::Relaxed),
+            queued_total: self.queued_total.load(Ordering::Relaxed),
+            rejected_memory: self.rejected_memory.load(Ordering::Relaxed),
+            rejected_queue_full: self.rejected_queue_full.load(Ordering::Relaxed),
+            rejected_timeout: self.rejected_timeout.load(Ordering::Relaxed),
+        }
+    }
+
+    fn try_reserve(&self, memory_mb: u64) -> Option<Reservation<'_>> {
+        let mut reserved = self.reserved.lock();
+        let load = f32::from_bits(self.load.load(Ordering::Relaxed));
+        if reserved.running >= self.slots
+            || reserved.memory_mb + memory_mb > self.memory_budget_mb
+            || load >= self.max_load
+        {
+            return None;
+        }
+        reserved.running += 1;
+        reserved.memory_mb += memory_mb;
+        self.admitted.fetch_add(1, Ordering::Relaxed);
+        Some(Reservation {
+            admission: self,
+            memory_mb,
+        })
+    }
+}
+
+/// Capacity held by a running task, returned when dropped
+pub struct Reservation<'a> {
+    admission: &'a Admission,
+    memory_mb: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        {
+            let mut reserved = self.admission.reserved.lock();
+            reserved.running -= 1;
+            reserved.memory_mb -= self.memory_mb;
+        }
+        self.admission.released.notify_waiters();
+    }
+}
+
+/// A place in the queue, given up however the wait ends
+struct QueueSlot<'a>(&'a Admission);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.reserved.lock().queued -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tasks_queue_until_capacity_is_released() {
+        let admission = Admission::new(1000, 2, 0.9, 4, Duration::from_secs(5));
+        let first = admission.admit(600).await.unwrap();
+        assert_eq!(admission.stats().reserved_memory_mb, 600);
+
+        // 600 + 600 > 1000: the second task waits for the first
+        let second = admission.admit(600);
+        tokio::pin!(second);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), second.as_mut())
+                .await
+                .is_err()
+        );
+        assert_eq!(admission.stats().queued, 1);
+
+        drop(first);
+        let second = second.await.unwrap();
+        let stats = admission.stats();
+        assert_eq!((stats.running, stats.queued), (1, 0));
+        assert_eq!((stats.admitted, stats.queued_total), (2, 1));
+        drop(second);
+        assert_eq!(admission.stats().reserved_memory_mb, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejections_carry_their_reason() {
+        let admission = Admission::new(1000, 1, 0.9, 1, Duration::from_millis(50));
+        assert_eq!(
+            admission.admit(2000).await.err(),
+            Some(AdmissionError::InsufficientMemory {
+                required_mb: 2000,
+                capacity_mb: 1000
+            })
+        );
+
+        let _running = admission.admit(100).await.unwrap();
+        let waiting = admission.admit(100);
+        tokio::pin!(waiting);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), waiting.as_mut())
+                .await
+                .is_err()
+        );
+        // The only CPU slot is taken and the queue holds one task already
+        assert_eq!(
+            admission.admit(100).await.err(),
+            Some(AdmissionError::QueueFull(1))
+        );
+        assert_eq!(waiting.await.err(), Some(AdmissionError::QueueTimeout(50)));
+
+        let stats = admission.stats();
+        assert_eq!(stats.rejected_memory, 1);
+        assert_eq!(stats.rejected_queue_full, 1);
+        assert_eq!(stats.rejected_timeout, 1);
+        assert_eq!(stats.queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_high_load_holds_tasks_back() {
+        let admission = Admission::new(1000, 4, 0.9, 4, Duration::from_secs(5));
+        admission.set_load(0.95);
+        let task = admission.admit(100);
+        tokio::pin!(task);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), task.as_mut())
+                .await
+                .is_err()
+        );
+
+        admission.set_load(0.2);
+        assert!(task.await.is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+//! On-disk cache of precompiled WASM modules
+//!
+//! Compiling a module costs far more than running a short task, so compiled
+//! artifacts are kept under the cache directory, keyed by the module's
+//! SHA-256 and by a fingerprint of the runtime settings that shape the
+//! compiled code. Each artifact carries a header with both keys and its own
+//! digest; an artifact that fails the check is deleted and compiled again.
+//! Artifacts of other fingerprints (an upgraded node, changed limits) are
+//! pruned when the cache is opened.
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::compute::sandbox::SandboxConfig;
+use crate::compute::types::ComputeError;
+
+/// Directory, under the cache directory, holding compiled modules
+pub const AOT_CACHE_DIR: &str = "compute-aot";
+
+/// Bump when the artifact layout or the compiler changes
+const AOT_FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"PAOT";
+/// Magic, format version, fingerprint, module hash, artifact digest
+const HEADER_LEN: usize = 4 + 4 + 32 + 32 + 32;
+
+/// Runtime settings compiled code depends on, hashed
+///
+/// Memory bounds and fuel metering are baked into compiled code, as are
+/// the target and the node version.
+pub fn engine_fingerprint(config: &SandboxConfig) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(AOT_FORMAT_VERSION.to_le_bytes());
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(std::env::consts::ARCH);
+    hasher.update(std::env::consts::OS);
+    hasher.update(config.max_memory_bytes.to_le_bytes());
+    hasher.update([config.max_cpu_cycles > 0, config.enable_wasi].map(u8::from));
+    hasher.finalize().into()
+}
+
+/// Compiled modules for one runtime configuration
+pub struct AotCache {
+    dir: PathBuf,
+    fingerprint: [u8; 32],
+}
+
+impl AotCache {
+    /// Open the cache under `root` for `config`, pruning stale artifacts
+    pub fn open(root: impl AsRef<Path>, config: &SandboxConfig) -> Result<Self, ComputeError> {
+        let root = root.as_ref();
+        let fingerprint = engine_fingerprint(config);
+        let current = hex::encode(&fingerprint[..8]);
+        fs::create_dir_all(root.join(&current))?;
+
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            if entry.file_name().to_str() != Some(current.as_str()) {
+                info!(
+                    "Removing compiled modules of an old runtime configuration: {:?}",
+                    entry.path()
+                );
+                if let Err(e) = fs::remove_dir_all(entry.path()) {
+                    warn!("Failed to remove {:?}: {}", entry.path(), e);
+                }
+            }
+        }
+
+        Ok(Self {
+            dir: root.join(current),
+            fingerprint,
+        })
+    }
+
+    /// Compiled artifact of the module with SHA-256 `module_hash`, if cached and intact
+    pub fn get(&self, module_hash: &[u8; 32]) -> Option<Vec<u8>> {
+        let path = self.path(module_hash);
+        let bytes = fs::read(&path).ok()?;
+        match self.check(&bytes, module_hash) {
+            Ok(()) => {
+                debug!("Loaded compiled module {}", hex::encode(&module_hash[..8]));
+                Some(bytes[HEADER_LEN..].to_vec())
+            }
+            Err(reason) => {
+                warn!("Discarding compiled module {:?}: {}", path, reason);
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Store the compiled artifact of the module with SHA-256 `module_hash`
+    pub fn put(&self, module_hash: &[u8; 32], artifact: &[u8]) -> Result<(), ComputeError> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + artifact.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&AOT_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.fingerprint);
+        bytes.extend_from_slice(module_hash);
+        bytes.extend_from_slice(&Sha256::digest(artifact));
+        bytes.extend_from_slice(artifact);
+
+        let path = self.path(module_hash);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn check(&self, bytes: &[u8], module_hash: &[u8; 32]) -> Result<(), &'static str> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err("not a compiled module");
+        }
+        if bytes[4..8] != AOT_FORMAT_VERSION.to_le_bytes() || bytes[8..40] != self.fingerprint {
+            return Err("compiled for another runtime configuration");
+        }
+        if bytes[40..72] != module_hash[..] {
+            return Err("compiled from another module");
+        }
+        if bytes[72..104] != Sha256::digest(&bytes[HEADER_LEN..])[..] {
+            return Err("artifact is corrupt");
+        }
+        Ok(())
+    }
+
+    fn path(&self, module_hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(format!("{}.cwasm", hex::encode(module_hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifacts_are_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AotCache::open(dir.path(), &SandboxConfig::default()).unwrap();
+        let module: [u8; 32] = Sha256::digest(b"module").into();
+        assert!(cache.get(&module).is_none());
+
+        cache.put(&module, b"compiled code").unwrap();
+        assert_eq!(cache.get(&module).unwrap(), b"compiled code");
+
+        // A flipped byte is detected and the artifact dropped
+        let path = cache.path(&module);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(cache.get(&module).is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_changed_runtime_flags_invalidate_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let module: [u8; 32] = Sha256::digest(b"module").into();
+        let config = SandboxConfig::default();
+        AotCache::open(dir.path(), &config)
+            .unwrap()
+            .put(&module, b"compiled code")
+            .unwrap();
+        assert!(AotCache::open(dir.path(), &config)
+            .unwrap()
+            .get(&module)
+            .is_some());
+
+        let wasi = SandboxConfig {
+            enable_wasi: true,
+            ..config.clone()
+        };
+        let reopened = AotCache::open(dir.path(), &wasi).unwrap();
+        assert!(reopened.get(&module).is_none());
+        // The old configuration's artifacts are gone from disk
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(AotCache::open(dir.path(), &config)
+            .unwrap()
+            .get(&module)
+            .is_none());
+    }
+}
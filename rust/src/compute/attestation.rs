@@ -0,0 +1,177 @@
+//! Signed execution attestations for compute results
+//!
+//! A result alone does not say what produced it. Engines holding a node
+//! identity attach an [`ExecutionAttestation`] to each [`TaskResult`]: a
+//! report of the engine (version, runtime fingerprint, simulation mode),
+//! the module and input it ran, the result hash, limits, usage and times,
+//! signed with the node's Ed25519 identity key. An orchestrator checks it
+//! against an [`AttestationPolicy`] and drops results from simulation-mode
+//! engines, unexpected engine versions or modules, or unknown signers.
+//!
+//! The attestation is the executor's own claim; it binds the executor to
+//! what it reports but does not prove the report true.
+
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::compute::types::TaskResult;
+use crate::identity::{peer_id_of, NodeIdentity};
+
+/// Prefix of every signed report, so the signature is not valid elsewhere
+const SIGNING_CONTEXT: &[u8] = b"pangea-compute-attestation-v1";
+
+/// What an engine reports about one execution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub task_id: String,
+    /// Crate version of the executing engine
+    pub engine_version: String,
+    /// Runtime settings fingerprint, as keyed by the AOT cache (hex)
+    pub engine_fingerprint: String,
+    /// Whether the engine only simulates execution
+    pub simulation_mode: bool,
+    /// SHA-256 of the WASM module (hex)
+    pub module_hash: String,
+    pub function_name: String,
+    /// SHA-256 of the input as received (hex)
+    pub input_hash: String,
+    /// Hash of the result, as in the task result
+    pub result_hash: String,
+    /// Whether input and result went through an I/O tunnel
+    pub tunnelled: bool,
+    pub max_memory_bytes: u64,
+    pub max_cpu_cycles: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Unix times, in milliseconds
+    pub started_at_ms: i64,
+    pub finished_at_ms: i64,
+}
+
+/// A report signed by the executing node's identity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionAttestation {
+    pub report: ExecutionReport,
+    /// Signer's Ed25519 public key, hex
+    pub signer: String,
+    /// Signature over the report, hex
+    pub signature: String,
+}
+
+impl ExecutionAttestation {
+    /// Sign `report` with a node's identity
+    pub fn sign(identity: &NodeIdentity, report: ExecutionReport) -> Result<Self> {
+        let signature = identity
+            .libp2p_keypair()
+            .sign(&signed_bytes(&report)?)
+            .context("Failed to sign execution report")?;
+        Ok(Self {
+            report,
+            signer: hex::encode(identity.verifying_key().as_bytes()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Check the signature; returns the signer
+    pub fn verify(&self) -> Result<PeerId> {
+        let mut public = [0u8; 32];
+        hex::decode_to_slice(&self.signer, &mut public).context("Malformed signer key")?;
+        let signature = hex::decode(&self.signature).context("Malformed signature")?;
+        let signature =
+            Signature::from_slice(&signature).map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        VerifyingKey::from_bytes(&public)
+            .map_err(|e| anyhow!("Invalid signer key: {}", e))?
+            .verify_strict(&signed_bytes(&self.report)?, &signature)
+            .map_err(|_| anyhow!("Bad execution attestation signature"))?;
+        peer_id_of(&public)
+    }
+}
+
+fn signed_bytes(report: &ExecutionReport) -> Result<Vec<u8>> {
+    let mut bytes = SIGNING_CONTEXT.to_vec();
+    serde_json::to_writer(&mut bytes, report)?;
+    Ok(bytes)
+}
+
+/// SHA-256 of `data`, hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// What an orchestrator accepts results from
+#[derive(Debug, Clone, Default)]
+pub struct AttestationPolicy {
+    /// Accept results of simulation-mode engines
+    pub allow_simulation: bool,
+    /// Accepted engine versions; empty accepts any
+    pub engine_versions: Vec<String>,
+    /// Accepted engine fingerprints (hex); empty accepts any
+    pub engine_fingerprints: Vec<String>,
+    /// Module the task was meant to run
+    pub module_hash: Option<String>,
+    /// Accepted signers; empty accepts any
+    pub signers: Vec<PeerId>,
+}
+
+impl AttestationPolicy {
+    /// Check `result` carries a valid attestation this policy accepts;
+    /// returns the signer
+    pub fn check(&self, result: &TaskResult) -> Result<PeerId> {
+        let attestation = result
+            .attestation
+            .as_ref()
+            .ok_or_else(|| anyhow!("Result of task {} is not attested", result.task_id))?;
+        let signer = attestation.verify()?;
+        let report = &attestation.report;
+        if report.task_id != result.task_id || report.result_hash != result.result_hash {
+            bail!(
+                "Attestation of task {} is for another result",
+                result.task_id
+            );
+        }
+        if !self.signers.is_empty() && !self.signers.contains(&signer) {
+            bail!(
+                "Task {} was executed by untrusted {}",
+                result.task_id,
+                signer
+            );
+        }
+        if report.simulation_mode && !self.allow_simulation {
+            bail!("Task {} ran on a simulation-mode engine", result.task_id);
+        }
+        if !self.engine_versions.is_empty()
+            && !self.engine_versions.contains(&report.engine_version)
+        {
+            bail!(
+                "Task {} ran on engine version {}",
+                result.task_id,
+                report.engine_version
+            );
+        }
+        if !self.engine_fingerprints.is_empty()
+            && !self
+                .engine_fingerprints
+                .contains(&report.engine_fingerprint)
+        {
+            bail!(
+                "Task {} ran on engine {}",
+                result.task_id,
+                report.engine_fingerprint
+            );
+        }
+        if let Some(module_hash) = &self.module_hash {
+            if &report.module_hash != module_hash {
+                bail!(
+                    "Task {} ran module {}, not {}",
+                    result.task_id,
+                    report.module_hash,
+                    module_hash
+                );
+            }
+        }
+        Ok(signer)
+    }
+}
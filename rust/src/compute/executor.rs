@@ -3,11 +3,16 @@
 //! This module handles the actual execution of compute tasks,
 //! including data serialization for WASM and result processing.
 
-use crate::compute::types::{ChunkInfo, ComputeConfig, ComputeError, JobManifest, TaskStatus};
+use crate::compute::types::{
+    ChunkInfo, ComputeConfig, ComputeError, JobManifest, SplitStrategy, TaskStatus,
+};
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 use tracing::{debug, info};
 
+/// Most chunks `deserialize_chunks` accepts in one buffer
+pub const MAX_SERIALIZED_CHUNKS: usize = 1 << 20;
+
 /// Execution context for a compute task
 #[derive(Debug, Clone)]
 pub struct ExecutionContext {
@@ -56,6 +61,13 @@ impl ComputeExecutor {
     ///
     /// Uses the job's split strategy to divide data into manageable chunks.
     /// Returns a vector of chunks and their metadata.
+    ///
+    /// `RowBased` and `Delimiter` cut only right after a separator, so every
+    /// record stays whole within one chunk. Chunks then aim for the same size
+    /// as fixed-size splitting and stay within the job's min/max sizes,
+    /// except that the last chunk may be smaller and a single record longer
+    /// than the max becomes a chunk of its own. `Custom` splitting runs in
+    /// WASM; here it falls back to fixed-size chunks.
     pub fn split_data(
         &self,
         job: &JobManifest,
@@ -63,12 +75,26 @@ impl ComputeExecutor {
     ) -> Result<(Vec<Vec<u8>>, Vec<ChunkInfo>), ComputeError> {
         let chunk_size = self.calculate_chunk_size(job, data.len());
         debug!(
-            "Splitting {} bytes into chunks of ~{} bytes",
+            "Splitting {} bytes into chunks of ~{} bytes ({:?})",
             data.len(),
-            chunk_size
+            chunk_size,
+            job.split_strategy
         );
 
-        let chunks: Vec<Vec<u8>> = data.chunks(chunk_size).map(|c| c.to_vec()).collect();
+        let chunks: Vec<Vec<u8>> = match job.split_strategy {
+            SplitStrategy::FixedSize | SplitStrategy::Custom => {
+                data.chunks(chunk_size).map(|c| c.to_vec()).collect()
+            }
+            SplitStrategy::RowBased => split_records(data, b"\n", chunk_size, job),
+            SplitStrategy::Delimiter => {
+                if job.delimiter.is_empty() {
+                    return Err(ComputeError::InvalidInput(
+                        "Delimiter split needs a non-empty delimiter".into(),
+                    ));
+                }
+                split_records(data, &job.delimiter, chunk_size, job)
+            }
+        };
 
         let chunk_infos: Vec<ChunkInfo> = chunks
             .par_iter()
@@ -143,6 +169,9 @@ impl ComputeExecutor {
     /// Deserialize chunks from WASM output
     ///
     /// Format: [num_chunks(4 bytes), [chunk_len(4 bytes), chunk_data]...]
+    ///
+    /// The input is untrusted: counts and lengths are checked against the
+    /// buffer before anything is allocated.
     pub fn deserialize_chunks(&self, data: &[u8]) -> Result<Vec<Vec<u8>>, ComputeError> {
         if data.len() < 4 {
             return Err(ComputeError::SerializationError(
@@ -151,31 +180,32 @@ impl ComputeExecutor {
         }
 
         let num_chunks = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-        let mut chunks = Vec::with_capacity(num_chunks);
+        if num_chunks > MAX_SERIALIZED_CHUNKS {
+            return Err(ComputeError::SerializationError(format!(
+                "Chunk count {} exceeds limit of {}",
+                num_chunks, MAX_SERIALIZED_CHUNKS
+            )));
+        }
+        // Every chunk needs at least its length prefix
+        let mut chunks = Vec::with_capacity(num_chunks.min((data.len() - 4) / 4));
         let mut offset = 4;
 
         for i in 0..num_chunks {
-            if offset + 4 > data.len() {
+            let Some(header) = data.get(offset..offset + 4) else {
                 return Err(ComputeError::SerializationError(format!(
                     "Truncated chunk header at chunk {}",
                     i
                 )));
-            }
-
-            let chunk_len = u32::from_le_bytes([
-                data[offset],
-                data[offset + 1],
-                data[offset + 2],
-                data[offset + 3],
-            ]) as usize;
+            };
+            let chunk_len =
+                u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
             offset += 4;
 
-            if offset + chunk_len > data.len() {
+            let remaining = data.len() - offset;
+            if chunk_len > remaining {
                 return Err(ComputeError::SerializationError(format!(
                     "Truncated chunk data at chunk {} (need {} bytes, have {})",
-                    i,
-                    chunk_len,
-                    data.len() - offset
+                    i, chunk_len, remaining
                 )));
             }
 
@@ -227,6 +257,49 @@ impl ComputeExecutor {
     }
 }
 
+/// Cut `data` right after occurrences of `delimiter`, aiming for `target`
+/// bytes per chunk within the job's min/max chunk sizes
+fn split_records(data: &[u8], delimiter: &[u8], target: usize, job: &JobManifest) -> Vec<Vec<u8>> {
+    let min = (job.min_chunk_size as usize).min(target);
+    let max = (job.max_chunk_size as usize).max(target);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while data.len() - start > target {
+        let rest = &data[start..];
+        // Prefer the first record end at or past the target, if within max;
+        // else the last one past min; else the end of an oversized record
+        let cut = record_end_after(rest, delimiter, target)
+            .filter(|&end| end <= max)
+            .or_else(|| last_record_end_before(rest, delimiter, target).filter(|&end| end >= min))
+            .or_else(|| record_end_after(rest, delimiter, 1))
+            .unwrap_or(rest.len());
+        chunks.push(rest[..cut].to_vec());
+        start += cut;
+    }
+    if start < data.len() {
+        chunks.push(data[start..].to_vec());
+    }
+    chunks
+}
+
+/// End (just past the delimiter) of the first record ending at or after `from`
+fn record_end_after(data: &[u8], delimiter: &[u8], from: usize) -> Option<usize> {
+    let first = from.saturating_sub(delimiter.len());
+    data.get(first..)?
+        .windows(delimiter.len())
+        .position(|window| window == delimiter)
+        .map(|i| first + i + delimiter.len())
+}
+
+/// End of the last record ending before `before`
+fn last_record_end_before(data: &[u8], delimiter: &[u8], before: usize) -> Option<usize> {
+    data[..before.min(data.len())]
+        .windows(delimiter.len())
+        .rposition(|window| window == delimiter)
+        .map(|i| i + delimiter.len())
+}
+
 impl Default for ComputeExecutor {
     fn default() -> Self {
         Self::new(ComputeConfig::default())
@@ -244,6 +317,7 @@ mod tests {
             wasm_module: vec![0; 1024],
             input_data: vec![0; data_size],
             split_strategy: SplitStrategy::FixedSize,
+            delimiter: Vec::new(),
             min_chunk_size: 1024,
             max_chunk_size: 65536,
             verification_mode: crate::compute::types::VerificationMode::Hash,
@@ -270,6 +344,57 @@ mod tests {
         assert_eq!(total, 100_000);
     }
 
+    #[test]
+    fn test_row_split_keeps_rows_whole() {
+        let executor = ComputeExecutor::default();
+        let mut job = create_test_job(0);
+        job.split_strategy = SplitStrategy::RowBased;
+        let data: Vec<u8> = (0..5000)
+            .flat_map(|i| format!("row {},{}\n", i, "x".repeat(i % 40)).into_bytes())
+            .collect();
+
+        let (chunks, _) = executor.split_data(&job, &data).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), data);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.ends_with(b"\n"), "chunk {} cuts a row", i);
+            assert!(chunk.starts_with(b"row "));
+            assert!(chunk.len() <= job.max_chunk_size as usize);
+            if i + 1 < chunks.len() {
+                assert!(chunk.len() >= job.min_chunk_size as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn test_delimiter_split() {
+        let executor = ComputeExecutor::default();
+        let mut job = create_test_job(0);
+        job.split_strategy = SplitStrategy::Delimiter;
+        assert!(executor.split_data(&job, b"a;;b").is_err());
+
+        job.delimiter = b";;".to_vec();
+        let records: Vec<Vec<u8>> = (0..2000u32)
+            .map(|i| format!("record-{};;", i).into_bytes())
+            .collect();
+        let data = records.concat();
+        let (chunks, infos) = executor.split_data(&job, &data).unwrap();
+        assert_eq!(chunks.concat(), data);
+        assert_eq!(chunks.len(), infos.len());
+        for chunk in &chunks {
+            assert!(chunk.ends_with(b";;"));
+            assert!(chunk.starts_with(b"record-"));
+        }
+
+        // A record longer than the max chunk size stays whole
+        let mut data = vec![b'a'; 100_000];
+        data.extend_from_slice(b";;tail");
+        let (chunks, _) = executor.split_data(&job, &data).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 100_002);
+        assert_eq!(chunks[1], b"tail");
+    }
+
     #[test]
     fn test_merge_results() {
         let executor = ComputeExecutor::default();
@@ -293,6 +418,25 @@ mod tests {
         assert_eq!(chunks, deserialized);
     }
 
+    #[test]
+    fn test_deserialize_rejects_malformed_input() {
+        let executor = ComputeExecutor::default();
+
+        // A huge chunk count must not be trusted for allocation
+        assert!(executor
+            .deserialize_chunks(&u32::MAX.to_le_bytes())
+            .is_err());
+        let mut claims_many = 1000u32.to_le_bytes().to_vec();
+        claims_many.extend_from_slice(&0u32.to_le_bytes());
+        assert!(executor.deserialize_chunks(&claims_many).is_err());
+
+        // Chunk length running past the end
+        let mut overlong = 1u32.to_le_bytes().to_vec();
+        overlong.extend_from_slice(&u32::MAX.to_le_bytes());
+        overlong.extend_from_slice(&[1, 2, 3]);
+        assert!(executor.deserialize_chunks(&overlong).is_err());
+    }
+
     #[test]
     fn test_complexity_calculation() {
         let executor = ComputeExecutor::default();
@@ -0,0 +1,577 @@
+//! Multi-stage compute jobs
+//!
+//! A [`JobGraph`] is a DAG of stages, each consuming the outputs of the
+//! stages it depends on (map → shuffle → reduce style). Every stage output
+//! is a list of partitions:
+//!
+//! - a source stage (no dependencies) reads the graph input, split with the
+//!   graph's split settings;
+//! - a stage with dependencies reads their partitions, concatenated in
+//!   `depends_on` order.
+//!
+//! Stages whose dependencies are done run together, and the tasks of a
+//! stage go through [`ComputeEngine::process_task`], so they run in
+//! parallel within admission limits.
+
+use futures::future::join_all;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::compute::types::{ComputeError, ComputeTask, JobManifest, SplitStrategy, TaskStatus};
+use crate::compute::ComputeEngine;
+
+/// What a stage does with its input partitions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StageKind {
+    /// Run the stage function on each partition; one output partition each
+    Map,
+    /// Regroup newline-terminated records into `partitions` buckets by key
+    /// (the bytes before the first tab), on the host; records with the same
+    /// key land in the same bucket
+    Shuffle { partitions: u32 },
+    /// Run the stage function once over all partitions, passed in the
+    /// length-prefixed chunk format `merge` functions take; one output
+    Reduce,
+}
+
+/// One step of a job graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stage {
+    /// Unique within the graph
+    pub name: String,
+    pub kind: StageKind,
+    /// WASM module with the stage function (unused by shuffles)
+    #[serde(default)]
+    pub wasm_module: Vec<u8>,
+    pub function_name: String,
+    /// Stages whose outputs this stage reads
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+impl Stage {
+    pub fn map(name: &str, wasm_module: Vec<u8>, function_name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: StageKind::Map,
+            wasm_module,
+            function_name: function_name.to_string(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    pub fn shuffle(name: &str, partitions: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: StageKind::Shuffle { partitions },
+            wasm_module: Vec::new(),
+            function_name: String::new(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    pub fn reduce(name: &str, wasm_module: Vec<u8>, function_name: &str) -> Self {
+        Self {
+            kind: StageKind::Reduce,
+            ..Self::map(name, wasm_module, function_name)
+        }
+    }
+
+    /// Read the outputs of `stages`
+    pub fn after(mut self, stages: &[&str]) -> Self {
+        self.depends_on = stages.iter().map(|stage| stage.to_string()).collect();
+        self
+    }
+}
+
+/// A multi-stage compute job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobGraph {
+    pub job_id: String,
+    /// Input of the source stages
+    pub input_data: Vec<u8>,
+    /// How source stages split the input
+    pub split_strategy: SplitStrategy,
+    #[serde(default)]
+    pub delimiter: Vec<u8>,
+    pub min_chunk_size: u64,
+    pub max_chunk_size: u64,
+    pub stages: Vec<Stage>,
+}
+
+impl JobGraph {
+    /// A graph with the default split settings of [`JobManifest::new`]
+    pub fn new(job_id: String, input_data: Vec<u8>, stages: Vec<Stage>) -> Self {
+        let defaults = JobManifest::new(String::new(), Vec::new(), Vec::new());
+        Self {
+            job_id,
+            input_data,
+            split_strategy: defaults.split_strategy,
+            delimiter: defaults.delimiter,
+            min_chunk_size: defaults.min_chunk_size,
+            max_chunk_size: defaults.max_chunk_size,
+            stages,
+        }
+    }
+
+    /// Stage names in an order where every stage follows its dependencies
+    ///
+    /// Fails on duplicate names, unknown dependencies and cycles.
+    pub fn topological_order(&self) -> Result<Vec<&str>, ComputeError> {
+        if self.stages.is_empty() {
+            return Err(ComputeError::InvalidInput("Job graph has no stages".into()));
+        }
+        let mut pending: HashMap<&str, usize> = HashMap::new();
+        for stage in &self.stages {
+            if pending
+                .insert(&stage.name, stage.depends_on.len())
+                .is_some()
+            {
+                return Err(ComputeError::InvalidInput(format!(
+                    "Duplicate stage {:?}",
+                    stage.name
+                )));
+            }
+            if let StageKind::Shuffle { partitions: 0 } = stage.kind {
+                return Err(ComputeError::InvalidInput(format!(
+                    "Shuffle stage {:?} needs at least one partition",
+                    stage.name
+                )));
+            }
+        }
+        for stage in &self.stages {
+            if let Some(unknown) = stage
+                .depends_on
+                .iter()
+                .find(|dep| !pending.contains_key(dep.as_str()))
+            {
+                return Err(ComputeError::InvalidInput(format!(
+                    "Stage {:?} depends on unknown stage {:?}",
+                    stage.name, unknown
+                )));
+            }
+        }
+
+        let mut order: Vec<&str> = Vec::with_capacity(self.stages.len());
+        let mut ready: Vec<&str> = self
+            .stages
+            .iter()
+            .filter(|stage| stage.depends_on.is_empty())
+            .map(|stage| stage.name.as_str())
+            .collect();
+        while let Some(done) = ready.pop() {
+            order.push(done);
+            for stage in &self.stages {
+                for _ in stage.depends_on.iter().filter(|dep| *dep == done) {
+                    let count = pending.get_mut(stage.name.as_str()).expect("known stage");
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(&stage.name);
+                    }
+                }
+            }
+        }
+        if order.len() != self.stages.len() {
+            return Err(ComputeError::InvalidInput(
+                "Job graph has a dependency cycle".into(),
+            ));
+        }
+        Ok(order)
+    }
+
+    fn stage(&self, name: &str) -> &Stage {
+        self.stages
+            .iter()
+            .find(|stage| stage.name == name)
+            .expect("stage names come from the graph")
+    }
+
+    /// Split settings of the source stages, as a job manifest
+    fn split_job(&self) -> JobManifest {
+        let mut job = JobManifest::new(self.job_id.clone(), Vec::new(), Vec::new());
+        job.split_strategy = self.split_strategy;
+        job.delimiter = self.delimiter.clone();
+        job.min_chunk_size = self.min_chunk_size;
+        job.max_chunk_size = self.max_chunk_size;
+        job
+    }
+}
+
+/// Where one stage of a running graph stands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageStatus {
+    pub name: String,
+    pub status: TaskStatus,
+    pub tasks_total: usize,
+    pub tasks_done: usize,
+    pub output_partitions: usize,
+    pub output_bytes: u64,
+    pub error: Option<String>,
+}
+
+/// Per-stage status of a graph run, readable while it runs
+#[derive(Debug, Clone, Default)]
+pub struct GraphProgress {
+    stages: Arc<Mutex<Vec<StageStatus>>>,
+}
+
+impl GraphProgress {
+    pub fn snapshot(&self) -> Vec<StageStatus> {
+        self.stages.lock().clone()
+    }
+
+    fn reset(&self, graph: &JobGraph) {
+        *self.stages.lock() = graph
+            .stages
+            .iter()
+            .map(|stage| StageStatus {
+                name: stage.name.clone(),
+                status: TaskStatus::Pending,
+                tasks_total: 0,
+                tasks_done: 0,
+                output_partitions: 0,
+                output_bytes: 0,
+                error: None,
+            })
+            .collect();
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut StageStatus)) {
+        if let Some(stage) = self.stages.lock().iter_mut().find(|s| s.name == name) {
+            f(stage);
+        }
+    }
+}
+
+/// Outputs of a finished graph
+#[derive(Debug, Clone)]
+pub struct GraphResult {
+    /// Partitions produced by each stage no other stage depends on
+    pub outputs: HashMap<String, Vec<Vec<u8>>>,
+    pub stages: Vec<StageStatus>,
+}
+
+impl ComputeEngine {
+    /// Run every stage of `graph`, each once its dependencies finished
+    ///
+    /// A failing stage fails the run; stages that have not started are
+    /// marked cancelled in `progress`.
+    pub async fn run_graph(
+        &self,
+        graph: &JobGraph,
+        progress: &GraphProgress,
+    ) -> Result<GraphResult, ComputeError> {
+        let order = graph.topological_order()?;
+        progress.reset(graph);
+        info!(
+            "Running job graph {} ({} stages)",
+            graph.job_id,
+            order.len()
+        );
+
+        let mut outputs: HashMap<&str, Arc<Vec<Vec<u8>>>> = HashMap::new();
+        let mut done: HashSet<&str> = HashSet::new();
+        while done.len() < order.len() {
+            let wave: Vec<&Stage> = order
+                .iter()
+                .filter(|name| !done.contains(*name))
+                .map(|name| graph.stage(name))
+                .filter(|stage| {
+                    stage
+                        .depends_on
+                        .iter()
+                        .all(|dep| done.contains(dep.as_str()))
+                })
+                .collect();
+
+            let results = join_all(wave.iter().map(|stage| {
+                let input = stage
+                    .depends_on
+                    .iter()
+                    .map(|dep| outputs[dep.as_str()].clone())
+                    .collect();
+                self.run_stage(graph, stage, input, progress)
+            }))
+            .await;
+
+            for (stage, result) in wave.iter().copied().zip(results) {
+                match result {
+                    Ok(output) => {
+                        outputs.insert(&stage.name, Arc::new(output));
+                        done.insert(&stage.name);
+                    }
+                    Err(e) => {
+                        warn!("Stage {} of job {} failed: {}", stage.name, graph.job_id, e);
+                        for status in progress.stages.lock().iter_mut() {
+                            if status.status == TaskStatus::Pending {
+                                status.status = TaskStatus::Cancelled;
+                            }
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        let sinks = graph
+            .stages
+            .iter()
+            .filter(|stage| {
+                !graph
+                    .stages
+                    .iter()
+                    .any(|other| other.depends_on.contains(&stage.name))
+            })
+            .map(|stage| {
+                let output = outputs.remove(stage.name.as_str()).expect("stage ran");
+                let output = Arc::try_unwrap(output).unwrap_or_else(|shared| (*shared).clone());
+                (stage.name.clone(), output)
+            })
+            .collect();
+        info!("Job graph {} completed", graph.job_id);
+        Ok(GraphResult {
+            outputs: sinks,
+            stages: progress.snapshot(),
+        })
+    }
+
+    async fn run_stage(
+        &self,
+        graph: &JobGraph,
+        stage: &Stage,
+        inputs: Vec<Arc<Vec<Vec<u8>>>>,
+        progress: &GraphProgress,
+    ) -> Result<Vec<Vec<u8>>, ComputeError> {
+        let partitions: Vec<Vec<u8>> = if stage.depends_on.is_empty() {
+            let (chunks, _) = self
+                .executor
+                .split_data(&graph.split_job(), &graph.input_data)?;
+            chunks
+        } else {
+            inputs
+                .iter()
+                .flat_map(|input| input.iter().cloned())
+                .collect()
+        };
+
+        let inputs: Vec<Vec<u8>> = match stage.kind {
+            StageKind::Map => partitions,
+            StageKind::Reduce => vec![self.executor.serialize_chunks(&partitions)?],
+            StageKind::Shuffle {
+                partitions: buckets,
+            } => {
+                progress.update(&stage.name, |s| s.status = TaskStatus::Computing);
+                let output = shuffle(&partitions, buckets as usize);
+                finish_stage(progress, &stage.name, &output);
+                return Ok(output);
+            }
+        };
+        progress.update(&stage.name, |s| {
+            s.status = TaskStatus::Computing;
+            s.tasks_total = inputs.len();
+        });
+
+        let tasks = inputs.into_iter().enumerate().map(|(index, input)| {
+            let mut task = ComputeTask::new(
+                format!("{}/{}", graph.job_id, stage.name),
+                index as u32,
+                stage.wasm_module.clone(),
+                input,
+            );
+            task.function_name = stage.function_name.clone();
+            async move {
+                let result = self.process_task(task).await;
+                if result.is_ok() {
+                    progress.update(&stage.name, |s| s.tasks_done += 1);
+                }
+                result
+            }
+        });
+        let results = join_all(tasks).await;
+
+        let mut output = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(result) => output.push(result.result_data),
+                Err(e) => {
+                    progress.update(&stage.name, |s| {
+                        s.status = TaskStatus::Failed;
+                        s.error = Some(e.to_string());
+                    });
+                    return Err(e);
+                }
+            }
+        }
+        finish_stage(progress, &stage.name, &output);
+        Ok(output)
+    }
+}
+
+fn finish_stage(progress: &GraphProgress, name: &str, output: &[Vec<u8>]) {
+    progress.update(name, |s| {
+        s.status = TaskStatus::Completed;
+        s.output_partitions = output.len();
+        s.output_bytes = output.iter().map(|p| p.len() as u64).sum();
+    });
+}
+
+/// Regroup newline-terminated records into `buckets` partitions by key
+fn shuffle(partitions: &[Vec<u8>], buckets: usize) -> Vec<Vec<u8>> {
+    let mut output = vec![Vec::new(); buckets];
+    for partition in partitions {
+        for record in partition.split_inclusive(|&b| b == b'\n') {
+            let key = record
+                .split(|&b| b == b'\t' || b == b'\n')
+                .next()
+                .unwrap_or(record);
+            let bucket = &mut output[(fnv1a(key) % buckets as u64) as usize];
+            bucket.extend_from_slice(record);
+            if !record.ends_with(b"\n") {
+                bucket.push(b'\n');
+            }
+        }
+    }
+    output
+}
+
+/// Stable across nodes and runs, unlike `std`'s randomly seeded hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::ComputeConfig;
+
+    fn engine() -> ComputeEngine {
+        ComputeEngine::new(ComputeConfig {
+            simulation_mode: true,
+            ..ComputeConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_map_shuffle_reduce() {
+        let module = b"module".to_vec();
+        let input: Vec<u8> = (0..3000)
+            .flat_map(|i| format!("key{}\tvalue {}\n", i % 7, i).into_bytes())
+            .collect();
+        let mut graph = JobGraph::new(
+            "wordcount".into(),
+            input.clone(),
+            vec![
+                Stage::reduce("collect", module.clone(), "merge").after(&["group"]),
+                Stage::map("map", module.clone(), "execute"),
+                Stage::shuffle("shuffle", 3).after(&["map"]),
+                Stage::map("group", module, "execute").after(&["shuffle"]),
+            ],
+        );
+        graph.split_strategy = SplitStrategy::RowBased;
+        graph.min_chunk_size = 1024;
+        graph.max_chunk_size = 8192;
+
+        let progress = GraphProgress::default();
+        let result = engine().run_graph(&graph, &progress).await.unwrap();
+
+        // Only the sink is reported, and it holds every record exactly once
+        assert_eq!(result.outputs.len(), 1);
+        let output = &result.outputs["collect"];
+        assert_eq!(output.len(), 1);
+        let mut records: Vec<&[u8]> = output[0].split_inclusive(|&b| b == b'\n').collect();
+        let mut expected: Vec<&[u8]> = input.split_inclusive(|&b| b == b'\n').collect();
+        records.sort();
+        expected.sort();
+        assert_eq!(records, expected);
+
+        let stages = progress.snapshot();
+        assert!(stages.iter().all(|s| s.status == TaskStatus::Completed));
+        let map = stages.iter().find(|s| s.name == "map").unwrap();
+        assert!(map.tasks_total > 1);
+        assert_eq!(map.tasks_done, map.tasks_total);
+        let shuffle = stages.iter().find(|s| s.name == "shuffle").unwrap();
+        assert_eq!(shuffle.output_partitions, 3);
+    }
+
+    #[test]
+    fn test_shuffle_groups_records_by_key() {
+        let partitions = vec![b"a\t1\nb\t2\nc\t3\n".to_vec(), b"b\t4\na\t5\nd".to_vec()];
+        let buckets = shuffle(&partitions, 2);
+        assert_eq!(buckets.len(), 2);
+        for key in [&b"a"[..], b"b", b"c", b"d"] {
+            let holding = buckets
+                .iter()
+                .filter(|bucket| {
+                    bucket
+                        .split(|&b| b == b'\n')
+                        .any(|record| record.split(|&b| b == b'\t').next() == Some(key))
+                })
+                .count();
+            assert_eq!(holding, 1, "key {:?} in several buckets", key);
+        }
+        // The unterminated last record gets its newline
+        assert_eq!(buckets.concat().len(), 23);
+    }
+
+    #[tokio::test]
+    async fn test_failed_stage_cancels_the_rest() {
+        let graph = JobGraph::new(
+            "broken".into(),
+            b"data".to_vec(),
+            vec![
+                Stage::map("first", b"module".to_vec(), "no_such_function"),
+                Stage::reduce("second", b"module".to_vec(), "merge").after(&["first"]),
+            ],
+        );
+        let progress = GraphProgress::default();
+        assert!(engine().run_graph(&graph, &progress).await.is_err());
+
+        let stages = progress.snapshot();
+        assert_eq!(stages[0].status, TaskStatus::Failed);
+        assert!(stages[0].error.is_some());
+        assert_eq!(stages[1].status, TaskStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_invalid_graphs_are_refused() {
+        let module = b"module".to_vec();
+        let cyclic = JobGraph::new(
+            "cyclic".into(),
+            Vec::new(),
+            vec![
+                Stage::map("a", module.clone(), "execute").after(&["b"]),
+                Stage::map("b", module.clone(), "execute").after(&["a"]),
+            ],
+        );
+        assert!(cyclic.topological_order().is_err());
+
+        let unknown = JobGraph::new(
+            "unknown".into(),
+            Vec::new(),
+            vec![Stage::map("a", module.clone(), "execute").after(&["missing"])],
+        );
+        assert!(unknown.topological_order().is_err());
+
+        let diamond = JobGraph::new(
+            "diamond".into(),
+            Vec::new(),
+            vec![
+                Stage::reduce("join", module.clone(), "merge").after(&["left", "right"]),
+                Stage::map("left", module.clone(), "execute").after(&["source"]),
+                Stage::map("right", module.clone(), "execute").after(&["source"]),
+                Stage::map("source", module, "execute"),
+            ],
+        );
+        let order = diamond.topological_order().unwrap();
+        let position = |name| order.iter().position(|s| *s == name).unwrap();
+        assert!(position("source") < position("left"));
+        assert!(position("right") < position("join"));
+        assert!(position("left") < position("join"));
+    }
+}
@@ -1,10 +1,74 @@
 use anyhow::{bail, Result};
 use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
 use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::compute::ComputeTask;
+use crate::envelope::{parse_public_key, NodeKeypair};
 
 const BLOCK_SIZE: usize = 1024;
 
+/// HKDF info string of tunnel keys
+const TUNNEL_INFO: &[u8] = b"pangea-io-tunnel-v1";
+
+/// Key agreement carried by a tunnelled task
+///
+/// The submitter makes an ephemeral X25519 key per task and combines it with
+/// the executor's long-lived node key, so only that executor can derive the
+/// tunnel key and only the submitter can read the results.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TunnelKeyExchange {
+    /// Submitter's ephemeral X25519 public key (hex)
+    pub submitter: String,
+    /// Executor's X25519 node key the task is addressed to (hex)
+    pub executor: String,
+}
+
+/// Submitter's end of a tunnel to one executor, for one task
+pub struct TunnelSession {
+    tunnel: IoTunnel,
+    exchange: TunnelKeyExchange,
+}
+
+impl TunnelSession {
+    /// Agree a tunnel key with the executor holding `executor`'s private key
+    pub fn open(executor: &PublicKey, task_id: &str) -> Self {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let submitter = PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(executor).to_bytes();
+        let key = tunnel_key(&shared, &submitter, executor, task_id);
+        Self {
+            tunnel: IoTunnel::new(&key).expect("tunnel keys are 32 bytes"),
+            exchange: TunnelKeyExchange {
+                submitter: hex::encode(submitter.as_bytes()),
+                executor: hex::encode(executor.as_bytes()),
+            },
+        }
+    }
+
+    pub fn exchange(&self) -> &TunnelKeyExchange {
+        &self.exchange
+    }
+
+    /// Encrypt the task's input and attach the key agreement
+    ///
+    /// The task ID must be the one the session was opened for.
+    pub fn seal(&self, task: &mut ComputeTask) -> Result<()> {
+        task.input_data = self.tunnel.encrypt(&task.input_data)?;
+        task.tunnel = Some(self.exchange.clone());
+        Ok(())
+    }
+
+    /// Decrypt a result returned by the sandbox
+    pub fn decrypt_result(&self, result: &[u8]) -> Result<Vec<u8>> {
+        self.tunnel.decrypt(result)
+    }
+}
+
 /// IoTunnel provides AEAD encryption + fixed-block padding for WASM I/O
 pub struct IoTunnel {
     aead: XChaCha20Poly1305,
@@ -20,6 +84,23 @@ impl IoTunnel {
         Ok(Self { aead })
     }
 
+    /// Executor's end of a tunnel agreed by `exchange` for `task_id`
+    ///
+    /// Fails when the exchange is addressed to another node's key.
+    pub fn accept(
+        keypair: &NodeKeypair,
+        exchange: &TunnelKeyExchange,
+        task_id: &str,
+    ) -> Result<Self> {
+        if exchange.executor != keypair.public_key_hex() {
+            bail!("tunnel is addressed to another node")
+        }
+        let submitter = parse_public_key(&exchange.submitter)?;
+        let shared = keypair.diffie_hellman(&submitter);
+        let key = tunnel_key(&shared, &submitter, &keypair.public_key(), task_id);
+        Self::new(&key)
+    }
+
     /// Encrypt with nonce prefix + ciphertext. Pads to multiple of BLOCK_SIZE using length-prefix.
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
         // Prepare buffer with length prefix
@@ -68,6 +149,25 @@ impl IoTunnel {
     }
 }
 
+/// Tunnel key bound to both public keys and the task
+fn tunnel_key(
+    shared: &[u8; 32],
+    submitter: &PublicKey,
+    executor: &PublicKey,
+    task_id: &str,
+) -> [u8; 32] {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(submitter.as_bytes());
+    salt[32..].copy_from_slice(executor.as_bytes());
+    let info = [TUNNEL_INFO, task_id.as_bytes()].concat();
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(&info, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,4 +186,26 @@ mod tests {
         let p = tunnel.decrypt(&c).expect("decrypt");
         assert_eq!(p.as_slice(), plain);
     }
+
+    #[test]
+    fn test_key_exchange_reaches_only_the_executor() {
+        let executor = NodeKeypair::generate(2);
+        let other = NodeKeypair::generate(3);
+        let session = TunnelSession::open(&executor.public_key(), "job:0");
+
+        let tunnel = IoTunnel::accept(&executor, session.exchange(), "job:0").unwrap();
+        let result = tunnel.encrypt(b"result").unwrap();
+        assert_eq!(session.decrypt_result(&result).unwrap(), b"result");
+
+        // Other nodes, and the same key for another task, cannot read it
+        assert!(IoTunnel::accept(&other, session.exchange(), "job:0").is_err());
+        let misaddressed = TunnelKeyExchange {
+            executor: other.public_key_hex(),
+            ..session.exchange().clone()
+        };
+        let wrong = IoTunnel::accept(&other, &misaddressed, "job:0").unwrap();
+        assert!(wrong.decrypt(&result).is_err());
+        let replayed = IoTunnel::accept(&executor, session.exchange(), "job:1").unwrap();
+        assert!(replayed.decrypt(&result).is_err());
+    }
 }
@@ -16,9 +16,14 @@
 //! └─────────────────────────────────────────────────────────────┘
 //! ```
 
+mod admission;
+mod aot;
+mod attestation;
 mod executor;
+mod graph;
 mod io_tunnel;
 mod metering;
+mod pool;
 mod sandbox;
 mod types;
 mod verification;
@@ -29,14 +34,23 @@ pub use types::{
     SplitStrategy, TaskResult, TaskStatus, VerificationMode,
 };
 
+pub use admission::{Admission, AdmissionStats, Reservation};
+pub use aot::{engine_fingerprint, AotCache, AOT_CACHE_DIR};
+pub use attestation::{AttestationPolicy, ExecutionAttestation, ExecutionReport};
 pub use executor::{ComputeExecutor, ExecutionContext};
+pub use graph::{GraphProgress, GraphResult, JobGraph, Stage, StageKind, StageStatus};
+pub use io_tunnel::{IoTunnel, TunnelKeyExchange, TunnelSession};
 pub use metering::{Metering, ResourceLimits, ResourceUsage};
+pub use pool::SandboxPool;
 pub use sandbox::{SandboxConfig, WasmSandbox};
 pub use verification::{MerkleTree, ResultVerifier, VerificationResult};
 
+use crate::blob::BlobStore;
+use crate::envelope::NodeKeypair;
+use crate::identity::NodeIdentity;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, info, instrument};
 
 /// Main entry point for the Compute Engine
 ///
@@ -44,10 +58,15 @@ use tracing::{debug, info};
 /// and verification of compute tasks.
 pub struct ComputeEngine {
     config: ComputeConfig,
-    sandbox: Arc<RwLock<WasmSandbox>>,
+    sandboxes: SandboxPool,
     executor: Arc<ComputeExecutor>,
     verifier: Arc<ResultVerifier>,
     capacity: Arc<RwLock<ComputeCapacity>>,
+    admission: Admission,
+    keypair: Option<Arc<NodeKeypair>>,
+    identity: Option<Arc<NodeIdentity>>,
+    /// Runtime fingerprint reported in attestations (hex)
+    fingerprint: String,
 }
 
 impl ComputeEngine {
@@ -68,63 +87,189 @@ impl ComputeEngine {
             simulation_mode: config.simulation_mode,
         };
 
-        let sandbox = WasmSandbox::new(sandbox_config)?;
+        let mut sandbox = WasmSandbox::new(sandbox_config.clone())?;
+        if let Some(dir) = &config.aot_cache_dir {
+            let aot = AotCache::open(dir, &sandbox_config)?;
+            info!("Compiled modules cached in {:?}", dir);
+            sandbox = sandbox.with_aot_cache(Arc::new(aot));
+        }
+        // One sandbox per worker, so `worker_threads` tasks run at once
+        let fingerprint = hex::encode(engine_fingerprint(&sandbox_config));
+        let sandboxes = SandboxPool::with_template(sandbox, config.worker_threads);
         let executor = ComputeExecutor::new(config.clone());
         let verifier = ResultVerifier::new(config.verification_mode);
 
         // Probe system capacity
         let capacity = ComputeCapacity::probe();
         info!("Compute capacity: {:?}", capacity);
+        let memory_budget_mb = match config.max_reserved_memory_mb {
+            0 => capacity.ram_mb,
+            limit => limit,
+        };
+        let admission = Admission::new(
+            memory_budget_mb,
+            config.worker_threads.min(capacity.cpu_cores as usize),
+            config.max_load,
+            config.max_queued_tasks,
+            std::time::Duration::from_millis(config.queue_timeout_ms),
+        );
+        admission.set_load(capacity.current_load);
 
         Ok(Self {
             config,
-            sandbox: Arc::new(RwLock::new(sandbox)),
+            sandboxes,
             executor: Arc::new(executor),
             verifier: Arc::new(verifier),
             capacity: Arc::new(RwLock::new(capacity)),
+            admission,
+            keypair: None,
+            identity: None,
+            fingerprint,
         })
     }
 
+    /// Keep compiled modules in `blobs`, shared with other subsystems
+    pub fn with_blob_store(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.sandboxes = self.sandboxes.with_blob_store(blobs);
+        self
+    }
+
+    /// Attest each result with `identity`
+    pub fn with_identity(mut self, identity: Arc<NodeIdentity>) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Accept tunnelled tasks addressed to `keypair`'s public key
+    pub fn with_keypair(mut self, keypair: Arc<NodeKeypair>) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
     /// Process a compute task
     ///
     /// This is the main entry point for executing a compute task.
-    /// The task's WASM module is loaded into the sandbox, executed with
-    /// resource limits, and the result is verified before returning.
+    /// The task's WASM module is executed in one of the engine's sandboxes
+    /// with resource limits, and the result is verified before returning.
+    /// Up to `worker_threads` tasks execute at once.
+    /// Tunnelled tasks are decrypted and their results encrypted inside the
+    /// sandbox; the result hash covers the encrypted result.
+    /// With an identity, the result carries a signed [`ExecutionAttestation`].
+    /// It runs only once admission control reserved the memory it may use;
+    /// until then it waits in the queue, or fails with
+    /// [`ComputeError::Rejected`].
+    #[instrument(skip_all, fields(task_id = %task.task_id, function = %task.function_name))]
     pub async fn process_task(&self, task: ComputeTask) -> Result<TaskResult, ComputeError> {
+        let _reservation = self
+            .admission
+            .admit(self.projected_memory_mb(&task))
+            .await?;
         let start = std::time::Instant::now();
+        let started_at_ms = chrono::Utc::now().timestamp_millis();
         debug!("Processing task: {}", task.task_id);
 
-        // Load WASM module if not already loaded
-        let sandbox = self.sandbox.write().await;
-
-        // Execute in sandbox
-        let result_data =
-            sandbox.execute(&task.wasm_module, &task.input_data, &task.function_name)?;
-
-        drop(sandbox);
-
-        // Verify result
-        let result_hash = self.verifier.hash_result(&result_data);
-        let merkle_proof = if self.config.verification_mode == VerificationMode::Merkle {
-            Some(self.verifier.create_merkle_proof(&result_data)?)
-        } else {
-            None
+        // Execute in a sandbox of the pool and hash the result on the same
+        // blocking thread, keeping both off the async runtime
+        let verifier = self.verifier.clone();
+        let merkle = self.config.verification_mode == VerificationMode::Merkle;
+        let tunnel = match (&task.tunnel, &self.keypair) {
+            (Some(exchange), Some(keypair)) => Some(
+                IoTunnel::accept(keypair, exchange, &task.task_id)
+                    .map_err(|e| ComputeError::InvalidInput(format!("I/O tunnel: {:#}", e)))?,
+            ),
+            (Some(_), None) => {
+                return Err(ComputeError::InvalidInput(
+                    "This node does not accept tunnelled tasks".into(),
+                ))
+            }
+            (None, _) => None,
         };
+        let tunnelled = tunnel.is_some();
+        let attest = self.identity.is_some();
+        let ComputeTask {
+            task_id,
+            wasm_module,
+            input_data,
+            function_name,
+            ..
+        } = task;
+        let report_function = function_name.clone();
+        let input_bytes = input_data.len() as u64;
+        let (result_data, result_hash, merkle_proof, hashes) = self
+            .sandboxes
+            .run(move |sandbox| {
+                let hashes = attest.then(|| {
+                    (
+                        attestation::sha256_hex(&wasm_module),
+                        attestation::sha256_hex(&input_data),
+                    )
+                });
+                let result_data = sandbox.execute_with_tunnel(
+                    &wasm_module,
+                    &input_data,
+                    &function_name,
+                    tunnel.as_ref(),
+                )?;
+                let result_hash = verifier.hash_result(&result_data);
+                let merkle_proof = if merkle {
+                    Some(verifier.create_merkle_proof(&result_data)?)
+                } else {
+                    None
+                };
+                Ok((result_data, result_hash, merkle_proof, hashes))
+            })
+            .await?;
 
         let execution_time_ms = start.elapsed().as_millis() as u64;
 
-        let result = TaskResult {
-            task_id: task.task_id.clone(),
+        let attestation = match (&self.identity, hashes) {
+            (Some(identity), Some((module_hash, input_hash))) => {
+                let report = ExecutionReport {
+                    task_id: task_id.clone(),
+                    engine_version: env!("CARGO_PKG_VERSION").to_string(),
+                    engine_fingerprint: self.fingerprint.clone(),
+                    simulation_mode: self.config.simulation_mode,
+                    module_hash,
+                    function_name: report_function,
+                    input_hash,
+                    result_hash: result_hash.clone(),
+                    tunnelled,
+                    max_memory_bytes: self.config.max_memory_mb * 1024 * 1024,
+                    max_cpu_cycles: self.config.max_cpu_cycles,
+                    input_bytes,
+                    output_bytes: result_data.len() as u64,
+                    started_at_ms,
+                    finished_at_ms: chrono::Utc::now().timestamp_millis(),
+                };
+                Some(
+                    ExecutionAttestation::sign(identity, report)
+                        .map_err(|e| ComputeError::Internal(format!("{:#}", e)))?,
+                )
+            }
+            _ => None,
+        };
+
+        info!("Task {} completed in {}ms", task_id, execution_time_ms);
+        Ok(TaskResult {
+            task_id,
             status: TaskStatus::Completed,
             result_data,
             result_hash,
             merkle_proof,
             execution_time_ms,
             error_message: None,
-        };
+            attestation,
+        })
+    }
 
-        info!("Task {} completed in {}ms", task.task_id, execution_time_ms);
-        Ok(result)
+    /// Compile a module ahead of the tasks that use it
+    ///
+    /// Returns the module hash. With an AOT cache configured the compiled
+    /// module is also kept on disk for later runs.
+    pub async fn precompile(&self, wasm_module: Vec<u8>) -> Result<String, ComputeError> {
+        self.sandboxes
+            .run(move |sandbox| sandbox.load_module(&wasm_module))
+            .await
     }
 
     /// Split data using the job's split function
@@ -135,12 +280,12 @@ impl ComputeEngine {
     ) -> Result<Vec<Vec<u8>>, ComputeError> {
         debug!("Splitting data for job: {}", job.job_id);
 
-        let sandbox = self.sandbox.write().await;
-
         // Execute split function
-        let chunks_data = sandbox.execute(&job.wasm_module, data, "split")?;
-
-        drop(sandbox);
+        let (module, data) = (job.wasm_module.clone(), data.to_vec());
+        let chunks_data = self
+            .sandboxes
+            .run(move |sandbox| sandbox.execute(&module, &data, "split"))
+            .await?;
 
         // Deserialize chunks (assuming they're length-prefixed)
         let chunks = self.executor.deserialize_chunks(&chunks_data)?;
@@ -164,12 +309,12 @@ impl ComputeEngine {
         // Serialize results for WASM
         let merged_input = self.executor.serialize_chunks(&results)?;
 
-        let sandbox = self.sandbox.write().await;
-
         // Execute merge function
-        let merged = sandbox.execute(&job.wasm_module, &merged_input, "merge")?;
-
-        drop(sandbox);
+        let module = job.wasm_module.clone();
+        let merged = self
+            .sandboxes
+            .run(move |sandbox| sandbox.execute(&module, &merged_input, "merge"))
+            .await?;
 
         info!("Merged {} results for job {}", results.len(), job.job_id);
         Ok(merged)
@@ -184,6 +329,18 @@ impl ComputeEngine {
     pub async fn update_load(&self, current_load: f32) {
         let mut capacity = self.capacity.write().await;
         capacity.current_load = current_load;
+        self.admission.set_load(current_load);
+    }
+
+    /// Admitted, queued and rejected tasks, and the capacity reserved now
+    pub fn admission_stats(&self) -> AdmissionStats {
+        self.admission.stats()
+    }
+
+    /// Memory a task may use: its sandbox limit plus its input and output
+    fn projected_memory_mb(&self, task: &ComputeTask) -> u64 {
+        let data_mb = (task.input_data.len() as u64 * 2).div_ceil(1024 * 1024);
+        self.config.max_memory_mb + data_mb
     }
 
     /// Verify a task result
@@ -209,6 +366,7 @@ impl Default for ComputeEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorCode;
 
     #[tokio::test]
     async fn test_compute_engine_creation() {
@@ -217,6 +375,124 @@ mod tests {
         assert!(engine.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_oversized_tasks_are_rejected_with_a_code() {
+        let config = ComputeConfig {
+            max_memory_mb: 64,
+            max_reserved_memory_mb: 32,
+            ..ComputeConfig::default()
+        };
+        let engine = ComputeEngine::new(config).unwrap();
+        let task = ComputeTask::new("job".into(), 0, Vec::new(), vec![1, 2, 3]);
+
+        let err = engine.process_task(task).await.unwrap_err();
+        match err {
+            ComputeError::Rejected(e) => {
+                assert_eq!(e.code(), crate::error::codes::COMPUTE_INSUFFICIENT_MEMORY)
+            }
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        assert_eq!(engine.admission_stats().rejected_memory, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_tasks_run_concurrently() {
+        let config = ComputeConfig {
+            worker_threads: 4,
+            simulation_mode: true,
+            ..ComputeConfig::default()
+        };
+        let engine = Arc::new(ComputeEngine::new(config).unwrap());
+        let tasks = (0..8).map(|i| {
+            let engine = engine.clone();
+            let task = ComputeTask::new("job".into(), i, b"module".to_vec(), vec![i as u8; 1024]);
+            tokio::spawn(async move { engine.process_task(task).await })
+        });
+        for (i, result) in futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .enumerate()
+        {
+            let result = result.unwrap().unwrap();
+            assert_eq!(result.task_id, format!("job:{}", i));
+            assert_eq!(result.result_data, vec![i as u8; 1024]);
+        }
+        assert_eq!(engine.admission_stats().running, 0);
+    }
+
+    #[tokio::test]
+    async fn test_tunnelled_task_is_readable_by_the_submitter_only() {
+        let config = ComputeConfig {
+            simulation_mode: true,
+            ..ComputeConfig::default()
+        };
+        let keypair = Arc::new(NodeKeypair::generate(2));
+        let engine = ComputeEngine::new(config.clone())
+            .unwrap()
+            .with_keypair(keypair.clone());
+
+        let mut task = ComputeTask::new("job".into(), 0, b"module".to_vec(), b"secret".to_vec());
+        let session = TunnelSession::open(&keypair.public_key(), &task.task_id);
+        session.seal(&mut task).unwrap();
+        assert_ne!(task.input_data, b"secret");
+
+        let result = engine.process_task(task.clone()).await.unwrap();
+        assert_ne!(result.result_data, b"secret");
+        assert_eq!(
+            session.decrypt_result(&result.result_data).unwrap(),
+            b"secret"
+        );
+
+        // Nodes without the addressed key refuse the task
+        let other = ComputeEngine::new(config)
+            .unwrap()
+            .with_keypair(Arc::new(NodeKeypair::generate(3)));
+        assert!(matches!(
+            other.process_task(task).await,
+            Err(ComputeError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_results_are_attested() {
+        let config = ComputeConfig {
+            simulation_mode: true,
+            ..ComputeConfig::default()
+        };
+        let identity = Arc::new(NodeIdentity::generate());
+        let engine = ComputeEngine::new(config)
+            .unwrap()
+            .with_identity(identity.clone());
+        let task = ComputeTask::new("job".into(), 0, b"module".to_vec(), b"data".to_vec());
+        let mut result = engine.process_task(task).await.unwrap();
+
+        let report = &result.attestation.as_ref().unwrap().report;
+        assert!(report.simulation_mode);
+        assert_eq!(report.engine_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(report.input_bytes, 4);
+        let module_hash = report.module_hash.clone();
+
+        // Simulation-mode results are refused unless allowed
+        let mut policy = AttestationPolicy {
+            signers: vec![identity.peer_id()],
+            module_hash: Some(module_hash),
+            ..AttestationPolicy::default()
+        };
+        assert!(policy.check(&result).is_err());
+        policy.allow_simulation = true;
+        assert_eq!(policy.check(&result).unwrap(), identity.peer_id());
+
+        // Other engines, or a report altered after signing, are refused
+        policy.engine_versions = vec!["0.0.0".to_string()];
+        assert!(policy.check(&result).is_err());
+        policy.engine_versions.clear();
+        let attestation = result.attestation.as_mut().unwrap();
+        attestation.report.simulation_mode = false;
+        assert!(policy.check(&result).is_err());
+        result.attestation = None;
+        assert!(policy.check(&result).is_err());
+    }
+
     #[tokio::test]
     async fn test_get_capacity() {
         let engine = ComputeEngine::default();
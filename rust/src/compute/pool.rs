@@ -0,0 +1,133 @@
+//! Pool of sandboxes for concurrent execution
+//!
+//! Each running task takes a sandbox of its own and runs on the blocking
+//! thread pool, so up to `size` tasks execute in parallel without holding
+//! up the async runtime. All sandboxes of a pool share one module cache.
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::blob::BlobStore;
+use crate::compute::sandbox::{SandboxConfig, WasmSandbox};
+use crate::compute::types::ComputeError;
+
+/// A fixed number of sandboxes, handed out one per task
+pub struct SandboxPool {
+    /// Never executes; idle sandboxes lost to a panicking task are forked from it
+    template: WasmSandbox,
+    idle: Arc<Mutex<Vec<WasmSandbox>>>,
+    available: Arc<Semaphore>,
+    size: usize,
+}
+
+impl SandboxPool {
+    pub fn new(config: SandboxConfig, size: usize) -> Result<Self, ComputeError> {
+        Ok(Self::with_template(WasmSandbox::new(config)?, size))
+    }
+
+    /// A pool of `size` forks of `template`
+    pub fn with_template(template: WasmSandbox, size: usize) -> Self {
+        let size = size.max(1);
+        let idle = (0..size).map(|_| template.fork()).collect();
+        Self {
+            template,
+            idle: Arc::new(Mutex::new(idle)),
+            available: Arc::new(Semaphore::new(size)),
+            size,
+        }
+    }
+
+    /// The same pool, keeping compiled modules in `blobs`
+    pub fn with_blob_store(self, blobs: Arc<dyn BlobStore>) -> Self {
+        Self::with_template(self.template.with_blob_store(blobs), self.size)
+    }
+
+    /// Sandboxes in the pool, i.e. tasks that can run at once
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Run `work` on an idle sandbox, waiting for one if all are busy
+    ///
+    /// The sandbox goes back to the pool when `work` returns, even if the
+    /// caller stopped waiting for the result.
+    pub async fn run<F, T>(&self, work: F) -> Result<T, ComputeError>
+    where
+        F: FnOnce(&WasmSandbox) -> Result<T, ComputeError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self
+            .available
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| ComputeError::Internal("Sandbox pool closed".into()))?;
+        let sandbox = self
+            .idle
+            .lock()
+            .pop()
+            .unwrap_or_else(|| self.template.fork());
+        let idle = self.idle.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = work(&sandbox);
+            idle.lock().push(sandbox);
+            drop(permit);
+            result
+        })
+        .await
+        .map_err(|e| ComputeError::Internal(format!("Sandbox task failed: {}", e)))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    fn pool(size: usize) -> SandboxPool {
+        SandboxPool::new(
+            SandboxConfig {
+                simulation_mode: true,
+                ..Default::default()
+            },
+            size,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tasks_run_side_by_side() {
+        let pool = pool(2);
+        // Each task waits for the other: this only finishes if both run at once
+        let barrier = Arc::new(Barrier::new(2));
+        let task = |barrier: Arc<Barrier>| {
+            pool.run(move |sandbox| {
+                barrier.wait();
+                sandbox.execute(b"module", b"data", "execute")
+            })
+        };
+        let both = futures::future::join(task(barrier.clone()), task(barrier));
+        let (a, b) = tokio::time::timeout(Duration::from_secs(5), both)
+            .await
+            .expect("tasks were serialized");
+        assert_eq!(a.unwrap(), b"data");
+        assert_eq!(b.unwrap(), b"data");
+        assert_eq!(pool.idle.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sandboxes_survive_a_panicking_task() {
+        let pool = pool(1);
+        let failed = pool.run(|_| -> Result<(), ComputeError> { panic!("boom") });
+        assert!(matches!(failed.await, Err(ComputeError::Internal(_))));
+
+        // The lost sandbox is replaced on the next run
+        let output = pool
+            .run(|sandbox| sandbox.execute(b"module", b"data", "execute"))
+            .await
+            .unwrap();
+        assert_eq!(output, b"data");
+    }
+}
@@ -19,11 +19,17 @@
 //! - No network access
 //! - No filesystem access (unless WASI is explicitly enabled)
 
+use crate::blob::{BlobId, BlobStore, TieredBlobStore};
+use crate::compute::aot::AotCache;
 use crate::compute::io_tunnel::IoTunnel;
 use crate::compute::metering::ResourceLimits;
 use crate::compute::types::ComputeError;
+use bytes::Bytes;
+use parking_lot::RwLock;
 use sha2::{Digest, Sha256};
-use tracing::{debug, info};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
 
 /// Configuration for the WASM sandbox
 #[derive(Debug, Clone)]
@@ -63,8 +69,12 @@ impl Default for SandboxConfig {
 pub struct WasmSandbox {
     config: SandboxConfig,
     resource_limits: ResourceLimits,
-    /// Cached module hash -> compiled module
-    module_cache: std::collections::HashMap<String, CachedModule>,
+    /// Cached module hash -> compiled module, shared with forked sandboxes
+    module_cache: Arc<RwLock<HashMap<String, CachedModule>>>,
+    /// Compiled modules kept across restarts
+    aot_cache: Option<Arc<AotCache>>,
+    /// Compiled artifacts of the modules in `module_cache`
+    blobs: Arc<dyn BlobStore>,
 }
 
 /// A cached compiled module
@@ -74,6 +84,10 @@ struct CachedModule {
     hash: String,
     /// Size of the module
     size: usize,
+    /// Compiled code, in the blob store
+    artifact: BlobId,
+    /// Loaded from the on-disk cache rather than compiled
+    precompiled: bool,
     /// Compilation time
     _compiled_at: std::time::Instant,
 }
@@ -97,10 +111,41 @@ impl WasmSandbox {
         Ok(Self {
             config,
             resource_limits,
-            module_cache: std::collections::HashMap::new(),
+            module_cache: Arc::default(),
+            aot_cache: None,
+            blobs: Arc::new(TieredBlobStore::unbounded()),
         })
     }
 
+    /// Keep compiled modules in `cache` so they are not compiled again
+    pub fn with_aot_cache(mut self, cache: Arc<AotCache>) -> Self {
+        self.aot_cache = Some(cache);
+        self
+    }
+
+    /// Keep compiled modules in `blobs`, shared with other subsystems
+    ///
+    /// Modules loaded so far are forgotten, so call this before loading any.
+    pub fn with_blob_store(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.module_cache = Arc::default();
+        self.blobs = blobs;
+        self
+    }
+
+    /// Another sandbox with the same limits, sharing this one's module cache
+    ///
+    /// Sandboxes are used by one task at a time; forks let tasks run side by
+    /// side without compiling the same module once per sandbox.
+    pub fn fork(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            resource_limits: self.resource_limits.clone(),
+            module_cache: self.module_cache.clone(),
+            aot_cache: self.aot_cache.clone(),
+            blobs: self.blobs.clone(),
+        }
+    }
+
     /// Execute a WASM function with the given input
     ///
     /// This is the main entry point for WASM execution.
@@ -315,23 +360,58 @@ impl WasmSandbox {
     }
 
     /// Load and cache a WASM module
-    pub fn load_module(&mut self, wasm_bytes: &[u8]) -> Result<String, ComputeError> {
+    ///
+    /// A module compiled before, by this node and with the same runtime
+    /// settings, is read back from the AOT cache instead of compiled.
+    pub fn load_module(&self, wasm_bytes: &[u8]) -> Result<String, ComputeError> {
         let hash = self.hash_module(wasm_bytes);
 
-        if !self.module_cache.contains_key(&hash) {
+        if !self.is_module_cached(&hash) {
             // Validate module (basic check - real implementation would parse WASM)
             if !self.validate_module(wasm_bytes) {
                 return Err(ComputeError::WasmLoadError("Invalid WASM module".into()));
             }
 
+            let digest: [u8; 32] = Sha256::digest(wasm_bytes).into();
+            let stored = self.aot_cache.as_ref().and_then(|aot| aot.get(&digest));
+            let precompiled = stored.is_some();
+            let artifact = match stored {
+                Some(artifact) => artifact,
+                None => {
+                    let artifact = self.compile(wasm_bytes)?;
+                    if let Some(aot) = &self.aot_cache {
+                        if let Err(e) = aot.put(&digest, &artifact) {
+                            warn!("Failed to store compiled module {}: {}", &hash[..16], e);
+                        }
+                    }
+                    artifact
+                }
+            };
+
+            let artifact = self.blobs.put(&artifact).map_err(|e| {
+                ComputeError::WasmLoadError(format!("No room for compiled module: {}", e))
+            })?;
             let cached = CachedModule {
                 hash: hash.clone(),
                 size: wasm_bytes.len(),
+                artifact,
+                precompiled,
                 _compiled_at: std::time::Instant::now(),
             };
 
-            self.module_cache.insert(hash.clone(), cached);
-            info!("Loaded and cached WASM module: {}", &hash[..16]);
+            // Another sandbox of the family may have loaded it meanwhile
+            if let Some(replaced) = self.module_cache.write().insert(hash.clone(), cached) {
+                self.blobs.release(&replaced.artifact);
+            }
+            info!(
+                "Loaded and cached WASM module: {} ({})",
+                &hash[..16],
+                if precompiled {
+                    "precompiled"
+                } else {
+                    "compiled"
+                }
+            );
         } else {
             debug!("Using cached WASM module: {}", &hash[..16]);
         }
@@ -339,14 +419,37 @@ impl WasmSandbox {
         Ok(hash)
     }
 
+    /// Compile a validated module for this sandbox's runtime
+    ///
+    /// The simulated runtime has nothing to compile, so the artifact is the
+    /// module itself; with Wasmtime this is `Engine::precompile_module`,
+    /// and loading goes through `Module::deserialize`.
+    fn compile(&self, wasm_bytes: &[u8]) -> Result<Vec<u8>, ComputeError> {
+        Ok(wasm_bytes.to_vec())
+    }
+
+    /// Whether a module was read back from the AOT cache rather than compiled
+    pub fn is_module_precompiled(&self, hash: &str) -> bool {
+        self.module_cache
+            .read()
+            .get(hash)
+            .is_some_and(|module| module.precompiled)
+    }
+
+    /// Compiled code of a loaded module
+    pub fn module_artifact(&self, hash: &str) -> Option<Bytes> {
+        let artifact = self.module_cache.read().get(hash)?.artifact;
+        self.blobs.get(&artifact)
+    }
+
     /// Check if a module is cached
     pub fn is_module_cached(&self, hash: &str) -> bool {
-        self.module_cache.contains_key(hash)
+        self.module_cache.read().contains_key(hash)
     }
 
     /// Get cached module info
     pub fn get_cached_module_size(&self, hash: &str) -> Option<usize> {
-        self.module_cache.get(hash).map(|m| m.size)
+        self.module_cache.read().get(hash).map(|m| m.size)
     }
 
     /// Basic WASM module validation
@@ -376,8 +479,10 @@ impl WasmSandbox {
     }
 
     /// Clear the module cache
-    pub fn clear_cache(&mut self) {
-        self.module_cache.clear();
+    pub fn clear_cache(&self) {
+        for (_, module) in self.module_cache.write().drain() {
+            self.blobs.release(&module.artifact);
+        }
         info!("Cleared WASM module cache");
     }
 
@@ -479,7 +584,7 @@ mod tests {
 
     #[test]
     fn test_module_caching() {
-        let mut sandbox = WasmSandbox::new(SandboxConfig {
+        let sandbox = WasmSandbox::new(SandboxConfig {
             simulation_mode: true,
             ..Default::default()
         })
@@ -491,6 +596,39 @@ mod tests {
         let hash2 = sandbox.load_module(wasm).unwrap();
 
         assert_eq!(hash1, hash2);
+
+        // Forks see modules loaded by any sandbox of the family
+        let fork = sandbox.fork();
+        assert!(fork.is_module_cached(&hash1));
+        assert_eq!(fork.module_artifact(&hash1).unwrap().as_ref(), wasm);
+        fork.clear_cache();
+        assert!(!sandbox.is_module_cached(&hash1));
+        assert_eq!(sandbox.blobs.stats().blobs, 0);
+    }
+
+    #[test]
+    fn test_compiled_modules_survive_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SandboxConfig {
+            simulation_mode: true,
+            ..Default::default()
+        };
+        let wasm = b"\x00asm\x01\x00\x00\x00fake_wasm_module_data";
+        let open = || {
+            let aot = AotCache::open(dir.path(), &config).unwrap();
+            WasmSandbox::new(config.clone())
+                .unwrap()
+                .with_aot_cache(Arc::new(aot))
+        };
+
+        let sandbox = open();
+        let hash = sandbox.load_module(wasm).unwrap();
+        assert!(!sandbox.is_module_precompiled(&hash));
+
+        // A new process finds the module already compiled
+        let restarted = open();
+        assert_eq!(restarted.load_module(wasm).unwrap(), hash);
+        assert!(restarted.is_module_precompiled(&hash));
     }
 
     #[test]
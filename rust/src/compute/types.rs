@@ -4,8 +4,13 @@
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::compute::attestation::ExecutionAttestation;
+use crate::compute::io_tunnel::TunnelKeyExchange;
+use crate::error::AdmissionError;
+
 /// Compute configuration
 #[derive(Debug, Clone)]
 pub struct ComputeConfig {
@@ -24,6 +29,16 @@ pub struct ComputeConfig {
     /// Simulation mode - when true, execute returns input unchanged (for testing only)
     /// SECURITY: MUST be set to false in production environments
     pub simulation_mode: bool,
+    /// Memory tasks may reserve in total (0 = the node's RAM)
+    pub max_reserved_memory_mb: u64,
+    /// Host CPU load (0.0 to 1.0) above which new tasks wait
+    pub max_load: f32,
+    /// Tasks allowed to wait for capacity before new ones are rejected
+    pub max_queued_tasks: usize,
+    /// How long a queued task waits for capacity before it is rejected
+    pub queue_timeout_ms: u64,
+    /// Where compiled modules are kept across restarts (`None` = not kept)
+    pub aot_cache_dir: Option<PathBuf>,
 }
 
 impl Default for ComputeConfig {
@@ -37,6 +52,11 @@ impl Default for ComputeConfig {
             worker_threads: num_cpus::get().max(1),
             // SECURITY: Default to false - simulation mode should only be enabled explicitly for testing
             simulation_mode: false,
+            max_reserved_memory_mb: 0,
+            max_load: 0.9,
+            max_queued_tasks: 16,
+            queue_timeout_ms: 10_000,
+            aot_cache_dir: None,
         }
     }
 }
@@ -61,9 +81,9 @@ pub enum SplitStrategy {
     /// Split by fixed size chunks
     #[default]
     FixedSize,
-    /// Split by row/line boundaries
+    /// Split at line ends (`\n`), never inside a row
     RowBased,
-    /// Split by custom delimiters
+    /// Split after the job's `delimiter` bytes, never inside a record
     Delimiter,
     /// Use WASM split function
     Custom,
@@ -116,6 +136,9 @@ pub struct JobManifest {
     pub input_data: Vec<u8>,
     /// Split strategy
     pub split_strategy: SplitStrategy,
+    /// Record separator for `SplitStrategy::Delimiter`
+    #[serde(default)]
+    pub delimiter: Vec<u8>,
     /// Minimum chunk size in bytes
     pub min_chunk_size: u64,
     /// Maximum chunk size in bytes
@@ -140,6 +163,7 @@ impl JobManifest {
             wasm_module,
             input_data,
             split_strategy: SplitStrategy::default(),
+            delimiter: Vec::new(),
             min_chunk_size: 65536,   // 64 KB
             max_chunk_size: 1048576, // 1 MB
             verification_mode: VerificationMode::default(),
@@ -179,6 +203,10 @@ pub struct ComputeTask {
     pub delegation_depth: u32,
     /// Timeout in milliseconds
     pub timeout_ms: u64,
+    /// Key agreement of an I/O tunnel; when set, `input_data` and the
+    /// result are encrypted for the sandbox and the submitter only
+    #[serde(default)]
+    pub tunnel: Option<TunnelKeyExchange>,
 }
 
 impl ComputeTask {
@@ -199,6 +227,7 @@ impl ComputeTask {
             function_name: "execute".to_string(),
             delegation_depth: 0,
             timeout_ms: 30_000,
+            tunnel: None,
         }
     }
 }
@@ -220,6 +249,9 @@ pub struct TaskResult {
     pub execution_time_ms: u64,
     /// Error message (if failed)
     pub error_message: Option<String>,
+    /// What executed the task, signed by the executor
+    #[serde(default)]
+    pub attestation: Option<ExecutionAttestation>,
 }
 
 impl TaskResult {
@@ -233,6 +265,7 @@ impl TaskResult {
             merkle_proof: None,
             execution_time_ms: 0,
             error_message: Some(error),
+            attestation: None,
         }
     }
 }
@@ -336,6 +369,9 @@ pub enum ComputeError {
     #[error("Task cancelled")]
     Cancelled,
 
+    #[error(transparent)]
+    Rejected(#[from] AdmissionError),
+
     #[error("Invalid input: {0}")]
     InvalidInput(String),
 
@@ -357,6 +357,7 @@ mod tests {
             merkle_proof: None,
             execution_time_ms: 100,
             error_message: None,
+            attestation: None,
         };
 
         let verification = verifier.verify(&result, Some(&hash));
@@ -375,6 +376,7 @@ mod tests {
             merkle_proof: None,
             execution_time_ms: 100,
             error_message: None,
+            attestation: None,
         };
 
         let verification = verifier.verify(&result, Some("expected_hash"));
@@ -396,6 +398,7 @@ mod tests {
             merkle_proof: Some(proof),
             execution_time_ms: 100,
             error_message: None,
+            attestation: None,
         };
 
         let verification = verifier.verify(&result, None);
@@ -414,6 +417,7 @@ mod tests {
             merkle_proof: None,
             execution_time_ms: 100,
             error_message: None,
+            attestation: None,
         };
 
         let result2 = TaskResult {
@@ -424,6 +428,7 @@ mod tests {
             merkle_proof: None,
             execution_time_ms: 110,
             error_message: None,
+            attestation: None,
         };
 
         let comparison = verifier.compare_results(&result1, &result2);
@@ -1,5 +1,6 @@
 //! Configuration system for DCDN
 
+use crate::dcdn::playback::PlayerFeedback;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -14,6 +15,10 @@ pub struct DcdnConfig {
     pub fec: FecConfig,
     pub p2p: P2PConfig,
     pub crypto: CryptoConfig,
+    #[serde(default)]
+    pub playback: PlaybackConfig,
+    #[serde(default)]
+    pub flow: FlowConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,12 +53,19 @@ pub struct QuicConfig {
     /// Maximum chunk size in bytes for receive operations (default: 10MB)
     #[serde(default = "default_max_chunk_size")]
     pub max_chunk_size: usize,
+    /// Resume sessions with known peers using 0-RTT (default: true)
+    #[serde(default = "default_enable_0rtt")]
+    pub enable_0rtt: bool,
 }
 
 fn default_max_chunk_size() -> usize {
     10 * 1024 * 1024 // 10 MB
 }
 
+fn default_enable_0rtt() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CongestionAlgo {
     #[serde(rename = "bbr")]
@@ -104,6 +116,50 @@ pub struct CryptoConfig {
     pub key_rotation_days: u64,
 }
 
+/// Player-side buffering for DCDN streams
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaybackConfig {
+    /// Media buffered before playback starts, and again after a stall
+    pub target_delay_ms: u64,
+    /// Media time carried by each chunk
+    pub chunk_duration_ms: u64,
+    /// Most chunks held ahead of the play head
+    pub max_buffered_chunks: usize,
+}
+
+impl Default for PlaybackConfig {
+    fn default() -> Self {
+        Self {
+            target_delay_ms: 2000,
+            chunk_duration_ms: 100,
+            max_buffered_chunks: 600,
+        }
+    }
+}
+
+/// Bounded handoff between DCDN pipeline stages (see `dcdn::flow`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlowConfig {
+    /// Most items queued between two stages
+    pub capacity: usize,
+    /// Queue length at which a stage counts as congested
+    pub high_watermark: usize,
+    /// Queue length a congested stage must drain to
+    pub low_watermark: usize,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            high_watermark: 192,
+            low_watermark: 64,
+        }
+    }
+}
+
 impl Default for DcdnConfig {
     fn default() -> Self {
         Self {
@@ -122,6 +178,7 @@ impl Default for DcdnConfig {
                 enable_gso: true,
                 idle_timeout_ms: 30000,
                 max_chunk_size: default_max_chunk_size(),
+                enable_0rtt: default_enable_0rtt(),
             },
             storage: StorageConfig {
                 ring_buffer_size_mb: 100,
@@ -144,6 +201,8 @@ impl Default for DcdnConfig {
                 signature_algorithm: "ed25519".to_string(),
                 key_rotation_days: 30,
             },
+            playback: PlaybackConfig::default(),
+            flow: FlowConfig::default(),
         }
     }
 }
@@ -204,6 +263,23 @@ impl DcdnConfig {
             anyhow::bail!("idle_timeout_ms must be >= 1000 for stable connections");
         }
 
+        // Playback validation
+        if self.playback.chunk_duration_ms == 0 {
+            anyhow::bail!("chunk_duration_ms must be > 0");
+        }
+        let window_ms = self.playback.max_buffered_chunks as u64 * self.playback.chunk_duration_ms;
+        if window_ms < self.playback.target_delay_ms {
+            anyhow::bail!("max_buffered_chunks must hold at least target_delay_ms of media");
+        }
+
+        // Flow control validation
+        if self.flow.high_watermark == 0 || self.flow.high_watermark > self.flow.capacity {
+            anyhow::bail!("high_watermark must be > 0 and <= capacity");
+        }
+        if self.flow.low_watermark >= self.flow.high_watermark {
+            anyhow::bail!("low_watermark must be < high_watermark");
+        }
+
         Ok(())
     }
 }
@@ -226,6 +302,16 @@ impl FecConfig {
         (block_size, parity_count)
     }
 
+    /// Select FEC parameters from a player's buffer health
+    ///
+    /// The media a player has buffered is the latency a FEC block may add
+    /// before it stalls: a draining buffer gets small blocks, a full one
+    /// larger and more efficient blocks.
+    pub fn select_for_feedback(&self, feedback: &PlayerFeedback) -> (usize, usize) {
+        let budget_ms = feedback.buffered_ms.min(u32::MAX as u64) as u32;
+        self.select_params(budget_ms, feedback.loss_rate)
+    }
+
     /// Calculate the number of parity blocks based on block size and loss rate.
     ///
     /// The `loss_rate` parameter is clamped to the range [0.0, 1.0].
@@ -268,4 +354,27 @@ mod tests {
         // parity may be 2 or higher depending on the algorithm; ensure minimum expected
         assert!(parity >= 2);
     }
+
+    #[test]
+    fn test_fec_follows_player_buffer() {
+        use crate::dcdn::playback::PlaybackState;
+        use crate::dcdn::types::PeerId;
+
+        let config = DcdnConfig::default().fec;
+        let mut feedback = PlayerFeedback {
+            peer: PeerId(1),
+            state: PlaybackState::Playing,
+            next_sequence: 0,
+            buffered_ms: 80,
+            target_delay_ms: 2000,
+            missing: Vec::new(),
+            underruns: 0,
+            stalls: 0,
+            loss_rate: 0.0,
+        };
+        // A nearly empty buffer cannot wait for a large block
+        assert_eq!(config.select_for_feedback(&feedback).0, 8);
+        feedback.buffered_ms = 2000;
+        assert_eq!(config.select_for_feedback(&feedback).0, 64);
+    }
 }
@@ -0,0 +1,255 @@
+//! Flow control between DCDN pipeline stages
+//!
+//! Received data passes transport → FEC decoder → signature verification →
+//! consumer (the playback buffer). Each hop is a bounded channel, so a slow
+//! stage makes the one before it wait instead of piling chunks up in memory.
+//! A channel that fills to `high_watermark` marks its stage congested until
+//! it drains to `low_watermark`; while any stage is congested,
+//! `FlowControl::request_budget` is zero and peer request scheduling holds
+//! back new requests, so backpressure reaches the peers sending to us.
+
+use crate::dcdn::config::FlowConfig;
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// The pipeline stage reading from a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PipelineStage {
+    /// FEC decoding of received packets
+    Decode,
+    /// Signature verification of decoded chunks
+    Verify,
+    /// The consumer of verified chunks
+    Consume,
+}
+
+/// Queue state of one stage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageStats {
+    pub stage: PipelineStage,
+    pub queued: usize,
+    pub congested: bool,
+    /// Most items ever queued at once
+    pub peak: usize,
+    /// Times the stage reached its high watermark
+    pub congestions: u64,
+}
+
+struct Watermark {
+    stage: PipelineStage,
+    high: usize,
+    low: usize,
+    queued: AtomicUsize,
+    congested: AtomicBool,
+    peak: AtomicUsize,
+    congestions: AtomicU64,
+}
+
+impl Watermark {
+    fn queued(&self) {
+        let queued = self.queued.fetch_add(1, Ordering::AcqRel) + 1;
+        self.peak.fetch_max(queued, Ordering::Relaxed);
+        if queued >= self.high && !self.congested.swap(true, Ordering::AcqRel) {
+            self.congestions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn dequeued(&self) {
+        let queued = self.queued.fetch_sub(1, Ordering::AcqRel) - 1;
+        if queued <= self.low {
+            self.congested.store(false, Ordering::Release);
+        }
+    }
+
+    fn stats(&self) -> StageStats {
+        StageStats {
+            stage: self.stage,
+            queued: self.queued.load(Ordering::Acquire),
+            congested: self.congested.load(Ordering::Acquire),
+            peak: self.peak.load(Ordering::Relaxed),
+            congestions: self.congestions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Watermarks of every stage of one pipeline
+pub struct FlowControl {
+    config: FlowConfig,
+    stages: Mutex<Vec<Arc<Watermark>>>,
+}
+
+impl FlowControl {
+    pub fn new(config: FlowConfig) -> Self {
+        Self {
+            config,
+            stages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A bounded channel feeding `stage`
+    pub fn channel<T>(&self, stage: PipelineStage) -> (FlowSender<T>, FlowReceiver<T>) {
+        let (tx, rx) = mpsc::channel(self.config.capacity.max(1));
+        let mark = Arc::new(Watermark {
+            stage,
+            high: self.config.high_watermark,
+            low: self.config.low_watermark,
+            queued: AtomicUsize::new(0),
+            congested: AtomicBool::new(false),
+            peak: AtomicUsize::new(0),
+            congestions: AtomicU64::new(0),
+        });
+        self.stages.lock().push(Arc::clone(&mark));
+        (
+            FlowSender {
+                tx,
+                mark: Arc::clone(&mark),
+            },
+            FlowReceiver { rx, mark },
+        )
+    }
+
+    /// Whether any stage is above its high watermark and not yet drained
+    pub fn is_congested(&self) -> bool {
+        self.stages
+            .lock()
+            .iter()
+            .any(|mark| mark.congested.load(Ordering::Acquire))
+    }
+
+    /// How many of `wanted` chunks may be requested from peers now
+    pub fn request_budget(&self, wanted: usize) -> usize {
+        if self.is_congested() {
+            0
+        } else {
+            wanted
+        }
+    }
+
+    pub fn stats(&self) -> Vec<StageStats> {
+        self.stages.lock().iter().map(|mark| mark.stats()).collect()
+    }
+}
+
+/// Sending half of a stage channel
+pub struct FlowSender<T> {
+    tx: mpsc::Sender<T>,
+    mark: Arc<Watermark>,
+}
+
+impl<T> Clone for FlowSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            mark: Arc::clone(&self.mark),
+        }
+    }
+}
+
+impl<T> FlowSender<T> {
+    /// Queue `item`, waiting while the channel is full
+    pub async fn send(&self, item: T) -> Result<()> {
+        let permit = self
+            .tx
+            .reserve()
+            .await
+            .map_err(|_| anyhow!("{:?} stage has shut down", self.mark.stage))?;
+        // Counted before it can be received, so the count never goes negative
+        self.mark.queued();
+        permit.send(item);
+        Ok(())
+    }
+
+    /// Queue `item` if there is room, handing it back otherwise
+    pub fn try_send(&self, item: T) -> std::result::Result<(), T> {
+        match self.tx.try_reserve() {
+            Ok(permit) => {
+                self.mark.queued();
+                permit.send(item);
+                Ok(())
+            }
+            Err(_) => Err(item),
+        }
+    }
+}
+
+/// Receiving half of a stage channel
+pub struct FlowReceiver<T> {
+    rx: mpsc::Receiver<T>,
+    mark: Arc<Watermark>,
+}
+
+impl<T> FlowReceiver<T> {
+    /// Next item, or `None` once every sender is gone
+    pub async fn recv(&mut self) -> Option<T> {
+        let item = self.rx.recv().await?;
+        self.mark.dequeued();
+        Some(item)
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        let item = self.rx.try_recv().ok()?;
+        self.mark.dequeued();
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn flow() -> FlowControl {
+        FlowControl::new(FlowConfig {
+            capacity: 4,
+            high_watermark: 3,
+            low_watermark: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_watermarks_gate_requests() {
+        let flow = flow();
+        let (tx, mut rx) = flow.channel::<u64>(PipelineStage::Consume);
+        assert_eq!(flow.request_budget(8), 8);
+
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        assert!(flow.is_congested());
+        assert_eq!(flow.request_budget(8), 0);
+
+        // Still congested until drained to the low watermark
+        rx.recv().await.unwrap();
+        assert!(flow.is_congested());
+        rx.recv().await.unwrap();
+        assert_eq!(flow.request_budget(8), 8);
+
+        let stats = &flow.stats()[0];
+        assert_eq!(stats.stage, PipelineStage::Consume);
+        assert_eq!((stats.queued, stats.peak, stats.congestions), (1, 3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_full_channels_hold_producers_back() {
+        let flow = flow();
+        let (tx, mut rx) = flow.channel::<u64>(PipelineStage::Decode);
+        for i in 0..4 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(tx.try_send(4), Err(4));
+        assert!(tokio::time::timeout(Duration::from_millis(50), tx.send(4))
+            .await
+            .is_err());
+
+        assert_eq!(rx.recv().await, Some(0));
+        tx.send(4).await.unwrap();
+        assert_eq!(flow.stats()[0].queued, 4);
+
+        drop(rx);
+        assert!(tx.send(5).await.is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Signed stream manifests
+//!
+//! Chunk IDs are assigned by the origin, so a consumer cannot work out which
+//! chunks make up a stream on its own. The origin publishes a
+//! `StreamManifest`: codec details plus, per time window, the sequences and
+//! chunk IDs carrying it, signed with the origin's Ed25519 key. Manifests are
+//! stored in the DHT under `StreamManifest::dht_key` and gossiped to peers
+//! watching the stream; a live stream republishes its manifest with a higher
+//! `version` as windows are added.
+//!
+//! Consumers drive fetching from it: the playback buffer turns its missing
+//! sequences into chunk IDs (`PlaybackBuffer::missing_chunks`), and a
+//! gateway renders it as an HLS media playlist (`StreamManifest::hls_playlist`).
+//! A stream transcoded into several renditions has one manifest per rendition,
+//! tied together by an HLS master playlist (`StreamManifest::hls_master_playlist`).
+
+use crate::dcdn::playback::StreamMode;
+use crate::dcdn::types::{ChunkId, PublicKey};
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::Options;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Largest encoded manifest accepted from the network
+pub const MAX_MANIFEST_BYTES: usize = 256 * 1024;
+
+/// Prefix of the DHT keys manifests are published under
+const DHT_KEY_PREFIX: &[u8] = b"dcdn-stream/";
+
+/// Domain separator for manifest signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-dcdn-stream-manifest-v1";
+
+/// How the stream's media is encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodecInfo {
+    /// RFC 6381 codec string, e.g. `avc1.64001f,mp4a.40.2`
+    pub codecs: String,
+    /// Container of each chunk, e.g. `mpegts` or `fmp4`
+    pub container: String,
+    pub bitrate_kbps: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A run of consecutive chunks covering one span of media time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkWindow {
+    /// Media time of the first chunk, in milliseconds from stream start
+    pub start_ms: u64,
+    pub first_sequence: u64,
+    /// ID of the first chunk; the rest follow consecutively
+    pub first_chunk: ChunkId,
+    pub count: u32,
+    pub chunk_duration_ms: u32,
+}
+
+impl ChunkWindow {
+    pub fn end_ms(&self) -> u64 {
+        self.start_ms + self.count as u64 * self.chunk_duration_ms as u64
+    }
+
+    pub fn end_sequence(&self) -> u64 {
+        self.first_sequence + self.count as u64
+    }
+
+    /// Chunk carrying `sequence`, if it falls in this window
+    pub fn chunk(&self, sequence: u64) -> Option<ChunkId> {
+        (self.first_sequence..self.end_sequence())
+            .contains(&sequence)
+            .then(|| ChunkId(self.first_chunk.0 + (sequence - self.first_sequence)))
+    }
+}
+
+/// The chunks of a stream, signed by its origin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamManifest {
+    pub stream_id: String,
+    pub mode: StreamMode,
+    pub codec: CodecInfo,
+    /// Consecutive windows in media order
+    pub windows: Vec<ChunkWindow>,
+    /// Raised on every change; a newer version replaces an older one
+    pub version: u64,
+    /// Ed25519 key of the origin
+    pub publisher: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+/// The signed part of a manifest
+#[derive(Serialize)]
+struct Unsigned<'a> {
+    stream_id: &'a str,
+    mode: StreamMode,
+    codec: &'a CodecInfo,
+    windows: &'a [ChunkWindow],
+    version: u64,
+    publisher: &'a [u8; 32],
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_MANIFEST_BYTES as u64)
+}
+
+impl StreamManifest {
+    /// An empty, unsigned manifest
+    pub fn new(stream_id: impl Into<String>, mode: StreamMode, codec: CodecInfo) -> Self {
+        Self {
+            stream_id: stream_id.into(),
+            mode,
+            codec,
+            windows: Vec::new(),
+            version: 0,
+            publisher: [0u8; 32],
+            signature: Vec::new(),
+        }
+    }
+
+    /// DHT key the manifest of `stream_id` is published under
+    pub fn dht_key(stream_id: &str) -> Vec<u8> {
+        [DHT_KEY_PREFIX, stream_id.as_bytes()].concat()
+    }
+
+    /// Append a window, which must continue the last one without gaps
+    ///
+    /// The manifest has to be signed again afterwards.
+    pub fn push_window(&mut self, window: ChunkWindow) -> Result<()> {
+        if window.count == 0 || window.chunk_duration_ms == 0 {
+            bail!("Stream window must hold chunks of non-zero duration");
+        }
+        if let Some(last) = self.windows.last() {
+            if window.first_sequence != last.end_sequence() || window.start_ms != last.end_ms() {
+                bail!(
+                    "Stream window at sequence {} ({} ms) does not follow sequence {} ({} ms)",
+                    window.first_sequence,
+                    window.start_ms,
+                    last.end_sequence(),
+                    last.end_ms()
+                );
+            }
+        }
+        self.windows.push(window);
+        self.version += 1;
+        self.signature.clear();
+        Ok(())
+    }
+
+    /// Sign as the stream's origin
+    pub fn sign(&mut self, key: &SigningKey) -> Result<()> {
+        self.publisher = key.verifying_key().to_bytes();
+        let signature = key.sign(&self.signed_bytes()?);
+        self.signature = signature.to_bytes().to_vec();
+        Ok(())
+    }
+
+    /// Check that `publisher` signed the manifest
+    pub fn verify(&self, publisher: &PublicKey) -> Result<()> {
+        if self.publisher != publisher.0 {
+            bail!(
+                "Manifest of stream {} has another publisher",
+                self.stream_id
+            );
+        }
+        let key = VerifyingKey::from_bytes(&self.publisher)
+            .map_err(|e| anyhow!("Invalid publisher key: {}", e))?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        key.verify_strict(&self.signed_bytes()?, &signature)
+            .map_err(|_| anyhow!("Bad signature on manifest of stream {}", self.stream_id))
+    }
+
+    /// Whether this manifest should replace `other`
+    pub fn supersedes(&self, other: &StreamManifest) -> bool {
+        self.stream_id == other.stream_id
+            && self.publisher == other.publisher
+            && self.version > other.version
+    }
+
+    pub fn publisher_key(&self) -> PublicKey {
+        PublicKey(self.publisher)
+    }
+
+    /// Chunk carrying `sequence`, if the manifest lists it yet
+    pub fn chunk_for_sequence(&self, sequence: u64) -> Option<ChunkId> {
+        let index = self
+            .windows
+            .partition_point(|w| w.end_sequence() <= sequence);
+        self.windows.get(index)?.chunk(sequence)
+    }
+
+    /// Sequence playing at media time `time_ms`, e.g. to seek to
+    pub fn sequence_at(&self, time_ms: u64) -> Option<u64> {
+        let index = self.windows.partition_point(|w| w.end_ms() <= time_ms);
+        let window = self.windows.get(index)?;
+        if time_ms < window.start_ms {
+            return None;
+        }
+        Some(window.first_sequence + (time_ms - window.start_ms) / window.chunk_duration_ms as u64)
+    }
+
+    /// First sequence and end (exclusive) of the listed chunks
+    pub fn sequences(&self) -> Option<(u64, u64)> {
+        Some((
+            self.windows.first()?.first_sequence,
+            self.windows.last()?.end_sequence(),
+        ))
+    }
+
+    /// HLS media playlist of the listed chunks, with `uri` naming each one
+    ///
+    /// VOD streams end with `#EXT-X-ENDLIST`; live playlists are re-rendered
+    /// whenever a newer manifest arrives.
+    pub fn hls_playlist(&self, uri: impl Fn(ChunkId) -> String) -> String {
+        let target = self
+            .windows
+            .iter()
+            .map(|w| (w.chunk_duration_ms as u64).div_ceil(1000))
+            .max()
+            .unwrap_or(1);
+        let first = self.windows.first().map_or(0, |w| w.first_sequence);
+
+        let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+        let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{}", target);
+        let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:{}", first);
+        for window in &self.windows {
+            let duration = window.chunk_duration_ms as f64 / 1000.0;
+            for offset in 0..window.count as u64 {
+                let _ = writeln!(playlist, "#EXTINF:{:.3},", duration);
+                let _ = writeln!(playlist, "{}", uri(ChunkId(window.first_chunk.0 + offset)));
+            }
+        }
+        if self.mode == StreamMode::Vod {
+            playlist.push_str("#EXT-X-ENDLIST\n");
+        }
+        playlist
+    }
+
+    /// HLS master playlist offering the renditions of one stream
+    ///
+    /// Each variant pairs a rendition's codec (e.g. from
+    /// `codecs::Transcoder::codec_infos`) with the URI of its media playlist;
+    /// players pick one by `BANDWIDTH` and switch as throughput changes.
+    pub fn hls_master_playlist<'a>(
+        variants: impl IntoIterator<Item = (&'a CodecInfo, String)>,
+    ) -> String {
+        let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+        for (codec, uri) in variants {
+            let _ = write!(
+                playlist,
+                "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"",
+                codec.bitrate_kbps as u64 * 1000,
+                codec.codecs
+            );
+            if codec.width > 0 && codec.height > 0 {
+                let _ = write!(playlist, ",RESOLUTION={}x{}", codec.width, codec.height);
+            }
+            let _ = writeln!(playlist);
+            let _ = writeln!(playlist, "{}", uri);
+        }
+        playlist
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        codec()
+            .serialize(self)
+            .context("Failed to serialize stream manifest")
+    }
+
+    /// Decode a manifest received from the network; verify it before use
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        codec()
+            .deserialize(bytes)
+            .context("Failed to deserialize stream manifest")
+    }
+
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        let body = codec()
+            .serialize(&Unsigned {
+                stream_id: &self.stream_id,
+                mode: self.mode,
+                codec: &self.codec,
+                windows: &self.windows,
+                version: self.version,
+                publisher: &self.publisher,
+            })
+            .context("Failed to serialize stream manifest")?;
+        Ok([SIGNING_CONTEXT, &body].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(mode: StreamMode) -> StreamManifest {
+        let mut manifest = StreamManifest::new(
+            "concert",
+            mode,
+            CodecInfo {
+                codecs: "avc1.64001f,mp4a.40.2".into(),
+                container: "mpegts".into(),
+                bitrate_kbps: 3000,
+                width: 1280,
+                height: 720,
+            },
+        );
+        manifest
+            .push_window(ChunkWindow {
+                start_ms: 0,
+                first_sequence: 0,
+                first_chunk: ChunkId(1000),
+                count: 3,
+                chunk_duration_ms: 2000,
+            })
+            .unwrap();
+        manifest
+            .push_window(ChunkWindow {
+                start_ms: 6000,
+                first_sequence: 3,
+                first_chunk: ChunkId(5000),
+                count: 2,
+                chunk_duration_ms: 2000,
+            })
+            .unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_signed_manifest_round_trips() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut manifest = manifest(StreamMode::Live);
+        manifest.sign(&key).unwrap();
+
+        let received = StreamManifest::from_bytes(&manifest.to_bytes().unwrap()).unwrap();
+        let publisher = PublicKey(key.verifying_key().to_bytes());
+        received.verify(&publisher).unwrap();
+
+        // Any change voids the signature
+        let mut tampered = received.clone();
+        tampered.windows[1].first_chunk = ChunkId(6000);
+        assert!(tampered.verify(&publisher).is_err());
+
+        // So does another publisher's key
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        assert!(received
+            .verify(&PublicKey(other.verifying_key().to_bytes()))
+            .is_err());
+
+        // A new window needs a new signature and supersedes the old manifest
+        let mut next = received.clone();
+        next.push_window(ChunkWindow {
+            start_ms: 10_000,
+            first_sequence: 5,
+            first_chunk: ChunkId(9000),
+            count: 1,
+            chunk_duration_ms: 2000,
+        })
+        .unwrap();
+        assert!(next.verify(&publisher).is_err());
+        next.sign(&key).unwrap();
+        assert!(next.supersedes(&received));
+        assert!(!received.supersedes(&next));
+    }
+
+    #[test]
+    fn test_chunks_are_found_by_sequence_and_time() {
+        let manifest = manifest(StreamMode::Vod);
+        assert_eq!(manifest.chunk_for_sequence(0), Some(ChunkId(1000)));
+        assert_eq!(manifest.chunk_for_sequence(2), Some(ChunkId(1002)));
+        assert_eq!(manifest.chunk_for_sequence(4), Some(ChunkId(5001)));
+        assert_eq!(manifest.chunk_for_sequence(5), None);
+        assert_eq!(manifest.sequence_at(5999), Some(2));
+        assert_eq!(manifest.sequence_at(6000), Some(3));
+        assert_eq!(manifest.sequence_at(10_000), None);
+        assert_eq!(manifest.sequences(), Some((0, 5)));
+
+        // Windows must continue each other
+        let mut gappy = manifest.clone();
+        assert!(gappy
+            .push_window(ChunkWindow {
+                start_ms: 12_000,
+                first_sequence: 6,
+                first_chunk: ChunkId(9000),
+                count: 1,
+                chunk_duration_ms: 2000,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_hls_playlist() {
+        let playlist = manifest(StreamMode::Vod).hls_playlist(|id| format!("/chunks/{}.ts", id.0));
+        let lines: Vec<&str> = playlist.lines().collect();
+        assert_eq!(lines[0], "#EXTM3U");
+        assert!(lines.contains(&"#EXT-X-TARGETDURATION:2"));
+        assert!(lines.contains(&"#EXT-X-MEDIA-SEQUENCE:0"));
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.starts_with("#EXTINF:2.000"))
+                .count(),
+            5
+        );
+        assert!(lines.contains(&"/chunks/5001.ts"));
+        assert_eq!(lines.last(), Some(&"#EXT-X-ENDLIST"));
+
+        let live = manifest(StreamMode::Live).hls_playlist(|id| id.0.to_string());
+        assert!(!live.contains("#EXT-X-ENDLIST"));
+    }
+
+    #[test]
+    fn test_hls_master_playlist() {
+        let video = manifest(StreamMode::Vod).codec;
+        let audio = CodecInfo {
+            codecs: "opus".into(),
+            container: "fmp4".into(),
+            bitrate_kbps: 64,
+            width: 0,
+            height: 0,
+        };
+        let playlist = StreamManifest::hls_master_playlist([
+            (&video, "/concert/720p.m3u8".to_string()),
+            (&audio, "/concert/audio.m3u8".to_string()),
+        ]);
+        let lines: Vec<&str> = playlist.lines().collect();
+        assert_eq!(
+            lines[2],
+            "#EXT-X-STREAM-INF:BANDWIDTH=3000000,CODECS=\"avc1.64001f,mp4a.40.2\",RESOLUTION=1280x720"
+        );
+        assert_eq!(lines[3], "/concert/720p.m3u8");
+        assert_eq!(
+            lines[4],
+            "#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"opus\""
+        );
+        assert_eq!(lines.len(), 6);
+    }
+}
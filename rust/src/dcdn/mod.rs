@@ -6,20 +6,31 @@
 //! - P2P mesh with tit-for-tat incentives
 //! - Ed25519 signature verification for content authenticity
 //! - Lock-free ring buffer for chunk storage
+//! - Playback buffer with player feedback for live and VOD streams
+//! - Signed stream manifests mapping media time to chunk IDs
+//! - Bounded, watermarked handoff between pipeline stages
 //!
 //! Based on design specification in dcdn_design_spec.txt
 
 pub mod config;
 pub mod fec;
+pub mod flow;
+pub mod manifest;
 pub mod p2p;
+pub mod playback;
 pub mod storage;
 pub mod transport;
 pub mod types;
 pub mod verifier;
 
-pub use config::DcdnConfig;
+pub use config::{DcdnConfig, FlowConfig, PlaybackConfig};
 pub use fec::{FecAlgorithm, FecEngine, FecEngineConfig, FecGroup};
+pub use flow::{FlowControl, FlowReceiver, FlowSender, PipelineStage, StageStats};
+pub use manifest::{ChunkWindow, CodecInfo, StreamManifest};
 pub use p2p::{P2PConfig, P2PEngine};
+pub use playback::{
+    BufferHealth, PlaybackBuffer, PlaybackMetrics, PlaybackState, PlayerFeedback, StreamMode,
+};
 pub use storage::ChunkStore;
 pub use transport::QuicTransport;
 pub use types::StorageStats;
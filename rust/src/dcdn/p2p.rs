@@ -1,11 +1,20 @@
 //! P2P transfer engine with tit-for-tat incentives
 
 use crate::dcdn::types::{ChunkId, PeerId, PeerStats};
+use crate::firewall::Firewall;
 use anyhow::Result;
 use dashmap::DashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use tracing::warn;
+
+/// Peers whose reliability falls below this are banned at the firewall
+pub const BAN_RELIABILITY_THRESHOLD: f32 = 0.2;
+
+/// How long a reputation ban lasts
+pub const REPUTATION_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
 
 /// P2P transfer engine with bandwidth allocation and incentive mechanism
 pub struct P2PEngine {
@@ -15,6 +24,12 @@ pub struct P2PEngine {
     unchoked_peers: Arc<RwLock<Vec<PeerId>>>,
     /// Configuration
     config: Arc<P2PConfig>,
+    /// Where bans for unreliable peers are sent
+    firewall: Option<Arc<Firewall>>,
+    /// Known addresses of peers, so bans can be applied at the firewall
+    peer_addrs: DashMap<PeerId, IpAddr>,
+    /// Probed available bandwidth per peer, bytes/second
+    peer_bandwidth: DashMap<PeerId, f64>,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +59,30 @@ impl P2PEngine {
             peer_stats: DashMap::new(),
             unchoked_peers: Arc::new(RwLock::new(Vec::new())),
             config: Arc::new(config),
+            firewall: None,
+            peer_addrs: DashMap::new(),
+            peer_bandwidth: DashMap::new(),
+        }
+    }
+
+    /// Ban peers at this firewall once their reliability drops below
+    /// `BAN_RELIABILITY_THRESHOLD`
+    pub fn with_firewall(mut self, firewall: Arc<Firewall>) -> Self {
+        self.firewall = Some(firewall);
+        self
+    }
+
+    /// Record the address a peer connects from
+    pub fn set_peer_addr(&self, peer: PeerId, ip: IpAddr) {
+        self.peer_addrs.insert(peer, ip);
+    }
+
+    /// Record a peer's probed available bandwidth (bytes/second)
+    ///
+    /// Allocations to the peer are capped at this rate.
+    pub fn set_peer_bandwidth(&self, peer: PeerId, bytes_per_sec: f64) {
+        if bytes_per_sec.is_finite() && bytes_per_sec > 0.0 {
+            self.peer_bandwidth.insert(peer, bytes_per_sec);
         }
     }
 
@@ -77,10 +116,28 @@ impl P2PEngine {
     }
 
     /// Update peer statistics
+    ///
+    /// A peer whose reliability falls below `BAN_RELIABILITY_THRESHOLD` is
+    /// dropped and, if its address is known, banned at the firewall.
     pub fn update_peer_state(&self, peer: PeerId, stats: PeerStats) {
+        if stats.reliability_score < BAN_RELIABILITY_THRESHOLD {
+            self.ban_peer(peer, stats.reliability_score);
+            return;
+        }
         self.peer_stats.insert(peer, stats);
     }
 
+    fn ban_peer(&self, peer: PeerId, reliability: f32) {
+        warn!(
+            "Peer {:?} reliability {:.2} below {:.2}, dropping it",
+            peer, reliability, BAN_RELIABILITY_THRESHOLD
+        );
+        self.peer_stats.remove(&peer);
+        if let (Some(firewall), Some(ip)) = (&self.firewall, self.peer_addrs.get(&peer)) {
+            firewall.ban_ip(*ip, REPUTATION_BAN_DURATION);
+        }
+    }
+
     /// Get currently unchoked peers
     pub async fn get_unchoked_peers(&self) -> Vec<PeerId> {
         self.unchoked_peers.read().await.clone()
@@ -146,6 +203,8 @@ impl P2PEngine {
     /// Remove a peer from the network
     pub fn remove_peer(&self, peer: &PeerId) {
         self.peer_stats.remove(peer);
+        self.peer_addrs.remove(peer);
+        self.peer_bandwidth.remove(peer);
     }
 
     /// Update uploaded bytes for a peer
@@ -175,16 +234,35 @@ impl P2PEngine {
             .collect()
     }
 
-    /// Calculate bandwidth allocation per peer
+    /// Calculate upload allocation per unchoked peer, in bits/second
+    ///
+    /// Upload capacity is split equally, except that a peer is never given
+    /// more than its probed bandwidth; what it cannot take is shared among
+    /// the others.
     pub async fn get_bandwidth_allocation(&self) -> Vec<(PeerId, u64)> {
         let unchoked = self.unchoked_peers.read().await;
-        let count = unchoked.len().max(1);
-        let bytes_per_peer = (self.config.max_upload_mbps * 1_000_000) / (count as u64);
+        let mut remaining = self.config.max_upload_mbps * 1_000_000;
 
-        unchoked
+        // Fill the slowest peers first so leftovers flow to faster ones
+        let mut peers: Vec<(PeerId, Option<u64>)> = unchoked
             .iter()
-            .map(|peer| (*peer, bytes_per_peer))
-            .collect()
+            .map(|peer| {
+                let cap = self.peer_bandwidth.get(peer).map(|bps| (*bps * 8.0) as u64);
+                (*peer, cap)
+            })
+            .collect();
+        peers.sort_by_key(|(_, cap)| cap.unwrap_or(u64::MAX));
+
+        let mut allocations = Vec::with_capacity(peers.len());
+        let mut left = peers.len() as u64;
+        for (peer, cap) in peers {
+            let share = remaining / left;
+            let bits = cap.map_or(share, |cap| cap.min(share));
+            allocations.push((peer, bits));
+            remaining -= bits;
+            left -= 1;
+        }
+        allocations
     }
 }
 
@@ -213,6 +291,27 @@ mod tests {
         assert_eq!(unchoked.len(), 0);
     }
 
+    #[test]
+    fn test_unreliable_peer_is_banned() {
+        let firewall = Arc::new(Firewall::default());
+        let engine = P2PEngine::new(P2PConfig::default()).with_firewall(firewall.clone());
+        let peer = PeerId::new(7);
+        let ip: IpAddr = "192.0.2.7".parse().unwrap();
+
+        engine.add_peer(peer);
+        engine.set_peer_addr(peer, ip);
+        engine.update_peer_state(
+            peer,
+            PeerStats {
+                reliability_score: 0.05,
+                ..PeerStats::default()
+            },
+        );
+
+        assert!(engine.get_peer_stats(&peer).is_none());
+        assert!(firewall.is_banned(ip));
+    }
+
     #[tokio::test]
     async fn test_add_peer() {
         let engine = P2PEngine::new(P2PConfig::default());
@@ -268,4 +367,31 @@ mod tests {
             assert!(bandwidth > 0);
         }
     }
+
+    #[tokio::test]
+    async fn test_allocation_capped_by_probed_bandwidth() {
+        let config = P2PConfig {
+            max_upload_mbps: 100,
+            ..Default::default()
+        };
+        let engine = P2PEngine::new(config);
+        for i in 1..=4 {
+            engine.add_peer(PeerId::new(i));
+        }
+        engine.update_unchoke_set().await.unwrap();
+
+        // Peer 1 can only take 1 MB/s (8 Mbit/s); the rest is shared out
+        engine.set_peer_bandwidth(PeerId::new(1), 1_000_000.0);
+        let allocations = engine.get_bandwidth_allocation().await;
+        assert_eq!(allocations.len(), 4);
+        for (peer, bits) in &allocations {
+            if *peer == PeerId::new(1) {
+                assert_eq!(*bits, 8_000_000);
+            } else {
+                assert!(bits.abs_diff(92_000_000 / 3) <= 1);
+            }
+        }
+        let total: u64 = allocations.iter().map(|(_, bits)| bits).sum();
+        assert_eq!(total, 100_000_000);
+    }
 }
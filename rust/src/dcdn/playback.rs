@@ -0,0 +1,498 @@
+//! Playback buffer and player feedback for DCDN streams
+//!
+//! Chunks arrive out of order from several peers. The `PlaybackBuffer`
+//! holds verified chunks until the play head reaches them, starts (and
+//! restarts after a stall) only once `target_delay` of media is buffered,
+//! and skips chunks that never arrive on live streams. Its health is sent
+//! upstream as `PlayerFeedback` so senders can trade latency against FEC
+//! (see `FecConfig::select_for_feedback`).
+
+use crate::dcdn::config::PlaybackConfig;
+use crate::dcdn::manifest::StreamManifest;
+use crate::dcdn::types::{ChunkData, ChunkId, PeerId};
+use crate::dcdn::verifier::SignatureVerifier;
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Most missing sequences listed in one feedback message
+pub const MAX_FEEDBACK_MISSING: usize = 64;
+
+/// Whether the stream can be seeked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamMode {
+    /// Live: missing chunks are skipped to stay near the edge
+    Live,
+    /// Video on demand: playback waits for every chunk and can seek
+    Vod,
+}
+
+/// What the player is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaybackState {
+    /// Filling up to the target delay (startup, seek or stall)
+    Buffering,
+    Playing,
+}
+
+/// Outcome of offering a chunk to the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushOutcome {
+    Buffered,
+    /// Already buffered
+    Duplicate,
+    /// Behind the play head
+    Late,
+    /// Too far ahead of the play head to hold
+    Overflow,
+    /// Failed signature verification
+    Rejected,
+}
+
+/// Counters for playback quality
+#[derive(Debug, Default)]
+pub struct PlaybackMetrics {
+    pub chunks_played: AtomicU64,
+    /// Chunks skipped on live streams because they never arrived
+    pub chunks_skipped: AtomicU64,
+    pub chunks_late: AtomicU64,
+    pub chunks_rejected: AtomicU64,
+    /// Times the next chunk was missing when the player needed it
+    pub underruns: AtomicU64,
+    /// Times playback stopped to rebuffer
+    pub stalls: AtomicU64,
+    pub stall_time_ms: AtomicU64,
+}
+
+impl PlaybackMetrics {
+    /// Render the counters in the Prometheus text format
+    pub fn export_prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        for (name, value) in [
+            ("chunks_played", &self.chunks_played),
+            ("chunks_skipped", &self.chunks_skipped),
+            ("chunks_late", &self.chunks_late),
+            ("chunks_rejected", &self.chunks_rejected),
+            ("underruns", &self.underruns),
+            ("stalls", &self.stalls),
+            ("stall_time_ms", &self.stall_time_ms),
+        ] {
+            let _ = writeln!(out, "# TYPE {}_playback_{}_total counter", prefix, name);
+            let _ = writeln!(
+                out,
+                "{}_playback_{}_total {}",
+                prefix,
+                name,
+                value.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+/// Snapshot of how well playback is being fed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferHealth {
+    pub state: PlaybackState,
+    /// Next sequence the player will play
+    pub next_sequence: u64,
+    /// Media time ready to play without a gap
+    pub buffered: Duration,
+    pub buffered_chunks: usize,
+    pub target_delay: Duration,
+    pub underruns: u64,
+    pub stalls: u64,
+    /// Fraction of chunks due so far that were skipped as lost
+    pub loss_rate: f32,
+}
+
+impl BufferHealth {
+    /// Buffered media as a fraction of the target delay
+    pub fn fill_ratio(&self) -> f32 {
+        if self.target_delay.is_zero() {
+            return 1.0;
+        }
+        (self.buffered.as_secs_f64() / self.target_delay.as_secs_f64()) as f32
+    }
+}
+
+/// Buffer health a player reports to the peers feeding it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerFeedback {
+    pub peer: PeerId,
+    pub state: PlaybackState,
+    pub next_sequence: u64,
+    pub buffered_ms: u64,
+    pub target_delay_ms: u64,
+    /// Sequences the player needs next but does not have
+    pub missing: Vec<u64>,
+    pub underruns: u64,
+    pub stalls: u64,
+    pub loss_rate: f32,
+}
+
+impl PlayerFeedback {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).context("Failed to serialize player feedback")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let feedback: Self =
+            bincode::deserialize(bytes).context("Failed to deserialize player feedback")?;
+        if feedback.missing.len() > MAX_FEEDBACK_MISSING {
+            anyhow::bail!(
+                "Player feedback lists {} missing chunks; limit is {}",
+                feedback.missing.len(),
+                MAX_FEEDBACK_MISSING
+            );
+        }
+        Ok(feedback)
+    }
+}
+
+struct BufferState {
+    next_sequence: u64,
+    pending: BTreeMap<u64, Arc<ChunkData>>,
+    state: PlaybackState,
+    stall_started: Option<Instant>,
+}
+
+/// Reorders verified chunks and paces them out to the player
+pub struct PlaybackBuffer {
+    config: PlaybackConfig,
+    mode: StreamMode,
+    verifier: Option<Arc<SignatureVerifier>>,
+    state: Mutex<BufferState>,
+    metrics: Arc<PlaybackMetrics>,
+}
+
+impl PlaybackBuffer {
+    /// Buffer a stream whose first chunk is `start_sequence`
+    pub fn new(config: PlaybackConfig, mode: StreamMode, start_sequence: u64) -> Self {
+        Self {
+            config,
+            mode,
+            verifier: None,
+            state: Mutex::new(BufferState {
+                next_sequence: start_sequence,
+                pending: BTreeMap::new(),
+                state: PlaybackState::Buffering,
+                stall_started: None,
+            }),
+            metrics: Arc::new(PlaybackMetrics::default()),
+        }
+    }
+
+    /// Drop chunks whose signature does not verify
+    pub fn with_verifier(mut self, verifier: Arc<SignatureVerifier>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    pub fn mode(&self) -> StreamMode {
+        self.mode
+    }
+
+    pub fn metrics(&self) -> Arc<PlaybackMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Offer a received chunk
+    pub fn push(&self, chunk: ChunkData) -> PushOutcome {
+        if let Some(verifier) = &self.verifier {
+            if !verifier.verify(&chunk).unwrap_or(false) {
+                self.metrics.chunks_rejected.fetch_add(1, Ordering::Relaxed);
+                return PushOutcome::Rejected;
+            }
+        }
+
+        let mut state = self.state.lock();
+        let sequence = chunk.sequence;
+        if sequence < state.next_sequence {
+            self.metrics.chunks_late.fetch_add(1, Ordering::Relaxed);
+            return PushOutcome::Late;
+        }
+        if sequence - state.next_sequence >= self.config.max_buffered_chunks as u64 {
+            return PushOutcome::Overflow;
+        }
+        if state.pending.contains_key(&sequence) {
+            return PushOutcome::Duplicate;
+        }
+        state.pending.insert(sequence, Arc::new(chunk));
+        PushOutcome::Buffered
+    }
+
+    /// Next chunk to play, or `None` while buffering
+    ///
+    /// Call when the player needs more media. A missing chunk is an
+    /// underrun: live streams skip it if the target delay is buffered past
+    /// the gap, otherwise playback stalls until the target is refilled.
+    pub fn next_chunk(&self, now: Instant) -> Option<Arc<ChunkData>> {
+        let mut state = self.state.lock();
+
+        if state.state == PlaybackState::Buffering {
+            if self.mode == StreamMode::Live {
+                self.skip_gap(&mut state);
+            }
+            if self.contiguous(&state) < self.target_chunks() {
+                return None;
+            }
+            if let Some(started) = state.stall_started.take() {
+                let stalled = now.saturating_duration_since(started).as_millis() as u64;
+                self.metrics
+                    .stall_time_ms
+                    .fetch_add(stalled, Ordering::Relaxed);
+            }
+            state.state = PlaybackState::Playing;
+        } else if !state.pending.contains_key(&state.next_sequence) {
+            self.metrics.underruns.fetch_add(1, Ordering::Relaxed);
+            if self.mode == StreamMode::Vod || !self.skip_gap(&mut state) {
+                self.metrics.stalls.fetch_add(1, Ordering::Relaxed);
+                state.state = PlaybackState::Buffering;
+                state.stall_started = Some(now);
+                return None;
+            }
+        }
+
+        let next = state.next_sequence;
+        let chunk = state.pending.remove(&next)?;
+        state.next_sequence = next + 1;
+        self.metrics.chunks_played.fetch_add(1, Ordering::Relaxed);
+        Some(chunk)
+    }
+
+    /// Jump a live play head over missing chunks to the next buffered run,
+    /// if that run already covers the target delay
+    fn skip_gap(&self, state: &mut BufferState) -> bool {
+        let Some(&first) = state.pending.keys().next() else {
+            return false;
+        };
+        if first == state.next_sequence
+            || Self::run_length(&state.pending, first) < self.target_chunks()
+        {
+            return false;
+        }
+        self.metrics
+            .chunks_skipped
+            .fetch_add(first - state.next_sequence, Ordering::Relaxed);
+        state.next_sequence = first;
+        true
+    }
+
+    /// Move the play head of a VOD stream to `sequence`
+    ///
+    /// Buffered chunks outside the new window are dropped and playback
+    /// rebuffers from there. Seeking is not a stall.
+    pub fn seek(&self, sequence: u64) -> Result<()> {
+        if self.mode == StreamMode::Live {
+            anyhow::bail!("Live streams cannot seek");
+        }
+        let mut state = self.state.lock();
+        let window_end = sequence.saturating_add(self.config.max_buffered_chunks as u64);
+        state
+            .pending
+            .retain(|&s, _| s >= sequence && s < window_end);
+        state.next_sequence = sequence;
+        state.state = PlaybackState::Buffering;
+        state.stall_started = None;
+        Ok(())
+    }
+
+    /// Sequences within the target window that have not arrived, soonest first
+    pub fn missing(&self, limit: usize) -> Vec<u64> {
+        let state = self.state.lock();
+        let window = self.target_chunks().max(1);
+        (state.next_sequence..state.next_sequence + window)
+            .filter(|s| !state.pending.contains_key(s))
+            .take(limit)
+            .collect()
+    }
+
+    /// Chunks to fetch next: the missing sequences the manifest lists yet
+    pub fn missing_chunks(&self, manifest: &StreamManifest, limit: usize) -> Vec<(u64, ChunkId)> {
+        self.missing(limit)
+            .into_iter()
+            .filter_map(|s| Some((s, manifest.chunk_for_sequence(s)?)))
+            .collect()
+    }
+
+    pub fn health(&self) -> BufferHealth {
+        let state = self.state.lock();
+        let contiguous = self.contiguous(&state);
+        let skipped = self.metrics.chunks_skipped.load(Ordering::Relaxed);
+        let due = skipped + self.metrics.chunks_played.load(Ordering::Relaxed);
+        BufferHealth {
+            state: state.state,
+            next_sequence: state.next_sequence,
+            buffered: self.chunk_duration() * contiguous as u32,
+            buffered_chunks: state.pending.len(),
+            target_delay: Duration::from_millis(self.config.target_delay_ms),
+            underruns: self.metrics.underruns.load(Ordering::Relaxed),
+            stalls: self.metrics.stalls.load(Ordering::Relaxed),
+            loss_rate: if due == 0 {
+                0.0
+            } else {
+                skipped as f32 / due as f32
+            },
+        }
+    }
+
+    /// Feedback message describing this buffer, sent as `peer`
+    pub fn feedback(&self, peer: PeerId) -> PlayerFeedback {
+        let health = self.health();
+        PlayerFeedback {
+            peer,
+            state: health.state,
+            next_sequence: health.next_sequence,
+            buffered_ms: health.buffered.as_millis() as u64,
+            target_delay_ms: self.config.target_delay_ms,
+            missing: self.missing(MAX_FEEDBACK_MISSING),
+            underruns: health.underruns,
+            stalls: health.stalls,
+            loss_rate: health.loss_rate,
+        }
+    }
+
+    fn chunk_duration(&self) -> Duration {
+        Duration::from_millis(self.config.chunk_duration_ms)
+    }
+
+    /// Chunks needed to cover the target delay
+    fn target_chunks(&self) -> u64 {
+        self.config
+            .target_delay_ms
+            .div_ceil(self.config.chunk_duration_ms.max(1))
+    }
+
+    /// Chunks ready from the play head without a gap
+    fn contiguous(&self, state: &BufferState) -> u64 {
+        Self::run_length(&state.pending, state.next_sequence)
+    }
+
+    fn run_length(pending: &BTreeMap<u64, Arc<ChunkData>>, from: u64) -> u64 {
+        pending
+            .range(from..)
+            .enumerate()
+            .take_while(|(offset, (&sequence, _))| sequence == from + *offset as u64)
+            .count() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dcdn::types::{ChunkId, Signature};
+    use bytes::Bytes;
+
+    fn chunk(sequence: u64) -> ChunkData {
+        ChunkData {
+            id: ChunkId(sequence),
+            sequence,
+            timestamp: Instant::now(),
+            source_peer: PeerId(1),
+            signature: Signature([0u8; 64]),
+            data: Bytes::from(vec![sequence as u8]),
+            fec_group: None,
+        }
+    }
+
+    fn config() -> PlaybackConfig {
+        PlaybackConfig {
+            target_delay_ms: 300,
+            chunk_duration_ms: 100,
+            max_buffered_chunks: 10,
+        }
+    }
+
+    #[test]
+    fn test_reorders_and_waits_for_target_delay() {
+        let buffer = PlaybackBuffer::new(config(), StreamMode::Vod, 0);
+        let now = Instant::now();
+        assert_eq!(buffer.push(chunk(1)), PushOutcome::Buffered);
+        assert_eq!(buffer.push(chunk(2)), PushOutcome::Buffered);
+        assert_eq!(buffer.push(chunk(2)), PushOutcome::Duplicate);
+        assert_eq!(buffer.push(chunk(50)), PushOutcome::Overflow);
+        assert!(buffer.next_chunk(now).is_none());
+        assert_eq!(buffer.missing(8), vec![0]);
+
+        buffer.push(chunk(0));
+        let played: Vec<u64> = (0..3)
+            .map(|_| buffer.next_chunk(now).unwrap().sequence)
+            .collect();
+        assert_eq!(played, vec![0, 1, 2]);
+        assert_eq!(buffer.push(chunk(1)), PushOutcome::Late);
+
+        // Running dry is an underrun and a stall
+        assert!(buffer.next_chunk(now).is_none());
+        let health = buffer.health();
+        assert_eq!(health.state, PlaybackState::Buffering);
+        assert_eq!((health.underruns, health.stalls), (1, 1));
+
+        buffer.seek(20).unwrap();
+        assert_eq!(buffer.health().next_sequence, 20);
+    }
+
+    #[test]
+    fn test_live_streams_skip_lost_chunks() {
+        let buffer = PlaybackBuffer::new(config(), StreamMode::Live, 0);
+        let now = Instant::now();
+        for sequence in [0, 1, 2, 4, 5, 6] {
+            buffer.push(chunk(sequence));
+        }
+        for expected in [0, 1, 2, 4] {
+            assert_eq!(buffer.next_chunk(now).unwrap().sequence, expected);
+        }
+        assert!(buffer.seek(0).is_err());
+
+        let metrics = buffer.metrics();
+        assert_eq!(metrics.underruns.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.stalls.load(Ordering::Relaxed), 0);
+        assert_eq!(buffer.health().loss_rate, 0.2);
+
+        let feedback = buffer.feedback(PeerId(9));
+        assert_eq!(feedback.buffered_ms, 200);
+        assert_eq!(feedback.missing, vec![7]);
+        let decoded = PlayerFeedback::from_bytes(&feedback.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, feedback);
+        assert!(metrics
+            .export_prometheus("dcdn")
+            .contains("dcdn_playback_chunks_skipped_total 1"));
+    }
+
+    #[test]
+    fn test_missing_chunks_come_from_the_manifest() {
+        use crate::dcdn::manifest::{ChunkWindow, CodecInfo};
+
+        let buffer = PlaybackBuffer::new(config(), StreamMode::Live, 0);
+        buffer.push(chunk(1));
+        let mut manifest = StreamManifest::new(
+            "live",
+            StreamMode::Live,
+            CodecInfo {
+                codecs: "avc1.64001f".into(),
+                container: "mpegts".into(),
+                bitrate_kbps: 1000,
+                width: 640,
+                height: 360,
+            },
+        );
+        manifest
+            .push_window(ChunkWindow {
+                start_ms: 0,
+                first_sequence: 0,
+                first_chunk: ChunkId(100),
+                count: 2,
+                chunk_duration_ms: 100,
+            })
+            .unwrap();
+
+        // Sequence 2 is missing too, but not published yet
+        assert_eq!(buffer.missing(8), vec![0, 2]);
+        assert_eq!(buffer.missing_chunks(&manifest, 8), vec![(0, ChunkId(100))]);
+    }
+}
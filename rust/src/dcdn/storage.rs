@@ -1,7 +1,13 @@
 //! Lock-free ring buffer for chunk storage
+//!
+//! Chunks pushed out of a full ring are dropped, unless the store has an
+//! overflow [`BlobStore`]: then their bytes move there and they stay
+//! readable until their TTL runs out.
 
+use crate::blob::{BlobId, BlobStore};
 use crate::dcdn::types::{ChunkData, ChunkId, StorageStats};
 use anyhow::Result;
+use bytes::Bytes;
 use dashmap::DashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -15,6 +21,9 @@ pub struct ChunkStore {
     write_head: AtomicUsize,
     /// Fast lookup: chunk_id → slot index
     index: DashMap<ChunkId, usize>,
+    /// Chunks evicted from the ring, with their bytes in `overflow`
+    spilled: DashMap<ChunkId, (ChunkData, BlobId)>,
+    overflow: Option<Arc<dyn BlobStore>>,
     /// Configuration
     capacity: usize,
     chunk_ttl: Duration,
@@ -36,6 +45,8 @@ impl ChunkStore {
             slots,
             write_head: AtomicUsize::new(0),
             index: DashMap::new(),
+            spilled: DashMap::new(),
+            overflow: None,
             capacity,
             chunk_ttl,
             evictions_total: AtomicUsize::new(0),
@@ -44,10 +55,17 @@ impl ChunkStore {
         }
     }
 
+    /// Keep chunks evicted from the ring in `blobs` until they expire
+    pub fn with_overflow(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.overflow = Some(blobs);
+        self
+    }
+
     /// Insert a chunk into the store
     pub fn insert(&self, chunk: ChunkData) -> Result<()> {
         let chunk_id = chunk.id;
         let chunk_arc = Arc::new(chunk);
+        self.drop_spilled(&chunk_id);
 
         // Check if chunk already exists and remove from old slot to prevent memory waste
         if let Some((_, old_slot_idx)) = self.index.remove(&chunk_id) {
@@ -68,6 +86,7 @@ impl ChunkStore {
             if let Some(old_chunk) = slot.take() {
                 self.index.remove(&old_chunk.id);
                 self.evictions_total.fetch_add(1, Ordering::Relaxed);
+                self.spill(&old_chunk);
             }
             *slot = Some(chunk_arc);
             // Update index while holding the lock to avoid race condition
@@ -77,6 +96,34 @@ impl ChunkStore {
         Ok(())
     }
 
+    /// Move an evicted chunk's bytes to the overflow store, if there is one
+    fn spill(&self, chunk: &ChunkData) {
+        let Some(blobs) = &self.overflow else {
+            return;
+        };
+        match blobs.put(&chunk.data) {
+            Ok(blob) => {
+                let meta = ChunkData {
+                    data: Bytes::new(),
+                    ..chunk.clone()
+                };
+                self.spilled.insert(chunk.id, (meta, blob));
+            }
+            Err(e) => tracing::debug!("Dropping chunk {:?}: {}", chunk.id, e),
+        }
+    }
+
+    fn drop_spilled(&self, id: &ChunkId) -> Option<ChunkData> {
+        let (_, (meta, blob)) = self.spilled.remove(id)?;
+        let blobs = self.overflow.as_ref()?;
+        let data = blobs.get(&blob);
+        blobs.release(&blob);
+        Some(ChunkData {
+            data: data?,
+            ..meta
+        })
+    }
+
     /// Get a chunk by ID
     pub fn get(&self, id: &ChunkId) -> Option<Arc<ChunkData>> {
         if let Some(slot_idx) = self.index.get(id) {
@@ -86,17 +133,31 @@ impl ChunkStore {
                 return Some(Arc::clone(chunk));
             }
         }
+        if let Some(chunk) = self.get_spilled(id) {
+            self.hits_total.fetch_add(1, Ordering::Relaxed);
+            return Some(Arc::new(chunk));
+        }
         self.misses_total.fetch_add(1, Ordering::Relaxed);
         None
     }
 
+    fn get_spilled(&self, id: &ChunkId) -> Option<ChunkData> {
+        let spilled = self.spilled.get(id)?;
+        let (meta, blob) = spilled.value();
+        let data = self.overflow.as_ref()?.get(blob)?;
+        Some(ChunkData {
+            data,
+            ..meta.clone()
+        })
+    }
+
     /// Remove a chunk by ID
     pub fn remove(&self, id: &ChunkId) -> Option<Arc<ChunkData>> {
         if let Some((_, slot_idx)) = self.index.remove(id) {
             let mut slot = self.slots[slot_idx].write();
             return slot.take();
         }
-        None
+        self.drop_spilled(id).map(Arc::new)
     }
 
     /// List expired chunks
@@ -113,6 +174,11 @@ impl ChunkStore {
                 }
             }
         }
+        for entry in self.spilled.iter() {
+            if now.duration_since(entry.value().0.timestamp) > self.chunk_ttl {
+                expired.push(*entry.key());
+            }
+        }
 
         expired
     }
@@ -166,6 +232,7 @@ impl ChunkStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blob::TieredBlobStore;
     use crate::dcdn::types::{PeerId, Signature};
     use bytes::Bytes;
 
@@ -213,6 +280,30 @@ mod tests {
         assert!(store.get(&ChunkId(4)).is_some());
     }
 
+    #[test]
+    fn test_evicted_chunks_overflow_to_blob_store() {
+        let blobs = Arc::new(TieredBlobStore::unbounded());
+        let store = ChunkStore::new(2, Duration::from_millis(50)).with_overflow(blobs.clone());
+        for i in 0..4 {
+            store
+                .insert(create_test_chunk(i, vec![i as u8; 8]))
+                .unwrap();
+        }
+        assert_eq!(store.len(), 2);
+        assert_eq!(blobs.stats().blobs, 2);
+
+        let spilled = store.get(&ChunkId(0)).unwrap();
+        assert_eq!(
+            (spilled.sequence, spilled.data.as_ref()),
+            (0, &[0u8; 8][..])
+        );
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(store.evict_expired(Instant::now()), 4);
+        assert!(store.get(&ChunkId(1)).is_none());
+        assert_eq!(blobs.stats().blobs, 0);
+    }
+
     #[test]
     fn test_expired_chunks() {
         let store = ChunkStore::new(10, Duration::from_millis(10));
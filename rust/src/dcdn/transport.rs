@@ -4,19 +4,31 @@ use crate::dcdn::config::QuicConfig;
 use crate::dcdn::types::{ChunkData, PeerId};
 use anyhow::{Context, Result};
 use dashmap::DashMap;
-use quinn::{Connection, Endpoint, ServerConfig};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{ClientConfig, Connection, Endpoint, ServerConfig};
+use rustls::client::Resumption;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::debug;
+
+/// TLS sessions remembered for 0-RTT resumption
+const SESSION_CACHE_SIZE: usize = 1024;
 
 /// Handle to a QUIC connection
 pub type ConnectionHandle = Arc<Connection>;
 
 /// QUIC transport manager
+///
+/// With `enable_0rtt`, sessions with peers are remembered and resumed with
+/// 0-RTT on reconnect, so chunks go out with the first packet. Chunks are
+/// signed and content addressed, so a replayed push is verified and
+/// discarded as a duplicate like any other.
 pub struct QuicTransport {
     endpoint: Arc<Mutex<Option<Endpoint>>>,
     active_connections: DashMap<PeerId, Connection>,
     config: Arc<QuicConfig>,
+    resumption: Resumption,
 }
 
 impl QuicTransport {
@@ -26,6 +38,7 @@ impl QuicTransport {
             endpoint: Arc::new(Mutex::new(None)),
             active_connections: DashMap::new(),
             config: Arc::new(config),
+            resumption: Resumption::in_memory_sessions(SESSION_CACHE_SIZE),
         }
     }
 
@@ -49,17 +62,30 @@ impl QuicTransport {
     ) -> Result<ConnectionHandle> {
         // Check if already connected
         if let Some(conn) = self.active_connections.get(&peer_id) {
-            return Ok(Arc::new(conn.clone()));
+            if conn.close_reason().is_none() {
+                return Ok(Arc::new(conn.clone()));
+            }
         }
 
         let endpoint = self.endpoint.lock().await;
         let endpoint = endpoint.as_ref().context("Endpoint not initialized")?;
 
-        let conn = endpoint
-            .connect(peer_addr, "localhost")
-            .context("Failed to initiate connection")?
-            .await
-            .context("Connection failed")?;
+        // Sessions are remembered per peer, so each gets its own server name
+        let client_config = Self::create_client_config(&self.config, &self.resumption)?;
+        let connecting = endpoint
+            .connect_with(
+                client_config,
+                peer_addr,
+                &format!("peer-{}.dcdn", peer_id.0),
+            )
+            .context("Failed to initiate connection")?;
+        let conn = match connecting.into_0rtt() {
+            Ok((conn, _)) => {
+                debug!("Resuming session with peer {:?} (0-RTT)", peer_id);
+                conn
+            }
+            Err(connecting) => connecting.await.context("Connection failed")?,
+        };
 
         self.active_connections.insert(peer_id, conn.clone());
 
@@ -71,12 +97,21 @@ impl QuicTransport {
         let endpoint = self.endpoint.lock().await;
         let endpoint = endpoint.as_ref().context("Endpoint not initialized")?;
 
-        let conn = endpoint
+        let connecting = endpoint
             .accept()
             .await
             .context("No incoming connection")?
-            .await
+            .accept()
             .context("Failed to accept connection")?;
+        // Read early chunks from resumed sessions right away (0.5-RTT)
+        let conn = if self.config.enable_0rtt {
+            match connecting.into_0rtt() {
+                Ok((conn, _)) => conn,
+                Err(connecting) => connecting.await.context("Failed to accept connection")?,
+            }
+        } else {
+            connecting.await.context("Failed to accept connection")?
+        };
 
         // Derive peer ID from TLS certificate using peer identity
         let peer_id = Self::derive_peer_id_from_connection(&conn)?;
@@ -179,10 +214,19 @@ impl QuicTransport {
 
         let priv_key = rustls::pki_types::PrivateKeyDer::try_from(key_der)
             .map_err(|e| anyhow::anyhow!("Failed to parse private key: {}", e))?;
-        let cert_chain = vec![rustls::pki_types::CertificateDer::from(cert_der)];
+        let cert = rustls::pki_types::CertificateDer::from(cert_der);
 
-        let mut server_config = ServerConfig::with_single_cert(cert_chain, priv_key)
+        let mut crypto = crate::network::server_crypto(cert, priv_key)
             .context("Failed to create server config")?;
+        if config.enable_0rtt {
+            crypto.session_storage =
+                rustls::server::ServerSessionMemoryCache::new(SESSION_CACHE_SIZE);
+            crypto.max_early_data_size = u32::MAX;
+        } else {
+            crypto.max_early_data_size = 0;
+        }
+        let mut server_config =
+            ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
 
         let mut transport_config = quinn::TransportConfig::default();
         transport_config.max_concurrent_uni_streams(
@@ -212,6 +256,22 @@ impl QuicTransport {
 
         Ok(server_config)
     }
+
+    /// Create client configuration, resuming sessions kept in `resumption`
+    fn create_client_config(config: &QuicConfig, resumption: &Resumption) -> Result<ClientConfig> {
+        let mut crypto = crate::network::client_crypto(resumption, None)?;
+        crypto.enable_early_data = config.enable_0rtt;
+        let mut client_config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto)?));
+
+        let mut transport_config = quinn::TransportConfig::default();
+        transport_config.max_idle_timeout(Some(
+            quinn::IdleTimeout::try_from(std::time::Duration::from_millis(config.idle_timeout_ms))
+                .unwrap_or(quinn::IdleTimeout::from(quinn::VarInt::from_u32(30000))),
+        ));
+        client_config.transport_config(Arc::new(transport_config));
+
+        Ok(client_config)
+    }
 }
 
 #[cfg(test)]
@@ -242,6 +302,7 @@ mod tests {
             enable_gso: true,
             idle_timeout_ms: 30000,
             max_chunk_size: 10 * 1024 * 1024,
+            enable_0rtt: true,
         };
 
         let transport = QuicTransport::new(config);
@@ -257,6 +318,7 @@ mod tests {
             enable_gso: true,
             idle_timeout_ms: 30000,
             max_chunk_size: 10 * 1024 * 1024,
+            enable_0rtt: true,
         };
 
         let transport = QuicTransport::new(config);
@@ -0,0 +1,371 @@
+/// Hash deny-list for content this node refuses to host
+///
+/// Operators list file hashes or shard content hashes (SHA-256, hex) that
+/// must not be stored. Entries come from a local file, edited through the
+/// `deny` CLI command, and optionally from a remote list fetched
+/// periodically. Every local change is appended to an audit log next to the
+/// list file.
+///
+/// `Cache::put_shard` consults the list before storing; any other path that
+/// persists received shards should call [`DenyList::check_shard`] first.
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Default file name of the deny-list inside the cache directory
+pub const DENY_LIST_FILE: &str = "denylist.txt";
+
+/// Default interval between remote list refreshes
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Largest remote list accepted
+const MAX_REMOTE_LIST_BYTES: usize = 16 * 1024 * 1024;
+
+/// Content was refused because its hash is deny-listed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("content {hash} is on the deny list")]
+pub struct Denied {
+    pub hash: String,
+}
+
+/// One locally configured entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenyEntry {
+    pub hash: String,
+    pub reason: Option<String>,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DenyAuditRecord {
+    pub timestamp: i64,
+    pub action: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Local user that made the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// Set of hashes this node refuses to store
+#[derive(Default)]
+pub struct DenyList {
+    path: Option<PathBuf>,
+    local: RwLock<BTreeMap<String, Option<String>>>,
+    remote: RwLock<HashSet<String>>,
+}
+
+impl DenyList {
+    /// In-memory list that is not persisted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the list at `path`; a missing file is an empty list
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let local = match std::fs::read_to_string(&path) {
+            Ok(text) => parse_list(&text)
+                .into_iter()
+                .map(|entry| (entry.hash, entry.reason))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read deny list {}", path.display()))
+            }
+        };
+        if !local.is_empty() {
+            info!(
+                "🚫 Loaded {} deny-list entries from {}",
+                local.len(),
+                path.display()
+            );
+        }
+        Ok(Self {
+            path: Some(path),
+            local: RwLock::new(local),
+            remote: RwLock::default(),
+        })
+    }
+
+    /// Deny `hash`, returning false if it was already listed
+    pub fn add(&self, hash: &str, reason: Option<&str>) -> Result<bool> {
+        let hash = normalize_hash(hash)?;
+        let added = {
+            let mut local = self.local.write();
+            if local.contains_key(&hash) {
+                false
+            } else {
+                local.insert(hash.clone(), reason.map(str::to_string));
+                true
+            }
+        };
+        if added {
+            self.save()?;
+            self.audit("add", &hash, reason)?;
+            info!("🚫 Added {} to the deny list", hash);
+        }
+        Ok(added)
+    }
+
+    /// Stop denying `hash`, returning false if it was not listed locally
+    pub fn remove(&self, hash: &str) -> Result<bool> {
+        let hash = normalize_hash(hash)?;
+        let removed = self.local.write().remove(&hash).is_some();
+        if removed {
+            self.save()?;
+            self.audit("remove", &hash, None)?;
+            info!("Removed {} from the deny list", hash);
+        }
+        Ok(removed)
+    }
+
+    /// Locally configured entries, sorted by hash
+    pub fn entries(&self) -> Vec<DenyEntry> {
+        self.local
+            .read()
+            .iter()
+            .map(|(hash, reason)| DenyEntry {
+                hash: hash.clone(),
+                reason: reason.clone(),
+            })
+            .collect()
+    }
+
+    /// Number of hashes denied, local and remote
+    pub fn len(&self) -> usize {
+        let local = self.local.read();
+        let remote = self.remote.read();
+        local.len() + remote.iter().filter(|h| !local.contains_key(*h)).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.local.read().is_empty() && self.remote.read().is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        let hash = hash.to_ascii_lowercase();
+        self.local.read().contains_key(&hash) || self.remote.read().contains(&hash)
+    }
+
+    /// Refuse a shard if its file hash or its content hash is denied
+    pub fn check_shard(&self, file_hash: &str, data: &[u8]) -> Result<(), Denied> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if self.contains(file_hash) {
+            return Err(Denied {
+                hash: file_hash.to_string(),
+            });
+        }
+        let content_hash = format!("{:x}", Sha256::digest(data));
+        if self.contains(&content_hash) {
+            return Err(Denied { hash: content_hash });
+        }
+        Ok(())
+    }
+
+    /// Replace the remotely sourced entries
+    pub fn replace_remote(&self, hashes: HashSet<String>) {
+        *self.remote.write() = hashes;
+    }
+
+    /// Fetch the list at `url` and make it the remote set
+    pub async fn refresh_from(&self, url: &str) -> Result<usize> {
+        let response = reqwest::get(url)
+            .await
+            .with_context(|| format!("Failed to fetch deny list from {}", url))?
+            .error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|len| len as usize > MAX_REMOTE_LIST_BYTES)
+        {
+            anyhow::bail!("Remote deny list at {} is too large", url);
+        }
+        let text = response.text().await?;
+        if text.len() > MAX_REMOTE_LIST_BYTES {
+            anyhow::bail!("Remote deny list at {} is too large", url);
+        }
+
+        let hashes: HashSet<String> = parse_list(&text)
+            .into_iter()
+            .map(|entry| entry.hash)
+            .collect();
+        let count = hashes.len();
+        self.replace_remote(hashes);
+        Ok(count)
+    }
+
+    /// Refresh the remote set from `url` every `interval`
+    ///
+    /// A failed refresh keeps the previous remote entries.
+    pub fn spawn_refresh(self: Arc<Self>, url: String, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.refresh_from(&url).await {
+                    Ok(count) => info!("🚫 Refreshed remote deny list: {} entries", count),
+                    Err(e) => warn!("Failed to refresh deny list from {}: {:#}", url, e),
+                }
+            }
+        })
+    }
+
+    /// Audit log path for a list stored at `path`
+    pub fn audit_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".audit.log");
+        path.with_file_name(name)
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut text = String::from("# Hashes this node refuses to store (SHA-256, hex)\n");
+        for (hash, reason) in self.local.read().iter() {
+            match reason {
+                Some(reason) => text.push_str(&format!("{}  # {}\n", hash, reason)),
+                None => text.push_str(&format!("{}\n", hash)),
+            }
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write then rename so a crash never leaves a truncated list
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text)
+            .with_context(|| format!("Failed to write deny list {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace deny list {}", path.display()))?;
+        Ok(())
+    }
+
+    fn audit(&self, action: &str, hash: &str, reason: Option<&str>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let record = DenyAuditRecord {
+            timestamp: chrono::Utc::now().timestamp(),
+            action: action.to_string(),
+            hash: hash.to_string(),
+            reason: reason.map(str::to_string),
+            user: std::env::var("USER").ok(),
+        };
+        let audit_path = Self::audit_path(path);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&audit_path)
+            .with_context(|| format!("Failed to open audit log {}", audit_path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    }
+}
+
+/// Lower-case a hex hash, rejecting anything else
+fn normalize_hash(hash: &str) -> Result<String> {
+    let hash = hash.trim().to_ascii_lowercase();
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("{:?} is not a hex hash", hash);
+    }
+    Ok(hash)
+}
+
+/// One hash per line, with optional `# reason` comments
+///
+/// Lines that are not hex hashes are skipped.
+fn parse_list(text: &str) -> Vec<DenyEntry> {
+    text.lines()
+        .filter_map(|line| {
+            let (hash, reason) = match line.split_once('#') {
+                Some((hash, reason)) => (hash, Some(reason.trim())),
+                None => (line, None),
+            };
+            let hash = normalize_hash(hash).ok()?;
+            Some(DenyEntry {
+                hash,
+                reason: reason.filter(|r| !r.is_empty()).map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_add_remove_persist_and_audit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(DENY_LIST_FILE);
+
+        let list = DenyList::open(&path).unwrap();
+        assert!(list.add("ABCDEF01", Some("takedown #42")).unwrap());
+        assert!(!list.add("abcdef01", None).unwrap());
+        assert!(list.add("1234", None).unwrap());
+        assert!(list.add("not-a-hash", None).is_err());
+
+        let reopened = DenyList::open(&path).unwrap();
+        assert_eq!(
+            reopened.entries(),
+            vec![
+                DenyEntry {
+                    hash: "1234".to_string(),
+                    reason: None
+                },
+                DenyEntry {
+                    hash: "abcdef01".to_string(),
+                    reason: Some("takedown #42".to_string())
+                },
+            ]
+        );
+
+        assert!(reopened.remove("1234").unwrap());
+        assert!(!reopened.remove("1234").unwrap());
+        assert!(!DenyList::open(&path).unwrap().contains("1234"));
+
+        let audit = std::fs::read_to_string(DenyList::audit_path(&path)).unwrap();
+        let actions: Vec<String> = audit
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<DenyAuditRecord>(line)
+                    .unwrap()
+                    .action
+            })
+            .collect();
+        assert_eq!(actions, vec!["add", "add", "remove"]);
+    }
+
+    #[test]
+    fn test_check_shard() {
+        let list = DenyList::new();
+        let shard = b"forbidden bytes";
+        assert!(list.check_shard("f00d", shard).is_ok());
+
+        list.add("f00d", None).unwrap();
+        assert_eq!(list.check_shard("F00D", b"other").unwrap_err().hash, "F00D");
+
+        let content_hash = format!("{:x}", Sha256::digest(shard));
+        list.replace_remote(HashSet::from([content_hash.clone()]));
+        assert_eq!(
+            list.check_shard("beef", shard).unwrap_err().hash,
+            content_hash
+        );
+        assert!(list.check_shard("beef", b"fine").is_ok());
+        assert_eq!(list.len(), 2);
+    }
+}
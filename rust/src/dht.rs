@@ -1,35 +1,372 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::{select, Either};
 use libp2p::{
+    connection_limits::{self, ConnectionLimits},
+    core::{transport::PortUse, Endpoint},
     identify,
-    kad::{self, store::MemoryStore, Mode, Record, RecordKey},
+    kad::{
+        self,
+        store::{MemoryStore, RecordStore},
+        Mode, Record, RecordKey,
+    },
+    memory_connection_limits,
+    multiaddr::Protocol,
     noise, ping,
-    swarm::{NetworkBehaviour, Swarm, SwarmEvent},
+    swarm::{
+        dummy, ConnectionDenied, ConnectionId, DialError, FromSwarm, ListenError, NetworkBehaviour,
+        Swarm, SwarmEvent, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
     tcp, Multiaddr, PeerId,
 };
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
 use tokio::{sync::RwLock, time::sleep};
-use tracing::{debug, info, warn};
+use tracing::{debug, info, instrument, warn};
+
+use crate::capabilities::HardwareCaps;
+use crate::dnsaddr::peer_of;
+use crate::firewall::{AcceptPath, Firewall};
+use crate::records::{RecordKind, RecordValidator};
+
+/// Receive window yamux grants each stream, which bounds its buffered data
+const YAMUX_STREAM_WINDOW: u64 = 256 * 1024;
+
+/// Swarm limit overrides (`[swarm]` table of the node config file)
+///
+/// Limits left unset are derived from the machine by [`SwarmLimits::for_caps`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwarmConfig {
+    /// Inbound connections still handshaking
+    pub max_pending_incoming: Option<u32>,
+    /// Dials in progress
+    pub max_pending_outgoing: Option<u32>,
+    /// Established inbound connections; the rest of the total is kept for
+    /// this node's own dials
+    pub max_established_incoming: Option<u32>,
+    /// Established connections to a single peer
+    pub max_established_per_peer: Option<u32>,
+    /// Established connections, inbound and outbound
+    pub max_established_total: Option<u32>,
+    /// Yamux streams open on one connection
+    pub max_streams_per_connection: Option<usize>,
+    /// Share of physical memory (0 - 1) the process may use before new
+    /// connections are refused
+    pub max_memory_fraction: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    swarm: SwarmConfig,
+}
+
+impl SwarmConfig {
+    /// Load the `[swarm]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[swarm]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.swarm.validate()?;
+        Ok(file.swarm)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let counts = [
+            ("max_pending_incoming", self.max_pending_incoming),
+            ("max_pending_outgoing", self.max_pending_outgoing),
+            ("max_established_incoming", self.max_established_incoming),
+            ("max_established_per_peer", self.max_established_per_peer),
+            ("max_established_total", self.max_established_total),
+        ];
+        for (name, limit) in counts {
+            if limit == Some(0) {
+                bail!("swarm: {} must be at least 1", name);
+            }
+        }
+        if self.max_streams_per_connection == Some(0) {
+            bail!("swarm: max_streams_per_connection must be at least 1");
+        }
+        if let (Some(incoming), Some(total)) =
+            (self.max_established_incoming, self.max_established_total)
+        {
+            if incoming > total {
+                bail!("swarm: max_established_incoming must not exceed max_established_total");
+            }
+        }
+        if let Some(fraction) = self.max_memory_fraction {
+            if !(fraction > 0.0 && fraction < 1.0) {
+                bail!("swarm: max_memory_fraction must be between 0 and 1");
+            }
+        }
+        Ok(())
+    }
+
+    /// Limits for this machine, with the configured overrides applied
+    pub fn resolve(&self, caps: &HardwareCaps) -> SwarmLimits {
+        let derived = SwarmLimits::for_caps(caps);
+        let established_total = self
+            .max_established_total
+            .unwrap_or(derived.established_total);
+        SwarmLimits {
+            pending_incoming: self
+                .max_pending_incoming
+                .unwrap_or(derived.pending_incoming),
+            pending_outgoing: self
+                .max_pending_outgoing
+                .unwrap_or(derived.pending_outgoing),
+            established_incoming: self
+                .max_established_incoming
+                .unwrap_or(incoming_share(established_total)),
+            established_per_peer: self
+                .max_established_per_peer
+                .unwrap_or(derived.established_per_peer),
+            established_total,
+            streams_per_connection: self
+                .max_streams_per_connection
+                .unwrap_or(derived.streams_per_connection),
+            memory_fraction: self.max_memory_fraction.unwrap_or(derived.memory_fraction),
+        }
+    }
+}
+
+/// Connection and stream limits the swarm enforces
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SwarmLimits {
+    pub pending_incoming: u32,
+    pub pending_outgoing: u32,
+    pub established_incoming: u32,
+    pub established_per_peer: u32,
+    pub established_total: u32,
+    pub streams_per_connection: usize,
+    pub memory_fraction: f64,
+}
+
+impl Default for SwarmLimits {
+    fn default() -> Self {
+        Self {
+            pending_incoming: 32,
+            pending_outgoing: 32,
+            established_incoming: incoming_share(256),
+            established_per_peer: 2,
+            established_total: 256,
+            streams_per_connection: 64,
+            memory_fraction: 0.8,
+        }
+    }
+}
+
+impl SwarmLimits {
+    /// Limits scaled to the machine: connections by cores, capped by memory,
+    /// and streams per connection so that every connection's stream windows
+    /// together fit in an eighth of RAM
+    pub fn for_caps(caps: &HardwareCaps) -> Self {
+        let cores = caps.cpu_cores.clamp(1, 1024) as u32;
+        let ram_gb = caps.ram_gb.clamp(1, 1 << 20);
+        let established_total = (cores * 64).min(ram_gb as u32 * 128).clamp(64, 2048);
+        let pending = (cores * 8).clamp(16, 256);
+        let stream_budget = (ram_gb << 30) / 8 / YAMUX_STREAM_WINDOW;
+        let streams_per_connection =
+            (stream_budget / established_total as u64).clamp(16, 512) as usize;
+        Self {
+            pending_incoming: pending,
+            pending_outgoing: pending,
+            established_incoming: incoming_share(established_total),
+            established_per_peer: 2,
+            established_total,
+            streams_per_connection,
+            memory_fraction: 0.8,
+        }
+    }
+
+    fn connection_limits(&self) -> ConnectionLimits {
+        ConnectionLimits::default()
+            .with_max_pending_incoming(Some(self.pending_incoming))
+            .with_max_pending_outgoing(Some(self.pending_outgoing))
+            .with_max_established_incoming(Some(self.established_incoming))
+            .with_max_established_per_peer(Some(self.established_per_peer))
+            .with_max_established(Some(self.established_total))
+    }
+}
+
+/// Inbound connections may take three quarters of the total, so peers
+/// dialing in cannot crowd out this node's own dials
+fn incoming_share(total: u32) -> u32 {
+    (total * 3 / 4).max(1)
+}
+
+/// Swarm resource usage against its limits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SwarmStats {
+    pub connected_peers: usize,
+    pub pending_incoming: u32,
+    pub pending_outgoing: u32,
+    pub established_incoming: u32,
+    pub established_outgoing: u32,
+    /// Connections refused because a connection limit was reached
+    pub denied_by_limits: u64,
+    /// Connections refused because the process was over its memory limit
+    pub denied_by_memory: u64,
+    pub limits: SwarmLimits,
+}
 
 #[derive(NetworkBehaviour)]
 pub struct PangeaBehaviour {
+    pub limits: connection_limits::Behaviour,
+    pub memory: memory_connection_limits::Behaviour,
     pub kad: kad::Behaviour<MemoryStore>,
     pub identify: identify::Behaviour,
     pub ping: ping::Behaviour,
+    pub firewall: FirewallGate,
+}
+
+/// Connection gate that consults the firewall
+///
+/// Inbound connections are checked by remote address before the handshake,
+/// then every connection is checked by peer ID once it is established.
+/// Admits everything until a firewall is attached.
+#[derive(Default)]
+pub struct FirewallGate {
+    firewall: Option<Arc<Firewall>>,
+}
+
+impl FirewallGate {
+    fn check_peer(&self, peer: &PeerId) -> Result<(), ConnectionDenied> {
+        match &self.firewall {
+            Some(firewall) => firewall
+                .admit_peer(AcceptPath::Libp2p, &peer.to_string())
+                .map_err(ConnectionDenied::new),
+            None => Ok(()),
+        }
+    }
+}
+
+impl NetworkBehaviour for FirewallGate {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = <dummy::Behaviour as NetworkBehaviour>::ToSwarm;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        match (&self.firewall, multiaddr_ip(remote_addr)) {
+            (Some(firewall), Some(ip)) => firewall
+                .admit(AcceptPath::Libp2p, ip)
+                .map_err(ConnectionDenied::new),
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut TaskContext<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+/// IP address a multiaddr points at, if it has one
+fn multiaddr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
 }
 
 pub struct DhtNode {
     swarm: Swarm<PangeaBehaviour>,
     peer_id: PeerId,
-    #[allow(dead_code)]
     bootstrap_peers: Vec<Multiaddr>,
+    /// Failed dials to bootstrap peers since one last connected
+    bootstrap_failures: u32,
+    limits: SwarmLimits,
+    denied_by_limits: u64,
+    denied_by_memory: u64,
+    /// Signs published records
+    keypair: libp2p::identity::Keypair,
+    validator: Arc<RecordValidator>,
+    rejected_records: u64,
+    /// Shard filters other peers put here, not yet taken by the caller
+    shard_adverts: Vec<Vec<u8>>,
 }
 
 impl DhtNode {
-    /// Create a new DHT node
-    pub async fn new(_port: u16, bootstrap_peers: Vec<Multiaddr>) -> Result<Self> {
-        // Generate keypair
-        let local_key = libp2p::identity::Keypair::generate_ed25519();
+    /// Create a new DHT node with an ephemeral key
+    pub async fn new(port: u16, bootstrap_peers: Vec<Multiaddr>) -> Result<Self> {
+        Self::with_keypair(
+            port,
+            bootstrap_peers,
+            libp2p::identity::Keypair::generate_ed25519(),
+        )
+        .await
+    }
+
+    /// Create a DHT node running under `local_key`, such as the node's
+    /// [`crate::identity::NodeIdentity`], so its DHT peer ID is its identity
+    pub async fn with_keypair(
+        port: u16,
+        bootstrap_peers: Vec<Multiaddr>,
+        local_key: libp2p::identity::Keypair,
+    ) -> Result<Self> {
+        Self::with_limits(port, bootstrap_peers, local_key, SwarmLimits::default()).await
+    }
+
+    /// Create a DHT node under `local_key` whose swarm enforces `limits`
+    pub async fn with_limits(
+        _port: u16,
+        bootstrap_peers: Vec<Multiaddr>,
+        local_key: libp2p::identity::Keypair,
+        limits: SwarmLimits,
+    ) -> Result<Self> {
         let peer_id = PeerId::from(local_key.public());
 
         info!("DHT node initialized with PeerId: {}", peer_id);
@@ -38,6 +375,9 @@ impl DhtNode {
         let protocol_id = libp2p::StreamProtocol::new("/pangea/kad/1.0.0");
         let mut kad_config = kad::Config::new(protocol_id);
         kad_config.set_query_timeout(Duration::from_secs(60));
+        // Records and provider announcements other peers put are stored by
+        // `next_event`, once the record has been validated
+        kad_config.set_record_filtering(kad::StoreInserts::FilterBoth);
 
         let store = MemoryStore::new(peer_id);
         let mut kad = kad::Behaviour::with_config(peer_id, store, kad_config);
@@ -60,30 +400,80 @@ impl DhtNode {
         let ping = ping::Behaviour::new(ping::Config::new());
 
         let behaviour = PangeaBehaviour {
+            limits: connection_limits::Behaviour::new(limits.connection_limits()),
+            memory: memory_connection_limits::Behaviour::with_max_percentage(
+                limits.memory_fraction,
+            ),
             kad,
             identify,
             ping,
+            firewall: FirewallGate::default(),
         };
 
         // Build the swarm
-        let swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
+        let swarm = libp2p::SwarmBuilder::with_existing_identity(local_key.clone())
             .with_tokio()
-            .with_tcp(
-                tcp::Config::default(),
-                noise::Config::new,
-                libp2p::yamux::Config::default,
-            )?
+            .with_tcp(tcp::Config::default(), noise::Config::new, move || {
+                let mut yamux = libp2p::yamux::Config::default();
+                yamux.set_max_num_streams(limits.streams_per_connection);
+                yamux
+            })?
             .with_behaviour(|_key| behaviour)?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
+        info!(
+            "DHT swarm limits: {} connections ({} inbound, {} per peer), {} streams each",
+            limits.established_total,
+            limits.established_incoming,
+            limits.established_per_peer,
+            limits.streams_per_connection
+        );
+
         Ok(Self {
             swarm,
             peer_id,
             bootstrap_peers,
+            bootstrap_failures: 0,
+            limits,
+            denied_by_limits: 0,
+            denied_by_memory: 0,
+            keypair: local_key,
+            validator: Arc::new(RecordValidator::default()),
+            rejected_records: 0,
+            shard_adverts: Vec::new(),
         })
     }
 
+    /// Gate new connections through the firewall
+    pub fn with_firewall(mut self, firewall: Arc<Firewall>) -> Self {
+        self.swarm.behaviour_mut().firewall.firewall = Some(firewall);
+        self
+    }
+
+    /// Check records against `validator` instead of the default checks
+    pub fn with_record_validator(mut self, validator: Arc<RecordValidator>) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Close connections to peers the firewall has banned since they connected
+    pub fn disconnect_banned(&mut self) {
+        let Some(firewall) = self.swarm.behaviour().firewall.firewall.clone() else {
+            return;
+        };
+        let banned: Vec<PeerId> = self
+            .swarm
+            .connected_peers()
+            .filter(|peer| firewall.check_peer(&peer.to_string()).is_err())
+            .copied()
+            .collect();
+        for peer in banned {
+            info!("Disconnecting banned peer {}", peer);
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+    }
+
     /// Start listening on a specific address
     pub fn listen_on(&mut self, addr: Multiaddr) -> Result<()> {
         self.swarm.listen_on(addr.clone())?;
@@ -92,6 +482,7 @@ impl DhtNode {
     }
 
     /// Bootstrap the DHT by connecting to bootstrap peers
+    #[instrument(skip(self))]
     pub fn bootstrap(&mut self) -> Result<()> {
         if let Err(e) = self.swarm.behaviour_mut().kad.bootstrap() {
             warn!("DHT bootstrap failed: {}", e);
@@ -101,8 +492,58 @@ impl DhtNode {
         Ok(())
     }
 
-    /// Put a record in the DHT
+    /// Replace the bootstrap peers, such as with re-resolved DNS entries,
+    /// and bootstrap again through them
+    pub fn set_bootstrap_peers(&mut self, peers: Vec<Multiaddr>) -> Result<()> {
+        for addr in &peers {
+            if self.bootstrap_peers.contains(addr) {
+                continue;
+            }
+            match peer_of(addr) {
+                Some(peer) => {
+                    self.swarm
+                        .behaviour_mut()
+                        .kad
+                        .add_address(&peer, addr.clone());
+                }
+                None => {
+                    if let Err(e) = self.swarm.dial(addr.clone()) {
+                        debug!("Failed to dial bootstrap peer {}: {}", addr, e);
+                    }
+                }
+            }
+        }
+        self.bootstrap_peers = peers;
+        self.bootstrap_failures = 0;
+        self.bootstrap()
+    }
+
+    /// Failed dials to bootstrap peers since one last connected
+    pub fn bootstrap_failures(&self) -> u32 {
+        self.bootstrap_failures
+    }
+
+    fn is_bootstrap_peer(&self, peer: &PeerId) -> bool {
+        self.bootstrap_peers
+            .iter()
+            .any(|addr| peer_of(addr).as_ref() == Some(peer))
+    }
+
+    fn is_bootstrap_dial(&self, peer: Option<PeerId>, error: &DialError) -> bool {
+        peer.is_some_and(|peer| self.is_bootstrap_peer(&peer))
+            || matches!(error, DialError::Transport(attempts)
+                if attempts.iter().any(|(addr, _)| self.bootstrap_peers.contains(addr)))
+    }
+
+    /// Put a record in the DHT, signed by this node
+    ///
+    /// Fails if the value is not a valid record for its key.
+    #[instrument(skip_all, fields(key_len = key.len(), value_len = value.len()))]
     pub fn put_record(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let value = self
+            .validator
+            .seal(&self.keypair, &key, value)
+            .context("Refusing to publish invalid DHT record")?;
         let record = Record {
             key: RecordKey::new(&key),
             value,
@@ -121,6 +562,7 @@ impl DhtNode {
     }
 
     /// Get a record from the DHT
+    #[instrument(skip_all, fields(key_len = key.len()))]
     pub fn get_record(&mut self, key: Vec<u8>) -> Result<()> {
         let key = RecordKey::new(&key);
         self.swarm.behaviour_mut().kad.get_record(key);
@@ -128,7 +570,59 @@ impl DhtNode {
         Ok(())
     }
 
+    /// Fetch every valid copy of a record the DHT returns within `timeout`
+    ///
+    /// Copies failing validation are dropped; the values returned are as
+    /// published, without their signatures. Drives the swarm until the query
+    /// finishes, so other events arriving meanwhile are not seen by the
+    /// caller's event loop.
+    #[instrument(skip_all, fields(key_len = key.len()))]
+    pub async fn get_record_values(&mut self, key: Vec<u8>, timeout: Duration) -> Vec<Vec<u8>> {
+        let query = self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .get_record(RecordKey::new(&key));
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut values = Vec::new();
+        while let Ok(Some(event)) = tokio::time::timeout_at(deadline, self.next_event()).await {
+            let SwarmEvent::Behaviour(PangeaBehaviourEvent::Kad(
+                kad::Event::OutboundQueryProgressed {
+                    id,
+                    result: kad::QueryResult::GetRecord(result),
+                    step,
+                    ..
+                },
+            )) = event
+            else {
+                continue;
+            };
+            if id != query {
+                continue;
+            }
+            match result {
+                Ok(kad::GetRecordOk::FoundRecord(found)) => {
+                    match self.validator.open(&key, &found.record.value) {
+                        Ok(record) => values.push(record.value),
+                        Err(e) => {
+                            self.rejected_records += 1;
+                            debug!("Dropped invalid record from {:?}: {:#}", found.peer, e);
+                        }
+                    }
+                }
+                Ok(kad::GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {}
+                Err(e) => debug!("Record query ended: {}", e),
+            }
+            if step.last() {
+                break;
+            }
+        }
+        debug!("Got {} copies of record", values.len());
+        values
+    }
+
     /// Find providers for a given file hash
+    #[instrument(skip_all)]
     pub fn find_providers(&mut self, file_hash: Vec<u8>) -> Result<()> {
         let key = RecordKey::new(&file_hash);
         self.swarm.behaviour_mut().kad.get_providers(key);
@@ -137,6 +631,7 @@ impl DhtNode {
     }
 
     /// Start providing a file (announce that this node has a shard)
+    #[instrument(skip_all)]
     pub fn start_providing(&mut self, file_hash: Vec<u8>) -> Result<()> {
         let key = RecordKey::new(&file_hash);
         self.swarm
@@ -148,6 +643,14 @@ impl DhtNode {
         Ok(())
     }
 
+    /// Stop announcing that this node holds a file
+    #[instrument(skip_all)]
+    pub fn stop_providing(&mut self, file_hash: Vec<u8>) {
+        let key = RecordKey::new(&file_hash);
+        self.swarm.behaviour_mut().kad.stop_providing(&key);
+        info!("Stopped providing file");
+    }
+
     /// Get the PeerId of this node
     pub fn peer_id(&self) -> &PeerId {
         &self.peer_id
@@ -156,7 +659,124 @@ impl DhtNode {
     /// Process swarm events (call this in a loop)
     pub async fn next_event(&mut self) -> Option<SwarmEvent<PangeaBehaviourEvent>> {
         use futures::StreamExt;
-        self.swarm.next().await
+        let event = self.swarm.next().await;
+        match &event {
+            Some(SwarmEvent::Behaviour(PangeaBehaviourEvent::Kad(
+                kad::Event::InboundRequest { request },
+            ))) => self.store_inbound(request),
+            Some(SwarmEvent::IncomingConnectionError {
+                error: ListenError::Denied { cause },
+                ..
+            })
+            | Some(SwarmEvent::OutgoingConnectionError {
+                error: DialError::Denied { cause },
+                ..
+            }) => self.count_denial(cause),
+            _ => {}
+        }
+        match &event {
+            Some(SwarmEvent::OutgoingConnectionError { peer_id, error, .. })
+                if self.is_bootstrap_dial(*peer_id, error) =>
+            {
+                self.bootstrap_failures += 1;
+            }
+            Some(SwarmEvent::ConnectionEstablished { peer_id, .. })
+                if self.is_bootstrap_peer(peer_id) =>
+            {
+                self.bootstrap_failures = 0;
+            }
+            _ => {}
+        }
+        event
+    }
+
+    /// Store what another peer put, if it is valid
+    fn store_inbound(&mut self, request: &kad::InboundRequest) {
+        match request {
+            kad::InboundRequest::PutRecord {
+                source,
+                record: Some(record),
+                ..
+            } => {
+                let valid = match self.validator.open(&record.key.to_vec(), &record.value) {
+                    Ok(valid) => valid,
+                    Err(e) => {
+                        self.rejected_records += 1;
+                        debug!("Refused invalid record from {}: {:#}", source, e);
+                        return;
+                    }
+                };
+                if valid.kind == RecordKind::ShardFilter {
+                    self.shard_adverts.push(valid.value);
+                }
+                if let Err(e) = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .store_mut()
+                    .put(record.clone())
+                {
+                    debug!("Failed to store record from {}: {}", source, e);
+                }
+            }
+            kad::InboundRequest::AddProvider {
+                record: Some(provider),
+            } => {
+                if let Err(e) = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .store_mut()
+                    .add_provider(provider.clone())
+                {
+                    debug!("Failed to store provider record: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records from other peers refused for failing validation
+    pub fn rejected_records(&self) -> u64 {
+        self.rejected_records
+    }
+
+    /// Shard filter advertisements other peers put on this node since the
+    /// last call, unsigned
+    pub fn take_shard_advertisements(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.shard_adverts)
+    }
+
+    fn count_denial(&mut self, cause: &ConnectionDenied) {
+        if cause
+            .downcast_ref::<connection_limits::Exceeded>()
+            .is_some()
+        {
+            self.denied_by_limits += 1;
+            debug!("DHT connection refused: {}", cause);
+        } else if cause
+            .downcast_ref::<memory_connection_limits::MemoryUsageLimitExceeded>()
+            .is_some()
+        {
+            self.denied_by_memory += 1;
+            debug!("DHT connection refused: {}", cause);
+        }
+    }
+
+    /// Current connections against the swarm's limits
+    pub fn stats(&self) -> SwarmStats {
+        let info = self.swarm.network_info();
+        let counters = info.connection_counters();
+        SwarmStats {
+            connected_peers: info.num_peers(),
+            pending_incoming: counters.num_pending_incoming(),
+            pending_outgoing: counters.num_pending_outgoing(),
+            established_incoming: counters.num_established_incoming(),
+            established_outgoing: counters.num_established_outgoing(),
+            denied_by_limits: self.denied_by_limits,
+            denied_by_memory: self.denied_by_memory,
+            limits: self.limits,
+        }
     }
 
     /// Connect to a peer
@@ -267,6 +887,74 @@ impl DualDht {
     }
 }
 
+#[cfg(test)]
+mod swarm_tests {
+    use super::*;
+
+    fn caps(cpu_cores: usize, ram_gb: u64) -> HardwareCaps {
+        HardwareCaps {
+            has_avx2: false,
+            has_neon: false,
+            has_io_uring: false,
+            has_ebpf: false,
+            ram_gb,
+            cpu_cores,
+        }
+    }
+
+    #[test]
+    fn test_limits_scale_with_hardware() {
+        let small = SwarmLimits::for_caps(&caps(1, 1));
+        let large = SwarmLimits::for_caps(&caps(16, 64));
+        assert_eq!(small.established_total, 64);
+        assert_eq!(large.established_total, 1024);
+        assert!(large.pending_incoming > small.pending_incoming);
+        assert!(small.established_incoming < small.established_total);
+
+        // Memory caps connections on machines with many cores and little RAM
+        assert_eq!(SwarmLimits::for_caps(&caps(64, 2)).established_total, 256);
+
+        // Stream windows of every connection fit in an eighth of RAM
+        for limits in [small, large] {
+            assert!(limits.streams_per_connection >= 16);
+        }
+        let budget = (64u64 << 30) / 8;
+        assert!(
+            large.established_total as u64
+                * large.streams_per_connection as u64
+                * YAMUX_STREAM_WINDOW
+                <= budget
+        );
+
+        // Missing hardware information still yields usable limits
+        let unknown = SwarmLimits::for_caps(&caps(0, 0));
+        assert_eq!(unknown, small);
+    }
+
+    #[test]
+    fn test_config_overrides_derived_limits() {
+        let config = SwarmConfig::from_toml(
+            "[swarm]\nmax_established_total = 40\nmax_streams_per_connection = 8\n",
+        )
+        .unwrap();
+        let limits = config.resolve(&caps(8, 16));
+        assert_eq!(limits.established_total, 40);
+        assert_eq!(limits.established_incoming, 30);
+        assert_eq!(limits.streams_per_connection, 8);
+        assert_eq!(
+            limits.pending_incoming,
+            SwarmLimits::for_caps(&caps(8, 16)).pending_incoming
+        );
+
+        assert!(SwarmConfig::from_toml("[swarm]\nmax_established_per_peer = 0\n").is_err());
+        assert!(SwarmConfig::from_toml("[swarm]\nmax_memory_fraction = 1.5\n").is_err());
+        assert!(SwarmConfig::from_toml(
+            "[swarm]\nmax_established_incoming = 100\nmax_established_total = 50\n"
+        )
+        .is_err());
+    }
+}
+
 #[cfg(test)]
 mod dual_tests {
     use super::DualDht;
@@ -0,0 +1,426 @@
+/// Disk space watchdog for the cache volume
+///
+/// Polls free space on the volume holding the cache directory and, as it
+/// drops through the configured thresholds, evicts progressively more of the
+/// cache: prefetched shards first, then unpinned shards in LRU order, then
+/// oversized shards. At the critical level the cache stops accepting inbound
+/// shards until space recovers. Level changes and evictions are broadcast as
+/// [`DiskEvent`]s and counted in [`DiskWatchdogStats`].
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{DiskExt, System, SystemExt};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::cache::Cache;
+
+/// How full the cache volume is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DiskLevel {
+    Normal,
+    /// Prefetched shards are evicted
+    Warning,
+    /// Unpinned shards are evicted as well
+    Low,
+    /// Oversized shards are evicted and inbound shards refused
+    Critical,
+}
+
+impl fmt::Display for DiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DiskLevel::Normal => "normal",
+            DiskLevel::Warning => "warning",
+            DiskLevel::Low => "low",
+            DiskLevel::Critical => "critical",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Eviction stage run by the watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvictionStage {
+    Prefetched,
+    UnpinnedLru,
+    Oversized,
+}
+
+/// Something the watchdog observed or did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiskEvent {
+    LevelChanged {
+        from: DiskLevel,
+        to: DiskLevel,
+        available_bytes: u64,
+    },
+    Evicted {
+        stage: EvictionStage,
+        bytes: u64,
+    },
+    InboundPaused,
+    InboundResumed,
+}
+
+/// Free and total space of a volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl DiskSpace {
+    /// Free fraction of the volume
+    pub fn free_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 1.0;
+        }
+        self.available_bytes as f64 / self.total_bytes as f64
+    }
+}
+
+/// Free-space thresholds, as fractions of the volume (`[disk_watchdog]`
+/// table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskWatchdogConfig {
+    pub enabled: bool,
+    /// Time between free-space checks
+    pub check_interval_secs: u64,
+    pub warning_free_ratio: f64,
+    pub low_free_ratio: f64,
+    pub critical_free_ratio: f64,
+    /// Shards at least this large are dropped at the critical level
+    pub oversized_shard_bytes: usize,
+}
+
+impl Default for DiskWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_secs: 30,
+            warning_free_ratio: 0.10,
+            low_free_ratio: 0.05,
+            critical_free_ratio: 0.02,
+            oversized_shard_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    disk_watchdog: DiskWatchdogConfig,
+}
+
+impl DiskWatchdogConfig {
+    /// Load the `[disk_watchdog]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[disk_watchdog]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.disk_watchdog.validate()?;
+        Ok(file.disk_watchdog)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.check_interval_secs == 0 {
+            bail!("disk_watchdog: check_interval_secs must be at least 1");
+        }
+        if !(self.critical_free_ratio <= self.low_free_ratio
+            && self.low_free_ratio <= self.warning_free_ratio
+            && self.warning_free_ratio < 1.0
+            && self.critical_free_ratio >= 0.0)
+        {
+            bail!("disk_watchdog: need 0 <= critical <= low <= warning < 1 free ratios");
+        }
+        Ok(())
+    }
+
+    pub fn level(&self, space: DiskSpace) -> DiskLevel {
+        let free = space.free_ratio();
+        if free < self.critical_free_ratio {
+            DiskLevel::Critical
+        } else if free < self.low_free_ratio {
+            DiskLevel::Low
+        } else if free < self.warning_free_ratio {
+            DiskLevel::Warning
+        } else {
+            DiskLevel::Normal
+        }
+    }
+
+    /// Bytes needed to get back above the warning threshold
+    fn deficit(&self, space: DiskSpace) -> u64 {
+        let wanted = (space.total_bytes as f64 * self.warning_free_ratio) as u64;
+        wanted.saturating_sub(space.available_bytes)
+    }
+}
+
+/// Counters exposed for metrics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskWatchdogStats {
+    pub level: Option<DiskLevel>,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub evictions: u64,
+    pub bytes_evicted: u64,
+    pub inbound_paused: bool,
+}
+
+/// Watches the cache volume and evicts under pressure
+pub struct DiskWatchdog {
+    cache: Arc<Cache>,
+    path: PathBuf,
+    config: DiskWatchdogConfig,
+    stats: Mutex<DiskWatchdogStats>,
+    events: broadcast::Sender<DiskEvent>,
+}
+
+impl DiskWatchdog {
+    pub fn new(cache: Arc<Cache>, path: impl AsRef<Path>, config: DiskWatchdogConfig) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            cache,
+            path: path.as_ref().to_path_buf(),
+            config,
+            stats: Mutex::new(DiskWatchdogStats::default()),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DiskEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn stats(&self) -> DiskWatchdogStats {
+        self.stats.lock().clone()
+    }
+
+    /// Measure the volume once and react to it
+    pub async fn check(&self) -> Result<DiskLevel> {
+        let space = disk_space(&self.path)?;
+        Ok(self.respond(space).await)
+    }
+
+    /// React to a measurement, evicting as much as the level calls for
+    pub async fn respond(&self, space: DiskSpace) -> DiskLevel {
+        let level = self.config.level(space);
+        let previous = {
+            let mut stats = self.stats.lock();
+            let previous = stats.level.replace(level);
+            stats.available_bytes = space.available_bytes;
+            stats.total_bytes = space.total_bytes;
+            previous
+        };
+        let previous = previous.unwrap_or(DiskLevel::Normal);
+        if previous != level {
+            match level {
+                DiskLevel::Normal => info!("💾 Cache volume space back to normal"),
+                DiskLevel::Warning | DiskLevel::Low => warn!(
+                    "💾 Cache volume space {}: {} of {} bytes free",
+                    level, space.available_bytes, space.total_bytes
+                ),
+                DiskLevel::Critical => error!(
+                    "💾 Cache volume space critical: {} of {} bytes free",
+                    space.available_bytes, space.total_bytes
+                ),
+            }
+            self.emit(DiskEvent::LevelChanged {
+                from: previous,
+                to: level,
+                available_bytes: space.available_bytes,
+            });
+        }
+
+        // Each stage only runs while the previous ones freed too little
+        let mut deficit = self.config.deficit(space);
+        if level >= DiskLevel::Warning && deficit > 0 {
+            let freed = self.cache.evict_prefetched().await;
+            deficit = deficit.saturating_sub(self.record(EvictionStage::Prefetched, freed));
+        }
+        if level >= DiskLevel::Low && deficit > 0 {
+            let target = usize::try_from(deficit).unwrap_or(usize::MAX);
+            let freed = self.cache.evict_unpinned_lru(target).await;
+            deficit = deficit.saturating_sub(self.record(EvictionStage::UnpinnedLru, freed));
+        }
+        if level >= DiskLevel::Critical && deficit > 0 {
+            let freed = self
+                .cache
+                .evict_oversized(self.config.oversized_shard_bytes)
+                .await;
+            self.record(EvictionStage::Oversized, freed);
+        }
+
+        let accepting = level < DiskLevel::Critical;
+        if self.cache.is_accepting() != accepting {
+            self.cache.set_accepting(accepting);
+            self.stats.lock().inbound_paused = !accepting;
+            self.emit(if accepting {
+                DiskEvent::InboundResumed
+            } else {
+                DiskEvent::InboundPaused
+            });
+        }
+        level
+    }
+
+    /// Check every `check_interval_secs` until the task is aborted
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(self.config.check_interval_secs.max(1));
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.check().await {
+                    warn!("Disk watchdog check failed: {:#}", e);
+                }
+            }
+        })
+    }
+
+    fn record(&self, stage: EvictionStage, freed: usize) -> u64 {
+        let freed = freed as u64;
+        if freed > 0 {
+            {
+                let mut stats = self.stats.lock();
+                stats.evictions += 1;
+                stats.bytes_evicted += freed;
+            }
+            info!("💾 Evicted {} bytes ({:?})", freed, stage);
+            self.emit(DiskEvent::Evicted {
+                stage,
+                bytes: freed,
+            });
+        }
+        freed
+    }
+
+    fn emit(&self, event: DiskEvent) {
+        // No subscribers is fine
+        let _ = self.events.send(event);
+    }
+}
+
+/// Free space on the volume holding `path`
+pub fn disk_space(path: &Path) -> Result<DiskSpace> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let mut system = System::new();
+    system.refresh_disks_list();
+
+    // The volume is the disk with the longest mount point containing `path`
+    system
+        .disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| DiskSpace {
+            available_bytes: disk.available_space(),
+            total_bytes: disk.total_space(),
+        })
+        .with_context(|| format!("No mounted volume found for {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TOTAL: u64 = 1000;
+
+    fn space(available_bytes: u64) -> DiskSpace {
+        DiskSpace {
+            available_bytes,
+            total_bytes: TOTAL,
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let config = DiskWatchdogConfig::default();
+        assert_eq!(config.level(space(500)), DiskLevel::Normal);
+        assert_eq!(config.level(space(90)), DiskLevel::Warning);
+        assert_eq!(config.level(space(40)), DiskLevel::Low);
+        assert_eq!(config.level(space(10)), DiskLevel::Critical);
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = DiskWatchdogConfig::from_toml(
+            "[disk_watchdog]\nenabled = false\ncheck_interval_secs = 5\nwarning_free_ratio = 0.2\n",
+        )
+        .unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.check_interval_secs, 5);
+        assert_eq!(config.warning_free_ratio, 0.2);
+        assert_eq!(config.low_free_ratio, 0.05);
+        assert!(DiskWatchdogConfig::from_toml("").unwrap().enabled);
+
+        assert!(
+            DiskWatchdogConfig::from_toml("[disk_watchdog]\ncheck_interval_secs = 0\n").is_err()
+        );
+        assert!(DiskWatchdogConfig::from_toml("[disk_watchdog]\nlow_free_ratio = 0.5\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_progressive_eviction_and_pause() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        cache
+            .put_prefetched_shard("pre", 0, vec![0; 30])
+            .await
+            .unwrap();
+        cache.put_shard("cold", 0, vec![0; 30]).await.unwrap();
+        cache.put_shard("big", 0, vec![0; 200]).await.unwrap();
+        cache.pin("big").await;
+
+        let watchdog = DiskWatchdog::new(
+            cache.clone(),
+            dir.path(),
+            DiskWatchdogConfig {
+                oversized_shard_bytes: 100,
+                ..Default::default()
+            },
+        );
+        let mut events = watchdog.subscribe();
+
+        // Warning: 20 bytes short, prefetched shards cover it
+        assert_eq!(watchdog.respond(space(80)).await, DiskLevel::Warning);
+        assert!(!cache.has_shard("pre", 0).await);
+        assert!(cache.has_shard("cold", 0).await);
+
+        // Critical: everything but pinned data goes, then oversized shards
+        assert_eq!(watchdog.respond(space(10)).await, DiskLevel::Critical);
+        assert!(!cache.has_shard("cold", 0).await);
+        assert!(!cache.has_shard("big", 0).await);
+        assert!(!cache.is_accepting());
+
+        assert_eq!(watchdog.respond(space(500)).await, DiskLevel::Normal);
+        assert!(cache.is_accepting());
+
+        let stats = watchdog.stats();
+        assert_eq!(stats.evictions, 3);
+        assert_eq!(stats.bytes_evicted, 260);
+        assert!(!stats.inbound_paused);
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(event);
+        }
+        assert!(seen.contains(&DiskEvent::InboundPaused));
+        assert_eq!(seen.last(), Some(&DiskEvent::InboundResumed));
+    }
+}
@@ -0,0 +1,369 @@
+/// DNS-named bootstrap peers
+///
+/// Fixed bootstrap multiaddrs break when the seed infrastructure moves.
+/// Bootstrap entries may instead name peers through DNS:
+///
+/// - `/dnsaddr/<domain>` follows the libp2p convention: the TXT records at
+///   `_dnsaddr.<domain>` hold `dnsaddr=<multiaddr>` answers, which may be
+///   `/dnsaddr` entries themselves (followed up to [`MAX_DNSADDR_DEPTH`]).
+///   With a `/p2p/<peer>` suffix only answers for that peer are kept.
+/// - `/dns/<host>`, `/dns4/<host>` and `/dns6/<host>` followed by the rest
+///   of an address become one address per A/AAAA answer.
+///
+/// A [`DnsBootstrap`] resolves them at start-up, then again every
+/// `dns_refresh_secs` and whenever the DHT reports `dns_redial_failures`
+/// failed dials to bootstrap peers, so long-lived daemons follow DNS
+/// changes. Entries that do not resolve are logged and skipped.
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::bootstrap::BootstrapConfig;
+
+/// Most nested `/dnsaddr` lookups followed from one entry
+pub const MAX_DNSADDR_DEPTH: usize = 4;
+
+/// Addresses kept from a single entry
+const MAX_ANSWERS_PER_ENTRY: usize = 64;
+
+/// DNS queries needed to resolve bootstrap entries
+#[async_trait]
+pub trait DnsLookup: Send + Sync {
+    /// TXT records at `name`, each joined into one string
+    async fn txt(&self, name: &str) -> Result<Vec<String>>;
+
+    /// A and AAAA answers for `host`
+    async fn ip(&self, host: &str) -> Result<Vec<IpAddr>>;
+}
+
+#[async_trait]
+impl DnsLookup for TokioAsyncResolver {
+    async fn txt(&self, name: &str) -> Result<Vec<String>> {
+        let lookup = self
+            .txt_lookup(name)
+            .await
+            .with_context(|| format!("TXT lookup of {} failed", name))?;
+        Ok(lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect()
+            })
+            .collect())
+    }
+
+    async fn ip(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let lookup = self
+            .lookup_ip(host)
+            .await
+            .with_context(|| format!("Address lookup of {} failed", host))?;
+        Ok(lookup.iter().collect())
+    }
+}
+
+/// Whether `addr` must be resolved before it can be dialled
+pub fn is_dns(addr: &Multiaddr) -> bool {
+    matches!(
+        addr.iter().next(),
+        Some(Protocol::Dnsaddr(_) | Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_))
+    )
+}
+
+/// Peer ID an address ends in, if any
+pub(crate) fn peer_of(addr: &Multiaddr) -> Option<PeerId> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(peer) => Some(peer),
+        _ => None,
+    })
+}
+
+/// Resolves bootstrap peers named through DNS, once or on a timer
+pub struct DnsBootstrap {
+    /// Bootstrap peers as configured, DNS entries unresolved
+    entries: Vec<Multiaddr>,
+    lookup: Arc<dyn DnsLookup>,
+    refresh_interval: Duration,
+    redial_failures: u32,
+    refresh_now: Notify,
+}
+
+impl DnsBootstrap {
+    /// Resolver for the DNS entries among `peers`, using the system's DNS
+    /// settings (or public defaults when they cannot be read); other peers
+    /// are passed through
+    pub fn new(peers: &[Multiaddr], config: &BootstrapConfig) -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            warn!("Using default DNS servers: {}", e);
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self {
+            entries: peers.to_vec(),
+            lookup: Arc::new(resolver),
+            refresh_interval: Duration::from_secs(config.dns_refresh_secs),
+            redial_failures: config.dns_redial_failures,
+            refresh_now: Notify::new(),
+        }
+    }
+
+    /// Answer queries from `lookup` instead of DNS
+    pub fn with_lookup(mut self, lookup: Arc<dyn DnsLookup>) -> Self {
+        self.lookup = lookup;
+        self
+    }
+
+    /// The bootstrap peers, unresolved
+    pub fn entries(&self) -> &[Multiaddr] {
+        &self.entries
+    }
+
+    /// Whether any bootstrap peer needs resolving
+    pub fn has_dns_entries(&self) -> bool {
+        self.entries.iter().any(is_dns)
+    }
+
+    /// Failed bootstrap dials after which entries are resolved early
+    pub fn redial_failures(&self) -> u32 {
+        self.redial_failures
+    }
+
+    /// `peers` with each DNS entry replaced by what it resolves to
+    pub async fn resolve_peers(&self, peers: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        let mut resolved = Vec::new();
+        for peer in peers {
+            if !is_dns(&peer) {
+                resolved.push(peer);
+                continue;
+            }
+            match self.resolve_entry(&peer).await {
+                Ok(addrs) => {
+                    debug!("{} resolved to {} addresses", peer, addrs.len());
+                    resolved.extend(addrs);
+                }
+                Err(e) => warn!("Could not resolve bootstrap entry {}: {:#}", peer, e),
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        resolved.retain(|addr| seen.insert(addr.clone()));
+        resolved
+    }
+
+    /// The bootstrap peers, with DNS entries resolved now
+    pub async fn resolve(&self) -> Vec<Multiaddr> {
+        self.resolve_peers(self.entries.clone()).await
+    }
+
+    /// Addresses one entry resolves to
+    pub async fn resolve_entry(&self, entry: &Multiaddr) -> Result<Vec<Multiaddr>> {
+        let mut pending = vec![(entry.clone(), 0)];
+        let mut resolved = Vec::new();
+        while let Some((addr, depth)) = pending.pop() {
+            let mut protocols = addr.iter();
+            let (host, v4, v6) = match protocols.next() {
+                Some(Protocol::Dnsaddr(domain)) => {
+                    if depth >= MAX_DNSADDR_DEPTH {
+                        bail!("{} nests /dnsaddr deeper than {}", entry, MAX_DNSADDR_DEPTH);
+                    }
+                    let wanted = peer_of(&addr);
+                    for txt in self.lookup.txt(&format!("_dnsaddr.{}", domain)).await? {
+                        let Some(answer) = txt.strip_prefix("dnsaddr=") else {
+                            continue;
+                        };
+                        let Ok(answer) = answer.parse::<Multiaddr>() else {
+                            debug!("Ignoring malformed dnsaddr answer {:?}", answer);
+                            continue;
+                        };
+                        if wanted.is_none() || peer_of(&answer) == wanted {
+                            pending.push((answer, depth + 1));
+                        }
+                    }
+                    continue;
+                }
+                Some(Protocol::Dns(host)) => (host, true, true),
+                Some(Protocol::Dns4(host)) => (host, true, false),
+                Some(Protocol::Dns6(host)) => (host, false, true),
+                _ => {
+                    resolved.push(addr.clone());
+                    continue;
+                }
+            };
+            let rest: Vec<Protocol> = protocols.collect();
+            for ip in self.lookup.ip(&host).await? {
+                if (ip.is_ipv4() && !v4) || (ip.is_ipv6() && !v6) {
+                    continue;
+                }
+                let mut resolved_addr = Multiaddr::from(ip);
+                for protocol in &rest {
+                    resolved_addr.push(protocol.clone());
+                }
+                resolved.push(resolved_addr);
+            }
+        }
+        if resolved.is_empty() {
+            bail!("{} resolved to no addresses", entry);
+        }
+        resolved.truncate(MAX_ANSWERS_PER_ENTRY);
+        Ok(resolved)
+    }
+
+    /// Resolve again as soon as possible, such as after failed dials
+    pub fn refresh_now(&self) {
+        self.refresh_now.notify_one();
+    }
+
+    /// Send freshly resolved addresses to `updates` every refresh interval
+    /// and after each [`refresh_now`](Self::refresh_now)
+    pub fn spawn_refresh(
+        self: Arc<Self>,
+        updates: mpsc::UnboundedSender<Vec<Multiaddr>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.refresh_interval);
+            // Entries were resolved at start-up
+            interval.tick().await;
+            loop {
+                let early = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = self.refresh_now.notified() => true,
+                };
+                if early {
+                    interval.reset();
+                }
+                let peers = self.resolve().await;
+                if peers.is_empty() {
+                    warn!("No bootstrap entry resolved; keeping the current peers");
+                    continue;
+                }
+                info!("Re-resolved DNS bootstrap entries: {} peers", peers.len());
+                if updates.send(peers).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct FakeDns {
+        txt: Mutex<HashMap<String, Vec<String>>>,
+        ip: Mutex<HashMap<String, Vec<IpAddr>>>,
+    }
+
+    #[async_trait]
+    impl DnsLookup for FakeDns {
+        async fn txt(&self, name: &str) -> Result<Vec<String>> {
+            self.txt.lock().get(name).cloned().context("NXDOMAIN")
+        }
+
+        async fn ip(&self, host: &str) -> Result<Vec<IpAddr>> {
+            self.ip.lock().get(host).cloned().context("NXDOMAIN")
+        }
+    }
+
+    fn bootstrap(entries: &[&str], dns: Arc<FakeDns>) -> DnsBootstrap {
+        let entries: Vec<Multiaddr> = entries.iter().map(|e| e.parse().unwrap()).collect();
+        DnsBootstrap::new(&entries, &BootstrapConfig::default()).with_lookup(dns)
+    }
+
+    #[tokio::test]
+    async fn test_dnsaddr_entries_follow_txt_records() {
+        let wanted = PeerId::random();
+        let other = PeerId::random();
+        let dns = Arc::new(FakeDns::default());
+        dns.txt.lock().insert(
+            "_dnsaddr.seed.example".to_string(),
+            vec![
+                format!("dnsaddr=/dnsaddr/eu.seed.example/p2p/{}", wanted),
+                format!("dnsaddr=/ip4/10.0.0.9/tcp/9091/p2p/{}", other),
+                "v=spf1 -all".to_string(),
+            ],
+        );
+        dns.txt.lock().insert(
+            "_dnsaddr.eu.seed.example".to_string(),
+            vec![format!(
+                "dnsaddr=/dns4/eu1.seed.example/tcp/9091/p2p/{}",
+                wanted
+            )],
+        );
+        dns.ip.lock().insert(
+            "eu1.seed.example".to_string(),
+            vec![
+                "10.0.0.1".parse().unwrap(),
+                "10.0.0.2".parse().unwrap(),
+                "fd00::1".parse().unwrap(),
+            ],
+        );
+
+        let entry = format!("/dnsaddr/seed.example/p2p/{}", wanted);
+        let dns_bootstrap = bootstrap(&[&entry, "/dnsaddr/gone.example"], dns.clone());
+        let mut resolved = dns_bootstrap.resolve().await;
+        resolved.sort_by_key(|addr| addr.to_string());
+        let expected: Vec<Multiaddr> = ["10.0.0.1", "10.0.0.2"]
+            .iter()
+            .map(|ip| {
+                format!("/ip4/{}/tcp/9091/p2p/{}", ip, wanted)
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(resolved, expected);
+
+        // Answers change with the records; plain addresses pass through
+        dns.ip.lock().insert(
+            "eu1.seed.example".to_string(),
+            vec!["10.0.0.3".parse().unwrap()],
+        );
+        let plain: Multiaddr = "/ip4/127.0.0.1/tcp/9091".parse().unwrap();
+        assert_eq!(
+            dns_bootstrap
+                .resolve_peers(vec![entry.parse().unwrap(), plain.clone()])
+                .await,
+            vec![
+                format!("/ip4/10.0.0.3/tcp/9091/p2p/{}", wanted)
+                    .parse()
+                    .unwrap(),
+                plain
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dnsaddr_loops_are_cut_off() {
+        let dns = Arc::new(FakeDns::default());
+        dns.txt.lock().insert(
+            "_dnsaddr.loop.example".to_string(),
+            vec!["dnsaddr=/dnsaddr/loop.example".to_string()],
+        );
+        dns.ip.lock().insert(
+            "both.example".to_string(),
+            vec!["10.0.0.1".parse().unwrap(), "fd00::1".parse().unwrap()],
+        );
+        let dns_bootstrap = bootstrap(&[], dns);
+
+        let looping: Multiaddr = "/dnsaddr/loop.example".parse().unwrap();
+        assert!(dns_bootstrap.resolve_entry(&looping).await.is_err());
+        let both: Multiaddr = "/dns/both.example/tcp/1".parse().unwrap();
+        assert_eq!(dns_bootstrap.resolve_entry(&both).await.unwrap().len(), 2);
+        let v6: Multiaddr = "/dns6/both.example/tcp/1".parse().unwrap();
+        assert_eq!(
+            dns_bootstrap.resolve_entry(&v6).await.unwrap(),
+            vec!["/ip6/fd00::1/tcp/1".parse::<Multiaddr>().unwrap()]
+        );
+    }
+}
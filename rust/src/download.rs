@@ -1,25 +1,86 @@
 use anyhow::{Context, Result};
+use std::future::Future;
 use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument, warn};
 
-use crate::cache::Cache;
+use crate::bloom::ShardAvailabilityIndex;
+use crate::cache::{Cache, FileManifest};
 use crate::ces::CesPipeline;
 use crate::go_client::GoClient;
+use crate::identity::PeerDirectory;
+use crate::metrics::MetricsTracker;
+use crate::network::QuicNode;
+use crate::placement::LocalParity;
+use crate::progress::{ProgressHub, ProgressTracker, TransferDirection};
+use crate::retry::{retry, RetryPolicy};
+use crate::rotation::ShardKeys;
+use crate::scheduler::{TransferPriority, TransferScheduler};
+use crate::store::NodeStore;
+use crate::transport::{ShardTransport, TransportSelector};
+
+/// Operation name under which shard fetch latencies are recorded
+pub const SHARD_FETCH_OPERATION: &str = "shard_fetch";
+
+/// Hedged request configuration
+#[derive(Debug, Clone)]
+pub struct HedgeConfig {
+    /// Enable hedged requests
+    pub enabled: bool,
+    /// Latency percentile used as the hedge delay
+    pub percentile: f64,
+    /// Samples required before trusting the percentile estimate
+    pub min_samples: usize,
+    /// Hedge delay used until enough samples exist
+    pub fallback_delay: Duration,
+    /// Lower bound on the hedge delay
+    pub min_delay: Duration,
+}
+
+impl Default for HedgeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            percentile: 0.95,
+            min_samples: 20,
+            fallback_delay: Duration::from_millis(500),
+            min_delay: Duration::from_millis(10),
+        }
+    }
+}
 
 /// Download protocol - handles file downloads with CES reconstruction
 pub struct DownloadProtocol {
     ces: Arc<CesPipeline>,
     go_client: Arc<GoClient>,
+    transport: TransportSelector,
     cache: Option<Arc<Cache>>,
+    availability: Option<Arc<ShardAvailabilityIndex>>,
+    scheduler: Option<Arc<TransferScheduler>>,
+    metrics: Option<Arc<MetricsTracker>>,
+    hedge: HedgeConfig,
+    store: Option<Arc<NodeStore>>,
+    retry: RetryPolicy,
+    progress: Option<ProgressHub>,
+    directory: Option<Arc<PeerDirectory>>,
 }
 
 impl DownloadProtocol {
     pub fn new(ces: Arc<CesPipeline>, go_client: Arc<GoClient>) -> Self {
         Self {
             ces,
+            transport: TransportSelector::go_only(go_client.clone()),
             go_client,
             cache: None,
+            availability: None,
+            scheduler: None,
+            metrics: None,
+            hedge: HedgeConfig::default(),
+            store: None,
+            retry: RetryPolicy::with_attempts(2),
+            progress: None,
+            directory: None,
         }
     }
 
@@ -27,9 +88,259 @@ impl DownloadProtocol {
     pub fn with_cache(ces: Arc<CesPipeline>, go_client: Arc<GoClient>, cache: Arc<Cache>) -> Self {
         Self {
             ces,
+            transport: TransportSelector::go_only(go_client.clone()),
             go_client,
             cache: Some(cache),
+            availability: None,
+            scheduler: None,
+            metrics: None,
+            hedge: HedgeConfig::default(),
+            store: None,
+            retry: RetryPolicy::with_attempts(2),
+            progress: None,
+            directory: None,
+        }
+    }
+
+    /// Use advertised shard filters to pick probable holders
+    pub fn with_availability_index(mut self, index: Arc<ShardAvailabilityIndex>) -> Self {
+        self.availability = Some(index);
+        self
+    }
+
+    /// Share bandwidth with other transfers through a scheduler
+    pub fn with_scheduler(mut self, scheduler: Arc<TransferScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Record fetch latencies and hedge slow fetches using their p95
+    pub fn with_metrics(mut self, metrics: Arc<MetricsTracker>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Feed per-peer fetch latencies into the node store and order sources by them
+    pub fn with_node_store(mut self, store: Arc<NodeStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Override the hedged request configuration
+    pub fn with_hedge_config(mut self, config: HedgeConfig) -> Self {
+        self.hedge = config;
+        self
+    }
+
+    /// Fall back to this node's QUIC connections when the Go node is unavailable
+    pub fn with_native_transport(mut self, network: Arc<QuicNode>) -> Self {
+        self.transport = self.transport.with_native_fallback(network);
+        self
+    }
+
+    /// Fetch every shard through `transport` instead of the Go node
+    pub fn with_transport(mut self, transport: Arc<dyn ShardTransport>) -> Self {
+        self.transport = TransportSelector::only(transport);
+        self
+    }
+
+    /// Override the retry policy for fetches from a single peer
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Publish per-shard progress for each file download
+    pub fn with_progress(mut self, hub: ProgressHub) -> Self {
+        self.progress = Some(hub);
+        self
+    }
+
+    /// Hold shard holders to the identities manifests record for them
+    pub fn with_peer_directory(mut self, directory: Arc<PeerDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Delay after which a duplicate request is issued, if hedging applies
+    fn hedge_delay(&self) -> Option<Duration> {
+        let metrics = self.metrics.as_ref()?;
+        if !self.hedge.enabled {
+            return None;
+        }
+
+        if metrics.sample_count(SHARD_FETCH_OPERATION) < self.hedge.min_samples {
+            return Some(self.hedge.fallback_delay);
+        }
+        let estimate_ms =
+            metrics.percentile_latency(SHARD_FETCH_OPERATION, self.hedge.percentile)?;
+        Some(Duration::from_secs_f64(estimate_ms / 1000.0).max(self.hedge.min_delay))
+    }
+
+    /// Fetch a shard from a single peer, recording its latency
+    #[instrument(skip(self))]
+    async fn fetch_from(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        peer_id: u32,
+    ) -> Option<(u32, Vec<u8>)> {
+        debug!("Fetching shard {} from peer {}", shard_index, peer_id);
+        let start = Instant::now();
+
+        let result = retry(&self.retry, SHARD_FETCH_OPERATION, || {
+            self.transport.fetch_shard(peer_id, file_hash, shard_index)
+        })
+        .await;
+
+        match result {
+            Ok(Some((data, transport))) => {
+                debug!(
+                    "Fetched shard {} from peer {} via {}",
+                    shard_index, peer_id, transport
+                );
+                let elapsed = start.elapsed();
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_sample(SHARD_FETCH_OPERATION.to_string(), elapsed);
+                    metrics.record_bytes(SHARD_FETCH_OPERATION, data.len() as u64);
+                }
+                if let Some(store) = &self.store {
+                    store.record_peer_latency(peer_id, elapsed).await;
+                    store
+                        .record_peer_throughput(peer_id, data.len(), elapsed)
+                        .await;
+                }
+                Some((peer_id, data))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                debug!(
+                    "Failed to fetch shard {} from peer {}: {}",
+                    shard_index, peer_id, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Fetch a shard from the first responsive source, hedging slow peers
+    ///
+    /// With a `check`, copies failing their hash are rejected and the next
+    /// source is tried.
+    async fn fetch_shard(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        sources: &[u32],
+        check: Option<ShardCheck<'_>>,
+    ) -> Option<Vec<u8>> {
+        let mut i = 0;
+        while i < sources.len() {
+            let primary = sources[i];
+            let backup = sources.get(i + 1).copied();
+
+            let fetched = match (backup, self.hedge_delay(), &self.metrics) {
+                (Some(backup), Some(delay), Some(metrics)) => {
+                    i += 2;
+                    hedged_request(
+                        self.fetch_from(file_hash, shard_index, primary),
+                        delay,
+                        || self.fetch_from(file_hash, shard_index, backup),
+                        metrics,
+                    )
+                    .await
+                }
+                _ => {
+                    i += 1;
+                    self.fetch_from(file_hash, shard_index, primary).await
+                }
+            };
+
+            match (fetched, &check) {
+                (Some((peer_id, data)), Some(check)) if !check.matches(&data) => {
+                    self.reject(check, shard_index, peer_id, &data).await;
+                }
+                (Some((_, data)), _) => return Some(data),
+                // Continue - Reed-Solomon can reconstruct from partial shards
+                (None, _) => {}
+            }
+        }
+        None
+    }
+
+    /// Quarantine a shard that failed its hash check and report who served it
+    async fn reject(&self, check: &ShardCheck<'_>, shard_index: usize, peer_id: u32, data: &[u8]) {
+        warn!(
+            "🚨 Peer {} served a corrupt copy of shard {} of {}",
+            peer_id, shard_index, check.file_hash
+        );
+        if let Some(store) = &self.store {
+            store
+                .report_bad_shard(peer_id, check.file_hash, shard_index)
+                .await;
+        }
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache
+                .quarantine_shard(check.file_hash, shard_index, peer_id, data)
+                .await
+            {
+                warn!("Failed to quarantine shard {}: {:#}", shard_index, e);
+            }
+        }
+    }
+
+    /// Charge fetched bytes against the scheduler's bandwidth budget
+    async fn throttle(&self, priority: TransferPriority, bytes: usize) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.acquire(priority, bytes as u64).await;
+        }
+    }
+
+    /// Order candidate peers for a shard, most probable holder first
+    ///
+    /// The manifest location is kept unless its fresh filter rules the shard
+    /// out; other advertised holders follow as fallbacks. With a node store,
+    /// candidates are then ordered by their rolling p95 latency, with peers
+    /// that served corrupt shards last and banned peers left out.
+    pub async fn plan_sources(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        peer_id: u32,
+    ) -> Vec<u32> {
+        let sources = self
+            .candidate_sources(file_hash, shard_index, peer_id)
+            .await;
+        match &self.store {
+            Some(store) => store.rank_sources(&sources).await,
+            None => sources,
+        }
+    }
+
+    async fn candidate_sources(
+        &self,
+        file_hash: &str,
+        shard_index: usize,
+        peer_id: u32,
+    ) -> Vec<u32> {
+        let Some(index) = &self.availability else {
+            return vec![peer_id];
+        };
+
+        let mut sources = Vec::new();
+        if index.might_hold(peer_id, file_hash, shard_index).await != Some(false) {
+            sources.push(peer_id);
+        }
+        for holder in index.probable_holders(file_hash, shard_index).await {
+            if !sources.contains(&holder) {
+                sources.push(holder);
+            }
+        }
+        if sources.is_empty() {
+            // Filters can be stale; fall back to the manifest location
+            sources.push(peer_id);
         }
+        sources
     }
 
     /// Download and reconstruct a file from shards
@@ -49,46 +360,228 @@ impl DownloadProtocol {
         shard_locations: Vec<(usize, u32)>,
         file_hash: Option<&str>,
     ) -> Result<usize> {
-        info!("Starting download to: {:?}", output_path);
+        self.download_file_with_priority(
+            output_path,
+            shard_locations,
+            file_hash,
+            TransferPriority::Interactive,
+        )
+        .await
+    }
 
-        // 1. Fetch shards from cache or peers
-        let mut shards = vec![None; shard_locations.len()];
-        for (shard_index, peer_id) in shard_locations {
-            // First, try to get from cache if file_hash is provided
-            if let (Some(hash), Some(cache)) = (file_hash, &self.cache) {
-                if let Some(cached_shard) = cache.get_shard(hash, shard_index).await {
-                    debug!("Cache hit for shard {} of {}", shard_index, hash);
-                    shards[shard_index] = Some(cached_shard);
-                    continue;
-                }
+    /// Download with an explicit transfer priority
+    pub async fn download_file_with_priority(
+        &self,
+        output_path: &Path,
+        shard_locations: Vec<(usize, u32)>,
+        file_hash: Option<&str>,
+        priority: TransferPriority,
+    ) -> Result<usize> {
+        self.download_file_with_key(output_path, shard_locations, file_hash, priority, None)
+            .await
+    }
+
+    /// Download, decrypting with `file_key` instead of the pipeline's key
+    pub async fn download_file_with_key(
+        &self,
+        output_path: &Path,
+        shard_locations: Vec<(usize, u32)>,
+        file_hash: Option<&str>,
+        priority: TransferPriority,
+        file_key: Option<[u8; 32]>,
+    ) -> Result<usize> {
+        self.download_shards(output_path, shard_locations, file_hash, priority, file_key)
+            .await
+    }
+
+    /// Download the file a manifest describes, checking each shard against
+    /// the manifest's shard hashes
+    ///
+    /// Shards failing the check are quarantined in the cache and their peer
+    /// reported to the node store; the next source is tried instead. Files
+    /// with local parity are repaired within their groups before falling
+    /// back to the outer code. Shards re-encrypted under a rotated key are
+    /// opened with `shard_keys`; without them only original copies are
+    /// used.
+    pub async fn download_manifest(
+        &self,
+        output_path: &Path,
+        manifest: &FileManifest,
+        priority: TransferPriority,
+        file_key: Option<[u8; 32]>,
+        shard_keys: Option<&ShardKeys>,
+    ) -> Result<usize> {
+        info!(
+            "Starting download to: {:?} (priority: {})",
+            output_path, priority
+        );
+        self.bind_identities(manifest).await?;
+        let no_keys = ShardKeys::default();
+        let keys = shard_keys.unwrap_or(&no_keys);
+        let (shards, tracker) = match &manifest.local_parity {
+            Some(local) => self.fetch_tiered(manifest, local, priority, keys).await,
+            None => {
+                let (mut shards, tracker) = self
+                    .fetch_shards(
+                        manifest.shard_locations.clone(),
+                        Some(&manifest.file_hash),
+                        Some(manifest),
+                        priority,
+                    )
+                    .await?;
+                keys.open_all(manifest, &mut shards);
+                (shards, tracker)
             }
+        };
 
-            // If not in cache, fetch from peer
-            debug!("Fetching shard {} from peer {}", shard_index, peer_id);
+        // Files may be sharded differently from this node's own config
+        let resharded = self.pipeline_for(manifest);
+        let ces = resharded.as_ref().unwrap_or(&*self.ces);
+        self.write_reconstructed(ces, output_path, shards, file_key, tracker)
+            .await
+    }
 
-            match self.go_client.receive_data(peer_id).await {
-                Ok(data) => {
-                    if !data.is_empty() {
-                        shards[shard_index] = Some(data.clone());
+    /// Bind the shard holders' identities the manifest records, so
+    /// connections to them must prove those identities
+    ///
+    /// Fails if one conflicts with an identity already bound: the manifest
+    /// names an impostor, or a node changed keys.
+    async fn bind_identities(&self, manifest: &FileManifest) -> Result<()> {
+        if let Some(directory) = &self.directory {
+            directory
+                .bind_all(&manifest.shard_identities)
+                .await
+                .with_context(|| {
+                    format!("Manifest {} names conflicting peers", manifest.file_hash)
+                })?;
+        }
+        Ok(())
+    }
 
-                        // Cache the shard for future downloads
-                        if let (Some(hash), Some(cache)) = (file_hash, &self.cache) {
-                            let _ = cache.put_shard(hash, shard_index, data).await;
-                        }
-                    }
-                }
-                Err(e) => {
-                    debug!(
-                        "Failed to fetch shard {} from peer {}: {}",
-                        shard_index, peer_id, e
-                    );
-                    // Continue - Reed-Solomon can reconstruct from partial shards
-                }
+    /// Pipeline matching the manifest's shard layout, if it differs from ours
+    ///
+    /// Manifests without a parity count predate per-file layouts and always
+    /// used the node's config.
+    fn pipeline_for(&self, manifest: &FileManifest) -> Option<CesPipeline> {
+        let parity = manifest.parity_count;
+        let data = manifest.outer_shard_count().checked_sub(parity)?;
+        let matches = data == self.ces.data_shard_count() && parity == self.ces.parity_count();
+        (parity > 0 && !matches).then(|| self.ces.resharded(data, parity))
+    }
+
+    /// Fetch the still-encrypted shards a manifest describes, without
+    /// reconstructing the file
+    ///
+    /// Slots are `None` for shards no source could provide. Used to export
+    /// files for offline transfer.
+    pub async fn fetch_manifest_shards(
+        &self,
+        manifest: &FileManifest,
+        priority: TransferPriority,
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        self.bind_identities(manifest).await?;
+        let (shards, tracker) = self
+            .fetch_shards(
+                manifest.shard_locations.clone(),
+                Some(&manifest.file_hash),
+                Some(manifest),
+                priority,
+            )
+            .await?;
+        if let Some(tracker) = tracker {
+            tracker.finish(true);
+        }
+        Ok(shards)
+    }
+
+    /// Warm the cache with enough of a file's shards to rebuild it
+    ///
+    /// Shards already cached count towards the data shards needed; missing
+    /// ones are fetched at prefetch priority and cached as prefetched, so
+    /// they are the first to go under disk pressure until read. Returns the
+    /// number of shards fetched.
+    pub async fn prefetch_manifest(&self, manifest: &FileManifest) -> Result<usize> {
+        let cache = self.cache.as_ref().context("Prefetching needs a cache")?;
+        self.bind_identities(manifest).await?;
+        let hash = &manifest.file_hash;
+        let needed = manifest.data_shard_count();
+        let mut cached = 0;
+        let mut missing = Vec::new();
+        for &(shard_index, peer_id) in &manifest.shard_locations {
+            if cache.has_shard(hash, shard_index).await {
+                cached += 1;
+            } else {
+                missing.push((shard_index, peer_id));
+            }
+        }
+
+        let mut fetched = 0;
+        for (shard_index, peer_id) in missing {
+            if cached + fetched >= needed {
+                break;
+            }
+            let sources = self.plan_sources(hash, shard_index, peer_id).await;
+            let check = ShardCheck::of(manifest, shard_index);
+            let mut stored = Ok(());
+            let store = &mut stored;
+            let data = cache
+                .fetch_shard_once(hash, shard_index, || async move {
+                    let data = self.fetch_shard(hash, shard_index, &sources, check).await?;
+                    self.throttle(TransferPriority::Prefetch, data.len()).await;
+                    *store = cache
+                        .put_prefetched_shard(hash, shard_index, data.clone())
+                        .await;
+                    Some(data)
+                })
+                .await;
+            stored?;
+            if data.is_some() {
+                fetched += 1;
             }
         }
+        if fetched > 0 {
+            debug!("Prefetched {} shard(s) of {}", fetched, hash);
+        }
+        Ok(fetched)
+    }
+
+    #[instrument(skip(self, shard_locations, file_key), fields(shards = shard_locations.len()))]
+    async fn download_shards(
+        &self,
+        output_path: &Path,
+        shard_locations: Vec<(usize, u32)>,
+        file_hash: Option<&str>,
+        priority: TransferPriority,
+        file_key: Option<[u8; 32]>,
+    ) -> Result<usize> {
+        info!(
+            "Starting download to: {:?} (priority: {})",
+            output_path, priority
+        );
+
+        // 1. Fetch shards from cache or peers
+        let (shards, tracker) = self
+            .fetch_shards(shard_locations, file_hash, None, priority)
+            .await?;
+
+        self.write_reconstructed(&self.ces, output_path, shards, file_key, tracker)
+            .await
+    }
 
+    /// Reconstruct fetched shards through `ces` and write the file
+    async fn write_reconstructed(
+        &self,
+        ces: &CesPipeline,
+        output_path: &Path,
+        shards: Vec<Option<Vec<u8>>>,
+        file_key: Option<[u8; 32]>,
+        tracker: Option<ProgressTracker>,
+    ) -> Result<usize> {
         // 2. Reconstruct through CES pipeline
-        let data = self.ces.reconstruct(shards)?;
+        let data = match file_key {
+            Some(key) => ces.rekeyed(key).reconstruct(shards)?,
+            None => ces.reconstruct(shards)?,
+        };
         info!("Reconstructed {} bytes", data.len());
 
         // 3. Write to file
@@ -97,21 +590,196 @@ impl DownloadProtocol {
             .context("Failed to write file")?;
 
         info!("Download complete: {} bytes written", data.len());
+        if let Some(tracker) = tracker {
+            tracker.finish(true);
+        }
         Ok(data.len())
     }
 
+    /// Fetch the outer shards of a two-tier file, repairing gaps from local
+    /// parity where a group allows it
+    ///
+    /// Local parity is only fetched for groups missing shards; whatever they
+    /// cannot repair is left to the outer code. Shards are opened with
+    /// `keys` before groups are repaired.
+    async fn fetch_tiered(
+        &self,
+        manifest: &FileManifest,
+        local: &LocalParity,
+        priority: TransferPriority,
+        keys: &ShardKeys,
+    ) -> (Vec<Option<Vec<u8>>>, Option<ProgressTracker>) {
+        let outer = manifest.outer_shard_count();
+        let (outer_locations, parity_locations): (Vec<_>, Vec<_>) = manifest
+            .shard_locations
+            .iter()
+            .copied()
+            .partition(|(index, _)| *index < outer);
+        let hash = Some(manifest.file_hash.as_str());
+        let mut shards = vec![None; manifest.shard_count];
+        let mut tracker = self.progress.as_ref().map(|hub| {
+            hub.track(
+                &manifest.file_hash,
+                TransferDirection::Download,
+                outer_locations.len(),
+            )
+        });
+        self.fetch_into(
+            &mut shards,
+            outer_locations,
+            hash,
+            Some(manifest),
+            priority,
+            &mut tracker,
+        )
+        .await;
+        keys.open_all(manifest, &mut shards);
+
+        let wanted: Vec<(usize, u32)> = parity_locations
+            .into_iter()
+            .filter(|(index, _)| {
+                local
+                    .short_groups(&shards)
+                    .any(|group| group.parity.contains(index))
+            })
+            .collect();
+        if !wanted.is_empty() {
+            self.fetch_into(
+                &mut shards,
+                wanted,
+                hash,
+                Some(manifest),
+                priority,
+                &mut tracker,
+            )
+            .await;
+            keys.open_all(manifest, &mut shards);
+            let rebuilt = local.repair_groups(&mut shards);
+            if rebuilt > 0 {
+                info!(
+                    "Rebuilt {} shard(s) of {} from local parity",
+                    rebuilt, manifest.file_hash
+                );
+            }
+        }
+        shards.truncate(outer);
+        (shards, tracker)
+    }
+
+    /// Fetch shards from cache or peers, verifying them against `manifest`
+    /// when its hashes are known
+    async fn fetch_shards(
+        &self,
+        shard_locations: Vec<(usize, u32)>,
+        file_hash: Option<&str>,
+        manifest: Option<&FileManifest>,
+        priority: TransferPriority,
+    ) -> Result<(Vec<Option<Vec<u8>>>, Option<ProgressTracker>)> {
+        let mut shards = shard_slots(&shard_locations)?;
+        let mut tracker = self.progress.as_ref().map(|hub| {
+            hub.track(
+                file_hash.unwrap_or_default(),
+                TransferDirection::Download,
+                shard_locations.len(),
+            )
+        });
+        self.fetch_into(
+            &mut shards,
+            shard_locations,
+            file_hash,
+            manifest,
+            priority,
+            &mut tracker,
+        )
+        .await;
+        Ok((shards, tracker))
+    }
+
+    /// Fill `shards` slots for `shard_locations` from cache or peers
+    async fn fetch_into(
+        &self,
+        shards: &mut [Option<Vec<u8>>],
+        shard_locations: Vec<(usize, u32)>,
+        file_hash: Option<&str>,
+        manifest: Option<&FileManifest>,
+        priority: TransferPriority,
+        tracker: &mut Option<ProgressTracker>,
+    ) {
+        for (shard_index, peer_id) in shard_locations {
+            // First, try to get from cache if file_hash is provided
+            if let (Some(hash), Some(cache)) = (file_hash, &self.cache) {
+                if let Some(cached_shard) = cache.get_shard(hash, shard_index).await {
+                    debug!("Cache hit for shard {} of {}", shard_index, hash);
+                    if let Some(tracker) = tracker.as_mut() {
+                        tracker.shard_done(cached_shard.len());
+                    }
+                    shards[shard_index] = Some(cached_shard);
+                    continue;
+                }
+            }
+
+            // If not in cache, fetch from the most probable holder
+            let sources = match file_hash {
+                Some(hash) => self.plan_sources(hash, shard_index, peer_id).await,
+                None => vec![peer_id],
+            };
+
+            let check = manifest.and_then(|manifest| ShardCheck::of(manifest, shard_index));
+            let fetch = || async {
+                let data = self
+                    .fetch_shard(file_hash.unwrap_or_default(), shard_index, &sources, check)
+                    .await?;
+                self.throttle(priority, data.len()).await;
+                // Cache the shard for future downloads
+                if let (Some(hash), Some(cache)) = (file_hash, &self.cache) {
+                    let _ = cache.put_shard(hash, shard_index, data.clone()).await;
+                }
+                Some(data)
+            };
+            // Concurrent downloads missing the same shard share one fetch
+            let fetched = match (file_hash, &self.cache) {
+                (Some(hash), Some(cache)) => cache.fetch_shard_once(hash, shard_index, fetch).await,
+                _ => fetch().await,
+            };
+            if let Some(data) = fetched {
+                if let Some(tracker) = tracker.as_mut() {
+                    tracker.shard_done(data.len());
+                }
+                shards[shard_index] = Some(data);
+            } else if let Some(tracker) = tracker.as_mut() {
+                tracker.warn(format!(
+                    "Shard {} unavailable from {} source(s); relying on parity",
+                    shard_index,
+                    sources.len()
+                ));
+            }
+        }
+    }
+
     /// Download raw data
     pub async fn download_data(&self, shard_locations: Vec<(usize, u32)>) -> Result<Vec<u8>> {
+        self.download_data_with_priority(shard_locations, TransferPriority::Interactive)
+            .await
+    }
+
+    /// Download raw data with an explicit transfer priority
+    ///
+    /// Without a file hash to name the shards, only transports that find
+    /// them by index (the Go node, simulated peers) can serve them.
+    #[instrument(skip(self, shard_locations), fields(shards = shard_locations.len()))]
+    pub async fn download_data_with_priority(
+        &self,
+        shard_locations: Vec<(usize, u32)>,
+        priority: TransferPriority,
+    ) -> Result<Vec<u8>> {
         info!("Starting data download: {} shards", shard_locations.len());
 
         // Fetch shards
-        let mut shards = vec![None; shard_locations.len()];
+        let mut shards = shard_slots(&shard_locations)?;
         for (shard_index, peer_id) in shard_locations {
-            match self.go_client.receive_data(peer_id).await {
-                Ok(data) if !data.is_empty() => {
-                    shards[shard_index] = Some(data);
-                }
-                _ => continue,
+            if let Some((_, data)) = self.fetch_from("", shard_index, peer_id).await {
+                self.throttle(priority, data.len()).await;
+                shards[shard_index] = Some(data);
             }
         }
 
@@ -142,6 +810,89 @@ impl DownloadProtocol {
     }
 }
 
+/// Race a request against a duplicate issued once `delay` has elapsed
+///
+/// The first successful response wins. If the primary fails before the
+/// delay, the backup is used as a plain failover and no hedge is counted.
+pub async fn hedged_request<T, P, B, F>(
+    primary: P,
+    delay: Duration,
+    backup: F,
+    metrics: &MetricsTracker,
+) -> Option<T>
+where
+    P: Future<Output = Option<T>>,
+    F: FnOnce() -> B,
+    B: Future<Output = Option<T>>,
+{
+    tokio::pin!(primary);
+    match tokio::time::timeout(delay, &mut primary).await {
+        Ok(Some(value)) => return Some(value),
+        Ok(None) => return backup().await,
+        Err(_) => {}
+    }
+
+    debug!("Request exceeded {:?}, issuing hedged duplicate", delay);
+    metrics.record_hedge_issued();
+    let backup = backup();
+    tokio::pin!(backup);
+
+    let (value, hedge_won) = tokio::select! {
+        result = &mut primary => match result {
+            Some(value) => (Some(value), false),
+            None => (backup.await, true),
+        },
+        result = &mut backup => match result {
+            Some(value) => (Some(value), true),
+            None => (primary.await, false),
+        },
+    };
+    if value.is_some() {
+        metrics.record_hedge_outcome(hedge_won);
+    }
+    value
+}
+
+/// Expected content of a shard, from its manifest
+struct ShardCheck<'a> {
+    file_hash: &'a str,
+    manifest: &'a FileManifest,
+    shard_index: usize,
+}
+
+impl<'a> ShardCheck<'a> {
+    /// Check for shard `shard_index`, if the manifest records its hash
+    fn of(manifest: &'a FileManifest, shard_index: usize) -> Option<Self> {
+        (shard_index < manifest.shard_hashes.len()).then_some(Self {
+            file_hash: &manifest.file_hash,
+            manifest,
+            shard_index,
+        })
+    }
+
+    /// Whether `data` is the shard, under any key version still accepted
+    fn matches(&self, data: &[u8]) -> bool {
+        self.manifest.shard_matches(self.shard_index, data)
+    }
+}
+
+/// One empty slot per shard location, rejecting out-of-range indices
+///
+/// Locations come from manifests that may have crossed the network, so an
+/// index past the end is an error rather than a panic.
+fn shard_slots(shard_locations: &[(usize, u32)]) -> Result<Vec<Option<Vec<u8>>>> {
+    let count = shard_locations.len();
+    if let Some((index, peer_id)) = shard_locations.iter().find(|(index, _)| *index >= count) {
+        anyhow::bail!(
+            "Shard index {} (peer {}) out of range for {} shard locations",
+            index,
+            peer_id,
+            count
+        );
+    }
+    Ok(vec![None; count])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,4 +914,93 @@ mod tests {
         // Protocol created successfully - just checking it doesn't panic
         drop(download);
     }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_plan_sources_prefers_advertised_holders() {
+        use crate::bloom::{ShardAdvertisement, ShardBloomFilter};
+
+        let caps = crate::capabilities::HardwareCaps::probe();
+        let config = CesConfig::adaptive(&caps, 8 * 1024 * 1024, 1.0);
+        let ces = Arc::new(CesPipeline::new(config));
+        let go_addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let go_client = Arc::new(GoClient::new(go_addr));
+
+        let index = Arc::new(ShardAvailabilityIndex::new());
+        let mut holds = ShardBloomFilter::new(10, 0.001);
+        holds.insert("file", 0);
+        index.update(ShardAdvertisement::new(5, holds)).await;
+        index
+            .update(ShardAdvertisement::new(3, ShardBloomFilter::new(10, 0.001)))
+            .await;
+
+        let download = DownloadProtocol::new(ces, go_client).with_availability_index(index);
+
+        // Manifest location 3 is ruled out by its filter; 5 advertises the shard
+        assert_eq!(download.plan_sources("file", 0, 3).await, vec![5]);
+        // Unknown peer keeps its slot ahead of advertised holders
+        assert_eq!(download.plan_sources("file", 0, 9).await, vec![9, 5]);
+        // Nothing advertised: fall back to the manifest location
+        assert_eq!(download.plan_sources("file", 1, 3).await, vec![3]);
+    }
+
+    #[test]
+    fn test_shard_slots_rejects_out_of_range_index() {
+        assert_eq!(shard_slots(&[(1, 7), (0, 8)]).unwrap().len(), 2);
+        assert!(shard_slots(&[(0, 7), (5, 8)]).is_err());
+    }
+
+    async fn respond_after(ms: u64, value: Option<u32>) -> Option<u32> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        value
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_fast_primary() {
+        let metrics = MetricsTracker::new(100);
+        let result = hedged_request(
+            respond_after(1, Some(1)),
+            Duration::from_millis(100),
+            || respond_after(1, Some(2)),
+            &metrics,
+        )
+        .await;
+
+        assert_eq!(result, Some(1));
+        assert_eq!(metrics.hedge_stats().hedges_issued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_slow_primary() {
+        let metrics = MetricsTracker::new(100);
+        let result = hedged_request(
+            respond_after(500, Some(1)),
+            Duration::from_millis(10),
+            || respond_after(5, Some(2)),
+            &metrics,
+        )
+        .await;
+
+        assert_eq!(result, Some(2));
+        let stats = metrics.hedge_stats();
+        assert_eq!(stats.hedges_issued, 1);
+        assert_eq!(stats.hedges_won, 1);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request_failed_hedge_falls_back() {
+        let metrics = MetricsTracker::new(100);
+        let result = hedged_request(
+            respond_after(50, Some(1)),
+            Duration::from_millis(5),
+            || respond_after(1, None),
+            &metrics,
+        )
+        .await;
+
+        assert_eq!(result, Some(1));
+        let stats = metrics.hedge_stats();
+        assert_eq!(stats.hedges_issued, 1);
+        assert_eq!(stats.primary_won, 1);
+    }
 }
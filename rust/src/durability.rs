@@ -0,0 +1,199 @@
+/// Durable writes of manifests and shards
+///
+/// Files are written beside their target under a `.partial` name and
+/// renamed into place, so a crash leaves either the old file or the new one,
+/// never a torn mix. How far each write is forced to disk first is the
+/// configured `Durability`:
+///
+/// - `none`: rely on the OS to flush eventually (fastest; a power cut can
+///   lose recent writes, or leave an empty file behind a rename)
+/// - `fsync-file`: fsync the file before renaming it into place
+/// - `fsync-dir`: also fsync the directory, so the rename itself survives
+///
+/// `.partial` files found on startup are writes a crash interrupted and are
+/// removed; see [`crate::cache::Cache::recover`] for the checks that catch
+/// files torn before writes were atomic.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+/// Suffix of files being written
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// How hard writes are pushed to stable storage before they count as done
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Durability {
+    None,
+    #[default]
+    FsyncFile,
+    FsyncDir,
+}
+
+/// Durability settings (`[durability]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DurabilityConfig {
+    pub level: Durability,
+    /// Check the cache for torn writes when the daemon starts
+    pub recover_on_start: bool,
+}
+
+impl Default for DurabilityConfig {
+    fn default() -> Self {
+        Self {
+            level: Durability::default(),
+            recover_on_start: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    durability: DurabilityConfig,
+}
+
+impl DurabilityConfig {
+    /// Load the `[durability]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[durability]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        Ok(file.durability)
+    }
+}
+
+/// Where `path` is written before it is renamed into place
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    path.with_file_name(name)
+}
+
+pub fn is_partial_write(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(PARTIAL_SUFFIX))
+}
+
+/// Replace `path` with `data` so readers see the old or the new contents
+///
+/// Parent directories are created as needed.
+pub async fn write_atomic(path: &Path, data: &[u8], durability: Durability) -> Result<()> {
+    let Some(dir) = path.parent() else {
+        bail!("No directory for {:?}", path);
+    };
+    tokio::fs::create_dir_all(dir).await?;
+    let partial = partial_path(path);
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .with_context(|| format!("Failed to create {:?}", partial))?;
+    file.write_all(data)
+        .await
+        .with_context(|| format!("Failed to write {:?}", partial))?;
+    if durability >= Durability::FsyncFile {
+        file.sync_all().await?;
+    }
+    drop(file);
+    tokio::fs::rename(&partial, path)
+        .await
+        .with_context(|| format!("Failed to move {:?} into place", path))?;
+    if durability >= Durability::FsyncDir {
+        sync_dir(dir).await?;
+    }
+    Ok(())
+}
+
+/// Make renames and removals in `dir` durable
+#[cfg(unix)]
+pub async fn sync_dir(dir: &Path) -> Result<()> {
+    tokio::fs::File::open(dir)
+        .await?
+        .sync_all()
+        .await
+        .with_context(|| format!("Failed to sync directory {:?}", dir))
+}
+
+/// Directories cannot be opened for syncing here; renames are durable once
+/// the file system journals them
+#[cfg(not(unix))]
+pub async fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Delete writes a crash interrupted under `dir`, returning how many
+pub async fn remove_partial_writes(dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                pending.push(path);
+            } else if is_partial_write(&path) {
+                match tokio::fs::remove_file(&path).await {
+                    Ok(()) => {
+                        debug!("Removed interrupted write {:?}", path);
+                        removed += 1;
+                    }
+                    Err(e) => warn!("Failed to remove interrupted write {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_atomic_writes_replace_whole_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifests").join("abc.json");
+        for level in [
+            Durability::None,
+            Durability::FsyncFile,
+            Durability::FsyncDir,
+        ] {
+            write_atomic(&path, format!("{:?}", level).as_bytes(), level)
+                .await
+                .unwrap();
+            assert_eq!(
+                std::fs::read(&path).unwrap(),
+                format!("{:?}", level).as_bytes()
+            );
+        }
+        assert!(!partial_path(&path).exists());
+
+        // A crash between create and rename leaves only the partial file
+        std::fs::write(partial_path(&path), b"torn").unwrap();
+        std::fs::create_dir_all(dir.path().join("shards/abc")).unwrap();
+        std::fs::write(dir.path().join("shards/abc/0.bin.partial"), b"torn").unwrap();
+        assert_eq!(remove_partial_writes(dir.path()).await.unwrap(), 2);
+        assert_eq!(std::fs::read(&path).unwrap(), b"FsyncDir");
+    }
+
+    #[test]
+    fn test_levels_parse_from_config() {
+        let config = DurabilityConfig::from_toml("[durability]\nlevel = \"fsync-dir\"\n").unwrap();
+        assert_eq!(config.level, Durability::FsyncDir);
+        assert!(config.recover_on_start);
+        let config = DurabilityConfig::from_toml("").unwrap();
+        assert_eq!(config.level, Durability::FsyncFile);
+    }
+}
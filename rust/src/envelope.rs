@@ -0,0 +1,252 @@
+/// X25519 key envelopes for sharing file keys with peers
+///
+/// Every node holds a long-lived X25519 keypair bound to its node ID. An
+/// uploader wraps the file's CES key once per recipient: an ephemeral X25519
+/// exchange with the recipient's public key, HKDF-SHA256 over the shared
+/// secret, then XChaCha20-Poly1305 around the 32-byte key. The wrapped keys
+/// are stored in the manifest, so a recipient can unwrap the key with its own
+/// private key and nothing has to be shared out of band.
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// HKDF info string for key wrapping
+const WRAP_INFO: &[u8] = b"pangea-ces-key-wrap-v1";
+
+/// A file key wrapped for one recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// Recipient's X25519 public key (hex)
+    pub recipient: String,
+    /// Ephemeral X25519 public key (hex)
+    pub ephemeral: String,
+    /// XChaCha20-Poly1305 nonce (hex)
+    pub nonce: String,
+    /// Encrypted file key (hex)
+    pub ciphertext: String,
+}
+
+/// On-disk form of a node keypair
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    node_id: u32,
+    secret: String,
+}
+
+/// A node's long-lived X25519 keypair
+pub struct NodeKeypair {
+    node_id: u32,
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl NodeKeypair {
+    /// Generate a fresh keypair
+    pub fn generate(node_id: u32) -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self::from_secret(node_id, bytes)
+    }
+
+    /// Rebuild a keypair from its secret key
+    pub fn from_secret(node_id: u32, secret: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+        Self {
+            node_id,
+            secret,
+            public,
+        }
+    }
+
+    /// Load the keypair stored at `path`, creating it on first use
+    ///
+    /// Fails if the file belongs to a different node ID.
+    pub fn load_or_generate(node_id: u32, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let keypair = Self::generate(node_id);
+            keypair.save(path)?;
+            return Ok(keypair);
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {:?}", path))?;
+        let file: KeyFile = serde_json::from_str(&content).context("Invalid key file")?;
+        if file.node_id != node_id {
+            bail!(
+                "Key file {:?} belongs to node {}, not node {}",
+                path,
+                file.node_id,
+                node_id
+            );
+        }
+        let mut secret = [0u8; 32];
+        hex::decode_to_slice(&file.secret, &mut secret).context("Invalid secret key")?;
+        Ok(Self::from_secret(node_id, secret))
+    }
+
+    /// Write the keypair to `path`, readable only by the owner
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(&KeyFile {
+            node_id: self.node_id,
+            secret: hex::encode(self.secret.to_bytes()),
+        })?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write key file {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.public.as_bytes())
+    }
+
+    /// X25519 shared secret with `peer`
+    pub(crate) fn diffie_hellman(&self, peer: &PublicKey) -> [u8; 32] {
+        self.secret.diffie_hellman(peer).to_bytes()
+    }
+
+    /// Unwrap the file key addressed to this node
+    ///
+    /// Returns `Ok(None)` when none of the envelopes names this node.
+    pub fn unwrap_key(&self, envelopes: &[WrappedKey]) -> Result<Option<[u8; 32]>> {
+        let ours = self.public_key_hex();
+        let Some(envelope) = envelopes.iter().find(|e| e.recipient == ours) else {
+            return Ok(None);
+        };
+
+        let ephemeral = parse_public_key(&envelope.ephemeral)?;
+        let shared = self.secret.diffie_hellman(&ephemeral);
+        let cipher = wrapping_cipher(shared.as_bytes(), &ephemeral, &self.public);
+
+        let nonce = hex::decode(&envelope.nonce).context("Invalid envelope nonce")?;
+        if nonce.len() != 24 {
+            bail!("Invalid envelope nonce length");
+        }
+        let ciphertext = hex::decode(&envelope.ciphertext).context("Invalid envelope")?;
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| anyhow!("Failed to unwrap file key"))?;
+
+        let key: [u8; 32] = plaintext
+            .try_into()
+            .map_err(|_| anyhow!("Unwrapped key has the wrong length"))?;
+        Ok(Some(key))
+    }
+}
+
+/// Wrap `key` so only the holder of `recipient`'s private key can read it
+pub fn wrap_key(key: &[u8; 32], recipient: &PublicKey) -> Result<WrappedKey> {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(recipient);
+    let cipher = wrapping_cipher(shared.as_bytes(), &ephemeral, recipient);
+
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), key.as_ref())
+        .map_err(|e| anyhow!("Failed to wrap file key: {}", e))?;
+
+    Ok(WrappedKey {
+        recipient: hex::encode(recipient.as_bytes()),
+        ephemeral: hex::encode(ephemeral.as_bytes()),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+/// Parse a hex-encoded X25519 public key
+pub fn parse_public_key(hex_key: &str) -> Result<PublicKey> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(hex_key, &mut bytes).context("Invalid X25519 public key")?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Symmetric cipher for one envelope, bound to both public keys
+fn wrapping_cipher(
+    shared: &[u8; 32],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> XChaCha20Poly1305 {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+
+    let mut wrap_key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut wrap_key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    XChaCha20Poly1305::new(&wrap_key.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_unwrap_roundtrip() {
+        let alice = NodeKeypair::generate(1);
+        let bob = NodeKeypair::generate(2);
+        let carol = NodeKeypair::generate(3);
+        let file_key = [7u8; 32];
+
+        let envelopes = vec![
+            wrap_key(&file_key, &alice.public_key()).unwrap(),
+            wrap_key(&file_key, &bob.public_key()).unwrap(),
+        ];
+
+        assert_eq!(alice.unwrap_key(&envelopes).unwrap(), Some(file_key));
+        assert_eq!(bob.unwrap_key(&envelopes).unwrap(), Some(file_key));
+        assert_eq!(carol.unwrap_key(&envelopes).unwrap(), None);
+    }
+
+    #[test]
+    fn test_tampered_envelope_rejected() {
+        let bob = NodeKeypair::generate(2);
+        let mut envelope = wrap_key(&[1u8; 32], &bob.public_key()).unwrap();
+        let flipped = if envelope.ciphertext.starts_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        envelope.ciphertext.replace_range(0..1, flipped);
+        assert!(bob.unwrap_key(&[envelope]).is_err());
+    }
+
+    #[test]
+    fn test_key_file_bound_to_node_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.key");
+
+        let created = NodeKeypair::load_or_generate(4, &path).unwrap();
+        let loaded = NodeKeypair::load_or_generate(4, &path).unwrap();
+        assert_eq!(created.public_key_hex(), loaded.public_key_hex());
+        assert!(NodeKeypair::load_or_generate(5, &path).is_err());
+    }
+}
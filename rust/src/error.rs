@@ -0,0 +1,500 @@
+/// Typed errors for the public node APIs
+///
+/// Each error maps to a stable numeric code so FFI and RPC callers can branch
+/// on failures without parsing messages. Codes are grouped by subsystem
+/// (1xxx network, 2xxx lookup, 3xxx upload, 4xxx download, 5xxx namespace,
+/// 6xxx compute admission, 9xxx FFI) and must
+/// never be renumbered once released.
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Stable error codes
+pub mod codes {
+    pub const OK: u32 = 0;
+
+    pub const NETWORK_NOT_CONNECTED: u32 = 1001;
+    pub const NETWORK_CONNECT_FAILED: u32 = 1002;
+    pub const NETWORK_RPC: u32 = 1003;
+    pub const NETWORK_PEER_UNREACHABLE: u32 = 1004;
+    pub const NETWORK_TIMEOUT: u32 = 1005;
+    pub const NETWORK_CIRCUIT_OPEN: u32 = 1006;
+    pub const NETWORK_NO_TRANSPORT: u32 = 1007;
+    pub const NETWORK_TRANSPORT_CORRUPTION: u32 = 1008;
+    pub const NETWORK_OTHER: u32 = 1999;
+
+    pub const LOOKUP_CACHE: u32 = 2001;
+    pub const LOOKUP_DHT: u32 = 2002;
+    pub const LOOKUP_INVALID_RECORD: u32 = 2003;
+    pub const LOOKUP_OTHER: u32 = 2999;
+
+    pub const UPLOAD_FILE_NOT_FOUND: u32 = 3001;
+    pub const UPLOAD_NOT_A_FILE: u32 = 3002;
+    pub const UPLOAD_NO_PEERS: u32 = 3003;
+    pub const UPLOAD_IO: u32 = 3004;
+    pub const UPLOAD_INVALID_MANIFEST: u32 = 3005;
+    pub const UPLOAD_TRANSFER: u32 = 3006;
+    pub const UPLOAD_NO_CLASS_PEERS: u32 = 3007;
+    pub const UPLOAD_SESSION_NOT_FOUND: u32 = 3008;
+    pub const UPLOAD_OTHER: u32 = 3999;
+
+    pub const DOWNLOAD_FILE_NOT_FOUND: u32 = 4001;
+    pub const DOWNLOAD_IO: u32 = 4002;
+    pub const DOWNLOAD_TRANSFER: u32 = 4003;
+    pub const DOWNLOAD_KEY_UNWRAP: u32 = 4004;
+    pub const DOWNLOAD_OTHER: u32 = 4999;
+
+    pub const NAMESPACE_INVALID: u32 = 5001;
+    pub const NAMESPACE_FORBIDDEN: u32 = 5002;
+    pub const NAMESPACE_QUOTA_EXCEEDED: u32 = 5003;
+
+    pub const COMPUTE_INSUFFICIENT_MEMORY: u32 = 6001;
+    pub const COMPUTE_QUEUE_FULL: u32 = 6002;
+    pub const COMPUTE_QUEUE_TIMEOUT: u32 = 6003;
+
+    pub const FFI_INVALID_ARGUMENT: u32 = 9001;
+    pub const FFI_PROCESS_FAILED: u32 = 9002;
+    pub const FFI_RECONSTRUCT_FAILED: u32 = 9003;
+    pub const FFI_KEY_DERIVATION_FAILED: u32 = 9004;
+    pub const FFI_SHM_UNSUPPORTED: u32 = 9005;
+    pub const FFI_SHM_FULL: u32 = 9006;
+
+    /// Symbolic name for a code (`"UNKNOWN"` for unassigned values)
+    pub fn name(code: u32) -> &'static str {
+        match code {
+            OK => "OK",
+            NETWORK_NOT_CONNECTED => "NETWORK_NOT_CONNECTED",
+            NETWORK_CONNECT_FAILED => "NETWORK_CONNECT_FAILED",
+            NETWORK_RPC => "NETWORK_RPC",
+            NETWORK_PEER_UNREACHABLE => "NETWORK_PEER_UNREACHABLE",
+            NETWORK_TIMEOUT => "NETWORK_TIMEOUT",
+            NETWORK_CIRCUIT_OPEN => "NETWORK_CIRCUIT_OPEN",
+            NETWORK_NO_TRANSPORT => "NETWORK_NO_TRANSPORT",
+            NETWORK_TRANSPORT_CORRUPTION => "NETWORK_TRANSPORT_CORRUPTION",
+            NETWORK_OTHER => "NETWORK_OTHER",
+            LOOKUP_CACHE => "LOOKUP_CACHE",
+            LOOKUP_DHT => "LOOKUP_DHT",
+            LOOKUP_INVALID_RECORD => "LOOKUP_INVALID_RECORD",
+            LOOKUP_OTHER => "LOOKUP_OTHER",
+            UPLOAD_FILE_NOT_FOUND => "UPLOAD_FILE_NOT_FOUND",
+            UPLOAD_NOT_A_FILE => "UPLOAD_NOT_A_FILE",
+            UPLOAD_NO_PEERS => "UPLOAD_NO_PEERS",
+            UPLOAD_IO => "UPLOAD_IO",
+            UPLOAD_INVALID_MANIFEST => "UPLOAD_INVALID_MANIFEST",
+            UPLOAD_TRANSFER => "UPLOAD_TRANSFER",
+            UPLOAD_NO_CLASS_PEERS => "UPLOAD_NO_CLASS_PEERS",
+            UPLOAD_SESSION_NOT_FOUND => "UPLOAD_SESSION_NOT_FOUND",
+            UPLOAD_OTHER => "UPLOAD_OTHER",
+            DOWNLOAD_FILE_NOT_FOUND => "DOWNLOAD_FILE_NOT_FOUND",
+            DOWNLOAD_IO => "DOWNLOAD_IO",
+            DOWNLOAD_TRANSFER => "DOWNLOAD_TRANSFER",
+            DOWNLOAD_KEY_UNWRAP => "DOWNLOAD_KEY_UNWRAP",
+            DOWNLOAD_OTHER => "DOWNLOAD_OTHER",
+            NAMESPACE_INVALID => "NAMESPACE_INVALID",
+            NAMESPACE_FORBIDDEN => "NAMESPACE_FORBIDDEN",
+            NAMESPACE_QUOTA_EXCEEDED => "NAMESPACE_QUOTA_EXCEEDED",
+            COMPUTE_INSUFFICIENT_MEMORY => "COMPUTE_INSUFFICIENT_MEMORY",
+            COMPUTE_QUEUE_FULL => "COMPUTE_QUEUE_FULL",
+            COMPUTE_QUEUE_TIMEOUT => "COMPUTE_QUEUE_TIMEOUT",
+            FFI_INVALID_ARGUMENT => "FFI_INVALID_ARGUMENT",
+            FFI_PROCESS_FAILED => "FFI_PROCESS_FAILED",
+            FFI_RECONSTRUCT_FAILED => "FFI_RECONSTRUCT_FAILED",
+            FFI_KEY_DERIVATION_FAILED => "FFI_KEY_DERIVATION_FAILED",
+            FFI_SHM_UNSUPPORTED => "FFI_SHM_UNSUPPORTED",
+            FFI_SHM_FULL => "FFI_SHM_FULL",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
+/// Errors that carry a stable numeric code
+pub trait ErrorCode: std::error::Error {
+    /// Stable code identifying the failure
+    ///
+    /// Wrapping variants report the code of the error they wrap, so callers
+    /// always see the root cause.
+    fn code(&self) -> u32;
+
+    /// Whether retrying the operation may succeed
+    ///
+    /// Network hiccups are transient; integrity, validation and local
+    /// configuration failures are permanent.
+    fn is_transient(&self) -> bool {
+        false
+    }
+
+    /// Code, name, message and cause chain for FFI/RPC responses
+    fn report(&self) -> ErrorReport {
+        let mut chain = Vec::new();
+        let mut source = self.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+
+        ErrorReport {
+            code: self.code(),
+            name: codes::name(self.code()).to_string(),
+            message: self.to_string(),
+            chain,
+        }
+    }
+}
+
+/// Serializable error description for RPC responses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    pub code: u32,
+    pub name: String,
+    pub message: String,
+    /// Underlying causes, outermost first
+    pub chain: Vec<String>,
+}
+
+/// Errors talking to the Go node or to peers
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("Not connected to Go node. Call connect() first")]
+    NotConnected,
+
+    #[error("Failed to connect to Go node at {addr}")]
+    ConnectFailed {
+        addr: SocketAddr,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("RPC error: {0}")]
+    Rpc(#[from] capnp::Error),
+
+    #[error("Peer {0} unreachable")]
+    PeerUnreachable(u32),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Go node circuit open; failing fast until it recovers")]
+    CircuitOpen,
+
+    #[error("No transport available for peer {0}")]
+    NoTransport(u32),
+
+    #[error("Shard {shard_index} from peer {peer_id} failed its transport checksum")]
+    TransportCorruption { peer_id: u32, shard_index: usize },
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ErrorCode for NetworkError {
+    fn code(&self) -> u32 {
+        match self {
+            NetworkError::NotConnected => codes::NETWORK_NOT_CONNECTED,
+            NetworkError::ConnectFailed { .. } => codes::NETWORK_CONNECT_FAILED,
+            NetworkError::Rpc(_) => codes::NETWORK_RPC,
+            NetworkError::PeerUnreachable(_) => codes::NETWORK_PEER_UNREACHABLE,
+            NetworkError::Timeout(_) => codes::NETWORK_TIMEOUT,
+            NetworkError::CircuitOpen => codes::NETWORK_CIRCUIT_OPEN,
+            NetworkError::NoTransport(_) => codes::NETWORK_NO_TRANSPORT,
+            NetworkError::TransportCorruption { .. } => codes::NETWORK_TRANSPORT_CORRUPTION,
+            NetworkError::Other(_) => codes::NETWORK_OTHER,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            NetworkError::ConnectFailed { .. }
+            | NetworkError::PeerUnreachable(_)
+            | NetworkError::Timeout(_) => true,
+            // The bytes were damaged on the way; another copy may arrive intact
+            NetworkError::TransportCorruption { .. } => true,
+            NetworkError::Rpc(e) => matches!(
+                e.kind,
+                capnp::ErrorKind::Disconnected | capnp::ErrorKind::Overloaded
+            ),
+            // Retrying before the breaker cools down only burns the budget
+            NetworkError::CircuitOpen => false,
+            NetworkError::NotConnected | NetworkError::NoTransport(_) | NetworkError::Other(_) => {
+                false
+            }
+        }
+    }
+}
+
+/// Errors resolving files through the cache and DHT
+#[derive(Debug, Error)]
+pub enum LookupError {
+    #[error("Cache error")]
+    Cache(#[source] anyhow::Error),
+
+    #[error("DHT error")]
+    Dht(#[source] anyhow::Error),
+
+    #[error("Invalid record: {0}")]
+    InvalidRecord(String),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ErrorCode for LookupError {
+    fn code(&self) -> u32 {
+        match self {
+            LookupError::Cache(_) => codes::LOOKUP_CACHE,
+            LookupError::Dht(_) => codes::LOOKUP_DHT,
+            LookupError::InvalidRecord(_) => codes::LOOKUP_INVALID_RECORD,
+            LookupError::Other(_) => codes::LOOKUP_OTHER,
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        matches!(self, LookupError::Dht(_))
+    }
+}
+
+impl From<serde_json::Error> for LookupError {
+    fn from(e: serde_json::Error) -> Self {
+        LookupError::InvalidRecord(e.to_string())
+    }
+}
+
+/// Errors scoping files to tenant namespaces
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NamespaceError {
+    #[error("Invalid namespace {0:?}")]
+    Invalid(String),
+
+    #[error("Not authorized for namespace {0:?}")]
+    Forbidden(String),
+
+    #[error("Quota exceeded for namespace {namespace:?}: {reason}")]
+    QuotaExceeded { namespace: String, reason: String },
+}
+
+impl ErrorCode for NamespaceError {
+    fn code(&self) -> u32 {
+        match self {
+            NamespaceError::Invalid(_) => codes::NAMESPACE_INVALID,
+            NamespaceError::Forbidden(_) => codes::NAMESPACE_FORBIDDEN,
+            NamespaceError::QuotaExceeded { .. } => codes::NAMESPACE_QUOTA_EXCEEDED,
+        }
+    }
+}
+
+/// Compute tasks turned away by admission control
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AdmissionError {
+    #[error("Task needs {required_mb} MB but this node can reserve at most {capacity_mb} MB")]
+    InsufficientMemory { required_mb: u64, capacity_mb: u64 },
+
+    #[error("Compute queue is full ({0} task(s) waiting)")]
+    QueueFull(usize),
+
+    #[error("No compute capacity freed up within {0}ms")]
+    QueueTimeout(u64),
+}
+
+impl ErrorCode for AdmissionError {
+    fn code(&self) -> u32 {
+        match self {
+            AdmissionError::InsufficientMemory { .. } => codes::COMPUTE_INSUFFICIENT_MEMORY,
+            AdmissionError::QueueFull(_) => codes::COMPUTE_QUEUE_FULL,
+            AdmissionError::QueueTimeout(_) => codes::COMPUTE_QUEUE_TIMEOUT,
+        }
+    }
+
+    /// A busy node may have room later; a task larger than the node never fits
+    fn is_transient(&self) -> bool {
+        !matches!(self, AdmissionError::InsufficientMemory { .. })
+    }
+}
+
+/// Errors from automated uploads
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("File not found: {0:?}")]
+    FileNotFound(PathBuf),
+
+    #[error("Path is not a file: {0:?}")]
+    NotAFile(PathBuf),
+
+    #[error("No available peers found. Start at least one other node.")]
+    NoPeers,
+
+    #[error("No available peers in storage class {0:?}")]
+    NoClassPeers(String),
+
+    #[error("No upload session {0:?} to resume")]
+    SessionNotFound(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(#[from] serde_json::Error),
+
+    #[error("Upload failed")]
+    Transfer(#[source] anyhow::Error),
+
+    #[error(transparent)]
+    Lookup(#[from] LookupError),
+
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+
+    #[error(transparent)]
+    Namespace(#[from] NamespaceError),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ErrorCode for UploadError {
+    fn code(&self) -> u32 {
+        match self {
+            UploadError::FileNotFound(_) => codes::UPLOAD_FILE_NOT_FOUND,
+            UploadError::NotAFile(_) => codes::UPLOAD_NOT_A_FILE,
+            UploadError::NoPeers => codes::UPLOAD_NO_PEERS,
+            UploadError::NoClassPeers(_) => codes::UPLOAD_NO_CLASS_PEERS,
+            UploadError::SessionNotFound(_) => codes::UPLOAD_SESSION_NOT_FOUND,
+            UploadError::Io(_) => codes::UPLOAD_IO,
+            UploadError::InvalidManifest(_) => codes::UPLOAD_INVALID_MANIFEST,
+            UploadError::Transfer(e) => cause_code(e).unwrap_or(codes::UPLOAD_TRANSFER),
+            UploadError::Lookup(e) => e.code(),
+            UploadError::Network(e) => e.code(),
+            UploadError::Namespace(e) => e.code(),
+            UploadError::Other(e) => cause_code(e).unwrap_or(codes::UPLOAD_OTHER),
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            UploadError::NoPeers | UploadError::NoClassPeers(_) => true,
+            UploadError::Transfer(e) | UploadError::Other(e) => network_transient(e),
+            UploadError::Lookup(e) => e.is_transient(),
+            UploadError::Network(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Errors from automated downloads
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("File not found in cache or DHT: {0}")]
+    FileNotFound(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Download failed")]
+    Transfer(#[source] anyhow::Error),
+
+    #[error("Failed to unwrap file key")]
+    KeyUnwrap(#[source] anyhow::Error),
+
+    #[error(transparent)]
+    Lookup(#[from] LookupError),
+
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+
+    #[error(transparent)]
+    Namespace(#[from] NamespaceError),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl ErrorCode for DownloadError {
+    fn code(&self) -> u32 {
+        match self {
+            DownloadError::FileNotFound(_) => codes::DOWNLOAD_FILE_NOT_FOUND,
+            DownloadError::Io(_) => codes::DOWNLOAD_IO,
+            DownloadError::Transfer(e) => cause_code(e).unwrap_or(codes::DOWNLOAD_TRANSFER),
+            DownloadError::KeyUnwrap(_) => codes::DOWNLOAD_KEY_UNWRAP,
+            DownloadError::Lookup(e) => e.code(),
+            DownloadError::Network(e) => e.code(),
+            DownloadError::Namespace(e) => e.code(),
+            DownloadError::Other(e) => cause_code(e).unwrap_or(codes::DOWNLOAD_OTHER),
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            DownloadError::Transfer(e) | DownloadError::Other(e) => network_transient(e),
+            DownloadError::Lookup(e) => e.is_transient(),
+            DownloadError::Network(e) => e.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Code of a `NetworkError` or `NamespaceError` buried in an anyhow context chain
+fn cause_code(err: &anyhow::Error) -> Option<u32> {
+    err.chain().find_map(|cause| {
+        cause
+            .downcast_ref::<NetworkError>()
+            .map(|e| e.code())
+            .or_else(|| cause.downcast_ref::<NamespaceError>().map(|e| e.code()))
+    })
+}
+
+/// Whether an anyhow error wraps a transient `NetworkError`
+pub fn network_transient(err: &anyhow::Error) -> bool {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<NetworkError>())
+        .is_some_and(|e| e.is_transient())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_codes_are_stable() {
+        assert_eq!(NetworkError::NotConnected.code(), 1001);
+        assert_eq!(UploadError::NoPeers.code(), 3003);
+        assert_eq!(DownloadError::FileNotFound("x".into()).code(), 4001);
+        assert_eq!(codes::name(codes::UPLOAD_NO_PEERS), "UPLOAD_NO_PEERS");
+        assert_eq!(codes::name(12345), "UNKNOWN");
+    }
+
+    #[test]
+    fn test_wrapped_errors_report_root_code() {
+        let err: DownloadError = LookupError::InvalidRecord("bad".into()).into();
+        assert_eq!(err.code(), codes::LOOKUP_INVALID_RECORD);
+
+        let transfer = Err::<(), _>(NetworkError::NotConnected)
+            .context("Failed to fetch shard 3")
+            .unwrap_err();
+        let err = UploadError::Transfer(transfer);
+        assert_eq!(err.code(), codes::NETWORK_NOT_CONNECTED);
+
+        let quota = Err::<(), _>(NamespaceError::QuotaExceeded {
+            namespace: "photos".into(),
+            reason: "file limit of 1 reached".into(),
+        })
+        .context("Failed to cache manifest")
+        .unwrap_err();
+        let err = UploadError::Transfer(quota);
+        assert_eq!(err.code(), codes::NAMESPACE_QUOTA_EXCEEDED);
+    }
+
+    #[test]
+    fn test_report_preserves_context_chain() {
+        let inner = Err::<(), _>(NetworkError::PeerUnreachable(7))
+            .context("Failed to fetch shard 0")
+            .unwrap_err();
+        let report = DownloadError::Transfer(inner).report();
+
+        assert_eq!(report.code, codes::NETWORK_PEER_UNREACHABLE);
+        assert_eq!(report.message, "Download failed");
+        assert_eq!(
+            report.chain,
+            vec!["Failed to fetch shard 0", "Peer 7 unreachable"]
+        );
+    }
+}
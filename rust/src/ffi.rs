@@ -1,11 +1,14 @@
 use rand::RngCore;
 /// FFI layer for Go ↔ Rust interop
 /// Exposes CES pipeline functions as C-compatible API
+use std::cell::Cell;
 use std::ffi::CString;
 use std::os::raw::{c_char, c_int, c_uchar};
 use std::slice;
 
 use crate::ces::CesPipeline;
+use crate::error::codes;
+use crate::shm::{ShardRing, ShmSlot};
 use crate::types::CesConfig;
 
 /// FFI Result structure
@@ -31,6 +34,32 @@ pub struct FFIShards {
     pub count: usize,
 }
 
+/// Shard written into a shared ring: `len` bytes at `ring base + offset`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FFIShmShard {
+    pub offset: usize,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR_CODE: Cell<u32> = const { Cell::new(codes::OK) };
+}
+
+fn set_last_error_code(code: u32) {
+    LAST_ERROR_CODE.with(|c| c.set(code));
+}
+
+/// Error code of the last FFI call on this thread (0 = success)
+///
+/// Codes are stable across releases; see `error::codes` for the full list.
+/// Read it immediately after the failing call from the same OS thread (Go
+/// callers should hold `runtime.LockOSThread` across both calls).
+#[no_mangle]
+pub extern "C" fn ces_last_error_code() -> u32 {
+    LAST_ERROR_CODE.with(|c| c.get())
+}
+
 /// Create a new CES pipeline instance with key from environment or random key
 /// Returns an opaque handle that must be freed with ces_free()
 ///
@@ -122,6 +151,52 @@ pub extern "C" fn ces_new_with_key(
     Box::into_raw(Box::new(pipeline))
 }
 
+/// Derive a 32-byte CES key from a passphrase with Argon2id
+/// Returns 0 on success and -1 on failure (see ces_last_error_code())
+///
+/// Produces the same key as the Rust node for the salt and cost parameters
+/// stored in a file manifest, so Go can decrypt passphrase-protected files.
+///
+/// # Safety
+/// `passphrase` and `salt` must point to `passphrase_len` and `salt_len`
+/// readable bytes, and `out_key` must point to 32 writable bytes.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn ces_derive_key(
+    passphrase: *const c_uchar,
+    passphrase_len: usize,
+    salt: *const c_uchar,
+    salt_len: usize,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+    out_key: *mut c_uchar,
+) -> c_int {
+    if passphrase.is_null() || salt.is_null() || out_key.is_null() {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
+        return -1;
+    }
+
+    let passphrase = unsafe { slice::from_raw_parts(passphrase, passphrase_len) };
+    let salt = unsafe { slice::from_raw_parts(salt, salt_len) };
+
+    match crate::kdf::derive_key(passphrase, salt, memory_kib, iterations, parallelism) {
+        Ok(key) => {
+            unsafe {
+                std::ptr::copy_nonoverlapping(key.as_ptr(), out_key, key.len());
+            }
+            set_last_error_code(codes::OK);
+            0
+        }
+        Err(e) => {
+            eprintln!("ERROR: ces_derive_key failed: {:#}", e);
+            set_last_error_code(codes::FFI_KEY_DERIVATION_FAILED);
+            -1
+        }
+    }
+}
+
 /// Free a CES pipeline instance
 ///
 /// # Safety
@@ -150,6 +225,7 @@ pub extern "C" fn ces_process(
 ) -> FFIShards {
     // Safety checks
     if pipeline.is_null() || data.is_null() {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
         return FFIShards {
             shards: std::ptr::null_mut(),
             count: 0,
@@ -163,6 +239,7 @@ pub extern "C" fn ces_process(
         // Process through CES pipeline
         match pipeline.process(input) {
             Ok(shards) => {
+                set_last_error_code(codes::OK);
                 let count = shards.len();
                 let mut ffi_shards = Vec::with_capacity(count);
 
@@ -178,10 +255,13 @@ pub extern "C" fn ces_process(
                     count,
                 }
             }
-            Err(_) => FFIShards {
-                shards: std::ptr::null_mut(),
-                count: 0,
-            },
+            Err(_) => {
+                set_last_error_code(codes::FFI_PROCESS_FAILED);
+                FFIShards {
+                    shards: std::ptr::null_mut(),
+                    count: 0,
+                }
+            }
         }
     }
 }
@@ -201,6 +281,7 @@ pub extern "C" fn ces_reconstruct(
 ) -> FFIResult {
     // Safety checks
     if pipeline.is_null() || shards.is_null() || shard_present.is_null() {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
         return FFIResult {
             success: false,
             error_msg: create_error_string("Invalid parameters"),
@@ -228,6 +309,7 @@ pub extern "C" fn ces_reconstruct(
         // Reconstruct
         match pipeline.reconstruct(rust_shards) {
             Ok(data) => {
+                set_last_error_code(codes::OK);
                 let len = data.len();
                 let data_ptr = Box::into_raw(data.into_boxed_slice()) as *mut c_uchar;
                 FFIResult {
@@ -237,12 +319,15 @@ pub extern "C" fn ces_reconstruct(
                     data_len: len,
                 }
             }
-            Err(e) => FFIResult {
-                success: false,
-                error_msg: create_error_string(&e.to_string()),
-                data: std::ptr::null_mut(),
-                data_len: 0,
-            },
+            Err(e) => {
+                set_last_error_code(codes::FFI_RECONSTRUCT_FAILED);
+                FFIResult {
+                    success: false,
+                    error_msg: create_error_string(&format!("{:#}", e)),
+                    data: std::ptr::null_mut(),
+                    data_len: 0,
+                }
+            }
         }
     }
 }
@@ -282,6 +367,145 @@ pub extern "C" fn ces_free_shards(shards: FFIShards) {
     }
 }
 
+/// Create a shared-memory shard ring of `capacity` bytes (0 = default size)
+/// Returns an opaque handle that must be freed with ces_shm_ring_free(), or
+/// null if the platform has no shared rings (FFI_SHM_UNSUPPORTED); callers
+/// then keep using ces_process()
+#[no_mangle]
+pub extern "C" fn ces_shm_ring_new(capacity: usize) -> *mut ShardRing {
+    let capacity = if capacity == 0 {
+        crate::shm::DEFAULT_RING_CAPACITY
+    } else {
+        capacity
+    };
+    match ShardRing::new(capacity) {
+        Ok(ring) => {
+            set_last_error_code(codes::OK);
+            Box::into_raw(Box::new(ring))
+        }
+        Err(e) => {
+            eprintln!("WARNING: shared shard ring unavailable: {:#}", e);
+            set_last_error_code(codes::FFI_SHM_UNSUPPORTED);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Start of the ring mapping; shard descriptors are offsets from here
+///
+/// # Safety
+/// This function dereferences raw pointers and must only be called with valid pointers
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ces_shm_ring_base(ring: *const ShardRing) -> *const c_uchar {
+    if ring.is_null() {
+        return std::ptr::null();
+    }
+    unsafe { (*ring).base_ptr() }
+}
+
+/// memfd backing the ring (-1 for a null ring), for mapping it elsewhere
+///
+/// # Safety
+/// This function dereferences raw pointers and must only be called with valid pointers
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ces_shm_ring_fd(ring: *const ShardRing) -> c_int {
+    if ring.is_null() {
+        return -1;
+    }
+    unsafe { (*ring).fd() }
+}
+
+/// Free a shared ring; every descriptor into it becomes invalid
+///
+/// # Safety
+/// This function dereferences raw pointers and must only be called with valid pointers
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ces_shm_ring_free(ring: *mut ShardRing) {
+    if !ring.is_null() {
+        unsafe {
+            drop(Box::from_raw(ring));
+        }
+    }
+}
+
+/// Process data through the CES pipeline, writing the shards into `ring`
+/// Returns the shard count and fills `out` with one descriptor per shard, or
+/// -1 on failure (see ces_last_error_code()). FFI_SHM_FULL means the ring had
+/// no room; nothing was written, so fall back to ces_process().
+/// Release each descriptor with ces_shm_release() once the shard is sent.
+///
+/// # Safety
+/// `out` must point to `out_capacity` writable descriptors, and the other
+/// pointers must be valid
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ces_process_shm(
+    pipeline: *const CesPipeline,
+    ring: *const ShardRing,
+    data: *const c_uchar,
+    data_len: usize,
+    out: *mut FFIShmShard,
+    out_capacity: usize,
+) -> c_int {
+    if pipeline.is_null() || ring.is_null() || data.is_null() || out.is_null() {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
+        return -1;
+    }
+
+    let (pipeline, ring) = unsafe { (&*pipeline, &*ring) };
+    let input = unsafe { slice::from_raw_parts(data, data_len) };
+    let shards = match pipeline.process(input) {
+        Ok(shards) => shards,
+        Err(_) => {
+            set_last_error_code(codes::FFI_PROCESS_FAILED);
+            return -1;
+        }
+    };
+    if shards.len() > out_capacity {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
+        return -1;
+    }
+    let Some(slots) = ring.write_all(&shards) else {
+        set_last_error_code(codes::FFI_SHM_FULL);
+        return -1;
+    };
+
+    let out = unsafe { slice::from_raw_parts_mut(out, slots.len()) };
+    for (descriptor, slot) in out.iter_mut().zip(&slots) {
+        *descriptor = FFIShmShard {
+            offset: slot.offset,
+            len: slot.len,
+        };
+    }
+    set_last_error_code(codes::OK);
+    slots.len() as c_int
+}
+
+/// Hand a shard's ring space back once it has been sent
+/// Returns 0, or -1 if the descriptor was not live (FFI_INVALID_ARGUMENT)
+///
+/// # Safety
+/// This function dereferences raw pointers and must only be called with valid pointers
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn ces_shm_release(ring: *const ShardRing, shard: FFIShmShard) -> c_int {
+    let released = !ring.is_null()
+        && unsafe { &*ring }.release(ShmSlot {
+            offset: shard.offset,
+            len: shard.len,
+        });
+    if released {
+        set_last_error_code(codes::OK);
+        0
+    } else {
+        set_last_error_code(codes::FFI_INVALID_ARGUMENT);
+        -1
+    }
+}
+
 /// Helper to create error strings for FFI
 fn create_error_string(msg: &str) -> *mut c_char {
     match CString::new(msg) {
@@ -320,6 +544,7 @@ mod tests {
 
         assert!(result.success);
         assert!(!result.data.is_null());
+        assert_eq!(ces_last_error_code(), codes::OK);
 
         // Verify data
         unsafe {
@@ -408,4 +633,120 @@ mod tests {
         }
         ces_free(pipeline2);
     }
+
+    #[test]
+    fn test_ffi_invalid_parameters_set_error_code() {
+        let result = ces_reconstruct(std::ptr::null(), std::ptr::null(), 0, std::ptr::null());
+        assert!(!result.success);
+        assert_eq!(ces_last_error_code(), codes::FFI_INVALID_ARGUMENT);
+        ces_free_result(result);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_ffi_shm_process_matches_copying_path() {
+        let key = [0x24u8; 32];
+        let pipeline = ces_new_with_key(3, key.as_ptr());
+        let ring = ces_shm_ring_new(1024 * 1024);
+        assert!(!ring.is_null());
+        assert!(ces_shm_ring_fd(ring) >= 0);
+
+        let test_data = b"Shards handed to Go through shared memory instead of copies.";
+        let mut descriptors = vec![FFIShmShard { offset: 0, len: 0 }; 32];
+        let count = ces_process_shm(
+            pipeline,
+            ring,
+            test_data.as_ptr(),
+            test_data.len(),
+            descriptors.as_mut_ptr(),
+            descriptors.len(),
+        );
+        assert!(count > 0);
+        assert_eq!(ces_last_error_code(), codes::OK);
+
+        // Reconstruct straight from the ring, as Go would
+        let base = ces_shm_ring_base(ring);
+        let ffi_shards: Vec<FFIShard> = descriptors[..count as usize]
+            .iter()
+            .map(|d| FFIShard {
+                data: unsafe { base.add(d.offset) } as *mut c_uchar,
+                len: d.len,
+            })
+            .collect();
+        let present = vec![1i32; ffi_shards.len()];
+        let result = ces_reconstruct(
+            pipeline,
+            ffi_shards.as_ptr(),
+            ffi_shards.len(),
+            present.as_ptr(),
+        );
+        assert!(result.success);
+        unsafe {
+            assert_eq!(
+                slice::from_raw_parts(result.data, result.data_len),
+                test_data
+            );
+        }
+        ces_free_result(result);
+
+        for descriptor in &descriptors[..count as usize] {
+            assert_eq!(ces_shm_release(ring, *descriptor), 0);
+        }
+        assert_eq!(ces_shm_release(ring, descriptors[0]), -1);
+
+        // A ring too small for the shards reports FFI_SHM_FULL
+        let tiny = ces_shm_ring_new(16);
+        let count = ces_process_shm(
+            pipeline,
+            tiny,
+            test_data.as_ptr(),
+            test_data.len(),
+            descriptors.as_mut_ptr(),
+            descriptors.len(),
+        );
+        assert_eq!(count, -1);
+        assert_eq!(ces_last_error_code(), codes::FFI_SHM_FULL);
+
+        ces_shm_ring_free(tiny);
+        ces_shm_ring_free(ring);
+        ces_free(pipeline);
+    }
+
+    #[test]
+    fn test_ffi_derive_key_matches_kdf() {
+        let params = crate::kdf::KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            ..crate::kdf::KdfParams::generate()
+        };
+        let salt = hex::decode(&params.salt).unwrap();
+        let passphrase = b"shared secret";
+
+        let mut key = [0u8; 32];
+        let rc = ces_derive_key(
+            passphrase.as_ptr(),
+            passphrase.len(),
+            salt.as_ptr(),
+            salt.len(),
+            params.memory_kib,
+            params.iterations,
+            params.parallelism,
+            key.as_mut_ptr(),
+        );
+        assert_eq!(rc, 0);
+        assert_eq!(key, params.derive_key(passphrase).unwrap());
+
+        let rc = ces_derive_key(
+            passphrase.as_ptr(),
+            passphrase.len(),
+            salt.as_ptr(),
+            2, // below Argon2's minimum salt length
+            64,
+            1,
+            1,
+            key.as_mut_ptr(),
+        );
+        assert_eq!(rc, -1);
+        assert_eq!(ces_last_error_code(), codes::FFI_KEY_DERIVATION_FAILED);
+    }
 }
@@ -1,5 +1,17 @@
 /// File type detection for optimal compression strategy
 /// Detects file types before CES pipeline to apply appropriate compression
+///
+/// Content signatures (magic bytes) are combined with filename extension
+/// hints; every detection carries the specific format and a confidence
+/// level. `CompressionPolicy` maps types or formats to compression levels
+/// and can be overridden in the `[compression]` section of the config file.
+///
+/// Data without a known signature may still be encrypted or random; a
+/// quick entropy estimate over a few sample windows catches it, and the
+/// policy's `max_entropy_bits` decides when to skip compression.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 
 /// Detected file type
@@ -53,8 +65,150 @@ impl FileType {
             FileType::Unknown => "Unknown",
         }
     }
+
+    /// Key used for this type in compression policies
+    pub fn policy_key(&self) -> &'static str {
+        match self {
+            FileType::Compressed => "compressed",
+            FileType::Image => "image",
+            FileType::Video => "video",
+            FileType::Audio => "audio",
+            FileType::Text => "text",
+            FileType::Binary => "binary",
+            FileType::Unknown => "unknown",
+        }
+    }
+}
+
+/// How sure a detection is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// Heuristic guess (e.g. printable-bytes ratio) or nothing matched
+    Low,
+    /// Filename extension only
+    Medium,
+    /// Matching content signature
+    High,
+}
+
+/// Result of file type detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub file_type: FileType,
+    /// Specific format, e.g. "sqlite" or "zstd" ("unknown" if none)
+    pub format: &'static str,
+    pub confidence: Confidence,
 }
 
+impl Detection {
+    fn new(file_type: FileType, format: &'static str, confidence: Confidence) -> Self {
+        Self {
+            file_type,
+            format,
+            confidence,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self::new(FileType::Unknown, "unknown", Confidence::Low)
+    }
+}
+
+/// Magic signature: bytes expected at an offset
+struct Signature {
+    offset: usize,
+    magic: &'static [u8],
+    file_type: FileType,
+    format: &'static str,
+}
+
+const fn sig(
+    offset: usize,
+    magic: &'static [u8],
+    file_type: FileType,
+    format: &'static str,
+) -> Signature {
+    Signature {
+        offset,
+        magic,
+        file_type,
+        format,
+    }
+}
+
+/// Signatures checked in order; more specific entries come first
+const SIGNATURES: &[Signature] = &[
+    // Compressed containers
+    sig(0, &[0x50, 0x4B, 0x03, 0x04], FileType::Compressed, "zip"),
+    sig(0, &[0x1F, 0x8B], FileType::Compressed, "gzip"),
+    sig(0, &[0x28, 0xB5, 0x2F, 0xFD], FileType::Compressed, "zstd"),
+    sig(
+        0,
+        &[0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00],
+        FileType::Compressed,
+        "xz",
+    ),
+    sig(0, b"BZh", FileType::Compressed, "bzip2"),
+    sig(
+        0,
+        &[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C],
+        FileType::Compressed,
+        "7z",
+    ),
+    sig(0, b"Rar!\x1A\x07", FileType::Compressed, "rar"),
+    sig(0, &[0x04, 0x22, 0x4D, 0x18], FileType::Compressed, "lz4"),
+    // Parquet pages are already compressed
+    sig(0, b"PAR1", FileType::Compressed, "parquet"),
+    // Uncompressed containers and databases compress well
+    sig(257, b"ustar", FileType::Binary, "tar"),
+    sig(0, b"SQLite format 3\0", FileType::Binary, "sqlite"),
+    sig(
+        0,
+        &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1],
+        FileType::Binary,
+        "ole2",
+    ),
+    sig(0, b"%PDF", FileType::Binary, "pdf"),
+    sig(0, b"\0asm", FileType::Binary, "wasm"),
+    sig(0, &[0x7F, 0x45, 0x4C, 0x46], FileType::Binary, "elf"),
+    sig(0, &[0x4D, 0x5A], FileType::Binary, "pe"),
+    // Images
+    sig(0, &[0x89, 0x50, 0x4E, 0x47], FileType::Image, "png"),
+    sig(0, &[0xFF, 0xD8, 0xFF], FileType::Image, "jpeg"),
+    sig(0, &[0x47, 0x49, 0x46, 0x38], FileType::Image, "gif"),
+    sig(8, b"WEBP", FileType::Image, "webp"),
+    // Audio and video
+    sig(0, &[0x66, 0x4C, 0x61, 0x43], FileType::Audio, "flac"),
+    sig(0, b"OggS", FileType::Audio, "ogg"),
+    sig(8, b"WAVE", FileType::Audio, "wav"),
+    sig(0, b"ID3", FileType::Audio, "mp3"),
+    sig(8, b"AVI ", FileType::Video, "avi"),
+    sig(0, &[0x1A, 0x45, 0xDF, 0xA3], FileType::Video, "matroska"),
+];
+
+/// ISO-BMFF brands (`ftyp` box) that hold still images rather than video
+const IMAGE_BRANDS: &[(&[u8; 4], &str)] = &[
+    (b"heic", "heic"),
+    (b"heix", "heic"),
+    (b"mif1", "heif"),
+    (b"msf1", "heif"),
+    (b"avif", "avif"),
+    (b"avis", "avif"),
+];
+
+/// Extensions of Office Open XML documents (zip containers)
+const OFFICE_EXTENSIONS: &[&str] = &["docx", "xlsx", "pptx", "odt", "ods", "odp"];
+
+/// Bytes per window sampled for the entropy estimate
+const ENTROPY_WINDOW_BYTES: usize = 4096;
+
+/// Smaller data says too little about its entropy to skip compression
+pub const MIN_ENTROPY_SAMPLE_BYTES: usize = 1024;
+
+/// Default entropy (bits per byte) above which compression is skipped;
+/// text is around 4-5, executables around 6, ciphertext just under 8
+pub const DEFAULT_MAX_ENTROPY_BITS: f64 = 7.5;
+
 /// File type detector
 pub struct FileDetector;
 
@@ -70,128 +224,106 @@ impl FileDetector {
 
     /// Detect file type from extension string
     pub fn detect_from_extension(ext: &str) -> FileType {
-        let ext_lower = ext.to_lowercase();
+        Self::extension_hint(ext).file_type
+    }
 
-        // Compressed archives
-        if matches!(
-            ext_lower.as_str(),
-            "zip" | "gz" | "bz2" | "xz" | "7z" | "tar" | "rar" | "zst" | "lz4"
-        ) {
-            return FileType::Compressed;
-        }
+    /// Type, format and confidence implied by an extension
+    pub fn extension_hint(ext: &str) -> Detection {
+        let ext_lower = ext.to_lowercase();
+        let (file_type, format) = match ext_lower.as_str() {
+            // Compressed archives and already-compressed containers
+            "zip" => (FileType::Compressed, "zip"),
+            "gz" | "tgz" => (FileType::Compressed, "gzip"),
+            "bz2" => (FileType::Compressed, "bzip2"),
+            "xz" | "txz" => (FileType::Compressed, "xz"),
+            "7z" => (FileType::Compressed, "7z"),
+            "rar" => (FileType::Compressed, "rar"),
+            "zst" | "tzst" => (FileType::Compressed, "zstd"),
+            "lz4" => (FileType::Compressed, "lz4"),
+            "parquet" => (FileType::Compressed, "parquet"),
+            "docx" | "xlsx" | "pptx" | "odt" | "ods" | "odp" => {
+                (FileType::Compressed, "office-openxml")
+            }
 
-        // Images
-        if matches!(
-            ext_lower.as_str(),
-            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "webp" | "svg" | "ico"
-        ) {
-            return FileType::Image;
-        }
+            // Images
+            "jpg" | "jpeg" => (FileType::Image, "jpeg"),
+            "png" => (FileType::Image, "png"),
+            "gif" => (FileType::Image, "gif"),
+            "webp" => (FileType::Image, "webp"),
+            "heic" | "heif" => (FileType::Image, "heic"),
+            "avif" => (FileType::Image, "avif"),
+            "bmp" | "tiff" | "svg" | "ico" => (FileType::Image, "image"),
 
-        // Video
-        if matches!(
-            ext_lower.as_str(),
-            "mp4" | "avi" | "mkv" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "mpg" | "mpeg"
-        ) {
-            return FileType::Video;
-        }
+            // Video
+            "mkv" | "webm" => (FileType::Video, "matroska"),
+            "avi" => (FileType::Video, "avi"),
+            "mp4" | "mov" | "wmv" | "flv" | "m4v" | "mpg" | "mpeg" => (FileType::Video, "video"),
 
-        // Audio
-        if matches!(
-            ext_lower.as_str(),
-            "mp3" | "wav" | "flac" | "aac" | "ogg" | "wma" | "m4a" | "opus"
-        ) {
-            return FileType::Audio;
-        }
+            // Audio
+            "mp3" => (FileType::Audio, "mp3"),
+            "flac" => (FileType::Audio, "flac"),
+            "wav" => (FileType::Audio, "wav"),
+            "ogg" | "opus" => (FileType::Audio, "ogg"),
+            "aac" | "wma" | "m4a" => (FileType::Audio, "audio"),
 
-        // Text
-        if matches!(
-            ext_lower.as_str(),
-            "txt"
-                | "log"
-                | "json"
-                | "xml"
-                | "yaml"
-                | "yml"
-                | "toml"
-                | "ini"
-                | "md"
-                | "rst"
-                | "csv"
-                | "tsv"
-                | "html"
-                | "htm"
-                | "css"
-                | "js"
-                | "py"
-                | "rs"
-                | "go"
-                | "c"
-                | "cpp"
-                | "h"
-                | "java"
-                | "sh"
-                | "bash"
-        ) {
-            return FileType::Text;
-        }
+            // Text
+            "txt" | "log" | "json" | "xml" | "yaml" | "yml" | "toml" | "ini" | "md" | "rst"
+            | "csv" | "tsv" | "html" | "htm" | "css" | "js" | "py" | "rs" | "go" | "c" | "cpp"
+            | "h" | "java" | "sh" | "bash" => (FileType::Text, "text"),
 
-        // Binary
-        if matches!(
-            ext_lower.as_str(),
-            "exe" | "dll" | "so" | "dylib" | "o" | "a" | "lib" | "bin" | "dat"
-        ) {
-            return FileType::Binary;
-        }
+            // Binary, databases and uncompressed containers
+            "tar" => (FileType::Binary, "tar"),
+            "sqlite" | "sqlite3" | "db" => (FileType::Binary, "sqlite"),
+            "doc" | "xls" | "ppt" => (FileType::Binary, "ole2"),
+            "pdf" => (FileType::Binary, "pdf"),
+            "wasm" => (FileType::Binary, "wasm"),
+            "exe" | "dll" | "so" | "dylib" | "o" | "a" | "lib" | "bin" | "dat" => {
+                (FileType::Binary, "binary")
+            }
 
-        FileType::Unknown
+            _ => return Detection::unknown(),
+        };
+        Detection::new(file_type, format, Confidence::Medium)
     }
 
     /// Detect file type from magic bytes (file signature)
     pub fn detect_from_content(data: &[u8]) -> FileType {
+        Self::content_hint(data).file_type
+    }
+
+    /// Type, format and confidence implied by the content alone
+    pub fn content_hint(data: &[u8]) -> Detection {
         if data.len() < 4 {
-            return FileType::Unknown;
+            return Detection::unknown();
         }
 
-        // Check common magic bytes
-        match &data[0..4] {
-            // ZIP: 50 4B 03 04
-            [0x50, 0x4B, 0x03, 0x04] => FileType::Compressed,
-            // GZIP: 1F 8B
-            [0x1F, 0x8B, ..] => FileType::Compressed,
-            // PNG: 89 50 4E 47
-            [0x89, 0x50, 0x4E, 0x47] => FileType::Image,
-            // JPEG: FF D8 FF
-            [0xFF, 0xD8, 0xFF, _] => FileType::Image,
-            // GIF: 47 49 46 38
-            [0x47, 0x49, 0x46, 0x38] => FileType::Image,
-            // MP4/M4V: various, check 'ftyp'
-            _ if data.len() >= 12 && &data[4..8] == b"ftyp" => FileType::Video,
-            // AVI: 52 49 46 46 ... 41 56 49 20
-            [0x52, 0x49, 0x46, 0x46] if data.len() >= 12 && &data[8..12] == b"AVI " => {
-                FileType::Video
-            }
-            // MP3: FF Fx where x is in range (MPEG audio frame sync)
-            // Common values: FF FB (layer 3), FF F3 (layer 3), FF F2 (layer 3)
-            [0xFF, b, ..] if *b >= 0xF0 && (*b & 0xE0) == 0xE0 => FileType::Audio,
-            // FLAC: 66 4C 61 43
-            [0x66, 0x4C, 0x61, 0x43] => FileType::Audio,
-            // WAV: 52 49 46 46 ... 57 41 56 45
-            [0x52, 0x49, 0x46, 0x46] if data.len() >= 12 && &data[8..12] == b"WAVE" => {
-                FileType::Audio
-            }
-            // ELF executable: 7F 45 4C 46
-            [0x7F, 0x45, 0x4C, 0x46] => FileType::Binary,
-            // Windows PE: 4D 5A
-            [0x4D, 0x5A, ..] => FileType::Binary,
-            // Check if looks like text (all printable ASCII)
-            _ => {
-                if Self::is_likely_text(data) {
-                    FileType::Text
-                } else {
-                    FileType::Unknown
-                }
-            }
+        // ISO-BMFF (MP4, MOV, HEIC, AVIF): 'ftyp' box, brand decides the type
+        if data.len() >= 12 && &data[4..8] == b"ftyp" {
+            let brand = &data[8..12];
+            return match IMAGE_BRANDS.iter().find(|(b, _)| b.as_slice() == brand) {
+                Some((_, format)) => Detection::new(FileType::Image, format, Confidence::High),
+                None => Detection::new(FileType::Video, "mp4", Confidence::High),
+            };
+        }
+
+        if let Some(signature) = SIGNATURES.iter().find(|s| {
+            data.len() >= s.offset + s.magic.len()
+                && &data[s.offset..s.offset + s.magic.len()] == s.magic
+        }) {
+            return Detection::new(signature.file_type, signature.format, Confidence::High);
+        }
+
+        // MP3: FF Fx where x is in range (MPEG audio frame sync)
+        // Common values: FF FB (layer 3), FF F3 (layer 3), FF F2 (layer 3)
+        if data[0] == 0xFF && (data[1] & 0xE0) == 0xE0 && data[1] >= 0xF0 {
+            return Detection::new(FileType::Audio, "mp3", Confidence::High);
+        }
+
+        // Check if looks like text (all printable ASCII)
+        if Self::is_likely_text(data) {
+            Detection::new(FileType::Text, "text", Confidence::Low)
+        } else {
+            Detection::unknown()
         }
     }
 
@@ -211,22 +343,164 @@ impl FileDetector {
         printable_count as f64 / sample_size as f64 > 0.9
     }
 
+    /// Shannon entropy of a sample of `data`, in bits per byte (0.0 - 8.0)
+    ///
+    /// Windows at the start, middle and end are counted together, so the
+    /// estimate costs the same however large the data is.
+    pub fn sample_entropy(data: &[u8]) -> f64 {
+        let windows: Vec<&[u8]> = if data.len() <= 3 * ENTROPY_WINDOW_BYTES {
+            vec![data]
+        } else {
+            let middle = (data.len() - ENTROPY_WINDOW_BYTES) / 2;
+            vec![
+                &data[..ENTROPY_WINDOW_BYTES],
+                &data[middle..middle + ENTROPY_WINDOW_BYTES],
+                &data[data.len() - ENTROPY_WINDOW_BYTES..],
+            ]
+        };
+        let mut counts = [0u64; 256];
+        for &byte in windows.iter().flat_map(|window| window.iter()) {
+            counts[byte as usize] += 1;
+        }
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
     /// Comprehensive detection using both path and content
     pub fn detect(path: &Path, data: &[u8]) -> FileType {
-        // First try extension
-        let from_path = Self::detect_from_path(path);
-        if from_path != FileType::Unknown {
-            // Verify with content if possible
-            let from_content = Self::detect_from_content(data);
-            if from_content != FileType::Unknown && from_content != from_path {
-                // If content detection disagrees, trust content over extension
-                return from_content;
+        Self::detect_with_confidence(Some(path), data).file_type
+    }
+
+    /// Combine the extension hint (if a path is known) with content signatures
+    ///
+    /// A content signature beats the extension, except that a zip signature
+    /// with an Office extension is reported as an Office document. When both
+    /// agree the detection is high confidence.
+    pub fn detect_with_confidence(path: Option<&Path>, data: &[u8]) -> Detection {
+        let from_content = Self::content_hint(data);
+        let ext = path
+            .and_then(|p| p.extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let from_path = match &ext {
+            Some(ext) => Self::extension_hint(ext),
+            None => return from_content,
+        };
+
+        if from_path.file_type == FileType::Unknown {
+            return from_content;
+        }
+        if from_content.format == "zip"
+            && ext
+                .as_deref()
+                .is_some_and(|e| OFFICE_EXTENSIONS.contains(&e))
+        {
+            return Detection::new(FileType::Compressed, "office-openxml", Confidence::High);
+        }
+        match from_content.confidence {
+            // Content signature wins over the extension
+            Confidence::High if from_content.file_type != from_path.file_type => from_content,
+            Confidence::High => Detection {
+                confidence: Confidence::High,
+                ..from_path
+            },
+            // Heuristics agree with the extension
+            _ if from_content.file_type == from_path.file_type => Detection {
+                confidence: Confidence::High,
+                ..from_path
+            },
+            _ => from_path,
+        }
+    }
+}
+
+/// Compression level per file type or format
+///
+/// Keys are format names ("sqlite", "parquet") or type keys ("text",
+/// "image"); a format entry takes precedence over its type. Types without an
+/// entry use `FileType::recommended_compression_level`, and a level of 0
+/// disables compression.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionPolicy {
+    pub levels: HashMap<String, i32>,
+    /// Data whose sampled entropy (bits per byte) is above this is stored
+    /// uncompressed whatever its type; 8.0 disables the check
+    pub max_entropy_bits: f64,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self {
+            levels: HashMap::new(),
+            max_entropy_bits: DEFAULT_MAX_ENTROPY_BITS,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    compression: CompressionPolicy,
+}
+
+impl CompressionPolicy {
+    /// Load the `[compression]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[compression]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: PolicyFile = toml::from_str(content)?;
+        file.compression.validate()?;
+        Ok(file.compression)
+    }
+
+    /// Validate configured levels
+    pub fn validate(&self) -> Result<()> {
+        for (key, level) in &self.levels {
+            if !(0..=22).contains(level) {
+                anyhow::bail!("compression level for {} must be between 0 and 22", key);
             }
-            return from_path;
         }
+        if !(0.0..=8.0).contains(&self.max_entropy_bits) {
+            anyhow::bail!("compression: max_entropy_bits must be between 0 and 8");
+        }
+        Ok(())
+    }
+
+    /// Override the level for a type key or format
+    pub fn with_level(mut self, key: &str, level: i32) -> Self {
+        self.levels.insert(key.to_lowercase(), level);
+        self
+    }
 
-        // Fall back to content detection
-        Self::detect_from_content(data)
+    /// Compression level for a detection (0 = don't compress)
+    pub fn level_for(&self, detection: &Detection) -> i32 {
+        if let Some(level) = self.levels.get(detection.format) {
+            return *level;
+        }
+        if let Some(level) = self.levels.get(detection.file_type.policy_key()) {
+            return *level;
+        }
+        if detection.file_type.skip_compression() {
+            0
+        } else {
+            detection.file_type.recommended_compression_level()
+        }
     }
 }
 
@@ -268,6 +542,104 @@ mod tests {
         assert_eq!(FileDetector::detect_from_content(text_data), FileType::Text);
     }
 
+    #[test]
+    fn test_expanded_signatures() {
+        let hint = |data: &[u8]| FileDetector::content_hint(data);
+
+        assert_eq!(hint(b"SQLite format 3\0rest").format, "sqlite");
+        assert_eq!(hint(b"PAR1\x15\x04").format, "parquet");
+        assert_eq!(hint(&[0x28, 0xB5, 0x2F, 0xFD, 0x00]).format, "zstd");
+        assert_eq!(hint(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]).format, "xz");
+
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(hint(&tar).format, "tar");
+        assert_eq!(hint(&tar).file_type, FileType::Binary);
+
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0";
+        assert_eq!(hint(heic).file_type, FileType::Image);
+        assert_eq!(hint(heic).format, "heic");
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0";
+        assert_eq!(hint(avif).format, "avif");
+        let mp4 = b"\0\0\0\x18ftypisom\0\0\0\0";
+        assert_eq!(hint(mp4).file_type, FileType::Video);
+        assert_eq!(hint(mp4).confidence, Confidence::High);
+    }
+
+    #[test]
+    fn test_extension_and_content_combined() {
+        let zip = [0x50, 0x4B, 0x03, 0x04, 0x14, 0x00];
+        let docx = FileDetector::detect_with_confidence(Some(Path::new("report.docx")), &zip);
+        assert_eq!(docx.format, "office-openxml");
+        assert_eq!(docx.confidence, Confidence::High);
+
+        // Extension only: medium confidence
+        let binary = [0x00, 0x01, 0x02, 0x03, 0x04];
+        let db = FileDetector::detect_with_confidence(Some(Path::new("app.db")), &binary);
+        assert_eq!(db.format, "sqlite");
+        assert_eq!(db.confidence, Confidence::Medium);
+
+        // Content signature overrides a misleading extension
+        let png = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A];
+        let renamed = FileDetector::detect_with_confidence(Some(Path::new("notes.txt")), &png);
+        assert_eq!(renamed.file_type, FileType::Image);
+
+        // Text heuristic plus matching extension
+        let text = FileDetector::detect_with_confidence(Some(Path::new("a.log")), b"hello log");
+        assert_eq!(text.confidence, Confidence::High);
+        let bare = FileDetector::detect_with_confidence(None, b"hello log");
+        assert_eq!(bare.confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn test_compression_policy() {
+        let policy = CompressionPolicy::from_toml(
+            r#"
+            [compression.levels]
+            text = 12
+            sqlite = 9
+            image = 0
+            "#,
+        )
+        .unwrap();
+
+        let sqlite = FileDetector::content_hint(b"SQLite format 3\0");
+        let pdf = FileDetector::content_hint(b"%PDF-1.7");
+        let png = FileDetector::content_hint(&[0x89, 0x50, 0x4E, 0x47]);
+        let text = FileDetector::content_hint(b"plain words here");
+        let zstd = FileDetector::content_hint(&[0x28, 0xB5, 0x2F, 0xFD]);
+        assert_eq!(policy.level_for(&sqlite), 9);
+        assert_eq!(policy.level_for(&pdf), 6);
+        assert_eq!(policy.level_for(&png), 0);
+        assert_eq!(policy.level_for(&text), 12);
+        assert_eq!(policy.level_for(&zstd), 0);
+
+        assert!(CompressionPolicy::from_toml("[compression.levels]\ntext = 40\n").is_err());
+        assert_eq!(
+            CompressionPolicy::from_toml("").unwrap(),
+            CompressionPolicy::default()
+        );
+    }
+
+    #[test]
+    fn test_sample_entropy() {
+        assert_eq!(FileDetector::sample_entropy(&[]), 0.0);
+        assert_eq!(FileDetector::sample_entropy(&[7u8; 100_000]), 0.0);
+
+        let text = b"The quick brown fox jumps over the lazy dog. ".repeat(1000);
+        let text_entropy = FileDetector::sample_entropy(&text);
+        assert!(text_entropy > 3.0 && text_entropy < 5.0, "{}", text_entropy);
+
+        let mut random = vec![0u8; 1 << 20];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut random);
+        assert!(FileDetector::sample_entropy(&random) > DEFAULT_MAX_ENTROPY_BITS);
+
+        let policy =
+            CompressionPolicy::from_toml("[compression]\nmax_entropy_bits = 7.9\n").unwrap();
+        assert_eq!(policy.max_entropy_bits, 7.9);
+        assert!(CompressionPolicy::from_toml("[compression]\nmax_entropy_bits = 9.0\n").is_err());
+    }
+
     #[test]
     fn test_compression_levels() {
         assert_eq!(FileType::Compressed.recommended_compression_level(), 0);
@@ -1,16 +1,90 @@
-use std::collections::HashSet;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, info};
 
 #[cfg(feature = "ebpf")]
 use tracing::warn;
 
-/// Firewall that filters connections based on IP allowlist
+/// Firewall that filters connections based on IP allowlist and bans
+///
+/// Decisions are made synchronously so they can be taken inside the QUIC
+/// accept loop and the libp2p connection gate.
 pub struct Firewall {
     allowed_ips: Arc<RwLock<HashSet<IpAddr>>>,
+    bans: Arc<RwLock<Bans>>,
     mode: FirewallMode,
+    policy: FirewallPolicy,
+    counters: RejectionCounters,
+}
+
+/// Which connections are admitted when no ban applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirewallPolicy {
+    /// Admit every address that is not banned
+    #[default]
+    Open,
+    /// Only admit allowlisted addresses
+    Allowlist,
+}
+
+/// Where a connection attempt was seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptPath {
+    Quic,
+    Libp2p,
+}
+
+/// Why a connection was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Rejection {
+    #[error("address {0} is not on the allowlist")]
+    NotAllowed(IpAddr),
+    #[error("address {0} is banned")]
+    BannedIp(IpAddr),
+    #[error("peer {0} is banned")]
+    BannedPeer(String),
+}
+
+/// Rejected connection attempts since start
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirewallStats {
+    pub quic_rejected: u64,
+    pub libp2p_rejected: u64,
+    /// Rejections because the address or peer was banned
+    pub banned_rejected: u64,
+    /// Rejections because the address was not allowlisted
+    pub not_allowed_rejected: u64,
+    pub bans_issued: u64,
+    pub active_bans: usize,
+}
+
+#[derive(Default)]
+struct RejectionCounters {
+    quic: AtomicU64,
+    libp2p: AtomicU64,
+    banned: AtomicU64,
+    not_allowed: AtomicU64,
+    bans_issued: AtomicU64,
+}
+
+/// Bans keyed by address or peer ID, with their expiry
+#[derive(Default)]
+struct Bans {
+    ips: HashMap<IpAddr, Instant>,
+    peers: HashMap<String, Instant>,
+}
+
+impl Bans {
+    fn prune(&mut self, now: Instant) {
+        self.ips.retain(|_, until| *until > now);
+        self.peers.retain(|_, until| *until > now);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -26,14 +100,22 @@ impl Firewall {
     pub fn new(mode: FirewallMode) -> Self {
         Self {
             allowed_ips: Arc::new(RwLock::new(HashSet::new())),
+            bans: Arc::new(RwLock::new(Bans::default())),
             mode,
+            policy: FirewallPolicy::default(),
+            counters: RejectionCounters::default(),
         }
     }
 
+    /// Choose which connections are admitted when no ban applies
+    pub fn with_policy(mut self, policy: FirewallPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     /// Add an IP to the allowlist
     pub async fn allow_ip(&self, ip: IpAddr) {
-        let mut allowed = self.allowed_ips.write().await;
-        allowed.insert(ip);
+        self.allowed_ips.write().insert(ip);
         info!("Added {} to firewall allowlist", ip);
 
         // If using eBPF, update the kernel map
@@ -45,8 +127,7 @@ impl Firewall {
 
     /// Remove an IP from the allowlist
     pub async fn block_ip(&self, ip: IpAddr) {
-        let mut allowed = self.allowed_ips.write().await;
-        allowed.remove(&ip);
+        self.allowed_ips.write().remove(&ip);
         info!("Removed {} from firewall allowlist", ip);
 
         // If using eBPF, update the kernel map
@@ -58,8 +139,112 @@ impl Firewall {
 
     /// Check if an IP is allowed (user-space filtering)
     pub async fn is_allowed(&self, ip: IpAddr) -> bool {
-        let allowed = self.allowed_ips.read().await;
-        allowed.contains(&ip)
+        self.allowed_ips.read().contains(&ip)
+    }
+
+    /// Refuse connections from `ip` for `duration`
+    ///
+    /// A longer ban already in place is kept.
+    pub fn ban_ip(&self, ip: IpAddr, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bans = self.bans.write();
+        let entry = bans.ips.entry(ip).or_insert(until);
+        *entry = (*entry).max(until);
+        self.counters.bans_issued.fetch_add(1, Ordering::Relaxed);
+        info!("Banned {} for {:?}", ip, duration);
+    }
+
+    /// Refuse connections from the peer with this ID for `duration`
+    pub fn ban_peer(&self, peer_id: &str, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bans = self.bans.write();
+        let entry = bans.peers.entry(peer_id.to_string()).or_insert(until);
+        *entry = (*entry).max(until);
+        self.counters.bans_issued.fetch_add(1, Ordering::Relaxed);
+        info!("Banned peer {} for {:?}", peer_id, duration);
+    }
+
+    /// Lift a ban on `ip` before it expires
+    pub fn unban_ip(&self, ip: IpAddr) {
+        self.bans.write().ips.remove(&ip);
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.bans
+            .read()
+            .ips
+            .get(&ip)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Decide whether a connection from `ip` may proceed, without counting it
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), Rejection> {
+        if self.is_banned(ip) {
+            return Err(Rejection::BannedIp(ip));
+        }
+        if self.policy == FirewallPolicy::Allowlist && !self.allowed_ips.read().contains(&ip) {
+            return Err(Rejection::NotAllowed(ip));
+        }
+        Ok(())
+    }
+
+    /// Decide whether a connection from a peer may proceed, without counting it
+    pub fn check_peer(&self, peer_id: &str) -> Result<(), Rejection> {
+        let banned = self
+            .bans
+            .read()
+            .peers
+            .get(peer_id)
+            .is_some_and(|until| *until > Instant::now());
+        if banned {
+            Err(Rejection::BannedPeer(peer_id.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Count a refused connection attempt
+    pub fn record_rejection(&self, path: AcceptPath, rejection: &Rejection) {
+        let counter = match path {
+            AcceptPath::Quic => &self.counters.quic,
+            AcceptPath::Libp2p => &self.counters.libp2p,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let reason = match rejection {
+            Rejection::NotAllowed(_) => &self.counters.not_allowed,
+            Rejection::BannedIp(_) | Rejection::BannedPeer(_) => &self.counters.banned,
+        };
+        reason.fetch_add(1, Ordering::Relaxed);
+        debug!("Firewall rejected {:?} connection: {}", path, rejection);
+    }
+
+    /// Check a connection from `ip` and count it if refused
+    pub fn admit(&self, path: AcceptPath, ip: IpAddr) -> Result<(), Rejection> {
+        self.check_ip(ip)
+            .inspect_err(|rejection| self.record_rejection(path, rejection))
+    }
+
+    /// Check a connection from a peer and count it if refused
+    pub fn admit_peer(&self, path: AcceptPath, peer_id: &str) -> Result<(), Rejection> {
+        self.check_peer(peer_id)
+            .inspect_err(|rejection| self.record_rejection(path, rejection))
+    }
+
+    /// Rejection counters and the number of bans in force
+    pub fn stats(&self) -> FirewallStats {
+        let active_bans = {
+            let mut bans = self.bans.write();
+            bans.prune(Instant::now());
+            bans.ips.len() + bans.peers.len()
+        };
+        FirewallStats {
+            quic_rejected: self.counters.quic.load(Ordering::Relaxed),
+            libp2p_rejected: self.counters.libp2p.load(Ordering::Relaxed),
+            banned_rejected: self.counters.banned.load(Ordering::Relaxed),
+            not_allowed_rejected: self.counters.not_allowed.load(Ordering::Relaxed),
+            bans_issued: self.counters.bans_issued.load(Ordering::Relaxed),
+            active_bans,
+        }
     }
 
     /// Get firewall mode
@@ -67,6 +252,10 @@ impl Firewall {
         self.mode
     }
 
+    pub fn policy(&self) -> FirewallPolicy {
+        self.policy
+    }
+
     /// Update eBPF map (Linux only)
     #[cfg(feature = "ebpf")]
     async fn update_ebpf_map(&self, ip: IpAddr, allow: bool) {
@@ -137,4 +326,48 @@ mod tests {
         firewall.block_ip(ip).await;
         assert!(!firewall.is_allowed(ip).await);
     }
+
+    #[test]
+    fn test_policy_and_bans() {
+        let ip = IpAddr::from_str("10.0.0.7").unwrap();
+
+        let open = Firewall::default();
+        assert!(open.admit(AcceptPath::Quic, ip).is_ok());
+
+        open.ban_ip(ip, Duration::from_secs(60));
+        assert_eq!(
+            open.admit(AcceptPath::Quic, ip),
+            Err(Rejection::BannedIp(ip))
+        );
+        open.unban_ip(ip);
+        assert!(open.admit(AcceptPath::Quic, ip).is_ok());
+
+        let strict = Firewall::default().with_policy(FirewallPolicy::Allowlist);
+        assert_eq!(
+            strict.admit(AcceptPath::Libp2p, ip),
+            Err(Rejection::NotAllowed(ip))
+        );
+
+        let stats = open.stats();
+        assert_eq!(stats.quic_rejected, 1);
+        assert_eq!(stats.banned_rejected, 1);
+        assert_eq!(stats.bans_issued, 1);
+        assert_eq!(stats.active_bans, 0);
+        assert_eq!(strict.stats().libp2p_rejected, 1);
+        assert_eq!(strict.stats().not_allowed_rejected, 1);
+    }
+
+    #[test]
+    fn test_peer_bans_expire() {
+        let firewall = Firewall::default();
+        firewall.ban_peer("12D3KooWbad", Duration::from_millis(20));
+        assert!(firewall
+            .admit_peer(AcceptPath::Libp2p, "12D3KooWbad")
+            .is_err());
+        assert!(firewall.check_peer("12D3KooWgood").is_ok());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(firewall.check_peer("12D3KooWbad").is_ok());
+        assert_eq!(firewall.stats().active_bans, 0);
+    }
 }
@@ -0,0 +1,431 @@
+/// Read-only gateway mode
+///
+/// A gateway is a public node that serves downloads to untrusted clients.
+/// Started with `--mode gateway`, the daemon:
+///
+/// - answers only the read-only RPC methods in [`READ_ONLY_METHODS`];
+///   uploads, compute jobs and anything that changes node state are refused
+/// - admits inbound peer connections only from the firewall allowlist and
+///   never serves storage for other peers
+/// - rate-limits every client IP with a token bucket, banning addresses at
+///   the firewall once they keep exceeding it
+/// - caches responses for a short time so popular content is not fetched
+///   again for every client
+///
+/// Limits come from the `[gateway]` section of the node config file.
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::firewall::Firewall;
+
+/// Startup profile of the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeMode {
+    /// Every service enabled
+    #[default]
+    Full,
+    /// Public read-only download gateway
+    Gateway,
+}
+
+/// RPC methods a gateway answers (names as in `schema.capnp`)
+pub const READ_ONLY_METHODS: &[&str] = &[
+    "getNode",
+    "getAllNodes",
+    "getConnectionQuality",
+    "getConnectedPeers",
+    "getNetworkMetrics",
+    "download",
+    "downloadWithProgress",
+    "getStream",
+    "getMany",
+    "getLocalMultiaddr",
+    "listLibp2pPeers",
+    "getMetricsHistory",
+    "getActiveTransfers",
+    "getLimits",
+    "listFiles",
+    "searchFiles",
+    "getFileInfo",
+    "getNamespaceStats",
+    "listNamespaceStats",
+];
+
+/// Limits for gateway mode (`[gateway]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    /// Sustained requests per second allowed from one client IP
+    pub requests_per_sec: f64,
+    /// Requests a client may make in a burst above the sustained rate
+    pub burst: u32,
+    /// Refused requests in a row before the client IP is banned (0 = never)
+    pub ban_after_refusals: u32,
+    pub ban_duration_secs: u64,
+    /// Total size of cached responses
+    pub cache_bytes: usize,
+    /// How long a cached response is served
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_sec: 10.0,
+            burst: 20,
+            ban_after_refusals: 100,
+            ban_duration_secs: 600,
+            cache_bytes: 256 * 1024 * 1024,
+            cache_ttl_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    gateway: GatewayConfig,
+}
+
+impl GatewayConfig {
+    /// Load the `[gateway]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[gateway]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.gateway.validate()?;
+        Ok(file.gateway)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.requests_per_sec.is_finite() || self.requests_per_sec <= 0.0 {
+            anyhow::bail!("gateway: requests_per_sec must be positive");
+        }
+        if self.burst == 0 {
+            anyhow::bail!("gateway: burst must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Why a gateway refused a request
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GatewayRejection {
+    #[error("{0} is not available on a read-only gateway")]
+    MethodDisabled(String),
+    #[error("too many requests from {0}")]
+    RateLimited(IpAddr),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    refusals: u32,
+}
+
+/// Token bucket per client IP
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_sec: f64, burst: u32) -> Self {
+        Self {
+            rate: requests_per_sec,
+            burst: burst as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `ip`
+    ///
+    /// `Ok` if the request may proceed, otherwise `Err` with how many
+    /// requests in a row this client has had refused.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), u32> {
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+            refusals: 0,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.refusals = 0;
+            Ok(())
+        } else {
+            bucket.refusals += 1;
+            Err(bucket.refusals)
+        }
+    }
+
+    /// Forget clients whose buckets have refilled completely
+    pub fn prune(&self, now: Instant) {
+        let refill = Duration::from_secs_f64(self.burst / self.rate);
+        self.buckets
+            .lock()
+            .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < refill);
+    }
+
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().len()
+    }
+}
+
+/// Byte-bounded cache of serialized responses with a fixed lifetime
+#[derive(Debug)]
+pub struct ResponseCache {
+    max_bytes: usize,
+    ttl: Duration,
+    state: Mutex<ResponseCacheState>,
+}
+
+#[derive(Debug, Default)]
+struct ResponseCacheState {
+    entries: HashMap<String, (Instant, Arc<Vec<u8>>)>,
+    /// Keys in insertion order, oldest first
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+impl ResponseCacheState {
+    fn remove(&mut self, key: &str) {
+        if let Some((_, body)) = self.entries.remove(key) {
+            self.bytes -= body.len();
+            self.order.retain(|k| k != key);
+        }
+    }
+}
+
+impl ResponseCache {
+    pub fn new(max_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            max_bytes,
+            ttl,
+            state: Mutex::new(ResponseCacheState::default()),
+        }
+    }
+
+    /// Cached response for `key`, unless it has expired
+    pub fn get(&self, key: &str, now: Instant) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.lock();
+        let (stored, body) = state.entries.get(key)?;
+        if now.saturating_duration_since(*stored) < self.ttl {
+            return Some(body.clone());
+        }
+        state.remove(key);
+        None
+    }
+
+    /// Cache `body` under `key`, evicting the oldest entries to fit
+    ///
+    /// Responses larger than the whole cache are not kept.
+    pub fn insert(&self, key: &str, body: Vec<u8>, now: Instant) -> Arc<Vec<u8>> {
+        let body = Arc::new(body);
+        if body.len() > self.max_bytes {
+            return body;
+        }
+        let mut state = self.state.lock();
+        state.remove(key);
+        while state.bytes + body.len() > self.max_bytes {
+            let Some(oldest) = state.order.pop_front() else {
+                break;
+            };
+            if let Some((_, evicted)) = state.entries.remove(&oldest) {
+                state.bytes -= evicted.len();
+            }
+        }
+        state.bytes += body.len();
+        state.order.push_back(key.to_string());
+        state.entries.insert(key.to_string(), (now, body.clone()));
+        body
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Method policy, rate limits and response cache of a gateway node
+pub struct Gateway {
+    config: GatewayConfig,
+    limiter: RateLimiter,
+    cache: ResponseCache,
+    firewall: Option<Arc<Firewall>>,
+}
+
+impl Gateway {
+    pub fn new(config: GatewayConfig) -> Self {
+        Self {
+            limiter: RateLimiter::new(config.requests_per_sec, config.burst),
+            cache: ResponseCache::new(
+                config.cache_bytes,
+                Duration::from_secs(config.cache_ttl_secs),
+            ),
+            config,
+            firewall: None,
+        }
+    }
+
+    /// Ban clients that keep exceeding their rate at this firewall
+    pub fn with_firewall(mut self, firewall: Arc<Firewall>) -> Self {
+        self.firewall = Some(firewall);
+        self
+    }
+
+    pub fn config(&self) -> &GatewayConfig {
+        &self.config
+    }
+
+    /// Whether a gateway answers `method` at all
+    pub fn allows_method(method: &str) -> bool {
+        READ_ONLY_METHODS.contains(&method)
+    }
+
+    /// Decide whether `client` may call `method` now
+    pub fn admit(&self, client: IpAddr, method: &str) -> Result<(), GatewayRejection> {
+        if !Self::allows_method(method) {
+            return Err(GatewayRejection::MethodDisabled(method.to_string()));
+        }
+        self.check_rate(client)
+    }
+
+    /// Charge one request to `client` without checking the method
+    ///
+    /// Used for new RPC connections as well as individual calls.
+    pub fn check_rate(&self, client: IpAddr) -> Result<(), GatewayRejection> {
+        let refusals = match self.limiter.check(client, Instant::now()) {
+            Ok(()) => return Ok(()),
+            Err(refusals) => refusals,
+        };
+        if self.config.ban_after_refusals > 0 && refusals == self.config.ban_after_refusals {
+            if let Some(firewall) = &self.firewall {
+                warn!(
+                    "Banning gateway client {} after {} refused requests",
+                    client, refusals
+                );
+                firewall.ban_ip(client, Duration::from_secs(self.config.ban_duration_secs));
+            }
+        }
+        Err(GatewayRejection::RateLimited(client))
+    }
+
+    /// Cached response for `key`
+    pub fn cached(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.cache.get(key, Instant::now())
+    }
+
+    /// Remember a response for later clients
+    pub fn cache_response(&self, key: &str, body: Vec<u8>) -> Arc<Vec<u8>> {
+        self.cache.insert(key, body, Instant::now())
+    }
+
+    /// Drop rate-limit state for clients that have gone quiet
+    pub fn prune(&self) {
+        self.limiter.prune(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gateway_refuses_writes_and_bans_floods() {
+        let firewall = Arc::new(Firewall::default());
+        let gateway = Gateway::new(GatewayConfig {
+            requests_per_sec: 1.0,
+            burst: 2,
+            ban_after_refusals: 3,
+            ..GatewayConfig::default()
+        })
+        .with_firewall(firewall.clone());
+        let client: IpAddr = "203.0.113.9".parse().unwrap();
+
+        for method in [
+            "upload",
+            "uploadWithProgress",
+            "submitComputeJob",
+            "updateNode",
+        ] {
+            assert_eq!(
+                gateway.admit(client, method),
+                Err(GatewayRejection::MethodDisabled(method.to_string()))
+            );
+        }
+
+        assert!(gateway.admit(client, "download").is_ok());
+        assert!(gateway.admit(client, "getMany").is_ok());
+        for _ in 0..2 {
+            assert_eq!(
+                gateway.admit(client, "download"),
+                Err(GatewayRejection::RateLimited(client))
+            );
+            assert!(!firewall.is_banned(client));
+        }
+        assert!(gateway.admit(client, "download").is_err());
+        assert!(firewall.is_banned(client));
+    }
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let limiter = RateLimiter::new(10.0, 1);
+        let ip: IpAddr = "198.51.100.1".parse().unwrap();
+        let start = Instant::now();
+        assert!(limiter.check(ip, start).is_ok());
+        assert_eq!(limiter.check(ip, start), Err(1));
+        assert!(limiter
+            .check(ip, start + Duration::from_millis(100))
+            .is_ok());
+
+        limiter.prune(start + Duration::from_secs(1));
+        assert_eq!(limiter.tracked_clients(), 0);
+    }
+
+    #[test]
+    fn test_response_cache_expires_and_evicts() {
+        let cache = ResponseCache::new(10, Duration::from_secs(5));
+        let now = Instant::now();
+        cache.insert("a", vec![1; 6], now);
+        assert_eq!(cache.get("a", now).unwrap().len(), 6);
+        assert!(cache.get("a", now + Duration::from_secs(5)).is_none());
+
+        cache.insert("a", vec![1; 6], now);
+        cache.insert("b", vec![2; 6], now);
+        assert!(cache.get("a", now).is_none());
+        assert!(cache.get("b", now).is_some());
+
+        // Too big to cache at all
+        cache.insert("c", vec![3; 11], now);
+        assert!(cache.get("c", now).is_none());
+        assert_eq!(cache.len(), 1);
+
+        assert!(GatewayConfig::from_toml("[gateway]\nburst = 0\n").is_err());
+        let config = GatewayConfig::from_toml("[gateway]\nrequests_per_sec = 2.5\n").unwrap();
+        assert_eq!(config.burst, 20);
+    }
+}
@@ -0,0 +1,251 @@
+/// Geo/ASN lookups for peer addresses
+///
+/// Loads the free ip2asn TSV dumps (`ip2asn-combined.tsv` and friends) and,
+/// with the `geoip` feature, MaxMind GeoLite2/GeoIP2 `.mmdb` databases. Several
+/// databases can be combined; the first one that knows a field wins, so an ASN
+/// database and a country database complement each other.
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+use tracing::info;
+
+use crate::types::GeoInfo;
+
+/// One contiguous range of an ip2asn dump
+#[derive(Debug, Clone)]
+struct AsnRange {
+    start: u128,
+    end: u128,
+    asn: u32,
+    country: Option<String>,
+    as_org: Option<String>,
+}
+
+/// A loaded lookup database
+enum Source {
+    Ip2Asn(Vec<AsnRange>),
+    #[cfg(feature = "geoip")]
+    MaxMind(maxminddb::Reader<Vec<u8>>),
+}
+
+impl Source {
+    fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        match self {
+            Source::Ip2Asn(ranges) => {
+                let key = ip_key(ip);
+                // Ranges are sorted and disjoint: find the last one starting at or before `key`
+                let idx = ranges.partition_point(|range| range.start <= key);
+                match idx.checked_sub(1).map(|i| &ranges[i]) {
+                    Some(range) if key <= range.end => GeoInfo {
+                        country: range.country.clone(),
+                        asn: Some(range.asn),
+                        as_org: range.as_org.clone(),
+                    },
+                    _ => GeoInfo::default(),
+                }
+            }
+            #[cfg(feature = "geoip")]
+            Source::MaxMind(reader) => {
+                use maxminddb::geoip2;
+
+                let mut info = GeoInfo::default();
+                if let Ok(country) = reader.lookup::<geoip2::Country>(ip) {
+                    info.country = country.country.and_then(|c| c.iso_code).map(str::to_string);
+                }
+                if let Ok(asn) = reader.lookup::<geoip2::Asn>(ip) {
+                    info.asn = asn.autonomous_system_number;
+                    info.as_org = asn.autonomous_system_organization.map(str::to_string);
+                }
+                info
+            }
+        }
+    }
+}
+
+/// Geo/ASN database built from one or more files
+#[derive(Default)]
+pub struct GeoDatabase {
+    sources: Vec<Source>,
+}
+
+impl GeoDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every file in `paths`, picking the format by extension
+    ///
+    /// `.mmdb` files need the `geoip` feature; anything else is read as an
+    /// ip2asn TSV dump.
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut db = Self::new();
+        for path in paths {
+            db.load(path.as_ref())?;
+        }
+        Ok(db)
+    }
+
+    /// Add one database file
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let is_mmdb = path.extension().is_some_and(|ext| ext == "mmdb");
+        if is_mmdb {
+            return self.load_mmdb(path);
+        }
+
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open geo database {}", path.display()))?;
+        let ranges = parse_ip2asn(BufReader::new(file))
+            .with_context(|| format!("Failed to parse ip2asn dump {}", path.display()))?;
+        info!(
+            "🌍 Loaded {} ASN ranges from {}",
+            ranges.len(),
+            path.display()
+        );
+        self.sources.push(Source::Ip2Asn(ranges));
+        Ok(())
+    }
+
+    #[cfg(feature = "geoip")]
+    fn load_mmdb(&mut self, path: &Path) -> Result<()> {
+        let reader = maxminddb::Reader::open_readfile(path)
+            .with_context(|| format!("Failed to open MaxMind database {}", path.display()))?;
+        info!(
+            "🌍 Loaded {} from {}",
+            reader.metadata.database_type,
+            path.display()
+        );
+        self.sources.push(Source::MaxMind(reader));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "geoip"))]
+    fn load_mmdb(&mut self, path: &Path) -> Result<()> {
+        anyhow::bail!(
+            "{} is a MaxMind database; rebuild with the `geoip` feature to load it",
+            path.display()
+        )
+    }
+
+    /// Add ranges from an ip2asn TSV dump
+    pub fn load_ip2asn(&mut self, reader: impl BufRead) -> Result<()> {
+        self.sources.push(Source::Ip2Asn(parse_ip2asn(reader)?));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Country and ASN of `ip`, or `None` if no database knows it
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoInfo> {
+        let mut merged = GeoInfo::default();
+        for source in &self.sources {
+            let found = source.lookup(ip);
+            merged.country = merged.country.or(found.country);
+            if merged.asn.is_none() {
+                merged.asn = found.asn;
+                merged.as_org = found.as_org;
+            }
+        }
+        (!merged.is_empty()).then_some(merged)
+    }
+}
+
+/// Addresses as one integer space, with IPv4 mapped into IPv6
+fn ip_key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Parse `range_start  range_end  AS_number  country_code  AS_description`
+///
+/// Unrouted ranges (AS 0) are skipped.
+fn parse_ip2asn(reader: impl BufRead) -> Result<Vec<AsnRange>> {
+    let mut ranges = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            anyhow::bail!("line {}: expected at least 3 fields", line_no + 1);
+        }
+        let parse_ip = |field: &str| -> Result<IpAddr> {
+            field
+                .parse()
+                .with_context(|| format!("line {}: invalid address {:?}", line_no + 1, field))
+        };
+        let asn: u32 = fields[2]
+            .parse()
+            .with_context(|| format!("line {}: invalid AS number", line_no + 1))?;
+        if asn == 0 {
+            continue;
+        }
+        let known = |field: Option<&&str>| {
+            field
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty() && *value != "None" && *value != "Unknown")
+                .map(str::to_string)
+        };
+        ranges.push(AsnRange {
+            start: ip_key(parse_ip(fields[0])?),
+            end: ip_key(parse_ip(fields[1])?),
+            asn,
+            country: known(fields.get(3)),
+            as_org: known(fields.get(4)),
+        });
+    }
+    ranges.sort_by_key(|range| range.start);
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET
+1.0.1.0\t1.0.3.255\t0\tNone\tNot routed
+5.0.0.0\t5.0.0.255\t3320\tDE\tDTAG
+2001:db8::\t2001:db8::ffff\t64500\tNL\tEXAMPLE-NET
+";
+
+    fn db() -> GeoDatabase {
+        let mut db = GeoDatabase::new();
+        db.load_ip2asn(DUMP.as_bytes()).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_ip2asn_lookup() {
+        let db = db();
+
+        let info = db.lookup("1.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(info.asn, Some(13335));
+        assert_eq!(info.country.as_deref(), Some("US"));
+        assert_eq!(info.as_org.as_deref(), Some("CLOUDFLARENET"));
+
+        let v6 = db.lookup("2001:db8::42".parse().unwrap()).unwrap();
+        assert_eq!(v6.asn, Some(64500));
+
+        // Unrouted and unlisted addresses are unknown
+        assert!(db.lookup("1.0.2.1".parse().unwrap()).is_none());
+        assert!(db.lookup("9.9.9.9".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_sources_complement_each_other() {
+        let mut db = GeoDatabase::new();
+        db.load_ip2asn("5.0.0.0\t5.0.0.255\t3320\tNone\tDTAG\n".as_bytes())
+            .unwrap();
+        db.load_ip2asn(DUMP.as_bytes()).unwrap();
+
+        let info = db.lookup("5.0.0.9".parse().unwrap()).unwrap();
+        assert_eq!(info.asn, Some(3320));
+        assert_eq!(info.country.as_deref(), Some("DE"));
+    }
+}
@@ -1,35 +1,172 @@
-use anyhow::{Context, Result};
-
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
+
+use crate::error::{ErrorCode, NetworkError};
+use crate::manifest_sync::{ManifestDigest, ManifestRecord};
+use crate::retry::{retry, RetryPolicy};
+use crate::schema_capnp::{manifest_digest, manifest_record, node_service};
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+/// Connection pool configuration
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Number of RPC connections kept to the Go node
+    pub size: usize,
+    /// Deadline for a single RPC call
+    pub request_timeout: Duration,
+    /// Interval between background health checks
+    pub health_check_interval: Duration,
+    /// Consecutive failures that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects calls before probing again
+    pub open_duration: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 2,
+            request_timeout: Duration::from_secs(10),
+            health_check_interval: Duration::from_secs(15),
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Circuit breaker state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CircuitState {
+    /// Calls flow normally
+    Closed,
+    /// Go node considered down; calls fail fast
+    Open,
+    /// Cooldown elapsed; calls are let through to probe the node
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Snapshot of the pool and circuit breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoClientStats {
+    /// `connect()` has succeeded
+    pub connected: bool,
+    pub circuit: CircuitState,
+    pub pool_size: usize,
+    pub healthy_connections: usize,
+    pub requests: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub reconnects: u64,
+    pub circuit_trips: u64,
+}
 
-use crate::schema_capnp::node_service;
+struct Connection {
+    client: node_service::Client,
+    alive: Arc<AtomicBool>,
+}
+
+struct Breaker {
+    state: CircuitState,
+    opened_at: Option<Instant>,
+    consecutive_failures: u32,
+    trips: u64,
+}
 
 /// Client for connecting to Go node's Cap'n Proto RPC
 pub struct GoClient {
     addr: SocketAddr,
-    client: RwLock<Option<node_service::Client>>,
+    config: PoolConfig,
+    retry: RetryPolicy,
+    pool: Mutex<Vec<Option<Connection>>>,
+    connected: AtomicBool,
+    next: AtomicUsize,
+    breaker: Mutex<Breaker>,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    timeouts: AtomicU64,
+    reconnects: AtomicU64,
 }
 
 impl GoClient {
     pub fn new(addr: SocketAddr) -> Self {
+        Self::with_pool_config(addr, PoolConfig::default())
+    }
+
+    /// Create a client with explicit pool settings
+    pub fn with_pool_config(addr: SocketAddr, config: PoolConfig) -> Self {
+        let size = config.size.max(1);
         Self {
             addr,
-            client: RwLock::new(None),
+            config,
+            retry: RetryPolicy::default(),
+            pool: Mutex::new((0..size).map(|_| None).collect()),
+            connected: AtomicBool::new(false),
+            next: AtomicUsize::new(0),
+            breaker: Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                opened_at: None,
+                consecutive_failures: 0,
+                trips: 0,
+            }),
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
         }
     }
 
-    /// Connect to Go node
+    /// Retry policy for connecting and for idempotent queries
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Connect to Go node, filling the connection pool
     pub async fn connect(&self) -> Result<()> {
         info!("Connecting to Go node at {}", self.addr);
 
+        let size = self.pool.lock().len();
+        for slot in 0..size {
+            let connection = self.open_connection().await?;
+            self.pool.lock()[slot] = Some(connection);
+        }
+        self.connected.store(true, Ordering::SeqCst);
+        info!("Connected to Go node successfully ({} connections)", size);
+
+        Ok(())
+    }
+
+    /// Open one RPC connection to the Go node
+    async fn open_connection(&self) -> Result<Connection> {
         // Connect to Go Cap'n Proto server
-        let stream = TcpStream::connect(self.addr)
-            .await
-            .context("Failed to connect to Go node")?;
+        let stream = retry(&self.retry, "go_connect", || async {
+            TcpStream::connect(self.addr)
+                .await
+                .map_err(|source| NetworkError::ConnectFailed {
+                    addr: self.addr,
+                    source,
+                })
+        })
+        .await?;
 
         use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
@@ -49,198 +186,386 @@ impl GoClient {
         let client: node_service::Client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
 
         // Spawn RPC system in background using spawn_local for non-Send futures
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_flag = alive.clone();
         tokio::task::spawn_local(async move {
             if let Err(e) = rpc_system.await {
                 error!("RPC system error: {}", e);
             }
+            alive_flag.store(false, Ordering::SeqCst);
         });
 
-        *self.client.write().unwrap() = Some(client);
-        info!("Connected to Go node successfully");
+        Ok(Connection { client, alive })
+    }
+
+    /// Pick a live pooled connection, reconnecting dead slots
+    async fn rpc(&self) -> Result<(usize, node_service::Client)> {
+        if !self.connected.load(Ordering::SeqCst) {
+            return Err(NetworkError::NotConnected);
+        }
+
+        let slot = {
+            let pool = self.pool.lock();
+            let start = self.next.fetch_add(1, Ordering::Relaxed);
+            let live = (0..pool.len())
+                .map(|i| (start + i) % pool.len())
+                .find(|i| matches!(&pool[*i], Some(c) if c.alive.load(Ordering::SeqCst)));
+            match live {
+                Some(i) => return Ok((i, pool[i].as_ref().unwrap().client.clone())),
+                None => start % pool.len(),
+            }
+        };
+
+        debug!("No live connection to Go node, reconnecting slot {}", slot);
+        let connection = self.open_connection().await?;
+        let client = connection.client.clone();
+        self.pool.lock()[slot] = Some(connection);
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+        Ok((slot, client))
+    }
+
+    /// Run one RPC through the pool, circuit breaker and request timeout
+    async fn call<T, F, Fut>(&self, operation: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(node_service::Client) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.check_circuit()?;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let result = match self.rpc().await {
+            Ok((slot, client)) => {
+                let result =
+                    match tokio::time::timeout(self.config.request_timeout, f(client)).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            self.timeouts.fetch_add(1, Ordering::Relaxed);
+                            Err(NetworkError::Timeout(operation.to_string()))
+                        }
+                    };
+                if let Err(NetworkError::Rpc(e)) = &result {
+                    if e.kind == capnp::ErrorKind::Disconnected {
+                        self.mark_dead(slot);
+                    }
+                }
+                result
+            }
+            Err(e) => Err(e),
+        };
+
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(e) if e.is_transient() => self.record_failure(operation),
+            Err(_) => {}
+        }
+        result
+    }
 
+    fn mark_dead(&self, slot: usize) {
+        if let Some(connection) = &self.pool.lock()[slot] {
+            connection.alive.store(false, Ordering::SeqCst);
+        }
+    }
+
+    fn check_circuit(&self) -> Result<()> {
+        let mut breaker = self.breaker.lock();
+        if breaker.state == CircuitState::Open {
+            let cooled = breaker
+                .opened_at
+                .is_some_and(|t| t.elapsed() >= self.config.open_duration);
+            if !cooled {
+                return Err(NetworkError::CircuitOpen);
+            }
+            breaker.state = CircuitState::HalfOpen;
+            info!("Go node circuit half-open, probing");
+        }
         Ok(())
     }
 
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock();
+        if breaker.state != CircuitState::Closed {
+            info!("Go node circuit closed");
+        }
+        breaker.state = CircuitState::Closed;
+        breaker.opened_at = None;
+        breaker.consecutive_failures = 0;
+    }
+
+    fn record_failure(&self, operation: &str) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        let mut breaker = self.breaker.lock();
+        breaker.consecutive_failures += 1;
+        let trip = breaker.state == CircuitState::HalfOpen
+            || (breaker.state == CircuitState::Closed
+                && breaker.consecutive_failures >= self.config.failure_threshold);
+        if trip {
+            warn!(
+                "Go node circuit open after {} consecutive failures ({})",
+                breaker.consecutive_failures, operation
+            );
+            breaker.state = CircuitState::Open;
+            breaker.opened_at = Some(Instant::now());
+            breaker.trips += 1;
+        }
+    }
+
+    /// Whether `connect()` has succeeded
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Current circuit breaker state
+    pub fn circuit_state(&self) -> CircuitState {
+        self.breaker.lock().state
+    }
+
+    /// Pool, breaker and request statistics
+    pub fn stats(&self) -> GoClientStats {
+        let pool = self.pool.lock();
+        GoClientStats {
+            connected: self.is_connected(),
+            circuit: self.circuit_state(),
+            pool_size: pool.len(),
+            healthy_connections: pool
+                .iter()
+                .flatten()
+                .filter(|c| c.alive.load(Ordering::SeqCst))
+                .count(),
+            requests: self.requests.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            circuit_trips: self.breaker.lock().trips,
+        }
+    }
+
+    /// Ping every pooled connection, returning how many are healthy
+    ///
+    /// Connections that fail the check are marked dead and reopened on next use.
+    pub async fn health_check(&self) -> usize {
+        let connections: Vec<(usize, node_service::Client)> = self
+            .pool
+            .lock()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| {
+                c.as_ref()
+                    .filter(|c| c.alive.load(Ordering::SeqCst))
+                    .map(|c| (i, c.client.clone()))
+            })
+            .collect();
+
+        let mut healthy = 0;
+        for (slot, client) in connections {
+            let ping = client.get_connected_peers_request().send().promise;
+            match tokio::time::timeout(self.config.request_timeout, ping).await {
+                Ok(Ok(_)) => healthy += 1,
+                _ => {
+                    warn!("Go node connection {} failed health check", slot);
+                    self.mark_dead(slot);
+                }
+            }
+        }
+
+        if healthy > 0 {
+            self.record_success();
+        } else if self.connected.load(Ordering::SeqCst) {
+            self.record_failure("health_check");
+        }
+        healthy
+    }
+
+    /// Connect, then check the pool every `health_check_interval`
+    ///
+    /// For running under a supervisor, which retries a failed connect. Must
+    /// run within a `LocalSet` (the client is not `Send`).
+    pub async fn maintain(self: Arc<Self>) -> Result<()> {
+        if !self.is_connected() {
+            self.connect().await?;
+        }
+        let mut ticker = tokio::time::interval(self.config.health_check_interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let healthy = self.health_check().await;
+            debug!("Go node health check: {} healthy connection(s)", healthy);
+        }
+    }
+
+    /// Run health checks in the background until the client is dropped
+    ///
+    /// Must be called from within a `LocalSet` (the client is not `Send`).
+    pub fn spawn_health_checks(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let client: Weak<Self> = Arc::downgrade(self);
+        let interval = self.config.health_check_interval;
+        tokio::task::spawn_local(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(client) = client.upgrade() else {
+                    break;
+                };
+                let healthy = client.health_check().await;
+                debug!("Go node health check: {} healthy connection(s)", healthy);
+            }
+        })
+    }
+
     /// Send data to Go node for transport
     pub async fn send_data(&self, peer_id: u32, data: Vec<u8>) -> Result<bool> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node. Call connect() first")?
-            .clone();
-
         info!(
             "Sending {} bytes to Go node for peer {}",
             data.len(),
             peer_id
         );
 
-        let mut request = client.send_message_request();
-        {
-            let mut msg = request.get().get_msg()?;
-            msg.set_to_peer_id(peer_id);
-            msg.set_data(&data);
-        }
+        self.call("send_data", |client| async move {
+            let mut request = client.send_message_request();
+            {
+                let mut msg = request.get().get_msg()?;
+                msg.set_to_peer_id(peer_id);
+                msg.set_data(&data);
+            }
 
-        let response = request.send().promise.await?;
-        let success = response.get()?.get_success();
+            let response = request.send().promise.await?;
+            let success = response.get()?.get_success();
 
-        Ok(success)
+            Ok(success)
+        })
+        .await
     }
 
     /// Get connection quality for a peer
     pub async fn get_connection_quality(&self, peer_id: u32) -> Result<(f32, f32, f32)> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
-        let mut request = client.get_connection_quality_request();
-        request.get().set_peer_id(peer_id);
-
-        let response = request.send().promise.await?;
-        let quality = response.get()?.get_quality()?;
-
-        Ok((
-            quality.get_latency_ms(),
-            quality.get_jitter_ms(),
-            quality.get_packet_loss(),
-        ))
+        retry(&self.retry, "get_connection_quality", || {
+            self.call("get_connection_quality", |client| async move {
+                let mut request = client.get_connection_quality_request();
+                request.get().set_peer_id(peer_id);
+
+                let response = request.send().promise.await?;
+                let quality = response.get()?.get_quality()?;
+
+                Ok((
+                    quality.get_latency_ms(),
+                    quality.get_jitter_ms(),
+                    quality.get_packet_loss(),
+                ))
+            })
+        })
+        .await
     }
 
     /// Connect to a peer via Go node
     pub async fn connect_peer(&self, host: &str, port: u16) -> Result<(bool, f32, f32, f32)> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
         info!("Connecting to peer {}:{} via Go node", host, port);
 
-        let mut request = client.connect_to_peer_request();
-        {
-            let mut peer = request.get().get_peer()?;
-            peer.set_peer_id(0); // Will be assigned by Go
-            peer.set_host(host);
-            peer.set_port(port);
-        }
+        self.call("connect_peer", |client| async move {
+            let mut request = client.connect_to_peer_request();
+            {
+                let mut peer = request.get().get_peer()?;
+                peer.set_peer_id(0); // Will be assigned by Go
+                peer.set_host(host);
+                peer.set_port(port);
+            }
 
-        let response = request.send().promise.await?;
-        let result = response.get()?;
-        let success = result.get_success();
-
-        if success {
-            let quality = result.get_quality()?;
-            Ok((
-                success,
-                quality.get_latency_ms(),
-                quality.get_jitter_ms(),
-                quality.get_packet_loss(),
-            ))
-        } else {
-            Ok((false, 0.0, 0.0, 0.0))
-        }
+            let response = request.send().promise.await?;
+            let result = response.get()?;
+            let success = result.get_success();
+
+            if success {
+                let quality = result.get_quality()?;
+                Ok((
+                    success,
+                    quality.get_latency_ms(),
+                    quality.get_jitter_ms(),
+                    quality.get_packet_loss(),
+                ))
+            } else {
+                Ok((false, 0.0, 0.0, 0.0))
+            }
+        })
+        .await
     }
 
     /// Disconnect from a peer
     pub async fn disconnect_peer(&self, peer_id: u32) -> Result<bool> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
+        self.call("disconnect_peer", |client| async move {
+            let mut request = client.disconnect_peer_request();
+            request.get().set_peer_id(peer_id);
 
-        let mut request = client.disconnect_peer_request();
-        request.get().set_peer_id(peer_id);
+            let response = request.send().promise.await?;
+            let success = response.get()?.get_success();
 
-        let response = request.send().promise.await?;
-        let success = response.get()?.get_success();
-
-        Ok(success)
+            Ok(success)
+        })
+        .await
     }
 
     /// Get list of connected peers
     pub async fn get_connected_peers(&self) -> Result<Vec<u32>> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
-        let request = client.get_connected_peers_request();
-        let response = request.send().promise.await?;
-        let peers_list = response.get()?.get_peers()?;
-
-        let mut peers = Vec::new();
-        for i in 0..peers_list.len() {
-            peers.push(peers_list.get(i));
-        }
-
-        Ok(peers)
+        retry(&self.retry, "get_connected_peers", || {
+            self.call("get_connected_peers", |client| async move {
+                let request = client.get_connected_peers_request();
+                let response = request.send().promise.await?;
+                let peers_list = response.get()?.get_peers()?;
+
+                let mut peers = Vec::new();
+                for i in 0..peers_list.len() {
+                    peers.push(peers_list.get(i));
+                }
+
+                Ok(peers)
+            })
+        })
+        .await
     }
 
     /// Get a specific node by ID
     pub async fn get_node(&self, node_id: u32) -> Result<Option<(u32, u32, f32, f32)>> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
-        let mut request = client.get_node_request();
-        request.get().get_query()?.set_node_id(node_id);
-
-        let response = request.send().promise.await?;
-        let node = response.get()?.get_node()?;
-
-        Ok(Some((
-            node.get_id(),
-            node.get_status(),
-            node.get_latency_ms(),
-            node.get_threat_score(),
-        )))
+        retry(&self.retry, "get_node", || {
+            self.call("get_node", |client| async move {
+                let mut request = client.get_node_request();
+                request.get().get_query()?.set_node_id(node_id);
+
+                let response = request.send().promise.await?;
+                let node = response.get()?.get_node()?;
+
+                Ok(Some((
+                    node.get_id(),
+                    node.get_status(),
+                    node.get_latency_ms(),
+                    node.get_threat_score(),
+                )))
+            })
+        })
+        .await
     }
 
     /// Get all nodes
     pub async fn get_all_nodes(&self) -> Result<Vec<(u32, u32, f32, f32)>> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
-        let request = client.get_all_nodes_request();
-        let response = request.send().promise.await?;
-        let node_list = response.get()?.get_nodes()?.get_nodes()?;
-
-        let mut nodes = Vec::new();
-        for i in 0..node_list.len() {
-            let node = node_list.get(i);
-            nodes.push((
-                node.get_id(),
-                node.get_status(),
-                node.get_latency_ms(),
-                node.get_threat_score(),
-            ));
-        }
-
-        Ok(nodes)
+        retry(&self.retry, "get_all_nodes", || {
+            self.call("get_all_nodes", |client| async move {
+                let request = client.get_all_nodes_request();
+                let response = request.send().promise.await?;
+                let node_list = response.get()?.get_nodes()?.get_nodes()?;
+
+                let mut nodes = Vec::new();
+                for i in 0..node_list.len() {
+                    let node = node_list.get(i);
+                    nodes.push((
+                        node.get_id(),
+                        node.get_status(),
+                        node.get_latency_ms(),
+                        node.get_threat_score(),
+                    ));
+                }
+
+                Ok(nodes)
+            })
+        })
+        .await
     }
 
     /// Update node threat score (called by AI/prediction)
@@ -250,26 +575,21 @@ impl GoClient {
         latency_ms: f32,
         threat_score: f32,
     ) -> Result<bool> {
-        let client = self
-            .client
-            .read()
-            .unwrap()
-            .as_ref()
-            .context("Not connected to Go node")?
-            .clone();
-
-        let mut request = client.update_node_request();
-        {
-            let mut update = request.get().get_update()?;
-            update.set_node_id(node_id);
-            update.set_latency_ms(latency_ms);
-            update.set_threat_score(threat_score);
-        }
+        self.call("update_node", |client| async move {
+            let mut request = client.update_node_request();
+            {
+                let mut update = request.get().get_update()?;
+                update.set_node_id(node_id);
+                update.set_latency_ms(latency_ms);
+                update.set_threat_score(threat_score);
+            }
 
-        let response = request.send().promise.await?;
-        let success = response.get()?.get_success();
+            let response = request.send().promise.await?;
+            let success = response.get()?.get_success();
 
-        Ok(success)
+            Ok(success)
+        })
+        .await
     }
 
     // UNTESTABLE: Data transfer methods below require extending the Cap'n Proto schema
@@ -293,18 +613,138 @@ impl GoClient {
         info!("get_peer_info called but not yet implemented in schema");
         Ok(None)
     }
+
+    /// Digests of the manifests the Go node holds
+    pub async fn get_manifest_digests(&self) -> Result<Vec<ManifestDigest>> {
+        retry(&self.retry, "get_manifest_digests", || {
+            self.call("get_manifest_digests", |client| async move {
+                let request = client.get_manifest_digests_request();
+                let response = request.send().promise.await?;
+                let digests = response.get()?.get_digests()?;
+
+                let mut out = Vec::with_capacity(digests.len() as usize);
+                for digest in digests.iter() {
+                    out.push(read_digest(digest)?);
+                }
+                Ok(out)
+            })
+        })
+        .await
+    }
+
+    /// Push manifest records to the Go node and fetch the wanted ones,
+    /// with how many of ours it stored
+    ///
+    /// The Go node keeps records byte for byte and resolves conflicts as
+    /// `ManifestDigest::supersedes` does.
+    pub async fn sync_manifests(
+        &self,
+        push: Vec<ManifestRecord>,
+        want: Vec<String>,
+    ) -> Result<(Vec<ManifestRecord>, u32)> {
+        self.call("sync_manifests", |client| async move {
+            let mut request = client.sync_manifests_request();
+            {
+                let mut params = request.get();
+                let mut records = params.reborrow().init_push(push.len() as u32);
+                for (i, record) in push.iter().enumerate() {
+                    fill_record(records.reborrow().get(i as u32), record);
+                }
+                let mut hashes = params.init_want(want.len() as u32);
+                for (i, hash) in want.iter().enumerate() {
+                    hashes.set(i as u32, hash.as_str());
+                }
+            }
+
+            let response = request.send().promise.await?;
+            let response = response.get()?;
+            let mut records = Vec::new();
+            for record in response.get_records()?.iter() {
+                records.push(read_record(record)?);
+            }
+            Ok((records, response.get_accepted()))
+        })
+        .await
+    }
+}
+
+pub(crate) fn read_digest(digest: manifest_digest::Reader) -> capnp::Result<ManifestDigest> {
+    Ok(ManifestDigest {
+        file_hash: digest.get_file_hash()?.to_string()?,
+        timestamp: digest.get_timestamp(),
+        digest: digest.get_digest()?.to_string()?,
+    })
+}
+
+pub(crate) fn read_record(record: manifest_record::Reader) -> capnp::Result<ManifestRecord> {
+    Ok(ManifestRecord {
+        manifest: record.get_manifest()?.to_string()?,
+        signer: record.get_signer()?.to_string()?,
+        signature: record.get_signature()?.to_string()?,
+    })
+}
+
+pub(crate) fn fill_record(mut builder: manifest_record::Builder, record: &ManifestRecord) {
+    builder.set_manifest(record.manifest.as_str());
+    builder.set_signer(record.signer.as_str());
+    builder.set_signature(record.signature.as_str());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn test_addr() -> SocketAddr {
+        "127.0.0.1:8080"
+            .parse()
+            .expect("Hard-coded Go addr 127.0.0.1:8080 must be a valid SocketAddr")
+    }
+
     #[tokio::test]
     async fn test_go_client_creation() {
-        let addr: SocketAddr = "127.0.0.1:8080"
-            .parse()
-            .expect("Hard-coded Go addr 127.0.0.1:8080 must be a valid SocketAddr");
+        let addr = test_addr();
         let client = GoClient::new(addr);
         assert_eq!(client.addr, addr);
+        assert_eq!(client.stats().pool_size, 2);
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_calls_before_connect_fail_fast() {
+        let client = GoClient::new(test_addr());
+        let result = client.get_connected_peers().await;
+        assert!(matches!(result, Err(NetworkError::NotConnected)));
+        // Not connecting is a caller error, not a sign the Go node is down
+        assert_eq!(client.stats().failures, 0);
+    }
+
+    #[test]
+    fn test_circuit_opens_and_half_opens() {
+        let client = GoClient::with_pool_config(
+            test_addr(),
+            PoolConfig {
+                failure_threshold: 2,
+                open_duration: Duration::from_millis(20),
+                ..Default::default()
+            },
+        );
+
+        client.record_failure("test");
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+        client.record_failure("test");
+        assert_eq!(client.circuit_state(), CircuitState::Open);
+        assert!(matches!(
+            client.check_circuit(),
+            Err(NetworkError::CircuitOpen)
+        ));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(client.check_circuit().is_ok());
+        assert_eq!(client.circuit_state(), CircuitState::HalfOpen);
+
+        // A failed probe reopens immediately
+        client.record_failure("test");
+        assert_eq!(client.circuit_state(), CircuitState::Open);
+        assert_eq!(client.stats().circuit_trips, 2);
     }
 }
@@ -0,0 +1,706 @@
+/// gRPC front end for the node API (`--features grpc`)
+///
+/// Serves the operations of [`NodeApi`] over tonic for ecosystems without
+/// good Cap'n Proto support (see `proto/pangea.proto`). Calls are handed to
+/// the RPC server's `LocalSet` through an [`ApiBridge`] and answered by the
+/// same [`NodeServiceImpl`](crate::rpc::NodeServiceImpl) Cap'n Proto clients
+/// get, so gateway limits, namespace tokens and audit records apply alike.
+///
+/// Namespace tokens travel in the `pangea-token` request metadata. Failures
+/// carry their stable error code (see `error::codes`) in the
+/// `pangea-error-code` response metadata.
+///
+/// `WatchPeers` streams the node store's [`PeerEvent`]s; gateways do not
+/// serve it, so the node's peers stay private.
+///
+/// [`DaemonClient`] is the other end, for Rust programs working through a
+/// running daemon's gRPC API. The CLI uses the Cap'n Proto one in `rpc.rs`.
+use anyhow::Context;
+use futures::stream::{self, BoxStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::metadata::{AsciiMetadataValue, MetadataValue};
+use tonic::transport::{Channel, Server};
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+use crate::api::{
+    ApiBridge, ApiError, DownloadCall, DownloadReply, NodeApi, UploadCall, MAX_API_FILE_BYTES,
+};
+use crate::automated::{FileInfo, UploadResult};
+use crate::compute::{ComputeTask, TunnelKeyExchange};
+use crate::error::codes;
+use crate::gateway::GatewayRejection;
+use crate::lookup::SearchSource;
+use crate::namespace::DEFAULT_NAMESPACE;
+use crate::rpc::RpcServer;
+use crate::scheduler::TransferPriority;
+use crate::store::{NodeStore, PeerEvent};
+use crate::types::{GeoInfo, Node};
+
+/// Generated protobuf types and service stubs
+pub mod pb {
+    #![allow(clippy::all)]
+    tonic::include_proto!("pangea.v1");
+}
+
+use pb::pangea_node_client::PangeaNodeClient;
+use pb::pangea_node_server::{PangeaNode, PangeaNodeServer};
+
+/// Request metadata key carrying the caller's namespace token
+pub const TOKEN_METADATA_KEY: &str = "pangea-token";
+
+/// Response metadata key carrying the stable error code of a failure
+pub const ERROR_CODE_METADATA_KEY: &str = "pangea-error-code";
+
+/// Headroom over the largest inline file for the rest of a message
+const MESSAGE_OVERHEAD_BYTES: usize = 1024 * 1024;
+
+/// How long a client waits for the daemon to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serve the gRPC API on `addr` until the server fails
+///
+/// Peer events are streamed from `peers`; without it `WatchPeers` is
+/// refused.
+pub async fn serve(
+    addr: SocketAddr,
+    bridge: ApiBridge<RpcServer>,
+    peers: Option<Arc<NodeStore>>,
+) -> anyhow::Result<()> {
+    let service = PangeaNodeServer::new(GrpcService { bridge, peers })
+        .max_decoding_message_size(MAX_API_FILE_BYTES + MESSAGE_OVERHEAD_BYTES)
+        .max_encoding_message_size(MAX_API_FILE_BYTES + MESSAGE_OVERHEAD_BYTES);
+    info!("gRPC server listening on {}", addr);
+    Server::builder().add_service(service).serve(addr).await?;
+    Ok(())
+}
+
+/// tonic service answering through the RPC server's `LocalSet`
+pub struct GrpcService {
+    bridge: ApiBridge<RpcServer>,
+    peers: Option<Arc<NodeStore>>,
+}
+
+/// Client address and namespace token of a request
+fn caller<T>(request: &Request<T>) -> (IpAddr, Option<String>) {
+    let client = request
+        .remote_addr()
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let token = request
+        .metadata()
+        .get(TOKEN_METADATA_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    (client, token)
+}
+
+fn namespace_or_default(namespace: String) -> String {
+    if namespace.is_empty() {
+        DEFAULT_NAMESPACE.to_string()
+    } else {
+        namespace
+    }
+}
+
+fn priority(priority: i32) -> TransferPriority {
+    match pb::Priority::try_from(priority) {
+        Ok(pb::Priority::Background) => TransferPriority::Background,
+        _ => TransferPriority::Interactive,
+    }
+}
+
+/// Wire priority of a transfer; heal and prefetch run as background
+fn wire_priority(priority: TransferPriority) -> pb::Priority {
+    match priority {
+        TransferPriority::Interactive => pb::Priority::Interactive,
+        _ => pb::Priority::Background,
+    }
+}
+
+fn file_info(file: FileInfo) -> pb::FileInfo {
+    pb::FileInfo {
+        file_hash: file.file_hash,
+        file_name: file.file_name,
+        file_size: file.file_size as u64,
+        shard_count: file.shard_count as u32,
+        is_available: file.is_available,
+        timestamp: file.timestamp,
+    }
+}
+
+fn from_file_info(file: pb::FileInfo) -> FileInfo {
+    FileInfo {
+        file_hash: file.file_hash,
+        file_name: file.file_name,
+        file_size: file.file_size as usize,
+        shard_count: file.shard_count as usize,
+        is_available: file.is_available,
+        timestamp: file.timestamp,
+        source: SearchSource::LocalCache,
+    }
+}
+
+fn peer(node: Node) -> pb::Peer {
+    pb::Peer {
+        status: format!("{:?}", node.status).to_lowercase(),
+        latency_ms: node.latency_ms,
+        threat_score: node.threat_score,
+        domain: node
+            .geo
+            .as_ref()
+            .map(GeoInfo::failure_domain)
+            .unwrap_or_default(),
+        identity: node.identity.unwrap_or_default(),
+    }
+}
+
+fn peer_event(event: PeerEvent) -> pb::PeerEvent {
+    let peer_id = event.peer_id();
+    let (kind, peer, bad_shards) = match event {
+        PeerEvent::PeerAdded(node) => (pb::PeerEventKind::Added, Some(peer(node)), 0),
+        PeerEvent::PeerUpdated(node) => (pb::PeerEventKind::Updated, Some(peer(node)), 0),
+        PeerEvent::PeerOffline { .. } => (pb::PeerEventKind::Offline, None, 0),
+        PeerEvent::PeerBanned(record) => (pb::PeerEventKind::Banned, None, record.bad_shards),
+    };
+    pb::PeerEvent {
+        kind: kind.into(),
+        peer_id,
+        peer,
+        bad_shards,
+    }
+}
+
+/// gRPC status for an API failure
+fn status(error: ApiError) -> Status {
+    let message = error.to_string();
+    match error {
+        ApiError::Unavailable(_) => Status::unavailable(message),
+        ApiError::InvalidArgument(_) => Status::invalid_argument(message),
+        ApiError::Gateway(GatewayRejection::MethodDisabled(_)) => {
+            Status::permission_denied(message)
+        }
+        ApiError::Gateway(GatewayRejection::RateLimited(_)) => Status::resource_exhausted(message),
+        ApiError::Internal(_) => Status::internal(message),
+        ApiError::Failed(report) => {
+            let mut status = match report.code {
+                codes::UPLOAD_FILE_NOT_FOUND
+                | codes::UPLOAD_SESSION_NOT_FOUND
+                | codes::DOWNLOAD_FILE_NOT_FOUND => Status::not_found(message),
+                codes::NAMESPACE_INVALID | codes::UPLOAD_NOT_A_FILE => {
+                    Status::invalid_argument(message)
+                }
+                codes::NAMESPACE_FORBIDDEN => Status::permission_denied(message),
+                codes::NAMESPACE_QUOTA_EXCEEDED
+                | codes::COMPUTE_INSUFFICIENT_MEMORY
+                | codes::COMPUTE_QUEUE_FULL
+                | codes::COMPUTE_QUEUE_TIMEOUT => Status::resource_exhausted(message),
+                codes::UPLOAD_NO_PEERS | codes::UPLOAD_NO_CLASS_PEERS => {
+                    Status::unavailable(message)
+                }
+                code if (1000..2000).contains(&code) => Status::unavailable(message),
+                _ => Status::internal(message),
+            };
+            status
+                .metadata_mut()
+                .insert(ERROR_CODE_METADATA_KEY, MetadataValue::from(report.code));
+            status
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PangeaNode for GrpcService {
+    type WatchPeersStream = BoxStream<'static, Result<pb::PeerEvent, Status>>;
+
+    async fn upload(
+        &self,
+        request: Request<pb::UploadRequest>,
+    ) -> Result<Response<pb::UploadReply>, Status> {
+        let (client, token) = caller(&request);
+        let request = request.into_inner();
+        let call = UploadCall {
+            file_name: request.file_name,
+            data: request.data,
+            namespace: namespace_or_default(request.namespace),
+            priority: priority(request.priority),
+        };
+        let result = self
+            .bridge
+            .call(move |server| async move {
+                server
+                    .service_for(client)?
+                    .upload(token.as_deref(), call)
+                    .await
+            })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::UploadReply {
+            file_hash: result.file_hash,
+            manifest_json: result.manifest_json,
+            shard_count: result.shard_count as u32,
+            total_peers: result.total_peers as u32,
+        }))
+    }
+
+    async fn download(
+        &self,
+        request: Request<pb::DownloadRequest>,
+    ) -> Result<Response<pb::DownloadReply>, Status> {
+        let (client, token) = caller(&request);
+        let request = request.into_inner();
+        let call = DownloadCall {
+            file_hash: request.file_hash,
+            namespace: namespace_or_default(request.namespace),
+            priority: priority(request.priority),
+        };
+        let reply = self
+            .bridge
+            .call(move |server| async move {
+                server
+                    .service_for(client)?
+                    .download(token.as_deref(), call)
+                    .await
+            })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::DownloadReply {
+            file_hash: reply.file_hash,
+            file_name: reply.file_name,
+            data: reply.data,
+            shards_fetched: reply.shards_fetched as u32,
+            peers: reply.peers,
+        }))
+    }
+
+    async fn list_files(
+        &self,
+        request: Request<pb::ListFilesRequest>,
+    ) -> Result<Response<pb::FileList>, Status> {
+        let (client, token) = caller(&request);
+        let namespace = namespace_or_default(request.into_inner().namespace);
+        let files = self
+            .bridge
+            .call(move |server| async move {
+                server
+                    .service_for(client)?
+                    .list(token.as_deref(), &namespace)
+                    .await
+            })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::FileList {
+            files: files.into_iter().map(file_info).collect(),
+        }))
+    }
+
+    async fn search_files(
+        &self,
+        request: Request<pb::SearchFilesRequest>,
+    ) -> Result<Response<pb::FileList>, Status> {
+        let (client, token) = caller(&request);
+        let request = request.into_inner();
+        let namespace = namespace_or_default(request.namespace);
+        let pattern = request.pattern;
+        let files = self
+            .bridge
+            .call(move |server| async move {
+                server
+                    .service_for(client)?
+                    .search(token.as_deref(), &namespace, &pattern)
+                    .await
+            })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::FileList {
+            files: files.into_iter().map(file_info).collect(),
+        }))
+    }
+
+    async fn get_file_info(
+        &self,
+        request: Request<pb::GetFileInfoRequest>,
+    ) -> Result<Response<pb::GetFileInfoReply>, Status> {
+        let (client, token) = caller(&request);
+        let request = request.into_inner();
+        let namespace = namespace_or_default(request.namespace);
+        let file_hash = request.file_hash;
+        let file = self
+            .bridge
+            .call(move |server| async move {
+                server
+                    .service_for(client)?
+                    .info(token.as_deref(), &namespace, &file_hash)
+                    .await
+            })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::GetFileInfoReply {
+            file: file.map(file_info),
+        }))
+    }
+
+    async fn compute(
+        &self,
+        request: Request<pb::ComputeRequest>,
+    ) -> Result<Response<pb::ComputeReply>, Status> {
+        let (client, _) = caller(&request);
+        let request = request.into_inner();
+        let mut task = ComputeTask::new(request.job_id, 0, request.wasm_module, request.input_data);
+        if !request.function_name.is_empty() {
+            task.function_name = request.function_name;
+        }
+        if request.timeout_ms > 0 {
+            task.timeout_ms = request.timeout_ms;
+        }
+        task.tunnel = request.tunnel.map(|tunnel| TunnelKeyExchange {
+            submitter: tunnel.submitter,
+            executor: tunnel.executor,
+        });
+        let result = self
+            .bridge
+            .call(move |server| async move { server.service_for(client)?.compute(task).await })
+            .await
+            .map_err(status)?;
+        Ok(Response::new(pb::ComputeReply {
+            task_id: result.task_id,
+            result_data: result.result_data,
+            result_hash: result.result_hash,
+            execution_time_ms: result.execution_time_ms,
+            attestation_json: match &result.attestation {
+                Some(attestation) => serde_json::to_string(attestation)
+                    .map_err(|e| Status::internal(e.to_string()))?,
+                None => String::new(),
+            },
+        }))
+    }
+
+    async fn watch_peers(
+        &self,
+        request: Request<pb::WatchPeersRequest>,
+    ) -> Result<Response<Self::WatchPeersStream>, Status> {
+        let Some(store) = &self.peers else {
+            return Err(status(
+                GatewayRejection::MethodDisabled("watchPeers".into()).into(),
+            ));
+        };
+        let mut events = store.subscribe_events().await;
+        if request.into_inner().skip_replay {
+            events = events.skip_replay();
+        }
+        let stream = stream::unfold(events, |mut events| async move {
+            let event = events.recv().await?;
+            Some((Ok(peer_event(event)), events))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Client of a running daemon's gRPC API
+pub struct DaemonClient {
+    client: PangeaNodeClient<Channel>,
+    token: Option<AsciiMetadataValue>,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon serving gRPC on `addr`
+    ///
+    /// Fails when nothing answers there within a couple of seconds.
+    pub async fn connect(addr: SocketAddr) -> anyhow::Result<Self> {
+        let channel = Channel::from_shared(format!("http://{}", addr))?
+            .connect_timeout(CONNECT_TIMEOUT)
+            .connect()
+            .await
+            .with_context(|| format!("No daemon is serving gRPC on {}", addr))?;
+        let client = PangeaNodeClient::new(channel)
+            .max_decoding_message_size(MAX_API_FILE_BYTES + MESSAGE_OVERHEAD_BYTES)
+            .max_encoding_message_size(MAX_API_FILE_BYTES + MESSAGE_OVERHEAD_BYTES);
+        Ok(Self {
+            client,
+            token: None,
+        })
+    }
+
+    /// Present `token` for namespaced calls
+    pub fn with_token(mut self, token: &str) -> anyhow::Result<Self> {
+        self.token = Some(token.parse().context("Invalid namespace token")?);
+        Ok(self)
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(token) = &self.token {
+            request
+                .metadata_mut()
+                .insert(TOKEN_METADATA_KEY, token.clone());
+        }
+        request
+    }
+
+    pub async fn upload(&self, call: UploadCall) -> anyhow::Result<UploadResult> {
+        let request = self.request(pb::UploadRequest {
+            file_name: call.file_name,
+            data: call.data,
+            namespace: call.namespace,
+            priority: wire_priority(call.priority).into(),
+        });
+        let reply = self
+            .client
+            .clone()
+            .upload(request)
+            .await
+            .map_err(daemon_error)?
+            .into_inner();
+        Ok(UploadResult {
+            file_hash: reply.file_hash,
+            manifest_json: reply.manifest_json,
+            shard_count: reply.shard_count as usize,
+            total_peers: reply.total_peers as usize,
+            transfers: Vec::new(),
+            deduplicated: false,
+        })
+    }
+
+    pub async fn download(&self, call: DownloadCall) -> anyhow::Result<DownloadReply> {
+        let request = self.request(pb::DownloadRequest {
+            file_hash: call.file_hash,
+            namespace: call.namespace,
+            priority: wire_priority(call.priority).into(),
+        });
+        let reply = self
+            .client
+            .clone()
+            .download(request)
+            .await
+            .map_err(daemon_error)?
+            .into_inner();
+        Ok(DownloadReply {
+            file_hash: reply.file_hash,
+            file_name: reply.file_name,
+            data: reply.data,
+            shards_fetched: reply.shards_fetched as usize,
+            peers: reply.peers,
+        })
+    }
+
+    pub async fn list(&self, namespace: &str) -> anyhow::Result<Vec<FileInfo>> {
+        let request = self.request(pb::ListFilesRequest {
+            namespace: namespace.to_string(),
+        });
+        let files = self
+            .client
+            .clone()
+            .list_files(request)
+            .await
+            .map_err(daemon_error)?
+            .into_inner()
+            .files;
+        Ok(files.into_iter().map(from_file_info).collect())
+    }
+
+    pub async fn search(&self, namespace: &str, pattern: &str) -> anyhow::Result<Vec<FileInfo>> {
+        let request = self.request(pb::SearchFilesRequest {
+            namespace: namespace.to_string(),
+            pattern: pattern.to_string(),
+        });
+        let files = self
+            .client
+            .clone()
+            .search_files(request)
+            .await
+            .map_err(daemon_error)?
+            .into_inner()
+            .files;
+        Ok(files.into_iter().map(from_file_info).collect())
+    }
+
+    pub async fn info(&self, namespace: &str, file_hash: &str) -> anyhow::Result<Option<FileInfo>> {
+        let request = self.request(pb::GetFileInfoRequest {
+            namespace: namespace.to_string(),
+            file_hash: file_hash.to_string(),
+        });
+        let reply = self
+            .client
+            .clone()
+            .get_file_info(request)
+            .await
+            .map_err(daemon_error)?
+            .into_inner();
+        Ok(reply.file.map(from_file_info))
+    }
+}
+
+/// Error for a failed daemon call, with its stable error code if any
+fn daemon_error(status: Status) -> anyhow::Error {
+    match status
+        .metadata()
+        .get(ERROR_CODE_METADATA_KEY)
+        .and_then(|code| code.to_str().ok())
+    {
+        Some(code) => anyhow::anyhow!(
+            "Daemon refused the call: {} (code {})",
+            status.message(),
+            code
+        ),
+        None => anyhow::anyhow!("Daemon refused the call: {}", status.message()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NamespaceError;
+    use tonic::Code;
+
+    #[test]
+    fn test_status_carries_error_code() {
+        let forbidden = status(NamespaceError::Forbidden("photos".into()).into());
+        assert_eq!(forbidden.code(), Code::PermissionDenied);
+        let code = forbidden.metadata().get(ERROR_CODE_METADATA_KEY).unwrap();
+        assert_eq!(
+            code.to_str().unwrap(),
+            codes::NAMESPACE_FORBIDDEN.to_string()
+        );
+
+        let limited = status(GatewayRejection::RateLimited(Ipv4Addr::LOCALHOST.into()).into());
+        assert_eq!(limited.code(), Code::ResourceExhausted);
+        assert!(limited.metadata().get(ERROR_CODE_METADATA_KEY).is_none());
+        assert_eq!(
+            status(ApiError::Unavailable("off".into())).code(),
+            Code::Unavailable
+        );
+    }
+
+    /// Answers listings with one file and refuses everything else
+    struct OneFile;
+
+    #[tonic::async_trait]
+    impl PangeaNode for OneFile {
+        async fn upload(
+            &self,
+            _request: Request<pb::UploadRequest>,
+        ) -> Result<Response<pb::UploadReply>, Status> {
+            Err(status(ApiError::Unavailable("read only".into())))
+        }
+
+        async fn download(
+            &self,
+            _request: Request<pb::DownloadRequest>,
+        ) -> Result<Response<pb::DownloadReply>, Status> {
+            Err(status(NamespaceError::Forbidden("photos".into()).into()))
+        }
+
+        async fn list_files(
+            &self,
+            request: Request<pb::ListFilesRequest>,
+        ) -> Result<Response<pb::FileList>, Status> {
+            assert!(request.metadata().get(TOKEN_METADATA_KEY).is_some());
+            Ok(Response::new(pb::FileList {
+                files: vec![pb::FileInfo {
+                    file_hash: "abc".into(),
+                    file_name: request.into_inner().namespace,
+                    file_size: 3,
+                    shard_count: 1,
+                    is_available: true,
+                    timestamp: 0,
+                }],
+            }))
+        }
+
+        async fn search_files(
+            &self,
+            _request: Request<pb::SearchFilesRequest>,
+        ) -> Result<Response<pb::FileList>, Status> {
+            Ok(Response::new(pb::FileList { files: Vec::new() }))
+        }
+
+        async fn get_file_info(
+            &self,
+            _request: Request<pb::GetFileInfoRequest>,
+        ) -> Result<Response<pb::GetFileInfoReply>, Status> {
+            Ok(Response::new(pb::GetFileInfoReply { file: None }))
+        }
+
+        async fn compute(
+            &self,
+            _request: Request<pb::ComputeRequest>,
+        ) -> Result<Response<pb::ComputeReply>, Status> {
+            Err(Status::unimplemented("compute"))
+        }
+
+        type WatchPeersStream = BoxStream<'static, Result<pb::PeerEvent, Status>>;
+
+        async fn watch_peers(
+            &self,
+            _request: Request<pb::WatchPeersRequest>,
+        ) -> Result<Response<Self::WatchPeersStream>, Status> {
+            Err(Status::unimplemented("watchPeers"))
+        }
+    }
+
+    #[test]
+    fn test_peer_events_on_the_wire() {
+        let mut node = Node::new(7);
+        node.identity = Some("12D3KooW".into());
+        let added = peer_event(PeerEvent::PeerAdded(node));
+        assert_eq!(added.kind(), pb::PeerEventKind::Added);
+        let peer = added.peer.unwrap();
+        assert_eq!((peer.status.as_str(), peer.domain.as_str()), ("active", ""));
+        assert_eq!(peer.identity, "12D3KooW");
+
+        let banned = peer_event(PeerEvent::PeerBanned(crate::store::MisbehaviorRecord {
+            peer_id: 7,
+            bad_shards: 3,
+            banned: true,
+            ..Default::default()
+        }));
+        assert_eq!(banned.kind(), pb::PeerEventKind::Banned);
+        assert_eq!((banned.peer_id, banned.bad_shards), (7, 3));
+        assert!(banned.peer.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_daemon_client_calls_the_running_daemon() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(DaemonClient::connect(addr).await.is_err());
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(PangeaNodeServer::new(OneFile))
+                .serve(addr),
+        );
+        let mut client = None;
+        for _ in 0..100 {
+            if let Ok(connected) = DaemonClient::connect(addr).await {
+                client = Some(connected);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let client = client
+            .expect("daemon never came up")
+            .with_token("secret")
+            .unwrap();
+
+        let files = client.list("photos").await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file_name, "photos");
+        assert!(client.info("photos", "abc").await.unwrap().is_none());
+
+        let refused = client
+            .download(DownloadCall {
+                file_hash: "abc".into(),
+                namespace: "photos".into(),
+                priority: TransferPriority::Interactive,
+            })
+            .await
+            .unwrap_err();
+        assert!(refused
+            .to_string()
+            .contains(&codes::NAMESPACE_FORBIDDEN.to_string()));
+    }
+}
@@ -0,0 +1,356 @@
+/// Cryptographic node identities
+///
+/// A `u32` node ID is only a claim: any node can answer as peer 7, so a
+/// manifest listing shard locations by ID cannot tell the node that took a
+/// shard from one impersonating it. A node's identity is instead an Ed25519
+/// key, named by its libp2p [`PeerId`]. The QUIC certificate a node presents
+/// is made from that key (and the DHT runs under the same one), so a client
+/// expecting an identity checks the certificate key against it and the
+/// handshake signature under that key: only the holder of the private key
+/// can complete the handshake.
+///
+/// The Go node and the RPC schema still address peers by `u32`.
+/// [`PeerDirectory`] maps those legacy IDs to identities: the first verified
+/// connection to an ID (or a manifest naming it) binds the two, and a later
+/// claim of a different identity for a bound ID is refused.
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use rand::RngCore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::durability::{write_atomic, Durability};
+
+/// Default file name of the identity key inside the cache directory
+pub const IDENTITY_FILE: &str = "identity.json";
+
+/// Default file name of the legacy ID mapping inside the cache directory
+pub const PEER_DIRECTORY_FILE: &str = "peer-directory.json";
+
+/// PKCS#8 v1 encoding of an Ed25519 key (RFC 8410), followed by the seed
+const PKCS8_ED25519_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// SubjectPublicKeyInfo of an Ed25519 key, followed by the public key
+const SPKI_ED25519_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// On-disk form of an identity key
+#[derive(Serialize, Deserialize)]
+struct IdentityFile {
+    secret: String,
+}
+
+/// A node's Ed25519 identity key
+pub struct NodeIdentity {
+    signing: SigningKey,
+}
+
+impl NodeIdentity {
+    /// Generate a fresh identity
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self::from_secret(secret)
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        Self {
+            signing: SigningKey::from_bytes(&secret),
+        }
+    }
+
+    /// Load the identity stored at `path`, creating it on first use
+    pub fn load_or_generate(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let identity = Self::generate();
+            identity.save(path)?;
+            info!("Generated node identity {}", identity.peer_id());
+            return Ok(identity);
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read identity file {:?}", path))?;
+        let file: IdentityFile = serde_json::from_str(&content).context("Invalid identity file")?;
+        let mut secret = [0u8; 32];
+        hex::decode_to_slice(&file.secret, &mut secret).context("Invalid identity key")?;
+        Ok(Self::from_secret(secret))
+    }
+
+    /// Write the key to `path`, readable only by the owner
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(&IdentityFile {
+            secret: hex::encode(self.signing.to_bytes()),
+        })?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write identity file {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing.verifying_key()
+    }
+
+    /// The peer ID this identity is known by
+    pub fn peer_id(&self) -> PeerId {
+        peer_id_of(self.verifying_key().as_bytes()).expect("valid Ed25519 public key")
+    }
+
+    /// The same key as a libp2p keypair, for the DHT
+    pub fn libp2p_keypair(&self) -> libp2p::identity::Keypair {
+        libp2p::identity::Keypair::ed25519_from_bytes(self.signing.to_bytes())
+            .expect("32-byte Ed25519 secret")
+    }
+
+    /// A self-signed certificate for this key, and the key in PKCS#8 form
+    pub fn certificate(&self) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+        let mut pkcs8 = PKCS8_ED25519_PREFIX.to_vec();
+        pkcs8.extend_from_slice(&self.signing.to_bytes());
+        let pkcs8 = PrivatePkcs8KeyDer::from(pkcs8);
+        let key_pair = rcgen::KeyPair::from_pkcs8_der_and_sign_algo(&pkcs8, &rcgen::PKCS_ED25519)?;
+        let params = rcgen::CertificateParams::new(vec!["localhost".to_string()])?;
+        let cert = params.self_signed(&key_pair)?;
+        Ok((cert.der().clone(), PrivateKeyDer::Pkcs8(pkcs8)))
+    }
+}
+
+/// Peer ID of an Ed25519 public key
+pub fn peer_id_of(public_key: &[u8; 32]) -> Result<PeerId> {
+    let key = libp2p::identity::ed25519::PublicKey::try_from_bytes(public_key)
+        .map_err(|e| anyhow!("Invalid Ed25519 public key: {}", e))?;
+    Ok(libp2p::identity::PublicKey::from(key).to_peer_id())
+}
+
+/// Parse a peer ID as written in manifests and peer records
+pub fn parse_peer_id(text: &str) -> Result<PeerId> {
+    text.parse()
+        .map_err(|e| anyhow!("Invalid peer identity {:?}: {}", text, e))
+}
+
+/// The Ed25519 public key a certificate is issued for
+///
+/// Only certificates holding exactly one Ed25519 key are accepted, so a
+/// certificate cannot carry a second key (in an extension, say) to be
+/// mistaken for its own.
+pub fn certificate_public_key(cert: &CertificateDer<'_>) -> Option<[u8; 32]> {
+    let der = cert.as_ref();
+    let mut found = der
+        .windows(SPKI_ED25519_PREFIX.len())
+        .enumerate()
+        .filter(|(_, window)| *window == SPKI_ED25519_PREFIX)
+        .map(|(offset, _)| offset + SPKI_ED25519_PREFIX.len());
+    let start = found.next()?;
+    if found.next().is_some() {
+        return None;
+    }
+    der.get(start..start + 32)?.try_into().ok()
+}
+
+/// The identity a certificate belongs to
+pub fn certificate_peer_id(cert: &CertificateDer<'_>) -> Option<PeerId> {
+    peer_id_of(&certificate_public_key(cert)?).ok()
+}
+
+/// Check a TLS 1.3 handshake signature against a certificate's Ed25519 key
+pub fn verify_certificate_signature(
+    cert: &CertificateDer<'_>,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let public = certificate_public_key(cert).context("Certificate has no Ed25519 key")?;
+    let key = VerifyingKey::from_bytes(&public)
+        .map_err(|e| anyhow!("Invalid Ed25519 public key: {}", e))?;
+    let signature = Signature::from_slice(signature).context("Malformed Ed25519 signature")?;
+    key.verify_strict(message, &signature)
+        .context("Handshake signature does not match the certificate key")
+}
+
+/// Legacy `u32` node IDs and the identities bound to them
+///
+/// Persisted to `path` (when given) so bindings survive restarts.
+#[derive(Default)]
+pub struct PeerDirectory {
+    path: Option<PathBuf>,
+    bindings: RwLock<BTreeMap<u32, PeerId>>,
+}
+
+impl PeerDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the directory kept at `path`, empty if the file does not exist
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut bindings = BTreeMap::new();
+        if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read peer directory {:?}", path))?;
+            let stored: BTreeMap<u32, String> =
+                serde_json::from_str(&content).context("Invalid peer directory")?;
+            for (node_id, identity) in stored {
+                bindings.insert(node_id, parse_peer_id(&identity)?);
+            }
+        }
+        Ok(Self {
+            path: Some(path),
+            bindings: RwLock::new(bindings),
+        })
+    }
+
+    /// The identity bound to `node_id`
+    pub fn identity_of(&self, node_id: u32) -> Option<PeerId> {
+        self.bindings.read().get(&node_id).copied()
+    }
+
+    /// The legacy ID bound to `identity`, for calls into the Go node
+    pub fn node_id_of(&self, identity: &PeerId) -> Option<u32> {
+        self.bindings
+            .read()
+            .iter()
+            .find(|(_, bound)| *bound == identity)
+            .map(|(node_id, _)| *node_id)
+    }
+
+    /// Bind `node_id` to `identity`
+    ///
+    /// Fails if the ID is already bound to a different identity.
+    pub async fn bind(&self, node_id: u32, identity: PeerId) -> Result<()> {
+        let snapshot = {
+            let mut bindings = self.bindings.write();
+            match bindings.get(&node_id) {
+                Some(bound) if *bound == identity => return Ok(()),
+                Some(bound) => bail!(
+                    "Node {} claims identity {}, but is bound to {}",
+                    node_id,
+                    identity,
+                    bound
+                ),
+                None => {
+                    bindings.insert(node_id, identity);
+                }
+            }
+            info!("Bound node {} to identity {}", node_id, identity);
+            bindings
+                .iter()
+                .map(|(node_id, identity)| (*node_id, identity.to_base58()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        if let Some(path) = &self.path {
+            if let Err(e) =
+                write_atomic(path, &serde_json::to_vec(&snapshot)?, Durability::FsyncFile).await
+            {
+                warn!("Failed to save peer directory {:?}: {:#}", path, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Bind every (ID, identity) pair a manifest names
+    ///
+    /// Fails on the first identity conflicting with an existing binding,
+    /// which means the manifest (or a node it names) is lying.
+    pub async fn bind_all(&self, identities: &BTreeMap<u32, String>) -> Result<()> {
+        for (node_id, identity) in identities {
+            self.bind(*node_id, parse_peer_id(identity)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Identities of `node_ids`, as manifests store them; unbound IDs are left out
+    pub fn identities_of(&self, node_ids: impl IntoIterator<Item = u32>) -> BTreeMap<u32, String> {
+        let bindings = self.bindings.read();
+        node_ids
+            .into_iter()
+            .filter_map(|node_id| Some((node_id, bindings.get(&node_id)?.to_base58())))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.read().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+
+    #[test]
+    fn test_certificate_carries_identity_key() {
+        let identity = NodeIdentity::from_secret([7u8; 32]);
+        let (cert, _) = identity.certificate().unwrap();
+        assert_eq!(
+            certificate_public_key(&cert),
+            Some(*identity.verifying_key().as_bytes())
+        );
+        assert_eq!(certificate_peer_id(&cert), Some(identity.peer_id()));
+        // The DHT runs under the same peer ID
+        assert_eq!(
+            identity.libp2p_keypair().public().to_peer_id(),
+            identity.peer_id()
+        );
+
+        let message = b"TLS 1.3, server CertificateVerify";
+        let signature = identity.signing.sign(message).to_bytes();
+        assert!(verify_certificate_signature(&cert, message, &signature).is_ok());
+        let other = NodeIdentity::generate().signing.sign(message).to_bytes();
+        assert!(verify_certificate_signature(&cert, message, &other).is_err());
+    }
+
+    #[test]
+    fn test_identity_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(IDENTITY_FILE);
+        let first = NodeIdentity::load_or_generate(&path).unwrap();
+        let second = NodeIdentity::load_or_generate(&path).unwrap();
+        assert_eq!(first.peer_id(), second.peer_id());
+        assert_eq!(
+            parse_peer_id(&first.peer_id().to_base58()).unwrap(),
+            first.peer_id()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_directory_refuses_rebinding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PEER_DIRECTORY_FILE);
+        let honest = NodeIdentity::generate().peer_id();
+        let impostor = NodeIdentity::generate().peer_id();
+
+        let directory = PeerDirectory::open(&path).unwrap();
+        directory.bind(7, honest).await.unwrap();
+        directory.bind(7, honest).await.unwrap();
+        assert!(directory.bind(7, impostor).await.is_err());
+        assert_eq!(directory.node_id_of(&honest), Some(7));
+        assert_eq!(directory.identities_of([7, 8]).len(), 1);
+
+        // Bindings survive a restart, and manifests naming another
+        // identity for a bound ID are refused
+        let reopened = PeerDirectory::open(&path).unwrap();
+        assert_eq!(reopened.identity_of(7), Some(honest));
+        let forged = BTreeMap::from([(7, impostor.to_base58())]);
+        assert!(reopened.bind_all(&forged).await.is_err());
+    }
+}
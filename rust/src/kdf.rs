@@ -0,0 +1,121 @@
+/// Passphrase-based key derivation for CES encryption keys
+///
+/// Keys are derived with Argon2id. The salt and cost parameters travel with
+/// the file manifest, so the same passphrase yields the same key on any node.
+/// The Go side derives identical keys through `ces_derive_key` in the FFI.
+use anyhow::{anyhow, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Salt length for newly generated parameters
+pub const SALT_LEN: usize = 16;
+/// Default memory cost in KiB (19 MiB)
+pub const DEFAULT_MEMORY_KIB: u32 = 19 * 1024;
+/// Default number of passes
+pub const DEFAULT_ITERATIONS: u32 = 2;
+/// Default degree of parallelism
+pub const DEFAULT_PARALLELISM: u32 = 1;
+
+/// Key derivation function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KdfAlgorithm {
+    Argon2id,
+}
+
+/// Salt and cost parameters needed to re-derive a key from a passphrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: KdfAlgorithm,
+    /// Hex-encoded salt
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Default costs with a fresh random salt
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            algorithm: KdfAlgorithm::Argon2id,
+            salt: hex::encode(salt),
+            memory_kib: DEFAULT_MEMORY_KIB,
+            iterations: DEFAULT_ITERATIONS,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    /// Derive the 32-byte encryption key for `passphrase`
+    pub fn derive_key(&self, passphrase: &[u8]) -> Result<[u8; 32]> {
+        let salt = hex::decode(&self.salt).context("Invalid KDF salt")?;
+        derive_key(
+            passphrase,
+            &salt,
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+        )
+    }
+}
+
+/// Derive a 32-byte key with Argon2id (version 0x13)
+pub fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<[u8; 32]> {
+    let params = Params::new(memory_kib, iterations, parallelism, Some(32))
+        .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheap_params() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            ..KdfParams::generate()
+        }
+    }
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let params = cheap_params();
+        let a = params.derive_key(b"correct horse").unwrap();
+        let b = params.derive_key(b"correct horse").unwrap();
+        let c = params.derive_key(b"battery staple").unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        let other_salt = cheap_params();
+        assert_ne!(a, other_salt.derive_key(b"correct horse").unwrap());
+    }
+
+    #[test]
+    fn test_params_roundtrip_json() {
+        let params = KdfParams::generate();
+        let json = serde_json::to_string(&params).unwrap();
+        assert!(json.contains("\"argon2id\""));
+        let parsed: KdfParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, params);
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        assert!(derive_key(b"pw", b"short", 64, 1, 1).is_err());
+        assert!(derive_key(b"pw", b"saltsaltsalt", 0, 1, 1).is_err());
+    }
+}
@@ -0,0 +1,551 @@
+/// Network-wide filename search through a DHT keyword index
+///
+/// `search` only sees manifests in the local cache. With `[search]` enabled,
+/// registering a file also publishes the keywords of its name to the DHT:
+/// one record per keyword under `kw/<keyword>`, listing the files whose
+/// names contain it. Every entry is signed by its publisher's Ed25519 key
+/// (the node's replication key) over the keyword and the file details, so
+/// entries cannot be forged or replayed under another keyword. A record
+/// holds entries from many publishers; each publisher merges its entry
+/// into what the DHT returns and drops anything unsigned or expired.
+/// Records are capped in entries and bytes, oldest entries going first.
+///
+/// Searches look up the keywords of the pattern and merge the entries with
+/// local matches, tagging each hit with where it came from.
+///
+/// Every file is also published under [`RECENT_KEYWORD`], whose record
+/// holds the newest files across the network; `list --network` shows it.
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::Options;
+use ed25519_dalek::Signature;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::cache::FileManifest;
+use crate::replication::{parse_verifying_key, ReplicationKey};
+
+/// Prefix of keyword record keys in the DHT
+pub const KEYWORD_KEY_PREFIX: &[u8] = b"kw/";
+
+/// Shortest and longest keywords indexed, in characters
+pub const MIN_KEYWORD_CHARS: usize = 2;
+pub const MAX_KEYWORD_CHARS: usize = 32;
+
+/// Pseudo-keyword of the record listing recently published files
+/// (`tokenize` never yields it)
+pub const RECENT_KEYWORD: &str = "*recent";
+
+/// Largest record Kademlia stores by default
+const MAX_DHT_RECORD_BYTES: usize = 65536;
+
+/// Domain separation for keyword entry signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-keyword-v1";
+
+/// Keyword index settings (`[search]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Publish keywords of registered files and query them when searching
+    pub enabled: bool,
+    /// Keywords published per file name
+    pub max_keywords_per_file: usize,
+    /// Entries kept in one keyword record; the oldest are dropped
+    pub max_entries_per_keyword: usize,
+    /// Largest keyword record published
+    pub max_record_bytes: usize,
+    /// Entries older than this are ignored and dropped on merge
+    pub entry_ttl_secs: u64,
+    /// How long a search waits for each keyword record
+    pub query_timeout_secs: u64,
+    /// Publisher keys (hex) whose entries are shown; empty accepts any
+    /// correctly signed entry
+    pub trusted_publishers: Vec<String>,
+    /// Network files `list --network` shows at most
+    pub list_limit: usize,
+    /// Network files published longer ago than this are not listed
+    pub list_max_age_secs: u64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_keywords_per_file: 8,
+            max_entries_per_keyword: 64,
+            max_record_bytes: 16 * 1024,
+            entry_ttl_secs: 7 * 24 * 3600,
+            query_timeout_secs: 5,
+            trusted_publishers: Vec::new(),
+            list_limit: 50,
+            list_max_age_secs: 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    search: SearchConfig,
+}
+
+impl SearchConfig {
+    /// Load the `[search]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[search]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.search.validate()?;
+        Ok(file.search)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_keywords_per_file == 0 || self.max_entries_per_keyword == 0 {
+            bail!("search: max_keywords_per_file and max_entries_per_keyword must be at least 1");
+        }
+        if self.list_limit == 0 {
+            bail!("search: list_limit must be at least 1");
+        }
+        if self.max_record_bytes == 0 || self.max_record_bytes > MAX_DHT_RECORD_BYTES {
+            bail!(
+                "search: max_record_bytes must be 1..={}",
+                MAX_DHT_RECORD_BYTES
+            );
+        }
+        for key in &self.trusted_publishers {
+            parse_verifying_key(key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lowercase keywords of a file name (or search pattern), in order of
+/// appearance and without repeats
+///
+/// Names are split on anything that is not a letter or digit, so
+/// `Tax_Report-2024.pdf` yields `tax`, `report`, `2024` and `pdf`.
+pub fn tokenize(name: &str, limit: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    name.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| (MIN_KEYWORD_CHARS..=MAX_KEYWORD_CHARS).contains(&word.chars().count()))
+        .filter(|word| seen.insert(word.clone()))
+        .take(limit)
+        .collect()
+}
+
+/// One file listed under a keyword, signed by the node that published it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeywordEntry {
+    pub file_hash: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub namespace: String,
+    /// Unix seconds when the entry was signed
+    pub published_at: i64,
+    /// Publisher's Ed25519 public key, hex
+    pub publisher: String,
+    pub signature: Vec<u8>,
+}
+
+impl KeywordEntry {
+    /// Sign an entry for `manifest` under `keyword`
+    pub fn sign(keyword: &str, manifest: &FileManifest, key: &ReplicationKey, now: i64) -> Self {
+        let mut entry = Self {
+            file_hash: manifest.file_hash.clone(),
+            file_name: manifest.file_name.clone(),
+            file_size: manifest.file_size as u64,
+            namespace: manifest.namespace.clone(),
+            published_at: now,
+            publisher: key.public_key_hex(),
+            signature: Vec::new(),
+        };
+        entry.signature = key.sign(&entry.signed_bytes(keyword)).to_bytes().to_vec();
+        entry
+    }
+
+    /// Check the signature against the publisher key the entry names
+    pub fn verify(&self, keyword: &str) -> Result<()> {
+        let key = parse_verifying_key(&self.publisher)?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        key.verify_strict(&self.signed_bytes(keyword), &signature)
+            .map_err(|_| anyhow!("Bad keyword entry signature from {}", self.publisher))
+    }
+
+    fn signed_bytes(&self, keyword: &str) -> Vec<u8> {
+        let mut bytes = SIGNING_CONTEXT.to_vec();
+        for field in [
+            keyword,
+            self.file_hash.as_str(),
+            self.file_name.as_str(),
+            self.namespace.as_str(),
+            self.publisher.as_str(),
+        ] {
+            bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+        bytes.extend_from_slice(&self.file_size.to_be_bytes());
+        bytes.extend_from_slice(&self.published_at.to_be_bytes());
+        bytes
+    }
+}
+
+/// DHT value stored under a keyword
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeywordRecord {
+    pub keyword: String,
+    pub entries: Vec<KeywordEntry>,
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_DHT_RECORD_BYTES as u64)
+}
+
+impl KeywordRecord {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        codec()
+            .serialize(self)
+            .context("Failed to serialize keyword record")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        codec()
+            .deserialize(bytes)
+            .context("Malformed keyword record")
+    }
+}
+
+/// Builds and reads keyword records; the lookup service moves them through
+/// the DHT
+pub struct KeywordIndex {
+    config: SearchConfig,
+    key: Option<ReplicationKey>,
+    trusted: HashSet<String>,
+}
+
+impl KeywordIndex {
+    /// Index that can search; add a signing key to publish as well
+    pub fn new(config: SearchConfig) -> Result<Self> {
+        config.validate()?;
+        let trusted = config
+            .trusted_publishers
+            .iter()
+            .map(|key| key.to_lowercase())
+            .collect();
+        Ok(Self {
+            config,
+            key: None,
+            trusted,
+        })
+    }
+
+    /// Sign published entries with `key`
+    pub fn with_signing_key(mut self, key: ReplicationKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    /// Whether this index has a key to publish entries with
+    pub fn can_publish(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// How long to wait for a keyword record
+    pub fn query_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.query_timeout_secs)
+    }
+
+    /// Keywords published for, or searched by, `name`
+    pub fn keywords(&self, name: &str) -> Vec<String> {
+        tokenize(name, self.config.max_keywords_per_file)
+    }
+
+    /// Keywords a file is published under: those of its name, then
+    /// [`RECENT_KEYWORD`]
+    pub fn published_keywords(&self, name: &str) -> Vec<String> {
+        let mut keywords = self.keywords(name);
+        keywords.push(RECENT_KEYWORD.to_string());
+        keywords
+    }
+
+    /// DHT key of a keyword's record
+    pub fn dht_key(keyword: &str) -> Vec<u8> {
+        [KEYWORD_KEY_PREFIX, keyword.as_bytes()].concat()
+    }
+
+    /// Record for `keyword` with this node's entry for `manifest` merged
+    /// into the `existing` copies the DHT returned
+    ///
+    /// `None` if this index has no signing key.
+    pub fn merge(
+        &self,
+        keyword: &str,
+        existing: &[Vec<u8>],
+        manifest: &FileManifest,
+        now: i64,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(key) = &self.key else {
+            return Ok(None);
+        };
+        let ours = KeywordEntry::sign(keyword, manifest, key, now);
+        let mut entries: Vec<KeywordEntry> = self
+            .valid_entries(keyword, existing, now, false)
+            .into_iter()
+            .filter(|entry| entry.file_hash != ours.file_hash || entry.publisher != ours.publisher)
+            .collect();
+        entries.push(ours);
+        // Newest first, so the cap drops the oldest
+        entries.sort_by(|a, b| b.published_at.cmp(&a.published_at));
+        entries.truncate(self.config.max_entries_per_keyword);
+
+        let mut record = KeywordRecord {
+            keyword: keyword.to_string(),
+            entries,
+        };
+        loop {
+            let bytes = record.to_bytes()?;
+            if bytes.len() <= self.config.max_record_bytes {
+                return Ok(Some(bytes));
+            }
+            if record.entries.len() <= 1 {
+                bail!(
+                    "Keyword record for {:?} is {} bytes even with one entry (limit {})",
+                    keyword,
+                    bytes.len(),
+                    self.config.max_record_bytes
+                );
+            }
+            record.entries.pop();
+        }
+    }
+
+    /// Fresh, correctly signed entries from trusted publishers across the
+    /// copies of a keyword's record
+    pub fn entries(&self, keyword: &str, records: &[Vec<u8>], now: i64) -> Vec<KeywordEntry> {
+        self.valid_entries(keyword, records, now, true)
+    }
+
+    /// Recently published files from the copies of the [`RECENT_KEYWORD`]
+    /// record: newest first, one entry per file, bounded by `list_limit`
+    /// and `list_max_age_secs`
+    pub fn recent(&self, records: &[Vec<u8>], now: i64) -> Vec<KeywordEntry> {
+        let max_age = self.config.list_max_age_secs as i64;
+        let mut entries = self.entries(RECENT_KEYWORD, records, now);
+        entries.retain(|entry| now.saturating_sub(entry.published_at) <= max_age);
+        entries.sort_by(|a, b| b.published_at.cmp(&a.published_at));
+        let mut seen = HashSet::new();
+        entries.retain(|entry| seen.insert(entry.file_hash.clone()));
+        entries.truncate(self.config.list_limit);
+        entries
+    }
+
+    fn valid_entries(
+        &self,
+        keyword: &str,
+        records: &[Vec<u8>],
+        now: i64,
+        trusted_only: bool,
+    ) -> Vec<KeywordEntry> {
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        for bytes in records {
+            let record = match KeywordRecord::from_bytes(bytes) {
+                Ok(record) if record.keyword == keyword => record,
+                Ok(_) => continue,
+                Err(e) => {
+                    debug!("Skipping keyword record for {:?}: {:#}", keyword, e);
+                    continue;
+                }
+            };
+            for entry in record.entries {
+                let age = now.saturating_sub(entry.published_at);
+                let fresh = age <= self.config.entry_ttl_secs as i64;
+                let trusted = !trusted_only
+                    || self.trusted.is_empty()
+                    || self.trusted.contains(&entry.publisher.to_lowercase());
+                if !fresh || !trusted || entry.verify(keyword).is_err() {
+                    continue;
+                }
+                if seen.insert((entry.file_hash.clone(), entry.publisher.clone())) {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(file_hash: &str, file_name: &str) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: file_name.to_string(),
+            file_size: 42,
+            shard_count: 3,
+            parity_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Tax_Report-2024 (final).PDF", 8),
+            vec!["tax", "report", "2024", "final", "pdf"]
+        );
+        assert_eq!(tokenize("a b report REPORT", 8), vec!["report"]);
+        assert_eq!(tokenize("one two three", 2), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_records_merge_publishers_and_reject_forgeries() {
+        let now = 1_700_000_000;
+        let alice = KeywordIndex::new(SearchConfig::default())
+            .unwrap()
+            .with_signing_key(ReplicationKey::generate(1));
+        let bob = KeywordIndex::new(SearchConfig::default())
+            .unwrap()
+            .with_signing_key(ReplicationKey::generate(2));
+
+        let first = alice
+            .merge("report", &[], &manifest("aa", "report.pdf"), now)
+            .unwrap()
+            .unwrap();
+        let merged = bob
+            .merge(
+                "report",
+                &[first],
+                &manifest("bb", "old report.txt"),
+                now + 5,
+            )
+            .unwrap()
+            .unwrap();
+        let entries = alice.entries("report", &[merged.clone()], now + 10);
+        let hashes: Vec<_> = entries.iter().map(|e| e.file_hash.as_str()).collect();
+        assert_eq!(hashes, vec!["bb", "aa"]);
+
+        // Entries do not verify under another keyword or once altered
+        assert!(alice.entries("pdf", &[merged.clone()], now).is_empty());
+        let mut record = KeywordRecord::from_bytes(&merged).unwrap();
+        record.entries[0].file_name = "malware.exe".to_string();
+        let tampered = record.to_bytes().unwrap();
+        assert_eq!(alice.entries("report", &[tampered], now).len(), 1);
+
+        // Expired entries and untrusted publishers are dropped
+        let day = 24 * 3600;
+        assert!(alice
+            .entries("report", &[merged.clone()], now + 8 * day)
+            .is_empty());
+        let trusting_bob = KeywordIndex::new(SearchConfig {
+            trusted_publishers: vec![bob.key.as_ref().unwrap().public_key_hex()],
+            ..SearchConfig::default()
+        })
+        .unwrap();
+        assert!(!trusting_bob.can_publish());
+        let entries = trusting_bob.entries("report", &[merged], now);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file_hash, "bb");
+    }
+
+    #[test]
+    fn test_records_are_capped() {
+        let index = KeywordIndex::new(SearchConfig {
+            max_entries_per_keyword: 3,
+            ..SearchConfig::default()
+        })
+        .unwrap()
+        .with_signing_key(ReplicationKey::generate(1));
+        let mut record = Vec::new();
+        for i in 0..5 {
+            let file = manifest(&format!("{:02}", i), "notes.txt");
+            record = index
+                .merge("notes", &[record], &file, 1_700_000_000 + i)
+                .unwrap()
+                .unwrap();
+        }
+        let entries = index.entries("notes", &[record], 1_700_000_010);
+        let hashes: Vec<_> = entries.iter().map(|e| e.file_hash.as_str()).collect();
+        assert_eq!(hashes, vec!["04", "03", "02"]);
+
+        let tiny = KeywordIndex::new(SearchConfig {
+            max_record_bytes: 64,
+            ..SearchConfig::default()
+        })
+        .unwrap()
+        .with_signing_key(ReplicationKey::generate(1));
+        assert!(tiny
+            .merge("notes", &[], &manifest("aa", "notes.txt"), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_recent_files_are_bounded_in_count_and_age() {
+        let index = KeywordIndex::new(SearchConfig {
+            list_limit: 2,
+            list_max_age_secs: 3600,
+            ..SearchConfig::default()
+        })
+        .unwrap()
+        .with_signing_key(ReplicationKey::generate(1));
+        assert_eq!(
+            index.published_keywords("notes.txt"),
+            vec!["notes", "txt", RECENT_KEYWORD]
+        );
+
+        let now = 1_700_000_000;
+        let mut record = Vec::new();
+        for (hash, published) in [("old", now - 7200), ("aa", now - 60), ("bb", now - 30)] {
+            record = index
+                .merge(
+                    RECENT_KEYWORD,
+                    &[record],
+                    &manifest(hash, "x.txt"),
+                    published,
+                )
+                .unwrap()
+                .unwrap();
+        }
+        let recent: Vec<_> = index
+            .recent(&[record.clone()], now)
+            .into_iter()
+            .map(|entry| entry.file_hash)
+            .collect();
+        assert_eq!(recent, vec!["bb", "aa"]);
+
+        let newer = index
+            .merge(RECENT_KEYWORD, &[record], &manifest("cc", "y.txt"), now)
+            .unwrap()
+            .unwrap();
+        let recent: Vec<_> = index
+            .recent(&[newer], now)
+            .into_iter()
+            .map(|entry| entry.file_hash)
+            .collect();
+        assert_eq!(recent, vec!["cc", "bb"]);
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = SearchConfig::from_toml("[search]\nenabled = true\n").unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.max_entries_per_keyword, 64);
+        assert!(SearchConfig::from_toml("[search]\nmax_record_bytes = 100000\n").is_err());
+        assert!(SearchConfig::from_toml("[search]\ntrusted_publishers = [\"zz\"]\n").is_err());
+        assert!(SearchConfig::from_toml("[search]\nlist_limit = 0\n").is_err());
+    }
+}
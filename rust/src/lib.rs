@@ -4,60 +4,195 @@ pub mod schema_capnp {
     include!(concat!(env!("OUT_DIR"), "/schema_capnp.rs"));
 }
 
+pub mod api;
+pub mod audit;
 pub mod auto_heal;
 pub mod automated;
+pub mod blob;
+pub mod bloom;
+pub mod bootstrap;
+pub mod browser;
+pub mod bundle;
 pub mod cache;
 pub mod capabilities;
 pub mod ces;
+pub mod chunking;
+#[cfg(feature = "chaos")]
+pub mod chaos; // Fault injection for auto-heal tests
+pub mod clock;
 pub mod codecs; // Phase 1: Media codecs
+pub mod compression;
 pub mod compute; // Distributed Compute System
 pub mod dcdn;
+pub mod denylist;
 pub mod dht;
+pub mod disk_watchdog;
 pub mod dkg;
+pub mod dnsaddr;
 pub mod download;
+pub mod durability;
+pub mod envelope;
+pub mod error;
 pub mod ffi;
 pub mod file_detector;
 pub mod firewall;
+pub mod gateway;
+pub mod geo;
 pub mod go_client;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod identity;
+pub mod kdf;
+pub mod keywords;
+pub mod limits;
 pub mod lookup;
+pub mod loudness;
+pub mod manifest_log;
+pub mod manifest_sync;
 pub mod metrics; // Phase 1: Performance metrics
+pub mod metrics_history;
+pub mod namespace;
+pub mod negotiation;
 pub mod network;
+pub mod node;
+pub mod placement;
+pub mod popularity;
+pub mod probe;
+pub mod progress;
+pub mod provider;
+pub mod quality;
+pub mod query_cache;
+pub mod records;
+pub mod recording;
+pub mod refcount;
+pub mod replication;
+pub mod retry;
+pub mod rotation;
 pub mod rpc;
+pub mod scheduler;
+pub mod scrub;
+pub mod shm;
+pub mod simulation;
+pub mod singleflight;
+pub mod snapshot;
 pub mod storage;
 pub mod store;
 pub mod streaming; // Phase 2: Real-time voice/video streaming
+pub mod supervisor;
+pub mod telemetry;
+pub mod transport;
 pub mod types;
 pub mod upload; // Distributed Content Delivery Network
+pub mod upload_session;
 
 // Re-export commonly used types for ease of use
+pub use api::{
+    ApiBridge, ApiError, ApiWorker, DownloadCall, DownloadReply, NodeApi, Transfers, UploadCall,
+    UploadOptions,
+};
+pub use audit::{AuditConfig, AuditLog, AuditRecord};
 pub use automated::{
-    AutomatedDownloader, AutomatedUploader, DownloadResult, FileInfo, UploadResult,
+    AutomatedDownloader, AutomatedUploader, DownloadResult, ExportResult, FileInfo, UploadResult,
 };
-pub use cache::{Cache, CacheStats, FileManifest};
+pub use blob::{BlobId, BlobStats, BlobStore, BlobStoreConfig, BlobStoreFull, TieredBlobStore};
+pub use bloom::{ShardAdvertisement, ShardAvailabilityIndex, ShardBloomFilter};
+pub use bootstrap::{BootstrapConfig, BootstrapDiscovery, BootstrapList};
+pub use browser::{BrowserConfig, BrowserFile, FileBrowser};
+pub use bundle::{Bundle, ImportReport};
+pub use cache::{Cache, CacheStats, FileManifest, InboundPaused, RecoveryReport};
 pub use capabilities::HardwareCaps;
 pub use ces::CesPipeline;
-pub use codecs::{AudioConfig, AudioDecoder, AudioEncoder, VideoConfig}; // Phase 1: Media codecs
-pub use dht::{DhtNode, DualDht};
-pub use firewall::Firewall;
-pub use lookup::{DiscoveryResult, LookupResult, LookupService};
-pub use metrics::{LatencyTimer, MetricsTracker, PerformanceReport, ThroughputTracker}; // Phase 1: Metrics
-pub use network::QuicNode;
+pub use chunking::{ChunkingPolicy, ShardLayout};
+pub use clock::{ClockConfig, ClockSample, PeerClocks};
+pub use codecs::{
+    AudioConfig, AudioDecoder, AudioEncoder, AudioRendition, RenditionMetrics, Resampler,
+    TranscodeStats, TranscodedPacket, Transcoder, VideoConfig,
+}; // Phase 1: Media codecs
+pub use compression::{
+    CompressionControlConfig, CompressionController, CompressionDecision, CompressionStats,
+};
+pub use denylist::DenyList;
+pub use dht::{DhtNode, DualDht, SwarmConfig, SwarmLimits, SwarmStats};
+pub use disk_watchdog::{DiskEvent, DiskLevel, DiskWatchdog, DiskWatchdogConfig};
+pub use dnsaddr::{DnsBootstrap, DnsLookup};
+pub use durability::{Durability, DurabilityConfig};
+pub use envelope::{NodeKeypair, WrappedKey};
+pub use error::{
+    AdmissionError, DownloadError, ErrorCode, ErrorReport, LookupError, NamespaceError,
+    NetworkError, UploadError,
+};
+pub use firewall::{Firewall, FirewallPolicy, FirewallStats};
+pub use gateway::{Gateway, GatewayConfig, GatewayRejection, NodeMode};
+pub use geo::GeoDatabase;
+pub use identity::{NodeIdentity, PeerDirectory};
+pub use kdf::KdfParams;
+pub use keywords::{KeywordEntry, KeywordIndex, KeywordRecord, SearchConfig};
+pub use limits::{ConcurrencyConfig, ConcurrencyLimits, LimitName, LimitPermit, LimitStats};
+pub use lookup::{DiscoveryResult, LookupResult, LookupService, SearchHit, SearchSource};
+pub use loudness::{GainControl, LoudnessConfig, LoudnessNormalizer, LoudnessStats};
+pub use manifest_log::{ManifestLog, Replay};
+pub use manifest_sync::{
+    ManifestDigest, ManifestRecord, ManifestSync, ManifestSyncConfig, ManifestSyncOutcome,
+    ManifestSyncPeer,
+};
+pub use metrics::{
+    HedgeStats, HistogramExport, LatencyHistogram, LatencyTimer, MetricsTracker,
+    PerformanceReport, ThroughputTracker,
+}; // Phase 1: Metrics
+pub use metrics_history::{MetricsHistory, MetricsPoint};
+pub use namespace::{NamespaceConfig, NamespaceQuota, NamespaceStats, TokenScopes};
+pub use negotiation::{Capabilities, Negotiated, PeerCapabilities};
+pub use network::{ConnectionConfig, ConnectionStats, QuicNode, SessionMac};
+pub use node::{ClusterBuilder, NodeBuilder, NodeCluster, NodeHandle};
+pub use placement::{LocalParity, ParityGroup, PlacementPolicy};
+pub use popularity::{Popularity, PopularityConfig, PopularityTracker, Temperature};
+pub use probe::{BandwidthProber, ProbeConfig, ProbeSample};
+pub use progress::{ProgressHub, TransferDirection, TransferProgress};
+pub use provider::{
+    ExpiredShard, PeerAccount, ProviderEvent, ProviderStats, StorageClient, StorageProvider,
+    StorageRequest, StorageResponse,
+};
+pub use quality::{PathSample, QualityEstimator};
+pub use query_cache::{QueryCache, QueryCacheConfig, QueryCacheStats};
+pub use recording::{OggOpusWriter, Recording, RecordingConfig, RecordingMode, SessionRecorder};
+pub use records::{RecordConfig, RecordKind, RecordValidator, SignedRecord, ValidRecord};
+pub use refcount::RefIndex;
+pub use replication::{
+    ReplicationConfig, ReplicationKey, ReplicationRequest, ReplicationResponse, Replicator,
+    SyncOutcome, TrustedPeer, REPLICATION_MAGIC,
+};
+pub use retry::{RetryBudget, RetryPolicy};
+pub use rotation::{KeyRotation, KeyVersion, ShardKeys};
+pub use scheduler::{SchedulerConfig, TransferPriority, TransferScheduler};
+pub use scrub::{ScrubConfig, ScrubReport, ScrubStats, Scrubber};
+pub use shm::{ShardRing, ShmSlot};
+pub use simulation::{LinkConditions, NetworkProfile, SimulatedNetwork};
+pub use snapshot::{RestoreReport, SnapshotConfig, SnapshotInfo, Snapshotter, StateLayout};
 pub use storage::StorageEngine;
-pub use store::NodeStore;
+pub use store::{
+    BandwidthEstimate, MisbehaviorPolicy, MisbehaviorRecord, MisbehaviorStats, NodeStore,
+    PeerEvent, PeerEvents, PeerLatency, StorageClassConfig,
+};
 pub use streaming::{
-    AudioStreamReceiver, AudioStreamSender, StreamConfig, StreamPacket, StreamStats, StreamType,
-    StreamingSession,
+    AudioStreamReceiver, AudioStreamSender, SessionEvent, StreamAdaptation, StreamConfig,
+    StreamPacket, StreamStats, StreamType, StreamingSession,
 }; // Phase 2: Streaming
+pub use supervisor::{Supervisor, TaskHealth, TaskState};
+pub use telemetry::TelemetryConfig;
+pub use transport::{ShardTransfer, ShardTransport, TransportKind, TransportSelector};
 pub use types::{
     CesConfig, CompressionAlgorithm, ConnectionQuality, Message, Node, NodeStatus, PeerAddress,
+    ThroughputClass,
 };
+pub use upload_session::{UploadSession, UploadSessions};
 
 // Distributed Compute System exports
 pub use compute::{
-    ChunkInfo, ComputeCapacity, ComputeConfig, ComputeEngine, ComputeError, ComputeExecutor,
-    ComputeTask, ExecutionContext, JobManifest, MerkleTree, Metering, ResourceLimits,
-    ResourceUsage, ResultVerifier, SandboxConfig, SplitStrategy, TaskResult, TaskStatus,
-    VerificationMode, VerificationResult, WasmSandbox,
+    AdmissionStats, AttestationPolicy, ChunkInfo, ComputeCapacity, ComputeConfig, ComputeEngine,
+    ComputeError, ComputeExecutor, ComputeTask, ExecutionAttestation, ExecutionContext,
+    ExecutionReport, GraphProgress, JobGraph, JobManifest, MerkleTree, Metering, ResourceLimits,
+    ResourceUsage, ResultVerifier, SandboxConfig, SplitStrategy, Stage, TaskResult, TaskStatus,
+    TunnelKeyExchange, TunnelSession, VerificationMode, VerificationResult, WasmSandbox,
 };
 pub use dkg::{generate_shares, reconstruct_secret, DkgError, Share};
 
@@ -65,5 +200,5 @@ pub use dkg::{generate_shares, reconstruct_secret, DkgError, Share};
 pub use dcdn::{
     ChunkData, ChunkId, ChunkStore, DcdnConfig, FecAlgorithm, FecEngine, FecEngineConfig, FecGroup,
     P2PConfig, P2PEngine, PeerStats as DcdnPeerStats, QuicTransport, SignatureVerifier,
-    StorageStats, VerificationMetrics,
+    StorageStats, StreamManifest, VerificationMetrics,
 };
@@ -0,0 +1,349 @@
+/// Runtime-adjustable concurrency limits
+///
+/// Uploads, downloads, heal repairs and DHT queries each take a permit from
+/// a named limiter before they start, so a burst of requests queues instead
+/// of opening an unbounded number of transfers. Limits come from the
+/// `[concurrency]` config section and can be changed while the node runs
+/// (`setLimit` RPC); raising a limit wakes waiters at once, lowering one lets
+/// running work finish and holds new work back until usage drops below it.
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::Notify;
+use tracing::{debug, info};
+
+/// A named concurrency limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LimitName {
+    /// Files being uploaded
+    Uploads,
+    /// Files being downloaded
+    Downloads,
+    /// Auto-heal repairs in flight
+    Heal,
+    /// DHT record queries in flight
+    DhtQueries,
+}
+
+impl LimitName {
+    pub const ALL: [LimitName; 4] = [
+        LimitName::Uploads,
+        LimitName::Downloads,
+        LimitName::Heal,
+        LimitName::DhtQueries,
+    ];
+
+    fn index(&self) -> usize {
+        match self {
+            LimitName::Uploads => 0,
+            LimitName::Downloads => 1,
+            LimitName::Heal => 2,
+            LimitName::DhtQueries => 3,
+        }
+    }
+}
+
+impl fmt::Display for LimitName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LimitName::Uploads => "uploads",
+            LimitName::Downloads => "downloads",
+            LimitName::Heal => "heal",
+            LimitName::DhtQueries => "dht_queries",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for LimitName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uploads" => Ok(LimitName::Uploads),
+            "downloads" => Ok(LimitName::Downloads),
+            "heal" => Ok(LimitName::Heal),
+            "dht_queries" => Ok(LimitName::DhtQueries),
+            other => Err(format!(
+                "unknown limit '{}' (expected uploads, downloads, heal or dht_queries)",
+                other
+            )),
+        }
+    }
+}
+
+/// Concurrency limit settings (`[concurrency]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Files uploaded at once
+    pub uploads: usize,
+    /// Files downloaded at once
+    pub downloads: usize,
+    /// Auto-heal repairs at once
+    pub heal: usize,
+    /// DHT queries at once
+    pub dht_queries: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            uploads: 4,
+            downloads: 8,
+            heal: 2,
+            dht_queries: 16,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    concurrency: ConcurrencyConfig,
+}
+
+impl ConcurrencyConfig {
+    /// Load the `[concurrency]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[concurrency]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.concurrency.validate()?;
+        Ok(file.concurrency)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for name in LimitName::ALL {
+            if self.limit(name) == 0 {
+                bail!("concurrency: {} must be at least 1", name);
+            }
+        }
+        Ok(())
+    }
+
+    /// The configured limit for `name`
+    pub fn limit(&self, name: LimitName) -> usize {
+        match name {
+            LimitName::Uploads => self.uploads,
+            LimitName::Downloads => self.downloads,
+            LimitName::Heal => self.heal,
+            LimitName::DhtQueries => self.dht_queries,
+        }
+    }
+}
+
+/// Usage of one limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitStats {
+    pub name: LimitName,
+    /// Permits allowed at once
+    pub limit: usize,
+    /// Permits currently held
+    pub in_use: usize,
+    /// Most permits held at once since the node started
+    pub peak: usize,
+    /// Tasks waiting for a permit
+    pub waiting: usize,
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    limit: usize,
+    in_use: usize,
+    peak: usize,
+    waiting: usize,
+}
+
+#[derive(Debug, Default)]
+struct Limiter {
+    state: Mutex<LimiterState>,
+    notify: Notify,
+}
+
+/// Named limiters shared by every transfer path of a node
+#[derive(Debug)]
+pub struct ConcurrencyLimits {
+    limiters: [Limiter; 4],
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self::new(&ConcurrencyConfig::default())
+    }
+}
+
+impl ConcurrencyLimits {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        let limiters = LimitName::ALL.map(|name| Limiter {
+            state: Mutex::new(LimiterState {
+                limit: config.limit(name).max(1),
+                ..LimiterState::default()
+            }),
+            notify: Notify::new(),
+        });
+        Self { limiters }
+    }
+
+    /// Wait for a permit of `name`; it is returned when the permit drops
+    pub async fn acquire(&self, name: LimitName) -> LimitPermit<'_> {
+        let limiter = &self.limiters[name.index()];
+        let mut waiter: Option<WaitGuard<'_>> = None;
+
+        loop {
+            let notified = limiter.notify.notified();
+            {
+                let mut state = limiter.state.lock();
+                if state.in_use < state.limit {
+                    state.in_use += 1;
+                    state.peak = state.peak.max(state.in_use);
+                    if let Some(mut waiter) = waiter.take() {
+                        waiter.done = true;
+                        state.waiting -= 1;
+                    }
+                    return LimitPermit { limiter };
+                }
+                if waiter.is_none() {
+                    state.waiting += 1;
+                    waiter = Some(WaitGuard {
+                        limiter,
+                        done: false,
+                    });
+                    debug!("Waiting for a {} permit ({} in use)", name, state.in_use);
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Change a limit while the node runs
+    ///
+    /// Returns the previous limit. Permits already held are not revoked.
+    pub fn set_limit(&self, name: LimitName, limit: usize) -> Result<usize> {
+        if limit == 0 {
+            bail!("{} limit must be at least 1", name);
+        }
+        let limiter = &self.limiters[name.index()];
+        let previous = std::mem::replace(&mut limiter.state.lock().limit, limit);
+        limiter.notify.notify_waiters();
+        info!(
+            "Concurrency limit {} changed from {} to {}",
+            name, previous, limit
+        );
+        Ok(previous)
+    }
+
+    /// The current limit for `name`
+    pub fn limit(&self, name: LimitName) -> usize {
+        self.limiters[name.index()].state.lock().limit
+    }
+
+    /// Usage of one limit
+    pub fn stats_for(&self, name: LimitName) -> LimitStats {
+        let state = self.limiters[name.index()].state.lock();
+        LimitStats {
+            name,
+            limit: state.limit,
+            in_use: state.in_use,
+            peak: state.peak,
+            waiting: state.waiting,
+        }
+    }
+
+    /// Usage of every limit
+    pub fn stats(&self) -> Vec<LimitStats> {
+        LimitName::ALL
+            .iter()
+            .map(|name| self.stats_for(*name))
+            .collect()
+    }
+}
+
+/// A held permit; released on drop
+#[derive(Debug)]
+pub struct LimitPermit<'a> {
+    limiter: &'a Limiter,
+}
+
+impl Drop for LimitPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().in_use -= 1;
+        self.limiter.notify.notify_waiters();
+    }
+}
+
+/// Stops counting a waiter if the acquiring future is dropped
+struct WaitGuard<'a> {
+    limiter: &'a Limiter,
+    done: bool,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.limiter.state.lock().waiting -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_name_parse() {
+        for name in LimitName::ALL {
+            assert_eq!(name.to_string().parse::<LimitName>().unwrap(), name);
+        }
+        assert_eq!("Uploads".parse::<LimitName>().unwrap(), LimitName::Uploads);
+        assert!("bandwidth".parse::<LimitName>().is_err());
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = ConcurrencyConfig::from_toml("[concurrency]\nheal = 1\n").unwrap();
+        assert_eq!(config.heal, 1);
+        assert_eq!(config.uploads, 4);
+        assert!(ConcurrencyConfig::from_toml("[concurrency]\ndht_queries = 0\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limit_blocks_and_raising_wakes_waiters() {
+        let limits = Arc::new(ConcurrencyLimits::new(&ConcurrencyConfig {
+            uploads: 1,
+            ..ConcurrencyConfig::default()
+        }));
+        let first = limits.acquire(LimitName::Uploads).await;
+
+        let waiter = {
+            let limits = limits.clone();
+            tokio::spawn(async move {
+                let _permit = limits.acquire(LimitName::Uploads).await;
+                limits.stats_for(LimitName::Uploads).in_use
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(limits.stats_for(LimitName::Uploads).waiting, 1);
+        assert!(!waiter.is_finished());
+
+        // Raising the limit lets the waiter in while the first permit is held
+        assert_eq!(limits.set_limit(LimitName::Uploads, 2).unwrap(), 1);
+        assert_eq!(waiter.await.unwrap(), 2);
+        drop(first);
+
+        let stats = limits.stats_for(LimitName::Uploads);
+        assert_eq!((stats.limit, stats.in_use, stats.peak), (2, 0, 2));
+        assert_eq!(stats.waiting, 0);
+        assert!(limits.set_limit(LimitName::Uploads, 0).is_err());
+    }
+}
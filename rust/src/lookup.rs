@@ -1,12 +1,25 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 use std::sync::Arc;
-use tracing::{debug, info, warn};
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
 
-use crate::cache::{Cache, FileManifest};
+use crate::bloom::{ShardAdvertisement, ShardAvailabilityIndex, DEFAULT_FALSE_POSITIVE_RATE};
+use crate::cache::{Cache, FileManifest, MAX_MANIFEST_SHARDS};
 use crate::dht::DhtNode;
+use crate::error::LookupError;
+use crate::keywords::{KeywordEntry, KeywordIndex, RECENT_KEYWORD};
+use crate::limits::{ConcurrencyLimits, LimitName};
+use crate::query_cache::{QueryCache, QueryCacheStats};
+use crate::retry::{retry, RetryPolicy};
 use crate::store::NodeStore;
 
+/// How long a DHT lookup waits for copies of a manifest record
+const MANIFEST_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Result<T> = std::result::Result<T, LookupError>;
+
 /// Lookup result containing file information and availability
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LookupResult {
@@ -44,11 +57,79 @@ pub enum DiscoverySource {
     PeerQuery,
 }
 
+/// Where a search hit came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchSource {
+    /// A manifest in the local cache
+    LocalCache,
+    /// A DHT keyword entry signed by this publisher (hex Ed25519 key)
+    Dht { publisher: String },
+}
+
+impl fmt::Display for SearchSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchSource::LocalCache => write!(f, "local"),
+            SearchSource::Dht { publisher } => {
+                write!(f, "dht:{}", publisher.get(..8).unwrap_or(publisher))
+            }
+        }
+    }
+}
+
+/// File matched by a name search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub file_hash: String,
+    pub file_name: String,
+    pub file_size: usize,
+    pub namespace: String,
+    /// Upload time, or when a DHT entry was published
+    pub timestamp: i64,
+    /// Number of shards (0 when only known from the DHT)
+    pub shard_count: usize,
+    pub source: SearchSource,
+}
+
+impl SearchHit {
+    fn local(manifest: FileManifest) -> Self {
+        Self {
+            file_hash: manifest.file_hash,
+            file_name: manifest.file_name,
+            file_size: manifest.file_size,
+            namespace: manifest.namespace,
+            timestamp: manifest.timestamp,
+            shard_count: manifest.shard_count,
+            source: SearchSource::LocalCache,
+        }
+    }
+
+    fn published(entry: KeywordEntry) -> Self {
+        Self {
+            file_hash: entry.file_hash,
+            file_name: entry.file_name,
+            file_size: entry.file_size as usize,
+            namespace: entry.namespace,
+            timestamp: entry.published_at,
+            shard_count: 0,
+            source: SearchSource::Dht {
+                publisher: entry.publisher,
+            },
+        }
+    }
+}
+
 /// Lookup service for finding files in the network
+#[derive(Clone)]
 pub struct LookupService {
     cache: Arc<Cache>,
     dht: Option<Arc<tokio::sync::RwLock<DhtNode>>>,
     store: Arc<NodeStore>,
+    availability: Option<Arc<ShardAvailabilityIndex>>,
+    keywords: Option<Arc<KeywordIndex>>,
+    queries: Arc<QueryCache<FileManifest>>,
+    limits: Option<Arc<ConcurrencyLimits>>,
+    retry: RetryPolicy,
 }
 
 impl LookupService {
@@ -58,10 +139,109 @@ impl LookupService {
         dht: Option<Arc<tokio::sync::RwLock<DhtNode>>>,
         store: Arc<NodeStore>,
     ) -> Self {
-        Self { cache, dht, store }
+        Self {
+            cache,
+            dht,
+            store,
+            availability: None,
+            keywords: None,
+            queries: Arc::new(QueryCache::default()),
+            limits: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Override the retry policy for DHT operations
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Consult advertised shard filters when checking availability
+    pub fn with_availability_index(mut self, index: Arc<ShardAvailabilityIndex>) -> Self {
+        self.availability = Some(index);
+        self
+    }
+
+    /// Publish and search filename keywords in the DHT
+    pub fn with_keyword_index(mut self, index: Arc<KeywordIndex>) -> Self {
+        self.keywords = Some(index);
+        self
+    }
+
+    /// Share DHT lookup results with other lookup services
+    pub fn with_query_cache(mut self, queries: Arc<QueryCache<FileManifest>>) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Bound the DHT queries in flight by the node's `dht_queries` limit
+    pub fn with_concurrency_limits(mut self, limits: Arc<ConcurrencyLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Hit/miss counters of the DHT lookup cache
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.queries.stats()
+    }
+
+    /// Peers whose advertised filters claim to hold a shard
+    pub async fn probable_holders(&self, file_hash: &str, shard_index: usize) -> Vec<u32> {
+        match &self.availability {
+            Some(index) => index.probable_holders(file_hash, shard_index).await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Build a filter of locally cached shards and publish it to the DHT
+    pub async fn publish_shard_filter(&self, local_peer_id: u32) -> Result<ShardAdvertisement> {
+        let filter = self.cache.shard_filter(DEFAULT_FALSE_POSITIVE_RATE).await;
+        let advert = ShardAdvertisement::new(local_peer_id, filter);
+
+        if self.dht.is_some() {
+            self.put_dht_record(
+                ShardAdvertisement::dht_key(local_peer_id),
+                advert.to_bytes()?,
+            )
+            .await?;
+        }
+
+        if let Some(index) = &self.availability {
+            index.update(advert.clone()).await;
+        }
+
+        info!(
+            "Published shard filter with {} keys ({} bits)",
+            advert.filter.len(),
+            advert.filter.num_bits()
+        );
+        Ok(advert)
+    }
+
+    /// Ingest a shard filter advertisement received from the network
+    pub async fn ingest_shard_advertisement(&self, bytes: &[u8]) -> Result<bool> {
+        let advert = ShardAdvertisement::from_bytes(bytes)
+            .map_err(|e| LookupError::InvalidRecord(format!("{:#}", e)))?;
+        // Files looked up in vain may be found through the advertising peer
+        let resolved = self.queries.invalidate_misses(|file_hash| {
+            (0..MAX_MANIFEST_SHARDS)
+                .any(|shard_index| advert.filter.contains(file_hash, shard_index))
+        });
+        if resolved > 0 {
+            debug!(
+                "Shard filter of peer {} dropped {} cached lookup miss(es)",
+                advert.peer_id, resolved
+            );
+        }
+        match &self.availability {
+            Some(index) => Ok(index.update(advert).await),
+            None => Ok(false),
+        }
     }
 
     /// Lookup a file by hash
+    #[instrument(skip(self))]
     pub async fn lookup_file(&self, file_hash: &str) -> Result<Option<LookupResult>> {
         info!("Looking up file: {}", file_hash);
 
@@ -75,7 +255,10 @@ impl LookupService {
         if let Some(manifest) = self.lookup_in_dht(file_hash).await? {
             debug!("Found file via DHT lookup");
             // Cache the manifest for future lookups
-            self.cache.put_manifest(manifest.clone()).await?;
+            self.cache
+                .put_manifest(manifest.clone())
+                .await
+                .map_err(LookupError::Cache)?;
             return self.check_availability(manifest).await.map(Some);
         }
 
@@ -121,45 +304,125 @@ impl LookupService {
     }
 
     /// Search for files by name pattern
-    pub async fn search_files(&self, pattern: &str) -> Result<Vec<FileManifest>> {
+    ///
+    /// Cached manifests come first. With a keyword index, files published
+    /// to the DHT under the pattern's keywords follow, if their name also
+    /// contains the pattern and no cached manifest already matched them.
+    pub async fn search_files(&self, pattern: &str) -> Result<Vec<SearchHit>> {
         let all_manifests = self.cache.list_manifests().await;
         let pattern_lower = pattern.to_lowercase();
 
-        let matches: Vec<FileManifest> = all_manifests
+        let mut hits: Vec<SearchHit> = all_manifests
             .into_iter()
             .filter(|m| m.file_name.to_lowercase().contains(&pattern_lower))
+            .map(SearchHit::local)
             .collect();
+        let local = hits.len();
+
+        if let (Some(index), Some(_)) = (&self.keywords, &self.dht) {
+            let mut seen: HashSet<String> = hits.iter().map(|hit| hit.file_hash.clone()).collect();
+            let now = chrono::Utc::now().timestamp();
+            for keyword in index.keywords(pattern) {
+                let records = self
+                    .get_dht_records(KeywordIndex::dht_key(&keyword), index.query_timeout())
+                    .await;
+                for entry in index.entries(&keyword, &records, now) {
+                    if entry.file_name.to_lowercase().contains(&pattern_lower)
+                        && seen.insert(entry.file_hash.clone())
+                    {
+                        hits.push(SearchHit::published(entry));
+                    }
+                }
+            }
+        }
 
         info!(
-            "Search for '{}' returned {} results",
+            "Search for '{}' returned {} results ({} from the DHT)",
             pattern,
-            matches.len()
+            hits.len(),
+            hits.len() - local
+        );
+        Ok(hits)
+    }
+
+    /// Files other nodes recently published to the DHT, newest first
+    ///
+    /// Empty without a DHT and keyword index. Bounded by the index's
+    /// `list_limit` and `list_max_age_secs`.
+    pub async fn recent_network_files(&self) -> Result<Vec<SearchHit>> {
+        let (Some(index), Some(_)) = (&self.keywords, &self.dht) else {
+            return Ok(Vec::new());
+        };
+        let records = self
+            .get_dht_records(KeywordIndex::dht_key(RECENT_KEYWORD), index.query_timeout())
+            .await;
+        let now = chrono::Utc::now().timestamp();
+        Ok(index
+            .recent(&records, now)
+            .into_iter()
+            .map(SearchHit::published)
+            .collect())
+    }
+
+    /// Publish the keywords of a file's name to the DHT
+    ///
+    /// Each keyword's record is fetched and this node's signed entry merged
+    /// into it. Returns the number of records published; 0 without a DHT or
+    /// a keyword index that can sign.
+    pub async fn publish_keywords(&self, manifest: &FileManifest) -> Result<usize> {
+        let Some(index) = &self.keywords else {
+            return Ok(0);
+        };
+        if self.dht.is_none() || !index.can_publish() {
+            return Ok(0);
+        }
+        let now = chrono::Utc::now().timestamp();
+        let mut published = 0;
+        for keyword in index.published_keywords(&manifest.file_name) {
+            let key = KeywordIndex::dht_key(&keyword);
+            let existing = self
+                .get_dht_records(key.clone(), index.query_timeout())
+                .await;
+            if let Some(record) = index.merge(&keyword, &existing, manifest, now)? {
+                self.put_dht_record(key, record).await?;
+                published += 1;
+            }
+        }
+        debug!(
+            "Published {} keyword(s) for {}",
+            published, manifest.file_hash
         );
-        Ok(matches)
+        Ok(published)
     }
 
     /// Lookup file in DHT
+    ///
+    /// Results, including "not found", are cached (see [`QueryCache`]).
+    #[instrument(skip(self))]
     async fn lookup_in_dht(&self, file_hash: &str) -> Result<Option<FileManifest>> {
-        if let Some(dht) = &self.dht {
-            debug!("Querying DHT for file: {}", file_hash);
-
-            // Query DHT for providers
-            let mut dht_guard = dht.write().await;
-            // Convert file hash to bytes
-            let file_hash_bytes = file_hash.as_bytes().to_vec();
-            let _ = dht_guard.find_providers(file_hash_bytes);
-
-            // TODO: Query providers for manifest
-            // For now, return None - full implementation would involve:
-            // 1. Wait for DHT event with provider list
-            // 2. Connect to providers
-            // 3. Request manifest
-            // 4. Verify and return
-
+        if self.dht.is_none() {
             return Ok(None);
         }
+        if let Some(cached) = self.queries.get(file_hash) {
+            debug!(
+                "DHT lookup of {} answered from cache (found: {})",
+                file_hash,
+                cached.is_some()
+            );
+            return Ok(cached);
+        }
 
-        Ok(None)
+        debug!("Querying DHT for file: {}", file_hash);
+        // `register_file` publishes manifests under the file hash
+        let found = self
+            .get_dht_records(file_hash.as_bytes().to_vec(), MANIFEST_QUERY_TIMEOUT)
+            .await
+            .iter()
+            .filter_map(|value| std::str::from_utf8(value).ok())
+            .filter_map(|json| FileManifest::from_json(json).ok())
+            .find(|manifest| manifest.file_hash == file_hash);
+        self.queries.insert(file_hash, found.clone());
+        Ok(found)
     }
 
     /// Discover files from DHT
@@ -186,13 +449,36 @@ impl LookupService {
 
         for (shard_index, peer_id) in &manifest.shard_locations {
             // Check if peer is in our store and online
-            let is_online = if let Some(node) = self.store.get_node(*peer_id).await {
+            let mut is_online = if let Some(node) = self.store.get_node(*peer_id).await {
                 node.status == crate::types::NodeStatus::Active
             } else {
                 false
             };
 
+            // A fresh filter that rules the shard out is authoritative (no false negatives)
             if is_online {
+                if let Some(index) = &self.availability {
+                    if index
+                        .might_hold(*peer_id, &manifest.file_hash, *shard_index)
+                        .await
+                        == Some(false)
+                    {
+                        debug!(
+                            "Peer {} filter excludes shard {} of {}",
+                            peer_id, shard_index, manifest.file_hash
+                        );
+                        is_online = false;
+                    }
+                }
+            }
+
+            // Shards held locally (e.g. imported from a bundle) need no peer
+            if is_online
+                || self
+                    .cache
+                    .has_shard(&manifest.file_hash, *shard_index)
+                    .await
+            {
                 available_count += 1;
             }
 
@@ -247,25 +533,68 @@ impl LookupService {
     /// Register a file in the DHT
     pub async fn register_file(&self, manifest: &FileManifest) -> Result<()> {
         // First, cache it locally
-        self.cache.put_manifest(manifest.clone()).await?;
+        self.cache
+            .put_manifest(manifest.clone())
+            .await
+            .map_err(LookupError::Cache)?;
 
         // Then publish to DHT if available
-        if let Some(dht) = &self.dht {
+        if self.dht.is_some() {
             info!("Registering file in DHT: {}", manifest.file_hash);
-            let mut dht_guard = dht.write().await;
+            self.queries.invalidate(&manifest.file_hash);
             let key = manifest.file_hash.as_bytes().to_vec();
             // Store file metadata as the value
             let value = serde_json::to_vec(manifest)?;
-            dht_guard.put_record(key, value)?;
+            self.put_dht_record(key, value).await?;
+
+            // Search still works without keywords, so a failure is not fatal
+            if let Err(e) = self.publish_keywords(manifest).await {
+                warn!(
+                    "Failed to publish keywords for {}: {}",
+                    manifest.file_hash, e
+                );
+            }
         }
 
         Ok(())
     }
 
+    /// Publish a DHT record, retrying transient failures
+    ///
+    /// The DHT lock is released between attempts so the swarm can make progress.
+    async fn put_dht_record(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        let Some(dht) = &self.dht else {
+            return Ok(());
+        };
+        retry(&self.retry, "dht_put_record", || async {
+            dht.write()
+                .await
+                .put_record(key.clone(), value.clone())
+                .map_err(LookupError::Dht)
+        })
+        .await
+    }
+
+    /// Every copy of a DHT record found within `timeout`
+    async fn get_dht_records(&self, key: Vec<u8>, timeout: Duration) -> Vec<Vec<u8>> {
+        let Some(dht) = &self.dht else {
+            return Vec::new();
+        };
+        let _permit = match &self.limits {
+            Some(limits) => Some(limits.acquire(LimitName::DhtQueries).await),
+            None => None,
+        };
+        dht.write().await.get_record_values(key, timeout).await
+    }
+
     /// Remove a file from cache and DHT
     pub async fn unregister_file(&self, file_hash: &str) -> Result<bool> {
         // Remove from cache
-        let removed = self.cache.remove_manifest(file_hash).await?;
+        let removed = self
+            .cache
+            .remove_manifest(file_hash)
+            .await
+            .map_err(LookupError::Cache)?;
 
         // TODO: Remove from DHT (DHT doesn't have a direct remove API)
         // In practice, provider records expire automatically
@@ -302,6 +631,7 @@ mod tests {
             shard_locations: vec![(0, 1), (1, 2), (2, 3)],
             timestamp: Utc::now().timestamp(),
             ttl: 3600,
+            ..Default::default()
         };
 
         cache.put_manifest(manifest.clone()).await.unwrap();
@@ -330,6 +660,7 @@ mod tests {
                 shard_locations: vec![(0, 1), (1, 2), (2, 3)],
                 timestamp: Utc::now().timestamp(),
                 ttl: 3600,
+                ..Default::default()
             };
             cache.put_manifest(manifest).await.unwrap();
         }
@@ -337,9 +668,97 @@ mod tests {
         // Search for documents
         let results = lookup.search_files("document").await.unwrap();
         assert_eq!(results.len(), 5);
+        assert!(results
+            .iter()
+            .all(|hit| hit.source == SearchSource::LocalCache));
 
         // Search for specific document
         let results = lookup.search_files("document_3").await.unwrap();
         assert_eq!(results.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_availability_uses_shard_filters() {
+        use crate::bloom::ShardBloomFilter;
+        use crate::types::Node;
+
+        let temp_dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        let store = Arc::new(NodeStore::new());
+        for id in 1..=2 {
+            store.upsert_node(Node::new(id)).await;
+        }
+
+        let index = Arc::new(ShardAvailabilityIndex::new());
+        let lookup =
+            LookupService::new(cache.clone(), None, store).with_availability_index(index.clone());
+
+        let manifest = FileManifest {
+            file_hash: "filtered".to_string(),
+            file_name: "filtered.bin".to_string(),
+            file_size: 100,
+            shard_count: 2,
+            shard_locations: vec![(0, 1), (1, 2)],
+            timestamp: Utc::now().timestamp(),
+            ttl: 3600,
+            ..Default::default()
+        };
+        cache.put_manifest(manifest).await.unwrap();
+
+        // Peer 1 advertises shard 0, peer 2 advertises nothing relevant
+        let mut holds = ShardBloomFilter::new(10, 0.001);
+        holds.insert("filtered", 0);
+        index.update(ShardAdvertisement::new(1, holds)).await;
+        index
+            .update(ShardAdvertisement::new(2, ShardBloomFilter::new(10, 0.001)))
+            .await;
+
+        let result = lookup.lookup_file("filtered").await.unwrap().unwrap();
+        assert_eq!(result.available_shards, 1);
+        assert!(!result.is_complete);
+        assert_eq!(lookup.probable_holders("filtered", 0).await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_shard_advertisements_drop_cached_misses() {
+        use crate::bloom::ShardBloomFilter;
+
+        let temp_dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        let queries = Arc::new(QueryCache::default());
+        let lookup = LookupService::new(cache, None, Arc::new(NodeStore::new()))
+            .with_query_cache(queries.clone());
+        queries.insert("appeared", None);
+        queries.insert("still_missing", None);
+
+        let mut holds = ShardBloomFilter::new(10, 0.001);
+        holds.insert("appeared", 4);
+        let advert = ShardAdvertisement::new(7, holds).to_bytes().unwrap();
+        lookup.ingest_shard_advertisement(&advert).await.unwrap();
+
+        assert!(queries.get("appeared").is_none());
+        assert!(matches!(queries.get("still_missing"), Some(None)));
+        let stats = lookup.query_cache_stats();
+        assert_eq!((stats.invalidations, stats.negative_hits), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_shard_advertisements_rejected() {
+        use crate::bloom::ShardBloomFilter;
+
+        let temp_dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(temp_dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        let lookup = LookupService::new(cache, None, Arc::new(NodeStore::new()))
+            .with_availability_index(Arc::new(ShardAvailabilityIndex::new()));
+
+        // A truncated bit array and an empty filter would panic on lookup
+        for filter in [
+            ShardBloomFilter::from_raw_parts(vec![0; 1], 4096, 3),
+            ShardBloomFilter::from_raw_parts(Vec::new(), 0, 3),
+        ] {
+            let advert = ShardAdvertisement::new(7, filter).to_bytes().unwrap();
+            assert!(lookup.ingest_shard_advertisement(&advert).await.is_err());
+        }
+        assert!(lookup.probable_holders("file", 0).await.is_empty());
+    }
 }
@@ -0,0 +1,489 @@
+/// Loudness normalization and volume control for received audio
+///
+/// Voice levels vary widely between participants' microphones. Each
+/// received stream passes through a `LoudnessNormalizer`, which measures
+/// loudness the way EBU R128 / ITU-R BS.1770 does (K-weighted mean square
+/// over 400 ms blocks, gated at -70 LUFS and 10 LU below the running mean)
+/// and slowly steers a gain toward `target_lufs`. The listener's own gain
+/// and mute for the stream (`GainControl`) apply on top, and a peak limiter
+/// keeps the result from clipping.
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Blocks quieter than this are silence and never measured (BS.1770)
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Blocks this far below the ungated mean are pauses, not speech
+const RELATIVE_GATE_LU: f64 = 10.0;
+
+/// Measurement hop; four hops make one 400 ms block
+const HOP_MS: u32 = 100;
+const HOPS_PER_BLOCK: usize = 4;
+
+/// How fast the limiter lets go after a peak
+const LIMITER_RELEASE_DB_PER_SEC: f32 = 20.0;
+
+/// Bounds of a listener's per-stream gain
+const MIN_USER_GAIN_DB: f32 = -60.0;
+const MAX_USER_GAIN_DB: f32 = 20.0;
+
+/// Loudness settings of a stream's receive path (part of `StreamConfig`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoudnessConfig {
+    /// Steer the stream toward `target_lufs`
+    pub normalize: bool,
+    /// Integrated loudness to aim for; EBU R128 programme level is -23
+    pub target_lufs: f32,
+    /// Most a quiet stream is amplified, in dB
+    pub max_gain_db: f32,
+    /// Most a loud stream is attenuated, in dB
+    pub max_attenuation_db: f32,
+    /// Media time the integrated loudness is measured over
+    pub window_ms: u32,
+    /// How fast the normalizing gain may move, so speech doesn't pump
+    pub slew_db_per_sec: f32,
+    /// Hold peaks below `ceiling_dbfs`
+    pub limiter: bool,
+    pub ceiling_dbfs: f32,
+}
+
+impl Default for LoudnessConfig {
+    fn default() -> Self {
+        Self {
+            normalize: true,
+            target_lufs: -23.0,
+            max_gain_db: 20.0,
+            max_attenuation_db: 20.0,
+            window_ms: 3000,
+            slew_db_per_sec: 6.0,
+            limiter: true,
+            ceiling_dbfs: -1.0,
+        }
+    }
+}
+
+impl LoudnessConfig {
+    /// Pass audio through untouched, apart from the listener's gain
+    pub fn disabled() -> Self {
+        Self {
+            normalize: false,
+            limiter: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(ABSOLUTE_GATE_LUFS as f32..=0.0).contains(&self.target_lufs) {
+            bail!("loudness: target_lufs must be between -70 and 0");
+        }
+        if self.max_gain_db < 0.0 || self.max_attenuation_db < 0.0 {
+            bail!("loudness: max_gain_db and max_attenuation_db must not be negative");
+        }
+        if self.window_ms < HOP_MS * HOPS_PER_BLOCK as u32 {
+            bail!("loudness: window_ms must be at least 400");
+        }
+        if self.slew_db_per_sec <= 0.0 {
+            bail!("loudness: slew_db_per_sec must be positive");
+        }
+        if self.ceiling_dbfs > 0.0 {
+            bail!("loudness: ceiling_dbfs must not be above 0");
+        }
+        Ok(())
+    }
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Loudness of a block's K-weighted mean square, summed over channels
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(f64::MIN_POSITIVE).log10()
+}
+
+/// Listener-side gain and mute of one stream
+///
+/// Clones share the setting, so a UI can adjust a stream while its receiver
+/// runs in another task.
+#[derive(Debug, Clone, Default)]
+pub struct GainControl {
+    inner: Arc<GainState>,
+}
+
+#[derive(Debug, Default)]
+struct GainState {
+    /// f32 bits of the gain in dB
+    gain_db: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl GainControl {
+    /// Set the gain in dB, clamped to -60..=+20
+    pub fn set_gain_db(&self, gain_db: f32) {
+        let gain_db = gain_db.clamp(MIN_USER_GAIN_DB, MAX_USER_GAIN_DB);
+        self.inner
+            .gain_db
+            .store(gain_db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        f32::from_bits(self.inner.gain_db.load(Ordering::Relaxed))
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.inner.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.inner.muted.load(Ordering::Relaxed)
+    }
+
+    fn linear(&self) -> f32 {
+        if self.is_muted() {
+            0.0
+        } else {
+            db_to_linear(self.gain_db())
+        }
+    }
+}
+
+/// Loudness measurements and gain of one stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoudnessStats {
+    /// Gated loudness of the received audio over the window, before any
+    /// gain; `None` until a block of non-silent audio has arrived
+    pub loudness_lufs: Option<f32>,
+    /// Gain the normalizer currently applies, in dB
+    pub normalizing_gain_db: f32,
+    /// Frames the limiter turned down
+    pub limited_frames: u64,
+    /// Samples that still had to be clipped
+    pub clipped_samples: u64,
+}
+
+/// Second-order IIR section (transposed direct form II)
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting (head-effect shelf, then high-pass) for any rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Biquad::default()
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Biquad::default()
+    };
+
+    [shelf, high_pass]
+}
+
+/// AGC, listener gain and clipping protection for one received stream
+pub struct LoudnessNormalizer {
+    config: LoudnessConfig,
+    sample_rate: u32,
+    channels: usize,
+    control: GainControl,
+    filters: Vec<[Biquad; 2]>,
+    hop_frames: usize,
+    hop_sum: f64,
+    hop_filled: usize,
+    /// Mean square of the last hops, to form overlapping blocks
+    hops: VecDeque<f64>,
+    /// Power of the 400 ms blocks within the window
+    blocks: VecDeque<f64>,
+    max_blocks: usize,
+    gain_db: f32,
+    limiter_gain: f32,
+    /// Total gain applied at the end of the last frame
+    applied: f32,
+    stats: LoudnessStats,
+}
+
+impl LoudnessNormalizer {
+    pub fn new(config: LoudnessConfig, sample_rate: u32, channels: usize) -> Result<Self> {
+        config.validate()?;
+        if sample_rate == 0 {
+            bail!("loudness: sample rate must be positive");
+        }
+        let channels = channels.max(1);
+        let max_blocks = (config.window_ms / HOP_MS) as usize - HOPS_PER_BLOCK + 1;
+        Ok(Self {
+            sample_rate,
+            channels,
+            control: GainControl::default(),
+            filters: vec![k_weighting(sample_rate); channels],
+            hop_frames: (sample_rate * HOP_MS / 1000).max(1) as usize,
+            hop_sum: 0.0,
+            hop_filled: 0,
+            hops: VecDeque::with_capacity(HOPS_PER_BLOCK),
+            blocks: VecDeque::with_capacity(max_blocks),
+            max_blocks,
+            gain_db: 0.0,
+            limiter_gain: 1.0,
+            applied: 1.0,
+            stats: LoudnessStats::default(),
+            config,
+        })
+    }
+
+    /// Handle for the listener's gain and mute of this stream
+    pub fn gain_control(&self) -> GainControl {
+        self.control.clone()
+    }
+
+    pub fn stats(&self) -> LoudnessStats {
+        self.stats.clone()
+    }
+
+    /// Measure, normalize and limit one frame of interleaved PCM in place
+    pub fn process(&mut self, pcm: &mut [i16]) {
+        let frames = pcm.len() / self.channels;
+        if frames == 0 {
+            return;
+        }
+        self.measure(pcm);
+        let frame_secs = frames as f32 / self.sample_rate as f32;
+
+        // Normalizing gain, slewed toward the target; held through silence
+        if self.config.normalize {
+            if let Some(loudness) = self.stats.loudness_lufs {
+                let wanted = (self.config.target_lufs - loudness)
+                    .clamp(-self.config.max_attenuation_db, self.config.max_gain_db);
+                let step = self.config.slew_db_per_sec * frame_secs;
+                self.gain_db += (wanted - self.gain_db).clamp(-step, step);
+            }
+        } else {
+            self.gain_db = 0.0;
+        }
+        self.stats.normalizing_gain_db = self.gain_db;
+        let gain = db_to_linear(self.gain_db) * self.control.linear();
+
+        // Limiter: instant attack, gradual release
+        if self.config.limiter {
+            let peak = pcm.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0) as f32;
+            let ceiling = db_to_linear(self.config.ceiling_dbfs) * i16::MAX as f32;
+            let allowed = if peak * gain > ceiling {
+                ceiling / (peak * gain)
+            } else {
+                1.0
+            };
+            let released = (self.limiter_gain
+                * db_to_linear(LIMITER_RELEASE_DB_PER_SEC * frame_secs))
+            .min(1.0);
+            self.limiter_gain = allowed.min(released);
+            if self.limiter_gain < 1.0 {
+                self.stats.limited_frames += 1;
+            }
+        } else {
+            self.limiter_gain = 1.0;
+        }
+
+        // Ramp from the last frame's gain so changes don't click
+        let target = gain * self.limiter_gain;
+        let start = self.applied;
+        for (index, frame) in pcm.chunks_mut(self.channels).enumerate() {
+            let gain = start + (target - start) * (index + 1) as f32 / frames as f32;
+            for sample in frame {
+                let scaled = (*sample as f32 * gain).round();
+                if scaled > i16::MAX as f32 || scaled < i16::MIN as f32 {
+                    self.stats.clipped_samples += 1;
+                }
+                *sample = scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+        }
+        self.applied = target;
+    }
+
+    /// Feed K-weighted power into 100 ms hops and 400 ms blocks
+    fn measure(&mut self, pcm: &[i16]) {
+        let mut updated = false;
+        for frame in pcm.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let weighted = high_pass.process(shelf.process(*sample as f64 / 32768.0));
+                self.hop_sum += weighted * weighted;
+            }
+            self.hop_filled += 1;
+            if self.hop_filled == self.hop_frames {
+                if self.hops.len() == HOPS_PER_BLOCK {
+                    self.hops.pop_front();
+                }
+                self.hops.push_back(self.hop_sum / self.hop_frames as f64);
+                self.hop_sum = 0.0;
+                self.hop_filled = 0;
+                if self.hops.len() == HOPS_PER_BLOCK {
+                    if self.blocks.len() == self.max_blocks {
+                        self.blocks.pop_front();
+                    }
+                    self.blocks
+                        .push_back(self.hops.iter().sum::<f64>() / HOPS_PER_BLOCK as f64);
+                    updated = true;
+                }
+            }
+        }
+        if updated {
+            if let Some(loudness) = self.gated_loudness() {
+                self.stats.loudness_lufs = Some(loudness as f32);
+            }
+        }
+    }
+
+    /// Two-stage gated loudness of the blocks in the window
+    fn gated_loudness(&self) -> Option<f64> {
+        let mean = |threshold: f64| {
+            let (sum, count) = self
+                .blocks
+                .iter()
+                .filter(|power| lufs(**power) > threshold)
+                .fold((0.0, 0usize), |(sum, count), power| {
+                    (sum + power, count + 1)
+                });
+            (count > 0).then_some(sum / count as f64)
+        };
+        let ungated = mean(ABSOLUTE_GATE_LUFS)?;
+        let relative = lufs(ungated) - RELATIVE_GATE_LU;
+        mean(relative.max(ABSOLUTE_GATE_LUFS)).map(lufs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+    const FRAME: usize = 960;
+
+    /// `secs` of a 1 kHz mono tone at `amplitude` (full scale 1.0), run
+    /// through `normalizer` 20 ms at a time; returns the last frame
+    fn feed(normalizer: &mut LoudnessNormalizer, amplitude: f64, secs: usize) -> Vec<i16> {
+        let mut last = Vec::new();
+        for frame in 0..secs * 50 {
+            let mut pcm: Vec<i16> = (0..FRAME)
+                .map(|i| {
+                    let t = (frame * FRAME + i) as f64 / RATE as f64;
+                    (amplitude * 32767.0 * (2.0 * std::f64::consts::PI * 1000.0 * t).sin()) as i16
+                })
+                .collect();
+            normalizer.process(&mut pcm);
+            last = pcm;
+        }
+        last
+    }
+
+    fn peak(pcm: &[i16]) -> i32 {
+        pcm.iter().map(|s| (*s as i32).abs()).max().unwrap()
+    }
+
+    #[test]
+    fn test_measures_bs1770_loudness() {
+        // A 1 kHz sine 20 dB below full scale reads -23 LUFS on one channel
+        let mut normalizer = LoudnessNormalizer::new(LoudnessConfig::disabled(), RATE, 1).unwrap();
+        feed(&mut normalizer, 0.1, 2);
+        let loudness = normalizer.stats().loudness_lufs.unwrap();
+        assert!((loudness + 23.0).abs() < 0.5, "{}", loudness);
+
+        // Silence is gated out rather than measured
+        let mut silent = LoudnessNormalizer::new(LoudnessConfig::default(), RATE, 1).unwrap();
+        feed(&mut silent, 0.0, 2);
+        assert_eq!(silent.stats().loudness_lufs, None);
+        assert_eq!(silent.stats().normalizing_gain_db, 0.0);
+    }
+
+    #[test]
+    fn test_quiet_stream_is_raised_to_target() {
+        let mut normalizer = LoudnessNormalizer::new(LoudnessConfig::default(), RATE, 1).unwrap();
+        // About -37 LUFS, 14 dB under the target
+        let last = feed(&mut normalizer, 0.02, 8);
+        let stats = normalizer.stats();
+        assert!(
+            (stats.normalizing_gain_db - 14.0).abs() < 0.7,
+            "{:?}",
+            stats
+        );
+        let expected = 0.1 * 32767.0;
+        assert!((peak(&last) as f64 - expected).abs() < expected * 0.1);
+        assert_eq!(stats.clipped_samples, 0);
+    }
+
+    #[test]
+    fn test_listener_gain_is_limited_and_mutable() {
+        let config = LoudnessConfig {
+            normalize: false,
+            ..LoudnessConfig::default()
+        };
+        let mut normalizer = LoudnessNormalizer::new(config, RATE, 1).unwrap();
+        let control = normalizer.gain_control();
+        control.set_gain_db(12.0);
+
+        // +12 dB on a half-scale tone would clip; the limiter holds -1 dBFS
+        let last = feed(&mut normalizer, 0.5, 1);
+        let ceiling = (db_to_linear(-1.0) * i16::MAX as f32) as i32;
+        assert!(peak(&last) <= ceiling + 1);
+        assert!(peak(&last) > ceiling - 200);
+        let stats = normalizer.stats();
+        assert!(stats.limited_frames > 0);
+        assert_eq!(stats.clipped_samples, 0);
+
+        control.set_muted(true);
+        feed(&mut normalizer, 0.5, 1);
+        assert_eq!(peak(&feed(&mut normalizer, 0.5, 1)), 0);
+
+        control.set_gain_db(100.0);
+        assert_eq!(control.gain_db(), MAX_USER_GAIN_DB);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(LoudnessConfig::default().validate().is_ok());
+        let bad = LoudnessConfig {
+            ceiling_dbfs: 1.0,
+            ..LoudnessConfig::default()
+        };
+        assert!(bad.validate().is_err());
+        let short = LoudnessConfig {
+            window_ms: 200,
+            ..LoudnessConfig::default()
+        };
+        assert!(LoudnessNormalizer::new(short, RATE, 1).is_err());
+    }
+}
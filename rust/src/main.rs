@@ -1,8 +1,7 @@
 use clap::Parser;
 use pangea_ces::*;
 use std::sync::Arc;
-use tracing::{error, info, warn};
-use tracing_subscriber;
+use tracing::{info, warn};
 
 // Constants for cache configuration
 const DEFAULT_CACHE_MAX_ENTRIES: usize = 1000;
@@ -10,7 +9,13 @@ const DEFAULT_CACHE_SIZE_BYTES: usize = 100 * 1024 * 1024; // 100MB
 
 // Constants for display formatting
 const BYTES_PER_MB: f64 = 1_048_576.0;
-const TABLE_SEPARATOR_LEN: usize = 10 + 30 + 15 + 10 + 10 + 4; // Column widths + spacing
+const TABLE_SEPARATOR_LEN: usize = 10 + 30 + 15 + 10 + 10 + 14 + 5; // Column widths + spacing
+
+// How often the daemon drops DHT connections to newly banned peers
+const BAN_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+// How often a storage provider removes shards with expired leases
+const STORAGE_GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 
 #[derive(Parser, Debug)]
 #[clap(name = "pangea-rust-node")]
@@ -27,6 +32,15 @@ struct Args {
     #[clap(long, default_value = "127.0.0.1:8080")]
     rpc_addr: String,
 
+    /// gRPC server address serving the same API as the RPC server
+    /// (requires building with --features grpc)
+    #[clap(long)]
+    grpc_addr: Option<String>,
+
+    /// Run WASM compute tasks submitted over RPC/gRPC (daemon mode)
+    #[clap(long)]
+    compute: bool,
+
     /// Go node RPC address (for calling Go transport layer)
     #[clap(long, default_value = "127.0.0.1:8082")]
     go_addr: String,
@@ -39,10 +53,79 @@ struct Args {
     #[clap(long, default_value = "127.0.0.1:9091")]
     dht_addr: String,
 
-    /// Bootstrap peers for DHT (multiaddr format)
+    /// Bootstrap peers for DHT (multiaddr format; /dnsaddr and /dns* entries
+    /// are resolved, and re-resolved while running)
     #[clap(long)]
     bootstrap: Vec<String>,
 
+    /// Node config file (TOML) - read for the [telemetry], [compression], [chunking],
+    /// [storage_classes], [gateway], [replication], [audit], [snapshot], [connections],
+    /// [namespaces] and [popularity] sections
+    #[clap(long)]
+    config: Option<String>,
+
+    /// Bandwidth limit shared by all transfers in bytes/sec (0 = unlimited)
+    #[clap(long, default_value = "0")]
+    bandwidth_limit: u64,
+
+    /// Re-probe connected peers' bandwidth this often, in seconds (0 = only on connect)
+    #[clap(long, default_value = "300")]
+    probe_interval_secs: u64,
+
+    /// Startup profile: `gateway` serves downloads only, with per-client rate
+    /// limits (see [gateway]) and inbound peers restricted to the allowlist
+    #[clap(long, value_enum, default_value = "full")]
+    mode: NodeMode,
+
+    /// Write nothing to disk: a fresh identity and keys each run, manifests,
+    /// pins and shards kept in memory only (daemon mode; for CI and sandboxes)
+    #[clap(long)]
+    in_memory: bool,
+
+    /// Keep shards for other peers over QUIC and RPC (daemon mode)
+    #[clap(long)]
+    serve_storage: bool,
+
+    /// Space offered to other peers with --serve-storage (e.g. 500GB, 64GiB)
+    #[clap(long, default_value = "100GB", value_parser = provider::parse_byte_size)]
+    storage_budget: u64,
+
+    /// Hours a stored shard is kept without its uploader renewing the lease;
+    /// uploads are renewed four times per lease
+    #[clap(long, default_value = "168")]
+    storage_lease_hours: u64,
+
+    /// Persist per-minute metrics aggregates to this directory (daemon mode)
+    #[clap(long)]
+    metrics_history: Option<String>,
+
+    /// Only accept QUIC and DHT connections from allowlisted addresses (daemon mode)
+    #[clap(long)]
+    firewall_allowlist: bool,
+
+    /// ip2asn TSV or MaxMind .mmdb database for peer country/ASN (repeatable)
+    #[clap(long)]
+    geo_db: Vec<String>,
+
+    /// Dev mode: put/get shards through simulated peers with this link profile
+    #[clap(long, value_enum, value_name = "PROFILE")]
+    simulate_network: Option<NetworkProfile>,
+
+    /// Number of simulated peers for --simulate-network
+    #[clap(long, default_value = "8")]
+    simulated_peers: u32,
+
+    /// Run put, get, list, search and info in this process instead of
+    /// through the running daemon
+    #[clap(long)]
+    standalone: bool,
+
+    /// RPC address of the running daemon (its --rpc-addr) that put, get,
+    /// list, search and info go through; namespace tokens are read from
+    /// PANGEA_TOKEN
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    daemon_addr: String,
+
     /// Enable verbose logging
     #[clap(short, long)]
     verbose: bool,
@@ -77,6 +160,41 @@ enum Command {
         /// File to upload
         #[clap(value_name = "FILE")]
         file: String,
+
+        /// Transfer priority (interactive, background, heal, prefetch)
+        #[clap(long, default_value = "interactive")]
+        priority: TransferPriority,
+
+        /// Derive the encryption key from a passphrase (prompted, or PANGEA_PASSPHRASE)
+        #[clap(long)]
+        encrypt_with_passphrase: bool,
+
+        /// Also wrap the file key for this X25519 public key (hex, repeatable)
+        #[clap(long = "recipient", value_name = "PUBKEY")]
+        recipients: Vec<String>,
+
+        /// Namespace to store the file in
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
+
+        /// Only place shards on peers in this storage class (see [storage_classes])
+        #[clap(long, value_name = "CLASS")]
+        class: Option<String>,
+
+        /// Upload even if the file is already complete in the network
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Finish an interrupted `put`, sending only the shards no peer received
+    ResumeUpload {
+        /// Session ID printed when the upload started or failed
+        #[clap(value_name = "SESSION_ID")]
+        session_id: String,
+
+        /// Transfer priority (interactive, background, heal, prefetch)
+        #[clap(long, default_value = "interactive")]
+        priority: TransferPriority,
     },
 
     /// Automated download - just provide file hash, handles everything
@@ -88,16 +206,68 @@ enum Command {
         /// Output file path (optional - uses original filename if not provided)
         #[clap(short = 'o', long)]
         output: Option<String>,
+
+        /// Transfer priority (interactive, background, heal, prefetch)
+        #[clap(long, default_value = "interactive")]
+        priority: TransferPriority,
+
+        /// Namespace the file is stored in
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+
+    /// Write a file's manifest and encrypted shards to one bundle for offline transfer
+    Export {
+        /// File hash
+        #[clap(value_name = "HASH")]
+        hash: String,
+
+        /// Bundle file to write
+        #[clap(value_name = "BUNDLE")]
+        output: String,
+
+        /// Transfer priority (interactive, background, heal, prefetch)
+        #[clap(long, default_value = "interactive")]
+        priority: TransferPriority,
+
+        /// Namespace the file is stored in
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
     },
 
+    /// Verify a bundle and add its manifest and shards to the local cache
+    Import {
+        /// Bundle file to read
+        #[clap(value_name = "BUNDLE")]
+        bundle: String,
+
+        /// Also publish the manifest to the DHT
+        #[clap(long)]
+        register: bool,
+    },
+
+    /// Print this node's X25519 public key (share it with uploaders)
+    PublicKey,
+
     /// List all available files
-    List,
+    List {
+        /// Only list files in this namespace
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
+        /// Also list files other nodes recently published (needs [search])
+        #[clap(long)]
+        network: bool,
+    },
 
     /// Search files by name pattern
     Search {
         /// Search pattern
         #[clap(value_name = "PATTERN")]
         pattern: String,
+
+        /// Only search files in this namespace
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
     },
 
     /// Get file information
@@ -105,19 +275,199 @@ enum Command {
         /// File hash
         #[clap(value_name = "HASH")]
         hash: String,
+
+        /// Namespace the file is stored in
+        #[clap(long, default_value = namespace::DEFAULT_NAMESPACE)]
+        namespace: String,
+    },
+
+    /// Check every shard of a file against its manifest (local and remote copies)
+    /// and prove it still reconstructs from the minimum shard set
+    Verify {
+        /// File hash
+        #[clap(
+            value_name = "HASH",
+            required_unless_present = "all",
+            conflicts_with = "all"
+        )]
+        hash: Option<String>,
+
+        /// Verify every manifest in the local cache
+        #[clap(long)]
+        all: bool,
+
+        /// Immediately heal files with missing or corrupt shards
+        #[clap(long)]
+        heal: bool,
+    },
+
+    /// Start a new key version for a file; heal and scrub passes re-encrypt
+    /// its shards under it while the older versions stay valid
+    RotateKey {
+        /// File hash
+        #[clap(value_name = "HASH")]
+        hash: String,
+
+        /// Wrap the keys for this X25519 public key instead of the file's
+        /// current recipients (hex, repeatable; this node is always included)
+        #[clap(long = "recipient", value_name = "PUBKEY")]
+        recipients: Vec<String>,
+    },
+
+    /// Show recorded uploads and downloads
+    Audit {
+        /// Only this operation
+        #[clap(long, value_enum)]
+        operation: Option<audit::AuditOperation>,
+
+        /// Only this file hash
+        #[clap(long)]
+        hash: Option<String>,
+
+        /// Only transfers involving this peer
+        #[clap(long)]
+        peer: Option<u32>,
+
+        /// Only transfers requested by this requester (e.g. cli:alice)
+        #[clap(long)]
+        requester: Option<String>,
+
+        /// Only transfers since this Unix timestamp
+        #[clap(long)]
+        since: Option<i64>,
+
+        /// Only failed transfers
+        #[clap(long)]
+        failed: bool,
+
+        /// Show at most this many of the most recent records
+        #[clap(long, default_value = "50")]
+        limit: usize,
+
+        /// Print raw JSON lines
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the most requested files and whether they are hot, warm or cold
+    Top {
+        /// Number of files to show
+        #[clap(long, default_value = "20")]
+        limit: usize,
+
+        /// Print JSON lines
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Manage the hashes this node refuses to store
+    Deny {
+        #[clap(subcommand)]
+        action: DenyAction,
+    },
+
+    /// Back up or restore manifests, pins, keys and accounting data
+    Snapshot {
+        #[clap(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Print a bootstrap list of these peers signed by this node's identity,
+    /// for other nodes' [bootstrap] sources
+    BootstrapList {
+        /// Peer multiaddrs
+        #[clap(value_name = "MULTIADDR", required = true)]
+        peers: Vec<String>,
     },
 
     /// Run as daemon (default mode - runs RPC server for Python to call)
     Daemon,
 }
 
+impl Command {
+    /// Whether the command runs through the daemon unless --standalone
+    fn uses_daemon(&self) -> bool {
+        matches!(
+            self,
+            Command::Put { .. }
+                | Command::Get { .. }
+                | Command::List { .. }
+                | Command::Search { .. }
+                | Command::Info { .. }
+        )
+    }
+}
+
+#[derive(Parser, Debug)]
+enum SnapshotAction {
+    /// Write a snapshot of this node's state
+    Create {
+        /// Snapshot file (default: a timestamped file in the [snapshot] directory)
+        #[clap(value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Rehydrate this node's state from a snapshot
+    Restore {
+        /// Snapshot file
+        #[clap(value_name = "FILE")]
+        snapshot: String,
+
+        /// Overwrite state that already exists on this node
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum DenyAction {
+    /// Refuse to store a file or shard hash
+    Add {
+        /// File hash or shard content hash (SHA-256, hex)
+        #[clap(value_name = "HASH")]
+        hash: String,
+
+        /// Why the hash is denied (recorded in the audit log)
+        #[clap(long)]
+        reason: Option<String>,
+    },
+
+    /// Allow a previously denied hash again
+    Remove {
+        #[clap(value_name = "HASH")]
+        hash: String,
+    },
+
+    /// List denied hashes
+    List,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     // Initialize logging
     let log_level = if args.verbose { "debug" } else { "info" };
-    tracing_subscriber::fmt().with_env_filter(log_level).init();
+    let telemetry_config = match &args.config {
+        Some(path) => telemetry::TelemetryConfig::from_file(path)?,
+        None => telemetry::TelemetryConfig::default(),
+    };
+    let _telemetry = telemetry::init(log_level, &telemetry_config)?;
+
+    if args.in_memory && !matches!(args.command, None | Some(Command::Daemon)) {
+        anyhow::bail!("--in-memory only applies to the daemon");
+    }
+
+    // Work through the running daemon so its state is not opened twice
+    if let Some(command) = args
+        .command
+        .as_ref()
+        .filter(|command| !args.standalone && command.uses_daemon())
+    {
+        return tokio::task::LocalSet::new()
+            .run_until(run_through_daemon(command, &args))
+            .await;
+    }
 
     // Handle commands (upload/download) or run as daemon
     match args.command {
@@ -133,23 +483,132 @@ async fn main() -> anyhow::Result<()> {
         }) => {
             return handle_download(file, shards.clone(), &args).await;
         }
-        Some(Command::Put { ref file }) => {
-            return handle_automated_upload(file, &args).await;
+        Some(Command::Put {
+            ref file,
+            priority,
+            encrypt_with_passphrase,
+            ref recipients,
+            ref namespace,
+            ref class,
+            force,
+        }) => {
+            return handle_automated_upload(
+                file,
+                priority,
+                encrypt_with_passphrase,
+                recipients,
+                namespace,
+                class.as_deref(),
+                force,
+                &args,
+            )
+            .await;
+        }
+        Some(Command::ResumeUpload {
+            ref session_id,
+            priority,
+        }) => {
+            return handle_resume_upload(session_id, priority, &args).await;
         }
         Some(Command::Get {
             ref hash,
             ref output,
+            priority,
+            ref namespace,
+        }) => {
+            return handle_automated_download(hash, output.as_deref(), priority, namespace, &args)
+                .await;
+        }
+        Some(Command::Export {
+            ref hash,
+            ref output,
+            priority,
+            ref namespace,
+        }) => {
+            return handle_export(hash, output, priority, namespace, &args).await;
+        }
+        Some(Command::Import {
+            ref bundle,
+            register,
+        }) => {
+            return handle_import(bundle, register, &args).await;
+        }
+        Some(Command::PublicKey) => {
+            println!("{}", load_keypair(&args)?.public_key_hex());
+            return Ok(());
+        }
+        Some(Command::List {
+            ref namespace,
+            network,
         }) => {
-            return handle_automated_download(hash, output.as_deref(), &args).await;
+            return handle_list(namespace, network, &args).await;
         }
-        Some(Command::List) => {
-            return handle_list(&args).await;
+        Some(Command::Search {
+            ref pattern,
+            ref namespace,
+        }) => {
+            return handle_search(pattern, namespace, &args).await;
+        }
+        Some(Command::Info {
+            ref hash,
+            ref namespace,
+        }) => {
+            return handle_info(hash, namespace, &args).await;
+        }
+        Some(Command::Verify {
+            ref hash,
+            all,
+            heal,
+        }) => {
+            return handle_verify(hash.as_deref(), all, heal, &args).await;
+        }
+        Some(Command::RotateKey {
+            ref hash,
+            ref recipients,
+        }) => {
+            return handle_rotate_key(hash, recipients, &args).await;
+        }
+        Some(Command::Audit {
+            operation,
+            ref hash,
+            peer,
+            ref requester,
+            since,
+            failed,
+            limit,
+            json,
+        }) => {
+            let filter = audit::AuditFilter {
+                operation,
+                file_hash: hash.clone(),
+                peer,
+                requester: requester.clone(),
+                since,
+                until: None,
+                failed_only: failed,
+            };
+            return handle_audit(&filter, limit, json, &args);
+        }
+        Some(Command::Top { limit, json }) => {
+            return handle_top(limit, json, &args).await;
         }
-        Some(Command::Search { ref pattern }) => {
-            return handle_search(pattern, &args).await;
+        Some(Command::Deny { ref action }) => {
+            return handle_deny(action);
         }
-        Some(Command::Info { ref hash }) => {
-            return handle_info(hash, &args).await;
+        Some(Command::Snapshot { ref action }) => {
+            return handle_snapshot(action, &args).await;
+        }
+        Some(Command::BootstrapList { ref peers }) => {
+            let peers = peers
+                .iter()
+                .map(|peer| peer.parse())
+                .collect::<Result<Vec<libp2p::Multiaddr>, _>>()?;
+            let issued_at = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs();
+            let list = BootstrapList::sign(&load_identity(&args)?, &peers, issued_at)?;
+            println!("{}", list.to_json()?);
+            return Ok(());
         }
         Some(Command::Daemon) | None => {
             // Run as daemon (default)
@@ -177,29 +636,228 @@ async fn main() -> anyhow::Result<()> {
     info!("Initializing components...");
 
     // Node store
-    let store = Arc::new(store::NodeStore::new());
+    let mut store = store::NodeStore::new();
+    if !args.geo_db.is_empty() {
+        store = store.with_geo(Arc::new(GeoDatabase::open(&args.geo_db)?));
+        info!("✓ Geo/ASN enrichment enabled");
+    }
+    let store = Arc::new(store);
     let self_node = types::Node::new(args.node_id);
     store.upsert_node(self_node).await;
+    if let Some(path) = &args.config {
+        store
+            .apply_storage_classes(&store::StorageClassConfig::from_file(path)?)
+            .await;
+    }
     info!("✓ Node store initialized");
 
     // Firewall
-    let firewall = Arc::new(firewall::create_adaptive_firewall(&caps));
-    info!("✓ Firewall initialized (mode: {:?})", firewall.mode());
+    let gateway_mode = args.mode == NodeMode::Gateway;
+    if gateway_mode && args.serve_storage {
+        anyhow::bail!("--serve-storage cannot be used with --mode gateway");
+    }
+    if args.in_memory {
+        if args.serve_storage {
+            anyhow::bail!("--serve-storage keeps other peers' shards on disk and cannot be used with --in-memory");
+        }
+        if args.metrics_history.is_some() {
+            anyhow::bail!("--metrics-history cannot be used with --in-memory");
+        }
+        warn!("⚠️  In-memory mode: nothing is written to disk; this node's identity, keys, manifests and cached shards are lost when it stops");
+    }
+    // Gateways only talk to peers the operator listed
+    let policy = if args.firewall_allowlist || gateway_mode {
+        firewall::FirewallPolicy::Allowlist
+    } else {
+        firewall::FirewallPolicy::Open
+    };
+    let firewall = Arc::new(firewall::create_adaptive_firewall(&caps).with_policy(policy));
+    info!(
+        "✓ Firewall initialized (mode: {:?}, policy: {:?})",
+        firewall.mode(),
+        firewall.policy()
+    );
 
     // Allow localhost for testing
     firewall.allow_ip("127.0.0.1".parse()?).await;
 
+    // Storage provider
+    let storage_lease = std::time::Duration::from_secs(args.storage_lease_hours.max(1) * 60 * 60);
+    let (provider_events_tx, mut provider_events) = tokio::sync::mpsc::unbounded_channel();
+    let durability = durability_config(&args)?;
+    // Peers' clock offsets, measured as they connect
+    let clock = match &args.config {
+        Some(path) => ClockConfig::from_file(path)?,
+        None => ClockConfig::default(),
+    };
+    let clocks = Arc::new(PeerClocks::new(clock));
+    // Formats each peer reads, negotiated as they connect
+    let capabilities = Arc::new(PeerCapabilities::new(Capabilities::local(args.node_id)));
+    let storage_provider = if args.serve_storage {
+        let dir = std::path::Path::new(&get_cache_dir()).join("provider");
+        let provider = Arc::new(
+            StorageProvider::open(&dir, args.storage_budget)
+                .await?
+                .with_lease_duration(storage_lease)
+                .with_events(provider_events_tx)
+                .with_durability(durability.level)
+                .with_lease_grace(clocks.tolerance()),
+        );
+        info!(
+            "✓ Serving storage from {:?} (budget: {}, lease: {}h)",
+            dir,
+            progress::format_bytes(args.storage_budget),
+            args.storage_lease_hours.max(1)
+        );
+        Some(provider)
+    } else {
+        None
+    };
+    // Background tasks are restarted if they fail and stopped in reverse order
+    let supervisor = Arc::new(Supervisor::new());
+    if let Some(provider) = storage_provider.clone() {
+        supervisor.watch("storage-gc", move || {
+            provider.clone().spawn_gc(STORAGE_GC_INTERVAL)
+        });
+    }
+
+    // Shard, chunk and compiled module bytes share one budget
+    let blob_config = match &args.config {
+        Some(path) => BlobStoreConfig::from_file(path)?,
+        None => BlobStoreConfig::default(),
+    };
+    let blobs: Arc<dyn BlobStore> = if args.in_memory {
+        Arc::new(blob_config.open_in_memory()?)
+    } else {
+        Arc::new(blob_config.open(get_cache_dir())?)
+    };
+
+    let cache = Arc::new(open_cache(&args, &get_cache_dir())?.with_blob_store(blobs.clone()));
+    if durability.recover_on_start {
+        cache.recover().await?;
+    }
+    cache.load_persisted_manifests().await?;
+    if let Some(popularity) = cache.popularity().cloned() {
+        supervisor.watch("popularity-saver", move || popularity.clone().spawn_saver());
+    }
+    // Evict from the cache as its volume fills up; nothing is on disk with
+    // --in-memory
+    let disk_watchdog = match &args.config {
+        Some(path) => DiskWatchdogConfig::from_file(path)?,
+        None => DiskWatchdogConfig::default(),
+    };
+    if disk_watchdog.enabled && !args.in_memory {
+        let watchdog = Arc::new(DiskWatchdog::new(
+            cache.clone(),
+            get_cache_dir(),
+            disk_watchdog,
+        ));
+        supervisor.watch("disk-watchdog", move || watchdog.clone().spawn());
+    }
+
+    // Manifest replication
+    let replication = match &args.config {
+        Some(path) => ReplicationConfig::from_file(path)?,
+        None => ReplicationConfig::default(),
+    };
+    let replicator = if replication.enabled {
+        let key = if args.in_memory {
+            warn!("In-memory mode: the replication key is new this run; trusted nodes need its public key again");
+            ReplicationKey::generate(args.node_id)
+        } else {
+            ReplicationKey::load_or_generate(
+                args.node_id,
+                replication.key_file_or(get_cache_dir()),
+            )?
+        };
+        let replicator = Arc::new(
+            Replicator::new(&replication, key, cache.clone())?
+                .with_peer_clocks(clocks.clone())
+                .with_peer_capabilities(capabilities.clone()),
+        );
+        info!(
+            "✓ Replicating manifests with nodes {:?} (public key: {})",
+            replicator.trusted_peers(),
+            replicator.public_key_hex()
+        );
+        Some(replicator)
+    } else {
+        None
+    };
+
     // QUIC network
     let p2p_addr: std::net::SocketAddr = args.p2p_addr.parse()?;
-    let network = Arc::new(network::QuicNode::new(args.node_id, p2p_addr).await?);
-    info!("✓ QUIC network initialized on {}", p2p_addr);
+    let connections = match &args.config {
+        Some(path) => ConnectionConfig::from_file(path)?,
+        None => ConnectionConfig::default(),
+    };
+    let identity = load_identity(&args)?;
+    let keypair = Arc::new(load_keypair(&args)?);
+    let directory = open_peer_directory(&args)?;
+    let mut quic = network::QuicNode::new(args.node_id, p2p_addr)
+        .await?
+        .with_identity(&identity)?
+        .with_peer_directory(directory.clone())
+        .with_firewall(firewall.clone())
+        .with_connection_config(connections)
+        .with_peer_clocks(clocks.clone())
+        .with_peer_capabilities(capabilities.clone())
+        .with_node_store(store.clone());
+    if let Some(provider) = &storage_provider {
+        quic = quic.with_request_handler(provider::REQUEST_MAGIC, provider.clone());
+    }
+    if let Some(replicator) = &replicator {
+        quic = quic.with_request_handler(REPLICATION_MAGIC, replicator.clone());
+    }
+    // Peers without a Go node send and fetch shards over QUIC
+    let quic = Arc::new(transport::ShardServer::new(cache.clone())).register(quic);
+    let network = Arc::new(quic);
+    {
+        let network = network.clone();
+        supervisor.watch("connection-manager", move || {
+            network.clone().spawn_connection_manager()
+        });
+    }
+    info!(
+        "✓ QUIC network initialized on {} (identity {})",
+        p2p_addr,
+        network.local_identity()
+    );
 
-    // DHT node
-    let bootstrap_peers: Vec<libp2p::Multiaddr> = args
-        .bootstrap
-        .iter()
-        .filter_map(|s| s.parse().ok())
-        .collect();
+    // Scheduled state snapshots
+    let snapshot = match &args.config {
+        Some(path) => SnapshotConfig::from_file(path)?,
+        None => SnapshotConfig::default(),
+    };
+    if snapshot.enabled && args.in_memory {
+        warn!("In-memory mode: [snapshot] is ignored");
+    } else if snapshot.enabled {
+        let cache_dir = get_cache_dir();
+        let snapshotter = Arc::new(Snapshotter::new(
+            args.node_id,
+            state_layout(&args, &cache_dir)?,
+            cache.clone(),
+        ));
+        supervisor.watch("snapshots", move || {
+            snapshotter.clone().spawn(&snapshot, &cache_dir)
+        });
+    }
+    if let Some(replicator) = replicator.clone() {
+        let network = network.clone();
+        supervisor.watch("replication", move || {
+            replicator.clone().spawn(network.clone())
+        });
+    }
+
+    // DHT node; bootstrap peers named through DNS are resolved now and
+    // kept fresh by the DNS refresh task
+    let bootstrap_config = bootstrap_config(&args)?;
+    let dns_bootstrap = Arc::new(DnsBootstrap::new(
+        &bootstrap_peers(&args, &bootstrap_config).await?,
+        &bootstrap_config,
+    ));
+    let bootstrap_peers = dns_bootstrap.resolve().await;
+    let has_bootstrap = !bootstrap_peers.is_empty();
 
     let dht_port = args
         .dht_addr
@@ -208,67 +866,343 @@ async fn main() -> anyhow::Result<()> {
         .and_then(|p| p.parse::<u16>().ok())
         .unwrap_or(9091);
 
-    let mut dht = dht::DhtNode::new(dht_port, bootstrap_peers).await?;
+    // Connection and stream limits scale with the machine unless configured
+    let swarm = match &args.config {
+        Some(path) => SwarmConfig::from_file(path)?,
+        None => SwarmConfig::default(),
+    };
+    let mut dht = dht::DhtNode::with_limits(
+        dht_port,
+        bootstrap_peers,
+        identity.libp2p_keypair(),
+        swarm.resolve(&caps),
+    )
+    .await?
+    .with_firewall(firewall.clone())
+    .with_record_validator(record_validator(&args, directory.clone())?);
     let dht_listen = dht::local_multiaddr(dht_port);
     dht.listen_on(dht_listen.clone())?;
     info!("✓ DHT node initialized on {}", dht_listen);
 
-    if !args.bootstrap.is_empty() {
+    if has_bootstrap {
         dht.bootstrap()?;
         info!("✓ DHT bootstrap initiated");
     }
 
     // RPC server
     let rpc_addr: std::net::SocketAddr = args.rpc_addr.parse()?;
-    let rpc_server = Arc::new(rpc::RpcServer::new(
-        rpc_addr,
-        store.clone(),
+    let mut rpc_server = rpc::RpcServer::new(rpc_addr, store.clone(), network.clone());
+    if let Some(dir) = &args.metrics_history {
+        let history = Arc::new(MetricsHistory::open(dir)?);
+        rpc_server = rpc_server.with_metrics_history(history.clone());
+        info!("✓ Metrics history persisted to {}", dir);
+        supervisor.watch("metrics-history", move || {
+            history.clone().spawn_flusher(None)
+        });
+    }
+    let namespaces = namespace_config(&args)?;
+    if !namespaces.tokens.is_empty() {
+        info!(
+            "✓ Namespace tokens enforced ({} token(s))",
+            namespaces.tokens.len()
+        );
+    }
+    let prober = Arc::new(BandwidthProber::new(
         network.clone(),
+        store.clone(),
+        ProbeConfig {
+            interval: std::time::Duration::from_secs(args.probe_interval_secs),
+            ..ProbeConfig::default()
+        },
     ));
-    info!("✓ RPC server initialized");
-
-    // CES pipeline demo
+    {
+        let prober = prober.clone();
+        // With probing disabled the task finishes at once
+        supervisor.watch("bandwidth-probe", move || {
+            prober
+                .clone()
+                .spawn()
+                .unwrap_or_else(|| tokio::spawn(async {}))
+        });
+    }
+    // Keep shards of our uploads alive on storage providers
+    {
+        let client = Arc::new(StorageClient::new(network.clone(), args.node_id));
+        let cache = cache.clone();
+        supervisor.watch("shard-renewal", move || {
+            client
+                .clone()
+                .spawn_renewal(cache.clone(), storage_lease / 4)
+        });
+    }
+    let gateway = if gateway_mode {
+        let config = match &args.config {
+            Some(path) => GatewayConfig::from_file(path)?,
+            None => GatewayConfig::default(),
+        };
+        info!(
+            "✓ Gateway mode: read-only RPC, {} req/s per client (burst {})",
+            config.requests_per_sec, config.burst
+        );
+        Some(Arc::new(
+            Gateway::new(config).with_firewall(firewall.clone()),
+        ))
+    } else {
+        None
+    };
+    // Uploads and downloads submitted over RPC/gRPC
     let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
     let compression_level = ces_config.compression_level;
-    let _pipeline = ces::CesPipeline::new(ces_config);
+    let pipeline = Arc::new(ces::CesPipeline::new(ces_config));
     info!(
         "✓ CES pipeline initialized (compression level: {})",
         compression_level
     );
+    #[allow(clippy::arc_with_non_send_sync)]
+    let go_client = Arc::new(go_client::GoClient::new(args.go_addr.parse()?));
+    let cache_dir = get_cache_dir();
+    let limits = concurrency_limits(&args)?;
+    let placement = match &args.config {
+        Some(path) => PlacementPolicy::from_file(path)?,
+        None => PlacementPolicy::default(),
+    };
+    // Heal traffic and API transfers share one bandwidth budget
+    let scheduler = create_scheduler(&args);
+    // Re-hash persisted shards and repair the ones that rotted on disk
+    let scrub = match &args.config {
+        Some(path) => ScrubConfig::from_file(path)?,
+        None => ScrubConfig::default(),
+    };
+    let scrubber = if scrub.enabled {
+        #[allow(clippy::arc_with_non_send_sync)]
+        let healer = Arc::new(
+            auto_heal::AutoHealer::new(
+                auto_heal::AutoHealConfig {
+                    count_local_copies: placement.count_self,
+                    ..Default::default()
+                },
+                cache.clone(),
+                pipeline.clone(),
+                go_client.clone(),
+                store.clone(),
+            )
+            .with_transport(Arc::new(transport::QuicTransport::new(network.clone())))
+            .with_concurrency_limits(limits.clone())
+            .with_scheduler(scheduler.clone())
+            .with_keypair(keypair.clone()),
+        );
+        Some(
+            Scrubber::new(scrub, cache.clone())
+                .with_healer(healer)
+                .with_keypair(keypair.clone())
+                .with_local_peer(args.node_id),
+        )
+    } else {
+        None
+    };
+    // Reconcile manifests with the Go node
+    let manifest_sync = match &args.config {
+        Some(path) => ManifestSyncConfig::from_file(path)?,
+        None => ManifestSyncConfig::default(),
+    };
+    let manifest_sync = if manifest_sync.enabled && !gateway_mode {
+        let sync = ManifestSync::new(manifest_sync, load_identity(&args)?, cache.clone())?;
+        Some((Arc::new(sync), go_client.clone()))
+    } else {
+        None
+    };
+    // Files sent or fetched inline over the API are staged while processed
+    let staging_dir = std::path::Path::new(&cache_dir).join("api-staging");
+    // Shard filters peers advertise in the DHT, and the one we publish
+    let availability = Arc::new(ShardAvailabilityIndex::new());
+    let shard_filters = Arc::new(
+        LookupService::new(cache.clone(), None, store.clone())
+            .with_availability_index(availability.clone()),
+    );
+    let mut transfers = Transfers::new(
+        pipeline,
+        go_client.clone(),
+        cache.clone(),
+        store.clone(),
+        staging_dir,
+    )
+    .with_native_transport(network.clone())
+    .with_peer_directory(directory.clone())
+    .with_query_cache(query_cache(&args)?)
+    .with_concurrency_limits(limits.clone())
+    .with_availability_index(availability)
+    .with_keypair(keypair.clone())
+    .with_scheduler(scheduler.clone())
+    .with_progress(rpc_server.progress().clone())
+    .with_placement(placement)
+    .with_local_peer(args.node_id);
+    if args.in_memory {
+        transfers = transfers.without_staging();
+    }
+    if let Some(audit) = open_audit_log(&args, &cache_dir)? {
+        transfers = transfers.with_audit_log(audit);
+    }
+    // Read-only file browser on localhost
+    let browser = match &args.config {
+        Some(path) => BrowserConfig::from_file(path)?,
+        None => BrowserConfig::default(),
+    };
+    if browser.enabled && args.in_memory {
+        warn!("In-memory mode: [browser] is disabled, it downloads files to disk");
+    }
+    let browser = if browser.enabled && !gateway_mode && !args.in_memory {
+        let dir = browser.download_dir.clone().unwrap_or_else(|| {
+            std::path::Path::new(&cache_dir).join(browser::BROWSER_DIR)
+        });
+        let file_browser =
+            FileBrowser::new(transfers.downloader(&browser.namespace, "browser"), dir);
+        Some((file_browser, browser.listen_addr()?))
+    } else {
+        None
+    };
+    // Warm the cache with the shards of the hottest files
+    let prefetch = cache
+        .popularity()
+        .map(|popularity| popularity.config().clone())
+        .filter(|config| config.prefetch_files > 0)
+        .map(|config| {
+            (
+                transfers.downloader(namespace::DEFAULT_NAMESPACE, "prefetch"),
+                config,
+            )
+        });
+    let mut rpc_server = rpc_server
+        .with_cache(cache.clone())
+        .with_transfers(Arc::new(transfers))
+        .with_token_scopes(TokenScopes::new(&namespaces.tokens))
+        .with_concurrency_limits(limits)
+        .with_bandwidth_prober(prober)
+        .with_supervisor(supervisor.clone())
+        .with_go_client(go_client.clone());
+    if let Some(provider) = storage_provider {
+        rpc_server = rpc_server.with_storage_provider(provider);
+    }
+    if let Some(gateway) = &gateway {
+        rpc_server = rpc_server.with_gateway(gateway.clone());
+    }
+    if let Some(replicator) = replicator {
+        rpc_server = rpc_server.with_replicator(replicator);
+    }
+    if let Some((sync, _)) = &manifest_sync {
+        rpc_server = rpc_server.with_manifest_sync(sync.clone());
+    }
+    let compute = if args.compute && !gateway_mode {
+        let engine = Arc::new(
+            ComputeEngine::new(ComputeConfig {
+                aot_cache_dir: (!args.in_memory)
+                    .then(|| std::path::Path::new(&cache_dir).join(compute::AOT_CACHE_DIR)),
+                ..ComputeConfig::default()
+            })?
+            .with_blob_store(blobs.clone())
+            .with_keypair(keypair.clone())
+            .with_identity(Arc::new(load_identity(&args)?)),
+        );
+        rpc_server = rpc_server.with_compute(engine.clone());
+        info!("✓ Compute engine enabled");
+        Some(engine)
+    } else {
+        None
+    };
+    #[allow(clippy::arc_with_non_send_sync)]
+    let rpc_server = Arc::new(rpc_server);
+    info!("✓ RPC server initialized");
 
     info!("🎯 All systems operational!");
     info!("📡 Listening:");
     info!("  - RPC (Cap'n Proto): {}", rpc_addr);
+    if let Some(addr) = &args.grpc_addr {
+        info!("  - gRPC: {}", addr);
+    }
     info!("  - P2P (QUIC): {}", p2p_addr);
     info!("  - DHT (libp2p): {}", dht_listen);
 
-    // Spawn RPC server task with LocalSet
+    // DHT event loop; the node outlives restarts of the loop
+    let dht = Arc::new(tokio::sync::Mutex::new(dht));
+    let provider_events = Arc::new(tokio::sync::Mutex::new(provider_events));
+    let (dns_updates_tx, dns_updates) = tokio::sync::mpsc::unbounded_channel();
+    let dns_bootstrap = Some(dns_bootstrap).filter(|dns| dns.has_dns_entries());
+    if let Some(dns_bootstrap) = &dns_bootstrap {
+        let dns_bootstrap = dns_bootstrap.clone();
+        supervisor.watch("dns-bootstrap", move || {
+            dns_bootstrap.clone().spawn_refresh(dns_updates_tx.clone())
+        });
+    }
+    let dns_updates = Arc::new(tokio::sync::Mutex::new(dns_updates));
+    let node_id = args.node_id;
+    supervisor.spawn("dht", move || {
+        run_dht_events(
+            dht.clone(),
+            provider_events.clone(),
+            dns_bootstrap.clone(),
+            dns_updates.clone(),
+            gateway.clone(),
+            shard_filters.clone(),
+            node_id,
+        )
+    });
+
+    // Lost peers speed up the heal transfers that replace their shards
+    {
+        let store = store.clone();
+        supervisor.watch("scheduler-peers", move || {
+            scheduler.clone().spawn_peer_watch(store.clone())
+        });
+    }
+
+    // QUIC accept loop
+    {
+        let network = network.clone();
+        supervisor.spawn("quic-accept", move || {
+            let network = network.clone();
+            async move { network.accept_connection().await }
+        });
+    }
+
+    // RPC server and the tasks that share its LocalSet
     let local = tokio::task::LocalSet::new();
-    let rpc_handle = {
+    let (api_bridge, api_worker) = ApiBridge::new();
+    local.spawn_local(api_worker.run(rpc_server.clone()));
+    {
+        let _guard = local.enter();
         let rpc = rpc_server.clone();
-        local.spawn_local(async move {
-            if let Err(e) = rpc.start().await {
-                error!("RPC server error: {}", e);
-            }
-        })
-    };
-
-    // Spawn DHT event loop
-    let dht_handle = tokio::spawn(async move {
-        loop {
-            if let Some(event) = dht.next_event().await {
-                info!("DHT event: {:?}", event);
-            }
+        supervisor.spawn_local("rpc", move || {
+            let rpc = rpc.clone();
+            async move { rpc.start().await }
+        });
+        if let Some((downloader, config)) = prefetch {
+            let downloader = std::rc::Rc::new(downloader);
+            supervisor.spawn_local("prefetch", move || {
+                prefetch_hot_files(downloader.clone(), config.clone())
+            });
         }
-    });
-
-    // Spawn QUIC accept loop
-    let network_clone = network.clone();
-    let accept_handle = tokio::spawn(async move {
-        if let Err(e) = network_clone.accept_connection().await {
-            error!("QUIC accept error: {}", e);
+        if let Some((file_browser, addr)) = browser {
+            let file_browser = std::rc::Rc::new(file_browser);
+            supervisor.spawn_local("browser", move || file_browser.clone().serve(addr));
         }
-    });
+        if let Some(scrubber) = scrubber {
+            let scrubber = std::rc::Rc::new(scrubber);
+            supervisor.spawn_local("scrub", move || scrubber.clone().run());
+        }
+        // Keep the Go node's connection pool open and health-checked
+        supervisor.spawn_local("go-client", move || {
+            let go_client = go_client.clone();
+            async move { Ok(go_client.maintain().await?) }
+        });
+        if let Some((sync, go_client)) = manifest_sync {
+            supervisor.spawn_local("manifest-sync", move || sync.clone().run(go_client.clone()));
+        }
+    }
+    // gRPC calls are answered by the RPC server on the LocalSet; gateways
+    // keep their peers to themselves
+    if let Some(addr) = &args.grpc_addr {
+        let peers = (!gateway_mode).then(|| store.clone());
+        supervise_grpc(&supervisor, addr.parse()?, api_bridge, peers)?;
+    }
 
     info!("Press Ctrl+C to shutdown...");
 
@@ -283,10 +1217,52 @@ async fn main() -> anyhow::Result<()> {
         })
         .await;
 
-    // Cleanup
-    rpc_handle.abort();
-    dht_handle.abort();
-    accept_handle.abort();
+    let stats = firewall.stats();
+    info!(
+        "Firewall rejected {} QUIC and {} DHT connection(s), issued {} ban(s)",
+        stats.quic_rejected, stats.libp2p_rejected, stats.bans_issued
+    );
+    let stats = network.connection_stats().await;
+    info!(
+        "Opened {} peer connection(s) ({} resumed with 0-RTT), reused them {} time(s) over {} stream(s), closed {} idle",
+        stats.connections_opened,
+        stats.zero_rtt_accepted,
+        stats.connections_reused,
+        stats.streams_opened,
+        stats.idle_closed
+    );
+    if stats.compressed_exchanges > 0 {
+        info!(
+            "Compressed {} control exchange(s), saving {} bytes",
+            stats.compressed_exchanges, stats.compression_saved_bytes
+        );
+    }
+    if let Some(engine) = compute {
+        let stats = engine.admission_stats();
+        info!(
+            "Admitted {} compute task(s) ({} queued first); rejected {} too large, {} on a full queue, {} timed out",
+            stats.admitted,
+            stats.queued_total,
+            stats.rejected_memory,
+            stats.rejected_queue_full,
+            stats.rejected_timeout
+        );
+    }
+
+    for task in supervisor.health() {
+        if task.restarts > 0 {
+            info!(
+                "Task {} restarted {} time(s), last error: {}",
+                task.name,
+                task.restarts,
+                task.last_error.as_deref().unwrap_or("none")
+            );
+        }
+    }
+
+    // Cleanup
+    local.run_until(supervisor.shutdown()).await;
+    save_popularity(&cache);
 
     info!("✓ Shutdown complete");
     Ok(())
@@ -303,6 +1279,7 @@ async fn handle_upload(file: &str, peers: Vec<u32>, args: &Args) -> anyhow::Resu
 
     // Create Go client
     let go_addr: std::net::SocketAddr = args.go_addr.parse()?;
+    #[allow(clippy::arc_with_non_send_sync)]
     let go_client = Arc::new(GoClient::new(go_addr));
 
     // Connect to Go node
@@ -314,10 +1291,18 @@ async fn handle_upload(file: &str, peers: Vec<u32>, args: &Args) -> anyhow::Resu
     let ces = Arc::new(ces::CesPipeline::new(ces_config));
 
     // Create upload protocol
-    let upload = UploadProtocol::new(ces, go_client);
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    let mut upload = UploadProtocol::new(ces, go_client);
+    if display_task.is_some() {
+        upload = upload.with_progress(display.clone());
+    }
 
     // Upload file
-    let manifest = upload.upload_file(Path::new(file), peers).await?;
+    let manifest = upload.upload_file(Path::new(file), peers).await;
+    drop(upload);
+    finish_progress_display(display, display_task).await;
+    let manifest = manifest?;
     info!("✅ Upload complete!");
     println!("{}", manifest);
 
@@ -352,6 +1337,7 @@ async fn handle_download(file: &str, shards: Vec<String>, args: &Args) -> anyhow
 
     // Create Go client
     let go_addr: std::net::SocketAddr = args.go_addr.parse()?;
+    #[allow(clippy::arc_with_non_send_sync)]
     let go_client = Arc::new(GoClient::new(go_addr));
 
     // Connect to Go node
@@ -363,12 +1349,20 @@ async fn handle_download(file: &str, shards: Vec<String>, args: &Args) -> anyhow
     let ces = Arc::new(ces::CesPipeline::new(ces_config));
 
     // Create download protocol
-    let download = DownloadProtocol::new(ces, go_client);
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    let mut download = DownloadProtocol::new(ces, go_client);
+    if display_task.is_some() {
+        download = download.with_progress(display.clone());
+    }
 
     // Download file
     let bytes = download
         .download_file(Path::new(file), shard_locations)
-        .await?;
+        .await;
+    drop(download);
+    finish_progress_display(display, display_task).await;
+    let bytes = bytes?;
     info!("✅ Download complete! {} bytes", bytes);
 
     Ok(())
@@ -384,6 +1378,237 @@ fn get_cache_dir() -> String {
     })
 }
 
+/// This node's identity key, kept in the cache directory; with --in-memory,
+/// one generated for this run
+fn load_identity(args: &Args) -> anyhow::Result<NodeIdentity> {
+    static EPHEMERAL: std::sync::OnceLock<[u8; 32]> = std::sync::OnceLock::new();
+    if args.in_memory {
+        return Ok(NodeIdentity::from_secret(
+            *EPHEMERAL.get_or_init(rand::random),
+        ));
+    }
+    NodeIdentity::load_or_generate(
+        std::path::Path::new(&get_cache_dir()).join(identity::IDENTITY_FILE),
+    )
+}
+
+/// DHT lookup cache shared by the lookups of one command
+fn query_cache(args: &Args) -> anyhow::Result<Arc<QueryCache<FileManifest>>> {
+    let config = match &args.config {
+        Some(path) => QueryCacheConfig::from_file(path)?,
+        None => QueryCacheConfig::default(),
+    };
+    Ok(Arc::new(QueryCache::new(config)))
+}
+
+/// Upload, download, heal and DHT query limits shared by one command
+fn concurrency_limits(args: &Args) -> anyhow::Result<Arc<ConcurrencyLimits>> {
+    let config = match &args.config {
+        Some(path) => ConcurrencyConfig::from_file(path)?,
+        None => ConcurrencyConfig::default(),
+    };
+    Ok(Arc::new(ConcurrencyLimits::new(&config)))
+}
+
+/// Node IDs bound to identities, kept in the cache directory unless --in-memory
+fn open_peer_directory(args: &Args) -> anyhow::Result<Arc<PeerDirectory>> {
+    if args.in_memory {
+        return Ok(Arc::new(PeerDirectory::new()));
+    }
+    let path = std::path::Path::new(&get_cache_dir()).join(identity::PEER_DIRECTORY_FILE);
+    Ok(Arc::new(PeerDirectory::open(path)?))
+}
+
+/// DHT record checks, with shard filters held to the identities in `directory`
+fn record_validator(
+    args: &Args,
+    directory: Arc<PeerDirectory>,
+) -> anyhow::Result<Arc<RecordValidator>> {
+    let config = match &args.config {
+        Some(path) => RecordConfig::from_file(path)?,
+        None => RecordConfig::default(),
+    };
+    Ok(Arc::new(
+        RecordValidator::new(config).with_peer_directory(directory),
+    ))
+}
+
+/// Deny-list kept next to the cache
+fn get_deny_list_path(cache_dir: &str) -> std::path::PathBuf {
+    std::path::Path::new(cache_dir).join(denylist::DENY_LIST_FILE)
+}
+
+/// Open the shard cache, refusing deny-listed hashes and enforcing namespace quotas
+///
+/// With --in-memory nothing is read from or written to `cache_dir`.
+fn open_cache(args: &Args, cache_dir: &str) -> anyhow::Result<Cache> {
+    let cache = if args.in_memory {
+        Cache::in_memory(DEFAULT_CACHE_MAX_ENTRIES, DEFAULT_CACHE_SIZE_BYTES)?
+            .with_deny_list(Arc::new(DenyList::new()))
+    } else {
+        let deny_list = DenyList::open(get_deny_list_path(cache_dir))?;
+        Cache::new(
+            cache_dir,
+            DEFAULT_CACHE_MAX_ENTRIES,
+            DEFAULT_CACHE_SIZE_BYTES,
+        )?
+        .with_deny_list(Arc::new(deny_list))
+    }
+    .with_quotas(namespace_config(args)?.quotas)
+    .with_durability(durability_config(args)?.level);
+    let popularity = match &args.config {
+        Some(path) => PopularityConfig::from_file(path)?,
+        None => PopularityConfig::default(),
+    };
+    if !popularity.enabled {
+        return Ok(cache);
+    }
+    let tracker = if args.in_memory {
+        PopularityTracker::new(popularity)
+    } else {
+        PopularityTracker::open(cache_dir, popularity)?
+    };
+    Ok(cache.with_popularity(Arc::new(tracker)))
+}
+
+/// Persist the popularity table so hot/cold classification survives restarts
+fn save_popularity(cache: &Cache) {
+    if let Some(popularity) = cache.popularity() {
+        if let Err(e) = popularity.save() {
+            warn!("Failed to save popularity statistics: {:#}", e);
+        }
+    }
+}
+
+/// Write sync level and startup recovery from the `[durability]` config section
+fn durability_config(args: &Args) -> anyhow::Result<DurabilityConfig> {
+    match &args.config {
+        Some(path) => DurabilityConfig::from_file(path),
+        None => Ok(DurabilityConfig::default()),
+    }
+}
+
+/// Quotas and RPC tokens from the `[namespaces]` config section
+fn namespace_config(args: &Args) -> anyhow::Result<NamespaceConfig> {
+    match &args.config {
+        Some(path) => NamespaceConfig::from_file(path),
+        None => Ok(NamespaceConfig::default()),
+    }
+}
+
+/// Audit log from the `[audit]` config section, unless disabled
+fn open_audit_log(args: &Args, cache_dir: &str) -> anyhow::Result<Option<Arc<AuditLog>>> {
+    let config = match &args.config {
+        Some(path) => audit::AuditConfig::from_file(path)?,
+        None => audit::AuditConfig::default(),
+    };
+    if !config.enabled {
+        return Ok(None);
+    }
+    if args.in_memory {
+        warn!("In-memory mode: [audit] is ignored, transfers are not recorded");
+        return Ok(None);
+    }
+    let path = config.path_or(cache_dir);
+    Ok(Some(Arc::new(AuditLog::open(path, config)?)))
+}
+
+/// Requester recorded for transfers started from this CLI
+fn cli_requester() -> String {
+    format!(
+        "cli:{}",
+        std::env::var("USER").unwrap_or_else(|_| "unknown".to_string())
+    )
+}
+
+/// Path of this node's X25519 keypair (PANGEA_KEY_FILE or ~/.pangea/keys/node-<id>.json)
+fn key_file_path(args: &Args) -> String {
+    std::env::var("PANGEA_KEY_FILE").unwrap_or_else(|_| {
+        format!(
+            "{}/.pangea/keys/node-{}.json",
+            std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string()),
+            args.node_id
+        )
+    })
+}
+
+/// Load this node's X25519 keypair; with --in-memory, generate one
+fn load_keypair(args: &Args) -> anyhow::Result<NodeKeypair> {
+    if args.in_memory {
+        return Ok(NodeKeypair::generate(args.node_id));
+    }
+    NodeKeypair::load_or_generate(args.node_id, key_file_path(args))
+}
+
+/// Where this node keeps the state captured by snapshots
+fn state_layout(args: &Args, cache_dir: &str) -> anyhow::Result<StateLayout> {
+    let (replication, audit) = match &args.config {
+        Some(path) => (
+            ReplicationConfig::from_file(path)?,
+            audit::AuditConfig::from_file(path)?,
+        ),
+        None => (ReplicationConfig::default(), audit::AuditConfig::default()),
+    };
+    let mut layout = StateLayout::new(cache_dir, key_file_path(args))
+        .with_replication_key(replication.key_file_or(cache_dir))
+        .with_audit_log(audit.path_or(cache_dir));
+    if let Some(dir) = &args.metrics_history {
+        layout = layout.with_metrics_dir(dir);
+    }
+    Ok(layout)
+}
+
+/// Read the encryption passphrase from PANGEA_PASSPHRASE or the terminal
+fn read_passphrase(confirm: bool) -> anyhow::Result<String> {
+    if let Ok(passphrase) = std::env::var("PANGEA_PASSPHRASE") {
+        if !passphrase.is_empty() {
+            return Ok(passphrase);
+        }
+    }
+
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase must not be empty");
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        anyhow::bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// The [bootstrap] section of the config file
+fn bootstrap_config(args: &Args) -> anyhow::Result<BootstrapConfig> {
+    match &args.config {
+        Some(path) => BootstrapConfig::from_file(path),
+        None => Ok(BootstrapConfig::default()),
+    }
+}
+
+/// `--bootstrap` peers, plus those of the signed lists from the
+/// [bootstrap] sources (or the last-known-good ones if none answer)
+///
+/// DNS entries are returned unresolved; see [`DnsBootstrap`].
+async fn bootstrap_peers(
+    args: &Args,
+    config: &BootstrapConfig,
+) -> anyhow::Result<Vec<libp2p::Multiaddr>> {
+    let configured = args
+        .bootstrap
+        .iter()
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    if config.sources.is_empty() {
+        return Ok(configured);
+    }
+    let mut discovery = BootstrapDiscovery::new(config.clone())?;
+    if !args.in_memory {
+        discovery = discovery.with_state_file(
+            std::path::Path::new(&get_cache_dir()).join(bootstrap::BOOTSTRAP_STATE_FILE),
+        );
+    }
+    Ok(discovery.discover(configured).await)
+}
+
 /// Initialize DHT with bootstrap peers
 async fn init_dht(args: &Args) -> Option<Arc<tokio::sync::RwLock<dht::DhtNode>>> {
     let dht_port = args
@@ -393,19 +1618,37 @@ async fn init_dht(args: &Args) -> Option<Arc<tokio::sync::RwLock<dht::DhtNode>>>
         .and_then(|p| p.parse::<u16>().ok())
         .unwrap_or(9091);
 
-    let bootstrap_peers: Vec<libp2p::Multiaddr> = args
-        .bootstrap
-        .iter()
-        .filter_map(|s| s.parse().ok())
-        .collect();
+    let bootstrap_peers = match bootstrap_config(args) {
+        Ok(config) => match bootstrap_peers(args, &config).await {
+            Ok(peers) => DnsBootstrap::new(&peers, &config).resolve().await,
+            Err(e) => {
+                warn!("DHT initialization failed: {}, continuing without DHT", e);
+                return None;
+            }
+        },
+        Err(e) => {
+            warn!("DHT initialization failed: {}, continuing without DHT", e);
+            return None;
+        }
+    };
+
+    let validator = match open_peer_directory(args).and_then(|dir| record_validator(args, dir)) {
+        Ok(validator) => validator,
+        Err(e) => {
+            warn!("DHT initialization failed: {}, continuing without DHT", e);
+            return None;
+        }
+    };
 
     match dht::DhtNode::new(dht_port, bootstrap_peers).await {
-        Ok(mut dht_node) => {
+        Ok(dht_node) => {
+            let mut dht_node = dht_node.with_record_validator(validator);
             let dht_listen = dht::local_multiaddr(dht_port);
             if let Err(e) = dht_node.listen_on(dht_listen) {
                 warn!("DHT listen failed: {}", e);
                 return None;
             }
+            #[allow(clippy::arc_with_non_send_sync)]
             Some(Arc::new(tokio::sync::RwLock::new(dht_node)))
         }
         Err(e) => {
@@ -415,9 +1658,13 @@ async fn init_dht(args: &Args) -> Option<Arc<tokio::sync::RwLock<dht::DhtNode>>>
     }
 }
 
-/// Create a downloader for read-only cache operations (list, search, info)
+/// Create a downloader for cache operations (list, search, info, import)
 /// This is optimized to not create unnecessary network components
-async fn create_cache_downloader(_args: &Args) -> anyhow::Result<AutomatedDownloader> {
+async fn create_cache_downloader(
+    _args: &Args,
+    namespace: &str,
+    dht: Option<Arc<tokio::sync::RwLock<dht::DhtNode>>>,
+) -> anyhow::Result<AutomatedDownloader> {
     use pangea_ces::{AutomatedDownloader, Cache};
 
     // For cache-only operations, we still need minimal setup
@@ -431,15 +1678,283 @@ async fn create_cache_downloader(_args: &Args) -> anyhow::Result<AutomatedDownlo
     let ces = Arc::new(ces::CesPipeline::new(ces_config));
 
     let cache_dir = get_cache_dir();
-    let cache = Arc::new(Cache::new(
-        &cache_dir,
-        DEFAULT_CACHE_MAX_ENTRIES,
-        DEFAULT_CACHE_SIZE_BYTES,
-    )?);
+    let cache = Arc::new(open_cache(_args, &cache_dir)?);
+    cache.load_persisted_manifests().await?;
 
     let store = Arc::new(store::NodeStore::new());
 
-    Ok(AutomatedDownloader::new(ces, go_client, cache, store, None))
+    namespace::validate_namespace(namespace)?;
+    Ok(AutomatedDownloader::new(ces, go_client, cache, store, dht).with_namespace(namespace))
+}
+
+/// Simulated peers for `--simulate-network`, registered as active nodes
+///
+/// Their shards are kept under the cache dir so a later `get` finds what
+/// `put` stored.
+async fn create_simulated_network(
+    args: &Args,
+    cache_dir: &str,
+    store: &store::NodeStore,
+) -> Option<Arc<SimulatedNetwork>> {
+    let profile = args.simulate_network?;
+    let peers = 1..=args.simulated_peers.max(1);
+    for peer in peers.clone() {
+        store.upsert_node(types::Node::new(peer)).await;
+    }
+    warn!(
+        "🧪 Simulating a {:?} network with {} peer(s); nothing leaves this machine",
+        profile, args.simulated_peers
+    );
+    Some(Arc::new(
+        SimulatedNetwork::new(profile.conditions())
+            .with_peers(peers)
+            .with_storage_dir(std::path::Path::new(cache_dir).join("simulated-network")),
+    ))
+}
+
+/// Connect to the Go node
+///
+/// Only the daemon's QUIC node is connected to peers, so without a Go node
+/// shards have to go through the daemon (`put` and `get` without
+/// `--standalone`), which falls back to native QUIC itself.
+async fn connect_go_node(go_client: &go_client::GoClient, args: &Args) -> anyhow::Result<()> {
+    go_client.connect().await.map_err(|e| {
+        anyhow::anyhow!(
+            "Go node at {} unavailable ({}); run put/get through the daemon, whose QUIC node is connected to peers",
+            args.go_addr,
+            e
+        )
+    })
+}
+
+/// Serve the gRPC API on `addr`
+#[cfg(feature = "grpc")]
+fn supervise_grpc(
+    supervisor: &Supervisor,
+    addr: std::net::SocketAddr,
+    bridge: ApiBridge<rpc::RpcServer>,
+    peers: Option<Arc<store::NodeStore>>,
+) -> anyhow::Result<()> {
+    supervisor.spawn("grpc", move || {
+        grpc::serve(addr, bridge.clone(), peers.clone())
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "grpc"))]
+fn supervise_grpc(
+    _supervisor: &Supervisor,
+    _addr: std::net::SocketAddr,
+    _bridge: ApiBridge<rpc::RpcServer>,
+    _peers: Option<Arc<store::NodeStore>>,
+) -> anyhow::Result<()> {
+    anyhow::bail!("--grpc-addr requires building with --features grpc")
+}
+
+/// Run a put, get, list, search or info through the running daemon
+///
+/// Must run within a `LocalSet`.
+async fn run_through_daemon(command: &Command, args: &Args) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::path::Path;
+
+    let addr: std::net::SocketAddr = args.daemon_addr.parse()?;
+    let mut client = rpc::DaemonClient::connect(addr).await.with_context(|| {
+        format!(
+            "Start the daemon with --rpc-addr {}, or pass --standalone to run in this process",
+            addr
+        )
+    })?;
+    if let Ok(token) = std::env::var("PANGEA_TOKEN") {
+        client = client.with_token(&token);
+    }
+
+    match command {
+        Command::Put {
+            file,
+            priority,
+            encrypt_with_passphrase,
+            recipients,
+            namespace,
+            class,
+            force,
+        } => {
+            let recipients = recipients
+                .iter()
+                .map(|key| envelope::parse_public_key(key))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if let Some(class) = class {
+                store::validate_storage_class(class)?;
+            }
+            let options = UploadOptions {
+                passphrase: if *encrypt_with_passphrase {
+                    Some(read_passphrase(true)?)
+                } else {
+                    None
+                },
+                recipients,
+                storage_class: class.clone(),
+                force: *force,
+            };
+            info!("🚀 Uploading {} through the daemon at {}", file, addr);
+            let result = client
+                .put(Path::new(file), namespace, *priority, &options)
+                .await?;
+            print_upload_summary(&result);
+        }
+        Command::Get {
+            hash,
+            output,
+            priority,
+            namespace,
+        } => {
+            info!("🚀 Downloading {} through the daemon at {}", hash, addr);
+            let call = DownloadCall {
+                file_hash: hash.clone(),
+                namespace: namespace.clone(),
+                priority: *priority,
+            };
+            let result = client.get(&call, output.as_deref().map(Path::new)).await?;
+
+            println!("\n📊 Download Summary:");
+            println!("  File: {}", result.file_name);
+            println!("  Hash: {}", result.file_hash);
+            println!("  Downloaded: {} bytes", result.bytes_written);
+            println!("  Shards fetched: {}", result.shards_fetched);
+            println!("  Saved to: {:?}", result.output_path);
+        }
+        Command::List { namespace, network } => {
+            if *network {
+                warn!("⚠️  The daemon lists its local files only; pass --standalone for --network");
+            }
+            print_file_list(namespace, &client.list(namespace).await?);
+        }
+        Command::Search { pattern, namespace } => {
+            print_search_results(pattern, &client.search(namespace, pattern).await?);
+        }
+        Command::Info { hash, namespace } => {
+            print_file_info(hash, client.info(namespace, hash).await?);
+        }
+        other => anyhow::bail!("{:?} does not run through the daemon", other),
+    }
+    Ok(())
+}
+
+/// Log DHT events, sweep bans, announce what the storage provider holds,
+/// follow re-resolved DNS bootstrap peers, and publish our shard filter
+/// while ingesting the ones peers put here
+#[allow(clippy::too_many_arguments)]
+async fn run_dht_events(
+    dht: Arc<tokio::sync::Mutex<dht::DhtNode>>,
+    provider_events: Arc<tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<ProviderEvent>>>,
+    dns_bootstrap: Option<Arc<DnsBootstrap>>,
+    dns_updates: Arc<
+        tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<Vec<libp2p::Multiaddr>>>,
+    >,
+    gateway: Option<Arc<Gateway>>,
+    shard_filters: Arc<LookupService>,
+    node_id: u32,
+) -> anyhow::Result<()> {
+    let mut dht = dht.lock().await;
+    let mut provider_events = provider_events.lock().await;
+    let mut dns_updates = dns_updates.lock().await;
+    let mut ban_sweep = tokio::time::interval(BAN_SWEEP_INTERVAL);
+    let mut republish = tokio::time::interval(std::time::Duration::from_secs(
+        bloom::REPUBLISH_INTERVAL_SECS,
+    ));
+    let mut denied = 0;
+    let mut bootstrap_failures = 0;
+    loop {
+        tokio::select! {
+            event = dht.next_event() => {
+                if let Some(event) = event {
+                    info!("DHT event: {:?}", event);
+                }
+                for advert in dht.take_shard_advertisements() {
+                    if let Err(e) = shard_filters.ingest_shard_advertisement(&advert).await {
+                        warn!("Ignored shard filter: {}", e);
+                    }
+                }
+                // Bootstrap peers that keep failing may have moved under their DNS names
+                let failures = dht.bootstrap_failures();
+                if let Some(dns) = &dns_bootstrap {
+                    if failures > bootstrap_failures && failures % dns.redial_failures() == 0 {
+                        warn!("{} failed dials to bootstrap peers; resolving them again", failures);
+                        dns.refresh_now();
+                    }
+                }
+                bootstrap_failures = failures;
+            }
+            Some(peers) = dns_updates.recv() => {
+                if let Err(e) = dht.set_bootstrap_peers(peers) {
+                    warn!("Failed to bootstrap through re-resolved peers: {}", e);
+                }
+            }
+            // Bans only gate new connections; drop peers banned since they connected
+            _ = ban_sweep.tick() => {
+                dht.disconnect_banned();
+                let stats = dht.stats();
+                let refused = stats.denied_by_limits + stats.denied_by_memory;
+                if refused > denied {
+                    warn!(
+                        "DHT refused {} connections at its limits ({} established, {} peers)",
+                        refused - denied,
+                        stats.established_incoming + stats.established_outgoing,
+                        stats.connected_peers
+                    );
+                    denied = refused;
+                }
+                if let Some(gateway) = &gateway {
+                    gateway.prune();
+                }
+            }
+            _ = republish.tick() => {
+                match shard_filters.publish_shard_filter(node_id).await {
+                    Ok(advert) => {
+                        let record = advert.to_bytes().and_then(|value| {
+                            dht.put_record(ShardAdvertisement::dht_key(node_id), value)
+                        });
+                        if let Err(e) = record {
+                            warn!("Failed to publish shard filter: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to build shard filter: {}", e),
+                }
+            }
+            Some(event) = provider_events.recv() => match event {
+                ProviderEvent::Stored { file_hash } => {
+                    if let Err(e) = dht.start_providing(file_hash.into_bytes()) {
+                        warn!("Failed to announce stored file: {}", e);
+                    }
+                }
+                ProviderEvent::Released { file_hash } => {
+                    dht.stop_providing(file_hash.into_bytes())
+                }
+            },
+        }
+    }
+}
+
+/// Fetch the shards of the hottest files every `prefetch_interval_secs`
+async fn prefetch_hot_files(
+    downloader: std::rc::Rc<AutomatedDownloader>,
+    config: PopularityConfig,
+) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        config.prefetch_interval_secs,
+    ));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        downloader.prefetch_hot(config.prefetch_files).await;
+    }
+}
+
+/// Create the transfer scheduler from CLI bandwidth settings
+fn create_scheduler(args: &Args) -> Arc<TransferScheduler> {
+    Arc::new(TransferScheduler::new(SchedulerConfig::with_rate(
+        args.bandwidth_limit,
+    )))
 }
 
 /// Format file information for display (UTF-8 safe)
@@ -466,11 +1981,97 @@ fn format_file_display(file: &FileInfo) -> (String, String, String) {
 }
 
 /// Handle automated upload command
-async fn handle_automated_upload(file: &str, args: &Args) -> anyhow::Result<()> {
-    use pangea_ces::{AutomatedUploader, Cache};
+#[allow(clippy::too_many_arguments)]
+async fn handle_automated_upload(
+    file: &str,
+    priority: TransferPriority,
+    encrypt_with_passphrase: bool,
+    recipients: &[String],
+    namespace: &str,
+    class: Option<&str>,
+    force: bool,
+    args: &Args,
+) -> anyhow::Result<()> {
     use std::path::Path;
 
-    info!("🚀 Automated upload mode: {}", file);
+    info!(
+        "🚀 Automated upload mode: {} (namespace: {})",
+        file, namespace
+    );
+
+    // Create CES pipeline
+    let caps = capabilities::HardwareCaps::probe();
+    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
+    let mut ces = ces::CesPipeline::new(ces_config);
+    if let Some(path) = &args.config {
+        ces = ces.with_compression_policy(file_detector::CompressionPolicy::from_file(path)?);
+    }
+    if encrypt_with_passphrase {
+        let passphrase = read_passphrase(true)?;
+        ces = ces.with_passphrase(passphrase.as_bytes(), kdf::KdfParams::generate())?;
+        info!("🔑 Encryption key derived from passphrase (Argon2id)");
+    }
+
+    let mut uploader = create_uploader(Arc::new(ces), recipients, args)
+        .await?
+        .with_namespace(namespace)
+        .with_force(force);
+    if let Some(class) = class {
+        store::validate_storage_class(class)?;
+        uploader = uploader.with_storage_class(class);
+    }
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    if display_task.is_some() {
+        uploader = uploader.with_progress(display.clone());
+    }
+
+    // Upload file
+    let result = uploader
+        .upload_with_priority(Path::new(file), priority)
+        .await;
+    drop(uploader);
+    finish_progress_display(display, display_task).await;
+    print_upload_summary(&result?);
+    Ok(())
+}
+
+/// Handle resume-upload command
+async fn handle_resume_upload(
+    session_id: &str,
+    priority: TransferPriority,
+    args: &Args,
+) -> anyhow::Result<()> {
+    info!("🚀 Resuming upload session {}", session_id);
+
+    // The session already holds processed shards; this pipeline never runs
+    let caps = capabilities::HardwareCaps::probe();
+    let ces = ces::CesPipeline::new(types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0));
+    let mut uploader = create_uploader(Arc::new(ces), &[], args).await?;
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    if display_task.is_some() {
+        uploader = uploader.with_progress(display.clone());
+    }
+
+    let result = uploader.resume(session_id, priority).await;
+    drop(uploader);
+    finish_progress_display(display, display_task).await;
+    print_upload_summary(&result?);
+    Ok(())
+}
+
+/// Uploader wired to this node's transports, cache, peers, DHT and audit log
+///
+/// Uploads are checkpointed in the cache directory so `resume-upload` can
+/// finish them. The file key is wrapped for this node and `recipients`.
+async fn create_uploader(
+    ces: Arc<ces::CesPipeline>,
+    recipients: &[String],
+    args: &Args,
+) -> anyhow::Result<pangea_ces::AutomatedUploader> {
+    use pangea_ces::{AutomatedUploader, ChunkingPolicy, PlacementPolicy, UploadSessions};
+
     info!("Using Go node at: {}", args.go_addr);
 
     // Create Go client
@@ -479,52 +2080,117 @@ async fn handle_automated_upload(file: &str, args: &Args) -> anyhow::Result<()>
     let go_client = Arc::new(go_client::GoClient::new(go_addr));
 
     // Connect to Go node
-    go_client.connect().await?;
+    let directory = open_peer_directory(args)?;
+    if args.simulate_network.is_none() {
+        connect_go_node(&go_client, args).await?;
+    }
 
-    // Create CES pipeline
-    let caps = capabilities::HardwareCaps::probe();
-    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
-    let ces = Arc::new(ces::CesPipeline::new(ces_config));
+    let chunking = match &args.config {
+        Some(path) => ChunkingPolicy::from_file(path)?,
+        None => ChunkingPolicy::default(),
+    };
+    let placement = match &args.config {
+        Some(path) => PlacementPolicy::from_file(path)?,
+        None => PlacementPolicy::default(),
+    };
+    let search = match &args.config {
+        Some(path) => SearchConfig::from_file(path)?,
+        None => SearchConfig::default(),
+    };
 
     // Create cache (use default location)
     let cache_dir = get_cache_dir();
-    let cache = Arc::new(Cache::new(
-        &cache_dir,
-        DEFAULT_CACHE_MAX_ENTRIES,
-        DEFAULT_CACHE_SIZE_BYTES,
-    )?);
+    let cache = Arc::new(open_cache(args, &cache_dir)?);
 
     // Create node store
     let store = Arc::new(store::NodeStore::new());
+    let simulated = create_simulated_network(args, &cache_dir, &store).await;
+    if let Some(path) = &args.config {
+        store
+            .apply_storage_classes(&store::StorageClassConfig::from_file(path)?)
+            .await;
+    }
 
     // Initialize DHT (optional)
     let dht = init_dht(args).await;
 
-    // Create automated uploader
-    let uploader = AutomatedUploader::new(ces, go_client, cache, store, dht);
+    // Wrap the file key for this node and any extra recipients
+    let keypair = load_keypair(args)?;
+    let mut recipient_keys = vec![keypair.public_key()];
+    for recipient in recipients {
+        recipient_keys.push(envelope::parse_public_key(recipient)?);
+    }
 
-    // Upload file
-    let result = uploader.upload(Path::new(file)).await?;
+    // Create automated uploader
+    let mut uploader = AutomatedUploader::new(ces, go_client, cache, store, dht)
+        .with_scheduler(create_scheduler(args))
+        .with_recipients(recipient_keys)
+        .with_compression_controller(Arc::new(CompressionController::default()))
+        .with_chunking(chunking)
+        .with_placement(placement)
+        .with_local_peer(args.node_id)
+        .with_sessions(Arc::new(UploadSessions::in_cache_dir(&cache_dir)))
+        .with_concurrency_limits(concurrency_limits(args)?)
+        .with_peer_directory(directory);
+    if let Some(network) = simulated {
+        uploader = uploader.with_transport(network);
+    }
+    if let Some(audit) = open_audit_log(args, &cache_dir)? {
+        uploader = uploader.with_audit_log(audit, cli_requester());
+    }
+    if search.enabled {
+        // Keyword entries are signed with the manifest replication key
+        let replication = match &args.config {
+            Some(path) => ReplicationConfig::from_file(path)?,
+            None => ReplicationConfig::default(),
+        };
+        let key =
+            ReplicationKey::load_or_generate(args.node_id, replication.key_file_or(&cache_dir))?;
+        info!(
+            "✓ Publishing filename keywords to the DHT (public key: {})",
+            key.public_key_hex()
+        );
+        uploader =
+            uploader.with_keyword_index(Arc::new(KeywordIndex::new(search)?.with_signing_key(key)));
+    }
+    Ok(uploader)
+}
 
+fn print_upload_summary(result: &pangea_ces::UploadResult) {
     println!("\n📊 Upload Summary:");
     println!("  File hash: {}", result.file_hash);
+    if result.deduplicated {
+        println!("  Already in the network: transfer skipped, TTL renewed (--force to re-upload)");
+    }
     println!("  Shards: {}", result.shard_count);
     println!("  Distributed to: {} peer(s)", result.total_peers);
+    let native_shards = result
+        .transfers
+        .iter()
+        .filter(|t| t.transport == TransportKind::NativeQuic)
+        .count();
+    if native_shards > 0 {
+        println!("  Sent over native QUIC: {} shard(s)", native_shards);
+    }
     println!("\n📝 Manifest:\n{}", result.manifest_json);
-
-    Ok(())
 }
 
 /// Handle automated download command
 async fn handle_automated_download(
     hash: &str,
     output: Option<&str>,
+    priority: TransferPriority,
+    namespace: &str,
     args: &Args,
 ) -> anyhow::Result<()> {
     use pangea_ces::{AutomatedDownloader, Cache};
     use std::path::PathBuf;
 
-    info!("🚀 Automated download mode: {}", hash);
+    info!(
+        "🚀 Automated download mode: {} (namespace: {})",
+        hash, namespace
+    );
+    namespace::validate_namespace(namespace)?;
     info!("Using Go node at: {}", args.go_addr);
 
     // Create Go client
@@ -533,29 +2199,60 @@ async fn handle_automated_download(
     let go_client = Arc::new(go_client::GoClient::new(go_addr));
 
     // Connect to Go node
-    go_client.connect().await?;
-
-    // Create CES pipeline
-    let caps = capabilities::HardwareCaps::probe();
-    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
-    let ces = Arc::new(ces::CesPipeline::new(ces_config));
+    let directory = open_peer_directory(args)?;
+    if args.simulate_network.is_none() {
+        connect_go_node(&go_client, args).await?;
+    }
 
-    // Create cache
+    // Create cache, including manifests imported or stored by earlier runs
     let cache_dir = get_cache_dir();
-    let cache = Arc::new(Cache::new(
-        &cache_dir,
-        DEFAULT_CACHE_MAX_ENTRIES,
-        DEFAULT_CACHE_SIZE_BYTES,
-    )?);
+    let cache = Arc::new(open_cache(args, &cache_dir)?);
+    cache.load_persisted_manifests().await?;
 
     // Create node store
     let store = Arc::new(store::NodeStore::new());
+    let simulated = create_simulated_network(args, &cache_dir, &store).await;
 
     // Initialize DHT (optional)
     let dht = init_dht(args).await;
 
+    // Create CES pipeline, re-deriving the key if the file was passphrase-encrypted
+    let caps = capabilities::HardwareCaps::probe();
+    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
+    let mut ces = ces::CesPipeline::new(ces_config);
+    let queries = query_cache(args)?;
+    let lookup = LookupService::new(cache.clone(), dht.clone(), store.clone())
+        .with_query_cache(queries.clone());
+    if let Some(params) = lookup
+        .lookup_file(hash)
+        .await?
+        .and_then(|found| found.manifest.kdf)
+    {
+        let passphrase = read_passphrase(false)?;
+        ces = ces.with_passphrase(passphrase.as_bytes(), params)?;
+        info!("🔑 Encryption key derived from passphrase (Argon2id)");
+    }
+    let ces = Arc::new(ces);
+
     // Create automated downloader
-    let downloader = AutomatedDownloader::new(ces, go_client, cache, store, dht);
+    let mut downloader = AutomatedDownloader::new(ces, go_client, cache.clone(), store, dht)
+        .with_scheduler(create_scheduler(args))
+        .with_keypair(Arc::new(load_keypair(args)?))
+        .with_namespace(namespace)
+        .with_peer_directory(directory)
+        .with_query_cache(queries)
+        .with_concurrency_limits(concurrency_limits(args)?);
+    if let Some(network) = simulated {
+        downloader = downloader.with_transport(network);
+    }
+    if let Some(audit) = open_audit_log(args, &cache_dir)? {
+        downloader = downloader.with_audit_log(audit, cli_requester());
+    }
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    if display_task.is_some() {
+        downloader = downloader.with_progress(display.clone());
+    }
 
     // Determine output path
     let output_path = if let Some(path) = output {
@@ -572,7 +2269,13 @@ async fn handle_automated_download(
     };
 
     // Download file
-    let result = downloader.download(hash, &output_path).await?;
+    let result = downloader
+        .download_with_priority(hash, &output_path, priority)
+        .await;
+    drop(downloader);
+    finish_progress_display(display, display_task).await;
+    let result = result?;
+    save_popularity(&cache);
 
     println!("\n📊 Download Summary:");
     println!("  File: {}", result.file_name);
@@ -584,51 +2287,382 @@ async fn handle_automated_download(
     Ok(())
 }
 
+/// Handle export command
+async fn handle_export(
+    hash: &str,
+    output: &str,
+    priority: TransferPriority,
+    namespace: &str,
+    args: &Args,
+) -> anyhow::Result<()> {
+    use pangea_ces::AutomatedDownloader;
+
+    info!("📦 Export mode: {} (namespace: {})", hash, namespace);
+    namespace::validate_namespace(namespace)?;
+
+    let go_addr: std::net::SocketAddr = args.go_addr.parse()?;
+    #[allow(clippy::arc_with_non_send_sync)]
+    let go_client = Arc::new(go_client::GoClient::new(go_addr));
+    let directory = open_peer_directory(args)?;
+    if args.simulate_network.is_none() {
+        connect_go_node(&go_client, args).await?;
+    }
+
+    let cache_dir = get_cache_dir();
+    let cache = Arc::new(open_cache(args, &cache_dir)?);
+    cache.load_persisted_manifests().await?;
+    let store = Arc::new(store::NodeStore::new());
+    let simulated = create_simulated_network(args, &cache_dir, &store).await;
+    let dht = init_dht(args).await;
+
+    // Shards are bundled still encrypted, so no key is needed here
+    let caps = capabilities::HardwareCaps::probe();
+    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
+    let ces = Arc::new(ces::CesPipeline::new(ces_config));
+
+    let mut downloader = AutomatedDownloader::new(ces, go_client, cache, store, dht)
+        .with_scheduler(create_scheduler(args))
+        .with_namespace(namespace)
+        .with_peer_directory(directory);
+    if let Some(network) = simulated {
+        downloader = downloader.with_transport(network);
+    }
+    let display = ProgressHub::default();
+    let display_task = spawn_progress_display(&display);
+    if display_task.is_some() {
+        downloader = downloader.with_progress(display.clone());
+    }
+
+    let result = downloader.export_bundle(hash, output, priority).await;
+    drop(downloader);
+    finish_progress_display(display, display_task).await;
+    let result = result?;
+
+    println!("\n📦 Export Summary:");
+    println!("  File: {}", result.file_name);
+    println!("  Hash: {}", result.file_hash);
+    println!("  Shards: {}", result.shards_exported);
+    println!(
+        "  Bundle: {:?} ({} bytes)",
+        result.bundle_path, result.bytes
+    );
+
+    Ok(())
+}
+
+/// Handle rotate-key command
+async fn handle_rotate_key(hash: &str, recipients: &[String], args: &Args) -> anyhow::Result<()> {
+    let cache_dir = get_cache_dir();
+    let cache = open_cache(args, &cache_dir)?;
+    cache.load_persisted_manifests().await?;
+    let manifest = cache
+        .get_manifest(hash)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("No manifest for {} in the local cache", hash))?;
+
+    let keypair = load_keypair(args)?;
+    let mut recipient_keys = Vec::new();
+    if !recipients.is_empty() {
+        recipient_keys.push(keypair.public_key());
+        for recipient in recipients {
+            recipient_keys.push(envelope::parse_public_key(recipient)?);
+        }
+    }
+    let rotated = rotation::rotate(&manifest, &keypair, &recipient_keys)?;
+    cache.put_manifest(rotated.clone()).await?;
+    let versions = rotated
+        .rotation
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Rotation of {} recorded no key version", hash))?;
+
+    let (current, total) = versions.progress(rotated.shard_locations.len());
+    println!("\n🔑 Rotated key of {} ({})", rotated.file_name, hash);
+    println!("  Key version: {}", versions.current);
+    println!(
+        "  Versions still accepted: {}-{}",
+        versions.oldest, versions.current
+    );
+    println!("  Recipients: {}", rotated.recipients.len());
+    println!("  Shard copies at the new version: {}/{}", current, total);
+    println!("  Shards are re-encrypted by scrub passes and `verify --heal`");
+    Ok(())
+}
+
+/// Handle verify command
+async fn handle_verify(
+    hash: Option<&str>,
+    all: bool,
+    heal: bool,
+    args: &Args,
+) -> anyhow::Result<()> {
+    use pangea_ces::auto_heal::{AutoHealConfig, AutoHealer, ShardStatus};
+
+    let go_addr: std::net::SocketAddr = args.go_addr.parse()?;
+    #[allow(clippy::arc_with_non_send_sync)]
+    let go_client = Arc::new(go_client::GoClient::new(go_addr));
+    if args.simulate_network.is_none() {
+        connect_go_node(&go_client, args).await?;
+    }
+
+    let cache_dir = get_cache_dir();
+    let cache = Arc::new(open_cache(args, &cache_dir)?);
+    cache.load_persisted_manifests().await?;
+    let store = Arc::new(store::NodeStore::new());
+    let simulated = create_simulated_network(args, &cache_dir, &store).await;
+    if let Some(path) = &args.config {
+        store
+            .apply_storage_classes(&store::StorageClassConfig::from_file(path)?)
+            .await;
+    }
+    // Healing only moves shards to peers the Go node is connected to
+    if simulated.is_none() {
+        for peer in go_client.get_connected_peers().await.unwrap_or_default() {
+            store.upsert_node(types::Node::new(peer)).await;
+        }
+    }
+
+    let manifests = if all {
+        cache.get_all_manifests().await?
+    } else {
+        let hash = hash.unwrap_or_default();
+        let dht = init_dht(args).await;
+        let lookup = LookupService::new(cache.clone(), dht, store.clone());
+        match lookup.lookup_file(hash).await? {
+            Some(found) => vec![found.manifest],
+            None => anyhow::bail!("No manifest found for {}", hash),
+        }
+    };
+
+    // Shards stay encrypted; Reed-Solomon alone proves reconstructability
+    let caps = capabilities::HardwareCaps::probe();
+    let ces_config = types::CesConfig::adaptive(&caps, 1024 * 1024, 1.0);
+    let ces = Arc::new(ces::CesPipeline::new(ces_config));
+    let transport: Arc<dyn ShardTransport> = match simulated {
+        Some(network) => network,
+        None => go_client.clone(),
+    };
+    let healer = AutoHealer::new(AutoHealConfig::default(), cache, ces, go_client, store)
+        .with_transport(transport)
+        .with_concurrency_limits(concurrency_limits(args)?)
+        .with_keypair(Arc::new(load_keypair(args)?));
+
+    println!("\n🔍 Verifying {} file(s)", manifests.len());
+    let mut unrecoverable = 0;
+    for manifest in &manifests {
+        let report = healer.verify(manifest).await;
+        println!(
+            "\n{} ({}, namespace: {})",
+            report.file_name, report.file_hash, report.namespace
+        );
+        println!(
+            "{:<8} {:<8} {:<12} {:<12}",
+            "Shard", "Peer", "Local", "Remote"
+        );
+        for shard in &report.shards {
+            println!(
+                "{:<8} {:<8} {:<12} {:<12}",
+                shard.shard_index, shard.peer_id, shard.local, shard.remote
+            );
+        }
+        println!(
+            "  Valid shards: {}/{} (need {}, {} more loss(es) tolerated)",
+            report.valid_shards(),
+            report.shard_count,
+            report.data_shards,
+            report.loss_tolerance()
+        );
+        println!(
+            "  Missing redundancy: {} shard(s)",
+            report.missing_redundancy()
+        );
+        let corrupt = report
+            .shards
+            .iter()
+            .filter(|s| s.local == ShardStatus::Corrupt || s.remote == ShardStatus::Corrupt)
+            .count();
+        if corrupt > 0 {
+            println!("  Corrupt copies: {}", corrupt);
+        }
+
+        if report.is_healthy() && !(heal && manifest.rotation_pending()) {
+            println!("  ✅ Healthy");
+            continue;
+        }
+        if !report.reconstructable {
+            println!("  ❌ Not reconstructable from the surviving shards");
+            unrecoverable += 1;
+            continue;
+        }
+        if !report.is_healthy() {
+            println!(
+                "  ⚠️  Degraded: {} location(s) without a valid copy",
+                report.degraded_locations()
+            );
+        }
+        if heal {
+            match healer.heal(manifest).await {
+                Ok(recovered) => println!("  🔧 Healed: {} shard(s) restored", recovered),
+                Err(e) => println!("  ❌ Heal failed: {}", e),
+            }
+        }
+    }
+
+    if unrecoverable > 0 {
+        anyhow::bail!(
+            "{} of {} file(s) cannot be reconstructed",
+            unrecoverable,
+            manifests.len()
+        );
+    }
+    Ok(())
+}
+
+/// Handle import command
+async fn handle_import(bundle: &str, register: bool, args: &Args) -> anyhow::Result<()> {
+    info!("📦 Import mode: {}", bundle);
+
+    let dht = if register { init_dht(args).await } else { None };
+    if register && dht.is_none() {
+        warn!("⚠️  DHT unavailable; the file is imported locally but not registered");
+    }
+    let downloader = create_cache_downloader(args, namespace::DEFAULT_NAMESPACE, dht).await?;
+    let report = downloader.import_bundle(bundle, register).await?;
+
+    println!("\n📦 Import Summary:");
+    println!("  File: {}", report.file_name);
+    println!("  Hash: {}", report.file_hash);
+    println!("  Namespace: {}", report.namespace);
+    println!("  Shards imported: {}", report.shards_imported);
+
+    Ok(())
+}
+
+/// Print live rate and ETA on one line of stderr, if it is a terminal
+fn spawn_progress_display(hub: &ProgressHub) -> Option<tokio::task::JoinHandle<()>> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stderr().is_terminal() {
+        return None;
+    }
+    let mut receiver = hub.subscribe();
+    Some(tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(progress) => {
+                    let mut stderr = std::io::stderr().lock();
+                    // Clear the line so a shorter update leaves no residue
+                    let _ = write!(stderr, "\r\x1b[2K{}", progress.status_line());
+                    if let Some(warning) = &progress.warning {
+                        let _ = writeln!(stderr, "\n  ⚠️  {}", warning);
+                    }
+                    if progress.finished {
+                        let _ = writeln!(stderr);
+                    }
+                    let _ = stderr.flush();
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }))
+}
+
+/// Let the display print the last snapshots once every reporter is gone
+async fn finish_progress_display(hub: ProgressHub, task: Option<tokio::task::JoinHandle<()>>) {
+    drop(hub);
+    if let Some(task) = task {
+        let _ = task.await;
+    }
+}
+
 /// Handle list command
-async fn handle_list(args: &Args) -> anyhow::Result<()> {
-    info!("📋 Listing files");
+async fn handle_list(namespace: &str, network: bool, args: &Args) -> anyhow::Result<()> {
+    info!("📋 Listing files in namespace {}", namespace);
 
-    let downloader = create_cache_downloader(args).await?;
-    let files = downloader.list_files().await?;
+    let search = match &args.config {
+        Some(path) => SearchConfig::from_file(path)?,
+        None => SearchConfig::default(),
+    };
+    let files = if network && search.enabled {
+        create_cache_downloader(args, namespace, init_dht(args).await)
+            .await?
+            .with_keyword_index(Arc::new(KeywordIndex::new(search)?))
+            .list_files_with_network()
+            .await?
+    } else {
+        if network {
+            warn!("⚠️  Listing network files needs [search] enabled; showing local files only");
+        }
+        create_cache_downloader(args, namespace, None)
+            .await?
+            .list_files()
+            .await?
+    };
+
+    print_file_list(namespace, &files);
+    Ok(())
+}
 
+fn print_file_list(namespace: &str, files: &[FileInfo]) {
     if files.is_empty() {
-        println!("No files found in cache.");
-        return Ok(());
+        println!("No files found in namespace '{}'.", namespace);
+        return;
     }
 
-    println!("\n📁 Available Files ({} total):\n", files.len());
     println!(
-        "{:<10} {:<30} {:<15} {:<10} {:<10}",
-        "Hash", "Name", "Size", "Shards", "Status"
+        "\n📁 Available Files in '{}' ({} total):\n",
+        namespace,
+        files.len()
+    );
+    print_file_table(files);
+}
+
+fn print_file_table(files: &[FileInfo]) {
+    println!(
+        "{:<10} {:<30} {:<15} {:<10} {:<10} {:<14}",
+        "Hash", "Name", "Size", "Shards", "Status", "Source"
     );
     println!("{}", "-".repeat(TABLE_SEPARATOR_LEN));
 
     for file in files {
-        let (hash_short, name_display, status) = format_file_display(&file);
+        let (hash_short, name_display, status) = format_file_display(file);
         println!(
-            "{:<10} {:<30} {:<15} {:<10} {:<10}",
+            "{:<10} {:<30} {:<15} {:<10} {:<10} {:<14}",
             hash_short,
             name_display,
             format!("{} B", file.file_size),
             file.shard_count,
-            status
+            status,
+            file.source.to_string()
         );
     }
     println!();
-
-    Ok(())
 }
 
 /// Handle search command
-async fn handle_search(pattern: &str, args: &Args) -> anyhow::Result<()> {
-    info!("🔍 Searching for: {}", pattern);
+async fn handle_search(pattern: &str, namespace: &str, args: &Args) -> anyhow::Result<()> {
+    info!("🔍 Searching for: {} (namespace: {})", pattern, namespace);
 
-    let downloader = create_cache_downloader(args).await?;
+    let search = match &args.config {
+        Some(path) => SearchConfig::from_file(path)?,
+        None => SearchConfig::default(),
+    };
+    let downloader = if search.enabled {
+        create_cache_downloader(args, namespace, init_dht(args).await)
+            .await?
+            .with_keyword_index(Arc::new(KeywordIndex::new(search)?))
+    } else {
+        create_cache_downloader(args, namespace, None).await?
+    };
     let files = downloader.search(pattern).await?;
+    print_search_results(pattern, &files);
+    Ok(())
+}
 
+fn print_search_results(pattern: &str, files: &[FileInfo]) {
     if files.is_empty() {
         println!("No files matching '{}' found.", pattern);
-        return Ok(());
+        return;
     }
 
     println!(
@@ -636,21 +2670,105 @@ async fn handle_search(pattern: &str, args: &Args) -> anyhow::Result<()> {
         pattern,
         files.len()
     );
+    print_file_table(files);
+}
+
+/// Handle info command
+fn handle_audit(
+    filter: &audit::AuditFilter,
+    limit: usize,
+    json: bool,
+    args: &Args,
+) -> anyhow::Result<()> {
+    let config = match &args.config {
+        Some(path) => audit::AuditConfig::from_file(path)?,
+        None => audit::AuditConfig::default(),
+    };
+    let records = AuditLog::read(config.path_or(get_cache_dir()), filter)?;
+    let shown = &records[records.len().saturating_sub(limit)..];
+
+    if json {
+        for record in shown {
+            println!("{}", serde_json::to_string(record)?);
+        }
+        return Ok(());
+    }
+    if shown.is_empty() {
+        println!("No matching audit records");
+        return Ok(());
+    }
+
     println!(
-        "{:<10} {:<30} {:<15} {:<10} {:<10}",
-        "Hash", "Name", "Size", "Shards", "Status"
+        "\n{:<20} {:<9} {:<18} {:>12} {:>9} {:<14} Peers",
+        "Time", "Operation", "Hash", "Bytes", "Duration", "Requester"
     );
-    println!("{}", "-".repeat(TABLE_SEPARATOR_LEN));
+    for record in shown {
+        let time = chrono::DateTime::<chrono::Utc>::from_timestamp(record.timestamp, 0)
+            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| record.timestamp.to_string());
+        let operation = match (record.operation, record.success) {
+            (audit::AuditOperation::Upload, true) => "upload",
+            (audit::AuditOperation::Download, true) => "download",
+            (audit::AuditOperation::Upload, false) => "upload ✗",
+            (audit::AuditOperation::Download, false) => "download ✗",
+        };
+        let hash = record.file_hash.as_deref().unwrap_or("-");
+        let peers: Vec<String> = record.peers.iter().map(u32::to_string).collect();
+        println!(
+            "{:<20} {:<9} {:<18} {:>12} {:>7}ms {:<14} {}",
+            time,
+            operation,
+            &hash[..hash.len().min(16)],
+            record.bytes,
+            record.duration_ms,
+            record.requester.as_deref().unwrap_or("-"),
+            peers.join(",")
+        );
+        if let Some(error) = &record.error {
+            println!("{:<20} error: {}", "", error);
+        }
+    }
+    println!("\n{} of {} matching record(s)", shown.len(), records.len());
+    Ok(())
+}
 
-    for file in files {
-        let (hash_short, name_display, status) = format_file_display(&file);
+/// Handle top command
+async fn handle_top(limit: usize, json: bool, args: &Args) -> anyhow::Result<()> {
+    let cache = open_cache(args, &get_cache_dir())?;
+    let Some(popularity) = cache.popularity() else {
+        anyhow::bail!("Popularity tracking is disabled in [popularity]");
+    };
+    let top = popularity.top(limit);
+
+    if json {
+        for entry in &top {
+            println!("{}", serde_json::to_string(entry)?);
+        }
+        return Ok(());
+    }
+    if top.is_empty() {
+        println!("No file has been requested yet");
+        return Ok(());
+    }
+
+    cache.load_persisted_manifests().await?;
+    println!(
+        "\n{:<18} {:<30} {:>9} {:>9} Temp",
+        "Hash", "Name", "Score", "Requests"
+    );
+    for entry in top {
+        let name = cache
+            .get_manifest(&entry.file_hash)
+            .await
+            .map(|manifest| manifest.file_name)
+            .unwrap_or_else(|| "-".to_string());
         println!(
-            "{:<10} {:<30} {:<15} {:<10} {:<10}",
-            hash_short,
-            name_display,
-            format!("{} B", file.file_size),
-            file.shard_count,
-            status
+            "{:<18} {:<30} {:>9.2} {:>9} {}",
+            &entry.file_hash[..entry.file_hash.len().min(16)],
+            name.chars().take(30).collect::<String>(),
+            entry.score,
+            entry.total,
+            entry.temperature
         );
     }
     println!();
@@ -658,13 +2776,103 @@ async fn handle_search(pattern: &str, args: &Args) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Handle info command
-async fn handle_info(hash: &str, args: &Args) -> anyhow::Result<()> {
+/// Handle snapshot commands
+async fn handle_snapshot(action: &SnapshotAction, args: &Args) -> anyhow::Result<()> {
+    let cache_dir = get_cache_dir();
+    let layout = state_layout(args, &cache_dir)?;
+
+    match action {
+        SnapshotAction::Create { output } => {
+            let cache = Arc::new(open_cache(args, &cache_dir)?);
+            cache.load_persisted_manifests().await?;
+            let snapshotter = Snapshotter::new(args.node_id, layout, cache);
+            let info = match output {
+                Some(path) => snapshotter.create(path).await?,
+                None => {
+                    let config = match &args.config {
+                        Some(path) => SnapshotConfig::from_file(path)?,
+                        None => SnapshotConfig::default(),
+                    };
+                    snapshotter
+                        .create_in(config.dir_or(&cache_dir), config.keep)
+                        .await?
+                }
+            };
+            println!("\n📸 Snapshot Summary:");
+            println!("  Node: {}", info.meta.node_id);
+            println!("  Files: {}", info.meta.entries.len());
+            println!("  Manifests: {}", info.manifests);
+            println!("  Snapshot: {:?} ({} bytes)", info.path, info.bytes);
+        }
+        SnapshotAction::Restore { snapshot, force } => {
+            let report =
+                pangea_ces::snapshot::restore(snapshot, &layout, args.node_id, *force).await?;
+            println!("\n📦 Restore Summary:");
+            println!("  Node: {}", report.meta.node_id);
+            println!("  Taken at: {} (Unix time)", report.meta.created_at);
+            println!("  Files restored: {}", report.files_restored);
+            println!("  Manifests: {}", report.manifests);
+            if !report.skipped.is_empty() {
+                println!(
+                    "  Skipped: {} metrics file(s); pass --metrics-history to restore them",
+                    report.skipped.len()
+                );
+            }
+            println!("  Shards are fetched from peers on demand; run `verify --all` to check them");
+        }
+    }
+    Ok(())
+}
+
+fn handle_deny(action: &DenyAction) -> anyhow::Result<()> {
+    let path = get_deny_list_path(&get_cache_dir());
+    let deny_list = DenyList::open(&path)?;
+
+    match action {
+        DenyAction::Add { hash, reason } => {
+            if deny_list.add(hash, reason.as_deref())? {
+                println!("🚫 Denied {}", hash);
+            } else {
+                println!("{} is already denied", hash);
+            }
+        }
+        DenyAction::Remove { hash } => {
+            if deny_list.remove(hash)? {
+                println!("✅ {} is no longer denied", hash);
+            } else {
+                println!("{} is not on the deny list", hash);
+            }
+        }
+        DenyAction::List => {
+            let entries = deny_list.entries();
+            if entries.is_empty() {
+                println!("No denied hashes");
+                return Ok(());
+            }
+            println!("\n🚫 Denied hashes ({}):", entries.len());
+            for entry in entries {
+                match entry.reason {
+                    Some(reason) => println!("  {}  ({})", entry.hash, reason),
+                    None => println!("  {}", entry.hash),
+                }
+            }
+            println!();
+        }
+    }
+    println!("Audit log: {}", DenyList::audit_path(&path).display());
+    Ok(())
+}
+
+async fn handle_info(hash: &str, namespace: &str, args: &Args) -> anyhow::Result<()> {
     info!("ℹ️  Getting info for: {}", hash);
 
-    let downloader = create_cache_downloader(args).await?;
+    let downloader = create_cache_downloader(args, namespace, None).await?;
+    print_file_info(hash, downloader.get_info(hash).await?);
+    Ok(())
+}
 
-    if let Some(info) = downloader.get_info(hash).await? {
+fn print_file_info(hash: &str, info: Option<FileInfo>) {
+    if let Some(info) = info {
         use chrono::{DateTime, Utc};
         let timestamp_str = DateTime::<Utc>::from_timestamp(info.timestamp, 0)
             .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
@@ -692,6 +2900,4 @@ async fn handle_info(hash: &str, args: &Args) -> anyhow::Result<()> {
     } else {
         println!("❌ File not found: {}", hash);
     }
-
-    Ok(())
 }
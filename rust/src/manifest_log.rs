@@ -0,0 +1,351 @@
+/// Append-only log of cached manifests
+///
+/// Manifests used to be persisted one JSON file each under `manifests/`,
+/// which costs an inode and a file write (plus fsyncs) per manifest and a
+/// directory walk on every start. They are now kept in a single log,
+/// `manifests/manifests.jsonl`: each put or removal appends one JSON line,
+/// and replaying the log gives the latest manifest of every file.
+///
+/// Superseded lines are compacted away progressively: once they outnumber
+/// the live manifests (and the log has grown past [`COMPACT_MIN_LINES`]),
+/// the log is rewritten atomically with one line per live manifest.
+///
+/// `manifests/*.json` files, from older caches or a restored snapshot, are
+/// imported into the log when the cache loads and then deleted.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::cache::FileManifest;
+use crate::durability::{sync_dir, write_atomic, Durability};
+
+/// File name of the log inside the manifest directory
+pub const MANIFEST_LOG_FILE: &str = "manifests.jsonl";
+
+/// Logs shorter than this are never compacted
+pub const COMPACT_MIN_LINES: usize = 1024;
+
+/// One line of the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LogEntry {
+    Put(FileManifest),
+    Remove(String),
+}
+
+/// What replaying the log found
+#[derive(Debug, Default)]
+pub struct Replay {
+    /// Latest manifest of every file still in the log
+    pub manifests: HashMap<String, FileManifest>,
+    /// Lines read, live or superseded
+    pub lines: usize,
+    /// Lines that did not parse or held an invalid manifest
+    pub skipped: usize,
+    /// Whether an unfinished last line (a crash mid-append) was cut off
+    pub truncated: bool,
+}
+
+/// The manifest log of one cache directory
+pub struct ManifestLog {
+    dir: PathBuf,
+    durability: Durability,
+    /// Lines in the log, known once it has been replayed
+    lines: AtomicUsize,
+    /// Compaction rewrites the log from the manifests in memory, so it
+    /// waits until they have been loaded from it
+    replayed: AtomicBool,
+    /// Serializes appends with compaction, which replaces the file
+    write: Mutex<()>,
+}
+
+impl ManifestLog {
+    /// Log kept in `dir`, the cache's manifest directory
+    pub fn new(dir: impl Into<PathBuf>, durability: Durability) -> Self {
+        Self {
+            dir: dir.into(),
+            durability,
+            lines: AtomicUsize::new(0),
+            replayed: AtomicBool::new(false),
+            write: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_LOG_FILE)
+    }
+
+    /// Record that `manifest` is cached
+    pub async fn put(&self, manifest: &FileManifest) -> Result<()> {
+        self.append(&LogEntry::Put(manifest.clone())).await
+    }
+
+    /// Record that the manifest of `file_hash` was removed
+    pub async fn remove(&self, file_hash: &str) -> Result<()> {
+        self.append(&LogEntry::Remove(file_hash.to_string())).await
+    }
+
+    async fn append(&self, entry: &LogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let path = self.path();
+        let _write = self.write.lock().await;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let created = !path.exists();
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to open {:?}", path))?;
+        file.write_all(&line)
+            .await
+            .with_context(|| format!("Failed to append to {:?}", path))?;
+        if self.durability >= Durability::FsyncFile {
+            file.sync_data().await?;
+        }
+        if created && self.durability >= Durability::FsyncDir {
+            sync_dir(&self.dir).await?;
+        }
+        self.lines.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Read the log back
+    ///
+    /// A last line left unfinished by a crash is cut off the file; other
+    /// lines that do not parse, or hold a manifest that fails validation,
+    /// are skipped.
+    pub async fn replay(&self) -> Result<Replay> {
+        let path = self.path();
+        let _write = self.write.lock().await;
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.replayed.store(true, Ordering::Relaxed);
+                return Ok(Replay::default());
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+
+        let mut replay = Replay::default();
+        let mut end = 0;
+        for line in data.split_inclusive(|byte| *byte == b'\n') {
+            if !line.ends_with(b"\n") {
+                replay.truncated = true;
+                break;
+            }
+            end += line.len();
+            replay.lines += 1;
+            match serde_json::from_slice::<LogEntry>(line) {
+                Ok(LogEntry::Put(manifest)) => match manifest.validate() {
+                    Ok(()) => {
+                        replay
+                            .manifests
+                            .insert(manifest.file_hash.clone(), manifest);
+                    }
+                    Err(e) => {
+                        warn!("Skipping invalid manifest in {:?}: {:#}", path, e);
+                        replay.skipped += 1;
+                    }
+                },
+                Ok(LogEntry::Remove(file_hash)) => {
+                    replay.manifests.remove(&file_hash);
+                }
+                Err(e) => {
+                    warn!(
+                        "Skipping unreadable line {} of {:?}: {}",
+                        replay.lines, path, e
+                    );
+                    replay.skipped += 1;
+                }
+            }
+        }
+        if replay.truncated {
+            warn!(
+                "Cutting off an unfinished line at the end of {:?} ({} bytes)",
+                path,
+                data.len() - end
+            );
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .await?;
+            file.set_len(end as u64).await?;
+            if self.durability >= Durability::FsyncFile {
+                file.sync_data().await?;
+            }
+        }
+        self.lines.store(replay.lines, Ordering::Relaxed);
+        self.replayed.store(true, Ordering::Relaxed);
+        Ok(replay)
+    }
+
+    /// Append `manifests/*.json` files to the log, then delete them
+    ///
+    /// Files that do not hold a valid manifest are left for
+    /// [`crate::cache::Cache::recover`] to quarantine. Returns how many
+    /// manifests were imported.
+    pub async fn import_legacy(&self) -> Result<usize> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut imported = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = match tokio::fs::read_to_string(&path).await {
+                Ok(json) => FileManifest::from_json(&json),
+                Err(e) => Err(e.into()),
+            };
+            match parsed {
+                Ok(manifest) => {
+                    self.put(&manifest).await?;
+                    imported.push(path);
+                }
+                Err(e) => warn!("Not importing manifest {:?}: {:#}", path, e),
+            }
+        }
+        // Only once every import is in the log
+        for path in &imported {
+            tokio::fs::remove_file(path).await?;
+        }
+        if !imported.is_empty() {
+            if self.durability >= Durability::FsyncDir {
+                sync_dir(&self.dir).await?;
+            }
+            info!(
+                "Imported {} manifest file(s) into {:?}",
+                imported.len(),
+                self.path()
+            );
+        }
+        Ok(imported.len())
+    }
+
+    /// Whether superseded lines have piled up enough to compact, given
+    /// `live` manifests
+    pub fn needs_compaction(&self, live: usize) -> bool {
+        let lines = self.lines.load(Ordering::Relaxed);
+        self.replayed.load(Ordering::Relaxed)
+            && lines >= COMPACT_MIN_LINES
+            && lines > live.saturating_mul(2)
+    }
+
+    /// Rewrite the log with one line per manifest in `live`
+    pub async fn compact<'a>(&self, live: impl Iterator<Item = &'a FileManifest>) -> Result<()> {
+        let mut data = Vec::new();
+        let mut count = 0;
+        for manifest in live {
+            serde_json::to_writer(&mut data, &LogEntry::Put(manifest.clone()))?;
+            data.push(b'\n');
+            count += 1;
+        }
+        let _write = self.write.lock().await;
+        let before = self.lines.load(Ordering::Relaxed);
+        write_atomic(&self.path(), &data, self.durability)
+            .await
+            .context("Failed to compact manifest log")?;
+        self.lines.store(count, Ordering::Relaxed);
+        debug!("Compacted manifest log from {} to {} lines", before, count);
+        Ok(())
+    }
+
+    /// Forget the log's contents after the manifest directory was emptied
+    pub fn reset(&self) {
+        self.lines.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(file_hash: &str, ttl: u64) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.txt", file_hash),
+            file_size: 100,
+            shard_count: 3,
+            parity_count: 1,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3)],
+            ttl,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_latest_and_cuts_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ManifestLog::new(dir.path(), Durability::None);
+        log.put(&manifest("aaaa", 1)).await.unwrap();
+        log.put(&manifest("bbbb", 1)).await.unwrap();
+        log.put(&manifest("aaaa", 2)).await.unwrap();
+        log.remove("bbbb").await.unwrap();
+
+        // A crash mid-append leaves half a line behind
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(log.path())
+            .unwrap();
+        std::io::Write::write_all(&mut file, b"{\"put\": {\"file_ha").unwrap();
+
+        let replay = ManifestLog::new(dir.path(), Durability::None)
+            .replay()
+            .await
+            .unwrap();
+        assert_eq!(replay.lines, 4);
+        assert!(replay.truncated);
+        assert_eq!(replay.manifests.len(), 1);
+        assert_eq!(replay.manifests["aaaa"].ttl, 2);
+
+        let again = log.replay().await.unwrap();
+        assert!(!again.truncated);
+        assert_eq!(again.lines, 4);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_drops_superseded_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ManifestLog::new(dir.path(), Durability::None);
+        // Not before the log has been replayed into memory
+        for ttl in 0..COMPACT_MIN_LINES as u64 {
+            log.put(&manifest("aaaa", ttl)).await.unwrap();
+        }
+        assert!(!log.needs_compaction(1));
+        log.replay().await.unwrap();
+        assert!(log.needs_compaction(1));
+        assert!(!log.needs_compaction(COMPACT_MIN_LINES));
+
+        let live = manifest("aaaa", 7);
+        log.compact(std::iter::once(&live)).await.unwrap();
+        assert!(!log.needs_compaction(1));
+        let replay = log.replay().await.unwrap();
+        assert_eq!(replay.lines, 1);
+        assert_eq!(replay.manifests["aaaa"].ttl, 7);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_files_are_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = serde_json::to_string_pretty(&manifest("aaaa", 5)).unwrap();
+        std::fs::write(dir.path().join("aaaa.json"), json).unwrap();
+        std::fs::write(dir.path().join("torn.json"), b"{\"file_hash\"").unwrap();
+
+        let log = ManifestLog::new(dir.path(), Durability::None);
+        assert_eq!(log.import_legacy().await.unwrap(), 1);
+        assert!(!dir.path().join("aaaa.json").exists());
+        assert!(dir.path().join("torn.json").exists());
+        assert_eq!(log.replay().await.unwrap().manifests["aaaa"].ttl, 5);
+        assert_eq!(log.import_legacy().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,619 @@
+/// Differential manifest sync between this node and the Go node
+///
+/// The Rust cache and the Go node each keep manifests, and they drift apart
+/// whenever one writes while the other is down or unreachable. They are
+/// reconciled in two round trips:
+///
+/// 1. `getManifestDigests`: the Go node lists a [`ManifestDigest`] per
+///    manifest it holds (file hash, timestamp, SHA-256 of the JSON)
+/// 2. `syncManifests`: we push the manifests the Go node lacks or holds an
+///    older version of, and name the ones we want back; the answer carries
+///    those
+///
+/// Only manifests that differ cross the wire. Both sides resolve a conflict
+/// between two versions of a manifest the same way, so they converge:
+///
+/// - a record whose signature does not verify is refused; with
+///   `require_signatures`, so is one not signed by a trusted key
+/// - otherwise the newer manifest `timestamp` wins, and equal timestamps
+///   fall back to the larger digest
+///
+/// Records are signed by the node that sends them with its identity key.
+/// Digests are taken over the record's JSON, so the Go node stores records
+/// byte for byte.
+///
+/// The daemon syncs when the Go node first answers, again whenever it comes
+/// back after being unreachable, and every `interval_secs` in between.
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use ed25519_dalek::{Signature, VerifyingKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::cache::{Cache, FileManifest, MAX_MANIFEST_BYTES};
+use crate::go_client::{CircuitState, GoClient};
+use crate::identity::{parse_peer_id, peer_id_of, NodeIdentity};
+
+/// Domain separation for manifest record signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-manifest-record-v1";
+
+/// Manifest bytes pushed in one sync; the rest follow in later rounds
+const MAX_BATCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// How often the Go node is checked for reconnects
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Manifest sync settings (`[manifest_sync]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ManifestSyncConfig {
+    pub enabled: bool,
+    /// Time between syncs while the Go node stays reachable
+    pub interval_secs: u64,
+    /// Peer IDs whose signed manifests are trusted, besides this node's own
+    pub trusted_signers: Vec<String>,
+    /// Refuse manifests not signed by a trusted key
+    pub require_signatures: bool,
+}
+
+impl Default for ManifestSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 300,
+            trusted_signers: Vec::new(),
+            require_signatures: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    manifest_sync: ManifestSyncConfig,
+}
+
+impl ManifestSyncConfig {
+    /// Load the `[manifest_sync]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[manifest_sync]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.manifest_sync.validate()?;
+        Ok(file.manifest_sync)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_secs == 0 {
+            bail!("manifest_sync: interval_secs must be at least 1");
+        }
+        for signer in &self.trusted_signers {
+            parse_peer_id(signer).context("manifest_sync: invalid trusted signer")?;
+        }
+        Ok(())
+    }
+}
+
+/// Version of one manifest, as exchanged by `getManifestDigests`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDigest {
+    pub file_hash: String,
+    /// The manifest's own timestamp
+    pub timestamp: i64,
+    /// SHA-256 of the manifest JSON, hex
+    pub digest: String,
+}
+
+impl ManifestDigest {
+    pub fn of(manifest: &FileManifest) -> Result<Self> {
+        Ok(Self::of_json(manifest, &serde_json::to_string(manifest)?))
+    }
+
+    fn of_json(manifest: &FileManifest, json: &str) -> Self {
+        Self {
+            file_hash: manifest.file_hash.clone(),
+            timestamp: manifest.timestamp,
+            digest: format!("{:x}", Sha256::digest(json.as_bytes())),
+        }
+    }
+
+    /// Whether this version wins a conflict with `other`
+    pub fn supersedes(&self, other: &ManifestDigest) -> bool {
+        (self.timestamp, &self.digest) > (other.timestamp, &other.digest)
+    }
+}
+
+/// A manifest as JSON, signed by the node that sent it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestRecord {
+    pub manifest: String,
+    /// Signer's Ed25519 public key, hex; empty when unsigned
+    #[serde(default)]
+    pub signer: String,
+    /// Signature over the manifest JSON, hex
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl ManifestRecord {
+    /// Sign a manifest with a node's identity
+    pub fn sign(identity: &NodeIdentity, manifest: &FileManifest) -> Result<Self> {
+        let manifest = serde_json::to_string(manifest)?;
+        let signature = identity
+            .libp2p_keypair()
+            .sign(&signed_bytes(&manifest))
+            .context("Failed to sign manifest record")?;
+        Ok(Self {
+            manifest,
+            signer: hex::encode(identity.verifying_key().as_bytes()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    pub fn is_signed(&self) -> bool {
+        !self.signer.is_empty()
+    }
+
+    /// Check the signature; returns the signer, or `None` if unsigned
+    pub fn verify(&self) -> Result<Option<PeerId>> {
+        if !self.is_signed() {
+            return Ok(None);
+        }
+        let mut public = [0u8; 32];
+        hex::decode_to_slice(&self.signer, &mut public).context("Malformed signer key")?;
+        let signature = hex::decode(&self.signature).context("Malformed signature")?;
+        let signature =
+            Signature::from_slice(&signature).map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        VerifyingKey::from_bytes(&public)
+            .map_err(|e| anyhow!("Invalid signer key: {}", e))?
+            .verify_strict(&signed_bytes(&self.manifest), &signature)
+            .map_err(|_| anyhow!("Bad manifest record signature"))?;
+        Ok(Some(peer_id_of(&public)?))
+    }
+
+    /// Parse the manifest and take its digest
+    pub fn open(&self) -> Result<(FileManifest, ManifestDigest)> {
+        if self.manifest.len() > MAX_MANIFEST_BYTES {
+            bail!("Manifest record is {} bytes", self.manifest.len());
+        }
+        let manifest = FileManifest::from_json(&self.manifest)?;
+        let digest = ManifestDigest::of_json(&manifest, &self.manifest);
+        Ok((manifest, digest))
+    }
+}
+
+fn signed_bytes(manifest: &str) -> Vec<u8> {
+    let mut bytes = SIGNING_CONTEXT.to_vec();
+    bytes.extend_from_slice(manifest.as_bytes());
+    bytes
+}
+
+/// The other side of a manifest sync
+#[async_trait(?Send)]
+pub trait ManifestSyncPeer {
+    /// Whether the peer can be reached now, reconnecting if needed
+    async fn ensure_connected(&self) -> bool {
+        true
+    }
+
+    /// Digests of every manifest the peer holds
+    async fn manifest_digests(&self) -> Result<Vec<ManifestDigest>>;
+
+    /// Hand the peer `push` and fetch the records named in `want`
+    async fn exchange_manifests(
+        &self,
+        push: Vec<ManifestRecord>,
+        want: Vec<String>,
+    ) -> Result<Vec<ManifestRecord>>;
+}
+
+/// Manifests exchanged in one sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ManifestSyncOutcome {
+    /// Records sent to the peer
+    pub pushed: usize,
+    /// Records stored locally
+    pub pulled: usize,
+    /// Records refused for their signature or content
+    pub rejected: usize,
+}
+
+/// Keeps the cache's manifests in step with another manifest store
+pub struct ManifestSync {
+    config: ManifestSyncConfig,
+    identity: NodeIdentity,
+    /// Trusted signers, this node included
+    trusted: Vec<PeerId>,
+    cache: Arc<Cache>,
+}
+
+impl ManifestSync {
+    pub fn new(
+        config: ManifestSyncConfig,
+        identity: NodeIdentity,
+        cache: Arc<Cache>,
+    ) -> Result<Self> {
+        config.validate()?;
+        let mut trusted: Vec<PeerId> = config
+            .trusted_signers
+            .iter()
+            .map(|signer| parse_peer_id(signer))
+            .collect::<Result<_>>()?;
+        trusted.push(identity.peer_id());
+        Ok(Self {
+            config,
+            identity,
+            trusted,
+            cache,
+        })
+    }
+
+    /// Digests of every manifest in the cache
+    ///
+    /// Backs `getManifestDigests` when the Go node syncs with us.
+    pub async fn digests(&self) -> Vec<ManifestDigest> {
+        self.cache
+            .list_manifests()
+            .await
+            .iter()
+            .filter_map(|manifest| ManifestDigest::of(manifest).ok())
+            .collect()
+    }
+
+    /// Store the pushed records and return the wanted ones
+    ///
+    /// Backs `syncManifests`; the count is of records stored.
+    pub async fn exchange(
+        &self,
+        push: &[ManifestRecord],
+        want: &[String],
+    ) -> (Vec<ManifestRecord>, usize) {
+        let mut accepted = 0;
+        for record in push {
+            match self.accept(record, None).await {
+                Ok(true) => accepted += 1,
+                Ok(false) => {}
+                Err(e) => debug!("Refused pushed manifest: {:#}", e),
+            }
+        }
+        (self.records(want).await, accepted)
+    }
+
+    /// Bring the cache and `peer` in step
+    pub async fn sync_with<P: ManifestSyncPeer + ?Sized>(
+        &self,
+        peer: &P,
+    ) -> Result<ManifestSyncOutcome> {
+        let remote: HashMap<String, ManifestDigest> = peer
+            .manifest_digests()
+            .await?
+            .into_iter()
+            .map(|digest| (digest.file_hash.clone(), digest))
+            .collect();
+        let local: HashMap<String, ManifestDigest> = self
+            .digests()
+            .await
+            .into_iter()
+            .map(|digest| (digest.file_hash.clone(), digest))
+            .collect();
+
+        let mut push: Vec<String> = local
+            .values()
+            .filter(|ours| {
+                remote
+                    .get(&ours.file_hash)
+                    .is_none_or(|theirs| ours.supersedes(theirs))
+            })
+            .map(|ours| ours.file_hash.clone())
+            .collect();
+        push.sort_unstable();
+        let mut want: Vec<String> = remote
+            .values()
+            .filter(|theirs| {
+                local
+                    .get(&theirs.file_hash)
+                    .is_none_or(|ours| theirs.supersedes(ours))
+            })
+            .map(|theirs| theirs.file_hash.clone())
+            .collect();
+        want.sort_unstable();
+        if push.is_empty() && want.is_empty() {
+            return Ok(ManifestSyncOutcome::default());
+        }
+
+        let push = self.records(&push).await;
+        let mut outcome = ManifestSyncOutcome {
+            pushed: push.len(),
+            ..Default::default()
+        };
+        let wanted: HashSet<String> = want.iter().cloned().collect();
+        for record in peer.exchange_manifests(push, want).await? {
+            match self.accept(&record, Some(&wanted)).await {
+                Ok(true) => outcome.pulled += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Refused manifest from the Go node: {:#}", e);
+                    outcome.rejected += 1;
+                }
+            }
+        }
+        if outcome != ManifestSyncOutcome::default() {
+            info!(
+                "Synced manifests with the Go node: {} pushed, {} pulled, {} refused",
+                outcome.pushed, outcome.pulled, outcome.rejected
+            );
+        }
+        Ok(outcome)
+    }
+
+    /// Sync on (re)connect and every `interval_secs`, until aborted
+    ///
+    /// Runs on a `LocalSet`, as the Go client is not `Send`.
+    pub async fn run<P: ManifestSyncPeer + ?Sized>(self: Arc<Self>, peer: Arc<P>) -> Result<()> {
+        info!(
+            "Syncing manifests with the Go node every {}s",
+            self.config.interval_secs
+        );
+        let interval = Duration::from_secs(self.config.interval_secs);
+        let mut poll = tokio::time::interval(RECONNECT_POLL_INTERVAL);
+        // Last attempt while the peer stayed reachable
+        let mut last_sync: Option<Instant> = None;
+        loop {
+            poll.tick().await;
+            if !peer.ensure_connected().await {
+                last_sync = None;
+                continue;
+            }
+            if last_sync.is_some_and(|at| at.elapsed() < interval) {
+                continue;
+            }
+            if let Err(e) = self.sync_with(peer.as_ref()).await {
+                warn!("Manifest sync with the Go node failed: {:#}", e);
+            }
+            last_sync = Some(Instant::now());
+        }
+    }
+
+    /// Signed records of the named manifests we hold, up to one batch
+    async fn records(&self, hashes: &[String]) -> Vec<ManifestRecord> {
+        let mut batch = Vec::new();
+        let mut bytes = 0;
+        for hash in hashes {
+            let Some(manifest) = self.cache.get_manifest(hash).await else {
+                continue;
+            };
+            let record = match ManifestRecord::sign(&self.identity, &manifest) {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!("Failed to sign manifest {}: {:#}", hash, e);
+                    continue;
+                }
+            };
+            if bytes + record.manifest.len() > MAX_BATCH_BYTES && !batch.is_empty() {
+                break;
+            }
+            bytes += record.manifest.len();
+            batch.push(record);
+        }
+        batch
+    }
+
+    /// Store a record if it verifies and wins over the version held
+    ///
+    /// With `wanted`, records for other manifests are refused.
+    async fn accept(
+        &self,
+        record: &ManifestRecord,
+        wanted: Option<&HashSet<String>>,
+    ) -> Result<bool> {
+        let signer = record.verify()?;
+        if self.config.require_signatures && !signer.is_some_and(|s| self.trusted.contains(&s)) {
+            match signer {
+                Some(signer) => bail!("Manifest record signed by untrusted {}", signer),
+                None => bail!("Unsigned manifest record"),
+            }
+        }
+        let (manifest, digest) = record.open()?;
+        if wanted.is_some_and(|wanted| !wanted.contains(&manifest.file_hash)) {
+            bail!("Manifest {} was not asked for", manifest.file_hash);
+        }
+        if let Some(held) = self.cache.get_manifest(&manifest.file_hash).await {
+            if !digest.supersedes(&ManifestDigest::of(&held)?) {
+                return Ok(false);
+            }
+        }
+        debug!("Storing synced manifest {}", manifest.file_hash);
+        self.cache.put_manifest(manifest).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait(?Send)]
+impl ManifestSyncPeer for ManifestSync {
+    async fn manifest_digests(&self) -> Result<Vec<ManifestDigest>> {
+        Ok(self.digests().await)
+    }
+
+    async fn exchange_manifests(
+        &self,
+        push: Vec<ManifestRecord>,
+        want: Vec<String>,
+    ) -> Result<Vec<ManifestRecord>> {
+        Ok(self.exchange(&push, &want).await.0)
+    }
+}
+
+#[async_trait(?Send)]
+impl ManifestSyncPeer for GoClient {
+    async fn ensure_connected(&self) -> bool {
+        if self.circuit_state() == CircuitState::Open {
+            return false;
+        }
+        if self.is_connected() {
+            return true;
+        }
+        match self.connect().await {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("Go node unreachable for manifest sync: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn manifest_digests(&self) -> Result<Vec<ManifestDigest>> {
+        Ok(self.get_manifest_digests().await?)
+    }
+
+    async fn exchange_manifests(
+        &self,
+        push: Vec<ManifestRecord>,
+        want: Vec<String>,
+    ) -> Result<Vec<ManifestRecord>> {
+        Ok(self.sync_manifests(push, want).await?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn manifest(file_hash: &str, file_name: &str, timestamp: i64) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: file_name.to_string(),
+            file_size: 1024,
+            shard_count: 4,
+            parity_count: 2,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3), (3, 4)],
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    fn node(dir: &Path, secret: u8, config: ManifestSyncConfig) -> ManifestSync {
+        let cache = Arc::new(Cache::new(dir, 100, 1024 * 1024).unwrap());
+        ManifestSync::new(config, NodeIdentity::from_secret([secret; 32]), cache).unwrap()
+    }
+
+    #[test]
+    fn test_records_verify_and_newer_digests_win() {
+        let identity = NodeIdentity::from_secret([1; 32]);
+        let record = ManifestRecord::sign(&identity, &manifest("a", "a.txt", 10)).unwrap();
+        assert_eq!(record.verify().unwrap(), Some(identity.peer_id()));
+        let (opened, digest) = record.open().unwrap();
+        assert_eq!(opened.file_name, "a.txt");
+        assert_eq!(digest, ManifestDigest::of(&opened).unwrap());
+
+        let mut forged = record.clone();
+        forged.manifest = forged.manifest.replace("a.txt", "b.txt");
+        assert!(forged.verify().is_err());
+
+        let older = ManifestDigest::of(&manifest("a", "a.txt", 10)).unwrap();
+        let newer = ManifestDigest::of(&manifest("a", "b.txt", 11)).unwrap();
+        assert!(newer.supersedes(&older));
+        assert!(!older.supersedes(&newer));
+        assert!(!older.supersedes(&older));
+    }
+
+    #[tokio::test]
+    async fn test_sync_exchanges_only_differing_manifests() {
+        let (dir_a, dir_b) = (tempdir().unwrap(), tempdir().unwrap());
+        let a = node(dir_a.path(), 1, ManifestSyncConfig::default());
+        let b = node(dir_b.path(), 2, ManifestSyncConfig::default());
+
+        a.cache
+            .put_manifest(manifest("shared", "old.txt", 10))
+            .await
+            .unwrap();
+        a.cache
+            .put_manifest(manifest("only-a", "a.txt", 10))
+            .await
+            .unwrap();
+        b.cache
+            .put_manifest(manifest("shared", "new.txt", 20))
+            .await
+            .unwrap();
+        b.cache
+            .put_manifest(manifest("only-b", "b.txt", 10))
+            .await
+            .unwrap();
+        b.cache
+            .put_manifest(manifest("same", "same.txt", 10))
+            .await
+            .unwrap();
+        a.cache
+            .put_manifest(manifest("same", "same.txt", 10))
+            .await
+            .unwrap();
+
+        let outcome = a.sync_with(&b).await.unwrap();
+        assert_eq!(
+            outcome,
+            ManifestSyncOutcome {
+                pushed: 1,
+                pulled: 2,
+                rejected: 0,
+            }
+        );
+        let shared = a.cache.get_manifest("shared").await.unwrap();
+        assert_eq!(shared.file_name, "new.txt");
+        assert!(a.cache.get_manifest("only-b").await.is_some());
+        assert!(b.cache.get_manifest("only-a").await.is_some());
+
+        // Converged: nothing left to exchange
+        assert_eq!(
+            a.sync_with(&b).await.unwrap(),
+            ManifestSyncOutcome::default()
+        );
+        assert_eq!(
+            b.sync_with(&a).await.unwrap(),
+            ManifestSyncOutcome::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_records_are_refused_when_signatures_are_required() {
+        let (dir_a, dir_b) = (tempdir().unwrap(), tempdir().unwrap());
+        let strict = ManifestSyncConfig {
+            require_signatures: true,
+            ..Default::default()
+        };
+        let a = node(dir_a.path(), 1, strict);
+        let b = node(dir_b.path(), 2, ManifestSyncConfig::default());
+        b.cache
+            .put_manifest(manifest("file", "b.txt", 10))
+            .await
+            .unwrap();
+
+        let outcome = a.sync_with(&b).await.unwrap();
+        assert_eq!(outcome.rejected, 1);
+        assert!(a.cache.get_manifest("file").await.is_none());
+
+        // Trusting b's key lets its records in
+        let trusting = ManifestSyncConfig {
+            require_signatures: true,
+            trusted_signers: vec![b.identity.peer_id().to_string()],
+            ..Default::default()
+        };
+        let a = ManifestSync::new(
+            trusting,
+            NodeIdentity::from_secret([1; 32]),
+            a.cache.clone(),
+        )
+        .unwrap();
+        assert_eq!(a.sync_with(&b).await.unwrap().pulled, 1);
+    }
+}
@@ -1,6 +1,10 @@
+use hdrhistogram::Histogram;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 /// Phase 1: Performance Metrics and Monitoring
 ///
 /// This module provides latency measurement and throughput tracking
@@ -10,9 +14,24 @@ use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
+use crate::metrics_history::MetricsHistory;
+
 /// Phase 1 success metric: maximum acceptable latency in milliseconds
 const PHASE1_LATENCY_TARGET_MS: f64 = 100.0;
 
+/// Latencies are recorded in microseconds, from 1us up to one hour
+const HISTOGRAM_MAX_US: u64 = 3_600_000_000;
+/// Significant decimal digits kept by each histogram (0.1% relative error)
+const HISTOGRAM_SIGFIGS: u8 = 3;
+
+/// Quantiles exported in the Prometheus summary format
+pub const SUMMARY_QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+
+/// Upper bounds in milliseconds of the Prometheus histogram buckets
+pub const DEFAULT_BUCKETS_MS: [f64; 14] = [
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0,
+];
+
 /// Latency measurement for a single operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LatencyMeasurement {
@@ -29,25 +48,236 @@ pub struct ThroughputMeasurement {
     pub throughput_mbps: f64,
 }
 
+/// Hedged request effectiveness counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HedgeStats {
+    /// Duplicate requests issued after the hedge delay
+    pub hedges_issued: u64,
+    /// Hedged fetches where the duplicate answered first
+    pub hedges_won: u64,
+    /// Hedged fetches where the original request still answered first
+    pub primary_won: u64,
+}
+
+impl HedgeStats {
+    /// Fraction of hedges that beat the original request
+    pub fn win_rate(&self) -> f64 {
+        if self.hedges_issued == 0 {
+            0.0
+        } else {
+            self.hedges_won as f64 / self.hedges_issued as f64
+        }
+    }
+}
+
+/// Constant-memory latency distribution backed by an HDR histogram
+///
+/// Quantiles are accurate to within 0.1%; the mean is exact.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+    sum_ms: f64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .field("sum_ms", &self.sum_ms)
+            .finish()
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            histogram: Histogram::new_with_bounds(1, HISTOGRAM_MAX_US, HISTOGRAM_SIGFIGS)
+                .expect("static histogram bounds are valid"),
+            sum_ms: 0.0,
+        }
+    }
+
+    /// Record one latency in milliseconds; values past one hour are clamped
+    pub fn record_ms(&mut self, latency_ms: f64) {
+        let micros = (latency_ms * 1000.0)
+            .round()
+            .clamp(1.0, HISTOGRAM_MAX_US as f64);
+        self.histogram.saturating_record(micros as u64);
+        self.sum_ms += latency_ms;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn sum_ms(&self) -> f64 {
+        self.sum_ms
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.sum_ms / count as f64)
+    }
+
+    /// Latency at `quantile` (0.0 - 1.0)
+    pub fn quantile_ms(&self, quantile: f64) -> Option<f64> {
+        (self.count() > 0).then(|| micros_to_ms(self.histogram.value_at_quantile(quantile)))
+    }
+
+    pub fn min_ms(&self) -> Option<f64> {
+        (self.count() > 0).then(|| micros_to_ms(self.histogram.min()))
+    }
+
+    pub fn max_ms(&self) -> Option<f64> {
+        (self.count() > 0).then(|| micros_to_ms(self.histogram.max()))
+    }
+
+    /// Fold another histogram's samples into this one
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        // Both sides share the same bounds, so adding cannot go out of range
+        let _ = self.histogram.add(&other.histogram);
+        self.sum_ms += other.sum_ms;
+    }
+
+    pub fn reset(&mut self) {
+        self.histogram.reset();
+        self.sum_ms = 0.0;
+    }
+
+    /// Cumulative sample counts at or below each bound, as Prometheus buckets
+    pub fn cumulative_counts(&self, bounds_ms: &[f64]) -> Vec<u64> {
+        bounds_ms
+            .iter()
+            .map(|bound| {
+                let micros = (bound * 1000.0).clamp(0.0, HISTOGRAM_MAX_US as f64) as u64;
+                if micros == 0 {
+                    0
+                } else {
+                    self.histogram.count_between(0, micros)
+                }
+            })
+            .collect()
+    }
+}
+
+fn micros_to_ms(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+/// Latency distribution of one operation in exportable form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramExport {
+    pub operation: String,
+    pub count: u64,
+    pub sum_ms: f64,
+    /// `(upper bound in ms, cumulative count)`; the `+Inf` bucket is `count`
+    pub buckets: Vec<(f64, u64)>,
+    /// `(quantile, latency in ms)` for `SUMMARY_QUANTILES`
+    pub quantiles: Vec<(f64, f64)>,
+}
+
+/// Histograms kept for one operation
+///
+/// `current` and `previous` rotate every `window` samples so percentile
+/// queries follow recent behaviour; `lifetime` never resets and backs the
+/// cumulative Prometheus export.
+#[derive(Default)]
+struct OperationStats {
+    current: LatencyHistogram,
+    previous: LatencyHistogram,
+    lifetime: LatencyHistogram,
+}
+
+impl OperationStats {
+    fn record(&mut self, latency_ms: f64, window: usize) {
+        if self.current.count() >= window as u64 {
+            std::mem::swap(&mut self.current, &mut self.previous);
+            self.current.reset();
+        }
+        self.current.record_ms(latency_ms);
+        self.lifetime.record_ms(latency_ms);
+    }
+
+    /// The last `window` to `2 * window` samples
+    fn recent(&self) -> LatencyHistogram {
+        let mut recent = self.current.clone();
+        recent.merge(&self.previous);
+        recent
+    }
+}
+
 /// Performance metrics tracker
+///
+/// Keeps a constant-memory latency histogram per operation. Percentile
+/// queries cover roughly the last `window` to `2 * window` samples of the
+/// operation, while exports cover everything recorded since start.
 pub struct MetricsTracker {
-    latencies: Arc<RwLock<VecDeque<LatencyMeasurement>>>,
-    max_samples: usize,
+    operations: RwLock<HashMap<String, OperationStats>>,
+    window: usize,
+    hedges_issued: AtomicU64,
+    hedges_won: AtomicU64,
+    hedges_primary_won: AtomicU64,
+    history: Option<Arc<MetricsHistory>>,
 }
 
 impl MetricsTracker {
-    /// Create a new metrics tracker
+    /// Create a new metrics tracker whose percentiles follow the most recent
+    /// `max_samples` samples of each operation
     pub fn new(max_samples: usize) -> Self {
         Self {
-            latencies: Arc::new(RwLock::new(VecDeque::with_capacity(max_samples))),
-            max_samples,
+            operations: RwLock::new(HashMap::new()),
+            window: max_samples.max(1),
+            hedges_issued: AtomicU64::new(0),
+            hedges_won: AtomicU64::new(0),
+            hedges_primary_won: AtomicU64::new(0),
+            history: None,
+        }
+    }
+
+    /// Also fold every sample into per-minute aggregates in `history`
+    pub fn with_history(mut self, history: Arc<MetricsHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Count bytes moved by an operation towards its per-minute throughput
+    ///
+    /// Only kept when a history is attached.
+    pub fn record_bytes(&self, operation: &str, bytes: u64) {
+        if let Some(history) = &self.history {
+            history.record_bytes(operation, bytes, crate::metrics_history::unix_now());
         }
     }
 
     /// Record a latency measurement
     pub fn record_latency(&self, operation: String, latency: Duration) {
+        let measurement = self.push_measurement(operation.clone(), latency);
+
+        // Log if latency exceeds Phase 1 target
+        if measurement.latency_ms > PHASE1_LATENCY_TARGET_MS {
+            warn!(
+                "⚠️  High latency detected: {} took {:.2}ms (target: <{}ms)",
+                operation, measurement.latency_ms, PHASE1_LATENCY_TARGET_MS
+            );
+        }
+    }
+
+    /// Record a latency sample without checking it against the Phase 1 target
+    ///
+    /// Used for bulk transfer operations where >100ms is expected.
+    pub fn record_sample(&self, operation: String, latency: Duration) {
+        self.push_measurement(operation, latency);
+    }
+
+    fn push_measurement(&self, operation: String, latency: Duration) -> LatencyMeasurement {
         let measurement = LatencyMeasurement {
-            operation: operation.clone(),
+            operation,
             latency_ms: latency.as_secs_f64() * 1000.0,
             timestamp: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -55,122 +285,182 @@ impl MetricsTracker {
                 .as_secs(),
         };
 
-        let mut latencies = match self.latencies.write() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                // If the lock is poisoned we recover the inner value and log once;
-                // metrics degradation should not take the whole process down.
-                eprintln!("metrics: RwLock poisoned in record_latency, recovering");
-                poisoned.into_inner()
-            }
-        };
-        if latencies.len() >= self.max_samples {
-            latencies.pop_front();
-        }
-        latencies.push_back(measurement.clone());
-
-        // Log if latency exceeds Phase 1 target
-        if measurement.latency_ms > PHASE1_LATENCY_TARGET_MS {
-            warn!(
-                "⚠️  High latency detected: {} took {:.2}ms (target: <{}ms)",
-                operation, measurement.latency_ms, PHASE1_LATENCY_TARGET_MS
+        self.operations
+            .write()
+            .entry(measurement.operation.clone())
+            .or_default()
+            .record(measurement.latency_ms, self.window);
+
+        if let Some(history) = &self.history {
+            history.record_latency(
+                &measurement.operation,
+                measurement.latency_ms,
+                measurement.timestamp,
             );
         }
+        measurement
+    }
+
+    /// Recent latency distribution of an operation
+    pub fn histogram(&self, operation: &str) -> Option<LatencyHistogram> {
+        let operations = self.operations.read();
+        let recent = operations.get(operation)?.recent();
+        (recent.count() > 0).then_some(recent)
     }
 
     /// Get average latency for an operation
     pub fn average_latency(&self, operation: &str) -> Option<f64> {
-        let latencies = match self.latencies.read() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("metrics: RwLock poisoned in average_latency, recovering");
-                poisoned.into_inner()
-            }
-        };
-        let matching: Vec<f64> = latencies
-            .iter()
-            .filter(|m| m.operation == operation)
-            .map(|m| m.latency_ms)
-            .collect();
+        self.histogram(operation)?.mean_ms()
+    }
 
-        if matching.is_empty() {
-            None
+    /// Get percentile latency (p50, p95, p99)
+    pub fn percentile_latency(&self, operation: &str, percentile: f64) -> Option<f64> {
+        self.histogram(operation)?.quantile_ms(percentile)
+    }
+
+    /// Number of recent samples recorded for an operation
+    pub fn sample_count(&self, operation: &str) -> usize {
+        self.histogram(operation)
+            .map_or(0, |histogram| histogram.count() as usize)
+    }
+
+    /// Record that a hedged duplicate request was issued
+    pub fn record_hedge_issued(&self) {
+        self.hedges_issued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record which request of a hedged pair answered first
+    pub fn record_hedge_outcome(&self, hedge_won: bool) {
+        if hedge_won {
+            self.hedges_won.fetch_add(1, Ordering::Relaxed);
         } else {
-            Some(matching.iter().sum::<f64>() / matching.len() as f64)
+            self.hedges_primary_won.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    /// Get percentile latency (p50, p95, p99)
-    pub fn percentile_latency(&self, operation: &str, percentile: f64) -> Option<f64> {
-        let latencies = match self.latencies.read() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("metrics: RwLock poisoned in percentile_latency, recovering");
-                poisoned.into_inner()
-            }
-        };
-        let mut matching: Vec<f64> = latencies
+    /// Snapshot of hedging counters
+    pub fn hedge_stats(&self) -> HedgeStats {
+        HedgeStats {
+            hedges_issued: self.hedges_issued.load(Ordering::Relaxed),
+            hedges_won: self.hedges_won.load(Ordering::Relaxed),
+            primary_won: self.hedges_primary_won.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Lifetime latency distribution of every operation, sorted by name
+    pub fn export_histograms(&self, bounds_ms: &[f64]) -> Vec<HistogramExport> {
+        let operations = self.operations.read();
+        let mut exports: Vec<HistogramExport> = operations
             .iter()
-            .filter(|m| m.operation == operation)
-            .map(|m| m.latency_ms)
+            .map(|(operation, stats)| {
+                let lifetime = &stats.lifetime;
+                HistogramExport {
+                    operation: operation.clone(),
+                    count: lifetime.count(),
+                    sum_ms: lifetime.sum_ms(),
+                    buckets: bounds_ms
+                        .iter()
+                        .copied()
+                        .zip(lifetime.cumulative_counts(bounds_ms))
+                        .collect(),
+                    quantiles: SUMMARY_QUANTILES
+                        .iter()
+                        .map(|&q| (q, lifetime.quantile_ms(q).unwrap_or(0.0)))
+                        .collect(),
+                }
+            })
             .collect();
+        exports.sort_by(|a, b| a.operation.cmp(&b.operation));
+        exports
+    }
 
-        if matching.is_empty() {
-            return None;
+    /// Render all operations in the Prometheus text format
+    ///
+    /// Emits `<prefix>_latency_ms` as a histogram with `DEFAULT_BUCKETS_MS`
+    /// and `<prefix>_latency_quantiles_ms` as a summary, both labelled by
+    /// operation.
+    pub fn export_prometheus(&self, prefix: &str) -> String {
+        let exports = self.export_histograms(&DEFAULT_BUCKETS_MS);
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE {}_latency_ms histogram", prefix);
+        for export in &exports {
+            let op = escape_label(&export.operation);
+            for (bound, count) in &export.buckets {
+                let _ = writeln!(
+                    out,
+                    "{}_latency_ms_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    prefix, op, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_latency_ms_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+                prefix, op, export.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_latency_ms_sum{{operation=\"{}\"}} {}",
+                prefix, op, export.sum_ms
+            );
+            let _ = writeln!(
+                out,
+                "{}_latency_ms_count{{operation=\"{}\"}} {}",
+                prefix, op, export.count
+            );
         }
 
-        matching.sort_by(|a, b| a.total_cmp(b)); // Use total_cmp for NaN-safe comparison
-        let index = ((matching.len() - 1) as f64 * percentile) as usize;
-        Some(matching[index])
-    }
-
-    /// Get all measurements for an operation
-    pub fn get_measurements(&self, operation: &str) -> Vec<LatencyMeasurement> {
-        let latencies = match self.latencies.read() {
-            Ok(guard) => guard,
-            Err(poisoned) => {
-                eprintln!("metrics: RwLock poisoned in percentiles, recovering");
-                poisoned.into_inner()
+        let _ = writeln!(out, "# TYPE {}_latency_quantiles_ms summary", prefix);
+        for export in &exports {
+            let op = escape_label(&export.operation);
+            for (quantile, value) in &export.quantiles {
+                let _ = writeln!(
+                    out,
+                    "{}_latency_quantiles_ms{{operation=\"{}\",quantile=\"{}\"}} {}",
+                    prefix, op, quantile, value
+                );
             }
-        };
-        latencies
-            .iter()
-            .filter(|m| m.operation == operation)
-            .cloned()
-            .collect()
+            let _ = writeln!(
+                out,
+                "{}_latency_quantiles_ms_sum{{operation=\"{}\"}} {}",
+                prefix, op, export.sum_ms
+            );
+            let _ = writeln!(
+                out,
+                "{}_latency_quantiles_ms_count{{operation=\"{}\"}} {}",
+                prefix, op, export.count
+            );
+        }
+        out
     }
 
     /// Generate a performance report
     pub fn generate_report(&self, operation: &str) -> Option<PerformanceReport> {
-        let avg = self.average_latency(operation)?;
-        let p50 = self.percentile_latency(operation, 0.5)?;
-        let p95 = self.percentile_latency(operation, 0.95)?;
-        let p99 = self.percentile_latency(operation, 0.99)?;
-
-        let measurements = self.get_measurements(operation);
-        let min = measurements
-            .iter()
-            .map(|m| m.latency_ms)
-            .min_by(|a, b| a.total_cmp(b))?; // NaN-safe
-        let max = measurements
-            .iter()
-            .map(|m| m.latency_ms)
-            .max_by(|a, b| a.total_cmp(b))?; // NaN-safe
+        let histogram = self.histogram(operation)?;
+        let p95 = histogram.quantile_ms(0.95)?;
 
         Some(PerformanceReport {
             operation: operation.to_string(),
-            sample_count: measurements.len(),
-            avg_latency_ms: avg,
-            min_latency_ms: min,
-            max_latency_ms: max,
-            p50_latency_ms: p50,
+            sample_count: histogram.count() as usize,
+            avg_latency_ms: histogram.mean_ms()?,
+            min_latency_ms: histogram.min_ms()?,
+            max_latency_ms: histogram.max_ms()?,
+            p50_latency_ms: histogram.quantile_ms(0.5)?,
             p95_latency_ms: p95,
-            p99_latency_ms: p99,
+            p99_latency_ms: histogram.quantile_ms(0.99)?,
             meets_phase1_target: p95 < PHASE1_LATENCY_TARGET_MS, // Phase 1: 95th percentile < 100ms
         })
     }
 }
 
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 /// Performance report for Phase 1 validation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerformanceReport {
@@ -370,4 +660,40 @@ mod tests {
         assert!(report.meets_phase1_target);
         assert!(report.p95_latency_ms < 100.0);
     }
+
+    #[test]
+    fn test_percentiles_follow_recent_window() {
+        let tracker = MetricsTracker::new(10);
+
+        for _ in 0..30 {
+            tracker.record_sample("fetch".to_string(), Duration::from_millis(500));
+        }
+        for _ in 0..20 {
+            tracker.record_sample("fetch".to_string(), Duration::from_millis(5));
+        }
+
+        // Only the last two windows are queried, so the slow samples age out
+        assert_eq!(tracker.sample_count("fetch"), 20);
+        let p99 = tracker.percentile_latency("fetch", 0.99).unwrap();
+        assert!((p99 - 5.0).abs() < 0.1);
+
+        // Exports still cover everything recorded
+        let export = &tracker.export_histograms(&[10.0, 1000.0])[0];
+        assert_eq!(export.count, 50);
+        assert_eq!(export.buckets, vec![(10.0, 20), (1000.0, 50)]);
+    }
+
+    #[test]
+    fn test_prometheus_export() {
+        let tracker = MetricsTracker::new(100);
+        tracker.record_sample("get".to_string(), Duration::from_millis(3));
+        tracker.record_sample("get".to_string(), Duration::from_millis(40));
+
+        let text = tracker.export_prometheus("pangea");
+        assert!(text.contains("# TYPE pangea_latency_ms histogram"));
+        assert!(text.contains("pangea_latency_ms_bucket{operation=\"get\",le=\"5\"} 1"));
+        assert!(text.contains("pangea_latency_ms_bucket{operation=\"get\",le=\"+Inf\"} 2"));
+        assert!(text.contains("pangea_latency_ms_count{operation=\"get\"} 2"));
+        assert!(text.contains("pangea_latency_quantiles_ms{operation=\"get\",quantile=\"0.5\"}"));
+    }
 }
@@ -0,0 +1,410 @@
+/// Per-minute metrics history
+///
+/// `MetricsTracker` only keeps a bounded ring of raw samples. When a
+/// `MetricsHistory` is attached, every sample is also folded into a bucket for
+/// its minute; closed minutes are aggregated (latency percentiles, throughput,
+/// cache hit rate) and appended to one JSON-lines file per UTC day, so trends
+/// can be queried over time ranges long after the raw samples are gone.
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::cache::{Cache, CacheStats};
+use crate::metrics::LatencyHistogram;
+
+/// Operation name under which cache hit rates are recorded
+pub const CACHE_OPERATION: &str = "cache";
+
+/// How often closed minutes are written out by `spawn_flusher`
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default number of daily files kept on disk
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Aggregated metrics for one operation over one minute
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsPoint {
+    /// Unix time of the start of the minute
+    pub minute: u64,
+    pub operation: String,
+    pub samples: u64,
+    pub avg_latency_ms: f64,
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+    /// Bytes transferred by the operation during the minute
+    pub bytes: u64,
+    pub throughput_mbps: f64,
+    /// Only set for the `cache` operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_hit_rate: Option<f64>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    latencies: LatencyHistogram,
+    bytes: u64,
+    cache_hits: u64,
+    cache_misses: u64,
+}
+
+impl Bucket {
+    fn aggregate(self, minute: u64, operation: String) -> MetricsPoint {
+        let percentile = |p: f64| self.latencies.quantile_ms(p).unwrap_or(0.0);
+        let lookups = self.cache_hits + self.cache_misses;
+
+        MetricsPoint {
+            minute,
+            samples: self.latencies.count(),
+            avg_latency_ms: self.latencies.mean_ms().unwrap_or(0.0),
+            p50_latency_ms: percentile(0.5),
+            p95_latency_ms: percentile(0.95),
+            p99_latency_ms: percentile(0.99),
+            bytes: self.bytes,
+            throughput_mbps: crate::metrics::calculate_throughput(
+                self.bytes,
+                Duration::from_secs(60),
+            ),
+            cache_hit_rate: (lookups > 0).then(|| self.cache_hits as f64 / lookups as f64),
+            operation,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    buckets: HashMap<(u64, String), Bucket>,
+    /// Cumulative cache counters at the last snapshot, to turn totals into deltas
+    last_cache: Option<(u64, u64)>,
+}
+
+/// Persistent per-minute metrics aggregates with a time-range query API
+pub struct MetricsHistory {
+    dir: PathBuf,
+    retention_days: u64,
+    pending: Mutex<Pending>,
+    /// Serializes appends so concurrent flushes do not interleave lines
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl MetricsHistory {
+    /// Store history under `dir`, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).context("Failed to create metrics history directory")?;
+        Ok(Self {
+            dir,
+            retention_days: DEFAULT_RETENTION_DAYS,
+            pending: Mutex::new(Pending::default()),
+            write_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Delete daily files older than `days`
+    pub fn with_retention_days(mut self, days: u64) -> Self {
+        self.retention_days = days.max(1);
+        self
+    }
+
+    /// Fold a latency sample taken at `timestamp` (Unix seconds) into its minute
+    pub fn record_latency(&self, operation: &str, latency_ms: f64, timestamp: u64) {
+        self.pending
+            .lock()
+            .buckets
+            .entry((minute_of(timestamp), operation.to_string()))
+            .or_default()
+            .latencies
+            .record_ms(latency_ms);
+    }
+
+    /// Count bytes transferred by an operation at `timestamp`
+    pub fn record_bytes(&self, operation: &str, bytes: u64, timestamp: u64) {
+        self.pending
+            .lock()
+            .buckets
+            .entry((minute_of(timestamp), operation.to_string()))
+            .or_default()
+            .bytes += bytes;
+    }
+
+    /// Snapshot cumulative cache counters; hits and misses since the previous
+    /// snapshot are charged to the minute of `timestamp`
+    pub fn record_cache_stats(&self, stats: &CacheStats, timestamp: u64) {
+        let hits = stats.shard_hits + stats.manifest_hits;
+        let misses = stats.shard_misses + stats.manifest_misses;
+        let mut pending = self.pending.lock();
+        let (last_hits, last_misses) = pending.last_cache.replace((hits, misses)).unwrap_or((0, 0));
+        let bucket = pending
+            .buckets
+            .entry((minute_of(timestamp), CACHE_OPERATION.to_string()))
+            .or_default();
+        // Counters only go down if the cache was recreated
+        bucket.cache_hits += hits.checked_sub(last_hits).unwrap_or(hits);
+        bucket.cache_misses += misses.checked_sub(last_misses).unwrap_or(misses);
+    }
+
+    /// Aggregate minutes that ended before `now` and append them to disk
+    ///
+    /// Returns the number of points written.
+    pub async fn flush(&self, now: u64) -> Result<usize> {
+        let current = minute_of(now);
+        let mut points: Vec<MetricsPoint> = {
+            let mut pending = self.pending.lock();
+            let closed: Vec<(u64, String)> = pending
+                .buckets
+                .keys()
+                .filter(|(minute, _)| *minute < current)
+                .cloned()
+                .collect();
+            closed
+                .into_iter()
+                .filter_map(|key| {
+                    let bucket = pending.buckets.remove(&key)?;
+                    Some(bucket.aggregate(key.0, key.1))
+                })
+                .collect()
+        };
+        if points.is_empty() {
+            return Ok(0);
+        }
+        points.sort_by(|a, b| (a.minute, &a.operation).cmp(&(b.minute, &b.operation)));
+
+        let _guard = self.write_lock.lock().await;
+        let mut by_day: HashMap<u64, String> = HashMap::new();
+        for point in &points {
+            let line = serde_json::to_string(point)?;
+            let text = by_day.entry(point.minute / SECS_PER_DAY).or_default();
+            text.push_str(&line);
+            text.push('\n');
+        }
+        for (day, text) in by_day {
+            let path = self.day_path(day);
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            file.write_all(text.as_bytes()).await?;
+        }
+
+        self.prune(now / SECS_PER_DAY).await;
+        debug!("Persisted {} metrics point(s)", points.len());
+        Ok(points.len())
+    }
+
+    /// Points for minutes starting in `[from, to]` (Unix seconds), oldest first
+    ///
+    /// With `operation` set only that operation is returned. Minutes that have
+    /// not been flushed yet are not included.
+    pub async fn query(
+        &self,
+        operation: Option<&str>,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<MetricsPoint>> {
+        let mut points = Vec::new();
+        if from > to {
+            return Ok(points);
+        }
+        for day in from / SECS_PER_DAY..=to / SECS_PER_DAY {
+            let path = self.day_path(day);
+            let text = match tokio::fs::read_to_string(&path).await {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            };
+            for line in text.lines().filter(|line| !line.is_empty()) {
+                match serde_json::from_str::<MetricsPoint>(line) {
+                    Ok(point)
+                        if (from..=to).contains(&point.minute)
+                            && operation.map_or(true, |op| op == point.operation) =>
+                    {
+                        points.push(point)
+                    }
+                    Ok(_) => {}
+                    // A torn write from a crash only loses that line
+                    Err(e) => warn!("Skipping bad metrics line in {}: {}", path.display(), e),
+                }
+            }
+        }
+        points.sort_by(|a, b| (a.minute, &a.operation).cmp(&(b.minute, &b.operation)));
+        Ok(points)
+    }
+
+    /// Flush closed minutes every minute, snapshotting `cache` stats first
+    pub fn spawn_flusher(self: Arc<Self>, cache: Option<Arc<Cache>>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = unix_now();
+                if let Some(cache) = &cache {
+                    self.record_cache_stats(&cache.get_stats().await, now);
+                }
+                if let Err(e) = self.flush(now).await {
+                    warn!("Failed to persist metrics history: {}", e);
+                }
+            }
+        })
+    }
+
+    fn day_path(&self, day: u64) -> PathBuf {
+        let date = chrono::DateTime::from_timestamp((day * SECS_PER_DAY) as i64, 0)
+            .unwrap_or_default()
+            .format("%Y-%m-%d");
+        self.dir.join(format!("metrics-{}.jsonl", date))
+    }
+
+    /// Remove daily files that fell out of the retention window
+    async fn prune(&self, today: u64) {
+        let Some(oldest_kept) = today.checked_sub(self.retention_days - 1) else {
+            return;
+        };
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+        let cutoff = self.day_path(oldest_kept);
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let is_history = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("metrics-") && name.ends_with(".jsonl"));
+            // Dates are zero-padded, so file names sort chronologically
+            if is_history && path < cutoff {
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    warn!(
+                        "Failed to remove old metrics file {}: {}",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn minute_of(timestamp: u64) -> u64 {
+    timestamp - timestamp % 60
+}
+
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_stats(hits: u64, misses: u64) -> CacheStats {
+        CacheStats {
+            shard_hits: hits,
+            shard_misses: misses,
+            manifest_hits: 0,
+            manifest_misses: 0,
+            total_shards_cached: 0,
+            total_manifests_cached: 0,
+            cache_size_bytes: 0,
+            shards_quarantined: 0,
+            coalesced_fetches: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_query_minutes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = MetricsHistory::open(dir.path()).unwrap();
+        let start = 1_700_000_040; // Start of a minute
+
+        for i in 1..=100 {
+            history.record_latency("fetch", i as f64, start + 5);
+        }
+        history.record_bytes("fetch", 7_500_000, start + 30);
+        history.record_latency("fetch", 10.0, start + 65);
+
+        // Only the first minute has closed
+        assert_eq!(history.flush(start + 70).await.unwrap(), 1);
+        assert_eq!(history.flush(start + 130).await.unwrap(), 1);
+
+        let points = history
+            .query(Some("fetch"), start, start + 60)
+            .await
+            .unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].minute, start);
+        assert_eq!(points[0].samples, 100);
+        assert!((points[0].p50_latency_ms - 50.0).abs() < 1.0);
+        assert!((points[0].p99_latency_ms - 99.0).abs() < 1.0);
+        assert!((points[0].throughput_mbps - 1.0).abs() < 1e-9);
+        assert_eq!(points[1].samples, 1);
+
+        assert!(history
+            .query(Some("other"), start, start + 60)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            history
+                .query(None, start + 60, start + 60)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_rate_uses_deltas() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = MetricsHistory::open(dir.path()).unwrap();
+        let start = 1_700_000_040;
+
+        history.record_cache_stats(&cache_stats(10, 10), start);
+        history.flush(start + 60).await.unwrap();
+        history.record_cache_stats(&cache_stats(19, 11), start + 60);
+        history.flush(start + 120).await.unwrap();
+
+        let points = history
+            .query(Some(CACHE_OPERATION), start, start + 60)
+            .await
+            .unwrap();
+        assert_eq!(points[0].cache_hit_rate, Some(0.5));
+        assert_eq!(points[1].cache_hit_rate, Some(0.9));
+    }
+
+    #[tokio::test]
+    async fn test_old_days_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = MetricsHistory::open(dir.path())
+            .unwrap()
+            .with_retention_days(2);
+        let day0 = 1_699_920_000; // Midnight UTC
+
+        history.record_latency("fetch", 1.0, day0);
+        history.flush(day0 + 60).await.unwrap();
+        history.record_latency("fetch", 1.0, day0 + 2 * SECS_PER_DAY);
+        history.flush(day0 + 2 * SECS_PER_DAY + 60).await.unwrap();
+
+        let points = history
+            .query(None, day0, day0 + 3 * SECS_PER_DAY)
+            .await
+            .unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].minute, day0 + 2 * SECS_PER_DAY);
+    }
+}
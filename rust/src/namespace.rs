@@ -0,0 +1,289 @@
+/// Multi-tenant namespaces
+///
+/// Every manifest belongs to one namespace, and its shards belong to the
+/// namespace of the manifest that references them. Applications sharing a
+/// daemon each get their own namespace: listing, search and download only
+/// see files in the caller's namespace, quotas cap how much each one may
+/// store, and RPC tokens are scoped to the namespaces they may touch.
+///
+/// Quotas and tokens live in the `[namespaces]` section of the node config
+/// file:
+///
+/// ```toml
+/// [namespaces.quotas.photos]
+/// max_files = 1000
+/// max_bytes = 10737418240
+///
+/// [namespaces.tokens]
+/// "s3cr3t-photos" = ["photos"]
+/// "s3cr3t-admin" = ["*"]
+/// ```
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::error::NamespaceError;
+
+/// Namespace of files stored without one, and of manifests that predate namespaces
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Longest accepted namespace name
+pub const MAX_NAMESPACE_LEN: usize = 64;
+
+/// Token scope entry granting every namespace
+pub const ALL_NAMESPACES: &str = "*";
+
+pub(crate) fn default_namespace() -> String {
+    DEFAULT_NAMESPACE.to_string()
+}
+
+pub(crate) fn is_default_namespace(namespace: &str) -> bool {
+    namespace == DEFAULT_NAMESPACE
+}
+
+/// Check a namespace name: 1-64 ASCII letters, digits, `-`, `_` or `.`
+pub fn validate_namespace(namespace: &str) -> Result<(), NamespaceError> {
+    let valid = !namespace.is_empty()
+        && namespace.len() <= MAX_NAMESPACE_LEN
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(NamespaceError::Invalid(namespace.to_string()))
+    }
+}
+
+/// Storage limits for one namespace (0 = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceQuota {
+    /// Most manifests the namespace may hold
+    #[serde(default)]
+    pub max_files: usize,
+    /// Most original file bytes the namespace may hold
+    #[serde(default)]
+    pub max_bytes: u64,
+}
+
+impl NamespaceQuota {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_files == 0 && self.max_bytes == 0
+    }
+
+    /// Fail if one more file of `file_size` bytes would exceed the quota
+    pub fn check(
+        &self,
+        namespace: &str,
+        files: usize,
+        bytes: u64,
+        file_size: u64,
+    ) -> Result<(), NamespaceError> {
+        if self.max_files > 0 && files + 1 > self.max_files {
+            return Err(NamespaceError::QuotaExceeded {
+                namespace: namespace.to_string(),
+                reason: format!("file limit of {} reached", self.max_files),
+            });
+        }
+        if self.max_bytes > 0 && bytes.saturating_add(file_size) > self.max_bytes {
+            return Err(NamespaceError::QuotaExceeded {
+                namespace: namespace.to_string(),
+                reason: format!(
+                    "{} + {} bytes exceeds limit of {}",
+                    bytes, file_size, self.max_bytes
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Usage of one namespace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceStats {
+    pub namespace: String,
+    /// Manifests in the namespace
+    pub files: usize,
+    /// Original size of those files
+    pub bytes: u64,
+    /// Shards of those files held in the local cache
+    pub shards_cached: usize,
+    pub shard_bytes_cached: u64,
+    pub quota: NamespaceQuota,
+}
+
+/// Namespace configuration (`[namespaces]` table of the node config file)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamespaceConfig {
+    /// Quota per namespace; unlisted namespaces are unlimited
+    #[serde(default)]
+    pub quotas: HashMap<String, NamespaceQuota>,
+    /// Namespaces each RPC token may use (`"*"` for all)
+    #[serde(default)]
+    pub tokens: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    namespaces: NamespaceConfig,
+}
+
+impl NamespaceConfig {
+    /// Load the `[namespaces]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[namespaces]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.namespaces.validate()?;
+        Ok(file.namespaces)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for namespace in self.quotas.keys() {
+            validate_namespace(namespace)?;
+        }
+        for (token, namespaces) in &self.tokens {
+            if token.is_empty() {
+                anyhow::bail!("namespace tokens must not be empty");
+            }
+            for namespace in namespaces {
+                if namespace != ALL_NAMESPACES {
+                    validate_namespace(namespace)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Namespaces each RPC token may use
+///
+/// With no tokens configured the node is single-tenant and every request is
+/// allowed, as before namespaces existed.
+#[derive(Debug, Clone, Default)]
+pub struct TokenScopes {
+    scopes: HashMap<String, HashSet<String>>,
+}
+
+impl TokenScopes {
+    pub fn new(tokens: &HashMap<String, Vec<String>>) -> Self {
+        Self {
+            scopes: tokens
+                .iter()
+                .map(|(token, namespaces)| (token.clone(), namespaces.iter().cloned().collect()))
+                .collect(),
+        }
+    }
+
+    /// Whether tokens are enforced at all
+    pub fn is_enforced(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    /// Allow `token` to use `namespace`
+    pub fn grant(&mut self, token: impl Into<String>, namespace: impl Into<String>) {
+        self.scopes
+            .entry(token.into())
+            .or_default()
+            .insert(namespace.into());
+    }
+
+    /// Fail unless `token` may use `namespace`
+    pub fn authorize(&self, token: Option<&str>, namespace: &str) -> Result<(), NamespaceError> {
+        validate_namespace(namespace)?;
+        if !self.is_enforced() {
+            return Ok(());
+        }
+        let allowed = token
+            .and_then(|token| self.scopes.get(token))
+            .is_some_and(|scope| scope.contains(ALL_NAMESPACES) || scope.contains(namespace));
+        if allowed {
+            Ok(())
+        } else {
+            Err(NamespaceError::Forbidden(namespace.to_string()))
+        }
+    }
+
+    /// Namespaces `token` may use, `None` if it may use all of them
+    pub fn namespaces(&self, token: Option<&str>) -> Option<Vec<String>> {
+        if !self.is_enforced() {
+            return None;
+        }
+        let scope = token.and_then(|token| self.scopes.get(token));
+        match scope {
+            Some(scope) if scope.contains(ALL_NAMESPACES) => None,
+            Some(scope) => {
+                let mut namespaces: Vec<String> = scope.iter().cloned().collect();
+                namespaces.sort();
+                Some(namespaces)
+            }
+            None => Some(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_scopes_tokens_and_quotas() {
+        let config = NamespaceConfig::from_toml(
+            r#"
+            [namespaces.quotas.photos]
+            max_files = 2
+
+            [namespaces.tokens]
+            photos-token = ["photos"]
+            admin-token = ["*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.quotas["photos"].max_files, 2);
+
+        let scopes = TokenScopes::new(&config.tokens);
+        assert!(scopes.authorize(Some("photos-token"), "photos").is_ok());
+        assert!(matches!(
+            scopes.authorize(Some("photos-token"), "backups"),
+            Err(NamespaceError::Forbidden(_))
+        ));
+        assert!(scopes.authorize(None, "photos").is_err());
+        assert!(scopes.authorize(Some("admin-token"), "backups").is_ok());
+        assert_eq!(
+            scopes.namespaces(Some("photos-token")),
+            Some(vec!["photos".to_string()])
+        );
+        assert_eq!(scopes.namespaces(Some("admin-token")), None);
+
+        // Without tokens every caller may use any valid namespace
+        let open = TokenScopes::default();
+        assert!(open.authorize(None, "anything").is_ok());
+        assert!(matches!(
+            open.authorize(None, "../etc"),
+            Err(NamespaceError::Invalid(_))
+        ));
+
+        assert!(NamespaceConfig::from_toml("[namespaces.quotas.\"bad name\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_quota_check() {
+        let quota = NamespaceQuota {
+            max_files: 2,
+            max_bytes: 100,
+        };
+        assert!(quota.check("ns", 1, 50, 50).is_ok());
+        assert!(quota.check("ns", 2, 0, 1).is_err());
+        assert!(quota.check("ns", 0, 60, 41).is_err());
+        assert!(NamespaceQuota::default()
+            .check("ns", 1000, u64::MAX, 1)
+            .is_ok());
+    }
+}
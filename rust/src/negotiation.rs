@@ -0,0 +1,345 @@
+/// Wire-format version and capability negotiation between peers
+///
+/// The ALPN only says a connection speaks pangea; it does not say which
+/// formats the peer can read, so a format change would break mixed
+/// networks. When a node connects to a peer it sends its [`Capabilities`]
+/// in a `PVER` request: the wire versions it speaks, the manifest format
+/// versions it reads, and the compression algorithms and shard transports
+/// it supports, and the checksums it verifies on shard transfers. The peer answers with its own and both keep the
+/// intersection, [`Negotiated`], for the other (the answering side under
+/// the node ID the caller claims).
+///
+/// Code about to use a newer format checks the peer's `Negotiated` first
+/// and falls back when the peer lacks it. Peers that predate negotiation
+/// drop the request; they, like peers not negotiated with yet, get
+/// [`Negotiated::baseline`]. Peers with no wire version in common are
+/// disconnected.
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tracing::{debug, warn};
+
+use crate::network::{RequestHandler, RequestTag};
+use crate::transport::TransportKind;
+
+/// Leading bytes of a capability request
+pub const VERSION_TAG: RequestTag = *b"PVER";
+
+/// Largest capability request or response
+pub const MAX_VERSION_RESPONSE_BYTES: usize = 4096;
+
+/// Wire version this build speaks
+pub const WIRE_VERSION: u16 = 1;
+
+/// Oldest wire version this build still speaks
+pub const MIN_WIRE_VERSION: u16 = 1;
+
+/// Single-tier manifests
+pub const MANIFEST_V1: u16 = 1;
+
+/// Two-tier manifests, whose shards after the outer code are local parity
+pub const MANIFEST_V2: u16 = 2;
+
+/// Manifests with a key rotation, whose shards may be sealed under a
+/// rotated key
+pub const MANIFEST_V3: u16 = 3;
+
+/// Compression algorithm of compressed control streams
+pub const ZSTD: &str = "zstd";
+
+/// Checksum of checksummed shard frames
+pub const CRC32C: &str = "crc32c";
+
+/// What a node supports, as sent in a capability exchange
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Sender's node ID
+    pub node_id: u32,
+    /// Newest wire version spoken
+    pub wire_version: u16,
+    /// Oldest wire version spoken
+    pub min_wire_version: u16,
+    /// Manifest format versions read
+    pub manifest_versions: Vec<u16>,
+    /// Compression algorithms, most preferred first
+    #[serde(default)]
+    pub compression: Vec<String>,
+    /// Shard transports, most preferred first
+    #[serde(default)]
+    pub transports: Vec<TransportKind>,
+    /// Checksums verified on shard frames
+    #[serde(default)]
+    pub checksums: Vec<String>,
+}
+
+impl Capabilities {
+    /// What this build supports
+    pub fn local(node_id: u32) -> Self {
+        Self {
+            node_id,
+            wire_version: WIRE_VERSION,
+            min_wire_version: MIN_WIRE_VERSION,
+            manifest_versions: vec![MANIFEST_V1, MANIFEST_V2, MANIFEST_V3],
+            compression: vec![ZSTD.to_string()],
+            transports: vec![TransportKind::NativeQuic, TransportKind::Go],
+            checksums: vec![CRC32C.to_string()],
+        }
+    }
+
+    /// What both sides support, in our order of preference
+    ///
+    /// Fails when the two share no wire version.
+    pub fn negotiate(&self, peer: &Capabilities) -> Result<Negotiated> {
+        let wire_version = self.wire_version.min(peer.wire_version);
+        if wire_version < self.min_wire_version.max(peer.min_wire_version) {
+            bail!(
+                "No common wire version: we speak {}-{}, node {} speaks {}-{}",
+                self.min_wire_version,
+                self.wire_version,
+                peer.node_id,
+                peer.min_wire_version,
+                peer.wire_version
+            );
+        }
+        Ok(Negotiated {
+            wire_version,
+            manifest_versions: common(&self.manifest_versions, &peer.manifest_versions),
+            compression: common(&self.compression, &peer.compression),
+            transports: common(&self.transports, &peer.transports),
+            checksums: common(&self.checksums, &peer.checksums),
+        })
+    }
+}
+
+/// Items of `ours` that `theirs` also lists, in our order
+fn common<T: PartialEq + Clone>(ours: &[T], theirs: &[T]) -> Vec<T> {
+    ours.iter()
+        .filter(|item| theirs.contains(item))
+        .cloned()
+        .collect()
+}
+
+/// What this node and one peer both support
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Negotiated {
+    pub wire_version: u16,
+    pub manifest_versions: Vec<u16>,
+    pub compression: Vec<String>,
+    pub transports: Vec<TransportKind>,
+    #[serde(default)]
+    pub checksums: Vec<String>,
+}
+
+impl Negotiated {
+    /// What every peer is assumed to support: wire and manifest version 1,
+    /// no compression or checksums, native QUIC
+    pub fn baseline() -> Self {
+        Self {
+            wire_version: MIN_WIRE_VERSION,
+            manifest_versions: vec![MANIFEST_V1],
+            compression: Vec::new(),
+            transports: vec![TransportKind::NativeQuic],
+            checksums: Vec::new(),
+        }
+    }
+
+    /// Whether the peer reads manifests of format `version`
+    pub fn reads_manifest(&self, version: u16) -> bool {
+        self.manifest_versions.contains(&version)
+    }
+
+    /// Compression algorithm to use with the peer, if any
+    pub fn compression(&self) -> Option<&str> {
+        self.compression.first().map(String::as_str)
+    }
+
+    pub fn supports_transport(&self, transport: TransportKind) -> bool {
+        self.transports.contains(&transport)
+    }
+
+    /// Whether shards sent to the peer may be checksummed frames
+    pub fn verifies_checksums(&self) -> bool {
+        self.checksums.iter().any(|checksum| checksum == CRC32C)
+    }
+}
+
+/// This node's capabilities and those negotiated with each peer
+pub struct PeerCapabilities {
+    local: Capabilities,
+    peers: RwLock<HashMap<u32, Negotiated>>,
+}
+
+impl PeerCapabilities {
+    pub fn new(local: Capabilities) -> Self {
+        Self {
+            local,
+            peers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn local(&self) -> &Capabilities {
+        &self.local
+    }
+
+    /// What `peer_id` was negotiated to support, the baseline if never
+    pub fn get(&self, peer_id: u32) -> Negotiated {
+        self.peers
+            .read()
+            .get(&peer_id)
+            .cloned()
+            .unwrap_or_else(Negotiated::baseline)
+    }
+
+    pub fn is_negotiated(&self, peer_id: u32) -> bool {
+        self.peers.read().contains_key(&peer_id)
+    }
+
+    /// Every negotiated peer, in node ID order
+    pub fn peers(&self) -> Vec<(u32, Negotiated)> {
+        let mut peers: Vec<_> = self
+            .peers
+            .read()
+            .iter()
+            .map(|(peer_id, negotiated)| (*peer_id, negotiated.clone()))
+            .collect();
+        peers.sort_unstable_by_key(|(peer_id, _)| *peer_id);
+        peers
+    }
+
+    /// Drop what was negotiated with `peer_id`, so it gets the baseline
+    pub fn forget(&self, peer_id: u32) {
+        self.peers.write().remove(&peer_id);
+    }
+
+    /// Capability request carrying this node's capabilities
+    pub fn request(&self) -> Result<Vec<u8>> {
+        let mut request = VERSION_TAG.to_vec();
+        serde_json::to_writer(&mut request, &self.local)?;
+        Ok(request)
+    }
+
+    /// Negotiate with `peer_id` from its answer to our request
+    ///
+    /// A peer sharing no wire version with us is forgotten and the error
+    /// returned, so the caller can disconnect it.
+    pub fn record_response(&self, peer_id: u32, response: &[u8]) -> Result<Negotiated> {
+        let peer: Capabilities =
+            serde_json::from_slice(response).context("Invalid capability response")?;
+        if peer.node_id != peer_id {
+            bail!("Node {} answered as node {}", peer_id, peer.node_id);
+        }
+        self.record(&peer)
+    }
+
+    fn record(&self, peer: &Capabilities) -> Result<Negotiated> {
+        match self.local.negotiate(peer) {
+            Ok(negotiated) => {
+                debug!("Negotiated with node {}: {:?}", peer.node_id, negotiated);
+                self.peers.write().insert(peer.node_id, negotiated.clone());
+                Ok(negotiated)
+            }
+            Err(e) => {
+                self.forget(peer.node_id);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for PeerCapabilities {
+    /// Record the caller's capabilities and answer with ours; incompatible
+    /// callers get ours too, so they see why and disconnect
+    async fn handle(&self, remote: SocketAddr, request: Vec<u8>) -> Vec<u8> {
+        let peer = request
+            .strip_prefix(&VERSION_TAG)
+            .context("Not a capability request")
+            .and_then(|body| {
+                serde_json::from_slice::<Capabilities>(body).context("Invalid capabilities")
+            });
+        match peer {
+            Ok(peer) if peer.node_id != self.local.node_id => {
+                if let Err(e) = self.record(&peer) {
+                    warn!(
+                        "Node {} at {} is incompatible: {:#}",
+                        peer.node_id, remote, e
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Refused capability request from {}: {:#}", remote, e),
+        }
+        serde_json::to_vec(&self.local).unwrap_or_default()
+    }
+
+    fn is_replay_safe(&self, _request: &[u8]) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiation_keeps_what_both_support() {
+        let ours = Capabilities::local(1);
+        let theirs = Capabilities {
+            node_id: 2,
+            wire_version: 3,
+            min_wire_version: 1,
+            manifest_versions: vec![MANIFEST_V1],
+            compression: vec!["lz4".to_string(), ZSTD.to_string()],
+            transports: vec![TransportKind::NativeQuic],
+            checksums: Vec::new(),
+        };
+        let negotiated = ours.negotiate(&theirs).unwrap();
+        assert_eq!(negotiated.wire_version, WIRE_VERSION);
+        assert!(negotiated.reads_manifest(MANIFEST_V1));
+        assert!(!negotiated.reads_manifest(MANIFEST_V2));
+        assert_eq!(negotiated.compression(), Some(ZSTD));
+        assert!(!negotiated.supports_transport(TransportKind::Go));
+        assert!(!negotiated.verifies_checksums());
+
+        // Nothing in common below their oldest version
+        let newer = Capabilities {
+            min_wire_version: WIRE_VERSION + 1,
+            wire_version: WIRE_VERSION + 2,
+            ..theirs
+        };
+        assert!(ours.negotiate(&newer).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exchange_records_both_sides() {
+        let client = PeerCapabilities::new(Capabilities::local(1));
+        let server = PeerCapabilities::new(Capabilities::local(2));
+        assert_eq!(client.get(2), Negotiated::baseline());
+
+        let remote: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let response = server.handle(remote, client.request().unwrap()).await;
+        let negotiated = client.record_response(2, &response).unwrap();
+        assert!(negotiated.reads_manifest(MANIFEST_V2));
+        assert!(negotiated.verifies_checksums());
+        assert_eq!(client.get(2), negotiated);
+        assert_eq!(server.get(1), negotiated);
+        assert_eq!(client.peers().len(), 1);
+
+        // An answer from the wrong node is not recorded
+        assert!(client.record_response(3, &response).is_err());
+        assert!(!client.is_negotiated(3));
+
+        // Malformed requests are not recorded, and garbled answers leave
+        // the peer on the baseline
+        server.handle(remote, b"PVERnot json".to_vec()).await;
+        assert_eq!(server.peers().len(), 1);
+        assert!(client.record_response(4, b"").is_err());
+        assert_eq!(client.get(4), Negotiated::baseline());
+
+        client.forget(2);
+        assert_eq!(client.get(2), Negotiated::baseline());
+    }
+}
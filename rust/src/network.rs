@@ -1,30 +1,385 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use blake2::digest::{consts::U32, Mac};
+use blake2::Blake2bMac;
 use bytes::Bytes;
-use quinn::{ClientConfig, Connection, Endpoint, ServerConfig};
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{ClientConfig, Connection, Endpoint, ServerConfig, ZeroRttAccepted};
+use rustls::client::Resumption;
+use rustls::crypto::CryptoProvider;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Read;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
-use tracing::{debug, info, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, instrument, warn};
 
-use crate::types::{ConnectionQuality, PeerAddress};
+use crate::clock::{PeerClocks, CLOCK_TAG, MAX_CLOCK_RESPONSE_BYTES};
+use crate::firewall::{AcceptPath, Firewall};
+use crate::identity::{self, NodeIdentity, PeerDirectory};
+use crate::negotiation::{PeerCapabilities, MAX_VERSION_RESPONSE_BYTES, VERSION_TAG};
+use crate::probe::ProbeSample;
+use crate::quality::{PathSample, QualityEstimator};
+use crate::store::NodeStore;
+use crate::types::{ConnectionQuality, PeerAddress, ThroughputClass};
+
+/// Largest request accepted on an incoming bidirectional stream
+pub const MAX_REQUEST_BYTES: usize = 65 * 1024 * 1024;
+
+/// QUIC idle timeout; keep-alives must be sent more often than this
+const TRANSPORT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// TLS sessions remembered for 0-RTT resumption, on each side
+const SESSION_CACHE_SIZE: usize = 1024;
+
+/// Leading bytes of a request naming the handler that answers it
+pub type RequestTag = [u8; 4];
+
+/// Starts a zstd-compressed request or response on connections that
+/// negotiated compression; the frame holds the whole message, tag included
+pub const COMPRESSED_TAG: RequestTag = *b"PZST";
+
+/// ALPN of connections whose control streams may be compressed
+const ALPN_COMPRESSED: &[u8] = b"pangea/1+zstd";
+/// ALPN of connections that only carry plain streams
+const ALPN_PLAIN: &[u8] = b"pangea/1";
+
+/// Close code for connections that did not negotiate a pangea ALPN
+const ALPN_REFUSED: u32 = 0x50;
+/// Close code for peers whose identity conflicts with the one bound to their ID
+const IDENTITY_REFUSED: u32 = 0x51;
+/// Close code for peers sharing no wire version with this node
+const VERSION_REFUSED: u32 = 0x52;
+
+/// RFC 5705 exporter label of [`SessionMac`] keys
+const MAC_EXPORTER_LABEL: &[u8] = b"EXPORTER-pangea-session-mac";
+
+/// Messages shorter than this are not worth compressing
+const MIN_COMPRESS_BYTES: usize = 256;
+/// Larger messages carry shard data, which is compressed already
+const MAX_COMPRESS_BYTES: usize = 1024 * 1024;
+
+/// Answers requests peers send on bidirectional streams
+#[async_trait]
+pub trait RequestHandler: Send + Sync {
+    /// Build the response to one request from `remote` (tag included)
+    async fn handle(&self, remote: SocketAddr, request: Vec<u8>) -> Vec<u8>;
+
+    /// Whether answering `request` more than once changes nothing
+    ///
+    /// Requests sent as 0-RTT early data can be replayed by anyone who
+    /// captured them, so only these are answered before the handshake
+    /// completes; the rest wait for it.
+    fn is_replay_safe(&self, _request: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Whether a request may travel as 0-RTT early data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySafety {
+    /// Repeating the request changes nothing (reads, proofs)
+    Idempotent,
+    /// The request changes state; it is held until the handshake completes
+    Mutating,
+}
+
+/// Connection reuse limits (`[connections]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionConfig {
+    /// Streams open to one peer at a time; further requests wait for a slot
+    pub max_streams_per_peer: usize,
+    /// How often an open connection is pinged to keep it (and NAT bindings) alive
+    pub keep_alive_secs: u64,
+    /// Connections without an open stream for this long are closed
+    pub idle_timeout_secs: u64,
+    /// Offer zstd compression of request/response streams (manifest
+    /// exchange, storage control) to peers; used when both sides agree
+    pub compress_control: bool,
+    /// zstd level for compressed control streams
+    pub compression_level: i32,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            max_streams_per_peer: 64,
+            keep_alive_secs: 15,
+            idle_timeout_secs: 300,
+            compress_control: true,
+            compression_level: 3,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    connections: ConnectionConfig,
+}
+
+impl ConnectionConfig {
+    /// Load the `[connections]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[connections]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.connections.validate()?;
+        Ok(file.connections)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_streams_per_peer == 0 {
+            anyhow::bail!("connections: max_streams_per_peer must be at least 1");
+        }
+        if self.keep_alive_secs == 0 || self.keep_alive() >= TRANSPORT_IDLE_TIMEOUT {
+            anyhow::bail!(
+                "connections: keep_alive_secs must be between 1 and {}",
+                TRANSPORT_IDLE_TIMEOUT.as_secs() - 1
+            );
+        }
+        if self.idle_timeout_secs == 0 {
+            anyhow::bail!("connections: idle_timeout_secs must be at least 1");
+        }
+        if !(1..=22).contains(&self.compression_level) {
+            anyhow::bail!("connections: compression_level must be between 1 and 22");
+        }
+        Ok(())
+    }
+
+    fn keep_alive(&self) -> Duration {
+        Duration::from_secs(self.keep_alive_secs)
+    }
+
+    fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+}
+
+/// Connection reuse since start, and the connections open now
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    pub open_connections: usize,
+    /// Streams open right now across all connections
+    pub active_streams: usize,
+    pub connections_opened: u64,
+    /// `connect_to_peer` calls answered with an already open connection
+    pub connections_reused: u64,
+    pub idle_closed: u64,
+    pub streams_opened: u64,
+    /// Streams that had to wait for a peer's stream limit
+    pub stream_waits: u64,
+    /// Reconnects that resumed a TLS session and sent requests as 0-RTT
+    pub zero_rtt_attempted: u64,
+    /// Of those, the ones whose early data the peer accepted
+    pub zero_rtt_accepted: u64,
+    /// Requests whose request or response went compressed, and the bytes
+    /// that saved both ways
+    pub compressed_exchanges: u64,
+    pub compression_saved_bytes: u64,
+    pub peers: Vec<PeerConnectionStats>,
+}
+
+/// One open connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerConnectionStats {
+    pub peer_id: u32,
+    pub remote: SocketAddr,
+    pub active_streams: usize,
+    pub streams_opened: u64,
+    pub rtt_ms: u64,
+    /// Seconds since the last stream on the connection finished
+    pub idle_secs: u64,
+}
+
+#[derive(Default)]
+struct ConnectionCounters {
+    opened: AtomicU64,
+    reused: AtomicU64,
+    idle_closed: AtomicU64,
+    streams: AtomicU64,
+    stream_waits: AtomicU64,
+    zero_rtt_attempted: AtomicU64,
+    zero_rtt_accepted: AtomicU64,
+    compressed_exchanges: AtomicU64,
+    compression_saved: AtomicU64,
+}
+
+/// What became of a connection's 0-RTT early data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EarlyData {
+    NotSent,
+    Accepted,
+    Rejected,
+}
+
+/// Completion of a connection's TLS handshake
+///
+/// Resumed connections are usable before the handshake completes; requests
+/// that are not replay safe wait for it.
+#[derive(Clone)]
+struct Handshake(watch::Receiver<Option<EarlyData>>);
+
+impl Handshake {
+    /// A connection whose handshake already completed
+    fn complete() -> Self {
+        let (_, rx) = watch::channel(Some(EarlyData::NotSent));
+        Self(rx)
+    }
+
+    /// A 0-RTT connection, complete once `accepted` resolves
+    fn track(accepted: ZeroRttAccepted, counters: Option<Arc<ConnectionCounters>>) -> Self {
+        let (tx, rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let early = if accepted.await {
+                if let Some(counters) = counters {
+                    counters.zero_rtt_accepted.fetch_add(1, Ordering::Relaxed);
+                }
+                EarlyData::Accepted
+            } else {
+                EarlyData::Rejected
+            };
+            let _ = tx.send(Some(early));
+        });
+        Self(rx)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Wait for the handshake; a connection lost before it counts as rejected
+    async fn wait(&self) -> EarlyData {
+        let mut rx = self.0.clone();
+        match rx.wait_for(Option::is_some).await {
+            Ok(early) => early.unwrap_or(EarlyData::Rejected),
+            Err(_) => EarlyData::Rejected,
+        }
+    }
+}
+
+/// A connection shared by every transfer to one peer
+struct PeerConnection {
+    conn: Connection,
+    handshake: Handshake,
+    /// Identity the peer proved in its handshake, if it has one
+    identity: Option<PeerId>,
+    streams: Arc<Semaphore>,
+    max_streams: usize,
+    streams_opened: AtomicU64,
+    last_used: Mutex<Instant>,
+}
+
+impl PeerConnection {
+    fn new(
+        conn: Connection,
+        handshake: Handshake,
+        identity: Option<PeerId>,
+        max_streams: usize,
+    ) -> Self {
+        Self {
+            conn,
+            handshake,
+            identity,
+            streams: Arc::new(Semaphore::new(max_streams)),
+            max_streams,
+            streams_opened: AtomicU64::new(0),
+            last_used: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.conn.close_reason().is_none()
+    }
+
+    fn active_streams(&self) -> usize {
+        self.max_streams - self.streams.available_permits()
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().elapsed()
+    }
+}
+
+/// A stream slot on a peer's connection, released (and the connection
+/// marked as used) when dropped
+struct StreamSlot {
+    peer: Arc<PeerConnection>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl StreamSlot {
+    fn conn(&self) -> &Connection {
+        &self.peer.conn
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.peer.touch();
+    }
+}
 
 /// QUIC-based P2P network node
+///
+/// Keeps one connection per peer and multiplexes every transfer to it as
+/// streams, up to `max_streams_per_peer` at a time. Open connections are
+/// kept alive with pings and closed once idle (see
+/// [`QuicNode::spawn_connection_manager`]).
+///
+/// TLS sessions are remembered per peer, so reconnecting resumes them with
+/// 0-RTT: replay-safe requests go out with the first packet instead of
+/// after a full handshake.
+///
+/// The node presents a certificate for its [`NodeIdentity`] (an ephemeral
+/// one unless [`QuicNode::with_identity`] is given). Peers whose identity is
+/// known, from their address or the [`PeerDirectory`], must prove it in the
+/// handshake; see [`crate::identity`].
 pub struct QuicNode {
     _node_id: u32,
     endpoint: Endpoint,
-    connections: Arc<RwLock<HashMap<u32, Connection>>>,
+    connections: Arc<RwLock<HashMap<u32, Arc<PeerConnection>>>>,
     _message_tx: mpsc::UnboundedSender<(u32, Bytes)>,
     quality_metrics: Arc<RwLock<HashMap<u32, ConnectionQuality>>>,
+    firewall: Option<Arc<Firewall>>,
+    handlers: HashMap<RequestTag, Arc<dyn RequestHandler>>,
+    clocks: Option<Arc<PeerClocks>>,
+    capabilities: Option<Arc<PeerCapabilities>>,
+    config: ConnectionConfig,
+    counters: Arc<ConnectionCounters>,
+    resumption: Resumption,
+    certificate: (CertificateDer<'static>, PrivateKeyDer<'static>),
+    local_identity: PeerId,
+    directory: Option<Arc<PeerDirectory>>,
+    store: Option<Arc<NodeStore>>,
 }
 
 impl QuicNode {
     /// Create a new QUIC node
     pub async fn new(node_id: u32, bind_addr: SocketAddr) -> Result<Self> {
-        let (cert, key) = generate_self_signed_cert()?;
+        let identity = NodeIdentity::generate();
+        let (cert, key) = identity.certificate()?;
 
-        let server_config = configure_server(cert.clone(), key)?;
+        let server_config = configure_server(cert.clone(), key.clone_key(), true)?;
         let endpoint = Endpoint::server(server_config, bind_addr)?;
 
         info!("QUIC node {} listening on {}", node_id, bind_addr);
@@ -37,42 +392,236 @@ impl QuicNode {
             connections: Arc::new(RwLock::new(HashMap::new())),
             _message_tx: message_tx,
             quality_metrics: Arc::new(RwLock::new(HashMap::new())),
+            firewall: None,
+            handlers: HashMap::new(),
+            clocks: None,
+            capabilities: None,
+            config: ConnectionConfig::default(),
+            counters: Arc::new(ConnectionCounters::default()),
+            resumption: Resumption::in_memory_sessions(SESSION_CACHE_SIZE),
+            certificate: (cert, key),
+            local_identity: identity.peer_id(),
+            directory: None,
+            store: None,
         })
     }
 
-    /// Connect to a peer
+    /// Present `identity` to peers instead of an ephemeral one
+    pub fn with_identity(mut self, identity: &NodeIdentity) -> Result<Self> {
+        let (cert, key) = identity.certificate()?;
+        let server_config =
+            configure_server(cert.clone(), key.clone_key(), self.config.compress_control)?;
+        self.endpoint.set_server_config(Some(server_config));
+        self.certificate = (cert, key);
+        self.local_identity = identity.peer_id();
+        info!("QUIC node presenting identity {}", self.local_identity);
+        Ok(self)
+    }
+
+    /// Check peers against the identities bound to their IDs, and bind the
+    /// identity of peers connected to for the first time
+    pub fn with_peer_directory(mut self, directory: Arc<PeerDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Keep each connected peer's quality in `store`, and classify its
+    /// throughput from the transfer rates and probes recorded there
+    pub fn with_node_store(mut self, store: Arc<NodeStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Identity this node presents to peers
+    pub fn local_identity(&self) -> PeerId {
+        self.local_identity
+    }
+
+    /// Identity `peer_id` proved on its current connection
+    pub async fn peer_identity(&self, peer_id: u32) -> Option<PeerId> {
+        self.connections.read().await.get(&peer_id)?.identity
+    }
+
+    /// Override stream limits, keep-alive, idle timeout and compression of
+    /// peer connections
+    pub fn with_connection_config(mut self, config: ConnectionConfig) -> Self {
+        if config.compress_control != self.config.compress_control {
+            let (cert, key) = &self.certificate;
+            match configure_server(cert.clone(), key.clone_key(), config.compress_control) {
+                Ok(server_config) => self.endpoint.set_server_config(Some(server_config)),
+                Err(e) => warn!("Failed to reconfigure QUIC server: {}", e),
+            }
+        }
+        self.config = config;
+        self
+    }
+
+    /// Address the endpoint is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Refuse incoming connections the firewall rejects
+    pub fn with_firewall(mut self, firewall: Arc<Firewall>) -> Self {
+        self.firewall = Some(firewall);
+        self
+    }
+
+    /// Answer requests starting with `tag` on incoming connections with this handler
+    pub fn with_request_handler(
+        mut self,
+        tag: RequestTag,
+        handler: Arc<dyn RequestHandler>,
+    ) -> Self {
+        self.handlers.insert(tag, handler);
+        self
+    }
+
+    /// Answer peers' clock requests, and measure each peer's clock skew
+    /// into `clocks` when connecting to it
+    pub fn with_peer_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.handlers.insert(CLOCK_TAG, clocks.clone());
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Answer peers' capability requests, and negotiate capabilities into
+    /// `capabilities` when connecting to a peer
+    pub fn with_peer_capabilities(mut self, capabilities: Arc<PeerCapabilities>) -> Self {
+        self.handlers.insert(VERSION_TAG, capabilities.clone());
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// What was negotiated with peers, if this node negotiates
+    pub fn peer_capabilities(&self) -> Option<Arc<PeerCapabilities>> {
+        self.capabilities.clone()
+    }
+
+    /// Connect to a peer, reusing the open connection to that address
+    ///
+    /// A peer whose address names an identity, or whose ID is bound to one
+    /// in the peer directory, must prove that identity in the handshake.
+    #[instrument(skip(self), fields(peer_id = peer.peer_id))]
     pub async fn connect_to_peer(&self, peer: PeerAddress) -> Result<ConnectionQuality> {
         let addr: SocketAddr = format!("{}:{}", peer.host, peer.port)
             .parse()
             .context("Invalid peer address")?;
+        let expected = match &peer.identity {
+            Some(claimed) => Some(identity::parse_peer_id(claimed)?),
+            None => self
+                .directory
+                .as_ref()
+                .and_then(|directory| directory.identity_of(peer.peer_id)),
+        };
+
+        let existing = self.connections.read().await.get(&peer.peer_id).cloned();
+        if let Some(existing) = existing {
+            if existing.is_open()
+                && existing.conn.remote_address() == addr
+                && (expected.is_none() || existing.identity == expected)
+            {
+                self.counters.reused.fetch_add(1, Ordering::Relaxed);
+                existing.touch();
+                debug!("Reusing connection to peer {}", peer.peer_id);
+                return Ok(self
+                    .get_connection_quality(peer.peer_id)
+                    .await
+                    .unwrap_or_default());
+            }
+        }
 
         info!("Connecting to peer {} at {}", peer.peer_id, addr);
 
-        let client_config = configure_client()?;
-        let connecting = self
-            .endpoint
-            .connect_with(client_config, addr, "localhost")?;
+        let client_config = configure_client(&self.config, &self.resumption, expected)?;
+        let connecting = self.endpoint.connect_with(
+            client_config,
+            addr,
+            &session_name(peer.peer_id, expected.as_ref()),
+        )?;
 
+        // Resume a remembered session with 0-RTT, else do the full handshake
         let start = std::time::Instant::now();
-        let conn = connecting.await.context("Failed to connect to peer")?;
+        let (conn, handshake) = match connecting.into_0rtt() {
+            Ok((conn, accepted)) => {
+                self.counters
+                    .zero_rtt_attempted
+                    .fetch_add(1, Ordering::Relaxed);
+                debug!("Resuming session with peer {} (0-RTT)", peer.peer_id);
+                let handshake = Handshake::track(accepted, Some(self.counters.clone()));
+                (conn, handshake)
+            }
+            Err(connecting) => {
+                let conn = connecting.await.context("Failed to connect to peer")?;
+                // Resumed sessions were pinned when first established
+                if !negotiated_pangea(&conn) {
+                    conn.close(ALPN_REFUSED.into(), b"ALPN");
+                    anyhow::bail!("Peer {} did not negotiate a pangea protocol", peer.peer_id);
+                }
+                (conn, Handshake::complete())
+            }
+        };
         let latency = start.elapsed().as_millis() as f32;
 
-        // Store connection
-        self.connections
-            .write()
-            .await
-            .insert(peer.peer_id, conn.clone());
+        // Sessions resumed under an identity's name were verified against
+        // it when first established
+        let proven = expected.or_else(|| connection_identity(&conn));
+        if let (Some(directory), Some(proven)) = (&self.directory, proven) {
+            if let Err(e) = directory.bind(peer.peer_id, proven).await {
+                conn.close(IDENTITY_REFUSED.into(), b"Identity");
+                return Err(e);
+            }
+        }
+
+        if let Some(clocks) = &self.clocks {
+            tokio::spawn(measure_clock(
+                clocks.clone(),
+                peer.peer_id,
+                conn.clone(),
+                handshake.clone(),
+            ));
+        }
+        if let Some(capabilities) = &self.capabilities {
+            tokio::spawn(negotiate_capabilities(
+                capabilities.clone(),
+                peer.peer_id,
+                conn.clone(),
+                handshake.clone(),
+            ));
+        }
+
+        // Store connection, closing the one it replaces
+        let pooled = Arc::new(PeerConnection::new(
+            conn.clone(),
+            handshake,
+            proven,
+            self.config.max_streams_per_peer,
+        ));
+        if let Some(replaced) = self.connections.write().await.insert(peer.peer_id, pooled) {
+            replaced.conn.close(0u32.into(), b"Replaced");
+        }
+        self.counters.opened.fetch_add(1, Ordering::Relaxed);
 
+        let throughput = match &self.store {
+            Some(store) => store.peer_transfer_rate(peer.peer_id).await,
+            None => None,
+        };
         let quality = ConnectionQuality {
             latency_ms: latency,
-            jitter_ms: 0.0,
-            packet_loss: 0.0,
+            throughput_bytes_per_sec: throughput.unwrap_or_default(),
+            throughput_class: throughput.map_or(ThroughputClass::Unknown, ThroughputClass::of),
+            ..ConnectionQuality::default()
         };
 
         self.quality_metrics
             .write()
             .await
             .insert(peer.peer_id, quality.clone());
+        if let Some(store) = &self.store {
+            store
+                .record_connection_quality(peer.peer_id, &quality)
+                .await;
+        }
 
         // Start ping task for this connection
         self.start_ping_task(peer.peer_id, conn);
@@ -84,12 +633,64 @@ impl QuicNode {
         Ok(quality)
     }
 
+    /// Take a stream slot on the peer's connection, waiting if all are in use
+    ///
+    /// Requests that are not replay safe also wait for the handshake of a
+    /// resumed connection to complete.
+    async fn open_slot(&self, peer_id: u32, safety: ReplaySafety) -> Result<StreamSlot> {
+        let peer = self
+            .connections
+            .read()
+            .await
+            .get(&peer_id)
+            .cloned()
+            .context("Peer not connected")?;
+        if !peer.is_open() {
+            self.drop_connection(peer_id, &peer).await;
+            anyhow::bail!("Connection to peer {} was closed", peer_id);
+        }
+        if safety == ReplaySafety::Mutating && !peer.handshake.is_complete() {
+            debug!(
+                "Holding request to peer {} until the handshake completes",
+                peer_id
+            );
+            peer.handshake.wait().await;
+        }
+
+        let permit = match peer.streams.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.counters.stream_waits.fetch_add(1, Ordering::Relaxed);
+                debug!("Waiting for a free stream to peer {}", peer_id);
+                peer.streams.clone().acquire_owned().await?
+            }
+        };
+        peer.touch();
+        peer.streams_opened.fetch_add(1, Ordering::Relaxed);
+        self.counters.streams.fetch_add(1, Ordering::Relaxed);
+        Ok(StreamSlot {
+            peer,
+            _permit: permit,
+        })
+    }
+
+    /// Forget `peer`'s connection unless it has been replaced meanwhile
+    async fn drop_connection(&self, peer_id: u32, peer: &Arc<PeerConnection>) -> bool {
+        let mut connections = self.connections.write().await;
+        match connections.get(&peer_id) {
+            Some(current) if Arc::ptr_eq(current, peer) => {
+                connections.remove(&peer_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Send a message to a peer
     pub async fn send_message(&self, peer_id: u32, data: Bytes) -> Result<()> {
-        let connections = self.connections.read().await;
-        let conn = connections.get(&peer_id).context("Peer not connected")?;
+        let slot = self.open_slot(peer_id, ReplaySafety::Mutating).await?;
 
-        let mut send_stream = conn.open_uni().await?;
+        let mut send_stream = slot.conn().open_uni().await?;
         send_stream.write_all(&data).await?;
         send_stream.finish()?;
 
@@ -97,11 +698,131 @@ impl QuicNode {
         Ok(())
     }
 
+    /// Send a request on a bidirectional stream and read the full response
+    ///
+    /// The request is treated as [`ReplaySafety::Mutating`]; use
+    /// [`QuicNode::request_with`] to let reads go out as 0-RTT early data.
+    pub async fn request(&self, peer_id: u32, request: &[u8], max_len: usize) -> Result<Vec<u8>> {
+        self.request_with(peer_id, request, max_len, ReplaySafety::Mutating)
+            .await
+    }
+
+    /// Send a request, as early data on a resumed connection if `safety` allows
+    ///
+    /// An idempotent request whose early data the peer rejected is sent
+    /// again once the handshake completes.
+    pub async fn request_with(
+        &self,
+        peer_id: u32,
+        request: &[u8],
+        max_len: usize,
+        safety: ReplaySafety,
+    ) -> Result<Vec<u8>> {
+        let slot = self.open_slot(peer_id, safety).await?;
+
+        // Compress on connections that negotiated it (known once the
+        // handshake data is in, so early 0-RTT requests go plain)
+        let level = self.compression_level(slot.conn());
+        let framed = match level {
+            Some(level) => encode_frame(request, level)?,
+            None => Cow::Borrowed(request),
+        };
+
+        let response = match exchange(slot.conn(), &framed, max_len).await {
+            Ok(response) => response,
+            Err(e) => {
+                if safety == ReplaySafety::Mutating
+                    || slot.peer.handshake.wait().await != EarlyData::Rejected
+                {
+                    return Err(e);
+                }
+                debug!(
+                    "Peer {} rejected 0-RTT data, sending the request again",
+                    peer_id
+                );
+                exchange(slot.conn(), &framed, max_len).await?
+            }
+        };
+        debug!("Received {} bytes from peer {}", response.len(), peer_id);
+        if self.compression_level(slot.conn()).is_none() {
+            return Ok(response);
+        }
+
+        let wire_len = framed.len() + response.len();
+        let response = decode_frame(response, max_len)?;
+        let raw_len = request.len() + response.len();
+        if raw_len != wire_len {
+            self.counters
+                .compressed_exchanges
+                .fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .compression_saved
+                .fetch_add(raw_len.saturating_sub(wire_len) as u64, Ordering::Relaxed);
+        }
+        Ok(response)
+    }
+
+    /// MAC keyed from the TLS session with `peer_id`, for `context`
+    ///
+    /// Waits for the handshake of a resumed connection, as keys are only
+    /// exported once it completes.
+    pub async fn session_mac(&self, peer_id: u32, context: &[u8]) -> Result<SessionMac> {
+        let peer = self
+            .connections
+            .read()
+            .await
+            .get(&peer_id)
+            .cloned()
+            .context("Peer not connected")?;
+        peer.handshake.wait().await;
+        SessionMac::export(&peer.conn, context)
+    }
+
+    /// zstd level for control streams on `conn`, if both sides agreed to compress
+    fn compression_level(&self, conn: &Connection) -> Option<i32> {
+        (self.config.compress_control && negotiated_compression(conn))
+            .then_some(self.config.compression_level)
+    }
+
+    /// Send a `bytes`-long probe burst and time it until fully acknowledged
+    ///
+    /// Used by the bandwidth prober; the receiver discards the stream like
+    /// any other unsolicited message.
+    pub async fn probe_bandwidth(&self, peer_id: u32, bytes: usize) -> Result<ProbeSample> {
+        let slot = self.open_slot(peer_id, ReplaySafety::Mutating).await?;
+        let conn = slot.conn();
+
+        let burst = vec![0u8; bytes];
+        let start = std::time::Instant::now();
+        let mut send_stream = conn.open_uni().await?;
+        send_stream.write_all(&burst).await?;
+        send_stream.finish()?;
+        // Resolves once the peer has acknowledged every byte of the stream
+        if let Some(code) = send_stream.stopped().await? {
+            anyhow::bail!("Peer {} stopped the probe stream ({})", peer_id, code);
+        }
+
+        Ok(ProbeSample {
+            bytes,
+            elapsed: start.elapsed(),
+            rtt: conn.rtt(),
+        })
+    }
+
+    /// Whether a connection to the peer is open
+    pub async fn is_connected(&self, peer_id: u32) -> bool {
+        self.connections
+            .read()
+            .await
+            .get(&peer_id)
+            .is_some_and(|peer| peer.is_open())
+    }
+
     /// Disconnect from a peer
     pub async fn disconnect_peer(&self, peer_id: u32) -> Result<()> {
         let mut connections = self.connections.write().await;
-        if let Some(conn) = connections.remove(&peer_id) {
-            conn.close(0u32.into(), b"Disconnecting");
+        if let Some(peer) = connections.remove(&peer_id) {
+            peer.conn.close(0u32.into(), b"Disconnecting");
             info!("Disconnected from peer {}", peer_id);
         }
         Ok(())
@@ -117,39 +838,118 @@ impl QuicNode {
         self.connections.read().await.keys().copied().collect()
     }
 
+    /// Close connections idle for longer than the idle timeout
+    ///
+    /// Connections the peer already closed are dropped too. Returns how many
+    /// connections were removed.
+    pub async fn close_idle_connections(&self) -> usize {
+        let idle_timeout = self.config.idle_timeout();
+        let mut connections = self.connections.write().await;
+        let before = connections.len();
+        connections.retain(|peer_id, peer| {
+            if !peer.is_open() {
+                debug!("Dropping closed connection to peer {}", peer_id);
+                return false;
+            }
+            if peer.active_streams() == 0 && peer.idle_for() >= idle_timeout {
+                peer.conn.close(0u32.into(), b"Idle");
+                self.counters.idle_closed.fetch_add(1, Ordering::Relaxed);
+                info!("Closed idle connection to peer {}", peer_id);
+                return false;
+            }
+            true
+        });
+        before - connections.len()
+    }
+
+    /// Close idle connections in the background until the task is aborted
+    pub fn spawn_connection_manager(self: Arc<Self>) -> JoinHandle<()> {
+        let period = (self.config.idle_timeout() / 4).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                self.close_idle_connections().await;
+            }
+        })
+    }
+
+    /// Connection reuse counters and the connections open now
+    pub async fn connection_stats(&self) -> ConnectionStats {
+        let connections = self.connections.read().await;
+        let mut peers: Vec<_> = connections
+            .iter()
+            .filter(|(_, peer)| peer.is_open())
+            .map(|(&peer_id, peer)| PeerConnectionStats {
+                peer_id,
+                remote: peer.conn.remote_address(),
+                active_streams: peer.active_streams(),
+                streams_opened: peer.streams_opened.load(Ordering::Relaxed),
+                rtt_ms: peer.conn.rtt().as_millis() as u64,
+                idle_secs: peer.idle_for().as_secs(),
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.peer_id);
+
+        let c = &self.counters;
+        ConnectionStats {
+            open_connections: peers.len(),
+            active_streams: peers.iter().map(|peer| peer.active_streams).sum(),
+            connections_opened: c.opened.load(Ordering::Relaxed),
+            connections_reused: c.reused.load(Ordering::Relaxed),
+            idle_closed: c.idle_closed.load(Ordering::Relaxed),
+            streams_opened: c.streams.load(Ordering::Relaxed),
+            stream_waits: c.stream_waits.load(Ordering::Relaxed),
+            zero_rtt_attempted: c.zero_rtt_attempted.load(Ordering::Relaxed),
+            zero_rtt_accepted: c.zero_rtt_accepted.load(Ordering::Relaxed),
+            compressed_exchanges: c.compressed_exchanges.load(Ordering::Relaxed),
+            compression_saved_bytes: c.compression_saved.load(Ordering::Relaxed),
+            peers,
+        }
+    }
+
     /// Start background ping task for latency measurement
+    ///
+    /// Runs every keep-alive interval until the connection closes. Each
+    /// ping also samples the connection's path statistics into the
+    /// peer's quality estimate (see [`crate::quality`]).
     fn start_ping_task(&self, peer_id: u32, conn: Connection) {
         let quality_metrics = self.quality_metrics.clone();
+        let store = self.store.clone();
+        let period = self.config.keep_alive();
 
         tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
-            let mut last_latency = 0.0f32;
+            let mut interval = tokio::time::interval(period);
+            let mut estimator = QualityEstimator::default();
 
             loop {
                 interval.tick().await;
 
                 // Send ping
-                let start = std::time::Instant::now();
                 match conn.open_uni().await {
                     Ok(mut stream) => {
                         if stream.write_all(b"PING").await.is_ok() && stream.finish().is_ok() {
-                            let latency = start.elapsed().as_millis() as f32;
-
-                            // Calculate jitter
-                            let jitter = if last_latency > 0.0 {
-                                (latency - last_latency).abs()
-                            } else {
-                                0.0
+                            let throughput = match &store {
+                                Some(store) => store.peer_transfer_rate(peer_id).await,
+                                None => None,
                             };
+                            let quality = estimator.update(path_sample(&conn), throughput);
 
-                            let mut metrics = quality_metrics.write().await;
-                            if let Some(quality) = metrics.get_mut(&peer_id) {
-                                quality.latency_ms = latency;
-                                quality.jitter_ms = quality.jitter_ms * 0.8 + jitter * 0.2;
+                            if let Some(metrics) = quality_metrics.write().await.get_mut(&peer_id) {
+                                *metrics = quality.clone();
+                            }
+                            if let Some(store) = &store {
+                                store.record_connection_quality(peer_id, &quality).await;
                             }
 
-                            last_latency = latency;
-                            debug!("Ping to peer {}: {}ms", peer_id, latency);
+                            debug!(
+                                "Peer {}: {:.1}ms rtt, {:.1}ms jitter, {:.1}% loss",
+                                peer_id,
+                                quality.latency_ms,
+                                quality.jitter_ms,
+                                quality.packet_loss * 100.0
+                            );
                         }
                     }
                     Err(e) => {
@@ -163,9 +963,55 @@ impl QuicNode {
 
     /// Accept incoming connections (call this in a loop)
     pub async fn accept_connection(&self) -> Result<()> {
-        while let Some(conn) = self.endpoint.accept().await {
-            let connecting = conn.await?;
+        while let Some(incoming) = self.endpoint.accept().await {
+            if let Some(firewall) = &self.firewall {
+                let remote = incoming.remote_address();
+                if let Err(rejection) = firewall.admit(AcceptPath::Quic, remote.ip()) {
+                    debug!("Refused QUIC connection from {}: {}", remote, rejection);
+                    incoming.refuse();
+                    continue;
+                }
+            }
+            // The ClientHello is in, so the ALPN is known without a round trip
+            let remote = incoming.remote_address();
+            let mut connecting = incoming.accept()?;
+            let protocol = match connecting.handshake_data().await {
+                Ok(data) => alpn_of(data),
+                Err(e) => {
+                    debug!("Handshake with {} failed: {}", remote, e);
+                    continue;
+                }
+            };
+            // Accept 0.5-RTT so early data from resumed sessions is read at
+            // once; serve_requests holds back whatever is not replay safe
+            let (connecting, handshake) = match connecting.into_0rtt() {
+                Ok((conn, accepted)) => (conn, Handshake::track(accepted, None)),
+                Err(connecting) => match connecting.await {
+                    Ok(conn) => (conn, Handshake::complete()),
+                    Err(e) => {
+                        debug!("Handshake with {} failed: {}", remote, e);
+                        continue;
+                    }
+                },
+            };
+            if !is_pangea_protocol(protocol.as_deref()) {
+                debug!("Refused connection from {} without a pangea ALPN", remote);
+                connecting.close(ALPN_REFUSED.into(), b"ALPN");
+                continue;
+            }
             info!("Accepted connection from {:?}", connecting.remote_address());
+            if !self.handlers.is_empty() {
+                let compression = self
+                    .config
+                    .compress_control
+                    .then_some(self.config.compression_level);
+                tokio::spawn(serve_requests(
+                    connecting.clone(),
+                    handshake,
+                    compression,
+                    self.handlers.clone(),
+                ));
+            }
 
             // TODO: Implement peer ID exchange and register connection
             // For now, we just accept the connection
@@ -174,62 +1020,409 @@ impl QuicNode {
     }
 }
 
-/// Generate self-signed certificate for QUIC
-fn generate_self_signed_cert() -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
-    let key = PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
-    let cert_der = CertificateDer::from(cert.cert);
-    Ok((cert_der, key))
+/// Send `request` on a new bidirectional stream and read the full response
+async fn exchange(conn: &Connection, request: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    let (mut send_stream, mut recv_stream) = conn.open_bi().await?;
+    send_stream.write_all(request).await?;
+    send_stream.finish()?;
+
+    recv_stream
+        .read_to_end(max_len)
+        .await
+        .context("Failed to read response")
+}
+
+/// Measure a newly connected peer's clock skew once its handshake completes
+///
+/// Peers without a clock handler drop the request; their timestamps then
+/// get the tolerance only.
+async fn measure_clock(
+    clocks: Arc<PeerClocks>,
+    peer_id: u32,
+    conn: Connection,
+    handshake: Handshake,
+) {
+    if handshake.wait().await == EarlyData::Rejected && conn.close_reason().is_some() {
+        return;
+    }
+    let sent_at_ms = chrono::Utc::now().timestamp_millis();
+    let start = Instant::now();
+    let measured = exchange(&conn, &CLOCK_TAG, MAX_CLOCK_RESPONSE_BYTES)
+        .await
+        .and_then(|response| {
+            clocks.record_exchange(peer_id, sent_at_ms, start.elapsed(), &response)
+        });
+    if let Err(e) = measured {
+        debug!("Could not measure the clock of peer {}: {:#}", peer_id, e);
+    }
+}
+
+/// Negotiate capabilities with a newly connected peer once its handshake
+/// completes, closing the connection if the two share no wire version
+///
+/// Peers without a capability handler drop the request and keep the
+/// baseline.
+async fn negotiate_capabilities(
+    capabilities: Arc<PeerCapabilities>,
+    peer_id: u32,
+    conn: Connection,
+    handshake: Handshake,
+) {
+    if handshake.wait().await == EarlyData::Rejected && conn.close_reason().is_some() {
+        return;
+    }
+    let request = match capabilities.request() {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Could not encode capabilities: {:#}", e);
+            return;
+        }
+    };
+    let response = match exchange(&conn, &request, MAX_VERSION_RESPONSE_BYTES).await {
+        Ok(response) => response,
+        Err(e) => {
+            debug!("Peer {} did not negotiate capabilities: {:#}", peer_id, e);
+            return;
+        }
+    };
+    if let Err(e) = capabilities.record_response(peer_id, &response) {
+        warn!("Disconnecting incompatible peer {}: {:#}", peer_id, e);
+        conn.close(VERSION_REFUSED.into(), b"Version");
+    }
+}
+
+/// Server name a peer's TLS session is remembered under
+///
+/// Certificates are not checked against the name, so it only has to be
+/// distinct per peer for resumption to find the right session. Resumed
+/// sessions skip certificate verification, so the name also carries the
+/// identity the session was verified against, if any.
+fn session_name(peer_id: u32, identity: Option<&PeerId>) -> String {
+    match identity {
+        Some(identity) => format!(
+            "{}.peer-{}.pangea",
+            identity.to_base58().to_lowercase(),
+            peer_id
+        ),
+        None => format!("peer-{}.pangea", peer_id),
+    }
+}
+
+/// Current RTT and packet counters of `conn`'s path
+fn path_sample(conn: &Connection) -> PathSample {
+    let path = conn.stats().path;
+    PathSample {
+        rtt: path.rtt,
+        sent_packets: path.sent_packets,
+        lost_packets: path.lost_packets,
+    }
+}
+
+/// Identity proven by the certificate `conn`'s peer presented
+fn connection_identity(conn: &Connection) -> Option<PeerId> {
+    let certs = conn
+        .peer_identity()?
+        .downcast::<Vec<CertificateDer<'static>>>()
+        .ok()?;
+    identity::certificate_peer_id(certs.first()?)
+}
+
+/// ALPN protocol negotiated in a connection's handshake data
+fn alpn_of(data: Box<dyn std::any::Any>) -> Option<Vec<u8>> {
+    data.downcast::<quinn::crypto::rustls::HandshakeData>()
+        .ok()
+        .and_then(|data| data.protocol)
+}
+
+/// Whether `protocol` is one of ours
+fn is_pangea_protocol(protocol: Option<&[u8]>) -> bool {
+    protocol.is_some_and(|protocol| protocol == ALPN_COMPRESSED || protocol == ALPN_PLAIN)
+}
+
+/// Whether `conn` negotiated one of our ALPN protocols
+///
+/// rustls refuses peers offering only other protocols but lets through
+/// those offering none, so both ends check.
+fn negotiated_pangea(conn: &Connection) -> bool {
+    is_pangea_protocol(conn.handshake_data().and_then(alpn_of).as_deref())
+}
+
+/// Whether the peer agreed to compress control streams on `conn`
+///
+/// Unknown until the handshake data is in, so early 0-RTT requests go
+/// uncompressed.
+fn negotiated_compression(conn: &Connection) -> bool {
+    conn.handshake_data()
+        .and_then(alpn_of)
+        .is_some_and(|protocol| protocol == ALPN_COMPRESSED)
+}
+
+/// MAC keyed from a connection's TLS session
+///
+/// Both ends export the same key (RFC 5705) and no other connection can,
+/// so a tag binds data to the session it was sent in. Shard payloads of
+/// public content carry no content key; a tag lets an application check
+/// they were not spliced in from another connection.
+pub struct SessionMac {
+    key: [u8; 32],
+}
+
+impl SessionMac {
+    /// Export the key of `conn` for `context` (after its handshake)
+    pub fn export(conn: &Connection, context: &[u8]) -> Result<Self> {
+        let mut key = [0u8; 32];
+        conn.export_keying_material(&mut key, MAC_EXPORTER_LABEL, context)
+            .map_err(|_| anyhow::anyhow!("TLS session keys are not exportable yet"))?;
+        Ok(Self { key })
+    }
+
+    pub fn tag(&self, data: &[u8]) -> [u8; 32] {
+        self.mac(data).finalize().into_bytes().into()
+    }
+
+    /// Whether `tag` is this session's tag of `data` (constant time)
+    pub fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        self.mac(data).verify_slice(tag).is_ok()
+    }
+
+    fn mac(&self, data: &[u8]) -> Blake2bMac<U32> {
+        let mut mac = <Blake2bMac<U32> as Mac>::new_from_slice(&self.key)
+            .expect("32-byte keys are valid for BLAKE2b");
+        mac.update(data);
+        mac
+    }
+}
+
+/// ALPN protocols offered (client) or accepted (server), preferred first
+fn alpn_protocols(compress: bool) -> Vec<Vec<u8>> {
+    if compress {
+        vec![ALPN_COMPRESSED.to_vec(), ALPN_PLAIN.to_vec()]
+    } else {
+        vec![ALPN_PLAIN.to_vec()]
+    }
+}
+
+/// Frame `data` for a connection that negotiated compression
+///
+/// Data of a worthwhile size is compressed when that makes it smaller.
+/// Data that happens to start with [`COMPRESSED_TAG`] is always wrapped, so
+/// the peer never mistakes it for a compressed frame.
+fn encode_frame(data: &[u8], level: i32) -> Result<Cow<'_, [u8]>> {
+    let must_wrap = data.starts_with(&COMPRESSED_TAG);
+    if must_wrap || (MIN_COMPRESS_BYTES..=MAX_COMPRESS_BYTES).contains(&data.len()) {
+        let mut frame = COMPRESSED_TAG.to_vec();
+        zstd::stream::copy_encode(data, &mut frame, level).context("Failed to compress frame")?;
+        if must_wrap || frame.len() < data.len() {
+            return Ok(Cow::Owned(frame));
+        }
+    }
+    Ok(Cow::Borrowed(data))
+}
+
+/// Undo [`encode_frame`], refusing frames that expand beyond `max_len`
+fn decode_frame(frame: Vec<u8>, max_len: usize) -> Result<Vec<u8>> {
+    let Some(body) = frame.strip_prefix(&COMPRESSED_TAG) else {
+        return Ok(frame);
+    };
+    let mut plain = Vec::new();
+    zstd::stream::read::Decoder::new(body)?
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut plain)
+        .context("Failed to decompress frame")?;
+    if plain.len() > max_len {
+        anyhow::bail!("Compressed frame expands beyond {} bytes", max_len);
+    }
+    Ok(plain)
+}
+
+/// Answer each bidirectional stream a peer opens until it disconnects
+///
+/// With `compression` (the zstd level) set, streams from peers that
+/// negotiated it are decoded and answered with [`encode_frame`].
+async fn serve_requests(
+    conn: Connection,
+    handshake: Handshake,
+    compression: Option<i32>,
+    handlers: HashMap<RequestTag, Arc<dyn RequestHandler>>,
+) {
+    let remote = conn.remote_address();
+    while let Ok((mut send_stream, mut recv_stream)) = conn.accept_bi().await {
+        let handlers = handlers.clone();
+        let handshake = handshake.clone();
+        let conn = conn.clone();
+        tokio::spawn(async move {
+            let request = match recv_stream.read_to_end(MAX_REQUEST_BYTES).await {
+                Ok(request) => request,
+                Err(e) => {
+                    debug!("Dropped request from {}: {}", remote, e);
+                    return;
+                }
+            };
+            let level = compression.filter(|_| negotiated_compression(&conn));
+            let request = match level {
+                Some(_) => match decode_frame(request, MAX_REQUEST_BYTES) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        debug!("Dropped request from {}: {:#}", remote, e);
+                        return;
+                    }
+                },
+                None => request,
+            };
+            let handler = request.get(..4).and_then(|tag| handlers.get(tag));
+            let Some(handler) = handler else {
+                debug!("Dropped untagged request from {}", remote);
+                return;
+            };
+            if !handshake.is_complete() && !handler.is_replay_safe(&request) {
+                debug!(
+                    "Holding early request from {} until the handshake completes",
+                    remote
+                );
+                if handshake.wait().await == EarlyData::Rejected {
+                    return;
+                }
+            }
+            let response = handler.handle(remote, request).await;
+            let response = match level {
+                Some(level) => match encode_frame(&response, level) {
+                    Ok(frame) => frame.into_owned(),
+                    Err(e) => {
+                        debug!("Dropped response to {}: {:#}", remote, e);
+                        return;
+                    }
+                },
+                None => response,
+            };
+            if send_stream.write_all(&response).await.is_ok() {
+                let _ = send_stream.finish();
+            }
+        });
+    }
+    debug!("Stopped serving requests from {}", remote);
+}
+
+/// TLS primitives of every QUIC transport: TLS 1.3 AEAD suites only
+///
+/// QUIC negotiates TLS 1.3 anyway; dropping the TLS 1.2 suites keeps a
+/// misconfigured transport from ever offering them.
+pub(crate) fn crypto_provider() -> Arc<CryptoProvider> {
+    let mut provider = rustls::crypto::ring::default_provider();
+    provider
+        .cipher_suites
+        .retain(|suite| suite.tls13().is_some());
+    Arc::new(provider)
+}
+
+/// TLS server settings shared by QUIC transports, presenting `cert`
+pub(crate) fn server_crypto(
+    cert: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+) -> Result<rustls::ServerConfig> {
+    Ok(
+        rustls::ServerConfig::builder_with_provider(crypto_provider())
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)?,
+    )
 }
 
 /// Configure QUIC server
 fn configure_server(
     cert: CertificateDer<'static>,
     key: PrivateKeyDer<'static>,
+    compress: bool,
 ) -> Result<ServerConfig> {
-    let mut server_config = ServerConfig::with_single_cert(vec![cert], key)?;
+    let mut crypto = server_crypto(cert, key)?;
+    // Remember sessions and take 0-RTT data from clients resuming them
+    crypto.session_storage = rustls::server::ServerSessionMemoryCache::new(SESSION_CACHE_SIZE);
+    crypto.max_early_data_size = u32::MAX;
+    crypto.alpn_protocols = alpn_protocols(compress);
+    let mut server_config =
+        ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
 
     let transport_config = Arc::get_mut(&mut server_config.transport)
         .context("Failed to get mutable transport config")?;
 
     transport_config.max_concurrent_uni_streams(1000u32.into());
-    transport_config.max_idle_timeout(Some(std::time::Duration::from_secs(60).try_into()?));
+    transport_config.max_idle_timeout(Some(TRANSPORT_IDLE_TIMEOUT.try_into()?));
 
     Ok(server_config)
 }
 
-/// Configure QUIC client with insecure certificate validation (for testing)
-fn configure_client() -> Result<ClientConfig> {
-    let crypto = rustls::ClientConfig::builder()
-        .dangerous()
-        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
-        .with_no_client_auth();
-
-    let mut client_config = ClientConfig::new(Arc::new(
-        quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?,
-    ));
+/// Configure QUIC client
+///
+/// Node certificates are self-signed, so there is no chain to verify; a
+/// peer is only held to the `expected` identity, if any.
+fn configure_client(
+    config: &ConnectionConfig,
+    resumption: &Resumption,
+    expected: Option<PeerId>,
+) -> Result<ClientConfig> {
+    let mut crypto = client_crypto(resumption, expected)?;
+    crypto.alpn_protocols = alpn_protocols(config.compress_control);
+    let mut client_config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto)?));
 
     // Configure transport
     let mut transport = quinn::TransportConfig::default();
-    transport.max_idle_timeout(Some(std::time::Duration::from_secs(60).try_into()?));
+    transport.max_idle_timeout(Some(TRANSPORT_IDLE_TIMEOUT.try_into()?));
+    transport.keep_alive_interval(Some(config.keep_alive()));
     client_config.transport_config(Arc::new(transport));
 
     Ok(client_config)
 }
 
-/// Skip server certificate verification (for testing only!)
+/// TLS client settings shared by QUIC transports: sessions kept in
+/// `resumption` are resumed with 0-RTT early data, and servers must prove
+/// the `expected` identity
+pub(crate) fn client_crypto(
+    resumption: &Resumption,
+    expected: Option<PeerId>,
+) -> Result<rustls::ClientConfig> {
+    let provider = crypto_provider();
+    let verifier = IdentityVerifier {
+        expected,
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let mut crypto = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    crypto.resumption = resumption.clone();
+    crypto.enable_early_data = true;
+    Ok(crypto)
+}
+
+/// Holds servers to the identity they are expected to have
+///
+/// Certificates are self-signed, so there is no chain to check. With an
+/// expected identity the certificate must carry that identity's Ed25519
+/// key, and the handshake signature must verify under it. Without one any
+/// certificate is accepted, but the handshake is still checked against the
+/// certificate's key, so the identity a connection reports is proven.
 #[derive(Debug)]
-struct SkipServerVerification;
+struct IdentityVerifier {
+    expected: Option<PeerId>,
+    algorithms: rustls::crypto::WebPkiSupportedAlgorithms,
+}
 
-impl rustls::client::danger::ServerCertVerifier for SkipServerVerification {
+impl rustls::client::danger::ServerCertVerifier for IdentityVerifier {
     fn verify_server_cert(
         &self,
-        _end_entity: &CertificateDer<'_>,
+        end_entity: &CertificateDer<'_>,
         _intermediates: &[CertificateDer<'_>],
         _server_name: &rustls::pki_types::ServerName<'_>,
         _ocsp_response: &[u8],
         _now: rustls::pki_types::UnixTime,
     ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        if let Some(expected) = &self.expected {
+            if identity::certificate_peer_id(end_entity).as_ref() != Some(expected) {
+                return Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                ));
+            }
+        }
         Ok(rustls::client::danger::ServerCertVerified::assertion())
     }
 
@@ -239,23 +1432,483 @@ impl rustls::client::danger::ServerCertVerifier for SkipServerVerification {
         _cert: &CertificateDer<'_>,
         _dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        Err(rustls::Error::PeerIncompatible(
+            rustls::PeerIncompatible::Tls13RequiredForQuic,
+        ))
     }
 
     fn verify_tls13_signature(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
     ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        if identity::certificate_public_key(cert).is_none() {
+            // Legacy peers present RSA or ECDSA certificates
+            return rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms);
+        }
+        if dss.scheme != rustls::SignatureScheme::ED25519 {
+            return Err(rustls::Error::PeerMisbehaved(
+                rustls::PeerMisbehaved::SignedHandshakeWithUnadvertisedSigScheme,
+            ));
+        }
+        identity::verify_certificate_signature(cert, message, dss.signature()).map_err(|_| {
+            rustls::Error::InvalidCertificate(rustls::CertificateError::BadSignature)
+        })?;
         Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
     }
 
     fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ED25519,
-        ]
+        self.algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::{Capabilities, MANIFEST_V2};
+
+    struct Echo;
+
+    #[async_trait]
+    impl RequestHandler for Echo {
+        async fn handle(&self, _remote: SocketAddr, request: Vec<u8>) -> Vec<u8> {
+            request
+        }
+
+        fn is_replay_safe(&self, _request: &[u8]) -> bool {
+            true
+        }
+    }
+
+    /// A node answering `ECHO` requests, and the address to reach it as `peer_id`
+    async fn echo_server(peer_id: u32) -> (Arc<QuicNode>, PeerAddress) {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let server = Arc::new(
+            QuicNode::new(peer_id, "127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap()
+                .with_request_handler(*b"ECHO", Arc::new(Echo)),
+        );
+        let accept = server.clone();
+        tokio::spawn(async move { accept.accept_connection().await });
+        let address = PeerAddress {
+            peer_id,
+            host: "127.0.0.1".to_string(),
+            port: server.local_addr().unwrap().port(),
+            identity: None,
+        };
+        (server, address)
+    }
+
+    #[test]
+    fn test_connection_config_from_toml() {
+        let config = ConnectionConfig::from_toml(
+            "[connections]\nmax_streams_per_peer = 8\nidle_timeout_secs = 30\n",
+        )
+        .unwrap();
+        assert_eq!(config.max_streams_per_peer, 8);
+        assert_eq!(config.keep_alive_secs, 15);
+        assert_eq!(config.idle_timeout_secs, 30);
+        assert_eq!(
+            ConnectionConfig::from_toml("")
+                .unwrap()
+                .max_streams_per_peer,
+            64
+        );
+
+        assert!(ConnectionConfig::from_toml("[connections]\nmax_streams_per_peer = 0\n").is_err());
+        assert!(ConnectionConfig::from_toml("[connections]\nkeep_alive_secs = 60\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_reused_limited_and_closed_when_idle() {
+        let (_server, peer) = echo_server(2).await;
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_connection_config(ConnectionConfig {
+                max_streams_per_peer: 1,
+                keep_alive_secs: 1,
+                idle_timeout_secs: 1,
+                ..ConnectionConfig::default()
+            });
+        client.connect_to_peer(peer.clone()).await.unwrap();
+        client.connect_to_peer(peer).await.unwrap();
+
+        // Both requests share the connection; the second waits for the first
+        let (a, b) = tokio::join!(
+            client.request(2, b"ECHOa", 64),
+            client.request(2, b"ECHOb", 64)
+        );
+        assert_eq!(a.unwrap(), b"ECHOa");
+        assert_eq!(b.unwrap(), b"ECHOb");
+
+        let stats = client.connection_stats().await;
+        assert_eq!(stats.open_connections, 1);
+        assert_eq!(stats.connections_opened, 1);
+        assert_eq!(stats.connections_reused, 1);
+        assert_eq!(stats.streams_opened, 2);
+        assert_eq!(stats.stream_waits, 1);
+        assert_eq!(stats.active_streams, 0);
+        assert_eq!(stats.peers[0].streams_opened, 2);
+
+        assert_eq!(client.close_idle_connections().await, 0);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(client.close_idle_connections().await, 1);
+        assert!(!client.is_connected(2).await);
+        let stats = client.connection_stats().await;
+        assert_eq!(stats.idle_closed, 1);
+        assert!(stats.peers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resumes_session_with_0rtt() {
+        let (_server, peer) = echo_server(2).await;
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        client.connect_to_peer(peer.clone()).await.unwrap();
+        assert_eq!(
+            client.request(2, b"ECHOfirst", 64).await.unwrap(),
+            b"ECHOfirst"
+        );
+        assert_eq!(client.connection_stats().await.zero_rtt_attempted, 0);
+        client.disconnect_peer(2).await.unwrap();
+
+        // The remembered session lets a read go out before the handshake
+        client.connect_to_peer(peer).await.unwrap();
+        let read = client
+            .request_with(2, b"ECHOread", 64, ReplaySafety::Idempotent)
+            .await
+            .unwrap();
+        assert_eq!(read, b"ECHOread");
+        // Writes wait for the handshake, which settles the early data
+        assert_eq!(
+            client.request(2, b"ECHOwrite", 64).await.unwrap(),
+            b"ECHOwrite"
+        );
+
+        let stats = client.connection_stats().await;
+        assert_eq!(stats.connections_opened, 2);
+        assert_eq!(stats.zero_rtt_attempted, 1);
+        assert_eq!(stats.zero_rtt_accepted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_control_streams_compress_when_both_sides_agree() {
+        let (_server, peer) = echo_server(2).await;
+        let mut request = b"ECHO".to_vec();
+        request.extend(b"manifest ".repeat(1000));
+
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        client.connect_to_peer(peer.clone()).await.unwrap();
+        assert_eq!(client.request(2, &request, 1 << 20).await.unwrap(), request);
+        // Small requests are not worth it
+        assert_eq!(client.request(2, b"ECHOhi", 64).await.unwrap(), b"ECHOhi");
+        let stats = client.connection_stats().await;
+        assert_eq!(stats.compressed_exchanges, 1);
+        assert!(stats.compression_saved_bytes > 10_000);
+
+        let plain = QuicNode::new(3, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_connection_config(ConnectionConfig {
+                compress_control: false,
+                ..ConnectionConfig::default()
+            });
+        plain.connect_to_peer(peer).await.unwrap();
+        assert_eq!(plain.request(2, &request, 1 << 20).await.unwrap(), request);
+        assert_eq!(plain.connection_stats().await.compressed_exchanges, 0);
+    }
+
+    #[tokio::test]
+    async fn test_peer_clocks_are_measured_on_connect() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let server = Arc::new(
+            QuicNode::new(2, "127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap()
+                .with_peer_clocks(Arc::new(PeerClocks::default())),
+        );
+        let accept = server.clone();
+        tokio::spawn(async move { accept.accept_connection().await });
+        let clocks = Arc::new(PeerClocks::default());
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_peer_clocks(clocks.clone());
+        client
+            .connect_to_peer(PeerAddress {
+                peer_id: 2,
+                host: "127.0.0.1".to_string(),
+                port: server.local_addr().unwrap().port(),
+                identity: None,
+            })
+            .await
+            .unwrap();
+
+        for _ in 0..100 {
+            if let Some(offset) = clocks.offset_ms(2) {
+                // Same host, same clock
+                assert!(offset.abs() < 1000, "offset {} ms", offset);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("peer clock never measured");
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_are_negotiated_on_connect() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let served = Arc::new(PeerCapabilities::new(Capabilities::local(2)));
+        let server = Arc::new(
+            QuicNode::new(2, "127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap()
+                .with_peer_capabilities(served.clone()),
+        );
+        let accept = server.clone();
+        tokio::spawn(async move { accept.accept_connection().await });
+        let capabilities = Arc::new(PeerCapabilities::new(Capabilities::local(1)));
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_peer_capabilities(capabilities.clone());
+        client
+            .connect_to_peer(PeerAddress {
+                peer_id: 2,
+                host: "127.0.0.1".to_string(),
+                port: server.local_addr().unwrap().port(),
+                identity: None,
+            })
+            .await
+            .unwrap();
+
+        for _ in 0..100 {
+            if capabilities.is_negotiated(2) {
+                assert!(capabilities.get(2).reads_manifest(MANIFEST_V2));
+                assert!(served.is_negotiated(1));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("capabilities never negotiated");
+    }
+
+    /// A UDP relay to `server` keeping every datagram passing through it
+    async fn recording_relay(server: SocketAddr) -> (u16, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let socket = Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let upstream = Arc::new(tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        upstream.connect(server).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let client = Arc::new(Mutex::new(None));
+
+        let (downstream, to_server, seen, from) = (
+            socket.clone(),
+            upstream.clone(),
+            captured.clone(),
+            client.clone(),
+        );
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65536];
+            while let Ok((len, addr)) = downstream.recv_from(&mut buf).await {
+                *from.lock() = Some(addr);
+                seen.lock().push(buf[..len].to_vec());
+                let _ = to_server.send(&buf[..len]).await;
+            }
+        });
+        let seen = captured.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 65536];
+            while let Ok(len) = upstream.recv(&mut buf).await {
+                seen.lock().push(buf[..len].to_vec());
+                let addr = *client.lock();
+                if let Some(addr) = addr {
+                    let _ = socket.send_to(&buf[..len], addr).await;
+                }
+            }
+        });
+        (port, captured)
+    }
+
+    #[tokio::test]
+    async fn test_public_content_never_crosses_the_wire_in_plaintext() {
+        let (server, _) = echo_server(2).await;
+        let (port, captured) = recording_relay(server.local_addr().unwrap()).await;
+        // Uncompressed, so only the session can hide the payload
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_connection_config(ConnectionConfig {
+                compress_control: false,
+                ..ConnectionConfig::default()
+            });
+        client
+            .connect_to_peer(PeerAddress {
+                peer_id: 2,
+                host: "127.0.0.1".to_string(),
+                port,
+                identity: None,
+            })
+            .await
+            .unwrap();
+
+        // A public shard: no content key, so it is plaintext until it is sent
+        let marker = b"PUBLIC-SHARD-PLAINTEXT";
+        let shard = marker.repeat(2048);
+        let mut request = b"ECHO".to_vec();
+        request.extend(&shard);
+        assert_eq!(client.request(2, &request, 1 << 20).await.unwrap(), request);
+        client
+            .send_message(2, Bytes::from(shard.clone()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let captured = captured.lock();
+        let wire: usize = captured.iter().map(Vec::len).sum();
+        assert!(wire > 3 * shard.len(), "only {} bytes relayed", wire);
+        assert!(captured
+            .iter()
+            .all(|datagram| !datagram.windows(marker.len()).any(|w| w == marker)));
+    }
+
+    #[tokio::test]
+    async fn test_connections_without_pangea_alpn_are_refused() {
+        let (server, _) = echo_server(2).await;
+        let addr = server.local_addr().unwrap();
+        let endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        let resumption = Resumption::disabled();
+
+        // rustls refuses a client offering only other protocols
+        let mut crypto = client_crypto(&resumption, None).unwrap();
+        crypto.alpn_protocols = vec![b"h3".to_vec()];
+        let config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto).unwrap()));
+        let connecting = endpoint.connect_with(config, addr, "localhost").unwrap();
+        assert!(connecting.await.is_err());
+
+        // A client offering none gets through TLS, then is closed
+        let crypto = client_crypto(&resumption, None).unwrap();
+        let config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto).unwrap()));
+        let conn = endpoint
+            .connect_with(config, addr, "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        assert!(!negotiated_pangea(&conn));
+        assert!(exchange(&conn, b"ECHOhi", 64).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_mac_is_shared_by_both_ends_only() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let (cert, key) = NodeIdentity::generate().certificate().unwrap();
+        let server = Endpoint::server(
+            configure_server(cert, key, false).unwrap(),
+            "127.0.0.1:0".parse().unwrap(),
+        )
+        .unwrap();
+        let addr = server.local_addr().unwrap();
+        let client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        let config =
+            configure_client(&ConnectionConfig::default(), &Resumption::disabled(), None).unwrap();
+
+        let mut sessions = Vec::new();
+        for _ in 0..2 {
+            let connecting = client
+                .connect_with(config.clone(), addr, "localhost")
+                .unwrap();
+            let (ours, theirs) =
+                tokio::join!(connecting, async { server.accept().await.unwrap().await });
+            sessions.push((ours.unwrap(), theirs.unwrap()));
+        }
+
+        let (ours, theirs) = &sessions[0];
+        assert!(negotiated_pangea(ours) && negotiated_pangea(theirs));
+        let sender = SessionMac::export(ours, b"shard").unwrap();
+        let receiver = SessionMac::export(theirs, b"shard").unwrap();
+        let tag = sender.tag(b"public shard");
+        assert!(receiver.verify(b"public shard", &tag));
+        assert!(!receiver.verify(b"spliced shard", &tag));
+
+        // Another context or another connection has another key
+        assert!(!SessionMac::export(theirs, b"proof")
+            .unwrap()
+            .verify(b"public shard", &tag));
+        assert!(!SessionMac::export(&sessions[1].1, b"shard")
+            .unwrap()
+            .verify(b"public shard", &tag));
+    }
+
+    #[test]
+    fn test_tls_is_limited_to_tls13_aead_suites() {
+        let provider = crypto_provider();
+        assert!(!provider.cipher_suites.is_empty());
+        assert!(provider
+            .cipher_suites
+            .iter()
+            .all(|suite| suite.tls13().is_some()));
+    }
+
+    #[test]
+    fn test_frames_compress_only_when_worthwhile() {
+        let data = vec![0u8; 100_000];
+        let frame = encode_frame(&data, 3).unwrap();
+        assert!(frame.starts_with(&COMPRESSED_TAG) && frame.len() < 1000);
+        assert_eq!(decode_frame(frame.to_vec(), data.len()).unwrap(), data);
+        assert!(decode_frame(frame.to_vec(), data.len() - 1).is_err());
+
+        // Too small, and a plain message that merely looks like a frame
+        assert!(matches!(
+            encode_frame(b"ECHOhi", 3).unwrap(),
+            Cow::Borrowed(_)
+        ));
+        let lookalike = encode_frame(b"PZSTnot a frame", 3).unwrap();
+        assert_eq!(
+            decode_frame(lookalike.to_vec(), 64).unwrap(),
+            b"PZSTnot a frame"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_peers_must_prove_their_identity() {
+        let (server, mut peer) = echo_server(2).await;
+        let directory = Arc::new(PeerDirectory::new());
+        let client = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_peer_directory(directory.clone());
+
+        // A peer not holding the identity it is addressed by fails the handshake
+        peer.identity = Some(NodeIdentity::generate().peer_id().to_base58());
+        assert!(client.connect_to_peer(peer.clone()).await.is_err());
+        assert!(directory.is_empty());
+
+        // The first connection binds the ID to the identity proven
+        peer.identity = None;
+        client.connect_to_peer(peer).await.unwrap();
+        assert_eq!(directory.identity_of(2), Some(server.local_identity()));
+        assert_eq!(client.peer_identity(2).await, Some(server.local_identity()));
+        assert_eq!(client.request(2, b"ECHOid", 64).await.unwrap(), b"ECHOid");
+
+        // Another node answering as peer 2 is refused
+        let (_impostor, impostor_address) = echo_server(2).await;
+        assert!(client.connect_to_peer(impostor_address).await.is_err());
+        assert_eq!(directory.identity_of(2), Some(server.local_identity()));
+
+        // Nodes present the identity they are given
+        let identity = NodeIdentity::generate();
+        let node = QuicNode::new(3, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_identity(&identity)
+            .unwrap();
+        assert_eq!(node.local_identity(), identity.peer_id());
     }
 }
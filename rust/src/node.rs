@@ -0,0 +1,929 @@
+/// Embedded node for applications that link the crate as a library
+///
+/// `NodeBuilder` assembles the same subsystems the CLI sets up (cache, CES
+/// pipeline, node store, transfer scheduler) from programmatic config instead
+/// of command-line flags. The Go node, DHT, QUIC endpoint and compute engine
+/// are all optional and no ports are bound unless they are enabled.
+///
+/// `start()` must run inside a Tokio `LocalSet`: the Go client and DHT are
+/// not `Send`, so their background tasks are spawned locally.
+///
+/// `NodeBuilder::cluster` starts several independent nodes in one process,
+/// for dense edge deployments and single-process integration tests. Each
+/// gets its own ID, cache, keypair and ports; they share the runtime they
+/// are started on and, optionally, one cache memory budget.
+use anyhow::{Context, Result};
+use libp2p::Multiaddr;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use x25519_dalek::PublicKey;
+
+use crate::audit::{AuditConfig, AuditLog};
+use crate::automated::{
+    AutomatedDownloader, AutomatedUploader, DownloadResult, FileInfo, UploadResult,
+    DEFAULT_BATCH_CONCURRENCY,
+};
+use crate::cache::Cache;
+use crate::capabilities::HardwareCaps;
+use crate::ces::CesPipeline;
+use crate::compression::CompressionController;
+use crate::compute::{ComputeConfig, ComputeEngine};
+use crate::denylist::DenyList;
+use crate::dht::{self, DhtNode};
+use crate::disk_watchdog::{DiskWatchdog, DiskWatchdogConfig};
+use crate::envelope::NodeKeypair;
+use crate::error::{DownloadError, UploadError};
+use crate::geo::GeoDatabase;
+use crate::go_client::GoClient;
+use crate::metrics::MetricsTracker;
+use crate::metrics_history::{MetricsHistory, MetricsPoint};
+use crate::network::QuicNode;
+use crate::progress::{ProgressHub, TransferProgress};
+use crate::scheduler::{SchedulerConfig, TransferPriority, TransferScheduler};
+use crate::store::NodeStore;
+use crate::transport::ShardServer;
+use crate::types::{CesConfig, Node, PeerAddress};
+
+/// Default number of cached shards
+const DEFAULT_CACHE_MAX_ENTRIES: usize = 1000;
+/// Default cache size limit (100MB)
+const DEFAULT_CACHE_SIZE_BYTES: usize = 100 * 1024 * 1024;
+/// Raw samples kept by the tracker feeding the metrics history
+const METRICS_MAX_SAMPLES: usize = 1000;
+/// How long the DHT pump holds the swarm lock per poll
+const DHT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Builder for an in-process node
+#[derive(Debug, Clone)]
+pub struct NodeBuilder {
+    node_id: u32,
+    cache_dir: PathBuf,
+    cache_max_entries: usize,
+    cache_max_bytes: usize,
+    go_addr: Option<SocketAddr>,
+    dht_port: Option<u16>,
+    bootstrap: Vec<Multiaddr>,
+    quic_addr: Option<SocketAddr>,
+    compute: Option<ComputeConfig>,
+    bandwidth_limit: u64,
+    key_file: Option<PathBuf>,
+    recipients: Vec<PublicKey>,
+    metrics_history_dir: Option<PathBuf>,
+    geo_databases: Vec<PathBuf>,
+    deny_list: Option<PathBuf>,
+    deny_list_url: Option<(String, Duration)>,
+    disk_watchdog: Option<DiskWatchdogConfig>,
+    audit: Option<AuditConfig>,
+}
+
+impl NodeBuilder {
+    /// Cache-only node storing data under `cache_dir`
+    pub fn new(node_id: u32, cache_dir: impl AsRef<Path>) -> Self {
+        Self {
+            node_id,
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            cache_max_entries: DEFAULT_CACHE_MAX_ENTRIES,
+            cache_max_bytes: DEFAULT_CACHE_SIZE_BYTES,
+            go_addr: None,
+            dht_port: None,
+            bootstrap: Vec::new(),
+            quic_addr: None,
+            compute: None,
+            bandwidth_limit: 0,
+            key_file: None,
+            recipients: Vec::new(),
+            metrics_history_dir: None,
+            geo_databases: Vec::new(),
+            deny_list: None,
+            deny_list_url: None,
+            disk_watchdog: None,
+            audit: None,
+        }
+    }
+
+    /// Set shard cache limits
+    pub fn with_cache_limits(mut self, max_entries: usize, max_bytes: usize) -> Self {
+        self.cache_max_entries = max_entries;
+        self.cache_max_bytes = max_bytes;
+        self
+    }
+
+    /// Transfer shards through a Go node listening at `addr`
+    pub fn with_go_node(mut self, addr: SocketAddr) -> Self {
+        self.go_addr = Some(addr);
+        self
+    }
+
+    /// Run a DHT node on `port`, bootstrapping from the given peers
+    pub fn with_dht(mut self, port: u16, bootstrap: Vec<Multiaddr>) -> Self {
+        self.dht_port = Some(port);
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    /// Open a QUIC endpoint on `addr` for native shard transfers
+    pub fn with_quic(mut self, addr: SocketAddr) -> Self {
+        self.quic_addr = Some(addr);
+        self
+    }
+
+    /// Enable the compute engine
+    pub fn with_compute(mut self, config: ComputeConfig) -> Self {
+        self.compute = Some(config);
+        self
+    }
+
+    /// Limit transfer bandwidth in bytes/sec (0 = unlimited)
+    pub fn with_bandwidth_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth_limit = bytes_per_sec;
+        self
+    }
+
+    /// Persist the node's X25519 keypair at `path` (ephemeral otherwise)
+    pub fn with_key_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.key_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also wrap the file keys of uploads for these X25519 public keys, so
+    /// the nodes holding them can read the files
+    pub fn with_recipients(mut self, recipients: Vec<PublicKey>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    /// Record fetch latency, throughput and cache hit rate per minute under `dir`
+    pub fn with_metrics_history(mut self, dir: impl AsRef<Path>) -> Self {
+        self.metrics_history_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Tag peers with country/ASN from these ip2asn or MaxMind databases
+    pub fn with_geo_databases(mut self, paths: Vec<PathBuf>) -> Self {
+        self.geo_databases = paths;
+        self
+    }
+
+    /// Refuse to store hashes listed in the file at `path`
+    pub fn with_deny_list(mut self, path: impl AsRef<Path>) -> Self {
+        self.deny_list = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also deny hashes from the list at `url`, refetched every `interval`
+    pub fn with_deny_list_url(mut self, url: impl Into<String>, interval: Duration) -> Self {
+        self.deny_list_url = Some((url.into(), interval));
+        self
+    }
+
+    /// Watch free space on the cache volume and evict under pressure
+    pub fn with_disk_watchdog(mut self, config: DiskWatchdogConfig) -> Self {
+        self.disk_watchdog = Some(config);
+        self
+    }
+
+    /// Record `put` and `get` calls in an audit log (a disabled config is ignored)
+    pub fn with_audit(mut self, config: AuditConfig) -> Self {
+        self.audit = Some(config);
+        self
+    }
+
+    /// `count` nodes set up like this one, numbered from this node's ID
+    pub fn cluster(self, count: usize) -> ClusterBuilder {
+        ClusterBuilder {
+            template: self,
+            count,
+            memory_budget: None,
+            mesh: false,
+        }
+    }
+
+    /// Construct and start all enabled subsystems
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub async fn start(self) -> Result<NodeHandle> {
+        info!("🚀 Starting embedded node {}", self.node_id);
+
+        let mut store = NodeStore::new();
+        if !self.geo_databases.is_empty() {
+            store = store.with_geo(Arc::new(GeoDatabase::open(&self.geo_databases)?));
+        }
+        let store = Arc::new(store);
+        store.upsert_node(Node::new(self.node_id)).await;
+
+        let deny_list = match &self.deny_list {
+            Some(path) => Some(Arc::new(DenyList::open(path)?)),
+            None if self.deny_list_url.is_some() => Some(Arc::new(DenyList::new())),
+            None => None,
+        };
+        let mut cache = Cache::new(
+            &self.cache_dir,
+            self.cache_max_entries,
+            self.cache_max_bytes,
+        )?;
+        if let Some(deny_list) = &deny_list {
+            cache = cache.with_deny_list(deny_list.clone());
+        }
+        let cache = Arc::new(cache);
+        if let Err(e) = cache.load_persisted_manifests().await {
+            warn!("Failed to load persisted manifests: {}", e);
+        }
+
+        let caps = HardwareCaps::probe();
+        let ces = Arc::new(CesPipeline::new(CesConfig::adaptive(
+            &caps,
+            1024 * 1024,
+            1.0,
+        )));
+        let scheduler = Arc::new(TransferScheduler::new(SchedulerConfig::with_rate(
+            self.bandwidth_limit,
+        )));
+
+        // Without a Go node the client stays disconnected and every shard
+        // transfer goes through the native QUIC fallback.
+        let go_client = Arc::new(GoClient::new(
+            self.go_addr
+                .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 0))),
+        ));
+        let mut tasks = vec![scheduler.clone().spawn_peer_watch(store.clone())];
+        if let (Some(deny_list), Some((url, interval))) = (&deny_list, &self.deny_list_url) {
+            tasks.push(deny_list.clone().spawn_refresh(url.clone(), *interval));
+        }
+        let disk_watchdog = self
+            .disk_watchdog
+            .clone()
+            .filter(|c| c.enabled)
+            .map(|config| {
+                let watchdog = Arc::new(DiskWatchdog::new(cache.clone(), &self.cache_dir, config));
+                tasks.push(watchdog.clone().spawn());
+                watchdog
+            });
+        if let Some(addr) = self.go_addr {
+            go_client
+                .connect()
+                .await
+                .with_context(|| format!("Failed to connect to Go node at {}", addr))?;
+            tasks.push(go_client.spawn_health_checks());
+            info!("✓ Connected to Go node at {}", addr);
+        }
+
+        let network = match self.quic_addr {
+            Some(addr) => {
+                let network = Arc::new(
+                    Arc::new(ShardServer::new(cache.clone()))
+                        .register(QuicNode::new(self.node_id, addr).await?),
+                );
+                let accept = network.clone();
+                tasks.push(tokio::spawn(async move {
+                    if let Err(e) = accept.accept_connection().await {
+                        warn!("QUIC accept error: {}", e);
+                    }
+                }));
+                tasks.push(network.clone().spawn_connection_manager());
+                info!("✓ QUIC network initialized on {}", addr);
+                Some(network)
+            }
+            None => None,
+        };
+
+        let dht = match self.dht_port {
+            Some(port) => {
+                let has_bootstrap = !self.bootstrap.is_empty();
+                let mut node = DhtNode::new(port, self.bootstrap).await?;
+                node.listen_on(dht::local_multiaddr(port))?;
+                if has_bootstrap {
+                    node.bootstrap()?;
+                }
+                let dht = Arc::new(RwLock::new(node));
+                tasks.push(spawn_dht_pump(dht.clone()));
+                info!("✓ DHT node initialized on port {}", port);
+                Some(dht)
+            }
+            None => None,
+        };
+
+        let keypair = Arc::new(match &self.key_file {
+            Some(path) => NodeKeypair::load_or_generate(self.node_id, path)?,
+            None => NodeKeypair::generate(self.node_id),
+        });
+
+        let compute = match self.compute {
+            Some(config) => {
+                let engine = ComputeEngine::new(config)
+                    .map_err(|e| anyhow::anyhow!("Failed to start compute engine: {}", e))?
+                    .with_keypair(keypair.clone());
+                info!("✓ Compute engine initialized");
+                Some(Arc::new(engine))
+            }
+            None => None,
+        };
+
+        let mut recipients = vec![keypair.public_key()];
+        for key in &self.recipients {
+            if !recipients.contains(key) {
+                recipients.push(*key);
+            }
+        }
+
+        let progress = ProgressHub::default();
+        let mut uploader = AutomatedUploader::new(
+            ces.clone(),
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            dht.clone(),
+        )
+        .with_scheduler(scheduler.clone())
+        .with_recipients(recipients)
+        .with_compression_controller(Arc::new(CompressionController::default()))
+        .with_progress(progress.clone())
+        .with_local_peer(self.node_id);
+        let mut downloader = AutomatedDownloader::new(
+            ces,
+            go_client.clone(),
+            cache.clone(),
+            store.clone(),
+            dht.clone(),
+        )
+        .with_scheduler(scheduler.clone())
+        .with_keypair(keypair.clone())
+        .with_progress(progress.clone());
+        if let Some(network) = &network {
+            uploader = uploader.with_native_transport(network.clone());
+            downloader = downloader.with_native_transport(network.clone());
+        }
+        if let Some(config) = self.audit.clone().filter(|config| config.enabled) {
+            let path = config.path_or(&self.cache_dir);
+            let audit = Arc::new(AuditLog::open(&path, config)?);
+            uploader = uploader.with_audit_log(audit.clone(), "embedded");
+            downloader = downloader.with_audit_log(audit, "embedded");
+            info!("✓ Audit log at {}", path.display());
+        }
+
+        let history = match &self.metrics_history_dir {
+            Some(dir) => {
+                let history = Arc::new(MetricsHistory::open(dir)?);
+                let metrics =
+                    MetricsTracker::new(METRICS_MAX_SAMPLES).with_history(history.clone());
+                downloader = downloader.with_metrics(Arc::new(metrics));
+                tasks.push(history.clone().spawn_flusher(Some(cache.clone())));
+                info!("✓ Metrics history persisted to {}", dir.display());
+                Some(history)
+            }
+            None => None,
+        };
+
+        info!("🎯 Embedded node {} ready", self.node_id);
+
+        Ok(NodeHandle {
+            node_id: self.node_id,
+            cache,
+            store,
+            scheduler,
+            go_client,
+            network,
+            dht,
+            compute,
+            keypair,
+            progress,
+            uploader,
+            downloader,
+            history,
+            disk_watchdog,
+            tasks,
+        })
+    }
+}
+
+/// Builder for several nodes in one process, from a template `NodeBuilder`
+///
+/// Node `i` gets ID `node_id + i` and keeps its cache, key file, metrics
+/// history and audit log under `node-<id>` in the template's cache
+/// directory. Fixed QUIC and DHT ports are offset by `i`; port 0 leaves
+/// each node's port to the OS.
+#[derive(Debug, Clone)]
+pub struct ClusterBuilder {
+    template: NodeBuilder,
+    count: usize,
+    memory_budget: Option<usize>,
+    mesh: bool,
+}
+
+impl ClusterBuilder {
+    /// Split `bytes` of shard cache evenly across the nodes
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Connect every node to every other over QUIC and list them as peers
+    /// in each other's node store (needs `with_quic` on the template)
+    ///
+    /// Each node's file keys are wrapped for all of them, so any node can
+    /// read another's uploads; nodes without a key file get one in their
+    /// directory.
+    pub fn with_mesh(mut self) -> Self {
+        self.mesh = true;
+        self
+    }
+
+    /// Builder for the node at `index`
+    pub fn node(&self, index: usize) -> Result<NodeBuilder> {
+        let template = &self.template;
+        let node_id = u32::try_from(index)
+            .ok()
+            .and_then(|index| template.node_id.checked_add(index))
+            .context("Cluster node IDs overflow")?;
+        let offset = |port: u16| -> Result<u16> {
+            if port == 0 {
+                return Ok(0);
+            }
+            u16::try_from(index)
+                .ok()
+                .and_then(|index| port.checked_add(index))
+                .context("Cluster ports overflow")
+        };
+        let dir = template.cache_dir.join(format!("node-{}", node_id));
+
+        let mut builder = template.clone();
+        builder.node_id = node_id;
+        if let Some(budget) = self.memory_budget {
+            builder.cache_max_bytes = budget / self.count.max(1);
+        }
+        builder.quic_addr = match template.quic_addr {
+            Some(addr) => Some(SocketAddr::new(addr.ip(), offset(addr.port())?)),
+            None => None,
+        };
+        builder.dht_port = template.dht_port.map(offset).transpose()?;
+        builder.key_file = template.key_file.as_ref().map(|path| {
+            dir.join(
+                path.file_name()
+                    .unwrap_or_else(|| std::ffi::OsStr::new("node.key")),
+            )
+        });
+        builder.metrics_history_dir = template
+            .metrics_history_dir
+            .as_ref()
+            .map(|_| dir.join("metrics"));
+        if let Some(audit) = &mut builder.audit {
+            audit.path = None;
+        }
+        builder.cache_dir = dir;
+        Ok(builder)
+    }
+
+    /// Start every node, in ID order, on the current runtime
+    pub async fn start(self) -> Result<NodeCluster> {
+        if self.count == 0 {
+            anyhow::bail!("A cluster needs at least one node");
+        }
+        if self.mesh && self.template.quic_addr.is_none() {
+            anyhow::bail!("A meshed cluster needs QUIC enabled");
+        }
+        let mut builders = (0..self.count)
+            .map(|index| self.node(index))
+            .collect::<Result<Vec<_>>>()?;
+        if self.mesh {
+            let mut keys = Vec::with_capacity(builders.len());
+            for builder in &mut builders {
+                let path = builder
+                    .key_file
+                    .get_or_insert_with(|| builder.cache_dir.join("node.key"));
+                keys.push(NodeKeypair::load_or_generate(builder.node_id, path)?.public_key());
+            }
+            for builder in &mut builders {
+                builder.recipients.extend(keys.iter().copied());
+            }
+        }
+        let mut nodes = Vec::with_capacity(self.count);
+        for builder in builders {
+            nodes.push(builder.start().await?);
+        }
+        let cluster = NodeCluster { nodes };
+        if self.mesh {
+            cluster.connect_all().await?;
+        }
+        info!("🎯 Cluster of {} nodes ready", cluster.len());
+        Ok(cluster)
+    }
+}
+
+/// Nodes started together by a `ClusterBuilder`
+pub struct NodeCluster {
+    nodes: Vec<NodeHandle>,
+}
+
+impl NodeCluster {
+    /// Connect each node's QUIC endpoint to every other node's
+    async fn connect_all(&self) -> Result<()> {
+        let mut addresses = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let network = node.network().context("Cluster node without QUIC")?;
+            let mut addr = network.local_addr()?;
+            if addr.ip().is_unspecified() {
+                addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+            }
+            addresses.push((node.node_id(), addr));
+        }
+        for node in &self.nodes {
+            let Some(network) = node.network() else {
+                continue;
+            };
+            for &(peer_id, addr) in &addresses {
+                if peer_id == node.node_id() {
+                    continue;
+                }
+                network
+                    .connect_to_peer(PeerAddress {
+                        peer_id,
+                        host: addr.ip().to_string(),
+                        port: addr.port(),
+                        identity: None,
+                    })
+                    .await
+                    .with_context(|| {
+                        format!("Node {} failed to reach node {}", node.node_id(), peer_id)
+                    })?;
+                node.store().upsert_node(Node::new(peer_id)).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn nodes(&self) -> &[NodeHandle] {
+        &self.nodes
+    }
+
+    /// Node at `index`, in ID order
+    pub fn node(&self, index: usize) -> Option<&NodeHandle> {
+        self.nodes.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn into_nodes(self) -> Vec<NodeHandle> {
+        self.nodes
+    }
+
+    /// Stop every node's background tasks
+    pub fn shutdown(self) {
+        for node in self.nodes {
+            node.shutdown();
+        }
+    }
+}
+
+/// Drive the DHT swarm, releasing the lock between polls so lookups can run
+fn spawn_dht_pump(dht: Arc<RwLock<DhtNode>>) -> JoinHandle<()> {
+    tokio::task::spawn_local(async move {
+        loop {
+            {
+                let mut node = dht.write().await;
+                if let Ok(Some(event)) =
+                    tokio::time::timeout(DHT_POLL_INTERVAL, node.next_event()).await
+                {
+                    tracing::debug!("DHT event: {:?}", event);
+                }
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+}
+
+/// Running embedded node
+///
+/// Methods mirror the CLI commands (`put`, `get`, `list`, `search`, `info`).
+/// Background tasks are stopped by `shutdown()` or when the handle is dropped.
+pub struct NodeHandle {
+    node_id: u32,
+    cache: Arc<Cache>,
+    store: Arc<NodeStore>,
+    scheduler: Arc<TransferScheduler>,
+    go_client: Arc<GoClient>,
+    network: Option<Arc<QuicNode>>,
+    dht: Option<Arc<RwLock<DhtNode>>>,
+    compute: Option<Arc<ComputeEngine>>,
+    keypair: Arc<NodeKeypair>,
+    progress: ProgressHub,
+    uploader: AutomatedUploader,
+    downloader: AutomatedDownloader,
+    history: Option<Arc<MetricsHistory>>,
+    disk_watchdog: Option<Arc<DiskWatchdog>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl NodeHandle {
+    /// Upload a file, discovering peers automatically
+    pub async fn put(
+        &self,
+        file_path: impl AsRef<Path>,
+        priority: TransferPriority,
+    ) -> Result<UploadResult, UploadError> {
+        self.uploader
+            .upload_with_priority(file_path, priority)
+            .await
+    }
+
+    /// Download a file by hash
+    ///
+    /// Without an output path the file is written to the current directory
+    /// under its original name.
+    pub async fn get(
+        &self,
+        file_hash: &str,
+        output_path: Option<&Path>,
+        priority: TransferPriority,
+    ) -> Result<DownloadResult, DownloadError> {
+        let output_path = match output_path {
+            Some(path) => path.to_path_buf(),
+            None => match self.downloader.get_info(file_hash).await? {
+                Some(info) => PathBuf::from(info.file_name),
+                None => {
+                    let hash_short: String = file_hash.chars().take(8).collect();
+                    PathBuf::from(format!("{}.bin", hash_short))
+                }
+            },
+        };
+        self.downloader
+            .download_with_priority(file_hash, output_path, priority)
+            .await
+    }
+
+    /// Upload several files with shared peer discovery
+    pub async fn put_many(
+        &self,
+        file_paths: Vec<PathBuf>,
+        priority: TransferPriority,
+    ) -> Result<Vec<Result<UploadResult, UploadError>>, UploadError> {
+        self.uploader
+            .upload_many(file_paths, priority, DEFAULT_BATCH_CONCURRENCY)
+            .await
+    }
+
+    /// Download several files, each to its given output path
+    pub async fn get_many(
+        &self,
+        items: Vec<(String, PathBuf)>,
+        priority: TransferPriority,
+    ) -> Vec<Result<DownloadResult, DownloadError>> {
+        self.downloader
+            .download_many(items, priority, DEFAULT_BATCH_CONCURRENCY)
+            .await
+    }
+
+    /// List all known files
+    pub async fn list(&self) -> Result<Vec<FileInfo>, DownloadError> {
+        self.downloader.list_files().await
+    }
+
+    /// Search files by name pattern
+    pub async fn search(&self, pattern: &str) -> Result<Vec<FileInfo>, DownloadError> {
+        self.downloader.search(pattern).await
+    }
+
+    /// Get information about a file
+    pub async fn info(&self, file_hash: &str) -> Result<Option<FileInfo>, DownloadError> {
+        self.downloader.get_info(file_hash).await
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    pub fn cache(&self) -> &Arc<Cache> {
+        &self.cache
+    }
+
+    pub fn store(&self) -> &Arc<NodeStore> {
+        &self.store
+    }
+
+    pub fn scheduler(&self) -> &Arc<TransferScheduler> {
+        &self.scheduler
+    }
+
+    pub fn go_client(&self) -> &Arc<GoClient> {
+        &self.go_client
+    }
+
+    /// QUIC endpoint, if enabled
+    pub fn network(&self) -> Option<&Arc<QuicNode>> {
+        self.network.as_ref()
+    }
+
+    /// DHT node, if enabled
+    pub fn dht(&self) -> Option<&Arc<RwLock<DhtNode>>> {
+        self.dht.as_ref()
+    }
+
+    /// Compute engine, if enabled
+    pub fn compute(&self) -> Option<&Arc<ComputeEngine>> {
+        self.compute.as_ref()
+    }
+
+    /// X25519 keypair used to unwrap file keys
+    pub fn keypair(&self) -> &Arc<NodeKeypair> {
+        &self.keypair
+    }
+
+    /// Per-minute metrics for minutes starting between `from` and `to` (Unix seconds)
+    ///
+    /// Fails unless the node was built `with_metrics_history`.
+    pub async fn metrics_history(
+        &self,
+        operation: Option<&str>,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<MetricsPoint>> {
+        let history = self
+            .history
+            .as_ref()
+            .context("Metrics history is not enabled on this node")?;
+        history.query(operation, from, to).await
+    }
+
+    /// Cache volume watchdog, if built `with_disk_watchdog`
+    pub fn disk_watchdog(&self) -> Option<&Arc<DiskWatchdog>> {
+        self.disk_watchdog.as_ref()
+    }
+
+    /// Progress of `put` and `get` calls, per shard
+    pub fn subscribe_progress(&self) -> tokio::sync::broadcast::Receiver<TransferProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Rate, ETA and counts of every `put` and `get` still in flight
+    pub fn active_transfers(&self) -> Vec<TransferProgress> {
+        self.progress.active()
+    }
+
+    /// Stop background tasks
+    pub fn shutdown(mut self) {
+        self.stop_tasks();
+        info!("✓ Embedded node {} stopped", self.node_id);
+    }
+
+    fn stop_tasks(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for NodeHandle {
+    fn drop(&mut self) {
+        self.stop_tasks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::FileManifest;
+
+    #[tokio::test]
+    async fn test_cache_only_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let node = NodeBuilder::new(7, dir.path()).start().await.unwrap();
+                assert_eq!(node.node_id(), 7);
+                assert!(node.network().is_none());
+                assert!(node.dht().is_none());
+                assert!(node.compute().is_none());
+                assert!(!node.go_client().is_connected());
+
+                node.cache()
+                    .put_manifest(FileManifest {
+                        file_hash: "abc123".into(),
+                        file_name: "report.pdf".into(),
+                        file_size: 42,
+                        shard_count: 4,
+                        parity_count: 2,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+
+                assert_eq!(node.list().await.unwrap().len(), 1);
+                assert_eq!(node.search("report").await.unwrap().len(), 1);
+                let info = node.info("abc123").await.unwrap().unwrap();
+                assert_eq!(info.file_name, "report.pdf");
+                node.shutdown();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_put_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let node = NodeBuilder::new(1, dir.path())
+                    .with_compute(ComputeConfig::default())
+                    .start()
+                    .await
+                    .unwrap();
+                assert!(node.compute().is_some());
+
+                let result = node
+                    .put(
+                        dir.path().join("missing.bin"),
+                        TransferPriority::Interactive,
+                    )
+                    .await;
+                assert!(matches!(result, Err(UploadError::FileNotFound(_))));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_cluster_of_meshed_nodes() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = tempfile::tempdir().unwrap();
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let builder = NodeBuilder::new(10, dir.path())
+                    .with_quic("127.0.0.1:0".parse().unwrap())
+                    .with_key_file(dir.path().join("node.key"))
+                    .cluster(3)
+                    .with_memory_budget(30 * 1024 * 1024)
+                    .with_mesh();
+                let node = builder.node(2).unwrap();
+                assert_eq!(node.node_id, 12);
+                assert_eq!(node.cache_dir, dir.path().join("node-12"));
+                assert_eq!(node.key_file, Some(dir.path().join("node-12/node.key")));
+                assert_eq!(node.cache_max_bytes, 10 * 1024 * 1024);
+
+                let cluster = builder.start().await.unwrap();
+                assert_eq!(cluster.len(), 3);
+                let mut addrs = Vec::new();
+                for (index, node) in cluster.nodes().iter().enumerate() {
+                    assert_eq!(node.node_id(), 10 + index as u32);
+                    assert_eq!(node.store().get_all_nodes().await.len(), 3);
+                    addrs.push(node.network().unwrap().local_addr().unwrap());
+                }
+                addrs.sort_unstable();
+                addrs.dedup();
+                assert_eq!(addrs.len(), 3);
+                assert_ne!(
+                    cluster.node(0).unwrap().keypair().public_key_hex(),
+                    cluster.node(1).unwrap().keypair().public_key_hex()
+                );
+
+                // A file put on one node is fetched by another over QUIC
+                let (first, second) = (cluster.node(0).unwrap(), cluster.node(1).unwrap());
+                let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+                let input = dir.path().join("shared.bin");
+                std::fs::write(&input, &data).unwrap();
+                let uploaded = first
+                    .put(&input, TransferPriority::Interactive)
+                    .await
+                    .unwrap();
+                // Without a DHT the manifest is handed over directly
+                let manifest = first
+                    .cache()
+                    .get_manifest(&uploaded.file_hash)
+                    .await
+                    .unwrap();
+                second.cache().put_manifest(manifest).await.unwrap();
+                let output = dir.path().join("shared.out");
+                second
+                    .get(
+                        &uploaded.file_hash,
+                        Some(&output),
+                        TransferPriority::Interactive,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(std::fs::read(&output).unwrap(), data);
+                cluster.shutdown();
+
+                // Fixed ports are offset per node
+                let builder = NodeBuilder::new(1, dir.path())
+                    .with_quic("127.0.0.1:7000".parse().unwrap())
+                    .cluster(2);
+                assert_eq!(builder.node(1).unwrap().quic_addr.unwrap().port(), 7001);
+                assert!(NodeBuilder::new(1, dir.path())
+                    .cluster(2)
+                    .with_mesh()
+                    .start()
+                    .await
+                    .is_err());
+            })
+            .await;
+    }
+}
@@ -0,0 +1,469 @@
+/// Two-level erasure coding across failure domains
+///
+/// The CES pipeline's Reed-Solomon code (the outer code) lets any
+/// `shard_count - parity_count` shards rebuild a file, so it survives whole
+/// regions going dark, but repairing even one lost shard means pulling that
+/// many shards from wherever they live. With `[placement]` tiers enabled the
+/// outer shards are striped across the failure domains of the target peers,
+/// and every domain's share gets a few local parity shards of its own (the
+/// inner code), placed in the same domain. One lost shard is then rebuilt
+/// from its group's neighbours; the outer code is only needed when a group
+/// loses more shards than it has local parity.
+///
+/// Local parity shards are numbered after the outer shards, so they are
+/// placed, hashed, cached and healed like any other shard. The manifest's
+/// `local_parity` records which outer shards each group covers, so a
+/// downloader can decode from either tier.
+///
+/// Shards kept on the uploading node add no redundancy the network can
+/// count on: that node is also the one most likely to delete its copy. It
+/// is left out of uploads unless `allow_self` is set, and heal discounts
+/// its copies unless `count_self` is.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{debug, warn};
+
+use crate::cache::MAX_MANIFEST_SHARDS;
+use crate::ces::CesPipeline;
+
+/// How shards are grouped for local repair and whether the uploading node
+/// takes any (`[placement]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlacementPolicy {
+    /// Add local parity groups to uploads
+    pub tiers: bool,
+    /// Local parity shards per group
+    pub local_parity: usize,
+    /// Most outer shards in one group; a domain holding more is split
+    pub max_group_shards: usize,
+    /// Place shards on the uploading node as well as its peers
+    pub allow_self: bool,
+    /// With `allow_self`, the uploading node's share of shards relative to
+    /// one peer's, in (0, 1]
+    pub self_weight: f64,
+    /// Count copies on the uploading node toward a file's redundancy
+    pub count_self: bool,
+}
+
+impl Default for PlacementPolicy {
+    fn default() -> Self {
+        Self {
+            tiers: false,
+            local_parity: 1,
+            max_group_shards: 8,
+            allow_self: false,
+            self_weight: 1.0,
+            count_self: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    placement: PlacementPolicy,
+}
+
+impl PlacementPolicy {
+    /// Load the `[placement]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[placement]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.placement.validate()?;
+        Ok(file.placement)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.local_parity == 0 || self.max_group_shards == 0 {
+            anyhow::bail!("placement: local_parity and max_group_shards must be at least 1");
+        }
+        if !(self.self_weight > 0.0 && self.self_weight <= 1.0) {
+            anyhow::bail!("placement: self_weight must be in (0, 1]");
+        }
+        Ok(())
+    }
+
+    /// Peers to place shards on round-robin, given the candidate peers and
+    /// the uploading node
+    ///
+    /// The uploading node is dropped unless `allow_self` is set; then it
+    /// appears once every `1 / self_weight` rounds over the other peers.
+    pub fn targets(&self, peers: &[u32], local: Option<u32>) -> Vec<u32> {
+        let Some(local) = local.filter(|local| peers.contains(local)) else {
+            return peers.to_vec();
+        };
+        let remote: Vec<u32> = peers.iter().copied().filter(|&p| p != local).collect();
+        if !self.allow_self {
+            return remote;
+        }
+        let rounds = (1.0 / self.self_weight).round().max(1.0) as usize;
+        let mut targets = Vec::with_capacity(remote.len() * rounds + 1);
+        targets.push(local);
+        for _ in 0..rounds {
+            targets.extend(&remote);
+        }
+        targets
+    }
+
+    /// Local parity groups for `outer` shards placed on peers grouped by
+    /// failure domain, or `None` when tiers are off or would not fit
+    ///
+    /// Outer shard `i` goes to domain `i % domains`, so each domain holds an
+    /// even share; with no more shards per domain than `outer_parity`, the
+    /// outer code survives losing any one domain.
+    pub fn plan(
+        &self,
+        outer: usize,
+        outer_parity: usize,
+        domains: &[(String, Vec<u32>)],
+    ) -> Option<LocalParity> {
+        if !self.tiers || domains.is_empty() || outer == 0 {
+            return None;
+        }
+        let mut members = vec![Vec::new(); domains.len()];
+        for index in 0..outer {
+            members[index % domains.len()].push(index);
+        }
+
+        let mut groups = Vec::new();
+        let mut next = outer;
+        for ((domain, _), members) in domains.iter().zip(members) {
+            if members.len() > outer_parity {
+                warn!(
+                    "Domain {} holds {} shards, more than the {} outer parity; losing it loses the file",
+                    domain,
+                    members.len(),
+                    outer_parity
+                );
+            }
+            for chunk in members.chunks(self.max_group_shards) {
+                groups.push(ParityGroup {
+                    domain: domain.clone(),
+                    members: chunk.to_vec(),
+                    parity: (next..next + self.local_parity).collect(),
+                });
+                next += self.local_parity;
+            }
+        }
+        if next > MAX_MANIFEST_SHARDS {
+            warn!(
+                "Local parity would take the file to {} shards (limit {}); uploading without it",
+                next, MAX_MANIFEST_SHARDS
+            );
+            return None;
+        }
+        Some(LocalParity { groups })
+    }
+}
+
+/// Outer shards repaired together, and the local parity covering them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParityGroup {
+    /// Failure domain the group's shards were placed in
+    pub domain: String,
+    /// Indices of the outer shards in the group
+    pub members: Vec<usize>,
+    /// Indices of the group's local parity shards
+    pub parity: Vec<usize>,
+}
+
+impl ParityGroup {
+    /// The group's slots, members first
+    fn slots(&self, shards: &[Option<Vec<u8>>]) -> Vec<Option<Vec<u8>>> {
+        self.members
+            .iter()
+            .chain(&self.parity)
+            .map(|&index| shards.get(index).cloned().flatten())
+            .collect()
+    }
+}
+
+/// Inner code of a two-tier layout, as recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalParity {
+    pub groups: Vec<ParityGroup>,
+}
+
+impl LocalParity {
+    /// Local parity shards across all groups
+    pub fn shard_count(&self) -> usize {
+        self.groups.iter().map(|group| group.parity.len()).sum()
+    }
+
+    /// Check the groups against a manifest's shard counts
+    ///
+    /// Members must be distinct outer shards and the parity indices must
+    /// number `outer..total` in group order.
+    pub fn validate(&self, outer: usize, total: usize) -> Result<()> {
+        let mut seen = vec![false; outer];
+        let mut next = outer;
+        for group in &self.groups {
+            if group.members.is_empty() || group.parity.is_empty() {
+                anyhow::bail!("Local parity group in {} is empty", group.domain);
+            }
+            for &index in &group.members {
+                match seen.get_mut(index) {
+                    Some(seen) if !*seen => *seen = true,
+                    _ => anyhow::bail!("Local parity member {} invalid or repeated", index),
+                }
+            }
+            for &index in &group.parity {
+                if index != next {
+                    anyhow::bail!(
+                        "Local parity shard {} out of order (expected {})",
+                        index,
+                        next
+                    );
+                }
+                next += 1;
+            }
+        }
+        if next != total {
+            anyhow::bail!("Local parity ends at shard {} of {} shards", next, total);
+        }
+        Ok(())
+    }
+
+    /// Local parity shards for the outer shards, in index order
+    pub fn encode(&self, outer: &[Vec<u8>]) -> Result<Vec<Vec<u8>>> {
+        let mut parity = Vec::with_capacity(self.shard_count());
+        for group in &self.groups {
+            let members: Vec<Vec<u8>> = group
+                .members
+                .iter()
+                .map(|&index| outer.get(index).cloned().context("Missing outer shard"))
+                .collect::<Result<_>>()?;
+            parity.extend(CesPipeline::encode_parity(&members, group.parity.len())?);
+        }
+        Ok(parity)
+    }
+
+    /// Groups missing at least one outer shard
+    pub fn short_groups<'a>(
+        &'a self,
+        shards: &'a [Option<Vec<u8>>],
+    ) -> impl Iterator<Item = &'a ParityGroup> + 'a {
+        self.groups.iter().filter(|group| {
+            group
+                .members
+                .iter()
+                .any(|&index| shards.get(index).is_none_or(Option::is_none))
+        })
+    }
+
+    /// Fill in outer shards from local parity where a group has enough left
+    ///
+    /// Returns the number of outer shards rebuilt; groups that cannot be
+    /// repaired locally are left for the outer code.
+    pub fn repair_groups(&self, shards: &mut [Option<Vec<u8>>]) -> usize {
+        let mut rebuilt = 0;
+        for group in &self.groups {
+            let slots = group.slots(shards);
+            let missing = slots.iter().filter(|slot| slot.is_none()).count();
+            let members_missing = slots[..group.members.len()].iter().any(Option::is_none);
+            if !members_missing || missing > group.parity.len() {
+                continue;
+            }
+            match CesPipeline::repair_shards(group.members.len(), group.parity.len(), slots) {
+                Ok(repaired) => {
+                    for (&index, shard) in group.members.iter().zip(repaired) {
+                        if let Some(slot) = shards.get_mut(index) {
+                            if slot.is_none() {
+                                *slot = Some(shard);
+                                rebuilt += 1;
+                            }
+                        }
+                    }
+                }
+                Err(e) => debug!("Local repair in {} failed: {}", group.domain, e),
+            }
+        }
+        rebuilt
+    }
+
+    /// Rebuild every shard of a two-tier file from the ones at hand
+    ///
+    /// Groups are repaired locally first, the outer code covers what they
+    /// could not, and local parity lost along the way is recomputed.
+    /// `shards` holds one slot per shard, outer shards first.
+    pub fn repair(
+        &self,
+        data_shards: usize,
+        outer_parity: usize,
+        mut shards: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Vec<u8>>> {
+        self.repair_groups(&mut shards);
+        let outer = data_shards + outer_parity;
+        if outer > shards.len() {
+            anyhow::bail!("{} outer shards but {} slots", outer, shards.len());
+        }
+        let mut local = shards.split_off(outer);
+        let outer_shards = if shards.iter().any(Option::is_none) {
+            CesPipeline::repair_shards(data_shards, outer_parity, shards)?
+        } else {
+            shards.into_iter().flatten().collect()
+        };
+
+        let recomputed = self.encode(&outer_shards)?;
+        for (slot, shard) in local.iter_mut().zip(recomputed) {
+            if slot.is_none() {
+                *slot = Some(shard);
+            }
+        }
+        Ok(outer_shards
+            .into_iter()
+            .chain(local.into_iter().flatten())
+            .collect())
+    }
+
+    /// Peer for each shard: a group's shards go round-robin over the peers
+    /// of its domain, continuing where the domain's previous group left off
+    ///
+    /// Shards of groups whose domain has no peers are left out.
+    pub fn assign(&self, domains: &[(String, Vec<u32>)]) -> HashMap<usize, u32> {
+        let mut used: HashMap<&str, usize> = HashMap::new();
+        let mut assigned = HashMap::new();
+        for group in &self.groups {
+            let Some((_, peers)) = domains
+                .iter()
+                .find(|(domain, peers)| *domain == group.domain && !peers.is_empty())
+            else {
+                continue;
+            };
+            let used = used.entry(group.domain.as_str()).or_default();
+            for &index in group.members.iter().chain(&group.parity) {
+                assigned.insert(index, peers[*used % peers.len()]);
+                *used += 1;
+            }
+        }
+        assigned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domains() -> Vec<(String, Vec<u32>)> {
+        vec![
+            ("AS1".to_string(), vec![1, 2, 3]),
+            ("AS2".to_string(), vec![4, 5, 6]),
+        ]
+    }
+
+    #[test]
+    fn test_plan_stripes_shards_across_domains() {
+        let policy = PlacementPolicy {
+            tiers: true,
+            local_parity: 1,
+            max_group_shards: 2,
+            ..PlacementPolicy::default()
+        };
+        let local = policy.plan(6, 3, &domains()).unwrap();
+        let layout: Vec<_> = local
+            .groups
+            .iter()
+            .map(|group| {
+                (
+                    group.domain.as_str(),
+                    group.members.clone(),
+                    group.parity.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                ("AS1", vec![0, 2], vec![6]),
+                ("AS1", vec![4], vec![7]),
+                ("AS2", vec![1, 3], vec![8]),
+                ("AS2", vec![5], vec![9]),
+            ]
+        );
+        local.validate(6, 10).unwrap();
+        assert!(local.validate(6, 11).is_err());
+
+        let assigned = local.assign(&domains());
+        assert_eq!(assigned[&0], 1);
+        assert_eq!(assigned[&6], 3);
+        assert_eq!(assigned[&4], 1);
+        assert_eq!(assigned[&9], 5);
+
+        assert!(PlacementPolicy::default().plan(6, 3, &domains()).is_none());
+    }
+
+    #[test]
+    fn test_repair_prefers_local_parity_and_falls_back_to_outer() {
+        let policy = PlacementPolicy {
+            tiers: true,
+            ..PlacementPolicy::default()
+        };
+        // 4 data + 2 outer parity shards
+        let data: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 16]).collect();
+        let mut outer = data.clone();
+        outer.extend(CesPipeline::encode_parity(&data, 2).unwrap());
+        let local = policy.plan(6, 2, &domains()).unwrap();
+        let mut all = outer.clone();
+        all.extend(local.encode(&outer).unwrap());
+        assert_eq!(all.len(), 8);
+
+        // One loss per group: both groups repair locally
+        let mut slots: Vec<_> = all.iter().cloned().map(Some).collect();
+        slots[0] = None;
+        slots[3] = None;
+        assert_eq!(local.repair_groups(&mut slots), 2);
+        assert_eq!(slots[0].as_deref(), Some(&all[0][..]));
+        assert_eq!(slots[3].as_deref(), Some(&all[3][..]));
+
+        // A whole domain lost: the outer code takes over
+        let mut slots: Vec<_> = all.iter().cloned().map(Some).collect();
+        for index in [0, 2, 4, 6] {
+            slots[index] = None;
+        }
+        assert_eq!(local.short_groups(&slots).count(), 1);
+        assert!(local.repair(4, 2, slots.clone()).is_err());
+        slots[4] = Some(all[4].clone());
+        assert_eq!(local.repair(4, 2, slots).unwrap(), all);
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let policy = PlacementPolicy::from_toml("[placement]\ntiers = true\n").unwrap();
+        assert!(policy.tiers);
+        assert_eq!(policy.local_parity, 1);
+        assert!(PlacementPolicy::from_toml("[placement]\nlocal_parity = 0\n").is_err());
+        assert!(!policy.allow_self);
+        assert!(PlacementPolicy::from_toml("[placement]\nself_weight = 0.0\n").is_err());
+    }
+
+    #[test]
+    fn test_targets_exclude_self_unless_allowed() {
+        let peers = [1, 2, 3];
+        let policy = PlacementPolicy::default();
+        assert_eq!(policy.targets(&peers, Some(2)), vec![1, 3]);
+        assert_eq!(policy.targets(&peers, Some(9)), vec![1, 2, 3]);
+        assert_eq!(policy.targets(&peers, None), vec![1, 2, 3]);
+        assert!(policy.targets(&[2], Some(2)).is_empty());
+
+        let policy = PlacementPolicy {
+            allow_self: true,
+            ..PlacementPolicy::default()
+        };
+        assert_eq!(policy.targets(&peers, Some(2)), vec![2, 1, 3]);
+        let policy = PlacementPolicy {
+            allow_self: true,
+            self_weight: 0.5,
+            ..PlacementPolicy::default()
+        };
+        assert_eq!(policy.targets(&peers, Some(2)), vec![2, 1, 3, 1, 3]);
+    }
+}
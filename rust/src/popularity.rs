@@ -0,0 +1,364 @@
+/// Content popularity tracking
+///
+/// Every download of a file counts as one access. Counts decay
+/// exponentially (halving every `half_life_hours`), so a file's score
+/// reflects recent demand rather than its lifetime total. Scores classify
+/// files as hot, warm or cold, which drives which cached shards are evicted
+/// first, which files are prefetched, and the `top` command. The table is
+/// saved to `popularity.json` in the cache directory so the classification
+/// survives restarts.
+use anyhow::{bail, Context, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Popularity table, in the cache directory
+pub const POPULARITY_FILE: &str = "popularity.json";
+
+/// Entries decayed below this score are forgotten when the table is saved
+const FORGET_BELOW: f64 = 0.01;
+
+/// Popularity settings (`[popularity]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PopularityConfig {
+    /// Track accesses at all
+    pub enabled: bool,
+    /// Hours for an access to count half as much
+    pub half_life_hours: f64,
+    /// Files scoring at least this are hot
+    pub hot_score: f64,
+    /// Files scoring below this are cold
+    pub cold_score: f64,
+    /// Hottest files whose shards the daemon keeps cached (0 = no prefetch)
+    pub prefetch_files: usize,
+    pub prefetch_interval_secs: u64,
+    /// How often the daemon saves the table
+    pub save_interval_secs: u64,
+}
+
+impl Default for PopularityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            half_life_hours: 24.0,
+            hot_score: 5.0,
+            cold_score: 0.5,
+            prefetch_files: 8,
+            prefetch_interval_secs: 600,
+            save_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    popularity: PopularityConfig,
+}
+
+impl PopularityConfig {
+    /// Load the `[popularity]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[popularity]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.popularity.validate()?;
+        Ok(file.popularity)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.half_life_hours.is_finite() || self.half_life_hours <= 0.0 {
+            bail!("popularity: half_life_hours must be greater than 0");
+        }
+        if !self.hot_score.is_finite() || self.cold_score < 0.0 || self.cold_score > self.hot_score
+        {
+            bail!("popularity: cold_score must be between 0 and hot_score");
+        }
+        if self.prefetch_files > 0 && self.prefetch_interval_secs == 0 {
+            bail!("popularity: prefetch_interval_secs must be greater than 0");
+        }
+        if self.save_interval_secs == 0 {
+            bail!("popularity: save_interval_secs must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// How much demand a file currently sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Temperature {
+    Cold,
+    Warm,
+    Hot,
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Temperature::Cold => write!(f, "cold"),
+            Temperature::Warm => write!(f, "warm"),
+            Temperature::Hot => write!(f, "hot"),
+        }
+    }
+}
+
+/// Access statistics of one file, as stored
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct AccessStats {
+    /// Decayed access count as of `updated`
+    score: f64,
+    /// Unix seconds
+    updated: i64,
+    /// Accesses ever recorded
+    total: u64,
+    /// Unix seconds of the latest access
+    last_access: i64,
+}
+
+/// A file's popularity right now
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Popularity {
+    pub file_hash: String,
+    pub score: f64,
+    pub temperature: Temperature,
+    pub total: u64,
+    pub last_access: i64,
+}
+
+/// Per-file access counts with exponential decay
+pub struct PopularityTracker {
+    config: PopularityConfig,
+    path: Option<PathBuf>,
+    entries: RwLock<HashMap<String, AccessStats>>,
+    dirty: AtomicBool,
+}
+
+impl PopularityTracker {
+    /// In-memory tracker
+    pub fn new(config: PopularityConfig) -> Self {
+        Self {
+            config,
+            path: None,
+            entries: RwLock::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Tracker saved to `popularity.json` in `cache_dir`, loading what was saved there
+    pub fn open(cache_dir: impl AsRef<Path>, config: PopularityConfig) -> Result<Self> {
+        let path = cache_dir.as_ref().join(POPULARITY_FILE);
+        let entries = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .with_context(|| format!("Invalid popularity table {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        Ok(Self {
+            path: Some(path),
+            entries: RwLock::new(entries),
+            ..Self::new(config)
+        })
+    }
+
+    pub fn config(&self) -> &PopularityConfig {
+        &self.config
+    }
+
+    /// Count one access to a file
+    pub fn record(&self, file_hash: &str) {
+        self.record_at(file_hash, chrono::Utc::now().timestamp());
+    }
+
+    /// Count one access at Unix time `now`
+    pub fn record_at(&self, file_hash: &str, now: i64) {
+        let mut entries = self.entries.write();
+        let entry = entries.entry(file_hash.to_string()).or_insert(AccessStats {
+            score: 0.0,
+            updated: now,
+            total: 0,
+            last_access: now,
+        });
+        entry.score = self.decayed(entry, now) + 1.0;
+        entry.updated = now;
+        entry.total += 1;
+        entry.last_access = now;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// A file's decayed access count (0 for files never accessed)
+    pub fn score(&self, file_hash: &str) -> f64 {
+        self.score_at(file_hash, chrono::Utc::now().timestamp())
+    }
+
+    pub fn score_at(&self, file_hash: &str, now: i64) -> f64 {
+        self.entries
+            .read()
+            .get(file_hash)
+            .map_or(0.0, |entry| self.decayed(entry, now))
+    }
+
+    pub fn temperature(&self, file_hash: &str) -> Temperature {
+        self.classify(self.score(file_hash))
+    }
+
+    /// The `limit` most popular files, hottest first
+    pub fn top(&self, limit: usize) -> Vec<Popularity> {
+        self.top_at(limit, chrono::Utc::now().timestamp())
+    }
+
+    pub fn top_at(&self, limit: usize, now: i64) -> Vec<Popularity> {
+        let mut ranked: Vec<Popularity> = self
+            .entries
+            .read()
+            .iter()
+            .map(|(file_hash, entry)| {
+                let score = self.decayed(entry, now);
+                Popularity {
+                    file_hash: file_hash.clone(),
+                    score,
+                    temperature: self.classify(score),
+                    total: entry.total,
+                    last_access: entry.last_access,
+                }
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.file_hash.cmp(&b.file_hash))
+        });
+        ranked.truncate(limit);
+        ranked
+    }
+
+    /// Hot files worth keeping cached, hottest first, at most `limit`
+    pub fn prefetch_candidates(&self, limit: usize) -> Vec<String> {
+        self.top(limit)
+            .into_iter()
+            .filter(|entry| entry.temperature == Temperature::Hot)
+            .map(|entry| entry.file_hash)
+            .collect()
+    }
+
+    /// Write the table if it changed since the last save
+    ///
+    /// Files whose score decayed to almost nothing are dropped first.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().timestamp();
+        let json = {
+            let mut entries = self.entries.write();
+            entries.retain(|_, entry| self.decayed(entry, now) >= FORGET_BELOW);
+            serde_json::to_vec(&*entries)?
+        };
+        let tmp = path.with_extension("json.tmp");
+        let written = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, path));
+        if let Err(e) = written {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e).with_context(|| format!("Failed to save {:?}", path));
+        }
+        debug!("Saved popularity table to {:?}", path);
+        Ok(())
+    }
+
+    /// Save the table every `save_interval_secs` until aborted
+    pub fn spawn_saver(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let interval = Duration::from_secs(self.config.save_interval_secs);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.save() {
+                    warn!("{:#}", e);
+                }
+            }
+        })
+    }
+
+    fn classify(&self, score: f64) -> Temperature {
+        if score >= self.config.hot_score {
+            Temperature::Hot
+        } else if score < self.config.cold_score {
+            Temperature::Cold
+        } else {
+            Temperature::Warm
+        }
+    }
+
+    fn decayed(&self, entry: &AccessStats, now: i64) -> f64 {
+        let elapsed_hours = (now - entry.updated).max(0) as f64 / 3600.0;
+        entry.score * 0.5f64.powf(elapsed_hours / self.config.half_life_hours)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+
+    #[test]
+    fn test_scores_decay_and_classify() {
+        let tracker = PopularityTracker::new(PopularityConfig::default());
+        for _ in 0..8 {
+            tracker.record_at("busy", 0);
+        }
+        tracker.record_at("quiet", 0);
+        assert_eq!(tracker.score_at("busy", 0), 8.0);
+        assert!((tracker.score_at("busy", 24 * HOUR) - 4.0).abs() < 1e-9);
+        assert_eq!(tracker.score_at("never", 0), 0.0);
+
+        let top = tracker.top_at(10, 0);
+        assert_eq!(top[0].file_hash, "busy");
+        assert_eq!(top[0].temperature, Temperature::Hot);
+        assert_eq!(top[1].temperature, Temperature::Warm);
+        assert_eq!(top[1].total, 1);
+
+        // A day later the busy file cooled to warm, the quiet one to cold
+        let top = tracker.top_at(10, 48 * HOUR);
+        assert_eq!(top[0].temperature, Temperature::Warm);
+        assert_eq!(top[1].temperature, Temperature::Cold);
+        assert_eq!(tracker.top_at(1, 0).len(), 1);
+    }
+
+    #[test]
+    fn test_table_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracker = PopularityTracker::open(dir.path(), PopularityConfig::default()).unwrap();
+        for _ in 0..6 {
+            tracker.record("aaaa");
+        }
+        tracker.save().unwrap();
+
+        let reopened = PopularityTracker::open(dir.path(), PopularityConfig::default()).unwrap();
+        assert_eq!(reopened.temperature("aaaa"), Temperature::Hot);
+        assert_eq!(reopened.prefetch_candidates(5), vec!["aaaa"]);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = PopularityConfig::from_toml("[popularity]\nhot_score = 20.0\n").unwrap();
+        assert_eq!(config.hot_score, 20.0);
+        assert!(PopularityConfig::from_toml("[popularity]\nhalf_life_hours = 0.0\n").is_err());
+        assert!(PopularityConfig::from_toml("[popularity]\ncold_score = 9.0\n").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+/// Active bandwidth probing of QUIC peers
+///
+/// Transfer throughput is only known after shards have moved, but peer
+/// selection needs an estimate before the first transfer. A probe sends a
+/// short burst on a fresh QUIC stream and times it until the peer has
+/// acknowledged every byte: the first acknowledgement takes about one
+/// round trip, and the remaining time is the burst draining through the
+/// bottleneck link. Estimates go to the `NodeStore` (which ranks shard
+/// sources by them) and, when attached, to the DCDN `P2PEngine`.
+///
+/// Peers are probed when they connect and again every `interval`. Probes
+/// run one at a time so they do not compete for the same uplink.
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::dcdn::{P2PEngine, PeerId};
+use crate::network::QuicNode;
+use crate::store::NodeStore;
+
+/// Shortest drain time a burst is credited with
+///
+/// Bursts that fit in one congestion window are acknowledged within about
+/// one round trip; clamping keeps their estimate finite.
+const MIN_DRAIN_TIME: Duration = Duration::from_millis(1);
+
+/// Probe settings
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// Bytes sent per probe; must exceed the initial congestion window to
+    /// measure the bottleneck rather than the handshake
+    pub burst_bytes: usize,
+    /// Time between rounds of probes (zero disables periodic probing)
+    pub interval: Duration,
+    /// Give up on a peer's probe after this long
+    pub timeout: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            burst_bytes: 256 * 1024,
+            interval: Duration::from_secs(300),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Timing of one probe burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeSample {
+    pub bytes: usize,
+    /// From the first byte written until the last was acknowledged
+    pub elapsed: Duration,
+    /// Smoothed round-trip time of the connection
+    pub rtt: Duration,
+}
+
+impl ProbeSample {
+    /// Estimated available bandwidth in bytes/second
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        if self.bytes == 0 || self.elapsed.is_zero() {
+            return None;
+        }
+        let drain = self.elapsed.saturating_sub(self.rtt).max(MIN_DRAIN_TIME);
+        Some(self.bytes as f64 / drain.as_secs_f64())
+    }
+}
+
+/// Probes connected peers and records their bandwidth
+pub struct BandwidthProber {
+    network: Arc<QuicNode>,
+    store: Arc<NodeStore>,
+    config: ProbeConfig,
+    p2p: Option<Arc<P2PEngine>>,
+}
+
+impl BandwidthProber {
+    pub fn new(network: Arc<QuicNode>, store: Arc<NodeStore>, config: ProbeConfig) -> Self {
+        Self {
+            network,
+            store,
+            config,
+            p2p: None,
+        }
+    }
+
+    /// Also cap this engine's per-peer allocations at the probed bandwidth
+    pub fn with_p2p_engine(mut self, engine: Arc<P2PEngine>) -> Self {
+        self.p2p = Some(engine);
+        self
+    }
+
+    /// Probe one peer and record the estimate (bytes/second)
+    pub async fn probe_peer(&self, peer_id: u32) -> Result<f64> {
+        let sample = tokio::time::timeout(
+            self.config.timeout,
+            self.network
+                .probe_bandwidth(peer_id, self.config.burst_bytes),
+        )
+        .await
+        .with_context(|| format!("Bandwidth probe to peer {} timed out", peer_id))??;
+        let bytes_per_sec = sample
+            .bytes_per_sec()
+            .context("Bandwidth probe returned no timing")?;
+
+        self.store
+            .record_peer_bandwidth(peer_id, bytes_per_sec)
+            .await;
+        if let Some(p2p) = &self.p2p {
+            p2p.set_peer_bandwidth(PeerId::new(peer_id as u64), bytes_per_sec);
+        }
+        debug!(
+            "Probed peer {}: {:.0} B/s ({} bytes in {:?}, rtt {:?})",
+            peer_id, bytes_per_sec, sample.bytes, sample.elapsed, sample.rtt
+        );
+        Ok(bytes_per_sec)
+    }
+
+    /// Probe every connected peer in turn; returns how many succeeded
+    pub async fn probe_connected(&self) -> usize {
+        let mut probed = 0;
+        for peer_id in self.network.get_connected_peers().await {
+            match self.probe_peer(peer_id).await {
+                Ok(_) => probed += 1,
+                Err(e) => warn!("Bandwidth probe to peer {} failed: {:#}", peer_id, e),
+            }
+        }
+        probed
+    }
+
+    /// Probe a newly connected peer without waiting for the result
+    pub fn probe_in_background(self: &Arc<Self>, peer_id: u32) {
+        let prober = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = prober.probe_peer(peer_id).await {
+                warn!("Bandwidth probe to peer {} failed: {:#}", peer_id, e);
+            }
+        });
+    }
+
+    /// Re-probe connected peers every `interval`
+    pub fn spawn(self: Arc<Self>) -> Option<JoinHandle<()>> {
+        if self.config.interval.is_zero() {
+            return None;
+        }
+        info!(
+            "Probing peer bandwidth every {:?} ({} byte bursts)",
+            self.config.interval, self.config.burst_bytes
+        );
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.config.interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let probed = self.probe_connected().await;
+                debug!("Bandwidth probe round finished: {} peer(s)", probed);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_sample_discounts_round_trip() {
+        let sample = ProbeSample {
+            bytes: 1_000_000,
+            elapsed: Duration::from_millis(150),
+            rtt: Duration::from_millis(50),
+        };
+        assert_eq!(sample.bytes_per_sec(), Some(10_000_000.0));
+
+        // A burst acknowledged within one round trip is clamped, not infinite
+        let quick = ProbeSample {
+            elapsed: Duration::from_millis(40),
+            ..sample
+        };
+        assert_eq!(quick.bytes_per_sec(), Some(1_000_000_000.0));
+
+        let empty = ProbeSample { bytes: 0, ..sample };
+        assert_eq!(empty.bytes_per_sec(), None);
+    }
+}
@@ -0,0 +1,402 @@
+/// Progress notifications for long-running transfers
+///
+/// Upload and download protocols report each shard they move to a
+/// `ProgressHub`. Subscribers (the RPC server forwarding to a Python
+/// `ProgressListener`, or the CLI) receive `TransferProgress` snapshots with
+/// the completed fraction, a moving-average transfer rate, an ETA and any
+/// warnings, such as a shard falling back to another transport or going
+/// missing. The hub also keeps the latest snapshot of every transfer still in
+/// flight for stats queries.
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// Snapshots buffered per subscriber before the oldest are dropped
+pub const DEFAULT_PROGRESS_CAPACITY: usize = 256;
+
+/// Period over which the transfer rate is averaged
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Which way a transfer moves data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+impl std::fmt::Display for TransferDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferDirection::Upload => write!(f, "upload"),
+            TransferDirection::Download => write!(f, "download"),
+        }
+    }
+}
+
+/// State of a transfer at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferProgress {
+    /// File hash for uploads and downloads of known files
+    pub transfer_id: String,
+    pub direction: TransferDirection,
+    pub shards_done: usize,
+    pub shards_total: usize,
+    pub bytes_done: u64,
+    /// Known size of the transfer, or an estimate from the average shard size
+    pub bytes_total: Option<u64>,
+    /// Rate averaged over the last few seconds
+    pub bytes_per_sec: f64,
+    /// Seconds until completion at the current rate
+    pub eta_secs: Option<f64>,
+    /// Set on the snapshot that reports a problem
+    pub warning: Option<String>,
+    /// True on the final snapshot
+    pub finished: bool,
+    /// True if the transfer completed successfully (only meaningful when finished)
+    pub success: bool,
+}
+
+impl TransferProgress {
+    /// Completed fraction as a percentage (0.0 - 100.0)
+    pub fn percent(&self) -> f32 {
+        if self.shards_total == 0 {
+            return if self.finished { 100.0 } else { 0.0 };
+        }
+        (self.shards_done as f32 / self.shards_total as f32 * 100.0).min(100.0)
+    }
+
+    /// One-line summary for terminal display, e.g. `42.0% 3.1 MiB/s ETA 12s`
+    pub fn status_line(&self) -> String {
+        let mut line = format!(
+            "{} {} {:5.1}% ({}/{} shards) {}/s",
+            self.direction,
+            short_id(&self.transfer_id),
+            self.percent(),
+            self.shards_done,
+            self.shards_total,
+            format_bytes(self.bytes_per_sec as u64),
+        );
+        if let Some(eta) = self.eta_secs.filter(|_| !self.finished) {
+            line.push_str(&format!(" ETA {}", format_eta(eta)));
+        }
+        line
+    }
+}
+
+fn short_id(transfer_id: &str) -> &str {
+    match transfer_id.char_indices().nth(12) {
+        Some((end, _)) => &transfer_id[..end],
+        None => transfer_id,
+    }
+}
+
+/// Human-readable byte count with binary units
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_eta(secs: f64) -> String {
+    let secs = secs.ceil() as u64;
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Latest snapshot of each unfinished transfer, keyed by tracker
+type ActiveTransfers = Arc<Mutex<HashMap<u64, TransferProgress>>>;
+
+/// Fan-out point for transfer progress
+#[derive(Debug, Clone)]
+pub struct ProgressHub {
+    sender: broadcast::Sender<TransferProgress>,
+    active: ActiveTransfers,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ProgressHub {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self {
+            sender,
+            active: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+
+    /// Receive every snapshot published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<TransferProgress> {
+        self.sender.subscribe()
+    }
+
+    /// Start tracking a transfer of `shards_total` shards
+    pub fn track(
+        &self,
+        transfer_id: impl Into<String>,
+        direction: TransferDirection,
+        shards_total: usize,
+    ) -> ProgressTracker {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        let state = TransferProgress {
+            transfer_id: transfer_id.into(),
+            direction,
+            shards_done: 0,
+            shards_total,
+            bytes_done: 0,
+            bytes_total: None,
+            bytes_per_sec: 0.0,
+            eta_secs: None,
+            warning: None,
+            finished: false,
+            success: false,
+        };
+        self.active.lock().insert(id, state.clone());
+
+        ProgressTracker {
+            id,
+            sender: self.sender.clone(),
+            active: self.active.clone(),
+            started,
+            samples: VecDeque::from([(started, 0)]),
+            known_total: None,
+            state,
+        }
+    }
+
+    /// Latest snapshot of every transfer still in flight, oldest first
+    pub fn active(&self) -> Vec<TransferProgress> {
+        let active = self.active.lock();
+        let mut ids: Vec<&u64> = active.keys().collect();
+        ids.sort();
+        ids.into_iter().map(|id| active[id].clone()).collect()
+    }
+
+    fn publish(sender: &broadcast::Sender<TransferProgress>, progress: TransferProgress) {
+        // No subscribers is fine; progress is best effort
+        let _ = sender.send(progress);
+    }
+}
+
+impl Default for ProgressHub {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROGRESS_CAPACITY)
+    }
+}
+
+/// Reports progress for one transfer
+///
+/// Dropping a tracker that was never finished publishes a failed final
+/// snapshot, so listeners are not left waiting when a transfer errors out.
+pub struct ProgressTracker {
+    id: u64,
+    sender: broadcast::Sender<TransferProgress>,
+    active: ActiveTransfers,
+    started: Instant,
+    /// `(when, bytes_done)` over the last `RATE_WINDOW`
+    samples: VecDeque<(Instant, u64)>,
+    known_total: Option<u64>,
+    state: TransferProgress,
+}
+
+impl ProgressTracker {
+    /// Size of the whole transfer, when known up front
+    pub fn with_bytes_total(mut self, bytes: u64) -> Self {
+        self.known_total = Some(bytes);
+        self.state.bytes_total = Some(bytes);
+        self
+    }
+
+    /// Record one more shard of `bytes` moved
+    pub fn shard_done(&mut self, bytes: usize) {
+        self.state.shards_done += 1;
+        self.state.bytes_done += bytes as u64;
+        self.publish(None);
+    }
+
+    /// Publish a warning without changing the counts
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.publish(Some(message.into()));
+    }
+
+    /// Publish the final snapshot
+    pub fn finish(mut self, success: bool) {
+        self.complete(success);
+    }
+
+    pub fn snapshot(&self) -> &TransferProgress {
+        &self.state
+    }
+
+    fn complete(&mut self, success: bool) {
+        self.state.finished = true;
+        self.state.success = success;
+        self.active.lock().remove(&self.id);
+        self.publish(None);
+    }
+
+    fn publish(&mut self, warning: Option<String>) {
+        self.update_rate(Instant::now());
+        if !self.state.finished {
+            self.active.lock().insert(self.id, self.state.clone());
+        }
+        let mut progress = self.state.clone();
+        progress.warning = warning;
+        ProgressHub::publish(&self.sender, progress);
+    }
+
+    /// Refresh the moving-average rate, total estimate and ETA
+    fn update_rate(&mut self, now: Instant) {
+        let bytes_done = self.state.bytes_done;
+        if self.samples.back().map(|&(_, bytes)| bytes) != Some(bytes_done) {
+            self.samples.push_back((now, bytes_done));
+        }
+        // Keep one sample older than the window as the baseline
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= RATE_WINDOW {
+            self.samples.pop_front();
+        }
+
+        let (since, base) = self.samples.front().copied().unwrap_or((self.started, 0));
+        let elapsed = now.duration_since(since).as_secs_f64();
+        if elapsed > 0.0 {
+            self.state.bytes_per_sec = (bytes_done - base) as f64 / elapsed;
+        }
+
+        if self.known_total.is_none() && self.state.shards_done > 0 {
+            let per_shard = bytes_done / self.state.shards_done as u64;
+            self.state.bytes_total = Some(per_shard * self.state.shards_total as u64);
+        }
+        self.state.eta_secs = match (self.state.bytes_total, self.state.finished) {
+            (_, true) => Some(0.0),
+            (Some(total), false) if self.state.bytes_per_sec > 0.0 => {
+                Some(total.saturating_sub(bytes_done) as f64 / self.state.bytes_per_sec)
+            }
+            _ => None,
+        };
+    }
+}
+
+impl Drop for ProgressTracker {
+    fn drop(&mut self) {
+        if !self.state.finished {
+            self.complete(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracker_publishes_snapshots() {
+        let hub = ProgressHub::default();
+        let mut rx = hub.subscribe();
+
+        let mut tracker = hub.track("abc", TransferDirection::Upload, 4);
+        tracker.shard_done(100);
+        tracker.warn("shard 1 sent via quic fallback");
+        tracker.shard_done(100);
+        tracker.finish(true);
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.percent(), 25.0);
+        assert_eq!(first.bytes_done, 100);
+        assert!(first.warning.is_none());
+
+        let warning = rx.recv().await.unwrap();
+        assert_eq!(
+            warning.warning.as_deref(),
+            Some("shard 1 sent via quic fallback")
+        );
+
+        assert_eq!(rx.recv().await.unwrap().percent(), 50.0);
+        let last = rx.recv().await.unwrap();
+        assert!(last.finished && last.success);
+        assert_eq!(last.transfer_id, "abc");
+    }
+
+    #[tokio::test]
+    async fn test_dropped_tracker_reports_failure() {
+        let hub = ProgressHub::default();
+        let mut rx = hub.subscribe();
+        {
+            let mut tracker = hub.track("xyz", TransferDirection::Download, 2);
+            tracker.shard_done(10);
+        }
+        rx.recv().await.unwrap();
+        let last = rx.recv().await.unwrap();
+        assert!(last.finished);
+        assert!(!last.success);
+        assert!(hub.active().is_empty());
+    }
+
+    #[test]
+    fn test_rate_and_eta_follow_recent_window() {
+        let hub = ProgressHub::default();
+        let mut tracker = hub.track("abc", TransferDirection::Download, 10);
+        let start = tracker.started;
+
+        // A slow start: 1 shard of 1000 bytes in 10 seconds
+        tracker.state.shards_done = 1;
+        tracker.state.bytes_done = 1000;
+        tracker.update_rate(start + Duration::from_secs(10));
+
+        // Then 4 more shards in the next 2 seconds
+        tracker.state.shards_done = 5;
+        tracker.state.bytes_done = 5000;
+        tracker.update_rate(start + Duration::from_secs(12));
+        // The window still reaches back to the start
+        assert!((tracker.state.bytes_per_sec - 5000.0 / 12.0).abs() < 1.0);
+
+        tracker.state.shards_done = 6;
+        tracker.state.bytes_done = 6000;
+        tracker.update_rate(start + Duration::from_secs(16));
+        // The slow first sample has aged out; the rate is over the last 6s
+        assert!((tracker.state.bytes_per_sec - 5000.0 / 6.0).abs() < 1.0);
+
+        // Total is estimated from the average shard size
+        assert_eq!(tracker.state.bytes_total, Some(10_000));
+        let eta = tracker.state.eta_secs.unwrap();
+        assert!((eta - 4000.0 / (5000.0 / 6.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_active_transfers() {
+        let hub = ProgressHub::default();
+        let mut upload = hub
+            .track("up", TransferDirection::Upload, 2)
+            .with_bytes_total(200);
+        let download = hub.track("down", TransferDirection::Download, 4);
+
+        upload.shard_done(100);
+        let active = hub.active();
+        assert_eq!(active.len(), 2);
+        assert_eq!(active[0].transfer_id, "up");
+        assert_eq!(active[0].bytes_done, 100);
+        assert_eq!(active[0].bytes_total, Some(200));
+
+        upload.finish(true);
+        download.finish(false);
+        assert!(hub.active().is_empty());
+    }
+}
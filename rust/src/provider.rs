@@ -0,0 +1,1081 @@
+/// Storage provider mode
+///
+/// A node started with `--serve-storage` keeps shards for other peers in its
+/// own directory, up to a fixed byte budget. Peers talk to it with
+/// [`StorageRequest`]s over QUIC bidirectional streams (see
+/// [`StorageClient`]) or through the RPC service:
+///
+/// - `Put` stores a shard if the budget (and per-peer quota) has room
+/// - `Get` / `Has` read it back; any peer may fetch a shard
+/// - `Delete` removes it, but only for the peer that stored it
+/// - `Challenge` proves the shard is still held by hashing it with a fresh
+///   nonce; uploaders compute expected answers before handing shards over
+/// - `Renew` extends the lease on the peer's shards
+///
+/// Shards are leased: a shard not renewed within `lease_duration` of being
+/// stored or last renewed is removed by garbage collection, so space held
+/// for uploaders that disappeared is reclaimed. The file's modification time
+/// records the last renewal, so leases survive restarts.
+///
+/// Every request is booked against the requesting peer's [`PeerAccount`].
+/// Peer IDs are taken from the request, as connections do not yet exchange
+/// authenticated identities.
+///
+/// Shards live at `<dir>/<file_hash>/<index>-<owner>.bin`, so usage and
+/// ownership are recovered by scanning the directory on start.
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::cache::{is_safe_file_hash, Cache, FileManifest};
+use crate::durability::{is_partial_write, write_atomic, Durability};
+use crate::network::{QuicNode, ReplaySafety, RequestHandler, RequestTag, MAX_REQUEST_BYTES};
+use crate::types::current_timestamp;
+
+/// Largest shard a provider accepts
+pub const MAX_PROVIDER_SHARD_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long a shard is kept without renewal
+pub const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Tags storage requests so they are not confused with other stream traffic
+pub const REQUEST_MAGIC: RequestTag = *b"PSTO";
+
+/// A request to a storage provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageRequest {
+    Put {
+        file_hash: String,
+        shard_index: usize,
+        data: Vec<u8>,
+    },
+    Get {
+        file_hash: String,
+        shard_index: usize,
+    },
+    Has {
+        file_hash: String,
+        shard_index: usize,
+    },
+    Delete {
+        file_hash: String,
+        shard_index: usize,
+    },
+    /// Answer with `storage_proof(nonce, shard)`
+    Challenge {
+        file_hash: String,
+        shard_index: usize,
+        nonce: [u8; 32],
+    },
+    /// Extend the lease on these shards of a file
+    Renew {
+        file_hash: String,
+        shard_indices: Vec<usize>,
+    },
+}
+
+/// A provider's answer to a [`StorageRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageResponse {
+    Stored,
+    Data(Vec<u8>),
+    Has(bool),
+    Deleted(bool),
+    Proof([u8; 32]),
+    NotFound,
+    QuotaExceeded {
+        needed: u64,
+        available: u64,
+    },
+    Rejected(String),
+    /// How many of the requested shards were renewed, and until when (unix)
+    Renewed {
+        shards: usize,
+        expires_at: u64,
+    },
+}
+
+/// What one peer has stored on and fetched from this provider
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerAccount {
+    pub shards_stored: usize,
+    pub bytes_stored: u64,
+    pub bytes_served: u64,
+    pub challenges_answered: u64,
+    /// Shards and bytes removed because their lease ran out
+    pub shards_expired: usize,
+    pub bytes_expired: u64,
+}
+
+/// Provider totals
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderStats {
+    pub budget_bytes: u64,
+    pub used_bytes: u64,
+    pub shards: usize,
+    pub peers: usize,
+}
+
+/// Changes to what the provider holds, for announcing it in the DHT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderEvent {
+    /// The first shard of a file was stored
+    Stored { file_hash: String },
+    /// The last shard of a file was deleted or expired
+    Released { file_hash: String },
+}
+
+/// A shard removed by garbage collection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiredShard {
+    pub file_hash: String,
+    pub shard_index: usize,
+    pub owner: u32,
+    pub bytes: u64,
+}
+
+/// Answer to a storage challenge: SHA-256 of the nonce followed by the shard
+pub fn storage_proof(nonce: &[u8; 32], shard: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(nonce);
+    hasher.update(shard);
+    hasher.finalize().into()
+}
+
+/// Parse a byte size such as `500GB`, `64MiB` or `1048576`
+///
+/// Decimal (`KB`, `MB`, `GB`, `TB`) and binary (`KiB` .. `TiB`) suffixes are
+/// accepted, case-insensitively.
+pub fn parse_byte_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size {:?}", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => anyhow::bail!("Unknown size unit {:?} in {:?}", other, s),
+    };
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        anyhow::bail!("Size {:?} is out of range", s);
+    }
+    Ok(bytes as u64)
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_REQUEST_BYTES as u64)
+}
+
+impl StorageRequest {
+    /// Encode for a stream, tagged with the requesting peer's ID
+    pub fn to_bytes(&self, from: u32) -> Result<Vec<u8>> {
+        let mut buffer = REQUEST_MAGIC.to_vec();
+        buffer.extend_from_slice(&from.to_be_bytes());
+        codec()
+            .serialize_into(&mut buffer, self)
+            .context("Failed to serialize storage request")?;
+        Ok(buffer)
+    }
+
+    /// Decode a tagged request, returning the requesting peer's ID
+    pub fn from_bytes(bytes: &[u8]) -> Result<(u32, Self)> {
+        let body = bytes
+            .strip_prefix(&REQUEST_MAGIC)
+            .context("Not a storage request")?;
+        let from = body.get(..4).context("Truncated storage request")?;
+        let from = u32::from_be_bytes(from.try_into()?);
+        let request = codec()
+            .deserialize(&body[4..])
+            .context("Failed to deserialize storage request")?;
+        Ok((from, request))
+    }
+
+    /// Reads and proofs may be replayed; anything that changes what the
+    /// provider stores may not
+    pub fn replay_safety(&self) -> ReplaySafety {
+        match self {
+            StorageRequest::Get { .. }
+            | StorageRequest::Has { .. }
+            | StorageRequest::Challenge { .. } => ReplaySafety::Idempotent,
+            StorageRequest::Put { .. }
+            | StorageRequest::Delete { .. }
+            | StorageRequest::Renew { .. } => ReplaySafety::Mutating,
+        }
+    }
+}
+
+impl StorageResponse {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        codec()
+            .serialize(self)
+            .context("Failed to serialize storage response")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        codec()
+            .deserialize(bytes)
+            .context("Failed to deserialize storage response")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StoredShard {
+    owner: u32,
+    bytes: u64,
+    /// Unix seconds of the put or latest renewal
+    renewed_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct ProviderState {
+    shards: HashMap<(String, usize), StoredShard>,
+    used_bytes: u64,
+    accounts: HashMap<u32, PeerAccount>,
+}
+
+impl ProviderState {
+    fn insert(&mut self, key: (String, usize), shard: StoredShard) {
+        self.used_bytes += shard.bytes;
+        let account = self.accounts.entry(shard.owner).or_default();
+        account.shards_stored += 1;
+        account.bytes_stored += shard.bytes;
+        self.shards.insert(key, shard);
+    }
+
+    fn remove(&mut self, key: &(String, usize)) -> Option<StoredShard> {
+        let shard = self.shards.remove(key)?;
+        self.used_bytes -= shard.bytes;
+        if let Some(account) = self.accounts.get_mut(&shard.owner) {
+            account.shards_stored -= 1;
+            account.bytes_stored -= shard.bytes;
+        }
+        Some(shard)
+    }
+
+    fn holds_file(&self, file_hash: &str) -> bool {
+        self.shards.keys().any(|(hash, _)| hash == file_hash)
+    }
+}
+
+/// Keeps shards for other peers within a byte budget
+pub struct StorageProvider {
+    dir: PathBuf,
+    budget_bytes: u64,
+    peer_quota: Option<u64>,
+    lease_duration: Duration,
+    /// Extra time a lease is kept past its expiry
+    lease_grace: Duration,
+    events: Option<mpsc::UnboundedSender<ProviderEvent>>,
+    durability: Durability,
+    state: RwLock<ProviderState>,
+}
+
+impl StorageProvider {
+    /// Serve shards from `dir`, taking stock of any already there
+    pub async fn open(dir: impl AsRef<Path>, budget_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Failed to create storage directory {:?}", dir))?;
+
+        let mut state = ProviderState::default();
+        let mut files = tokio::fs::read_dir(&dir).await?;
+        while let Some(file_dir) = files.next_entry().await? {
+            let file_hash = file_dir.file_name().to_string_lossy().into_owned();
+            if !is_safe_file_hash(&file_hash) || !file_dir.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut shards = tokio::fs::read_dir(file_dir.path()).await?;
+            while let Some(entry) = shards.next_entry().await? {
+                if is_partial_write(&entry.path()) {
+                    debug!("Removing interrupted write {:?}", entry.path());
+                    tokio::fs::remove_file(entry.path()).await?;
+                    continue;
+                }
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let Some((shard_index, owner)) = parse_shard_file_name(&name) else {
+                    warn!("Ignoring unexpected file {:?} in storage", entry.path());
+                    continue;
+                };
+                let metadata = entry.metadata().await?;
+                let renewed_at = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                state.insert(
+                    (file_hash.clone(), shard_index),
+                    StoredShard {
+                        owner,
+                        bytes: metadata.len(),
+                        renewed_at,
+                    },
+                );
+            }
+        }
+        info!(
+            "Storage provider at {:?}: {} shard(s), {} of {} bytes used",
+            dir,
+            state.shards.len(),
+            state.used_bytes,
+            budget_bytes
+        );
+        Ok(Self {
+            dir,
+            budget_bytes,
+            peer_quota: None,
+            lease_duration: DEFAULT_LEASE_DURATION,
+            lease_grace: Duration::ZERO,
+            events: None,
+            durability: Durability::default(),
+            state: RwLock::new(state),
+        })
+    }
+
+    /// Limit how many bytes any one peer may store
+    pub fn with_peer_quota(mut self, bytes: u64) -> Self {
+        self.peer_quota = Some(bytes);
+        self
+    }
+
+    /// Remove shards not renewed within `duration`
+    pub fn with_lease_duration(mut self, duration: Duration) -> Self {
+        self.lease_duration = duration;
+        self
+    }
+
+    /// Keep shards `grace` past their lease before collecting them
+    ///
+    /// Owners time renewals on their own clocks; set this to the clock skew
+    /// tolerance so an owner whose clock runs slow is not cut off early.
+    pub fn with_lease_grace(mut self, grace: Duration) -> Self {
+        self.lease_grace = grace;
+        self
+    }
+
+    /// Report files starting and ceasing to be held on this channel
+    pub fn with_events(mut self, events: mpsc::UnboundedSender<ProviderEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// How far shard writes are synced before they are acknowledged
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub async fn stats(&self) -> ProviderStats {
+        let state = self.state.read().await;
+        ProviderStats {
+            budget_bytes: self.budget_bytes,
+            used_bytes: state.used_bytes,
+            shards: state.shards.len(),
+            peers: state.accounts.len(),
+        }
+    }
+
+    pub async fn account(&self, peer_id: u32) -> Option<PeerAccount> {
+        self.state.read().await.accounts.get(&peer_id).cloned()
+    }
+
+    /// Accounts of every peer that has used this provider
+    pub async fn accounts(&self) -> Vec<(u32, PeerAccount)> {
+        let state = self.state.read().await;
+        let mut accounts: Vec<_> = state
+            .accounts
+            .iter()
+            .map(|(peer, account)| (*peer, account.clone()))
+            .collect();
+        accounts.sort_by_key(|(peer, _)| *peer);
+        accounts
+    }
+
+    /// Serve one request from `peer_id`
+    pub async fn serve(&self, peer_id: u32, request: StorageRequest) -> StorageResponse {
+        let result = match request {
+            StorageRequest::Put {
+                file_hash,
+                shard_index,
+                data,
+            } => self.put(peer_id, file_hash, shard_index, data).await,
+            StorageRequest::Get {
+                file_hash,
+                shard_index,
+            } => self.get(peer_id, &file_hash, shard_index).await,
+            StorageRequest::Has {
+                file_hash,
+                shard_index,
+            } => {
+                let key = (file_hash, shard_index);
+                Ok(StorageResponse::Has(
+                    self.state.read().await.shards.contains_key(&key),
+                ))
+            }
+            StorageRequest::Delete {
+                file_hash,
+                shard_index,
+            } => self.delete(peer_id, file_hash, shard_index).await,
+            StorageRequest::Challenge {
+                file_hash,
+                shard_index,
+                nonce,
+            } => {
+                self.challenge(peer_id, &file_hash, shard_index, &nonce)
+                    .await
+            }
+            StorageRequest::Renew {
+                file_hash,
+                shard_indices,
+            } => self.renew(peer_id, &file_hash, &shard_indices).await,
+        };
+        result.unwrap_or_else(|e| {
+            warn!("Storage request from peer {} failed: {:#}", peer_id, e);
+            StorageResponse::Rejected(format!("{:#}", e))
+        })
+    }
+
+    async fn put(
+        &self,
+        peer_id: u32,
+        file_hash: String,
+        shard_index: usize,
+        data: Vec<u8>,
+    ) -> Result<StorageResponse> {
+        if data.len() > MAX_PROVIDER_SHARD_BYTES {
+            return Ok(StorageResponse::Rejected(format!(
+                "Shard of {} bytes exceeds limit of {}",
+                data.len(),
+                MAX_PROVIDER_SHARD_BYTES
+            )));
+        }
+        let key = (file_hash, shard_index);
+        let path = self.shard_path(&key.0, shard_index, peer_id)?;
+        let bytes = data.len() as u64;
+
+        let mut state = self.state.write().await;
+        let replaced = match state.shards.get(&key) {
+            Some(existing) if existing.owner != peer_id => {
+                return Ok(StorageResponse::Rejected(format!(
+                    "Shard {}:{} is held for another peer",
+                    key.0, shard_index
+                )));
+            }
+            Some(existing) => existing.bytes,
+            None => 0,
+        };
+        let available = self.budget_bytes - (state.used_bytes - replaced).min(self.budget_bytes);
+        if bytes > available {
+            return Ok(StorageResponse::QuotaExceeded {
+                needed: bytes,
+                available,
+            });
+        }
+        if let Some(quota) = self.peer_quota {
+            let stored = state.accounts.get(&peer_id).map_or(0, |a| a.bytes_stored) - replaced;
+            if stored + bytes > quota {
+                return Ok(StorageResponse::QuotaExceeded {
+                    needed: bytes,
+                    available: quota.saturating_sub(stored),
+                });
+            }
+        }
+
+        write_atomic(&path, &data, self.durability)
+            .await
+            .context("Failed to write shard")?;
+        state.remove(&key);
+        if !state.holds_file(&key.0) {
+            self.notify(ProviderEvent::Stored {
+                file_hash: key.0.clone(),
+            });
+        }
+        state.insert(
+            key,
+            StoredShard {
+                owner: peer_id,
+                bytes,
+                renewed_at: current_timestamp(),
+            },
+        );
+        debug!("Stored shard for peer {} at {:?}", peer_id, path);
+        Ok(StorageResponse::Stored)
+    }
+
+    async fn get(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<StorageResponse> {
+        let Some(data) = self.load(file_hash, shard_index).await? else {
+            return Ok(StorageResponse::NotFound);
+        };
+        self.state
+            .write()
+            .await
+            .accounts
+            .entry(peer_id)
+            .or_default()
+            .bytes_served += data.len() as u64;
+        Ok(StorageResponse::Data(data))
+    }
+
+    async fn load(&self, file_hash: &str, shard_index: usize) -> Result<Option<Vec<u8>>> {
+        let owner = {
+            let state = self.state.read().await;
+            match state.shards.get(&(file_hash.to_string(), shard_index)) {
+                Some(shard) => shard.owner,
+                None => return Ok(None),
+            }
+        };
+        let path = self.shard_path(file_hash, shard_index, owner)?;
+        let data = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read shard {:?}", path))?;
+        Ok(Some(data))
+    }
+
+    async fn delete(
+        &self,
+        peer_id: u32,
+        file_hash: String,
+        shard_index: usize,
+    ) -> Result<StorageResponse> {
+        let key = (file_hash, shard_index);
+        let mut state = self.state.write().await;
+        match state.shards.get(&key) {
+            None => return Ok(StorageResponse::Deleted(false)),
+            Some(shard) if shard.owner != peer_id => {
+                return Ok(StorageResponse::Rejected(format!(
+                    "Shard {}:{} was stored by another peer",
+                    key.0, shard_index
+                )));
+            }
+            Some(_) => {}
+        }
+        let path = self.shard_path(&key.0, shard_index, peer_id)?;
+        tokio::fs::remove_file(&path)
+            .await
+            .with_context(|| format!("Failed to remove shard {:?}", path))?;
+        state.remove(&key);
+        self.release_if_empty(&state, &key.0, &path).await;
+        Ok(StorageResponse::Deleted(true))
+    }
+
+    async fn renew(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_indices: &[usize],
+    ) -> Result<StorageResponse> {
+        let now = SystemTime::now();
+        let renewed_at = current_timestamp();
+        let mut renewed = 0;
+        let mut state = self.state.write().await;
+        for &shard_index in shard_indices {
+            let key = (file_hash.to_string(), shard_index);
+            let Some(shard) = state.shards.get_mut(&key) else {
+                continue;
+            };
+            if shard.owner != peer_id {
+                continue;
+            }
+            let path = self.shard_path(file_hash, shard_index, peer_id)?;
+            let file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .await
+                .with_context(|| format!("Failed to open shard {:?}", path))?;
+            // The modification time is the lease's persisted renewal time
+            file.into_std().await.set_modified(now)?;
+            shard.renewed_at = renewed_at;
+            renewed += 1;
+        }
+        Ok(StorageResponse::Renewed {
+            shards: renewed,
+            expires_at: renewed_at + self.lease_duration.as_secs(),
+        })
+    }
+
+    /// Remove every shard whose lease ran out before `now` (unix seconds)
+    ///
+    /// Owners' accounts are charged with the expired shards, and files no
+    /// longer held at all are reported as released.
+    pub async fn collect_expired(&self, now: u64) -> Result<Vec<ExpiredShard>> {
+        let lease = (self.lease_duration + self.lease_grace).as_secs();
+        let mut state = self.state.write().await;
+        let expired: Vec<(String, usize)> = state
+            .shards
+            .iter()
+            .filter(|(_, shard)| shard.renewed_at.saturating_add(lease) < now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut removed = Vec::with_capacity(expired.len());
+        for key in expired {
+            let Some(shard) = state.shards.get(&key).copied() else {
+                continue;
+            };
+            let path = self.shard_path(&key.0, key.1, shard.owner)?;
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    warn!("Failed to remove expired shard {:?}: {}", path, e);
+                    continue;
+                }
+            }
+            state.remove(&key);
+            let account = state.accounts.entry(shard.owner).or_default();
+            account.shards_expired += 1;
+            account.bytes_expired += shard.bytes;
+            self.release_if_empty(&state, &key.0, &path).await;
+            removed.push(ExpiredShard {
+                file_hash: key.0,
+                shard_index: key.1,
+                owner: shard.owner,
+                bytes: shard.bytes,
+            });
+        }
+        if !removed.is_empty() {
+            info!(
+                "Storage GC removed {} shard(s) with expired leases ({} bytes)",
+                removed.len(),
+                removed.iter().map(|s| s.bytes).sum::<u64>()
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Collect expired shards every `interval`
+    pub fn spawn_gc(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.collect_expired(current_timestamp()).await {
+                    warn!("Storage GC failed: {:#}", e);
+                }
+            }
+        })
+    }
+
+    /// Drop a file's directory and report it once its last shard is gone
+    async fn release_if_empty(&self, state: &ProviderState, file_hash: &str, shard_path: &Path) {
+        if state.holds_file(file_hash) {
+            return;
+        }
+        if let Some(dir) = shard_path.parent() {
+            let _ = tokio::fs::remove_dir(dir).await;
+        }
+        self.notify(ProviderEvent::Released {
+            file_hash: file_hash.to_string(),
+        });
+    }
+
+    fn notify(&self, event: ProviderEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    async fn challenge(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        nonce: &[u8; 32],
+    ) -> Result<StorageResponse> {
+        let Some(data) = self.load(file_hash, shard_index).await? else {
+            return Ok(StorageResponse::NotFound);
+        };
+        let mut state = self.state.write().await;
+        state
+            .accounts
+            .entry(peer_id)
+            .or_default()
+            .challenges_answered += 1;
+        Ok(StorageResponse::Proof(storage_proof(nonce, &data)))
+    }
+
+    fn shard_path(&self, file_hash: &str, shard_index: usize, owner: u32) -> Result<PathBuf> {
+        if !is_safe_file_hash(file_hash) {
+            anyhow::bail!("Invalid file hash {:?}", file_hash);
+        }
+        Ok(self
+            .dir
+            .join(file_hash)
+            .join(format!("{}-{}.bin", shard_index, owner)))
+    }
+}
+
+/// `<index>-<owner>.bin`
+fn parse_shard_file_name(name: &str) -> Option<(usize, u32)> {
+    let (index, owner) = name.strip_suffix(".bin")?.split_once('-')?;
+    Some((index.parse().ok()?, owner.parse().ok()?))
+}
+
+#[async_trait]
+impl RequestHandler for StorageProvider {
+    async fn handle(&self, remote: SocketAddr, request: Vec<u8>) -> Vec<u8> {
+        let response = match StorageRequest::from_bytes(&request) {
+            Ok((peer_id, request)) => self.serve(peer_id, request).await,
+            Err(e) => {
+                debug!("Bad storage request from {}: {:#}", remote, e);
+                StorageResponse::Rejected(format!("{:#}", e))
+            }
+        };
+        response.to_bytes().unwrap_or_default()
+    }
+
+    fn is_replay_safe(&self, request: &[u8]) -> bool {
+        StorageRequest::from_bytes(request)
+            .is_ok_and(|(_, request)| request.replay_safety() == ReplaySafety::Idempotent)
+    }
+}
+
+/// Sends storage requests to provider peers over QUIC
+pub struct StorageClient {
+    network: Arc<QuicNode>,
+    node_id: u32,
+}
+
+impl StorageClient {
+    /// Requests are booked to `node_id` on the provider
+    pub fn new(network: Arc<QuicNode>, node_id: u32) -> Self {
+        Self { network, node_id }
+    }
+
+    /// Send any request and return the provider's answer
+    pub async fn request(&self, peer_id: u32, request: &StorageRequest) -> Result<StorageResponse> {
+        let bytes = request.to_bytes(self.node_id)?;
+        let response = self
+            .network
+            .request_with(peer_id, &bytes, MAX_REQUEST_BYTES, request.replay_safety())
+            .await?;
+        StorageResponse::from_bytes(&response)
+    }
+
+    pub async fn put(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let request = StorageRequest::Put {
+            file_hash: file_hash.to_string(),
+            shard_index,
+            data,
+        };
+        match self.request(peer_id, &request).await? {
+            StorageResponse::Stored => Ok(()),
+            other => anyhow::bail!("Peer {} did not store the shard: {:?}", peer_id, other),
+        }
+    }
+
+    /// Fetch a shard, or `None` if the provider does not hold it
+    pub async fn get(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        let request = StorageRequest::Get {
+            file_hash: file_hash.to_string(),
+            shard_index,
+        };
+        match self.request(peer_id, &request).await? {
+            StorageResponse::Data(data) => Ok(Some(data)),
+            StorageResponse::NotFound => Ok(None),
+            other => anyhow::bail!("Peer {} did not return the shard: {:?}", peer_id, other),
+        }
+    }
+
+    /// Whether the provider answers `nonce` with the `expected` proof
+    pub async fn verify(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        nonce: [u8; 32],
+        expected: [u8; 32],
+    ) -> Result<bool> {
+        let request = StorageRequest::Challenge {
+            file_hash: file_hash.to_string(),
+            shard_index,
+            nonce,
+        };
+        match self.request(peer_id, &request).await? {
+            StorageResponse::Proof(proof) => Ok(proof == expected),
+            StorageResponse::NotFound => Ok(false),
+            other => anyhow::bail!("Peer {} did not answer the challenge: {:?}", peer_id, other),
+        }
+    }
+
+    /// Extend leases on shards of a file held by `peer_id`; returns how many
+    /// the provider renewed
+    pub async fn renew(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_indices: Vec<usize>,
+    ) -> Result<usize> {
+        let request = StorageRequest::Renew {
+            file_hash: file_hash.to_string(),
+            shard_indices,
+        };
+        match self.request(peer_id, &request).await? {
+            StorageResponse::Renewed { shards, .. } => Ok(shards),
+            other => anyhow::bail!("Peer {} did not renew the lease: {:?}", peer_id, other),
+        }
+    }
+
+    /// Renew the leases on every shard of an uploaded file
+    ///
+    /// Peers that do not answer are skipped; returns how many shards were
+    /// renewed.
+    pub async fn renew_manifest(&self, manifest: &FileManifest) -> usize {
+        let mut by_peer: HashMap<u32, Vec<usize>> = HashMap::new();
+        for &(shard_index, peer_id) in &manifest.shard_locations {
+            by_peer.entry(peer_id).or_default().push(shard_index);
+        }
+        let mut renewed = 0;
+        for (peer_id, shard_indices) in by_peer {
+            match self
+                .renew(peer_id, &manifest.file_hash, shard_indices)
+                .await
+            {
+                Ok(count) => renewed += count,
+                Err(e) => debug!(
+                    "Lease renewal for {} on peer {} failed: {:#}",
+                    manifest.file_hash, peer_id, e
+                ),
+            }
+        }
+        renewed
+    }
+
+    /// Renew leases for every file in `cache` every `interval`
+    ///
+    /// The interval should be well inside the providers' lease duration so
+    /// one missed round does not lose shards.
+    pub fn spawn_renewal(self: Arc<Self>, cache: Arc<Cache>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                let mut renewed = 0;
+                for manifest in cache.list_manifests().await {
+                    renewed += self.renew_manifest(&manifest).await;
+                }
+                debug!("Renewed leases on {} shard(s)", renewed);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn put(file_hash: &str, shard_index: usize, data: Vec<u8>) -> StorageRequest {
+        StorageRequest::Put {
+            file_hash: file_hash.to_string(),
+            shard_index,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("500GB").unwrap(), 500_000_000_000);
+        assert_eq!(parse_byte_size("64MiB").unwrap(), 64 * 1024 * 1024);
+        assert_eq!(parse_byte_size("1.5 kb").unwrap(), 1500);
+        assert_eq!(parse_byte_size("4096").unwrap(), 4096);
+        assert!(parse_byte_size("12 parsecs").is_err());
+        assert!(parse_byte_size("GB").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_provider_enforces_budget_and_ownership() {
+        let temp_dir = tempdir().unwrap();
+        let provider = StorageProvider::open(temp_dir.path(), 100)
+            .await
+            .unwrap()
+            .with_peer_quota(80);
+
+        assert_eq!(
+            provider.serve(1, put("abc", 0, vec![1; 60])).await,
+            StorageResponse::Stored
+        );
+        // Peer 1's quota and the total budget are both enforced
+        assert_eq!(
+            provider.serve(1, put("abc", 1, vec![2; 30])).await,
+            StorageResponse::QuotaExceeded {
+                needed: 30,
+                available: 20
+            }
+        );
+        assert_eq!(
+            provider.serve(2, put("def", 0, vec![3; 50])).await,
+            StorageResponse::QuotaExceeded {
+                needed: 50,
+                available: 40
+            }
+        );
+        assert_eq!(
+            provider.serve(2, put("def", 0, vec![3; 40])).await,
+            StorageResponse::Stored
+        );
+
+        // Only the storing peer may overwrite or delete
+        let delete = StorageRequest::Delete {
+            file_hash: "abc".to_string(),
+            shard_index: 0,
+        };
+        assert!(matches!(
+            provider.serve(2, delete.clone()).await,
+            StorageResponse::Rejected(_)
+        ));
+        assert!(matches!(
+            provider.serve(2, put("abc", 0, vec![9; 10])).await,
+            StorageResponse::Rejected(_)
+        ));
+
+        let get = StorageRequest::Get {
+            file_hash: "abc".to_string(),
+            shard_index: 0,
+        };
+        assert_eq!(
+            provider.serve(3, get.clone()).await,
+            StorageResponse::Data(vec![1; 60])
+        );
+        assert_eq!(provider.account(3).await.unwrap().bytes_served, 60);
+
+        assert_eq!(
+            provider.serve(1, delete).await,
+            StorageResponse::Deleted(true)
+        );
+        assert_eq!(provider.serve(3, get).await, StorageResponse::NotFound);
+        assert_eq!(provider.stats().await.used_bytes, 40);
+        assert!(matches!(
+            provider.serve(1, put("../etc", 0, vec![0])).await,
+            StorageResponse::Rejected(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_challenges_and_restart_recover_state() {
+        let temp_dir = tempdir().unwrap();
+        let shard = vec![7u8; 32];
+        {
+            let provider = StorageProvider::open(temp_dir.path(), 1024).await.unwrap();
+            provider.serve(5, put("file", 2, shard.clone())).await;
+        }
+
+        let provider = StorageProvider::open(temp_dir.path(), 1024).await.unwrap();
+        assert_eq!(provider.stats().await.used_bytes, 32);
+        assert_eq!(provider.account(5).await.unwrap().shards_stored, 1);
+
+        let nonce = [42u8; 32];
+        let challenge = StorageRequest::Challenge {
+            file_hash: "file".to_string(),
+            shard_index: 2,
+            nonce,
+        };
+        let response = provider.serve(6, challenge.clone()).await;
+        assert_eq!(
+            response,
+            StorageResponse::Proof(storage_proof(&nonce, &shard))
+        );
+        assert_ne!(
+            response,
+            StorageResponse::Proof(storage_proof(&[0; 32], &shard))
+        );
+        let account = provider.account(6).await.unwrap();
+        assert_eq!((account.challenges_answered, account.bytes_served), (1, 0));
+
+        // Requests survive the wire format
+        let bytes = challenge.to_bytes(6).unwrap();
+        assert_eq!(StorageRequest::from_bytes(&bytes).unwrap(), (6, challenge));
+        assert!(StorageRequest::from_bytes(&bytes[4..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expired_leases_are_collected() {
+        let temp_dir = tempdir().unwrap();
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let provider = StorageProvider::open(temp_dir.path(), 1024)
+            .await
+            .unwrap()
+            .with_lease_duration(Duration::from_secs(60))
+            .with_events(events_tx);
+        provider.serve(1, put("old", 0, vec![1; 10])).await;
+        provider.serve(1, put("old", 1, vec![2; 10])).await;
+        provider.serve(2, put("kept", 0, vec![3; 10])).await;
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ProviderEvent::Stored {
+                file_hash: "old".to_string()
+            }
+        );
+
+        // Peer 2 renews (and peer 1 cannot renew peer 2's shard)
+        let renew = |file_hash: &str| StorageRequest::Renew {
+            file_hash: file_hash.to_string(),
+            shard_indices: vec![0, 1],
+        };
+        assert!(matches!(
+            provider.serve(1, renew("kept")).await,
+            StorageResponse::Renewed { shards: 0, .. }
+        ));
+        assert!(matches!(
+            provider.serve(2, renew("kept")).await,
+            StorageResponse::Renewed { shards: 1, .. }
+        ));
+        // Age peer 1's shards past their lease
+        for shard in provider.state.write().await.shards.values_mut() {
+            if shard.owner == 1 {
+                shard.renewed_at -= 120;
+            }
+        }
+
+        let expired = provider.collect_expired(current_timestamp()).await.unwrap();
+        assert_eq!(expired.len(), 2);
+        assert!(expired.iter().all(|s| s.file_hash == "old" && s.owner == 1));
+        assert!(!temp_dir.path().join("old").exists());
+        let account = provider.account(1).await.unwrap();
+        assert_eq!((account.shards_stored, account.shards_expired), (0, 2));
+        assert_eq!(account.bytes_expired, 20);
+        assert_eq!(provider.stats().await.used_bytes, 10);
+
+        let released: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert!(released.contains(&ProviderEvent::Released {
+            file_hash: "old".to_string()
+        }));
+    }
+}
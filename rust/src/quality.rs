@@ -0,0 +1,131 @@
+/// Per-peer connection quality estimation
+///
+/// Every keep-alive period the QUIC node samples each connection's path
+/// statistics: the smoothed round-trip time, and how many packets were
+/// sent and declared lost since the previous sample. From those a
+/// [`QualityEstimator`] keeps
+///
+/// - latency: the smoothed RTT
+/// - jitter: mean RTT variation between samples (RFC 3550 style, gain 1/16)
+/// - loss: share of packets lost, averaged over recent samples
+///
+/// and classifies the throughput measured by transfers (or, before the
+/// first transfer, the probed bandwidth). The result is the peer's
+/// [`ConnectionQuality`], kept by the QUIC node and the `NodeStore` and
+/// used by streams to pick a bitrate and FEC settings.
+use std::time::Duration;
+
+use crate::types::{ConnectionQuality, ThroughputClass};
+
+/// Weight of a new RTT variation in the jitter estimate
+const JITTER_GAIN: f32 = 1.0 / 16.0;
+
+/// Weight of a new interval's loss rate in the loss estimate
+const LOSS_EWMA_ALPHA: f32 = 0.25;
+
+/// Path statistics of a connection at one instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PathSample {
+    /// Smoothed round-trip time
+    pub rtt: Duration,
+    /// Packets sent since the connection opened
+    pub sent_packets: u64,
+    /// Packets declared lost since the connection opened
+    pub lost_packets: u64,
+}
+
+/// Running quality estimate of one connection
+#[derive(Debug, Clone, Default)]
+pub struct QualityEstimator {
+    last: Option<PathSample>,
+    quality: ConnectionQuality,
+}
+
+impl QualityEstimator {
+    /// Fold in a new path sample and the peer's current throughput
+    pub fn update(&mut self, sample: PathSample, throughput: Option<f64>) -> ConnectionQuality {
+        let latency_ms = sample.rtt.as_secs_f32() * 1000.0;
+        if let Some(last) = self.last {
+            let variation = (latency_ms - self.quality.latency_ms).abs();
+            self.quality.jitter_ms += JITTER_GAIN * (variation - self.quality.jitter_ms);
+
+            // Counters only grow on one connection; a reset means a new one
+            let sent = sample.sent_packets.saturating_sub(last.sent_packets);
+            let lost = sample.lost_packets.saturating_sub(last.lost_packets);
+            if sent > 0 {
+                let loss = (lost as f32 / sent as f32).min(1.0);
+                self.quality.packet_loss += LOSS_EWMA_ALPHA * (loss - self.quality.packet_loss);
+            }
+        }
+        self.quality.latency_ms = latency_ms;
+        self.last = Some(sample);
+
+        if let Some(bytes_per_sec) = throughput {
+            self.quality.throughput_bytes_per_sec = bytes_per_sec;
+            self.quality.throughput_class = ThroughputClass::of(bytes_per_sec);
+        }
+        self.quality.clone()
+    }
+
+    /// The latest estimate
+    pub fn quality(&self) -> &ConnectionQuality {
+        &self.quality
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(rtt_ms: u64, sent: u64, lost: u64) -> PathSample {
+        PathSample {
+            rtt: Duration::from_millis(rtt_ms),
+            sent_packets: sent,
+            lost_packets: lost,
+        }
+    }
+
+    #[test]
+    fn test_estimator_tracks_rtt_jitter_and_loss() {
+        let mut estimator = QualityEstimator::default();
+        let quality = estimator.update(sample(50, 100, 0), None);
+        assert_eq!(quality.latency_ms, 50.0);
+        assert_eq!(quality.jitter_ms, 0.0);
+        assert_eq!(quality.throughput_class, ThroughputClass::Unknown);
+
+        // RTT swinging by 16 ms moves jitter toward 16 ms
+        let mut quality = quality;
+        for i in 0..200 {
+            let rtt = if i % 2 == 0 { 66 } else { 50 };
+            quality = estimator.update(sample(rtt, 100 + i * 100, 0), Some(2_000_000.0));
+        }
+        assert!((quality.jitter_ms - 16.0).abs() < 0.5);
+        assert_eq!(quality.packet_loss, 0.0);
+        assert_eq!(quality.throughput_class, ThroughputClass::High);
+
+        // A steady 10% loss is reached after a few intervals
+        let mut sent = 100 + 200 * 100;
+        let mut lost = 0;
+        for _ in 0..30 {
+            sent += 100;
+            lost += 10;
+            quality = estimator.update(sample(50, sent, lost), Some(50_000.0));
+        }
+        assert!((quality.packet_loss - 0.1).abs() < 0.01);
+        assert_eq!(quality.throughput_class, ThroughputClass::Low);
+
+        // Idle intervals keep the last loss estimate
+        let idle = estimator.update(sample(50, sent, lost), None);
+        assert_eq!(idle.packet_loss, quality.packet_loss);
+        assert_eq!(idle.throughput_bytes_per_sec, 50_000.0);
+    }
+
+    #[test]
+    fn test_throughput_classes() {
+        assert_eq!(ThroughputClass::of(0.0), ThroughputClass::Unknown);
+        assert_eq!(ThroughputClass::of(f64::NAN), ThroughputClass::Unknown);
+        assert_eq!(ThroughputClass::of(100_000.0), ThroughputClass::Low);
+        assert_eq!(ThroughputClass::of(500_000.0), ThroughputClass::Medium);
+        assert_eq!(ThroughputClass::of(1_250_000.0), ThroughputClass::High);
+    }
+}
@@ -0,0 +1,273 @@
+/// Caching of DHT lookup results
+///
+/// A DHT query walks the network and waits out a timeout when nothing
+/// answers, so looking up the same hash again (the CLI looks a file up
+/// before downloading it, healers and retries look up missing files over
+/// and over) repeats that cost. Results are kept for `positive_ttl_secs`;
+/// "not found" is kept too, but only for `negative_ttl_secs`, since a file
+/// may be published any moment.
+///
+/// A cached miss is dropped early when a provider announcement suggests the
+/// file turned up: a shard filter advertised by a peer that may hold one of
+/// its shards, or a manifest registered through this node.
+use anyhow::{bail, Context, Result};
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Query cache settings (`[query_cache]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryCacheConfig {
+    pub enabled: bool,
+    /// How long a found result is reused
+    pub positive_ttl_secs: u64,
+    /// How long "not found" is reused
+    pub negative_ttl_secs: u64,
+    /// Results kept; the least recently used go first
+    pub max_entries: usize,
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            positive_ttl_secs: 300,
+            negative_ttl_secs: 30,
+            max_entries: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    query_cache: QueryCacheConfig,
+}
+
+impl QueryCacheConfig {
+    /// Load the `[query_cache]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[query_cache]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.query_cache.validate()?;
+        Ok(file.query_cache)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_entries == 0 {
+            bail!("query_cache: max_entries must be at least 1");
+        }
+        if self.negative_ttl_secs > self.positive_ttl_secs {
+            bail!("query_cache: negative_ttl_secs must not exceed positive_ttl_secs");
+        }
+        Ok(())
+    }
+}
+
+/// Query cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryCacheStats {
+    /// Lookups answered with a cached result
+    pub hits: u64,
+    /// Lookups answered with a cached "not found"
+    pub negative_hits: u64,
+    /// Lookups that had to query the DHT
+    pub misses: u64,
+    /// Results dropped early by provider announcements
+    pub invalidations: u64,
+    /// Results currently cached, found or not
+    pub entries: usize,
+}
+
+/// A cached result: `None` if the query found nothing
+struct Entry<V> {
+    value: Option<V>,
+    expires: Instant,
+}
+
+/// Results of recent DHT queries, by key
+pub struct QueryCache<V> {
+    config: QueryCacheConfig,
+    entries: Mutex<LruCache<String, Entry<V>>>,
+    hits: AtomicU64,
+    negative_hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl<V: Clone> Default for QueryCache<V> {
+    fn default() -> Self {
+        Self::new(QueryCacheConfig::default())
+    }
+}
+
+impl<V: Clone> QueryCache<V> {
+    pub fn new(config: QueryCacheConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            config,
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            negative_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &QueryCacheConfig {
+        &self.config
+    }
+
+    /// The cached result for `key`: `Some(None)` is a cached "not found",
+    /// `None` means the DHT has to be asked
+    pub fn get(&self, key: &str) -> Option<Option<V>> {
+        if !self.config.enabled {
+            return None;
+        }
+        let mut entries = self.entries.lock();
+        let cached = match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        let counter = match &cached {
+            Some(Some(_)) => &self.hits,
+            Some(None) => &self.negative_hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    /// Remember what a query for `key` found
+    pub fn insert(&self, key: &str, value: Option<V>) {
+        if !self.config.enabled {
+            return;
+        }
+        let ttl = match value {
+            Some(_) => self.config.positive_ttl_secs,
+            None => self.config.negative_ttl_secs,
+        };
+        if ttl == 0 {
+            return;
+        }
+        let entry = Entry {
+            value,
+            expires: Instant::now() + Duration::from_secs(ttl),
+        };
+        self.entries.lock().put(key.to_string(), entry);
+    }
+
+    /// Drop the result for `key`; returns whether one was cached
+    pub fn invalidate(&self, key: &str) -> bool {
+        let removed = self.entries.lock().pop(key).is_some();
+        if removed {
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    /// Drop every cached "not found" whose key `turned_up` says may now be
+    /// found; returns how many were dropped
+    pub fn invalidate_misses(&self, turned_up: impl Fn(&str) -> bool) -> usize {
+        let mut entries = self.entries.lock();
+        let stale: Vec<String> = entries
+            .iter()
+            .filter(|(key, entry)| entry.value.is_none() && turned_up(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            entries.pop(key);
+        }
+        self.invalidations
+            .fetch_add(stale.len() as u64, Ordering::Relaxed);
+        stale.len()
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            negative_hits: self.negative_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            entries: self.entries.lock().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hits_misses_and_negative_results() {
+        let cache = QueryCache::<u32>::default();
+        assert_eq!(cache.get("a"), None);
+        cache.insert("a", Some(1));
+        cache.insert("b", None);
+        assert_eq!(cache.get("a"), Some(Some(1)));
+        assert_eq!(cache.get("b"), Some(None));
+        assert_eq!(
+            cache.stats(),
+            QueryCacheStats {
+                hits: 1,
+                negative_hits: 1,
+                misses: 1,
+                invalidations: 0,
+                entries: 2,
+            }
+        );
+
+        // Only misses a provider announcement may have resolved are dropped
+        cache.insert("c", None);
+        assert_eq!(cache.invalidate_misses(|key| key != "c"), 1);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(None));
+        assert_eq!(cache.get("a"), Some(Some(1)));
+        assert!(cache.invalidate("a"));
+        assert!(!cache.invalidate("a"));
+        assert_eq!(cache.stats().invalidations, 2);
+    }
+
+    #[test]
+    fn test_negative_results_expire_first() {
+        let cache = QueryCache::<u32>::new(QueryCacheConfig {
+            negative_ttl_secs: 0,
+            ..QueryCacheConfig::default()
+        });
+        cache.insert("found", Some(1));
+        cache.insert("missing", None);
+        assert_eq!(cache.get("found"), Some(Some(1)));
+        assert_eq!(cache.get("missing"), None);
+
+        let disabled = QueryCache::<u32>::new(QueryCacheConfig {
+            enabled: false,
+            ..QueryCacheConfig::default()
+        });
+        disabled.insert("found", Some(1));
+        assert_eq!(disabled.get("found"), None);
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = QueryCacheConfig::from_toml("[query_cache]\nnegative_ttl_secs = 5\n").unwrap();
+        assert_eq!(config.negative_ttl_secs, 5);
+        assert_eq!(config.positive_ttl_secs, 300);
+        assert!(QueryCacheConfig::from_toml("[query_cache]\nmax_entries = 0\n").is_err());
+        assert!(QueryCacheConfig::from_toml("[query_cache]\nnegative_ttl_secs = 600\n").is_err());
+    }
+}
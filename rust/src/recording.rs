@@ -0,0 +1,527 @@
+/// Recording of streaming sessions to Ogg Opus files
+///
+/// A `SessionRecorder` captures a call as one mixed track or as one track
+/// per participant, encoding with Opus and writing Ogg pages (RFC 7845) as
+/// it goes. Only participants who explicitly consented are recorded: audio
+/// from anyone else is dropped before it is encoded, and a participant who
+/// withdraws consent is left out from then on. At session end
+/// `StreamingSession::end` uploads the finished files to the content network.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::codecs::{AudioConfig, AudioEncoder};
+
+/// File extension of recordings
+pub const RECORDING_EXTENSION: &str = "opus";
+
+/// Ogg Opus granule positions always count 48 kHz samples
+const GRANULE_RATE: u64 = 48000;
+
+/// Samples the decoder drops at the start (libopus encoder lookahead)
+const OPUS_PRE_SKIP: u16 = 312;
+
+/// How far one participant may run ahead of a silent one before the mix
+/// goes on without the silent one (25 frames of 20 ms)
+const MAX_MIX_LAG_FRAMES: usize = 25;
+
+/// How a session is recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordingMode {
+    /// Consenting participants mixed into one track
+    Mixed,
+    /// One track per consenting participant
+    PerParticipant,
+}
+
+/// Recording settings of a streaming session
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    pub mode: RecordingMode,
+    /// Directory the Ogg files are written to
+    pub output_dir: PathBuf,
+    /// Upload the files at session end; otherwise they are only kept locally
+    pub upload: bool,
+    /// Delete a file once it is uploaded
+    pub remove_after_upload: bool,
+}
+
+impl RecordingConfig {
+    pub fn new(mode: RecordingMode, output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            mode,
+            output_dir: output_dir.into(),
+            upload: true,
+            remove_after_upload: true,
+        }
+    }
+}
+
+/// A finished recording file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub path: PathBuf,
+    /// Whose track this is; `None` for the mixed track
+    pub participant: Option<u32>,
+    /// Consenting participants heard in the file
+    pub participants: Vec<u32>,
+    pub duration_ms: u64,
+}
+
+/// CRC-32 of an Ogg page (polynomial 0x04c11db7, not reflected)
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= (*byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Writes Opus packets as an Ogg Opus stream, one packet per page
+pub struct OggOpusWriter<W: Write> {
+    writer: W,
+    serial: u32,
+    sequence: u32,
+    /// 48 kHz samples per sample of the encoded rate
+    granule_scale: u64,
+    granule: u64,
+    /// Last packet and its end granule, held back so the final page can be
+    /// marked end-of-stream
+    held: Option<(Vec<u8>, u64)>,
+}
+
+impl<W: Write> OggOpusWriter<W> {
+    const BEGIN_OF_STREAM: u8 = 0x02;
+    const END_OF_STREAM: u8 = 0x04;
+
+    /// Start a stream, writing the `OpusHead` and `OpusTags` headers
+    ///
+    /// `comments` are `KEY=value` tags stored in the file.
+    pub fn new(writer: W, audio: &AudioConfig, serial: u32, comments: &[String]) -> Result<Self> {
+        let granule_scale = GRANULE_RATE / audio.sample_rate as u64;
+        if granule_scale == 0 || GRANULE_RATE % audio.sample_rate as u64 != 0 {
+            bail!("Unsupported Opus sample rate {}", audio.sample_rate);
+        }
+        let mut ogg = Self {
+            writer,
+            serial,
+            sequence: 0,
+            granule_scale,
+            granule: 0,
+            held: None,
+        };
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(audio.channels as u8);
+        head.extend_from_slice(&OPUS_PRE_SKIP.to_le_bytes());
+        head.extend_from_slice(&audio.sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // mono/stereo channel mapping
+        ogg.write_page(Self::BEGIN_OF_STREAM, 0, &head)?;
+
+        let vendor = concat!("pangea ", env!("CARGO_PKG_VERSION"));
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            tags.extend_from_slice(comment.as_bytes());
+        }
+        ogg.write_page(0, 0, &tags)?;
+        Ok(ogg)
+    }
+
+    /// Append a packet carrying `samples` samples per channel
+    pub fn write_packet(&mut self, packet: &[u8], samples: usize) -> Result<()> {
+        self.granule += samples as u64 * self.granule_scale;
+        if let Some((previous, granule)) = self.held.replace((packet.to_vec(), self.granule)) {
+            self.write_page(0, granule, &previous)?;
+        }
+        Ok(())
+    }
+
+    /// 48 kHz samples written so far
+    pub fn granule(&self) -> u64 {
+        self.granule
+    }
+
+    /// End the stream and return the writer
+    pub fn finish(mut self) -> Result<W> {
+        let (packet, granule) = self.held.take().unwrap_or((Vec::new(), self.granule));
+        self.write_page(Self::END_OF_STREAM, granule, &packet)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_page(&mut self, flags: u8, granule: u64, packet: &[u8]) -> Result<()> {
+        // Lacing: 255-byte segments, then the remainder (0 if none)
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+        if lacing.len() > 255 {
+            bail!(
+                "Ogg packet of {} bytes needs more than one page",
+                packet.len()
+            );
+        }
+
+        let mut page = Vec::with_capacity(27 + lacing.len() + packet.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0); // version
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0u8; 4]); // checksum, filled in below
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(packet);
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        self.writer.write_all(&page)?;
+        self.sequence += 1;
+        Ok(())
+    }
+}
+
+/// One output file
+struct Track {
+    path: PathBuf,
+    encoder: AudioEncoder,
+    writer: OggOpusWriter<BufWriter<File>>,
+    participants: BTreeSet<u32>,
+}
+
+impl Track {
+    fn create(path: PathBuf, audio: &AudioConfig, comments: &[String]) -> Result<Self> {
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create recording {:?}", path))?;
+        let writer = OggOpusWriter::new(BufWriter::new(file), audio, rand::random(), comments)?;
+        debug!("Recording to {:?}", path);
+        Ok(Self {
+            path,
+            encoder: AudioEncoder::new(audio.clone())?,
+            writer,
+            participants: BTreeSet::new(),
+        })
+    }
+}
+
+/// Captures a session's audio from consenting participants
+pub struct SessionRecorder {
+    session_id: String,
+    config: RecordingConfig,
+    audio: AudioConfig,
+    consent: BTreeSet<u32>,
+    /// Mixed mode: the single track is keyed `None`
+    tracks: BTreeMap<Option<u32>, Track>,
+    /// Mixed mode: frames waiting for the other participants' frames
+    pending: BTreeMap<u32, VecDeque<Vec<i16>>>,
+    dropped_frames: u64,
+}
+
+impl SessionRecorder {
+    /// Start recording `session_id`, encoded as `audio`
+    ///
+    /// Participants' PCM frames must match `audio`'s frame size and
+    /// channels. Nobody is recorded until they consent.
+    pub fn new(session_id: &str, config: RecordingConfig, audio: AudioConfig) -> Result<Self> {
+        if session_id.is_empty()
+            || !session_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("Invalid session id {:?} for a recording", session_id);
+        }
+        std::fs::create_dir_all(&config.output_dir).with_context(|| {
+            format!(
+                "Failed to create recording directory {:?}",
+                config.output_dir
+            )
+        })?;
+        info!("🎙️  Recording session {} ({:?})", session_id, config.mode);
+        Ok(Self {
+            session_id: session_id.to_string(),
+            config,
+            audio,
+            consent: BTreeSet::new(),
+            tracks: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            dropped_frames: 0,
+        })
+    }
+
+    pub fn mode(&self) -> RecordingMode {
+        self.config.mode
+    }
+
+    pub fn config(&self) -> &RecordingConfig {
+        &self.config
+    }
+
+    /// Record `participant` from now on
+    pub fn grant_consent(&mut self, participant: u32) {
+        self.consent.insert(participant);
+    }
+
+    /// Stop recording `participant`; audio not yet mixed is discarded
+    pub fn revoke_consent(&mut self, participant: u32) {
+        self.consent.remove(&participant);
+        self.pending.remove(&participant);
+    }
+
+    pub fn has_consent(&self, participant: u32) -> bool {
+        self.consent.contains(&participant)
+    }
+
+    /// Frames dropped because their participant had not consented
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// Add one frame of `participant`'s decoded audio
+    pub fn record(&mut self, participant: u32, pcm: &[i16]) -> Result<()> {
+        let frame_len = self.audio.frame_size() * self.audio.channels as usize;
+        if pcm.len() != frame_len {
+            bail!(
+                "Recording frame has {} samples, expected {}",
+                pcm.len(),
+                frame_len
+            );
+        }
+        if !self.consent.contains(&participant) {
+            self.dropped_frames += 1;
+            return Ok(());
+        }
+        match self.config.mode {
+            RecordingMode::PerParticipant => self.write(Some(participant), &[participant], pcm),
+            RecordingMode::Mixed => {
+                self.pending
+                    .entry(participant)
+                    .or_default()
+                    .push_back(pcm.to_vec());
+                self.mix(false)
+            }
+        }
+    }
+
+    /// Close every track, returning the files that hold audio
+    pub fn finish(mut self) -> Result<Vec<Recording>> {
+        self.mix(true)?;
+        let mut recordings = Vec::new();
+        for (participant, track) in std::mem::take(&mut self.tracks) {
+            let samples = track.writer.granule();
+            track.writer.finish()?.into_inner()?.sync_all()?;
+            if track.participants.is_empty() {
+                std::fs::remove_file(&track.path)?;
+                continue;
+            }
+            recordings.push(Recording {
+                path: track.path,
+                participant,
+                participants: track.participants.into_iter().collect(),
+                duration_ms: samples * 1000 / GRANULE_RATE,
+            });
+        }
+        info!(
+            "🎙️  Recording of session {} finished: {} file(s)",
+            self.session_id,
+            recordings.len()
+        );
+        Ok(recordings)
+    }
+
+    /// Mix every complete set of pending frames; with `flush`, mix what is
+    /// left too, filling in silence for participants who sent nothing
+    fn mix(&mut self, flush: bool) -> Result<()> {
+        loop {
+            // Consenting participants who have not sent yet are waited for too
+            let waiting: Vec<usize> = self
+                .consent
+                .iter()
+                .map(|p| self.pending.get(p).map_or(0, VecDeque::len))
+                .collect();
+            let any = waiting.iter().any(|len| *len > 0);
+            let ready = waiting.iter().all(|len| *len > 0)
+                || waiting.iter().any(|len| *len > MAX_MIX_LAG_FRAMES)
+                || flush;
+            if !any || !ready {
+                return Ok(());
+            }
+
+            let frame_len = self.audio.frame_size() * self.audio.channels as usize;
+            let mut mixed = vec![0i32; frame_len];
+            let mut heard = Vec::new();
+            for (participant, frames) in &mut self.pending {
+                if let Some(frame) = frames.pop_front() {
+                    for (sum, sample) in mixed.iter_mut().zip(frame) {
+                        *sum += sample as i32;
+                    }
+                    heard.push(*participant);
+                }
+            }
+            let mixed: Vec<i16> = mixed
+                .into_iter()
+                .map(|sum| sum.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+                .collect();
+            self.write(None, &heard, &mixed)?;
+        }
+    }
+
+    fn write(&mut self, key: Option<u32>, heard: &[u32], pcm: &[i16]) -> Result<()> {
+        let track = match self.tracks.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let name = match key {
+                    Some(participant) => format!("{}-{}", self.session_id, participant),
+                    None => format!("{}-mixed", self.session_id),
+                };
+                let path = self
+                    .config
+                    .output_dir
+                    .join(Path::new(&name).with_extension(RECORDING_EXTENSION));
+                let comments = vec![
+                    format!("PANGEA_SESSION={}", self.session_id),
+                    "PANGEA_CONSENT=explicit".to_string(),
+                ];
+                entry.insert(Track::create(path, &self.audio, &comments)?)
+            }
+        };
+        let packet = track.encoder.encode(pcm)?;
+        track
+            .writer
+            .write_packet(&packet, self.audio.frame_size())?;
+        track.participants.extend(heard);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(audio: &AudioConfig, value: i16) -> Vec<i16> {
+        vec![value; audio.frame_size() * audio.channels as usize]
+    }
+
+    /// (flags, granule, sequence) of each page, checking every CRC
+    fn pages(bytes: &[u8]) -> Vec<(u8, u64, u32)> {
+        let mut pages = Vec::new();
+        let mut at = 0;
+        while at < bytes.len() {
+            assert_eq!(&bytes[at..at + 4], b"OggS");
+            let segments = bytes[at + 26] as usize;
+            let body: usize = bytes[at + 27..at + 27 + segments]
+                .iter()
+                .map(|len| *len as usize)
+                .sum();
+            let end = at + 27 + segments + body;
+            let mut page = bytes[at..end].to_vec();
+            let crc = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].copy_from_slice(&[0; 4]);
+            assert_eq!(ogg_crc(&page), crc);
+            pages.push((
+                bytes[at + 5],
+                u64::from_le_bytes(bytes[at + 6..at + 14].try_into().unwrap()),
+                u32::from_le_bytes(bytes[at + 18..at + 22].try_into().unwrap()),
+            ));
+            at = end;
+        }
+        pages
+    }
+
+    #[test]
+    fn test_ogg_opus_pages() -> Result<()> {
+        let audio = AudioConfig::low_latency();
+        let mut encoder = AudioEncoder::new(audio.clone())?;
+        let mut ogg = OggOpusWriter::new(Vec::new(), &audio, 7, &["TITLE=test".into()])?;
+        for _ in 0..3 {
+            let packet = encoder.encode(&frame(&audio, 100))?;
+            ogg.write_packet(&packet, audio.frame_size())?;
+        }
+        let bytes = ogg.finish()?;
+        assert_eq!(&bytes[28..36], b"OpusHead");
+
+        let pages = pages(&bytes);
+        assert_eq!(pages.len(), 5);
+        assert_eq!(pages[0], (0x02, 0, 0));
+        assert_eq!(pages[1].1, 0);
+        // 10 ms frames are 480 samples at 48 kHz
+        assert_eq!(pages[2].1, 480);
+        assert_eq!(pages[4], (0x04, 1440, 4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_consenting_participants_are_recorded() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let audio = AudioConfig::low_latency();
+        let config = RecordingConfig::new(RecordingMode::PerParticipant, dir.path());
+        let mut recorder = SessionRecorder::new("call-1", config, audio.clone())?;
+
+        recorder.grant_consent(1);
+        for _ in 0..5 {
+            recorder.record(1, &frame(&audio, 500))?;
+            recorder.record(2, &frame(&audio, 500))?;
+        }
+        assert_eq!(recorder.dropped_frames(), 5);
+        assert!(recorder.record(1, &[0; 3]).is_err());
+
+        let recordings = recorder.finish()?;
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].participant, Some(1));
+        assert_eq!(recordings[0].duration_ms, 50);
+        assert_eq!(recordings[0].path, dir.path().join("call-1-1.opus"));
+        assert!(!dir.path().join("call-1-2.opus").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_recording_waits_for_participants() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let audio = AudioConfig::low_latency();
+        let config = RecordingConfig::new(RecordingMode::Mixed, dir.path());
+        let mut recorder = SessionRecorder::new("call-2", config, audio.clone())?;
+        recorder.grant_consent(1);
+        recorder.grant_consent(2);
+
+        // Participant 2 lags by two frames; the mix holds them back
+        for _ in 0..4 {
+            recorder.record(1, &frame(&audio, 100))?;
+        }
+        recorder.record(2, &frame(&audio, 100))?;
+        recorder.record(2, &frame(&audio, 100))?;
+        assert_eq!(recorder.tracks[&None].writer.granule(), 2 * 480);
+
+        // Withdrawn consent drops what was waiting; the rest is flushed
+        recorder.revoke_consent(2);
+        recorder.record(2, &frame(&audio, 100))?;
+        let recordings = recorder.finish()?;
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].participant, None);
+        assert_eq!(recordings[0].participants, vec![1, 2]);
+        assert_eq!(recordings[0].duration_ms, 40);
+
+        assert!(SessionRecorder::new(
+            "../escape",
+            RecordingConfig::new(RecordingMode::Mixed, dir.path()),
+            audio
+        )
+        .is_err());
+        Ok(())
+    }
+}
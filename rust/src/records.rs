@@ -0,0 +1,475 @@
+/// Validation of DHT records
+///
+/// Kademlia stores whatever a peer puts under a key and hands back whatever
+/// it stored, so a record read from the DHT is no more trustworthy than the
+/// least honest peer on the path. Every record is checked on the way out,
+/// before it is stored for another peer, and before a lookup acts on it:
+///
+/// - size: keys and values are capped (`[dht_records]`)
+/// - schema: the key says what the record is (`pangea/shard-filter/<id>`
+///   for shard filters, `kw/<keyword>` for keyword records, anything else a
+///   file hash naming a manifest) and the value must decode as that kind and
+///   agree with its key
+/// - signature: values are wrapped in a [`SignedRecord`], signed by the
+///   publisher's node identity over key and value. A shard filter for a
+///   node ID bound in the [`PeerDirectory`] must be signed by that identity;
+///   keyword entries carry their own signatures and every one must verify.
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::Options;
+use ed25519_dalek::{Signature, VerifyingKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bloom::{self, ShardAdvertisement};
+use crate::cache::{is_safe_file_hash, FileManifest};
+use crate::identity::{peer_id_of, PeerDirectory};
+use crate::keywords::{KeywordRecord, KEYWORD_KEY_PREFIX};
+
+/// Marks a value as a [`SignedRecord`]
+const SIGNED_RECORD_MAGIC: &[u8; 4] = b"PSR1";
+
+/// Domain separation for record signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-dht-record-v1";
+
+/// Room a [`SignedRecord`] needs beyond its value
+const ENVELOPE_OVERHEAD: u64 = 1024;
+
+/// DHT record checks (`[dht_records]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordConfig {
+    /// Longest record key accepted
+    pub max_key_bytes: usize,
+    /// Largest record value accepted, before signing
+    pub max_value_bytes: usize,
+    /// Reject values that are not signed; off only to read records
+    /// published by nodes that predate signing
+    pub require_signatures: bool,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        Self {
+            max_key_bytes: 256,
+            max_value_bytes: 65536,
+            require_signatures: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    dht_records: RecordConfig,
+}
+
+impl RecordConfig {
+    /// Load the `[dht_records]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[dht_records]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.dht_records.validate()?;
+        Ok(file.dht_records)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_key_bytes == 0 {
+            bail!("dht_records: max_key_bytes must be at least 1");
+        }
+        if self.max_value_bytes == 0 {
+            bail!("dht_records: max_value_bytes must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// What a DHT record holds, as told by its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    /// A file manifest, keyed by file hash
+    Manifest,
+    /// A node's shard filter advertisement
+    ShardFilter,
+    /// Files listed under a filename keyword
+    Keyword,
+}
+
+impl RecordKind {
+    pub fn of_key(key: &[u8]) -> Self {
+        if key.starts_with(bloom::DHT_KEY_PREFIX.as_bytes()) {
+            Self::ShardFilter
+        } else if key.starts_with(KEYWORD_KEY_PREFIX) {
+            Self::Keyword
+        } else {
+            Self::Manifest
+        }
+    }
+}
+
+/// A record value and its publisher's signature over key and value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRecord {
+    pub value: Vec<u8>,
+    /// Publisher's Ed25519 public key
+    pub publisher: [u8; 32],
+    pub signature: Vec<u8>,
+}
+
+impl SignedRecord {
+    /// Sign `value` under `key` with a node's DHT keypair
+    pub fn sign(keypair: &libp2p::identity::Keypair, key: &[u8], value: Vec<u8>) -> Result<Self> {
+        let publisher = keypair
+            .public()
+            .try_into_ed25519()
+            .map_err(|_| anyhow!("DHT records can only be signed with Ed25519 keys"))?
+            .to_bytes();
+        let signature = keypair
+            .sign(&signed_bytes(key, &value))
+            .context("Failed to sign DHT record")?;
+        Ok(Self {
+            value,
+            publisher,
+            signature,
+        })
+    }
+
+    /// Check the signature; returns the publisher's identity
+    pub fn verify(&self, key: &[u8]) -> Result<PeerId> {
+        let public = VerifyingKey::from_bytes(&self.publisher)
+            .map_err(|e| anyhow!("Invalid publisher key: {}", e))?;
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        public
+            .verify_strict(&signed_bytes(key, &self.value), &signature)
+            .map_err(|_| anyhow!("Bad DHT record signature"))?;
+        peer_id_of(&self.publisher)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = SIGNED_RECORD_MAGIC.to_vec();
+        codec(self.value.len() as u64)
+            .serialize_into(&mut bytes, self)
+            .context("Failed to serialize signed record")?;
+        Ok(bytes)
+    }
+
+    /// `None` if `bytes` is not a signed record at all
+    pub fn from_bytes(bytes: &[u8], max_value_bytes: usize) -> Option<Result<Self>> {
+        let body = bytes.strip_prefix(SIGNED_RECORD_MAGIC)?;
+        Some(
+            codec(max_value_bytes as u64)
+                .deserialize(body)
+                .context("Malformed signed record"),
+        )
+    }
+}
+
+fn codec(max_value_bytes: u64) -> impl Options {
+    bincode::DefaultOptions::new().with_limit(max_value_bytes + ENVELOPE_OVERHEAD)
+}
+
+fn signed_bytes(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut bytes = SIGNING_CONTEXT.to_vec();
+    for field in [key, value] {
+        bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(field);
+    }
+    bytes
+}
+
+/// A record that passed validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidRecord {
+    pub kind: RecordKind,
+    /// The value as published, without its signature
+    pub value: Vec<u8>,
+    /// Who signed it; `None` for unsigned records when those are allowed
+    pub publisher: Option<PeerId>,
+}
+
+/// Checks records published to and read from the DHT
+#[derive(Default)]
+pub struct RecordValidator {
+    config: RecordConfig,
+    directory: Option<Arc<PeerDirectory>>,
+}
+
+impl RecordValidator {
+    pub fn new(config: RecordConfig) -> Self {
+        Self {
+            config,
+            directory: None,
+        }
+    }
+
+    /// Require shard filters of bound node IDs to be signed by their identity
+    pub fn with_peer_directory(mut self, directory: Arc<PeerDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    pub fn config(&self) -> &RecordConfig {
+        &self.config
+    }
+
+    /// Check a value this node is about to publish and sign it
+    pub fn seal(
+        &self,
+        keypair: &libp2p::identity::Keypair,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let record = SignedRecord::sign(keypair, key, value)?;
+        let publisher = peer_id_of(&record.publisher)?;
+        self.check(key, &record.value, Some(&publisher))?;
+        record.to_bytes()
+    }
+
+    /// Check a record read from or put to the DHT by another peer
+    pub fn open(&self, key: &[u8], bytes: &[u8]) -> Result<ValidRecord> {
+        let (value, publisher) = match SignedRecord::from_bytes(bytes, self.config.max_value_bytes)
+        {
+            Some(record) => {
+                let record = record?;
+                let publisher = record.verify(key)?;
+                (record.value, Some(publisher))
+            }
+            None if self.config.require_signatures => bail!("DHT record is not signed"),
+            None => (bytes.to_vec(), None),
+        };
+        let kind = self.check(key, &value, publisher.as_ref())?;
+        Ok(ValidRecord {
+            kind,
+            value,
+            publisher,
+        })
+    }
+
+    /// Size and schema checks shared by both directions
+    fn check(&self, key: &[u8], value: &[u8], publisher: Option<&PeerId>) -> Result<RecordKind> {
+        if key.is_empty() || key.len() > self.config.max_key_bytes {
+            bail!(
+                "DHT record key is {} bytes; limit is {}",
+                key.len(),
+                self.config.max_key_bytes
+            );
+        }
+        if value.len() > self.config.max_value_bytes {
+            bail!(
+                "DHT record value is {} bytes; limit is {}",
+                value.len(),
+                self.config.max_value_bytes
+            );
+        }
+        let kind = RecordKind::of_key(key);
+        match kind {
+            RecordKind::Manifest => {
+                let file_hash = std::str::from_utf8(key)
+                    .ok()
+                    .filter(|hash| is_safe_file_hash(hash))
+                    .context("DHT record key is not a file hash")?;
+                let json = std::str::from_utf8(value).context("Manifest record is not UTF-8")?;
+                let manifest = FileManifest::from_json(json)?;
+                if manifest.file_hash != file_hash {
+                    bail!(
+                        "Manifest for {} stored under {}",
+                        manifest.file_hash,
+                        file_hash
+                    );
+                }
+            }
+            RecordKind::ShardFilter => {
+                let peer_id: u32 = std::str::from_utf8(&key[bloom::DHT_KEY_PREFIX.len()..])
+                    .ok()
+                    .and_then(|id| id.parse().ok())
+                    .context("Shard filter key does not name a node ID")?;
+                // Decoding also checks the filter is well formed, so a
+                // malformed one is never stored or served to other peers
+                let advert = ShardAdvertisement::from_bytes(value)?;
+                if advert.peer_id != peer_id {
+                    bail!(
+                        "Shard filter of node {} stored under node {}",
+                        advert.peer_id,
+                        peer_id
+                    );
+                }
+                let bound = self
+                    .directory
+                    .as_ref()
+                    .and_then(|directory| directory.identity_of(peer_id));
+                if let (Some(bound), Some(publisher)) = (bound, publisher) {
+                    if bound != *publisher {
+                        bail!(
+                            "Shard filter of node {} signed by {}, not its identity {}",
+                            peer_id,
+                            publisher,
+                            bound
+                        );
+                    }
+                }
+            }
+            RecordKind::Keyword => {
+                let keyword = std::str::from_utf8(&key[KEYWORD_KEY_PREFIX.len()..])
+                    .context("Keyword record key is not UTF-8")?;
+                let record = KeywordRecord::from_bytes(value)?;
+                if record.keyword != keyword {
+                    bail!(
+                        "Record for keyword {:?} stored under {:?}",
+                        record.keyword,
+                        keyword
+                    );
+                }
+                for entry in &record.entries {
+                    entry.verify(keyword)?;
+                }
+            }
+        }
+        Ok(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bloom::ShardBloomFilter;
+    use crate::identity::NodeIdentity;
+
+    fn manifest_json(file_hash: &str) -> Vec<u8> {
+        let manifest = FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: "notes.txt".to_string(),
+            file_size: 100,
+            shard_count: 3,
+            parity_count: 1,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3)],
+            namespace: crate::namespace::DEFAULT_NAMESPACE.to_string(),
+            ..Default::default()
+        };
+        serde_json::to_vec(&manifest).unwrap()
+    }
+
+    #[test]
+    fn test_records_round_trip_and_reject_tampering() {
+        let identity = NodeIdentity::generate();
+        let keypair = identity.libp2p_keypair();
+        let validator = RecordValidator::default();
+
+        let sealed = validator
+            .seal(&keypair, b"abc123", manifest_json("abc123"))
+            .unwrap();
+        let record = validator.open(b"abc123", &sealed).unwrap();
+        assert_eq!(record.kind, RecordKind::Manifest);
+        assert_eq!(record.value, manifest_json("abc123"));
+        assert_eq!(record.publisher, Some(identity.peer_id()));
+
+        // Moved to another key, the signature no longer holds
+        assert!(validator.open(b"def456", &sealed).is_err());
+        let mut tampered = SignedRecord::from_bytes(&sealed, 65536).unwrap().unwrap();
+        tampered.value = manifest_json("abc1234");
+        assert!(validator
+            .open(b"abc123", &tampered.to_bytes().unwrap())
+            .is_err());
+
+        // Unsigned records only pass when allowed, and still need a schema
+        assert!(validator.open(b"abc123", &manifest_json("abc123")).is_err());
+        let lenient = RecordValidator::new(RecordConfig {
+            require_signatures: false,
+            ..RecordConfig::default()
+        });
+        assert_eq!(
+            lenient
+                .open(b"abc123", &manifest_json("abc123"))
+                .unwrap()
+                .publisher,
+            None
+        );
+        assert!(lenient.open(b"abc123", b"not a manifest").is_err());
+        assert!(lenient.open(b"abc123", &manifest_json("other")).is_err());
+    }
+
+    #[test]
+    fn test_sizes_and_schemas_are_checked() {
+        let keypair = NodeIdentity::generate().libp2p_keypair();
+        let validator = RecordValidator::new(RecordConfig {
+            max_value_bytes: 4096,
+            ..RecordConfig::default()
+        });
+        assert!(validator.seal(&keypair, b"abc", vec![b' '; 5000]).is_err());
+        assert!(validator.seal(&keypair, &[b'a'; 300], Vec::new()).is_err());
+        assert!(validator
+            .seal(&keypair, b"../etc", manifest_json("../etc"))
+            .is_err());
+
+        let advert = ShardAdvertisement::new(7, ShardBloomFilter::new(16, 0.01));
+        let key = ShardAdvertisement::dht_key(7);
+        assert!(validator
+            .seal(&keypair, &key, advert.to_bytes().unwrap())
+            .is_ok());
+        assert!(validator
+            .seal(
+                &keypair,
+                &ShardAdvertisement::dht_key(8),
+                advert.to_bytes().unwrap()
+            )
+            .is_err());
+
+        let record = KeywordRecord {
+            keyword: "holiday".to_string(),
+            entries: Vec::new(),
+        };
+        assert!(validator
+            .seal(&keypair, b"kw/holiday", record.to_bytes().unwrap())
+            .is_ok());
+        assert!(validator
+            .seal(&keypair, b"kw/beach", record.to_bytes().unwrap())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shard_filters_must_come_from_the_bound_identity() {
+        let owner = NodeIdentity::generate();
+        let impostor = NodeIdentity::generate();
+        let directory = Arc::new(PeerDirectory::new());
+        directory.bind(7, owner.peer_id()).await.unwrap();
+        let validator = RecordValidator::default().with_peer_directory(directory);
+
+        let key = ShardAdvertisement::dht_key(7);
+        let value = ShardAdvertisement::new(7, ShardBloomFilter::new(16, 0.01))
+            .to_bytes()
+            .unwrap();
+        let forged = SignedRecord::sign(&impostor.libp2p_keypair(), &key, value.clone())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(validator.open(&key, &forged).is_err());
+        let genuine = validator
+            .seal(&owner.libp2p_keypair(), &key, value)
+            .unwrap();
+        assert_eq!(
+            validator.open(&key, &genuine).unwrap().kind,
+            RecordKind::ShardFilter
+        );
+
+        // A correctly signed filter must still be well formed
+        let malformed =
+            ShardAdvertisement::new(7, ShardBloomFilter::from_raw_parts(vec![0; 1], 4096, 3))
+                .to_bytes()
+                .unwrap();
+        let signed = SignedRecord::sign(&owner.libp2p_keypair(), &key, malformed.clone())
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(validator.open(&key, &signed).is_err());
+        assert!(validator
+            .seal(&owner.libp2p_keypair(), &key, malformed)
+            .is_err());
+    }
+}
@@ -0,0 +1,132 @@
+/// Reference counts between manifests and the shards they use
+///
+/// Several manifests can point at the same shard content (identical files,
+/// or shared chunks once deduplication is on). The index records which
+/// manifests reference each shard so deleting one manifest only releases
+/// shards nobody else still needs. It is rebuilt from the manifests on
+/// startup, so it is never persisted separately.
+use std::collections::{HashMap, HashSet};
+
+/// Shard reference -> manifests (file hashes) referencing it
+#[derive(Debug, Default, Clone)]
+pub struct RefIndex {
+    referrers: HashMap<String, HashSet<String>>,
+    /// Manifest -> shard references it holds, for removal
+    holdings: HashMap<String, HashSet<String>>,
+}
+
+impl RefIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the shards a manifest references
+    ///
+    /// Re-adding a manifest replaces its previous references and returns the
+    /// shards that lost their last reference as a result.
+    pub fn add(
+        &mut self,
+        manifest: &str,
+        shard_refs: impl IntoIterator<Item = String>,
+    ) -> Vec<String> {
+        let released = self.remove(manifest);
+        let refs: HashSet<String> = shard_refs.into_iter().collect();
+        for shard in &refs {
+            self.referrers
+                .entry(shard.clone())
+                .or_default()
+                .insert(manifest.to_string());
+        }
+        let still_released = released
+            .into_iter()
+            .filter(|shard| !refs.contains(shard))
+            .collect();
+        if !refs.is_empty() {
+            self.holdings.insert(manifest.to_string(), refs);
+        }
+        still_released
+    }
+
+    /// Drop a manifest's references, returning shards that are now unreferenced
+    pub fn remove(&mut self, manifest: &str) -> Vec<String> {
+        let Some(refs) = self.holdings.remove(manifest) else {
+            return Vec::new();
+        };
+        let mut released = Vec::new();
+        for shard in refs {
+            if let Some(holders) = self.referrers.get_mut(&shard) {
+                holders.remove(manifest);
+                if holders.is_empty() {
+                    self.referrers.remove(&shard);
+                    released.push(shard);
+                }
+            }
+        }
+        released.sort();
+        released
+    }
+
+    /// Number of manifests referencing a shard
+    pub fn count(&self, shard: &str) -> usize {
+        self.referrers.get(shard).map_or(0, |holders| holders.len())
+    }
+
+    pub fn is_referenced(&self, shard: &str) -> bool {
+        self.count(shard) > 0
+    }
+
+    /// Manifests referencing a shard
+    pub fn referrers(&self, shard: &str) -> Vec<String> {
+        let mut holders: Vec<String> = self
+            .referrers
+            .get(shard)
+            .map(|holders| holders.iter().cloned().collect())
+            .unwrap_or_default();
+        holders.sort();
+        holders
+    }
+
+    /// Number of distinct referenced shards
+    pub fn len(&self) -> usize {
+        self.referrers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.referrers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn test_shared_shards_survive_until_last_reference() {
+        let mut index = RefIndex::new();
+        index.add("file-a", refs(&["s1", "s2"]));
+        index.add("file-b", refs(&["s2", "s3"]));
+        assert_eq!(index.count("s2"), 2);
+        assert_eq!(index.referrers("s2"), refs(&["file-a", "file-b"]));
+
+        assert_eq!(index.remove("file-a"), refs(&["s1"]));
+        assert!(index.is_referenced("s2"));
+
+        assert_eq!(index.remove("file-b"), refs(&["s2", "s3"]));
+        assert!(index.is_empty());
+        assert!(index.remove("file-b").is_empty());
+    }
+
+    #[test]
+    fn test_readd_replaces_references() {
+        let mut index = RefIndex::new();
+        index.add("file-a", refs(&["s1", "s2"]));
+        let released = index.add("file-a", refs(&["s2", "s3"]));
+        assert_eq!(released, refs(&["s1"]));
+        assert_eq!(index.count("s2"), 1);
+        assert_eq!(index.len(), 2);
+    }
+}
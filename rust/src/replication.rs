@@ -0,0 +1,854 @@
+/// Warm-standby manifest replication between trusted nodes
+///
+/// A file whose manifest is held by a single node becomes unreachable when
+/// that node dies, even though its shards survive on peers. Nodes listed in
+/// each other's `[replication]` section keep copies of each other's
+/// manifests, so a lookup succeeds on any replica:
+///
+/// - `Offer` hands a peer manifests it does not hold yet
+/// - `Sync` sends the hashes of every manifest the sender holds; the answer
+///   carries the manifests the sender lacks and names the ones the answering
+///   node lacks, which the sender then offers back (anti-entropy)
+///
+/// A node syncs with a trusted peer whenever the peer (re)connects, and with
+/// every connected trusted peer each `sync_interval_secs`, so manifests
+/// written while the two were apart are exchanged on reconnect.
+///
+/// Every message is signed with the sender's Ed25519 key and carries its send
+/// time. A node only answers and only stores what is signed by a key listed
+/// for the claimed sender, within `MAX_CLOCK_SKEW_SECS` of its own clock
+/// (corrected by the sender's measured clock offset, when known).
+/// Manifests already held are never overwritten by a replica's copy.
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use bincode::Options;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::cache::{Cache, FileManifest};
+use crate::clock::PeerClocks;
+use crate::negotiation::{Negotiated, PeerCapabilities};
+use crate::network::{QuicNode, RequestHandler, RequestTag, MAX_REQUEST_BYTES};
+use crate::types::current_timestamp;
+
+/// Tags replication requests so they are not confused with other stream traffic
+pub const REPLICATION_MAGIC: RequestTag = *b"PREP";
+
+/// Default file name of the signing key inside the cache directory
+pub const REPLICATION_KEY_FILE: &str = "replication-key.json";
+
+/// Largest difference between a message's send time and the local clock
+pub const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+/// Manifest bytes sent in one message; the rest follow in later rounds
+const MAX_BATCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// How often connected peers are checked for reconnects
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Domain separator for replication signatures
+const SIGNING_CONTEXT: &[u8] = b"pangea-manifest-replication-v1";
+
+/// A peer whose manifests are accepted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedPeer {
+    pub node_id: u32,
+    /// Ed25519 public key (hex), printed by the peer on start
+    pub public_key: String,
+}
+
+/// Replication settings (`[replication]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplicationConfig {
+    pub enabled: bool,
+    /// Signing key (defaults to `replication-key.json` in the cache directory)
+    pub key_file: Option<PathBuf>,
+    /// Seconds between syncs with every connected trusted peer
+    pub sync_interval_secs: u64,
+    /// Peers manifests are exchanged with
+    pub peers: Vec<TrustedPeer>,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key_file: None,
+            sync_interval_secs: 600,
+            peers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    replication: ReplicationConfig,
+}
+
+impl ReplicationConfig {
+    /// Load the `[replication]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[replication]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.replication.validate()?;
+        Ok(file.replication)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && self.sync_interval_secs == 0 {
+            bail!("replication: sync_interval_secs must be greater than 0");
+        }
+        self.trusted_keys().map(|_| ())
+    }
+
+    /// Key file to use when the config does not name one
+    pub fn key_file_or(&self, cache_dir: impl AsRef<Path>) -> PathBuf {
+        self.key_file
+            .clone()
+            .unwrap_or_else(|| cache_dir.as_ref().join(REPLICATION_KEY_FILE))
+    }
+
+    /// Parsed public key of each trusted peer
+    pub fn trusted_keys(&self) -> Result<HashMap<u32, VerifyingKey>> {
+        let mut keys = HashMap::new();
+        for peer in &self.peers {
+            let key = parse_verifying_key(&peer.public_key)
+                .with_context(|| format!("replication: bad key for peer {}", peer.node_id))?;
+            if keys.insert(peer.node_id, key).is_some() {
+                bail!("replication: peer {} is listed twice", peer.node_id);
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Parse a hex-encoded Ed25519 public key
+pub fn parse_verifying_key(hex_key: &str) -> Result<VerifyingKey> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(hex_key, &mut bytes).context("Invalid Ed25519 public key")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("Invalid Ed25519 public key: {}", e))
+}
+
+/// On-disk form of a signing key
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    node_id: u32,
+    secret: String,
+}
+
+/// A node's Ed25519 key for signing replication messages
+pub struct ReplicationKey {
+    node_id: u32,
+    signing: SigningKey,
+}
+
+impl ReplicationKey {
+    /// Generate a fresh key
+    pub fn generate(node_id: u32) -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self::from_secret(node_id, secret)
+    }
+
+    pub fn from_secret(node_id: u32, secret: [u8; 32]) -> Self {
+        Self {
+            node_id,
+            signing: SigningKey::from_bytes(&secret),
+        }
+    }
+
+    /// Load the key stored at `path`, creating it on first use
+    ///
+    /// Fails if the file belongs to a different node ID.
+    pub fn load_or_generate(node_id: u32, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            let key = Self::generate(node_id);
+            key.save(path)?;
+            return Ok(key);
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {:?}", path))?;
+        let file: KeyFile = serde_json::from_str(&content).context("Invalid key file")?;
+        if file.node_id != node_id {
+            bail!(
+                "Key file {:?} belongs to node {}, not node {}",
+                path,
+                file.node_id,
+                node_id
+            );
+        }
+        let mut secret = [0u8; 32];
+        hex::decode_to_slice(&file.secret, &mut secret).context("Invalid secret key")?;
+        Ok(Self::from_secret(node_id, secret))
+    }
+
+    /// Write the key to `path`, readable only by the owner
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(&KeyFile {
+            node_id: self.node_id,
+            secret: hex::encode(self.signing.to_bytes()),
+        })?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write key file {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing.verifying_key()
+    }
+
+    /// Public key to list in trusted peers' config
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.verifying_key().as_bytes())
+    }
+
+    /// Sign `message` with this node's key
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.signing.sign(message)
+    }
+}
+
+/// A request to a replica
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationRequest {
+    /// Manifests (JSON) for the replica to store if it lacks them
+    Offer { manifests: Vec<String> },
+    /// Hashes of every manifest the sender holds
+    Sync { have: Vec<String> },
+}
+
+/// A replica's answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationResponse {
+    /// How many offered manifests were new to the replica
+    Accepted {
+        stored: usize,
+    },
+    /// Manifests (JSON) the sender lacks, and hashes the replica lacks
+    Synced {
+        manifests: Vec<String>,
+        missing: Vec<String>,
+    },
+    Rejected(String),
+}
+
+/// A message signed by its sender
+///
+/// Manifests travel as JSON inside the payload because `FileManifest` skips
+/// empty fields, which bincode cannot round-trip.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub signer: u32,
+    /// Unix seconds when the message was signed
+    pub sent_at: u64,
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_REQUEST_BYTES as u64)
+}
+
+fn signed_bytes(signer: u32, sent_at: u64, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(SIGNING_CONTEXT.len() + 12 + payload.len());
+    bytes.extend_from_slice(SIGNING_CONTEXT);
+    bytes.extend_from_slice(&signer.to_be_bytes());
+    bytes.extend_from_slice(&sent_at.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+impl SignedMessage {
+    /// Serialize and sign `body` as sent now
+    pub fn seal<T: Serialize>(key: &ReplicationKey, body: &T) -> Result<Self> {
+        Self::seal_at(key, body, current_timestamp())
+    }
+
+    fn seal_at<T: Serialize>(key: &ReplicationKey, body: &T, sent_at: u64) -> Result<Self> {
+        let payload = codec()
+            .serialize(body)
+            .context("Failed to serialize replication message")?;
+        let signature = key
+            .signing
+            .sign(&signed_bytes(key.node_id, sent_at, &payload));
+        Ok(Self {
+            signer: key.node_id,
+            sent_at,
+            payload,
+            signature: signature.to_bytes().to_vec(),
+        })
+    }
+
+    /// Check the signature against the signer's trusted key and decode the body
+    pub fn open<T: DeserializeOwned>(
+        &self,
+        trusted: &HashMap<u32, VerifyingKey>,
+        now: u64,
+    ) -> Result<T> {
+        let key = trusted
+            .get(&self.signer)
+            .with_context(|| format!("Node {} is not a trusted replica", self.signer))?;
+        if self.sent_at.abs_diff(now) > MAX_CLOCK_SKEW_SECS {
+            bail!(
+                "Message from node {} was sent at {}, local time is {}",
+                self.signer,
+                self.sent_at,
+                now
+            );
+        }
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        key.verify_strict(
+            &signed_bytes(self.signer, self.sent_at, &self.payload),
+            &signature,
+        )
+        .map_err(|_| anyhow!("Bad signature from node {}", self.signer))?;
+        codec()
+            .deserialize(&self.payload)
+            .context("Failed to deserialize replication message")
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        codec()
+            .serialize(self)
+            .context("Failed to serialize signed message")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        codec()
+            .deserialize(bytes)
+            .context("Failed to deserialize signed message")
+    }
+}
+
+/// Manifests exchanged in one sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncOutcome {
+    /// New manifests stored locally
+    pub received: usize,
+    /// Manifests the peer stored from us
+    pub sent: usize,
+}
+
+/// Keeps manifests in step with trusted replicas
+pub struct Replicator {
+    key: ReplicationKey,
+    /// Trusted peers' keys (never our own)
+    trusted: HashMap<u32, VerifyingKey>,
+    cache: Arc<Cache>,
+    sync_interval: Duration,
+    clocks: Option<Arc<PeerClocks>>,
+    capabilities: Option<Arc<PeerCapabilities>>,
+}
+
+impl Replicator {
+    pub fn new(config: &ReplicationConfig, key: ReplicationKey, cache: Arc<Cache>) -> Result<Self> {
+        let mut trusted = config.trusted_keys()?;
+        trusted.remove(&key.node_id);
+        Ok(Self {
+            key,
+            trusted,
+            cache,
+            sync_interval: Duration::from_secs(config.sync_interval_secs.max(1)),
+            clocks: None,
+            capabilities: None,
+        })
+    }
+
+    /// Check message freshness against each signer's measured clock
+    pub fn with_peer_clocks(mut self, clocks: Arc<PeerClocks>) -> Self {
+        self.clocks = Some(clocks);
+        self
+    }
+
+    /// Only send peers manifest formats they were negotiated to read
+    pub fn with_peer_capabilities(mut self, capabilities: Arc<PeerCapabilities>) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Whether `peer_id` reads `manifest`; peers not negotiated with get
+    /// the baseline
+    fn peer_reads(&self, peer_id: u32, manifest: &FileManifest) -> bool {
+        let negotiated = match &self.capabilities {
+            Some(capabilities) => capabilities.get(peer_id),
+            None => Negotiated::baseline(),
+        };
+        negotiated.reads_manifest(manifest.format_version())
+    }
+
+    /// What `peer_id`'s clock reads now, as far as we know
+    fn peer_now(&self, peer_id: u32) -> u64 {
+        let now = current_timestamp();
+        self.clocks
+            .as_ref()
+            .map_or(now, |clocks| clocks.peer_now(peer_id, now))
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        self.key.public_key_hex()
+    }
+
+    pub fn is_trusted(&self, peer_id: u32) -> bool {
+        self.trusted.contains_key(&peer_id)
+    }
+
+    /// Trusted peers, in node ID order
+    pub fn trusted_peers(&self) -> Vec<u32> {
+        let mut peers: Vec<u32> = self.trusted.keys().copied().collect();
+        peers.sort_unstable();
+        peers
+    }
+
+    /// Store a replica's manifest unless one with its hash is already held
+    async fn accept(&self, json: &str) -> Result<bool> {
+        let manifest = FileManifest::from_json(json)?;
+        if self.cache.get_manifest(&manifest.file_hash).await.is_some() {
+            return Ok(false);
+        }
+        debug!("Storing replicated manifest {}", manifest.file_hash);
+        self.cache.put_manifest(manifest).await?;
+        Ok(true)
+    }
+
+    /// Store each new manifest, skipping (and logging) invalid ones
+    async fn accept_all(&self, from: u32, manifests: &[String]) -> usize {
+        let mut stored = 0;
+        for json in manifests {
+            match self.accept(json).await {
+                Ok(true) => stored += 1,
+                Ok(false) => {}
+                Err(e) => warn!("Ignored manifest from node {}: {:#}", from, e),
+            }
+        }
+        stored
+    }
+
+    /// JSON of the named manifests we hold that `peer_id` reads, up to
+    /// one batch
+    async fn manifest_batch(
+        &self,
+        peer_id: u32,
+        hashes: impl IntoIterator<Item = &String>,
+    ) -> Vec<String> {
+        let mut batch = Vec::new();
+        let mut bytes = 0;
+        for hash in hashes {
+            let Some(manifest) = self.cache.get_manifest(hash).await else {
+                continue;
+            };
+            if !self.peer_reads(peer_id, &manifest) {
+                continue;
+            }
+            let Ok(json) = serde_json::to_string(&manifest) else {
+                continue;
+            };
+            if bytes + json.len() > MAX_BATCH_BYTES && !batch.is_empty() {
+                break;
+            }
+            bytes += json.len();
+            batch.push(json);
+        }
+        batch
+    }
+
+    async fn local_hashes(&self) -> Vec<String> {
+        self.cache
+            .list_manifests()
+            .await
+            .into_iter()
+            .map(|m| m.file_hash)
+            .collect()
+    }
+
+    /// Answer a request whose signature has been checked
+    pub async fn serve(&self, from: u32, request: ReplicationRequest) -> ReplicationResponse {
+        match request {
+            ReplicationRequest::Offer { manifests } => ReplicationResponse::Accepted {
+                stored: self.accept_all(from, &manifests).await,
+            },
+            ReplicationRequest::Sync { have } => {
+                let have: HashSet<String> = have.into_iter().collect();
+                let local = self.local_hashes().await;
+                let wanted: Vec<&String> = local.iter().filter(|h| !have.contains(*h)).collect();
+                let local: HashSet<&String> = local.iter().collect();
+                ReplicationResponse::Synced {
+                    manifests: self.manifest_batch(from, wanted).await,
+                    missing: have
+                        .iter()
+                        .filter(|h| !local.contains(h))
+                        .cloned()
+                        .collect(),
+                }
+            }
+        }
+    }
+
+    /// Send a signed request and check the answer is signed by `peer_id`
+    pub async fn request(
+        &self,
+        network: &QuicNode,
+        peer_id: u32,
+        request: &ReplicationRequest,
+    ) -> Result<ReplicationResponse> {
+        if !self.is_trusted(peer_id) {
+            bail!("Node {} is not a trusted replica", peer_id);
+        }
+        let mut bytes = REPLICATION_MAGIC.to_vec();
+        bytes.extend_from_slice(&SignedMessage::seal(&self.key, request)?.to_bytes()?);
+        let response = network.request(peer_id, &bytes, MAX_REQUEST_BYTES).await?;
+        let response = SignedMessage::from_bytes(&response)?;
+        if response.signer != peer_id {
+            bail!(
+                "Answer from node {} is signed by node {}",
+                peer_id,
+                response.signer
+            );
+        }
+        response.open(&self.trusted, self.peer_now(peer_id))
+    }
+
+    /// Offer a newly stored manifest to every connected trusted peer
+    ///
+    /// Returns how many peers stored it.
+    pub async fn replicate(&self, network: &QuicNode, manifest: &FileManifest) -> Result<usize> {
+        let request = ReplicationRequest::Offer {
+            manifests: vec![serde_json::to_string(manifest)?],
+        };
+        let mut stored = 0;
+        for peer_id in network.get_connected_peers().await {
+            if !self.is_trusted(peer_id) || !self.peer_reads(peer_id, manifest) {
+                continue;
+            }
+            match self.request(network, peer_id, &request).await {
+                Ok(ReplicationResponse::Accepted { stored: 1 }) => stored += 1,
+                Ok(_) => {}
+                Err(e) => warn!("Failed to replicate manifest to node {}: {:#}", peer_id, e),
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Exchange the manifests each side is missing with one peer
+    pub async fn sync_with(&self, network: &QuicNode, peer_id: u32) -> Result<SyncOutcome> {
+        let have = self.local_hashes().await;
+        let (manifests, missing) = match self
+            .request(network, peer_id, &ReplicationRequest::Sync { have })
+            .await?
+        {
+            ReplicationResponse::Synced { manifests, missing } => (manifests, missing),
+            ReplicationResponse::Rejected(reason) => {
+                bail!("Node {} refused to sync: {}", peer_id, reason)
+            }
+            other => bail!("Unexpected sync answer from node {}: {:?}", peer_id, other),
+        };
+
+        let mut outcome = SyncOutcome {
+            received: self.accept_all(peer_id, &manifests).await,
+            sent: 0,
+        };
+        let offer = self.manifest_batch(peer_id, &missing).await;
+        if !offer.is_empty() {
+            let request = ReplicationRequest::Offer { manifests: offer };
+            match self.request(network, peer_id, &request).await? {
+                ReplicationResponse::Accepted { stored } => outcome.sent = stored,
+                other => bail!("Node {} did not take our manifests: {:?}", peer_id, other),
+            }
+        }
+        if outcome != SyncOutcome::default() {
+            info!(
+                "Synced manifests with node {}: {} received, {} sent",
+                peer_id, outcome.received, outcome.sent
+            );
+        }
+        Ok(outcome)
+    }
+
+    /// Sync with a newly connected peer without waiting for the result
+    pub fn sync_in_background(self: &Arc<Self>, network: Arc<QuicNode>, peer_id: u32) {
+        if !self.is_trusted(peer_id) {
+            return;
+        }
+        let replicator = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = replicator.sync_with(&network, peer_id).await {
+                warn!("Manifest sync with node {} failed: {:#}", peer_id, e);
+            }
+        });
+    }
+
+    /// Sync with trusted peers as they (re)connect and every `sync_interval`
+    ///
+    /// Full rounds first reload the manifest directory, picking up manifests
+    /// written by other processes sharing the cache (such as CLI uploads).
+    pub fn spawn(self: Arc<Self>, network: Arc<QuicNode>) -> JoinHandle<()> {
+        info!(
+            "Replicating manifests with {} trusted node(s) every {:?}",
+            self.trusted.len(),
+            self.sync_interval
+        );
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(RECONNECT_POLL_INTERVAL);
+            let mut last_round = tokio::time::Instant::now();
+            let mut connected: HashSet<u32> = HashSet::new();
+            loop {
+                poll.tick().await;
+                let now_connected: HashSet<u32> = network
+                    .get_connected_peers()
+                    .await
+                    .into_iter()
+                    .filter(|peer| self.is_trusted(*peer))
+                    .collect();
+
+                let full_round = last_round.elapsed() >= self.sync_interval;
+                if full_round {
+                    last_round = tokio::time::Instant::now();
+                    if let Err(e) = self.cache.load_persisted_manifests().await {
+                        warn!("Failed to reload manifests: {:#}", e);
+                    }
+                }
+                for &peer_id in &now_connected {
+                    if !full_round && connected.contains(&peer_id) {
+                        continue;
+                    }
+                    if let Err(e) = self.sync_with(&network, peer_id).await {
+                        warn!("Manifest sync with node {} failed: {:#}", peer_id, e);
+                    }
+                }
+                connected = now_connected;
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl RequestHandler for Replicator {
+    async fn handle(&self, remote: SocketAddr, request: Vec<u8>) -> Vec<u8> {
+        let opened = request
+            .strip_prefix(&REPLICATION_MAGIC)
+            .context("Not a replication request")
+            .and_then(SignedMessage::from_bytes)
+            .and_then(|message| {
+                let request = message.open(&self.trusted, self.peer_now(message.signer))?;
+                Ok((message.signer, request))
+            });
+        let response = match opened {
+            Ok((from, request)) => self.serve(from, request).await,
+            Err(e) => {
+                debug!("Refused replication request from {}: {:#}", remote, e);
+                ReplicationResponse::Rejected(format!("{:#}", e))
+            }
+        };
+        SignedMessage::seal(&self.key, &response)
+            .and_then(|message| message.to_bytes())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::negotiation::Capabilities;
+    use crate::placement::LocalParity;
+    use tempfile::tempdir;
+
+    fn manifest(file_hash: &str) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.txt", file_hash),
+            file_size: 1000,
+            shard_count: 3,
+            parity_count: 1,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3)],
+            timestamp: 1_700_000_000,
+            ..Default::default()
+        }
+    }
+
+    fn trusting(keys: &[&ReplicationKey]) -> HashMap<u32, VerifyingKey> {
+        keys.iter()
+            .map(|key| (key.node_id(), key.verifying_key()))
+            .collect()
+    }
+
+    #[test]
+    fn test_signed_message_checks_signer_and_time() {
+        let alice = ReplicationKey::generate(1);
+        let mallory = ReplicationKey::generate(2);
+        let trusted = trusting(&[&alice]);
+        let request = ReplicationRequest::Sync {
+            have: vec!["abc".to_string()],
+        };
+
+        let message = SignedMessage::seal_at(&alice, &request, 1_000).unwrap();
+        let opened: ReplicationRequest = message.open(&trusted, 1_100).unwrap();
+        assert_eq!(opened, request);
+
+        // Stale or future messages are refused
+        assert!(message
+            .open::<ReplicationRequest>(&trusted, 1_000 + MAX_CLOCK_SKEW_SECS + 1)
+            .is_err());
+
+        // A tampered payload no longer matches the signature
+        let mut tampered = message.clone();
+        tampered.payload.push(0);
+        assert!(tampered
+            .open::<ReplicationRequest>(&trusted, 1_000)
+            .is_err());
+
+        // Untrusted signers, and signers claiming someone else's ID, are refused
+        let untrusted = SignedMessage::seal_at(&mallory, &request, 1_000).unwrap();
+        assert!(untrusted
+            .open::<ReplicationRequest>(&trusted, 1_000)
+            .is_err());
+        let forged = SignedMessage {
+            signer: 1,
+            ..untrusted
+        };
+        assert!(forged.open::<ReplicationRequest>(&trusted, 1_000).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_exchanges_missing_manifests() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        cache.put_manifest(manifest("ours")).await.unwrap();
+        cache.put_manifest(manifest("shared")).await.unwrap();
+        let replicator = Replicator::new(
+            &ReplicationConfig::default(),
+            ReplicationKey::generate(1),
+            cache.clone(),
+        )
+        .unwrap();
+
+        let response = replicator
+            .serve(
+                2,
+                ReplicationRequest::Sync {
+                    have: vec!["shared".to_string(), "theirs".to_string()],
+                },
+            )
+            .await;
+        let ReplicationResponse::Synced { manifests, missing } = response else {
+            panic!("unexpected response: {:?}", response);
+        };
+        assert_eq!(missing, vec!["theirs".to_string()]);
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(
+            FileManifest::from_json(&manifests[0]).unwrap().file_hash,
+            "ours"
+        );
+
+        // Offered manifests are stored once; invalid ones are skipped
+        let offer = ReplicationRequest::Offer {
+            manifests: vec![
+                serde_json::to_string(&manifest("theirs")).unwrap(),
+                serde_json::to_string(&manifest("shared")).unwrap(),
+                "{}".to_string(),
+            ],
+        };
+        assert_eq!(
+            replicator.serve(2, offer).await,
+            ReplicationResponse::Accepted { stored: 1 }
+        );
+        assert!(cache.get_manifest("theirs").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sync_only_sends_formats_the_peer_reads() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 10 * 1024 * 1024).unwrap());
+        cache.put_manifest(manifest("single")).await.unwrap();
+        cache
+            .put_manifest(FileManifest {
+                local_parity: Some(LocalParity { groups: Vec::new() }),
+                ..manifest("two-tier")
+            })
+            .await
+            .unwrap();
+        let capabilities = Arc::new(PeerCapabilities::new(Capabilities::local(1)));
+        let replicator = Replicator::new(
+            &ReplicationConfig::default(),
+            ReplicationKey::generate(1),
+            cache.clone(),
+        )
+        .unwrap()
+        .with_peer_capabilities(capabilities.clone());
+        let sync = || ReplicationRequest::Sync { have: Vec::new() };
+
+        // Peers not negotiated with only read single-tier manifests
+        let ReplicationResponse::Synced { manifests, .. } = replicator.serve(2, sync()).await
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(
+            FileManifest::from_json(&manifests[0]).unwrap().file_hash,
+            "single"
+        );
+
+        let response = serde_json::to_vec(&Capabilities::local(2)).unwrap();
+        capabilities.record_response(2, &response).unwrap();
+        let ReplicationResponse::Synced { manifests, .. } = replicator.serve(2, sync()).await
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(manifests.len(), 2);
+    }
+
+    #[test]
+    fn test_config_parses_trusted_peers() {
+        let peer = ReplicationKey::generate(2);
+        let toml = format!(
+            "[replication]\nenabled = true\n\n[[replication.peers]]\nnode_id = 2\npublic_key = \"{}\"\n",
+            peer.public_key_hex()
+        );
+        let config = ReplicationConfig::from_toml(&toml).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.sync_interval_secs, 600);
+        assert_eq!(config.trusted_keys().unwrap()[&2], peer.verifying_key());
+
+        let duplicate = format!(
+            "{}\n[[replication.peers]]\nnode_id = 2\npublic_key = \"{}\"\n",
+            toml,
+            peer.public_key_hex()
+        );
+        assert!(ReplicationConfig::from_toml(&duplicate).is_err());
+        assert!(ReplicationConfig::from_toml(
+            "[[replication.peers]]\nnode_id = 3\npublic_key = \"zz\"\n"
+        )
+        .is_err());
+    }
+}
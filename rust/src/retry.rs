@@ -0,0 +1,284 @@
+/// Shared retry policy with exponential backoff and jitter
+///
+/// Operations are retried only when their error is classified as transient
+/// (network hiccups, timeouts, DHT churn). Permanent failures such as
+/// integrity or validation errors are returned immediately. An optional
+/// `RetryBudget` caps retries across all callers sharing it, so a degraded
+/// network does not turn into a retry storm.
+use parking_lot::Mutex;
+use rand::Rng;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::error::ErrorCode;
+
+/// Retry configuration
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first (1 = no retries)
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for a single delay
+    pub max_backoff: Duration,
+    /// Growth factor between consecutive delays
+    pub multiplier: f64,
+    /// Random spread applied to each delay (0.0 - 1.0)
+    pub jitter: f64,
+    /// Stop retrying once this much time has passed since the first attempt
+    pub max_elapsed: Option<Duration>,
+    /// Shared budget limiting retries across callers
+    pub budget: Option<Arc<RetryBudget>>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_elapsed: Some(Duration::from_secs(10)),
+            budget: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Default policy with the given attempt count
+    pub fn with_attempts(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Share a retry budget with other policies
+    pub fn with_budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Backoff before retry number `retry` (0-based), without jitter
+    pub fn base_delay(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.max(1.0).powi(retry.min(63) as i32);
+        let delay = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Backoff before retry number `retry` (0-based), with jitter applied
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self.base_delay(retry).as_secs_f64();
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return Duration::from_secs_f64(base);
+        }
+        let spread = rand::thread_rng().gen_range(-jitter..=jitter);
+        Duration::from_secs_f64((base * (1.0 + spread)).max(0.0))
+    }
+}
+
+/// Token bucket limiting the ratio of retries to requests
+///
+/// Every call deposits `retry_ratio` tokens (up to `max_tokens`) and every
+/// retry withdraws one, so sustained retries are held to roughly
+/// `retry_ratio` of the request rate.
+#[derive(Debug)]
+pub struct RetryBudget {
+    tokens: Mutex<f64>,
+    max_tokens: f64,
+    retry_ratio: f64,
+}
+
+impl RetryBudget {
+    pub fn new(max_tokens: f64, retry_ratio: f64) -> Self {
+        Self {
+            tokens: Mutex::new(max_tokens),
+            max_tokens,
+            retry_ratio,
+        }
+    }
+
+    /// Record a new call
+    pub fn deposit(&self) {
+        let mut tokens = self.tokens.lock();
+        *tokens = (*tokens + self.retry_ratio).min(self.max_tokens);
+    }
+
+    /// Take one retry token, returning false when the budget is exhausted
+    pub fn try_withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock();
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Tokens currently available
+    pub fn available(&self) -> f64 {
+        *self.tokens.lock()
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(10.0, 0.1)
+    }
+}
+
+/// Retry an operation whose error type carries a transient/permanent classification
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, operation: &str, op: F) -> Result<T, E>
+where
+    E: ErrorCode,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_if(policy, operation, |e: &E| e.is_transient(), op).await
+}
+
+/// Retry an operation, using `is_transient` to decide which errors are retried
+pub async fn retry_if<T, E, C, F, Fut>(
+    policy: &RetryPolicy,
+    operation: &str,
+    is_transient: C,
+    mut op: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    C: Fn(&E) -> bool,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let started = Instant::now();
+    if let Some(budget) = &policy.budget {
+        budget.deposit();
+    }
+
+    let mut attempt = 1;
+    loop {
+        let err = match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        if attempt >= policy.max_attempts || !is_transient(&err) {
+            return Err(err);
+        }
+
+        let delay = policy.backoff(attempt - 1);
+        if let Some(max_elapsed) = policy.max_elapsed {
+            if started.elapsed() + delay > max_elapsed {
+                debug!(
+                    "{}: retry deadline reached after {} attempts",
+                    operation, attempt
+                );
+                return Err(err);
+            }
+        }
+        if let Some(budget) = &policy.budget {
+            if !budget.try_withdraw() {
+                debug!("{}: retry budget exhausted", operation);
+                return Err(err);
+            }
+        }
+
+        debug!(
+            "{} failed (attempt {}/{}): {}; retrying in {:?}",
+            operation, attempt, policy.max_attempts, err, delay
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{LookupError, NetworkError};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(350));
+
+        let jittered = RetryPolicy::default().backoff(0);
+        assert!(jittered >= Duration::from_millis(80) && jittered <= Duration::from_millis(120));
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&fast_policy(3), "test", || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(NetworkError::PeerUnreachable(1))
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_errors_fail_fast() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry(&fast_policy(5), "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(LookupError::InvalidRecord("bad signature".into()))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_budget_limits_retries() {
+        let budget = Arc::new(RetryBudget::new(1.0, 0.0));
+        let policy = fast_policy(5).with_budget(budget.clone());
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry(&policy, "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(NetworkError::Timeout("shard fetch".into()))
+        })
+        .await;
+
+        assert!(result.is_err());
+        // One initial attempt plus the single retry the budget allows
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(budget.available() < 1.0);
+    }
+}
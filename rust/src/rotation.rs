@@ -0,0 +1,458 @@
+/// File key rotation with lazy shard re-encryption
+///
+/// A leaked file key exposes every copy of the file's shards, and
+/// re-uploading the file to change it would move all of them at once.
+/// Instead, [`rotate`] generates a new key version and wraps it, the file
+/// key and the versions still in use for the recipients, without touching
+/// a shard. Copies are re-encrypted one at a time when a heal or scrub pass
+/// next handles them: a shard at key version `v` is the original shard
+/// sealed with XChaCha20-Poly1305 under version `v`'s key, so the file key
+/// alone no longer reads it.
+///
+/// Copies under older versions stay valid while the rotation runs. The
+/// [`KeyRotation`] in the manifest records the hash of each shard under
+/// each version and the version of the copy at each shard location; once
+/// every location holds the newest version, [`KeyRotation::retire`] drops
+/// the older keys and their copies are no longer accepted.
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use x25519_dalek::PublicKey;
+
+use crate::blob::BlobId;
+use crate::cache::FileManifest;
+use crate::envelope::{self, NodeKeypair, WrappedKey};
+
+/// Key version of the shards as uploaded, encrypted with the file key only
+pub const ORIGINAL_VERSION: u32 = 0;
+
+/// Prefix of the nonce and associated data of every sealed shard
+const SEAL_CONTEXT: &[u8] = b"pangea-shard-seal-v1";
+
+/// One rotated key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyVersion {
+    pub version: u32,
+    /// The version's key wrapped for each recipient
+    pub recipients: Vec<WrappedKey>,
+    /// SHA-256 of each shard sealed under this version, by index; `None`
+    /// until the shard is first sealed
+    pub shard_hashes: Vec<Option<String>>,
+}
+
+/// Progress of a file's key rotation, kept in its manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// Newest version, which copies are moved to
+    pub current: u32,
+    /// Oldest version whose copies are still accepted
+    pub oldest: u32,
+    /// Rotated keys still in use, oldest first
+    pub versions: Vec<KeyVersion>,
+    /// Version of the copy at each shard location, in `shard_locations`
+    /// order
+    pub location_versions: Vec<u32>,
+    /// Unix time the newest version was introduced
+    pub started_at: i64,
+}
+
+impl KeyRotation {
+    /// Check the invariants lookups index by
+    pub fn validate(&self, shard_count: usize) -> Result<()> {
+        if self.oldest > self.current {
+            bail!(
+                "Key rotation keeps version {} past current {}",
+                self.oldest,
+                self.current
+            );
+        }
+        if self.version(self.current).is_none() {
+            bail!("Key rotation lacks its current version {}", self.current);
+        }
+        if let Some(version) = self
+            .versions
+            .iter()
+            .find(|version| version.shard_hashes.len() != shard_count)
+        {
+            bail!(
+                "Key version {} lists {} shard hashes for {} shards",
+                version.version,
+                version.shard_hashes.len(),
+                shard_count
+            );
+        }
+        Ok(())
+    }
+
+    fn version(&self, version: u32) -> Option<&KeyVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+
+    /// Version of the copy at shard location `position`; locations added
+    /// since the rotation started hold the original
+    pub fn location_version(&self, position: usize) -> u32 {
+        self.location_versions
+            .get(position)
+            .copied()
+            .unwrap_or(ORIGINAL_VERSION)
+    }
+
+    /// Record that the copy at `position` is now at the current version
+    pub fn mark_current(&mut self, position: usize, locations: usize) {
+        self.location_versions.resize(locations, ORIGINAL_VERSION);
+        if let Some(version) = self.location_versions.get_mut(position) {
+            *version = self.current;
+        }
+    }
+
+    /// Version of a copy of shard `index` with hash `sha256`, if one still
+    /// accepted; `original` is the shard's hash as uploaded
+    pub fn version_of(&self, index: usize, original: &str, sha256: &str) -> Option<u32> {
+        if self.oldest == ORIGINAL_VERSION && original == sha256 {
+            return Some(ORIGINAL_VERSION);
+        }
+        self.versions
+            .iter()
+            .filter(|version| version.version >= self.oldest)
+            .find(|version| {
+                version
+                    .shard_hashes
+                    .get(index)
+                    .is_some_and(|hash| hash.as_deref() == Some(sha256))
+            })
+            .map(|version| version.version)
+    }
+
+    /// Shard locations at the current version, out of all `locations`
+    pub fn progress(&self, locations: usize) -> (usize, usize) {
+        let rotated = (0..locations)
+            .filter(|&position| self.location_version(position) == self.current)
+            .count();
+        (rotated, locations)
+    }
+
+    /// Whether every one of `locations` holds the current version
+    pub fn is_complete(&self, locations: usize) -> bool {
+        let (rotated, total) = self.progress(locations);
+        rotated == total
+    }
+
+    /// Stop accepting copies under older versions once every one of
+    /// `locations` holds the current one; returns whether any were dropped
+    pub fn retire(&mut self, locations: usize) -> bool {
+        if self.oldest == self.current || !self.is_complete(locations) {
+            return false;
+        }
+        self.oldest = self.current;
+        let current = self.current;
+        self.versions.retain(|version| version.version >= current);
+        true
+    }
+}
+
+/// Keys of the rotation versions still in use, as unwrapped by one
+/// recipient
+#[derive(Default)]
+pub struct ShardKeys {
+    keys: BTreeMap<u32, [u8; 32]>,
+}
+
+impl ShardKeys {
+    /// Unwrap every version of `manifest`'s rotation still in use with
+    /// `keypair`
+    ///
+    /// Fails when one is not wrapped for this node. Manifests without a
+    /// rotation need no keys.
+    pub fn unwrap(keypair: &NodeKeypair, manifest: &FileManifest) -> Result<Self> {
+        let mut keys = BTreeMap::new();
+        if let Some(rotation) = &manifest.rotation {
+            for version in &rotation.versions {
+                let key = keypair.unwrap_key(&version.recipients)?.with_context(|| {
+                    format!(
+                        "Key version {} of {} is not wrapped for this node",
+                        version.version, manifest.file_hash
+                    )
+                })?;
+                keys.insert(version.version, key);
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// The original shard from a valid copy of shard `index`, which may
+    /// already be the original
+    ///
+    /// Copies under a retired version, the original included, are refused.
+    pub fn open(&self, manifest: &FileManifest, index: usize, data: &[u8]) -> Result<Vec<u8>> {
+        if manifest.shard_hashes.get(index).is_none() {
+            bail!("Shard {} has no recorded hash", index);
+        }
+        let version = manifest
+            .shard_version(index, data)
+            .with_context(|| format!("Not a valid copy of shard {}", index))?;
+        if version == ORIGINAL_VERSION {
+            return Ok(data.to_vec());
+        }
+        let key = self
+            .keys
+            .get(&version)
+            .with_context(|| format!("No key for version {} of shard {}", version, index))?;
+        let context = seal_context(&manifest.file_hash, index, version);
+        XChaCha20Poly1305::new(&(*key).into())
+            .decrypt(
+                XNonce::from_slice(&seal_nonce(&context)),
+                Payload {
+                    msg: data,
+                    aad: &context,
+                },
+            )
+            .map_err(|_| anyhow!("Failed to open shard {} at key version {}", index, version))
+    }
+
+    /// Replace each copy in `shards` with its original shard, dropping
+    /// copies that do not open so the codes treat them as missing; shards
+    /// of files never rotated are left alone
+    pub fn open_all(&self, manifest: &FileManifest, shards: &mut [Option<Vec<u8>>]) {
+        if manifest.rotation.is_none() {
+            return;
+        }
+        for (index, slot) in shards.iter_mut().enumerate() {
+            if let Some(data) = slot.take() {
+                *slot = self.open(manifest, index, &data).ok();
+            }
+        }
+    }
+
+    /// Seal original shard `index` under the current version, recording
+    /// its hash in `manifest`
+    pub fn seal(
+        &self,
+        manifest: &mut FileManifest,
+        index: usize,
+        original: &[u8],
+    ) -> Result<Vec<u8>> {
+        let file_hash = manifest.file_hash.clone();
+        let rotation = manifest
+            .rotation
+            .as_mut()
+            .context("Manifest has no key rotation")?;
+        let current = rotation.current;
+        let key = self
+            .keys
+            .get(&current)
+            .with_context(|| format!("No key for version {}", current))?;
+        let context = seal_context(&file_hash, index, current);
+        let sealed = XChaCha20Poly1305::new(&(*key).into())
+            .encrypt(
+                XNonce::from_slice(&seal_nonce(&context)),
+                Payload {
+                    msg: original,
+                    aad: &context,
+                },
+            )
+            .map_err(|e| anyhow!("Failed to seal shard {}: {}", index, e))?;
+        let version = rotation
+            .versions
+            .iter_mut()
+            .find(|version| version.version == current)
+            .context("Key rotation lacks its current version")?;
+        let hash = version
+            .shard_hashes
+            .get_mut(index)
+            .with_context(|| format!("Shard {} out of range", index))?;
+        *hash = Some(BlobId::of(&sealed).to_hex());
+        Ok(sealed)
+    }
+}
+
+/// Start a new key version for `manifest`, as one of its recipients
+///
+/// The file key, the versions still in use and the new one are wrapped
+/// for `recipients` (the manifest's own recipients if empty), so a
+/// recipient left out can no longer read copies sealed under the new
+/// version. No shard is touched; heal and scrub passes re-encrypt them.
+pub fn rotate(
+    manifest: &FileManifest,
+    keypair: &NodeKeypair,
+    recipients: &[PublicKey],
+) -> Result<FileManifest> {
+    if manifest.shard_hashes.is_empty() {
+        bail!(
+            "{} predates shard hashes and cannot be rotated",
+            manifest.file_hash
+        );
+    }
+    let file_key = keypair.unwrap_key(&manifest.recipients)?.with_context(|| {
+        format!(
+            "File key of {} is not wrapped for this node",
+            manifest.file_hash
+        )
+    })?;
+    let keys = ShardKeys::unwrap(keypair, manifest)?;
+    let recipients = if recipients.is_empty() {
+        manifest
+            .recipients
+            .iter()
+            .map(|wrapped| envelope::parse_public_key(&wrapped.recipient))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        recipients.to_vec()
+    };
+    let wrap = |key: &[u8; 32]| -> Result<Vec<WrappedKey>> {
+        recipients
+            .iter()
+            .map(|recipient| envelope::wrap_key(key, recipient))
+            .collect()
+    };
+
+    let mut new_key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut new_key);
+    let mut rotation = manifest.rotation.clone().unwrap_or(KeyRotation {
+        current: ORIGINAL_VERSION,
+        oldest: ORIGINAL_VERSION,
+        versions: Vec::new(),
+        location_versions: vec![ORIGINAL_VERSION; manifest.shard_locations.len()],
+        started_at: 0,
+    });
+    for version in &mut rotation.versions {
+        version.recipients = wrap(&keys.keys[&version.version])?;
+    }
+    rotation.current += 1;
+    rotation.versions.push(KeyVersion {
+        version: rotation.current,
+        recipients: wrap(&new_key)?,
+        shard_hashes: vec![None; manifest.shard_count],
+    });
+    rotation.started_at = chrono::Utc::now().timestamp();
+
+    let mut rotated = manifest.clone();
+    rotated.recipients = wrap(&file_key)?;
+    rotated.rotation = Some(rotation);
+    Ok(rotated)
+}
+
+/// Nonce and associated data of shard `index` of `file_hash` sealed under
+/// `version`
+fn seal_context(file_hash: &str, index: usize, version: u32) -> Vec<u8> {
+    let mut context = SEAL_CONTEXT.to_vec();
+    context.extend_from_slice(file_hash.as_bytes());
+    context.extend_from_slice(&(index as u64).to_le_bytes());
+    context.extend_from_slice(&version.to_le_bytes());
+    context
+}
+
+/// Sealing is deterministic: a key seals each shard under one version once,
+/// so the nonce comes from the shard's place rather than at random
+fn seal_nonce(context: &[u8]) -> [u8; 24] {
+    let digest = Sha256::digest(context);
+    let mut nonce = [0u8; 24];
+    nonce.copy_from_slice(&digest[..24]);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(owner: &NodeKeypair, shards: &[Vec<u8>]) -> FileManifest {
+        FileManifest {
+            file_hash: "rotating".to_string(),
+            file_name: "rotating.bin".to_string(),
+            file_size: 8,
+            shard_count: shards.len(),
+            parity_count: 1,
+            shard_locations: (0..shards.len()).map(|i| (i, i as u32 + 1)).collect(),
+            recipients: vec![envelope::wrap_key(&[9; 32], &owner.public_key()).unwrap()],
+            shard_hashes: shards
+                .iter()
+                .map(|shard| format!("{:x}", Sha256::digest(shard)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_shards_move_to_the_new_key_one_at_a_time() {
+        let owner = NodeKeypair::generate(1);
+        let shards = vec![vec![1u8; 4], vec![2u8; 4], vec![3u8; 4]];
+        let original = manifest(&owner, &shards);
+        let mut manifest = rotate(&original, &owner, &[]).unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(
+            owner.unwrap_key(&manifest.recipients).unwrap(),
+            Some([9; 32])
+        );
+
+        // Re-encrypt the copy at the first location only
+        let keys = ShardKeys::unwrap(&owner, &manifest).unwrap();
+        let sealed = keys.seal(&mut manifest, 0, &shards[0]).unwrap();
+        assert_ne!(sealed, shards[0]);
+        let locations = manifest.shard_locations.len();
+        manifest
+            .rotation
+            .as_mut()
+            .unwrap()
+            .mark_current(0, locations);
+
+        // Both versions are valid copies, and open to the original
+        assert_eq!(manifest.shard_version(0, &shards[0]), Some(0));
+        assert_eq!(manifest.shard_version(0, &sealed), Some(1));
+        assert_eq!(keys.open(&manifest, 0, &sealed).unwrap(), shards[0]);
+        assert!(manifest.shard_version(0, &shards[1]).is_none());
+        let rotation = manifest.rotation.as_mut().unwrap();
+        assert_eq!(rotation.progress(locations), (1, 3));
+        assert!(!rotation.retire(locations));
+
+        // Once every location is current the file key alone reads nothing
+        rotation.mark_current(1, locations);
+        rotation.mark_current(2, locations);
+        assert!(rotation.retire(locations));
+        assert!(manifest.shard_version(0, &shards[0]).is_none());
+        assert_eq!(manifest.shard_version(0, &sealed), Some(1));
+
+        // A second rotation keeps version 1 readable for the new recipient
+        let other = NodeKeypair::generate(2);
+        let manifest =
+            rotate(&manifest, &owner, &[owner.public_key(), other.public_key()]).unwrap();
+        let keys = ShardKeys::unwrap(&other, &manifest).unwrap();
+        assert_eq!(keys.open(&manifest, 0, &sealed).unwrap(), shards[0]);
+        assert_eq!(manifest.rotation.as_ref().unwrap().current, 2);
+
+        // Nobody else can start one
+        let stranger = NodeKeypair::generate(3);
+        assert!(rotate(&original, &stranger, &[]).is_err());
+    }
+
+    #[test]
+    fn test_retired_rotation_refuses_original_copies() {
+        let owner = NodeKeypair::generate(1);
+        let shards = vec![vec![1u8; 4], vec![2u8; 4]];
+        let mut manifest = rotate(&manifest(&owner, &shards), &owner, &[]).unwrap();
+        let keys = ShardKeys::unwrap(&owner, &manifest).unwrap();
+        let sealed: Vec<_> = (0..shards.len())
+            .map(|index| keys.seal(&mut manifest, index, &shards[index]).unwrap())
+            .collect();
+        let locations = manifest.shard_locations.len();
+        let rotation = manifest.rotation.as_mut().unwrap();
+        for position in 0..locations {
+            rotation.mark_current(position, locations);
+        }
+
+        // Originals open while the rotation runs
+        assert_eq!(keys.open(&manifest, 0, &shards[0]).unwrap(), shards[0]);
+
+        assert!(manifest.rotation.as_mut().unwrap().retire(locations));
+        assert!(keys.open(&manifest, 0, &shards[0]).is_err());
+        assert_eq!(keys.open(&manifest, 0, &sealed[0]).unwrap(), shards[0]);
+
+        let mut copies = vec![Some(shards[0].clone()), Some(sealed[1].clone())];
+        keys.open_all(&manifest, &mut copies);
+        assert_eq!(copies, vec![None, Some(shards[1].clone())]);
+    }
+}
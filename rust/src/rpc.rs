@@ -1,18 +1,85 @@
-use anyhow::Result;
-use std::net::SocketAddr;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use capnp::capability::Rc;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tracing::{error, info};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
 
+use crate::api::{
+    ApiError, DownloadCall, DownloadReply, NodeApi, StagingDir, Transfers, UploadCall,
+    UploadOptions, MAX_API_FILE_BYTES,
+};
+use crate::automated::{DownloadResult, FileInfo, UploadResult, DEFAULT_BATCH_CONCURRENCY};
+use crate::cache::{Cache, FileManifest};
+use crate::compute::{ComputeEngine, ComputeTask, TaskResult};
+use crate::envelope::parse_public_key;
+use crate::error::NamespaceError;
+use crate::gateway::{Gateway, GatewayRejection};
+use crate::go_client::{fill_record, read_record, GoClient, GoClientStats};
+use crate::limits::{ConcurrencyLimits, LimitName, LimitStats};
+use crate::lookup::SearchSource;
+use crate::manifest_sync::{ManifestDigest, ManifestRecord, ManifestSync};
+use crate::metrics_history::{MetricsHistory, MetricsPoint};
+use crate::namespace::{NamespaceStats, TokenScopes, DEFAULT_NAMESPACE};
 use crate::network::QuicNode;
-use crate::store::NodeStore;
+use crate::probe::BandwidthProber;
+use crate::progress::{ProgressHub, TransferDirection, TransferProgress};
+use crate::provider::{
+    PeerAccount, ProviderStats, StorageProvider, StorageRequest, StorageResponse,
+};
+use crate::replication::Replicator;
+use crate::scheduler::TransferPriority;
+use crate::schema_capnp::{
+    download_sink, file_manifest, node_service, progress_listener, stored_file, transfer_progress,
+    upload_stream,
+};
+use crate::store::{MisbehaviorRecord, MisbehaviorStats, NodeStore};
+use crate::supervisor::{Supervisor, TaskHealth};
 use crate::types::{ConnectionQuality, Node, PeerAddress};
 
+/// Largest Cap'n Proto message accepted from a client
+///
+/// Inline uploads and downloads carry up to `MAX_API_FILE_BYTES` of data.
+pub const MAX_MESSAGE_BYTES: usize = 4 * MAX_API_FILE_BYTES;
+
+/// Name recorded for files uploaded through `uploadWithProgress`, whose
+/// request carries no file name
+const UNNAMED_UPLOAD: &str = "uploaded_file";
+
+/// Largest chunk of a file sent in one `putStream` or `getStream` write
+pub const STREAM_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// How long the CLI waits for the daemon to accept its connection
+const DAEMON_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// RPC server using Cap'n Proto
 pub struct RpcServer {
     addr: SocketAddr,
     store: Arc<NodeStore>,
     network: Arc<QuicNode>,
+    progress: ProgressHub,
+    history: Option<Arc<MetricsHistory>>,
+    cache: Option<Arc<Cache>>,
+    tokens: Arc<TokenScopes>,
+    prober: Option<Arc<BandwidthProber>>,
+    provider: Option<Arc<StorageProvider>>,
+    gateway: Option<Arc<Gateway>>,
+    replicator: Option<Arc<Replicator>>,
+    transfers: Option<Arc<Transfers>>,
+    compute: Option<Arc<ComputeEngine>>,
+    supervisor: Option<Arc<Supervisor>>,
+    limits: Option<Arc<ConcurrencyLimits>>,
+    manifest_sync: Option<Arc<ManifestSync>>,
+    go_client: Option<Arc<GoClient>>,
 }
 
 impl RpcServer {
@@ -21,7 +88,162 @@ impl RpcServer {
             addr,
             store,
             network,
+            progress: ProgressHub::default(),
+            history: None,
+            cache: None,
+            tokens: Arc::new(TokenScopes::default()),
+            prober: None,
+            provider: None,
+            gateway: None,
+            replicator: None,
+            transfers: None,
+            compute: None,
+            supervisor: None,
+            limits: None,
+            manifest_sync: None,
+            go_client: None,
+        }
+    }
+
+    /// Answer namespace file and usage queries from this cache
+    pub fn with_cache(mut self, cache: Arc<Cache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Require callers to present a token scoped to the namespace they use
+    pub fn with_token_scopes(mut self, tokens: TokenScopes) -> Self {
+        self.tokens = Arc::new(tokens);
+        self
+    }
+
+    /// Answer `getMetricsHistory` from this history
+    pub fn with_metrics_history(mut self, history: Arc<MetricsHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Probe the bandwidth of peers connected through `connectToPeer`
+    pub fn with_bandwidth_prober(mut self, prober: Arc<BandwidthProber>) -> Self {
+        self.prober = Some(prober);
+        self
+    }
+
+    /// Serve shard storage requests from this provider
+    pub fn with_storage_provider(mut self, provider: Arc<StorageProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Run as a read-only gateway: refuse writes and rate-limit clients
+    pub fn with_gateway(mut self, gateway: Arc<Gateway>) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Sync manifests with trusted peers connected through `connectToPeer`
+    pub fn with_replicator(mut self, replicator: Arc<Replicator>) -> Self {
+        self.replicator = Some(replicator);
+        self
+    }
+
+    /// Serve uploads and downloads through these transfers
+    pub fn with_transfers(mut self, transfers: Arc<Transfers>) -> Self {
+        self.transfers = Some(transfers);
+        self
+    }
+
+    /// Run compute tasks clients submit on this engine
+    pub fn with_compute(mut self, engine: Arc<ComputeEngine>) -> Self {
+        self.compute = Some(engine);
+        self
+    }
+
+    /// Report the health of the daemon's background tasks
+    pub fn with_supervisor(mut self, supervisor: Arc<Supervisor>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+
+    /// Report and adjust these concurrency limits at runtime
+    pub fn with_concurrency_limits(mut self, limits: Arc<ConcurrencyLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Answer the Go node's manifest syncs from this cache
+    pub fn with_manifest_sync(mut self, sync: Arc<ManifestSync>) -> Self {
+        self.manifest_sync = Some(sync);
+        self
+    }
+
+    /// Report the pool and circuit breaker of this client in `getNodeStats`
+    pub fn with_go_client(mut self, client: Arc<GoClient>) -> Self {
+        self.go_client = Some(client);
+        self
+    }
+
+    /// Forward progress from this hub to `uploadWithProgress` and
+    /// `downloadWithProgress` listeners
+    pub fn with_progress(mut self, hub: ProgressHub) -> Self {
+        self.progress = hub;
+        self
+    }
+
+    /// Hub that upload and download protocols should report to
+    pub fn progress(&self) -> &ProgressHub {
+        &self.progress
+    }
+
+    /// Service for one client, once a gateway's rate limit lets it in
+    ///
+    /// Every front end (Cap'n Proto connections, gRPC calls) builds its
+    /// service here so they share one configuration.
+    pub fn service_for(&self, client: IpAddr) -> Result<NodeServiceImpl, GatewayRejection> {
+        if let Some(gateway) = &self.gateway {
+            gateway.check_rate(client)?;
+        }
+        let mut service = NodeServiceImpl::new(self.store.clone(), self.network.clone())
+            .with_client(client)
+            .with_progress(self.progress.clone())
+            .with_token_scopes(self.tokens.clone());
+        if let Some(history) = &self.history {
+            service = service.with_metrics_history(history.clone());
+        }
+        if let Some(cache) = &self.cache {
+            service = service.with_cache(cache.clone());
+        }
+        if let Some(prober) = &self.prober {
+            service = service.with_bandwidth_prober(prober.clone());
+        }
+        if let Some(provider) = &self.provider {
+            service = service.with_storage_provider(provider.clone());
         }
+        if let Some(gateway) = &self.gateway {
+            service = service.with_gateway(gateway.clone(), client);
+        }
+        if let Some(replicator) = &self.replicator {
+            service = service.with_replicator(replicator.clone());
+        }
+        if let Some(transfers) = &self.transfers {
+            service = service.with_transfers(transfers.clone());
+        }
+        if let Some(engine) = &self.compute {
+            service = service.with_compute(engine.clone());
+        }
+        if let Some(supervisor) = &self.supervisor {
+            service = service.with_supervisor(supervisor.clone());
+        }
+        if let Some(limits) = &self.limits {
+            service = service.with_concurrency_limits(limits.clone());
+        }
+        if let Some(sync) = &self.manifest_sync {
+            service = service.with_manifest_sync(sync.clone());
+        }
+        if let Some(client) = &self.go_client {
+            service = service.with_go_client(client.clone());
+        }
+        Ok(service)
     }
 
     /// Start the RPC server
@@ -32,15 +254,19 @@ impl RpcServer {
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
+                    let service = match self.service_for(addr.ip()) {
+                        Ok(service) => service,
+                        Err(rejection) => {
+                            debug!("Refused RPC connection: {}", rejection);
+                            continue;
+                        }
+                    };
                     info!("RPC connection from {}", addr);
 
-                    let store = self.store.clone();
-                    let network = self.network.clone();
-
                     // Spawn on the current task using tokio::task::spawn_local
                     // Or handle inline for simplicity
                     tokio::task::spawn_local(async move {
-                        if let Err(e) = handle_rpc_connection(stream, store, network).await {
+                        if let Err(e) = handle_rpc_connection(stream, service).await {
                             error!("RPC connection error: {}", e);
                         }
                     });
@@ -53,12 +279,12 @@ impl RpcServer {
     }
 }
 
-/// Handle a single RPC connection
-async fn handle_rpc_connection(
-    stream: tokio::net::TcpStream,
-    store: Arc<NodeStore>,
-    network: Arc<QuicNode>,
-) -> Result<()> {
+/// Handle a single RPC connection, serving `service` as its bootstrap
+/// capability until the client disconnects
+async fn handle_rpc_connection<S>(stream: S, service: NodeServiceImpl) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
     use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
     use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
@@ -67,19 +293,17 @@ async fn handle_rpc_connection(
     let reader = reader.compat();
     let writer = writer.compat_write();
 
+    let mut options = capnp::message::ReaderOptions::new();
+    options.traversal_limit_in_words(Some(MAX_MESSAGE_BYTES / 8));
     let rpc_network = Box::new(twoparty::VatNetwork::new(
         reader,
         writer,
         rpc_twoparty_capnp::Side::Server,
-        Default::default(),
+        options,
     ));
 
-    // Create service implementation
-    let _service_impl = NodeServiceImpl::new(store, network);
-
-    // TODO: Bootstrap with actual service implementation
-    // For now, this is a placeholder that accepts connections
-    let rpc_system = RpcSystem::new(rpc_network, None);
+    let client: node_service::Client = capnp_rpc::new_client(service);
+    let rpc_system = RpcSystem::new(rpc_network, Some(client.client));
 
     info!("RPC system initialized");
 
@@ -89,16 +313,208 @@ async fn handle_rpc_connection(
     Ok(())
 }
 
-/// Simplified RPC service implementation (without full Cap'n Proto codegen)
-/// In production, this would be generated from schema.capnp
+/// Node operations served to one RPC client
+///
+/// Backs the Cap'n Proto `NodeService` (methods it does not serve answer
+/// "unimplemented") and the gRPC front end.
 pub struct NodeServiceImpl {
     store: Arc<NodeStore>,
     network: Arc<QuicNode>,
+    progress: ProgressHub,
+    history: Option<Arc<MetricsHistory>>,
+    cache: Option<Arc<Cache>>,
+    tokens: Arc<TokenScopes>,
+    prober: Option<Arc<BandwidthProber>>,
+    provider: Option<Arc<StorageProvider>>,
+    gateway: Option<Arc<Gateway>>,
+    client: Option<IpAddr>,
+    replicator: Option<Arc<Replicator>>,
+    transfers: Option<Arc<Transfers>>,
+    compute: Option<Arc<ComputeEngine>>,
+    supervisor: Option<Arc<Supervisor>>,
+    limits: Option<Arc<ConcurrencyLimits>>,
+    manifest_sync: Option<Arc<ManifestSync>>,
+    go_client: Option<Arc<GoClient>>,
 }
 
 impl NodeServiceImpl {
     pub fn new(store: Arc<NodeStore>, network: Arc<QuicNode>) -> Self {
-        Self { store, network }
+        Self {
+            store,
+            network,
+            progress: ProgressHub::default(),
+            history: None,
+            cache: None,
+            tokens: Arc::new(TokenScopes::default()),
+            prober: None,
+            provider: None,
+            gateway: None,
+            client: None,
+            replicator: None,
+            transfers: None,
+            compute: None,
+            supervisor: None,
+            limits: None,
+            manifest_sync: None,
+            go_client: None,
+        }
+    }
+
+    /// Serve namespace file listings and usage from this cache
+    pub fn with_cache(mut self, cache: Arc<Cache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Check namespace requests against these token scopes
+    pub fn with_token_scopes(mut self, tokens: Arc<TokenScopes>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Serve persisted per-minute metrics
+    pub fn with_metrics_history(mut self, history: Arc<MetricsHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Probe newly connected peers' bandwidth
+    pub fn with_bandwidth_prober(mut self, prober: Arc<BandwidthProber>) -> Self {
+        self.prober = Some(prober);
+        self
+    }
+
+    /// Answer shard storage requests from this provider
+    pub fn with_storage_provider(mut self, provider: Arc<StorageProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Exchange missing manifests with trusted peers when they connect
+    pub fn with_replicator(mut self, replicator: Arc<Replicator>) -> Self {
+        self.replicator = Some(replicator);
+        self
+    }
+
+    /// Upload and download files for clients
+    pub fn with_transfers(mut self, transfers: Arc<Transfers>) -> Self {
+        self.transfers = Some(transfers);
+        self
+    }
+
+    /// Run clients' compute tasks
+    pub fn with_compute(mut self, engine: Arc<ComputeEngine>) -> Self {
+        self.compute = Some(engine);
+        self
+    }
+
+    /// Report the health of these background tasks
+    pub fn with_supervisor(mut self, supervisor: Arc<Supervisor>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+
+    /// Report and adjust these concurrency limits
+    pub fn with_concurrency_limits(mut self, limits: Arc<ConcurrencyLimits>) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Sync manifests with the Go node
+    pub fn with_manifest_sync(mut self, sync: Arc<ManifestSync>) -> Self {
+        self.manifest_sync = Some(sync);
+        self
+    }
+
+    /// Report the pool and circuit breaker of this Go node client
+    pub fn with_go_client(mut self, client: Arc<GoClient>) -> Self {
+        self.go_client = Some(client);
+        self
+    }
+
+    /// Address of the client being served, for audit records
+    pub fn with_client(mut self, client: IpAddr) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Serve `client` as a read-only gateway
+    pub fn with_gateway(mut self, gateway: Arc<Gateway>, client: IpAddr) -> Self {
+        self.gateway = Some(gateway);
+        self.client = Some(client);
+        self
+    }
+
+    /// Report transfer progress from this hub
+    pub fn with_progress(mut self, hub: ProgressHub) -> Self {
+        self.progress = hub;
+        self
+    }
+
+    /// Push progress for one transfer to a listener until it finishes
+    ///
+    /// Backs `ProgressListener.onProgress` for `uploadWithProgress` and
+    /// `downloadWithProgress`. Subscribe before starting the transfer so no
+    /// snapshot is missed.
+    pub async fn stream_progress<F, Fut>(
+        &self,
+        receiver: broadcast::Receiver<TransferProgress>,
+        transfer_id: &str,
+        on_progress: F,
+    ) -> Result<Option<TransferProgress>>
+    where
+        F: FnMut(TransferProgress) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        forward_progress(receiver, transfer_id, on_progress).await
+    }
+
+    /// Subscribe to progress of all transfers
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<TransferProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Latest progress of every transfer still in flight
+    ///
+    /// Backs `getActiveTransfers`.
+    pub fn active_transfers(&self) -> Vec<TransferProgress> {
+        self.progress.active()
+    }
+
+    /// Run `transfer`, pushing the progress of `transfer_id` to `listener`
+    /// as it goes
+    ///
+    /// Unlike `stream_progress` this stops when the transfer returns, so a
+    /// transfer that fails before publishing anything (or is deduplicated)
+    /// does not leave the caller waiting. Listener failures are logged and
+    /// never fail the transfer.
+    async fn relay_progress<T>(
+        &self,
+        transfer_id: &str,
+        listener: &progress_listener::Client,
+        transfer: impl Future<Output = T>,
+    ) -> T {
+        let mut receiver = self.subscribe_progress();
+        tokio::pin!(transfer);
+        let output = loop {
+            tokio::select! {
+                output = &mut transfer => break output,
+                received = receiver.recv() => match received {
+                    Ok(progress) if progress.transfer_id == transfer_id => {
+                        notify_listener(listener, &progress).await;
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break (&mut transfer).await,
+                },
+            }
+        };
+        // Snapshots published just before the transfer returned
+        while let Ok(progress) = receiver.try_recv() {
+            if progress.transfer_id == transfer_id {
+                notify_listener(listener, &progress).await;
+            }
+        }
+        output
     }
 
     /// Get a specific node
@@ -111,6 +527,122 @@ impl NodeServiceImpl {
         self.store.get_all_nodes().await
     }
 
+    /// Totals of the bad-shard ledger, for spotting poisoning attempts
+    pub async fn get_misbehavior_stats(&self) -> MisbehaviorStats {
+        self.store.misbehavior_stats().await
+    }
+
+    /// Peers that served corrupt shards, worst first
+    pub async fn get_misbehaving_peers(&self) -> Vec<MisbehaviorRecord> {
+        self.store.misbehaving_peers().await
+    }
+
+    /// State, restarts and last error of each background task
+    ///
+    /// Empty when the node runs without a supervisor.
+    pub fn get_task_health(&self) -> Vec<TaskHealth> {
+        self.supervisor
+            .as_ref()
+            .map(|supervisor| supervisor.health())
+            .unwrap_or_default()
+    }
+
+    /// Fail if this node is a gateway and the client may not call `method`
+    /// (its `schema.capnp` name) right now
+    ///
+    /// Always passes on a full node.
+    pub fn admit(&self, method: &str) -> Result<(), GatewayRejection> {
+        match &self.gateway {
+            Some(gateway) => gateway.admit(
+                self.client.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                method,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail unless `token` may use `namespace`
+    ///
+    /// Every namespaced request calls this first; with no tokens configured
+    /// any valid namespace is allowed.
+    pub fn authorize(&self, token: Option<&str>, namespace: &str) -> Result<(), NamespaceError> {
+        self.tokens.authorize(token, namespace)
+    }
+
+    /// Manifests in a namespace the token may use
+    pub async fn list_files(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+    ) -> Result<Vec<FileManifest>> {
+        self.admit("listFiles")?;
+        self.authorize(token, namespace)?;
+        let Some(gateway) = &self.gateway else {
+            return Ok(self.cache()?.list_manifests_in(namespace).await);
+        };
+        // Gateways answer repeated listings from their response cache
+        let key = format!("listFiles:{}", namespace);
+        if let Some(body) = gateway.cached(&key) {
+            return Ok(serde_json::from_slice(&body)?);
+        }
+        let manifests = self.cache()?.list_manifests_in(namespace).await;
+        gateway.cache_response(&key, serde_json::to_vec(&manifests)?);
+        Ok(manifests)
+    }
+
+    /// Usage and quota of a namespace the token may use
+    pub async fn get_namespace_stats(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+    ) -> Result<NamespaceStats> {
+        self.admit("getNamespaceStats")?;
+        self.authorize(token, namespace)?;
+        Ok(self.cache()?.namespace_stats(namespace).await)
+    }
+
+    /// Usage and quota of every namespace the token may see
+    pub async fn list_namespace_stats(&self, token: Option<&str>) -> Result<Vec<NamespaceStats>> {
+        self.admit("listNamespaceStats")?;
+        let mut stats = self.cache()?.all_namespace_stats().await;
+        if let Some(allowed) = self.tokens.namespaces(token) {
+            stats.retain(|s| allowed.contains(&s.namespace));
+        }
+        Ok(stats)
+    }
+
+    fn cache(&self) -> Result<&Arc<Cache>> {
+        self.cache
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("File cache is not enabled on this node"))
+    }
+
+    /// Put, get, check, delete or prove a shard held for `peer_id`
+    pub async fn storage_request(
+        &self,
+        peer_id: u32,
+        request: StorageRequest,
+    ) -> Result<StorageResponse> {
+        self.admit("storageRequest")?;
+        Ok(self.provider()?.serve(peer_id, request).await)
+    }
+
+    /// Budget and usage of the storage provider
+    pub async fn get_storage_stats(&self) -> Result<ProviderStats> {
+        Ok(self.provider()?.stats().await)
+    }
+
+    /// What each peer has stored on and fetched from the provider
+    pub async fn get_storage_accounts(&self) -> Result<Vec<(u32, PeerAccount)>> {
+        Ok(self.provider()?.accounts().await)
+    }
+
+    fn provider(&self) -> Result<&Arc<StorageProvider>> {
+        self.provider
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Storage provider mode is not enabled on this node"))
+    }
+
     /// Update node
     pub async fn update_node(
         &self,
@@ -118,6 +650,7 @@ impl NodeServiceImpl {
         latency_ms: f32,
         threat_score: f32,
     ) -> Result<bool> {
+        self.admit("updateNode")?;
         if let Some(_node) = self.store.get_node(node_id).await {
             self.store.update_latency(node_id, latency_ms).await?;
             self.store
@@ -131,8 +664,25 @@ impl NodeServiceImpl {
 
     /// Connect to peer
     pub async fn connect_to_peer(&self, peer: PeerAddress) -> Result<(bool, ConnectionQuality)> {
+        self.admit("connectToPeer")?;
         match self.network.connect_to_peer(peer.clone()).await {
-            Ok(quality) => Ok((true, quality)),
+            Ok(quality) => {
+                if let Ok(ip) = peer.host.parse() {
+                    self.store.record_peer_address(peer.peer_id, ip).await;
+                }
+                if let Some(identity) = self.network.peer_identity(peer.peer_id).await {
+                    self.store
+                        .record_peer_identity(peer.peer_id, &identity)
+                        .await;
+                }
+                if let Some(prober) = &self.prober {
+                    prober.probe_in_background(peer.peer_id);
+                }
+                if let Some(replicator) = &self.replicator {
+                    replicator.sync_in_background(self.network.clone(), peer.peer_id);
+                }
+                Ok((true, quality))
+            }
             Err(e) => {
                 error!("Failed to connect to peer {}: {}", peer.peer_id, e);
                 Ok((false, ConnectionQuality::default()))
@@ -142,6 +692,7 @@ impl NodeServiceImpl {
 
     /// Send message
     pub async fn send_message(&self, peer_id: u32, data: Vec<u8>) -> Result<bool> {
+        self.admit("sendMessage")?;
         match self.network.send_message(peer_id, data.into()).await {
             Ok(_) => Ok(true),
             Err(e) => {
@@ -158,6 +709,7 @@ impl NodeServiceImpl {
 
     /// Disconnect peer
     pub async fn disconnect_peer(&self, peer_id: u32) -> Result<bool> {
+        self.admit("disconnectPeer")?;
         self.network.disconnect_peer(peer_id).await?;
         Ok(true)
     }
@@ -166,4 +718,1783 @@ impl NodeServiceImpl {
     pub async fn get_connected_peers(&self) -> Vec<u32> {
         self.network.get_connected_peers().await
     }
+
+    /// Per-minute metrics between `from` and `to` (Unix seconds)
+    ///
+    /// Backs `getMetricsHistory`; an empty `operation` selects all operations.
+    pub async fn get_metrics_history(
+        &self,
+        operation: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<MetricsPoint>> {
+        self.admit("getMetricsHistory")?;
+        let history = self
+            .history
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Metrics history is not enabled on this node"))?;
+        let operation = (!operation.is_empty()).then_some(operation);
+        history.query(operation, from, to).await
+    }
+
+    /// Limit, current and peak usage of each concurrency limit
+    ///
+    /// Backs `getLimits`.
+    pub fn get_limits(&self) -> Result<Vec<LimitStats>> {
+        self.admit("getLimits")?;
+        Ok(self.concurrency_limits()?.stats())
+    }
+
+    /// Change a concurrency limit (`uploads`, `downloads`, `heal` or
+    /// `dht_queries`) without restarting the node, returning the old limit
+    ///
+    /// Backs `setLimit`.
+    pub fn set_limit(&self, name: &str, limit: u32) -> Result<usize> {
+        self.admit("setLimit")?;
+        let name: LimitName = name.parse().map_err(anyhow::Error::msg)?;
+        self.concurrency_limits()?.set_limit(name, limit as usize)
+    }
+
+    /// Digest of every cached manifest
+    ///
+    /// Backs `getManifestDigests`.
+    pub async fn get_manifest_digests(&self) -> Result<Vec<ManifestDigest>> {
+        self.admit("getManifestDigests")?;
+        Ok(self.manifest_sync()?.digests().await)
+    }
+
+    /// Store pushed manifests that win over the cached versions and return
+    /// the wanted ones, with how many were stored
+    ///
+    /// Backs `syncManifests`.
+    pub async fn sync_manifests(
+        &self,
+        push: &[ManifestRecord],
+        want: &[String],
+    ) -> Result<(Vec<ManifestRecord>, usize)> {
+        self.admit("syncManifests")?;
+        Ok(self.manifest_sync()?.exchange(push, want).await)
+    }
+
+    /// Upload several inline files into the default namespace, discovering
+    /// peers once for the batch
+    ///
+    /// Backs `putMany`. At most `concurrency` files are in flight (the
+    /// default when zero); results are in request order and a failed file
+    /// does not stop the others.
+    pub async fn put_many(
+        &self,
+        files: Vec<Vec<u8>>,
+        concurrency: usize,
+    ) -> Result<Vec<Result<FileManifest, ApiError>>, ApiError> {
+        self.admit("putMany")?;
+        self.authorize(None, DEFAULT_NAMESPACE)?;
+        if let Some(data) = files.iter().find(|data| data.len() > MAX_API_FILE_BYTES) {
+            return Err(ApiError::InvalidArgument(format!(
+                "File is {} bytes; limit is {}",
+                data.len(),
+                MAX_API_FILE_BYTES
+            )));
+        }
+        let transfers = self.transfers()?;
+        // One staging directory per file, so each keeps the same name
+        let mut staged = Vec::with_capacity(files.len());
+        let mut paths = Vec::with_capacity(files.len());
+        for data in &files {
+            let staging = transfers.stage().await?;
+            let path = staging.file(UNNAMED_UPLOAD)?;
+            tokio::fs::write(&path, data)
+                .await
+                .map_err(|e| ApiError::Internal(format!("Failed to stage upload: {}", e)))?;
+            staged.push(staging);
+            paths.push(path);
+        }
+        let results = transfers
+            .uploader(DEFAULT_NAMESPACE, &self.requester())
+            .upload_many(
+                paths,
+                TransferPriority::Interactive,
+                batch_concurrency(concurrency),
+            )
+            .await?;
+        Ok(results
+            .into_iter()
+            .map(|result| -> Result<FileManifest, ApiError> {
+                let result = result?;
+                Ok(FileManifest::from_json(&result.manifest_json)?)
+            })
+            .collect())
+    }
+
+    /// Download several files from the default namespace
+    ///
+    /// Backs `getMany`. At most `concurrency` files are in flight (the
+    /// default when zero); results are in request order and a failed file
+    /// does not stop the others. Files past `MAX_MESSAGE_BYTES` of data in
+    /// total fail rather than overflow the reply.
+    pub async fn get_many(
+        &self,
+        file_hashes: Vec<String>,
+        concurrency: usize,
+    ) -> Result<Vec<Result<Vec<u8>, ApiError>>, ApiError> {
+        self.admit("getMany")?;
+        self.authorize(None, DEFAULT_NAMESPACE)?;
+        let transfers = self.transfers()?;
+        let staging = transfers.stage().await?;
+        let items = file_hashes
+            .into_iter()
+            .enumerate()
+            .map(|(i, file_hash)| (file_hash, staging.path().join(i.to_string())))
+            .collect();
+        let results = transfers
+            .downloader(DEFAULT_NAMESPACE, &self.requester())
+            .download_many(
+                items,
+                TransferPriority::Interactive,
+                batch_concurrency(concurrency),
+            )
+            .await;
+
+        let mut total = 0;
+        let mut replies = Vec::with_capacity(results.len());
+        for result in results {
+            let reply = match result {
+                Ok(result) if result.bytes_written > MAX_API_FILE_BYTES => {
+                    Err(ApiError::InvalidArgument(format!(
+                        "File is {} bytes; limit is {}",
+                        result.bytes_written, MAX_API_FILE_BYTES
+                    )))
+                }
+                Ok(result) if total + result.bytes_written > MAX_MESSAGE_BYTES => {
+                    Err(ApiError::InvalidArgument(format!(
+                        "Batch reply would exceed {} bytes; fetch {} separately",
+                        MAX_MESSAGE_BYTES, result.file_hash
+                    )))
+                }
+                Ok(result) => {
+                    total += result.bytes_written;
+                    tokio::fs::read(&result.output_path)
+                        .await
+                        .map_err(|e| ApiError::Internal(format!("Failed to read download: {}", e)))
+                }
+                Err(e) => Err(e.into()),
+            };
+            replies.push(reply);
+        }
+        Ok(replies)
+    }
+
+    /// Download a file into a fresh staging directory
+    ///
+    /// Backs `getStream`, which then streams the file to the caller; it is
+    /// removed with the returned directory.
+    pub async fn download_staged(
+        &self,
+        token: Option<&str>,
+        call: &DownloadCall,
+    ) -> Result<(StagingDir, DownloadResult), ApiError> {
+        self.admit("getStream")?;
+        self.authorize(token, &call.namespace)?;
+        let transfers = self.transfers()?;
+        let staging = transfers.stage().await?;
+        let path = staging.path().join("download");
+        let result = transfers
+            .downloader(&call.namespace, &self.requester())
+            .download_with_priority(&call.file_hash, &path, call.priority)
+            .await?;
+        Ok((staging, result))
+    }
+
+    /// Connection pool and circuit breaker of the Go node client
+    ///
+    /// Backs `getNodeStats`.
+    pub fn get_go_client_stats(&self) -> Result<GoClientStats> {
+        self.admit("getNodeStats")?;
+        let client = self
+            .go_client
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No Go node client on this node"))?;
+        Ok(client.stats())
+    }
+
+    fn manifest_sync(&self) -> Result<&Arc<ManifestSync>> {
+        self.manifest_sync
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Manifest sync is not enabled on this node"))
+    }
+
+    fn concurrency_limits(&self) -> Result<&Arc<ConcurrencyLimits>> {
+        self.limits
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Concurrency limits are not enabled on this node"))
+    }
+
+    fn transfers(&self) -> Result<&Arc<Transfers>, ApiError> {
+        self.transfers
+            .as_ref()
+            .ok_or_else(|| ApiError::Unavailable("Transfers are not enabled on this node".into()))
+    }
+
+    /// Audit log attribution for this client's transfers
+    fn requester(&self) -> String {
+        match self.client {
+            Some(client) => format!("rpc:{}", client),
+            None => "rpc:unknown".to_string(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl NodeApi for NodeServiceImpl {
+    async fn upload(
+        &self,
+        token: Option<&str>,
+        call: UploadCall,
+    ) -> Result<UploadResult, ApiError> {
+        self.admit("upload")?;
+        self.authorize(token, &call.namespace)?;
+        if call.data.len() > MAX_API_FILE_BYTES {
+            return Err(ApiError::InvalidArgument(format!(
+                "File is {} bytes; limit is {}",
+                call.data.len(),
+                MAX_API_FILE_BYTES
+            )));
+        }
+        let transfers = self.transfers()?;
+        let staging = transfers.stage().await?;
+        let path = staging.file(&call.file_name)?;
+        tokio::fs::write(&path, &call.data)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to stage upload: {}", e)))?;
+        Ok(transfers
+            .uploader(&call.namespace, &self.requester())
+            .upload_with_priority(&path, call.priority)
+            .await?)
+    }
+
+    async fn download(
+        &self,
+        token: Option<&str>,
+        call: DownloadCall,
+    ) -> Result<DownloadReply, ApiError> {
+        self.admit("download")?;
+        self.authorize(token, &call.namespace)?;
+        let transfers = self.transfers()?;
+        let staging = transfers.stage().await?;
+        let path = staging.path().join("download");
+        let result = transfers
+            .downloader(&call.namespace, &self.requester())
+            .download_with_priority(&call.file_hash, &path, call.priority)
+            .await?;
+        if result.bytes_written > MAX_API_FILE_BYTES {
+            return Err(ApiError::InvalidArgument(format!(
+                "File is {} bytes; limit is {}",
+                result.bytes_written, MAX_API_FILE_BYTES
+            )));
+        }
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to read download: {}", e)))?;
+        Ok(DownloadReply {
+            file_hash: result.file_hash,
+            file_name: result.file_name,
+            data,
+            shards_fetched: result.shards_fetched,
+            peers: result.peers,
+        })
+    }
+
+    async fn list(&self, token: Option<&str>, namespace: &str) -> Result<Vec<FileInfo>, ApiError> {
+        self.admit("listFiles")?;
+        self.authorize(token, namespace)?;
+        Ok(self
+            .transfers()?
+            .downloader(namespace, &self.requester())
+            .list_files()
+            .await?)
+    }
+
+    async fn search(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+        pattern: &str,
+    ) -> Result<Vec<FileInfo>, ApiError> {
+        self.admit("searchFiles")?;
+        self.authorize(token, namespace)?;
+        Ok(self
+            .transfers()?
+            .downloader(namespace, &self.requester())
+            .search(pattern)
+            .await?)
+    }
+
+    async fn info(
+        &self,
+        token: Option<&str>,
+        namespace: &str,
+        file_hash: &str,
+    ) -> Result<Option<FileInfo>, ApiError> {
+        self.admit("getFileInfo")?;
+        self.authorize(token, namespace)?;
+        Ok(self
+            .transfers()?
+            .downloader(namespace, &self.requester())
+            .get_info(file_hash)
+            .await?)
+    }
+
+    async fn compute(&self, task: ComputeTask) -> Result<TaskResult, ApiError> {
+        self.admit("compute")?;
+        let engine = self
+            .compute
+            .as_ref()
+            .ok_or_else(|| ApiError::Unavailable("Compute is not enabled on this node".into()))?;
+        Ok(engine.process_task(task).await?)
+    }
+}
+
+impl node_service::Server for NodeServiceImpl {
+    /// Upload into the default namespace, placing shards by this node's
+    /// placement policy (`targetPeers` is ignored)
+    async fn upload_with_progress(
+        self: Rc<Self>,
+        params: node_service::UploadWithProgressParams,
+        mut results: node_service::UploadWithProgressResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let data = params.get_request()?.get_data()?.to_vec();
+        let listener = params.get_listener()?;
+        let file_hash = format!("{:x}", Sha256::digest(&data));
+        let call = UploadCall {
+            file_name: UNNAMED_UPLOAD.to_string(),
+            data,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            priority: TransferPriority::Interactive,
+        };
+        let uploaded = self
+            .relay_progress(&file_hash, &listener, NodeApi::upload(&*self, None, call))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| {
+                FileManifest::from_json(&result.manifest_json).map_err(|e| e.to_string())
+            });
+
+        let mut response = results.get().init_response();
+        match uploaded {
+            Ok(manifest) => {
+                response.set_success(true);
+                fill_manifest(response.init_manifest(), &manifest);
+            }
+            Err(e) => {
+                response.set_success(false);
+                response.set_error_msg(e.as_str());
+            }
+        }
+        Ok(())
+    }
+
+    /// Download from the default namespace, finding shards through the
+    /// manifest (`shardLocations` is ignored)
+    async fn download_with_progress(
+        self: Rc<Self>,
+        params: node_service::DownloadWithProgressParams,
+        mut results: node_service::DownloadWithProgressResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let file_hash = params.get_request()?.get_file_hash()?.to_str()?.to_string();
+        let listener = params.get_listener()?;
+        let call = DownloadCall {
+            file_hash: file_hash.clone(),
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            priority: TransferPriority::Interactive,
+        };
+        let downloaded = self
+            .relay_progress(&file_hash, &listener, NodeApi::download(&*self, None, call))
+            .await;
+
+        let mut response = results.get().init_response();
+        match downloaded {
+            Ok(reply) => {
+                response.set_success(true);
+                response.set_bytes_downloaded(reply.data.len() as u64);
+                response.set_data(&reply.data[..]);
+            }
+            Err(e) => {
+                response.set_success(false);
+                response.set_error_msg(e.to_string().as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn put_many(
+        self: Rc<Self>,
+        params: node_service::PutManyParams,
+        mut results: node_service::PutManyResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let files = params
+            .get_requests()?
+            .iter()
+            .map(|request| Ok(request.get_data()?.to_vec()))
+            .collect::<Result<Vec<_>, capnp::Error>>()?;
+        let count = files.len();
+        let concurrency = params.get_max_concurrency() as usize;
+        let uploaded = match NodeServiceImpl::put_many(&self, files, concurrency).await {
+            Ok(uploaded) => uploaded,
+            // The whole batch failed; report it against every file
+            Err(e) => (0..count).map(|_| Err(e.clone())).collect(),
+        };
+
+        let mut responses = results.get().init_responses(uploaded.len() as u32);
+        for (i, result) in uploaded.iter().enumerate() {
+            let mut response = responses.reborrow().get(i as u32);
+            match result {
+                Ok(manifest) => {
+                    response.set_success(true);
+                    fill_manifest(response.init_manifest(), manifest);
+                }
+                Err(e) => {
+                    response.set_success(false);
+                    response.set_error_msg(e.to_string().as_str());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_many(
+        self: Rc<Self>,
+        params: node_service::GetManyParams,
+        mut results: node_service::GetManyResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let file_hashes = params
+            .get_requests()?
+            .iter()
+            .map(|request| Ok(request.get_file_hash()?.to_str()?.to_string()))
+            .collect::<Result<Vec<_>, capnp::Error>>()?;
+        let count = file_hashes.len();
+        let concurrency = params.get_max_concurrency() as usize;
+        let downloaded = match NodeServiceImpl::get_many(&self, file_hashes, concurrency).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => (0..count).map(|_| Err(e.clone())).collect(),
+        };
+
+        let mut responses = results.get().init_responses(downloaded.len() as u32);
+        for (i, result) in downloaded.iter().enumerate() {
+            let mut response = responses.reborrow().get(i as u32);
+            match result {
+                Ok(data) => {
+                    response.set_success(true);
+                    response.set_bytes_downloaded(data.len() as u64);
+                    response.set_data(&data[..]);
+                }
+                Err(e) => {
+                    response.set_success(false);
+                    response.set_error_msg(e.to_string().as_str());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_metrics_history(
+        self: Rc<Self>,
+        params: node_service::GetMetricsHistoryParams,
+        mut results: node_service::GetMetricsHistoryResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let operation = params.get_operation()?.to_str()?;
+        let history = NodeServiceImpl::get_metrics_history(
+            &self,
+            operation,
+            params.get_from_unix(),
+            params.get_to_unix(),
+        )
+        .await;
+
+        let mut results = results.get();
+        match history {
+            Ok(history) => {
+                results.set_success(true);
+                let mut points = results.init_points(history.len() as u32);
+                for (i, point) in history.iter().enumerate() {
+                    let mut builder = points.reborrow().get(i as u32);
+                    builder.set_minute(point.minute);
+                    builder.set_operation(point.operation.as_str());
+                    builder.set_samples(point.samples);
+                    builder.set_avg_latency_ms(point.avg_latency_ms);
+                    builder.set_p50_latency_ms(point.p50_latency_ms);
+                    builder.set_p95_latency_ms(point.p95_latency_ms);
+                    builder.set_p99_latency_ms(point.p99_latency_ms);
+                    builder.set_bytes(point.bytes);
+                    builder.set_throughput_mbps(point.throughput_mbps);
+                    builder.set_cache_hit_rate(point.cache_hit_rate.unwrap_or(-1.0));
+                }
+            }
+            Err(e) => {
+                results.set_success(false);
+                results.set_error_msg(e.to_string().as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_active_transfers(
+        self: Rc<Self>,
+        _: node_service::GetActiveTransfersParams,
+        mut results: node_service::GetActiveTransfersResults,
+    ) -> Result<(), capnp::Error> {
+        let active = self.active_transfers();
+        let mut transfers = results.get().init_transfers(active.len() as u32);
+        for (i, progress) in active.iter().enumerate() {
+            fill_progress(transfers.reborrow().get(i as u32), progress);
+        }
+        Ok(())
+    }
+
+    async fn set_limit(
+        self: Rc<Self>,
+        params: node_service::SetLimitParams,
+        mut results: node_service::SetLimitResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let name = params.get_name()?.to_str()?;
+        let changed = NodeServiceImpl::set_limit(&self, name, params.get_limit());
+
+        let mut results = results.get();
+        match changed {
+            Ok(_) => results.set_success(true),
+            Err(e) => {
+                results.set_success(false);
+                results.set_error_msg(e.to_string().as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_limits(
+        self: Rc<Self>,
+        _: node_service::GetLimitsParams,
+        mut results: node_service::GetLimitsResults,
+    ) -> Result<(), capnp::Error> {
+        let stats =
+            NodeServiceImpl::get_limits(&self).map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut limits = results.get().init_limits(stats.len() as u32);
+        for (i, stat) in stats.iter().enumerate() {
+            let mut builder = limits.reborrow().get(i as u32);
+            builder.set_name(stat.name.to_string().as_str());
+            builder.set_limit(stat.limit as u32);
+            builder.set_in_use(stat.in_use as u32);
+            builder.set_peak(stat.peak as u32);
+            builder.set_waiting(stat.waiting as u32);
+        }
+        Ok(())
+    }
+
+    async fn get_manifest_digests(
+        self: Rc<Self>,
+        _: node_service::GetManifestDigestsParams,
+        mut results: node_service::GetManifestDigestsResults,
+    ) -> Result<(), capnp::Error> {
+        let digests = NodeServiceImpl::get_manifest_digests(&self)
+            .await
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut list = results.get().init_digests(digests.len() as u32);
+        for (i, digest) in digests.iter().enumerate() {
+            let mut builder = list.reborrow().get(i as u32);
+            builder.set_file_hash(digest.file_hash.as_str());
+            builder.set_timestamp(digest.timestamp);
+            builder.set_digest(digest.digest.as_str());
+        }
+        Ok(())
+    }
+
+    async fn sync_manifests(
+        self: Rc<Self>,
+        params: node_service::SyncManifestsParams,
+        mut results: node_service::SyncManifestsResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let push = params
+            .get_push()?
+            .iter()
+            .map(read_record)
+            .collect::<capnp::Result<Vec<_>>>()?;
+        let want = params
+            .get_want()?
+            .iter()
+            .map(|hash| Ok(hash?.to_string()?))
+            .collect::<capnp::Result<Vec<_>>>()?;
+        let (records, accepted) = NodeServiceImpl::sync_manifests(&self, &push, &want)
+            .await
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+
+        let mut results = results.get();
+        results.set_accepted(accepted as u32);
+        let mut list = results.init_records(records.len() as u32);
+        for (i, record) in records.iter().enumerate() {
+            fill_record(list.reborrow().get(i as u32), record);
+        }
+        Ok(())
+    }
+
+    async fn get_node_stats(
+        self: Rc<Self>,
+        _: node_service::GetNodeStatsParams,
+        mut results: node_service::GetNodeStatsResults,
+    ) -> Result<(), capnp::Error> {
+        let stats = self
+            .get_go_client_stats()
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut go_client = results.get().init_stats().init_go_client();
+        go_client.set_connected(stats.connected);
+        go_client.set_circuit(stats.circuit.as_str());
+        go_client.set_pool_size(stats.pool_size as u32);
+        go_client.set_healthy_connections(stats.healthy_connections as u32);
+        go_client.set_requests(stats.requests);
+        go_client.set_failures(stats.failures);
+        go_client.set_timeouts(stats.timeouts);
+        go_client.set_reconnects(stats.reconnects);
+        go_client.set_circuit_trips(stats.circuit_trips);
+        Ok(())
+    }
+
+    /// Stage the file the caller writes to the returned stream; `finish`
+    /// uploads it
+    async fn put_stream(
+        self: Rc<Self>,
+        params: node_service::PutStreamParams,
+        mut results: node_service::PutStreamResults,
+    ) -> Result<(), capnp::Error> {
+        let request = params.get()?.get_request()?;
+        let recipients = request
+            .get_recipients()?
+            .iter()
+            .map(|key| {
+                parse_public_key(key?.to_str()?)
+                    .map_err(|e| capnp::Error::failed(format!("{:#}", e)))
+            })
+            .collect::<capnp::Result<Vec<_>>>()?;
+        let options = UploadOptions {
+            passphrase: non_empty(request.get_passphrase()?.to_str()?),
+            recipients,
+            storage_class: non_empty(request.get_storage_class()?.to_str()?),
+            force: request.get_force(),
+        };
+        let stream = UploadStreamImpl::open(
+            self.clone(),
+            non_empty(request.get_token()?.to_str()?),
+            request.get_namespace()?.to_string()?,
+            wire_priority(request.get_priority()?.to_str()?)?,
+            options,
+            request.get_file_name()?.to_str()?,
+        )
+        .await
+        .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        results.get().set_stream(capnp_rpc::new_client(stream));
+        Ok(())
+    }
+
+    /// Download a file, then write it to the caller's sink in chunks
+    async fn get_stream(
+        self: Rc<Self>,
+        params: node_service::GetStreamParams,
+        mut results: node_service::GetStreamResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let request = params.get_request()?;
+        let sink = params.get_sink()?;
+        let token = non_empty(request.get_token()?.to_str()?);
+        let call = DownloadCall {
+            file_hash: request.get_file_hash()?.to_string()?,
+            namespace: request.get_namespace()?.to_string()?,
+            priority: wire_priority(request.get_priority()?.to_str()?)?,
+        };
+        let (_staging, result) = self
+            .download_staged(token.as_deref(), &call)
+            .await
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+
+        let mut file = tokio::fs::File::open(&result.output_path)
+            .await
+            .map_err(|e| capnp::Error::failed(format!("Failed to read download: {}", e)))?;
+        let mut chunk = vec![0u8; STREAM_CHUNK_BYTES];
+        loop {
+            let read = file
+                .read(&mut chunk)
+                .await
+                .map_err(|e| capnp::Error::failed(format!("Failed to read download: {}", e)))?;
+            if read == 0 {
+                break;
+            }
+            let mut write = sink.write_request();
+            write.get().set_chunk(&chunk[..read]);
+            write.send().promise.await?;
+        }
+
+        let mut reply = results.get().init_result();
+        reply.set_file_hash(result.file_hash.as_str());
+        reply.set_file_name(result.file_name.as_str());
+        reply.set_bytes(result.bytes_written as u64);
+        reply.set_shards_fetched(result.shards_fetched as u32);
+        Ok(())
+    }
+
+    async fn list_files(
+        self: Rc<Self>,
+        params: node_service::ListFilesParams,
+        mut results: node_service::ListFilesResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let token = non_empty(params.get_token()?.to_str()?);
+        let files = NodeApi::list(&*self, token.as_deref(), params.get_namespace()?.to_str()?)
+            .await
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut list = results.get().init_files(files.len() as u32);
+        for (i, file) in files.iter().enumerate() {
+            fill_stored_file(list.reborrow().get(i as u32), file);
+        }
+        Ok(())
+    }
+
+    async fn search_files(
+        self: Rc<Self>,
+        params: node_service::SearchFilesParams,
+        mut results: node_service::SearchFilesResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let token = non_empty(params.get_token()?.to_str()?);
+        let files = NodeApi::search(
+            &*self,
+            token.as_deref(),
+            params.get_namespace()?.to_str()?,
+            params.get_pattern()?.to_str()?,
+        )
+        .await
+        .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut list = results.get().init_files(files.len() as u32);
+        for (i, file) in files.iter().enumerate() {
+            fill_stored_file(list.reborrow().get(i as u32), file);
+        }
+        Ok(())
+    }
+
+    async fn get_file_info(
+        self: Rc<Self>,
+        params: node_service::GetFileInfoParams,
+        mut results: node_service::GetFileInfoResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let token = non_empty(params.get_token()?.to_str()?);
+        let file = NodeApi::info(
+            &*self,
+            token.as_deref(),
+            params.get_namespace()?.to_str()?,
+            params.get_file_hash()?.to_str()?,
+        )
+        .await
+        .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        let mut results = results.get();
+        if let Some(file) = file {
+            results.set_found(true);
+            fill_stored_file(results.init_file(), &file);
+        }
+        Ok(())
+    }
+}
+
+/// A file being written through `putStream`, staged until `finish`
+struct UploadStreamImpl {
+    service: Rc<NodeServiceImpl>,
+    token: Option<String>,
+    namespace: String,
+    priority: TransferPriority,
+    options: UploadOptions,
+    path: PathBuf,
+    /// Writes run one at a time, in the order they arrived
+    file: tokio::sync::Mutex<tokio::fs::File>,
+    finished: Cell<bool>,
+    _staging: StagingDir,
+}
+
+impl UploadStreamImpl {
+    async fn open(
+        service: Rc<NodeServiceImpl>,
+        token: Option<String>,
+        namespace: String,
+        priority: TransferPriority,
+        options: UploadOptions,
+        file_name: &str,
+    ) -> Result<Self, ApiError> {
+        service.admit("putStream")?;
+        service.authorize(token.as_deref(), &namespace)?;
+        let staging = service.transfers()?.stage().await?;
+        let path = staging.file(file_name)?;
+        let file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to stage upload: {}", e)))?;
+        Ok(Self {
+            service,
+            token,
+            namespace,
+            priority,
+            options,
+            path,
+            file: tokio::sync::Mutex::new(file),
+            finished: Cell::new(false),
+            _staging: staging,
+        })
+    }
+
+    async fn upload(&self) -> Result<UploadResult, ApiError> {
+        let transfers = self.service.transfers()?;
+        let uploader = transfers
+            .uploader_with(&self.namespace, &self.service.requester(), &self.options)
+            .await?;
+        Ok(uploader
+            .upload_with_priority(&self.path, self.priority)
+            .await?)
+    }
+}
+
+impl upload_stream::Server for UploadStreamImpl {
+    async fn write(
+        self: Rc<Self>,
+        params: upload_stream::WriteParams,
+        _: upload_stream::WriteResults,
+    ) -> Result<(), capnp::Error> {
+        let chunk = params.get()?.get_chunk()?;
+        let mut file = self.file.lock().await;
+        if self.finished.get() {
+            return Err(capnp::Error::failed("Upload already finished".into()));
+        }
+        file.write_all(chunk)
+            .await
+            .map_err(|e| capnp::Error::failed(format!("Failed to stage upload: {}", e)))
+    }
+
+    async fn finish(
+        self: Rc<Self>,
+        _: upload_stream::FinishParams,
+        mut results: upload_stream::FinishResults,
+    ) -> Result<(), capnp::Error> {
+        {
+            let mut file = self.file.lock().await;
+            if self.finished.replace(true) {
+                return Err(capnp::Error::failed("Upload already finished".into()));
+            }
+            file.flush()
+                .await
+                .map_err(|e| capnp::Error::failed(format!("Failed to stage upload: {}", e)))?;
+        }
+        let uploaded = self
+            .upload()
+            .await
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+
+        let mut result = results.get().init_result();
+        result.set_file_hash(uploaded.file_hash.as_str());
+        result.set_manifest_json(uploaded.manifest_json.as_str());
+        result.set_shard_count(uploaded.shard_count as u32);
+        result.set_total_peers(uploaded.total_peers as u32);
+        result.set_deduplicated(uploaded.deduplicated);
+        Ok(())
+    }
+}
+
+/// Writes a `getStream` file to disk as the daemon sends it
+struct DownloadSinkImpl {
+    file: std::rc::Rc<tokio::sync::Mutex<tokio::fs::File>>,
+}
+
+impl download_sink::Server for DownloadSinkImpl {
+    async fn write(
+        self: Rc<Self>,
+        params: download_sink::WriteParams,
+        _: download_sink::WriteResults,
+    ) -> Result<(), capnp::Error> {
+        let chunk = params.get()?.get_chunk()?;
+        self.file
+            .lock()
+            .await
+            .write_all(chunk)
+            .await
+            .map_err(|e| capnp::Error::failed(format!("Failed to write download: {}", e)))
+    }
+}
+
+/// Push one snapshot to a caller's `ProgressListener`
+async fn notify_listener(listener: &progress_listener::Client, progress: &TransferProgress) {
+    let mut request = listener.on_progress_request();
+    fill_progress(request.get().init_progress(), progress);
+    if let Err(e) = request.send().promise.await {
+        debug!(
+            "Progress listener for {} failed: {}",
+            progress.transfer_id, e
+        );
+    }
+}
+
+/// Files in flight at once for a batch call; zero picks the default
+fn batch_concurrency(requested: usize) -> usize {
+    if requested == 0 {
+        DEFAULT_BATCH_CONCURRENCY
+    } else {
+        requested
+    }
+}
+
+fn fill_progress(mut builder: transfer_progress::Builder<'_>, progress: &TransferProgress) {
+    builder.set_transfer_id(progress.transfer_id.as_str());
+    builder.set_is_upload(progress.direction == TransferDirection::Upload);
+    builder.set_shards_done(progress.shards_done as u32);
+    builder.set_shards_total(progress.shards_total as u32);
+    builder.set_bytes_done(progress.bytes_done);
+    builder.set_bytes_per_sec(progress.bytes_per_sec);
+    builder.set_percent(progress.percent());
+    if let Some(warning) = &progress.warning {
+        builder.set_warning(warning.as_str());
+    }
+    builder.set_finished(progress.finished);
+    builder.set_success(progress.success);
+    builder.set_bytes_total(progress.bytes_total.unwrap_or(0));
+    builder.set_eta_secs(progress.eta_secs.unwrap_or(-1.0));
+}
+
+/// `None` for a text field left empty, which requests use for "not set"
+fn non_empty(text: &str) -> Option<String> {
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Priority named in a request; empty means interactive
+fn wire_priority(name: &str) -> Result<TransferPriority, capnp::Error> {
+    if name.is_empty() {
+        return Ok(TransferPriority::Interactive);
+    }
+    name.parse().map_err(capnp::Error::failed)
+}
+
+fn fill_stored_file(mut builder: stored_file::Builder<'_>, file: &FileInfo) {
+    builder.set_file_hash(file.file_hash.as_str());
+    builder.set_file_name(file.file_name.as_str());
+    builder.set_file_size(file.file_size as u64);
+    builder.set_shard_count(file.shard_count as u32);
+    builder.set_is_available(file.is_available);
+    builder.set_timestamp(file.timestamp);
+    if let SearchSource::Dht { publisher } = &file.source {
+        builder.set_publisher(publisher.as_str());
+    }
+}
+
+fn read_stored_file(file: stored_file::Reader<'_>) -> capnp::Result<FileInfo> {
+    let publisher = file.get_publisher()?.to_str()?;
+    Ok(FileInfo {
+        file_hash: file.get_file_hash()?.to_string()?,
+        file_name: file.get_file_name()?.to_string()?,
+        file_size: file.get_file_size() as usize,
+        shard_count: file.get_shard_count() as usize,
+        is_available: file.get_is_available(),
+        timestamp: file.get_timestamp(),
+        source: match publisher {
+            "" => SearchSource::LocalCache,
+            publisher => SearchSource::Dht {
+                publisher: publisher.to_string(),
+            },
+        },
+    })
+}
+
+fn fill_manifest(mut builder: file_manifest::Builder<'_>, manifest: &FileManifest) {
+    builder.set_file_hash(manifest.file_hash.as_str());
+    builder.set_file_name(manifest.file_name.as_str());
+    builder.set_file_size(manifest.file_size as u64);
+    builder.set_shard_count(manifest.shard_count as u32);
+    builder.set_parity_count(manifest.parity_count as u32);
+    builder.set_timestamp(manifest.timestamp);
+    builder.set_ttl(manifest.ttl.min(u32::MAX as u64) as u32);
+    let mut locations = builder.init_shard_locations(manifest.shard_locations.len() as u32);
+    for (i, (shard_index, peer_id)) in manifest.shard_locations.iter().enumerate() {
+        let mut location = locations.reborrow().get(i as u32);
+        location.set_shard_index(*shard_index as u32);
+        location.set_peer_id(*peer_id);
+    }
+}
+
+/// Forward snapshots for `transfer_id` to `on_progress`
+///
+/// Returns the final snapshot, or `None` if the hub closed first. Snapshots
+/// dropped because the listener fell behind are skipped; the next one still
+/// carries cumulative counts.
+pub async fn forward_progress<F, Fut>(
+    mut receiver: broadcast::Receiver<TransferProgress>,
+    transfer_id: &str,
+    mut on_progress: F,
+) -> Result<Option<TransferProgress>>
+where
+    F: FnMut(TransferProgress) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    loop {
+        match receiver.recv().await {
+            Ok(progress) if progress.transfer_id == transfer_id => {
+                let finished = progress.finished;
+                on_progress(progress.clone()).await?;
+                if finished {
+                    return Ok(Some(progress));
+                }
+            }
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Progress listener for {} lagged, skipped {} updates",
+                    transfer_id, skipped
+                );
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(None),
+        }
+    }
+}
+
+/// Client of a running daemon's RPC server
+///
+/// The CLI's put, get, list, search and info go through it, sharing the
+/// daemon's peers and cache. Files are streamed in chunks, so they may be
+/// any size. Use it within a `LocalSet`.
+pub struct DaemonClient {
+    client: node_service::Client,
+    token: String,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon serving RPC on `addr`
+    ///
+    /// Fails when nothing answers there within a couple of seconds.
+    pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+        use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+        let connect = tokio::time::timeout(DAEMON_CONNECT_TIMEOUT, TcpStream::connect(addr));
+        let stream = match connect.await {
+            Ok(stream) => {
+                stream.with_context(|| format!("No daemon is serving RPC on {}", addr))?
+            }
+            Err(_) => anyhow::bail!("No daemon answered on {} in time", addr),
+        };
+        let (reader, writer) = tokio::io::split(stream);
+        let network = twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        );
+        let mut rpc_system = RpcSystem::new(Box::new(network), None);
+        let client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+        tokio::task::spawn_local(async move {
+            if let Err(e) = rpc_system.await {
+                debug!("Daemon connection closed: {}", e);
+            }
+        });
+        Ok(Self {
+            client,
+            token: String::new(),
+        })
+    }
+
+    /// Present `token` for namespaced calls
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = token.to_string();
+        self
+    }
+
+    /// Upload the file at `path`
+    pub async fn put(
+        &self,
+        path: &Path,
+        namespace: &str,
+        priority: TransferPriority,
+        options: &UploadOptions,
+    ) -> Result<UploadResult> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("{:?} names no file", path))?;
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("Failed to open {:?}", path))?;
+
+        let mut request = self.client.put_stream_request();
+        {
+            let mut put = request.get().init_request();
+            put.set_file_name(file_name);
+            put.set_namespace(namespace);
+            put.set_priority(priority.to_string().as_str());
+            put.set_token(self.token.as_str());
+            if let Some(passphrase) = &options.passphrase {
+                put.set_passphrase(passphrase.as_str());
+            }
+            let mut recipients = put
+                .reborrow()
+                .init_recipients(options.recipients.len() as u32);
+            for (i, key) in options.recipients.iter().enumerate() {
+                recipients.set(i as u32, hex::encode(key.as_bytes()).as_str());
+            }
+            if let Some(class) = &options.storage_class {
+                put.set_storage_class(class.as_str());
+            }
+            put.set_force(options.force);
+        }
+        let response = request.send().promise.await?;
+        let stream = response.get()?.get_stream()?;
+
+        let mut chunk = vec![0u8; STREAM_CHUNK_BYTES];
+        loop {
+            let read = file.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            let mut write = stream.write_request();
+            write.get().set_chunk(&chunk[..read]);
+            write.send().promise.await?;
+        }
+        let response = stream.finish_request().send().promise.await?;
+        let result = response.get()?.get_result()?;
+        Ok(UploadResult {
+            file_hash: result.get_file_hash()?.to_string()?,
+            manifest_json: result.get_manifest_json()?.to_string()?,
+            shard_count: result.get_shard_count() as usize,
+            total_peers: result.get_total_peers() as usize,
+            transfers: Vec::new(),
+            deduplicated: result.get_deduplicated(),
+        })
+    }
+
+    /// Download a file to `output`, or under its own name in the current
+    /// directory
+    ///
+    /// The file is written next to where it ends up and renamed into place
+    /// once complete.
+    pub async fn get(&self, call: &DownloadCall, output: Option<&Path>) -> Result<DownloadResult> {
+        let dir = output
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let mut id = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut id);
+        let partial = dir.join(format!(".pangea-{}.partial", hex::encode(id)));
+        let file = tokio::fs::File::create(&partial)
+            .await
+            .with_context(|| format!("Failed to create {:?}", partial))?;
+
+        let result = match self.receive(call, file, &partial).await {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(e);
+            }
+        };
+        let output_path = match output {
+            Some(path) => path.to_path_buf(),
+            None => match Path::new(&result.file_name).file_name() {
+                Some(name) => PathBuf::from(name),
+                None => PathBuf::from(&call.file_hash),
+            },
+        };
+        tokio::fs::rename(&partial, &output_path)
+            .await
+            .with_context(|| format!("Failed to move download to {:?}", output_path))?;
+        Ok(DownloadResult {
+            output_path,
+            ..result
+        })
+    }
+
+    /// Run `getStream` into `file`, checking every byte arrived
+    async fn receive(
+        &self,
+        call: &DownloadCall,
+        file: tokio::fs::File,
+        partial: &Path,
+    ) -> Result<DownloadResult> {
+        let file = std::rc::Rc::new(tokio::sync::Mutex::new(file));
+        let mut request = self.client.get_stream_request();
+        {
+            let mut get = request.get().init_request();
+            get.set_file_hash(call.file_hash.as_str());
+            get.set_namespace(call.namespace.as_str());
+            get.set_priority(call.priority.to_string().as_str());
+            get.set_token(self.token.as_str());
+        }
+        request
+            .get()
+            .set_sink(capnp_rpc::new_client(DownloadSinkImpl {
+                file: file.clone(),
+            }));
+        let response = request.send().promise.await?;
+        let result = response.get()?.get_result()?;
+        file.lock().await.flush().await?;
+
+        let bytes = result.get_bytes();
+        let received = tokio::fs::metadata(partial).await?.len();
+        if received != bytes {
+            anyhow::bail!("Daemon sent {} of {} bytes", received, bytes);
+        }
+        Ok(DownloadResult {
+            file_hash: result.get_file_hash()?.to_string()?,
+            file_name: result.get_file_name()?.to_string()?,
+            bytes_written: bytes as usize,
+            shards_fetched: result.get_shards_fetched() as usize,
+            output_path: partial.to_path_buf(),
+            peers: Vec::new(),
+        })
+    }
+
+    pub async fn list(&self, namespace: &str) -> Result<Vec<FileInfo>> {
+        let mut request = self.client.list_files_request();
+        request.get().set_namespace(namespace);
+        request.get().set_token(self.token.as_str());
+        let response = request.send().promise.await?;
+        Ok(response
+            .get()?
+            .get_files()?
+            .iter()
+            .map(read_stored_file)
+            .collect::<capnp::Result<_>>()?)
+    }
+
+    pub async fn search(&self, namespace: &str, pattern: &str) -> Result<Vec<FileInfo>> {
+        let mut request = self.client.search_files_request();
+        request.get().set_namespace(namespace);
+        request.get().set_pattern(pattern);
+        request.get().set_token(self.token.as_str());
+        let response = request.send().promise.await?;
+        Ok(response
+            .get()?
+            .get_files()?
+            .iter()
+            .map(read_stored_file)
+            .collect::<capnp::Result<_>>()?)
+    }
+
+    pub async fn info(&self, namespace: &str, file_hash: &str) -> Result<Option<FileInfo>> {
+        let mut request = self.client.get_file_info_request();
+        request.get().set_namespace(namespace);
+        request.get().set_file_hash(file_hash);
+        request.get().set_token(self.token.as_str());
+        let response = request.send().promise.await?;
+        let results = response.get()?;
+        if !results.get_found() {
+            return Ok(None);
+        }
+        Ok(Some(read_stored_file(results.get_file()?)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::TransferDirection;
+
+    #[tokio::test]
+    async fn test_forward_progress_filters_by_transfer() {
+        let hub = ProgressHub::default();
+        let receiver = hub.subscribe();
+
+        let mut other = hub.track("other", TransferDirection::Upload, 1);
+        let mut ours = hub.track("ours", TransferDirection::Upload, 2);
+        other.shard_done(5);
+        ours.shard_done(10);
+        ours.warn("slow peer");
+        ours.shard_done(10);
+        ours.finish(true);
+
+        let mut seen = Vec::new();
+        let last = forward_progress(receiver, "ours", |progress| {
+            seen.push(progress);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(seen.len(), 4);
+        assert!(seen.iter().all(|p| p.transfer_id == "ours"));
+        assert_eq!(seen[1].warning.as_deref(), Some("slow peer"));
+        assert!(last.success);
+        assert_eq!(last.bytes_done, 20);
+        drop(other);
+    }
+
+    /// Records (transfer ID, shards done, finished) of every snapshot
+    struct RecordingListener(std::rc::Rc<std::cell::RefCell<Vec<(String, u32, bool)>>>);
+
+    impl progress_listener::Server for RecordingListener {
+        async fn on_progress(
+            self: Rc<Self>,
+            params: progress_listener::OnProgressParams,
+            _: progress_listener::OnProgressResults,
+        ) -> Result<(), capnp::Error> {
+            let progress = params.get()?.get_progress()?;
+            self.0.borrow_mut().push((
+                progress.get_transfer_id()?.to_str()?.to_string(),
+                progress.get_shards_done(),
+                progress.get_finished(),
+            ));
+            Ok(())
+        }
+    }
+
+    async fn service() -> NodeServiceImpl {
+        let network = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        NodeServiceImpl::new(Arc::new(NodeStore::new()), Arc::new(network))
+    }
+
+    #[tokio::test]
+    async fn test_relay_progress_pushes_snapshots_to_listener() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let service = service().await;
+                let hub = service.progress.clone();
+                let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+                let listener: progress_listener::Client =
+                    capnp_rpc::new_client(RecordingListener(seen.clone()));
+
+                let output = service
+                    .relay_progress("ours", &listener, async {
+                        let mut other = hub.track("other", TransferDirection::Upload, 1);
+                        let mut ours = hub.track("ours", TransferDirection::Upload, 2);
+                        other.shard_done(5);
+                        ours.shard_done(10);
+                        ours.shard_done(10);
+                        ours.finish(true);
+                        drop(other);
+                        7
+                    })
+                    .await;
+
+                assert_eq!(output, 7);
+                let seen = seen.borrow();
+                assert_eq!(
+                    *seen,
+                    vec![
+                        ("ours".to_string(), 1, false),
+                        ("ours".to_string(), 2, false),
+                        ("ours".to_string(), 2, true),
+                    ]
+                );
+            })
+            .await;
+    }
+
+    /// Serve `service` over an in-memory connection, returning the client
+    ///
+    /// Call from inside a `LocalSet`.
+    fn connect(service: NodeServiceImpl) -> node_service::Client {
+        use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+        use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::task::spawn_local(handle_rpc_connection(server_io, service));
+
+        let (reader, writer) = tokio::io::split(client_io);
+        let network = twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        );
+        let mut rpc_system = RpcSystem::new(Box::new(network), None);
+        let client = rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+        tokio::task::spawn_local(rpc_system);
+        client
+    }
+
+    #[tokio::test]
+    async fn test_rpc_connection_serves_node_service() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let client = connect(service().await);
+
+                let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+                let mut request = client.upload_with_progress_request();
+                request.get().init_request().set_data(&b"hello"[..]);
+                request
+                    .get()
+                    .set_listener(capnp_rpc::new_client(RecordingListener(seen.clone())));
+                let reply = request.send().promise.await.unwrap();
+                let response = reply.get().unwrap().get_response().unwrap();
+
+                // No transfers are configured, but the call reached the service
+                assert!(!response.get_success());
+                assert_eq!(
+                    response.get_error_msg().unwrap().to_str().unwrap(),
+                    "Transfers are not enabled on this node"
+                );
+                assert!(seen.borrow().is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_failure_is_reported_per_file() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let client = connect(service().await);
+
+                let mut request = client.put_many_request();
+                {
+                    let mut requests = request.get().init_requests(2);
+                    requests.reborrow().get(0).set_data(&b"one"[..]);
+                    requests.reborrow().get(1).set_data(&b"two"[..]);
+                }
+                let reply = request.send().promise.await.unwrap();
+                let responses = reply.get().unwrap().get_responses().unwrap();
+                assert_eq!(responses.len(), 2);
+                for response in responses.iter() {
+                    assert!(!response.get_success());
+                    assert_eq!(
+                        response.get_error_msg().unwrap().to_str().unwrap(),
+                        "Transfers are not enabled on this node"
+                    );
+                }
+
+                let mut request = client.get_many_request();
+                request.get().init_requests(1).get(0).set_file_hash("abc");
+                let reply = request.send().promise.await.unwrap();
+                let responses = reply.get().unwrap().get_responses().unwrap();
+                assert_eq!(responses.len(), 1);
+                assert!(!responses.get(0).get_success());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_metrics_history_over_rpc() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = Arc::new(MetricsHistory::open(dir.path()).unwrap());
+        let start = 1_700_000_040; // Start of a minute
+        history.record_latency("fetch", 12.0, start + 5);
+        history.flush(start + 60).await.unwrap();
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let client = connect(service().await.with_metrics_history(history));
+
+                let mut request = client.get_metrics_history_request();
+                request.get().set_operation("fetch");
+                request.get().set_from_unix(start);
+                request.get().set_to_unix(start + 60);
+                let reply = request.send().promise.await.unwrap();
+                let reply = reply.get().unwrap();
+                assert!(reply.get_success());
+                let points = reply.get_points().unwrap();
+                assert_eq!(points.len(), 1);
+                assert_eq!(points.get(0).get_minute(), start);
+                assert_eq!(points.get(0).get_samples(), 1);
+                assert_eq!(points.get(0).get_cache_hit_rate(), -1.0);
+
+                // Without a history the reply says so
+                let client = connect(service().await);
+                let reply = client
+                    .get_metrics_history_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+                let reply = reply.get().unwrap();
+                assert!(!reply.get_success());
+                assert_eq!(
+                    reply.get_error_msg().unwrap().to_str().unwrap(),
+                    "Metrics history is not enabled on this node"
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_active_transfers_over_rpc() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let service = service().await;
+                let hub = service.progress.clone();
+                let client = connect(service);
+
+                let mut upload = hub.track("inflight", TransferDirection::Upload, 4);
+                upload.shard_done(10);
+                hub.track("done", TransferDirection::Download, 1)
+                    .finish(true);
+
+                let reply = client
+                    .get_active_transfers_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+                let transfers = reply.get().unwrap().get_transfers().unwrap();
+                assert_eq!(transfers.len(), 1);
+                let transfer = transfers.get(0);
+                assert_eq!(
+                    transfer.get_transfer_id().unwrap().to_str().unwrap(),
+                    "inflight"
+                );
+                assert!(transfer.get_is_upload());
+                assert_eq!(transfer.get_shards_done(), 1);
+                assert_eq!(transfer.get_shards_total(), 4);
+                assert_eq!(transfer.get_percent(), 25.0);
+                drop(upload);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_limits_over_rpc() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let limits = Arc::new(ConcurrencyLimits::default());
+                let client = connect(service().await.with_concurrency_limits(limits.clone()));
+
+                let mut request = client.set_limit_request();
+                request.get().set_name("uploads");
+                request.get().set_limit(3);
+                let reply = request.send().promise.await.unwrap();
+                assert!(reply.get().unwrap().get_success());
+                assert_eq!(limits.stats_for(LimitName::Uploads).limit, 3);
+
+                let mut request = client.set_limit_request();
+                request.get().set_name("bogus");
+                request.get().set_limit(3);
+                let reply = request.send().promise.await.unwrap();
+                assert!(!reply.get().unwrap().get_success());
+
+                let reply = client.get_limits_request().send().promise.await.unwrap();
+                let reported = reply.get().unwrap().get_limits().unwrap();
+                assert_eq!(reported.len() as usize, LimitName::ALL.len());
+                let uploads = reported
+                    .iter()
+                    .find(|l| l.get_name().unwrap().to_str().unwrap() == "uploads")
+                    .unwrap();
+                assert_eq!(uploads.get_limit(), 3);
+                assert_eq!(uploads.get_in_use(), 0);
+            })
+            .await;
+    }
+
+    fn manifest(file_hash: &str, timestamp: i64) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.txt", file_hash),
+            file_size: 1024,
+            shard_count: 4,
+            parity_count: 2,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3), (3, 4)],
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_go_client_syncs_manifests_over_rpc() {
+        use crate::go_client::PoolConfig;
+        use crate::identity::NodeIdentity;
+        use crate::manifest_sync::ManifestSyncConfig;
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (dir_a, dir_b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+                let served_cache = Arc::new(Cache::new(dir_a.path(), 100, 1024 * 1024).unwrap());
+                let our_cache = Arc::new(Cache::new(dir_b.path(), 100, 1024 * 1024).unwrap());
+                served_cache
+                    .put_manifest(manifest("served", 10))
+                    .await
+                    .unwrap();
+                our_cache.put_manifest(manifest("ours", 10)).await.unwrap();
+                let served = ManifestSync::new(
+                    ManifestSyncConfig::default(),
+                    NodeIdentity::from_secret([1; 32]),
+                    served_cache.clone(),
+                )
+                .unwrap();
+                let ours = ManifestSync::new(
+                    ManifestSyncConfig::default(),
+                    NodeIdentity::from_secret([2; 32]),
+                    our_cache.clone(),
+                )
+                .unwrap();
+
+                // The served node stands in for the Go node
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                let service = service().await.with_manifest_sync(Arc::new(served));
+                tokio::task::spawn_local(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    handle_rpc_connection(stream, service).await
+                });
+                let go_client = GoClient::with_pool_config(
+                    addr,
+                    PoolConfig {
+                        size: 1,
+                        ..Default::default()
+                    },
+                );
+                go_client.connect().await.unwrap();
+
+                let outcome = ours.sync_with(&go_client).await.unwrap();
+                assert_eq!(
+                    (outcome.pushed, outcome.pulled, outcome.rejected),
+                    (1, 1, 0)
+                );
+                assert!(served_cache.get_manifest("ours").await.is_some());
+                assert!(our_cache.get_manifest("served").await.is_some());
+
+                // Nothing differs on the second round
+                let outcome = ours.sync_with(&go_client).await.unwrap();
+                assert_eq!(outcome.pushed + outcome.pulled, 0);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_daemon_client_streams_files() {
+        use crate::ces::CesPipeline;
+        use crate::simulation::{LinkConditions, SimulatedNetwork};
+        use crate::types::CesConfig;
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let dir = tempfile::tempdir().unwrap();
+                let store = Arc::new(NodeStore::new());
+                for id in 1..=4 {
+                    store.upsert_node(Node::new(id)).await;
+                }
+                let cache =
+                    Arc::new(Cache::new(dir.path().join("cache"), 100, 64 * 1024 * 1024).unwrap());
+                let transfers = Transfers::new(
+                    Arc::new(CesPipeline::new(CesConfig::default())),
+                    Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap())),
+                    cache,
+                    store.clone(),
+                    dir.path().join("staging"),
+                )
+                .with_transport(Arc::new(
+                    SimulatedNetwork::new(LinkConditions::default()).with_peers(1..=4),
+                ));
+                let network = QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+                    .await
+                    .unwrap();
+                let service = NodeServiceImpl::new(store, Arc::new(network))
+                    .with_transfers(Arc::new(transfers));
+
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::task::spawn_local(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    handle_rpc_connection(stream, service).await
+                });
+                let client = DaemonClient::connect(addr).await.unwrap();
+
+                // Several chunks each way
+                let data: Vec<u8> = (0..3 * STREAM_CHUNK_BYTES + 100)
+                    .map(|i| (i % 251) as u8)
+                    .collect();
+                let source = dir.path().join("video.bin");
+                tokio::fs::write(&source, &data).await.unwrap();
+                let uploaded = client
+                    .put(
+                        &source,
+                        DEFAULT_NAMESPACE,
+                        TransferPriority::Interactive,
+                        &UploadOptions::default(),
+                    )
+                    .await
+                    .unwrap();
+
+                let files = client.list(DEFAULT_NAMESPACE).await.unwrap();
+                assert_eq!(files.len(), 1);
+                assert_eq!(files[0].file_name, "video.bin");
+                assert_eq!(files[0].source, SearchSource::LocalCache);
+                let info = client
+                    .info(DEFAULT_NAMESPACE, &uploaded.file_hash)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(info.file_size, data.len());
+                assert!(client
+                    .info(DEFAULT_NAMESPACE, "missing")
+                    .await
+                    .unwrap()
+                    .is_none());
+
+                let output = dir.path().join("copy.bin");
+                let call = DownloadCall {
+                    file_hash: uploaded.file_hash.clone(),
+                    namespace: DEFAULT_NAMESPACE.to_string(),
+                    priority: TransferPriority::Interactive,
+                };
+                let downloaded = client.get(&call, Some(&output)).await.unwrap();
+                assert_eq!(downloaded.output_path, output);
+                assert_eq!(downloaded.bytes_written, data.len());
+                assert_eq!(tokio::fs::read(&output).await.unwrap(), data);
+
+                // A failed download leaves nothing behind
+                let missing = DownloadCall {
+                    file_hash: "missing".to_string(),
+                    ..call
+                };
+                let output = dir.path().join("missing.bin");
+                assert!(client.get(&missing, Some(&output)).await.is_err());
+                let mut entries = tokio::fs::read_dir(dir.path()).await.unwrap();
+                while let Some(entry) = entries.next_entry().await.unwrap() {
+                    let name = entry.file_name().into_string().unwrap();
+                    assert!(!name.ends_with(".partial"), "left {}", name);
+                }
+                assert!(!output.exists());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_node_stats_report_go_client() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let go_client = Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap()));
+                let client = connect(service().await.with_go_client(go_client));
+
+                let reply = client
+                    .get_node_stats_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+                let stats = reply.get().unwrap().get_stats().unwrap();
+                let go_client = stats.get_go_client().unwrap();
+                assert!(!go_client.get_connected());
+                assert_eq!(go_client.get_circuit().unwrap().to_str().unwrap(), "closed");
+                assert_eq!(go_client.get_pool_size(), 2);
+                assert_eq!(go_client.get_healthy_connections(), 0);
+
+                // Without a client the call fails rather than reporting zeros
+                let client = connect(service().await);
+                assert!(client
+                    .get_node_stats_request()
+                    .send()
+                    .promise
+                    .await
+                    .is_err());
+            })
+            .await;
+    }
 }
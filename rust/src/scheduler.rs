@@ -0,0 +1,488 @@
+/// Transfer scheduling with priority classes
+///
+/// Uploads, downloads and heals share a bandwidth token bucket. Waiting
+/// transfers are served in weighted fair order (stride scheduling), so a
+/// background heal cannot starve an interactive get. For a while after a
+/// peer is lost, heal transfers are weighted like the heaviest class so its
+/// shard copies are rebuilt sooner.
+use crate::store::NodeStore;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Priority class of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum TransferPriority {
+    /// User is waiting on the result
+    #[default]
+    Interactive,
+    /// Non-urgent user transfers
+    Background,
+    /// Auto-heal repair traffic
+    Heal,
+    /// Speculative prefetching
+    Prefetch,
+}
+
+impl TransferPriority {
+    pub const ALL: [TransferPriority; 4] = [
+        TransferPriority::Interactive,
+        TransferPriority::Background,
+        TransferPriority::Heal,
+        TransferPriority::Prefetch,
+    ];
+
+    /// Default share of bandwidth relative to other classes
+    pub fn default_weight(&self) -> u32 {
+        match self {
+            TransferPriority::Interactive => 8,
+            TransferPriority::Background => 4,
+            TransferPriority::Heal => 2,
+            TransferPriority::Prefetch => 1,
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            TransferPriority::Interactive => 0,
+            TransferPriority::Background => 1,
+            TransferPriority::Heal => 2,
+            TransferPriority::Prefetch => 3,
+        }
+    }
+}
+
+impl fmt::Display for TransferPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransferPriority::Interactive => "interactive",
+            TransferPriority::Background => "background",
+            TransferPriority::Heal => "heal",
+            TransferPriority::Prefetch => "prefetch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TransferPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "interactive" => Ok(TransferPriority::Interactive),
+            "background" => Ok(TransferPriority::Background),
+            "heal" => Ok(TransferPriority::Heal),
+            "prefetch" => Ok(TransferPriority::Prefetch),
+            other => Err(format!(
+                "unknown priority '{}' (expected interactive, background, heal or prefetch)",
+                other
+            )),
+        }
+    }
+}
+
+/// Scheduler configuration
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Bandwidth budget in bytes per second (0 = unlimited)
+    pub bytes_per_sec: u64,
+    /// Maximum token bucket size in bytes
+    pub burst_bytes: u64,
+    /// Relative weight per class (indexed like `TransferPriority::ALL`)
+    pub weights: [u32; 4],
+    /// How long heal transfers are boosted after a peer is lost
+    pub heal_boost: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            bytes_per_sec: 0,
+            burst_bytes: 1024 * 1024, // 1MB
+            weights: TransferPriority::ALL.map(|p| p.default_weight()),
+            heal_boost: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// Limit bandwidth to the given rate with default burst and weights
+    pub fn with_rate(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            ..Default::default()
+        }
+    }
+}
+
+/// Per-class scheduler statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClassStats {
+    pub grants: u64,
+    pub bytes_granted: u64,
+    pub total_wait_ms: u64,
+}
+
+struct SchedulerState {
+    tokens: f64,
+    last_refill: Instant,
+    queues: [VecDeque<u64>; 4],
+    pass: [f64; 4],
+    virtual_time: f64,
+    next_ticket: u64,
+    stats: [ClassStats; 4],
+    heal_boost_until: Option<Instant>,
+}
+
+/// Weighted fair transfer scheduler
+pub struct TransferScheduler {
+    config: SchedulerConfig,
+    state: Mutex<SchedulerState>,
+    notify: Notify,
+}
+
+impl TransferScheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        let tokens = config.burst_bytes as f64;
+        Self {
+            config,
+            state: Mutex::new(SchedulerState {
+                tokens,
+                last_refill: Instant::now(),
+                queues: Default::default(),
+                pass: [0.0; 4],
+                virtual_time: 0.0,
+                next_ticket: 0,
+                stats: Default::default(),
+                heal_boost_until: None,
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Scheduler without a bandwidth limit (grants are immediate)
+    pub fn unlimited() -> Self {
+        Self::new(SchedulerConfig::default())
+    }
+
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+
+    /// Wait until `bytes` of bandwidth may be used by a transfer of this class
+    pub async fn acquire(&self, priority: TransferPriority, bytes: u64) {
+        let class = priority.index();
+        let started = Instant::now();
+
+        if self.config.bytes_per_sec == 0 {
+            self.state.lock().stats[class].record(bytes, started);
+            return;
+        }
+
+        let ticket = {
+            let mut state = self.state.lock();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            if state.queues[class].is_empty() {
+                // Idle classes don't bank credit while away
+                state.pass[class] = state.pass[class].max(state.virtual_time);
+            }
+            state.queues[class].push_back(ticket);
+            ticket
+        };
+        let mut guard = TicketGuard {
+            scheduler: self,
+            class,
+            ticket,
+            granted: false,
+        };
+
+        loop {
+            let notified = self.notify.notified();
+
+            let wait = {
+                let mut state = self.state.lock();
+                self.refill(&mut state);
+
+                if self.select(&state) == Some(class)
+                    && state.queues[class].front() == Some(&ticket)
+                {
+                    let needed = bytes.min(self.config.burst_bytes) as f64;
+                    if state.tokens >= needed {
+                        state.tokens -= bytes as f64;
+                        state.queues[class].pop_front();
+                        let weight = self.weight(&state, class);
+                        state.pass[class] += bytes as f64 / weight;
+                        state.virtual_time = state.pass[class];
+                        state.stats[class].record(bytes, started);
+                        guard.granted = true;
+                        None
+                    } else {
+                        let deficit = needed - state.tokens;
+                        Some(Some(Duration::from_secs_f64(
+                            deficit / self.config.bytes_per_sec as f64,
+                        )))
+                    }
+                } else {
+                    Some(None)
+                }
+            };
+
+            match wait {
+                None => {
+                    debug!("Granted {} bytes to {} transfer", bytes, priority);
+                    self.notify.notify_waiters();
+                    return;
+                }
+                Some(Some(delay)) => tokio::time::sleep(delay).await,
+                Some(None) => notified.await,
+            }
+        }
+    }
+
+    /// Weight heal transfers like the heaviest class for `heal_boost`
+    pub fn boost_heal(&self) {
+        self.state.lock().heal_boost_until = Some(Instant::now() + self.config.heal_boost);
+    }
+
+    /// Whether heal transfers are currently boosted
+    pub fn heal_boosted(&self) -> bool {
+        self.state
+            .lock()
+            .heal_boost_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Boost heal transfers whenever the store reports a peer lost
+    pub fn spawn_peer_watch(self: Arc<Self>, store: Arc<NodeStore>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut events = store.subscribe_events().await.skip_replay();
+            while let Some(peer_id) = events.next_peer_loss().await {
+                debug!("Peer {} lost, boosting heal transfers", peer_id);
+                self.boost_heal();
+            }
+        })
+    }
+
+    /// Statistics for one class
+    pub fn class_stats(&self, priority: TransferPriority) -> ClassStats {
+        self.state.lock().stats[priority.index()].clone()
+    }
+
+    /// Statistics for all classes
+    pub fn stats(&self) -> HashMap<TransferPriority, ClassStats> {
+        let state = self.state.lock();
+        TransferPriority::ALL
+            .iter()
+            .map(|p| (*p, state.stats[p.index()].clone()))
+            .collect()
+    }
+
+    /// Number of transfers currently waiting in a class
+    pub fn queued(&self, priority: TransferPriority) -> usize {
+        self.state.lock().queues[priority.index()].len()
+    }
+
+    /// Number of transfers waiting across all classes
+    pub fn backlog(&self) -> usize {
+        self.state.lock().queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn refill(&self, state: &mut SchedulerState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.last_refill = now;
+        state.tokens = (state.tokens + elapsed * self.config.bytes_per_sec as f64)
+            .min(self.config.burst_bytes as f64);
+    }
+
+    fn weight(&self, state: &SchedulerState, class: usize) -> f64 {
+        let boosted = class == TransferPriority::Heal.index()
+            && state
+                .heal_boost_until
+                .is_some_and(|until| Instant::now() < until);
+        let weight = if boosted {
+            self.config.weights.iter().copied().max().unwrap_or(1)
+        } else {
+            self.config.weights[class]
+        };
+        weight.max(1) as f64
+    }
+
+    /// Non-empty class with the lowest pass value
+    fn select(&self, state: &SchedulerState) -> Option<usize> {
+        (0..4)
+            .filter(|c| !state.queues[*c].is_empty())
+            .min_by(|a, b| state.pass[*a].total_cmp(&state.pass[*b]))
+    }
+}
+
+impl ClassStats {
+    fn record(&mut self, bytes: u64, started: Instant) {
+        self.grants += 1;
+        self.bytes_granted += bytes;
+        self.total_wait_ms += started.elapsed().as_millis() as u64;
+    }
+}
+
+/// Removes a waiting ticket if the acquiring future is dropped
+struct TicketGuard<'a> {
+    scheduler: &'a TransferScheduler,
+    class: usize,
+    ticket: u64,
+    granted: bool,
+}
+
+impl Drop for TicketGuard<'_> {
+    fn drop(&mut self) {
+        if !self.granted {
+            let mut state = self.scheduler.state.lock();
+            state.queues[self.class].retain(|t| *t != self.ticket);
+            drop(state);
+            self.scheduler.notify.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Node;
+
+    #[test]
+    fn test_priority_parse() {
+        assert_eq!(
+            "heal".parse::<TransferPriority>().unwrap(),
+            TransferPriority::Heal
+        );
+        assert_eq!(
+            "Interactive".parse::<TransferPriority>().unwrap(),
+            TransferPriority::Interactive
+        );
+        assert!("urgent".parse::<TransferPriority>().is_err());
+        assert_eq!(TransferPriority::Prefetch.to_string(), "prefetch");
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_grants_immediately() {
+        let scheduler = TransferScheduler::unlimited();
+        scheduler
+            .acquire(TransferPriority::Background, 10 * 1024 * 1024)
+            .await;
+
+        let stats = scheduler.class_stats(TransferPriority::Background);
+        assert_eq!(stats.grants, 1);
+        assert_eq!(stats.bytes_granted, 10 * 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_not_starved_by_heal() {
+        let scheduler = Arc::new(TransferScheduler::new(SchedulerConfig {
+            bytes_per_sec: 400 * 1024,
+            burst_bytes: 8 * 1024,
+            ..Default::default()
+        }));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        // Drain the initial burst so all waiters queue up
+        scheduler.acquire(TransferPriority::Heal, 8 * 1024).await;
+
+        let mut handles = Vec::new();
+        for priority in [TransferPriority::Heal, TransferPriority::Interactive] {
+            for _ in 0..4 {
+                let scheduler = scheduler.clone();
+                let order = order.clone();
+                handles.push(tokio::spawn(async move {
+                    scheduler.acquire(priority, 8 * 1024).await;
+                    order.lock().push(priority);
+                }));
+            }
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let order = order.lock();
+        assert_eq!(order.len(), 8);
+        // Interactive has 4x the weight of heal, so it should finish first overall
+        let first_half_interactive = order[..4]
+            .iter()
+            .filter(|p| **p == TransferPriority::Interactive)
+            .count();
+        assert!(first_half_interactive >= 3, "order: {:?}", *order);
+    }
+
+    #[tokio::test]
+    async fn test_peer_loss_boosts_heal() {
+        let store = Arc::new(NodeStore::new());
+        store.upsert_node(Node::new(1)).await;
+        let scheduler = Arc::new(TransferScheduler::new(SchedulerConfig {
+            bytes_per_sec: 400 * 1024,
+            burst_bytes: 8 * 1024,
+            ..Default::default()
+        }));
+        let watch = scheduler.clone().spawn_peer_watch(store.clone());
+        tokio::task::yield_now().await;
+        assert!(!scheduler.heal_boosted());
+
+        store.remove_node(1).await;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !scheduler.heal_boosted() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        watch.abort();
+
+        // Boosted heal keeps pace with interactive instead of trailing it
+        scheduler.acquire(TransferPriority::Heal, 8 * 1024).await;
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for priority in [TransferPriority::Heal, TransferPriority::Interactive] {
+            for _ in 0..4 {
+                let scheduler = scheduler.clone();
+                let order = order.clone();
+                handles.push(tokio::spawn(async move {
+                    scheduler.acquire(priority, 8 * 1024).await;
+                    order.lock().push(priority);
+                }));
+            }
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let order = order.lock();
+        let first_half_heal = order[..4]
+            .iter()
+            .filter(|p| **p == TransferPriority::Heal)
+            .count();
+        assert_eq!(first_half_heal, 2, "order: {:?}", *order);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_is_removed() {
+        let scheduler = TransferScheduler::new(SchedulerConfig {
+            bytes_per_sec: 1024,
+            burst_bytes: 1024,
+            ..Default::default()
+        });
+        scheduler.acquire(TransferPriority::Prefetch, 1024).await;
+
+        let pending = tokio::time::timeout(
+            Duration::from_millis(20),
+            scheduler.acquire(TransferPriority::Prefetch, 1024),
+        )
+        .await;
+        assert!(pending.is_err());
+        assert_eq!(scheduler.queued(TransferPriority::Prefetch), 0);
+    }
+}
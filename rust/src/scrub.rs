@@ -0,0 +1,549 @@
+/// Background scrubbing of persisted shards
+///
+/// Bits on disk rot without any read failing, so a corrupt shard is only
+/// noticed when a download needs it. The scrubber walks the shards the
+/// cache keeps on disk in a fixed order and, every `interval_secs`,
+/// re-hashes the next `fraction` of them against their manifest:
+///
+/// - a copy that no longer matches is quarantined (see
+///   [`Cache::quarantine_shard`]), so it is never served again
+/// - the file is then healed by the [`AutoHealer`], which fetches the shard
+///   from its holder or rebuilds it from parity, and the repaired copy is
+///   persisted in place of the corrupt one
+/// - a valid copy still under an older key of a rotated file (see
+///   [`crate::rotation`]) is re-encrypted under the newest key, given the
+///   node keypair
+///
+/// Where the walk stopped is kept between cycles, so every stored shard is
+/// checked once per `1 / fraction` cycles. [`ScrubStats`] reports how far
+/// the current pass got and how many shards were found corrupt, repaired
+/// and re-encrypted.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::auto_heal::AutoHealer;
+use crate::cache::{Cache, FileManifest};
+use crate::envelope::NodeKeypair;
+use crate::rotation::ShardKeys;
+use crate::types::current_timestamp;
+
+/// Peer recorded for shards found corrupt on this node's own disk
+const LOCAL_PEER: u32 = 0;
+
+/// Scrubbing settings (`[scrub]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrubConfig {
+    pub enabled: bool,
+    /// Time between scrub cycles
+    pub interval_secs: u64,
+    /// Share of the stored shards re-hashed per cycle
+    pub fraction: f64,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 3600,
+            fraction: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    scrub: ScrubConfig,
+}
+
+impl ScrubConfig {
+    /// Load the `[scrub]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[scrub]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.scrub.validate()?;
+        Ok(file.scrub)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.interval_secs == 0 {
+            bail!("scrub: interval_secs must be at least 1");
+        }
+        if !(self.fraction > 0.0 && self.fraction <= 1.0) {
+            bail!("scrub: fraction must be in (0, 1]");
+        }
+        Ok(())
+    }
+
+    /// Shards checked per cycle out of `stored`
+    fn batch_size(&self, stored: usize) -> usize {
+        ((stored as f64 * self.fraction).ceil() as usize).min(stored)
+    }
+}
+
+/// Outcome of one scrub cycle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// Shards read back and hashed
+    pub checked: usize,
+    /// Shards failing their manifest's hash
+    pub corrupted: usize,
+    /// Corrupt shards replaced with a valid copy
+    pub repaired: usize,
+    /// Valid shards re-encrypted under a rotated file key
+    pub reencrypted: usize,
+}
+
+/// Scrubbing statistics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ScrubStats {
+    pub cycles: u64,
+    pub shards_checked: u64,
+    pub shards_corrupted: u64,
+    pub shards_repaired: u64,
+    pub shards_reencrypted: u64,
+    /// Corrupt shards left quarantined without a replacement
+    pub repairs_failed: u64,
+    /// Full passes over the stored shards
+    pub passes_completed: u64,
+    /// Share of the stored shards checked so far in the current pass
+    pub coverage: f64,
+    /// Shards on disk when the last cycle ran
+    pub shards_stored: usize,
+    /// Unix time of the last cycle
+    pub last_cycle_at: Option<u64>,
+}
+
+/// Periodically re-hashes persisted shards and repairs corrupt ones
+pub struct Scrubber {
+    config: ScrubConfig,
+    cache: Arc<Cache>,
+    /// Repairs corrupt shards; without it they are only quarantined
+    healer: Option<Arc<AutoHealer>>,
+    /// Unwraps the keys of rotated files; without it their shards are
+    /// only checked
+    keypair: Option<Arc<NodeKeypair>>,
+    /// This node's ID, whose shard locations move to the newest key with
+    /// the copies on disk
+    local_peer: Option<u32>,
+    /// Last shard checked, as (file hash, index)
+    cursor: Mutex<Option<(String, usize)>>,
+    /// Shards checked in the current pass
+    pass_checked: Mutex<usize>,
+    stats: RwLock<ScrubStats>,
+}
+
+impl Scrubber {
+    pub fn new(config: ScrubConfig, cache: Arc<Cache>) -> Self {
+        Self {
+            config,
+            cache,
+            healer: None,
+            keypair: None,
+            local_peer: None,
+            cursor: Mutex::new(None),
+            pass_checked: Mutex::new(0),
+            stats: RwLock::new(ScrubStats::default()),
+        }
+    }
+
+    /// Repair corrupt shards through `healer`
+    pub fn with_healer(mut self, healer: Arc<AutoHealer>) -> Self {
+        self.healer = Some(healer);
+        self
+    }
+
+    /// Re-encrypt shards of rotated files with keys wrapped for `keypair`
+    pub fn with_keypair(mut self, keypair: Arc<NodeKeypair>) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Record re-encrypted copies at locations naming `peer_id`
+    pub fn with_local_peer(mut self, peer_id: u32) -> Self {
+        self.local_peer = Some(peer_id);
+        self
+    }
+
+    /// Scrub every `interval_secs` until the task is aborted
+    ///
+    /// The auto-healer talks to the Go node, so this runs on a `LocalSet`.
+    pub async fn run(self: Rc<Self>) -> Result<()> {
+        info!(
+            "🧽 Scrubbing {:.1}% of stored shards every {}s",
+            self.config.fraction * 100.0,
+            self.config.interval_secs
+        );
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.run_cycle().await;
+        }
+    }
+
+    /// Check the next batch of stored shards and repair the corrupt ones
+    pub async fn run_cycle(&self) -> ScrubReport {
+        let mut manifests = self.cache.list_manifests().await;
+        manifests.sort_by(|a, b| a.file_hash.cmp(&b.file_hash));
+        let mut stored = Vec::new();
+        for (position, manifest) in manifests.iter().enumerate() {
+            for index in 0..manifest.shard_hashes.len() {
+                if self.cache.has_persisted_shard(&manifest.file_hash, index) {
+                    stored.push((position, index));
+                }
+            }
+        }
+
+        let mut cursor = self.cursor.lock().await;
+        let start = match cursor.as_ref() {
+            Some((hash, last)) => stored
+                .iter()
+                .position(|&(position, index)| {
+                    (manifests[position].file_hash.as_str(), index) > (hash.as_str(), *last)
+                })
+                // Past the last stored shard: start the next pass
+                .unwrap_or(0),
+            None => 0,
+        };
+        let batch = self.config.batch_size(stored.len());
+
+        let mut report = ScrubReport::default();
+        let mut corrupt: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut stale: BTreeMap<usize, Vec<(usize, Vec<u8>)>> = BTreeMap::new();
+        for &(position, index) in stored[start..].iter().chain(&stored[..start]).take(batch) {
+            let manifest = &manifests[position];
+            *cursor = Some((manifest.file_hash.clone(), index));
+            let Some(data) = self
+                .cache
+                .read_persisted_shard(&manifest.file_hash, index)
+                .await
+            else {
+                continue;
+            };
+            report.checked += 1;
+            if let Some(version) = manifest.shard_version(index, &data) {
+                if manifest.rotation_pending()
+                    && manifest
+                        .rotation
+                        .as_ref()
+                        .is_some_and(|rotation| rotation.current != version)
+                {
+                    stale.entry(position).or_default().push((index, data));
+                }
+                continue;
+            }
+
+            warn!(
+                "🚨 Shard {}:{} failed its hash check on disk",
+                manifest.file_hash, index
+            );
+            report.corrupted += 1;
+            if let Err(e) = self
+                .cache
+                .quarantine_shard(&manifest.file_hash, index, LOCAL_PEER, &data)
+                .await
+            {
+                warn!(
+                    "Failed to quarantine shard {}:{}: {:#}",
+                    manifest.file_hash, index, e
+                );
+            }
+            corrupt.entry(position).or_default().push(index);
+        }
+        drop(cursor);
+
+        for (position, indices) in &corrupt {
+            report.repaired += self.repair(&manifests[position], indices).await;
+        }
+        for (position, copies) in stale {
+            let manifest = &manifests[position];
+            match self.reencrypt(manifest, copies).await {
+                Ok(reencrypted) => report.reencrypted += reencrypted,
+                Err(e) => debug!(
+                    "Not re-encrypting shards of {}: {:#}",
+                    manifest.file_hash, e
+                ),
+            }
+        }
+
+        let coverage = {
+            let mut pass_checked = self.pass_checked.lock().await;
+            let wrapped = start + batch >= stored.len() && batch > 0;
+            let mut stats = self.stats.write().await;
+            if wrapped {
+                stats.passes_completed += 1;
+                *pass_checked = batch - (stored.len() - start);
+            } else {
+                *pass_checked += batch;
+            }
+            stats.cycles += 1;
+            stats.shards_checked += report.checked as u64;
+            stats.shards_corrupted += report.corrupted as u64;
+            stats.shards_repaired += report.repaired as u64;
+            stats.shards_reencrypted += report.reencrypted as u64;
+            stats.repairs_failed += (report.corrupted - report.repaired) as u64;
+            stats.coverage = if stored.is_empty() {
+                0.0
+            } else {
+                (*pass_checked as f64 / stored.len() as f64).min(1.0)
+            };
+            stats.shards_stored = stored.len();
+            stats.last_cycle_at = Some(current_timestamp());
+            stats.coverage
+        };
+
+        if report.corrupted > 0 {
+            warn!(
+                "🧽 Scrubbed {} shard(s): {} corrupt, {} repaired ({:.1}% of this pass)",
+                report.checked,
+                report.corrupted,
+                report.repaired,
+                coverage * 100.0
+            );
+        } else {
+            debug!(
+                "Scrubbed {} shard(s), none corrupt ({:.1}% of this pass)",
+                report.checked,
+                coverage * 100.0
+            );
+        }
+        if report.reencrypted > 0 {
+            info!(
+                "🔑 Re-encrypted {} shard(s) under rotated keys",
+                report.reencrypted
+            );
+        }
+        report
+    }
+
+    /// Move valid copies of a rotated file's shards on disk to its newest
+    /// key, returning how many were rewritten
+    ///
+    /// Locations naming this node are recorded at the newest version, and
+    /// the older keys are retired once every location is.
+    async fn reencrypt(
+        &self,
+        manifest: &FileManifest,
+        copies: Vec<(usize, Vec<u8>)>,
+    ) -> Result<usize> {
+        let keypair = self.keypair.as_ref().context("No node keypair")?;
+        let keys = ShardKeys::unwrap(keypair, manifest)?;
+        // The manifest may have moved on since the cycle listed it
+        let mut updated = self
+            .cache
+            .get_manifest(&manifest.file_hash)
+            .await
+            .unwrap_or_else(|| manifest.clone());
+        let mut reencrypted = 0;
+        for (index, data) in copies {
+            let sealed = keys
+                .open(&updated, index, &data)
+                .and_then(|original| keys.seal(&mut updated, index, &original))?;
+            self.cache
+                .persist_shard(&manifest.file_hash, index, sealed)
+                .await?;
+            reencrypted += 1;
+
+            let locations = updated.shard_locations.len();
+            let local: Vec<usize> = updated
+                .shard_locations
+                .iter()
+                .enumerate()
+                .filter(|(_, &(shard, peer))| shard == index && Some(peer) == self.local_peer)
+                .map(|(position, _)| position)
+                .collect();
+            if let Some(rotation) = updated.rotation.as_mut() {
+                for position in local {
+                    rotation.mark_current(position, locations);
+                }
+            }
+        }
+        let locations = updated.shard_locations.len();
+        if let Some(rotation) = updated.rotation.as_mut() {
+            if rotation.retire(locations) {
+                info!(
+                    "🔑 Every copy of {} is at key version {}; older keys retired",
+                    manifest.file_hash, rotation.current
+                );
+            }
+        }
+        self.cache.put_manifest(updated).await?;
+        Ok(reencrypted)
+    }
+
+    /// Heal a file and persist valid copies of its corrupt shards
+    ///
+    /// Returns the number of shards replaced.
+    async fn repair(&self, manifest: &FileManifest, indices: &[usize]) -> usize {
+        let Some(healer) = &self.healer else {
+            return 0;
+        };
+        // Re-placing shards on peers can fail after the local rebuild
+        // succeeded, so the cache is checked either way
+        if let Err(e) = healer.heal(manifest).await {
+            warn!("Healing {} after scrub: {:#}", manifest.file_hash, e);
+        }
+
+        let mut repaired = 0;
+        for &index in indices {
+            let Some(data) = self.cache.get_shard(&manifest.file_hash, index).await else {
+                continue;
+            };
+            if !shard_intact(manifest, index, &data) {
+                continue;
+            }
+            match self
+                .cache
+                .persist_shard(&manifest.file_hash, index, data)
+                .await
+            {
+                Ok(()) => {
+                    info!("🔧 Repaired shard {}:{}", manifest.file_hash, index);
+                    repaired += 1;
+                }
+                Err(e) => warn!(
+                    "Failed to persist repaired shard {}:{}: {:#}",
+                    manifest.file_hash, index, e
+                ),
+            }
+        }
+        repaired
+    }
+
+    /// Get current statistics
+    pub async fn get_stats(&self) -> ScrubStats {
+        self.stats.read().await.clone()
+    }
+}
+
+/// Whether `data` matches a hash the manifest records for shard `index`,
+/// under any key version still accepted
+fn shard_intact(manifest: &FileManifest, index: usize, data: &[u8]) -> bool {
+    manifest.shard_version(index, data).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ces::CesPipeline;
+    use crate::go_client::GoClient;
+    use crate::store::NodeStore;
+    use crate::types::CesConfig;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_config_parsing() {
+        let config =
+            ScrubConfig::from_toml("[scrub]\ninterval_secs = 60\nfraction = 0.5\n").unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.interval_secs, 60);
+        assert_eq!(config.batch_size(5), 3);
+        assert_eq!(config.batch_size(0), 0);
+        assert!(ScrubConfig::from_toml("[scrub]\nfraction = 0.0\n").is_err());
+        assert!(ScrubConfig::from_toml("[scrub]\nfraction = 1.5\n").is_err());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_scrub_finds_and_repairs_rotted_shards() {
+        let ces =
+            Arc::new(CesPipeline::new(CesConfig::default().with_shards(4, 2)).with_key([7; 32]));
+        let shards = ces.process(&b"scrub me ".repeat(500)).unwrap();
+        let manifest = FileManifest {
+            file_hash: "scrubme".to_string(),
+            file_name: "scrubme.txt".to_string(),
+            file_size: 4500,
+            shard_count: shards.len(),
+            parity_count: 2,
+            shard_locations: (0..shards.len()).map(|i| (i, i as u32 + 1)).collect(),
+            shard_hashes: shards
+                .iter()
+                .map(|shard| format!("{:x}", Sha256::digest(shard)))
+                .collect(),
+            namespace: crate::namespace::DEFAULT_NAMESPACE.to_string(),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+        cache.put_manifest(manifest.clone()).await.unwrap();
+        for (index, shard) in shards.iter().enumerate() {
+            cache
+                .persist_shard("scrubme", index, shard.clone())
+                .await
+                .unwrap();
+        }
+
+        // Shard 4 rots on disk while memory still holds a good copy
+        let rotted = dir.path().join("shards").join("scrubme").join("4.bin");
+        let mut bytes = std::fs::read(&rotted).unwrap();
+        bytes[0] ^= 0xff;
+        std::fs::write(&rotted, &bytes).unwrap();
+
+        // No peers answer, so healing rebuilds the shard from parity
+        let go_client = Arc::new(GoClient::new("127.0.0.1:1".parse().unwrap()));
+        let healer = AutoHealer::new(
+            crate::auto_heal::AutoHealConfig::default(),
+            cache.clone(),
+            ces,
+            go_client,
+            Arc::new(NodeStore::new()),
+        );
+        let config = ScrubConfig {
+            fraction: 0.5,
+            ..ScrubConfig::default()
+        };
+        let scrubber = Scrubber::new(config, cache.clone()).with_healer(Arc::new(healer));
+
+        let first = scrubber.run_cycle().await;
+        assert_eq!(
+            first,
+            ScrubReport {
+                checked: 3,
+                ..Default::default()
+            }
+        );
+        assert_eq!(scrubber.get_stats().await.coverage, 0.5);
+
+        let second = scrubber.run_cycle().await;
+        assert_eq!(
+            second,
+            ScrubReport {
+                checked: 3,
+                corrupted: 1,
+                repaired: 1,
+                reencrypted: 0,
+            }
+        );
+        assert_eq!(std::fs::read(&rotted).unwrap(), shards[4]);
+        assert_eq!(cache.get_stats().await.shards_quarantined, 1);
+
+        let stats = scrubber.get_stats().await;
+        assert_eq!(stats.passes_completed, 1);
+        assert_eq!(stats.coverage, 0.0);
+        assert_eq!(stats.shards_stored, 6);
+        assert_eq!(stats.shards_checked, 6);
+        assert_eq!(stats.shards_corrupted, 1);
+        assert_eq!(stats.shards_repaired, 1);
+        assert_eq!(stats.repairs_failed, 0);
+
+        // The next pass starts over and finds everything intact
+        let third = scrubber.run_cycle().await;
+        assert_eq!(third.checked, 3);
+        assert_eq!(third.corrupted, 0);
+    }
+}
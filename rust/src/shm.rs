@@ -0,0 +1,299 @@
+/// Shared-memory shard ring for the Go FFI layer
+///
+/// Returning shards through `ces_process` allocates a boxed slice per shard
+/// that Go then copies into its own heap with `C.GoBytes`, so every shard
+/// crosses FFI twice and leaves garbage for both allocators. A `ShardRing`
+/// is one `memfd`-backed mapping that Rust writes shards into; Go receives
+/// `(offset, len)` descriptors and reads the bytes in place, releasing each
+/// descriptor once the shard has been sent. The fd can also be handed to
+/// another process that maps the same memory.
+///
+/// Only Linux supports `memfd_create`; elsewhere [`ShardRing::new`] fails and
+/// callers fall back to the copying path.
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// Default ring capacity (enough for a few 1MB-chunk files in flight)
+pub const DEFAULT_RING_CAPACITY: usize = 64 * 1024 * 1024;
+
+/// Location of a shard inside the ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShmSlot {
+    pub offset: usize,
+    pub len: usize,
+}
+
+#[derive(Debug)]
+struct Allocation {
+    slot: ShmSlot,
+    released: bool,
+}
+
+/// Live allocations, oldest first
+///
+/// Space is reclaimed from either end: releasing a shard in the middle only
+/// frees it once every older (or every newer) shard has been released too.
+#[derive(Debug, Default)]
+struct RingState {
+    head: usize,
+    live: VecDeque<Allocation>,
+}
+
+impl RingState {
+    fn allocate(&mut self, len: usize, capacity: usize) -> Option<ShmSlot> {
+        let Some(oldest) = self.live.front() else {
+            self.head = 0;
+            return self.take(0, len, capacity);
+        };
+        let tail = oldest.slot.offset;
+        if self.head >= tail {
+            // Live bytes are [tail, head): use the end, else wrap to the front
+            if self.head + len <= capacity {
+                self.take(self.head, len, capacity)
+            } else if len <= tail {
+                self.take(0, len, capacity)
+            } else {
+                None
+            }
+        } else if self.head + len <= tail {
+            // Live bytes wrap around: only [head, tail) is free
+            self.take(self.head, len, capacity)
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, offset: usize, len: usize, capacity: usize) -> Option<ShmSlot> {
+        if offset + len > capacity {
+            return None;
+        }
+        let slot = ShmSlot { offset, len };
+        self.head = offset + len;
+        self.live.push_back(Allocation {
+            slot,
+            released: false,
+        });
+        Some(slot)
+    }
+
+    fn release(&mut self, slot: ShmSlot) -> bool {
+        let Some(allocation) = self.live.iter_mut().find(|a| a.slot == slot && !a.released) else {
+            return false;
+        };
+        allocation.released = true;
+        while self.live.front().is_some_and(|a| a.released) {
+            self.live.pop_front();
+        }
+        // The newest slots can be reclaimed at once, which undoes a batch
+        // that did not fit
+        while self.live.back().is_some_and(|a| a.released) {
+            self.live.pop_back();
+        }
+        self.head = self.live.back().map_or(0, |a| a.slot.offset + a.slot.len);
+        true
+    }
+
+    fn used(&self) -> usize {
+        self.live
+            .iter()
+            .filter(|a| !a.released)
+            .map(|a| a.slot.len)
+            .sum()
+    }
+}
+
+/// Fixed-size shared mapping that shards are written into for Go to read
+pub struct ShardRing {
+    base: *mut u8,
+    capacity: usize,
+    fd: i32,
+    state: Mutex<RingState>,
+}
+
+// The mapping is only written through slots handed out under `state`, and
+// never remapped while the ring is alive.
+unsafe impl Send for ShardRing {}
+unsafe impl Sync for ShardRing {}
+
+impl ShardRing {
+    /// Map a new anonymous shared ring of `capacity` bytes
+    #[cfg(target_os = "linux")]
+    pub fn new(capacity: usize) -> Result<Self> {
+        if capacity == 0 {
+            anyhow::bail!("Shared ring capacity must be > 0");
+        }
+        let name = b"pangea-shards\0";
+        let fd = unsafe { libc::memfd_create(name.as_ptr().cast(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if unsafe { libc::ftruncate(fd, capacity as libc::off_t) } != 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err.into());
+        }
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                capacity,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err.into());
+        }
+        Ok(Self {
+            base: base as *mut u8,
+            capacity,
+            fd,
+            state: Mutex::new(RingState::default()),
+        })
+    }
+
+    /// Shared rings need `memfd_create`, which only Linux provides
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_capacity: usize) -> Result<Self> {
+        anyhow::bail!("Shared-memory shard rings are not supported on this platform")
+    }
+
+    /// Whether this platform can map shared rings at all
+    pub fn is_supported() -> bool {
+        cfg!(target_os = "linux")
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes held by shards not yet released
+    pub fn used(&self) -> usize {
+        self.state.lock().used()
+    }
+
+    /// Start of the mapping; slots are offsets from here
+    pub fn base_ptr(&self) -> *const u8 {
+        self.base
+    }
+
+    /// memfd backing the ring, for mapping it in another process
+    pub fn fd(&self) -> i32 {
+        self.fd
+    }
+
+    /// Copy `data` into the ring, or `None` if there is no room for it
+    pub fn write(&self, data: &[u8]) -> Option<ShmSlot> {
+        let slot = self.state.lock().allocate(data.len(), self.capacity)?;
+        // The slot is ours until released and lies inside the mapping
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.base.add(slot.offset), data.len());
+        }
+        Some(slot)
+    }
+
+    /// Write every shard, or none of them if the ring runs out of room
+    pub fn write_all<T: AsRef<[u8]>>(&self, shards: &[T]) -> Option<Vec<ShmSlot>> {
+        let mut slots = Vec::with_capacity(shards.len());
+        for shard in shards {
+            match self.write(shard.as_ref()) {
+                Some(slot) => slots.push(slot),
+                None => {
+                    for slot in slots {
+                        self.release(slot);
+                    }
+                    return None;
+                }
+            }
+        }
+        Some(slots)
+    }
+
+    /// Copy a live slot back out (for callers without direct pointer access)
+    pub fn read(&self, slot: ShmSlot) -> Option<Vec<u8>> {
+        let state = self.state.lock();
+        if !state.live.iter().any(|a| a.slot == slot && !a.released) {
+            return None;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(self.base.add(slot.offset), slot.len) };
+        Some(bytes.to_vec())
+    }
+
+    /// Hand a slot back; false if it was not live
+    pub fn release(&self, slot: ShmSlot) -> bool {
+        self.state.lock().release(slot)
+    }
+}
+
+impl Drop for ShardRing {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.capacity);
+            libc::close(self.fd);
+        }
+    }
+}
+
+impl std::fmt::Debug for ShardRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardRing")
+            .field("capacity", &self.capacity)
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_allocation_wraps_and_reclaims_in_order() {
+        let mut state = RingState::default();
+        let a = state.allocate(30, 100).unwrap();
+        let b = state.allocate(30, 100).unwrap();
+        let c = state.allocate(30, 100).unwrap();
+        assert_eq!((a.offset, b.offset, c.offset), (0, 30, 60));
+
+        // Releasing a slot in the middle frees nothing yet
+        assert!(state.release(b));
+        assert!(state.allocate(20, 100).is_none());
+
+        // Once the oldest goes, new slots wrap to the front
+        assert!(state.release(a));
+        assert!(!state.release(a));
+        let d = state.allocate(20, 100).unwrap();
+        assert_eq!(d.offset, 0);
+        assert!(state.allocate(50, 100).is_none());
+
+        assert!(state.release(c));
+        assert_eq!(state.allocate(70, 100).unwrap().offset, 20);
+        assert_eq!(state.used(), 90);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_shard_ring_roundtrip() {
+        let ring = ShardRing::new(1024).unwrap();
+        let slots = ring
+            .write_all(&[vec![1u8; 300], vec![2u8; 300], vec![3u8; 300]])
+            .unwrap();
+        assert_eq!(ring.read(slots[1]), Some(vec![2u8; 300]));
+        assert_eq!(ring.used(), 900);
+
+        // All-or-nothing: a batch that does not fit leaves the ring as it was
+        assert!(ring.write_all(&[vec![4u8; 100], vec![5u8; 100]]).is_none());
+        assert_eq!(ring.used(), 900);
+
+        for slot in slots {
+            assert!(ring.release(slot));
+        }
+        assert_eq!(ring.used(), 0);
+        assert!(ring.write(&[6u8; 1024]).is_some());
+    }
+}
@@ -0,0 +1,565 @@
+/// Simulated shard transport with injected network faults
+///
+/// `SimulatedNetwork` implements [`ShardTransport`] over in-process peers so
+/// FEC, hedging and retry behaviour can be exercised deterministically. Each
+/// link applies a latency distribution, random loss, reordering (a fraction of
+/// transfers are held back so later ones overtake them) and a bandwidth cap
+/// that serialises transfers on the same link. Peers can be taken down and
+/// brought back at runtime. The same transport backs the CLI's
+/// `--simulate-network` dev mode.
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::error::NetworkError;
+use crate::transport::{ShardTransport, TransportKind};
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+/// One-way delay of a simulated transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LatencyModel {
+    Fixed(Duration),
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Normal distribution, clamped at zero
+    Normal {
+        mean: Duration,
+        std_dev: Duration,
+    },
+    /// Usually `base`, but `tail_probability` of transfers take `tail` instead
+    LongTail {
+        base: Duration,
+        tail: Duration,
+        tail_probability: f64,
+    },
+}
+
+impl LatencyModel {
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match self {
+            LatencyModel::Fixed(delay) => *delay,
+            LatencyModel::Uniform { min, max } if max > min => rng.gen_range(*min..=*max),
+            LatencyModel::Uniform { min, .. } => *min,
+            LatencyModel::Normal { mean, std_dev } => {
+                // Box-Muller transform
+                let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+                let u2: f64 = rng.gen();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                let secs = mean.as_secs_f64() + z * std_dev.as_secs_f64();
+                Duration::from_secs_f64(secs.max(0.0))
+            }
+            LatencyModel::LongTail {
+                base,
+                tail,
+                tail_probability,
+            } => {
+                if rng.gen_bool(tail_probability.clamp(0.0, 1.0)) {
+                    *tail
+                } else {
+                    *base
+                }
+            }
+        }
+    }
+}
+
+/// Fault profile of a link between this node and a peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkConditions {
+    pub latency: LatencyModel,
+    /// Fraction of transfers lost (0.0 - 1.0)
+    pub loss_rate: f64,
+    /// Fraction of transfers delayed by `reorder_delay` on top of latency
+    pub reorder_rate: f64,
+    pub reorder_delay: Duration,
+    /// Link capacity in bytes/sec (0 = unlimited)
+    pub bandwidth_bytes_per_sec: u64,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        NetworkProfile::Perfect.conditions()
+    }
+}
+
+/// Ready-made link conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum NetworkProfile {
+    /// No delay, loss or bandwidth limit
+    Perfect,
+    /// Sub-millisecond latency, gigabit links
+    Lan,
+    /// Tens of milliseconds with jitter and occasional loss
+    Wan,
+    /// Heavy loss and reordering with a long latency tail
+    Lossy,
+    /// High latency and a narrow link
+    Satellite,
+}
+
+impl NetworkProfile {
+    pub fn conditions(self) -> LinkConditions {
+        let ms = Duration::from_millis;
+        match self {
+            NetworkProfile::Perfect => LinkConditions {
+                latency: LatencyModel::Fixed(Duration::ZERO),
+                loss_rate: 0.0,
+                reorder_rate: 0.0,
+                reorder_delay: Duration::ZERO,
+                bandwidth_bytes_per_sec: 0,
+            },
+            NetworkProfile::Lan => LinkConditions {
+                latency: LatencyModel::Uniform {
+                    min: Duration::from_micros(200),
+                    max: ms(1),
+                },
+                loss_rate: 0.0,
+                reorder_rate: 0.0,
+                reorder_delay: Duration::ZERO,
+                bandwidth_bytes_per_sec: 125_000_000,
+            },
+            NetworkProfile::Wan => LinkConditions {
+                latency: LatencyModel::Normal {
+                    mean: ms(40),
+                    std_dev: ms(10),
+                },
+                loss_rate: 0.01,
+                reorder_rate: 0.02,
+                reorder_delay: ms(20),
+                bandwidth_bytes_per_sec: 12_500_000,
+            },
+            NetworkProfile::Lossy => LinkConditions {
+                latency: LatencyModel::LongTail {
+                    base: ms(30),
+                    tail: ms(800),
+                    tail_probability: 0.05,
+                },
+                loss_rate: 0.15,
+                reorder_rate: 0.1,
+                reorder_delay: ms(50),
+                bandwidth_bytes_per_sec: 2_500_000,
+            },
+            NetworkProfile::Satellite => LinkConditions {
+                latency: LatencyModel::Normal {
+                    mean: ms(600),
+                    std_dev: ms(50),
+                },
+                loss_rate: 0.02,
+                reorder_rate: 0.0,
+                reorder_delay: Duration::ZERO,
+                bandwidth_bytes_per_sec: 1_250_000,
+            },
+        }
+    }
+}
+
+/// Counters of what the simulation did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulationStats {
+    pub sent: u64,
+    pub fetched: u64,
+    pub lost: u64,
+    pub reordered: u64,
+    pub unreachable: u64,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    fetched: AtomicU64,
+    lost: AtomicU64,
+    reordered: AtomicU64,
+    unreachable: AtomicU64,
+    bytes: AtomicU64,
+}
+
+/// Outcome of pushing one transfer through a link
+enum Delivery {
+    Delivered,
+    Lost,
+}
+
+/// In-process network of simulated peers
+pub struct SimulatedNetwork {
+    defaults: LinkConditions,
+    links: Mutex<HashMap<u32, LinkConditions>>,
+    peers: Mutex<HashSet<u32>>,
+    down: Mutex<HashSet<u32>>,
+    /// Shards held by each peer, by shard index
+    shards: Mutex<HashMap<u32, HashMap<usize, Vec<u8>>>>,
+    /// When each link finishes its queued transfers
+    link_free_at: Mutex<HashMap<u32, Instant>>,
+    rng: Mutex<StdRng>,
+    storage_dir: Option<PathBuf>,
+    counters: Counters,
+}
+
+impl SimulatedNetwork {
+    /// Network where every link has `defaults` conditions
+    pub fn new(defaults: LinkConditions) -> Self {
+        Self {
+            defaults,
+            links: Mutex::new(HashMap::new()),
+            peers: Mutex::new(HashSet::new()),
+            down: Mutex::new(HashSet::new()),
+            shards: Mutex::new(HashMap::new()),
+            link_free_at: Mutex::new(HashMap::new()),
+            rng: Mutex::new(StdRng::from_entropy()),
+            storage_dir: None,
+            counters: Counters::default(),
+        }
+    }
+
+    /// Make fault injection reproducible
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock() = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Add peers to the network
+    pub fn with_peers(self, peers: impl IntoIterator<Item = u32>) -> Self {
+        self.peers.lock().extend(peers);
+        self
+    }
+
+    /// Keep peers' shards on disk under `dir` so they outlive the process
+    pub fn with_storage_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.storage_dir = Some(dir.into());
+        self
+    }
+
+    /// Override the conditions of one peer's link
+    pub fn set_link(&self, peer_id: u32, conditions: LinkConditions) {
+        self.links.lock().insert(peer_id, conditions);
+    }
+
+    /// Make a peer unreachable, or reachable again
+    pub fn set_peer_down(&self, peer_id: u32, down: bool) {
+        let mut set = self.down.lock();
+        if down {
+            set.insert(peer_id);
+        } else {
+            set.remove(&peer_id);
+        }
+    }
+
+    /// Drop everything a peer holds
+    pub fn wipe_peer(&self, peer_id: u32) {
+        self.shards.lock().remove(&peer_id);
+        if let Some(dir) = &self.storage_dir {
+            let _ = std::fs::remove_dir_all(dir.join(format!("peer-{}", peer_id)));
+        }
+    }
+
+    pub fn peers(&self) -> Vec<u32> {
+        let mut peers: Vec<u32> = self.peers.lock().iter().copied().collect();
+        peers.sort_unstable();
+        peers
+    }
+
+    /// Shard indices a peer holds
+    pub fn shards_on(&self, peer_id: u32) -> Vec<usize> {
+        let mut indices: Vec<usize> = self
+            .shards
+            .lock()
+            .get(&peer_id)
+            .map(|held| held.keys().copied().collect())
+            .unwrap_or_default();
+        indices.sort_unstable();
+        indices
+    }
+
+    pub fn stats(&self) -> SimulationStats {
+        let c = &self.counters;
+        SimulationStats {
+            sent: c.sent.load(Ordering::Relaxed),
+            fetched: c.fetched.load(Ordering::Relaxed),
+            lost: c.lost.load(Ordering::Relaxed),
+            reordered: c.reordered.load(Ordering::Relaxed),
+            unreachable: c.unreachable.load(Ordering::Relaxed),
+            bytes: c.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn conditions(&self, peer_id: u32) -> LinkConditions {
+        self.links
+            .lock()
+            .get(&peer_id)
+            .cloned()
+            .unwrap_or_else(|| self.defaults.clone())
+    }
+
+    fn is_reachable(&self, peer_id: u32) -> bool {
+        self.peers.lock().contains(&peer_id) && !self.down.lock().contains(&peer_id)
+    }
+
+    /// Wait out latency, queueing and reordering for `bytes` on a link
+    async fn traverse(&self, peer_id: u32, bytes: usize) -> Delivery {
+        let conditions = self.conditions(peer_id);
+        let (latency, lost, reordered) = {
+            let mut rng = self.rng.lock();
+            (
+                conditions.latency.sample(&mut *rng),
+                rng.gen_bool(conditions.loss_rate.clamp(0.0, 1.0)),
+                rng.gen_bool(conditions.reorder_rate.clamp(0.0, 1.0)),
+            )
+        };
+
+        // Transfers on one link share its bandwidth, so they queue
+        let now = Instant::now();
+        let transmitted_at = if conditions.bandwidth_bytes_per_sec > 0 {
+            let transmission =
+                Duration::from_secs_f64(bytes as f64 / conditions.bandwidth_bytes_per_sec as f64);
+            let mut free_at = self.link_free_at.lock();
+            let start = free_at.get(&peer_id).copied().unwrap_or(now).max(now);
+            let done = start + transmission;
+            free_at.insert(peer_id, done);
+            done
+        } else {
+            now
+        };
+
+        let mut arrival = transmitted_at + latency;
+        if reordered {
+            arrival += conditions.reorder_delay;
+            self.counters.reordered.fetch_add(1, Ordering::Relaxed);
+        }
+        tokio::time::sleep_until(arrival).await;
+
+        if lost {
+            self.counters.lost.fetch_add(1, Ordering::Relaxed);
+            Delivery::Lost
+        } else {
+            self.counters
+                .bytes
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+            Delivery::Delivered
+        }
+    }
+
+    fn unreachable(&self, peer_id: u32) -> NetworkError {
+        self.counters.unreachable.fetch_add(1, Ordering::Relaxed);
+        NetworkError::PeerUnreachable(peer_id)
+    }
+
+    fn shard_path(&self, peer_id: u32, shard_index: usize) -> Option<PathBuf> {
+        self.storage_dir.as_ref().map(|dir| {
+            dir.join(format!("peer-{}", peer_id))
+                .join(format!("shard-{}", shard_index))
+        })
+    }
+
+    fn store(&self, peer_id: u32, shard_index: usize, shard: Vec<u8>) -> Result<()> {
+        if let Some(path) = self.shard_path(peer_id, shard_index) {
+            let write = || -> std::io::Result<()> {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&path, &shard)
+            };
+            write().map_err(|e| NetworkError::Other(e.into()))?;
+        }
+        self.shards
+            .lock()
+            .entry(peer_id)
+            .or_default()
+            .insert(shard_index, shard);
+        Ok(())
+    }
+
+    fn load(&self, peer_id: u32, shard_index: usize) -> Option<Vec<u8>> {
+        if let Some(shard) = self
+            .shards
+            .lock()
+            .get(&peer_id)
+            .and_then(|held| held.get(&shard_index))
+        {
+            return Some(shard.clone());
+        }
+        self.shard_path(peer_id, shard_index)
+            .and_then(|path| std::fs::read(path).ok())
+    }
+}
+
+#[async_trait(?Send)]
+impl ShardTransport for SimulatedNetwork {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Simulated
+    }
+
+    async fn is_available(&self, peer_id: u32) -> bool {
+        self.peers.lock().contains(&peer_id)
+    }
+
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        _file_hash: &str,
+        shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<bool> {
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+        if !self.is_reachable(peer_id) {
+            return Err(self.unreachable(peer_id));
+        }
+        match self.traverse(peer_id, shard.len()).await {
+            Delivery::Delivered => {
+                debug!(
+                    "Simulated send of shard {} to peer {} ({} bytes)",
+                    shard_index,
+                    peer_id,
+                    shard.len()
+                );
+                self.store(peer_id, shard_index, shard)?;
+                Ok(true)
+            }
+            Delivery::Lost => Err(NetworkError::Timeout(format!(
+                "simulated loss sending shard {} to peer {}",
+                shard_index, peer_id
+            ))),
+        }
+    }
+
+    async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        _file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Vec<u8>> {
+        self.counters.fetched.fetch_add(1, Ordering::Relaxed);
+        if !self.is_reachable(peer_id) {
+            return Err(self.unreachable(peer_id));
+        }
+        // A peer without the shard answers with an empty response
+        let shard = self.load(peer_id, shard_index).unwrap_or_default();
+        match self.traverse(peer_id, shard.len()).await {
+            Delivery::Delivered => Ok(shard),
+            Delivery::Lost => Err(NetworkError::Timeout(format!(
+                "simulated loss fetching shard {} from peer {}",
+                shard_index, peer_id
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn fixed(ms: u64) -> LinkConditions {
+        LinkConditions {
+            latency: LatencyModel::Fixed(Duration::from_millis(ms)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip_and_unreachable_peer() {
+        let network = SimulatedNetwork::new(fixed(1)).with_peers([1, 2]);
+        network.send_shard(1, "file", 0, vec![7; 16]).await.unwrap();
+        assert_eq!(
+            network.fetch_shard(1, "file", 0).await.unwrap(),
+            vec![7; 16]
+        );
+        assert!(network.fetch_shard(1, "file", 1).await.unwrap().is_empty());
+
+        network.set_peer_down(2, true);
+        assert!(matches!(
+            network.send_shard(2, "file", 0, vec![1]).await,
+            Err(NetworkError::PeerUnreachable(2))
+        ));
+        assert_eq!(network.stats().unreachable, 1);
+    }
+
+    #[tokio::test]
+    async fn test_total_loss_is_transient() {
+        let network = SimulatedNetwork::new(LinkConditions {
+            loss_rate: 1.0,
+            ..Default::default()
+        })
+        .with_peers([1]);
+
+        let err = network.send_shard(1, "file", 0, vec![1]).await.unwrap_err();
+        assert!(crate::error::ErrorCode::is_transient(&err));
+        assert!(network.shards_on(1).is_empty());
+        assert_eq!(network.stats().lost, 1);
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_cap_serialises_link() {
+        let network = Arc::new(
+            SimulatedNetwork::new(LinkConditions {
+                bandwidth_bytes_per_sec: 100_000,
+                ..Default::default()
+            })
+            .with_peers([1]),
+        );
+
+        // Two 2 KB transfers at 100 KB/s take at least 40ms together
+        let start = std::time::Instant::now();
+        let (a, b) = tokio::join!(
+            network.send_shard(1, "file", 0, vec![0; 2000]),
+            network.send_shard(1, "file", 1, vec![0; 2000])
+        );
+        a.unwrap();
+        b.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_reordered_transfer_is_overtaken() {
+        let network = SimulatedNetwork::new(LinkConditions {
+            reorder_rate: 1.0,
+            reorder_delay: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .with_peers([1, 2]);
+        network.set_link(2, fixed(1));
+
+        let order = Mutex::new(Vec::new());
+        tokio::join!(
+            async {
+                network.send_shard(1, "file", 0, vec![0]).await.unwrap();
+                order.lock().push(1);
+            },
+            async {
+                network.send_shard(2, "file", 0, vec![0]).await.unwrap();
+                order.lock().push(2);
+            }
+        );
+        assert_eq!(*order.lock(), vec![2, 1]);
+        assert_eq!(network.stats().reordered, 1);
+    }
+
+    #[test]
+    fn test_latency_models_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let uniform = LatencyModel::Uniform {
+            min: Duration::from_millis(5),
+            max: Duration::from_millis(10),
+        };
+        let normal = LatencyModel::Normal {
+            mean: Duration::from_millis(1),
+            std_dev: Duration::from_millis(50),
+        };
+        for _ in 0..1000 {
+            let d = uniform.sample(&mut rng);
+            assert!(d >= Duration::from_millis(5) && d <= Duration::from_millis(10));
+            // Clamped at zero rather than panicking on negative samples
+            normal.sample(&mut rng);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+/// Coalescing of concurrent identical work
+///
+/// When several downloads miss the cache on the same shard at once, only the
+/// first fetches it; the rest wait for that fetch and share its result. If
+/// the fetching caller is cancelled, one of the waiters takes over.
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// Runs at most one piece of work per key at a time
+pub struct SingleFlight<K, V> {
+    inflight: Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+    coalesced: AtomicU64,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` for `key`, or wait for the run already in flight
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let done = loop {
+            let mut waiting = {
+                let mut inflight = self.inflight.lock();
+                match inflight.get(&key) {
+                    Some(rx) => rx.clone(),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        inflight.insert(key.clone(), rx);
+                        break tx;
+                    }
+                }
+            };
+            if let Ok(value) = waiting.wait_for(Option::is_some).await {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                return value.clone().expect("waited for a value");
+            }
+            // The running caller was cancelled; take over
+        };
+
+        // Declared after `done`, so waiters find the key gone when woken
+        let _flight = Flight {
+            inflight: &self.inflight,
+            key: &key,
+        };
+        let value = work().await;
+        let _ = done.send(Some(value.clone()));
+        value
+    }
+
+    /// Keys with work in flight
+    pub fn in_flight(&self) -> usize {
+        self.inflight.lock().len()
+    }
+
+    /// Callers that waited on another's run instead of doing the work
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+/// Clears a key once its run finishes or is cancelled
+struct Flight<'a, K: Hash + Eq, V> {
+    inflight: &'a Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+    key: &'a K,
+}
+
+impl<K: Hash + Eq, V> Drop for Flight<'_, K, V> {
+    fn drop(&mut self) {
+        self.inflight.lock().remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_one_run() {
+        let flight = Arc::new(SingleFlight::<&str, u32>::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+        let callers: Vec<_> = (0..8)
+            .map(|_| {
+                let flight = flight.clone();
+                let runs = runs.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("file:0", || async {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            7
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for caller in callers {
+            assert_eq!(caller.await.unwrap(), 7);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(flight.coalesced(), 7);
+        assert_eq!(flight.in_flight(), 0);
+
+        // Later calls run again
+        assert_eq!(flight.run("file:0", || async { 8 }).await, 8);
+    }
+
+    #[tokio::test]
+    async fn test_waiter_takes_over_a_cancelled_run() {
+        let flight = Arc::new(SingleFlight::<u32, u32>::new());
+        let leader = {
+            let flight = flight.clone();
+            tokio::spawn(async move { flight.run(1, std::future::pending::<u32>).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let waiter = {
+            let flight = flight.clone();
+            tokio::spawn(async move { flight.run(1, || async { 2 }).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        leader.abort();
+        assert_eq!(waiter.await.unwrap(), 2);
+        assert_eq!(flight.coalesced(), 0);
+    }
+}
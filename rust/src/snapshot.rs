@@ -0,0 +1,690 @@
+/// Snapshots of node state
+///
+/// A snapshot is a zstd-compressed tarball holding what a node needs to come
+/// back on fresh hardware: manifests, pins, identity keys, the deny list,
+/// content popularity and accounting data (audit log and metrics history).
+/// Shards are left out;
+/// they are fetched from peers again after a restore.
+///
+/// `snapshot.json`, the first entry, records the format version, the node
+/// and the SHA-256 of every other entry. Restore checks all of them before
+/// writing anything and refuses to overwrite existing state unless forced.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::audit::AUDIT_LOG_FILE;
+use crate::cache::{Cache, FileManifest, PINS_FILE};
+use crate::denylist::DENY_LIST_FILE;
+use crate::popularity::POPULARITY_FILE;
+use crate::replication::REPLICATION_KEY_FILE;
+
+/// Snapshot format version written by this node
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Metadata entry at the start of every snapshot
+pub const SNAPSHOT_META: &str = "snapshot.json";
+
+/// Extension of snapshot files
+pub const SNAPSHOT_EXTENSION: &str = "tar.zst";
+
+const NODE_KEY_ENTRY: &str = "keys/node-key.json";
+const REPLICATION_KEY_ENTRY: &str = "keys/replication-key.json";
+const MANIFESTS_PREFIX: &str = "manifests/";
+const METRICS_PREFIX: &str = "metrics/";
+
+/// Largest single entry accepted from a snapshot
+const MAX_ENTRY_BYTES: u64 = 256 * 1024 * 1024;
+
+/// zstd level for snapshot archives
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// Snapshot settings (`[snapshot]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Take snapshots on a schedule while the daemon runs
+    pub enabled: bool,
+    /// Hours between scheduled snapshots
+    pub interval_hours: u64,
+    /// Where scheduled snapshots go (defaults to `snapshots` in the cache directory)
+    pub dir: Option<PathBuf>,
+    /// Scheduled snapshots kept; older ones are deleted
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: 24,
+            dir: None,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    snapshot: SnapshotConfig,
+}
+
+impl SnapshotConfig {
+    /// Load the `[snapshot]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[snapshot]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.snapshot.validate()?;
+        Ok(file.snapshot)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && self.interval_hours == 0 {
+            bail!("snapshot: interval_hours must be greater than 0");
+        }
+        if self.keep == 0 {
+            bail!("snapshot: keep must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Snapshot directory to use when the config does not name one
+    pub fn dir_or(&self, cache_dir: impl AsRef<Path>) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| cache_dir.as_ref().join("snapshots"))
+    }
+}
+
+/// Where each part of a node's state lives on disk
+#[derive(Debug, Clone)]
+pub struct StateLayout {
+    /// Manifests, pins and the deny list
+    pub cache_dir: PathBuf,
+    /// X25519 identity keypair
+    pub node_key: PathBuf,
+    /// Ed25519 manifest replication key
+    pub replication_key: PathBuf,
+    pub audit_log: PathBuf,
+    /// Per-minute metrics aggregates, if the node keeps them
+    pub metrics_dir: Option<PathBuf>,
+}
+
+impl StateLayout {
+    /// Default layout under `cache_dir`, with the identity key at `node_key`
+    pub fn new(cache_dir: impl Into<PathBuf>, node_key: impl Into<PathBuf>) -> Self {
+        let cache_dir = cache_dir.into();
+        Self {
+            node_key: node_key.into(),
+            replication_key: cache_dir.join(REPLICATION_KEY_FILE),
+            audit_log: cache_dir.join(AUDIT_LOG_FILE),
+            metrics_dir: None,
+            cache_dir,
+        }
+    }
+
+    pub fn with_replication_key(mut self, path: impl Into<PathBuf>) -> Self {
+        self.replication_key = path.into();
+        self
+    }
+
+    pub fn with_audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = path.into();
+        self
+    }
+
+    pub fn with_metrics_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.metrics_dir = Some(dir.into());
+        self
+    }
+
+    /// Single-file entries and where they live
+    fn files(&self) -> [(&'static str, PathBuf); 6] {
+        [
+            (PINS_FILE, self.cache_dir.join(PINS_FILE)),
+            (DENY_LIST_FILE, self.cache_dir.join(DENY_LIST_FILE)),
+            (POPULARITY_FILE, self.cache_dir.join(POPULARITY_FILE)),
+            (NODE_KEY_ENTRY, self.node_key.clone()),
+            (REPLICATION_KEY_ENTRY, self.replication_key.clone()),
+            (AUDIT_LOG_FILE, self.audit_log.clone()),
+        ]
+    }
+
+    /// Where a snapshot entry is restored to
+    ///
+    /// `Ok(None)` means the entry has no home on this node (metrics without
+    /// a metrics directory); unknown or unsafe names are errors.
+    fn target(&self, entry: &str) -> Result<Option<PathBuf>> {
+        if let Some((_, path)) = self.files().into_iter().find(|(name, _)| *name == entry) {
+            return Ok(Some(path));
+        }
+        if let Some(name) = entry.strip_prefix(MANIFESTS_PREFIX) {
+            return Ok(Some(
+                self.cache_dir.join("manifests").join(plain_name(name)?),
+            ));
+        }
+        if let Some(name) = entry.strip_prefix(METRICS_PREFIX) {
+            let name = plain_name(name)?;
+            return Ok(self.metrics_dir.as_ref().map(|dir| dir.join(name)));
+        }
+        bail!("Unexpected snapshot entry {:?}", entry)
+    }
+}
+
+/// A file name without directory components
+fn plain_name(name: &str) -> Result<&str> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        bail!("Unsafe snapshot entry name {:?}", name);
+    }
+    Ok(name)
+}
+
+/// Contents of `snapshot.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMeta {
+    pub version: u32,
+    pub node_id: u32,
+    /// Unix seconds
+    pub created_at: u64,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// One archived file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents
+    pub sha256: String,
+}
+
+/// A snapshot written to disk
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub manifests: usize,
+    pub meta: SnapshotMeta,
+}
+
+/// Outcome of a restore
+#[derive(Debug, Clone)]
+pub struct RestoreReport {
+    pub meta: SnapshotMeta,
+    pub files_restored: usize,
+    pub manifests: usize,
+    /// Entries this node had nowhere to put
+    pub skipped: Vec<String>,
+}
+
+/// Takes snapshots of one node's state
+pub struct Snapshotter {
+    node_id: u32,
+    layout: StateLayout,
+    cache: Arc<Cache>,
+}
+
+impl Snapshotter {
+    /// Manifests and pins are read from `cache`, so a running node's
+    /// snapshot matches what it serves; everything else is read from disk.
+    pub fn new(node_id: u32, layout: StateLayout, cache: Arc<Cache>) -> Self {
+        Self {
+            node_id,
+            layout,
+            cache,
+        }
+    }
+
+    /// Write a snapshot to `path`
+    ///
+    /// The archive is written next to `path` and renamed into place, so a
+    /// crash never leaves a truncated snapshot under the final name.
+    pub async fn create(&self, path: impl AsRef<Path>) -> Result<SnapshotInfo> {
+        let path = path.as_ref().to_path_buf();
+        let (entries, manifests) = self.collect().await?;
+        let meta = SnapshotMeta {
+            version: SNAPSHOT_VERSION,
+            node_id: self.node_id,
+            created_at: unix_now(),
+            entries: entries
+                .iter()
+                .map(|(name, data)| SnapshotEntry {
+                    path: name.clone(),
+                    size: data.len() as u64,
+                    sha256: sha256_hex(data),
+                })
+                .collect(),
+        };
+
+        let archive_meta = meta.clone();
+        let target = path.clone();
+        let bytes =
+            tokio::task::spawn_blocking(move || write_archive(&target, &archive_meta, entries))
+                .await??;
+        info!(
+            "📸 Snapshot of {} file(s) written to {:?} ({} bytes)",
+            meta.entries.len(),
+            path,
+            bytes
+        );
+        Ok(SnapshotInfo {
+            path,
+            bytes,
+            manifests,
+            meta,
+        })
+    }
+
+    /// Write a timestamped snapshot into `dir`, keeping only the newest `keep`
+    pub async fn create_in(&self, dir: impl AsRef<Path>, keep: usize) -> Result<SnapshotInfo> {
+        let dir = dir.as_ref();
+        tokio::fs::create_dir_all(dir).await?;
+        let name = format!(
+            "snapshot-{}-{}.{}",
+            self.node_id,
+            unix_now(),
+            SNAPSHOT_EXTENSION
+        );
+        let info = self.create(dir.join(name)).await?;
+        prune(dir, self.node_id, keep).await?;
+        Ok(info)
+    }
+
+    /// Snapshot into `config`'s directory every `interval_hours`
+    pub fn spawn(self: Arc<Self>, config: &SnapshotConfig, cache_dir: &str) -> JoinHandle<()> {
+        let dir = config.dir_or(cache_dir);
+        let keep = config.keep;
+        let interval = Duration::from_secs(config.interval_hours.max(1) * 60 * 60);
+        info!(
+            "Snapshotting node state to {:?} every {:?} (keeping {})",
+            dir, interval, keep
+        );
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick fires at once; the node just loaded its state
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.create_in(&dir, keep).await {
+                    warn!("Scheduled snapshot failed: {:#}", e);
+                }
+            }
+        })
+    }
+
+    /// Every entry to archive, plus the number of manifests among them
+    async fn collect(&self) -> Result<(Vec<(String, Vec<u8>)>, usize)> {
+        let mut entries = Vec::new();
+
+        let mut manifests = self.cache.get_all_manifests().await?;
+        manifests.sort_by(|a, b| a.file_hash.cmp(&b.file_hash));
+        let manifest_count = manifests.len();
+        for manifest in manifests {
+            entries.push((
+                format!("{}{}.json", MANIFESTS_PREFIX, manifest.file_hash),
+                serde_json::to_vec_pretty(&manifest)?,
+            ));
+        }
+        entries.push((
+            PINS_FILE.to_string(),
+            serde_json::to_vec_pretty(&self.cache.pinned_files().await)?,
+        ));
+
+        for (name, path) in self.layout.files() {
+            if name == PINS_FILE {
+                continue;
+            }
+            match tokio::fs::read(&path).await {
+                Ok(data) => entries.push((name.to_string(), data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+            }
+        }
+
+        if let Some(dir) = &self.layout.metrics_dir {
+            if dir.exists() {
+                let mut names = Vec::new();
+                let mut listing = tokio::fs::read_dir(dir).await?;
+                while let Some(entry) = listing.next_entry().await? {
+                    if entry.file_type().await?.is_file() {
+                        names.push(entry.file_name().to_string_lossy().into_owned());
+                    }
+                }
+                names.sort_unstable();
+                for name in names {
+                    let data = tokio::fs::read(dir.join(&name)).await?;
+                    entries.push((format!("{}{}", METRICS_PREFIX, name), data));
+                }
+            }
+        }
+        Ok((entries, manifest_count))
+    }
+}
+
+/// Restore a snapshot into `layout`
+///
+/// Every entry is read and checked against `snapshot.json` before anything
+/// is written. Existing state is never overwritten unless `force` is set,
+/// and a snapshot of another node is refused: its keys are that node's
+/// identity.
+pub async fn restore(
+    path: impl AsRef<Path>,
+    layout: &StateLayout,
+    node_id: u32,
+    force: bool,
+) -> Result<RestoreReport> {
+    let path = path.as_ref().to_path_buf();
+    let (meta, entries) = tokio::task::spawn_blocking(move || read_archive(&path)).await??;
+    if meta.node_id != node_id {
+        bail!(
+            "Snapshot belongs to node {}; restore it with --node-id {}",
+            meta.node_id,
+            meta.node_id
+        );
+    }
+
+    // Resolve every target and refuse to clobber before writing anything
+    let mut plan = Vec::new();
+    let mut skipped = Vec::new();
+    let mut manifests = 0;
+    for (name, data) in entries {
+        if let Some(json) = name.strip_prefix(MANIFESTS_PREFIX) {
+            let manifest = FileManifest::from_json(std::str::from_utf8(&data)?)
+                .with_context(|| format!("Invalid manifest {:?} in snapshot", json))?;
+            if format!("{}.json", manifest.file_hash) != json {
+                bail!("Snapshot manifest {:?} does not match its name", json);
+            }
+            manifests += 1;
+        }
+        match layout.target(&name)? {
+            Some(target) => {
+                if !force && target.exists() {
+                    bail!(
+                        "{:?} already exists; restore onto a fresh node or pass --force",
+                        target
+                    );
+                }
+                plan.push((target, data));
+            }
+            None => {
+                warn!("Skipping {} (no metrics directory configured)", name);
+                skipped.push(name);
+            }
+        }
+    }
+
+    for (target, data) in &plan {
+        write_atomic(target, data).await?;
+    }
+    info!(
+        "✅ Restored {} file(s) ({} manifest(s)) from a snapshot taken at {}",
+        plan.len(),
+        manifests,
+        meta.created_at
+    );
+    Ok(RestoreReport {
+        meta,
+        files_restored: plan.len(),
+        manifests,
+        skipped,
+    })
+}
+
+/// Write `data` beside `target` and rename it into place, owner-only
+async fn write_atomic(target: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp = target.with_extension("restore-tmp");
+    tokio::fs::write(&tmp, data)
+        .await
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    tokio::fs::rename(&tmp, target).await?;
+    Ok(())
+}
+
+fn write_archive(path: &Path, meta: &SnapshotMeta, entries: Vec<(String, Vec<u8>)>) -> Result<u64> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("partial");
+    let file =
+        std::fs::File::create(&tmp).with_context(|| format!("Failed to create {:?}", tmp))?;
+    let encoder = zstd::stream::write::Encoder::new(file, COMPRESSION_LEVEL)?;
+    let mut archive = tar::Builder::new(encoder);
+
+    let meta_json = serde_json::to_vec_pretty(meta)?;
+    append(&mut archive, SNAPSHOT_META, &meta_json, meta.created_at)?;
+    for (name, data) in &entries {
+        append(&mut archive, name, data, meta.created_at)?;
+    }
+    archive.into_inner()?.finish()?.sync_all()?;
+
+    std::fs::rename(&tmp, path)?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+fn append<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Read and verify a snapshot
+fn read_archive(path: &Path) -> Result<(SnapshotMeta, Vec<(String, Vec<u8>)>)> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut archive = tar::Archive::new(zstd::stream::read::Decoder::new(file)?);
+
+    let mut meta: Option<SnapshotMeta> = None;
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            bail!("Snapshot holds a non-file entry");
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if entry.size() > MAX_ENTRY_BYTES {
+            bail!("Snapshot entry {} is {} bytes", name, entry.size());
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.take(MAX_ENTRY_BYTES).read_to_end(&mut data)?;
+
+        match &meta {
+            None if name == SNAPSHOT_META => {
+                let parsed: SnapshotMeta =
+                    serde_json::from_slice(&data).context("Invalid snapshot metadata")?;
+                if parsed.version == 0 || parsed.version > SNAPSHOT_VERSION {
+                    bail!(
+                        "Snapshot format version {} is not supported (this node reads up to {})",
+                        parsed.version,
+                        SNAPSHOT_VERSION
+                    );
+                }
+                meta = Some(parsed);
+            }
+            None => bail!("Snapshot does not start with {}", SNAPSHOT_META),
+            Some(_) => entries.push((name, data)),
+        }
+    }
+    let meta = meta.context("Snapshot is empty")?;
+
+    let expected: HashMap<&str, &SnapshotEntry> = meta
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+    if expected.len() != meta.entries.len() || expected.len() != entries.len() {
+        bail!(
+            "Snapshot lists {} entries but holds {}",
+            meta.entries.len(),
+            entries.len()
+        );
+    }
+    for (name, data) in &entries {
+        let listed = expected
+            .get(name.as_str())
+            .with_context(|| format!("Snapshot entry {} is not listed in its metadata", name))?;
+        if listed.size != data.len() as u64 || listed.sha256 != sha256_hex(data) {
+            bail!("Snapshot entry {} is corrupt", name);
+        }
+    }
+    Ok((meta, entries))
+}
+
+/// Delete all but the newest `keep` snapshots of `node_id` in `dir`
+async fn prune(dir: &Path, node_id: u32, keep: usize) -> Result<()> {
+    let prefix = format!("snapshot-{}-", node_id);
+    let suffix = format!(".{}", SNAPSHOT_EXTENSION);
+    let mut snapshots = Vec::new();
+    let mut listing = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = listing.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let taken_at = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+            .and_then(|secs| secs.parse::<u64>().ok());
+        if let Some(taken_at) = taken_at {
+            snapshots.push((taken_at, entry.path()));
+        }
+    }
+    snapshots.sort_unstable();
+    let excess = snapshots.len().saturating_sub(keep);
+    for (_, path) in snapshots.into_iter().take(excess) {
+        info!("Removing old snapshot {:?}", path);
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn manifest(file_hash: &str) -> FileManifest {
+        FileManifest {
+            file_hash: file_hash.to_string(),
+            file_name: format!("{}.txt", file_hash),
+            file_size: 10,
+            shard_count: 3,
+            parity_count: 1,
+            shard_locations: vec![(0, 1), (1, 2), (2, 3)],
+            namespace: crate::namespace::DEFAULT_NAMESPACE.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restores_onto_fresh_node() {
+        let old = tempdir().unwrap();
+        let layout = StateLayout::new(old.path().join("cache"), old.path().join("node.key"));
+        let cache = Arc::new(Cache::new(&layout.cache_dir, 100, 1024 * 1024).unwrap());
+        cache.put_manifest(manifest("aaaa")).await.unwrap();
+        cache.put_manifest(manifest("bbbb")).await.unwrap();
+        cache.pin("aaaa").await;
+        std::fs::write(&layout.node_key, b"secret").unwrap();
+        std::fs::write(&layout.audit_log, b"{}\n").unwrap();
+
+        // Older snapshots beyond `keep` are pruned
+        let dir = old.path().join("snapshots");
+        std::fs::create_dir_all(&dir).unwrap();
+        for taken_at in [100, 200] {
+            std::fs::write(dir.join(format!("snapshot-4-{}.tar.zst", taken_at)), b"").unwrap();
+        }
+        let snapshotter = Snapshotter::new(4, layout, cache);
+        let snapshot = snapshotter.create_in(&dir, 2).await.unwrap().path;
+        assert!(!dir.join("snapshot-4-100.tar.zst").exists());
+        assert!(dir.join("snapshot-4-200.tar.zst").exists());
+
+        let fresh = tempdir().unwrap();
+        let target = StateLayout::new(fresh.path().join("cache"), fresh.path().join("node.key"));
+        assert!(restore(&snapshot, &target, 5, false).await.is_err());
+        let report = restore(&snapshot, &target, 4, false).await.unwrap();
+        assert_eq!(report.manifests, 2);
+        assert_eq!(std::fs::read(&target.node_key).unwrap(), b"secret");
+
+        let restored = Cache::new(&target.cache_dir, 100, 1024 * 1024).unwrap();
+        assert_eq!(restored.load_persisted_manifests().await.unwrap(), 2);
+        assert_eq!(restored.pinned_files().await, vec!["aaaa"]);
+
+        // Existing state is left alone without --force
+        assert!(restore(&snapshot, &target, 4, false).await.is_err());
+        assert!(restore(&snapshot, &target, 4, true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_snapshot_is_rejected() {
+        let dir = tempdir().unwrap();
+        let layout = StateLayout::new(dir.path().join("cache"), dir.path().join("node.key"));
+        std::fs::create_dir_all(&layout.cache_dir).unwrap();
+        std::fs::write(&layout.node_key, b"secret").unwrap();
+        let meta = SnapshotMeta {
+            version: SNAPSHOT_VERSION,
+            node_id: 1,
+            created_at: 0,
+            entries: vec![SnapshotEntry {
+                path: NODE_KEY_ENTRY.to_string(),
+                size: 6,
+                sha256: sha256_hex(b"secret"),
+            }],
+        };
+        let path = dir.path().join("bad.tar.zst");
+        write_archive(
+            &path,
+            &meta,
+            vec![(NODE_KEY_ENTRY.into(), b"sekret".to_vec())],
+        )
+        .unwrap();
+        let err = read_archive(&path).unwrap_err();
+        assert!(err.to_string().contains("corrupt"), "{}", err);
+
+        let escape = vec![("metrics/nested/evil".to_string(), b"x".to_vec())];
+        let mut meta = meta;
+        meta.entries = vec![SnapshotEntry {
+            path: escape[0].0.clone(),
+            size: 1,
+            sha256: sha256_hex(b"x"),
+        }];
+        write_archive(&path, &meta, escape).unwrap();
+        assert!(restore(&path, &layout, 1, true).await.is_err());
+    }
+}
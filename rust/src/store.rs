@@ -1,27 +1,434 @@
-use anyhow::Result;
-use std::collections::HashMap;
+use anyhow::{Context, Result};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, info, warn};
 
-use crate::types::{Node, NodeStatus};
+use crate::geo::GeoDatabase;
+use crate::types::{current_timestamp, ConnectionQuality, Node, NodeStatus};
+
+/// Maximum latency samples kept per peer
+const MAX_LATENCY_SAMPLES: usize = 64;
+
+/// Default half-life for latency sample weights
+const DEFAULT_LATENCY_HALF_LIFE: Duration = Duration::from_secs(300);
+
+/// Weight of the newest sample in a peer's throughput average
+const THROUGHPUT_EWMA_ALPHA: f64 = 0.3;
+
+/// Transfers smaller than this measure latency rather than throughput
+const MIN_THROUGHPUT_SAMPLE_BYTES: usize = 16 * 1024;
+
+/// Weight of the newest probe in a peer's bandwidth estimate
+const BANDWIDTH_EWMA_ALPHA: f64 = 0.5;
+
+/// Transfer size sources are ranked for (a typical shard)
+const RANK_TRANSFER_BYTES: usize = 1024 * 1024;
+
+/// Longest storage class / peer tag name
+pub const MAX_STORAGE_CLASS_LEN: usize = 32;
+
+/// Peer events buffered per subscriber before it lags
+const PEER_EVENT_CAPACITY: usize = 256;
+
+/// Bad shards after which a peer is tried last
+const DEFAULT_DEPRIORITIZE_AFTER: u32 = 1;
+
+/// Bad shards after which a peer is no longer asked for shards
+const DEFAULT_BAN_AFTER: u32 = 3;
+
+/// When peers that served corrupt shards are deprioritized and banned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MisbehaviorPolicy {
+    pub deprioritize_after: u32,
+    pub ban_after: u32,
+}
+
+impl Default for MisbehaviorPolicy {
+    fn default() -> Self {
+        Self {
+            deprioritize_after: DEFAULT_DEPRIORITIZE_AFTER,
+            ban_after: DEFAULT_BAN_AFTER,
+        }
+    }
+}
+
+/// Ledger entry for a peer that served shards failing their hash check
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MisbehaviorRecord {
+    pub peer_id: u32,
+    pub bad_shards: u32,
+    /// Unix seconds of the most recent offence
+    pub last_offense: u64,
+    /// Most recent offending shard (`file_hash:index`)
+    pub last_shard: String,
+    pub banned: bool,
+}
+
+/// Totals over the misbehavior ledger
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MisbehaviorStats {
+    pub offenders: usize,
+    pub banned: usize,
+    pub bad_shards: u64,
+}
+
+/// Change to the set of known peers
+///
+/// Metric refreshes (latency, threat score, packet loss) are not events;
+/// subscribers that need them still read the store.
+#[derive(Debug, Clone)]
+pub enum PeerEvent {
+    /// A node became known
+    PeerAdded(Node),
+    /// A known node's address, identity or status changed
+    PeerUpdated(Node),
+    /// A node stopped being active or was removed
+    PeerOffline { peer_id: u32 },
+    /// A node served enough bad shards to be banned
+    PeerBanned(MisbehaviorRecord),
+}
+
+impl PeerEvent {
+    pub fn peer_id(&self) -> u32 {
+        match self {
+            PeerEvent::PeerAdded(node) | PeerEvent::PeerUpdated(node) => node.id,
+            PeerEvent::PeerOffline { peer_id } => *peer_id,
+            PeerEvent::PeerBanned(record) => record.peer_id,
+        }
+    }
+
+    /// Whether the peer can no longer be relied on for shards
+    pub fn is_loss(&self) -> bool {
+        matches!(
+            self,
+            PeerEvent::PeerOffline { .. } | PeerEvent::PeerBanned(_)
+        )
+    }
+}
+
+/// A subscription to [`PeerEvent`]s
+///
+/// Starts with a replay of the store's state at subscription time (every
+/// node added, the inactive ones then offline, banned peers banned), then
+/// yields live events, so a subscriber never misses a peer.
+pub struct PeerEvents {
+    replay: VecDeque<PeerEvent>,
+    receiver: broadcast::Receiver<PeerEvent>,
+}
+
+impl PeerEvents {
+    /// Next event, `None` once the store is gone
+    ///
+    /// A subscriber too slow to keep up skips what it missed.
+    pub async fn recv(&mut self) -> Option<PeerEvent> {
+        if let Some(event) = self.replay.pop_front() {
+            return Some(event);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Peer event subscriber lagged, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Drop the replay, keeping only live events
+    pub fn skip_replay(mut self) -> Self {
+        self.replay.clear();
+        self
+    }
+
+    /// Wait for a peer to go offline or be banned; returns its ID
+    pub async fn next_peer_loss(&mut self) -> Option<u32> {
+        loop {
+            let event = self.recv().await?;
+            if event.is_loss() {
+                return Some(event.peer_id());
+            }
+        }
+    }
+}
+
+/// Check a storage class (peer tag) name: 1-32 of `[a-z0-9_-]`
+pub fn validate_storage_class(class: &str) -> Result<()> {
+    let valid = !class.is_empty()
+        && class.len() <= MAX_STORAGE_CLASS_LEN
+        && class
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+    if !valid {
+        anyhow::bail!(
+            "Invalid storage class {:?}: use 1-{} characters of a-z, 0-9, '-' and '_'",
+            class,
+            MAX_STORAGE_CLASS_LEN
+        );
+    }
+    Ok(())
+}
+
+/// Peers in each storage class (`[storage_classes]` table of the node config)
+///
+/// ```toml
+/// [storage_classes]
+/// archival = [4, 5, 6]
+/// edge = [1, 2]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageClassConfig {
+    pub classes: BTreeMap<String, Vec<u32>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    storage_classes: BTreeMap<String, Vec<u32>>,
+}
+
+impl StorageClassConfig {
+    /// Load the `[storage_classes]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[storage_classes]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        for class in file.storage_classes.keys() {
+            validate_storage_class(class)?;
+        }
+        Ok(Self {
+            classes: file.storage_classes,
+        })
+    }
+}
+
+/// Probed available bandwidth towards a peer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BandwidthEstimate {
+    pub bytes_per_sec: f64,
+    pub samples: u32,
+    /// Unix seconds of the latest probe
+    pub measured_at: u64,
+}
+
+/// Rolling latency summary for a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerLatency {
+    pub peer_id: u32,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub samples: usize,
+}
+
+/// Recent operation latencies for one peer
+#[derive(Debug, Clone, Default)]
+struct LatencyHistory {
+    samples: VecDeque<(f64, Instant)>,
+}
+
+impl LatencyHistory {
+    fn record(&mut self, latency_ms: f64) {
+        if self.samples.len() >= MAX_LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((latency_ms, Instant::now()));
+    }
+
+    /// Percentile weighted by sample age, so stale measurements fade out
+    fn percentile(&self, percentile: f64, half_life: Duration) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let half_life = half_life.as_secs_f64().max(f64::EPSILON);
+        let mut weighted: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|(latency, at)| {
+                let age = at.elapsed().as_secs_f64();
+                (*latency, 0.5f64.powf(age / half_life))
+            })
+            .collect();
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = weighted.iter().map(|(_, w)| w).sum();
+        let target = total * percentile.clamp(0.0, 1.0);
+        let mut cumulative = 0.0;
+        for (latency, weight) in &weighted {
+            cumulative += weight;
+            if cumulative >= target {
+                return Some(*latency);
+            }
+        }
+        weighted.last().map(|(latency, _)| *latency)
+    }
+}
 
 /// Thread-safe node storage
 pub struct NodeStore {
     nodes: Arc<RwLock<HashMap<u32, Node>>>,
+    latencies: Arc<RwLock<HashMap<u32, LatencyHistory>>>,
+    latency_half_life: Duration,
+    /// Moving average of bytes/second each peer moved shards at
+    throughputs: Arc<RwLock<HashMap<u32, f64>>>,
+    /// Available bandwidth from active probes, before any transfer
+    bandwidths: Arc<RwLock<HashMap<u32, BandwidthEstimate>>>,
+    /// Latest connection quality estimate of each connected peer
+    qualities: Arc<RwLock<HashMap<u32, ConnectionQuality>>>,
+    geo: Option<Arc<GeoDatabase>>,
+    misbehavior: Arc<RwLock<HashMap<u32, MisbehaviorRecord>>>,
+    misbehavior_policy: MisbehaviorPolicy,
+    /// Storage classes (e.g. `archival`, `edge`) each peer belongs to; kept
+    /// apart from `nodes` so refreshing a node keeps its tags
+    tags: Arc<RwLock<HashMap<u32, BTreeSet<String>>>>,
+    events: broadcast::Sender<PeerEvent>,
 }
 
 impl NodeStore {
     pub fn new() -> Self {
+        Self::with_latency_half_life(DEFAULT_LATENCY_HALF_LIFE)
+    }
+
+    /// Create a store with a custom decay half-life for latency samples
+    pub fn with_latency_half_life(latency_half_life: Duration) -> Self {
         Self {
             nodes: Arc::new(RwLock::new(HashMap::new())),
+            latencies: Arc::new(RwLock::new(HashMap::new())),
+            latency_half_life,
+            throughputs: Arc::new(RwLock::new(HashMap::new())),
+            bandwidths: Arc::new(RwLock::new(HashMap::new())),
+            qualities: Arc::new(RwLock::new(HashMap::new())),
+            geo: None,
+            misbehavior: Arc::new(RwLock::new(HashMap::new())),
+            misbehavior_policy: MisbehaviorPolicy::default(),
+            tags: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(PEER_EVENT_CAPACITY).0,
         }
     }
 
+    /// Look up country/ASN of peers in `geo` when they connect
+    pub fn with_geo(mut self, geo: Arc<GeoDatabase>) -> Self {
+        self.geo = Some(geo);
+        self
+    }
+
+    /// Override when bad-shard offenders are deprioritized and banned
+    pub fn with_misbehavior_policy(mut self, policy: MisbehaviorPolicy) -> Self {
+        self.misbehavior_policy = policy;
+        self
+    }
+
+    /// Note the address a peer connected from, adding its country/ASN
+    ///
+    /// Creates the node if it is not known yet. Without a geo database this
+    /// only registers the node.
+    pub async fn record_peer_address(&self, node_id: u32, ip: IpAddr) {
+        let geo = self.geo.as_ref().and_then(|db| db.lookup(ip));
+        if let Some(geo) = &geo {
+            debug!("Peer {} at {} is in {}", node_id, ip, geo.failure_domain());
+        }
+
+        let mut nodes = self.nodes.write().await;
+        let added = !nodes.contains_key(&node_id);
+        let node = nodes.entry(node_id).or_insert_with(|| Node::new(node_id));
+        let updated = geo.is_some();
+        if updated {
+            node.geo = geo;
+        }
+        if added {
+            self.publish(PeerEvent::PeerAdded(node.clone()));
+        } else if updated {
+            self.publish(PeerEvent::PeerUpdated(node.clone()));
+        }
+    }
+
+    /// Remember the identity `node_id` proved when connected to
+    pub async fn record_peer_identity(&self, node_id: u32, identity: &PeerId) {
+        let identity = identity.to_base58();
+        let mut nodes = self.nodes.write().await;
+        let added = !nodes.contains_key(&node_id);
+        let node = nodes.entry(node_id).or_insert_with(|| Node::new(node_id));
+        if node.identity.as_deref() == Some(identity.as_str()) {
+            return;
+        }
+        node.identity = Some(identity);
+        self.publish(if added {
+            PeerEvent::PeerAdded(node.clone())
+        } else {
+            PeerEvent::PeerUpdated(node.clone())
+        });
+    }
+
     /// Add or update a node
     pub async fn upsert_node(&self, node: Node) {
         let mut nodes = self.nodes.write().await;
-        nodes.insert(node.id, node);
+        let event = node.clone();
+        match nodes.insert(node.id, node) {
+            None => self.publish(PeerEvent::PeerAdded(event)),
+            Some(previous) => self.publish_change(&previous, event),
+        }
+    }
+
+    /// Subscribe to peer events, starting with a replay of current state
+    pub async fn subscribe_events(&self) -> PeerEvents {
+        // Subscribing under the locks events are sent under keeps the replay
+        // and the live events from overlapping or leaving a gap
+        let nodes = self.nodes.read().await;
+        let ledger = self.misbehavior.read().await;
+        let receiver = self.events.subscribe();
+
+        let mut known: Vec<&Node> = nodes.values().collect();
+        known.sort_unstable_by_key(|node| node.id);
+        let mut replay: VecDeque<PeerEvent> = known
+            .iter()
+            .map(|node| PeerEvent::PeerAdded((*node).clone()))
+            .collect();
+        replay.extend(
+            known
+                .iter()
+                .filter(|node| node.status != NodeStatus::Active)
+                .map(|node| PeerEvent::PeerOffline { peer_id: node.id }),
+        );
+        let mut banned: Vec<&MisbehaviorRecord> =
+            ledger.values().filter(|record| record.banned).collect();
+        banned.sort_unstable_by_key(|record| record.peer_id);
+        replay.extend(banned.into_iter().cloned().map(PeerEvent::PeerBanned));
+
+        PeerEvents { replay, receiver }
+    }
+
+    /// Best effort: nobody listening is not an error
+    fn publish(&self, event: PeerEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Announce `node` updated, and offline if it just stopped being active.
+    /// Nothing is published when only its metrics changed.
+    fn publish_change(&self, previous: &Node, node: Node) {
+        if previous.status == node.status
+            && previous.geo == node.geo
+            && previous.identity == node.identity
+        {
+            return;
+        }
+        let went_offline =
+            previous.status == NodeStatus::Active && node.status != NodeStatus::Active;
+        let peer_id = node.id;
+        self.publish(PeerEvent::PeerUpdated(node));
+        if went_offline {
+            self.publish(PeerEvent::PeerOffline { peer_id });
+        }
     }
 
     /// Get a node by ID
@@ -46,6 +453,375 @@ impl NodeStore {
         Ok(())
     }
 
+    /// Record the latency of an operation against a peer
+    ///
+    /// Also refreshes the node's latency/jitter with the rolling p50.
+    pub async fn record_peer_latency(&self, node_id: u32, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let p50 = {
+            let mut latencies = self.latencies.write().await;
+            let history = latencies.entry(node_id).or_default();
+            history.record(latency_ms);
+            history.percentile(0.5, self.latency_half_life)
+        };
+
+        if let Some(p50) = p50 {
+            let mut nodes = self.nodes.write().await;
+            if let Some(node) = nodes.get_mut(&node_id) {
+                node.update_latency(p50 as f32);
+            }
+        }
+    }
+
+    /// Rolling p50/p95 latency for a peer
+    pub async fn peer_latency(&self, node_id: u32) -> Option<PeerLatency> {
+        let latencies = self.latencies.read().await;
+        let history = latencies.get(&node_id)?;
+        Some(PeerLatency {
+            peer_id: node_id,
+            p50_ms: history.percentile(0.5, self.latency_half_life)?,
+            p95_ms: history.percentile(0.95, self.latency_half_life)?,
+            samples: history.samples.len(),
+        })
+    }
+
+    /// Record a shard of `bytes` moved to or from a peer in `elapsed`
+    ///
+    /// Transfers too small to measure bandwidth are ignored.
+    pub async fn record_peer_throughput(&self, node_id: u32, bytes: usize, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if bytes < MIN_THROUGHPUT_SAMPLE_BYTES || secs <= 0.0 {
+            return;
+        }
+        let sample = bytes as f64 / secs;
+        let mut throughputs = self.throughputs.write().await;
+        throughputs
+            .entry(node_id)
+            .and_modify(|avg| *avg += THROUGHPUT_EWMA_ALPHA * (sample - *avg))
+            .or_insert(sample);
+    }
+
+    /// Average bytes/second measured for a peer
+    pub async fn peer_throughput(&self, node_id: u32) -> Option<f64> {
+        self.throughputs.read().await.get(&node_id).copied()
+    }
+
+    /// Median measured throughput across `peers`, `None` if none are measured
+    pub async fn median_throughput(&self, peers: &[u32]) -> Option<f64> {
+        let throughputs = self.throughputs.read().await;
+        let mut measured: Vec<f64> = peers
+            .iter()
+            .filter_map(|peer| throughputs.get(peer).copied())
+            .collect();
+        if measured.is_empty() {
+            return None;
+        }
+        measured.sort_by(f64::total_cmp);
+        Some(measured[measured.len() / 2])
+    }
+
+    /// Record a probe's estimate of the bandwidth available to a peer
+    pub async fn record_peer_bandwidth(&self, node_id: u32, bytes_per_sec: f64) {
+        if !bytes_per_sec.is_finite() || bytes_per_sec <= 0.0 {
+            return;
+        }
+        let mut bandwidths = self.bandwidths.write().await;
+        let estimate = bandwidths.entry(node_id).or_insert(BandwidthEstimate {
+            bytes_per_sec,
+            samples: 0,
+            measured_at: 0,
+        });
+        estimate.bytes_per_sec += BANDWIDTH_EWMA_ALPHA * (bytes_per_sec - estimate.bytes_per_sec);
+        estimate.samples += 1;
+        estimate.measured_at = current_timestamp();
+    }
+
+    pub async fn peer_bandwidth(&self, node_id: u32) -> Option<BandwidthEstimate> {
+        self.bandwidths.read().await.get(&node_id).copied()
+    }
+
+    /// Bytes/second expected from a peer: measured by transfers, else probed
+    pub async fn peer_transfer_rate(&self, node_id: u32) -> Option<f64> {
+        match self.peer_throughput(node_id).await {
+            Some(throughput) => Some(throughput),
+            None => self
+                .peer_bandwidth(node_id)
+                .await
+                .map(|estimate| estimate.bytes_per_sec),
+        }
+    }
+
+    /// Record a peer's connection quality, refreshing the node's latency,
+    /// jitter and packet loss with it
+    pub async fn record_connection_quality(&self, node_id: u32, quality: &ConnectionQuality) {
+        self.qualities
+            .write()
+            .await
+            .insert(node_id, quality.clone());
+
+        let mut nodes = self.nodes.write().await;
+        let node = nodes.entry(node_id).or_insert_with(|| Node::new(node_id));
+        node.latency_ms = quality.latency_ms;
+        node.jitter_ms = quality.jitter_ms;
+        node.update_packet_loss(quality.packet_loss);
+    }
+
+    /// Latest connection quality of a peer
+    pub async fn connection_quality(&self, node_id: u32) -> Option<ConnectionQuality> {
+        self.qualities.read().await.get(&node_id).cloned()
+    }
+
+    /// Order peers by the expected time to move `bytes`: rolling p95
+    /// latency plus `bytes` at the probed bandwidth
+    ///
+    /// Peers not yet probed are assumed to have the median bandwidth of
+    /// those that were, so without any probes this is latency order. Peers
+    /// with neither measurement keep their relative order at the end.
+    pub async fn rank_by_expected_time(&self, peers: &[u32], bytes: usize) -> Vec<u32> {
+        let latencies = self.latencies.read().await;
+        let bandwidths = self.bandwidths.read().await;
+        let mut probed: Vec<f64> = peers
+            .iter()
+            .filter_map(|peer| bandwidths.get(peer).map(|b| b.bytes_per_sec))
+            .collect();
+        probed.sort_by(f64::total_cmp);
+        let typical = probed.get(probed.len() / 2).copied();
+
+        let mut measured = Vec::new();
+        let mut unmeasured = Vec::new();
+        for peer in peers {
+            let latency = latencies
+                .get(peer)
+                .and_then(|h| h.percentile(0.95, self.latency_half_life));
+            let bandwidth = bandwidths.get(peer).map(|b| b.bytes_per_sec);
+            if latency.is_none() && bandwidth.is_none() {
+                unmeasured.push(*peer);
+                continue;
+            }
+            let transfer_secs = bandwidth.or(typical).map_or(0.0, |bps| bytes as f64 / bps);
+            let expected = latency.unwrap_or(0.0) / 1000.0 + transfer_secs;
+            measured.push((*peer, expected));
+        }
+
+        measured.sort_by(|a, b| a.1.total_cmp(&b.1));
+        measured
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .chain(unmeasured)
+            .collect()
+    }
+
+    /// Order peers by rolling p95 latency (fastest first)
+    ///
+    /// Peers without measurements keep their relative order after measured ones.
+    pub async fn rank_by_latency(&self, peers: &[u32]) -> Vec<u32> {
+        let latencies = self.latencies.read().await;
+        let mut measured = Vec::new();
+        let mut unmeasured = Vec::new();
+
+        for peer in peers {
+            match latencies
+                .get(peer)
+                .and_then(|h| h.percentile(0.95, self.latency_half_life))
+            {
+                Some(p95) => measured.push((*peer, p95)),
+                None => unmeasured.push(*peer),
+            }
+        }
+
+        measured.sort_by(|a, b| a.1.total_cmp(&b.1));
+        measured
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .chain(unmeasured)
+            .collect()
+    }
+
+    /// Record that `peer_id` served a shard failing its hash check
+    ///
+    /// Returns the peer's updated ledger entry; repeat offenders are banned
+    /// once they reach the policy's `ban_after`.
+    pub async fn report_bad_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> MisbehaviorRecord {
+        let mut ledger = self.misbehavior.write().await;
+        let record = ledger.entry(peer_id).or_insert_with(|| MisbehaviorRecord {
+            peer_id,
+            ..Default::default()
+        });
+        record.bad_shards += 1;
+        record.last_offense = current_timestamp();
+        record.last_shard = format!("{}:{}", file_hash, shard_index);
+        if !record.banned && record.bad_shards >= self.misbehavior_policy.ban_after {
+            record.banned = true;
+            warn!(
+                "🚫 Banning peer {} after {} bad shards",
+                peer_id, record.bad_shards
+            );
+            self.publish(PeerEvent::PeerBanned(record.clone()));
+        }
+        record.clone()
+    }
+
+    pub async fn misbehavior(&self, peer_id: u32) -> Option<MisbehaviorRecord> {
+        self.misbehavior.read().await.get(&peer_id).cloned()
+    }
+
+    /// Every peer with a bad shard on record, worst first
+    pub async fn misbehaving_peers(&self) -> Vec<MisbehaviorRecord> {
+        let mut records: Vec<_> = self.misbehavior.read().await.values().cloned().collect();
+        records.sort_by(|a, b| {
+            b.bad_shards
+                .cmp(&a.bad_shards)
+                .then(a.peer_id.cmp(&b.peer_id))
+        });
+        records
+    }
+
+    pub async fn misbehavior_stats(&self) -> MisbehaviorStats {
+        let ledger = self.misbehavior.read().await;
+        MisbehaviorStats {
+            offenders: ledger.len(),
+            banned: ledger.values().filter(|r| r.banned).count(),
+            bad_shards: ledger.values().map(|r| r.bad_shards as u64).sum(),
+        }
+    }
+
+    pub async fn is_banned(&self, peer_id: u32) -> bool {
+        self.misbehavior
+            .read()
+            .await
+            .get(&peer_id)
+            .is_some_and(|r| r.banned)
+    }
+
+    /// Forget a peer's offences, lifting any ban
+    pub async fn pardon(&self, peer_id: u32) -> bool {
+        self.misbehavior.write().await.remove(&peer_id).is_some()
+    }
+
+    /// Order shard sources: banned peers dropped, offenders last, the rest
+    /// by expected time to move a shard (latency plus probed bandwidth)
+    pub async fn rank_sources(&self, peers: &[u32]) -> Vec<u32> {
+        let ranked = self.rank_by_expected_time(peers, RANK_TRANSFER_BYTES).await;
+        let ledger = self.misbehavior.read().await;
+        let (clean, offenders): (Vec<u32>, Vec<u32>) = ranked
+            .into_iter()
+            .filter(|peer| !ledger.get(peer).is_some_and(|r| r.banned))
+            .partition(|peer| {
+                ledger.get(peer).map_or(0, |r| r.bad_shards)
+                    < self.misbehavior_policy.deprioritize_after
+            });
+        clean.into_iter().chain(offenders).collect()
+    }
+
+    /// Reorder peers so consecutive picks come from different failure domains
+    ///
+    /// Shards are assigned to peers round-robin, so interleaving domains keeps
+    /// neighbouring shards apart. Order within a domain, and the order in
+    /// which domains first appear, is preserved; peers without geo data share
+    /// one `unknown` domain.
+    pub async fn spread_across_domains(&self, peers: &[u32]) -> Vec<u32> {
+        let mut domains: Vec<(String, VecDeque<u32>)> = self
+            .group_by_domain(peers)
+            .await
+            .into_iter()
+            .map(|(domain, members)| (domain, members.into()))
+            .collect();
+
+        let mut spread = Vec::with_capacity(peers.len());
+        while spread.len() < peers.len() {
+            for (_, members) in domains.iter_mut() {
+                if let Some(peer) = members.pop_front() {
+                    spread.push(peer);
+                }
+            }
+        }
+        spread
+    }
+
+    /// Peers grouped by failure domain, in order of first appearance
+    ///
+    /// Peers without geo data share one `unknown` domain.
+    pub async fn group_by_domain(&self, peers: &[u32]) -> Vec<(String, Vec<u32>)> {
+        let nodes = self.nodes.read().await;
+        let mut domains: Vec<(String, Vec<u32>)> = Vec::new();
+        for peer in peers {
+            let domain = nodes
+                .get(peer)
+                .map_or_else(|| "unknown".to_string(), Node::failure_domain);
+            match domains.iter_mut().find(|(name, _)| *name == domain) {
+                Some((_, members)) => members.push(*peer),
+                None => domains.push((domain, vec![*peer])),
+            }
+        }
+        domains
+    }
+
+    /// Replace the storage classes a peer belongs to
+    pub async fn set_peer_tags(
+        &self,
+        node_id: u32,
+        tags: impl IntoIterator<Item = String>,
+    ) -> Result<()> {
+        let tags: BTreeSet<String> = tags.into_iter().collect();
+        for tag in &tags {
+            validate_storage_class(tag)?;
+        }
+        let mut all = self.tags.write().await;
+        if tags.is_empty() {
+            all.remove(&node_id);
+        } else {
+            all.insert(node_id, tags);
+        }
+        Ok(())
+    }
+
+    /// Tag every peer listed in `config` with its class(es)
+    pub async fn apply_storage_classes(&self, config: &StorageClassConfig) {
+        if config.classes.is_empty() {
+            return;
+        }
+        let mut all = self.tags.write().await;
+        for (class, peers) in &config.classes {
+            for peer in peers {
+                all.entry(*peer).or_default().insert(class.clone());
+            }
+        }
+        info!(
+            "Storage classes: {}",
+            config
+                .classes
+                .iter()
+                .map(|(class, peers)| format!("{} ({} peer(s))", class, peers.len()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    /// Storage classes a peer belongs to, sorted
+    pub async fn peer_tags(&self, node_id: u32) -> Vec<String> {
+        self.tags
+            .read()
+            .await
+            .get(&node_id)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Keep only the peers in `class`, preserving their order
+    pub async fn filter_by_class(&self, peers: &[u32], class: &str) -> Vec<u32> {
+        let tags = self.tags.read().await;
+        peers
+            .iter()
+            .copied()
+            .filter(|peer| tags.get(peer).is_some_and(|t| t.contains(class)))
+            .collect()
+    }
+
     /// Update node threat score
     pub async fn update_threat_score(&self, node_id: u32, threat_score: f32) -> Result<()> {
         let mut nodes = self.nodes.write().await;
@@ -71,7 +847,38 @@ impl NodeStore {
     /// Remove a node
     pub async fn remove_node(&self, id: u32) {
         let mut nodes = self.nodes.write().await;
-        nodes.remove(&id);
+        if nodes.remove(&id).is_some() {
+            self.publish(PeerEvent::PeerOffline { peer_id: id });
+        }
+        self.latencies.write().await.remove(&id);
+        self.bandwidths.write().await.remove(&id);
+    }
+
+    /// Mark a peer dead, as if it had stopped answering (chaos testing)
+    ///
+    /// Returns false if the peer is unknown.
+    #[cfg(feature = "chaos")]
+    pub async fn chaos_kill_peer(&self, id: u32) -> bool {
+        self.set_status(id, NodeStatus::Dead).await
+    }
+
+    /// Bring a peer killed with `chaos_kill_peer` back
+    #[cfg(feature = "chaos")]
+    pub async fn chaos_revive_peer(&self, id: u32) -> bool {
+        self.set_status(id, NodeStatus::Active).await
+    }
+
+    #[cfg(feature = "chaos")]
+    async fn set_status(&self, id: u32, status: NodeStatus) -> bool {
+        match self.nodes.write().await.get_mut(&id) {
+            Some(node) => {
+                let previous = node.clone();
+                node.status = status;
+                self.publish_change(&previous, node.clone());
+                true
+            }
+            None => false,
+        }
     }
 
     /// Get nodes by status
@@ -100,3 +907,228 @@ impl Default for NodeStore {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_throughput_average() {
+        let store = NodeStore::new();
+        let second = Duration::from_secs(1);
+        store.record_peer_throughput(1, 1_000_000, second).await;
+        store.record_peer_throughput(1, 2_000_000, second).await;
+        assert_eq!(store.peer_throughput(1).await, Some(1_300_000.0));
+
+        // Tiny transfers say nothing about bandwidth
+        store
+            .record_peer_throughput(2, 100, Duration::from_millis(1))
+            .await;
+        assert_eq!(store.peer_throughput(2).await, None);
+
+        store.record_peer_throughput(3, 4_000_000, second).await;
+        store.record_peer_throughput(4, 500_000, second).await;
+        assert_eq!(
+            store.median_throughput(&[1, 2, 3, 4]).await,
+            Some(1_300_000.0)
+        );
+        assert_eq!(store.median_throughput(&[2, 5]).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_storage_classes_filter_peers() {
+        let config = StorageClassConfig::from_toml(
+            "[storage_classes]\narchival = [4, 5, 6]\nedge = [1, 2, 6]\n",
+        )
+        .unwrap();
+        let store = NodeStore::new();
+        store.apply_storage_classes(&config).await;
+
+        // Refreshing a node does not drop its tags
+        store.upsert_node(Node::new(6)).await;
+        assert_eq!(store.peer_tags(6).await, vec!["archival", "edge"]);
+        assert_eq!(
+            store.filter_by_class(&[6, 1, 5, 3], "archival").await,
+            vec![6, 5]
+        );
+        assert!(store.filter_by_class(&[1, 2], "cold").await.is_empty());
+
+        store.set_peer_tags(6, Vec::new()).await.unwrap();
+        assert_eq!(store.filter_by_class(&[6, 1], "edge").await, vec![1]);
+        assert!(store.set_peer_tags(1, ["Fast!".to_string()]).await.is_err());
+        assert!(StorageClassConfig::from_toml("[storage_classes]\n\"\" = [1]\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_peer_latency_percentiles() {
+        let store = NodeStore::new();
+        store.upsert_node(Node::new(1)).await;
+        for ms in 1..=100 {
+            store
+                .record_peer_latency(1, Duration::from_millis(ms))
+                .await;
+        }
+
+        let summary = store.peer_latency(1).await.unwrap();
+        // Only the most recent MAX_LATENCY_SAMPLES are kept (37..=100)
+        assert_eq!(summary.samples, MAX_LATENCY_SAMPLES);
+        assert!((summary.p50_ms - 68.0).abs() < 3.0);
+        assert!(summary.p95_ms > 95.0);
+
+        let node = store.get_node(1).await.unwrap();
+        assert!((node.latency_ms - summary.p50_ms as f32).abs() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_misbehavior_ledger_deprioritizes_then_bans() {
+        let store = NodeStore::new();
+        assert_eq!(store.rank_sources(&[1, 2, 3]).await, vec![1, 2, 3]);
+
+        store.report_bad_shard(1, "file", 0).await;
+        assert_eq!(store.rank_sources(&[1, 2, 3]).await, vec![2, 3, 1]);
+
+        store.report_bad_shard(1, "file", 1).await;
+        let record = store.report_bad_shard(1, "file", 2).await;
+        assert!(record.banned);
+        assert_eq!(record.last_shard, "file:2");
+        assert_eq!(store.rank_sources(&[1, 2, 3]).await, vec![2, 3]);
+
+        store.report_bad_shard(2, "file", 0).await;
+        assert_eq!(
+            store.misbehavior_stats().await,
+            MisbehaviorStats {
+                offenders: 2,
+                banned: 1,
+                bad_shards: 4
+            }
+        );
+        assert_eq!(store.misbehaving_peers().await[0].peer_id, 1);
+
+        assert!(store.pardon(1).await);
+        assert!(!store.is_banned(1).await);
+    }
+
+    #[tokio::test]
+    async fn test_spread_across_domains() {
+        let mut db = GeoDatabase::new();
+        db.load_ip2asn(
+            "10.0.0.0\t10.0.0.255\t64500\tDE\tNET-A\n10.0.1.0\t10.0.1.255\t64501\tFR\tNET-B\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let store = NodeStore::new().with_geo(Arc::new(db));
+
+        store
+            .record_peer_address(1, "10.0.0.1".parse().unwrap())
+            .await;
+        store
+            .record_peer_address(2, "10.0.0.2".parse().unwrap())
+            .await;
+        store
+            .record_peer_address(3, "10.0.1.1".parse().unwrap())
+            .await;
+        store.upsert_node(Node::new(4)).await;
+
+        let geo = store.get_node(3).await.unwrap().geo.unwrap();
+        assert_eq!(geo.asn, Some(64501));
+        assert_eq!(geo.country.as_deref(), Some("FR"));
+
+        assert_eq!(
+            store.spread_across_domains(&[1, 2, 3, 4]).await,
+            vec![1, 3, 4, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probed_bandwidth_shapes_source_ranking() {
+        let store = NodeStore::new();
+        store
+            .record_peer_latency(1, Duration::from_millis(10))
+            .await;
+        store
+            .record_peer_latency(2, Duration::from_millis(40))
+            .await;
+        assert_eq!(store.rank_sources(&[2, 1, 3]).await, vec![1, 2, 3]);
+
+        // A 1 MiB shard takes ~1s over peer 1's 1 MB/s but ~10ms over peer 2
+        store.record_peer_bandwidth(1, 1_000_000.0).await;
+        store.record_peer_bandwidth(2, 100_000_000.0).await;
+        store.record_peer_bandwidth(2, f64::NAN).await;
+        assert_eq!(store.rank_sources(&[2, 1, 3]).await, vec![2, 1, 3]);
+
+        store.record_peer_bandwidth(1, 3_000_000.0).await;
+        let estimate = store.peer_bandwidth(1).await.unwrap();
+        assert_eq!(estimate.bytes_per_sec, 2_000_000.0);
+        assert_eq!(estimate.samples, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rank_by_latency() {
+        let store = NodeStore::new();
+        store
+            .record_peer_latency(1, Duration::from_millis(200))
+            .await;
+        store
+            .record_peer_latency(2, Duration::from_millis(20))
+            .await;
+
+        assert_eq!(store.rank_by_latency(&[3, 1, 2]).await, vec![2, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_peer_events_replay_then_follow_changes() {
+        let store = NodeStore::new();
+        store.upsert_node(Node::new(2)).await;
+        let mut dead = Node::new(1);
+        dead.status = NodeStatus::Dead;
+        store.upsert_node(dead).await;
+        for index in 0..3 {
+            store.report_bad_shard(2, "file", index).await;
+        }
+
+        let mut events = store.subscribe_events().await;
+        let replay: Vec<u32> = events.replay.iter().map(PeerEvent::peer_id).collect();
+        assert_eq!(replay, vec![1, 2, 1, 2]);
+        assert!(matches!(events.replay[2], PeerEvent::PeerOffline { .. }));
+        assert!(matches!(&events.replay[3], PeerEvent::PeerBanned(record) if record.banned));
+        events.replay.clear();
+
+        // Live events follow; metric refreshes are not events
+        store.update_latency(2, 10.0).await.unwrap();
+        let mut refreshed = store.get_node(2).await.unwrap();
+        refreshed.latency_ms = 25.0;
+        refreshed.last_seen += 1;
+        store.upsert_node(refreshed).await;
+        store.upsert_node(Node::new(3)).await;
+        let mut offline = Node::new(3);
+        offline.status = NodeStatus::Purgatory;
+        store.upsert_node(offline).await;
+        assert!(matches!(
+            events.recv().await,
+            Some(PeerEvent::PeerAdded(node)) if node.id == 3
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(PeerEvent::PeerUpdated(node)) if node.status == NodeStatus::Purgatory
+        ));
+        assert_eq!(events.next_peer_loss().await, Some(3));
+
+        let mut live = store.subscribe_events().await.skip_replay();
+        store.remove_node(2).await;
+        assert_eq!(live.next_peer_loss().await, Some(2));
+    }
+
+    #[test]
+    fn test_stale_samples_decay() {
+        let mut history = LatencyHistory::default();
+        let old = Instant::now().checked_sub(Duration::from_secs(60)).unwrap();
+        for _ in 0..10 {
+            history.samples.push_back((1000.0, old));
+        }
+        history.record(10.0);
+
+        // Ten minute-old slow samples are outweighed by one fresh fast sample
+        let p50 = history.percentile(0.5, Duration::from_secs(1)).unwrap();
+        assert_eq!(p50, 10.0);
+    }
+}
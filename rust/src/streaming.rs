@@ -15,11 +15,30 @@
 /// - Modularity: Separate concerns for audio, video, and transport
 /// - Low latency: Optimized for real-time communication
 /// - Resilience: Handles packet loss gracefully
-use anyhow::{Context, Result};
-use tokio::sync::mpsc;
+/// - Even levels: received audio is loudness-normalized and limited
+///   (`loudness.rs`), with a per-stream gain and mute for the listener
+/// - Consent-based recording: sessions can be recorded to Ogg Opus and
+///   uploaded at session end (`recording.rs`), reported as `SessionEvent`s
+use anyhow::{bail, Context, Result};
+use rand::RngCore;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
 use tracing::{debug, info, warn};
 
+use crate::automated::AutomatedUploader;
 use crate::codecs::{AudioConfig, AudioDecoder, AudioEncoder};
+use crate::loudness::{GainControl, LoudnessConfig, LoudnessNormalizer, LoudnessStats};
+use crate::recording::{RecordingConfig, RecordingMode, SessionRecorder};
+use crate::types::{ConnectionQuality, ThroughputClass};
+
+/// Fixed part of a serialized packet: sequence, timestamp, type and both
+/// length prefixes
+pub const STREAM_PACKET_HEADER_LEN: usize = 8 + 8 + 1 + 4 + 4;
+
+/// Largest payload or FEC block accepted from the wire
+pub const MAX_STREAM_FIELD_BYTES: usize = 1024 * 1024;
 
 /// Stream type identifier
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,6 +64,8 @@ pub struct StreamConfig {
     pub buffer_size: usize,
     /// Enable Forward Error Correction
     pub enable_fec: bool,
+    /// Loudness normalization and clipping protection of received audio
+    pub loudness: LoudnessConfig,
 }
 
 impl Default for StreamConfig {
@@ -55,6 +76,7 @@ impl Default for StreamConfig {
             max_packet_size: 1350, // Safe size for UDP/QUIC
             buffer_size: 100,
             enable_fec: true,
+            loudness: LoudnessConfig::default(),
         }
     }
 }
@@ -68,6 +90,7 @@ impl StreamConfig {
             max_packet_size: 1350,
             buffer_size: 50, // Smaller buffer for lower latency
             enable_fec: true,
+            loudness: LoudnessConfig::default(),
         }
     }
 
@@ -79,6 +102,7 @@ impl StreamConfig {
             max_packet_size: 1350,
             buffer_size: 100,
             enable_fec: true,
+            loudness: LoudnessConfig::default(),
         }
     }
 }
@@ -105,7 +129,16 @@ impl StreamPacket {
         // [8 bytes: sequence][8 bytes: timestamp][1 byte: stream_type]
         // [4 bytes: payload_len][payload][4 bytes: fec_len][fec_data]
 
-        let mut buffer = Vec::new();
+        let fec_len = self.fec_data.as_ref().map_or(0, Vec::len);
+        if self.payload.len().max(fec_len) > MAX_STREAM_FIELD_BYTES {
+            anyhow::bail!(
+                "Packet field exceeds limit of {} bytes",
+                MAX_STREAM_FIELD_BYTES
+            );
+        }
+
+        let mut buffer =
+            Vec::with_capacity(STREAM_PACKET_HEADER_LEN + self.payload.len() + fec_len);
         buffer.extend_from_slice(&self.sequence.to_be_bytes());
         buffer.extend_from_slice(&self.timestamp.to_be_bytes());
 
@@ -130,8 +163,11 @@ impl StreamPacket {
     }
 
     /// Deserialize packet from bytes
+    ///
+    /// Packets arrive straight off the network, so every length field is
+    /// checked against the buffer and against [`MAX_STREAM_FIELD_BYTES`].
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
-        if data.len() < 21 {
+        if data.len() < STREAM_PACKET_HEADER_LEN {
             anyhow::bail!("Packet too small: {} bytes", data.len());
         }
 
@@ -145,20 +181,17 @@ impl StreamPacket {
             _ => anyhow::bail!("Invalid stream type: {}", data[16]),
         };
 
-        let payload_len = u32::from_be_bytes(data[17..21].try_into()?) as usize;
-        if data.len() < 21 + payload_len + 4 {
-            anyhow::bail!("Incomplete packet data");
+        let (payload, rest) = read_field(&data[17..], "payload")?;
+        let (fec, rest) = read_field(rest, "fec")?;
+        if !rest.is_empty() {
+            anyhow::bail!("{} trailing bytes after packet", rest.len());
         }
 
-        let payload = data[21..21 + payload_len].to_vec();
-        let fec_len_start = 21 + payload_len;
-        let fec_len =
-            u32::from_be_bytes(data[fec_len_start..fec_len_start + 4].try_into()?) as usize;
-
-        let fec_data = if fec_len > 0 {
-            Some(data[fec_len_start + 4..fec_len_start + 4 + fec_len].to_vec())
-        } else {
+        let payload = payload.to_vec();
+        let fec_data = if fec.is_empty() {
             None
+        } else {
+            Some(fec.to_vec())
         };
 
         Ok(StreamPacket {
@@ -171,11 +204,89 @@ impl StreamPacket {
     }
 }
 
+/// Split a `[u32 BE length][bytes]` field off the front of `data`
+fn read_field<'a>(data: &'a [u8], name: &str) -> Result<(&'a [u8], &'a [u8])> {
+    let len_bytes = data
+        .get(..4)
+        .with_context(|| format!("Truncated {} length", name))?;
+    let len = u32::from_be_bytes(len_bytes.try_into()?) as usize;
+    if len > MAX_STREAM_FIELD_BYTES {
+        anyhow::bail!(
+            "{} length {} exceeds limit of {} bytes",
+            name,
+            len,
+            MAX_STREAM_FIELD_BYTES
+        );
+    }
+    let rest = &data[4..];
+    if rest.len() < len {
+        anyhow::bail!(
+            "Incomplete {}: need {} bytes, have {}",
+            name,
+            len,
+            rest.len()
+        );
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Lowest bitrate an adapted audio stream is set to (bits/second)
+const MIN_ADAPTED_BITRATE: i32 = 8000;
+
+/// Bitrate cap for connections of the low throughput class
+const LOW_THROUGHPUT_BITRATE: i32 = 16000;
+
+/// Encoder settings chosen for a connection's quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamAdaptation {
+    /// Encoder bitrate in bits/second
+    pub bitrate: i32,
+    /// Whether packets carry in-band FEC for the previous frame
+    pub fec: bool,
+    /// Packet loss the encoder is tuned for
+    pub expected_loss_percent: u8,
+}
+
+impl StreamAdaptation {
+    /// Settings for a stream configured as `config` over a connection of
+    /// `quality`
+    ///
+    /// The configured bitrate is an upper bound. Slow connections get at
+    /// most 16 kbps; lossy ones give up a quarter of the bitrate (more
+    /// above 10% loss) to make room for FEC, which turns on from 1% loss
+    /// when the stream enables it.
+    pub fn for_quality(config: &StreamConfig, quality: &ConnectionQuality) -> Self {
+        let configured = config
+            .audio_config
+            .as_ref()
+            .map_or(AudioConfig::low_latency().bitrate, |audio| audio.bitrate);
+        let expected_loss_percent = (quality.packet_loss.clamp(0.0, 1.0) * 100.0).round() as u8;
+        let fec = config.enable_fec && expected_loss_percent >= 1;
+
+        let mut bitrate = configured;
+        if quality.throughput_class == ThroughputClass::Low {
+            bitrate = bitrate.min(LOW_THROUGHPUT_BITRATE);
+        }
+        if fec {
+            bitrate = if expected_loss_percent > 10 {
+                bitrate / 2
+            } else {
+                bitrate * 3 / 4
+            };
+        }
+
+        Self {
+            bitrate: bitrate.max(MIN_ADAPTED_BITRATE).min(configured),
+            fec,
+            expected_loss_percent,
+        }
+    }
+}
+
 /// Audio stream sender
 pub struct AudioStreamSender {
     encoder: AudioEncoder,
     sequence: u64,
-    #[allow(dead_code)]
     config: StreamConfig,
     peer_id: u32,
 }
@@ -248,11 +359,28 @@ impl AudioStreamSender {
     pub fn set_bitrate(&mut self, bitrate: i32) -> Result<()> {
         self.encoder.set_bitrate(bitrate)
     }
+
+    /// Retune bitrate and FEC for the peer's current connection quality
+    ///
+    /// Call whenever a new estimate arrives, e.g. from
+    /// `QuicNode::get_connection_quality` or `NodeStore::connection_quality`.
+    pub fn adapt_to(&mut self, quality: &ConnectionQuality) -> Result<StreamAdaptation> {
+        let adaptation = StreamAdaptation::for_quality(&self.config, quality);
+        self.encoder.set_bitrate(adaptation.bitrate)?;
+        self.encoder
+            .set_fec(adaptation.fec, adaptation.expected_loss_percent)?;
+        debug!(
+            "Adapted audio stream to peer {}: {}bps, FEC {} ({}% loss)",
+            self.peer_id, adaptation.bitrate, adaptation.fec, adaptation.expected_loss_percent
+        );
+        Ok(adaptation)
+    }
 }
 
 /// Audio stream receiver
 pub struct AudioStreamReceiver {
     decoder: AudioDecoder,
+    loudness: LoudnessNormalizer,
     last_sequence: u64,
     #[allow(dead_code)]
     config: StreamConfig,
@@ -267,12 +395,18 @@ impl AudioStreamReceiver {
             .clone()
             .context("Audio config required for audio stream")?;
 
+        let loudness = LoudnessNormalizer::new(
+            config.loudness.clone(),
+            audio_config.sample_rate,
+            audio_config.channels as usize,
+        )?;
         let decoder = AudioDecoder::new(audio_config)?;
 
         info!("Created audio stream receiver with config: {:?}", config);
 
         Ok(Self {
             decoder,
+            loudness,
             last_sequence: 0,
             config,
             packet_rx,
@@ -305,11 +439,12 @@ impl AudioStreamReceiver {
 
                 self.last_sequence = packet.sequence;
 
-                // Decode audio
-                let pcm = self
+                // Decode audio, then even out its level
+                let mut pcm = self
                     .decoder
                     .decode(&packet.payload)
                     .context("Failed to decode audio packet")?;
+                self.loudness.process(&mut pcm);
 
                 debug!(
                     "Received and decoded audio packet {} ({} samples)",
@@ -322,11 +457,55 @@ impl AudioStreamReceiver {
             None => Ok(None),
         }
     }
+
+    /// Handle for the listener's gain and mute of this stream
+    pub fn gain_control(&self) -> GainControl {
+        self.loudness.gain_control()
+    }
+
+    /// Measured loudness, normalizing gain and limiter activity
+    pub fn loudness_stats(&self) -> LoudnessStats {
+        self.loudness.stats()
+    }
+}
+
+/// Something that happened in a streaming session, for the application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    RecordingStarted {
+        mode: RecordingMode,
+    },
+    /// A participant agreed to, or withdrew from, being recorded
+    RecordingConsent {
+        participant: u32,
+        granted: bool,
+    },
+    /// A recording was uploaded to the content network
+    RecordingUploaded {
+        file_hash: String,
+        /// Whose track it is; `None` for the mixed track
+        participant: Option<u32>,
+        /// Participants heard in it, all of whom consented
+        participants: Vec<u32>,
+    },
+    /// A recording was kept locally, because uploading is off or failed
+    RecordingSaved {
+        path: PathBuf,
+        participant: Option<u32>,
+        participants: Vec<u32>,
+        error: Option<String>,
+    },
+    Ended,
 }
 
 /// Streaming session manager
 pub struct StreamingSession {
     config: StreamConfig,
+    session_id: String,
+    events: broadcast::Sender<SessionEvent>,
+    consent: BTreeSet<u32>,
+    recorder: Option<SessionRecorder>,
+    uploader: Option<Arc<AutomatedUploader>>,
 }
 
 impl StreamingSession {
@@ -334,7 +513,142 @@ impl StreamingSession {
     pub fn new(config: StreamConfig) -> Self {
         info!("Created streaming session: {:?}", config);
 
-        Self { config }
+        let mut id = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut id);
+        let (events, _) = broadcast::channel(64);
+        Self {
+            config,
+            session_id: hex::encode(id),
+            events,
+            consent: BTreeSet::new(),
+            recorder: None,
+            uploader: None,
+        }
+    }
+
+    /// Upload recordings through `uploader` when the session ends
+    pub fn with_recording_uploader(mut self, uploader: Arc<AutomatedUploader>) -> Self {
+        self.uploader = Some(uploader);
+        self
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Subscribe to recording and lifecycle events of this session
+    pub fn subscribe_events(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start recording the participants who consent
+    ///
+    /// Consent given before or after this call counts; nobody is recorded
+    /// without it.
+    pub fn start_recording(&mut self, config: RecordingConfig) -> Result<()> {
+        if self.recorder.is_some() {
+            bail!("Session {} is already being recorded", self.session_id);
+        }
+        let audio = self
+            .config
+            .audio_config
+            .clone()
+            .context("Audio config required to record a session")?;
+        let mode = config.mode;
+        let mut recorder = SessionRecorder::new(&self.session_id, config, audio)?;
+        for participant in &self.consent {
+            recorder.grant_consent(*participant);
+        }
+        self.recorder = Some(recorder);
+        self.emit(SessionEvent::RecordingStarted { mode });
+        Ok(())
+    }
+
+    /// Record whether `participant` agreed to be recorded
+    pub fn set_recording_consent(&mut self, participant: u32, granted: bool) {
+        if granted {
+            self.consent.insert(participant);
+        } else {
+            self.consent.remove(&participant);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            if granted {
+                recorder.grant_consent(participant);
+            } else {
+                recorder.revoke_consent(participant);
+            }
+        }
+        self.emit(SessionEvent::RecordingConsent {
+            participant,
+            granted,
+        });
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Pass one decoded frame of `participant` to the recorder, if any
+    pub fn record_audio(&mut self, participant: u32, pcm: &[i16]) -> Result<()> {
+        match &mut self.recorder {
+            Some(recorder) => recorder.record(participant, pcm),
+            None => Ok(()),
+        }
+    }
+
+    /// End the session, finishing and uploading any recording
+    ///
+    /// Returns the hashes of the uploaded recordings. Each file is also
+    /// reported as a `SessionEvent`; one that could not be uploaded stays
+    /// on disk.
+    pub async fn end(&mut self) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        if let Some(recorder) = self.recorder.take() {
+            let config = recorder.config().clone();
+            for recording in recorder.finish()? {
+                let uploader = self.uploader.as_ref().filter(|_| config.upload);
+                let error = match uploader {
+                    Some(uploader) => match uploader.upload(&recording.path).await {
+                        Ok(result) => {
+                            info!(
+                                "🎙️  Uploaded recording {:?} as {}",
+                                recording.path, result.file_hash
+                            );
+                            if config.remove_after_upload {
+                                if let Err(e) = std::fs::remove_file(&recording.path) {
+                                    warn!("Failed to remove {:?}: {}", recording.path, e);
+                                }
+                            }
+                            hashes.push(result.file_hash.clone());
+                            self.emit(SessionEvent::RecordingUploaded {
+                                file_hash: result.file_hash,
+                                participant: recording.participant,
+                                participants: recording.participants,
+                            });
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!("Failed to upload recording {:?}: {}", recording.path, e);
+                            Some(e.to_string())
+                        }
+                    },
+                    None => None,
+                };
+                self.emit(SessionEvent::RecordingSaved {
+                    path: recording.path,
+                    participant: recording.participant,
+                    participants: recording.participants,
+                    error,
+                });
+            }
+        }
+        self.emit(SessionEvent::Ended);
+        Ok(hashes)
+    }
+
+    fn emit(&self, event: SessionEvent) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
     }
 
     /// Create audio sender for this session
@@ -394,11 +708,153 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_stream_packet_rejects_bad_lengths() -> Result<()> {
+        let packet = StreamPacket {
+            sequence: 1,
+            timestamp: 2,
+            stream_type: StreamType::Video,
+            payload: vec![9; 16],
+            fec_data: Some(vec![7; 8]),
+        };
+        let bytes = packet.to_bytes()?;
+
+        // Every truncation is an error, never a panic
+        for len in 0..bytes.len() {
+            assert!(StreamPacket::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // FEC length pointing past the end of the buffer
+        let mut oversized_fec = bytes.clone();
+        let fec_len_at = 17 + 4 + 16;
+        oversized_fec[fec_len_at..fec_len_at + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(StreamPacket::from_bytes(&oversized_fec).is_err());
+
+        // Payload length above the sanity limit
+        let mut oversized_payload = bytes;
+        oversized_payload[17..21]
+            .copy_from_slice(&((MAX_STREAM_FIELD_BYTES + 1) as u32).to_be_bytes());
+        assert!(StreamPacket::from_bytes(&oversized_payload).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_stream_config_defaults() {
         let config = StreamConfig::voice();
         assert_eq!(config.stream_type, StreamType::Audio);
         assert!(config.audio_config.is_some());
         assert_eq!(config.max_packet_size, 1350);
+        assert!(config.loudness.normalize);
+    }
+
+    #[test]
+    fn test_adaptation_follows_connection_quality() {
+        let config = StreamConfig::voice();
+        let quality = |packet_loss, throughput_class| ConnectionQuality {
+            packet_loss,
+            throughput_class,
+            ..ConnectionQuality::default()
+        };
+        let adapt = |quality: &ConnectionQuality| StreamAdaptation::for_quality(&config, quality);
+
+        let clean = adapt(&quality(0.0, ThroughputClass::High));
+        assert_eq!((clean.bitrate, clean.fec), (32000, false));
+        let lossy = adapt(&quality(0.05, ThroughputClass::High));
+        assert_eq!(
+            (lossy.bitrate, lossy.fec, lossy.expected_loss_percent),
+            (24000, true, 5)
+        );
+        assert_eq!(adapt(&quality(0.2, ThroughputClass::Medium)).bitrate, 16000);
+        assert_eq!(adapt(&quality(0.0, ThroughputClass::Low)).bitrate, 16000);
+        assert_eq!(adapt(&quality(0.2, ThroughputClass::Low)).bitrate, 8000);
+
+        // Without FEC the loss only tunes the encoder
+        let config = StreamConfig {
+            enable_fec: false,
+            ..StreamConfig::voice()
+        };
+        let plain = StreamAdaptation::for_quality(&config, &quality(0.05, ThroughputClass::High));
+        assert_eq!(
+            (plain.bitrate, plain.fec, plain.expected_loss_percent),
+            (32000, false, 5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receiver_applies_listener_gain() -> Result<()> {
+        let config = StreamConfig {
+            loudness: LoudnessConfig::disabled(),
+            ..StreamConfig::voice()
+        };
+        let audio = config.audio_config.clone().unwrap();
+        let (packet_tx, packet_rx) = mpsc::channel(4);
+        let mut sender = AudioStreamSender::new(config.clone(), 1)?;
+        let mut receiver = AudioStreamReceiver::new(config, packet_rx)?;
+
+        receiver.gain_control().set_muted(true);
+        let tone: Vec<i16> = (0..audio.frame_size())
+            .map(|i| ((i as f32 * 0.3).sin() * 8000.0) as i16)
+            .collect();
+        for _ in 0..2 {
+            packet_tx.send(sender.encode_audio(&tone)?).await?;
+        }
+        receiver.receive_audio().await?;
+        let muted = receiver.receive_audio().await?.unwrap();
+        assert!(muted.iter().all(|s| *s == 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_records_consenting_participants() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut session = StreamingSession::new(StreamConfig::voice());
+        let mut events = session.subscribe_events();
+        let frame = vec![1000i16; AudioConfig::low_latency().frame_size()];
+
+        session.set_recording_consent(1, true);
+        session.start_recording(RecordingConfig::new(
+            RecordingMode::PerParticipant,
+            dir.path(),
+        ))?;
+        assert!(session
+            .start_recording(RecordingConfig::new(RecordingMode::Mixed, dir.path()))
+            .is_err());
+        for _ in 0..3 {
+            session.record_audio(1, &frame)?;
+            session.record_audio(2, &frame)?;
+        }
+
+        // Without an uploader the file is kept and reported
+        assert!(session.end().await?.is_empty());
+        assert!(!session.is_recording());
+        assert_eq!(
+            events.recv().await?,
+            SessionEvent::RecordingConsent {
+                participant: 1,
+                granted: true
+            }
+        );
+        assert_eq!(
+            events.recv().await?,
+            SessionEvent::RecordingStarted {
+                mode: RecordingMode::PerParticipant
+            }
+        );
+        let SessionEvent::RecordingSaved {
+            path,
+            participant,
+            participants,
+            error,
+        } = events.recv().await?
+        else {
+            panic!("expected the saved recording");
+        };
+        assert!(path.exists());
+        assert_eq!(participant, Some(1));
+        assert_eq!(participants, vec![1]);
+        assert_eq!(error, None);
+        assert_eq!(events.recv().await?, SessionEvent::Ended);
+        Ok(())
     }
 }
@@ -0,0 +1,365 @@
+/// Supervision of the node's background tasks
+///
+/// The daemon runs many long-lived loops: the DHT event loop, the QUIC
+/// accept loop, storage GC, snapshots, replication, the RPC server and so
+/// on. Each is registered with the `Supervisor` under a name. A task that
+/// fails, by returning an error or panicking, is restarted after an
+/// exponential backoff; a task that returns `Ok` is done and stays stopped.
+/// `health` reports every task's state, restart count and last error.
+/// `shutdown` stops tasks in reverse order of registration, so front ends
+/// registered last stop before the services beneath them.
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info};
+
+use crate::retry::RetryPolicy;
+use crate::types::current_timestamp;
+
+/// A run lasting this long resets the backoff
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// What a supervised task is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskState {
+    Running,
+    /// Failed and waiting out its backoff
+    Restarting,
+    /// Returned successfully; not restarted
+    Finished,
+    /// Stopped by shutdown
+    Stopped,
+}
+
+/// Health of one supervised task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub state: TaskState,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Unix seconds of the last failure
+    pub last_failure_at: Option<u64>,
+}
+
+struct SupervisedTask {
+    health: Arc<Mutex<TaskHealth>>,
+    stop: Arc<Notify>,
+    /// Taken by `shutdown`
+    monitor: Option<JoinHandle<()>>,
+}
+
+/// Owns the node's named background tasks
+pub struct Supervisor {
+    backoff: RetryPolicy,
+    tasks: Mutex<Vec<SupervisedTask>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            backoff: RetryPolicy {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+                ..RetryPolicy::default()
+            },
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wait between restarts according to `policy`
+    ///
+    /// Only the backoff fields are used; tasks are restarted indefinitely.
+    pub fn with_backoff(mut self, policy: RetryPolicy) -> Self {
+        self.backoff = policy;
+        self
+    }
+
+    /// Run the future `make` returns on the runtime, restarting it on failure
+    pub fn spawn<M, F>(&self, name: &str, make: M)
+    where
+        M: Fn() -> F + Send + 'static,
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let start = move || tokio::spawn(make());
+        self.register(name, |monitor| tokio::spawn(monitor.run(start)));
+    }
+
+    /// Like `spawn`, for futures that must stay on the current `LocalSet`
+    pub fn spawn_local<M, F>(&self, name: &str, make: M)
+    where
+        M: Fn() -> F + 'static,
+        F: Future<Output = Result<()>> + 'static,
+    {
+        let start = move || tokio::task::spawn_local(make());
+        self.register(name, |monitor| tokio::task::spawn_local(monitor.run(start)));
+    }
+
+    /// Supervise a task started by `start`, such as a `spawn_*` helper
+    ///
+    /// These tasks report no errors; a panic counts as a failure.
+    pub fn watch<S>(&self, name: &str, start: S)
+    where
+        S: Fn() -> JoinHandle<()> + Send + 'static,
+    {
+        self.register(name, |monitor| tokio::spawn(monitor.run(start)));
+    }
+
+    fn register(&self, name: &str, spawn: impl FnOnce(Monitor) -> JoinHandle<()>) {
+        let health = Arc::new(Mutex::new(TaskHealth {
+            name: name.to_string(),
+            state: TaskState::Running,
+            restarts: 0,
+            last_error: None,
+            last_failure_at: None,
+        }));
+        let stop = Arc::new(Notify::new());
+        let monitor = spawn(Monitor {
+            health: health.clone(),
+            backoff: self.backoff.clone(),
+            stop: stop.clone(),
+        });
+        self.tasks.lock().push(SupervisedTask {
+            health,
+            stop,
+            monitor: Some(monitor),
+        });
+    }
+
+    /// Health of every task, in order of registration
+    pub fn health(&self) -> Vec<TaskHealth> {
+        self.tasks
+            .lock()
+            .iter()
+            .map(|task| task.health.lock().clone())
+            .collect()
+    }
+
+    /// Tasks waiting to be restarted after a failure
+    pub fn failing(&self) -> Vec<TaskHealth> {
+        self.health()
+            .into_iter()
+            .filter(|task| task.state == TaskState::Restarting)
+            .collect()
+    }
+
+    /// Stop every task, the most recently registered first
+    ///
+    /// Each task has stopped before the next one is told to.
+    pub async fn shutdown(&self) {
+        let tasks: Vec<_> = self
+            .tasks
+            .lock()
+            .iter_mut()
+            .map(|task| (task.health.clone(), task.stop.clone(), task.monitor.take()))
+            .collect();
+        for (health, stop, monitor) in tasks.into_iter().rev() {
+            stop.notify_one();
+            if let Some(monitor) = monitor {
+                let _ = monitor.await;
+            }
+            info!("Stopped {}", health.lock().name);
+        }
+    }
+}
+
+/// Output of a supervised task
+trait TaskOutput {
+    fn into_result(self) -> Result<()>;
+}
+
+impl TaskOutput for () {
+    fn into_result(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl TaskOutput for Result<()> {
+    fn into_result(self) -> Result<()> {
+        self
+    }
+}
+
+/// Runs one task, restarting it with backoff until it succeeds or is stopped
+struct Monitor {
+    health: Arc<Mutex<TaskHealth>>,
+    backoff: RetryPolicy,
+    stop: Arc<Notify>,
+}
+
+impl Monitor {
+    async fn run<S, T>(self, start: S)
+    where
+        S: Fn() -> JoinHandle<T>,
+        T: TaskOutput,
+    {
+        let name = self.health.lock().name.clone();
+        let mut failures = 0u32;
+        loop {
+            self.health.lock().state = TaskState::Running;
+            let started = Instant::now();
+            let mut task = start();
+            let outcome = tokio::select! {
+                outcome = &mut task => outcome,
+                _ = self.stop.notified() => {
+                    task.abort();
+                    let _ = task.await;
+                    self.stopped();
+                    return;
+                }
+            };
+
+            let reason = match outcome.map(TaskOutput::into_result) {
+                Ok(Ok(())) => {
+                    info!("Task {} finished", name);
+                    self.health.lock().state = TaskState::Finished;
+                    return;
+                }
+                Ok(Err(e)) => format!("{:#}", e),
+                Err(e) => describe_join_error(e),
+            };
+
+            if started.elapsed() >= STABLE_RUN {
+                failures = 0;
+            }
+            let delay = self.backoff.backoff(failures);
+            failures = failures.saturating_add(1);
+            error!(
+                "Task {} failed: {}; restarting in {:?}",
+                name, reason, delay
+            );
+            {
+                let mut health = self.health.lock();
+                health.state = TaskState::Restarting;
+                health.last_error = Some(reason);
+                health.last_failure_at = Some(current_timestamp());
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.stop.notified() => {
+                    self.stopped();
+                    return;
+                }
+            }
+            self.health.lock().restarts += 1;
+        }
+    }
+
+    fn stopped(&self) {
+        self.health.lock().state = TaskState::Stopped;
+    }
+}
+
+fn describe_join_error(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let payload = error.into_panic();
+    match payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+    {
+        Some(message) => format!("panicked: {}", message),
+        None => "panicked".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn supervisor() -> Supervisor {
+        Supervisor::new().with_backoff(RetryPolicy {
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(5),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        })
+    }
+
+    async fn wait_for(supervisor: &Supervisor, done: impl Fn(&TaskHealth) -> bool) -> TaskHealth {
+        for _ in 0..200 {
+            if let Some(health) = supervisor.health().into_iter().find(|h| done(h)) {
+                return health;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!(
+            "task never reached the expected state: {:?}",
+            supervisor.health()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_tasks_are_restarted() {
+        let supervisor = supervisor();
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        supervisor.spawn("flaky", move || {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match run {
+                    0 => anyhow::bail!("connection reset"),
+                    1 => panic!("bad state"),
+                    _ => Ok(()),
+                }
+            }
+        });
+
+        let health = wait_for(&supervisor, |h| h.state == TaskState::Finished).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(health.restarts, 2);
+        assert_eq!(health.last_error.as_deref(), Some("panicked: bad state"));
+        assert!(health.last_failure_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_tasks_in_reverse_order() {
+        let supervisor = supervisor();
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        for name in ["storage", "network", "rpc"] {
+            let stopped = stopped.clone();
+            supervisor.watch(name, move || {
+                let guard = StopRecorder(name, stopped.clone());
+                tokio::spawn(async move {
+                    let _guard = guard;
+                    std::future::pending::<()>().await
+                })
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(supervisor
+            .health()
+            .iter()
+            .all(|h| h.state == TaskState::Running));
+
+        supervisor.shutdown().await;
+        assert_eq!(*stopped.lock(), vec!["rpc", "network", "storage"]);
+        assert!(supervisor
+            .health()
+            .iter()
+            .all(|h| h.state == TaskState::Stopped));
+    }
+
+    /// Records its name when the task owning it is dropped
+    struct StopRecorder(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Drop for StopRecorder {
+        fn drop(&mut self) {
+            self.1.lock().push(self.0);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+/// Tracing setup with optional OpenTelemetry (OTLP) export
+///
+/// Logs always go to stdout. When built with the `otel` feature and enabled
+/// in the `[telemetry]` section of the node config file, spans are also
+/// exported to an OTLP collector.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Telemetry configuration (`[telemetry]` table of the node config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// Export spans over OTLP
+    #[serde(default)]
+    pub enabled: bool,
+    /// OTLP gRPC collector endpoint
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    /// Fraction of root traces to sample (0.0 - 1.0)
+    #[serde(default = "default_sampling_ratio")]
+    pub sampling_ratio: f64,
+    /// Service name reported to the collector
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_endpoint() -> String {
+    "http://127.0.0.1:4317".to_string()
+}
+
+fn default_sampling_ratio() -> f64 {
+    1.0
+}
+
+fn default_service_name() -> String {
+    "pangea-rust-node".to_string()
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: default_endpoint(),
+            sampling_ratio: default_sampling_ratio(),
+            service_name: default_service_name(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    telemetry: TelemetryConfig,
+}
+
+impl TelemetryConfig {
+    /// Load the `[telemetry]` section from a TOML config file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
+        Self::from_toml(&content)
+    }
+
+    /// Parse the `[telemetry]` section from TOML content
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(content)?;
+        file.telemetry.validate()?;
+        Ok(file.telemetry)
+    }
+
+    /// Validate configuration parameters
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.sampling_ratio) {
+            anyhow::bail!("sampling_ratio must be between 0.0 and 1.0");
+        }
+        if self.enabled && self.endpoint.is_empty() {
+            anyhow::bail!("endpoint must be set when telemetry is enabled");
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the exporter alive; flushes pending spans on drop
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("telemetry: failed to flush spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global tracing subscriber
+///
+/// Must be called from within a Tokio runtime when OTLP export is enabled.
+pub fn init(log_filter: &str, config: &TelemetryConfig) -> Result<TelemetryGuard> {
+    let filter = EnvFilter::new(log_filter);
+    let fmt_layer = tracing_subscriber::fmt::layer();
+
+    #[cfg(feature = "otel")]
+    {
+        if config.enabled {
+            let (layer, provider) = otel::layer(config)?;
+            tracing_subscriber::registry()
+                .with(filter)
+                .with(fmt_layer)
+                .with(layer)
+                .try_init()?;
+            tracing::info!(
+                "✓ OTLP span export to {} (sampling: {})",
+                config.endpoint,
+                config.sampling_ratio
+            );
+            return Ok(TelemetryGuard {
+                provider: Some(provider),
+            });
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .try_init()?;
+
+    #[cfg(not(feature = "otel"))]
+    if config.enabled {
+        tracing::warn!("Telemetry export requested but built without the `otel` feature");
+    }
+
+    Ok(TelemetryGuard {
+        #[cfg(feature = "otel")]
+        provider: None,
+    })
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::TelemetryConfig;
+    use anyhow::Result;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+    use opentelemetry_sdk::{runtime, Resource};
+    use tracing::Subscriber;
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub(super) fn layer<S>(
+        config: &TelemetryConfig,
+    ) -> Result<(impl tracing_subscriber::Layer<S>, TracerProvider)>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.endpoint.clone())
+            .build()?;
+
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sampling_ratio,
+            ))))
+            .with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )]))
+            .build();
+
+        let tracer = provider.tracer("pangea-rust-node");
+        Ok((tracing_opentelemetry::layer().with_tracer(tracer), provider))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_when_section_missing() {
+        let config = TelemetryConfig::from_toml("").unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.sampling_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_parse_telemetry_section() {
+        let config = TelemetryConfig::from_toml(
+            r#"
+            [telemetry]
+            enabled = true
+            endpoint = "http://collector:4317"
+            sampling_ratio = 0.25
+            "#,
+        )
+        .unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.endpoint, "http://collector:4317");
+        assert_eq!(config.sampling_ratio, 0.25);
+        assert_eq!(config.service_name, "pangea-rust-node");
+    }
+
+    #[test]
+    fn test_invalid_sampling_ratio() {
+        let result = TelemetryConfig::from_toml("[telemetry]\nsampling_ratio = 2.0\n");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,830 @@
+/// Shard transport abstraction
+///
+/// Shards normally travel through the Go node (`GoClient`). When it is not
+/// running, or its circuit breaker is open, the `TransportSelector` falls back
+/// to sending over this node's own QUIC endpoint. Every transfer records
+/// which transport carried it.
+///
+/// Shards sent to peers that negotiated checksums travel in a frame carrying
+/// their CRC32C, and fetched frames are checked before the shard is handed
+/// on, so bytes damaged between the Go node, the QUIC stack and this process
+/// fail as [`NetworkError::TransportCorruption`] (retried, or fetched over
+/// the next transport) instead of surfacing as a failed reconstruction.
+///
+/// Native QUIC transfers are [`ShardRequest`]s naming the file and shard;
+/// peers answer them with a [`ShardServer`] keeping shards in their cache.
+use anyhow::Context;
+use async_trait::async_trait;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::cache::{is_safe_file_hash, Cache};
+use crate::error::{ErrorCode, NetworkError};
+use crate::go_client::{CircuitState, GoClient};
+use crate::negotiation::PeerCapabilities;
+use crate::network::{QuicNode, ReplaySafety, RequestHandler, RequestTag};
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+/// Maximum size of a shard fetched over native QUIC
+const MAX_NATIVE_SHARD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Starts a checksummed shard frame: the tag, the shard's CRC32C
+/// (big-endian) and the shard
+pub const CHECKSUM_TAG: RequestTag = *b"PCRC";
+
+/// Bytes a checksummed frame adds to a shard
+pub const FRAME_OVERHEAD: usize = CHECKSUM_TAG.len() + 4;
+
+/// Tags native shard requests so they are not confused with other stream traffic
+pub const SHARD_TAG: RequestTag = *b"PSHD";
+
+/// A shard sent to or asked of a peer over native QUIC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShardRequest {
+    /// Keep this shard (bare, or in a checksummed frame)
+    Put {
+        file_hash: String,
+        shard_index: usize,
+        data: Vec<u8>,
+    },
+    Get {
+        file_hash: String,
+        shard_index: usize,
+    },
+}
+
+/// A peer's answer to a [`ShardRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShardResponse {
+    Stored,
+    Data(Vec<u8>),
+    NotFound,
+    /// The checksummed frame did not match its shard
+    Corrupt,
+    Rejected(String),
+}
+
+fn codec() -> impl Options {
+    bincode::DefaultOptions::new().with_limit((MAX_NATIVE_SHARD_BYTES + 1024) as u64)
+}
+
+impl ShardRequest {
+    /// Encode for a stream, tag included
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = SHARD_TAG.to_vec();
+        codec()
+            .serialize_into(&mut buffer, self)
+            .context("Failed to serialize shard request")?;
+        Ok(buffer)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let body = bytes
+            .strip_prefix(&SHARD_TAG)
+            .context("Not a shard request")?;
+        codec()
+            .deserialize(body)
+            .context("Failed to deserialize shard request")
+    }
+
+    /// Fetches may be replayed; stores may not
+    pub fn replay_safety(&self) -> ReplaySafety {
+        match self {
+            ShardRequest::Get { .. } => ReplaySafety::Idempotent,
+            ShardRequest::Put { .. } => ReplaySafety::Mutating,
+        }
+    }
+}
+
+impl ShardResponse {
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        codec()
+            .serialize(self)
+            .context("Failed to serialize shard response")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        codec()
+            .deserialize(bytes)
+            .context("Failed to deserialize shard response")
+    }
+}
+
+/// Answers [`ShardRequest`]s from peers out of this node's cache
+pub struct ShardServer {
+    cache: Arc<Cache>,
+}
+
+impl ShardServer {
+    pub fn new(cache: Arc<Cache>) -> Self {
+        Self { cache }
+    }
+
+    /// Answer shard requests on `network`'s incoming connections
+    pub fn register(self: Arc<Self>, network: QuicNode) -> QuicNode {
+        network.with_request_handler(SHARD_TAG, self)
+    }
+
+    pub async fn serve(&self, request: ShardRequest) -> ShardResponse {
+        match request {
+            ShardRequest::Put {
+                file_hash,
+                shard_index,
+                data,
+            } => {
+                if !is_safe_file_hash(&file_hash) {
+                    return ShardResponse::Rejected(format!("Bad file hash {:?}", file_hash));
+                }
+                let Ok(shard) = open_shard(0, shard_index, data) else {
+                    return ShardResponse::Corrupt;
+                };
+                match self.cache.put_shard(&file_hash, shard_index, shard).await {
+                    Ok(()) => ShardResponse::Stored,
+                    Err(e) => ShardResponse::Rejected(format!("{:#}", e)),
+                }
+            }
+            ShardRequest::Get {
+                file_hash,
+                shard_index,
+            } => match self.cache.get_shard(&file_hash, shard_index).await {
+                Some(data) => ShardResponse::Data(data),
+                None => ShardResponse::NotFound,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl RequestHandler for ShardServer {
+    async fn handle(&self, remote: SocketAddr, request: Vec<u8>) -> Vec<u8> {
+        let response = match ShardRequest::from_bytes(&request) {
+            Ok(request) => self.serve(request).await,
+            Err(e) => {
+                debug!("Bad shard request from {}: {:#}", remote, e);
+                ShardResponse::Rejected(format!("{:#}", e))
+            }
+        };
+        response.to_bytes().unwrap_or_default()
+    }
+
+    fn is_replay_safe(&self, request: &[u8]) -> bool {
+        ShardRequest::from_bytes(request)
+            .is_ok_and(|request| request.replay_safety() == ReplaySafety::Idempotent)
+    }
+}
+
+/// Wrap a shard in a checksummed frame
+pub fn seal_shard(shard: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_OVERHEAD + shard.len());
+    frame.extend_from_slice(&CHECKSUM_TAG);
+    frame.extend_from_slice(&crc32c::crc32c(shard).to_be_bytes());
+    frame.extend_from_slice(shard);
+    frame
+}
+
+/// Undo [`seal_shard`] on shard `shard_index` received from `peer_id`
+///
+/// Payloads without the tag come from peers that do not checksum and are
+/// passed on unchecked.
+pub fn open_shard(peer_id: u32, shard_index: usize, frame: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(body) = frame.strip_prefix(&CHECKSUM_TAG) else {
+        return Ok(frame);
+    };
+    let corrupt = NetworkError::TransportCorruption {
+        peer_id,
+        shard_index,
+    };
+    if body.len() < 4 {
+        return Err(corrupt);
+    }
+    let (checksum, shard) = body.split_at(4);
+    if crc32c::crc32c(shard).to_be_bytes() != checksum {
+        return Err(corrupt);
+    }
+    Ok(shard.to_vec())
+}
+
+/// Transport that carried a shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransportKind {
+    /// Go node over Cap'n Proto RPC
+    Go,
+    /// This node's QUIC endpoint
+    NativeQuic,
+    /// In-process simulated network (tests and `--simulate-network`)
+    Simulated,
+}
+
+impl fmt::Display for TransportKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportKind::Go => write!(f, "go"),
+            TransportKind::NativeQuic => write!(f, "native-quic"),
+            TransportKind::Simulated => write!(f, "simulated"),
+        }
+    }
+}
+
+/// Record of a single shard transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardTransfer {
+    pub shard_index: usize,
+    pub peer_id: u32,
+    pub transport: TransportKind,
+}
+
+/// Something that can move shards between this node and a peer
+#[async_trait(?Send)]
+pub trait ShardTransport {
+    fn kind(&self) -> TransportKind;
+
+    /// Whether the transport is worth trying right now
+    async fn is_available(&self, peer_id: u32) -> bool;
+
+    /// Send shard `shard_index` of `file_hash` to a peer
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<bool>;
+
+    /// Fetch shard `shard_index` of `file_hash` from a peer
+    async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Vec<u8>>;
+}
+
+#[async_trait(?Send)]
+impl ShardTransport for GoClient {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Go
+    }
+
+    async fn is_available(&self, _peer_id: u32) -> bool {
+        self.is_connected() && self.circuit_state() != CircuitState::Open
+    }
+
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        _file_hash: &str,
+        _shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<bool> {
+        self.send_data(peer_id, shard).await
+    }
+
+    async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        _file_hash: &str,
+        _shard_index: usize,
+    ) -> Result<Vec<u8>> {
+        self.receive_data(peer_id).await
+    }
+}
+
+/// Shard transfer over this node's QUIC connections
+pub struct QuicTransport {
+    network: Arc<QuicNode>,
+}
+
+impl QuicTransport {
+    pub fn new(network: Arc<QuicNode>) -> Self {
+        Self { network }
+    }
+
+    async fn request(&self, peer_id: u32, request: &ShardRequest) -> Result<ShardResponse> {
+        let bytes = request.to_bytes()?;
+        let response = self
+            .network
+            .request_with(
+                peer_id,
+                &bytes,
+                MAX_NATIVE_SHARD_BYTES + 1024,
+                request.replay_safety(),
+            )
+            .await
+            .map_err(|e| {
+                debug!("Native QUIC request to peer {} failed: {:#}", peer_id, e);
+                NetworkError::PeerUnreachable(peer_id)
+            })?;
+        Ok(ShardResponse::from_bytes(&response)?)
+    }
+}
+
+#[async_trait(?Send)]
+impl ShardTransport for QuicTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::NativeQuic
+    }
+
+    async fn is_available(&self, peer_id: u32) -> bool {
+        self.network.is_connected(peer_id).await
+    }
+
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<bool> {
+        let request = ShardRequest::Put {
+            file_hash: file_hash.to_string(),
+            shard_index,
+            data: shard,
+        };
+        match self.request(peer_id, &request).await? {
+            ShardResponse::Stored => Ok(true),
+            ShardResponse::Corrupt => Err(NetworkError::TransportCorruption {
+                peer_id,
+                shard_index,
+            }),
+            other => {
+                Err(anyhow::anyhow!("Peer {} did not store the shard: {:?}", peer_id, other).into())
+            }
+        }
+    }
+
+    /// Peers without the shard answer with no data
+    async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Vec<u8>> {
+        let request = ShardRequest::Get {
+            file_hash: file_hash.to_string(),
+            shard_index,
+        };
+        match self.request(peer_id, &request).await? {
+            ShardResponse::Data(data) => Ok(data),
+            ShardResponse::NotFound => Ok(Vec::new()),
+            other => Err(
+                anyhow::anyhow!("Peer {} did not return the shard: {:?}", peer_id, other).into(),
+            ),
+        }
+    }
+}
+
+/// Tries transports in preference order, falling back on transient failures
+pub struct TransportSelector {
+    transports: Vec<Arc<dyn ShardTransport>>,
+    /// Peers that verify checksummed frames
+    capabilities: Option<Arc<PeerCapabilities>>,
+}
+
+impl TransportSelector {
+    /// Selector that only uses the Go node
+    pub fn go_only(go_client: Arc<GoClient>) -> Self {
+        Self {
+            transports: vec![go_client],
+            capabilities: None,
+        }
+    }
+
+    /// Selector that only uses `transport`
+    pub fn only(transport: Arc<dyn ShardTransport>) -> Self {
+        Self {
+            transports: vec![transport],
+            capabilities: None,
+        }
+    }
+
+    /// Add a lower-preference transport
+    pub fn with_fallback(mut self, transport: Arc<dyn ShardTransport>) -> Self {
+        self.transports.push(transport);
+        self
+    }
+
+    /// Fall back to `network`'s QUIC connections, and checksum shards for
+    /// the peers it negotiated checksums with
+    pub fn with_native_fallback(mut self, network: Arc<QuicNode>) -> Self {
+        if let Some(capabilities) = network.peer_capabilities() {
+            self.capabilities = Some(capabilities);
+        }
+        self.with_fallback(Arc::new(QuicTransport::new(network)))
+    }
+
+    /// Checksum shards sent to peers that negotiated it
+    pub fn with_peer_capabilities(mut self, capabilities: Arc<PeerCapabilities>) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Transports in preference order
+    pub fn kinds(&self) -> Vec<TransportKind> {
+        self.transports.iter().map(|t| t.kind()).collect()
+    }
+
+    /// Send a shard using the first transport that succeeds
+    pub async fn send_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<ShardTransfer> {
+        let shard = if self.verifies_checksums(peer_id) {
+            seal_shard(&shard)
+        } else {
+            shard
+        };
+        let mut last_err = NetworkError::NoTransport(peer_id);
+        for transport in self.candidates(peer_id).await {
+            match transport
+                .send_shard(peer_id, file_hash, shard_index, shard.clone())
+                .await
+            {
+                Ok(_) => {
+                    return Ok(ShardTransfer {
+                        shard_index,
+                        peer_id,
+                        transport: transport.kind(),
+                    })
+                }
+                Err(e) => {
+                    if !self.should_fall_back(transport.kind(), &e) {
+                        return Err(e);
+                    }
+                    last_err = e;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Fetch a shard using the first transport that returns data
+    ///
+    /// Checksummed frames are opened; a damaged one counts as a transient
+    /// failure of that transport. Returns `Ok(None)` when every transport
+    /// answered without data.
+    pub async fn fetch_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+    ) -> Result<Option<(Vec<u8>, TransportKind)>> {
+        let mut last_err = None;
+        let mut answered = false;
+        for transport in self.candidates(peer_id).await {
+            let fetched = transport
+                .fetch_shard(peer_id, file_hash, shard_index)
+                .await
+                .and_then(|frame| open_shard(peer_id, shard_index, frame));
+            match fetched {
+                Ok(data) if !data.is_empty() => return Ok(Some((data, transport.kind()))),
+                Ok(_) => answered = true,
+                Err(e) => {
+                    if !self.should_fall_back(transport.kind(), &e) {
+                        return Err(e);
+                    }
+                    last_err = Some(e);
+                }
+            }
+        }
+        match last_err {
+            Some(e) if !answered => Err(e),
+            None if !answered => Err(NetworkError::NoTransport(peer_id)),
+            _ => Ok(None),
+        }
+    }
+
+    fn verifies_checksums(&self, peer_id: u32) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.get(peer_id).verifies_checksums())
+    }
+
+    async fn candidates(&self, peer_id: u32) -> Vec<&Arc<dyn ShardTransport>> {
+        let mut available = Vec::new();
+        for transport in &self.transports {
+            if transport.is_available(peer_id).await {
+                available.push(transport);
+            }
+        }
+        available
+    }
+
+    fn should_fall_back(&self, kind: TransportKind, err: &NetworkError) -> bool {
+        let fall_back = err.is_transient()
+            || matches!(err, NetworkError::NotConnected | NetworkError::CircuitOpen);
+        if fall_back {
+            warn!("{} transport failed ({}), trying next transport", kind, err);
+        }
+        fall_back
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    struct FakeTransport {
+        kind: TransportKind,
+        available: bool,
+        fail_with: Option<fn() -> NetworkError>,
+        sent: Mutex<Vec<usize>>,
+    }
+
+    impl FakeTransport {
+        fn new(
+            kind: TransportKind,
+            available: bool,
+            fail_with: Option<fn() -> NetworkError>,
+        ) -> Self {
+            Self {
+                kind,
+                available,
+                fail_with,
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl ShardTransport for FakeTransport {
+        fn kind(&self) -> TransportKind {
+            self.kind
+        }
+
+        async fn is_available(&self, _peer_id: u32) -> bool {
+            self.available
+        }
+
+        async fn send_shard(
+            &self,
+            _peer_id: u32,
+            _file_hash: &str,
+            shard_index: usize,
+            _shard: Vec<u8>,
+        ) -> Result<bool> {
+            if let Some(fail) = self.fail_with {
+                return Err(fail());
+            }
+            self.sent.lock().push(shard_index);
+            Ok(true)
+        }
+
+        async fn fetch_shard(
+            &self,
+            _peer_id: u32,
+            _file_hash: &str,
+            shard_index: usize,
+        ) -> Result<Vec<u8>> {
+            match self.fail_with {
+                Some(fail) => Err(fail()),
+                None => Ok(vec![shard_index as u8]),
+            }
+        }
+    }
+
+    /// Answers every fetch with the same bytes and keeps what it was sent
+    struct FramedTransport {
+        kind: TransportKind,
+        frame: Vec<u8>,
+        sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl FramedTransport {
+        fn new(kind: TransportKind, frame: Vec<u8>) -> Self {
+            Self {
+                kind,
+                frame,
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl ShardTransport for FramedTransport {
+        fn kind(&self) -> TransportKind {
+            self.kind
+        }
+
+        async fn is_available(&self, _peer_id: u32) -> bool {
+            true
+        }
+
+        async fn send_shard(
+            &self,
+            _peer_id: u32,
+            _file_hash: &str,
+            _shard_index: usize,
+            shard: Vec<u8>,
+        ) -> Result<bool> {
+            self.sent.lock().push(shard);
+            Ok(true)
+        }
+
+        async fn fetch_shard(
+            &self,
+            _peer_id: u32,
+            _file_hash: &str,
+            _shard_index: usize,
+        ) -> Result<Vec<u8>> {
+            Ok(self.frame.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_when_primary_unavailable() {
+        let go = Arc::new(FakeTransport::new(TransportKind::Go, false, None));
+        let quic = Arc::new(FakeTransport::new(TransportKind::NativeQuic, true, None));
+        let selector = TransportSelector {
+            transports: vec![go.clone(), quic.clone()],
+            capabilities: None,
+        };
+
+        let transfer = selector.send_shard(3, "file", 1, vec![0; 8]).await.unwrap();
+        assert_eq!(transfer.transport, TransportKind::NativeQuic);
+        assert!(go.sent.lock().is_empty());
+        assert_eq!(*quic.sent.lock(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_transient_error() {
+        let go = Arc::new(FakeTransport::new(
+            TransportKind::Go,
+            true,
+            Some(|| NetworkError::Timeout("send_data".into())),
+        ));
+        let quic = Arc::new(FakeTransport::new(TransportKind::NativeQuic, true, None));
+        let selector = TransportSelector {
+            transports: vec![go, quic],
+            capabilities: None,
+        };
+
+        let (data, kind) = selector.fetch_shard(3, "file", 5).await.unwrap().unwrap();
+        assert_eq!(data, vec![5]);
+        assert_eq!(kind, TransportKind::NativeQuic);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_error_does_not_fall_back() {
+        let go = Arc::new(FakeTransport::new(
+            TransportKind::Go,
+            true,
+            Some(|| NetworkError::Other(anyhow::anyhow!("shard rejected"))),
+        ));
+        let quic = Arc::new(FakeTransport::new(TransportKind::NativeQuic, true, None));
+        let selector = TransportSelector {
+            transports: vec![go, quic.clone()],
+            capabilities: None,
+        };
+
+        assert!(selector.send_shard(3, "file", 0, vec![1]).await.is_err());
+        assert!(quic.sent.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_available_transport() {
+        let go = Arc::new(FakeTransport::new(TransportKind::Go, false, None));
+        let selector = TransportSelector {
+            transports: vec![go],
+            capabilities: None,
+        };
+
+        assert!(matches!(
+            selector.send_shard(9, "file", 0, vec![1]).await,
+            Err(NetworkError::NoTransport(9))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shards_are_checksummed_for_peers_that_verify() {
+        use crate::negotiation::Capabilities;
+
+        let capabilities = Arc::new(PeerCapabilities::new(Capabilities::local(1)));
+        let response = serde_json::to_vec(&Capabilities::local(2)).unwrap();
+        capabilities.record_response(2, &response).unwrap();
+        let go = Arc::new(FramedTransport::new(TransportKind::Go, Vec::new()));
+        let selector = TransportSelector::only(go.clone()).with_peer_capabilities(capabilities);
+
+        // Peer 4 never negotiated, so it gets the bare shard
+        selector
+            .send_shard(2, "file", 0, vec![7; 32])
+            .await
+            .unwrap();
+        selector
+            .send_shard(4, "file", 0, vec![7; 32])
+            .await
+            .unwrap();
+        let sent = go.sent.lock().clone();
+        assert_eq!(sent[0], seal_shard(&[7; 32]));
+        assert_eq!(sent[1], vec![7; 32]);
+        assert_eq!(open_shard(2, 0, sent[0].clone()).unwrap(), vec![7; 32]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_frames_fail_over_then_fail_typed() {
+        let mut corrupt = seal_shard(&[5; 16]);
+        corrupt[FRAME_OVERHEAD + 3] ^= 0x40;
+        let go = Arc::new(FramedTransport::new(TransportKind::Go, corrupt));
+        let quic = Arc::new(FramedTransport::new(
+            TransportKind::NativeQuic,
+            seal_shard(&[5; 16]),
+        ));
+        let selector = TransportSelector {
+            transports: vec![go.clone(), quic],
+            capabilities: None,
+        };
+
+        let (data, kind) = selector.fetch_shard(3, "file", 5).await.unwrap().unwrap();
+        assert_eq!(data, vec![5; 16]);
+        assert_eq!(kind, TransportKind::NativeQuic);
+
+        let err = TransportSelector::only(go)
+            .fetch_shard(3, "file", 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            NetworkError::TransportCorruption {
+                peer_id: 3,
+                shard_index: 5
+            }
+        ));
+        assert!(err.is_transient());
+
+        // Bare payloads pass unchecked; a truncated frame does not
+        assert_eq!(open_shard(3, 0, vec![1, 2]).unwrap(), vec![1, 2]);
+        assert!(open_shard(3, 0, CHECKSUM_TAG.to_vec()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_native_quic_puts_and_gets_shards_between_two_nodes() {
+        use crate::types::PeerAddress;
+
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+        let server = Arc::new(
+            Arc::new(ShardServer::new(cache.clone())).register(
+                QuicNode::new(2, "127.0.0.1:0".parse().unwrap())
+                    .await
+                    .unwrap(),
+            ),
+        );
+        let accept = server.clone();
+        tokio::spawn(async move { accept.accept_connection().await });
+        let client = Arc::new(
+            QuicNode::new(1, "127.0.0.1:0".parse().unwrap())
+                .await
+                .unwrap(),
+        );
+        client
+            .connect_to_peer(PeerAddress {
+                peer_id: 2,
+                host: "127.0.0.1".to_string(),
+                port: server.local_addr().unwrap().port(),
+                identity: None,
+            })
+            .await
+            .unwrap();
+
+        let transport = Arc::new(QuicTransport::new(client));
+        let selector = TransportSelector::only(transport.clone());
+        let transfer = selector
+            .send_shard(2, "abc123", 4, seal_shard(&[9; 4096]))
+            .await
+            .unwrap();
+        assert_eq!(transfer.transport, TransportKind::NativeQuic);
+        assert_eq!(cache.get_shard("abc123", 4).await, Some(vec![9; 4096]));
+
+        let (data, kind) = selector.fetch_shard(2, "abc123", 4).await.unwrap().unwrap();
+        assert_eq!(data, vec![9; 4096]);
+        assert_eq!(kind, TransportKind::NativeQuic);
+
+        // Another file's shard at the same index is not served
+        assert!(selector
+            .fetch_shard(2, "def456", 4)
+            .await
+            .unwrap()
+            .is_none());
+
+        // A frame damaged on the way is refused, not stored
+        let mut frame = seal_shard(&[1; 64]);
+        frame[FRAME_OVERHEAD] ^= 0x01;
+        assert!(matches!(
+            transport.send_shard(2, "abc123", 5, frame).await,
+            Err(NetworkError::TransportCorruption {
+                peer_id: 2,
+                shard_index: 5
+            })
+        ));
+        assert_eq!(cache.get_shard("abc123", 5).await, None);
+    }
+}
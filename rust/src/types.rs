@@ -31,6 +31,12 @@ pub struct Node {
     pub jitter_ms: f32,
     pub packet_loss: f32,
     pub last_seen: u64,
+    /// Country/ASN of the address the peer was last reached at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoInfo>,
+    /// Peer ID (base58) the node proved it holds; `id` alone is only a claim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
 }
 
 impl Node {
@@ -43,9 +49,18 @@ impl Node {
             jitter_ms: 0.0,
             packet_loss: 0.0,
             last_seen: current_timestamp(),
+            geo: None,
+            identity: None,
         }
     }
 
+    /// Failure domain used to spread shards, `unknown` without geo data
+    pub fn failure_domain(&self) -> String {
+        self.geo
+            .as_ref()
+            .map_or_else(|| "unknown".to_string(), GeoInfo::failure_domain)
+    }
+
     /// Update latency and calculate jitter
     pub fn update_latency(&mut self, new_latency: f32) {
         if self.latency_ms > 0.0 {
@@ -84,12 +99,47 @@ impl Node {
     }
 }
 
+/// Where a peer's address is, as far as the geo databases know
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 country code
+    pub country: Option<String>,
+    /// Autonomous system number
+    pub asn: Option<u32>,
+    /// Organisation operating the autonomous system
+    pub as_org: Option<String>,
+}
+
+impl GeoInfo {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none() && self.asn.is_none()
+    }
+
+    /// Peers in the same domain are likely to fail together
+    ///
+    /// The network operator (ASN) is the tighter grouping; the country is
+    /// used when the ASN is unknown.
+    pub fn failure_domain(&self) -> String {
+        match (&self.asn, &self.country) {
+            (Some(asn), _) => format!("AS{}", asn),
+            (None, Some(country)) => format!("country:{}", country),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+}
+
 /// Connection quality metrics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionQuality {
     pub latency_ms: f32,
     pub jitter_ms: f32,
+    /// Share of packets lost, 0.0 - 1.0
     pub packet_loss: f32,
+    /// Measured transfer rate, or the probed bandwidth before any transfer
+    #[serde(default)]
+    pub throughput_bytes_per_sec: f64,
+    #[serde(default)]
+    pub throughput_class: ThroughputClass,
 }
 
 impl Default for ConnectionQuality {
@@ -98,6 +148,37 @@ impl Default for ConnectionQuality {
             latency_ms: 0.0,
             jitter_ms: 0.0,
             packet_loss: 0.0,
+            throughput_bytes_per_sec: 0.0,
+            throughput_class: ThroughputClass::Unknown,
+        }
+    }
+}
+
+/// Coarse bandwidth bucket of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThroughputClass {
+    /// Nothing measured yet
+    #[default]
+    Unknown,
+    /// Below 1 Mbit/s
+    Low,
+    /// 1 - 10 Mbit/s
+    Medium,
+    /// 10 Mbit/s and above
+    High,
+}
+
+impl ThroughputClass {
+    pub fn of(bytes_per_sec: f64) -> Self {
+        if !bytes_per_sec.is_finite() || bytes_per_sec <= 0.0 {
+            Self::Unknown
+        } else if bytes_per_sec < 125_000.0 {
+            Self::Low
+        } else if bytes_per_sec < 1_250_000.0 {
+            Self::Medium
+        } else {
+            Self::High
         }
     }
 }
@@ -115,6 +196,9 @@ pub struct PeerAddress {
     pub peer_id: u32,
     pub host: String,
     pub port: u16,
+    /// Peer ID (base58) the peer must prove when connected to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
 }
 
 impl fmt::Display for PeerAddress {
@@ -143,6 +227,15 @@ pub enum CompressionAlgorithm {
     None,
 }
 
+impl CompressionAlgorithm {
+    /// Every supported algorithm
+    pub const ALL: [CompressionAlgorithm; 3] = [
+        CompressionAlgorithm::Zstd,
+        CompressionAlgorithm::Brotli,
+        CompressionAlgorithm::None,
+    ];
+}
+
 /// Configuration for CES pipeline
 #[derive(Debug, Clone)]
 pub struct CesConfig {
@@ -182,6 +275,41 @@ impl CesConfig {
             chunk_size,
         }
     }
+
+    /// Same config with a different compression algorithm and level
+    pub fn with_compression(mut self, algorithm: CompressionAlgorithm, level: i32) -> Self {
+        self.compression_algorithm = algorithm;
+        self.compression_level = level;
+        self
+    }
+
+    /// Same config with `data` data shards and `parity` parity shards
+    pub fn with_shards(mut self, data: usize, parity: usize) -> Self {
+        self.shard_count = data;
+        self.parity_count = parity;
+        self
+    }
+
+    /// Data plus parity shards produced per chunk
+    pub fn total_shards(&self) -> usize {
+        self.shard_count + self.parity_count
+    }
+
+    /// Every algorithm crossed with every valid shard layout up to the
+    /// given counts (at least one data and one parity shard)
+    pub fn enumerate(max_data: usize, max_parity: usize) -> impl Iterator<Item = CesConfig> {
+        CompressionAlgorithm::ALL
+            .into_iter()
+            .flat_map(move |algorithm| {
+                (1..=max_data).flat_map(move |data| {
+                    (1..=max_parity).map(move |parity| {
+                        CesConfig::default()
+                            .with_compression(algorithm, 3)
+                            .with_shards(data, parity)
+                    })
+                })
+            })
+    }
 }
 
 impl Default for CesConfig {
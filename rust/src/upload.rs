@@ -1,27 +1,75 @@
 use anyhow::{Context, Result};
 use chrono;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, info};
+use std::time::Instant;
+use tracing::{debug, info, instrument};
+use x25519_dalek::PublicKey;
 
 use crate::cache::{Cache, FileManifest};
 use crate::ces::CesPipeline;
+use crate::chunking::ChunkingPolicy;
+use crate::compression::CompressionController;
 use crate::go_client::GoClient;
+use crate::identity::PeerDirectory;
+use crate::namespace::{validate_namespace, DEFAULT_NAMESPACE};
+use crate::network::QuicNode;
+use crate::placement::PlacementPolicy;
+use crate::progress::{ProgressHub, TransferDirection};
+use crate::retry::{retry, RetryPolicy};
+use crate::scheduler::{TransferPriority, TransferScheduler};
+use crate::store::{validate_storage_class, NodeStore};
+use crate::transport::{ShardTransfer, ShardTransport, TransportSelector};
+use crate::upload_session::{UploadSession, UploadSessions};
+
+/// Manifest of an uploaded file plus the transport used for each shard
+#[derive(Debug, Clone)]
+pub struct UploadReport {
+    pub manifest_json: String,
+    pub transfers: Vec<ShardTransfer>,
+}
 
 /// Upload protocol - handles file uploads with CES pipeline
 pub struct UploadProtocol {
     ces: Arc<CesPipeline>,
-    go_client: Arc<GoClient>,
+    transport: TransportSelector,
     cache: Option<Arc<Cache>>,
+    scheduler: Option<Arc<TransferScheduler>>,
+    store: Option<Arc<NodeStore>>,
+    retry: RetryPolicy,
+    recipients: Vec<PublicKey>,
+    compression: Option<Arc<CompressionController>>,
+    progress: Option<ProgressHub>,
+    namespace: String,
+    chunking: Option<ChunkingPolicy>,
+    placement: PlacementPolicy,
+    local_peer: Option<u32>,
+    storage_class: Option<String>,
+    sessions: Option<Arc<UploadSessions>>,
+    directory: Option<Arc<PeerDirectory>>,
 }
 
 impl UploadProtocol {
     pub fn new(ces: Arc<CesPipeline>, go_client: Arc<GoClient>) -> Self {
         Self {
             ces,
-            go_client,
+            transport: TransportSelector::go_only(go_client),
             cache: None,
+            scheduler: None,
+            store: None,
+            retry: RetryPolicy::default(),
+            recipients: Vec::new(),
+            compression: None,
+            progress: None,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            chunking: None,
+            placement: PlacementPolicy::default(),
+            local_peer: None,
+            storage_class: None,
+            sessions: None,
+            directory: None,
         }
     }
 
@@ -29,14 +77,261 @@ impl UploadProtocol {
     pub fn with_cache(ces: Arc<CesPipeline>, go_client: Arc<GoClient>, cache: Arc<Cache>) -> Self {
         Self {
             ces,
-            go_client,
+            transport: TransportSelector::go_only(go_client),
             cache: Some(cache),
+            scheduler: None,
+            store: None,
+            retry: RetryPolicy::default(),
+            recipients: Vec::new(),
+            compression: None,
+            progress: None,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            chunking: None,
+            placement: PlacementPolicy::default(),
+            local_peer: None,
+            storage_class: None,
+            sessions: None,
+            directory: None,
+        }
+    }
+
+    /// Share bandwidth with other transfers through a scheduler
+    pub fn with_scheduler(mut self, scheduler: Arc<TransferScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Feed per-peer send latencies into the node store
+    pub fn with_node_store(mut self, store: Arc<NodeStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Fall back to this node's QUIC connections when the Go node is unavailable
+    pub fn with_native_transport(mut self, network: Arc<QuicNode>) -> Self {
+        self.transport = self.transport.with_native_fallback(network);
+        self
+    }
+
+    /// Send every shard through `transport` instead of the Go node
+    pub fn with_transport(mut self, transport: Arc<dyn ShardTransport>) -> Self {
+        self.transport = TransportSelector::only(transport);
+        self
+    }
+
+    /// Override the retry policy for sending a shard to a peer
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Pick each file's compression level from live CPU load and backlog
+    pub fn with_compression_controller(mut self, controller: Arc<CompressionController>) -> Self {
+        self.compression = Some(controller);
+        self
+    }
+
+    /// Wrap the file key in the manifest for these X25519 public keys
+    pub fn with_recipients(mut self, recipients: Vec<PublicKey>) -> Self {
+        self.recipients = recipients;
+        self
+    }
+
+    /// Publish per-shard progress for each upload
+    pub fn with_progress(mut self, hub: ProgressHub) -> Self {
+        self.progress = Some(hub);
+        self
+    }
+
+    /// Record uploaded files in this namespace, subject to its quota
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Pick each file's shard layout from its size, peers and their throughput
+    pub fn with_chunking(mut self, policy: ChunkingPolicy) -> Self {
+        self.chunking = policy.enabled.then_some(policy);
+        self
+    }
+
+    /// Give each failure domain's shards local parity, placed in the domain,
+    /// and decide whether this node takes shards of its own uploads
+    pub fn with_placement(mut self, policy: PlacementPolicy) -> Self {
+        self.placement = policy;
+        self
+    }
+
+    /// This node's peer ID, kept out of uploads unless the placement policy
+    /// allows it and recorded in manifests as the uploader
+    pub fn with_local_peer(mut self, peer_id: u32) -> Self {
+        self.local_peer = Some(peer_id);
+        self
+    }
+
+    /// Record the storage class the caller placed the shards in
+    ///
+    /// Choosing peers in the class is up to the caller; the manifest keeps
+    /// the class so heal re-places shards on the same kind of peer.
+    pub fn with_storage_class(mut self, class: impl Into<String>) -> Self {
+        self.storage_class = Some(class.into());
+        self
+    }
+
+    /// Persist each upload's shards and placements so it can be resumed
+    pub fn with_sessions(mut self, sessions: Arc<UploadSessions>) -> Self {
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Record in manifests the identity each shard holder is bound to
+    pub fn with_peer_directory(mut self, directory: Arc<PeerDirectory>) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    pub fn storage_class(&self) -> Option<&str> {
+        self.storage_class.as_deref()
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Whether `manifest` already stores a file the way this protocol would
+    ///
+    /// The namespace and storage class must match, and every recipient of
+    /// this protocol must already hold a wrapped key. Passphrase uploads
+    /// derive a fresh key each time, so they never reuse a manifest.
+    pub fn can_reuse(&self, manifest: &FileManifest) -> bool {
+        manifest.namespace == self.namespace
+            && manifest.storage_class == self.storage_class
+            && manifest.kdf.is_none()
+            && self.ces.key_derivation().is_none()
+            && self.recipients.iter().all(|key| {
+                let key = hex::encode(key.as_bytes());
+                manifest.recipients.iter().any(|w| w.recipient == key)
+            })
+    }
+
+    /// Send a shard and record how long the peer took to accept it
+    #[instrument(skip(self, shard), fields(bytes = shard.len()))]
+    async fn send_shard(
+        &self,
+        peer_id: u32,
+        file_hash: &str,
+        shard_index: usize,
+        shard: Vec<u8>,
+    ) -> Result<ShardTransfer> {
+        let start = Instant::now();
+        let transfer = retry(&self.retry, "shard_send", || {
+            self.transport
+                .send_shard(peer_id, file_hash, shard_index, shard.clone())
+        })
+        .await?;
+        if let Some(store) = &self.store {
+            let elapsed = start.elapsed();
+            store.record_peer_latency(peer_id, elapsed).await;
+            store
+                .record_peer_throughput(peer_id, shard.len(), elapsed)
+                .await;
+        }
+        debug!(
+            "Shard {} sent to peer {} via {}",
+            shard_index, peer_id, transfer.transport
+        );
+        Ok(transfer)
+    }
+
+    /// Compression level for a file, adjusted for current load if controlled
+    fn compression_level(&self, file_path: &Path, data: &[u8]) -> i32 {
+        let base = self.ces.recommended_level_for(Some(file_path), data);
+        let Some(controller) = &self.compression else {
+            return base;
+        };
+        let backlog = self.scheduler.as_ref().map_or(0, |s| s.backlog());
+        controller.observe(controller.sample_load(), backlog);
+        let level = controller.level_for(base);
+        if level != base {
+            info!(
+                "Adjusted compression level {} -> {} for current load",
+                base, level
+            );
+        }
+        level
+    }
+
+    /// Pipeline sharding this file the way the chunking policy suggests
+    async fn pipeline_for(&self, file_size: usize, target_peers: &[u32]) -> Option<CesPipeline> {
+        let policy = self.chunking.as_ref()?;
+        let mut peers = target_peers.to_vec();
+        peers.sort_unstable();
+        peers.dedup();
+        let throughput = match &self.store {
+            Some(store) => store.median_throughput(&peers).await,
+            None => None,
+        };
+        let layout = policy.plan(file_size as u64, peers.len(), throughput);
+        info!(
+            "Shard layout: {} data + {} parity shards of ~{} bytes ({} peer(s), {})",
+            layout.data_shards,
+            layout.parity_shards,
+            layout.shard_bytes,
+            peers.len(),
+            throughput.map_or("throughput unmeasured".to_string(), |bps| format!(
+                "{:.0} B/s",
+                bps
+            ))
+        );
+        Some(self.ces.resharded(layout.data_shards, layout.parity_shards))
+    }
+
+    /// Distinct target peers grouped by failure domain
+    async fn peers_by_domain(&self, target_peers: &[u32]) -> Vec<(String, Vec<u32>)> {
+        let mut peers = target_peers.to_vec();
+        peers.sort_unstable();
+        peers.dedup();
+        match &self.store {
+            Some(store) => store.group_by_domain(&peers).await,
+            None => vec![("unknown".to_string(), peers)],
+        }
+    }
+
+    /// Wait for bandwidth before sending a shard
+    async fn throttle(&self, priority: TransferPriority, bytes: usize) {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.acquire(priority, bytes as u64).await;
         }
     }
 
     /// Upload a file with compression, encryption, and sharding
     pub async fn upload_file(&self, file_path: &Path, target_peers: Vec<u32>) -> Result<String> {
-        info!("Starting upload: {:?}", file_path);
+        self.upload_file_with_priority(file_path, target_peers, TransferPriority::Interactive)
+            .await
+    }
+
+    /// Upload a file with an explicit transfer priority
+    pub async fn upload_file_with_priority(
+        &self,
+        file_path: &Path,
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
+    ) -> Result<String> {
+        self.upload_file_with_report(file_path, target_peers, priority)
+            .await
+            .map(|report| report.manifest_json)
+    }
+
+    /// Upload a file, reporting which transport carried each shard
+    #[instrument(skip(self, target_peers), fields(peers = target_peers.len()))]
+    pub async fn upload_file_with_report(
+        &self,
+        file_path: &Path,
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
+    ) -> Result<UploadReport> {
+        info!("Starting upload: {:?} (priority: {})", file_path, priority);
+        let target_peers = self.placement.targets(&target_peers, self.local_peer);
 
         // 1. Read file
         let data = tokio::fs::read(file_path)
@@ -45,63 +340,229 @@ impl UploadProtocol {
         let file_size = data.len();
         info!("Read {} bytes from file", file_size);
 
+        // Refuse early rather than after every shard has been sent
+        validate_namespace(&self.namespace)?;
+        if let Some(class) = &self.storage_class {
+            validate_storage_class(class)?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.check_quota(&self.namespace, file_size as u64).await?;
+        }
+
         // 2. Calculate file hash
         let mut hasher = Sha256::new();
         hasher.update(&data);
         let file_hash = format!("{:x}", hasher.finalize());
 
-        // 3. Process through CES pipeline
-        let shards = self.ces.process(&data)?;
+        // 3. Process through CES pipeline, sharded for this file if chunking is on
+        let compression_level = self.compression_level(file_path, &data);
+        let resharded = self.pipeline_for(file_size, &target_peers).await;
+        let ces = resharded.as_ref().unwrap_or(&*self.ces);
+        let mut shards = ces.process_at_level(&data, compression_level)?;
         info!("Created {} shards from file", shards.len());
 
-        // 4. Distribute shards to peers via Go transport and cache them
-        let mut shard_locations = Vec::new();
-        for (i, shard) in shards.iter().enumerate() {
-            let peer_id = target_peers[i % target_peers.len()];
-
-            debug!(
-                "Sending shard {} ({} bytes) to peer {}",
-                i,
-                shard.len(),
-                peer_id
+        // Local parity per failure domain, numbered after the outer shards
+        let local_parity = if self.placement.tiers {
+            self.placement.plan(
+                shards.len(),
+                ces.parity_count(),
+                &self.peers_by_domain(&target_peers).await,
+            )
+        } else {
+            None
+        };
+        if let Some(local) = &local_parity {
+            let parity = local.encode(&shards)?;
+            info!(
+                "Added {} local parity shards in {} group(s)",
+                parity.len(),
+                local.groups.len()
             );
-            self.go_client.send_data(peer_id, shard.clone()).await?;
-
-            // Cache the shard locally if caching is enabled
-            if let Some(cache) = &self.cache {
-                cache.put_shard(&file_hash, i, shard.clone()).await?;
-            }
-
-            shard_locations.push((i, peer_id));
+            shards.extend(parity);
         }
 
-        // 5. Create and cache manifest
+        // 4. Describe the file; shard locations are filled in as peers accept them
         let file_name = file_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown")
             .to_string();
 
+        let shard_hashes = shards
+            .iter()
+            .map(|shard| format!("{:x}", Sha256::digest(shard)))
+            .collect();
+
         let manifest = FileManifest {
             file_hash: file_hash.clone(),
             file_name,
             file_size,
             shard_count: shards.len(),
-            parity_count: self.ces.parity_count(),
-            shard_locations: shard_locations.clone(),
+            parity_count: ces.parity_count(),
+            shard_locations: Vec::new(),
             timestamp: chrono::Utc::now().timestamp(),
             ttl: 0, // 0 = permanent
+            kdf: ces.key_derivation().cloned(),
+            recipients: ces.wrap_key_for(&self.recipients)?,
+            shard_hashes,
+            compression_level: Some(compression_level),
+            namespace: self.namespace.clone(),
+            storage_class: self.storage_class.clone(),
+            local_parity,
+            shard_identities: Default::default(),
+            uploader: self.local_peer,
+            rotation: None,
         };
+        let mut session = UploadSession::new(file_path, manifest);
+        if let Some(sessions) = &self.sessions {
+            sessions.create(&session, &shards).await?;
+            info!("Upload session: {}", session.id);
+        }
 
+        // 5. Distribute shards to peers and cache them
+        let pending = shards.into_iter().enumerate().collect();
+        self.place_shards(&mut session, pending, &target_peers, priority)
+            .await
+    }
+
+    /// Load an interrupted upload session
+    pub async fn load_session(&self, session_id: &str) -> Result<Option<UploadSession>> {
+        match &self.sessions {
+            Some(sessions) => sessions.load(session_id).await,
+            None => anyhow::bail!("Upload sessions are not enabled"),
+        }
+    }
+
+    /// Send the shards an interrupted session never placed, then publish it
+    ///
+    /// The shards come from the session store as they were processed, so
+    /// the file is neither read nor run through the CES pipeline again.
+    #[instrument(skip(self, session, target_peers), fields(session = %session.id))]
+    pub async fn resume_session(
+        &self,
+        mut session: UploadSession,
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
+    ) -> Result<UploadReport> {
+        let sessions = self
+            .sessions
+            .as_ref()
+            .context("Upload sessions are not enabled")?;
+        if let Some(cache) = &self.cache {
+            cache
+                .check_quota(
+                    &session.manifest.namespace,
+                    session.manifest.file_size as u64,
+                )
+                .await?;
+        }
+        let target_peers = self.placement.targets(&target_peers, self.local_peer);
+        let mut pending = Vec::new();
+        for index in session.pending() {
+            pending.push((index, sessions.load_shard(&session, index).await?));
+        }
+        info!(
+            "Resuming upload {} of {}: {} of {} shards left",
+            session.id,
+            session.manifest.file_hash,
+            pending.len(),
+            session.manifest.shard_count
+        );
+        self.place_shards(&mut session, pending, &target_peers, priority)
+            .await
+    }
+
+    /// Send `pending` shards of a session, recording each placement, and
+    /// cache the manifest once every shard is placed
+    async fn place_shards(
+        &self,
+        session: &mut UploadSession,
+        pending: Vec<(usize, Vec<u8>)>,
+        target_peers: &[u32],
+        priority: TransferPriority,
+    ) -> Result<UploadReport> {
+        if target_peers.is_empty() {
+            anyhow::bail!("No peers to place shards on");
+        }
+        let file_hash = session.manifest.file_hash.clone();
+        let mut tracker = self.progress.as_ref().map(|hub| {
+            let bytes_total = pending.iter().map(|(_, shard)| shard.len() as u64).sum();
+            hub.track(&file_hash, TransferDirection::Upload, pending.len())
+                .with_bytes_total(bytes_total)
+        });
+        // Two-tier layouts keep each group's shards in the group's domain
+        let assigned = match &session.manifest.local_parity {
+            Some(local) => local.assign(&self.peers_by_domain(target_peers).await),
+            None => HashMap::new(),
+        };
+        let mut transfers = Vec::with_capacity(pending.len());
+        for (i, shard) in pending {
+            let peer_id = assigned
+                .get(&i)
+                .copied()
+                .unwrap_or(target_peers[i % target_peers.len()]);
+
+            debug!(
+                "Sending shard {} ({} bytes) to peer {}",
+                i,
+                shard.len(),
+                peer_id
+            );
+            self.throttle(priority, shard.len()).await;
+            match self.send_shard(peer_id, &file_hash, i, shard.clone()).await {
+                Ok(transfer) => transfers.push(transfer),
+                Err(e) => {
+                    if let Some(tracker) = &mut tracker {
+                        tracker.warn(format!("Shard {} to peer {} failed: {:#}", i, peer_id, e));
+                    }
+                    if self.sessions.is_some() {
+                        return Err(e.context(format!(
+                            "Upload interrupted; resume it with `resume-upload {}`",
+                            session.id
+                        )));
+                    }
+                    return Err(e);
+                }
+            }
+            session.placed(i, peer_id);
+            if let Some(sessions) = &self.sessions {
+                sessions.save(session).await?;
+            }
+
+            // Cache the shard locally if caching is enabled
+            if let Some(cache) = &self.cache {
+                cache.put_shard(&file_hash, i, shard.clone()).await?;
+            }
+
+            if let Some(tracker) = &mut tracker {
+                tracker.shard_done(shard.len());
+            }
+        }
+
+        // 6. Cache the manifest; the session is done with
+        let mut manifest = session.clone().finish()?;
+        if let Some(directory) = &self.directory {
+            manifest.shard_identities = directory
+                .identities_of(manifest.shard_locations.iter().map(|&(_, peer_id)| peer_id));
+        }
         if let Some(cache) = &self.cache {
             cache.put_manifest(manifest.clone()).await?;
             info!("Cached manifest for file: {}", file_hash);
         }
+        if let Some(sessions) = &self.sessions {
+            sessions.remove(&session.id).await?;
+        }
 
-        // 6. Return manifest as JSON
+        // 7. Return manifest as JSON
         let manifest_json = serde_json::to_string_pretty(&manifest)?;
         info!("Upload complete: {}", file_hash);
-        Ok(manifest_json)
+        if let Some(tracker) = tracker {
+            tracker.finish(true);
+        }
+        Ok(UploadReport {
+            manifest_json,
+            transfers,
+        })
     }
 
     /// Upload raw data
@@ -109,10 +570,27 @@ impl UploadProtocol {
         &self,
         data: &[u8],
         target_peers: Vec<u32>,
+    ) -> Result<Vec<(usize, u32)>> {
+        self.upload_data_with_priority(data, target_peers, TransferPriority::Interactive)
+            .await
+    }
+
+    /// Upload raw data with an explicit transfer priority
+    #[instrument(skip_all, fields(bytes = data.len(), %priority))]
+    pub async fn upload_data_with_priority(
+        &self,
+        data: &[u8],
+        target_peers: Vec<u32>,
+        priority: TransferPriority,
     ) -> Result<Vec<(usize, u32)>> {
         info!("Starting data upload: {} bytes", data.len());
+        let target_peers = self.placement.targets(&target_peers, self.local_peer);
+        if target_peers.is_empty() {
+            anyhow::bail!("No peers to place shards on");
+        }
 
         // Process through CES pipeline
+        let file_hash = format!("{:x}", Sha256::digest(data));
         let shards = self.ces.process(data)?;
         info!("Created {} shards", shards.len());
 
@@ -120,7 +598,9 @@ impl UploadProtocol {
         let mut shard_locations = Vec::new();
         for (i, shard) in shards.iter().enumerate() {
             let peer_id = target_peers[i % target_peers.len()];
-            self.go_client.send_data(peer_id, shard.clone()).await?;
+            self.throttle(priority, shard.len()).await;
+            self.send_shard(peer_id, &file_hash, i, shard.clone())
+                .await?;
             shard_locations.push((i, peer_id));
         }
 
@@ -0,0 +1,271 @@
+/// Upload sessions that survive node restarts
+///
+/// Before the first shard leaves the node, an upload writes its encrypted
+/// shards and the manifest it will publish under the sessions directory,
+/// then records each shard's peer as soon as the peer accepts it. An upload
+/// cut short by a crash or a failed peer is picked up again with
+/// `resume-upload <session-id>`: only the shards never placed are sent, and
+/// the CES pipeline does not run again. The session is removed once its
+/// manifest is cached.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::cache::FileManifest;
+
+/// Directory, under the cache directory, holding upload sessions
+pub const SESSIONS_DIR: &str = "upload-sessions";
+
+/// Placement state of an upload in progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    /// File the shards were made from (informational; it is not read again)
+    pub file_path: PathBuf,
+    /// Unix time the session was created
+    pub created: i64,
+    /// Manifest to publish; its `shard_locations` are filled in by [`Self::finish`]
+    pub manifest: FileManifest,
+    /// Peer holding each shard, `None` until one accepted it
+    pub placements: Vec<Option<u32>>,
+}
+
+impl UploadSession {
+    /// Start a session for `manifest`, with no shard placed yet
+    pub fn new(file_path: impl Into<PathBuf>, manifest: FileManifest) -> Self {
+        Self {
+            id: format!("{:016x}", rand::random::<u64>()),
+            file_path: file_path.into(),
+            created: chrono::Utc::now().timestamp(),
+            placements: vec![None; manifest.shard_count],
+            manifest,
+        }
+    }
+
+    /// Indices of shards not yet placed, in order
+    pub fn pending(&self) -> Vec<usize> {
+        self.placements
+            .iter()
+            .enumerate()
+            .filter(|(_, peer)| peer.is_none())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.placements.iter().all(Option::is_some)
+    }
+
+    /// Record that `peer_id` accepted shard `index`
+    pub fn placed(&mut self, index: usize, peer_id: u32) {
+        self.placements[index] = Some(peer_id);
+    }
+
+    /// The manifest with every placement recorded, stamped now
+    pub fn finish(self) -> Result<FileManifest> {
+        let mut manifest = self.manifest;
+        manifest.shard_locations = self
+            .placements
+            .iter()
+            .enumerate()
+            .map(|(index, peer)| {
+                peer.map(|peer| (index, peer))
+                    .with_context(|| format!("Shard {} was never placed", index))
+            })
+            .collect::<Result<_>>()?;
+        manifest.timestamp = chrono::Utc::now().timestamp();
+        Ok(manifest)
+    }
+}
+
+/// On-disk store of upload sessions: `<id>/session.json` plus `<id>/<index>.bin`
+pub struct UploadSessions {
+    dir: PathBuf,
+}
+
+impl UploadSessions {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Sessions kept under `cache_dir`
+    pub fn in_cache_dir(cache_dir: impl AsRef<Path>) -> Self {
+        Self::new(cache_dir.as_ref().join(SESSIONS_DIR))
+    }
+
+    /// Persist a new session together with all of its shards
+    pub async fn create(&self, session: &UploadSession, shards: &[Vec<u8>]) -> Result<()> {
+        let dir = self.session_dir(&session.id)?;
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context("Failed to create upload session")?;
+        for (index, shard) in shards.iter().enumerate() {
+            tokio::fs::write(dir.join(format!("{}.bin", index)), shard)
+                .await
+                .with_context(|| format!("Failed to persist shard {}", index))?;
+        }
+        self.save(session).await?;
+        debug!(
+            "Created upload session {} ({} shards)",
+            session.id,
+            shards.len()
+        );
+        Ok(())
+    }
+
+    /// Record the session's placements, replacing the previous record atomically
+    pub async fn save(&self, session: &UploadSession) -> Result<()> {
+        let dir = self.session_dir(&session.id)?;
+        let tmp = dir.join("session.json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(session)?)
+            .await
+            .context("Failed to write upload session")?;
+        tokio::fs::rename(&tmp, dir.join("session.json"))
+            .await
+            .context("Failed to write upload session")?;
+        Ok(())
+    }
+
+    /// Load a session, `None` if there is no such session
+    pub async fn load(&self, id: &str) -> Result<Option<UploadSession>> {
+        let path = self.session_dir(id)?.join("session.json");
+        let json = match tokio::fs::read(&path).await {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read upload session"),
+        };
+        let session: UploadSession = serde_json::from_slice(&json)
+            .with_context(|| format!("Invalid upload session {:?}", path))?;
+        if session.id != id || session.placements.len() != session.manifest.shard_count {
+            anyhow::bail!("Upload session {} is inconsistent", id);
+        }
+        session.manifest.validate()?;
+        Ok(Some(session))
+    }
+
+    /// Read a persisted shard, checking it against the session's manifest
+    pub async fn load_shard(&self, session: &UploadSession, index: usize) -> Result<Vec<u8>> {
+        let path = self
+            .session_dir(&session.id)?
+            .join(format!("{}.bin", index));
+        let shard = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read shard {} of session {}", index, session.id))?;
+        let expected = session.manifest.shard_hashes.get(index);
+        if expected.is_some_and(|hash| *hash != format!("{:x}", Sha256::digest(&shard))) {
+            anyhow::bail!("Shard {} of session {} is corrupt", index, session.id);
+        }
+        Ok(shard)
+    }
+
+    /// All sessions still waiting to finish, oldest first
+    pub async fn list(&self) -> Result<Vec<UploadSession>> {
+        let mut sessions = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(sessions),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(id) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            match self.load(&id).await {
+                Ok(Some(session)) => sessions.push(session),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping upload session {:?}: {:#}", entry.path(), e),
+            }
+        }
+        sessions.sort_by_key(|session| session.created);
+        Ok(sessions)
+    }
+
+    /// Delete a session and its shards
+    pub async fn remove(&self, id: &str) -> Result<()> {
+        let dir = self.session_dir(id)?;
+        if dir.exists() {
+            tokio::fs::remove_dir_all(&dir)
+                .await
+                .context("Failed to remove upload session")?;
+        }
+        debug!("Removed upload session {}", id);
+        Ok(())
+    }
+
+    fn session_dir(&self, id: &str) -> Result<PathBuf> {
+        if id.len() != 16 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid upload session id {:?}", id);
+        }
+        Ok(self.dir.join(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(shards: &[Vec<u8>]) -> FileManifest {
+        FileManifest {
+            file_hash: "ab".repeat(32),
+            file_name: "photo.jpg".into(),
+            file_size: 1000,
+            shard_count: shards.len(),
+            parity_count: 1,
+            shard_hashes: shards
+                .iter()
+                .map(|shard| format!("{:x}", Sha256::digest(shard)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::in_cache_dir(dir.path());
+        let shards = vec![vec![1u8; 10], vec![2u8; 10], vec![3u8; 10]];
+        let mut session = UploadSession::new("photo.jpg", manifest(&shards));
+        sessions.create(&session, &shards).await.unwrap();
+        session.placed(0, 7);
+        sessions.save(&session).await.unwrap();
+
+        // A restarted node sees the placement and the remaining shards
+        let reopened = UploadSessions::in_cache_dir(dir.path());
+        let mut loaded = reopened.load(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.pending(), vec![1, 2]);
+        assert_eq!(reopened.load_shard(&loaded, 2).await.unwrap(), shards[2]);
+        assert_eq!(reopened.list().await.unwrap().len(), 1);
+        assert!(loaded.clone().finish().is_err());
+
+        loaded.placed(1, 8);
+        loaded.placed(2, 9);
+        assert!(loaded.is_complete());
+        let manifest = loaded.finish().unwrap();
+        assert_eq!(manifest.shard_locations, vec![(0, 7), (1, 8), (2, 9)]);
+
+        reopened.remove(&session.id).await.unwrap();
+        assert!(reopened.load(&session.id).await.unwrap().is_none());
+        assert!(reopened.list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_shards_and_bad_ids_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let sessions = UploadSessions::in_cache_dir(dir.path());
+        let shards = vec![vec![1u8; 10], vec![2u8; 10]];
+        let session = UploadSession::new("photo.jpg", manifest(&shards));
+        sessions.create(&session, &shards).await.unwrap();
+
+        let path = dir
+            .path()
+            .join(SESSIONS_DIR)
+            .join(&session.id)
+            .join("1.bin");
+        std::fs::write(path, b"tampered").unwrap();
+        assert!(sessions.load_shard(&session, 1).await.is_err());
+
+        assert!(sessions.load("../../etc/passwd").await.is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use pangea_ces::ces::{ENCRYPTION_OVERHEAD_BYTES, LENGTH_PREFIX_BYTES};
+use pangea_ces::types::CompressionAlgorithm;
+use pangea_ces::*;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+
+const KEY: [u8; 32] = [7; 32];
+
+fn algorithm() -> impl Strategy<Value = CompressionAlgorithm> {
+    prop::sample::select(CompressionAlgorithm::ALL.to_vec())
+}
+
+/// Random data, biased towards compressible runs so both paths are hit
+fn data() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..32 * 1024),
+        (any::<u8>(), 0usize..64 * 1024).prop_map(|(byte, len)| vec![byte; len]),
+        prop::collection::vec(0u8..4, 0..16 * 1024),
+    ]
+}
+
+/// A shard layout plus a random subset of at most `parity` shard indices
+fn layout_with_losses() -> impl Strategy<Value = (usize, usize, Vec<usize>)> {
+    (1usize..=10, 1usize..=6).prop_flat_map(|(data, parity)| {
+        let total = data + parity;
+        (0..=parity).prop_flat_map(move |lost| {
+            subsequence((0..total).collect::<Vec<_>>(), lost)
+                .prop_map(move |indices| (data, parity, indices))
+        })
+    })
+}
+
+fn pipeline(config: CesConfig) -> CesPipeline {
+    CesPipeline::new(config).with_key(KEY)
+}
+
+/// Ciphertext length recorded at the front of the data shards
+fn recorded_len(shards: &[Vec<u8>], data_shards: usize) -> usize {
+    let prefix: Vec<u8> = shards[..data_shards]
+        .iter()
+        .flatten()
+        .take(LENGTH_PREFIX_BYTES)
+        .copied()
+        .collect();
+    u32::from_le_bytes(prefix.try_into().unwrap()) as usize
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn roundtrip_survives_losses_up_to_parity(
+        data in data(),
+        algorithm in algorithm(),
+        level in 0i32..=9,
+        (data_shards, parity, lost) in layout_with_losses(),
+    ) {
+        let config = CesConfig::default()
+            .with_compression(algorithm, level)
+            .with_shards(data_shards, parity);
+        let ces = pipeline(config.clone());
+
+        let shards = ces.process_at_level(&data, level).unwrap();
+        prop_assert_eq!(shards.len(), config.total_shards());
+
+        let mut received: Vec<Option<Vec<u8>>> = shards.into_iter().map(Some).collect();
+        for index in lost {
+            received[index] = None;
+        }
+        prop_assert_eq!(ces.reconstruct(received).unwrap(), data);
+    }
+
+    #[test]
+    fn losing_more_than_parity_fails(
+        data in prop::collection::vec(any::<u8>(), 1..4096),
+        (data_shards, parity) in (1usize..=8, 1usize..=4),
+        seed in any::<u64>(),
+    ) {
+        let ces = pipeline(CesConfig::default().with_shards(data_shards, parity));
+        let shards = ces.process(&data).unwrap();
+
+        // Drop parity + 1 shards starting at a random offset
+        let total = shards.len();
+        let mut received: Vec<Option<Vec<u8>>> = shards.into_iter().map(Some).collect();
+        for i in 0..=parity {
+            received[(seed as usize + i) % total] = None;
+        }
+        prop_assert!(ces.reconstruct(received).is_err());
+    }
+
+    #[test]
+    fn shard_header_invariants(
+        data in data(),
+        algorithm in algorithm(),
+        (data_shards, parity) in (1usize..=10, 1usize..=6),
+    ) {
+        let ces = pipeline(
+            CesConfig::default()
+                .with_compression(algorithm, 0)
+                .with_shards(data_shards, parity),
+        );
+        let shards = ces.process_at_level(&data, 0).unwrap();
+
+        // All shards share one size, just large enough for prefix + ciphertext
+        let shard_size = shards[0].len();
+        prop_assert!(shards.iter().all(|shard| shard.len() == shard_size));
+        let enc_len = recorded_len(&shards, data_shards);
+        prop_assert_eq!(enc_len, data.len() + ENCRYPTION_OVERHEAD_BYTES);
+        let framed = LENGTH_PREFIX_BYTES + enc_len;
+        prop_assert_eq!(shard_size, framed.div_ceil(data_shards));
+
+        // Padding after the ciphertext is zeroed
+        let padding: Vec<u8> = shards[..data_shards].iter().flatten().skip(framed).copied().collect();
+        prop_assert!(padding.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn corrupted_shard_is_detected(
+        data in prop::collection::vec(any::<u8>(), 1..4096),
+        position in any::<prop::sample::Index>(),
+        flip in 1u8..=255,
+    ) {
+        let ces = pipeline(CesConfig::default().with_shards(4, 2));
+        let mut shards = ces.process(&data).unwrap();
+
+        // Any flipped byte in a data shard must never yield different plaintext
+        let shard_size = shards[0].len();
+        let at = position.index(4 * shard_size);
+        shards[at / shard_size][at % shard_size] ^= flip;
+        let received = shards.into_iter().map(Some).collect();
+        if let Ok(decoded) = ces.reconstruct(received) {
+            prop_assert_eq!(decoded, data);
+        }
+    }
+}
+
+#[test]
+fn every_enumerated_config_roundtrips() {
+    let data = b"enumerate every layout ".repeat(64);
+    let mut seen = 0;
+    for config in CesConfig::enumerate(6, 3) {
+        let ces = pipeline(config.clone());
+        let shards = ces.process(&data).unwrap();
+        assert_eq!(shards.len(), config.total_shards(), "{:?}", config);
+        let received = shards.into_iter().map(Some).collect();
+        assert_eq!(ces.reconstruct(received).unwrap(), data, "{:?}", config);
+        seen += 1;
+    }
+    assert_eq!(seen, CompressionAlgorithm::ALL.len() * 6 * 3);
+}
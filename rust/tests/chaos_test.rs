@@ -0,0 +1,139 @@
+#![cfg(feature = "chaos")]
+
+use pangea_ces::auto_heal::{AutoHealConfig, AutoHealer};
+use pangea_ces::chaos::ChaosTransport;
+use pangea_ces::download::DownloadProtocol;
+use pangea_ces::store::NodeStore;
+use pangea_ces::*;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_elapsed: None,
+        ..Default::default()
+    }
+}
+
+#[allow(clippy::arc_with_non_send_sync)]
+fn go_client() -> Arc<go_client::GoClient> {
+    // Never connected: every shard goes through the chaos transport
+    Arc::new(go_client::GoClient::new(SocketAddr::from((
+        [127, 0, 0, 1],
+        0,
+    ))))
+}
+
+#[tokio::test]
+async fn test_auto_heal_restores_redundancy_under_faults() {
+    let network = Arc::new(
+        SimulatedNetwork::new(NetworkProfile::Lan.conditions())
+            .with_seed(3)
+            .with_peers(1..=10),
+    );
+    let chaos = Arc::new(ChaosTransport::new(network.clone()).with_seed(11));
+    let store = Arc::new(NodeStore::new());
+    for id in 1..=10 {
+        store.upsert_node(Node::new(id)).await;
+    }
+
+    // Spread a 4+2 encoded file over peers 1-6
+    let ces = Arc::new(CesPipeline::new(CesConfig::default().with_shards(4, 2)).with_key([9; 32]));
+    let data = b"heal me if you can ".repeat(2000);
+    let shards = ces.process(&data).unwrap();
+    let mut shard_locations = Vec::new();
+    for (index, shard) in shards.iter().enumerate() {
+        let peer = index as u32 + 1;
+        network
+            .send_shard(peer, "healme", index, shard.clone())
+            .await
+            .unwrap();
+        shard_locations.push((index, peer));
+    }
+    let manifest = FileManifest {
+        file_hash: "healme".to_string(),
+        file_name: "healme.txt".to_string(),
+        file_size: data.len(),
+        shard_count: shards.len(),
+        parity_count: 2,
+        shard_locations,
+        shard_hashes: shards
+            .iter()
+            .map(|shard| format!("{:x}", Sha256::digest(shard)))
+            .collect(),
+        namespace: namespace::DEFAULT_NAMESPACE.to_string(),
+        ..Default::default()
+    };
+    let dir = tempdir().unwrap();
+    let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+    cache.put_manifest(manifest).await.unwrap();
+
+    // Peer 2 dies, peer 5 serves corrupt bytes and a fifth of requests vanish
+    assert!(store.chaos_kill_peer(2).await);
+    chaos.kill_peer(2);
+    chaos.corrupt_peer(5);
+    chaos.set_drop_rate(0.2);
+
+    let healer = AutoHealer::new(
+        AutoHealConfig {
+            min_shard_copies: 4,
+            target_shard_copies: 6,
+            ..Default::default()
+        },
+        cache.clone(),
+        ces.clone(),
+        go_client(),
+        store.clone(),
+    )
+    .with_transport(chaos.clone())
+    .with_retry_policy(fast_retries(20));
+    healer.run_healing_cycle().await.unwrap();
+
+    let stats = healer.get_stats().await;
+    assert_eq!(stats.heals_succeeded, 1);
+    assert_eq!(stats.shards_recovered, 2);
+    assert!(chaos.stats().corrupted > 0);
+
+    // Both lost shards moved to spare peers
+    let healed = cache.get_manifest("healme").await.unwrap();
+    let holders: Vec<u32> = healed.shard_locations.iter().map(|(_, p)| *p).collect();
+    assert!(
+        !holders.contains(&2) && !holders.contains(&5),
+        "{:?}",
+        holders
+    );
+
+    // Every location holds the exact shard the manifest records
+    chaos.calm();
+    for (index, peer) in &healed.shard_locations {
+        let shard = network
+            .fetch_shard(*peer, &healed.file_hash, *index)
+            .await
+            .unwrap();
+        assert_eq!(
+            format!("{:x}", Sha256::digest(&shard)),
+            healed.shard_hashes[*index]
+        );
+    }
+
+    // Parity is whole again: the faulty peers plus two more can go away
+    for peer in [1, 2, 3, 5] {
+        network.set_peer_down(peer, true);
+    }
+    let download = DownloadProtocol::new(ces, go_client())
+        .with_transport(network.clone())
+        .with_retry_policy(RetryPolicy::none());
+    assert_eq!(
+        download
+            .download_data(healed.shard_locations)
+            .await
+            .unwrap(),
+        data
+    );
+}
@@ -0,0 +1,205 @@
+use pangea_ces::download::DownloadProtocol;
+use pangea_ces::simulation::LatencyModel;
+use pangea_ces::upload::UploadProtocol;
+use pangea_ces::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Retry quickly so lossy links don't slow the tests down
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+        max_elapsed: None,
+        ..Default::default()
+    }
+}
+
+fn pipeline() -> Arc<CesPipeline> {
+    Arc::new(CesPipeline::new(CesConfig {
+        compression_level: 3,
+        compression_algorithm: types::CompressionAlgorithm::Zstd,
+        shard_count: 4,
+        parity_count: 2,
+        chunk_size: 1024 * 1024,
+    }))
+}
+
+#[allow(clippy::arc_with_non_send_sync)]
+fn go_client() -> Arc<go_client::GoClient> {
+    // Never connected: every shard goes through the simulated transport
+    Arc::new(go_client::GoClient::new(SocketAddr::from((
+        [127, 0, 0, 1],
+        0,
+    ))))
+}
+
+#[tokio::test]
+async fn test_retries_ride_out_packet_loss() {
+    let network = Arc::new(
+        SimulatedNetwork::new(LinkConditions {
+            latency: LatencyModel::Uniform {
+                min: Duration::from_millis(1),
+                max: Duration::from_millis(3),
+            },
+            loss_rate: 0.3,
+            reorder_rate: 0.2,
+            reorder_delay: Duration::from_millis(2),
+            bandwidth_bytes_per_sec: 0,
+        })
+        .with_seed(42)
+        .with_peers(1..=6),
+    );
+    let ces = pipeline();
+    let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let upload = UploadProtocol::new(ces.clone(), go_client())
+        .with_transport(network.clone())
+        .with_retry_policy(fast_retries(20));
+    let locations = upload.upload_data(&data, (1..=6).collect()).await.unwrap();
+
+    let download = DownloadProtocol::new(ces, go_client())
+        .with_transport(network.clone())
+        .with_retry_policy(fast_retries(20));
+    assert_eq!(download.download_data(locations).await.unwrap(), data);
+
+    let stats = network.stats();
+    assert!(stats.lost > 0, "expected some injected loss: {:?}", stats);
+}
+
+#[tokio::test]
+async fn test_parity_covers_unreachable_peers() {
+    let network =
+        Arc::new(SimulatedNetwork::new(NetworkProfile::Lan.conditions()).with_peers(1..=6));
+    let ces = pipeline();
+    let data = b"erasure coding keeps this readable".repeat(500);
+
+    let upload = UploadProtocol::new(ces.clone(), go_client()).with_transport(network.clone());
+    let locations = upload.upload_data(&data, (1..=6).collect()).await.unwrap();
+
+    // Two of six shards lost is exactly what the parity allows
+    network.set_peer_down(2, true);
+    network.wipe_peer(5);
+    let download = DownloadProtocol::new(ces.clone(), go_client())
+        .with_transport(network.clone())
+        .with_retry_policy(RetryPolicy::none());
+    assert_eq!(
+        download.download_data(locations.clone()).await.unwrap(),
+        data
+    );
+
+    // A third missing shard is one too many
+    network.set_peer_down(3, true);
+    assert!(download.download_data(locations).await.is_err());
+}
+
+#[tokio::test]
+async fn test_corrupt_shard_is_quarantined_and_reported() {
+    use sha2::{Digest, Sha256};
+
+    let network =
+        Arc::new(SimulatedNetwork::new(NetworkProfile::Lan.conditions()).with_peers(1..=6));
+    let ces = pipeline();
+    let data = b"poisoned shards are set aside ".repeat(300);
+    let shards = ces.process(&data).unwrap();
+    let mut shard_locations = Vec::new();
+    for (index, shard) in shards.iter().enumerate() {
+        let peer = index as u32 + 1;
+        network
+            .send_shard(peer, "poisoned", index, shard.clone())
+            .await
+            .unwrap();
+        shard_locations.push((index, peer));
+    }
+    // Peer 3 swaps its shard for garbage of the same size
+    network
+        .send_shard(3, "poisoned", 2, vec![0xAA; shards[2].len()])
+        .await
+        .unwrap();
+
+    let manifest = FileManifest {
+        file_hash: "poisoned".to_string(),
+        file_name: "poisoned.txt".to_string(),
+        file_size: data.len(),
+        shard_count: shards.len(),
+        parity_count: 2,
+        shard_locations,
+        shard_hashes: shards
+            .iter()
+            .map(|shard| format!("{:x}", Sha256::digest(shard)))
+            .collect(),
+        namespace: namespace::DEFAULT_NAMESPACE.to_string(),
+        ..Default::default()
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Arc::new(Cache::new(dir.path(), 100, 64 * 1024 * 1024).unwrap());
+    let store = Arc::new(NodeStore::new());
+    let download = DownloadProtocol::with_cache(ces, go_client(), cache.clone())
+        .with_transport(network.clone())
+        .with_node_store(store.clone())
+        .with_retry_policy(RetryPolicy::none());
+    let output = dir.path().join("out.txt");
+    download
+        .download_manifest(
+            &output,
+            &manifest,
+            TransferPriority::Interactive,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), data);
+
+    // The bad copy never reached the cache; the peer is on record
+    assert!(!cache.has_shard("poisoned", 2).await);
+    assert_eq!(cache.get_stats().await.shards_quarantined, 1);
+    assert_eq!(
+        std::fs::read_dir(cache.quarantine_dir()).unwrap().count(),
+        1
+    );
+    assert_eq!(store.misbehavior(3).await.unwrap().bad_shards, 1);
+    assert_eq!(store.rank_sources(&[3, 4]).await, vec![4, 3]);
+}
+
+#[tokio::test]
+async fn test_chunked_upload_downloads_with_manifest_layout() {
+    let network =
+        Arc::new(SimulatedNetwork::new(NetworkProfile::Lan.conditions()).with_peers(1..=9));
+    let ces = pipeline();
+    let data: Vec<u8> = (0..512 * 1024).map(|i| (i % 253) as u8).collect();
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("chunked.bin");
+    std::fs::write(&input, &data).unwrap();
+
+    // Nine peers and 512 KiB: six data shards plus three parity, not the
+    // pipeline's static 4 + 2
+    let upload = UploadProtocol::new(ces.clone(), go_client())
+        .with_transport(network.clone())
+        .with_chunking(ChunkingPolicy::default());
+    let report = upload
+        .upload_file_with_report(&input, (1..=9).collect(), TransferPriority::Interactive)
+        .await
+        .unwrap();
+    let manifest = FileManifest::from_json(&report.manifest_json).unwrap();
+    assert_eq!((manifest.shard_count, manifest.parity_count), (9, 3));
+
+    let download = DownloadProtocol::new(ces, go_client())
+        .with_transport(network.clone())
+        .with_retry_policy(RetryPolicy::none());
+    let output = dir.path().join("out.bin");
+    download
+        .download_manifest(
+            &output,
+            &manifest,
+            TransferPriority::Interactive,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), data);
+}
@@ -301,6 +301,7 @@ async fn test_quic_transport_creation() {
         enable_gso: true,
         idle_timeout_ms: 30000,
         max_chunk_size: 10 * 1024 * 1024,
+        enable_0rtt: true,
     };
 
     let transport = QuicTransport::new(config);
@@ -0,0 +1,69 @@
+#!/bin/bash
+# Criterion benchmark baselines and regression gate for the Rust node
+#
+#   scripts/bench_gate.sh save [name]                 record a baseline (default: main)
+#   scripts/bench_gate.sh compare [name] [percent]    fail if any bench is slower
+#                                                     than the baseline by more than
+#                                                     percent (default: 10)
+#
+# Extra arguments after `--` are passed to cargo bench, e.g. `-- --bench ces`.
+
+set -euo pipefail
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+RUST_DIR="$(cd "$SCRIPT_DIR/../rust" && pwd)"
+
+MODE="${1:-}"
+BASELINE="${2:-main}"
+THRESHOLD="${3:-10}"
+shift $(( $# < 3 ? $# : 3 ))
+if [ "${1:-}" = "--" ]; then
+    shift
+fi
+
+cd "$RUST_DIR"
+
+case "$MODE" in
+    save)
+        echo "📊 Recording benchmark baseline '$BASELINE'"
+        cargo bench --features bench "$@" -- --save-baseline "$BASELINE"
+        ;;
+    compare)
+        echo "📊 Comparing against baseline '$BASELINE' (gate: +${THRESHOLD}%)"
+        # Stale change estimates from earlier runs would skew the gate
+        find target/criterion -path '*/change/estimates.json' -delete 2>/dev/null || true
+        cargo bench --features bench "$@" -- --baseline "$BASELINE"
+        python3 - "$THRESHOLD" <<'PY'
+import json
+import pathlib
+import sys
+
+threshold = float(sys.argv[1]) / 100
+rows = []
+for path in sorted(pathlib.Path("target/criterion").glob("**/change/estimates.json")):
+    bench = str(path.parent.parent.relative_to("target/criterion"))
+    change = json.loads(path.read_text())["mean"]["point_estimate"]
+    rows.append((bench, change))
+
+if not rows:
+    sys.exit("❌ No comparison data; record a baseline with 'save' first")
+
+width = max(len(bench) for bench, _ in rows)
+regressions = 0
+for bench, change in rows:
+    flag = ""
+    if change > threshold:
+        flag = "  ❌ regression"
+        regressions += 1
+    print(f"{bench:<{width}}  {change * 100:+7.2f}%{flag}")
+
+if regressions:
+    sys.exit(f"❌ {regressions} benchmark(s) regressed by more than {threshold * 100:.0f}%")
+print("✅ No regressions beyond threshold")
+PY
+        ;;
+    *)
+        sed -n '2,10p' "$0"
+        exit 1
+        ;;
+esac
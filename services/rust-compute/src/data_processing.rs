@@ -1,7 +1,8 @@
 use anyhow::Result;
-use log::{info, warn};
+use log::info;
 use rayon::prelude::*;
-use std::sync::Arc;
+
+use crate::operators::{self, PipelineResult, PipelineSpec};
 
 /// PreprocessResult holds the output of data preprocessing
 #[derive(Debug, Clone)]
@@ -78,6 +79,14 @@ impl Preprocessor {
             .collect()
     }
 
+    /// Run a preprocessing pipeline over `input` on this preprocessor's workers
+    ///
+    /// Records are processed in chunks of `chunk_size` on a pool of
+    /// `num_workers` threads.
+    pub fn run_pipeline(&self, spec: &PipelineSpec, input: &[u8]) -> Result<PipelineResult> {
+        operators::run_pipeline(spec, input, self.num_workers, self.chunk_size)
+    }
+
     /// Serialize preprocessed data to Cap'n Proto format (stub)
     pub fn serialize_to_capnp(&self, result: &PreprocessResult) -> Result<Vec<u8>> {
         info!(
@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::{info, warn};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::metrics::Metrics;
+
+/// Memory is accounted in KiB so a budget fits in semaphore permits
+const KIB: usize = 1024;
+
+/// Prefix of the error returned when a job is shed, so the orchestrator can
+/// tell backpressure apart from a bad job and retry elsewhere or later
+pub const NO_CREDITS: &str = "no ingest credits";
+
+/// What to do with a job whose input does not fit in the memory budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ShedPolicy {
+    /// Refuse the job; the orchestrator has to retry it
+    Reject,
+    /// Write the input to disk and load it once memory frees up
+    Spill,
+}
+
+/// Limits applied to incoming jobs
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    /// Jobs admitted but not yet finished; each one holds a credit
+    pub queue_capacity: usize,
+    /// Input bytes held in memory across all admitted jobs
+    pub memory_budget: usize,
+    pub policy: ShedPolicy,
+    /// Where spilled inputs are written
+    pub spill_dir: PathBuf,
+}
+
+/// Input of an admitted job, either held in memory or spilled to disk
+pub enum JobInput {
+    Memory(Vec<u8>),
+    Spilled { path: PathBuf, len: usize },
+}
+
+/// Credit and memory held by an admitted job, released on drop
+pub struct IngestPermit {
+    credit: Option<OwnedSemaphorePermit>,
+    memory: Option<OwnedSemaphorePermit>,
+    spill_path: Option<PathBuf>,
+    queue: Arc<IngestQueue>,
+}
+
+impl Drop for IngestPermit {
+    fn drop(&mut self) {
+        if let Some(path) = self.spill_path.take() {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove spill file {}: {}", path.display(), e);
+            }
+        }
+        // Release before publishing so the gauges see the freed budget
+        self.credit.take();
+        self.memory.take();
+        self.queue.publish();
+    }
+}
+
+/// Bounded ingestion queue with credit-based backpressure
+///
+/// Every admitted job takes one credit until it finishes, so at most
+/// `queue_capacity` jobs are queued or running. Inputs held in memory are
+/// charged against `memory_budget`; an input that does not fit is either
+/// rejected or spilled to disk, depending on the shed policy.
+pub struct IngestQueue {
+    config: IngestConfig,
+    credits: Arc<Semaphore>,
+    memory: Arc<Semaphore>,
+    memory_kib: usize,
+    metrics: Arc<Metrics>,
+}
+
+impl IngestQueue {
+    pub fn new(config: IngestConfig, metrics: Arc<Metrics>) -> Arc<Self> {
+        let memory_kib = (config.memory_budget / KIB).clamp(1, Semaphore::MAX_PERMITS);
+        metrics.ingest_credits.set(config.queue_capacity as i64);
+        Arc::new(Self {
+            credits: Arc::new(Semaphore::new(config.queue_capacity)),
+            memory: Arc::new(Semaphore::new(memory_kib)),
+            memory_kib,
+            config,
+            metrics,
+        })
+    }
+
+    /// Jobs that can be admitted right now
+    pub fn credits(&self) -> usize {
+        self.credits.available_permits()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.config.queue_capacity
+    }
+
+    /// Bytes of job input currently held in memory
+    pub fn memory_in_use(&self) -> usize {
+        (self.memory_kib - self.memory.available_permits()) * KIB
+    }
+
+    fn publish(&self) {
+        self.metrics.ingest_credits.set(self.credits() as i64);
+        self.metrics
+            .ingest_memory_bytes
+            .set(self.memory_in_use() as i64);
+    }
+
+    /// Fraction of the queue or memory budget in use, whichever is higher
+    pub fn pressure(&self) -> f32 {
+        let queue = 1.0 - self.credits() as f32 / self.capacity().max(1) as f32;
+        let memory = 1.0 - self.memory.available_permits() as f32 / self.memory_kib as f32;
+        queue.max(memory)
+    }
+
+    /// Admit a job's input or shed it
+    ///
+    /// Fails with a [`NO_CREDITS`] error when the queue is full, or when the
+    /// input does not fit in memory under the reject policy.
+    pub async fn admit(
+        self: &Arc<Self>,
+        job_id: &str,
+        input: Vec<u8>,
+    ) -> Result<(IngestPermit, JobInput)> {
+        let credit = match Arc::clone(&self.credits).try_acquire_owned() {
+            Ok(credit) => credit,
+            Err(_) => {
+                self.metrics.ingest_rejected_total.inc();
+                anyhow::bail!(
+                    "{}: {} job(s) already queued",
+                    NO_CREDITS,
+                    self.config.queue_capacity
+                );
+            }
+        };
+
+        let kib = kib_for(input.len());
+        if kib > self.memory_kib && self.config.policy == ShedPolicy::Reject {
+            self.metrics.ingest_rejected_total.inc();
+            anyhow::bail!(
+                "Job input of {} bytes exceeds the memory budget of {} bytes",
+                input.len(),
+                self.config.memory_budget
+            );
+        }
+
+        let mut permit = IngestPermit {
+            credit: Some(credit),
+            memory: None,
+            spill_path: None,
+            queue: Arc::clone(self),
+        };
+        let reserved = u32::try_from(kib)
+            .ok()
+            .and_then(|kib| Arc::clone(&self.memory).try_acquire_many_owned(kib).ok());
+
+        let input = match (reserved, self.config.policy) {
+            (Some(memory), _) => {
+                permit.memory = Some(memory);
+                JobInput::Memory(input)
+            }
+            (None, ShedPolicy::Reject) => {
+                self.metrics.ingest_rejected_total.inc();
+                anyhow::bail!(
+                    "{}: {} of {} bytes of input memory in use",
+                    NO_CREDITS,
+                    self.memory_in_use(),
+                    self.config.memory_budget
+                );
+            }
+            (None, ShedPolicy::Spill) => {
+                let path = self.config.spill_dir.join(spill_name(job_id));
+                tokio::fs::create_dir_all(&self.config.spill_dir)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to create spill dir {}",
+                            self.config.spill_dir.display()
+                        )
+                    })?;
+                tokio::fs::write(&path, &input)
+                    .await
+                    .with_context(|| format!("Failed to spill input to {}", path.display()))?;
+                permit.spill_path = Some(path.clone());
+                self.metrics.ingest_spilled_total.inc();
+                info!(
+                    "💾 Spilled {} bytes of input for job {}",
+                    input.len(),
+                    job_id
+                );
+                JobInput::Spilled {
+                    path,
+                    len: input.len(),
+                }
+            }
+        };
+
+        self.publish();
+        Ok((permit, input))
+    }
+
+    /// Bring a job's input into memory, waiting for budget if it was spilled
+    pub async fn load(&self, permit: &mut IngestPermit, input: JobInput) -> Result<Vec<u8>> {
+        let (path, len) = match input {
+            JobInput::Memory(data) => return Ok(data),
+            JobInput::Spilled { path, len } => (path, len),
+        };
+
+        // Inputs larger than the whole budget take all of it while they run
+        let kib = kib_for(len).min(self.memory_kib) as u32;
+        let memory = Arc::clone(&self.memory)
+            .acquire_many_owned(kib)
+            .await
+            .context("Ingest memory budget closed")?;
+        permit.memory = Some(memory);
+        self.publish();
+
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read spilled input {}", path.display()))
+    }
+}
+
+fn kib_for(bytes: usize) -> usize {
+    bytes.div_ceil(KIB).max(1)
+}
+
+/// File name for a spilled input, keeping job IDs from escaping the spill dir
+fn spill_name(job_id: &str) -> String {
+    let safe: String = job_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-{}.input", safe, std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(policy: ShedPolicy, capacity: usize, budget: usize) -> Arc<IngestQueue> {
+        IngestQueue::new(
+            IngestConfig {
+                queue_capacity: capacity,
+                memory_budget: budget,
+                policy,
+                spill_dir: std::env::temp_dir().join("pangea-ingest-test"),
+            },
+            Metrics::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_credits_are_returned_on_drop() {
+        let queue = queue(ShedPolicy::Reject, 2, 1024 * KIB);
+        let (a, _) = queue.admit("a", vec![0; 10]).await.unwrap();
+        let (_b, _) = queue.admit("b", vec![0; 10]).await.unwrap();
+        assert_eq!(queue.credits(), 0);
+
+        let err = queue.admit("c", vec![0; 10]).await.err().unwrap();
+        assert!(err.to_string().starts_with(NO_CREDITS));
+
+        drop(a);
+        assert_eq!(queue.credits(), 1);
+        assert!(queue.admit("c", vec![0; 10]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reject_when_memory_is_full() {
+        let queue = queue(ShedPolicy::Reject, 8, 4 * KIB);
+        let (_a, _) = queue.admit("a", vec![0; 3 * KIB]).await.unwrap();
+        assert_eq!(queue.memory_in_use(), 3 * KIB);
+
+        let err = queue.admit("b", vec![0; 2 * KIB]).await.err().unwrap();
+        assert!(err.to_string().starts_with(NO_CREDITS));
+        // The rejected job's credit is handed back
+        assert_eq!(queue.credits(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_spill_and_load() {
+        let queue = queue(ShedPolicy::Spill, 8, 4 * KIB);
+        let (first, _) = queue.admit("first", vec![1; 3 * KIB]).await.unwrap();
+
+        let (mut permit, input) = queue.admit("second", vec![2; 2 * KIB]).await.unwrap();
+        let JobInput::Spilled { path, .. } = &input else {
+            panic!("expected input to be spilled");
+        };
+        let path = path.clone();
+        assert!(path.exists());
+
+        drop(first);
+        let data = queue.load(&mut permit, input).await.unwrap();
+        assert_eq!(data, vec![2; 2 * KIB]);
+        assert_eq!(queue.memory_in_use(), 2 * KIB);
+
+        drop(permit);
+        assert!(!path.exists());
+        assert_eq!(queue.memory_in_use(), 0);
+    }
+}
@@ -1,16 +1,33 @@
 mod data_processing;
+mod ingest;
 mod metrics;
+mod operators;
+mod rpc;
+// Shared with the main node: generated from rust/schema.capnp
+#[allow(warnings, clippy::all)]
+#[path = "../../../rust/src/schema_capnp.rs"]
+mod schema_capnp;
 
 use anyhow::Result;
 use axum::{routing::get, Router};
 use clap::Parser;
 use log::info;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 
 use data_processing::Preprocessor;
+use ingest::{IngestConfig, IngestQueue, ShedPolicy};
 use metrics::Metrics;
+use rpc::{ComputeNodeService, JobTable};
+use schema_capnp::node_service;
+
+/// How long open connections get to finish in-flight calls on shutdown
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
 
 #[derive(Parser, Debug)]
 #[command(name = "Pangea Rust Compute Core")]
@@ -36,6 +53,26 @@ struct Args {
     #[arg(short, long, default_value = "32")]
     chunk_size: usize,
 
+    /// Jobs that may be queued or running at once; further submissions are shed
+    #[arg(long, default_value = "64")]
+    queue_capacity: usize,
+
+    /// Jobs executed at the same time; the rest wait in the queue
+    #[arg(long, default_value = "1")]
+    concurrent_jobs: usize,
+
+    /// Memory budget for queued job inputs in megabytes
+    #[arg(long, default_value = "1024")]
+    memory_budget_mb: usize,
+
+    /// What to do with inputs that do not fit in the memory budget
+    #[arg(long, value_enum, default_value = "reject")]
+    shed_policy: ShedPolicy,
+
+    /// Directory for inputs spilled under the spill policy
+    #[arg(long)]
+    spill_dir: Option<PathBuf>,
+
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
@@ -57,6 +94,10 @@ async fn main() -> Result<()> {
     info!("   Orchestrator: {}", args.orchestrator);
     info!("   Workers: {}", args.workers);
     info!("   Chunk size: {}", args.chunk_size);
+    info!(
+        "   Ingest: {} job(s), {} MB, {:?} policy",
+        args.queue_capacity, args.memory_budget_mb, args.shed_policy
+    );
 
     // Initialize metrics
     let metrics = Metrics::new();
@@ -69,38 +110,97 @@ async fn main() -> Result<()> {
     });
 
     // Create preprocessor
-    let preprocessor = Preprocessor::new(args.workers, args.chunk_size);
+    let preprocessor = Arc::new(Preprocessor::new(args.workers, args.chunk_size));
     info!("✅ Data preprocessor initialized");
 
+    let ingest = IngestQueue::new(
+        IngestConfig {
+            queue_capacity: args.queue_capacity,
+            memory_budget: args.memory_budget_mb * 1024 * 1024,
+            policy: args.shed_policy,
+            spill_dir: args
+                .spill_dir
+                .clone()
+                .unwrap_or_else(|| std::env::temp_dir().join("pangea-compute-spill")),
+        },
+        Arc::clone(&metrics),
+    );
+    let jobs = JobTable::new(
+        preprocessor,
+        Arc::clone(&metrics),
+        ingest,
+        args.concurrent_jobs,
+    );
+    let service: node_service::Client =
+        capnp_rpc::new_client(ComputeNodeService::new(jobs, args.workers));
+
     // Setup TCP listener
     let addr: SocketAddr = args.listen.parse()?;
     let listener = TcpListener::bind(&addr).await?;
     info!("📡 RPC server listening on {}", addr);
 
-    // Main server loop
+    // Cap'n Proto RPC systems are not Send, so connections run on a LocalSet
+    let local = tokio::task::LocalSet::new();
+    local.run_until(serve(listener, service, metrics)).await
+}
+
+/// Accept orchestrator connections until Ctrl-C, then drain them
+async fn serve(
+    listener: TcpListener,
+    service: node_service::Client,
+    metrics: Arc<Metrics>,
+) -> Result<()> {
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
     loop {
-        let (socket, peer_addr) = listener.accept().await?;
-        info!("✅ New connection from {}", peer_addr);
-        
-        metrics.requests_total.inc();
-        
-        // In production, handle the connection with Cap'n Proto RPC
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(socket).await {
-                log::error!("Connection error: {}", e);
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (socket, peer_addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        log::error!("Failed to accept RPC connection: {}", e);
+                        continue;
+                    }
+                };
+                info!("✅ New connection from {}", peer_addr);
+
+                let client = service.clone();
+                let shutdown = shutdown_rx.clone();
+                let metrics = Arc::clone(&metrics);
+                connections.spawn_local(async move {
+                    if let Err(e) = rpc::serve_connection(socket, client, shutdown).await {
+                        metrics.errors_total.inc();
+                        log::error!("Connection error: {}", e);
+                    }
+                    info!("👋 Connection from {} closed", peer_addr);
+                });
+            }
+            // Reap finished connections so the set does not grow unbounded
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = &mut ctrl_c => {
+                info!("🛑 Shutdown requested, closing {} connection(s)", connections.len());
+                break;
             }
-        });
+        }
     }
-}
 
-/// Handle incoming RPC connections
-async fn handle_connection(mut socket: tokio::net::TcpStream) -> Result<()> {
-    use tokio::io::AsyncReadExt;
-    
-    let mut buffer = vec![0; 1024];
-    let n = socket.read(&mut buffer).await?;
-    info!("📨 Received {} bytes", n);
-    
+    drop(listener);
+    let _ = shutdown_tx.send(true);
+    let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        log::warn!(
+            "Dropping {} connection(s) still open after {:?}",
+            connections.len(),
+            SHUTDOWN_GRACE
+        );
+    }
+    info!("👋 Rust compute core stopped");
     Ok(())
 }
 
@@ -1,5 +1,6 @@
 use prometheus::{
-    Counter, Encoder, Gauge, Histogram, HistogramOpts, IntCounter, Opts, Registry, TextEncoder,
+    Counter, Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntGauge, Opts, Registry,
+    TextEncoder,
 };
 use std::sync::Arc;
 
@@ -10,6 +11,10 @@ pub struct Metrics {
     pub active_tasks: Gauge,
     pub data_bytes_processed: Counter,
     pub errors_total: IntCounter,
+    pub ingest_credits: IntGauge,
+    pub ingest_memory_bytes: IntGauge,
+    pub ingest_rejected_total: IntCounter,
+    pub ingest_spilled_total: IntCounter,
 }
 
 impl Metrics {
@@ -54,6 +59,34 @@ impl Metrics {
         )
         .unwrap();
 
+        let ingest_credits = IntGauge::with_opts(
+            Opts::new("ingest_credits", "Jobs the ingestion queue can still admit")
+                .namespace("pangea")
+                .subsystem("rust_compute"),
+        )
+        .unwrap();
+
+        let ingest_memory_bytes = IntGauge::with_opts(
+            Opts::new("ingest_memory_bytes", "Bytes of job input held in memory")
+                .namespace("pangea")
+                .subsystem("rust_compute"),
+        )
+        .unwrap();
+
+        let ingest_rejected_total = IntCounter::with_opts(
+            Opts::new("ingest_rejected_total", "Total number of jobs rejected")
+                .namespace("pangea")
+                .subsystem("rust_compute"),
+        )
+        .unwrap();
+
+        let ingest_spilled_total = IntCounter::with_opts(
+            Opts::new("ingest_spilled_total", "Total number of inputs spilled")
+                .namespace("pangea")
+                .subsystem("rust_compute"),
+        )
+        .unwrap();
+
         registry.register(Box::new(requests_total.clone())).unwrap();
         registry
             .register(Box::new(processing_duration.clone()))
@@ -63,6 +96,16 @@ impl Metrics {
             .register(Box::new(data_bytes_processed.clone()))
             .unwrap();
         registry.register(Box::new(errors_total.clone())).unwrap();
+        registry.register(Box::new(ingest_credits.clone())).unwrap();
+        registry
+            .register(Box::new(ingest_memory_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(ingest_rejected_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(ingest_spilled_total.clone()))
+            .unwrap();
 
         Arc::new(Self {
             registry,
@@ -71,6 +114,10 @@ impl Metrics {
             active_tasks,
             data_bytes_processed,
             errors_total,
+            ingest_credits,
+            ingest_memory_bytes,
+            ingest_rejected_total,
+            ingest_spilled_total,
         })
     }
 
@@ -0,0 +1,605 @@
+use anyhow::{anyhow, bail, Context, Result};
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Node, Value as ExprValue};
+use log::info;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+/// A record flowing through a pipeline: field name -> value
+pub type Record = Map<String, Value>;
+
+/// Input format of the data submitted with a pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum SourceSpec {
+    /// Delimited text; column names come from the header row or are `col0..`
+    Csv {
+        #[serde(default = "default_delimiter")]
+        delimiter: char,
+        #[serde(default = "default_true")]
+        has_header: bool,
+    },
+    /// Parquet file bytes
+    Parquet,
+    /// One JSON object per line
+    JsonLines,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How `normalize` rescales a numeric field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalizeMethod {
+    /// Rescale to 0.0 - 1.0
+    MinMax,
+    /// Subtract the mean and divide by the standard deviation
+    ZScore,
+}
+
+/// One step of a preprocessing pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum OperatorSpec {
+    /// Rescale numeric fields using statistics over the whole dataset
+    Normalize {
+        fields: Vec<String>,
+        method: NormalizeMethod,
+    },
+    /// Split a text field into a list of tokens
+    Tokenize {
+        field: String,
+        /// Field to write tokens to (defaults to `field`)
+        #[serde(default)]
+        output: Option<String>,
+        #[serde(default)]
+        lowercase: bool,
+        /// Split on any of these characters; whitespace if empty
+        #[serde(default)]
+        delimiters: String,
+    },
+    /// Keep records for which the expression is true
+    Filter { expr: String },
+    /// Set `field` to the expression's value
+    Map { field: String, expr: String },
+}
+
+/// A pipeline as submitted over RPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineSpec {
+    pub source: SourceSpec,
+    #[serde(default)]
+    pub operators: Vec<OperatorSpec>,
+}
+
+impl PipelineSpec {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Invalid pipeline spec")
+    }
+}
+
+/// Operator ready to run, with expressions parsed once
+enum Operator {
+    Normalize {
+        fields: Vec<String>,
+        method: NormalizeMethod,
+    },
+    Tokenize {
+        field: String,
+        output: String,
+        lowercase: bool,
+        delimiters: Vec<char>,
+    },
+    Filter {
+        expr: Node,
+    },
+    Map {
+        field: String,
+        expr: Node,
+    },
+}
+
+impl Operator {
+    fn compile(spec: &OperatorSpec) -> Result<Self> {
+        Ok(match spec {
+            OperatorSpec::Normalize { fields, method } => Operator::Normalize {
+                fields: fields.clone(),
+                method: *method,
+            },
+            OperatorSpec::Tokenize {
+                field,
+                output,
+                lowercase,
+                delimiters,
+            } => Operator::Tokenize {
+                field: field.clone(),
+                output: output.clone().unwrap_or_else(|| field.clone()),
+                lowercase: *lowercase,
+                delimiters: delimiters.chars().collect(),
+            },
+            OperatorSpec::Filter { expr } => Operator::Filter {
+                expr: parse_expr(expr)?,
+            },
+            OperatorSpec::Map { field, expr } => Operator::Map {
+                field: field.clone(),
+                expr: parse_expr(expr)?,
+            },
+        })
+    }
+}
+
+fn parse_expr(expr: &str) -> Result<Node> {
+    evalexpr::build_operator_tree(expr).map_err(|e| anyhow!("Invalid expression {:?}: {}", expr, e))
+}
+
+/// A validated pipeline
+pub struct Pipeline {
+    source: SourceSpec,
+    operators: Vec<Operator>,
+}
+
+impl Pipeline {
+    /// Parse expressions and check the spec before any data is read
+    pub fn compile(spec: &PipelineSpec) -> Result<Self> {
+        let operators = spec
+            .operators
+            .iter()
+            .map(Operator::compile)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            source: spec.source.clone(),
+            operators,
+        })
+    }
+
+    /// Parse the input according to the pipeline's source format
+    pub fn parse_input(&self, input: &[u8]) -> Result<Vec<Record>> {
+        match &self.source {
+            SourceSpec::Csv {
+                delimiter,
+                has_header,
+            } => parse_csv(input, *delimiter, *has_header),
+            SourceSpec::Parquet => parse_parquet(input),
+            SourceSpec::JsonLines => parse_json_lines(input),
+        }
+    }
+
+    /// Run every operator over `records`, `chunk_size` records per task
+    ///
+    /// Call inside a Rayon pool to bound the worker count.
+    pub fn execute(&self, mut records: Vec<Record>, chunk_size: usize) -> Result<Vec<Record>> {
+        let chunk_size = chunk_size.max(1);
+        for operator in &self.operators {
+            records = match operator {
+                Operator::Normalize { fields, method } => {
+                    normalize(&mut records, fields, *method, chunk_size)?;
+                    records
+                }
+                Operator::Tokenize {
+                    field,
+                    output,
+                    lowercase,
+                    delimiters,
+                } => {
+                    records.par_chunks_mut(chunk_size).for_each(|chunk| {
+                        for record in chunk {
+                            tokenize(record, field, output, *lowercase, delimiters);
+                        }
+                    });
+                    records
+                }
+                Operator::Filter { expr } => {
+                    let keep = records
+                        .par_chunks(chunk_size)
+                        .map(|chunk| {
+                            chunk
+                                .iter()
+                                .map(|record| match eval(expr, record)? {
+                                    ExprValue::Boolean(keep) => Ok(keep),
+                                    other => bail!("Filter expression returned {:?}", other),
+                                })
+                                .collect::<Result<Vec<_>>>()
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    records
+                        .into_iter()
+                        .zip(keep.into_iter().flatten())
+                        .filter_map(|(record, keep)| keep.then_some(record))
+                        .collect()
+                }
+                Operator::Map { field, expr } => {
+                    records.par_chunks_mut(chunk_size).try_for_each(|chunk| {
+                        chunk.iter_mut().try_for_each(|record| {
+                            let value = eval(expr, record)?;
+                            record.insert(field.clone(), to_json(value));
+                            Ok::<_, anyhow::Error>(())
+                        })
+                    })?;
+                    records
+                }
+            };
+        }
+        Ok(records)
+    }
+}
+
+/// Evaluate an expression with the record's fields as variables
+fn eval(expr: &Node, record: &Record) -> Result<ExprValue> {
+    let mut context = HashMapContext::new();
+    for (name, value) in record {
+        if let Some(value) = to_expr(value) {
+            context
+                .set_value(name.clone(), value)
+                .map_err(|e| anyhow!("Field {:?}: {}", name, e))?;
+        }
+    }
+    expr.eval_with_context(&context)
+        .map_err(|e| anyhow!("Expression failed: {}", e))
+}
+
+fn to_expr(value: &Value) -> Option<ExprValue> {
+    Some(match value {
+        Value::Null => ExprValue::Empty,
+        Value::Bool(b) => ExprValue::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => ExprValue::Int(i),
+            None => ExprValue::Float(n.as_f64()?),
+        },
+        Value::String(s) => ExprValue::String(s.clone()),
+        Value::Array(items) => ExprValue::Tuple(items.iter().filter_map(to_expr).collect()),
+        Value::Object(_) => return None,
+    })
+}
+
+fn to_json(value: ExprValue) -> Value {
+    match value {
+        ExprValue::String(s) => Value::String(s),
+        ExprValue::Float(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ExprValue::Int(i) => Value::Number(i.into()),
+        ExprValue::Boolean(b) => Value::Bool(b),
+        ExprValue::Tuple(items) => Value::Array(items.into_iter().map(to_json).collect()),
+        ExprValue::Empty => Value::Null,
+    }
+}
+
+fn tokenize(record: &mut Record, field: &str, output: &str, lowercase: bool, delimiters: &[char]) {
+    let Some(Value::String(text)) = record.get(field) else {
+        return;
+    };
+    let text = if lowercase {
+        text.to_lowercase()
+    } else {
+        text.clone()
+    };
+    let tokens: Vec<Value> = if delimiters.is_empty() {
+        text.split_whitespace()
+            .map(|t| Value::String(t.to_string()))
+            .collect()
+    } else {
+        text.split(|c| delimiters.contains(&c))
+            .filter(|t| !t.is_empty())
+            .map(|t| Value::String(t.to_string()))
+            .collect()
+    };
+    record.insert(output.to_string(), Value::Array(tokens));
+}
+
+/// Rescale numeric fields in place; non-numeric values are left alone
+fn normalize(
+    records: &mut [Record],
+    fields: &[String],
+    method: NormalizeMethod,
+    chunk_size: usize,
+) -> Result<()> {
+    for field in fields {
+        let values: Vec<f64> = records
+            .par_iter()
+            .filter_map(|record| record.get(field).and_then(Value::as_f64))
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+
+        let (offset, scale) = match method {
+            NormalizeMethod::MinMax => {
+                let min = values
+                    .par_iter()
+                    .copied()
+                    .reduce(|| f64::INFINITY, f64::min);
+                let max = values
+                    .par_iter()
+                    .copied()
+                    .reduce(|| f64::NEG_INFINITY, f64::max);
+                (min, max - min)
+            }
+            NormalizeMethod::ZScore => {
+                let n = values.len() as f64;
+                let mean = values.par_iter().sum::<f64>() / n;
+                let variance = values.par_iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+                (mean, variance.sqrt())
+            }
+        };
+
+        records.par_chunks_mut(chunk_size).for_each(|chunk| {
+            for record in chunk {
+                if let Some(value) = record.get(field).and_then(Value::as_f64) {
+                    // Constant columns map to 0.0
+                    let scaled = if scale > 0.0 {
+                        (value - offset) / scale
+                    } else {
+                        0.0
+                    };
+                    if let Some(number) = Number::from_f64(scaled) {
+                        record.insert(field.clone(), Value::Number(number));
+                    }
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Numbers and booleans in CSV cells become typed values
+fn parse_cell(cell: &str) -> Value {
+    if let Ok(i) = cell.parse::<i64>() {
+        return Value::Number(i.into());
+    }
+    if let Ok(f) = cell.parse::<f64>() {
+        if let Some(n) = Number::from_f64(f) {
+            return Value::Number(n);
+        }
+    }
+    match cell {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "" => Value::Null,
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+fn parse_csv(input: &[u8], delimiter: char, has_header: bool) -> Result<Vec<Record>> {
+    if !delimiter.is_ascii() {
+        bail!("CSV delimiter must be ASCII");
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .has_headers(has_header)
+        .flexible(true)
+        .from_reader(input);
+
+    let headers: Vec<String> = if has_header {
+        reader
+            .headers()
+            .context("Failed to read CSV header")?
+            .iter()
+            .map(str::to_string)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row.context("Invalid CSV row")?;
+        let record = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let name = headers
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| format!("col{}", i));
+                (name, parse_cell(cell))
+            })
+            .collect();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn parse_parquet(input: &[u8]) -> Result<Vec<Record>> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let reader = SerializedFileReader::new(bytes::Bytes::copy_from_slice(input))
+        .context("Invalid Parquet file")?;
+    let mut records = Vec::new();
+    for row in reader.get_row_iter(None)? {
+        match row?.to_json_value() {
+            Value::Object(record) => records.push(record),
+            other => bail!("Unexpected Parquet row {:?}", other),
+        }
+    }
+    Ok(records)
+}
+
+fn parse_json_lines(input: &[u8]) -> Result<Vec<Record>> {
+    let text = std::str::from_utf8(input).context("JSON lines input is not UTF-8")?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| match serde_json::from_str(line) {
+            Ok(Value::Object(record)) => Ok(record),
+            Ok(_) => bail!("Line {} is not a JSON object", i + 1),
+            Err(e) => Err(anyhow!("Line {}: {}", i + 1, e)),
+        })
+        .collect()
+}
+
+/// Output of a pipeline run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineResult {
+    pub records: Vec<Record>,
+    pub input_records: usize,
+    pub output_records: usize,
+    pub processing_time_ms: u128,
+}
+
+/// Parse the input and run the pipeline on a pool of `num_workers` threads
+pub fn run_pipeline(
+    spec: &PipelineSpec,
+    input: &[u8],
+    num_workers: usize,
+    chunk_size: usize,
+) -> Result<PipelineResult> {
+    let start = std::time::Instant::now();
+    let pipeline = Pipeline::compile(spec)?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_workers.max(1))
+        .build()
+        .context("Failed to build worker pool")?;
+
+    let records = pipeline.parse_input(input)?;
+    let input_records = records.len();
+    info!(
+        "🔄 Running {} operator(s) over {} record(s) on {} worker(s)",
+        spec.operators.len(),
+        input_records,
+        num_workers
+    );
+    let records = pool.install(|| pipeline.execute(records, chunk_size))?;
+
+    let processing_time_ms = start.elapsed().as_millis();
+    info!(
+        "✅ Pipeline complete in {}ms: {} -> {} record(s)",
+        processing_time_ms,
+        input_records,
+        records.len()
+    );
+    Ok(PipelineResult {
+        output_records: records.len(),
+        records,
+        input_records,
+        processing_time_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(json: &str) -> PipelineSpec {
+        PipelineSpec::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_csv_filter_map_normalize() {
+        let spec = spec(
+            r#"{
+                "source": {"format": "csv"},
+                "operators": [
+                    {"op": "filter", "expr": "age >= 18"},
+                    {"op": "map", "field": "score2", "expr": "score * 2"},
+                    {"op": "normalize", "fields": ["age"], "method": "min_max"}
+                ]
+            }"#,
+        );
+        let csv = b"name,age,score\nann,30,1.5\nbob,12,2\ncid,18,3\nday,42,4\n";
+
+        let result = run_pipeline(&spec, csv, 2, 1).unwrap();
+        assert_eq!(result.input_records, 4);
+        assert_eq!(result.output_records, 3);
+
+        let names: Vec<_> = result.records.iter().map(|r| r["name"].clone()).collect();
+        assert_eq!(names, vec!["ann", "cid", "day"]);
+        assert_eq!(result.records[0]["score2"], 3.0);
+        assert_eq!(result.records[1]["age"], 0.0);
+        assert_eq!(result.records[2]["age"], 1.0);
+    }
+
+    #[test]
+    fn test_tokenize_and_zscore() {
+        let spec = spec(
+            r#"{
+                "source": {"format": "json_lines"},
+                "operators": [
+                    {"op": "tokenize", "field": "text", "output": "tokens", "lowercase": true},
+                    {"op": "normalize", "fields": ["x"], "method": "z_score"}
+                ]
+            }"#,
+        );
+        let input = b"{\"text\": \"Hello World\", \"x\": 1}\n{\"text\": \"a b  c\", \"x\": 3}\n";
+
+        let result = run_pipeline(&spec, input, 1, 8).unwrap();
+        assert_eq!(
+            result.records[0]["tokens"],
+            serde_json::json!(["hello", "world"])
+        );
+        assert_eq!(result.records[1]["tokens"].as_array().unwrap().len(), 3);
+        assert_eq!(result.records[0]["x"], -1.0);
+        assert_eq!(result.records[1]["x"], 1.0);
+    }
+
+    #[test]
+    fn test_parquet_source() {
+        use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+        use std::sync::Arc;
+
+        let schema = Arc::new(
+            parse_message_type(
+                "message rows { REQUIRED INT64 id; REQUIRED BYTE_ARRAY label (UTF8); }",
+            )
+            .unwrap(),
+        );
+        let mut buffer = Vec::new();
+        let mut writer = SerializedFileWriter::new(
+            &mut buffer,
+            schema,
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+        let mut row_group = writer.next_row_group().unwrap();
+        let mut ids = row_group.next_column().unwrap().unwrap();
+        ids.typed::<Int64Type>()
+            .write_batch(&[1, 2, 3], None, None)
+            .unwrap();
+        ids.close().unwrap();
+        let mut labels = row_group.next_column().unwrap().unwrap();
+        labels
+            .typed::<ByteArrayType>()
+            .write_batch(
+                &[
+                    ByteArray::from("a"),
+                    ByteArray::from("b"),
+                    ByteArray::from("c"),
+                ],
+                None,
+                None,
+            )
+            .unwrap();
+        labels.close().unwrap();
+        row_group.close().unwrap();
+        writer.close().unwrap();
+
+        let spec = spec(
+            r#"{"source": {"format": "parquet"},
+                "operators": [{"op": "filter", "expr": "id != 2"}]}"#,
+        );
+        let result = run_pipeline(&spec, &buffer, 2, 2).unwrap();
+        assert_eq!(result.output_records, 2);
+        assert_eq!(result.records[1]["label"], "c");
+    }
+
+    #[test]
+    fn test_invalid_specs_rejected() {
+        assert!(PipelineSpec::from_json(r#"{"source": {"format": "xml"}}"#).is_err());
+
+        let bad_expr = spec(
+            r#"{"source": {"format": "csv"}, "operators": [{"op": "filter", "expr": "(age > 1"}]}"#,
+        );
+        assert!(Pipeline::compile(&bad_expr).is_err());
+
+        let non_bool = spec(
+            r#"{"source": {"format": "csv"}, "operators": [{"op": "filter", "expr": "age + 1"}]}"#,
+        );
+        assert!(run_pipeline(&non_bool, b"age\n1\n", 1, 1).is_err());
+    }
+}
@@ -0,0 +1,670 @@
+use anyhow::{bail, Result};
+use capnp::capability::Rc;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::{DiskExt, System, SystemExt};
+use tokio::net::TcpStream;
+use tokio::sync::{watch, Semaphore};
+
+use crate::data_processing::Preprocessor;
+use crate::ingest::{IngestPermit, IngestQueue, JobInput, NO_CREDITS};
+use crate::metrics::Metrics;
+use crate::operators::PipelineSpec;
+use crate::schema_capnp::node_service;
+
+/// Largest Cap'n Proto message accepted from the orchestrator
+pub const MAX_MESSAGE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Finished jobs kept around for status and result queries
+const MAX_FINISHED_JOBS: usize = 1024;
+
+/// Lifecycle of a submitted job, named as the Go compute manager names them
+#[derive(Debug, Clone)]
+pub enum JobState {
+    Pending,
+    Computing,
+    /// Holds the `PipelineResult` serialized as JSON
+    Completed(Arc<Vec<u8>>),
+    Failed(String),
+    Timeout,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Computing => "computing",
+            JobState::Completed(_) => "completed",
+            JobState::Failed(_) => "failed",
+            JobState::Timeout => "timeout",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Pending | JobState::Computing)
+    }
+}
+
+/// A job as submitted in a `ComputeJobManifest`
+///
+/// This service runs preprocessing pipelines rather than WASM, so the
+/// manifest's `wasmModule` carries the JSON pipeline spec.
+#[derive(Debug, Clone)]
+pub struct JobRequest {
+    /// Assigned by the service when empty
+    pub job_id: String,
+    pub spec: PipelineSpec,
+    pub input: Vec<u8>,
+    /// No limit when zero
+    pub timeout_secs: u32,
+}
+
+struct Job {
+    seq: u64,
+    state: watch::Sender<JobState>,
+}
+
+/// Jobs submitted to this node, run on the preprocessor's worker pool
+///
+/// Submissions pass through the ingestion queue first, so a full queue or
+/// memory budget sheds them before they are recorded.
+pub struct JobTable {
+    preprocessor: Arc<Preprocessor>,
+    metrics: Arc<Metrics>,
+    ingest: Arc<IngestQueue>,
+    /// Admitted jobs wait here for one of the execution slots
+    running: Arc<Semaphore>,
+    jobs: Mutex<HashMap<String, Job>>,
+    next_seq: AtomicU64,
+}
+
+impl JobTable {
+    pub fn new(
+        preprocessor: Arc<Preprocessor>,
+        metrics: Arc<Metrics>,
+        ingest: Arc<IngestQueue>,
+        concurrent_jobs: usize,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            preprocessor,
+            metrics,
+            ingest,
+            running: Arc::new(Semaphore::new(concurrent_jobs.max(1))),
+            jobs: Mutex::new(HashMap::new()),
+            next_seq: AtomicU64::new(1),
+        })
+    }
+
+    pub fn ingest(&self) -> &IngestQueue {
+        &self.ingest
+    }
+
+    /// Admit a job and start it in the background, returning its ID
+    pub async fn submit(self: &Arc<Self>, request: JobRequest) -> Result<String> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let job_id = if request.job_id.is_empty() {
+            format!("job-{}", seq)
+        } else {
+            request.job_id.clone()
+        };
+
+        // Reserve the ID before admission so concurrent duplicates are refused
+        let (state, _) = watch::channel(JobState::Pending);
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.contains_key(&job_id) {
+                bail!("Job {} already exists", job_id);
+            }
+            prune_finished(&mut jobs);
+            jobs.insert(job_id.clone(), Job { seq, state });
+        }
+
+        let JobRequest {
+            spec,
+            input,
+            timeout_secs,
+            ..
+        } = request;
+        let (permit, input) = match self.ingest.admit(&job_id, input).await {
+            Ok(admitted) => admitted,
+            Err(e) => {
+                self.jobs.lock().unwrap().remove(&job_id);
+                return Err(e);
+            }
+        };
+
+        let table = Arc::clone(self);
+        let id = job_id.clone();
+        tokio::spawn(async move { table.run(&id, spec, permit, input, timeout_secs).await });
+
+        info!(
+            "📥 Accepted job {} ({} credit(s) left)",
+            job_id,
+            self.ingest.credits()
+        );
+        Ok(job_id)
+    }
+
+    async fn run(
+        &self,
+        job_id: &str,
+        spec: PipelineSpec,
+        mut permit: IngestPermit,
+        input: JobInput,
+        timeout_secs: u32,
+    ) {
+        let Some(mut state) = self.subscribe(job_id) else {
+            return;
+        };
+        // Give the credit back straight away if the job is cancelled while queued
+        let _slot = tokio::select! {
+            slot = Arc::clone(&self.running).acquire_owned() => match slot {
+                Ok(slot) => slot,
+                Err(_) => return,
+            },
+            _ = state.wait_for(JobState::is_finished) => return,
+        };
+        let input = match self.ingest.load(&mut permit, input).await {
+            Ok(input) => input,
+            Err(e) => {
+                self.metrics.errors_total.inc();
+                warn!("Job {} lost its input: {:#}", job_id, e);
+                self.set_state(job_id, JobState::Failed(format!("{:#}", e)));
+                return;
+            }
+        };
+
+        self.set_state(job_id, JobState::Computing);
+        self.metrics.active_tasks.inc();
+        let timer = self.metrics.processing_duration.start_timer();
+
+        let preprocessor = Arc::clone(&self.preprocessor);
+        let input_len = input.len();
+        let work = tokio::task::spawn_blocking(move || {
+            // The input stays charged to the budget until the work returns,
+            // even if the job has timed out by then
+            let _permit = permit;
+            let result = preprocessor.run_pipeline(&spec, &input)?;
+            Ok::<_, anyhow::Error>(serde_json::to_vec(&result)?)
+        });
+        let outcome = if timeout_secs > 0 {
+            tokio::time::timeout(Duration::from_secs(timeout_secs.into()), work).await
+        } else {
+            Ok(work.await)
+        };
+
+        timer.observe_duration();
+        self.metrics.active_tasks.dec();
+
+        let state = match outcome {
+            Ok(Ok(Ok(result))) => {
+                self.metrics.data_bytes_processed.inc_by(input_len as f64);
+                info!("✅ Job {} completed", job_id);
+                JobState::Completed(Arc::new(result))
+            }
+            Ok(Ok(Err(e))) => {
+                self.metrics.errors_total.inc();
+                warn!("Job {} failed: {:#}", job_id, e);
+                JobState::Failed(format!("{:#}", e))
+            }
+            Ok(Err(e)) => {
+                self.metrics.errors_total.inc();
+                warn!("Job {} worker panicked: {}", job_id, e);
+                JobState::Failed(e.to_string())
+            }
+            Err(_) => {
+                self.metrics.errors_total.inc();
+                warn!("Job {} timed out after {}s", job_id, timeout_secs);
+                JobState::Timeout
+            }
+        };
+        self.set_state(job_id, state);
+    }
+
+    fn subscribe(&self, job_id: &str) -> Option<watch::Receiver<JobState>> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(job_id).map(|job| job.state.subscribe())
+    }
+
+    /// Move a job to `state` unless it already finished (e.g. was cancelled)
+    fn set_state(&self, job_id: &str, state: JobState) {
+        if let Some(job) = self.jobs.lock().unwrap().get(job_id) {
+            job.state.send_if_modified(|current| {
+                if current.is_finished() {
+                    return false;
+                }
+                *current = state;
+                true
+            });
+        }
+    }
+
+    /// Current state of a job, if it is known
+    pub fn state(&self, job_id: &str) -> Option<JobState> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(job_id).map(|job| job.state.borrow().clone())
+    }
+
+    /// Wait up to `timeout` for a job to finish and return its final state
+    ///
+    /// Returns the state at the deadline if the job is still running, and
+    /// `None` for unknown jobs.
+    pub async fn wait(&self, job_id: &str, timeout: Duration) -> Option<JobState> {
+        let mut receiver = self.subscribe(job_id)?;
+        // The sender lives in the table, so the receiver only errors if the
+        // job was pruned while we waited
+        let _ = tokio::time::timeout(timeout, receiver.wait_for(JobState::is_finished)).await;
+        let state = receiver.borrow().clone();
+        Some(state)
+    }
+
+    /// Cancel a job that has not finished yet
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get(job_id) else {
+            return false;
+        };
+        let cancelled = job.state.send_if_modified(|current| {
+            if current.is_finished() {
+                return false;
+            }
+            *current = JobState::Cancelled;
+            true
+        });
+        if cancelled {
+            // Pipeline work cannot be interrupted once started; its result is
+            // discarded when it returns
+            info!("🛑 Cancelled job {}", job_id);
+        }
+        cancelled
+    }
+
+    /// Jobs that are pending or computing
+    pub fn running(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .filter(|job| !job.state.borrow().is_finished())
+            .count()
+    }
+}
+
+/// Drop the oldest finished jobs once more than `MAX_FINISHED_JOBS` are kept
+fn prune_finished(jobs: &mut HashMap<String, Job>) {
+    let mut finished: Vec<(u64, String)> = jobs
+        .iter()
+        .filter(|(_, job)| job.state.borrow().is_finished())
+        .map(|(id, job)| (job.seq, id.clone()))
+        .collect();
+    if finished.len() < MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort_unstable();
+    for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+        jobs.remove(id);
+    }
+}
+
+/// `NodeService` implementation exposing the compute methods of the shared schema
+///
+/// Every other method answers with the generated "unimplemented" error.
+pub struct ComputeNodeService {
+    jobs: Arc<JobTable>,
+    num_workers: usize,
+    system: Mutex<System>,
+}
+
+impl ComputeNodeService {
+    pub fn new(jobs: Arc<JobTable>, num_workers: usize) -> Self {
+        Self {
+            jobs,
+            num_workers: num_workers.max(1),
+            system: Mutex::new(System::new()),
+        }
+    }
+
+    fn fill_status(
+        mut status: crate::schema_capnp::compute_job_status::Builder<'_>,
+        job_id: &str,
+        state: &JobState,
+    ) {
+        let done = matches!(state, JobState::Completed(_));
+        status.set_job_id(job_id);
+        status.set_status(state.as_str());
+        status.set_progress(if done { 1.0 } else { 0.0 });
+        status.set_completed_chunks(done as u32);
+        status.set_total_chunks(1);
+        status.set_estimated_time_remaining(0);
+        if let JobState::Failed(error) = state {
+            status.set_error_msg(error.as_str());
+        }
+    }
+}
+
+impl node_service::Server for ComputeNodeService {
+    async fn submit_compute_job(
+        self: Rc<Self>,
+        params: node_service::SubmitComputeJobParams,
+        mut results: node_service::SubmitComputeJobResults,
+    ) -> Result<(), capnp::Error> {
+        let manifest = params.get()?.get_manifest()?;
+        let job_id = manifest.get_job_id()?.to_str()?.to_string();
+        self.jobs.metrics.requests_total.inc();
+
+        let input = manifest.get_input_data()?.to_vec();
+        let request = std::str::from_utf8(manifest.get_wasm_module()?)
+            .map_err(anyhow::Error::from)
+            .and_then(PipelineSpec::from_json)
+            .map(|spec| JobRequest {
+                job_id,
+                spec,
+                input,
+                timeout_secs: manifest.get_timeout_secs(),
+            });
+        let submitted = match request {
+            Ok(request) => self.jobs.submit(request).await,
+            Err(e) => Err(e),
+        };
+
+        let mut results = results.get();
+        match submitted {
+            Ok(job_id) => {
+                results.set_job_id(job_id.as_str());
+                results.set_success(true);
+            }
+            Err(e) => {
+                let message = format!("{:#}", e);
+                // Shedding is counted by the ingest metrics, not as an error
+                if message.starts_with(NO_CREDITS) {
+                    info!("⏸️ Shed job submission: {}", message);
+                } else {
+                    self.jobs.metrics.errors_total.inc();
+                    warn!("Rejected job submission: {}", message);
+                }
+                results.set_success(false);
+                results.set_error_msg(message.as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_compute_job_status(
+        self: Rc<Self>,
+        params: node_service::GetComputeJobStatusParams,
+        mut results: node_service::GetComputeJobStatusResults,
+    ) -> Result<(), capnp::Error> {
+        let job_id = params.get()?.get_job_id()?.to_str()?.to_string();
+        let status = results.get().init_status();
+        match self.jobs.state(&job_id) {
+            Some(state) => Self::fill_status(status, &job_id, &state),
+            None => Self::fill_status(
+                status,
+                &job_id,
+                &JobState::Failed(format!("Unknown job {}", job_id)),
+            ),
+        }
+        Ok(())
+    }
+
+    async fn get_compute_job_result(
+        self: Rc<Self>,
+        params: node_service::GetComputeJobResultParams,
+        mut results: node_service::GetComputeJobResultResults,
+    ) -> Result<(), capnp::Error> {
+        let params = params.get()?;
+        let job_id = params.get_job_id()?.to_str()?.to_string();
+        let timeout = Duration::from_millis(params.get_timeout_ms().into());
+
+        let state = self.jobs.wait(&job_id, timeout).await;
+        let mut results = results.get();
+        match state {
+            Some(JobState::Completed(result)) => {
+                results.set_result(result.as_slice());
+                results.set_success(true);
+                results.set_worker_node(format!("rust-compute-{}", std::process::id()).as_str());
+            }
+            Some(JobState::Failed(error)) => {
+                results.set_success(false);
+                results.set_error_msg(error.as_str());
+            }
+            Some(state) => {
+                results.set_success(false);
+                results.set_error_msg(format!("Job {} is {}", job_id, state.as_str()).as_str());
+            }
+            None => {
+                results.set_success(false);
+                results.set_error_msg(format!("Unknown job {}", job_id).as_str());
+            }
+        }
+        Ok(())
+    }
+
+    async fn cancel_compute_job(
+        self: Rc<Self>,
+        params: node_service::CancelComputeJobParams,
+        mut results: node_service::CancelComputeJobResults,
+    ) -> Result<(), capnp::Error> {
+        let job_id = params.get()?.get_job_id()?.to_str()?.to_string();
+        results.get().set_success(self.jobs.cancel(&job_id));
+        Ok(())
+    }
+
+    async fn get_compute_capacity(
+        self: Rc<Self>,
+        _: node_service::GetComputeCapacityParams,
+        mut results: node_service::GetComputeCapacityResults,
+    ) -> Result<(), capnp::Error> {
+        let (ram_mb, disk_mb) = {
+            let mut sys = self.system.lock().unwrap();
+            sys.refresh_memory();
+            sys.refresh_disks_list();
+            let disk_bytes: u64 = sys.disks().iter().map(|d| d.available_space()).sum();
+            (
+                sys.available_memory() / (1024 * 1024),
+                disk_bytes / (1024 * 1024),
+            )
+        };
+        // Reaches 1.0 once the ingest queue has no credits left, telling the
+        // orchestrator to stop dispatching here until jobs finish
+        let load = (self.jobs.running() as f32 / self.num_workers as f32)
+            .max(self.jobs.ingest().pressure());
+
+        let mut capacity = results.get().init_capacity();
+        capacity.set_cpu_cores(self.num_workers as u32);
+        capacity.set_ram_mb(ram_mb);
+        capacity.set_current_load(load.min(1.0));
+        capacity.set_disk_mb(disk_mb);
+        // Not measured by this service
+        capacity.set_bandwidth_mbps(0.0);
+        Ok(())
+    }
+}
+
+/// Serve one orchestrator connection until it closes or `shutdown` fires
+///
+/// On shutdown the connection is disconnected gracefully: calls already in
+/// flight get their replies before the stream is closed.
+pub async fn serve_connection(
+    stream: TcpStream,
+    client: node_service::Client,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+    stream.set_nodelay(true)?;
+    let (reader, writer) = tokio::io::split(stream);
+
+    let mut options = capnp::message::ReaderOptions::new();
+    options.traversal_limit_in_words(Some(MAX_MESSAGE_BYTES / 8));
+    let network = twoparty::VatNetwork::new(
+        reader.compat(),
+        writer.compat_write(),
+        rpc_twoparty_capnp::Side::Server,
+        options,
+    );
+
+    let mut rpc_system = RpcSystem::new(Box::new(network), Some(client.client));
+    let disconnector = rpc_system.get_disconnector();
+
+    tokio::select! {
+        result = &mut rpc_system => result?,
+        _ = shutdown.wait_for(|stop| *stop) => {
+            info!("🔌 Closing RPC connection for shutdown");
+            disconnector.await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::{IngestConfig, ShedPolicy};
+
+    fn table_with_capacity(queue_capacity: usize) -> Arc<JobTable> {
+        let metrics = Metrics::new();
+        let ingest = IngestQueue::new(
+            IngestConfig {
+                queue_capacity,
+                memory_budget: 1024 * 1024,
+                policy: ShedPolicy::Reject,
+                spill_dir: std::env::temp_dir(),
+            },
+            Arc::clone(&metrics),
+        );
+        JobTable::new(Arc::new(Preprocessor::new(2, 4)), metrics, ingest, 1)
+    }
+
+    fn table() -> Arc<JobTable> {
+        table_with_capacity(16)
+    }
+
+    fn request(job_id: &str) -> JobRequest {
+        JobRequest {
+            job_id: job_id.to_string(),
+            spec: PipelineSpec::from_json(
+                r#"{"source": {"format": "csv"}, "operators": [{"op": "filter", "expr": "x > 1"}]}"#,
+            )
+            .unwrap(),
+            input: b"x\n1\n2\n3\n".to_vec(),
+            timeout_secs: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_completes() {
+        let jobs = table();
+        let id = jobs.submit(request("")).await.unwrap();
+
+        let state = jobs.wait(&id, Duration::from_secs(5)).await.unwrap();
+        let JobState::Completed(result) = state else {
+            panic!("unexpected state {:?}", state);
+        };
+        let result: serde_json::Value = serde_json::from_slice(&result).unwrap();
+        assert_eq!(result["output_records"], 2);
+        assert_eq!(jobs.running(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_and_unknown_jobs() {
+        let jobs = table();
+        jobs.submit(request("a")).await.unwrap();
+        assert!(jobs.submit(request("a")).await.is_err());
+        assert!(jobs.state("missing").is_none());
+        assert!(!jobs.cancel("missing"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_finished_job_is_noop() {
+        let jobs = table();
+        let id = jobs.submit(request("b")).await.unwrap();
+        jobs.wait(&id, Duration::from_secs(5)).await.unwrap();
+
+        assert!(!jobs.cancel(&id));
+        assert_eq!(jobs.state(&id).unwrap().as_str(), "completed");
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_until_a_job_finishes() {
+        let jobs = table_with_capacity(1);
+        let id = jobs.submit(request("first")).await.unwrap();
+
+        let err = jobs.submit(request("second")).await.err().unwrap();
+        assert!(err.to_string().starts_with(NO_CREDITS));
+        assert!(jobs.state("second").is_none());
+        assert_eq!(jobs.ingest().pressure(), 1.0);
+
+        jobs.wait(&id, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(jobs.ingest().credits(), 1);
+        assert!(jobs.submit(request("second")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_submit_and_fetch_result() {
+        use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+        use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+
+                let service: node_service::Client =
+                    capnp_rpc::new_client(ComputeNodeService::new(table(), 2));
+                let (reader, writer) = tokio::io::split(server_io);
+                let network = twoparty::VatNetwork::new(
+                    reader.compat(),
+                    writer.compat_write(),
+                    rpc_twoparty_capnp::Side::Server,
+                    Default::default(),
+                );
+                tokio::task::spawn_local(RpcSystem::new(Box::new(network), Some(service.client)));
+
+                let (reader, writer) = tokio::io::split(client_io);
+                let network = twoparty::VatNetwork::new(
+                    reader.compat(),
+                    writer.compat_write(),
+                    rpc_twoparty_capnp::Side::Client,
+                    Default::default(),
+                );
+                let mut rpc_system = RpcSystem::new(Box::new(network), None);
+                let client: node_service::Client =
+                    rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+                tokio::task::spawn_local(rpc_system);
+
+                let request = request("rpc-job");
+                let spec = serde_json::to_vec(&request.spec).unwrap();
+                let mut submit = client.submit_compute_job_request();
+                {
+                    let mut manifest = submit.get().init_manifest();
+                    manifest.set_job_id("rpc-job");
+                    manifest.set_wasm_module(&spec[..]);
+                    manifest.set_input_data(&request.input[..]);
+                }
+                let reply = submit.send().promise.await.unwrap();
+                assert!(reply.get().unwrap().get_success());
+
+                let mut fetch = client.get_compute_job_result_request();
+                fetch.get().set_job_id("rpc-job");
+                fetch.get().set_timeout_ms(5000);
+                let reply = fetch.send().promise.await.unwrap();
+                let reply = reply.get().unwrap();
+                assert!(reply.get_success());
+                let result: serde_json::Value =
+                    serde_json::from_slice(reply.get_result().unwrap()).unwrap();
+                assert_eq!(result["output_records"], 2);
+
+                let mut status = client.get_compute_job_status_request();
+                status.get().set_job_id("rpc-job");
+                let reply = status.send().promise.await.unwrap();
+                let status = reply.get().unwrap().get_status().unwrap();
+                assert_eq!(status.get_status().unwrap().to_str().unwrap(), "completed");
+            })
+            .await;
+    }
+}